@@ -1,60 +1,110 @@
+//! Packs an asset pack into a single archive.
+//!
+//! Usage: `assets_packer [--no-compress] <pack>...`
+//!
+//! Each pack is read from `./assets/<pack>/` and written to
+//! `./assets/packed/<pack>.pack`. Files are compressed when it
+//! saves space, already compressed formats (e.g. png or ogg) are
+//! stored as is so they can be read without copying them.
 
-extern crate byteorder;
+extern crate flate2;
+extern crate univercity_util as util;
 
 use std::fs;
 use std::path::{self, Path, PathBuf};
-use std::io::{self, Seek, Write};
-use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::{self, Read, Write};
 use std::env;
+use std::process;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use util::{ArchiveCompression, ArchiveWriter};
+
+/// Files smaller than this aren't worth compressing
+const MIN_COMPRESS_SIZE: usize = 256;
 
 fn main() {
-    let mut args = env::args();
-    args.next();
-    let pack = args.next().unwrap();
-    build_assets(pack);
+    let mut compress = true;
+    let mut packs = vec![];
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--no-compress" => compress = false,
+            _ => packs.push(arg),
+        }
+    }
+    if packs.is_empty() {
+        eprintln!("Usage: assets_packer [--no-compress] <pack>...");
+        process::exit(1);
+    }
+    for pack in packs {
+        if let Err(err) = build_assets(&pack, compress) {
+            eprintln!("Failed to pack {}: {}", pack, err);
+            process::exit(1);
+        }
+    }
 }
 
-fn build_assets(name: String) {
+fn build_assets(name: &str, compress: bool) -> io::Result<()> {
     let mut assets = vec![];
-    let root = format!("./assets/{}", name);
-    collect_files(&mut assets, &root);
+    let root = PathBuf::from(format!("./assets/{}", name));
+    collect_files(&mut assets, &root)?;
+    // Sorted so that packing the same files gives the same archive
+    assets.sort();
 
-    fs::create_dir_all("./assets/packed/").unwrap();
-
-    let mut pck = fs::File::create(&format!("./assets/packed/{}.assets", name)).unwrap();
-    let mut pck_index = fs::File::create(&format!("./assets/packed/{}.index", name)).unwrap();
-    pck_index.write_u32::<LittleEndian>(assets.len() as u32).unwrap();
-    for asset in assets {
-        let asset_short = asset.strip_prefix(&root).unwrap();
+    fs::create_dir_all("./assets/packed/")?;
+    let out = io::BufWriter::new(fs::File::create(&format!("./assets/packed/{}.pack", name))?);
+    let mut archive = ArchiveWriter::new(out)?;
+    let mut total = 0;
+    let mut stored = 0;
+    for asset in &assets {
+        let asset_short = asset.strip_prefix(&root)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "file outside of the pack"))?;
         let mut standard_path = String::new();
         for part in asset_short.components() {
-            standard_path.push_str("/");
             if let path::Component::Normal(p) = part {
-                standard_path.push_str(p.to_str().unwrap());
+                let part = p.to_str()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{:?} isn't utf-8", asset)))?;
+                if !standard_path.is_empty() {
+                    standard_path.push('/');
+                }
+                standard_path.push_str(part);
             } else {
                 break;
             }
         }
-        let mut data = fs::File::open(&asset).unwrap();
-        let start = pck.seek(io::SeekFrom::Current(0)).unwrap();
-        let len = io::copy(&mut data, &mut pck).unwrap();
-
-        pck_index.write_u16::<LittleEndian>(standard_path.len() as u16).unwrap();
-        pck_index.write_all(standard_path.as_bytes()).unwrap();
-        pck_index.write_u64::<LittleEndian>(start).unwrap();
-        pck_index.write_u64::<LittleEndian>(len).unwrap();
+
+        let mut data = vec![];
+        fs::File::open(asset)?.read_to_end(&mut data)?;
+        let compressed = if compress && data.len() >= MIN_COMPRESS_SIZE {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(&data)?;
+            Some(encoder.finish()?)
+                // Only worth it if it saves at least a tenth
+                .filter(|v| v.len() < data.len() - data.len() / 10)
+        } else {
+            None
+        };
+        total += data.len();
+        if let Some(compressed) = compressed {
+            stored += compressed.len();
+            archive.add(&standard_path, ArchiveCompression::Deflate, &compressed, data.len() as u64)?;
+        } else {
+            stored += data.len();
+            archive.add(&standard_path, ArchiveCompression::None, &data, data.len() as u64)?;
+        }
     }
+    archive.finish()?;
+    println!("Packed {} files from {} ({} bytes, {} stored)", assets.len(), name, total, stored);
+    Ok(())
 }
 
-fn collect_files<P: AsRef<Path>>(assets: &mut Vec<PathBuf>, path: P) {
-    let files = fs::read_dir(path).unwrap()
-        .into_iter()
-        .map(|v| v.unwrap());
-    for file in files {
-        if file.file_type().unwrap().is_dir() {
-            collect_files(assets, file.path());
+fn collect_files(assets: &mut Vec<PathBuf>, path: &Path) -> io::Result<()> {
+    for file in fs::read_dir(path)? {
+        let file = file?;
+        if file.file_type()?.is_dir() {
+            collect_files(assets, &file.path())?;
         } else {
             assets.push(file.path());
         }
     }
+    Ok(())
 }
@@ -1,5 +1,6 @@
 
 use std::io::{Read, Seek};
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::sync::atomic::{
     AtomicBool,
@@ -36,6 +37,12 @@ pub trait AudioDataSource {
         VolumeStream::new(self, left, right)
     }
 
+    fn low_pass(self) -> LowPassStream<Self>
+        where Self: Sized
+    {
+        LowPassStream::new(self)
+    }
+
     fn mix<A>(self, other: A) -> MixStream<Self, A>
         where Self: Sized,
               A: AudioDataSource
@@ -60,6 +67,7 @@ pub trait AudioDataSource {
     }
 
     fn set_volume_sides(&mut self, left: f32, right: f32);
+    fn set_low_pass(&mut self, amount: f32);
 }
 
 impl AudioDataSource for Box<dyn AudioDataSource> {
@@ -74,6 +82,10 @@ impl AudioDataSource for Box<dyn AudioDataSource> {
     fn set_volume_sides(&mut self, left: f32, right: f32) {
         (**self).set_volume_sides(left, right)
     }
+
+    fn set_low_pass(&mut self, amount: f32) {
+        (**self).set_low_pass(amount)
+    }
 }
 
 #[derive(Clone)]
@@ -91,6 +103,15 @@ impl AudioBuffer {
         BufferedSource {
             buffer: self.data.clone(),
             offset: 0,
+            looping: false,
+        }
+    }
+
+    pub fn looped_source(&self) -> BufferedSource {
+        BufferedSource {
+            buffer: self.data.clone(),
+            offset: 0,
+            looping: true,
         }
     }
 }
@@ -98,6 +119,7 @@ impl AudioBuffer {
 pub struct BufferedSource {
     buffer: Arc<BufferData>,
     offset: usize,
+    looping: bool,
 }
 
 impl AudioDataSource for BufferedSource {
@@ -106,6 +128,9 @@ impl AudioDataSource for BufferedSource {
     }
 
     fn next(&mut self) -> Option<(i16, i16)> {
+        if self.looping && self.offset >= self.buffer.data.len() {
+            self.offset = 0;
+        }
         let data = self.buffer.data.get(self.offset).cloned();
         self.offset += 1;
         data
@@ -114,6 +139,83 @@ impl AudioDataSource for BufferedSource {
     fn set_volume_sides(&mut self, _left: f32, _right: f32) {
 
     }
+
+    fn set_low_pass(&mut self, _amount: f32) {
+
+    }
+}
+
+pub fn stream(sample_rate: u32) -> (StreamWriter, StreamSource) {
+    let shared = Arc::new(StreamShared {
+        samples: Mutex::new(VecDeque::new()),
+        closed: AtomicBool::new(false),
+    });
+    (
+        StreamWriter {
+            shared: shared.clone(),
+        },
+        StreamSource {
+            shared,
+            sample_rate,
+        },
+    )
+}
+
+struct StreamShared {
+    samples: Mutex<VecDeque<i16>>,
+    closed: AtomicBool,
+}
+
+pub struct StreamWriter {
+    shared: Arc<StreamShared>,
+}
+
+impl StreamWriter {
+    pub fn write(&self, samples: &[i16]) {
+        let mut data = self.shared.samples.lock().unwrap();
+        data.extend(samples);
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.shared.samples.lock().unwrap().len()
+    }
+}
+
+impl Drop for StreamWriter {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+pub struct StreamSource {
+    shared: Arc<StreamShared>,
+    sample_rate: u32,
+}
+
+impl AudioDataSource for StreamSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn next(&mut self) -> Option<(i16, i16)> {
+        let mut data = self.shared.samples.lock().unwrap();
+        if let Some(val) = data.pop_front() {
+            Some((val, val))
+        } else if self.shared.closed.load(Ordering::Relaxed) {
+            None
+        } else {
+            // Play silence until more data is written
+            Some((0, 0))
+        }
+    }
+
+    fn set_volume_sides(&mut self, _left: f32, _right: f32) {
+
+    }
+
+    fn set_low_pass(&mut self, _amount: f32) {
+
+    }
 }
 
 #[derive(Clone)]
@@ -163,6 +265,7 @@ impl AudioMixer {
             paused: AtomicBool::new(true),
             ended: AtomicBool::new(false),
             volume: Mutex::new(None),
+            low_pass: Mutex::new(None),
         });
 
         data.sounds.push(Sound {
@@ -187,6 +290,7 @@ struct SoundShared {
     paused: AtomicBool,
     ended: AtomicBool,
     volume: Mutex<Option<(f32, f32)>>,
+    low_pass: Mutex<Option<f32>>,
 }
 
 #[derive(Clone)]
@@ -222,6 +326,14 @@ impl SoundRef {
     pub fn set_volume_sides(&self, left: f32, right: f32) {
         *self.shared.volume.lock().unwrap() = Some((left, right));
     }
+
+    /// Sets how muffled the sound is, from `0.0` (untouched)
+    /// to `1.0` (heavily muffled).
+    ///
+    /// Only has an effect on sounds with a `LowPassStream`
+    pub fn set_low_pass(&self, amount: f32) {
+        *self.shared.low_pass.lock().unwrap() = Some(amount);
+    }
 }
 
 pub struct AudioMixerData {
@@ -243,6 +355,10 @@ impl AudioMixerData {
             if let Some(vol) = volume.take() {
                 sound.data.set_volume_sides(vol.0, vol.1);
             }
+            let mut low_pass = sound.shared.low_pass.lock().unwrap();
+            if let Some(amount) = low_pass.take() {
+                sound.data.set_low_pass(amount);
+            }
             if sound.time_to_play <= 0.0 && !sound.shared.ended.load(Ordering::Relaxed) {
                 if let Some((l, r)) = sound.data.next() {
                     left = left.saturating_add(l);
@@ -304,6 +420,11 @@ impl <A, B> AudioDataSource for MixStream<A, B>
         self.a.set_volume_sides(left, right);
         self.b.set_volume_sides(left, right);
     }
+
+    fn set_low_pass(&mut self, amount: f32) {
+        self.a.set_low_pass(amount);
+        self.b.set_low_pass(amount);
+    }
 }
 
 pub struct ResampleStream<A> {
@@ -393,6 +514,10 @@ impl <A> AudioDataSource for ResampleStream<A>
     fn set_volume_sides(&mut self, left: f32, right: f32) {
         self.inner.set_volume_sides(left, right);
     }
+
+    fn set_low_pass(&mut self, amount: f32) {
+        self.inner.set_low_pass(amount);
+    }
 }
 
 
@@ -437,6 +562,60 @@ impl <A> AudioDataSource for VolumeStream<A>
         self.left = left;
         self.right = right;
     }
+
+    fn set_low_pass(&mut self, amount: f32) {
+        self.inner.set_low_pass(amount);
+    }
+}
+
+/// A one pole low pass filter used to muffle sounds, e.g.
+/// when heard through a wall.
+pub struct LowPassStream<A> {
+    inner: A,
+    /// How much of each new sample is let through. `1.0`
+    /// disables the filter.
+    factor: f32,
+    last: (f32, f32),
+}
+
+impl <A> LowPassStream<A>
+    where A: AudioDataSource
+{
+    fn new(stream: A) -> LowPassStream<A> {
+        LowPassStream {
+            inner: stream,
+            factor: 1.0,
+            last: (0.0, 0.0),
+        }
+    }
+}
+
+impl <A> AudioDataSource for LowPassStream<A>
+    where A: AudioDataSource
+{
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn next(&mut self) -> Option<(i16, i16)> {
+        if let Some((l, r)) = self.inner.next() {
+            self.last.0 += (f32::from(l) - self.last.0) * self.factor;
+            self.last.1 += (f32::from(r) - self.last.1) * self.factor;
+            Some((self.last.0 as i16, self.last.1 as i16))
+        } else {
+            None
+        }
+    }
+
+    fn set_volume_sides(&mut self, left: f32, right: f32) {
+        self.inner.set_volume_sides(left, right);
+    }
+
+    fn set_low_pass(&mut self, amount: f32) {
+        self.inner.set_low_pass(amount);
+        // Never fully closes otherwise the sound would be silenced
+        self.factor = 1.0 - amount.max(0.0).min(1.0) * 0.95;
+    }
 }
 
 pub struct OggStream<R: Read + Seek> {
@@ -519,4 +698,7 @@ impl <R> AudioDataSource for OggStream<R>
 
     fn set_volume_sides(&mut self, _left: f32, _right: f32) {
     }
+
+    fn set_low_pass(&mut self, _amount: f32) {
+    }
 }
@@ -0,0 +1,358 @@
+//! A small HTTP interface for managing the server without attaching
+//! a game client.
+//!
+//! Only built with the `admin-http` feature and started by passing
+//! `--admin-http <address>`. The `GET` endpoints are read only:
+//!
+//! * `/status` - the state of the server and the players in the game
+//! * `/players` - just the players
+//! * `/metrics` - the tick timings averaged over the last second
+//!
+//! `POST /command` runs the request's body as a console command the
+//! same as typing it into the server's terminal. This requires the
+//! token passed with `--admin-token` (or the `UNIVERCITY_ADMIN_TOKEN`
+//! environment variable) as an `Authorization: Bearer <token>` header
+//! and is disabled when no token is set. There is no TLS so the
+//! interface should only be reachable from a trusted network or via
+//! a proxy.
+
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use server::{ServerStatus, TickStats};
+use server::metrics::{self, TickPhase};
+use slog::Logger;
+use serde::Serialize;
+
+/// The largest request line and headers accepted
+const MAX_HEADER_SIZE: u64 = 8 * 1024;
+/// The largest body accepted
+const MAX_BODY_SIZE: usize = 4 * 1024;
+/// How long a client may take to send its request
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// The most requests handled at once
+const MAX_CONNECTIONS: usize = 8;
+
+/// How the admin interface is set up
+pub struct AdminConfig {
+    /// The address to listen on
+    pub addr: SocketAddr,
+    /// The token required to run commands, commands can't be
+    /// run without one
+    pub token: Option<String>,
+}
+
+/// Parses the admin interface's settings from the command line.
+///
+/// Returns `None` unless `--admin-http <address>` was passed.
+pub fn parse_config(log: &Logger) -> Option<AdminConfig> {
+    let mut addr = None;
+    let mut token = env::var("UNIVERCITY_ADMIN_TOKEN").ok();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--admin-http" => if let Some(val) = args.next() {
+                match val.parse() {
+                    Ok(val) => addr = Some(val),
+                    Err(err) => warn!(log, "Invalid --admin-http address {:?}: {}", val, err),
+                }
+            },
+            "--admin-token" => if let Some(val) = args.next() {
+                token = Some(val);
+            },
+            _ => {},
+        }
+    }
+    Some(AdminConfig {
+        addr: addr?,
+        token: token.filter(|v| !v.is_empty()),
+    })
+}
+
+/// The latest information sent by the server
+#[derive(Default)]
+struct ServerInfo {
+    status: Option<ServerStatus>,
+    /// The most recent ticks, oldest first
+    ticks: Vec<TickStats>,
+}
+
+type CommandHandler = dyn Fn(&str) -> Result<String, String> + Send + Sync;
+
+struct Admin {
+    log: Logger,
+    token: Option<String>,
+    info: Mutex<ServerInfo>,
+    command: Box<CommandHandler>,
+    connections: AtomicUsize,
+}
+
+/// Starts the interface on its own threads.
+///
+/// `command` is called with each command posted and returns the
+/// message to reply with.
+pub fn start<F>(
+    log: &Logger,
+    config: AdminConfig,
+    status: mpsc::Receiver<ServerStatus>,
+    ticks: mpsc::Receiver<TickStats>,
+    command: F,
+) -> io::Result<()>
+    where F: Fn(&str) -> Result<String, String> + Send + Sync + 'static
+{
+    let listener = TcpListener::bind(config.addr)?;
+    let log = log.new(o!("admin_http" => config.addr.to_string()));
+    if config.token.is_none() {
+        warn!(log, "No --admin-token set, commands can't be run via the admin interface");
+    }
+    info!(log, "Admin interface listening");
+    let admin = Arc::new(Admin {
+        log,
+        token: config.token,
+        info: Mutex::new(ServerInfo::default()),
+        command: Box::new(command),
+        connections: AtomicUsize::new(0),
+    });
+
+    {
+        let admin = admin.clone();
+        thread::spawn(move || for val in status.iter() {
+            if let Ok(mut info) = admin.info.lock() {
+                info.status = Some(val);
+            }
+        });
+    }
+    {
+        let admin = admin.clone();
+        thread::spawn(move || for val in ticks.iter() {
+            if let Ok(mut info) = admin.info.lock() {
+                if info.ticks.len() >= metrics::REPORT_INTERVAL as usize {
+                    info.ticks.remove(0);
+                }
+                info.ticks.push(val);
+            }
+        });
+    }
+
+    thread::spawn(move || for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(val) => val,
+            Err(err) => {
+                warn!(admin.log, "Failed to accept an admin connection: {}", err);
+                continue;
+            }
+        };
+        if admin.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            admin.connections.fetch_sub(1, Ordering::SeqCst);
+            let mut stream = stream;
+            let _ = respond(&mut stream, 503, &json_error("too many connections"));
+            continue;
+        }
+        let admin = admin.clone();
+        thread::spawn(move || {
+            let mut stream = stream;
+            if let Err(err) = admin.handle(&mut stream) {
+                debug!(admin.log, "Admin request failed: {}", err);
+            }
+            admin.connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    });
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Admin {
+    fn handle(&self, stream: &mut TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let req = match read_request(stream)? {
+            Ok(val) => val,
+            Err((code, msg)) => return respond(stream, code, &json_error(msg)),
+        };
+        let (code, body) = self.route(&req);
+        respond(stream, code, &body)
+    }
+
+    fn route(&self, req: &Request) -> (u16, String) {
+        let info = match self.info.lock() {
+            Ok(val) => val,
+            Err(_) => return (500, json_error("server information unavailable")),
+        };
+        match (req.method.as_str(), req.path.as_str()) {
+            ("GET", "/status") => match info.status.as_ref() {
+                Some(status) => (200, to_json(status)),
+                None => (503, json_error("the server hasn't reported its status yet")),
+            },
+            ("GET", "/players") => match info.status.as_ref() {
+                Some(status) => (200, to_json(&status.players)),
+                None => (503, json_error("the server hasn't reported its status yet")),
+            },
+            ("GET", "/metrics") => if info.ticks.is_empty() {
+                (503, json_error("no ticks have been run, the game may not have started"))
+            } else {
+                (200, metrics_json(&info.ticks))
+            },
+            ("POST", "/command") => {
+                drop(info);
+                self.run_command(req)
+            },
+            (_, "/status") | (_, "/players") | (_, "/metrics") | (_, "/command") => (405, json_error("method not allowed")),
+            _ => (404, json_error("not found")),
+        }
+    }
+
+    fn run_command(&self, req: &Request) -> (u16, String) {
+        let token = if let Some(token) = self.token.as_ref() {
+            token
+        } else {
+            return (403, json_error("commands are disabled as no admin token is set"));
+        };
+        let given = req.authorization.as_ref()
+            .and_then(|v| if v.starts_with("Bearer ") { Some(v["Bearer ".len()..].trim()) } else { None });
+        if !given.map_or(false, |v| token_matches(token, v)) {
+            return (401, json_error("missing or invalid admin token"));
+        }
+        let command = match String::from_utf8(req.body.clone()) {
+            Ok(val) => val,
+            Err(_) => return (400, json_error("the command must be utf-8")),
+        };
+        let command = command.trim();
+        if command.is_empty() || command.contains('\n') {
+            return (400, json_error("expected a single command"));
+        }
+        info!(self.log, "Running command from the admin interface: {}", command);
+        match (self.command)(command) {
+            Ok(msg) => (202, to_json(&json!({ "result": msg }))),
+            Err(msg) => (400, json_error(&msg)),
+        }
+    }
+}
+
+/// Reads a request from the stream, returning the status code and
+/// reason to fail with if it is invalid
+fn read_request(stream: &mut TcpStream) -> io::Result<Result<Request, (u16, &'static str)>> {
+    let mut reader = BufReader::new(stream.take(MAX_HEADER_SIZE));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let (method, path) = {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(path), Some(version)) if version.starts_with("HTTP/1.") => {
+                // Query strings aren't used
+                let path = path.split('?').next().unwrap_or(path);
+                (method.to_owned(), path.to_owned())
+            },
+            _ => return Ok(Err((400, "invalid request line"))),
+        }
+    };
+
+    let mut authorization = None;
+    let mut length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(Err((431, "headers too large or incomplete")));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let mut parts = header.splitn(2, ':');
+        let (name, value) = match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) => (name.trim(), value.trim()),
+            _ => return Ok(Err((400, "invalid header"))),
+        };
+        if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_owned());
+        } else if name.eq_ignore_ascii_case("content-length") {
+            length = match value.parse::<usize>() {
+                Ok(val) => val,
+                Err(_) => return Ok(Err((400, "invalid content length"))),
+            };
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Ok(Err((501, "chunked bodies aren't supported")));
+        }
+    }
+    if length > MAX_BODY_SIZE {
+        return Ok(Err((413, "body too large")));
+    }
+    // Part of the body may already be buffered, the limit only
+    // covers what is still left in the stream
+    reader.get_mut().set_limit(length as u64);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok(Request {
+        method,
+        path,
+        authorization,
+        body,
+    }))
+}
+
+fn respond(stream: &mut TcpStream, code: u16, body: &str) -> io::Result<()> {
+    let reason = match code {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    write!(stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code, reason, body.len(), body,
+    )?;
+    stream.flush()
+}
+
+/// Compares the tokens without stopping at the first difference
+/// so that the time taken doesn't reveal how much matched
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected.bytes().zip(given.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn to_json<T: Serialize + ?Sized>(val: &T) -> String {
+    serde_json::to_string(val).unwrap_or_else(|_| json_error("failed to encode the response"))
+}
+
+fn json_error(msg: &str) -> String {
+    json!({ "error": msg }).to_string()
+}
+
+/// Averages the ticks' timings in milliseconds
+fn metrics_json(ticks: &[TickStats]) -> String {
+    let count = ticks.len() as f64;
+    let ms = |time: Duration| time.as_micros() as f64 / 1000.0;
+    let phases: serde_json::Map<String, serde_json::Value> = TickPhase::ALL.iter()
+        .map(|phase| {
+            let total: f64 = ticks.iter().map(|v| ms(v.phases.get(*phase))).sum();
+            (phase.name().to_owned(), json!(total / count))
+        })
+        .collect();
+    let tick_time: f64 = ticks.iter().map(|v| ms(v.time)).sum();
+    let worst = ticks.iter().map(|v| ms(v.time)).fold(0.0, f64::max);
+    to_json(&json!({
+        "ticks": ticks.len(),
+        "tick_time_ms": tick_time / count,
+        "worst_tick_time_ms": worst,
+        "script_memory": ticks.last().map_or(0, |v| v.script_memory),
+        "phases_ms": phases,
+    }))
+}
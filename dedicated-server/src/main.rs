@@ -9,9 +9,15 @@ extern crate slog_async;
 extern crate slog_json;
 #[macro_use]
 extern crate univercity_util;
+#[cfg(feature = "admin-http")]
+extern crate serde;
+#[cfg(feature = "admin-http")]
+#[macro_use]
+extern crate serde_json;
+#[cfg(feature = "steam")]
 use server::steamworks;
 
-use std::net::{self, SocketAddr};
+use std::net::SocketAddr;
 use std::env;
 use std::sync::mpsc;
 use std::thread;
@@ -19,8 +25,13 @@ use std::path::Path;
 use server::assets;
 use server::network::UdpSocketListener;
 use server::{Server, ServerConfig};
+use server::steam::BoxedSteam;
 use server::saving::filesystem::*;
 use slog::Drain;
+use univercity_util::LogFilter;
+
+#[cfg(feature = "admin-http")]
+mod admin;
 
 fn main() -> server::errors::Result<()> {
     // This forces the dedicated server to use the game's appid even when launched
@@ -29,6 +40,13 @@ fn main() -> server::errors::Result<()> {
     // that way during the review.
     env::set_var("SteamAppId", "808160");
 
+    // Allows external tools (server browsers, replay analyzers)
+    // to get a description of the wire format
+    if env::args().any(|v| v == "--protocol-schema") {
+        println!("{}", server::network::packet::protocol_schema_json());
+        return Ok(());
+    }
+
     let decorator = slog_term::TermDecorator::new().build();
     let drain = slog_term::FullFormat::new(decorator).build().fuse();
     let json_drain = slog_json::Json::default(::std::fs::File::create("log.json").unwrap()).fuse();
@@ -37,36 +55,39 @@ fn main() -> server::errors::Result<()> {
         json_drain
     ).fuse())
         .build_with_guard();
-    let drain = drain.fuse();
-    // TODO: Filter debug logging at some point
+    let log_filter = univercity_util::LogFilter::new(univercity_util::DEFAULT_LOG_LEVEL);
+    let drain = log_filter.drain(drain.fuse()).fuse();
     let log = slog::Logger::root(drain, o!(
         "dedicated_server" => "true"
     ));
     univercity_util::log_panics(&log, server::GAME_HASH, false);
+    let default_filter = parse_log_filter();
+    if let Err(err) = log_filter.reset(&default_filter) {
+        warn!(log, "Invalid --log-filter: {}", err);
+    }
 
     let asset_manager = server::register_loaders(assets::AssetManager::with_packs(&log, &["base".to_owned()]))
         .build();
-    let addr: SocketAddr = assume!(log, "0.0.0.0:23347".parse());
 
-    let ip = if let net::IpAddr::V4(ip) =  addr.ip() {
-        ip
-    } else {
-        panic!("IPv6 not supported with steamworks currently")
-    };
-    let port = addr.port();
-    let (steam, _single) = assume!(log, steamworks::Server::init(
-        ip,
-        port + 1, port, port + 2,
-        steamworks::ServerMode::Authentication,
-        server::GAME_HASH
-    ));
-
-    steam.set_product("UniverCity");
-    steam.set_game_description("default");
-    steam.set_dedicated_server(true);
-    steam.log_on_anonymous();
+    // Prints how the loaded packs depend on and override each
+    // other for tracking down load order problems
+    if let Some(format) = parse_pack_graph() {
+        let graph = asset_manager.pack_graph();
+        match format.as_str() {
+            "json" => println!("{}", graph.to_json()),
+            "dot" => println!("{}", graph.to_dot()),
+            _ => error!(log, "Unknown pack graph format {:?}, expected dot or json", format),
+        }
+        return Ok(());
+    }
+    let addr: SocketAddr = assume!(log, "0.0.0.0:23347".parse());
 
+    let (steam, _steam_guard) = init_steam(&log, addr);
     let (cmd_send, cmd_recv) = mpsc::channel();
+    let cmd_log = log.clone();
+    let console_send = cmd_send.clone();
+    let console_filter = log_filter.clone();
+    let console_default = default_filter.clone();
     thread::spawn(move || {
         use std::io::{stdin, BufRead};
         let stdin = stdin();
@@ -75,19 +96,29 @@ fn main() -> server::errors::Result<()> {
         loop {
             line.clear();
             if stdin.read_line(&mut line).is_err() {
-                let _ = cmd_send.send("quit".into());
+                let _ = console_send.send("quit".into());
                 return;
             }
             let l = line.trim();
-            if !l.is_empty() {
-                if cmd_send.send(l.to_owned()).is_err() {
+            match log_command(l, &console_filter, &console_default) {
+                Some(Ok(msg)) => info!(cmd_log, "{}", msg),
+                Some(Err(err)) => warn!(cmd_log, "{}", err),
+                None => if !l.is_empty() && console_send.send(l.to_owned()).is_err() {
                     return;
-                }
+                },
             }
         }
     });
 
     let fs = NativeFileSystem::new(Path::new("./saves/")).into_boxed();
+    #[cfg(not(feature = "steam"))]
+    let auth = parse_auth(&log)?;
+    let seasons = parse_seasons();
+    let idle = parse_idle();
+    recover_save(&log, &fs, "dedicated");
+
+    #[cfg(feature = "admin-http")]
+    let (admin_log, admin_config) = (log.clone(), admin::parse_config(&log));
 
     let (mut server, _) = Server::<UdpSocketListener, _>::new(log, asset_manager, steam, fs, addr, ServerConfig {
         save_type: server::saving::SaveType::ServerFreePlay,
@@ -98,8 +129,375 @@ fn main() -> server::errors::Result<()> {
         player_area_size: 100,
         locked_players: false,
         mission: None,
+        goals: None,
         tick_rate: std::cell::Cell::new(20),
+        seasons,
+        incremental_saves: !env::args().any(|v| v == "--no-incremental-saves"),
+        journal_interval: parse_journal_interval(),
+        save_on_exit: !env::args().any(|v| v == "--no-save-on-exit"),
+        idle,
+        dirt: parse_dirt(),
+        litter: parse_litter(),
+        lod: parse_lod(),
+        construction: parse_construction(),
+        visitors: parse_visitors(),
+        #[cfg(not(feature = "steam"))]
+        auth,
     }, None, Some(cmd_recv))?;
+
+    #[cfg(feature = "admin-http")]
+    {
+        if let Some(config) = admin_config {
+            let (status_send, status_recv) = mpsc::channel();
+            let (ticks_send, ticks_recv) = mpsc::channel();
+            server.report_status(status_send);
+            server.report_tick_times(ticks_send);
+            let cmd_send = ::std::sync::Mutex::new(cmd_send);
+            let res = admin::start(&admin_log, config, status_recv, ticks_recv, move |l| {
+                if let Some(res) = log_command(l, &log_filter, &default_filter) {
+                    return res;
+                }
+                let sent = cmd_send.lock()
+                    .map(|v| v.send(l.to_owned()).is_ok())
+                    .unwrap_or(false);
+                if sent {
+                    Ok(format!("Sent {:?} to the server", l))
+                } else {
+                    Err("The server has stopped".to_owned())
+                }
+            });
+            if let Err(err) = res {
+                error!(admin_log, "Failed to start the admin interface: {}", err);
+            }
+        }
+    }
+
     server.run();
     Ok(())
+}
+
+/// Handles the `log` command, returning `None` for any other
+/// command.
+///
+/// Handled outside of the server so that logging can be changed
+/// even whilst the server is busy
+fn log_command(line: &str, log_filter: &LogFilter, default_filter: &str) -> Option<Result<String, String>> {
+    if line != "log" && !line.starts_with("log ") {
+        return None;
+    }
+    let res = match line["log".len()..].trim() {
+        "" => Ok(()),
+        "reset" => log_filter.reset(default_filter),
+        filter => log_filter.apply(filter),
+    };
+    Some(res.map(|()| format!("Log filter: {}", log_filter.describe())))
+}
+
+/// Parses the seasons to force from the command line.
+///
+/// Each `--season <name>` enables the named season whilst
+/// `--no-seasons` disables all of them. If neither is passed
+/// the seasons are picked using the current date.
+fn parse_seasons() -> Option<Vec<String>> {
+    let mut seasons = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--season" => if let Some(season) = args.next() {
+                seasons.get_or_insert_with(Vec::new).push(season);
+            },
+            "--no-seasons" => {
+                seasons = Some(Vec::new());
+            },
+            _ => {},
+        }
+    }
+    seasons
+}
+
+/// Parses how idle players are handled from the command line.
+///
+/// `--afk-minutes <n>` sets how long until a player is marked
+/// as away with `0` disabling it, `--afk-kick-minutes <n>` removes
+/// idle players from the lobby after the time and
+/// `--afk-pause-intake` stops students arriving for away players.
+fn parse_idle() -> server::player::IdleConfig {
+    use std::time::Duration;
+    let mut idle = server::player::IdleConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--afk-minutes" => if let Some(minutes) = args.next().and_then(|v| v.parse::<u64>().ok()) {
+                idle.afk_after = Some(Duration::from_secs(minutes * 60))
+                    .filter(|_| minutes > 0);
+            },
+            "--afk-kick-minutes" => if let Some(minutes) = args.next().and_then(|v| v.parse::<u64>().ok()) {
+                idle.kick_after = Some(Duration::from_secs(minutes * 60));
+            },
+            "--afk-pause-intake" => {
+                idle.pause_intake = true;
+            },
+            _ => {},
+        }
+    }
+    idle
+}
+
+/// Parses the log levels to start with from the command line.
+///
+/// `--log-filter <filter>` takes a comma separated list of levels
+/// optionally prefixed by a module, e.g. `info,univercity_server::network=debug`.
+/// This can be changed later using the `log` command.
+fn parse_log_filter() -> String {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--log-filter" {
+            if let Some(filter) = args.next() {
+                return filter;
+            }
+        }
+    }
+    "info".to_owned()
+}
+
+/// Parses how often recovery journals are written from the command line.
+///
+/// `--journal-interval <ticks>` sets the ticks between journals
+/// with `0` disabling them.
+fn parse_journal_interval() -> u32 {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--journal-interval" {
+            if let Some(ticks) = args.next().and_then(|v| v.parse::<u32>().ok()) {
+                return ticks;
+            }
+        }
+    }
+    server::saving::DEFAULT_JOURNAL_INTERVAL
+}
+
+/// Handles a recovery journal left behind by a server that
+/// didn't shut down cleanly.
+///
+/// The journal is only restored when `--recover` is passed,
+/// otherwise it is left alone and replaced by the next save.
+fn recover_save(log: &slog::Logger, fs: &BoxedFileSystem, name: &str) {
+    if !server::saving::has_journal(fs, name, server::saving::SaveType::ServerFreePlay) {
+        return;
+    }
+    if env::args().any(|v| v == "--recover") {
+        match server::saving::restore_journal(fs, name) {
+            Ok(()) => info!(log, "Restored the save from the recovery journal"),
+            Err(err) => warn!(log, "Failed to restore the recovery journal: {}", err),
+        }
+    } else {
+        warn!(log, "The server didn't shut down cleanly, start with --recover to restore the recovery journal");
+    }
+}
+
+/// Parses how quickly floors get dirty from the command line.
+///
+/// `--dirt-rate <rate>` sets the dirt added per a tick whilst
+/// an entity walks across a tile, zero disables dirt.
+fn parse_dirt() -> server::entity::dirt::DirtConfig {
+    let mut dirt = server::entity::dirt::DirtConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--dirt-rate" {
+            if let Some(rate) = args.next().and_then(|v| v.parse::<f32>().ok()) {
+                dirt.rate = rate;
+            }
+        }
+    }
+    dirt
+}
+
+/// Parses how much litter students drop from the command line.
+///
+/// `--litter-rate <chance>` sets the chance per a tick of a
+/// walking student dropping litter and `--bin-range <tiles>`
+/// how far they will go to use a bin instead.
+fn parse_litter() -> server::entity::litter::LitterConfig {
+    let mut litter = server::entity::litter::LitterConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--litter-rate" => if let Some(rate) = args.next().and_then(|v| v.parse::<f32>().ok()) {
+                litter.rate = rate;
+            },
+            "--bin-range" => if let Some(range) = args.next().and_then(|v| v.parse::<f32>().ok()) {
+                litter.bin_range = range;
+            },
+            _ => {},
+        }
+    }
+    litter
+}
+
+/// Parses how often visitors arrive from the command line.
+///
+/// `--parent-rate <count>` and `--tourist-rate <count>` set the
+/// average number of each that visit a player per an in-game day,
+/// `--max-visitors <count>` the most on a campus at once and
+/// `--open-days <month-day,...>` (e.g. `6-20,10-10`) the days
+/// campuses hold open days on, empty for none.
+fn parse_visitors() -> server::entity::visitor::VisitorConfig {
+    let mut visitors = server::entity::visitor::VisitorConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--parent-rate" => if let Some(rate) = args.next().and_then(|v| v.parse::<f32>().ok()) {
+                visitors.parent_rate = rate;
+            },
+            "--tourist-rate" => if let Some(rate) = args.next().and_then(|v| v.parse::<f32>().ok()) {
+                visitors.tourist_rate = rate;
+            },
+            "--max-visitors" => if let Some(max) = args.next().and_then(|v| v.parse::<u32>().ok()) {
+                visitors.max_visitors = max;
+            },
+            "--open-days" => if let Some(days) = args.next() {
+                visitors.open_days = days.split(',')
+                    .filter(|v| !v.is_empty())
+                    .filter_map(|v| {
+                        let mut parts = v.splitn(2, '-');
+                        let month = parts.next()?.trim().parse().ok()?;
+                        let day = parts.next()?.trim().parse().ok()?;
+                        Some(server::assets::MonthDay { month, day })
+                    })
+                    .collect();
+            },
+            _ => {},
+        }
+    }
+    visitors
+}
+
+/// Parses the format the pack graph should be printed in.
+///
+/// `--pack-graph <dot|json>` prints the graph of the loaded
+/// packs instead of starting the server. Defaults to dot when
+/// no format is given.
+fn parse_pack_graph() -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--pack-graph" {
+            return Some(args.next()
+                .filter(|v| !v.starts_with("--"))
+                .unwrap_or_else(|| "dot".to_owned()));
+        }
+    }
+    None
+}
+
+/// Parses when entities are simplified from the command line.
+///
+/// `--no-lod` keeps every entity fully simulated whilst
+/// `--lod-radius <tiles>` sets how far from a player's camera
+/// entities are simulated in full.
+fn parse_lod() -> server::entity::lod::LodConfig {
+    let mut lod = server::entity::lod::LodConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-lod" => {
+                lod.enabled = false;
+            },
+            "--lod-radius" => if let Some(radius) = args.next().and_then(|v| v.parse::<f32>().ok()) {
+                lod.radius = radius;
+            },
+            _ => {},
+        }
+    }
+    lod
+}
+
+/// Parses how long rooms take to build from the command line.
+///
+/// `--instant-construction` opens rooms as soon as they are
+/// placed whilst `--construction-ticks <ticks>` sets the ticks
+/// each tile of a room takes to build without builders.
+fn parse_construction() -> server::entity::construction::ConstructionConfig {
+    let mut construction = server::entity::construction::ConstructionConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--instant-construction" => {
+                construction.instant = true;
+            },
+            "--construction-ticks" => if let Some(ticks) = args.next().and_then(|v| v.parse::<u32>().ok()) {
+                construction.ticks_per_tile = ticks;
+            },
+            _ => {},
+        }
+    }
+    construction
+}
+
+/// Returns the steam api for the server along with a guard
+/// that must be kept alive for as long as the server runs.
+#[cfg(feature = "steam")]
+fn init_steam(log: &slog::Logger, addr: SocketAddr) -> (BoxedSteam, Box<dyn std::any::Any>) {
+    let ip = if let std::net::IpAddr::V4(ip) =  addr.ip() {
+        ip
+    } else {
+        panic!("IPv6 not supported with steamworks currently")
+    };
+    let port = addr.port();
+    let (steam, single) = assume!(log, steamworks::Server::init(
+        ip,
+        port + 1, port, port + 2,
+        steamworks::ServerMode::Authentication,
+        server::GAME_HASH
+    ));
+    steam.set_product("UniverCity");
+    steam.set_game_description("default");
+    steam.set_dedicated_server(true);
+    steam.log_on_anonymous();
+    (Box::new(steam), Box::new(single))
+}
+
+#[cfg(not(feature = "steam"))]
+fn init_steam(log: &slog::Logger, _addr: SocketAddr) -> (BoxedSteam, Box<dyn std::any::Any>) {
+    info!(log, "Built without steam, players will be authenticated by the server");
+    (Box::new(()), Box::new(()))
+}
+
+/// Parses the authentication method from the command line.
+///
+/// `--password <password>` requires every player to provide the
+/// password whilst `--tokens <file>` requires each player to
+/// provide the token listed next to their username in the file
+/// (one `username token` pair per line).
+#[cfg(not(feature = "steam"))]
+fn parse_auth(log: &slog::Logger) -> server::errors::Result<server::ServerAuth> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--password" => {
+                let password = args.next()
+                    .ok_or_else(|| "Missing password after --password")?;
+                return Ok(server::ServerAuth::Password(password));
+            },
+            "--tokens" => {
+                let file = args.next()
+                    .ok_or_else(|| "Missing file after --tokens")?;
+                let tokens = std::fs::read_to_string(&file)?
+                    .lines()
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty() && !v.starts_with('#'))
+                    .filter_map(|line| {
+                        let mut parts = line.splitn(2, char::is_whitespace);
+                        let name = parts.next()?;
+                        let token = parts.next()?.trim();
+                        Some((name.to_owned(), token.to_owned()))
+                    })
+                    .collect::<univercity_util::FNVMap<_, _>>();
+                info!(log, "Loaded {} player tokens from {}", tokens.len(), file);
+                return Ok(server::ServerAuth::Tokens(tokens));
+            },
+            _ => {},
+        }
+    }
+    warn!(log, "No --password or --tokens provided, anyone will be able to join");
+    Ok(server::ServerAuth::None)
 }
\ No newline at end of file
@@ -0,0 +1,170 @@
+use super::*;
+
+/// A set of component types that can be copied between
+/// containers.
+///
+/// Implemented for tuples of up to 8 component types that are
+/// `Clone + Send`.
+pub trait ComponentSet {
+    #[doc(hidden)]
+    fn register(c: &mut Container);
+    #[doc(hidden)]
+    fn copy_components(from: &Container, to: &mut Container, id: u32);
+}
+
+macro_rules! impl_component_set {
+    ($($ty:ident),*) => (
+        impl <$($ty),*> ComponentSet for ($($ty,)*)
+            where $($ty: Component + Clone + Send),*
+        {
+            fn register(c: &mut Container) {
+                $(
+                    c.components.register_component::<$ty>();
+                )*
+            }
+            fn copy_components(from: &Container, to: &mut Container, id: u32) {
+                $(
+                    if let Some(v) = from.components.get_component::<$ty>(id) {
+                        to.components.add_component(id, v.clone());
+                    }
+                )*
+            }
+        }
+    )
+}
+
+impl_component_set!(A);
+impl_component_set!(A, B);
+impl_component_set!(A, B, C);
+impl_component_set!(A, B, C, D);
+impl_component_set!(A, B, C, D, E);
+impl_component_set!(A, B, C, D, E, F);
+impl_component_set!(A, B, C, D, E, F, G);
+impl_component_set!(A, B, C, D, E, F, G, H);
+
+/// A standalone copy of some of the entities and components
+/// of a `Container`.
+///
+/// Created by `Container::clone_filtered`. Entities keep the same
+/// ids and generations as the container they were copied from
+/// so results can be merged back with `Container::merge_filtered`.
+///
+/// Unlike `Container` this can be sent to another thread as it
+/// only contains `Send` components.
+pub struct FilteredContainer {
+    container: Container,
+}
+
+// Only components from a `ComponentSet` can be registered which
+// requires them to be `Send`.
+unsafe impl Send for FilteredContainer {}
+
+impl FilteredContainer {
+    /// Runs the passed function like a system
+    pub fn with<'a, F, D>(&mut self, f: F) -> F::Return
+        where F: IntoWithSystem<'a, D> + 'a
+    {
+        self.container.with(f)
+    }
+
+    /// Adds a component to an entity.
+    ///
+    /// The component must have been part of the set the container
+    /// was created with.
+    #[inline]
+    pub fn add_component<T: Component + Send>(&mut self, e: Entity, val: T) {
+        self.container.add_component(e, val)
+    }
+
+    /// Removes a component from an entity.
+    #[inline]
+    pub fn remove_component<T: Component + Send>(&mut self, e: Entity) -> Option<T> {
+        self.container.remove_component(e)
+    }
+
+    /// Gets a mutable reference to a component from an entity.
+    #[inline]
+    pub fn get_component_mut<T: Component + Send>(&mut self, e: Entity) -> Option<&mut T> {
+        self.container.get_component_mut(e)
+    }
+}
+
+impl Deref for FilteredContainer {
+    type Target = Container;
+    fn deref(&self) -> &Container {
+        &self.container
+    }
+}
+
+impl Container {
+    /// Creates a standalone copy of the entities in the mask with
+    /// copies of the components in the component set.
+    ///
+    /// Useful for running expensive processing on another thread
+    /// against a consistent view of the entities whilst this
+    /// container continues to be modified.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a component in the set isn't registered
+    pub fn clone_filtered<C: ComponentSet>(&self, mask: &EntityMask) -> FilteredContainer {
+        let mut entities = self.entities
+            .read()
+            .expect("Failed to lock entities")
+            .clone();
+        let ids = (0 .. mask.max)
+            .filter(|id| mask.mask.get(*id as usize) && entities.entities.get(*id as usize))
+            .collect::<Vec<_>>();
+        entities.entities.clear();
+        entities.entities.set(0, true); // Reserve the world entity
+        for id in &ids {
+            entities.entities.set(*id as usize, true);
+        }
+
+        let mut container = Container {
+            entities: RwLock::new(entities),
+            components: internal::ComponentStore::new(),
+        };
+        C::register(&mut container);
+        for id in ids {
+            C::copy_components(self, &mut container, id);
+        }
+        FilteredContainer {
+            container,
+        }
+    }
+
+    /// Copies the components in the component set from the filtered
+    /// container back into this one.
+    ///
+    /// Components are only copied to entities that still exist in
+    /// this container and existing components are replaced. Components
+    /// missing from the filtered container are left alone.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a component in the set isn't registered in either
+    /// container
+    pub fn merge_filtered<C: ComponentSet>(&mut self, other: &FilteredContainer) {
+        let ids = {
+            let entities = self.entities
+                .read()
+                .expect("Failed to lock entities");
+            let others = other.entities
+                .read()
+                .expect("Failed to lock entities");
+            (0 .. others.max_entities)
+                .filter(|id| others.entities.get(*id as usize))
+                .map(|id| Entity {
+                    id,
+                    generation: others.generations[id as usize],
+                })
+                .filter(|e| entities.is_valid(*e))
+                .map(|e| e.id)
+                .collect::<Vec<_>>()
+        };
+        for id in ids {
+            C::copy_components(other, self, id);
+        }
+    }
+}
@@ -235,6 +235,7 @@ pub trait Accessor {
     fn and_not_mask(&self, mask: super::EntityMask) -> super::EntityMask;
 }
 
+#[derive(Clone)]
 pub struct EntityAllocator {
     pub max_entities: u32,
     pub entities: util::BitSet,
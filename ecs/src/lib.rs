@@ -142,6 +142,10 @@ mod par;
 pub use crate::par::*;
 mod group;
 pub use crate::group::*;
+mod filtered;
+pub use crate::filtered::*;
+mod map;
+pub use crate::map::*;
 mod util;
 
 use std::any::{Any, TypeId};
@@ -532,6 +536,11 @@ macro_rules! component {
             type Storage = $crate::VecStorage<$ty>;
         }
     );
+    ($ty:ty => Dense) => (
+        impl $crate::Component for $ty {
+            type Storage = $crate::DenseStorage<$ty>;
+        }
+    );
     ($ty:ty => Marker) => (
         impl $crate::Component for $ty {
             type Storage = $crate::DefaultStorage<$ty>;
@@ -1005,6 +1014,21 @@ impl <'a, T> Write<'a, T>
     }
 }
 
+impl <'a, T> Write<'a, T>
+    where T: Component<Storage = DenseStorage<T>>
+{
+    /// Returns a mutable iterator over every component of this
+    /// type in storage order.
+    ///
+    /// Unlike iterating over a mask this walks the components
+    /// directly without looking up each entity.
+    #[inline]
+    pub fn dense_iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        let storage = unsafe { &mut *self.storage };
+        storage.iter_mut()
+    }
+}
+
 impl <'a, T: Component> Write<'a, T> {
     /// Returns a read accessor of this component type
     pub fn read(&self) -> Read<'a, T> {
@@ -1194,6 +1218,21 @@ pub struct Read<'a, T: Component> {
 unsafe impl <'a, T: Component + Send> Send for Read<'a, T> {}
 unsafe impl <'a, T: Component + Sync> Sync for Read<'a, T> {}
 
+impl <'a, T> Read<'a, T>
+    where T: Component<Storage = DenseStorage<T>>
+{
+    /// Returns an iterator over every component of this type
+    /// in storage order.
+    ///
+    /// Unlike iterating over a mask this walks the components
+    /// directly without looking up each entity.
+    #[inline]
+    pub fn dense_iter(&self) -> std::slice::Iter<'_, T> {
+        let storage = unsafe { &*self.storage };
+        storage.iter()
+    }
+}
+
 impl <'a, T: Component> Read<'a, T> {
     /// Gets an immutable reference to a component from an entity.
     #[inline]
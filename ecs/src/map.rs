@@ -0,0 +1,180 @@
+use crate::Entity;
+use std::fmt::{self, Debug, Formatter};
+use std::mem;
+
+/// A map keyed by entities that only returns values for the
+/// generation of the entity they were inserted with.
+///
+/// Entity ids are reused once an entity is removed, keying a
+/// normal map by entity id would return the previous entity's
+/// data for the new entity. Inserting a value for a newer
+/// generation replaces the stale value.
+#[derive(Clone)]
+pub struct EntityMap<V> {
+    entries: Vec<Option<(u32, V)>>,
+    len: usize,
+}
+
+impl <V> Default for EntityMap<V> {
+    fn default() -> EntityMap<V> {
+        EntityMap::new()
+    }
+}
+
+impl <V> EntityMap<V> {
+    /// Creates an empty map
+    pub fn new() -> EntityMap<V> {
+        EntityMap {
+            entries: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of values in the map
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the map is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Inserts the value for the entity replacing any value
+    /// that was stored for an entity with the same id.
+    ///
+    /// Only returns the previous value if it was stored
+    /// for the same generation of the entity.
+    pub fn insert(&mut self, e: Entity, val: V) -> Option<V> {
+        let idx = e.id as usize;
+        if idx >= self.entries.len() {
+            self.entries.resize_with(idx + 1, || None);
+        }
+        match mem::replace(&mut self.entries[idx], Some((e.generation, val))) {
+            Some((gen, old)) => if gen == e.generation {
+                Some(old)
+            } else {
+                None
+            },
+            None => {
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Returns the value stored for the entity if any
+    #[inline]
+    pub fn get(&self, e: Entity) -> Option<&V> {
+        match self.entries.get(e.id as usize) {
+            Some(Some((gen, val))) if *gen == e.generation => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Returns the value stored for the entity if any
+    #[inline]
+    pub fn get_mut(&mut self, e: Entity) -> Option<&mut V> {
+        match self.entries.get_mut(e.id as usize) {
+            Some(Some((gen, val))) if *gen == e.generation => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Returns whether a value is stored for the entity
+    #[inline]
+    pub fn contains_key(&self, e: Entity) -> bool {
+        self.get(e).is_some()
+    }
+
+    /// Removes the value stored for the entity returning it
+    pub fn remove(&mut self, e: Entity) -> Option<V> {
+        let entry = self.entries.get_mut(e.id as usize)?;
+        if entry.as_ref().map_or(true, |v| v.0 != e.generation) {
+            return None;
+        }
+        self.len -= 1;
+        entry.take().map(|v| v.1)
+    }
+
+    /// Removes all values from the map
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.len = 0;
+    }
+
+    /// Only keeps the values that the passed function
+    /// returns true for
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(Entity, &mut V) -> bool
+    {
+        for (id, entry) in self.entries.iter_mut().enumerate() {
+            let keep = if let Some((gen, val)) = entry.as_mut() {
+                f(Entity { id: id as u32, generation: *gen }, val)
+            } else {
+                continue;
+            };
+            if !keep {
+                *entry = None;
+                self.len -= 1;
+            }
+        }
+    }
+
+    /// Iterates over the entities and their values in
+    /// id order
+    pub fn iter(&self) -> impl Iterator<Item=(Entity, &V)> + '_ {
+        self.entries.iter()
+            .enumerate()
+            .filter_map(|(id, v)| v.as_ref()
+                .map(|(gen, val)| (Entity { id: id as u32, generation: *gen }, val)))
+    }
+
+    /// Iterates mutably over the entities and their values
+    /// in id order
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=(Entity, &mut V)> + '_ {
+        self.entries.iter_mut()
+            .enumerate()
+            .filter_map(|(id, v)| v.as_mut()
+                .map(|(gen, val)| (Entity { id: id as u32, generation: *gen }, val)))
+    }
+}
+
+impl <V: Debug> Debug for EntityMap<V> {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        fmt.debug_map()
+            .entries(self.iter())
+            .finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::*;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+    // Stored as a list of (id, generation, value)
+    impl <V: Serialize> Serialize for EntityMap<V> {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where S: Serializer
+        {
+            serializer.collect_seq(self.iter()
+                .map(|(e, v)| (e.id, e.generation, v)))
+        }
+    }
+
+    impl <'de, V: Deserialize<'de>> Deserialize<'de> for EntityMap<V> {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where D: Deserializer<'de>
+        {
+            let entries = Vec::<(u32, u32, V)>::deserialize(deserializer)?;
+            let mut map = EntityMap::new();
+            for (id, generation, val) in entries {
+                map.insert(Entity { id, generation }, val);
+            }
+            Ok(map)
+        }
+    }
+}
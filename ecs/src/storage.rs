@@ -219,6 +219,124 @@ impl <T: Component> internal::BoxedStorage for VecStorage<T> {
     }
 }
 
+/// Stores components tightly packed in a `Vec`.
+///
+/// Entity ids are mapped to a slot in the packed list so that
+/// iterating over every component (via `Read::dense_iter` or
+/// `Write::dense_iter_mut`) walks contiguous memory no matter
+/// how spread out the entity ids are. Removing a component moves
+/// the last component into its slot so the order of components
+/// isn't stable.
+pub struct DenseStorage<T: Component> {
+    /// Maps an entity id to its slot in `data`
+    slots: Vec<u32>,
+    /// Maps a slot back to the entity id that owns it
+    ids: Vec<u32>,
+    data: Vec<T>,
+}
+
+impl <T: Component> DenseStorage<T> {
+    const EMPTY: u32 = 0xFFFF_FFFF;
+
+    #[inline]
+    fn slot(&self, id: u32) -> Option<usize> {
+        self.slots.get(id as usize)
+            .cloned()
+            .filter(|v| *v != Self::EMPTY)
+            .map(|v| v as usize)
+    }
+
+    /// Returns the number of components in the storage
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns whether the storage contains no components
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns an iterator over every component in the storage
+    /// in slot order.
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Returns a mutable iterator over every component in the
+    /// storage in slot order.
+    #[inline]
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.data.iter_mut()
+    }
+}
+
+unsafe impl <T: Component> ComponentStorage<T> for DenseStorage<T> {
+    fn new() -> Self {
+        DenseStorage {
+            slots: Vec::new(),
+            ids: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+    #[inline]
+    fn add_component(&mut self, id: u32, val: T) {
+        if let Some(slot) = self.slot(id) {
+            self.data[slot] = val;
+            return;
+        }
+        if self.slots.len() <= id as usize {
+            self.slots.resize(id as usize + 1, Self::EMPTY);
+        }
+        self.slots[id as usize] = self.data.len() as u32;
+        self.ids.push(id);
+        self.data.push(val);
+    }
+    #[inline]
+    fn remove_component(&mut self, id: u32) -> Option<T> {
+        let slot = self.slot(id)?;
+        self.slots[id as usize] = Self::EMPTY;
+        self.ids.swap_remove(slot);
+        // Fix up the slot of the component that was moved to
+        // fill the gap
+        if let Some(moved) = self.ids.get(slot) {
+            self.slots[*moved as usize] = slot as u32;
+        }
+        Some(self.data.swap_remove(slot))
+    }
+
+    #[inline]
+    fn get_component(&self, id: u32) -> Option<&T> {
+        let slot = self.slot(id)?;
+        self.data.get(slot)
+    }
+
+    #[inline]
+    fn get_component_mut(&mut self, id: u32) -> Option<&mut T> {
+        let slot = self.slot(id)?;
+        self.data.get_mut(slot)
+    }
+
+    #[inline]
+    fn self_bookkeeps() -> bool { true }
+}
+
+impl <T: Component> internal::BoxedStorage for DenseStorage<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn free_id(&mut self, id: u32) {
+        self.remove_component(id);
+    }
+}
+
 /// Always returns the `Default::default()` value for a component.
 pub struct DefaultStorage<T: Component> {
     val: T
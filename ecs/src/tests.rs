@@ -341,4 +341,180 @@ fn test_rayon_group() {
             .count();
         assert_eq!(count, 1123);
     });
-}
\ No newline at end of file
+}
+#[derive(Debug, PartialEq, Eq)]
+struct DensePosition {
+    x: i32,
+    y: i32
+}
+component!(DensePosition => Dense);
+
+#[test]
+fn test_dense() {
+    let mut c = Container::new();
+    c.register_component::<DensePosition>();
+    let entities: Vec<_> = (0 .. 100)
+        .map(|i| {
+            let e = c.new_entity();
+            c.add_component(e, DensePosition { x: i, y: -i });
+            e
+        })
+        .collect();
+
+    // Forces components to be moved between slots
+    for e in entities.iter().step_by(3) {
+        assert!(c.remove_component::<DensePosition>(*e).is_some());
+    }
+    c.remove_entity(entities[1]);
+    c.add_component(entities[2], DensePosition { x: 500, y: 500 });
+
+    for (i, e) in entities.iter().enumerate() {
+        let pos = c.get_component::<DensePosition>(*e);
+        if i % 3 == 0 || i == 1 {
+            assert_eq!(pos, None);
+        } else if i == 2 {
+            assert_eq!(pos, Some(&DensePosition { x: 500, y: 500 }));
+        } else {
+            assert_eq!(pos, Some(&DensePosition { x: i as i32, y: -(i as i32) }));
+        }
+    }
+
+    c.with(|
+        _em: EntityManager<'_>,
+        mut pos: Write<'_, DensePosition>,
+    | {
+        assert_eq!(pos.dense_iter_mut().count(), 100 - 34 - 1);
+        for p in pos.dense_iter_mut() {
+            p.x = 1;
+        }
+        assert!(pos.read().dense_iter().all(|v| v.x == 1));
+    });
+}
+
+/// Compares iterating over a dense storage with a vec storage
+/// when entities are spread out.
+#[test]
+fn bench_dense_vs_vec() {
+    use std::time::Instant;
+    let mut c = Container::new();
+    c.register_component::<Position>();
+    c.register_component::<DensePosition>();
+    for i in 0 .. 100_000 {
+        let e = c.new_entity();
+        if i % 10 == 0 {
+            c.add_component(e, Position { x: i, y: 0 });
+            c.add_component(e, DensePosition { x: i, y: 0 });
+        }
+    }
+
+    c.with(|
+        em: EntityManager<'_>,
+        pos: Read<'_, Position>,
+        dense: Read<'_, DensePosition>,
+    | {
+        let start = Instant::now();
+        let mut vec_total = 0i64;
+        for _ in 0 .. 20 {
+            let mask = pos.mask();
+            for e in em.iter_mask(&mask) {
+                vec_total += i64::from(pos.get_component(e).unwrap().x);
+            }
+        }
+        let vec_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut dense_total = 0i64;
+        for _ in 0 .. 20 {
+            for p in dense.dense_iter() {
+                dense_total += i64::from(p.x);
+            }
+        }
+        let dense_time = start.elapsed();
+
+        assert_eq!(vec_total, dense_total);
+        println!("VecStorage: {:?}, DenseStorage: {:?}", vec_time, dense_time);
+    });
+}
+
+#[test]
+fn test_clone_filtered() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Health(i32);
+    component!(Health => Vec);
+    #[derive(Clone, Debug, PartialEq)]
+    struct Score(i32);
+    component!(Score => Map);
+
+    let mut c = Container::new();
+    c.register_component::<Health>();
+    c.register_component::<Score>();
+    c.register_component::<Position>();
+    let entities: Vec<_> = (0 .. 50)
+        .map(|i| {
+            let e = c.new_entity();
+            c.add_component(e, Position { x: i, y: i });
+            if i % 2 == 0 {
+                c.add_component(e, Health(i));
+            }
+            e
+        })
+        .collect();
+
+    let mask = c.mask_for::<Health>();
+    let mut copy = c.clone_filtered::<(Health, Score)>(&mask);
+
+    // Modifications after the copy shouldn't be seen by it
+    c.add_component(entities[0], Health(-1));
+    c.remove_entity(entities[2]);
+
+    let mut copy = std::thread::spawn(move || {
+        let mask = copy.mask_for::<Health>();
+        let es: Vec<_> = copy.iter_mask(&mask).collect();
+        assert_eq!(es.len(), 25);
+        for e in es {
+            let score = copy.get_component::<Health>(e).unwrap().0 * 10;
+            copy.add_component(e, Score(score));
+        }
+        copy
+    }).join().unwrap();
+    assert!(!copy.is_valid(entities[1]));
+    copy.add_component(entities[4], Health(1000));
+
+    c.merge_filtered::<(Score,)>(&copy);
+    assert_eq!(c.get_component::<Score>(entities[0]), Some(&Score(0)));
+    assert_eq!(c.get_component::<Health>(entities[0]), Some(&Health(-1)));
+    assert_eq!(c.get_component::<Score>(entities[1]), None);
+    assert_eq!(c.get_component::<Score>(entities[2]), None);
+    assert_eq!(c.get_component::<Score>(entities[6]), Some(&Score(60)));
+    assert_eq!(c.get_component::<Health>(entities[4]), Some(&Health(4)));
+}
+
+#[test]
+fn test_entity_map() {
+    let mut c = Container::new();
+    let mut map = EntityMap::new();
+    let a = c.new_entity();
+    let b = c.new_entity();
+    map.insert(a, 1);
+    map.insert(b, 2);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(a), Some(&1));
+
+    // The id of `a` gets reused for `new`
+    c.remove_entity(a);
+    let new = c.new_entity();
+    assert_eq!(map.get(new), None);
+    assert_eq!(map.insert(new, 3), None);
+    assert_eq!(map.get(a), None);
+    assert_eq!(map.remove(a), None);
+    assert_eq!(map.len(), 2);
+
+    *map.get_mut(b).unwrap() += 10;
+    let vals: Vec<_> = map.iter().collect();
+    assert_eq!(vals, vec![(new, &3), (b, &12)]);
+
+    map.retain(|e, _| e != new);
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.remove(b), Some(12));
+    assert!(map.is_empty());
+}
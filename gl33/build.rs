@@ -11,7 +11,7 @@ fn main() {
     let dest = Path::new(&out_dir);
 
     let mut file = BufWriter::new(File::create(&dest.join("bindings.rs")).unwrap());
-    Registry::new(Api::Gl, (3, 3), Profile::Core, Fallbacks::All, [])
+    Registry::new(Api::Gl, (3, 3), Profile::Core, Fallbacks::All, ["GL_ARB_robustness"])
          .write_bindings(GlobalGenerator, &mut file)
          .unwrap();
 }
@@ -0,0 +1,409 @@
+//! Generates `LuaUsable` implementations for plain structs.
+//!
+//! ```ignore
+//! #[derive(LuaBind)]
+//! #[lua(cell, methods, metatable = "script::support_getters_setters")]
+//! struct Counter {
+//!     #[lua(get, set)]
+//!     count: i32,
+//!     #[lua(get, name = "label")]
+//!     name: String,
+//! }
+//!
+//! #[lua_methods(cell)]
+//! impl Counter {
+//!     #[lua]
+//!     fn increment(&mut self, by: i32) -> i32 {
+//!         self.count += by;
+//!         self.count
+//!     }
+//! }
+//! ```
+//!
+//! Fields marked with `get` or `set` are exposed as `get_<name>`
+//! and `set_<name>` which `support_getters_setters` turns into
+//! properties. Setters require `cell` as the value must be stored
+//! as a `RefCell` to be modified.
+//!
+//! Methods in a `lua_methods` impl marked with `#[lua]` are exposed
+//! under their own name unless `name` is given. A `&Lua` first
+//! parameter is passed the engine, `String` and `&str` parameters
+//! are converted from lua strings and `String` or `&str` return
+//! values are converted into lua strings. All other parameters and return
+//! values must implement `Value`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input,
+    parse_quote,
+    Attribute,
+    AttributeArgs,
+    Data,
+    DeriveInput,
+    Error,
+    Fields,
+    FnArg,
+    Ident,
+    ImplItem,
+    ItemImpl,
+    Lit,
+    Meta,
+    NestedMeta,
+    Result,
+    ReturnType,
+    Signature,
+    Type,
+};
+
+/// The largest number of parameters supported by `lua::closureN`
+const MAX_PARAMS: usize = 8;
+
+/// Generates a `LuaUsable` implementation exposing the marked
+/// fields of the struct.
+#[proc_macro_derive(LuaBind, attributes(lua))]
+pub fn derive_lua_bind(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    lua_bind(&input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+/// Exposes the methods marked with `#[lua]` in the impl to a
+/// `LuaBind` type that uses `#[lua(methods)]`.
+#[proc_macro_attribute]
+pub fn lua_methods(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let item = parse_macro_input!(item as ItemImpl);
+    methods(&args, item)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[derive(Default)]
+struct TypeOptions {
+    cell: bool,
+    methods: bool,
+    metatable: Option<syn::Path>,
+}
+
+#[derive(Default)]
+struct FieldOptions {
+    get: bool,
+    set: bool,
+    name: Option<String>,
+}
+
+fn lua_bind(input: &DeriveInput) -> Result<TokenStream2> {
+    let mut opts = TypeOptions::default();
+    for_each_option(&input.attrs, |meta| {
+        match *meta {
+            Meta::Path(ref p) if p.is_ident("cell") => opts.cell = true,
+            Meta::Path(ref p) if p.is_ident("methods") => opts.methods = true,
+            Meta::NameValue(ref v) if v.path.is_ident("metatable") => {
+                opts.metatable = Some(lit_str(&v.lit)?.parse()?);
+            },
+            _ => return Err(Error::new_spanned(meta, "unknown lua option")),
+        }
+        Ok(())
+    })?;
+
+    let fields = match input.data {
+        Data::Struct(ref s) => match s.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(Error::new_spanned(&input.ident, "LuaBind requires named fields")),
+        },
+        _ => return Err(Error::new_spanned(&input.ident, "LuaBind can only be used on structs")),
+    };
+
+    let this_ty = this_type(opts.cell);
+    let mut bindings = Vec::new();
+    for field in fields {
+        let mut fopts = FieldOptions::default();
+        for_each_option(&field.attrs, |meta| {
+            match *meta {
+                Meta::Path(ref p) if p.is_ident("get") => fopts.get = true,
+                Meta::Path(ref p) if p.is_ident("set") => fopts.set = true,
+                Meta::NameValue(ref v) if v.path.is_ident("name") => {
+                    fopts.name = Some(lit_str(&v.lit)?.value());
+                },
+                _ => return Err(Error::new_spanned(meta, "unknown lua field option")),
+            }
+            Ok(())
+        })?;
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+        let name = fopts.name.unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_owned());
+
+        if fopts.get {
+            let get_name = format!("get_{}", name);
+            let borrow = if opts.cell {
+                quote!(let this = ::std::cell::RefCell::borrow(&this);)
+            } else {
+                quote!()
+            };
+            let (lua, value) = if is_string(ty) {
+                (quote!(lua), quote!(lua::Ref::new_string(lua, this.#ident.as_str())))
+            } else if is_str(ty) {
+                (quote!(lua), quote!(lua::Ref::new_string(lua, this.#ident)))
+            } else {
+                (quote!(_lua), quote!(::std::clone::Clone::clone(&this.#ident)))
+            };
+            bindings.push(quote! {
+                t.field(#get_name, lua::closure1(|#lua, this: #this_ty| {
+                    #borrow
+                    #value
+                }));
+            });
+        }
+        if fopts.set {
+            if !opts.cell {
+                return Err(Error::new_spanned(ident, "setters require #[lua(cell)] on the struct"));
+            }
+            if is_str(ty) {
+                return Err(Error::new_spanned(ty, "&str fields can't have setters"));
+            }
+            let set_name = format!("set_{}", name);
+            let (param, value) = if is_string(ty) {
+                (quote!(lua::Ref<String>), quote!(value.to_string()))
+            } else {
+                (quote!(#ty), quote!(value))
+            };
+            bindings.push(quote! {
+                t.field(#set_name, lua::closure2(|_lua, this: #this_ty, value: #param| {
+                    ::std::cell::RefCell::borrow_mut(&this).#ident = #value;
+                }));
+            });
+        }
+    }
+    if opts.methods {
+        bindings.push(quote!(Self::__lua_methods(t);));
+    }
+
+    let metatable = opts.metatable.map(|path| quote! {
+        fn metatable(t: &lua::TypeBuilder) {
+            #path(t);
+        }
+    });
+
+    // Avoid an unused parameter when nothing is exposed
+    let t = if bindings.is_empty() {
+        quote!(_t)
+    } else {
+        quote!(t)
+    };
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics lua::LuaUsable for #ident #ty_generics #where_clause {
+            #metatable
+
+            fn fields(#t: &lua::TypeBuilder) {
+                #(#bindings)*
+            }
+        }
+    })
+}
+
+fn methods(args: &[NestedMeta], mut item: ItemImpl) -> Result<TokenStream2> {
+    let mut cell = false;
+    for arg in args {
+        match *arg {
+            NestedMeta::Meta(Meta::Path(ref p)) if p.is_ident("cell") => cell = true,
+            _ => return Err(Error::new_spanned(arg, "unknown lua_methods option")),
+        }
+    }
+
+    let mut bindings = Vec::new();
+    for impl_item in &mut item.items {
+        let method = match *impl_item {
+            ImplItem::Method(ref mut method) => method,
+            _ => continue,
+        };
+        let mut exposed = false;
+        let mut name = None;
+        let mut attrs = Vec::with_capacity(method.attrs.len());
+        for attr in method.attrs.drain(..) {
+            if !attr.path.is_ident("lua") {
+                attrs.push(attr);
+                continue;
+            }
+            exposed = true;
+            if attr.tokens.is_empty() {
+                continue;
+            }
+            for_each_option(&[attr], |meta| {
+                match *meta {
+                    Meta::NameValue(ref v) if v.path.is_ident("name") => {
+                        name = Some(lit_str(&v.lit)?.value());
+                    },
+                    _ => return Err(Error::new_spanned(meta, "unknown lua method option")),
+                }
+                Ok(())
+            })?;
+        }
+        method.attrs = attrs;
+        if exposed {
+            let name = name.unwrap_or_else(|| method.sig.ident.to_string());
+            bindings.push(method_binding(&method.sig, &name, cell)?);
+        }
+    }
+
+    item.items.push(parse_quote! {
+        #[doc(hidden)]
+        pub fn __lua_methods(t: &lua::TypeBuilder) {
+            #(#bindings)*
+        }
+    });
+    Ok(quote!(#item))
+}
+
+fn method_binding(sig: &Signature, name: &str, cell: bool) -> Result<TokenStream2> {
+    let mut receiver = None;
+    let mut pass_lua = false;
+    let mut first = true;
+    let mut params: Vec<TokenStream2> = Vec::new();
+    let mut args: Vec<TokenStream2> = Vec::new();
+
+    for input in &sig.inputs {
+        let ty = match *input {
+            FnArg::Receiver(ref recv) => {
+                if recv.reference.is_none() {
+                    return Err(Error::new_spanned(recv, "methods exposed to lua can't take self by value"));
+                }
+                receiver = Some(recv.mutability.is_some());
+                continue;
+            },
+            FnArg::Typed(ref pat) => &*pat.ty,
+        };
+        if first && is_lua(ty) {
+            first = false;
+            pass_lua = true;
+            args.push(quote!(lua));
+            continue;
+        }
+        first = false;
+        let arg = Ident::new(&format!("arg{}", params.len()), Span::call_site());
+        if is_string(ty) {
+            params.push(quote!(#arg: lua::Ref<String>));
+            args.push(quote!(#arg.to_string()));
+        } else if is_str(ty) {
+            params.push(quote!(#arg: lua::Ref<String>));
+            args.push(quote!(&*#arg));
+        } else if let Type::Reference(_) = *ty {
+            return Err(Error::new_spanned(ty, "parameters of methods exposed to lua must be owned"));
+        } else {
+            params.push(quote!(#arg: #ty));
+            args.push(quote!(#arg));
+        }
+    }
+
+    let ident = &sig.ident;
+    let target = match receiver {
+        None => quote!(Self::#ident),
+        Some(false) if cell => quote!(::std::cell::RefCell::borrow(&this).#ident),
+        Some(false) => quote!((*this).#ident),
+        Some(true) if cell => quote!(::std::cell::RefCell::borrow_mut(&this).#ident),
+        Some(true) => return Err(Error::new_spanned(sig, "&mut self methods require #[lua_methods(cell)]")),
+    };
+    let call = quote!(#target(#(#args),*));
+    let returns_string = match sig.output {
+        ReturnType::Type(_, ref ty) => is_string(ty) || is_str(ty),
+        ReturnType::Default => false,
+    };
+    let body = if returns_string {
+        quote!(lua::Ref::new_string(lua, #call))
+    } else {
+        call
+    };
+
+    let mut closure_params = Vec::with_capacity(params.len() + 2);
+    closure_params.push(if pass_lua || returns_string { quote!(lua) } else { quote!(_lua) });
+    if receiver.is_some() {
+        let this_ty = this_type(cell);
+        closure_params.push(quote!(this: #this_ty));
+    }
+    let count = closure_params.len() - 1 + params.len();
+    closure_params.extend(params);
+    if count > MAX_PARAMS {
+        return Err(Error::new_spanned(sig, format!("methods exposed to lua may have at most {} parameters", MAX_PARAMS)));
+    }
+    let closure = if count == 0 {
+        Ident::new("closure", Span::call_site())
+    } else {
+        Ident::new(&format!("closure{}", count), Span::call_site())
+    };
+    Ok(quote! {
+        t.field(#name, lua::#closure(|#(#closure_params),*| {
+            #body
+        }));
+    })
+}
+
+/// The type of the reference to the value passed to the
+/// generated closures
+fn this_type(cell: bool) -> TokenStream2 {
+    if cell {
+        quote!(lua::CellRef<Self>)
+    } else {
+        quote!(lua::Ref<Self>)
+    }
+}
+
+/// Calls the function for each option in the `#[lua(...)]`
+/// attributes
+fn for_each_option<F>(attrs: &[Attribute], mut f: F) -> Result<()>
+    where F: FnMut(&Meta) -> Result<()>
+{
+    for attr in attrs.iter().filter(|v| v.path.is_ident("lua")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, "expected #[lua(...)]")),
+        };
+        for nested in &list.nested {
+            match *nested {
+                NestedMeta::Meta(ref meta) => f(meta)?,
+                NestedMeta::Lit(ref lit) => return Err(Error::new_spanned(lit, "unexpected literal")),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lit_str(lit: &Lit) -> Result<&syn::LitStr> {
+    match *lit {
+        Lit::Str(ref s) => Ok(s),
+        _ => Err(Error::new_spanned(lit, "expected a string")),
+    }
+}
+
+/// Returns whether the type is the named type without
+/// any generics
+fn is_named(ty: &Type, name: &str) -> bool {
+    match *ty {
+        Type::Path(ref p) => p.qself.is_none() && p.path.segments.last()
+            .map_or(false, |v| v.ident == name && v.arguments.is_empty()),
+        _ => false,
+    }
+}
+
+fn is_string(ty: &Type) -> bool {
+    is_named(ty, "String")
+}
+
+fn is_str(ty: &Type) -> bool {
+    match *ty {
+        Type::Reference(ref r) => r.mutability.is_none() && is_named(&r.elem, "str"),
+        _ => false,
+    }
+}
+
+fn is_lua(ty: &Type) -> bool {
+    match *ty {
+        Type::Reference(ref r) => r.mutability.is_none() && is_named(&r.elem, "Lua"),
+        _ => false,
+    }
+}
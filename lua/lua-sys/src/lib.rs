@@ -38,6 +38,13 @@ extern "C" {
 	pub fn luaopen_ffi(L: *mut lua_State) -> libc::c_int;
 
 	pub fn luaL_openlibs(L: *mut lua_State);
+
+	pub fn luaJIT_setmode(L: *mut lua_State, idx: libc::c_int, mode: libc::c_int) -> libc::c_int;
 }
 
+pub const LUAJIT_MODE_ENGINE: libc::c_int = 0;
+pub const LUAJIT_MODE_OFF: libc::c_int = 0x0000;
+pub const LUAJIT_MODE_ON: libc::c_int = 0x0100;
+pub const LUAJIT_MODE_FLUSH: libc::c_int = 0x0200;
+
 pub use self::lua::*;
\ No newline at end of file
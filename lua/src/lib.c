@@ -6,6 +6,10 @@ struct RustClosureData {
 // Save having to include the header
 extern void* lua_touserdata(void*, int);
 extern void lua_error(void*);
+extern int lua_sethook(void*, void (*)(void*, void*), int, int);
+extern void lua_pushboolean(void*, int);
+extern void lua_pushstring(void*, const char*);
+extern void lua_setfield(void*, int, const char*);
 
 int invoke_rust_closure(void* state) {
     struct RustClosureData* func = lua_touserdata(state, -10003);
@@ -14,4 +18,16 @@ int invoke_rust_closure(void* state) {
         lua_error(state);
     }
     return ret;
-}
\ No newline at end of file
+}
+
+// Count hook used by `Lua::set_execution_limit`. Once the limit is
+// reached the hook fires on every instruction so that a script
+// can't catch the error with pcall and carry on
+void execution_limit_hook(void* state, void* ar) {
+    (void) ar;
+    lua_sethook(state, execution_limit_hook, 8 /* LUA_MASKCOUNT */, 1);
+    lua_pushboolean(state, 1);
+    lua_setfield(state, -10000 /* LUA_REGISTRYINDEX */, "execution_limit_reached");
+    lua_pushstring(state, "execution limit reached");
+    lua_error(state);
+}
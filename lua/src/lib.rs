@@ -4,6 +4,7 @@
 #![allow(clippy::new_without_default)]
 
 extern crate lua_sys as sys;
+extern crate lua_derive;
 extern crate univercity_util as util;
 extern crate serde;
 #[macro_use]
@@ -11,6 +12,11 @@ extern crate serde;
 extern crate serde_derive;
 #[macro_use]
 extern crate failure;
+#[macro_use]
+extern crate bitflags;
+// Lets the code generated by `LuaBind` be tested in this crate
+#[cfg(test)]
+extern crate self as lua;
 
 use std::ffi::{CStr, CString};
 use std::ptr;
@@ -24,6 +30,7 @@ use util::FNVMap as HashMap;
 
 mod serde_support;
 pub use serde_support::{Deserializer, Serializer};
+pub use lua_derive::{LuaBind, lua_methods};
 
 /// Contains a lua scripting instance with all its state.
 #[derive(Clone)]
@@ -51,15 +58,110 @@ pub enum Scope {
     Registry
 }
 
+bitflags! {
+    /// A selection of the standard libraries to open in a
+    /// lua state
+    pub struct LibSet: u16 {
+        /// The base functions (`pairs`, `pcall` etc) and the
+        /// `coroutine` library
+        const BASE = 0b0000_0000_0001;
+        /// `require` and the `package` library. Can load native
+        /// libraries
+        const PACKAGE = 0b0000_0000_0010;
+        /// The `table` library
+        const TABLE = 0b0000_0000_0100;
+        /// The `io` library. Provides file system access
+        const IO = 0b0000_0000_1000;
+        /// The `os` library. Can run commands and remove files
+        const OS = 0b0000_0001_0000;
+        /// The `string` library
+        const STRING = 0b0000_0010_0000;
+        /// The `math` library
+        const MATH = 0b0000_0100_0000;
+        /// The `debug` library. Can break out of any sandbox
+        /// set up in lua
+        const DEBUG = 0b0000_1000_0000;
+        /// The `bit` library
+        const BIT = 0b0001_0000_0000;
+        /// The `jit` library
+        const JIT = 0b0010_0000_0000;
+        /// The `ffi` library. Only loadable via `require` so
+        /// this needs `PACKAGE` as well
+        const FFI = 0b0100_0000_0000;
+
+        /// Libraries that don't give access to anything outside
+        /// of the lua state
+        const SAFE = Self::BASE.bits | Self::TABLE.bits | Self::STRING.bits
+            | Self::MATH.bits | Self::BIT.bits;
+    }
+}
+
+/// Base functions that can load code from files or bytecode
+/// which can be used to escape a sandbox
+pub const UNSAFE_GLOBALS: &[&str] = &[
+    "dofile",
+    "loadfile",
+    "load",
+    "loadstring",
+];
+
 impl Lua {
-    /// Allocates a lua scripting instance
+    /// Allocates a lua scripting instance with every standard
+    /// library open
     pub fn new() -> Lua {
-        use std::mem;
-        let state = internal::LuaState(unsafe {
+        Lua::from_state(unsafe {
             let s = sys::luaL_newstate();
             sys::luaL_openlibs(s);
             s
-        }, None);
+        })
+    }
+
+    /// Allocates a lua scripting instance with only the selected
+    /// standard libraries open
+    ///
+    /// Unlike `new` this is suitable for running untrusted
+    /// scripts when `libs` is limited to `LibSet::SAFE`. If
+    /// `BASE` is selected the globals in `UNSAFE_GLOBALS` should
+    /// normally be removed via `remove_unsafe_globals` as well.
+    pub fn new_sandboxed(libs: LibSet) -> Lua {
+        Lua::from_state(unsafe {
+            let s = sys::luaL_newstate();
+            internal::open_libs(s, libs);
+            s
+        })
+    }
+
+    /// Allocates a lua scripting instance with every standard
+    /// library open which can't use more than `limit_bytes` of
+    /// memory.
+    ///
+    /// Calls into lua that would go over the limit fail with
+    /// `Error::MemoryLimit`. The limit is only enforced whilst lua
+    /// code is running so values created from rust outside of a
+    /// call always succeed, rust functions called by lua may fail
+    /// however. Fails if the lua build doesn't support custom
+    /// allocators (64 bit LuaJIT without GC64).
+    pub fn new_with_allocator(limit_bytes: usize) -> Result<Lua, Error> {
+        unsafe {
+            let s = internal::new_limited_state(limit_bytes)?;
+            sys::luaL_openlibs(s);
+            Ok(Lua::from_state(s))
+        }
+    }
+
+    /// Allocates a lua scripting instance like `new_sandboxed`
+    /// with the memory limit of `new_with_allocator`
+    pub fn new_sandboxed_with_allocator(libs: LibSet, limit_bytes: usize) -> Result<Lua, Error> {
+        unsafe {
+            let s = internal::new_limited_state(limit_bytes)?;
+            internal::open_libs(s, libs);
+            Ok(Lua::from_state(s))
+        }
+    }
+
+    fn from_state(s: *mut sys::lua_State) -> Lua {
+        use std::mem;
+        let state = internal::LuaState(s, None);
         let lua = Lua {
             state: Rc::new(state),
         };
@@ -75,10 +177,67 @@ impl Lua {
             let data = sys::lua_newuserdata(lua.state.0, mem::size_of::<BorrowTable>());
             ptr::write(data as *mut BorrowTable, borrow_store);
             sys::lua_setfield(lua.state.0, i32::from(sys::LUA_REGISTRYINDEX), b"borrow_store\0".as_ptr() as *const _);
+
+            let data = sys::lua_newuserdata(lua.state.0, mem::size_of::<internal::ExecutionLimit>());
+            ptr::write(data as *mut internal::ExecutionLimit, internal::ExecutionLimit {
+                instructions: Cell::new(None),
+                depth: Cell::new(0),
+            });
+            sys::lua_setfield(lua.state.0, i32::from(sys::LUA_REGISTRYINDEX), b"execution_limit\0".as_ptr() as *const _);
         }
         lua
     }
 
+    /// Limits the number of instructions each call into lua from
+    /// rust can run before it is stopped with `Error::Timeout`,
+    /// `None` removes the limit.
+    ///
+    /// Calls made from rust functions whilst lua is already
+    /// running share the limit of the outermost call. Scripts
+    /// catching the error with `pcall` will keep failing until the
+    /// outermost call returns. The JIT compiler is turned off whilst
+    /// a limit is set as compiled code doesn't check the limit.
+    pub fn set_execution_limit(&self, instructions: Option<u32>) {
+        let instructions = instructions
+            .map(|v| v.max(1).min(sys::libc::c_int::max_value() as u32) as sys::libc::c_int);
+        unsafe {
+            let limit = internal::execution_limit(self.state.0);
+            if limit.instructions.get().is_none() && instructions.is_some() {
+                sys::luaJIT_setmode(self.state.0, 0, sys::LUAJIT_MODE_ENGINE | sys::LUAJIT_MODE_FLUSH);
+                sys::luaJIT_setmode(self.state.0, 0, sys::LUAJIT_MODE_ENGINE | sys::LUAJIT_MODE_OFF);
+            } else if limit.instructions.get().is_some() && instructions.is_none() {
+                sys::luaJIT_setmode(self.state.0, 0, sys::LUAJIT_MODE_ENGINE | sys::LUAJIT_MODE_ON);
+            }
+            limit.instructions.set(instructions);
+        }
+    }
+
+    /// Returns the number of instructions each call into lua can
+    /// run for if limited
+    pub fn execution_limit(&self) -> Option<u32> {
+        unsafe {
+            internal::execution_limit(self.state.0).instructions.get()
+                .map(|v| v as u32)
+        }
+    }
+
+    /// Removes the named global. Used to strip functions scripts
+    /// shouldn't have access to
+    pub fn remove_global(&self, name: &str) {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            sys::lua_pushnil(self.state.0);
+            sys::lua_setfield(self.state.0, i32::from(sys::LUA_GLOBALSINDEX), c_name.as_ptr());
+        }
+    }
+
+    /// Removes every global in `UNSAFE_GLOBALS`
+    pub fn remove_unsafe_globals(&self) {
+        for name in UNSAFE_GLOBALS {
+            self.remove_global(name);
+        }
+    }
+
     /// Starts building a list of borrowed values that will be accessible
     /// during the execution of the function called at the end
     pub fn with_borrows(&self) -> BorrowBuilder {
@@ -107,42 +266,14 @@ impl Lua {
     ///
     /// This is works like lua's `loadstring` and will execute bytecode if passed it.
     pub fn execute_named_string<Ret: Value>(&self, name: &str, script: &str) -> Result<Ret, Error> {
-        let c_script = CString::new(script).unwrap();
-        let c_name = CString::new(name).unwrap();
         unsafe {
             // Used to validate the stack after use
             #[cfg(debug_assertions)]
             let orig_top = sys::lua_gettop(self.state.0);
 
-            // Load and parse the code into the vm
-            let status = sys::luaL_loadbuffer(self.state.0, c_script.as_ptr(), script.len(), c_name.as_ptr());
-            if status != 0 {
-                // Pop the error off the stack and return it
-                let ret = CStr::from_ptr(sys::lua_tolstring(self.state.0, -1, ptr::null_mut()));
-                internal::lua_pop(self.state.0, 1);
-                return Err(Error::Raw {
-                    msg: ret.to_string_lossy()
-                        .into_owned()
-                        .into_boxed_str()
-                });
-            }
-            // Invoke the loaded script with no arguments and the return size
+            // Invoke the loaded script with the return size
             // of what `Ret` holds
-            let res = sys::lua_pcall(self.state.0, 0, Ret::stack_size(), 0);
-            if res != 0 {
-                // Pop the error off the stack and return it
-                let ret: Ref<String> = match internal::InternalValue::to_rust(&self.state, -1) {
-                    Ok(val) => val,
-                    Err(err) => {
-                        internal::lua_pop(self.state.0, 1);
-                        return Err(err);
-                    }
-                };
-                internal::lua_pop(self.state.0, 1);
-                return Err(Error::Raw {
-                    msg: ret.to_string().into_boxed_str()
-                });
-            }
+            self.load_and_call(name, script, Ret::stack_size())?;
             // Try and make the type into something we can work with
             let ret = Ret::to_rust(&self.state, -Ret::stack_size());
             // Clean up the stack
@@ -156,6 +287,90 @@ impl Lua {
         }
     }
 
+    /// Loads and executes the passed string returning every value
+    /// the string returned.
+    ///
+    /// Unlike `execute_string` the number of values doesn't need to
+    /// be known ahead of time.
+    pub fn execute_string_multi(&self, script: &str) -> Result<Vec<Ref<Unknown>>, Error> {
+        self.execute_named_string_multi("<string>", script)
+    }
+
+    /// Loads and executes the passed string returning every value
+    /// the string returned. The loaded script will have the passed name
+    pub fn execute_named_string_multi(&self, name: &str, script: &str) -> Result<Vec<Ref<Unknown>>, Error> {
+        unsafe {
+            let base = sys::lua_gettop(self.state.0);
+            self.load_and_call(name, script, i32::from(sys::LUA_MULTRET))?;
+            let count = sys::lua_gettop(self.state.0) - base;
+            let mut ret = Vec::with_capacity(count as usize);
+            for idx in 0 .. count {
+                // Referencing a value can't fail so nothing is left
+                // on the stack by returning early
+                ret.push(<Ref<Unknown> as internal::InternalValue>::to_rust(&self.state, base + 1 + idx)?);
+            }
+            internal::lua_pop(self.state.0, count);
+
+            // Validate the stack size
+            #[cfg(debug_assertions)]
+            debug_assert_eq!(base, sys::lua_gettop(self.state.0));
+
+            Ok(ret)
+        }
+    }
+
+    /// Loads and calls the passed string leaving `results` values
+    /// on the stack, or every value returned if `LUA_MULTRET`.
+    ///
+    /// Nothing is left on the stack if an error is returned
+    unsafe fn load_and_call(&self, name: &str, script: &str, results: i32) -> Result<(), Error> {
+        let c_script = CString::new(script).unwrap();
+        let c_name = CString::new(name).unwrap();
+        // Load and parse the code into the vm
+        let status = sys::luaL_loadbuffer(self.state.0, c_script.as_ptr(), script.len(), c_name.as_ptr());
+        if status != 0 {
+            // Pop the error off the stack and return it
+            let ret = CStr::from_ptr(sys::lua_tolstring(self.state.0, -1, ptr::null_mut()));
+            let msg = ret.to_string_lossy()
+                .into_owned()
+                .into_boxed_str();
+            internal::lua_pop(self.state.0, 1);
+            return Err(Error::Raw { msg });
+        }
+        // Invoke the loaded script with no arguments
+        protected_call(&self.state, 0, results)
+    }
+
+    /// Loads the named chunk of source or bytecode without
+    /// executing it, returning the chunk as a function.
+    pub fn load_chunk(&self, name: &str, chunk: &[u8]) -> Result<Ref<Function>, Error> {
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            let status = sys::luaL_loadbuffer(self.state.0, chunk.as_ptr() as *const _, chunk.len(), c_name.as_ptr());
+            if status != 0 {
+                // Pop the error off the stack and return it
+                let ret = CStr::from_ptr(sys::lua_tolstring(self.state.0, -1, ptr::null_mut()));
+                let msg = ret.to_string_lossy()
+                    .into_owned()
+                    .into_boxed_str();
+                internal::lua_pop(self.state.0, 1);
+                return Err(Error::Raw { msg });
+            }
+            let r = sys::luaL_ref(self.state.0, i32::from(sys::LUA_REGISTRYINDEX));
+            Ok(Ref {
+                value: r,
+                state: Rc::downgrade(&internal::LuaState::root(self.state.clone())),
+                _t: PhantomData,
+            })
+        }
+    }
+
+    /// Loads bytecode created by `Bytecode::compile` without
+    /// executing it, returning the chunk as a function.
+    pub fn load_bytecode(&self, code: &Bytecode) -> Result<Ref<Function>, Error> {
+        self.load_chunk(&code.name, &code.data)
+    }
+
     /// Sets the value in the scope with the given name to the passed
     /// value.
     pub fn set<T: Value>(&self, scope: Scope, name: &str, val: T) {
@@ -170,6 +385,24 @@ impl Lua {
         }
     }
 
+    /// Creates an interned copy of the passed string.
+    ///
+    /// The string is created on the lua heap once and kept alive
+    /// until every copy of the returned `InternedStr` is dropped.
+    /// Useful for keys that are used repeatedly.
+    pub fn intern(&self, s: &str) -> InternedStr {
+        unsafe {
+            let state = internal::LuaState::root(self.state.clone());
+            internal::push_string(state.0, s);
+            let r = sys::luaL_ref(state.0, i32::from(sys::LUA_REGISTRYINDEX));
+            InternedStr(Rc::new(Ref {
+                value: r,
+                state: Rc::downgrade(&state),
+                _t: PhantomData,
+            }))
+        }
+    }
+
     /// Same as set but requires that the name is already null terminated
     pub unsafe fn set_unsafe<T: Value>(&self, scope: Scope, name: &[u8], val: T) {
         let scope = match scope {
@@ -209,6 +442,87 @@ impl Lua {
         val
     }
 
+    /// Runs `func` with a scope that values can be pinned to.
+    ///
+    /// Values pinned to the scope live on the lua stack instead of
+    /// in the registry so creating and using them is cheaper than
+    /// a `Ref`. Everything pinned is removed from the stack when the
+    /// scope ends. Useful for code that runs often and only needs
+    /// values for a short time.
+    pub fn scope<F, R>(&self, func: F) -> R
+        where F: FnOnce(&StackScope<'_>) -> R
+    {
+        let base = unsafe { sys::lua_gettop(self.state.0) };
+        let scope = StackScope {
+            lua: self,
+            base,
+            top: Cell::new(base),
+        };
+        func(&scope)
+    }
+
+    /// Performs an incremental step of garbage collection.
+    ///
+    /// Larger values of `kb` perform more work in the step.
+    /// Returns whether the step finished a collection cycle.
+    pub fn gc_step(&self, kb: i32) -> bool {
+        unsafe {
+            sys::lua_gc(self.state.0, i32::from(sys::LUA_GCSTEP), kb) == 1
+        }
+    }
+
+    /// Performs a full garbage collection cycle
+    pub fn gc_collect(&self) {
+        unsafe {
+            sys::lua_gc(self.state.0, i32::from(sys::LUA_GCCOLLECT), 0);
+        }
+    }
+
+    /// Sets how long the collector waits before starting a new
+    /// cycle as a percentage of the memory in use after the last
+    /// cycle. Returns the previous value.
+    pub fn gc_set_pause(&self, pause: i32) -> i32 {
+        unsafe {
+            sys::lua_gc(self.state.0, i32::from(sys::LUA_GCSETPAUSE), pause)
+        }
+    }
+
+    /// Sets the speed of the collector relative to the speed of
+    /// allocation as a percentage. Returns the previous value.
+    pub fn gc_set_step_multiplier(&self, mul: i32) -> i32 {
+        unsafe {
+            sys::lua_gc(self.state.0, i32::from(sys::LUA_GCSETSTEPMUL), mul)
+        }
+    }
+
+    /// Returns the amount of memory in use by lua in bytes
+    pub fn gc_count(&self) -> usize {
+        unsafe {
+            let kb = sys::lua_gc(self.state.0, i32::from(sys::LUA_GCCOUNT), 0);
+            let b = sys::lua_gc(self.state.0, i32::from(sys::LUA_GCCOUNTB), 0);
+            kb as usize * 1024 + b as usize
+        }
+    }
+
+    /// Returns the number of bytes currently allocated by lua.
+    ///
+    /// Exact for instances created with `new_with_allocator`,
+    /// otherwise the same as `gc_count`.
+    pub fn memory_used(&self) -> usize {
+        if let Some(memory) = unsafe { internal::memory_limit(self.state.0) } {
+            memory.used.get()
+        } else {
+            self.gc_count()
+        }
+    }
+
+    /// Returns the memory limit in bytes the instance was created
+    /// with if any
+    pub fn memory_limit(&self) -> Option<usize> {
+        unsafe { internal::memory_limit(self.state.0) }
+            .map(|v| v.limit)
+    }
+
     /// Get an immutable reference to a value borrowed
     /// via `BorrowBuilder::borrow`
     ///
@@ -474,22 +788,7 @@ impl <'a> BorrowBuilder<'a> {
 
             sys::lua_getfield(self.lua.state.0, i32::from(sys::LUA_GLOBALSINDEX), c_name.as_ptr());
             param.to_lua(&self.lua.state).unwrap();
-            let res = sys::lua_pcall(self.lua.state.0, P::stack_size(), Ret::stack_size(), 0);
-            if res != 0 {
-                // Pop the error off the stack and return it
-                let ret: Ref<String> = match internal::InternalValue::to_rust(&self.lua.state, -1) {
-                    Ok(val) => val,
-                    Err(err) => {
-                        internal::lua_pop(self.lua.state.0, 1);
-                        return Err(err);
-                    }
-                };
-                internal::lua_pop(self.lua.state.0, 1);
-                return Err(Error::Raw {
-                    msg: ret.to_string()
-                        .into_boxed_str()
-                });
-            }
+            protected_call(&self.lua.state, P::stack_size(), Ret::stack_size())?;
             // Try and make the type into something we can work with
             let ret = Ret::to_rust(&self.lua.state, -Ret::stack_size());
             // Clean up the stack
@@ -501,6 +800,15 @@ impl <'a> BorrowBuilder<'a> {
             ret
         }
     }
+
+    /// Invokes the pinned function passing the parameters to the
+    /// function and converting the result to the requested type.
+    pub fn invoke<P: Value, Ret: Value>(self, func: StackRef<'_, Function>, param: P) -> Result<Ret, Error> {
+        unsafe {
+            sys::lua_pushvalue(self.lua.state.0, func.idx);
+            invoke_top(&self.lua.state, param)
+        }
+    }
 }
 
 impl <'a> Drop for BorrowBuilder<'a> {
@@ -602,6 +910,87 @@ unsafe impl internal::InternalValue for bool {
     }
 }
 
+impl Value for String {}
+unsafe impl internal::InternalValue for String {
+    unsafe fn to_rust(state: &Rc<internal::LuaState>, idx: i32) -> Result<Self, Error> {
+        internal::copy_string(state.0, idx)
+            .and_then(|v| String::from_utf8(v).ok())
+            .ok_or(Error::TypeMismatch {
+                wanted: "String",
+            })
+    }
+
+    fn stack_size() -> i32 {
+        1
+    }
+
+    unsafe fn to_lua(self, state: &Rc<internal::LuaState>) -> Result<(), Error> {
+        internal::push_string(state.0, self);
+        Ok(())
+    }
+}
+
+/// Strings can only be passed to lua as borrowed values,
+/// use `String` or `Ref<String>` to get them from lua
+impl <'a> Value for &'a str {}
+unsafe impl <'a> internal::InternalValue for &'a str {
+    unsafe fn to_rust(_state: &Rc<internal::LuaState>, _idx: i32) -> Result<Self, Error> {
+        Err(Error::UnsupportedType {
+            ty: "&str",
+        })
+    }
+
+    fn stack_size() -> i32 {
+        1
+    }
+
+    unsafe fn to_lua(self, state: &Rc<internal::LuaState>) -> Result<(), Error> {
+        internal::push_string(state.0, self);
+        Ok(())
+    }
+}
+
+/// A lua string as raw bytes which may contain any data
+/// including NULs and invalid utf-8
+impl Value for Vec<u8> {}
+unsafe impl internal::InternalValue for Vec<u8> {
+    unsafe fn to_rust(state: &Rc<internal::LuaState>, idx: i32) -> Result<Self, Error> {
+        internal::copy_string(state.0, idx)
+            .ok_or(Error::TypeMismatch {
+                wanted: "String",
+            })
+    }
+
+    fn stack_size() -> i32 {
+        1
+    }
+
+    unsafe fn to_lua(self, state: &Rc<internal::LuaState>) -> Result<(), Error> {
+        internal::push_string(state.0, self);
+        Ok(())
+    }
+}
+
+/// Bytes can only be passed to lua as borrowed values,
+/// use `Vec<u8>` or `Ref<String>` to get them from lua
+impl <'a> Value for &'a [u8] {}
+unsafe impl <'a> internal::InternalValue for &'a [u8] {
+    unsafe fn to_rust(_state: &Rc<internal::LuaState>, _idx: i32) -> Result<Self, Error> {
+        Err(Error::UnsupportedType {
+            ty: "&[u8]",
+        })
+    }
+
+    fn stack_size() -> i32 {
+        1
+    }
+
+    unsafe fn to_lua(self, state: &Rc<internal::LuaState>) -> Result<(), Error> {
+        internal::push_string(state.0, self);
+        Ok(())
+    }
+}
+
 impl <T> Value for Option<T>
     where T: Value {}
 unsafe impl <T> internal::InternalValue for Option<T>
@@ -791,9 +1180,8 @@ impl Ref<String> {
     #[inline]
     pub fn new_string<S: Into<Vec<u8>>>(lua: &Lua, s: S) -> Ref<String> {
         unsafe {
-            let s = CString::new(s).unwrap();
             let state = internal::LuaState::root(lua.state.clone());
-            sys::lua_pushstring(state.0, s.as_ptr());
+            internal::push_string(state.0, s.into());
             let r = sys::luaL_ref(state.0, i32::from(sys::LUA_REGISTRYINDEX));
             Ref {
                 value: r,
@@ -824,6 +1212,9 @@ unsafe impl internal::InternalValue for Ref<String> {
     unsafe fn to_rust(state: &Rc<internal::LuaState>, idx: i32) -> Result<Self, Error> {
         if sys::lua_isstring(state.0, idx) != 0 {
             sys::lua_pushvalue(state.0, idx);
+            // Converts a number into a string so that the
+            // referenced value is always a string
+            sys::lua_tolstring(state.0, -1, ptr::null_mut());
             let r = sys::luaL_ref(state.0, i32::from(sys::LUA_REGISTRYINDEX));
             let state = internal::LuaState::root(state.clone());
             Ok(Ref {
@@ -848,23 +1239,36 @@ unsafe impl internal::InternalValue for Ref<String> {
     }
 }
 
-impl Deref for Ref<String> {
-    type Target = str;
-    fn deref(&self) -> &str {
+impl Ref<String> {
+    /// Returns the bytes of the string including any embedded
+    /// NULs.
+    ///
+    /// Lua strings aren't required to be utf-8 so unlike
+    /// dereferencing this works with binary data.
+    pub fn as_bytes(&self) -> &[u8] {
         unsafe {
             let state = if let Some(state) = self.state.upgrade() {
                 state
             } else {
-                return ""
+                return &[]
             };
             sys::lua_rawgeti(state.0, i32::from(sys::LUA_REGISTRYINDEX), self.value);
-            let cstr = CStr::from_ptr(sys::lua_tolstring(state.0, -1, ptr::null_mut()));
+            // Kept alive by the registry
+            let bytes = internal::string_bytes(state.0, -1);
             internal::lua_pop(state.0, 1);
-            cstr.to_str().unwrap_or("")
+            bytes
         }
     }
 }
 
+impl Deref for Ref<String> {
+    type Target = str;
+    /// Returns an empty string if the string isn't valid utf-8
+    fn deref(&self) -> &str {
+        std::str::from_utf8(self.as_bytes()).unwrap_or("")
+    }
+}
+
 impl Display for Ref<String> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         Display::fmt(self.deref(), f)
@@ -877,6 +1281,53 @@ impl Debug for Ref<String> {
     }
 }
 
+/// A string kept alive on the lua heap.
+///
+/// Unlike `Ref<String>`, cloning is cheap and doesn't create a new
+/// registry reference so it can be passed by value as a table key
+/// or value without allocating.
+///
+/// This type is created by the `Lua::intern` method
+#[derive(Clone)]
+pub struct InternedStr(Rc<Ref<String>>);
+
+impl Value for InternedStr {}
+
+unsafe impl internal::InternalValue for InternedStr {
+    unsafe fn to_rust(state: &Rc<internal::LuaState>, idx: i32) -> Result<Self, Error> {
+        Ref::<String>::to_rust(state, idx)
+            .map(|v| InternedStr(Rc::new(v)))
+    }
+
+    fn stack_size() -> i32 {
+        1
+    }
+
+    unsafe fn to_lua(self, state: &Rc<internal::LuaState>) -> Result<(), Error> {
+        sys::lua_rawgeti(state.0, i32::from(sys::LUA_REGISTRYINDEX), self.0.value);
+        Ok(())
+    }
+}
+
+impl Deref for InternedStr {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &**self.0
+    }
+}
+
+impl Display for InternedStr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(self.deref(), f)
+    }
+}
+
+impl Debug for InternedStr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Debug::fmt(self.deref(), f)
+    }
+}
+
 // Tables
 
 /// A lua table.
@@ -899,15 +1350,57 @@ impl Ref<Table> {
         }
     }
 
-    /// Inserts the passed value into the table with the given key
+    /// Creates an empty table on the lua heap with space
+    /// preallocated for `narr` array elements and `nrec`
+    /// non-array elements.
     #[inline]
-    pub fn insert<K, V>(&self, k: K, v: V)
-        where K: Value,
-              V: Value
-    {
+    pub fn with_capacity(lua: &Lua, narr: i32, nrec: i32) -> Ref<Table> {
         unsafe {
-            let state = if let Some(state) = self.state.upgrade() {
-                state
+            sys::lua_createtable(lua.state.0, narr, nrec);
+            let r = sys::luaL_ref(lua.state.0, i32::from(sys::LUA_REGISTRYINDEX));
+            Ref {
+                value: r,
+                state: Rc::downgrade(&internal::LuaState::root(lua.state.clone())),
+                _t: PhantomData,
+            }
+        }
+    }
+
+    /// Inserts every key/value pair from the iterator into
+    /// the table.
+    ///
+    /// Unlike calling `insert` per a value the table is only
+    /// fetched from the registry once for the whole batch.
+    pub fn extend_from_iter<K, V, I>(&self, iter: I)
+        where K: Value,
+              V: Value,
+              I: IntoIterator<Item=(K, V)>,
+    {
+        unsafe {
+            let state = if let Some(state) = self.state.upgrade() {
+                state
+            } else {
+                return
+            };
+            sys::lua_rawgeti(state.0, i32::from(sys::LUA_REGISTRYINDEX), self.value);
+            for (k, v) in iter {
+                k.to_lua(&state).unwrap();
+                v.to_lua(&state).unwrap();
+                sys::lua_rawset(state.0, -3);
+            }
+            internal::lua_pop(state.0, 1);
+        }
+    }
+
+    /// Inserts the passed value into the table with the given key
+    #[inline]
+    pub fn insert<K, V>(&self, k: K, v: V)
+        where K: Value,
+              V: Value
+    {
+        unsafe {
+            let state = if let Some(state) = self.state.upgrade() {
+                state
             } else {
                 return
             };
@@ -943,6 +1436,73 @@ impl Ref<Table> {
         }
     }
 
+    /// Gets the value with the given key from the table as lua
+    /// would with `tbl[k]`.
+    ///
+    /// Unlike `get` this respects the table's `__index` metamethod
+    /// and returns any error raised whilst indexing the table or
+    /// converting the value instead of hiding it. Use `Option<V>`
+    /// to allow for missing values.
+    pub fn get_protected<K, V>(&self, k: K) -> Result<V, Error>
+        where K: Value,
+              V: Value
+    {
+        unsafe extern "C" fn get_value(state: *mut sys::lua_State) -> sys::libc::c_int {
+            // Stack: table, key
+            sys::lua_gettable(state, 1);
+            1
+        }
+        unsafe {
+            let state = if let Some(state) = self.state.upgrade() {
+                state
+            } else {
+                return Err(Error::Shutdown)
+            };
+            let top = sys::lua_gettop(state.0);
+            sys::lua_pushcclosure(state.0, Some(get_value), 0);
+            sys::lua_rawgeti(state.0, i32::from(sys::LUA_REGISTRYINDEX), self.value);
+            if let Err(err) = k.to_lua(&state) {
+                sys::lua_settop(state.0, top);
+                return Err(err);
+            }
+            protected_call(&state, 2, 1)?;
+            let val = V::to_rust(&state, -1);
+            internal::lua_pop(state.0, 1);
+            val
+        }
+    }
+
+    /// Inserts the passed value into the table with the given key
+    /// as lua would with `tbl[k] = v`.
+    ///
+    /// Unlike `insert` this respects the table's `__newindex`
+    /// metamethod and returns any error raised whilst doing so.
+    pub fn insert_protected<K, V>(&self, k: K, v: V) -> Result<(), Error>
+        where K: Value,
+              V: Value
+    {
+        unsafe extern "C" fn set_value(state: *mut sys::lua_State) -> sys::libc::c_int {
+            // Stack: table, key, value
+            sys::lua_settable(state, 1);
+            0
+        }
+        unsafe {
+            let state = if let Some(state) = self.state.upgrade() {
+                state
+            } else {
+                return Err(Error::Shutdown)
+            };
+            let top = sys::lua_gettop(state.0);
+            sys::lua_pushcclosure(state.0, Some(set_value), 0);
+            sys::lua_rawgeti(state.0, i32::from(sys::LUA_REGISTRYINDEX), self.value);
+            if let Err(err) = k.to_lua(&state).and_then(|_| v.to_lua(&state)) {
+                sys::lua_settop(state.0, top);
+                return Err(err);
+            }
+            protected_call(&state, 3, 0)
+        }
+    }
+
     /// Returns the 'length' of this table.
     ///
     /// This is the same as lua's `#` operator. Only returns
@@ -986,6 +1546,70 @@ impl Ref<Table> {
     }
 }
 
+extern "C" {
+    fn execution_limit_hook(state: *mut sys::lua_State, ar: *mut sys::lua_Debug);
+}
+
+/// Calls the function below the `args` arguments at the top of
+/// the stack in protected mode.
+///
+/// On failure the error is popped from the stack and returned,
+/// otherwise the `results` remain on the stack. The outermost
+/// call is limited by the state's execution limit if it has one.
+unsafe fn protected_call(state: &Rc<internal::LuaState>, args: i32, results: i32) -> Result<(), Error> {
+    let limit = internal::execution_limit(state.0);
+    let depth = limit.depth.get();
+    if depth == 0 {
+        if let Some(instructions) = limit.instructions.get() {
+            sys::lua_sethook(state.0, Some(execution_limit_hook), i32::from(sys::LUA_MASKCOUNT), instructions);
+        }
+    }
+    let memory = internal::memory_limit(state.0);
+    if let Some(memory) = memory.filter(|_| depth == 0) {
+        memory.enforced.set(true);
+    }
+    limit.depth.set(depth + 1);
+    let res = sys::lua_pcall(state.0, args, results, 0);
+    limit.depth.set(depth);
+
+    let reached = internal::limit_reached(state.0, depth == 0);
+    if depth == 0 && limit.instructions.get().is_some() {
+        sys::lua_sethook(state.0, None, 0, 0);
+    }
+    let out_of_memory = if let Some(memory) = memory {
+        let exceeded = memory.exceeded.get();
+        if depth == 0 {
+            memory.enforced.set(false);
+            memory.exceeded.set(false);
+        }
+        exceeded
+    } else {
+        false
+    };
+    if res != 0 && reached {
+        internal::lua_pop(state.0, 1);
+        return Err(Error::Timeout);
+    }
+    if res == i32::from(sys::LUA_ERRMEM) && out_of_memory {
+        internal::lua_pop(state.0, 1);
+        return Err(Error::MemoryLimit);
+    }
+    if res != 0 {
+        let ret: Ref<String> = match internal::InternalValue::to_rust(state, -1) {
+            Ok(val) => val,
+            Err(err) => {
+                internal::lua_pop(state.0, 1);
+                return Err(err);
+            }
+        };
+        internal::lua_pop(state.0, 1);
+        return Err(Error::Raw {
+            msg: ret.to_string().into_boxed_str()
+        });
+    }
+    Ok(())
+}
+
 /// Deserializes the given type from the table
 #[allow(clippy::redundant_closure)]
 pub fn from_table<T>(tbl: &Ref<Table>) -> Result<T, Error>
@@ -1205,22 +1829,7 @@ impl Ref<Function> {
 
             sys::lua_rawgeti(state.0, i32::from(sys::LUA_REGISTRYINDEX), self.value);
             param.to_lua(&state).unwrap();
-            let res = sys::lua_pcall(state.0, P::stack_size(), Ret::stack_size(), 0);
-            if res != 0 {
-                // Pop the error off the stack and return it
-                let ret: Ref<String> = match internal::InternalValue::to_rust(&state, -1) {
-                    Ok(val) => val,
-                    Err(err) => {
-                        internal::lua_pop(state.0, 1);
-                        return Err(err);
-                    }
-                };
-                internal::lua_pop(state.0, 1);
-                return Err(Error::Raw {
-                    msg: ret.to_string()
-                        .into_boxed_str()
-                });
-            }
+            protected_call(&state, P::stack_size(), Ret::stack_size())?;
             // Try and make the type into something we can work with
             let ret = Ret::to_rust(&state, -Ret::stack_size());
             // Clean up the stack
@@ -1346,6 +1955,17 @@ impl TypeBuilder {
         }
     }
 
+    /// Overloads the operator for the type currently being built.
+    ///
+    /// Must be used from `LuaUsable::metatable`. The function is
+    /// passed both operands, for `Operator::Unm` both are the same
+    /// value.
+    pub fn operator<T>(&self, op: Operator, func: T)
+        where T: Value
+    {
+        self.field(op.metamethod(), func);
+    }
+
     pub fn metatable<F>(&self, f: F)
         where F: FnOnce(&TypeBuilder)
     {
@@ -1357,6 +1977,55 @@ impl TypeBuilder {
     }
 }
 
+/// Operators that can be overloaded via `TypeBuilder::operator`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    /// `a + b`
+    Add,
+    /// `a - b`
+    Sub,
+    /// `a * b`
+    Mul,
+    /// `a / b`
+    Div,
+    /// `a % b`
+    Mod,
+    /// `a ^ b`
+    Pow,
+    /// `-a`
+    Unm,
+    /// `a .. b`
+    Concat,
+    /// `a == b`. Only used when both values are native
+    /// values of the same type
+    Eq,
+    /// `a < b`
+    Lt,
+    /// `a <= b`
+    Le,
+    /// `tostring(a)`
+    ToString,
+}
+
+impl Operator {
+    fn metamethod(self) -> &'static str {
+        match self {
+            Operator::Add => "__add",
+            Operator::Sub => "__sub",
+            Operator::Mul => "__mul",
+            Operator::Div => "__div",
+            Operator::Mod => "__mod",
+            Operator::Pow => "__pow",
+            Operator::Unm => "__unm",
+            Operator::Concat => "__concat",
+            Operator::Eq => "__eq",
+            Operator::Lt => "__lt",
+            Operator::Le => "__le",
+            Operator::ToString => "__tostring",
+        }
+    }
+}
+
 type UserdataTable = RefCell<HashMap<any::TypeId, i32>>;
 
 impl <T> Ref<T>
@@ -1434,6 +2103,57 @@ impl <T> Ref<T>
     }
 }
 
+/// A value on the lua heap that can be modified from rust.
+///
+/// Short for `Ref<RefCell<T>>` which is how values that lua
+/// functions modify are normally stored
+pub type CellRef<T> = Ref<RefCell<T>>;
+
+impl <T> Ref<RefCell<T>>
+    where T: LuaUsable {
+
+    /// Places the value on the lua heap inside of a `RefCell`
+    /// so that it can be modified via `borrow_mut`
+    pub fn new_cell(lua: &Lua, val: T) -> CellRef<T> {
+        Ref::new(lua, RefCell::new(val))
+    }
+
+    /// Immutably borrows the value
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is mutably borrowed
+    pub fn borrow(&self) -> std::cell::Ref<'_, T> {
+        RefCell::borrow(self)
+    }
+
+    /// Mutably borrows the value
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is already borrowed
+    pub fn borrow_mut(&self) -> std::cell::RefMut<'_, T> {
+        RefCell::borrow_mut(self)
+    }
+
+    /// Immutably borrows the value, failing with `Error::Borrowed`
+    /// instead of panicking if it is mutably borrowed.
+    ///
+    /// Useful in functions called by lua where a script could
+    /// pass the value back in whilst it is borrowed
+    pub fn try_borrow(&self) -> Result<std::cell::Ref<'_, T>, Error> {
+        RefCell::try_borrow(self)
+            .map_err(|_| Error::Borrowed { ty: any::type_name::<T>() })
+    }
+
+    /// Mutably borrows the value, failing with `Error::Borrowed`
+    /// instead of panicking if it is already borrowed
+    pub fn try_borrow_mut(&self) -> Result<std::cell::RefMut<'_, T>, Error> {
+        RefCell::try_borrow_mut(self)
+            .map_err(|_| Error::Borrowed { ty: any::type_name::<T>() })
+    }
+}
+
 impl <T> Deref for Ref<T>
     where T: LuaUsable
 {
@@ -1537,6 +2257,358 @@ impl <T> Drop for Ref<T> {
     }
 }
 
+// Stack scopes
+
+/// A scope that values can be pinned to on the lua stack.
+///
+/// Created by `Lua::scope`
+pub struct StackScope<'a> {
+    lua: &'a Lua,
+    /// The top of the stack when the scope was created
+    base: i32,
+    /// The top of the stack after the last value was pinned
+    top: Cell<i32>,
+}
+
+impl <'a> StackScope<'a> {
+    /// Checks that the scope's values are at the top of the
+    /// stack before pinning another.
+    ///
+    /// Values pinned to an outer scope whilst a nested one is
+    /// active would be removed along with the nested scope's.
+    unsafe fn reserve(&self, slots: i32) {
+        assert_eq!(sys::lua_gettop(self.lua.state.0), self.top.get(), "pinned to a scope whilst a nested scope is active");
+        assert!(sys::lua_checkstack(self.lua.state.0, slots) != 0, "lua stack overflow");
+    }
+
+    /// Marks the value at the top of the stack as pinned
+    unsafe fn pinned<T>(&self) -> StackRef<'_, T> {
+        let idx = sys::lua_gettop(self.lua.state.0);
+        self.top.set(idx);
+        StackRef {
+            lua: self.lua,
+            idx,
+            _t: PhantomData,
+        }
+    }
+
+    /// Pins the value to the scope
+    pub fn push<V: Value>(&self, val: V) -> Result<StackRef<'_, Unknown>, Error> {
+        if V::stack_size() != 1 {
+            return Err(Error::UnsupportedType {
+                ty: "multiple values",
+            });
+        }
+        unsafe {
+            self.reserve(1);
+            if let Err(err) = val.to_lua(&self.lua.state) {
+                sys::lua_settop(self.lua.state.0, self.top.get());
+                return Err(err);
+            }
+            Ok(self.pinned())
+        }
+    }
+
+    /// Pins the referenced value to the scope.
+    ///
+    /// Unlike cloning the `Ref` this doesn't create a new
+    /// reference in the registry.
+    pub fn pin<T>(&self, val: &Ref<T>) -> StackRef<'_, T> {
+        unsafe {
+            self.reserve(1);
+            sys::lua_rawgeti(self.lua.state.0, i32::from(sys::LUA_REGISTRYINDEX), val.value);
+            self.pinned()
+        }
+    }
+
+    /// Pins a new string to the scope
+    pub fn string<S: AsRef<[u8]>>(&self, s: S) -> StackRef<'_, String> {
+        unsafe {
+            self.reserve(1);
+            internal::push_string(self.lua.state.0, s);
+            self.pinned()
+        }
+    }
+
+    /// Pins the named global to the scope
+    pub fn global(&self, name: &str) -> StackRef<'_, Unknown> {
+        unsafe {
+            self.reserve(1);
+            internal::push_string(self.lua.state.0, name);
+            sys::lua_rawget(self.lua.state.0, i32::from(sys::LUA_GLOBALSINDEX));
+            self.pinned()
+        }
+    }
+
+    /// Pins the named global function to the scope.
+    ///
+    /// Returns an error if the global isn't a function
+    pub fn function(&self, name: &str) -> Result<StackRef<'_, Function>, Error> {
+        self.global(name).as_function()
+            .ok_or(Error::TypeMismatch {
+                wanted: "Function",
+            })
+    }
+
+    /// Pins the value with the given key in the table to the
+    /// scope
+    pub fn field<K>(&self, table: StackRef<'_, Table>, k: K) -> StackRef<'_, Unknown>
+        where K: Value
+    {
+        unsafe {
+            self.reserve(1 + K::stack_size());
+            k.to_lua(&self.lua.state).unwrap();
+            sys::lua_rawget(self.lua.state.0, table.idx);
+            self.pinned()
+        }
+    }
+
+    /// Runs `func` with a scope nested within this one.
+    ///
+    /// Values pinned to the nested scope are removed when it
+    /// ends, values from this scope can still be used within it
+    /// but no more can be pinned to it until the nested scope
+    /// ends. Useful for loops that would otherwise fill the stack.
+    pub fn scope<F, R>(&self, func: F) -> R
+        where F: FnOnce(&StackScope<'_>) -> R
+    {
+        self.lua.scope(func)
+    }
+}
+
+impl <'a> Drop for StackScope<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            sys::lua_settop(self.lua.state.0, self.base);
+        }
+    }
+}
+
+/// A value pinned to a `StackScope`.
+///
+/// Copying this is free and it can be passed to lua like a `Ref`.
+/// It can't be returned from lua or outlive its scope, `to_ref`
+/// can be used to keep the value for longer.
+pub struct StackRef<'s, T> {
+    lua: &'s Lua,
+    idx: i32,
+    _t: PhantomData<T>,
+}
+
+impl <'s, T> Clone for StackRef<'s, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl <'s, T> Copy for StackRef<'s, T> {}
+
+impl <'s, T> StackRef<'s, T> {
+    /// Returns whether the value is nil
+    pub fn is_nil(&self) -> bool {
+        unsafe {
+            sys::lua_type(self.lua.state.0, self.idx) == i32::from(sys::LUA_TNIL)
+        }
+    }
+
+    /// Tries to convert the value into the requested type
+    pub fn try_convert<V: Value>(&self) -> Result<V, Error> {
+        unsafe {
+            V::to_rust(&self.lua.state, self.idx)
+        }
+    }
+
+    /// Creates a reference in the registry to the value so that
+    /// it can outlive the scope
+    pub fn to_ref(&self) -> Ref<T> {
+        unsafe {
+            sys::lua_pushvalue(self.lua.state.0, self.idx);
+            let r = sys::luaL_ref(self.lua.state.0, i32::from(sys::LUA_REGISTRYINDEX));
+            Ref {
+                value: r,
+                state: Rc::downgrade(&internal::LuaState::root(self.lua.state.clone())),
+                _t: PhantomData,
+            }
+        }
+    }
+
+    fn cast<O>(self, ty: i32) -> Option<StackRef<'s, O>> {
+        unsafe {
+            if sys::lua_type(self.lua.state.0, self.idx) == ty {
+                Some(StackRef {
+                    lua: self.lua,
+                    idx: self.idx,
+                    _t: PhantomData,
+                })
+            } else {
+                None
+            }
+        }
+    }
+}
+
+impl <'s> StackRef<'s, Unknown> {
+    /// Returns the value as a table if it is one
+    pub fn as_table(self) -> Option<StackRef<'s, Table>> {
+        self.cast(i32::from(sys::LUA_TTABLE))
+    }
+
+    /// Returns the value as a function if it is one
+    pub fn as_function(self) -> Option<StackRef<'s, Function>> {
+        self.cast(i32::from(sys::LUA_TFUNCTION))
+    }
+}
+
+impl <'s> StackRef<'s, Table> {
+    /// Inserts the value into the table with the given key
+    pub fn insert<K, V>(&self, k: K, v: V)
+        where K: Value,
+              V: Value
+    {
+        unsafe {
+            k.to_lua(&self.lua.state).unwrap();
+            v.to_lua(&self.lua.state).unwrap();
+            sys::lua_rawset(self.lua.state.0, self.idx);
+        }
+    }
+
+    /// Gets the value with the given key from the table.
+    ///
+    /// Returns `None` if the value doesn't exist or the
+    /// value can't be converted into the required type
+    pub fn get<K, V>(&self, k: K) -> Option<V>
+        where K: Value,
+              V: Value
+    {
+        unsafe {
+            k.to_lua(&self.lua.state).unwrap();
+            sys::lua_rawget(self.lua.state.0, self.idx);
+            let val = V::to_rust(&self.lua.state, -1);
+            internal::lua_pop(self.lua.state.0, 1);
+            val.ok()
+        }
+    }
+
+    /// Returns the length of the table
+    ///
+    /// This is the same as lua's `#` operator
+    pub fn length(&self) -> i32 {
+        unsafe {
+            sys::lua_objlen(self.lua.state.0, self.idx) as i32
+        }
+    }
+}
+
+impl <'s> StackRef<'s, Function> {
+    /// Invokes the function passing the parameters to the
+    /// function and converting the result to the requested type.
+    pub fn invoke<P: Value, Ret: Value>(&self, param: P) -> Result<Ret, Error> {
+        unsafe {
+            sys::lua_pushvalue(self.lua.state.0, self.idx);
+            invoke_top(&self.lua.state, param)
+        }
+    }
+}
+
+impl <'s> StackRef<'s, String> {
+    /// Returns the bytes of the string including any embedded
+    /// NULs
+    pub fn as_bytes(&self) -> &'s [u8] {
+        unsafe {
+            // Kept alive by the stack until the scope ends
+            internal::string_bytes(self.lua.state.0, self.idx)
+        }
+    }
+}
+
+impl <'s, T> Value for StackRef<'s, T> {}
+unsafe impl <'s, T> internal::InternalValue for StackRef<'s, T> {
+    unsafe fn to_rust(_state: &Rc<internal::LuaState>, _idx: i32) -> Result<Self, Error> {
+        Err(Error::UnsupportedType {
+            ty: "StackRef",
+        })
+    }
+
+    fn stack_size() -> i32 {
+        1
+    }
+
+    unsafe fn to_lua(self, state: &Rc<internal::LuaState>) -> Result<(), Error> {
+        sys::lua_pushvalue(state.0, self.idx);
+        Ok(())
+    }
+}
+
+/// Calls the function at the top of the stack with the parameters
+/// and converts the result, leaving the stack as it was before
+/// the function was pushed
+unsafe fn invoke_top<P: Value, Ret: Value>(state: &Rc<internal::LuaState>, param: P) -> Result<Ret, Error> {
+    let top = sys::lua_gettop(state.0) - 1;
+    if let Err(err) = param.to_lua(state) {
+        sys::lua_settop(state.0, top);
+        return Err(err);
+    }
+    protected_call(state, P::stack_size(), Ret::stack_size())?;
+    let ret = Ret::to_rust(state, -Ret::stack_size());
+    internal::lua_pop(state.0, Ret::stack_size());
+    debug_assert_eq!(top, sys::lua_gettop(state.0));
+    ret
+}
+
+/// A compiled chunk of lua that can be loaded by `Lua::load_bytecode`.
+///
+/// Compiling doesn't require a `Lua` instance so this can be
+/// created on any thread and moved to the thread that owns
+/// the instance.
+#[derive(Clone, Debug)]
+pub struct Bytecode {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl Bytecode {
+    /// Compiles the source of a chunk with the passed name without
+    /// executing it.
+    ///
+    /// Returns the syntax error if the source fails to parse
+    pub fn compile(name: &str, source: &str) -> Result<Bytecode, Error> {
+        unsafe extern "C" fn write(_: *mut sys::lua_State, p: *const sys::libc::c_void, sz: usize, ud: *mut sys::libc::c_void) -> sys::libc::c_int {
+            let out = &mut *(ud as *mut Vec<u8>);
+            out.extend_from_slice(std::slice::from_raw_parts(p as *const u8, sz));
+            0
+        }
+        let c_name = CString::new(name).unwrap();
+        unsafe {
+            // A bare state is enough to parse the source and keeps
+            // this independent of any other instance
+            let state = sys::luaL_newstate();
+            let status = sys::luaL_loadbuffer(state, source.as_ptr() as *const _, source.len(), c_name.as_ptr());
+            let ret = if status != 0 {
+                let msg = CStr::from_ptr(sys::lua_tolstring(state, -1, ptr::null_mut()));
+                Err(Error::Raw {
+                    msg: msg.to_string_lossy()
+                        .into_owned()
+                        .into_boxed_str()
+                })
+            } else {
+                let mut data = Vec::with_capacity(source.len());
+                sys::lua_dump(state, Some(write), &mut data as *mut Vec<u8> as *mut _);
+                Ok(Bytecode {
+                    name: name.to_owned(),
+                    data,
+                })
+            };
+            sys::lua_close(state);
+            ret
+        }
+    }
+
+    /// Returns the name of the chunk
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 /// Standard lua error type
 #[derive(Debug, PartialEq, Eq, Fail)]
 pub enum Error {
@@ -1570,6 +2642,20 @@ pub enum Error {
     },
     #[fail(display = "the lua instance has be shutdown")]
     Shutdown,
+    /// The script ran for longer than the execution limit
+    /// set by `Lua::set_execution_limit`
+    #[fail(display = "script exceeded its execution limit")]
+    Timeout,
+    /// The script tried to use more memory than the limit
+    /// passed to `Lua::new_with_allocator`
+    #[fail(display = "script exceeded its memory limit")]
+    MemoryLimit,
+    /// A value stored in a `CellRef` was borrowed whilst
+    /// already being modified
+    #[fail(display = "{} is already borrowed", ty)]
+    Borrowed {
+        ty: &'static str,
+    },
 }
 
 macro_rules! impl_tuple {
@@ -1765,6 +2851,138 @@ mod internal {
     use super::*;
 
     pub struct LuaState(pub *mut sys::lua_State, pub Option<Rc<LuaState>>);
+
+    /// Stored in the registry to track the limit set by
+    /// `Lua::set_execution_limit`
+    pub struct ExecutionLimit {
+        pub instructions: Cell<Option<sys::libc::c_int>>,
+        /// The number of protected calls currently running
+        pub depth: Cell<u32>,
+    }
+
+    pub unsafe fn execution_limit<'a>(state: *mut sys::lua_State) -> &'a ExecutionLimit {
+        sys::lua_getfield(state, i32::from(sys::LUA_REGISTRYINDEX), b"execution_limit\0".as_ptr() as *const _);
+        let limit = sys::lua_touserdata(state, -1) as *const ExecutionLimit;
+        lua_pop(state, 1);
+        &*limit
+    }
+
+    /// Returns whether the execution limit was reached, optionally
+    /// clearing the flag set by the hook
+    pub unsafe fn limit_reached(state: *mut sys::lua_State, clear: bool) -> bool {
+        sys::lua_getfield(state, i32::from(sys::LUA_REGISTRYINDEX), b"execution_limit_reached\0".as_ptr() as *const _);
+        let reached = sys::lua_toboolean(state, -1) != 0;
+        lua_pop(state, 1);
+        if reached && clear {
+            sys::lua_pushnil(state);
+            sys::lua_setfield(state, i32::from(sys::LUA_REGISTRYINDEX), b"execution_limit_reached\0".as_ptr() as *const _);
+        }
+        reached
+    }
+    /// Tracks the memory used by states created by
+    /// `Lua::new_with_allocator`.
+    ///
+    /// Passed to the allocator so it lives outside of lua
+    pub struct MemoryLimit {
+        pub limit: usize,
+        pub used: Cell<usize>,
+        /// Only set whilst lua code is running
+        pub enforced: Cell<bool>,
+        /// Set when an allocation failed because of the limit
+        pub exceeded: Cell<bool>,
+    }
+
+    unsafe extern "C" fn limited_alloc(ud: *mut sys::libc::c_void, ptr: *mut sys::libc::c_void, osize: usize, nsize: usize) -> *mut sys::libc::c_void {
+        let memory = &*(ud as *const MemoryLimit);
+        let osize = if ptr.is_null() { 0 } else { osize };
+        if nsize == 0 {
+            sys::libc::free(ptr);
+            memory.used.set(memory.used.get() - osize);
+            return ptr::null_mut();
+        }
+        let used = memory.used.get() - osize + nsize;
+        // Shrinking must never fail
+        if nsize > osize && memory.enforced.get() && used > memory.limit {
+            memory.exceeded.set(true);
+            return ptr::null_mut();
+        }
+        let new = sys::libc::realloc(ptr, nsize);
+        if !new.is_null() {
+            memory.used.set(used);
+        }
+        new
+    }
+
+    /// Same as the panic function `luaL_newstate` uses
+    unsafe extern "C" fn limited_panic(state: *mut sys::lua_State) -> sys::libc::c_int {
+        let msg = sys::lua_tolstring(state, -1, ptr::null_mut());
+        if !msg.is_null() {
+            eprintln!("PANIC: unprotected error in call to Lua API ({})", CStr::from_ptr(msg).to_string_lossy());
+        }
+        0
+    }
+
+    pub unsafe fn new_limited_state(limit: usize) -> Result<*mut sys::lua_State, Error> {
+        let memory = Box::into_raw(Box::new(MemoryLimit {
+            limit,
+            used: Cell::new(0),
+            enforced: Cell::new(false),
+            exceeded: Cell::new(false),
+        }));
+        let s = sys::lua_newstate(Some(limited_alloc), memory as *mut _);
+        if s.is_null() {
+            drop(Box::from_raw(memory));
+            return Err(Error::Raw {
+                msg: "custom allocators aren't supported by this build of lua".into(),
+            });
+        }
+        sys::lua_atpanic(s, Some(limited_panic));
+        Ok(s)
+    }
+
+    /// Returns the memory tracking of the state if it was created
+    /// with a limit.
+    ///
+    /// Doesn't touch the lua stack so that it can't fail whilst
+    /// the limit is enforced
+    pub unsafe fn memory_limit<'a>(state: *mut sys::lua_State) -> Option<&'a MemoryLimit> {
+        let mut ud = ptr::null_mut();
+        let alloc = sys::lua_getallocf(state, &mut ud);
+        if alloc.map_or(false, |v| v as usize == limited_alloc as usize) {
+            Some(&*(ud as *const MemoryLimit))
+        } else {
+            None
+        }
+    }
+
+    pub unsafe fn open_libs(s: *mut sys::lua_State, libs: LibSet) {
+        let libraries: &[(LibSet, &[u8], unsafe extern "C" fn(*mut sys::lua_State) -> i32)] = &[
+            // Same order as `luaL_openlibs`
+            (LibSet::BASE, b"\0", sys::luaopen_base),
+            (LibSet::PACKAGE, b"package\0", sys::luaopen_package),
+            (LibSet::TABLE, b"table\0", sys::luaopen_table),
+            (LibSet::IO, b"io\0", sys::luaopen_io),
+            (LibSet::OS, b"os\0", sys::luaopen_os),
+            (LibSet::STRING, b"string\0", sys::luaopen_string),
+            (LibSet::MATH, b"math\0", sys::luaopen_math),
+            (LibSet::DEBUG, b"debug\0", sys::luaopen_debug),
+            (LibSet::BIT, b"bit\0", sys::luaopen_bit),
+            (LibSet::JIT, b"jit\0", sys::luaopen_jit),
+        ];
+        for &(_, name, func) in libraries.iter().filter(|v| libs.contains(v.0)) {
+            sys::lua_pushcclosure(s, Some(func), 0);
+            sys::lua_pushstring(s, name.as_ptr() as *const _);
+            sys::lua_call(s, 1, 0);
+        }
+        if libs.contains(LibSet::PACKAGE | LibSet::FFI) {
+            // Like `luaL_openlibs` ffi is only preloaded
+            sys::lua_getfield(s, i32::from(sys::LUA_REGISTRYINDEX), b"_PRELOAD\0".as_ptr() as *const _);
+            sys::lua_pushcclosure(s, Some(sys::luaopen_ffi), 0);
+            sys::lua_setfield(s, -2, b"ffi\0".as_ptr() as *const _);
+            lua_pop(s, 1);
+        }
+    }
+
     impl LuaState {
         pub fn root(node: Rc<LuaState>) -> Rc<LuaState> {
             if let Some(p) = node.1.clone() {
@@ -1781,7 +2999,14 @@ mod internal {
                     sys::lua_getfield(self.0, i32::from(sys::LUA_REGISTRYINDEX), b"userdata_store\0".as_ptr() as *const _);
                     ptr::drop_in_place(sys::lua_touserdata(self.0, -1) as *mut UserdataTable);
                     internal::lua_pop(self.0, 1);
+                    // Lua frees everything via the allocator whilst
+                    // closing so it has to outlive the state
+                    let memory = memory_limit(self.0)
+                        .map(|v| v as *const MemoryLimit as *mut MemoryLimit);
                     sys::lua_close(self.0);
+                    if let Some(memory) = memory {
+                        drop(Box::from_raw(memory));
+                    }
                 }
             }
         }
@@ -1802,11 +3027,41 @@ mod internal {
         sys::lua_settop(state, -num-1);
     }
 
+    /// Pushes the bytes as a lua string, unlike C strings these
+    /// may contain NULs
     pub unsafe fn push_string<S>(state: *mut sys::lua_State, s: S)
-        where S: Into<Vec<u8>>
+        where S: AsRef<[u8]>
     {
-        let s = CString::new(s).unwrap();
-        sys::lua_pushstring(state, s.as_ptr());
+        let s = s.as_ref();
+        sys::lua_pushlstring(state, s.as_ptr() as *const _, s.len());
+    }
+
+    /// Returns the bytes of the string at the index, which must
+    /// be a string and not a number.
+    ///
+    /// The bytes are only valid whilst the string is alive
+    pub unsafe fn string_bytes<'a>(state: *mut sys::lua_State, idx: i32) -> &'a [u8] {
+        let mut len = 0;
+        let ptr = sys::lua_tolstring(state, idx, &mut len);
+        if ptr.is_null() {
+            &[]
+        } else {
+            std::slice::from_raw_parts(ptr as *const u8, len)
+        }
+    }
+
+    /// Copies the string (or number converted to one) at the
+    /// index without modifying the value on the stack
+    pub unsafe fn copy_string(state: *mut sys::lua_State, idx: i32) -> Option<Vec<u8>> {
+        if sys::lua_isstring(state, idx) == 0 {
+            return None;
+        }
+        // `lua_tolstring` converts numbers in place which would
+        // break `lua_next` if used on a key
+        sys::lua_pushvalue(state, idx);
+        let bytes = string_bytes(state, -1).to_vec();
+        lua_pop(state, 1);
+        Some(bytes)
     }
 }
 
@@ -1814,6 +3069,104 @@ mod internal {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_bytecode() {
+        let code = ::std::thread::spawn(|| Bytecode::compile("test", "return 5 * 3"))
+            .join()
+            .unwrap()
+            .unwrap();
+        let lua = Lua::new();
+        let func = lua.load_bytecode(&code).unwrap();
+        assert_eq!(func.invoke::<(), i32>(()).unwrap(), 15);
+
+        assert!(Bytecode::compile("test", "return (").is_err());
+        assert!(lua.load_chunk("test", b"return (").is_err());
+    }
+
+    #[test]
+    fn test_sandboxed() {
+        let lua = Lua::new_sandboxed(LibSet::SAFE);
+        assert!(lua.execute_string::<bool>("return io == nil and os == nil and debug == nil and package == nil").unwrap());
+        assert!(lua.execute_string::<bool>("return require == nil and jit == nil").unwrap());
+        assert_eq!(lua.execute_string::<i32>("return string.len('abc') + math.floor(1.5) + bit.bor(4, 0)").unwrap(), 8);
+        assert!(lua.execute_string::<bool>("return coroutine.create ~= nil and pairs ~= nil").unwrap());
+
+        assert!(lua.execute_string::<bool>("return loadstring ~= nil").unwrap());
+        lua.remove_unsafe_globals();
+        assert!(lua.execute_string::<bool>("return loadstring == nil and dofile == nil").unwrap());
+        assert!(lua.execute_string::<bool>("return pcall ~= nil").unwrap());
+
+        let lua = Lua::new_sandboxed(LibSet::BASE | LibSet::OS);
+        assert!(lua.execute_string::<bool>("return type(os.time()) == 'number' and string == nil").unwrap());
+
+        let lua = Lua::new_sandboxed(LibSet::empty());
+        assert!(lua.execute_string::<bool>("return pairs == nil and string == nil").unwrap());
+    }
+
+    #[test]
+    fn test_execution_limit() {
+        let lua = Lua::new();
+        lua.execute_string::<()>(r#"
+function spin()
+    while true do end
+end
+function catch()
+    while true do pcall(spin) end
+end
+function add(a, b)
+    return a + b
+end
+        "#).unwrap();
+        assert_eq!(lua.execution_limit(), None);
+        lua.set_execution_limit(Some(100_000));
+        assert_eq!(lua.execution_limit(), Some(100_000));
+
+        assert_eq!(lua.invoke_function::<_, ()>("spin", ()), Err(Error::Timeout));
+        // pcall can't be used to keep the script going
+        assert_eq!(lua.invoke_function::<_, ()>("catch", ()), Err(Error::Timeout));
+        assert_eq!(lua.execute_string::<()>("while true do end"), Err(Error::Timeout));
+        let spin: Ref<Function> = lua.get(Scope::Global, "spin").unwrap();
+        assert_eq!(spin.invoke::<(), ()>(()), Err(Error::Timeout));
+
+        // Each call gets the full limit again
+        assert_eq!(lua.invoke_function::<_, i32>("add", (1, 2)), Ok(3));
+        assert_eq!(lua.execute_string::<i32>("local t = 0 for i = 1, 1000 do t = t + i end return t"), Ok(500_500));
+        // Normal errors can still be caught
+        assert_eq!(lua.execute_string::<bool>("return pcall(error, 'failed') == false"), Ok(true));
+
+        lua.set_execution_limit(None);
+        assert_eq!(lua.execute_string::<i32>("local t = 0 for i = 1, 1000000 do t = t + 1 end return t"), Ok(1_000_000));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let lua = match Lua::new_with_allocator(4 * 1024 * 1024) {
+            Ok(val) => val,
+            // Not supported by this build of LuaJIT
+            Err(_) => return,
+        };
+        assert_eq!(lua.memory_limit(), Some(4 * 1024 * 1024));
+        assert_eq!(Lua::new().memory_limit(), None);
+        let base = lua.memory_used();
+        assert!(base > 0 && base < 4 * 1024 * 1024);
+
+        lua.execute_string::<()>(r#"
+function grow()
+    local t = {}
+    for i = 1, 10000000 do
+        t[i] = "value " .. i
+    end
+end
+        "#).unwrap();
+        assert_eq!(lua.invoke_function::<_, ()>("grow", ()), Err(Error::MemoryLimit));
+        // Caught errors don't fail the call
+        assert_eq!(lua.execute_string::<bool>("return pcall(grow)"), Ok(false));
+
+        lua.gc_collect();
+        assert!(lua.memory_used() < 4 * 1024 * 1024);
+        assert_eq!(lua.execute_string::<i32>("local t = {} for i = 1, 1000 do t[i] = i end return #t"), Ok(1000));
+    }
+
     #[test]
     fn test() {
         let lua = Lua::new();
@@ -1823,6 +3176,31 @@ return 5 + 5
         assert_eq!(test, 10);
     }
 
+    #[test]
+    fn test_multi() {
+        let lua = Lua::new();
+        let ret = lua.execute_string_multi(r#"
+local count = 3
+local vals = {}
+for i = 1, count do
+    vals[i] = i * 2
+end
+return unpack(vals)
+        "#).unwrap();
+        let ret: Vec<i32> = ret.iter()
+            .map(|v| v.try_convert().unwrap())
+            .collect();
+        assert_eq!(ret, vec![2, 4, 6]);
+
+        assert!(lua.execute_string_multi("local a = 5").unwrap().is_empty());
+        let ret = lua.execute_string_multi("return nil, \"hi\"").unwrap();
+        assert!(ret[0].is_nil());
+        assert_eq!(&*ret[1].try_convert::<Ref<String>>().unwrap(), "hi");
+
+        assert!(lua.execute_string_multi("return (").is_err());
+        assert!(lua.execute_string_multi("error(\"failed\")").is_err());
+    }
+
     #[test]
     fn test_string() {
         let lua = Lua::new();
@@ -1832,6 +3210,94 @@ return "hello world"
         assert_eq!(&*test, "hello world");
     }
 
+    #[test]
+    fn test_scope() {
+        let lua = Lua::new();
+        lua.execute_string::<()>(r#"
+counts = {}
+function count(tbl, name)
+    counts[name] = (counts[name] or 0) + 1
+    return counts[name] + #tbl
+end
+        "#).unwrap();
+        let top = unsafe { sys::lua_gettop(lua.state.0) };
+        let data = Ref::new_table(&lua);
+        data.insert(1, 5);
+        let total = lua.scope(|s| {
+            let count = s.function("count").unwrap();
+            let data = s.pin(&data);
+            let name = s.string("room");
+            let mut total = 0;
+            for _ in 0 .. 100 {
+                total += s.scope(|s| {
+                    let other = s.string("other");
+                    count.invoke::<_, i32>((data, other)).unwrap();
+                    count.invoke::<_, i32>((data, name)).unwrap()
+                });
+                assert_eq!(unsafe { sys::lua_gettop(lua.state.0) }, top + 3);
+            }
+            let counts = s.global("counts").as_table().unwrap();
+            assert_eq!(counts.get::<_, i32>("other"), Some(100));
+            assert_eq!(s.field(counts, "room").try_convert::<i32>(), Ok(100));
+            counts.insert("rust", 1);
+            assert!(s.global("missing").is_nil());
+            assert!(s.function("missing").is_err());
+            assert_eq!(name.as_bytes(), b"room");
+            total
+        });
+        // (1 + 1) + (2 + 1) + ... + (100 + 1)
+        assert_eq!(total, 5050 + 100);
+        assert_eq!(unsafe { sys::lua_gettop(lua.state.0) }, top);
+        assert_eq!(lua.execute_string::<i32>("return counts.rust"), Ok(1));
+
+        let kept = lua.scope(|s| s.string("kept").to_ref());
+        assert_eq!(&*kept, "kept");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_scope_nested_pin() {
+        let lua = Lua::new();
+        lua.scope(|outer| {
+            outer.scope(|inner| {
+                inner.string("inner");
+                // Would be removed along with the nested scope
+                outer.string("outer");
+            });
+        });
+    }
+
+    #[test]
+    fn test_binary_string() {
+        let lua = Lua::new();
+        let data = vec![0u8, 1, 0, 255, b'a', 0];
+        let tbl = Ref::new_table(&lua);
+        tbl.insert("data", data.clone());
+        tbl.insert("text", "before\0after");
+        tbl.insert(1, "owned\0".to_owned());
+        tbl.insert(2, &b"\0raw"[..]);
+        lua.set(Scope::Global, "tbl", tbl.clone());
+        assert_eq!(lua.execute_string::<i32>("return #tbl.data"), Ok(6));
+        assert_eq!(lua.execute_string::<i32>("return #tbl.text"), Ok(12));
+
+        assert_eq!(tbl.get::<_, Vec<u8>>("data"), Some(data.clone()));
+        assert_eq!(tbl.get::<_, String>("text"), Some("before\0after".to_owned()));
+        assert_eq!(tbl.get::<_, String>(1), Some("owned\0".to_owned()));
+        assert_eq!(tbl.get::<_, Vec<u8>>(2), Some(b"\0raw".to_vec()));
+        // Not valid utf-8
+        assert_eq!(tbl.get::<_, String>("data"), None);
+        assert!(tbl.get::<_, &str>("text").is_none());
+
+        let text: Ref<String> = lua.execute_string("return tbl.text").unwrap();
+        assert_eq!(&*text, "before\0after");
+        let raw: Ref<String> = lua.execute_string("return tbl.data").unwrap();
+        assert_eq!(raw.as_bytes(), &data[..]);
+        assert_eq!(&*raw, "");
+        let num: Ref<String> = lua.execute_string("return 5").unwrap();
+        assert_eq!(&*num, "5");
+        assert_eq!(lua.execute_string::<Vec<u8>>("return 'a\\0b'"), Ok(b"a\0b".to_vec()));
+    }
+
     #[test]
     fn test_invoke() {
         let lua = Lua::new();
@@ -1883,6 +3349,68 @@ end
         assert_eq!(state.get(Scope::Registry, "testing"), Ok(5));
     }
 
+    #[test]
+    fn test_gc() {
+        let state = Lua::new();
+        state.gc_collect();
+        let before = state.gc_count();
+        state.execute_string::<()>(r#"
+    garbage = {}
+    for i = 1, 10000 do
+        garbage[i] = {i}
+    end
+    "#).unwrap();
+        assert!(state.gc_count() > before);
+        state.execute_string::<()>("garbage = nil").unwrap();
+        // The first cycle may have started whilst the table was
+        // still reachable
+        for _ in 0 .. 2 {
+            while !state.gc_step(64) {}
+        }
+        assert!(state.gc_count() < before + 64 * 1024);
+
+        let pause = state.gc_set_pause(150);
+        assert_eq!(state.gc_set_pause(pause), 150);
+        let mul = state.gc_set_step_multiplier(300);
+        assert_eq!(state.gc_set_step_multiplier(mul), 300);
+    }
+
+    #[test]
+    fn test_table_extend() {
+        let state = Lua::new();
+        let tbl = Ref::<Table>::with_capacity(&state, 100, 1);
+        tbl.extend_from_iter((1 ..= 100).map(|v| (v, v * 2)));
+        tbl.insert(Ref::new_string(&state, "test"), 5);
+        assert_eq!(tbl.length(), 100);
+        assert_eq!(tbl.get::<i32, i32>(50), Some(100));
+        assert_eq!(tbl.get::<_, i32>(Ref::new_string(&state, "test")), Some(5));
+
+        state.set(Scope::Global, "tbl", tbl);
+        state.execute_string::<()>(r#"
+    assert(#tbl == 100)
+    assert(tbl[100] == 200)
+    "#).unwrap();
+    }
+
+    #[test]
+    fn test_interned() {
+        let state = Lua::new();
+        let key = state.intern("name");
+        assert_eq!(&*key, "name");
+
+        let tbl = Ref::new_table(&state);
+        tbl.insert(key.clone(), 5);
+        assert_eq!(tbl.get::<_, i32>(Ref::new_string(&state, "name")), Some(5));
+        assert_eq!(tbl.get::<_, i32>(key.clone()), Some(5));
+
+        state.set(Scope::Global, "tbl", tbl);
+        state.set(Scope::Global, "key", key);
+        state.execute_string::<()>(r#"
+    assert(tbl.name == 5)
+    assert(key == "name")
+    "#).unwrap();
+    }
+
     #[test]
     fn test_return() {
         let state = Lua::new();
@@ -1994,6 +3522,123 @@ custom:change(22);
         }
     }
 
+    #[test]
+    fn test_derive() {
+        use std::cell::RefCell;
+
+        #[derive(LuaBind)]
+        #[lua(cell, methods)]
+        struct Counter {
+            #[lua(get, set)]
+            count: i32,
+            #[lua(get, name = "label")]
+            name: String,
+            #[allow(dead_code)]
+            hidden: i32,
+        }
+
+        #[lua_methods(cell)]
+        impl Counter {
+            #[lua]
+            fn add(&mut self, by: i32) -> i32 {
+                self.count += by;
+                self.count
+            }
+
+            #[lua(name = "describe")]
+            fn description(&self, prefix: &str) -> String {
+                format!("{}{}: {}", prefix, self.name, self.count)
+            }
+
+            #[lua]
+            fn double(_lua: &Lua, val: i32) -> i32 {
+                val * 2
+            }
+        }
+
+        let state = Lua::new();
+        state.set(
+            Scope::Global, "counter",
+            Ref::new(&state, RefCell::new(Counter {
+                count: 1,
+                name: "test".into(),
+                hidden: 0,
+            }))
+        );
+        state.execute_string::<()>(r#"
+assert(counter:get_count() == 1);
+assert(counter:get_label() == "test");
+assert(counter.get_hidden == nil);
+counter:set_count(3);
+assert(counter:add(2) == 5);
+assert(counter:describe("a ") == "a test: 5");
+assert(counter.double(4) == 8);
+        "#).unwrap();
+
+        let c = state.get::<Ref<RefCell<Counter>>>(Scope::Global, "counter").unwrap();
+        assert_eq!(c.borrow().count, 5);
+    }
+
+    #[test]
+    fn test_cell() {
+        struct Stack {
+            values: Vec<i32>,
+        }
+        impl LuaUsable for Stack {
+            fn fields(t: &TypeBuilder) {
+                t.field("push", closure2(|_, s: CellRef<Stack>, val: i32| {
+                    s.borrow_mut().values.push(val);
+                }));
+                t.field("len", closure1(|_, s: CellRef<Stack>| {
+                    s.borrow().values.len() as i32
+                }));
+            }
+        }
+
+        let state = Lua::new();
+        let stack = Ref::new_cell(&state, Stack { values: vec![1] });
+        state.set(Scope::Global, "stack", stack.clone());
+        assert_eq!(state.execute_string::<i32>("stack:push(2) stack:push(3) return stack:len()"), Ok(3));
+        assert_eq!(stack.borrow().values, vec![1, 2, 3]);
+
+        // The same type as a manually wrapped value
+        let c = state.get::<Ref<RefCell<Stack>>>(Scope::Global, "stack").unwrap();
+        assert_eq!(c.borrow().values.len(), 3);
+
+        {
+            let _values = stack.borrow_mut();
+            assert!(stack.try_borrow().is_err());
+            match stack.try_borrow_mut() {
+                Err(Error::Borrowed { ty }) => assert!(ty.ends_with("Stack")),
+                _ => panic!("expected the value to be borrowed"),
+            }
+        }
+        assert!(stack.try_borrow_mut().is_ok());
+    }
+
+    #[test]
+    fn test_operators() {
+        struct Num(i32);
+        impl LuaUsable for Num {
+            fn metatable(t: &TypeBuilder) {
+                t.operator(Operator::Add, closure2(|lua, a: Ref<Num>, b: Ref<Num>| Ref::new(lua, Num(a.0 + b.0))));
+                t.operator(Operator::Unm, closure2(|lua, a: Ref<Num>, _: Ref<Num>| Ref::new(lua, Num(-a.0))));
+                t.operator(Operator::Eq, closure2(|_, a: Ref<Num>, b: Ref<Num>| a.0 == b.0));
+                t.operator(Operator::Lt, closure2(|_, a: Ref<Num>, b: Ref<Num>| a.0 < b.0));
+                t.operator(Operator::ToString, closure1(|lua, a: Ref<Num>| Ref::new_string(lua, format!("num({})", a.0))));
+            }
+        }
+        let state = Lua::new();
+        state.set(Scope::Global, "num", closure1(|lua, v: i32| Ref::new(lua, Num(v))));
+
+        let val = state.execute_string::<Ref<Num>>("return -(num(2) + num(5))").unwrap();
+        assert_eq!(val.0, -7);
+        assert!(state.execute_string::<bool>("return num(2) == num(2)").unwrap());
+        assert!(!state.execute_string::<bool>("return num(2) == num(3)").unwrap());
+        assert!(state.execute_string::<bool>("return num(2) < num(3)").unwrap());
+        assert_eq!(&*state.execute_string::<Ref<String>>("return tostring(num(4))").unwrap(), "num(4)");
+    }
+
     #[test]
     fn test_userdata_data() {
         struct CustomType {
@@ -2242,4 +3887,47 @@ custom:change(22);
             assert_eq!(c.thing, -5);
         }
     }
+
+    #[test]
+    fn test_table_protected() {
+        let state = Lua::new();
+
+        let tbl = state.execute_string::<Ref<Table>>(r#"
+        local store = {}
+        return setmetatable({}, {
+            __index = function(_, k)
+                if k == "bad" then
+                    error("no bad keys")
+                end
+                return store[k] or k .. "!"
+            end,
+            __newindex = function(_, k, v)
+                if k == "bad" then
+                    error("no bad keys")
+                end
+                store[k] = v * 2
+            end,
+        })
+        "#).unwrap();
+        let top = unsafe { ::sys::lua_gettop(state.state.0) };
+
+        // Raw access skips the metatable
+        assert!(tbl.get::<_, Ref<String>>("hello").is_none());
+        assert_eq!(&*tbl.get_protected::<_, Ref<String>>("hello").unwrap(), "hello!");
+
+        tbl.insert_protected("num", 21).unwrap();
+        assert_eq!(tbl.get::<_, i32>("num"), None);
+        assert_eq!(tbl.get_protected::<_, i32>("num").unwrap(), 42);
+        assert_eq!(tbl.get_protected::<_, Option<i32>>("num").unwrap(), Some(42));
+
+        match tbl.get_protected::<_, i32>("bad") {
+            Err(Error::Raw { msg }) => assert!(msg.contains("no bad keys")),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        assert!(tbl.insert_protected("bad", 1).is_err());
+        assert!(tbl.get_protected::<_, i32>("hello").is_err());
+
+        // The stack should be left as it was
+        assert_eq!(unsafe { ::sys::lua_gettop(state.state.0) }, top);
+    }
 }
@@ -265,9 +265,8 @@ impl <'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         where V: Visitor<'de>
     {
         unsafe {
-            if sys::lua_isstring(self.state.0, self.idx) != 0 {
-                let cstr = CStr::from_ptr(sys::lua_tolstring(self.state.0, self.idx, ptr::null_mut()));
-                visitor.visit_str(cstr.to_str().unwrap_or(""))
+            if let Some(bytes) = internal::copy_string(self.state.0, self.idx) {
+                visitor.visit_str(::std::str::from_utf8(&bytes).unwrap_or(""))
             } else {
                 Err(DError(Error::TypeMismatch {
                     wanted: "String",
@@ -0,0 +1,228 @@
+//! Detection of packs that fight over the same assets.
+//!
+//! When several packs provide the same file the last pack loaded
+//! silently wins. That's how a single pack overrides another
+//! module but as soon as two packs change the same file one of
+//! them is quietly ignored. The asset manager checks for this when
+//! the packs are loaded and keeps a report of every conflict found.
+//!
+//! Scripts are covered by the same checks. Each script runs in its
+//! own global scope so packs can't collide through lua globals;
+//! they can only collide by providing the same script file.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use super::{ModuleKey, ResourceKey};
+
+/// The type of a conflict between packs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Several packs use the same module name
+    DuplicateModule,
+    /// Several packs add the same resource to a module that
+    /// doesn't provide it itself
+    DuplicateResource,
+    /// Several packs override the same resource of another
+    /// module
+    OverlappingOverride,
+}
+
+impl ConflictKind {
+    /// Returns the name of the kind as used by the ui
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConflictKind::DuplicateModule => "duplicate_module",
+            ConflictKind::DuplicateResource => "duplicate_resource",
+            ConflictKind::OverlappingOverride => "overlapping_override",
+        }
+    }
+}
+
+/// A single conflict between packs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// The type of the conflict
+    pub kind: ConflictKind,
+    /// The module that is fought over
+    pub module: ModuleKey<'static>,
+    /// The file that is fought over, `None` for duplicate
+    /// modules
+    pub resource: Option<String>,
+    /// The packs involved in load order. The last pack is
+    /// the one that gets used
+    pub packs: Vec<String>,
+}
+
+impl Conflict {
+    /// Returns the pack whose version is used
+    pub fn winner(&self) -> &str {
+        self.packs.last().map_or("", |v| v.as_str())
+    }
+
+    /// Returns whether the conflict is over a script
+    pub fn is_script(&self) -> bool {
+        self.resource.as_ref().map_or(false, |v| v.ends_with(".lua"))
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packs = self.packs.join(", ");
+        match (self.kind, self.resource.as_ref()) {
+            (ConflictKind::DuplicateModule, _) | (_, None) => write!(f,
+                "The packs {} all provide the module {}, only {} is used",
+                packs, self.module.module(), self.winner()
+            ),
+            (ConflictKind::DuplicateResource, Some(res)) => write!(f,
+                "The packs {} all add {}:{}, only the one from {} is used",
+                packs, self.module.module(), res, self.winner()
+            ),
+            (ConflictKind::OverlappingOverride, Some(res)) => write!(f,
+                "The packs {} all override {}:{}, only the changes from {} are used",
+                packs, self.module.module(), res, self.winner()
+            ),
+        }
+    }
+}
+
+/// Every conflict found between the loaded packs
+#[derive(Debug, Clone, Default)]
+pub struct ConflictReport {
+    /// The conflicts ordered by module and resource
+    pub conflicts: Vec<Conflict>,
+}
+
+/// The files a single pack provides
+pub(super) struct PackFiles<'a> {
+    /// The name the pack was loaded with
+    pub name: &'a str,
+    /// The module the pack provides
+    pub module: ModuleKey<'static>,
+    /// Every file in the pack including overrides for
+    /// other modules
+    pub files: Vec<ResourceKey<'static>>,
+}
+
+impl ConflictReport {
+    /// Compares the files of the packs, passed in load order
+    pub(super) fn analyze(packs: &[PackFiles<'_>]) -> ConflictReport {
+        let mut conflicts = vec![];
+
+        let mut owners: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for pack in packs {
+            owners.entry(pack.module.module())
+                .or_insert_with(Vec::new)
+                .push(pack.name);
+        }
+        for (module, names) in &owners {
+            if names.len() > 1 {
+                conflicts.push(Conflict {
+                    kind: ConflictKind::DuplicateModule,
+                    module: ModuleKey::new(module.to_string()),
+                    resource: None,
+                    packs: names.iter().map(|v| v.to_string()).collect(),
+                });
+            }
+        }
+
+        // The packs providing each file and whether the pack
+        // owns the file's module
+        let mut providers: BTreeMap<(&str, &str), Vec<(&str, bool)>> = BTreeMap::new();
+        for pack in packs {
+            for file in &pack.files {
+                providers.entry((file.module(), file.resource()))
+                    .or_insert_with(Vec::new)
+                    .push((pack.name, file.module() == pack.module.module()));
+            }
+        }
+        for ((module, resource), list) in providers {
+            let overrides = list.iter().filter(|v| !v.1).count();
+            if overrides < 2 {
+                continue;
+            }
+            let kind = if list.iter().any(|v| v.1) {
+                ConflictKind::OverlappingOverride
+            } else {
+                ConflictKind::DuplicateResource
+            };
+            conflicts.push(Conflict {
+                kind,
+                module: ModuleKey::new(module.to_string()),
+                resource: Some(resource.to_owned()),
+                packs: list.into_iter().map(|v| v.0.to_owned()).collect(),
+            });
+        }
+        conflicts.sort_by(|a, b| (a.module.module(), a.resource.as_ref())
+            .cmp(&(b.module.module(), b.resource.as_ref())));
+
+        ConflictReport {
+            conflicts,
+        }
+    }
+
+    /// Returns whether any conflicts were found
+    pub fn is_empty(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack<'a>(name: &'a str, module: &str, files: &[&str]) -> PackFiles<'a> {
+        PackFiles {
+            name,
+            module: ModuleKey::new(module.to_owned()),
+            files: files.iter()
+                .map(|v| {
+                    let mut parts = v.splitn(2, ':');
+                    let module = parts.next().unwrap().to_owned();
+                    ResourceKey::new(module, parts.next().unwrap().to_owned())
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn single_override() {
+        let report = ConflictReport::analyze(&[
+            pack("base", "base", &["base:rooms/office.json", "base:textures/grass.png"]),
+            pack("mod_a", "mod_a", &["base:rooms/office.json", "mod_a:rooms/lab.json"]),
+        ]);
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn overlapping_override() {
+        let report = ConflictReport::analyze(&[
+            pack("base", "base", &["base:rooms/office.json", "base:scripts/init.lua"]),
+            pack("mod_a", "mod_a", &["base:rooms/office.json", "base:scripts/init.lua"]),
+            pack("mod_b", "mod_b", &["base:scripts/init.lua"]),
+        ]);
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!(conflict.kind, ConflictKind::OverlappingOverride);
+        assert_eq!(conflict.resource.as_ref().map(|v| v.as_str()), Some("scripts/init.lua"));
+        assert_eq!(conflict.packs, vec!["base", "mod_a", "mod_b"]);
+        assert_eq!(conflict.winner(), "mod_b");
+        assert!(conflict.is_script());
+    }
+
+    #[test]
+    fn duplicates() {
+        let report = ConflictReport::analyze(&[
+            pack("base", "base", &[]),
+            pack("mod_a", "mod_a", &["base:rooms/pool.json"]),
+            pack("mod_b", "mod_b", &["base:rooms/pool.json"]),
+            pack("workshop:one", "shared", &[]),
+            pack("workshop:two", "shared", &[]),
+        ]);
+        assert_eq!(report.conflicts.len(), 2);
+        assert_eq!(report.conflicts[0].kind, ConflictKind::DuplicateResource);
+        assert_eq!(report.conflicts[0].packs, vec!["mod_a", "mod_b"]);
+        assert_eq!(report.conflicts[1].kind, ConflictKind::DuplicateModule);
+        assert_eq!(report.conflicts[1].packs, vec!["workshop:one", "workshop:two"]);
+        assert_eq!(report.conflicts[1].resource, None);
+    }
+}
@@ -0,0 +1,336 @@
+//! A graph of how the loaded packs depend on and override each
+//! other.
+//!
+//! Packs can list the modules they need in their `meta.json`
+//! as `dependencies`. Those modules must be loaded before the
+//! pack for its overrides to apply on top of them so a dependency
+//! that is missing or loaded later is flagged. Along with the
+//! overrides found in each pack this is meant to make load order
+//! problems easier to track down. The graph can be written out as
+//! DOT for graphviz or as json for other tools.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use serde::Serialize;
+use crate::util::FNVMap;
+use super::conflicts::PackFiles;
+
+/// How the loaded packs relate to each other
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PackGraph {
+    /// The packs in load order
+    pub packs: Vec<PackNode>,
+    /// The files each pack provides for modules other
+    /// than its own ordered by pack and module
+    pub overrides: Vec<Override>,
+}
+
+/// A single loaded pack
+#[derive(Debug, Clone, Serialize)]
+pub struct PackNode {
+    /// The name the pack was loaded with
+    pub name: String,
+    /// The module the pack provides
+    pub module: String,
+    /// The number of files in the pack including overrides
+    pub files: usize,
+    /// The modules the pack declared it needs
+    pub dependencies: Vec<Dependency>,
+}
+
+/// A module a pack needs
+#[derive(Debug, Clone, Serialize)]
+pub struct Dependency {
+    /// The name of the module
+    pub module: String,
+    /// Whether the module was loaded in time
+    pub state: DependencyState,
+}
+
+/// Whether a dependency was loaded before the pack
+/// needing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyState {
+    /// Loaded before the pack
+    Loaded,
+    /// Loaded after the pack so the pack's overrides of
+    /// it are replaced
+    LoadedAfter,
+    /// Not loaded at all
+    Missing,
+}
+
+/// Files a pack provides for another module
+#[derive(Debug, Clone, Serialize)]
+pub struct Override {
+    /// The pack providing the files
+    pub pack: String,
+    /// The module the files belong to
+    pub module: String,
+    /// The packs providing the module, normally just one
+    pub owners: Vec<String>,
+    /// The files provided by the pack
+    pub resources: Vec<String>,
+    /// The files a later pack provides as well meaning
+    /// this pack's version isn't used
+    pub shadowed: Vec<String>,
+}
+
+impl PackGraph {
+    /// Builds the graph from the files of the packs, passed in
+    /// load order, and the dependencies declared by each module
+    pub(super) fn build(packs: &[PackFiles<'_>], dependencies: &FNVMap<String, Vec<String>>) -> PackGraph {
+        let nodes = packs.iter().enumerate()
+            .map(|(idx, pack)| PackNode {
+                name: pack.name.to_owned(),
+                module: pack.module.module().to_owned(),
+                files: pack.files.len(),
+                dependencies: dependencies.get(pack.module.module())
+                    .into_iter()
+                    .flatten()
+                    .map(|module| {
+                        let state = match packs.iter().position(|v| v.module.module() == module) {
+                            Some(pos) if pos < idx => DependencyState::Loaded,
+                            Some(_) => DependencyState::LoadedAfter,
+                            None => DependencyState::Missing,
+                        };
+                        Dependency {
+                            module: module.clone(),
+                            state,
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mut overrides = vec![];
+        for (idx, pack) in packs.iter().enumerate() {
+            let mut modules: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+            for file in pack.files.iter().filter(|v| v.module() != pack.module.module()) {
+                modules.entry(file.module())
+                    .or_insert_with(Vec::new)
+                    .push(file.resource());
+            }
+            for (module, mut resources) in modules {
+                resources.sort_unstable();
+                let later = &packs[idx + 1 ..];
+                let shadowed = resources.iter()
+                    .filter(|res| later.iter()
+                        .flat_map(|v| &v.files)
+                        .any(|v| v.module() == module && v.resource() == **res))
+                    .map(|v| (*v).to_owned())
+                    .collect();
+                overrides.push(Override {
+                    pack: pack.name.to_owned(),
+                    module: module.to_owned(),
+                    owners: packs.iter()
+                        .filter(|v| v.module.module() == module)
+                        .map(|v| v.name.to_owned())
+                        .collect(),
+                    resources: resources.into_iter().map(|v| v.to_owned()).collect(),
+                    shadowed,
+                });
+            }
+        }
+
+        PackGraph {
+            packs: nodes,
+            overrides,
+        }
+    }
+
+    /// Returns whether any pack has a dependency that wasn't
+    /// loaded before it
+    pub fn has_problems(&self) -> bool {
+        self.packs.iter()
+            .flat_map(|v| &v.dependencies)
+            .any(|v| v.state != DependencyState::Loaded)
+    }
+
+    /// Returns the graph in graphviz's DOT format.
+    ///
+    /// Dependencies are solid edges, red if they weren't
+    /// loaded in time, and overrides are dashed edges labeled
+    /// with the number of files.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph packs {{");
+        let _ = writeln!(out, "    rankdir=BT;");
+        for (idx, pack) in self.packs.iter().enumerate() {
+            let _ = writeln!(out, "    {} [shape=box, label={}];",
+                quote(&pack.name),
+                quote(&format!("{}. {}\n{} ({} files)", idx + 1, pack.name, pack.module, pack.files)),
+            );
+        }
+        // Modules that are needed or overridden but that no pack
+        // provides
+        let mut missing: Vec<&str> = self.packs.iter()
+            .flat_map(|v| &v.dependencies)
+            .filter(|v| v.state == DependencyState::Missing)
+            .map(|v| v.module.as_str())
+            .chain(self.overrides.iter()
+                .filter(|v| v.owners.is_empty())
+                .map(|v| v.module.as_str()))
+            .collect();
+        missing.sort_unstable();
+        missing.dedup();
+        for module in &missing {
+            let _ = writeln!(out, "    {} [shape=box, style=dashed, color=red, label={}];",
+                quote(&missing_id(module)),
+                quote(&format!("{}\n(not loaded)", module)),
+            );
+        }
+
+        for pack in &self.packs {
+            for dep in &pack.dependencies {
+                match dep.state {
+                    DependencyState::Missing => {
+                        let _ = writeln!(out, "    {} -> {} [color=red];", quote(&pack.name), quote(&missing_id(&dep.module)));
+                    },
+                    state => for owner in self.owners(&dep.module) {
+                        let color = if state == DependencyState::Loaded { "black" } else { "red" };
+                        let _ = writeln!(out, "    {} -> {} [color={}];", quote(&pack.name), quote(owner), color);
+                    },
+                }
+            }
+        }
+        for ov in &self.overrides {
+            let label = if ov.shadowed.is_empty() {
+                format!("overrides {}", ov.resources.len())
+            } else {
+                format!("overrides {} ({} unused)", ov.resources.len(), ov.shadowed.len())
+            };
+            if ov.owners.is_empty() {
+                let _ = writeln!(out, "    {} -> {} [style=dashed, label={}];", quote(&ov.pack), quote(&missing_id(&ov.module)), quote(&label));
+            }
+            for owner in &ov.owners {
+                let _ = writeln!(out, "    {} -> {} [style=dashed, label={}];", quote(&ov.pack), quote(owner), quote(&label));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Returns the graph as json
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize the pack graph")
+    }
+
+    /// Returns the names of the packs providing the module
+    fn owners<'a>(&'a self, module: &'a str) -> impl Iterator<Item=&'a str> + 'a {
+        self.packs.iter()
+            .filter(move |v| v.module == module)
+            .map(|v| v.name.as_str())
+    }
+}
+
+/// The id used for the node of a module that isn't loaded.
+/// Prefixed so that it doesn't clash with a pack's name
+fn missing_id(module: &str) -> String {
+    format!("missing:{}", module)
+}
+
+/// Quotes the string for use as a DOT id
+fn quote(val: &str) -> String {
+    let mut out = String::with_capacity(val.len() + 2);
+    out.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::{ModuleKey, ResourceKey};
+
+    fn pack<'a>(name: &'a str, module: &str, files: &[&str]) -> PackFiles<'a> {
+        PackFiles {
+            name,
+            module: ModuleKey::new(module.to_owned()),
+            files: files.iter()
+                .map(|v| {
+                    let mut parts = v.splitn(2, ':');
+                    let module = parts.next().unwrap().to_owned();
+                    ResourceKey::new(module, parts.next().unwrap().to_owned())
+                })
+                .collect(),
+        }
+    }
+
+    fn graph() -> PackGraph {
+        let mut deps = FNVMap::default();
+        deps.insert("mod_a".to_owned(), vec!["base".to_owned(), "mod_b".to_owned(), "tools".to_owned()]);
+        deps.insert("mod_b".to_owned(), vec!["base".to_owned()]);
+        PackGraph::build(&[
+            pack("base", "base", &["base:rooms/office.json", "base:scripts/init.lua"]),
+            pack("mod_a", "mod_a", &["base:scripts/init.lua", "base:rooms/office.json", "extra:icons/a.png", "mod_a:rooms/lab.json"]),
+            pack("workshop:b", "mod_b", &["base:scripts/init.lua"]),
+        ], &deps)
+    }
+
+    #[test]
+    fn dependencies() {
+        let graph = graph();
+        assert_eq!(graph.packs.len(), 3);
+        assert_eq!(graph.packs[1].files, 4);
+        let states: Vec<_> = graph.packs[1].dependencies.iter()
+            .map(|v| (v.module.as_str(), v.state))
+            .collect();
+        assert_eq!(states, vec![
+            ("base", DependencyState::Loaded),
+            ("mod_b", DependencyState::LoadedAfter),
+            ("tools", DependencyState::Missing),
+        ]);
+        assert_eq!(graph.packs[2].dependencies[0].state, DependencyState::Loaded);
+        assert!(graph.packs[0].dependencies.is_empty());
+        assert!(graph.has_problems());
+    }
+
+    #[test]
+    fn overrides() {
+        let graph = graph();
+        assert_eq!(graph.overrides.len(), 3);
+
+        let ov = &graph.overrides[0];
+        assert_eq!((ov.pack.as_str(), ov.module.as_str()), ("mod_a", "base"));
+        assert_eq!(ov.owners, vec!["base"]);
+        assert_eq!(ov.resources, vec!["rooms/office.json", "scripts/init.lua"]);
+        assert_eq!(ov.shadowed, vec!["scripts/init.lua"]);
+
+        let ov = &graph.overrides[1];
+        assert_eq!(ov.module, "extra");
+        assert!(ov.owners.is_empty());
+
+        let ov = &graph.overrides[2];
+        assert_eq!(ov.pack, "workshop:b");
+        assert!(ov.shadowed.is_empty());
+    }
+
+    #[test]
+    fn dot() {
+        let dot = graph().to_dot();
+        assert!(dot.starts_with("digraph packs {"));
+        assert!(dot.contains("\"mod_a\" -> \"base\" [color=black];"));
+        assert!(dot.contains("\"mod_a\" -> \"workshop:b\" [color=red];"));
+        assert!(dot.contains("\"mod_a\" -> \"missing:tools\" [color=red];"));
+        assert!(dot.contains("\"mod_a\" -> \"base\" [style=dashed, label=\"overrides 2 (1 unused)\"];"));
+        assert!(dot.contains("\"missing:extra\" [shape=box"));
+        assert_eq!(quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+
+    #[test]
+    fn json() {
+        let json: serde_json::Value = serde_json::from_str(&graph().to_json()).unwrap();
+        assert_eq!(json["packs"][1]["dependencies"][1]["state"], "loaded_after");
+        assert_eq!(json["overrides"][0]["shadowed"][0], "scripts/init.lua");
+    }
+}
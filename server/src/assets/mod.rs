@@ -25,6 +25,12 @@
 //! ---- rooms/...
 //! ```
 //!
+//! Packages may also be shipped packed into a single file
+//! at `assets/packed/<name>.pack` (built with the
+//! `assets-builder` tool) which is used when there isn't
+//! a folder with the package's name. This is faster to
+//! load than thousands of small files.
+//!
 //! Resources are generally represented as a module and a
 //! resource name in the form `module:resource`. `ModuleKey`
 //! is the `module` part and the `ResourceKey` is the
@@ -33,7 +39,7 @@
 //! left off where it can be inferred to avoid repeating
 //! youself, `LazyResourceKey` may be used for this case.
 
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::io::{self, SeekFrom, Write, Read, Seek};
 use std::path::{Path, PathBuf};
 use std::fs;
@@ -45,11 +51,21 @@ use byteorder::{LittleEndian, ReadBytesExt};
 use crate::util::*;
 use delta_encode::DeltaEncodable;
 use crate::errors;
+use crate::script;
 use crate::prelude::*;
 use memmap;
 
 mod arcstr;
 use self::arcstr::ArcStr;
+mod season;
+pub use self::season::{MonthDay, SeasonInfo, today};
+use self::season::Season;
+pub mod conflicts;
+use self::conflicts::{ConflictReport, PackFiles};
+pub mod graph;
+use self::graph::{PackGraph, DependencyState};
+mod typed;
+pub use self::typed::{AssetCategory, TypedKey, TextureAsset, ModelAsset, AnimatedModelAsset};
 
 /// A key that can be used to reference a module.
 ///
@@ -591,9 +607,80 @@ impl AssetManager {
             a
         };
 
+        let mut seasons = Vec::new();
+        for (module, fetcher) in &assets {
+            if let Some(file) = fetcher.open(module.borrow(), "seasons.json") {
+                let info: FNVMap<String, SeasonInfo> = match serde_json::from_reader(file) {
+                    Ok(val) => val,
+                    Err(err) => {
+                        error!(log, "Failed to load the seasons for {:?}: {}", module, err);
+                        continue;
+                    }
+                };
+                seasons.extend(info.into_iter()
+                    .map(|(name, info)| Season::new(module.borrow(), name, info)));
+            }
+        }
+
+        let mut api_versions = FNVMap::default();
+        let mut dependencies = FNVMap::default();
+        for (module, fetcher) in &assets {
+            if let Some(file) = fetcher.open(module.borrow(), "meta.json") {
+                let manifest: PackManifest = match serde_json::from_reader(file) {
+                    Ok(val) => val,
+                    Err(err) => {
+                        error!(log, "Failed to load the manifest for {:?}: {}", module, err);
+                        continue;
+                    }
+                };
+                if let Some(version) = manifest.api_version {
+                    if version > script::API_VERSION {
+                        warn!(log, "{:?} requires script api version {} but only {} is supported", module, version, script::API_VERSION);
+                    }
+                    api_versions.insert(module.module().to_owned(), version);
+                }
+                if !manifest.dependencies.is_empty() {
+                    dependencies.insert(module.module().to_owned(), manifest.dependencies);
+                }
+            }
+        }
+
+        // Only zipped with the loaded packs so the debug pack's
+        // name is ignored when it isn't loaded
+        let pack_files = packs.iter()
+            .map(|v| v.as_str())
+            .chain(Some("debug"))
+            .zip(&assets)
+            .map(|(name, (module, fetcher))| PackFiles {
+                name,
+                module: module.clone(),
+                files: fetcher.files(),
+            })
+            .collect::<Vec<_>>();
+        let conflicts = ConflictReport::analyze(&pack_files);
+        for conflict in &conflicts.conflicts {
+            warn!(log, "Pack conflict: {}", conflict);
+        }
+        let graph = PackGraph::build(&pack_files, &dependencies);
+        for pack in &graph.packs {
+            for dep in &pack.dependencies {
+                match dep.state {
+                    DependencyState::Loaded => {},
+                    DependencyState::LoadedAfter => warn!(log, "{} depends on {:?} which is loaded after it", pack.name, dep.module),
+                    DependencyState::Missing => warn!(log, "{} depends on {:?} which isn't loaded", pack.name, dep.module),
+                }
+            }
+        }
+
         AssetsBuilder {
             store: Store {
                 assets,
+                seasons,
+                conflicts,
+                graph,
+                api_versions,
+                deprecations: Mutex::new(script::compat::Deprecations::default()),
+                active_seasons: RwLock::new(Vec::new()),
                 log,
             },
             loader_data: FNVMap::default(),
@@ -605,6 +692,41 @@ impl AssetManager {
         self.inner.store.get_packs()
     }
 
+    /// Returns the conflicts found between the loaded packs
+    pub fn conflicts(&self) -> &ConflictReport {
+        &self.inner.store.conflicts
+    }
+
+    /// Returns how the loaded packs depend on and override
+    /// each other
+    pub fn pack_graph(&self) -> &PackGraph {
+        &self.inner.store.graph
+    }
+
+    /// Returns the script api version the pack declared in its
+    /// manifest if any
+    pub fn api_version<'a, M>(&self, module: M) -> Option<u32>
+        where M: Into<ModuleKey<'a>>
+    {
+        self.inner.store.api_versions.get(module.into().module()).cloned()
+    }
+
+    /// Records a pack's use of a deprecated script function,
+    /// returning whether this was the first time it was used
+    /// by the pack
+    pub fn report_deprecation(&self, warning: script::Deprecation) -> bool {
+        assume!(self.inner.store.log, self.inner.store.deprecations.lock())
+            .record(warning)
+    }
+
+    /// Returns the uses of deprecated script functions by the
+    /// loaded packs
+    pub fn deprecations(&self) -> Vec<script::Deprecation> {
+        assume!(self.inner.store.log, self.inner.store.deprecations.lock())
+            .warnings()
+            .to_vec()
+    }
+
     /// Opens the named asset from the named pack
     ///
     /// This is case sensitive and paths should not start
@@ -628,6 +750,63 @@ impl AssetManager {
         self.inner.store.modified_time(module, name)
     }
 
+    /// Returns the names of every file within the folder of the
+    /// named pack, including those in sub-folders, sorted by name.
+    ///
+    /// Returned names include the folder e.g. `scripts/init.lua`
+    pub fn list_files<'a>(&self, module: ModuleKey<'a>, folder: &str) -> Vec<String> {
+        self.inner.store.list_files(module, folder)
+    }
+
+    /// Returns the names of all the seasons provided by the
+    /// loaded packs
+    pub fn seasons(&self) -> Vec<String> {
+        let mut seasons: Vec<String> = self.inner.store.seasons.iter()
+            .map(|v| v.name.clone())
+            .collect();
+        seasons.sort();
+        seasons.dedup();
+        seasons
+    }
+
+    /// Returns the names of the seasons that would be active
+    /// on the given day
+    pub fn seasons_for_day(&self, day: MonthDay) -> Vec<String> {
+        let mut seasons: Vec<String> = self.inner.store.seasons.iter()
+            .filter(|v| v.info.is_active_on(day))
+            .map(|v| v.name.clone())
+            .collect();
+        seasons.sort();
+        seasons.dedup();
+        seasons
+    }
+
+    /// Returns the names of the currently active seasons
+    pub fn active_seasons(&self) -> Vec<String> {
+        assume!(self.inner.store.log, self.inner.store.active_seasons.read()).clone()
+    }
+
+    /// Returns whether the named season is active
+    pub fn is_season_active(&self, name: &str) -> bool {
+        assume!(self.inner.store.log, self.inner.store.active_seasons.read())
+            .iter()
+            .any(|v| v == name)
+    }
+
+    /// Sets the seasons that are currently active.
+    ///
+    /// This only effects assets opened after this call, assets
+    /// cached by loaders will not be reloaded.
+    pub fn set_active_seasons(&self, seasons: Vec<String>) {
+        *assume!(self.inner.store.log, self.inner.store.active_seasons.write()) = seasons;
+    }
+
+    /// Returns whether the named asset is seasonal content that
+    /// is currently disabled.
+    pub fn is_gated<'a>(&self, module: ModuleKey<'a>, name: &str) -> bool {
+        self.inner.store.is_gated(module, name)
+    }
+
     /// Opens the named asset using the specified loader.
     ///
     /// Loaders can be used to load assets in the background
@@ -657,7 +836,11 @@ pub trait AssetLoader<'a> {
     /// Loader's are keyed on this type. Multiple loaders can
     /// share a single data source.
     type LoaderData: Any + Send;
-    /// The type of key used for the input of this loader
+    /// The type of key used for the input of this loader.
+    ///
+    /// Loaders for a single category of asset should use a
+    /// `TypedKey` so that keys for other categories can't be
+    /// passed to them.
     type Key;
     /// This is the type that will be returned to the caller
     /// when trying to load an asset with the loader.
@@ -672,9 +855,27 @@ pub trait AssetLoader<'a> {
     fn load(data: &mut Self::LoaderData, assets: &AssetManager, key: Self::Key) -> UResult<Self::Return>;
 }
 
+/// The parts of a pack's `meta.json` used when loading it
+#[derive(Deserialize)]
+struct PackManifest {
+    /// The version of the script api the pack was written against
+    #[serde(default)]
+    api_version: Option<u32>,
+    /// The modules that must be loaded before the pack
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
 /// Collection of packs
 pub struct Store {
     assets: Vec<(ModuleKey<'static>, Box<dyn Fetcher + Sync + Send>)>,
+    seasons: Vec<Season>,
+    active_seasons: RwLock<Vec<String>>,
+    conflicts: ConflictReport,
+    graph: PackGraph,
+    /// The script api versions declared by packs
+    api_versions: FNVMap<String, u32>,
+    deprecations: Mutex<script::compat::Deprecations>,
     /// The asset manager's logger
     pub log: Logger,
 }
@@ -695,6 +896,23 @@ impl Store {
     /// complex paths (e.g. with `..` or `.`) on all types of
     /// 'packs'.
     pub fn open_from_pack<'a>(&self, module: ModuleKey<'a>, name: &str) -> errors::Result<Asset> {
+        if !self.seasons.is_empty() {
+            let active = assume!(self.log, self.active_seasons.read());
+            let key = ResourceKey::new(module.borrow(), name);
+            for season in &self.seasons {
+                if active.contains(&season.name) {
+                    if let Some((_, rpl)) = season.replace.iter().find(|v| v.0 == key) {
+                        return self.open_unseasoned(rpl.module_key(), rpl.resource());
+                    }
+                } else if season.exclusive.iter().any(|v| *v == key) {
+                    return Err(format!("Missing file: {:?} : {} (requires the {} season)", module, name, season.name).into());
+                }
+            }
+        }
+        self.open_unseasoned(module, name)
+    }
+
+    fn open_unseasoned<'a>(&self, module: ModuleKey<'a>, name: &str) -> errors::Result<Asset> {
         for asset in self.assets.iter().rev() {
             if let Some(file) = asset.1.open(module.borrow(), name) {
                 return Ok(file);
@@ -703,6 +921,19 @@ impl Store {
         Err(format!("Missing file: {:?} : {}", module, name).into())
     }
 
+    /// Returns whether the named asset is seasonal content that
+    /// is currently disabled.
+    pub fn is_gated<'a>(&self, module: ModuleKey<'a>, name: &str) -> bool {
+        if self.seasons.is_empty() {
+            return false;
+        }
+        let active = assume!(self.log, self.active_seasons.read());
+        let key = ResourceKey::new(module, name);
+        self.seasons.iter()
+            .filter(|v| !active.contains(&v.name))
+            .any(|v| v.exclusive.iter().any(|v| *v == key))
+    }
+
     /// Returns the modified time of the named file if the
     /// pack supports it.
     ///
@@ -718,6 +949,18 @@ impl Store {
         }
         None
     }
+
+    /// Returns the names of every file within the folder of the
+    /// named pack, including those in sub-folders, sorted by name.
+    pub fn list_files<'a>(&self, module: ModuleKey<'a>, folder: &str) -> Vec<String> {
+        let mut files = FNVSet::default();
+        for asset in &self.assets {
+            files.extend(asset.1.list(module.borrow(), folder));
+        }
+        let mut files: Vec<_> = files.into_iter().collect();
+        files.sort();
+        files
+    }
 }
 
 /// An asset that has been loaded from an
@@ -768,6 +1011,9 @@ trait Fetcher: Sync + Send {
     fn modified_time(&self, _module: ModuleKey<'_>, _name: &str) -> Option<SystemTime> {
         None
     }
+    fn list(&self, module: ModuleKey<'_>, folder: &str) -> Vec<String>;
+    /// Returns every file in the fetcher for any module
+    fn files(&self) -> Vec<ResourceKey<'static>>;
 }
 
 struct DirFetcher(PathBuf);
@@ -816,35 +1062,121 @@ impl Fetcher for DirFetcher {
         };
         fs::metadata(path).and_then(|v| v.modified()).ok()
     }
+
+    fn list(&self, module: ModuleKey<'_>, folder: &str) -> Vec<String> {
+        fn walk(path: &Path, name: &str, out: &mut Vec<String>) {
+            let dir = if let Ok(dir) = fs::read_dir(path) {
+                dir
+            } else {
+                return;
+            };
+            for entry in dir.filter_map(|v| v.ok()) {
+                let file_name = entry.file_name();
+                let file_name = if let Some(val) = file_name.to_str() {
+                    val
+                } else {
+                    continue
+                };
+                let full = format!("{}/{}", name, file_name);
+                match entry.file_type() {
+                    Ok(ty) if ty.is_dir() => walk(&entry.path(), &full, out),
+                    Ok(ty) if ty.is_file() => out.push(full),
+                    _ => {},
+                }
+            }
+        }
+        let mut out = vec![];
+        let folder = folder.trim_end_matches('/');
+        if let Some(path) = self.clamp_path(module, folder) {
+            walk(&path, folder, &mut out);
+        }
+        out
+    }
+
+    fn files(&self) -> Vec<ResourceKey<'static>> {
+        let modules = fs::read_dir(&self.0).into_iter()
+            .flatten()
+            .filter_map(|v| v.ok())
+            .filter(|v| v.file_type().map_or(false, |v| v.is_dir()))
+            .filter_map(|v| v.file_name().into_string().ok());
+        let mut out = vec![];
+        for module in modules {
+            let module = ModuleKey::new(module);
+            for file in self.list(module.borrow(), "") {
+                out.push(ResourceKey::new(module.clone(), file.trim_start_matches('/').to_owned()));
+            }
+        }
+        out
+    }
+}
+
+/// Where a file is stored in a packed pack
+struct PackedEntry {
+    offset: usize,
+    stored_len: usize,
+    len: usize,
+    compression: ArchiveCompression,
 }
 
+/// Loads files from a packed pack, see `util::read_archive_index`
+/// for the format.
+///
+/// `assets/packed/<name>.pack` is used if it exists otherwise the
+/// older format, a `<name>.index` and `<name>.assets` file pair
+/// without any compression, is used.
 struct PackedFetcher {
-    index: FNVMap<ResourceKey<'static>, (u64, u64)>,
+    index: FNVMap<ResourceKey<'static>, PackedEntry>,
     map: Arc<memmap::Mmap>,
 }
 
 impl PackedFetcher {
     fn new(name: &str) -> UResult<PackedFetcher> {
+        let archive = format!("./assets/packed/{}.pack", name);
+        if fs::metadata(&archive).is_ok() {
+            Self::open_archive(&archive)
+        } else {
+            Self::open_legacy(name)
+        }
+    }
+
+    fn open_archive(path: &str) -> UResult<PackedFetcher> {
+        let map = unsafe {
+            memmap::MmapOptions::new()
+                .map(&fs::File::open(path)?)?
+        };
         let mut index = FNVMap::default();
-        let mut fi = fs::File::open(&format!("./assets/packed/{}.index", name))?;
+        for entry in read_archive_index(&map)? {
+            let key = Self::parse_path(&entry.path)?;
+            index.insert(key, PackedEntry {
+                offset: entry.offset as usize,
+                stored_len: entry.stored_len as usize,
+                len: entry.len as usize,
+                compression: entry.compression,
+            });
+        }
+        Ok(PackedFetcher {
+            index,
+            map: Arc::new(map),
+        })
+    }
+
+    fn open_legacy(name: &str) -> UResult<PackedFetcher> {
+        let mut index = FNVMap::default();
+        let mut fi = io::BufReader::new(fs::File::open(&format!("./assets/packed/{}.index", name))?);
         let len = fi.read_u32::<LittleEndian>()?;
         for _ in 0 .. len {
             let slen = fi.read_u16::<LittleEndian>()?;
             let mut buf = vec![0; slen as usize];
             fi.read_exact(&mut buf)?;
-            let name = String::from_utf8(buf)?;
-            let name = name.trim_start_matches('/');
-            let pos = name.char_indices().find(|v| v.1 == '/')
-                .ok_or_else::<ErrorKind, _>(|| "Invalid file path in index".into())?;
-            let (module, res) = name.split_at(pos.0);
-            let key = ResourceKey::new(
-                module,
-                &res[1..]
-            );
-            index.insert(key.into_owned(), (
-                fi.read_u64::<LittleEndian>()?,
-                fi.read_u64::<LittleEndian>()?
-            ));
+            let key = Self::parse_path(&String::from_utf8(buf)?)?;
+            let offset = fi.read_u64::<LittleEndian>()? as usize;
+            let len = fi.read_u64::<LittleEndian>()? as usize;
+            index.insert(key, PackedEntry {
+                offset,
+                stored_len: len,
+                len,
+                compression: ArchiveCompression::None,
+            });
         }
         Ok(PackedFetcher {
             index,
@@ -854,20 +1186,68 @@ impl PackedFetcher {
             }),
         })
     }
+
+    /// Splits a `module/path/to/file` path into its key
+    fn parse_path(path: &str) -> UResult<ResourceKey<'static>> {
+        let path = path.trim_start_matches('/');
+        let pos = path.char_indices().find(|v| v.1 == '/')
+            .ok_or_else::<ErrorKind, _>(|| "Invalid file path in index".into())?;
+        let (module, res) = path.split_at(pos.0);
+        Ok(ResourceKey::new(
+            module,
+            &res[1..]
+        ).into_owned())
+    }
 }
 
 impl Fetcher for PackedFetcher {
     fn open(&self, module: ModuleKey<'_>, name: &str) -> Option<Asset> {
-        let offset = self.index.get(&ResourceKey::new(module, name))?;
-        let data = self.map.get(offset.0 as usize .. offset.0 as usize + offset.1 as usize)?;
-        let data = unsafe {
-            &*(data as *const [u8] as *const [u8])
-        };
-        let mapped = MappedAsset {
-            _map: self.map.clone(),
-            reader: io::Cursor::new(data),
+        let entry = self.index.get(&ResourceKey::new(module, name))?;
+        let data = self.map.get(entry.offset .. entry.offset.checked_add(entry.stored_len)?)?;
+        match entry.compression {
+            ArchiveCompression::None => {
+                let data = unsafe {
+                    &*(data as *const [u8] as *const [u8])
+                };
+                let mapped = MappedAsset {
+                    _map: self.map.clone(),
+                    reader: io::Cursor::new(data),
+                };
+                Some(Asset::Mapped(mapped))
+            },
+            ArchiveCompression::Deflate => {
+                use flate2::read::DeflateDecoder;
+                let mut buf = Vec::with_capacity(entry.len);
+                // Limited in case the entry's length is wrong
+                DeflateDecoder::new(data)
+                    .take(entry.len as u64 + 1)
+                    .read_to_end(&mut buf)
+                    .ok()?;
+                if buf.len() != entry.len {
+                    return None;
+                }
+                Some(Asset::Buffer(io::Cursor::new(buf)))
+            },
+        }
+    }
+
+    fn list(&self, module: ModuleKey<'_>, folder: &str) -> Vec<String> {
+        let folder = folder.trim_end_matches('/');
+        let prefix = if folder.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", folder)
         };
-        Some(Asset::Mapped(mapped))
+        self.index.keys()
+            .filter(|v| v.module() == module.module() && v.resource().starts_with(&prefix))
+            .map(|v| v.resource().to_owned())
+            .collect()
+    }
+
+    fn files(&self) -> Vec<ResourceKey<'static>> {
+        self.index.keys()
+            .cloned()
+            .collect()
     }
 }
 
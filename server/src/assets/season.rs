@@ -0,0 +1,142 @@
+//! Seasonal content that is only enabled during
+//! certain parts of the year.
+//!
+//! Each pack may contain a `seasons.json` file within its
+//! own module folder that describes the seasons it provides:
+//!
+//! ```ignore
+//! {
+//!     "halloween": {
+//!         "start": {"month": 10, "day": 20},
+//!         "end": {"month": 11, "day": 2},
+//!         "replace": {
+//!             "textures/tiles/grass": "seasonal/halloween/grass"
+//!         },
+//!         "exclusive": [
+//!             "objects/pumpkin.json"
+//!         ]
+//!     }
+//! }
+//! ```
+//!
+//! `replace` redirects a file to another whilst the season
+//! is active and `exclusive` files can only be opened whilst
+//! the season is active. Paths may include a module prefix
+//! (`module:path`) to target another module's files.
+
+use crate::util::FNVMap;
+use crate::prelude::*;
+
+/// A day of the year, ignoring the year itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct MonthDay {
+    /// The month, starting at 1
+    pub month: u32,
+    /// The day of the month, starting at 1
+    pub day: u32,
+}
+
+impl MonthDay {
+    /// Returns whether the day is within the range (inclusive).
+    ///
+    /// `end` may be before `start` for ranges that cross over
+    /// the new year
+    pub fn is_between(self, start: MonthDay, end: MonthDay) -> bool {
+        if start <= end {
+            self >= start && self <= end
+        } else {
+            self >= start || self <= end
+        }
+    }
+}
+
+/// Information about a single season provided by a pack
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeasonInfo {
+    /// The first day the season is active
+    pub start: MonthDay,
+    /// The last day the season is active.
+    ///
+    /// May be before `start` for seasons that cross over
+    /// the new year
+    pub end: MonthDay,
+    /// Files that are replaced by another whilst the season
+    /// is active
+    #[serde(default)]
+    pub replace: FNVMap<String, String>,
+    /// Files that can only be opened whilst the season is
+    /// active
+    #[serde(default)]
+    pub exclusive: Vec<String>,
+}
+
+impl SeasonInfo {
+    /// Returns whether the season is active on the given day
+    pub fn is_active_on(&self, day: MonthDay) -> bool {
+        day.is_between(self.start, self.end)
+    }
+}
+
+/// A loaded season with its file paths resolved
+pub(super) struct Season {
+    pub(super) name: String,
+    pub(super) info: SeasonInfo,
+    pub(super) replace: Vec<(ResourceKey<'static>, ResourceKey<'static>)>,
+    pub(super) exclusive: Vec<ResourceKey<'static>>,
+}
+
+impl Season {
+    pub(super) fn new(module: ModuleKey<'_>, name: String, info: SeasonInfo) -> Season {
+        Season {
+            replace: info.replace.iter()
+                .map(|(k, v)| (
+                    LazyResourceKey::parse(k).or_module(module.borrow()).into_owned(),
+                    LazyResourceKey::parse(v).or_module(module.borrow()).into_owned(),
+                ))
+                .collect(),
+            exclusive: info.exclusive.iter()
+                .map(|v| LazyResourceKey::parse(v).or_module(module.borrow()).into_owned())
+                .collect(),
+            name,
+            info,
+        }
+    }
+}
+
+/// Returns the current day of the year in local time
+pub fn today() -> MonthDay {
+    use chrono::Datelike;
+    let now = chrono::Local::now();
+    MonthDay {
+        month: now.month(),
+        day: now.day(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn season(start: (u32, u32), end: (u32, u32)) -> SeasonInfo {
+        SeasonInfo {
+            start: MonthDay { month: start.0, day: start.1 },
+            end: MonthDay { month: end.0, day: end.1 },
+            replace: FNVMap::default(),
+            exclusive: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_season_range() {
+        let halloween = season((10, 20), (11, 2));
+        assert!(halloween.is_active_on(MonthDay { month: 10, day: 31 }));
+        assert!(halloween.is_active_on(MonthDay { month: 11, day: 2 }));
+        assert!(!halloween.is_active_on(MonthDay { month: 11, day: 3 }));
+        assert!(!halloween.is_active_on(MonthDay { month: 1, day: 1 }));
+
+        let winter = season((12, 1), (1, 31));
+        assert!(winter.is_active_on(MonthDay { month: 12, day: 25 }));
+        assert!(winter.is_active_on(MonthDay { month: 1, day: 10 }));
+        assert!(!winter.is_active_on(MonthDay { month: 6, day: 1 }));
+    }
+}
@@ -0,0 +1,249 @@
+//! Resource keys tagged with the category of asset they reference.
+//!
+//! A plain `ResourceKey` doesn't say what it points at so passing a
+//! model's key where a texture was expected only fails once the
+//! texture is looked up (as `textures/<model>.png`). `TypedKey` carries
+//! the category in its type so that loaders which only handle a single
+//! category can use it as their `AssetLoader::Key` and have mix ups
+//! caught at compile time instead.
+//!
+//! Keys read from data files are plain resource keys and are converted
+//! with `TypedKey::from_key` (or `TypedKey::check` to also make sure
+//! the file exists) where their category is known.
+
+use super::*;
+use std::marker::PhantomData;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+/// A category of asset stored in its own folder in a module
+pub trait AssetCategory: 'static {
+    /// The name of the category used in errors
+    const NAME: &'static str;
+    /// The folder within the module the assets are stored in
+    const FOLDER: &'static str;
+    /// The extension of the asset's files
+    const EXTENSION: &'static str;
+}
+
+/// Images stored as `textures/<name>.png`
+pub enum TextureAsset {}
+
+impl AssetCategory for TextureAsset {
+    const NAME: &'static str = "texture";
+    const FOLDER: &'static str = "textures";
+    const EXTENSION: &'static str = "png";
+}
+
+/// Static models stored as `models/<name>.umod`
+pub enum ModelAsset {}
+
+impl AssetCategory for ModelAsset {
+    const NAME: &'static str = "model";
+    const FOLDER: &'static str = "models";
+    const EXTENSION: &'static str = "umod";
+}
+
+/// Animated models stored as `models/<name>.uamod`
+pub enum AnimatedModelAsset {}
+
+impl AssetCategory for AnimatedModelAsset {
+    const NAME: &'static str = "animated model";
+    const FOLDER: &'static str = "models";
+    const EXTENSION: &'static str = "uamod";
+}
+
+/// A resource key that references an asset of the category `C`
+pub struct TypedKey<'a, C> {
+    key: ResourceKey<'a>,
+    // `fn() -> C` keeps the key `Send` and `Sync` whatever the category
+    _category: PhantomData<fn() -> C>,
+}
+
+impl <'a, C: AssetCategory> TypedKey<'a, C> {
+    /// Creates a key that references the named resource.
+    ///
+    /// The resource doesn't include the category's folder or
+    /// extension.
+    pub fn new<'b: 'a, 'c: 'a, M, S>(module: M, resource: S) -> TypedKey<'a, C>
+        where S: Into<ArcStr<'b>>,
+            M: Into<ModuleKey<'c>>
+    {
+        Self::from_key(ResourceKey::new(module, resource))
+    }
+
+    /// Marks the key as referencing an asset of this category
+    /// without checking it exists.
+    pub fn from_key(key: ResourceKey<'a>) -> TypedKey<'a, C> {
+        TypedKey {
+            key,
+            _category: PhantomData,
+        }
+    }
+
+    /// Marks the key as referencing an asset of this category,
+    /// returning an error if the asset doesn't exist.
+    ///
+    /// Useful for checking keys from data files when they are
+    /// loaded instead of when they are first used.
+    pub fn check(key: ResourceKey<'a>, assets: &AssetManager) -> UResult<TypedKey<'a, C>> {
+        let key = Self::from_key(key);
+        if assets.open_from_pack(key.key.module_key(), &key.file()).is_err() {
+            bail!("{:?} isn't a {} (missing {})", key.key, C::NAME, key.file());
+        }
+        Ok(key)
+    }
+
+    /// Parses the passed string into a key.
+    ///
+    /// Returns `None` if the string doesn't contain a `:` (module)
+    pub fn parse(val: &'a str) -> Option<TypedKey<'a, C>> {
+        ResourceKey::parse(val).map(Self::from_key)
+    }
+
+    /// Returns the untyped resource key
+    pub fn key(&'a self) -> ResourceKey<'a> {
+        self.key.borrow()
+    }
+
+    /// Returns the untyped resource key
+    pub fn into_key(self) -> ResourceKey<'a> {
+        self.key
+    }
+
+    /// Returns the module name of this key
+    pub fn module(&self) -> &str {
+        self.key.module()
+    }
+
+    /// Returns the resource of this key
+    pub fn resource(&self) -> &str {
+        self.key.resource()
+    }
+
+    /// Returns the path of the asset's file within its module
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use univercity_server::assets::*;
+    /// let key: TypedKey<'_, TextureAsset> = TypedKey::new("base", "tiles/grass");
+    /// assert_eq!(key.file(), "textures/tiles/grass.png");
+    /// ```
+    pub fn file(&self) -> String {
+        format!("{}/{}.{}", C::FOLDER, self.key.resource(), C::EXTENSION)
+    }
+
+    /// Opens the asset's file
+    pub fn open(&self, assets: &AssetManager) -> errors::Result<Asset> {
+        assets.open_from_pack(self.key.module_key(), &self.file())
+    }
+
+    /// Returns a owned version of the key.
+    pub fn into_owned(self) -> TypedKey<'static, C> {
+        TypedKey::from_key(self.key.into_owned())
+    }
+
+    /// Creates a key that references the same data as this key.
+    pub fn borrow(&'a self) -> TypedKey<'a, C> {
+        TypedKey::from_key(self.key.borrow())
+    }
+}
+
+impl <'a, C> Clone for TypedKey<'a, C> {
+    fn clone(&self) -> Self {
+        TypedKey {
+            key: self.key.clone(),
+            _category: PhantomData,
+        }
+    }
+}
+
+impl <'a, 'b, C> PartialEq<TypedKey<'a, C>> for TypedKey<'b, C> {
+    fn eq(&self, other: &TypedKey<'a, C>) -> bool {
+        self.key == other.key
+    }
+}
+
+impl <'a, C> Eq for TypedKey<'a, C> {}
+
+impl <'a, C> Hash for TypedKey<'a, C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state)
+    }
+}
+
+impl <'a, C> PartialOrd for TypedKey<'a, C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl <'a, C> Ord for TypedKey<'a, C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl <'a, C: AssetCategory> Debug for TypedKey<'a, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}({}:{})", C::NAME, self.key.module(), self.key.resource())
+    }
+}
+
+impl <'a, C> From<TypedKey<'a, C>> for ResourceKey<'a> {
+    fn from(v: TypedKey<'a, C>) -> ResourceKey<'a> {
+        v.key
+    }
+}
+
+impl <'a, C> Serialize for TypedKey<'a, C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.key.serialize(serializer)
+    }
+}
+
+impl <'de, 'a, C> Deserialize<'de> for TypedKey<'a, C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(TypedKey {
+            key: ResourceKey::deserialize(deserializer)?,
+            _category: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files() {
+        let tex: TypedKey<'_, TextureAsset> = TypedKey::new("base", "tiles/grass");
+        assert_eq!(tex.file(), "textures/tiles/grass.png");
+        let model: TypedKey<'_, ModelAsset> = TypedKey::new("base", "tiles/grass");
+        assert_eq!(model.file(), "models/tiles/grass.umod");
+        let animated: TypedKey<'_, AnimatedModelAsset> = TypedKey::new("base", "student");
+        assert_eq!(animated.file(), "models/student.uamod");
+    }
+
+    #[test]
+    fn convert() {
+        let key = ResourceKey::new("base", "wall");
+        let tex = TypedKey::<TextureAsset>::from_key(key.borrow());
+        assert_eq!(tex.key(), key);
+        assert_eq!(ResourceKey::from(tex.clone().into_owned()), key);
+        assert_eq!(TypedKey::<TextureAsset>::parse("base:wall"), Some(tex));
+        assert_eq!(TypedKey::<TextureAsset>::parse("wall"), None);
+        assert_eq!(format!("{:?}", TypedKey::<ModelAsset>::from_key(key)), "model(base:wall)");
+    }
+
+    #[test]
+    fn serialize() {
+        let tex = TypedKey::<TextureAsset>::new("base", "wall");
+        let json = serde_json::to_string(&tex).unwrap();
+        assert_eq!(json, serde_json::to_string(&ResourceKey::new("base", "wall")).unwrap());
+        let back: TypedKey<'static, TextureAsset> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, tex);
+    }
+}
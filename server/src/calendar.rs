@@ -0,0 +1,339 @@
+//! The in-game calendar and the outdoor seasons it drives.
+//!
+//! Each pack may contain an `outdoor_seasons.json` file within its
+//! own module folder that describes the outdoor variants used for
+//! each season:
+//!
+//! ```ignore
+//! {
+//!     "winter": {
+//!         "textures": {
+//!             "grass": "seasonal/winter/grass"
+//!         },
+//!         "models": {
+//!             "static/tree": "static/tree_snow"
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Later packs override the variants of earlier ones per key.
+//! Paths may include a module prefix (`module:path`) to target
+//! another module's files.
+//!
+//! This is separate from `assets::season` which follows the real
+//! world's calendar instead of the game's.
+
+use chrono::{self, NaiveDate, Datelike};
+use crate::assets::MonthDay;
+use crate::util::FNVMap;
+use crate::prelude::*;
+
+/// The year the game starts in
+pub const START_YEAR: i32 = 2018;
+/// The number of days at the end of a season spent changing
+/// into the next one
+pub const TRANSITION_DAYS: u32 = 7;
+/// The number of steps a transition between seasons is split
+/// into
+pub const BLEND_STEPS: u8 = 16;
+
+/// Returns the date of the given in-game day
+pub fn date(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd(START_YEAR, 1, 1) + chrono::Duration::days(i64::from(day))
+}
+
+/// Returns the day of the year of the given in-game day
+pub fn month_day(day: u32) -> MonthDay {
+    let date = date(day);
+    MonthDay {
+        month: date.month(),
+        day: date.day(),
+    }
+}
+
+/// A season of the outdoor environment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutdoorSeason {
+    /// March to May
+    Spring,
+    /// June to August
+    Summer,
+    /// September to November
+    Autumn,
+    /// December to February
+    Winter,
+}
+
+impl OutdoorSeason {
+    /// Every season in the order they happen
+    pub const ALL: [OutdoorSeason; 4] = [
+        OutdoorSeason::Spring,
+        OutdoorSeason::Summer,
+        OutdoorSeason::Autumn,
+        OutdoorSeason::Winter,
+    ];
+
+    /// Returns the season the day is in
+    pub fn for_day(day: MonthDay) -> OutdoorSeason {
+        match day.month {
+            3 ..= 5 => OutdoorSeason::Spring,
+            6 ..= 8 => OutdoorSeason::Summer,
+            9 ..= 11 => OutdoorSeason::Autumn,
+            _ => OutdoorSeason::Winter,
+        }
+    }
+
+    /// Returns the name of the season as used in `outdoor_seasons.json`
+    pub fn name(self) -> &'static str {
+        match self {
+            OutdoorSeason::Spring => "spring",
+            OutdoorSeason::Summer => "summer",
+            OutdoorSeason::Autumn => "autumn",
+            OutdoorSeason::Winter => "winter",
+        }
+    }
+
+    /// Returns the season that follows this one
+    pub fn next(self) -> OutdoorSeason {
+        match self {
+            OutdoorSeason::Spring => OutdoorSeason::Summer,
+            OutdoorSeason::Summer => OutdoorSeason::Autumn,
+            OutdoorSeason::Autumn => OutdoorSeason::Winter,
+            OutdoorSeason::Winter => OutdoorSeason::Spring,
+        }
+    }
+
+    /// Returns the month the season starts in
+    fn start_month(self) -> u32 {
+        match self {
+            OutdoorSeason::Spring => 3,
+            OutdoorSeason::Summer => 6,
+            OutdoorSeason::Autumn => 9,
+            OutdoorSeason::Winter => 12,
+        }
+    }
+}
+
+/// The progress of the change from one season to the next
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SeasonBlend {
+    /// The season being changed from
+    pub from: OutdoorSeason,
+    /// The season being changed into
+    pub to: OutdoorSeason,
+    /// How far through the change it is, from `0.0` (only `from`)
+    /// to `1.0` (only `to`)
+    pub amount: f32,
+}
+
+impl SeasonBlend {
+    /// Returns the step of the change that the blend is on.
+    ///
+    /// The visuals only need updating when this changes
+    pub fn step(self) -> u8 {
+        ((self.amount * f32::from(BLEND_STEPS)) as u8).min(BLEND_STEPS)
+    }
+
+    /// Returns the season the thing with the given seed should
+    /// use.
+    ///
+    /// Things are moved into the next season a few at a time
+    /// so the change fades across the campus instead of all
+    /// happening at once
+    pub fn season_for(self, seed: u32) -> OutdoorSeason {
+        if (seed % u32::from(BLEND_STEPS)) < u32::from(self.step()) {
+            self.to
+        } else {
+            self.from
+        }
+    }
+}
+
+/// Returns the seasons at the given in-game day and the fraction
+/// (`0.0` to `1.0`) through it
+pub fn season_blend(day: u32, day_fraction: f32) -> SeasonBlend {
+    let today = date(day);
+    let from = OutdoorSeason::for_day(MonthDay {
+        month: today.month(),
+        day: today.day(),
+    });
+    let to = from.next();
+    // Winter crosses over the new year so spring may start in
+    // the next one
+    let next_year = if to == OutdoorSeason::Spring && today.month() == 12 {
+        today.year() + 1
+    } else {
+        today.year()
+    };
+    let start = NaiveDate::from_ymd(next_year, to.start_month(), 1);
+    let remaining = (start - today).num_days() as f32 - day_fraction.max(0.0).min(1.0);
+    SeasonBlend {
+        from,
+        to,
+        amount: (1.0 - remaining / TRANSITION_DAYS as f32).max(0.0).min(1.0),
+    }
+}
+
+/// Returns a seed for the location used to pick a season during
+/// a change
+pub fn location_seed(loc: Location) -> u32 {
+    let v = (loc.x as u32).wrapping_mul(73_856_093) ^ (loc.y as u32).wrapping_mul(19_349_663);
+    v ^ (v >> 13)
+}
+
+/// The textures and models used outdoors for each season
+#[derive(Debug, Default)]
+pub struct SeasonVariants {
+    seasons: FNVMap<OutdoorSeason, Variants>,
+}
+
+#[derive(Debug, Default)]
+struct Variants {
+    textures: FNVMap<ResourceKey<'static>, ResourceKey<'static>>,
+    models: FNVMap<ResourceKey<'static>, ResourceKey<'static>>,
+}
+
+impl SeasonVariants {
+    /// Loads the variants from every pack
+    pub fn load(log: &Logger, assets: &AssetManager) -> SeasonVariants {
+        let mut variants = SeasonVariants::default();
+        for module in assets.get_packs() {
+            let file = match assets.open_from_pack(module.borrow(), "outdoor_seasons.json") {
+                Ok(val) => val,
+                Err(_) => continue,
+            };
+            let info: FNVMap<String, VariantsInfo> = match serde_json::from_reader(file) {
+                Ok(val) => val,
+                Err(err) => {
+                    error!(log, "Failed to parse outdoor_seasons.json for pack {:?}: {}", module, err);
+                    continue
+                }
+            };
+            variants.add(module.borrow(), log, info);
+        }
+        variants
+    }
+
+    fn add(&mut self, module: ModuleKey<'_>, log: &Logger, info: FNVMap<String, VariantsInfo>) {
+        let key = |v: &str| LazyResourceKey::parse(v).or_module(module.borrow()).into_owned();
+        for (name, info) in info {
+            let season = if let Some(season) = OutdoorSeason::ALL.iter().find(|v| v.name() == name) {
+                *season
+            } else {
+                warn!(log, "Unknown outdoor season {:?} in pack {:?}", name, module);
+                continue;
+            };
+            let variants = self.seasons.entry(season).or_insert_with(Variants::default);
+            variants.textures.extend(info.textures.iter().map(|(k, v)| (key(k), key(v))));
+            variants.models.extend(info.models.iter().map(|(k, v)| (key(k), key(v))));
+        }
+    }
+
+    /// Returns whether no pack provides any variants
+    pub fn is_empty(&self) -> bool {
+        self.seasons.values().all(|v| v.textures.is_empty() && v.models.is_empty())
+    }
+
+    /// Returns whether the model or texture is replaced during
+    /// any season
+    pub fn is_seasonal(&self, model: ResourceKey<'_>, texture: Option<ResourceKey<'_>>) -> bool {
+        self.seasons.values().any(|v|
+            v.models.contains_key(&model)
+            || texture.as_ref().map_or(false, |t| v.textures.contains_key(t))
+        )
+    }
+
+    /// Returns the texture to use in place of the given one during
+    /// the season, if it is replaced
+    pub fn texture(&self, season: OutdoorSeason, texture: ResourceKey<'_>) -> Option<ResourceKey<'static>> {
+        self.seasons.get(&season)
+            .and_then(|v| v.textures.get(&texture))
+            .cloned()
+    }
+
+    /// Returns the model to use in place of the given one during
+    /// the season, if it is replaced
+    pub fn model(&self, season: OutdoorSeason, model: ResourceKey<'_>) -> Option<ResourceKey<'static>> {
+        self.seasons.get(&season)
+            .and_then(|v| v.models.get(&model))
+            .cloned()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct VariantsInfo {
+    #[serde(default)]
+    textures: FNVMap<String, String>,
+    #[serde(default)]
+    models: FNVMap<String, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(0), NaiveDate::from_ymd(2018, 1, 1));
+        assert_eq!(month_day(59), MonthDay { month: 3, day: 1 });
+        assert_eq!(OutdoorSeason::for_day(month_day(0)), OutdoorSeason::Winter);
+        assert_eq!(OutdoorSeason::for_day(month_day(59)), OutdoorSeason::Spring);
+        assert_eq!(OutdoorSeason::for_day(month_day(364)), OutdoorSeason::Winter);
+    }
+
+    #[test]
+    fn blend() {
+        // Mid January is settled into winter
+        let b = season_blend(14, 0.0);
+        assert_eq!((b.from, b.to, b.step()), (OutdoorSeason::Winter, OutdoorSeason::Spring, 0));
+        // The last day of February is most of the way into spring
+        let b = season_blend(58, 0.5);
+        assert_eq!(b.to, OutdoorSeason::Spring);
+        assert!(b.step() > BLEND_STEPS / 2 && b.step() < BLEND_STEPS);
+        // Spring into summer
+        let b = season_blend(150, 1.0);
+        assert_eq!((b.from, b.to, b.step()), (OutdoorSeason::Spring, OutdoorSeason::Summer, BLEND_STEPS));
+        // December counts towards the next year's spring
+        let b = season_blend(340, 0.0);
+        assert_eq!((b.from, b.to, b.step()), (OutdoorSeason::Winter, OutdoorSeason::Spring, 0));
+    }
+
+    #[test]
+    fn season_for() {
+        let blend = SeasonBlend {
+            from: OutdoorSeason::Summer,
+            to: OutdoorSeason::Autumn,
+            amount: 0.5,
+        };
+        let changed = (0 .. u32::from(BLEND_STEPS))
+            .filter(|v| blend.season_for(*v) == OutdoorSeason::Autumn)
+            .count();
+        assert_eq!(changed, usize::from(BLEND_STEPS / 2));
+        let done = SeasonBlend { amount: 1.0, .. blend };
+        assert!((0 .. 64).all(|v| done.season_for(v) == OutdoorSeason::Autumn));
+    }
+
+    #[test]
+    fn variants_override() {
+        let log = Logger::root(::slog::Discard, o!());
+        let mut variants = SeasonVariants::default();
+        let info = |tex: &str| {
+            let mut textures = FNVMap::default();
+            textures.insert("grass".to_owned(), tex.to_owned());
+            let mut info = FNVMap::default();
+            info.insert("winter".to_owned(), VariantsInfo {
+                textures,
+                models: FNVMap::default(),
+            });
+            info
+        };
+        variants.add(ModuleKey::new("base"), &log, info("snow"));
+        variants.add(ModuleKey::new("extra"), &log, info("base:slush"));
+        assert_eq!(
+            variants.texture(OutdoorSeason::Winter, ResourceKey::new("base", "grass")),
+            Some(ResourceKey::new("base", "slush"))
+        );
+        assert_eq!(variants.texture(OutdoorSeason::Summer, ResourceKey::new("base", "grass")), None);
+    }
+}
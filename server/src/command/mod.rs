@@ -2,12 +2,15 @@
 
 use crate::prelude::*;
 use crate::player::State;
+use crate::level::scatter::ScatterStroke;
 use crate::entity::snapshot::Snapshots;
 use crate::common::ScriptData;
 use std::sync::Arc;
 
 use std::fmt;
+use std::io;
 use lua;
+use delta_encode::DeltaEncodable;
 
 /// The number of commands that an implementation should keep
 /// in a history queue to allow rolling back.
@@ -29,6 +32,21 @@ pub struct CommandParams<'a, E: Invokable + 'static> {
     pub mission_handler: Option<ResourceKey<'a>>,
 }
 
+impl <'a, E: Invokable + 'static> CommandParams<'a, E> {
+    /// Borrows the parameters again so that they can be passed
+    /// to another command
+    pub fn reborrow(&mut self) -> CommandParams<'_, E> {
+        CommandParams {
+            log: self.log,
+            level: &mut *self.level,
+            engine: self.engine,
+            entities: &mut *self.entities,
+            snapshots: self.snapshots,
+            mission_handler: self.mission_handler.as_ref().map(|v| v.borrow()),
+        }
+    }
+}
+
 impl ::lua::LuaUsable for Command {}
 
 /// Tries to execute the passed command, runs the internal block on success.
@@ -114,6 +132,8 @@ macro_rules! commands {
                 $(#[$cattr])*
                 $name($name),
             )*
+            /// Several commands executed together as one
+            Batch(Batch),
         }
 
         impl Command {
@@ -128,6 +148,17 @@ macro_rules! commands {
                             true
                         },
                     )*
+                    Command::Batch(ref b) => b.commands.iter().all(Command::should_sync),
+                }
+            }
+
+            /// Returns the name of this command's type
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(
+                        Command::$name(_) => stringify!($name),
+                    )*
+                    Command::Batch(_) => "Batch",
                 }
             }
         }
@@ -142,6 +173,11 @@ macro_rules! commands {
                 where C: CommandHandler,
                       E: Invokable,
             {
+                // Each command in the batch is filtered as it executes
+                if let Command::Batch(ref mut b) = *self {
+                    return b.execute(handler, player, params);
+                }
+                handler.filter_command(self, player, &mut params)?;
                 match *self {
                     $(
                         Command::$name(ref mut c) => {
@@ -161,6 +197,7 @@ macro_rules! commands {
                             Ok(())
                         },
                     )*
+                    Command::Batch(..) => unreachable!(),
                 }
             }
 
@@ -177,6 +214,7 @@ macro_rules! commands {
                             handler.$unname(c, player, &mut params);
                         },
                     )*
+                    Command::Batch(ref mut b) => b.undo(handler, player, params),
                 }
             }
         }
@@ -185,6 +223,14 @@ macro_rules! commands {
         pub trait CommandHandler {
             /// The type of player this handle can handle
             type Player: Player;
+
+            /// Called before any command is executed allowing the
+            /// implementation to reject it before any state is changed
+            fn filter_command<E>(&mut self, _: &Command, _: &mut Self::Player, _params: &mut CommandParams<'_, E>) -> UResult<()>
+                where E: Invokable,
+            {
+                Ok(())
+            }
             $(
                 /// Implementation specific executing of this command
                 fn $exname<E>(&mut self, _: &mut $name, _: &mut Self::Player, _params: &mut CommandParams<'_, E>) -> UResult<()>
@@ -332,6 +378,11 @@ commands! {
                     }
                     let id = params.level.finalize_placement(active_room);
                     cmd.room_id = Some(id);
+                    // Room ids are reused so clear out the history
+                    // of any room that had the id before
+                    if let Some(finances) = params.entities.get_component_mut::<player::RoomFinances>(Container::WORLD) {
+                        finances.remove_room(id);
+                    }
                     player.set_state(State::EditRoom{
                         active_room: id
                     });
@@ -441,7 +492,12 @@ commands! {
             #[delta_default]
             old_active_room: Option<room::Id>,
         },
-        impl FinalizeRoom {},
+        impl FinalizeRoom {
+            /// Returns the room this command finalized once executed
+            pub fn room(&self) -> Option<room::Id> {
+                self.old_active_room
+            }
+        },
         #[derive(Serialize)]
         struct FinalizeRoomParam {
             key: String,
@@ -558,6 +614,158 @@ commands! {
             },
         }
     }
+    /// Resizes the room that is currently being built
+    command ResizeEditRoom {
+        pub struct ResizeEditRoom {
+            new_bound: Bound,
+            shift_objects: bool,
+            #[delta_default]
+            reshaped: Option<ReshapedRoom>,
+        },
+        impl ResizeEditRoom {
+            /// Creates a ResizeEditRoom request that will change the active
+            /// room's size to the passed bounds.
+            ///
+            /// If `shift_objects` is set the room's objects are moved with
+            /// the top left corner of the room.
+            pub fn new(bound: Bound, shift_objects: bool) -> ResizeEditRoom {
+                ResizeEditRoom {
+                    new_bound: bound,
+                    shift_objects,
+                    reshaped: None,
+                }
+            }
+        },
+        impl Clone for ResizeEditRoom {
+            fn clone(&self) -> Self {
+                ResizeEditRoom {
+                    new_bound: self.new_bound,
+                    shift_objects: self.shift_objects,
+                    reshaped: None,
+                }
+            }
+        }
+        exec {
+            execute execute_resize_edit_room fn execute_resize_edit_room<P, E>(cmd: &mut ResizeEditRoom, player: &mut P, params: &mut CommandParams<'_, E>) -> UResult<()>
+                where P: Player,
+                      E: Invokable,
+            {
+                if let State::EditRoom{active_room} = player.get_state() {
+                    {
+                        let room = params.level.try_room_info(active_room)
+                            .ok_or_else(|| ErrorKind::InvalidRoomState)?;
+                        if !room.state.is_building() || room.limited_editing || room.owner != player.get_uid() {
+                            return Err(ErrorKind::InvalidRoomState.into());
+                        }
+                    }
+                    let reshaped = params.level.reshape_room::<P::EntityCreator, _>(
+                        params.engine, params.entities,
+                        active_room, cmd.new_bound,
+                        cmd.shift_objects
+                    )?;
+                    cmd.reshaped = Some(reshaped);
+                    Ok(())
+                } else {
+                    Err(ErrorKind::NoActiveRoom.into())
+                }
+            },
+            undo undo_resize_edit_room fn undo_resize_edit_room<P, E>(cmd: &mut ResizeEditRoom, player: &mut P, params: &mut CommandParams<'_, E>)
+                where P: Player,
+                      E: Invokable,
+            {
+                if let (Some(reshaped), State::EditRoom{active_room}) = (cmd.reshaped.take(), player.get_state()) {
+                    params.level.undo_reshape_room::<P::EntityCreator, _>(params.engine, params.entities, active_room, reshaped);
+                }
+            },
+        }
+    }
+    /// Moves the room that is currently being built so that its
+    /// top left corner is at the target location.
+    ///
+    /// Rooms that have already been paid for are charged a fee
+    /// to be moved.
+    command MoveRoom {
+        pub struct MoveRoom {
+            target: Location,
+            #[delta_default]
+            reshaped: Option<ReshapedRoom>,
+            #[delta_default]
+            cost: UniDollar,
+        },
+        impl MoveRoom {
+            /// Creates a MoveRoom request that will move the active
+            /// room to the passed location.
+            pub fn new(target: Location) -> MoveRoom {
+                MoveRoom {
+                    target,
+                    reshaped: None,
+                    cost: UniDollar(0),
+                }
+            }
+        },
+        impl Clone for MoveRoom {
+            fn clone(&self) -> Self {
+                MoveRoom::new(self.target)
+            }
+        }
+        exec {
+            execute execute_move_room fn execute_move_room<P, E>(cmd: &mut MoveRoom, player: &mut P, params: &mut CommandParams<'_, E>) -> UResult<()>
+                where P: Player,
+                      E: Invokable,
+            {
+                if let State::EditRoom{active_room} = player.get_state() {
+                    let (room_info, area, paid) = {
+                        let room = params.level.try_room_info(active_room)
+                            .ok_or_else(|| ErrorKind::InvalidRoomState)?;
+                        if !room.state.is_building() || room.limited_editing || room.owner != player.get_uid() {
+                            return Err(ErrorKind::InvalidRoomState.into());
+                        }
+                        (
+                            params.level.asset_manager.loader_open::<room::Loader>(room.key.borrow())?,
+                            room.area,
+                            room.placement_cost != UniDollar(0)
+                        )
+                    };
+
+                    // Rooms that are still only being planned are free
+                    // to move as nothing has been built yet
+                    let cost = if player.can_charge() && paid {
+                        let cost = room_info.cost_for_move(area);
+                        if player.get_money() < cost && cost != UniDollar(0) {
+                            return Err(ErrorKind::NotEnoughMoney.into());
+                        }
+                        cost
+                    } else { UniDollar(0) };
+
+                    let offset = (cmd.target.x - area.min.x, cmd.target.y - area.min.y);
+                    let mut new_bound = area;
+                    new_bound.min += offset;
+                    new_bound.max += offset;
+                    let reshaped = params.level.reshape_room::<P::EntityCreator, _>(
+                        params.engine, params.entities,
+                        active_room, new_bound,
+                        true
+                    )?;
+                    player.change_money(-cost);
+                    cmd.cost = cost;
+                    cmd.reshaped = Some(reshaped);
+                    Ok(())
+                } else {
+                    Err(ErrorKind::NoActiveRoom.into())
+                }
+            },
+            undo undo_move_room fn undo_move_room<P, E>(cmd: &mut MoveRoom, player: &mut P, params: &mut CommandParams<'_, E>)
+                where P: Player,
+                      E: Invokable,
+            {
+                if let (Some(reshaped), State::EditRoom{active_room}) = (cmd.reshaped.take(), player.get_state()) {
+                    params.level.undo_reshape_room::<P::EntityCreator, _>(params.engine, params.entities, active_room, reshaped);
+                    player.change_money(cmd.cost);
+                    cmd.cost = UniDollar(0);
+                }
+            },
+        }
+    }
     /// Edits a placed room
     command EditRoom {
         #[derive(Clone)]
@@ -809,6 +1017,85 @@ commands! {
             },
         }
     }
+    /// Furnishes the active room using one of the room's prefabs
+    command AutoFurnish {
+        pub struct AutoFurnish {
+            prefab: u32,
+            #[delta_default]
+            rev: Vec<usize>,
+        },
+        impl Clone for AutoFurnish {
+            fn clone(&self) -> AutoFurnish {
+                AutoFurnish {
+                    prefab: self.prefab,
+                    rev: Vec::new(),
+                }
+            }
+        },
+        impl AutoFurnish {
+            /// Creates a new auto furnish command using the
+            /// prefab at the given index in the room's prefab list
+            pub fn new(prefab: usize) -> AutoFurnish {
+                AutoFurnish {
+                    prefab: prefab as u32,
+                    rev: Vec::new(),
+                }
+            }
+        }
+        exec {
+            execute execute_auto_furnish fn execute_auto_furnish<P, E>(cmd: &mut AutoFurnish, player: &mut P, params: &mut CommandParams<'_, E>) -> UResult<()>
+                where P: Player,
+                      E: Invokable,
+            {
+                if let State::EditRoom{active_room} = player.get_state() {
+                    let (room_info, area, old_cost) = {
+                        let room = params.level.get_room_info(active_room);
+                        if !room.state.is_building() && !room.limited_editing {
+                            return Err(ErrorKind::InvalidRoomState.into());
+                        }
+                        (
+                            params.level.asset_manager.loader_open::<room::Loader>(room.key.borrow())?,
+                            room.area,
+                            room.placement_cost,
+                        )
+                    };
+                    let prefab = room_info.prefabs.get(cmd.prefab as usize)
+                        .ok_or_else(|| ErrorKind::InvalidCommand)?;
+                    if !prefab.fits(area) {
+                        return Err(ErrorKind::UnplaceableArea.into());
+                    }
+                    // Only spend what the player will have left after
+                    // paying for what is already in the room
+                    let budget = if player.can_charge() {
+                        let current = room_info.cost_for_room(params.level, active_room) - old_cost;
+                        let current = if current < UniDollar(0) {
+                            UniDollar(0)
+                        } else {
+                            current
+                        };
+                        Some(player.get_money() - current)
+                    } else { None };
+
+                    cmd.rev = prefab.furnish::<_, P::EntityCreator>(params.level, active_room, params.engine, params.entities, budget)?;
+                    Ok(())
+                } else {
+                    Err(ErrorKind::NoActiveRoom.into())
+                }
+            },
+            undo undo_auto_furnish fn undo_auto_furnish<P, E>(cmd: &mut AutoFurnish, player: &mut P, params: &mut CommandParams<'_, E>)
+                where P: Player,
+                      E: Invokable,
+            {
+                if let State::EditRoom{active_room} = player.get_state() {
+                    for id in cmd.rev.drain(..).rev() {
+                        assume!(params.log,
+                            params.level.remove_object::<P::EntityCreator>(params.entities, active_room, id)
+                        );
+                    }
+                }
+            },
+        }
+    }
     /// Places the active entity to the target location
     command RemoveObject {
         pub struct RemoveObject {
@@ -1122,6 +1409,85 @@ commands! {
         }
         sync false
     }
+    /// Orders the staff member to the location, giving them to
+    /// the room there if it needs staff.
+    ///
+    /// Unlike `StartMoveStaff` this doesn't pick the staff member up
+    /// so many can be ordered at once within a `Batch`.
+    command OrderStaff {
+        pub struct OrderStaff {
+            /// The target entity's network id
+            pub target: u32,
+            #[doc(hidden)]
+            pub(crate) location: InWorldPosition,
+            /// The info to reverse this command
+            #[delta_default]
+            pub rev: Option<(Entity, f32, f32)>,
+        },
+        impl Clone for OrderStaff {
+            fn clone(&self) -> OrderStaff {
+                OrderStaff {
+                    target: self.target,
+                    location: self.location,
+                    rev: None,
+                }
+            }
+        },
+        impl OrderStaff {
+            /// Creates an order staff command for the entity with
+            /// the given network id
+            pub fn new(target: u32, location: (f32, f32)) -> OrderStaff {
+                OrderStaff {
+                    target,
+                    location: InWorldPosition {
+                        x: location.0,
+                        y: location.1,
+                    },
+                    rev: None,
+                }
+            }
+        }
+        exec {
+            execute execute_order_staff fn execute_order_staff<P, E>(cmd: &mut OrderStaff, player: &mut P, params: &mut CommandParams<'_, E>) -> UResult<()>
+                where P: Player,
+                      E: Invokable,
+            {
+                if let State::None = player.get_state() {
+                    if let Some(entity) = params.snapshots.get_entity_by_id(cmd.target) {
+                        let player_id = player.get_uid();
+                        if !params.entities.get_component::<Owned>(entity).map_or(false, |v| v.player_id == player_id) {
+                            bail!("Entity not owned by player");
+                        }
+                        if params.entities.get_component::<Paid>(entity).is_none() {
+                            bail!("Entity not controlled by player");
+                        }
+                        if !can_visit(
+                            &*params.level.tiles.borrow(), &*params.level.rooms.borrow(),
+                            (cmd.location.x * 4.0) as usize, (cmd.location.y * 4.0) as usize
+                        ) {
+                            return Err(ErrorKind::UnplaceableArea.into());
+                        }
+                        let pos = assume!(params.log, params.entities.get_component::<Position>(entity));
+                        cmd.rev = Some((entity, pos.x, pos.z));
+                        Ok(())
+                    } else {
+                        bail!("Missing entity")
+                    }
+                } else {
+                    bail!("Incorrect state")
+                }
+            },
+            undo undo_order_staff fn undo_order_staff<P, E>(cmd: &mut OrderStaff, _player: &mut P, params: &mut CommandParams<'_, E>)
+                where P: Player,
+                      E: Invokable,
+            {
+                if let Some((e, x, z)) = cmd.rev {
+                    teleport(params.entities, e, x, z);
+                }
+            },
+        }
+        sync false
+    }
     /// Places the named entity at the location
     command CancelPlaceStaff {
         pub struct CancelPlaceStaff {
@@ -1288,7 +1654,11 @@ commands! {
                     }
                     if let Some(paid) = params.entities.get_component_mut::<Paid>(entity) {
                         if cmd.bonus {
-                            player.change_money(-paid.cost / 10);
+                            let bonus = paid.cost / 10;
+                            player.change_money(-bonus);
+                            if let Some(room) = params.entities.get_component::<RoomOwned>(entity).map(|v| v.room_id) {
+                                player::record_room_money(params.entities, room, player::FinanceKind::Wages, bonus);
+                            }
                         } else {
                             paid.cost = paid.wanted_cost;
                         }
@@ -1599,10 +1969,330 @@ commands! {
         }
         sync false
     }
+    /// Moves a student on to another course
+    command AssignCourse {
+        #[derive(Clone)]
+        pub struct AssignCourse {
+            /// The target entity's network id
+            pub target: u32,
+            pub(crate) course: course::CourseId,
+        },
+        impl AssignCourse {
+            /// Creates an assign course command for the entity with
+            /// the given network id
+            pub fn new(target: u32, course: course::CourseId) -> AssignCourse {
+                AssignCourse {
+                    target,
+                    course,
+                }
+            }
+        }
+        exec {
+            execute execute_assign_course fn execute_assign_course<P, E>(cmd: &mut AssignCourse, player: &mut P, params: &mut CommandParams<'_, E>) -> UResult<()>
+                where P: Player,
+                      E: Invokable,
+            {
+                if let State::None = player.get_state() {
+                    if let Some(entity) = params.snapshots.get_entity_by_id(cmd.target) {
+                        let player_id = player.get_uid();
+                        if !params.entities.get_component::<Owned>(entity).map_or(false, |v| v.player_id == player_id) {
+                            bail!("Entity not owned by player");
+                        }
+                        Ok(())
+                    } else {
+                        bail!("Missing entity")
+                    }
+                } else {
+                    bail!("Incorrect state")
+                }
+            },
+            undo undo_assign_course fn undo_assign_course<P, E>(_cmd: &mut AssignCourse, _player: &mut P, _params: &mut CommandParams<'_, E>)
+                where P: Player,
+                      E: Invokable,
+            {
+            },
+        }
+        sync false
+    }
+    /// Paints decorative props onto the level with the
+    /// level editor's scatter brush
+    command PaintScatter {
+        #[derive(Clone)]
+        pub struct PaintScatter {
+            stroke: ScatterStroke,
+        },
+        impl PaintScatter {
+            /// Creates a paint scatter command for the stroke
+            pub fn new(stroke: ScatterStroke) -> PaintScatter {
+                PaintScatter {
+                    stroke,
+                }
+            }
+        }
+        exec {
+            execute execute_paint_scatter fn execute_paint_scatter<P, E>(cmd: &mut PaintScatter, player: &mut P, params: &mut CommandParams<'_, E>) -> UResult<()>
+                where P: Player,
+                      E: Invokable,
+            {
+                if let State::None = player.get_state() {
+                    params.level.scatter.add(cmd.stroke.clone())
+                } else {
+                    bail!("Incorrect state")
+                }
+            },
+            undo undo_paint_scatter fn undo_paint_scatter<P, E>(cmd: &mut PaintScatter, _player: &mut P, params: &mut CommandParams<'_, E>)
+                where P: Player,
+                      E: Invokable,
+            {
+                params.level.scatter.remove(cmd.stroke.id);
+            },
+        }
+    }
+    /// Removes every scatter brush stroke covering the location
+    command EraseScatter {
+        pub struct EraseScatter {
+            x: f32,
+            y: f32,
+            #[delta_default]
+            removed: Vec<ScatterStroke>,
+        },
+        impl Clone for EraseScatter {
+            fn clone(&self) -> EraseScatter {
+                EraseScatter {
+                    x: self.x,
+                    y: self.y,
+                    removed: Vec::new(),
+                }
+            }
+        },
+        impl EraseScatter {
+            /// Creates an erase scatter command at the location
+            pub fn new(x: f32, y: f32) -> EraseScatter {
+                EraseScatter {
+                    x,
+                    y,
+                    removed: Vec::new(),
+                }
+            }
+        }
+        exec {
+            execute execute_erase_scatter fn execute_erase_scatter<P, E>(cmd: &mut EraseScatter, player: &mut P, params: &mut CommandParams<'_, E>) -> UResult<()>
+                where P: Player,
+                      E: Invokable,
+            {
+                if let State::None = player.get_state() {
+                    let ids = params.level.scatter.strokes_at(cmd.x, cmd.y);
+                    if ids.is_empty() {
+                        bail!("Nothing to erase");
+                    }
+                    cmd.removed = ids.into_iter()
+                        .filter_map(|id| params.level.scatter.remove(id))
+                        .collect();
+                    Ok(())
+                } else {
+                    bail!("Incorrect state")
+                }
+            },
+            undo undo_erase_scatter fn undo_erase_scatter<P, E>(cmd: &mut EraseScatter, _player: &mut P, params: &mut CommandParams<'_, E>)
+                where P: Player,
+                      E: Invokable,
+            {
+                for stroke in cmd.removed.drain(..) {
+                    if let Err(err) = params.level.scatter.add(stroke) {
+                        warn!(params.log, "Failed to restore scatter stroke"; "error" => %err);
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// The most commands a single batch can contain
+pub const MAX_BATCH_SIZE: usize = 64;
+
+/// Several commands that are validated and executed together,
+/// e.g. every object placed by auto-furnishing a room.
+///
+/// Either every command in the batch executes or, if one of them
+/// fails, the commands before it are undone so that nothing is
+/// left half built. The batch is a single entry in the command
+/// queue so rolling it back undoes all of it at once.
+///
+/// Batches can't contain other batches and the commands in a batch
+/// must either all be synced to other players or none of them.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    commands: Vec<Command>,
+}
+
+impl Batch {
+    /// Creates a batch of the commands, executed in order
+    pub fn new(commands: Vec<Command>) -> UResult<Batch> {
+        let batch = Batch {
+            commands,
+        };
+        batch.validate()?;
+        Ok(batch)
+    }
+
+    /// Returns the commands in the batch
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    fn validate(&self) -> UResult<()> {
+        if self.commands.is_empty() {
+            bail!("Empty command batch");
+        }
+        if self.commands.len() > MAX_BATCH_SIZE {
+            bail!("Too many commands in batch ({} > {})", self.commands.len(), MAX_BATCH_SIZE);
+        }
+        if self.commands.iter().any(|v| matches!(v, Command::Batch(..))) {
+            bail!("Command batches can't be nested");
+        }
+        let sync = self.commands[0].should_sync();
+        if self.commands.iter().any(|v| v.should_sync() != sync) {
+            bail!("Command batches can't mix synced and unsynced commands");
+        }
+        Ok(())
+    }
+
+    fn execute<C, E>(&mut self, handler: &mut C, player: &mut C::Player, mut params: CommandParams<'_, E>) -> UResult<()>
+        where C: CommandHandler,
+              E: Invokable,
+    {
+        self.validate()?;
+        for idx in 0 .. self.commands.len() {
+            if let Err(err) = self.commands[idx].execute(handler, player, params.reborrow()) {
+                // The failed command has already reversed itself
+                for cmd in self.commands[..idx].iter_mut().rev() {
+                    cmd.undo(handler, player, params.reborrow());
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn undo<C, E>(&mut self, handler: &mut C, player: &mut C::Player, mut params: CommandParams<'_, E>)
+        where C: CommandHandler,
+              E: Invokable,
+    {
+        for cmd in self.commands.iter_mut().rev() {
+            cmd.undo(handler, player, params.reborrow());
+        }
+    }
+}
+
+impl From<Batch> for Command {
+    fn from(c: Batch) -> Command {
+        Command::Batch(c)
+    }
+}
+
+thread_local! {
+    // Set whilst a batch is being decoded to stop nested
+    // batches from recursing
+    static DECODING_BATCH: ::std::cell::Cell<bool> = ::std::cell::Cell::new(false);
+}
+
+impl DeltaEncodable for Batch {
+    fn encode<W>(&self, _base: Option<&Self>, w: &mut bitio::Writer<W>) -> io::Result<()>
+        where W: io::Write
+    {
+        w.write_unsigned(self.commands.len() as u64, 8)?;
+        for cmd in &self.commands {
+            cmd.encode(None, w)?;
+        }
+        Ok(())
+    }
+
+    fn decode<R>(_base: Option<&Self>, r: &mut bitio::Reader<R>) -> io::Result<Self>
+        where R: io::Read
+    {
+        if DECODING_BATCH.with(|v| v.replace(true)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Nested command batch"));
+        }
+        let res = (|| {
+            let len = r.read_unsigned(8)? as usize;
+            if len > MAX_BATCH_SIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Command batch too large"));
+            }
+            let mut commands = Vec::with_capacity(len);
+            for _ in 0 .. len {
+                commands.push(Command::decode(None, r)?);
+            }
+            Ok(Batch {
+                commands,
+            })
+        })();
+        DECODING_BATCH.with(|v| v.set(false));
+        res
+    }
 }
 
 #[derive(DeltaEncode, Debug, Clone, Copy, PartialEq)]
 pub(crate) struct InWorldPosition {
     pub(crate) x: f32,
     pub(crate) y: f32,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(cmd: &Command) -> io::Result<Command> {
+        let mut w = bitio::Writer::new(vec![]);
+        cmd.encode(None, &mut w)?;
+        let data = w.finish()?;
+        let mut r = bitio::Reader::new(io::Cursor::new(data));
+        Command::decode(None, &mut r)
+    }
+
+    #[test]
+    fn batch_limits() {
+        assert!(Batch::new(vec![]).is_err());
+        assert!(Batch::new(vec![Sorry{}.into(); MAX_BATCH_SIZE + 1]).is_err());
+        let batch = Batch::new(vec![Sorry{}.into(); MAX_BATCH_SIZE]).unwrap();
+        assert!(Batch::new(vec![batch.into()]).is_err());
+    }
+
+    #[test]
+    fn batch_encoding() {
+        let batch: Command = Batch {
+            commands: vec![Sorry{}.into(), Sorry{}.into()],
+        }.into();
+        match decode(&batch) {
+            Ok(Command::Batch(b)) => assert_eq!(b.commands().len(), 2),
+            other => panic!("Unexpected decode result {:?}", other),
+        }
+
+        // Built by hand as `new` rejects nested batches
+        let nested: Command = Batch {
+            commands: vec![batch],
+        }.into();
+        assert!(decode(&nested).is_err());
+        // Decoding still works after a failure
+        assert!(decode(&Batch { commands: vec![Sorry{}.into()] }.into()).is_ok());
+    }
+
+    #[test]
+    fn order_staff_batch() {
+        let cmds = (0 .. 3)
+            .map(|v| Command::from(OrderStaff::new(v, (v as f32, 2.5))))
+            .collect();
+        let batch: Command = Batch::new(cmds).unwrap().into();
+        match decode(&batch) {
+            Ok(Command::Batch(b)) => {
+                assert_eq!(b.commands().len(), 3);
+                match &b.commands()[2] {
+                    Command::OrderStaff(cmd) => {
+                        assert_eq!(cmd.target, 2);
+                        assert_eq!(cmd.location, InWorldPosition { x: 2.0, y: 2.5 });
+                    },
+                    other => panic!("Unexpected command {:?}", other),
+                }
+            },
+            other => panic!("Unexpected decode result {:?}", other),
+        }
+    }
+}
@@ -47,6 +47,52 @@ impl AnimationInfo {
     }
 }
 
+/// A collection of animations an object plays when triggered,
+/// sorted by name so that triggers can be referred to by index
+pub type AnimationTriggers = Arc<Vec<AnimationTrigger>>;
+
+/// Animations played on an object in response to an event, e.g.
+/// a door opening or a vending machine being used
+pub struct AnimationTrigger {
+    /// The name of the event that triggers the animations
+    pub name: String,
+    /// The names of the animations (from the object's animation
+    /// set) to play in order
+    pub animations: Vec<String>,
+    /// The sound to play at the object when triggered
+    pub sound: Option<assets::ResourceKey<'static>>,
+}
+
+/// JSON'able version of `AnimationTrigger`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnimationTriggerInfo {
+    animations: Vec<String>,
+    #[serde(default)]
+    sound: Option<String>,
+}
+
+impl AnimationTriggerInfo {
+    /// Converts the JSON'able collection of triggers into `AnimationTriggers`
+    pub fn map_to_triggers(module: assets::ModuleKey<'_>, vals: FNVMap<String, AnimationTriggerInfo>) -> AnimationTriggers {
+        let mut triggers: Vec<_> = vals.into_iter()
+            .map(|(k, v)| AnimationTrigger {
+                name: k,
+                animations: v.animations,
+                sound: v.sound.map(|v| assets::LazyResourceKey::parse(&v)
+                    .or_module(module.borrow())
+                    .into_owned()),
+            })
+            .collect();
+        triggers.sort_by(|a, b| a.name.cmp(&b.name));
+        Arc::new(triggers)
+    }
+}
+
+/// Returns the index of the named trigger
+pub fn trigger_index(triggers: &[AnimationTrigger], name: &str) -> Option<usize> {
+    triggers.binary_search_by(|v| v.name.as_str().cmp(name)).ok()
+}
+
 /// Describes an entry into the mission table
 #[derive(Debug, Deserialize, Clone)]
 pub struct MissionEntry {
@@ -0,0 +1,240 @@
+//! Scripted camera sequences defined by missions.
+//!
+//! Mission handlers start a cutscene from their server methods
+//! with `control.play_cutscene`:
+//!
+//! ```ignore
+//! control.play_cutscene {
+//!     letterbox = true,
+//!     camera = {
+//!         {time = 0.0, x = 20, y = 20, rotation = 45, zoom = 1.0},
+//!         {time = 6.0, x = 40, y = 32, zoom = 0.6},
+//!     },
+//!     spotlights = {
+//!         {time = 2.0, duration = 3.0, entity = dean:get_id()},
+//!         {time = 5.0, duration = 1.0, x = 40, y = 32},
+//!     },
+//!     dialogue = {
+//!         {time = 0.5, duration = 4.0, speaker = "Dean", text = "Welcome!"},
+//!     },
+//! }
+//! ```
+//!
+//! Times are in seconds from the start of the cutscene. The
+//! camera moves linearly between its keys and keeps its current
+//! rotation and zoom if a key doesn't provide them.
+//!
+//! The cutscene is sent to every player once they have all
+//! loaded so that they watch it together. Each player may skip
+//! it without affecting the others.
+
+use crate::prelude::*;
+use lua;
+use delta_encode::AlwaysVec;
+
+/// The longest a cutscene may last in seconds
+pub const MAX_LENGTH: f32 = 5.0 * 60.0;
+
+/// A sequence of camera movements and dialogue played by
+/// the client
+#[derive(Debug, Clone, DeltaEncode)]
+pub struct Cutscene {
+    /// Whether black bars are displayed at the top and bottom
+    /// of the screen
+    pub letterbox: bool,
+    /// The positions the camera moves between, ordered by time
+    pub camera: AlwaysVec<CameraKey>,
+    /// Locations or entities to highlight
+    pub spotlights: AlwaysVec<Spotlight>,
+    /// Text boxes displayed during the cutscene
+    pub dialogue: AlwaysVec<Dialogue>,
+}
+
+/// A position of the camera at a point in the cutscene
+#[derive(Debug, Clone, DeltaEncode, Deserialize)]
+pub struct CameraKey {
+    /// The time in seconds the camera reaches this key
+    pub time: f32,
+    /// The x position of the camera
+    pub x: f32,
+    /// The y position of the camera
+    pub y: f32,
+    /// The rotation of the camera in degrees
+    #[serde(default)]
+    pub rotation: Option<f32>,
+    /// The zoom level of the camera
+    #[serde(default)]
+    pub zoom: Option<f32>,
+}
+
+/// Highlights a location or an entity for a period of the
+/// cutscene
+#[derive(Debug, Clone, DeltaEncode, Deserialize)]
+pub struct Spotlight {
+    /// The time in seconds the spotlight appears
+    pub time: f32,
+    /// How long the spotlight is displayed for in seconds
+    pub duration: f32,
+    /// The network id of the entity to follow if any
+    #[serde(default)]
+    pub entity: Option<u32>,
+    /// The x position to highlight if not following an entity
+    #[serde(default)]
+    pub x: f32,
+    /// The y position to highlight if not following an entity
+    #[serde(default)]
+    pub y: f32,
+}
+
+/// A text box displayed during a cutscene
+#[derive(Debug, Clone, DeltaEncode, Deserialize)]
+pub struct Dialogue {
+    /// The time in seconds the text appears
+    pub time: f32,
+    /// How long the text is displayed for in seconds
+    pub duration: f32,
+    /// The name of the character speaking if any
+    #[serde(default)]
+    pub speaker: Option<String>,
+    /// The text to display
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+struct CutsceneDesc {
+    #[serde(default)]
+    letterbox: bool,
+    #[serde(default)]
+    camera: Vec<CameraKey>,
+    #[serde(default)]
+    spotlights: Vec<Spotlight>,
+    #[serde(default)]
+    dialogue: Vec<Dialogue>,
+}
+
+impl Cutscene {
+    /// Parses a cutscene from the table passed by a script
+    pub fn from_lua(tbl: &lua::Ref<lua::Table>) -> UResult<Cutscene> {
+        let desc = lua::from_table::<CutsceneDesc>(tbl)
+            .map_err(ErrorKind::Lua)?;
+        let mut camera = desc.camera;
+        camera.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(::std::cmp::Ordering::Equal));
+
+        let cutscene = Cutscene {
+            letterbox: desc.letterbox,
+            camera: AlwaysVec(camera),
+            spotlights: AlwaysVec(desc.spotlights),
+            dialogue: AlwaysVec(desc.dialogue),
+        };
+        let length = cutscene.length();
+        if !length.is_finite() || length > MAX_LENGTH {
+            bail!("Cutscene is too long ({}s), the limit is {}s", length, MAX_LENGTH);
+        }
+        Ok(cutscene)
+    }
+
+    /// Returns the length of the cutscene in seconds
+    pub fn length(&self) -> f32 {
+        let camera = self.camera.0.iter().map(|v| v.time);
+        let spotlights = self.spotlights.0.iter().map(|v| v.time + v.duration);
+        let dialogue = self.dialogue.0.iter().map(|v| v.time + v.duration);
+        camera.chain(spotlights).chain(dialogue)
+            .fold(0.0, f32::max)
+    }
+
+    /// Returns the position, rotation and zoom of the camera at
+    /// the passed time. Rotation and zoom are `None` when no key
+    /// before the time provided them.
+    pub fn camera_at(&self, time: f32) -> Option<((f32, f32), Option<f32>, Option<f32>)> {
+        camera_at(&self.camera.0, time)
+    }
+}
+
+/// Returns the position, rotation and zoom of the camera at the
+/// passed time along the keys which must be ordered by time.
+///
+/// See `Cutscene::camera_at`
+pub fn camera_at(keys: &[CameraKey], time: f32) -> Option<((f32, f32), Option<f32>, Option<f32>)> {
+    let first = keys.first()?;
+    let idx = keys.iter().rposition(|v| v.time <= time);
+    let (prev, next) = match idx {
+        None => (first, first),
+        Some(idx) => (&keys[idx], keys.get(idx + 1).unwrap_or(&keys[idx])),
+    };
+    let t = if next.time > prev.time {
+        ((time - prev.time) / (next.time - prev.time)).max(0.0).min(1.0)
+    } else {
+        0.0
+    };
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+    // Only use the next key's value if the previous key set one
+    // otherwise use the latest value before this point
+    let latest = |f: fn(&CameraKey) -> Option<f32>| {
+        let upto = idx.unwrap_or(0);
+        keys[..=upto].iter().rev().filter_map(f).next()
+    };
+    let rotation = match (prev.rotation, next.rotation) {
+        (Some(a), Some(b)) => Some(lerp(a, b)),
+        _ => latest(|v| v.rotation),
+    };
+    let zoom = match (prev.zoom, next.zoom) {
+        (Some(a), Some(b)) => Some(lerp(a, b)),
+        _ => latest(|v| v.zoom),
+    };
+    Some(((lerp(prev.x, next.x), lerp(prev.y, next.y)), rotation, zoom))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, x: f32, zoom: Option<f32>) -> CameraKey {
+        CameraKey {
+            time,
+            x,
+            y: 0.0,
+            rotation: None,
+            zoom,
+        }
+    }
+
+    fn cutscene(camera: Vec<CameraKey>) -> Cutscene {
+        Cutscene {
+            letterbox: false,
+            camera: AlwaysVec(camera),
+            spotlights: AlwaysVec(vec![]),
+            dialogue: AlwaysVec(vec![Dialogue {
+                time: 8.0,
+                duration: 4.0,
+                speaker: None,
+                text: "Hello".into(),
+            }]),
+        }
+    }
+
+    #[test]
+    fn length_includes_dialogue() {
+        let c = cutscene(vec![key(0.0, 0.0, None), key(5.0, 10.0, None)]);
+        assert_eq!(c.length(), 12.0);
+    }
+
+    #[test]
+    fn camera_interpolates() {
+        let c = cutscene(vec![key(0.0, 0.0, Some(1.0)), key(4.0, 8.0, Some(0.5))]);
+        let ((x, _), _, zoom) = c.camera_at(1.0).unwrap();
+        assert_eq!(x, 2.0);
+        assert_eq!(zoom, Some(0.875));
+        // Clamped to the ends
+        assert_eq!((c.camera_at(-1.0).unwrap().0).0, 0.0);
+        assert_eq!((c.camera_at(10.0).unwrap().0).0, 8.0);
+    }
+
+    #[test]
+    fn camera_keeps_missing_zoom() {
+        let c = cutscene(vec![key(0.0, 0.0, Some(1.0)), key(4.0, 8.0, None)]);
+        assert_eq!(c.camera_at(2.0).unwrap().2, Some(1.0));
+        let c = cutscene(vec![key(0.0, 0.0, None), key(4.0, 8.0, None)]);
+        assert_eq!(c.camera_at(2.0).unwrap().2, None);
+        assert!(cutscene(vec![]).camera_at(0.0).is_none());
+    }
+}
@@ -0,0 +1,206 @@
+//! Visual effects that mission scripts can play on every
+//! player's screen.
+//!
+//! Mission handlers play an effect from their server methods:
+//!
+//! ```ignore
+//! control.screen_shake {strength = 0.5, duration = 1.5}
+//! control.screen_flash {r = 255, g = 240, b = 200, duration = 0.5}
+//! control.set_fog {density = 0.6, fade = 4.0}
+//! control.confetti {x = 20, y = 32, count = 80}
+//! ```
+//!
+//! Durations are in seconds. Values outside of the allowed ranges
+//! are clamped here and the client applies its own limits on top,
+//! reducing or skipping effects if the player asked for fewer
+//! effects in the settings.
+
+use crate::prelude::*;
+use lua;
+
+/// The longest a shake, flash or fog fade may last in seconds
+pub const MAX_DURATION: f32 = 10.0;
+/// The densest the fog may become
+pub const MAX_FOG_DENSITY: f32 = 0.8;
+/// The most confetti a single burst may create
+pub const MAX_CONFETTI: u16 = 150;
+/// The number of effects that may be waiting to be sent to a
+/// player at once, extra effects are rejected
+pub const MAX_PENDING: usize = 16;
+
+/// An effect played by the client
+#[derive(Debug, Clone, PartialEq, DeltaEncode)]
+#[delta_always]
+pub enum VisualEffect {
+    /// Shakes the camera
+    Shake {
+        /// How far the camera moves, between 0 and 1
+        strength: f32,
+        /// The time in seconds the shake lasts for
+        duration: f32,
+    },
+    /// Flashes the screen a color before fading back
+    Flash {
+        /// The red component of the flash
+        r: u8,
+        /// The green component of the flash
+        g: u8,
+        /// The blue component of the flash
+        b: u8,
+        /// The time in seconds the flash takes to fade
+        duration: f32,
+    },
+    /// Changes the density of the fog covering the level
+    Fog {
+        /// The density of the fog, 0 being no fog
+        density: f32,
+        /// The time in seconds taken to reach the density
+        fade: f32,
+    },
+    /// Throws confetti into the air at a location
+    Confetti {
+        /// The x position of the burst
+        x: f32,
+        /// The y position of the burst
+        y: f32,
+        /// The number of pieces of confetti
+        count: u16,
+    },
+}
+
+#[derive(Deserialize)]
+struct ShakeDesc {
+    #[serde(default = "default_strength")]
+    strength: f32,
+    #[serde(default = "default_duration")]
+    duration: f32,
+}
+
+#[derive(Deserialize)]
+struct FlashDesc {
+    #[serde(default = "default_channel")]
+    r: u8,
+    #[serde(default = "default_channel")]
+    g: u8,
+    #[serde(default = "default_channel")]
+    b: u8,
+    #[serde(default = "default_duration")]
+    duration: f32,
+}
+
+#[derive(Deserialize)]
+struct FogDesc {
+    density: f32,
+    #[serde(default = "default_duration")]
+    fade: f32,
+}
+
+#[derive(Deserialize)]
+struct ConfettiDesc {
+    x: f32,
+    y: f32,
+    #[serde(default = "default_count")]
+    count: u16,
+}
+
+fn default_strength() -> f32 { 0.5 }
+fn default_duration() -> f32 { 1.0 }
+fn default_channel() -> u8 { 255 }
+fn default_count() -> u16 { 60 }
+
+impl VisualEffect {
+    /// Parses the named effect from the table passed by a script
+    pub fn from_lua(kind: &str, tbl: &lua::Ref<lua::Table>) -> UResult<VisualEffect> {
+        let effect = match kind {
+            "shake" => {
+                let desc = lua::from_table::<ShakeDesc>(tbl).map_err(ErrorKind::Lua)?;
+                VisualEffect::Shake {
+                    strength: desc.strength,
+                    duration: desc.duration,
+                }
+            },
+            "flash" => {
+                let desc = lua::from_table::<FlashDesc>(tbl).map_err(ErrorKind::Lua)?;
+                VisualEffect::Flash {
+                    r: desc.r,
+                    g: desc.g,
+                    b: desc.b,
+                    duration: desc.duration,
+                }
+            },
+            "fog" => {
+                let desc = lua::from_table::<FogDesc>(tbl).map_err(ErrorKind::Lua)?;
+                VisualEffect::Fog {
+                    density: desc.density,
+                    fade: desc.fade,
+                }
+            },
+            "confetti" => {
+                let desc = lua::from_table::<ConfettiDesc>(tbl).map_err(ErrorKind::Lua)?;
+                VisualEffect::Confetti {
+                    x: desc.x,
+                    y: desc.y,
+                    count: desc.count,
+                }
+            },
+            kind => bail!("Unknown visual effect {:?}", kind),
+        };
+        effect.clamped()
+    }
+
+    /// Limits the effect's values to the allowed ranges.
+    ///
+    /// Fails if any of the values aren't numbers
+    pub fn clamped(self) -> UResult<VisualEffect> {
+        fn clamp(v: f32, max: f32) -> UResult<f32> {
+            if !v.is_finite() {
+                bail!("Visual effect values must be finite");
+            }
+            Ok(v.max(0.0).min(max))
+        }
+        Ok(match self {
+            VisualEffect::Shake { strength, duration } => VisualEffect::Shake {
+                strength: clamp(strength, 1.0)?,
+                duration: clamp(duration, MAX_DURATION)?,
+            },
+            VisualEffect::Flash { r, g, b, duration } => VisualEffect::Flash {
+                r, g, b,
+                duration: clamp(duration, MAX_DURATION)?,
+            },
+            VisualEffect::Fog { density, fade } => VisualEffect::Fog {
+                density: clamp(density, MAX_FOG_DENSITY)?,
+                fade: clamp(fade, MAX_DURATION)?,
+            },
+            VisualEffect::Confetti { x, y, count } => {
+                if !x.is_finite() || !y.is_finite() {
+                    bail!("Visual effect values must be finite");
+                }
+                VisualEffect::Confetti {
+                    x, y,
+                    count: count.min(MAX_CONFETTI),
+                }
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps_to_limits() {
+        let shake = VisualEffect::Shake { strength: 4.0, duration: 600.0 }.clamped().unwrap();
+        assert_eq!(shake, VisualEffect::Shake { strength: 1.0, duration: MAX_DURATION });
+        let fog = VisualEffect::Fog { density: -1.0, fade: 2.0 }.clamped().unwrap();
+        assert_eq!(fog, VisualEffect::Fog { density: 0.0, fade: 2.0 });
+        let confetti = VisualEffect::Confetti { x: 1.0, y: 2.0, count: 10_000 }.clamped().unwrap();
+        assert_eq!(confetti, VisualEffect::Confetti { x: 1.0, y: 2.0, count: MAX_CONFETTI });
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(VisualEffect::Shake { strength: ::std::f32::NAN, duration: 1.0 }.clamped().is_err());
+        assert!(VisualEffect::Confetti { x: ::std::f32::INFINITY, y: 0.0, count: 1 }.clamped().is_err());
+    }
+}
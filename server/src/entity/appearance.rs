@@ -0,0 +1,292 @@
+//! Generates the names and appearances of entities.
+//!
+//! Packs describe the possible appearances of an entity in
+//! `entity/appearance/<name>.json` as pools of names per locale,
+//! a palette of colors for each tinted part of the model and the
+//! mesh parts to pick between for each slot of the model (e.g. the
+//! face or hair). Everything is generated from a single seed so
+//! clients only need to be sent the seed to end up with the same
+//! appearance as the server.
+
+use serde_json;
+use std::sync::Arc;
+use crate::assets;
+use crate::ecs;
+use crate::prelude::*;
+use lua::{self, Ref, Scope, Table};
+use super::{NameList, TintColor};
+use super::info::NameListInfo;
+
+/// The locale used for names when there isn't a pool of names
+/// for the requested one
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Loads appearance descriptions
+pub enum Loader {}
+
+impl <'a> assets::AssetLoader<'a> for Loader {
+    type LoaderData = LoaderData;
+    type Return = Arc<AppearanceData>;
+    type Key = assets::ResourceKey<'a>;
+
+    fn init(_assets: &assets::Store) -> Self::LoaderData {
+        LoaderData {
+            appearances: Default::default(),
+        }
+    }
+
+    fn load(data: &mut Self::LoaderData, assets: &assets::AssetManager, resource: Self::Key) -> UResult<Self::Return> {
+        use std::collections::hash_map::Entry;
+        Ok(match data.appearances.entry(resource.into_owned()) {
+            Entry::Occupied(val) => val.into_mut().clone(),
+            Entry::Vacant(val) => {
+                let file = assets.open_from_pack(val.key().module_key(), &format!("entity/appearance/{}.json", val.key().resource()))?;
+                let info: AppearanceInfo = serde_json::from_reader(file)?;
+                val.insert(Arc::new(AppearanceData::from_info(info))).clone()
+            }
+        })
+    }
+}
+
+/// Loaded appearance descriptions
+pub struct LoaderData {
+    appearances: FNVMap<assets::ResourceKey<'static>, Arc<AppearanceData>>,
+}
+
+/// The possible appearances of a type of entity
+pub struct AppearanceData {
+    /// Pools of names by locale
+    pub names: FNVMap<String, Arc<NameList>>,
+    /// The colors that each tinted part of the model can use
+    pub palettes: Vec<Vec<(u8, u8, u8, u8)>>,
+    /// The mesh parts that can be used for each slot of
+    /// the model
+    pub parts: Vec<Vec<Arc<str>>>,
+}
+
+/// An appearance generated from a seed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generated {
+    /// The first and second name, if there are any names for
+    /// the locale
+    pub name: Option<(Arc<str>, Arc<str>)>,
+    /// The tint for each tinted part of the model
+    pub tints: Vec<(u8, u8, u8, u8)>,
+    /// The mesh part picked for each slot of the model
+    pub parts: Vec<Arc<str>>,
+}
+
+impl AppearanceData {
+    fn from_info(info: AppearanceInfo) -> AppearanceData {
+        AppearanceData {
+            names: info.names.into_iter()
+                .map(|(k, v)| (k, Arc::new(v.into_list())))
+                .collect(),
+            palettes: info.palettes.into_iter()
+                .map(|v| v.into_iter()
+                    .map(|v| (v.0, v.1, v.2, v.3))
+                    .collect())
+                .collect(),
+            parts: info.parts.into_iter()
+                .map(|v| v.into_iter()
+                    .map(Into::into)
+                    .collect())
+                .collect(),
+        }
+    }
+
+    /// Returns the pool of names for the locale falling back
+    /// to the default locale
+    pub fn names(&self, locale: &str) -> Option<&Arc<NameList>> {
+        self.names.get(locale)
+            .or_else(|| self.names.get(DEFAULT_LOCALE))
+    }
+
+    /// Generates the appearance for the seed.
+    ///
+    /// The same seed always generates the same appearance. The
+    /// locale only changes the name, the rest of the appearance
+    /// is the same for every locale.
+    pub fn generate(&self, seed: u32, locale: &str) -> Generated {
+        let mut rng = SeedRng::new(seed);
+        // Both picks are always made so that the number of values
+        // used doesn't depend on the locale
+        let names = self.names(locale);
+        let first = rng.choose(names.map_or(&[][..], |v| &v.first[..]));
+        let second = rng.choose(names.map_or(&[][..], |v| &v.second[..]));
+        Generated {
+            name: first.and_then(|f| second.map(|s| (f.clone(), s.clone()))),
+            tints: self.palettes.iter()
+                .map(|v| rng.choose(v).cloned().unwrap_or((255, 255, 255, 255)))
+                .collect(),
+            parts: self.parts.iter()
+                .map(|v| rng.choose(v).cloned().unwrap_or_else(|| "".into()))
+                .collect(),
+        }
+    }
+}
+
+/// The seed an entity's appearance was generated from and
+/// the mesh parts picked for it
+#[derive(Debug, Clone)]
+pub struct Appearance {
+    /// The seed the appearance was generated from
+    pub seed: u32,
+    /// The mesh part used for each slot of the model
+    pub parts: Vec<Arc<str>>,
+}
+component!(Appearance => Map);
+
+/// Gives the entity the generated appearance, replacing any
+/// tints it had
+pub fn apply(em: &mut ecs::Container, e: ecs::Entity, seed: u32, generated: Generated) {
+    if !generated.tints.is_empty() {
+        em.add_component(e, super::Tints {
+            tints: generated.tints,
+        });
+    }
+    em.add_component(e, Appearance {
+        seed,
+        parts: generated.parts,
+    });
+}
+
+/// A small random number generator used for appearances and
+/// other values generated from a saved seed.
+///
+/// `rand`'s generators aren't guaranteed to produce the same
+/// values between versions which would break older saves and
+/// clients, so a fixed algorithm (splitmix64) is used instead.
+pub(crate) struct SeedRng(u64);
+
+impl SeedRng {
+    pub(crate) fn new(seed: u32) -> SeedRng {
+        SeedRng(u64::from(seed))
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a value between 0 (inclusive) and 1 (exclusive)
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        // The top 24 bits are all that fit exactly in a f32
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Picks a value from the list. Always uses a value even
+    /// if the list is empty
+    fn choose<'a, T>(&mut self, list: &'a [T]) -> Option<&'a T> {
+        let val = self.next();
+        if list.is_empty() {
+            None
+        } else {
+            Some(&list[(val % list.len() as u64) as usize])
+        }
+    }
+}
+
+/// Sets up an interface for scripts to generate characters,
+/// e.g. for missions that refer to a character before they arrive
+pub fn init_appearancelib(lua: &lua::Lua) {
+    lua.set(Scope::Global, "generate_character", lua::closure2(|lua, key: Ref<String>, seed: i32| -> UResult<Ref<Table>> {
+        let assets = lua.get_tracked::<AssetManager>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let key = ResourceKey::parse(&*key)
+            .ok_or_else(|| ErrorKind::Msg("Invalid resource key".into()))?;
+        let data = assets.loader_open::<Loader>(key)?;
+        let generated = data.generate(seed as u32, DEFAULT_LOCALE);
+
+        let out = Ref::new_table(lua);
+        if let Some((first, second)) = generated.name {
+            out.insert(Ref::new_string(lua, "first_name"), Ref::new_string(lua, &*first));
+            out.insert(Ref::new_string(lua, "second_name"), Ref::new_string(lua, &*second));
+        }
+        let tints = Ref::new_table(lua);
+        for (r, g, b, a) in generated.tints {
+            tints.insert(tints.length() + 1, Ref::new_string(lua, format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)));
+        }
+        out.insert(Ref::new_string(lua, "tints"), tints);
+        let parts = Ref::new_table(lua);
+        for part in generated.parts {
+            parts.insert(parts.length() + 1, Ref::new_string(lua, &*part));
+        }
+        out.insert(Ref::new_string(lua, "parts"), parts);
+        Ok(out)
+    }));
+}
+
+#[derive(Deserialize)]
+struct AppearanceInfo {
+    #[serde(default)]
+    names: FNVMap<String, NameListInfo>,
+    #[serde(default)]
+    palettes: Vec<Vec<TintColor>>,
+    #[serde(default)]
+    parts: Vec<Vec<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> AppearanceData {
+        let mut names = FNVMap::default();
+        names.insert("en".to_owned(), Arc::new(NameList {
+            first: vec!["Alex".into(), "Sam".into(), "Jo".into()],
+            second: vec!["Smith".into(), "Jones".into()],
+        }));
+        names.insert("fr".to_owned(), Arc::new(NameList {
+            first: vec!["Camille".into()],
+            second: vec!["Martin".into()],
+        }));
+        AppearanceData {
+            names,
+            palettes: vec![
+                vec![(255, 0, 0, 255), (0, 255, 0, 255), (0, 0, 255, 255)],
+                vec![],
+            ],
+            parts: vec![
+                vec!["face_a".into(), "face_b".into()],
+                vec!["hair_a".into(), "hair_b".into(), "hair_c".into()],
+            ],
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let data = data();
+        for seed in 0 .. 64 {
+            assert_eq!(data.generate(seed, "en"), data.generate(seed, "en"));
+        }
+        // Not every seed should look the same
+        let first = data.generate(0, "en");
+        assert!((1 .. 64).any(|seed| data.generate(seed, "en") != first));
+    }
+
+    #[test]
+    fn locale_only_changes_name() {
+        let data = data();
+        for seed in 0 .. 16 {
+            let en = data.generate(seed, "en");
+            let fr = data.generate(seed, "fr");
+            assert_eq!(fr.name, Some(("Camille".into(), "Martin".into())));
+            assert_eq!(en.tints, fr.tints);
+            assert_eq!(en.parts, fr.parts);
+            // Missing locales use the default names
+            assert_eq!(data.generate(seed, "de"), en);
+        }
+    }
+
+    #[test]
+    fn empty_palette() {
+        let generated = data().generate(7, "en");
+        assert_eq!(generated.tints.len(), 2);
+        assert_eq!(generated.tints[1], (255, 255, 255, 255));
+        assert_eq!(generated.parts.len(), 2);
+    }
+}
@@ -0,0 +1,324 @@
+//! Rooms being built over time after they are placed.
+//!
+//! Finalizing a room with a controller turns it into a construction
+//! site instead of opening it straight away. Sites are closed to
+//! everyone but builders, entities with a `Builder` component, who
+//! speed the work up whilst standing in or next to the site. Once
+//! the work is done the owner is notified and the room opens as
+//! normal.
+//!
+//! Rooms without a controller (e.g. corridors and roads) are always
+//! built instantly as are all rooms when `ConstructionConfig::instant`
+//! is set.
+
+use crate::ecs::{self, closure_system, Read, Write, EntityManager};
+use crate::level::{self, room};
+use crate::util::{FNVMap, FNVSet, Bound};
+use super::*;
+
+/// The number of visual stages a site goes through whilst being
+/// built
+pub const STAGES: u8 = 4;
+/// The work done on every site per a tick without any builders
+const BASE_RATE: f32 = 1.0;
+/// How far in tiles outside of a site builders can work on it from
+const BUILD_RANGE: i32 = 1;
+
+/// Registers components required by this module
+pub fn register_components(c: &mut ecs::Container) {
+    c.register_component::<Construction>();
+    c.register_component::<Builder>();
+}
+
+/// Registers systems required by this module
+pub fn register_systems(sys: &mut ecs::Systems) {
+    sys.add(build_rooms);
+}
+
+/// Controls how long rooms take to build
+#[derive(Clone, Debug)]
+pub struct ConstructionConfig {
+    /// Whether rooms open as soon as they are placed like
+    /// they did before construction took time
+    pub instant: bool,
+    /// The number of ticks each tile of a room takes to build
+    /// without any builders
+    pub ticks_per_tile: u32,
+}
+
+impl Default for ConstructionConfig {
+    fn default() -> ConstructionConfig {
+        ConstructionConfig {
+            instant: false,
+            ticks_per_tile: 20,
+        }
+    }
+}
+
+/// Allows the entity to speed up the construction of rooms
+/// owned by the same player
+pub struct Builder {
+    /// The extra work done on a site near the entity per a tick
+    pub rate: f32,
+}
+component!(Builder => Map);
+
+/// The progress of a room being built
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Site {
+    /// The work done on the room so far
+    pub progress: f32,
+    /// The work required to finish the room
+    pub required: f32,
+}
+
+impl Site {
+    /// Returns the visual stage of the site, from zero up to
+    /// but not including `STAGES`
+    pub fn stage(self) -> u8 {
+        let fraction = if self.required > 0.0 {
+            self.progress / self.required
+        } else {
+            1.0
+        };
+        ((fraction * f32::from(STAGES)) as u8).min(STAGES - 1)
+    }
+}
+
+/// Every room being built in the level.
+///
+/// Stored on the world entity
+#[derive(Default)]
+pub struct Construction {
+    instant: bool,
+    ticks_per_tile: u32,
+    sites: FNVMap<room::Id, Site>,
+    /// Sites whose stage changed, started or finished since
+    /// the last call to `take_changes`
+    changed: FNVSet<room::Id>,
+}
+component!(Construction => Map);
+
+impl Construction {
+    /// Creates the construction state using the config
+    pub fn new(config: &ConstructionConfig) -> Construction {
+        Construction {
+            instant: config.instant,
+            ticks_per_tile: config.ticks_per_tile,
+            .. Construction::default()
+        }
+    }
+
+    /// Starts building the room over the area, replacing any
+    /// previous progress.
+    ///
+    /// Does nothing when rooms are built instantly
+    pub fn start(&mut self, room: room::Id, area: Bound) {
+        if self.instant || self.ticks_per_tile == 0 {
+            return;
+        }
+        let tiles = (area.width() * area.height()) as f32;
+        self.sites.insert(room, Site {
+            progress: 0.0,
+            required: tiles * self.ticks_per_tile as f32,
+        });
+        self.changed.insert(room);
+    }
+
+    /// Restores a site loaded from a save
+    pub fn restore(&mut self, room: room::Id, site: Site) {
+        self.sites.insert(room, site);
+        self.changed.insert(room);
+    }
+
+    /// Stops building the room
+    pub fn cancel(&mut self, room: room::Id) {
+        if self.sites.remove(&room).is_some() {
+            self.changed.insert(room);
+        }
+    }
+
+    /// Returns whether the room is still being built
+    pub fn is_building(&self, room: room::Id) -> bool {
+        self.sites.contains_key(&room)
+    }
+
+    /// Returns the progress of the room if it is being built
+    pub fn site(&self, room: room::Id) -> Option<Site> {
+        self.sites.get(&room).cloned()
+    }
+
+    /// Returns every room being built and its progress
+    pub fn sites(&self) -> impl Iterator<Item=(room::Id, Site)> + '_ {
+        self.sites.iter().map(|(id, site)| (*id, *site))
+    }
+
+    /// Adds work to the site returning whether the room was
+    /// finished by it
+    pub fn work(&mut self, room: room::Id, amount: f32) -> bool {
+        let finished = if let Some(site) = self.sites.get_mut(&room) {
+            let old = site.stage();
+            site.progress += amount;
+            if site.stage() != old {
+                self.changed.insert(room);
+            }
+            site.progress >= site.required
+        } else {
+            return false;
+        };
+        if finished {
+            self.cancel(room);
+        }
+        finished
+    }
+
+    /// Returns the rooms whose site changed since this was last
+    /// called along with their new stage, `None` once the room
+    /// is no longer being built
+    pub fn take_changes(&mut self) -> Vec<(room::Id, Option<u8>)> {
+        let changed = ::std::mem::replace(&mut self.changed, FNVSet::default());
+        changed.into_iter()
+            .map(|v| (v, self.sites.get(&v).map(|s| s.stage())))
+            .collect()
+    }
+}
+
+/// Returns whether the room is still being built, rooms are never
+/// being built without construction state (e.g. on the client).
+pub fn is_building(construction: &Read<'_, Construction>, room: room::Id) -> bool {
+    construction.get_component(Container::WORLD)
+        .map_or(false, |v| v.is_building(room))
+}
+
+closure_system!(fn build_rooms(
+    em: EntityManager<'_>,
+    log: Read<CLogger>,
+    assets: Read<AssetManager>,
+    rooms: Read<level::LevelRooms>,
+    position: Read<Position>,
+    owned: Read<Owned>,
+    frozen: Read<Frozen>,
+    builder: Read<Builder>,
+    mut construction: Write<Construction>,
+    mut players: Write<crate::PlayerInfoMap>
+) {
+    let construction = if let Some(construction) = construction.get_component_mut(Container::WORLD) {
+        construction
+    } else {
+        return
+    };
+    if construction.sites.is_empty() {
+        return;
+    }
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let assets = assume!(log.log, assets.get_component(Container::WORLD));
+    let rooms = assume!(log.log, rooms.get_component(Container::WORLD));
+    let players = assume!(log.log, players.get_component_mut(Container::WORLD));
+
+    // Rooms that were removed or are being edited again stop
+    // being built
+    let stale: Vec<_> = construction.sites.keys()
+        .filter(|v| rooms.try_room_info(**v).map_or(true, |v| !v.state.is_done()))
+        .cloned()
+        .collect();
+    for id in stale {
+        construction.cancel(id);
+    }
+
+    let mut work: FNVMap<room::Id, f32> = construction.sites.keys()
+        .map(|v| (*v, BASE_RATE))
+        .collect();
+    for (e, b) in em.group_mask(&builder, |m| m
+        .and(&position)
+        .and(&owned)
+        .and_not(&frozen)
+    ) {
+        let pos = assume!(log.log, position.get_component(e));
+        let owner = assume!(log.log, owned.get_component(e)).player_id;
+        let loc = Location::new(pos.x as i32, pos.z as i32);
+        let site = work.keys()
+            .cloned()
+            .find(|v| {
+                let room = rooms.get_room_info(*v);
+                room.owner == owner && room.area.extend(BUILD_RANGE).in_bounds(loc)
+            });
+        if let Some(site) = site {
+            *assume!(log.log, work.get_mut(&site)) += b.rate;
+        }
+    }
+
+    for (id, amount) in work {
+        if !construction.work(id, amount) {
+            continue;
+        }
+        let room = rooms.get_room_info(id);
+        let name = assets.loader_open::<room::Loader>(room.key.borrow())
+            .map(|v| v.name.clone())
+            .unwrap_or_else(|_| "room".into());
+        if let Some(player) = players.get_mut(&room.owner) {
+            player.notifications.push(crate::notify::Notification::Text {
+                icon: ResourceKey::new("base", "solid"),
+                title: "Construction complete".into(),
+                description: format!("Your {} has been built and is ready to use", name),
+            });
+        }
+    }
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(width: i32, height: i32) -> Bound {
+        Bound::new(Location::new(0, 0), Location::new(width - 1, height - 1))
+    }
+
+    #[test]
+    fn builds_in_stages() {
+        let mut construction = Construction::new(&ConstructionConfig {
+            instant: false,
+            ticks_per_tile: 10,
+        });
+        let room = room::Id(3);
+        construction.start(room, area(2, 2));
+        assert_eq!(construction.site(room).map(|v| v.required as i32), Some(40));
+        assert_eq!(construction.take_changes(), vec![(room, Some(0))]);
+
+        assert!(!construction.work(room, 5.0));
+        assert!(construction.take_changes().is_empty());
+        assert!(!construction.work(room, 5.0));
+        assert_eq!(construction.take_changes(), vec![(room, Some(1))]);
+
+        assert!(!construction.work(room, 29.0));
+        assert_eq!(construction.take_changes(), vec![(room, Some(STAGES - 1))]);
+        assert!(construction.is_building(room));
+        assert!(construction.work(room, 1.0));
+        assert!(!construction.is_building(room));
+        assert_eq!(construction.take_changes(), vec![(room, None)]);
+        // Finished rooms take no more work
+        assert!(!construction.work(room, 1.0));
+    }
+
+    #[test]
+    fn instant() {
+        let mut construction = Construction::new(&ConstructionConfig {
+            instant: true,
+            ticks_per_tile: 10,
+        });
+        construction.start(room::Id(1), area(4, 4));
+        assert!(!construction.is_building(room::Id(1)));
+        assert!(construction.take_changes().is_empty());
+    }
+
+    #[test]
+    fn cancel() {
+        let mut construction = Construction::new(&ConstructionConfig::default());
+        construction.start(room::Id(1), area(4, 4));
+        construction.take_changes();
+        construction.cancel(room::Id(1));
+        assert_eq!(construction.take_changes(), vec![(room::Id(1), None)]);
+        // Cancelling a room that isn't being built does nothing
+        construction.cancel(room::Id(2));
+        assert!(construction.take_changes().is_empty());
+    }
+}
@@ -23,6 +23,8 @@ pub struct LessonManager {
     lessons: FNVMap<ResourceKey<'static>, Lesson>,
     /// A list of known lesson groups
     pub groups: Vec<String>,
+    /// Course templates defined by packs sorted by name
+    pub templates: Vec<CourseTemplate>,
 }
 component!(LessonManager => Vec);
 
@@ -30,6 +32,9 @@ impl LessonManager {
 
     /// Creates a lesson manager preloaded with all listed lessons
     /// loaded.
+    ///
+    /// Lessons and templates that fail to validate are logged and
+    /// skipped.
     pub fn new(log: Logger, assets: &AssetManager) -> LessonManager {
         use std::io::Read;
 
@@ -71,26 +76,12 @@ impl LessonManager {
                 };
 
                 let lkey = s_key.into_owned();
-                let lesson = Lesson {
-                    key: lkey.clone(),
-                    name: info.name,
-                    group: info.group,
-                    valid_rooms: info.valid_rooms
-                        .into_iter()
-                        .map(|v| LazyResourceKey::parse(&v)
-                            .or_module(pack.borrow())
-                            .into_owned()
-                        )
-                        .collect(),
-                    valid_staff: info.valid_staff
-                        .into_iter()
-                        .map(|v| LazyResourceKey::parse(&v)
-                            .or_module(pack.borrow())
-                            .into_owned()
-                        )
-                        .collect(),
-                    required_lessons: info.required_lessons,
-                    description: info.description,
+                let lesson = match Lesson::from_info(pack.borrow(), lkey.clone(), info) {
+                    Ok(v) => v,
+                    Err(err) => {
+                        error!(log, "Invalid lesson {:?} in pack {:?}", lkey, pack; "error" => %err);
+                        continue;
+                    }
                 };
 
                 groups.insert(lesson.group.clone());
@@ -99,10 +90,37 @@ impl LessonManager {
             }
         }
 
-        LessonManager {
+        let mut manager = LessonManager {
             lessons,
             groups: groups.into_iter().collect(),
+            templates: Vec::new(),
+        };
+
+        for pack in assets.get_packs() {
+            // Templates are optional so a missing list isn't an error
+            let file = if let Ok(file) = assets.open_from_pack(pack.borrow(), "lessons/courses.json") {
+                file
+            } else {
+                continue;
+            };
+            let infos: Vec<CourseTemplateInfo> = match serde_json::from_reader(file) {
+                Ok(v) => v,
+                Err(err) => {
+                    error!(log, "Failed to load course templates from pack {:?}", pack; "error" => ?err);
+                    continue;
+                }
+            };
+            for info in infos {
+                let name = info.name.clone();
+                match manager.template_from_info(pack.borrow(), info) {
+                    Ok(template) => manager.templates.push(template),
+                    Err(err) => error!(log, "Invalid course template {:?} in pack {:?}", name, pack; "error" => %err),
+                }
+            }
         }
+        manager.templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+        manager
     }
 
     /// Returns the lesson information for the given key if it exists
@@ -119,6 +137,47 @@ impl LessonManager {
         self.lessons.values()
             .filter(move |v| v.group == group)
     }
+
+    /// Returns an iterator over all templates for the given group
+    pub fn templates_in_group<'a>(&'a self, group: &'a str) -> impl Iterator<Item=&'a CourseTemplate> {
+        self.templates.iter()
+            .filter(move |v| v.group == group)
+    }
+
+    /// Validates a template against the loaded lessons
+    fn template_from_info(&self, module: ModuleKey<'_>, info: CourseTemplateInfo) -> UResult<CourseTemplate> {
+        if info.name.trim().is_empty() {
+            bail!("Course templates require a name");
+        }
+        if info.lessons.len() > 7 * NUM_TIMETABLE_SLOTS {
+            bail!("{} periods given but a timetable only has {}", info.lessons.len(), 7 * NUM_TIMETABLE_SLOTS);
+        }
+        let mut lessons = Vec::with_capacity(info.lessons.len());
+        for l in info.lessons {
+            let key = if let Some(l) = l {
+                LazyResourceKey::parse(&l)
+                    .or_module(module.borrow())
+                    .into_owned()
+            } else {
+                lessons.push(None);
+                continue;
+            };
+            let lesson = self.get(key.borrow())
+                .ok_or_else(|| ErrorKind::Msg(format!("Unknown lesson {:?}", key)))?;
+            if lesson.group != info.group {
+                bail!("Lesson {:?} is in the group {:?} not {:?}", key, lesson.group, info.group);
+            }
+            lessons.push(Some(key));
+        }
+        if lessons.iter().all(|v| v.is_none()) {
+            bail!("Course templates require at least one lesson");
+        }
+        Ok(CourseTemplate {
+            name: info.name,
+            group: info.group,
+            lessons,
+        })
+    }
 }
 
 /// A unique id for a course
@@ -450,6 +509,183 @@ pub struct Lesson {
     pub required_lessons: u32,
     /// A description of the lesson for the user interface
     pub description: String,
+    /// Object type tags that the room must contain at least
+    /// one of each of
+    pub required_tags: Vec<String>,
+    /// The minimum stats required by the staff teaching the
+    /// lesson
+    pub staff_requirements: Vec<StaffRequirement>,
+    /// The grades students are likely to get from this lesson
+    pub outcomes: GradeOutcomes,
+}
+
+impl Lesson {
+    fn from_info(module: ModuleKey<'_>, key: ResourceKey<'static>, info: LessonInfo) -> UResult<Lesson> {
+        if info.valid_rooms.is_empty() {
+            bail!("No valid rooms listed");
+        }
+        if info.valid_staff.is_empty() {
+            bail!("No valid staff listed");
+        }
+        if info.required_lessons == 0 {
+            bail!("required_lessons must be at least 1");
+        }
+        if info.required_lessons as usize > 7 * NUM_TIMETABLE_SLOTS {
+            bail!("required_lessons is {} but a timetable only has {} periods", info.required_lessons, 7 * NUM_TIMETABLE_SLOTS);
+        }
+        if let Some(tag) = info.required_tags.iter().find(|v| v.trim().is_empty()) {
+            bail!("Invalid object tag {:?}", tag);
+        }
+
+        let mut staff_requirements = Vec::with_capacity(info.staff_requirements.len());
+        for (name, min) in info.staff_requirements {
+            let stat = Stat::from_str(Stats::PROFESSOR, &name)
+                .ok_or_else(|| ErrorKind::Msg(format!("Unknown staff stat {:?}", name)))?;
+            if !(0.0 ..= 1.0).contains(&min) {
+                bail!("The minimum for {:?} must be between 0 and 1 not {}", name, min);
+            }
+            staff_requirements.push(StaffRequirement {
+                stat,
+                min,
+            });
+        }
+        // Keep the order stable for the user interface
+        staff_requirements.sort_by_key(|v| v.stat.index);
+
+        let outcomes = if let Some(outcomes) = info.outcomes {
+            let mut weights = [0; 6];
+            for (grade, weight) in outcomes {
+                let grade = Grade::from_str(&grade)
+                    .ok_or_else(|| ErrorKind::Msg(format!("Unknown grade {:?} in outcomes", grade)))?;
+                weights[grade.as_index()] = weight;
+            }
+            GradeOutcomes::new(weights)
+                .ok_or_else(|| ErrorKind::Msg("Outcomes must give at least one grade a weight".into()))?
+        } else {
+            GradeOutcomes::default()
+        };
+
+        let to_key = |v: String| LazyResourceKey::parse(&v)
+            .or_module(module.borrow())
+            .into_owned();
+        Ok(Lesson {
+            key,
+            name: info.name,
+            group: info.group,
+            valid_rooms: info.valid_rooms
+                .into_iter()
+                .map(&to_key)
+                .collect(),
+            valid_staff: info.valid_staff
+                .into_iter()
+                .map(&to_key)
+                .collect(),
+            required_lessons: info.required_lessons,
+            description: info.description,
+            required_tags: info.required_tags,
+            staff_requirements,
+            outcomes,
+        })
+    }
+
+    /// Returns whether the room contains every object type
+    /// required by this lesson
+    pub fn room_has_tags(&self, log: &Logger, assets: &AssetManager, room: &room::RoomPlacement) -> bool {
+        if self.required_tags.is_empty() {
+            return true;
+        }
+        let tags = room.objects.iter()
+            .filter_map(|v| v.as_ref())
+            .filter_map(|v| assets.loader_open::<object::Loader>(v.0.key.borrow())
+                .map_err(|err| warn!(log, "Failed to load object {:?}", v.0.key; "error" => %err))
+                .ok())
+            .filter_map(|v| v.ty.clone())
+            .collect::<FNVSet<_>>();
+        self.required_tags.iter()
+            .all(|v| tags.contains(v))
+    }
+}
+
+/// A minimum stat requirement for staff teaching a lesson
+#[derive(Clone, Copy)]
+pub struct StaffRequirement {
+    /// The stat to check
+    pub stat: Stat,
+    /// The lowest value the stat can have
+    pub min: f32,
+}
+
+impl StaffRequirement {
+    /// Returns whether the staff member's stats meet this
+    /// requirement
+    pub fn is_met(&self, vars: &choice::EntityVars<()>) -> bool {
+        vars.get_float(self.stat.as_string())
+            .map_or(false, |val| val >= self.min)
+    }
+}
+
+/// The relative chance of each grade being given for a lesson
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradeOutcomes {
+    weights: [u32; 6],
+    total: u32,
+}
+
+impl Default for GradeOutcomes {
+    /// Every grade is equally likely
+    fn default() -> GradeOutcomes {
+        GradeOutcomes {
+            weights: [1; 6],
+            total: 6,
+        }
+    }
+}
+
+impl GradeOutcomes {
+    /// Creates outcomes from the weights for each grade (in the
+    /// order of `Grade::as_index`).
+    ///
+    /// Returns `None` if every weight is zero
+    pub fn new(weights: [u32; 6]) -> Option<GradeOutcomes> {
+        let total = weights.iter()
+            .fold(0u32, |acc, v| acc.saturating_add(*v));
+        if total == 0 {
+            return None;
+        }
+        Some(GradeOutcomes {
+            weights,
+            total,
+        })
+    }
+
+    /// Returns the weight for the grade
+    pub fn weight(&self, grade: Grade) -> u32 {
+        self.weights[grade.as_index()]
+    }
+
+    /// Picks a grade using a score between 0 and 1.
+    ///
+    /// Low scores pick from the worst grades and high scores
+    /// from the best, so a random score follows the distribution
+    /// whilst a score based on how well the student did shifts
+    /// it.
+    pub fn pick(&self, score: f32) -> Grade {
+        let target = (score.max(0.0).min(1.0) * self.total as f32) as u32;
+        let mut acc = 0;
+        let mut best = Grade::F;
+        for grade in Grade::ALL.iter().rev() {
+            let weight = self.weight(*grade);
+            if weight == 0 {
+                continue;
+            }
+            acc += weight;
+            best = *grade;
+            if target < acc {
+                return *grade;
+            }
+        }
+        best
+    }
 }
 
 #[derive(Deserialize)]
@@ -460,4 +696,143 @@ struct LessonInfo {
     valid_staff: Vec<String>,
     required_lessons: u32,
     description: String,
+    #[serde(default)]
+    required_tags: Vec<String>,
+    #[serde(default)]
+    staff_requirements: FNVMap<String, f32>,
+    #[serde(default)]
+    outcomes: Option<FNVMap<String, u32>>,
+}
+
+/// A pack provided starting point for a course
+#[derive(Clone, Debug)]
+pub struct CourseTemplate {
+    /// The name of the course
+    pub name: String,
+    /// The lesson group (science/art/etc)
+    pub group: String,
+    /// The lessons in timetable order, filling each day's
+    /// periods before moving to the next day. `None` is a
+    /// free period.
+    pub lessons: Vec<Option<ResourceKey<'static>>>,
+}
+
+impl CourseTemplate {
+    /// Returns the day and period of each lesson in the template
+    pub fn periods(&self) -> impl Iterator<Item=(usize, usize, &ResourceKey<'static>)> {
+        self.lessons.iter()
+            .enumerate()
+            .filter_map(|(idx, v)| v.as_ref()
+                .map(|v| (idx / NUM_TIMETABLE_SLOTS, idx % NUM_TIMETABLE_SLOTS, v)))
+    }
+}
+
+#[derive(Deserialize)]
+struct CourseTemplateInfo {
+    name: String,
+    group: String,
+    lessons: Vec<Option<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(extra: &str) -> LessonInfo {
+        serde_json::from_str(&format!(r#"{{
+            "name": "Test",
+            "group": "science",
+            "valid_rooms": ["lab"],
+            "valid_staff": ["professor"],
+            "required_lessons": 2,
+            "description": "A test lesson"
+            {}
+        }}"#, extra)).expect("Failed to parse lesson")
+    }
+
+    fn lesson(extra: &str) -> UResult<Lesson> {
+        Lesson::from_info(ModuleKey::new("base"), ResourceKey::new("base", "test"), info(extra))
+    }
+
+    #[test]
+    fn lesson_defaults() {
+        let l = lesson("").expect("Lesson should be valid");
+        assert!(l.required_tags.is_empty());
+        assert!(l.staff_requirements.is_empty());
+        assert_eq!(l.outcomes, GradeOutcomes::default());
+        assert_eq!(l.valid_rooms, vec![ResourceKey::new("base", "lab")]);
+    }
+
+    #[test]
+    fn lesson_requirements() {
+        let l = lesson(r#",
+            "required_tags": ["microscope"],
+            "staff_requirements": {"skill": 0.5},
+            "outcomes": {"A": 1, "C": 3}
+        "#).expect("Lesson should be valid");
+        assert_eq!(l.required_tags, vec!["microscope".to_owned()]);
+        assert_eq!(l.staff_requirements.len(), 1);
+        assert!(l.staff_requirements[0].stat == Stats::PROFESSOR_SKILL);
+        assert_eq!(l.outcomes.weight(Grade::C), 3);
+        assert_eq!(l.outcomes.weight(Grade::B), 0);
+    }
+
+    #[test]
+    fn lesson_invalid() {
+        assert!(lesson(r#", "staff_requirements": {"strength": 0.5}"#).is_err());
+        assert!(lesson(r#", "staff_requirements": {"skill": 1.5}"#).is_err());
+        assert!(lesson(r#", "outcomes": {"Z": 1}"#).is_err());
+        assert!(lesson(r#", "outcomes": {"A": 0}"#).is_err());
+        assert!(lesson(r#", "required_tags": [""]"#).is_err());
+
+        let mut i = info("");
+        i.required_lessons = 0;
+        assert!(Lesson::from_info(ModuleKey::new("base"), ResourceKey::new("base", "test"), i).is_err());
+        let mut i = info("");
+        i.valid_rooms.clear();
+        assert!(Lesson::from_info(ModuleKey::new("base"), ResourceKey::new("base", "test"), i).is_err());
+    }
+
+    #[test]
+    fn outcomes_pick() {
+        let outcomes = GradeOutcomes::new([1, 0, 2, 0, 0, 1]).expect("Outcomes should be valid");
+        assert_eq!(outcomes.pick(0.0), Grade::F);
+        assert_eq!(outcomes.pick(0.3), Grade::C);
+        assert_eq!(outcomes.pick(0.7), Grade::C);
+        assert_eq!(outcomes.pick(0.9), Grade::A);
+        assert_eq!(outcomes.pick(1.0), Grade::A);
+        assert_eq!(outcomes.pick(2.0), Grade::A);
+        assert!(GradeOutcomes::new([0; 6]).is_none());
+    }
+
+    #[test]
+    fn templates() {
+        let mut manager = LessonManager {
+            lessons: FNVMap::default(),
+            groups: vec!["science".into()],
+            templates: Vec::new(),
+        };
+        let l = lesson("").expect("Lesson should be valid");
+        manager.lessons.insert(l.key.clone(), l);
+
+        let template = manager.template_from_info(ModuleKey::new("base"), CourseTemplateInfo {
+            name: "Science".into(),
+            group: "science".into(),
+            lessons: vec![Some("test".into()), None, None, None, Some("base:test".into())],
+        }).expect("Template should be valid");
+        let periods = template.periods()
+            .map(|(d, p, _)| (d, p))
+            .collect::<Vec<_>>();
+        assert_eq!(periods, vec![(0, 0), (1, 0)]);
+
+        let invalid = |group: &str, lessons: Vec<Option<String>>| manager.template_from_info(ModuleKey::new("base"), CourseTemplateInfo {
+            name: "Science".into(),
+            group: group.into(),
+            lessons,
+        }).is_err();
+        assert!(invalid("science", vec![Some("missing".into())]));
+        assert!(invalid("art", vec![Some("test".into())]));
+        assert!(invalid("science", vec![None]));
+        assert!(invalid("science", vec![Some("test".into()); 7 * NUM_TIMETABLE_SLOTS + 1]));
+    }
 }
@@ -0,0 +1,214 @@
+//! Dirt that builds up on the floor where entities walk.
+//!
+//! Every tick an entity spends walking across a tile adds dirt to
+//! it at the rate set by the server's `DirtConfig`, so the busiest
+//! paths through the campus get dirty first. The dirt on a tile is
+//! split into a few stages which are synced to clients to draw as
+//! decals on the floor.
+//!
+//! Scripts remove dirt again via `clean_floor` on entities, e.g.
+//! janitors walking to the tile returned by `nearest_dirt`.
+
+use crate::ecs::{self, closure_system, Read, Write, EntityManager};
+use crate::util::{FNVMap, FNVSet, Bound};
+use super::*;
+
+/// The highest stage of dirt a tile can reach
+pub const MAX_STAGE: u8 = 3;
+/// The amount of dirt needed to reach each stage
+const STAGE_AMOUNT: f32 = 1.0;
+
+/// Registers components required by this module
+pub fn register_components(c: &mut ecs::Container) {
+    c.register_component::<Dirt>();
+}
+
+/// Registers systems required by this module
+pub fn register_systems(sys: &mut ecs::Systems) {
+    sys.add(accumulate_dirt);
+}
+
+/// Controls how quickly floors get dirty
+#[derive(Clone, Debug)]
+pub struct DirtConfig {
+    /// The dirt added to a tile per a tick whilst an entity
+    /// walks across it. Zero stops floors getting dirty
+    pub rate: f32,
+}
+
+impl Default for DirtConfig {
+    fn default() -> DirtConfig {
+        DirtConfig {
+            rate: 0.002,
+        }
+    }
+}
+
+/// The dirt on every tile in the level.
+///
+/// Stored on the world entity
+#[derive(Default)]
+pub struct Dirt {
+    rate: f32,
+    tiles: FNVMap<Location, f32>,
+    /// Tiles whose stage changed since the last call to
+    /// `take_changes`
+    changed: FNVSet<Location>,
+}
+component!(Dirt => Map);
+
+impl Dirt {
+    /// Creates an empty level's dirt using the config's rate
+    pub fn new(config: &DirtConfig) -> Dirt {
+        Dirt {
+            rate: config.rate.max(0.0),
+            .. Dirt::default()
+        }
+    }
+
+    /// Returns the stage of the dirt on the tile, zero being
+    /// clean
+    pub fn stage(&self, loc: Location) -> u8 {
+        self.tiles.get(&loc).map_or(0, |v| Self::stage_of(*v))
+    }
+
+    fn stage_of(amount: f32) -> u8 {
+        ((amount / STAGE_AMOUNT) as u8).min(MAX_STAGE)
+    }
+
+    fn set(&mut self, loc: Location, amount: f32) {
+        let amount = amount.max(0.0).min(f32::from(MAX_STAGE) * STAGE_AMOUNT);
+        let old = self.stage(loc);
+        if amount > 0.0 {
+            self.tiles.insert(loc, amount);
+        } else {
+            self.tiles.remove(&loc);
+        }
+        if self.stage(loc) != old {
+            self.changed.insert(loc);
+        }
+    }
+
+    /// Adds dirt to the tile
+    pub fn add(&mut self, loc: Location, amount: f32) {
+        let current = self.tiles.get(&loc).cloned().unwrap_or(0.0);
+        self.set(loc, current + amount);
+    }
+
+    /// Removes dirt from the tile, returning whether any dirt
+    /// is left on it
+    pub fn clean(&mut self, loc: Location, amount: f32) -> bool {
+        if let Some(current) = self.tiles.get(&loc).cloned() {
+            self.set(loc, current - amount);
+        }
+        self.tiles.contains_key(&loc)
+    }
+
+    /// Returns every tile with visible dirt and its stage
+    pub fn tiles(&self) -> impl Iterator<Item=(Location, u8)> + '_ {
+        self.tiles.iter()
+            .map(|(l, v)| (*l, Self::stage_of(*v)))
+            .filter(|v| v.1 > 0)
+    }
+
+    /// Returns the closest tile to the location within the
+    /// bounds that is at least at the passed stage
+    pub fn nearest(&self, loc: Location, min_stage: u8, bound: Bound) -> Option<Location> {
+        self.tiles()
+            .filter(|v| v.1 >= min_stage.max(1) && bound.in_bounds(v.0))
+            .map(|v| v.0)
+            .min_by_key(|v| (v.x - loc.x) * (v.x - loc.x) + (v.y - loc.y) * (v.y - loc.y))
+    }
+
+    /// Returns the tiles whose stage changed since this was
+    /// last called along with their new stage
+    pub fn take_changes(&mut self) -> Vec<(Location, u8)> {
+        let changed = ::std::mem::replace(&mut self.changed, FNVSet::default());
+        changed.into_iter()
+            .map(|v| (v, self.stage(v)))
+            .collect()
+    }
+}
+
+closure_system!(fn accumulate_dirt(
+    em: EntityManager<'_>,
+    position: Read<Position>,
+    path_info: Read<pathfind::PathInfo>,
+    frozen: Read<Frozen>,
+    mut dirt: Write<Dirt>
+) {
+    let dirt = if let Some(dirt) = dirt.get_component_mut(Container::WORLD) {
+        dirt
+    } else {
+        return
+    };
+    if dirt.rate <= 0.0 {
+        return;
+    }
+    let mask = position.mask()
+        .and(&path_info)
+        .and_not(&frozen);
+    for e in em.iter_mask(&mask) {
+        if !path_info.get_component(e).map_or(false, |v| v.is_moving() && !v.is_simplified()) {
+            continue;
+        }
+        let pos = position.get_component(e).expect("Missing position");
+        let rate = dirt.rate;
+        dirt.add(Location::new(pos.x as i32, pos.z as i32), rate);
+    }
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_in_stages() {
+        let mut dirt = Dirt::new(&DirtConfig { rate: 0.5 });
+        let loc = Location::new(3, 4);
+        dirt.add(loc, 0.5);
+        assert_eq!(dirt.stage(loc), 0);
+        assert!(dirt.take_changes().is_empty());
+        dirt.add(loc, 0.5);
+        assert_eq!(dirt.stage(loc), 1);
+        assert_eq!(dirt.take_changes(), vec![(loc, 1)]);
+        // Only stage changes are reported
+        dirt.add(loc, 0.1);
+        assert!(dirt.take_changes().is_empty());
+        for _ in 0 .. 100 {
+            dirt.add(loc, 1.0);
+        }
+        assert_eq!(dirt.stage(loc), MAX_STAGE);
+        assert_eq!(dirt.tiles().collect::<Vec<_>>(), vec![(loc, MAX_STAGE)]);
+    }
+
+    #[test]
+    fn cleaning() {
+        let mut dirt = Dirt::default();
+        let loc = Location::new(0, 0);
+        dirt.add(loc, 2.5);
+        dirt.take_changes();
+        assert!(dirt.clean(loc, 1.0));
+        assert_eq!(dirt.stage(loc), 1);
+        assert!(!dirt.clean(loc, 5.0));
+        assert_eq!(dirt.stage(loc), 0);
+        assert_eq!(dirt.take_changes(), vec![(loc, 0)]);
+        // Cleaning a clean tile does nothing
+        assert!(!dirt.clean(Location::new(1, 1), 1.0));
+        assert!(dirt.take_changes().is_empty());
+    }
+
+    #[test]
+    fn nearest() {
+        let mut dirt = Dirt::default();
+        dirt.add(Location::new(10, 10), 1.0);
+        dirt.add(Location::new(2, 2), 3.0);
+        dirt.add(Location::new(1, 1), 0.5);
+        let bound = Bound::new(Location::new(0, 0), Location::new(20, 20));
+        assert_eq!(dirt.nearest(Location::new(0, 0), 1, bound), Some(Location::new(2, 2)));
+        assert_eq!(dirt.nearest(Location::new(9, 9), 1, bound), Some(Location::new(10, 10)));
+        assert_eq!(dirt.nearest(Location::new(9, 9), 2, bound), Some(Location::new(2, 2)));
+        let bound = Bound::new(Location::new(5, 5), Location::new(20, 20));
+        assert_eq!(dirt.nearest(Location::new(0, 0), 1, bound), Some(Location::new(10, 10)));
+    }
+}
@@ -0,0 +1,542 @@
+//! Fires and the evacuation of the campus whilst they burn.
+//!
+//! Fires are started by missions via `control_start_fire(x, y)`
+//! or the `/fire <x> <y>` command. A burning tile spreads to its
+//! neighbours based on the flammability of the objects on them,
+//! walls stop the spread but doors don't.
+//!
+//! Whilst anything is burning the alarm is raised and every
+//! entity drops what it is doing to walk to the nearest road.
+//! Entities with a `Firefighter` component walk to the fire
+//! instead and put it out alongside any objects that provide
+//! `suppression`. Once the fire is out the damage it caused is
+//! charged to the owners of the rooms that burnt.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use rand::Rng;
+use rand::thread_rng;
+
+use crate::ecs::{self, closure_system, Read, Write, EntityManager};
+use crate::level::{self, room, object};
+use crate::util::{FNVMap, FNVSet, ALL_DIRECTIONS};
+use super::*;
+
+/// The intensity a tile starts burning at
+const IGNITE_INTENSITY: f32 = 0.1;
+/// How quickly a burning tile grows whilst it has fuel left
+const GROWTH: f32 = 0.01;
+/// How quickly a burning tile dies down after its fuel runs out
+const DECAY: f32 = 0.005;
+/// The fuel a tile has for each point of flammability
+const FUEL_PER_FLAMMABILITY: f32 = 20.0 * 60.0;
+/// The fuel of a tile with nothing flammable on it
+const MIN_FUEL: f32 = 20.0 * 5.0;
+/// The chance per a tick of a fully burning tile spreading to a
+/// neighbour with a flammability of one
+const SPREAD_CHANCE: f32 = 0.002;
+/// The flammability of the floor of rooms with walls
+const ROOM_FLAMMABILITY: f32 = 0.2;
+/// How far in tiles suppression objects reach
+const SUPPRESSION_RADIUS: i32 = 2;
+/// The cost of damage per a tick for a fully burning tile
+const DAMAGE_RATE: f32 = 2.0;
+/// How close in tiles a firefighter has to be to a burning tile
+/// to put it out
+const FIGHT_RANGE: f32 = 1.5;
+
+/// Registers components required by this module
+pub fn register_components(c: &mut ecs::Container) {
+    c.register_component::<Fires>();
+    c.register_component::<Evacuating>();
+    c.register_component::<Firefighter>();
+}
+
+/// Registers systems required by this module
+pub fn register_systems(sys: &mut ecs::Systems) {
+    sys.add(tick_fires);
+    sys.add(evacuate);
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Burning {
+    intensity: f32,
+    fuel: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct TileInfo {
+    flammability: f32,
+    suppression: f32,
+}
+
+/// The state of every fire in the level.
+///
+/// Stored on the world entity
+#[derive(Default)]
+pub struct Fires {
+    burning: FNVMap<Location, Burning>,
+    burnt: FNVSet<Location>,
+    pending: Vec<Location>,
+    tiles: FNVMap<Location, TileInfo>,
+    /// Set when the rooms around the fire are edited and
+    /// `tiles` needs recomputing
+    stale: Rc<Cell<bool>>,
+    /// The rooms `tiles` was computed from
+    covered: FNVSet<RoomId>,
+    /// Tiles set alight since the last check for the fire
+    /// reaching new rooms
+    ignited: Vec<Location>,
+    damage: FNVMap<PlayerId, f32>,
+    /// The damage done to each room during the fire
+    damaged_rooms: FNVMap<RoomId, f32>,
+}
+component!(Fires => Map);
+
+impl Fires {
+    /// Sets the tile on fire on the next tick
+    pub fn start(&mut self, loc: Location) {
+        self.pending.push(loc);
+        // Ensure the flammability around the new fire is known
+        self.stale.set(true);
+    }
+
+    /// Watches the level for edits to the rooms around the fire
+    /// so that their flammability is recomputed
+    pub fn observe(&self, level: &level::Level) {
+        let stale = self.stale.clone();
+        level.on_change(
+            level::ChangeKind::ROOM_PLACED
+                | level::ChangeKind::ROOM_REMOVED
+                | level::ChangeKind::OBJECT_PLACED
+                | level::ChangeKind::OBJECT_REMOVED,
+            move |_| stale.set(true)
+        );
+    }
+
+    /// Returns whether the alarm is raised
+    pub fn alarm(&self) -> bool {
+        !self.burning.is_empty() || !self.pending.is_empty()
+    }
+
+    /// Returns whether the tile is on fire
+    pub fn is_burning(&self, loc: Location) -> bool {
+        self.burning.contains_key(&loc)
+    }
+
+    /// Returns the burning tiles and their intensity between
+    /// zero and one
+    pub fn burning(&self) -> impl Iterator<Item=(Location, f32)> + '_ {
+        self.burning.iter().map(|(l, b)| (*l, b.intensity))
+    }
+
+    /// Reduces the intensity of the fire on the tile
+    pub fn suppress(&mut self, loc: Location, amount: f32) {
+        if let Some(b) = self.burning.get_mut(&loc) {
+            b.intensity -= amount;
+        }
+    }
+
+    fn ignite(&mut self, loc: Location) {
+        if self.burnt.contains(&loc) || self.burning.contains_key(&loc) {
+            return;
+        }
+        let info = self.tiles.get(&loc).cloned().unwrap_or_default();
+        self.burning.insert(loc, Burning {
+            intensity: IGNITE_INTENSITY,
+            fuel: MIN_FUEL + info.flammability * FUEL_PER_FLAMMABILITY,
+        });
+        self.ignited.push(loc);
+    }
+
+    /// Grows, spreads and burns out the fire on every tile.
+    ///
+    /// `open` returns whether fire can spread across the edge of
+    /// the tile in the passed direction.
+    fn step<R, F>(&mut self, rng: &mut R, open: F)
+        where R: Rng,
+              F: Fn(Location, Direction) -> bool,
+    {
+        for loc in ::std::mem::replace(&mut self.pending, vec![]) {
+            self.ignite(loc);
+        }
+
+        let mut spread = vec![];
+        for (loc, b) in &mut self.burning {
+            let info = self.tiles.get(loc).cloned().unwrap_or_default();
+            if b.fuel > 0.0 {
+                b.fuel -= b.intensity;
+                b.intensity = (b.intensity + GROWTH).min(1.0);
+            } else {
+                b.intensity -= DECAY;
+            }
+            b.intensity -= info.suppression;
+
+            for dir in &ALL_DIRECTIONS {
+                let next = loc.shift(*dir);
+                if self.burnt.contains(&next) || !open(*loc, *dir) {
+                    continue;
+                }
+                let flammability = self.tiles.get(&next).map_or(0.0, |v| v.flammability);
+                let chance = SPREAD_CHANCE * b.intensity * flammability;
+                if chance > 0.0 && rng.gen::<f32>() < chance {
+                    spread.push(next);
+                }
+            }
+        }
+        let burnt = &mut self.burnt;
+        self.burning.retain(|loc, b| if b.intensity <= 0.0 {
+            burnt.insert(*loc);
+            false
+        } else {
+            true
+        });
+        for loc in spread {
+            self.ignite(loc);
+        }
+    }
+
+    /// Recomputes the flammability and suppression of the tiles
+    /// around the fire from the objects placed on them
+    fn update_tiles(&mut self, log: &Logger, tiles: &level::LevelTiles, rooms: &level::LevelRooms, assets: &AssetManager) {
+        let area: FNVSet<RoomId> = self.burning.keys()
+            .chain(&self.pending)
+            .flat_map(|v| neighbouring_rooms(tiles, *v))
+            .collect();
+
+        self.tiles.clear();
+        self.ignited.clear();
+        self.stale.set(false);
+        for id in &area {
+            let id = *id;
+            let room = rooms.get_room_info(id);
+            let ty = assume!(log, assets.loader_open::<room::Loader>(room.key.borrow()));
+            if ty.wall.is_some() {
+                for loc in room.area {
+                    self.tiles.entry(loc).or_default().flammability += ROOM_FLAMMABILITY;
+                }
+            }
+            for obj in room.objects.iter().filter_map(|v| v.as_ref()) {
+                let obj = &obj.0;
+                let ty = assume!(log, assets.loader_open::<object::Loader>(obj.key.borrow()));
+                let loc = Location::new(obj.position.x as i32, obj.position.y as i32);
+                if ty.flammability > 0.0 {
+                    self.tiles.entry(loc).or_default().flammability += ty.flammability;
+                }
+                if ty.suppression > 0.0 {
+                    for oy in -SUPPRESSION_RADIUS ..= SUPPRESSION_RADIUS {
+                        for ox in -SUPPRESSION_RADIUS ..= SUPPRESSION_RADIUS {
+                            let loc = Location::new(loc.x + ox, loc.y + oy);
+                            let info = self.tiles.entry(loc).or_default();
+                            info.suppression = info.suppression.max(ty.suppression);
+                        }
+                    }
+                }
+            }
+        }
+        self.covered = area;
+    }
+
+    /// Returns whether the fire has spread next to a room that
+    /// `tiles` wasn't computed from since this was last called
+    fn spread_outside(&mut self, tiles: &level::LevelTiles) -> bool {
+        let covered = &self.covered;
+        self.ignited.drain(..)
+            .flat_map(|v| neighbouring_rooms(tiles, v))
+            .any(|v| !covered.contains(&v))
+    }
+}
+
+/// Returns the owners of the tile and the tiles around it
+fn neighbouring_rooms(tiles: &level::LevelTiles, loc: Location) -> impl Iterator<Item=RoomId> + '_ {
+    (-1 ..= 1)
+        .flat_map(move |oy| (-1 ..= 1).map(move |ox| Location::new(loc.x + ox, loc.y + oy)))
+        .filter_map(move |v| tiles.get_room_owner(v))
+}
+
+/// Marks an entity as being controlled by the alarm whilst a fire
+/// burns
+pub struct Evacuating {
+    /// The location the entity is walking to
+    target: Option<Location>,
+}
+component!(Evacuating => Map);
+
+/// Allows the entity to put out fires instead of evacuating
+pub struct Firefighter {
+    /// How quickly the entity reduces the intensity of fires
+    /// near it per a tick
+    pub rate: f32,
+}
+component!(Firefighter => Map);
+
+fn can_spread(tiles: &level::LevelTiles, loc: Location, dir: Direction) -> bool {
+    tiles.level_bounds.in_bounds(loc.shift(dir))
+        && tiles.get_wall_info(loc, dir)
+            .map_or(true, |v| v.flag == level::TileWallFlag::Door)
+}
+
+closure_system!(fn tick_fires(
+    _em: EntityManager<'_>,
+    log: Read<CLogger>,
+    tiles: Read<level::LevelTiles>,
+    rooms: Read<level::LevelRooms>,
+    assets: Read<AssetManager>,
+    day: Read<DayTick>,
+    mut fires: Write<Fires>,
+    mut players: Write<crate::PlayerInfoMap>,
+    mut finances: Write<crate::player::RoomFinances>
+) {
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let fires = if let Some(fires) = fires.get_component_mut(Container::WORLD) {
+        fires
+    } else {
+        return
+    };
+    if !fires.alarm() && fires.damage.is_empty() {
+        return;
+    }
+    let tiles = assume!(log.log, tiles.get_component(Container::WORLD));
+    let rooms = assume!(log.log, rooms.get_component(Container::WORLD));
+    let assets = assume!(log.log, assets.get_component(Container::WORLD));
+    let players = assume!(log.log, players.get_component_mut(Container::WORLD));
+
+    if fires.stale.get() || fires.spread_outside(tiles) {
+        fires.update_tiles(&log.log, tiles, rooms, assets);
+    }
+
+    fires.step(&mut thread_rng(), |loc, dir| can_spread(tiles, loc, dir));
+
+    for (loc, b) in &fires.burning {
+        if let Some(id) = tiles.get_room_owner(*loc) {
+            let room = rooms.get_room_info(id);
+            *fires.damage.entry(room.owner).or_insert(0.0) += b.intensity * DAMAGE_RATE;
+            *fires.damaged_rooms.entry(id).or_insert(0.0) += b.intensity * DAMAGE_RATE;
+        }
+    }
+
+    if !fires.alarm() {
+        let rooms_damaged = fires.damaged_rooms.len();
+        for (owner, damage) in fires.damage.drain() {
+            let cost = UniDollar(damage.ceil() as i64);
+            if let Some(player) = players.get_mut(&owner) {
+                player.change_money(-cost);
+                player.notifications.push(crate::notify::Notification::Text {
+                    icon: ResourceKey::new("base", "solid"),
+                    title: "The fire is out".into(),
+                    description: format!("The fire damaged {} rooms costing {} to repair", rooms_damaged, cost),
+                });
+            }
+        }
+        let day = assume!(log.log, day.get_component(Container::WORLD));
+        let finances = assume!(log.log, finances.get_component_mut(Container::WORLD));
+        for (id, damage) in fires.damaged_rooms.drain() {
+            finances.record(id, day.day, crate::player::FinanceKind::Maintenance, UniDollar(damage.ceil() as i64));
+        }
+        fires.burnt.clear();
+        fires.tiles.clear();
+        fires.covered.clear();
+        fires.ignited.clear();
+    }
+});
+
+closure_system!(fn evacuate(
+    em: EntityManager<'_>,
+    log: Read<CLogger>,
+    rooms: Read<level::LevelRooms>,
+    position: Read<Position>,
+    living: Read<Living>,
+    owned: Read<Owned>,
+    frozen: Read<Frozen>,
+    quitting: Read<Quitting>,
+    firefighter: Read<Firefighter>,
+    mut fires: Write<Fires>,
+    mut evacuating: Write<Evacuating>,
+    mut controlled: Write<Controlled>,
+    mut goto_room: Write<GotoRoom>,
+    mut idle: Write<Idle>,
+    mut activity: Write<Activity>,
+    mut target: Write<pathfind::Target>,
+    mut path_info: Write<pathfind::PathInfo>
+) {
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let rooms = assume!(log.log, rooms.get_component(Container::WORLD));
+    let fires = if let Some(fires) = fires.get_component_mut(Container::WORLD) {
+        fires
+    } else {
+        return
+    };
+
+    if !fires.alarm() {
+        // Return everyone to what they were doing
+        let done: Vec<_> = em.iter_mask(&evacuating.mask()).collect();
+        for e in done {
+            evacuating.remove_component(e);
+            target.remove_component(e);
+            path_info.remove_component(e);
+            // Allows the timetable to send students back to
+            // the lesson they left
+            activity.remove_component(e);
+            if let Some(c) = controlled.get_component_mut(e) {
+                if c.by == Some(Controller::Evacuate) {
+                    c.by = None;
+                }
+                c.wanted = None;
+                c.should_release = false;
+            }
+            if idle.get_component(e).is_none() {
+                idle.add_component(e, Idle::new());
+            }
+        }
+        return;
+    }
+
+    let road = ResourceKey::new("base", "external/road");
+    let mask = living.mask()
+        .and(&owned)
+        .and(&position)
+        .and_not(&frozen)
+        .and_not(&quitting);
+    for e in em.iter_mask(&mask).collect::<Vec<_>>() {
+        if evacuating.get_component(e).is_none() {
+            // Wait for the current controller to release the entity
+            if let Some(c) = controlled.get_component_mut(e) {
+                if c.by.is_some() && c.by != Some(Controller::Evacuate) {
+                    c.wanted = Some(Controller::Evacuate);
+                    c.should_release = true;
+                    continue;
+                }
+                c.by = Some(Controller::Evacuate);
+                c.wanted = None;
+                c.should_release = false;
+            }
+            goto_room.remove_component(e);
+            idle.remove_component(e);
+            target.remove_component(e);
+            path_info.remove_component(e);
+            evacuating.add_component(e, Evacuating {
+                target: None,
+            });
+        }
+        if target.get_component(e).is_some() {
+            continue;
+        }
+        let pos = assume!(log.log, position.get_component(e));
+        let loc = Location::new(pos.x as i32, pos.z as i32);
+
+        if let Some(ff) = firefighter.get_component(e) {
+            let mut in_range = vec![];
+            for (fire, _) in fires.burning() {
+                let dx = (fire.x as f32 + 0.5) - pos.x;
+                let dz = (fire.y as f32 + 0.5) - pos.z;
+                if dx * dx + dz * dz <= FIGHT_RANGE * FIGHT_RANGE {
+                    in_range.push(fire);
+                }
+            }
+            if !in_range.is_empty() {
+                for fire in in_range {
+                    fires.suppress(fire, ff.rate);
+                }
+                continue;
+            }
+            if path_info.get_component(e).map_or(false, |v| v.is_moving()) {
+                continue;
+            }
+            let nearest = fires.burning()
+                .map(|v| v.0)
+                .min_by_key(|v| (v.x - loc.x) * (v.x - loc.x) + (v.y - loc.y) * (v.y - loc.y));
+            if let Some(fire) = nearest {
+                target.add_component(e, pathfind::Target::try_new(fire.x as f32 + 0.5, fire.y as f32 + 0.5));
+            }
+            continue;
+        }
+
+        let ev = assume!(log.log, evacuating.get_component_mut(e));
+        if ev.target.is_some() {
+            continue;
+        }
+        // Head to the closest point on the closest road
+        let exit = rooms.room_ids()
+            .map(|v| rooms.get_room_info(v))
+            .filter(|v| v.key == road)
+            .map(|v| Location::new(
+                loc.x.max(v.area.min.x).min(v.area.max.x),
+                loc.y.max(v.area.min.y).min(v.area.max.y),
+            ))
+            .filter(|v| !fires.is_burning(*v))
+            .min_by_key(|v| (v.x - loc.x) * (v.x - loc.x) + (v.y - loc.y) * (v.y - loc.y));
+        if let Some(exit) = exit {
+            ev.target = Some(exit);
+            if exit != loc {
+                target.add_component(e, pathfind::Target::try_new(exit.x as f32 + 0.5, exit.y as f32 + 0.5));
+            }
+        }
+    }
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn fires(flammable: &[(i32, i32)]) -> Fires {
+        let mut fires = Fires::default();
+        for &(x, y) in flammable {
+            fires.tiles.insert(Location::new(x, y), TileInfo {
+                flammability: 5.0,
+                suppression: 0.0,
+            });
+        }
+        fires
+    }
+
+    #[test]
+    fn burns_out() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut fires = fires(&[]);
+        fires.start(Location::new(0, 0));
+        assert!(fires.alarm());
+        for _ in 0 .. 20 * 60 {
+            fires.step(&mut rng, |_, _| true);
+        }
+        assert!(!fires.alarm());
+        assert!(fires.burnt.contains(&Location::new(0, 0)));
+    }
+
+    #[test]
+    fn spreads_to_flammable() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut fires = fires(&[(0, 0), (1, 0), (2, 0)]);
+        fires.start(Location::new(0, 0));
+        for _ in 0 .. 20 * 60 * 5 {
+            fires.step(&mut rng, |_, _| true);
+        }
+        assert!(fires.burnt.contains(&Location::new(2, 0)) || fires.is_burning(Location::new(2, 0)));
+        // Nothing to burn here
+        assert!(!fires.burnt.contains(&Location::new(0, 1)));
+    }
+
+    #[test]
+    fn walls_block() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut fires = fires(&[(0, 0), (1, 0)]);
+        fires.start(Location::new(0, 0));
+        for _ in 0 .. 20 * 60 * 5 {
+            fires.step(&mut rng, |_, _| false);
+        }
+        assert!(!fires.burnt.contains(&Location::new(1, 0)));
+    }
+
+    #[test]
+    fn suppressed() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut fires = fires(&[(0, 0)]);
+        fires.tiles.get_mut(&Location::new(0, 0)).unwrap().suppression = 0.1;
+        fires.start(Location::new(0, 0));
+        fires.step(&mut rng, |_, _| true);
+        fires.step(&mut rng, |_, _| true);
+        assert!(!fires.alarm());
+    }
+}
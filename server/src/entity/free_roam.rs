@@ -24,17 +24,23 @@ pub(crate) fn server_tick(
         log,
         entities, scripting,
         player_info,
-        "server_handler"
+        "server_handler",
+        // Wandering around can wait whilst the pack is throttled
+        |module| scripting.allow_optional(module),
     );
 }
 
-/// Ticks all free roaming entities
-pub fn tick<E: script::ScriptTypes, C: 'static>(
+/// Ticks all free roaming entities.
+///
+/// Entities whose script's module isn't allowed by `allow` are
+/// skipped this tick
+pub fn tick<E: script::ScriptTypes, C: 'static, F: Fn(&str) -> bool>(
         log: &Logger,
         entities: &mut Container,
         scripting: &lua::Lua,
         extra: &mut C,
         handler: &'static str,
+        allow: F,
 ) {
     let mask = entities.mask_for::<free_roam::FreeRoam>()
         .and_component::<Owned>(entities)
@@ -42,6 +48,11 @@ pub fn tick<E: script::ScriptTypes, C: 'static>(
         .and_not_component::<Quitting>(entities);
 
     for e in entities.iter_mask(&mask).collect::<Vec<_>>() {
+        let allowed = entities.get_component::<free_roam::FreeRoam>(e)
+            .map_or(false, |v| allow(v.script.module()));
+        if !allowed {
+            continue;
+        }
         let props = entities.with(|
             _em: EntityManager<'_>,
             mut roam_props: ecs::Write<LuaRoamEntityProperties>,
@@ -0,0 +1,407 @@
+//! Victory and defeat conditions for free-play games.
+//!
+//! Packs provide sets of conditions in `goals/goals.json` which
+//! are selected between in the lobby. Once the game begins the
+//! selected set is checked for every player at the start of each
+//! day and their progress is sent to them to display.
+
+use serde_json;
+use delta_encode::AlwaysVec;
+
+use crate::ecs::{self, closure_system, Read, Write, EntityManager};
+use crate::network::packet;
+use crate::util::FNVMap;
+use super::*;
+
+/// Registers components required by this module
+pub fn register_components(c: &mut ecs::Container) {
+    c.register_component::<Goals>();
+}
+
+/// Registers systems required by this module
+pub fn register_systems(sys: &mut ecs::Systems) {
+    sys.add(check_goals);
+}
+
+/// A set of conditions that can be selected for a game
+#[derive(Debug, Clone)]
+pub struct GoalSet {
+    /// The key used to select this set
+    pub key: ResourceKey<'static>,
+    /// The name of the set shown in the lobby
+    pub display_name: String,
+    /// A description of the set shown in the lobby
+    pub description: String,
+    /// Conditions that must all be met to win
+    pub victory: Vec<Condition>,
+    /// Conditions that each cause a loss when met
+    pub defeat: Vec<Condition>,
+}
+
+/// A single condition of a goal set
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// Met once the player's rating reaches the value
+    Rating {
+        at_least: i16,
+    },
+    /// Met once the game has run for the number of days
+    SurviveDays {
+        days: u32,
+    },
+    /// Met once the player has at least the amount of money
+    Money {
+        at_least: i64,
+    },
+    /// Met once the player has had less than the amount of
+    /// money at the start of a day for the number of days
+    /// in a row
+    Bankrupt {
+        below: i64,
+        #[serde(default)]
+        days: u32,
+    },
+}
+
+/// The values conditions are checked against
+#[derive(Debug, Clone, Copy)]
+struct PlayerStats {
+    days: u32,
+    rating: i16,
+    money: UniDollar,
+    bankrupt_days: u32,
+}
+
+impl Condition {
+    /// Returns how close the condition is to being met between
+    /// 0.0 and 1.0
+    fn progress(&self, stats: &PlayerStats) -> f32 {
+        fn ratio(current: f64, target: f64) -> f32 {
+            if target <= 0.0 {
+                1.0
+            } else {
+                (current / target).max(0.0).min(1.0) as f32
+            }
+        }
+        match *self {
+            Condition::Rating{at_least} => ratio(f64::from(stats.rating), f64::from(at_least)),
+            Condition::SurviveDays{days} => ratio(f64::from(stats.days), f64::from(days)),
+            Condition::Money{at_least} => ratio(stats.money.0 as f64, at_least as f64),
+            Condition::Bankrupt{days, ..} => ratio(f64::from(stats.bankrupt_days), f64::from(days.max(1))),
+        }
+    }
+
+    fn is_met(&self, stats: &PlayerStats) -> bool {
+        match *self {
+            Condition::Rating{at_least} => stats.rating >= at_least,
+            Condition::SurviveDays{days} => stats.days >= days,
+            Condition::Money{at_least} => stats.money.0 >= at_least,
+            Condition::Bankrupt{days, ..} => stats.bankrupt_days >= days.max(1),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match *self {
+            Condition::Rating{at_least} => format!("Reach a rating of {}", at_least),
+            Condition::SurviveDays{days} => format!("Survive for {} days", days),
+            Condition::Money{at_least} => format!("Have {} in the bank", UniDollar(at_least)),
+            Condition::Bankrupt{below, days} if days <= 1 => format!("Fall below {}", UniDollar(below)),
+            Condition::Bankrupt{below, days} => format!("Stay below {} for {} days", UniDollar(below), days),
+        }
+    }
+
+    fn entry(&self, stats: &PlayerStats) -> packet::GoalEntry {
+        packet::GoalEntry {
+            description: self.describe(),
+            progress: self.progress(stats),
+            complete: self.is_met(stats),
+        }
+    }
+}
+
+/// The result of a game with goals
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    /// Every victory condition was met
+    Victory,
+    /// A defeat condition was met
+    Defeat,
+}
+
+/// The progress of a single player
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PlayerGoals {
+    /// The number of days in a row each defeat condition
+    /// has been below its bankruptcy threshold
+    bankrupt_days: Vec<u32>,
+    outcome: Option<Outcome>,
+}
+
+/// The savable state of the goals of a game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalState {
+    key: ResourceKey<'static>,
+    start_day: Option<u32>,
+    last_day: Option<u32>,
+    players: FNVMap<PlayerId, PlayerGoals>,
+}
+
+/// Tracks every player's progress towards the selected goals
+pub struct Goals {
+    set: GoalSet,
+    state: GoalState,
+}
+component!(Goals => Map);
+
+impl Goals {
+    /// Starts tracking the goal set for a new game
+    pub fn new(set: GoalSet) -> Goals {
+        Goals {
+            state: GoalState {
+                key: set.key.clone(),
+                start_day: None,
+                last_day: None,
+                players: FNVMap::default(),
+            },
+            set,
+        }
+    }
+
+    /// Continues tracking the goals of a loaded game.
+    ///
+    /// Returns `None` if the goal set no longer exists
+    pub fn load(log: &Logger, assets: &AssetManager, state: GoalState) -> Option<Goals> {
+        let set = find_goal_set(log, assets, state.key.borrow())?;
+        Some(Goals {
+            set,
+            state,
+        })
+    }
+
+    /// Returns the state of the goals to be saved
+    pub fn save(&self) -> GoalState {
+        self.state.clone()
+    }
+
+    /// Returns the goal set being tracked
+    pub fn set(&self) -> &GoalSet {
+        &self.set
+    }
+
+    /// Returns the outcome for the player if they have won or lost
+    pub fn outcome(&self, player: PlayerId) -> Option<Outcome> {
+        self.state.players.get(&player).and_then(|v| v.outcome)
+    }
+
+    /// Checks the conditions for the player returning their
+    /// progress or `None` if they have already won or lost
+    fn check(&mut self, day: u32, player: &PlayerInfo) -> Option<packet::GoalProgress> {
+        let start = *self.state.start_day.get_or_insert(day);
+        let goals = self.state.players.entry(player.uid).or_insert_with(PlayerGoals::default);
+        if goals.outcome.is_some() {
+            return None;
+        }
+
+        goals.bankrupt_days.resize(self.set.defeat.len(), 0);
+        for (days, condition) in goals.bankrupt_days.iter_mut().zip(&self.set.defeat) {
+            if let Condition::Bankrupt{below, ..} = *condition {
+                if player.money.0 < below {
+                    *days += 1;
+                } else {
+                    *days = 0;
+                }
+            }
+        }
+
+        let stats = PlayerStats {
+            days: day.wrapping_sub(start),
+            rating: player.rating,
+            money: player.money,
+            bankrupt_days: 0,
+        };
+        let victory: Vec<_> = self.set.victory.iter().map(|v| v.entry(&stats)).collect();
+        let defeat: Vec<_> = self.set.defeat.iter()
+            .zip(&goals.bankrupt_days)
+            .map(|(v, days)| v.entry(&PlayerStats {
+                bankrupt_days: *days,
+                .. stats
+            }))
+            .collect();
+
+        goals.outcome = if defeat.iter().any(|v| v.complete) {
+            Some(Outcome::Defeat)
+        } else if !victory.is_empty() && victory.iter().all(|v| v.complete) {
+            Some(Outcome::Victory)
+        } else {
+            None
+        };
+
+        Some(packet::GoalProgress {
+            name: self.set.display_name.clone(),
+            victory: AlwaysVec(victory),
+            defeat: AlwaysVec(defeat),
+            won: goals.outcome == Some(Outcome::Victory),
+            lost: goals.outcome == Some(Outcome::Defeat),
+        })
+    }
+}
+
+closure_system!(fn check_goals(
+    _em: EntityManager<'_>,
+    log: Read<CLogger>,
+    day: Read<DayTick>,
+    mut goals: Write<Goals>,
+    mut players: Write<crate::PlayerInfoMap>
+) {
+    let goals = if let Some(goals) = goals.get_component_mut(Container::WORLD) {
+        goals
+    } else {
+        return
+    };
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let day = assume!(log.log, day.get_component(Container::WORLD));
+    if goals.state.last_day == Some(day.day) {
+        return;
+    }
+    goals.state.last_day = Some(day.day);
+    let players = assume!(log.log, players.get_component_mut(Container::WORLD));
+
+    for player in players.values_mut() {
+        let progress = if let Some(progress) = goals.check(day.day, player) {
+            progress
+        } else {
+            continue
+        };
+        if progress.won || progress.lost {
+            let (title, description) = if progress.won {
+                ("Victory", format!("You have completed every goal of {}", progress.name))
+            } else {
+                ("Defeat", format!("You have failed {}", progress.name))
+            };
+            player.notifications.push(crate::notify::Notification::Text {
+                icon: ResourceKey::new("base", "solid"),
+                title: title.into(),
+                description,
+            });
+        }
+        player.goal_progress = Some(progress);
+    }
+});
+
+#[derive(Debug, Deserialize)]
+struct GoalSetJson {
+    name: String,
+    display_name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    victory: Vec<Condition>,
+    #[serde(default)]
+    defeat: Vec<Condition>,
+}
+
+/// Loads the goal sets provided by every pack
+pub fn load_goal_sets(log: &Logger, assets: &AssetManager) -> Vec<GoalSet> {
+    let mut sets = vec![];
+    for module in assets.get_packs() {
+        let goals_file = match assets.open_from_pack(module.borrow(), "goals/goals.json") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let goals_raw: Vec<GoalSetJson> = match serde_json::from_reader(goals_file) {
+            Ok(val) => val,
+            Err(err) => {
+                error!(log, "Failed to parse goals.json for pack {:?}: {}", module, err);
+                continue
+            }
+        };
+        sets.extend(goals_raw.into_iter()
+            .map(|v| GoalSet {
+                key: LazyResourceKey::parse(&v.name)
+                    .or_module(module.borrow())
+                    .into_owned(),
+                display_name: v.display_name,
+                description: v.description,
+                victory: v.victory,
+                defeat: v.defeat,
+            }));
+    }
+    sets
+}
+
+/// Finds the goal set with the key
+pub fn find_goal_set(log: &Logger, assets: &AssetManager, key: ResourceKey<'_>) -> Option<GoalSet> {
+    load_goal_sets(log, assets).into_iter()
+        .find(|v| v.key == key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(money: i64, rating: i16) -> PlayerInfo {
+        #[cfg(feature = "steam")]
+        let key = player::PlayerKey::Steam(steamworks::SteamId::from_raw(0));
+        #[cfg(not(feature = "steam"))]
+        let key = player::PlayerKey::Username("test".into());
+        let mut info = PlayerInfo::new(key, "test".into(), PlayerId(1), &[]);
+        info.money = UniDollar(money);
+        info.rating = rating;
+        info
+    }
+
+    fn goals(victory: Vec<Condition>, defeat: Vec<Condition>) -> Goals {
+        Goals::new(GoalSet {
+            key: ResourceKey::new("base", "test"),
+            display_name: "Test".into(),
+            description: String::new(),
+            victory,
+            defeat,
+        })
+    }
+
+    #[test]
+    fn victory_needs_every_condition() {
+        let mut goals = goals(vec![
+            Condition::Rating{at_least: 100},
+            Condition::SurviveDays{days: 2},
+        ], vec![]);
+        let p = info(50_000, 150);
+        let progress = goals.check(0, &p).unwrap();
+        assert!(!progress.won);
+        assert!(progress.victory.0[0].complete);
+        assert_eq!(progress.victory.0[1].progress, 0.0);
+
+        let progress = goals.check(2, &p).unwrap();
+        assert!(progress.won);
+        assert_eq!(goals.outcome(PlayerId(1)), Some(Outcome::Victory));
+        // Nothing more to report once the game is decided
+        assert!(goals.check(3, &p).is_none());
+    }
+
+    #[test]
+    fn bankrupt_days_in_a_row() {
+        let mut goals = goals(vec![], vec![
+            Condition::Bankrupt{below: 0, days: 2},
+        ]);
+        assert!(!goals.check(0, &info(-10, 0)).unwrap().lost);
+        // Recovering resets the count
+        assert!(!goals.check(1, &info(10, 0)).unwrap().lost);
+        assert!(!goals.check(2, &info(-10, 0)).unwrap().lost);
+        assert!(goals.check(3, &info(-10, 0)).unwrap().lost);
+        assert_eq!(goals.outcome(PlayerId(1)), Some(Outcome::Defeat));
+    }
+
+    #[test]
+    fn defeat_before_victory() {
+        let mut goals = goals(vec![
+            Condition::Money{at_least: 0},
+        ], vec![
+            Condition::Bankrupt{below: 1_000, days: 1},
+        ]);
+        let progress = goals.check(0, &info(100, 0)).unwrap();
+        assert!(progress.lost);
+        assert!(!progress.won);
+    }
+}
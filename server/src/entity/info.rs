@@ -13,6 +13,7 @@ use crate::ecs;
 use crate::prelude::*;
 
 use crate::common::{AnimationInfo, AnimationSet};
+use super::appearance;
 
 /// Loads entity descriptions
 pub struct Loader<CC> {
@@ -47,6 +48,7 @@ impl <'a, CC> assets::AssetLoader<'a> for Loader<CC>
             let animations = &info.animations;
             let log = &data.log;
             let icon = &info.icon;
+            let default_appearance = &info.appearance;
 
             data.entities.insert(resource.borrow().into_owned(), Arc::new(Type {
                 key: resource.borrow().into_owned(),
@@ -68,26 +70,33 @@ impl <'a, CC> assets::AssetLoader<'a> for Loader<CC>
                     .map(|v| {
                         let names = assets::LazyResourceKey::parse(v.names.as_ref().unwrap_or(names))
                                 .or_module(resource.module_key());
+                        let appearance = v.appearance.as_ref().or_else(|| default_appearance.as_ref())
+                            .map(|key| {
+                                let key = assets::LazyResourceKey::parse(key)
+                                    .or_module(resource.module_key());
+                                assume!(log, assets.loader_open::<appearance::Loader>(key))
+                            });
 
-                        let name_list = dnames.entry(names.borrow().into_owned())
-                            .or_insert_with(|| {
-                                let file = assume!(log, assets.open_from_pack(names.module_key(), &format!("entity/names/{}.json", names.resource())));
-                                let names: NameListInfo = assume!(log, serde_json::from_reader(file));
-                                Arc::new(NameList {
-                                    first: names.first.into_iter()
-                                        .map(Into::into)
-                                        .collect(),
-                                    second: names.second.into_iter()
-                                        .map(Into::into)
-                                        .collect(),
+                        // Names supplied by the appearance replace the
+                        // entity's own list
+                        let name_list = if let Some(names) = appearance.as_ref().and_then(|v| v.names(appearance::DEFAULT_LOCALE)) {
+                            names.clone()
+                        } else {
+                            dnames.entry(names.borrow().into_owned())
+                                .or_insert_with(|| {
+                                    let file = assume!(log, assets.open_from_pack(names.module_key(), &format!("entity/names/{}.json", names.resource())));
+                                    let names: NameListInfo = assume!(log, serde_json::from_reader(file));
+                                    Arc::new(names.into_list())
                                 })
-                            });
+                                .clone()
+                        };
                         let mut ani = animations.clone();
                         for (k, v) in v.animations {
                             ani.insert(k, v);
                         }
                         SubType {
-                            name_list: name_list.clone(),
+                            name_list,
+                            appearance,
                             model: assets::LazyResourceKey::parse(v.model.as_ref().unwrap_or(model))
                                 .or_module(resource.module_key())
                                 .into_owned(),
@@ -153,6 +162,9 @@ pub struct Highlight {
 pub struct SubType {
     /// The key of the list of names to use for this entity
     pub name_list: Arc<NameList>,
+    /// The possible generated appearances of this entity, if
+    /// it has any
+    pub appearance: Option<Arc<appearance::AppearanceData>>,
     /// The icon to display in the gui representing this
     /// object
     pub icon: assets::ResourceKey<'static>,
@@ -165,25 +177,47 @@ pub struct SubType {
 impl <CC: ComponentCreator> Type<CC> {
 
     /// Creates a new entity based on this type.
+    ///
+    /// Variants with an appearance get a random one which can
+    /// be replaced via `set_appearance`.
     pub fn create_entity(&self, em: &mut ecs::Container, variant_id: usize, name: Option<(Arc<str>, Arc<str>)>) -> ecs::Entity {
-        use rand::thread_rng;
+        use rand::{thread_rng, Rng};
         let variant = &self.variants[variant_id];
         let e = <CC::Creator as super::EntityCreator>::animated_model(em, variant.model.borrow(), None, variant.animations.clone(), "idle");
         let mut rng = thread_rng();
+        let seed: u32 = rng.gen();
+        let mut generated = variant.appearance.as_ref()
+            .map(|v| v.generate(seed, appearance::DEFAULT_LOCALE));
         em.add_component(e, super::Living {
             key: self.key.clone(),
             variant: variant_id,
-            name: name.unwrap_or_else(|| (
-               variant.name_list.first.choose(&mut rng).cloned().unwrap_or_else(|| "Missing".into()),
-               variant.name_list.second.choose(&mut rng).cloned().unwrap_or_else(|| "Name".into()),
-            )),
+            name: name
+                .or_else(|| generated.as_mut().and_then(|v| v.name.take()))
+                .unwrap_or_else(|| (
+                   variant.name_list.first.choose(&mut rng).cloned().unwrap_or_else(|| "Missing".into()),
+                   variant.name_list.second.choose(&mut rng).cloned().unwrap_or_else(|| "Name".into()),
+                )),
         });
         em.add_component(e, Controlled::new());
         for c in &self.components {
             c.apply(em, e);
         }
+        if let Some(generated) = generated {
+            appearance::apply(em, e, seed, generated);
+        }
         e
     }
+
+    /// Replaces the appearance of an entity of this type with
+    /// the one generated from the seed. The entity's name is
+    /// left as is.
+    ///
+    /// Does nothing if the variant doesn't have an appearance
+    pub fn set_appearance(&self, em: &mut ecs::Container, e: ecs::Entity, variant_id: usize, seed: u32) {
+        if let Some(data) = self.variants.get(variant_id).and_then(|v| v.appearance.as_ref()) {
+            appearance::apply(em, e, seed, data.generate(seed, appearance::DEFAULT_LOCALE));
+        }
+    }
 }
 
 /// Used to create components from json descriptions
@@ -220,6 +254,21 @@ pub enum ServerComponent {
         /// The movement speed of the entity
         speed: f32,
     },
+    /// How far other entities keep away whilst walking
+    Steering {
+        /// The radius of the entity in tiles
+        radius: f32,
+    },
+    /// Puts out fires instead of evacuating
+    Firefighter {
+        /// How quickly the entity puts out fires per a tick
+        rate: f32,
+    },
+    /// Speeds up the construction of nearby rooms
+    Builder {
+        /// The extra work done on a room per a tick
+        rate: f32,
+    },
     /// Entity requires payment
     Paid {
         /// The cost per a term for the entity
@@ -248,7 +297,7 @@ pub enum ServerComponent {
 
 #[doc(hidden)]
 #[derive(Debug, Clone, Copy)]
-pub struct TintColor(u8, u8, u8, u8);
+pub struct TintColor(pub(crate) u8, pub(crate) u8, pub(crate) u8, pub(crate) u8);
 
 impl ComponentCreator for ServerComponent {
     type Creator = super::ServerEntityCreator;
@@ -260,6 +309,9 @@ impl ComponentCreator for ServerComponent {
                 width, height, depth,
             },
             ServerComponentInfo::Speed{speed} => ServerComponent::Speed{speed},
+            ServerComponentInfo::Steering{radius} => ServerComponent::Steering{radius},
+            ServerComponentInfo::Firefighter{rate} => ServerComponent::Firefighter{rate},
+            ServerComponentInfo::Builder{rate} => ServerComponent::Builder{rate},
             ServerComponentInfo::Paid{cost} => ServerComponent::Paid{cost},
             ServerComponentInfo::Student{} => ServerComponent::Student{},
             ServerComponentInfo::Tint{tints} => ServerComponent::Tint{tints},
@@ -292,6 +344,21 @@ impl ComponentCreator for ServerComponent {
                     base_speed: speed,
                 });
             },
+            Steering{radius} => {
+                em.add_component(e, super::steering::Steering {
+                    radius,
+                });
+            },
+            Firefighter{rate} => {
+                em.add_component(e, super::fire::Firefighter {
+                    rate,
+                });
+            },
+            Builder{rate} => {
+                em.add_component(e, super::construction::Builder {
+                    rate,
+                });
+            },
             Paid{cost} => {
                 em.add_component(e, super::Paid {
                     cost,
@@ -389,6 +456,7 @@ pub fn load_staff_list(log: &Logger, assets: &AssetManager) -> Vec<StaffInfo> {
 #[doc(hidden)]
 pub enum ServerComponentInfo {
     AnimateMovement {},
+    LifecycleEffects {},
     Size {
         width: f32,
         height: f32,
@@ -397,6 +465,15 @@ pub enum ServerComponentInfo {
     Speed {
         speed: f32,
     },
+    Steering {
+        radius: f32,
+    },
+    Firefighter {
+        rate: f32,
+    },
+    Builder {
+        rate: f32,
+    },
     Paid {
         cost: UniDollar,
     },
@@ -420,11 +497,24 @@ struct StaffInfoJson {
 }
 
 #[derive(Deserialize)]
-struct NameListInfo {
+pub(super) struct NameListInfo {
     pub first: Vec<String>,
     pub second: Vec<String>,
 }
 
+impl NameListInfo {
+    pub(super) fn into_list(self) -> NameList {
+        NameList {
+            first: self.first.into_iter()
+                .map(Into::into)
+                .collect(),
+            second: self.second.into_iter()
+                .map(Into::into)
+                .collect(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct TypeInfo<C> {
     name: String,
@@ -435,6 +525,8 @@ struct TypeInfo<C> {
     components: Vec<C>,
     #[serde(default)]
     generator: Option<String>,
+    #[serde(default)]
+    appearance: Option<String>,
 
     variants: Vec<SubTypeInfoOpt>,
     #[serde(default)]
@@ -457,6 +549,8 @@ struct SubTypeInfoOpt {
     #[serde(default)]
     model: Option<String>,
     #[serde(default)]
+    appearance: Option<String>,
+    #[serde(default)]
     animations: FNVMap<String, AnimationInfo>,
 }
 
@@ -521,6 +615,7 @@ mod tests {
         let log = ::slog::Logger::root(::slog::Discard, o!());
         let assets = AssetManager::with_packs(&log, &["base".to_owned()])
             .register::<super::Loader<ServerComponent>>()
+            .register::<crate::entity::appearance::Loader>()
             .build();
         load_dir(&assets, Path::new("./assets/base/base/entity"));
     }
@@ -537,7 +632,7 @@ mod tests {
                 let path_str = &path_str[..path_str.len() - 5];
 
                 // Not a room file, just a list of them
-                if path_str == "entities" || path_str.starts_with("names/") {
+                if path_str == "entities" || path_str.starts_with("names/") || path_str.starts_with("appearance/") {
                     continue;
                 }
                 println!("Trying to load: {:?}", path_str);
@@ -0,0 +1,451 @@
+//! Litter dropped by students as they walk around the campus.
+//!
+//! Every so often a walking student drops a piece of litter at the
+//! rate set by the server's `LitterConfig`, more often the unhappier
+//! they are. If a bin with space is close by the litter goes in the
+//! bin instead. Each tile holds a few pieces which are synced to
+//! clients to draw as decals and upset the students walking over
+//! them.
+//!
+//! Littered tiles and full bins are queued as tasks for janitors.
+//! Scripts claim the closest task via `claim_litter_task` on the
+//! janitor, so two janitors never go after the same piece, and then
+//! finish it with `pick_up_litter` or `empty_bin`.
+
+use std::collections::VecDeque;
+use rand::{thread_rng, Rng};
+use crate::ecs::{self, closure_system, Read, Write, EntityManager};
+use crate::util::{FNVMap, FNVSet, Bound};
+use super::*;
+
+/// The most pieces of litter a tile can hold
+pub const MAX_PIECES: u8 = 3;
+/// How far in tiles a janitor can empty a bin from
+pub const EMPTY_RANGE: f32 = 1.5;
+
+/// Registers components required by this module
+pub fn register_components(c: &mut ecs::Container) {
+    c.register_component::<Litter>();
+    c.register_component::<Bin>();
+}
+
+/// Registers systems required by this module
+pub fn register_systems(sys: &mut ecs::Systems) {
+    sys.add(drop_litter);
+}
+
+/// Controls how much litter students drop
+#[derive(Clone, Debug)]
+pub struct LitterConfig {
+    /// The chance per a tick of a happy student dropping litter
+    /// whilst walking. Doubles for the unhappiest students and
+    /// zero stops litter being dropped
+    pub rate: f32,
+    /// How far in tiles students will walk to put litter in
+    /// a bin instead of dropping it
+    pub bin_range: f32,
+    /// The happiness a student loses per a tick for each piece
+    /// of litter on the tile they are walking across
+    pub happiness_loss: f32,
+}
+
+impl Default for LitterConfig {
+    fn default() -> LitterConfig {
+        LitterConfig {
+            rate: 0.0002,
+            bin_range: 6.0,
+            happiness_loss: 0.0002,
+        }
+    }
+}
+
+/// A bin that students put litter into whilst it has space
+#[derive(Debug)]
+pub struct Bin {
+    /// The pieces of litter the bin can hold
+    pub capacity: u32,
+    /// The pieces of litter in the bin
+    pub contents: u32,
+}
+component!(Bin => Map);
+
+impl Bin {
+    /// Creates an empty bin
+    pub fn new(capacity: u32) -> Bin {
+        Bin {
+            capacity,
+            contents: 0,
+        }
+    }
+
+    /// Returns whether the bin can't take any more litter
+    pub fn is_full(&self) -> bool {
+        self.contents >= self.capacity
+    }
+}
+
+/// A job for a janitor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Task {
+    /// Pick up the litter on the tile
+    Litter(Location),
+    /// Empty the full bin
+    EmptyBin(Entity),
+}
+
+/// The litter on every tile in the level and the tasks
+/// waiting for janitors.
+///
+/// Stored on the world entity
+#[derive(Default)]
+pub struct Litter {
+    config: LitterConfig,
+    tiles: FNVMap<Location, u8>,
+    /// Tiles whose number of pieces changed since the last
+    /// call to `take_changes`
+    changed: FNVSet<Location>,
+    /// Tasks in the order they were created
+    tasks: VecDeque<Task>,
+    /// The task each janitor is working on
+    claimed: FNVMap<Entity, Task>,
+}
+component!(Litter => Map);
+
+impl Litter {
+    /// Creates a clean level using the config's rates
+    pub fn new(config: &LitterConfig) -> Litter {
+        Litter {
+            config: LitterConfig {
+                rate: config.rate.max(0.0),
+                bin_range: config.bin_range.max(0.0),
+                happiness_loss: config.happiness_loss.max(0.0),
+            },
+            .. Litter::default()
+        }
+    }
+
+    /// Returns the number of pieces of litter on the tile
+    pub fn pieces(&self, loc: Location) -> u8 {
+        self.tiles.get(&loc).cloned().unwrap_or(0)
+    }
+
+    /// Drops a piece of litter on the tile, returning whether
+    /// it had space for it
+    pub fn drop_piece(&mut self, loc: Location) -> bool {
+        let pieces = self.pieces(loc);
+        if pieces >= MAX_PIECES {
+            return false;
+        }
+        if pieces == 0 {
+            self.tasks.push_back(Task::Litter(loc));
+        }
+        self.tiles.insert(loc, pieces + 1);
+        self.changed.insert(loc);
+        true
+    }
+
+    /// Picks up a piece of litter from the tile, returning whether
+    /// any litter is left on it
+    pub fn pick_up(&mut self, loc: Location) -> bool {
+        match self.pieces(loc) {
+            0 => return false,
+            1 => {
+                self.tiles.remove(&loc);
+                self.finish(Task::Litter(loc));
+            },
+            pieces => {
+                self.tiles.insert(loc, pieces - 1);
+            },
+        }
+        self.changed.insert(loc);
+        self.tiles.contains_key(&loc)
+    }
+
+    /// Queues the bin to be emptied now that it is full
+    pub fn bin_filled(&mut self, bin: Entity) {
+        let task = Task::EmptyBin(bin);
+        if !self.tasks.contains(&task) {
+            self.tasks.push_back(task);
+        }
+    }
+
+    /// Removes the task from the queue and any janitor
+    /// working on it
+    pub fn finish(&mut self, task: Task) {
+        self.tasks.retain(|v| *v != task);
+        self.claimed.retain(|_, v| *v != task);
+    }
+
+    /// Stops the janitor working on their task, leaving it for
+    /// another janitor to claim
+    pub fn release(&mut self, janitor: Entity) {
+        self.claimed.remove(&janitor);
+    }
+
+    /// Returns the task the janitor is working on if any
+    pub fn claimed_by(&self, janitor: Entity) -> Option<Task> {
+        self.claimed.get(&janitor).cloned()
+    }
+
+    /// Claims the task closest to the location within the bounds
+    /// that no other janitor is working on. Returns the janitor's
+    /// current task instead if they have one.
+    ///
+    /// `locate` returns where the task needs doing or `None` if it
+    /// can no longer be done, e.g. the bin was removed, in which
+    /// case the task is dropped.
+    pub fn claim<F>(&mut self, janitor: Entity, loc: Location, bound: Bound, locate: F) -> Option<(Task, Location)>
+        where F: Fn(Task) -> Option<Location>
+    {
+        let stale: Vec<_> = self.tasks.iter()
+            .cloned()
+            .filter(|v| locate(*v).is_none())
+            .collect();
+        for task in stale {
+            self.finish(task);
+        }
+        if let Some(task) = self.claimed_by(janitor) {
+            if let Some(at) = locate(task) {
+                return Some((task, at));
+            }
+        }
+
+        let claimed = &self.claimed;
+        // Ties go to the oldest task
+        let (task, at) = self.tasks.iter()
+            .cloned()
+            .filter(|v| !claimed.values().any(|c| c == v))
+            .filter_map(|v| locate(v).map(|l| (v, l)))
+            .filter(|v| bound.in_bounds(v.1))
+            .min_by_key(|v| (v.1.x - loc.x) * (v.1.x - loc.x) + (v.1.y - loc.y) * (v.1.y - loc.y))?;
+        self.claimed.insert(janitor, task);
+        Some((task, at))
+    }
+
+    /// Removes the claims of janitors that no longer exist
+    pub fn retain_claims<F>(&mut self, valid: F)
+        where F: Fn(Entity) -> bool
+    {
+        self.claimed.retain(|e, _| valid(*e));
+    }
+
+    /// Returns the janitors with a claimed task
+    pub fn janitors(&self) -> impl Iterator<Item=Entity> + '_ {
+        self.claimed.keys().cloned()
+    }
+
+    /// Returns every tile with litter and the number of pieces
+    /// on it
+    pub fn tiles(&self) -> impl Iterator<Item=(Location, u8)> + '_ {
+        self.tiles.iter().map(|(l, v)| (*l, *v))
+    }
+
+    /// Returns the tiles whose number of pieces changed since
+    /// this was last called along with their new number
+    pub fn take_changes(&mut self) -> Vec<(Location, u8)> {
+        let changed = ::std::mem::replace(&mut self.changed, FNVSet::default());
+        changed.into_iter()
+            .map(|v| (v, self.pieces(v)))
+            .collect()
+    }
+}
+
+/// Claims a task for the janitor from the world's queue, returning
+/// it along with where it needs doing
+pub fn claim_task(entities: &mut Container, janitor: Entity, bound: Bound) -> Option<(Task, Location)> {
+    let loc = entities.get_component::<Position>(janitor)
+        .map(|v| Location::new(v.x as i32, v.z as i32))?;
+    let gone: Vec<_> = entities.get_component::<Litter>(Container::WORLD)?
+        .janitors()
+        .filter(|v| !entities.is_valid(*v))
+        .collect();
+    // A snapshot of the bins' positions so the queue can be
+    // borrowed mutably
+    let bins: FNVMap<Entity, Location> = entities.get_component::<Litter>(Container::WORLD)?
+        .tasks.iter()
+        .filter_map(|v| if let Task::EmptyBin(e) = *v { Some(e) } else { None })
+        .filter(|v| entities.is_valid(*v) && entities.get_component::<Bin>(*v).is_some())
+        .filter_map(|v| entities.get_component::<Position>(v).map(|p| (v, Location::new(p.x as i32, p.z as i32))))
+        .collect();
+    let litter = entities.get_component_mut::<Litter>(Container::WORLD)?;
+    litter.retain_claims(|e| !gone.contains(&e));
+    litter.claim(janitor, loc, bound, |task| match task {
+        Task::Litter(loc) => Some(loc),
+        Task::EmptyBin(e) => bins.get(&e).cloned(),
+    })
+}
+
+/// Empties the bin claimed by the janitor if they are close enough
+/// to it, returning whether it was emptied
+pub fn empty_claimed_bin(entities: &mut Container, janitor: Entity) -> bool {
+    let bin = match entities.get_component::<Litter>(Container::WORLD).and_then(|v| v.claimed_by(janitor)) {
+        Some(Task::EmptyBin(bin)) => bin,
+        _ => return false,
+    };
+    let in_range = match (entities.get_component::<Position>(janitor), entities.get_component::<Position>(bin)) {
+        (Some(a), Some(b)) => {
+            let (dx, dz) = (a.x - b.x, a.z - b.z);
+            dx * dx + dz * dz <= EMPTY_RANGE * EMPTY_RANGE
+        },
+        _ => false,
+    };
+    if !in_range {
+        return false;
+    }
+    if let Some(bin) = entities.get_component_mut::<Bin>(bin) {
+        bin.contents = 0;
+    }
+    if let Some(litter) = entities.get_component_mut::<Litter>(Container::WORLD) {
+        litter.finish(Task::EmptyBin(bin));
+    }
+    true
+}
+
+closure_system!(fn drop_litter(
+    em: EntityManager<'_>,
+    position: Read<Position>,
+    path_info: Read<pathfind::PathInfo>,
+    frozen: Read<Frozen>,
+    mut student_vars: Write<StudentVars>,
+    mut bins: Write<Bin>,
+    mut litter: Write<Litter>
+) {
+    let litter = if let Some(litter) = litter.get_component_mut(Container::WORLD) {
+        litter
+    } else {
+        return
+    };
+    let config = litter.config.clone();
+    if config.rate <= 0.0 && config.happiness_loss <= 0.0 {
+        return;
+    }
+    let bin_positions: Vec<_> = em.group_mask(&position, |m| m.and(&bins))
+        .map(|(e, p)| (e, p.x, p.z))
+        .collect();
+    let mut rng = thread_rng();
+
+    for (e, pos) in em.group_mask(&position, |m| m.and(&path_info).and(&student_vars).and_not(&frozen)) {
+        if !path_info.get_component(e).map_or(false, |v| v.is_moving() && !v.is_simplified()) {
+            continue;
+        }
+        let loc = Location::new(pos.x as i32, pos.z as i32);
+        let vars = if let Some(vars) = student_vars.get_custom(e) {
+            vars
+        } else {
+            continue
+        };
+        let happiness = vars.get_stat(Stats::STUDENT_HAPPINESS);
+        let pieces = litter.pieces(loc);
+        if pieces > 0 {
+            vars.set_stat(Stats::STUDENT_HAPPINESS, happiness - config.happiness_loss * f32::from(pieces));
+        }
+
+        let chance = config.rate * (2.0 - happiness.max(0.0).min(1.0));
+        if rng.gen::<f32>() >= chance {
+            continue;
+        }
+        let bin = bin_positions.iter()
+            .filter(|v| bins.get_component(v.0).map_or(false, |v| !v.is_full()))
+            .map(|v| (v.0, (v.1 - pos.x) * (v.1 - pos.x) + (v.2 - pos.z) * (v.2 - pos.z)))
+            .filter(|v| v.1 <= config.bin_range * config.bin_range)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(::std::cmp::Ordering::Equal))
+            .map(|v| v.0);
+        if let Some(bin) = bin.and_then(|v| bins.get_component_mut(v).map(|b| (v, b))) {
+            bin.1.contents += 1;
+            if bin.1.is_full() {
+                litter.bin_filled(bin.0);
+            }
+        } else {
+            litter.drop_piece(loc);
+        }
+    }
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entities(count: usize) -> Vec<Entity> {
+        let mut c = ecs::Container::new();
+        (0 .. count).map(|_| c.new_entity()).collect()
+    }
+
+    fn bound() -> Bound {
+        Bound::new(Location::new(0, 0), Location::new(20, 20))
+    }
+
+    #[test]
+    fn drop_and_pick_up() {
+        let mut litter = Litter::default();
+        let loc = Location::new(3, 4);
+        assert!(litter.drop_piece(loc));
+        assert!(litter.drop_piece(loc));
+        assert_eq!(litter.pieces(loc), 2);
+        assert_eq!(litter.take_changes(), vec![(loc, 2)]);
+        // Only one task per a tile
+        assert_eq!(litter.tasks.len(), 1);
+        for _ in 0 .. 10 {
+            litter.drop_piece(loc);
+        }
+        assert_eq!(litter.pieces(loc), MAX_PIECES);
+
+        assert!(litter.pick_up(loc));
+        assert!(litter.pick_up(loc));
+        assert!(!litter.pick_up(loc));
+        assert_eq!(litter.pieces(loc), 0);
+        assert!(litter.tasks.is_empty());
+        assert_eq!(litter.take_changes(), vec![(loc, 0)]);
+        // Picking up from a clean tile does nothing
+        assert!(!litter.pick_up(loc));
+        assert!(litter.take_changes().is_empty());
+    }
+
+    #[test]
+    fn claiming() {
+        let mut litter = Litter::default();
+        let near = Location::new(2, 2);
+        let far = Location::new(15, 15);
+        litter.drop_piece(far);
+        litter.drop_piece(near);
+        let locate = |v: Task| match v {
+            Task::Litter(loc) => Some(loc),
+            Task::EmptyBin(_) => None,
+        };
+        let e = entities(2);
+        let (a, b) = (e[0], e[1]);
+
+        assert_eq!(litter.claim(a, Location::new(0, 0), bound(), locate), Some((Task::Litter(near), near)));
+        // Claiming again keeps the same task
+        assert_eq!(litter.claim(a, Location::new(14, 14), bound(), locate), Some((Task::Litter(near), near)));
+        // Other janitors can't take it
+        assert_eq!(litter.claim(b, Location::new(0, 0), bound(), locate), Some((Task::Litter(far), far)));
+
+        litter.pick_up(near);
+        assert_eq!(litter.claimed_by(a), None);
+        assert_eq!(litter.claim(a, Location::new(0, 0), bound(), locate), None);
+        litter.release(b);
+        assert_eq!(litter.claim(a, Location::new(0, 0), bound(), locate), Some((Task::Litter(far), far)));
+    }
+
+    #[test]
+    fn claiming_bounds_and_bins() {
+        let mut litter = Litter::default();
+        let e = entities(2);
+        let (janitor, bin) = (e[0], e[1]);
+        // Outside of the janitor's area
+        litter.drop_piece(Location::new(30, 30));
+        litter.bin_filled(bin);
+        litter.bin_filled(bin);
+        assert_eq!(litter.tasks.len(), 2);
+
+        let none = |_: Task| None;
+        let bin_at = |v: Task| match v {
+            Task::Litter(loc) => Some(loc),
+            Task::EmptyBin(_) => Some(Location::new(5, 5)),
+        };
+        assert_eq!(litter.claim(janitor, Location::new(0, 0), bound(), bin_at), Some((Task::EmptyBin(bin), Location::new(5, 5))));
+        // Tasks that can't be done any more are dropped
+        assert_eq!(litter.claim(janitor, Location::new(0, 0), bound(), none), None);
+        assert!(litter.tasks.is_empty());
+    }
+}
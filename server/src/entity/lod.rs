@@ -0,0 +1,331 @@
+//! Simplifies entities that no player is looking at.
+//!
+//! Clients send the position of their camera as a `ViewArea`
+//! which is kept in the world's `Lod`. Every few ticks living
+//! entities further than the configured radius from every view
+//! are marked as `Simplified` and go back to the full simulation
+//! once a player looks their way again. A little extra distance
+//! is needed before an entity is simplified so that entities on
+//! the edge don't keep switching.
+//!
+//! Simplified entities don't pathfind. Their targets (and any
+//! path they were already following) are replaced with a path
+//! that waits for roughly the time walking there would take and
+//! then jumps the entity to the end, so scripts waiting on them
+//! to arrive behave the same. Their needs keep ticking as normal
+//! but new idle choices are only picked every so often, leaving
+//! them idle in between. When rehydrated the remaining path is
+//! computed again from where the entity is.
+
+use crate::ecs::{self, closure_system, Read, Write, EntityManager};
+use super::*;
+
+/// How often in ticks entities are checked against the views
+pub const UPDATE_INTERVAL: u32 = 10;
+/// Roughly how much longer than a straight line a walk to a
+/// target is
+pub const DETOUR_FACTOR: f32 = 1.3;
+
+/// Registers components required by this module
+pub fn register_components(c: &mut ecs::Container) {
+    c.register_component::<Lod>();
+    c.register_component::<Simplified>();
+}
+
+/// Registers systems required by this module
+pub fn register_systems(sys: &mut ecs::Systems) {
+    sys.add(update_lod);
+    sys.add(travel_simplified);
+}
+
+/// Controls when entities are simplified
+#[derive(Clone, Debug)]
+pub struct LodConfig {
+    /// Whether entities are ever simplified
+    pub enabled: bool,
+    /// How far in tiles from a player's camera entities
+    /// are fully simulated
+    pub radius: f32,
+    /// How much further than `radius` in tiles an entity
+    /// has to be before it is simplified
+    pub hysteresis: f32,
+    /// How often in ticks simplified entities may pick a new
+    /// idle choice
+    pub choice_interval: u32,
+}
+
+impl Default for LodConfig {
+    fn default() -> LodConfig {
+        LodConfig {
+            enabled: true,
+            radius: 40.0,
+            hysteresis: 8.0,
+            choice_interval: 40,
+        }
+    }
+}
+
+/// Marks an entity as being simplified because no
+/// player is near it
+#[derive(Default)]
+pub struct Simplified;
+component!(Simplified => Marker);
+
+/// The areas players are looking at.
+///
+/// Stored on the world entity
+#[derive(Default)]
+pub struct Lod {
+    config: LodConfig,
+    areas: Vec<(f32, f32)>,
+    ticks: u32,
+}
+component!(Lod => Map);
+
+impl Lod {
+    /// Creates a level with no known views using the config
+    pub fn new(config: &LodConfig) -> Lod {
+        Lod {
+            config: LodConfig {
+                radius: config.radius.max(0.0),
+                hysteresis: config.hysteresis.max(0.0),
+                choice_interval: config.choice_interval.max(1),
+                .. config.clone()
+            },
+            .. Lod::default()
+        }
+    }
+
+    /// Replaces the positions of every player's camera
+    pub fn set_areas<I>(&mut self, areas: I)
+        where I: IntoIterator<Item=(f32, f32)>
+    {
+        self.areas.clear();
+        self.areas.extend(areas);
+    }
+
+    /// Returns whether an entity at the position should be
+    /// fully simulated.
+    ///
+    /// Until a view is known everything is simulated as
+    /// normal
+    pub fn is_observed(&self, x: f32, z: f32, simplified: bool) -> bool {
+        if !self.config.enabled || self.areas.is_empty() {
+            return true;
+        }
+        let radius = if simplified {
+            self.config.radius
+        } else {
+            self.config.radius + self.config.hysteresis
+        };
+        self.areas.iter()
+            .any(|v| (v.0 - x) * (v.0 - x) + (v.1 - z) * (v.1 - z) <= radius * radius)
+    }
+
+    /// Returns whether simplified entities may pick a new
+    /// idle choice this tick
+    pub fn allows_choice(&self) -> bool {
+        self.ticks % self.config.choice_interval == 0
+    }
+}
+
+/// Returns how many ticks walking the distance would take
+fn travel_ticks(distance: f32, speed: Option<&MovementSpeed>) -> f32 {
+    match speed {
+        Some(speed) if speed.base_speed > 0.0 => distance / speed.base_speed,
+        _ => 0.0,
+    }
+}
+
+closure_system!(fn update_lod(
+    em: EntityManager<'_>,
+    mut lod: Write<Lod>,
+    position: Read<Position>,
+    living: Read<Living>,
+    mut simplified: Write<Simplified>,
+    mut info: Write<pathfind::PathInfo>,
+    mut target: Write<pathfind::Target>,
+    mut target_f: Write<pathfind::TargetFacing>
+) {
+    let lod = if let Some(lod) = lod.get_component_mut(Container::WORLD) {
+        lod
+    } else {
+        return
+    };
+    lod.ticks = lod.ticks.wrapping_add(1);
+    if lod.ticks % UPDATE_INTERVAL != 0 {
+        return;
+    }
+
+    for (e, pos) in em.group_mask(&position, |m| m.and(&living)) {
+        let was_simplified = simplified.get_component(e).is_some();
+        if lod.is_observed(pos.x, pos.z, was_simplified) != was_simplified {
+            continue;
+        }
+        if !was_simplified {
+            simplified.add_component(e, Simplified);
+            continue;
+        }
+        simplified.remove_component(e);
+        // Walk the rest of the way instead
+        if !info.get_component(e).map_or(false, |v| v.is_simplified()) {
+            continue;
+        }
+        if let Some(path) = info.remove_component(e) {
+            if target.get_component(e).is_none() {
+                let end = path.last();
+                target.add_component(e, pathfind::Target::new(end.0, end.1));
+                if let Some(rotation) = path.end_rotation {
+                    target_f.add_component(e, pathfind::TargetFacing { rotation });
+                }
+            }
+        }
+    }
+});
+
+closure_system!(fn travel_simplified(
+    em: EntityManager<'_>,
+    log: Read<CLogger>,
+    tiles: Read<level::LevelTiles>,
+    rooms: Read<level::LevelRooms>,
+    frozen: Read<Frozen>,
+    speed: Read<MovementSpeed>,
+    mut simplified: Write<Simplified>,
+    mut position: Write<Position>,
+    mut info: Write<pathfind::PathInfo>,
+    mut target: Write<pathfind::Target>,
+    mut target_f: Write<pathfind::TargetFacing>,
+    mut target_pos: Write<TargetPosition>,
+    mut target_rotation: Write<TargetRotation>,
+    mut teleported: Write<Teleported>
+) {
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let tiles = assume!(log.log, tiles.get_component(Container::WORLD));
+    let rooms = assume!(log.log, rooms.get_component(Container::WORLD));
+
+    let mask = simplified.mask()
+        .and(&position)
+        .and_not(&frozen);
+    for e in em.iter_mask(&mask) {
+        let pos = assume!(log.log, position.get_component_mut(e));
+
+        if let Some(tar) = target.get_component(e) {
+            let (x, z) = tar.location;
+            // Can't tell where the pathfinder would end up so
+            // let it work it out
+            if !level::can_visit(tiles, rooms, (x * 4.0) as usize, (z * 4.0) as usize) {
+                simplified.remove_component(e);
+                continue;
+            }
+            let distance = ((x - pos.x) * (x - pos.x) + (z - pos.z) * (z - pos.z)).sqrt() * DETOUR_FACTOR;
+            let rotation = target_f.remove_component(e).map(|v| v.rotation);
+            target.remove_component(e);
+            info.add_component(e, pathfind::PathInfo::simplified(
+                (x, z),
+                travel_ticks(distance, speed.get_component(e)),
+                rotation
+            ));
+        } else if let Some(path) = info.get_component(e).filter(|v| !v.is_simplified()) {
+            if path.is_empty() {
+                continue;
+            }
+            let ticks = travel_ticks(path.distance((pos.x, pos.z)), speed.get_component(e));
+            let path = pathfind::PathInfo::simplified(path.last(), ticks, path.end_rotation);
+            info.add_component(e, path);
+        }
+
+        let arrived = if let Some(path) = info.get_component_mut(e).filter(|v| v.is_simplified()) {
+            path.time -= 1.0;
+            path.time <= 0.0
+        } else {
+            false
+        };
+        if arrived {
+            let path = assume!(log.log, info.remove_component(e));
+            let (x, z) = path.last();
+            pos.x = x;
+            pos.z = z;
+            let id = teleported.get_component(e)
+                .map_or(0, |v| v.id.wrapping_add(1));
+            teleported.add_component(e, Teleported { id, x, z, ticks: 0 });
+            target_pos.remove_component(e);
+            if let Some(rotation) = path.end_rotation {
+                target_rotation.add_component(e, TargetRotation {
+                    rotation,
+                    ticks: 8.0,
+                });
+            }
+        }
+    }
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observed() {
+        let mut lod = Lod::new(&LodConfig {
+            enabled: true,
+            radius: 10.0,
+            hysteresis: 5.0,
+            choice_interval: 4,
+        });
+        // Nothing is simplified until a player's view is known
+        assert!(lod.is_observed(100.0, 100.0, false));
+
+        lod.set_areas(vec![(0.0, 0.0), (50.0, 0.0)]);
+        assert!(lod.is_observed(8.0, 0.0, false));
+        assert!(lod.is_observed(8.0, 0.0, true));
+        assert!(lod.is_observed(50.0, 9.0, true));
+        // Within the extra distance entities stay as they are
+        assert!(lod.is_observed(13.0, 0.0, false));
+        assert!(!lod.is_observed(13.0, 0.0, true));
+        assert!(!lod.is_observed(16.0, 0.0, false));
+        assert!(!lod.is_observed(25.0, 25.0, false));
+
+        lod.set_areas(vec![]);
+        assert!(lod.is_observed(25.0, 25.0, true));
+    }
+
+    #[test]
+    fn disabled() {
+        let mut lod = Lod::new(&LodConfig {
+            enabled: false,
+            .. LodConfig::default()
+        });
+        lod.set_areas(vec![(0.0, 0.0)]);
+        assert!(lod.is_observed(1000.0, 1000.0, false));
+    }
+
+    #[test]
+    fn choices() {
+        let mut lod = Lod::new(&LodConfig {
+            choice_interval: 0,
+            .. LodConfig::default()
+        });
+        // Treated as every tick
+        assert!(lod.allows_choice());
+        lod.ticks = 7;
+        assert!(lod.allows_choice());
+
+        let mut lod = Lod::new(&LodConfig {
+            choice_interval: 3,
+            .. LodConfig::default()
+        });
+        lod.ticks = 4;
+        assert!(!lod.allows_choice());
+        lod.ticks = 6;
+        assert!(lod.allows_choice());
+    }
+
+    #[test]
+    fn ticks() {
+        let speed = MovementSpeed {
+            speed: 0.1,
+            base_speed: 0.25,
+        };
+        assert!((travel_ticks(10.0, Some(&speed)) - 40.0).abs() < 0.001);
+        assert!(travel_ticks(10.0, None).abs() < 0.001);
+    }
+}
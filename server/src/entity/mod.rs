@@ -2,9 +2,19 @@
 
 pub mod snapshot;
 pub mod pathfind;
+pub mod steering;
+pub mod fire;
+pub mod dirt;
+pub mod litter;
+pub mod lod;
+pub mod construction;
+pub mod visitor;
+pub mod goals;
 mod info;
+pub mod appearance;
 pub mod free_roam;
 pub mod course;
+pub mod template;
 
 mod timetable;
 
@@ -14,6 +24,7 @@ pub(crate) use self::timetable::{
     TimeTableCompleted,
     TimeTableStart,
     generate_time_table,
+    change_course,
 };
 pub use self::timetable::{LESSON_LENGTH, NUM_TIMETABLE_SLOTS};
 
@@ -46,6 +57,15 @@ use lua::{Ref, Table, Lua, Coroutine};
 /// Registers components required by the server and the client
 pub fn register_components(c: &mut ecs::Container) {
     pathfind::register_components(c);
+    fire::register_components(c);
+    dirt::register_components(c);
+    litter::register_components(c);
+    lod::register_components(c);
+    construction::register_components(c);
+    visitor::register_components(c);
+    goals::register_components(c);
+    crate::saving::scheduled::register_components(c);
+    crate::reward::register_components(c);
 
     c.register_component::<Position>();
     c.register_component::<Size>();
@@ -53,7 +73,10 @@ pub fn register_components(c: &mut ecs::Container) {
     c.register_component::<TargetPosition>();
     c.register_component::<TargetRotation>();
     c.register_component::<CatchupBuffer>();
+    c.register_component::<Teleported>();
+    c.register_component::<Interacted>();
     c.register_component::<MovementSpeed>();
+    c.register_component::<steering::Steering>();
     c.register_component::<LagMovementAdjust>();
     c.register_component::<NetworkId>();
 
@@ -65,6 +88,8 @@ pub fn register_components(c: &mut ecs::Container) {
     c.register_component::<Living>();
     c.register_component::<InvalidPlacement>();
     c.register_component::<Door>();
+    c.register_component::<ObjectTriggers>();
+    c.register_component::<TriggeredAnimation>();
 
     c.register_component::<RoomController>();
     c.register_component::<GotoRoom>();
@@ -89,6 +114,7 @@ pub fn register_components(c: &mut ecs::Container) {
     c.register_component::<crate::PlayerInfoMap>();
     c.register_component::<Idle>();
     c.register_component::<Tints>();
+    c.register_component::<appearance::Appearance>();
     c.register_component::<ForceLeave>();
     c.register_component::<Quitting>();
     c.register_component::<assets::AssetManager>();
@@ -98,6 +124,11 @@ pub fn register_components(c: &mut ecs::Container) {
     c.register_component::<LuaRoomProperties>();
     c.register_component::<crate::script_room::LuaEntityRef>();
     c.register_component::<CLogger>();
+    c.register_component::<crate::player::Trades>();
+    c.register_component::<crate::player::RoomFinances>();
+    c.register_component::<crate::player::CampusBranding>();
+    c.register_component::<crate::player::Campuses>();
+    c.register_component::<crate::player::Profiles>();
     c.register_component::<RequiresRoom>();
 
     c.register_component::<AutoRest>();
@@ -123,11 +154,19 @@ pub fn register_server_systems(sys: &mut ecs::Systems) {
     sys.add(pathfind::tick_pathfinder);
     sys.add(sys::no_lerp_target_pos);
     sys.add(sys::no_lerp_target_rot);
+    sys.add(sys::tick_teleported);
     sys.add(sys::manage_room);
     sys.add(sys::manage_entity_dispatch);
     sys.add(sys::get_timetable);
     sys.add(sys::walk_to_room);
     sys.add(sys::open_door_server);
+    fire::register_systems(sys);
+    dirt::register_systems(sys);
+    litter::register_systems(sys);
+    lod::register_systems(sys);
+    construction::register_systems(sys);
+    visitor::register_systems(sys);
+    goals::register_systems(sys);
     sys.add(sys::leave_room);
     sys.add(timetable::manage_time_table);
     sys.add(follow_sys);
@@ -241,6 +280,63 @@ pub struct CatchupBuffer {
 }
 component!(CatchupBuffer => Vec);
 
+/// Marks that the entity was last moved instantly instead
+/// of walking.
+///
+/// Sent to clients with the entity's snapshot so that they
+/// jump the entity to its new position instead of smoothly
+/// moving it across the map. Use `teleport` to move an entity.
+#[derive(Debug, Clone, Copy)]
+pub struct Teleported {
+    /// Changed every time the entity is teleported so that
+    /// clients can tell teleports apart
+    pub id: u8,
+    /// The position on the x axis the entity was moved to
+    pub x: f32,
+    /// The position on the z axis the entity was moved to
+    pub z: f32,
+    /// The number of ticks since the teleport, stops counting
+    /// at `u8::MAX`
+    pub ticks: u8,
+}
+component!(Teleported => Map);
+
+/// The number of ticks after a teleport that clients are told
+/// the entity was recently teleported.
+///
+/// Clients don't play spawn or despawn effects for these entities
+pub const RECENT_TELEPORT_TICKS: u8 = 20;
+
+impl Teleported {
+    /// Returns whether the teleport happened within the last
+    /// `RECENT_TELEPORT_TICKS` ticks
+    pub fn is_recent(&self) -> bool {
+        self.ticks < RECENT_TELEPORT_TICKS
+    }
+}
+
+/// Instantly moves the entity to the location, cancelling
+/// any movement it was doing.
+///
+/// Clients will jump the entity to the location instead of
+/// interpolating its movement.
+pub fn teleport(entities: &mut ecs::Container, e: ecs::Entity, x: f32, z: f32) {
+    if let Some(pos) = entities.get_component_mut::<Position>(e) {
+        pos.x = x;
+        pos.z = z;
+    } else {
+        return;
+    }
+    let id = entities.get_component::<Teleported>(e)
+        .map_or(0, |v| v.id.wrapping_add(1));
+    entities.add_component(e, Teleported { id, x, z, ticks: 0 });
+    entities.remove_component::<TargetPosition>(e);
+    entities.remove_component::<CatchupBuffer>(e);
+    entities.remove_component::<pathfind::Target>(e);
+    entities.remove_component::<pathfind::TargetTime>(e);
+    entities.remove_component::<pathfind::PathInfo>(e);
+}
+
 /// Contains the size of an entity
 pub struct Size {
     /// The width (x axis)
@@ -290,6 +386,13 @@ component!(Object => Map);
 pub struct InvalidPlacement;
 component!(InvalidPlacement => Marker);
 
+/// The number of ticks between entities passing through a door
+/// if its object doesn't provide one
+pub const DEFAULT_DOOR_FLOW: i32 = 6;
+/// How quickly the congestion of a door follows the number of
+/// entities waiting at it
+const CONGESTION_SMOOTHING: f32 = 0.05;
+
 /// Marks an object as a door that can be opened to pass through
 pub struct Door {
     /// If the door is open this is greater than
@@ -300,6 +403,18 @@ pub struct Door {
     pub was_open: bool,
     /// How long the door has been open for
     pub open_time: i32,
+    /// The number of ticks between entities passing through
+    /// the door. Zero allows any number to pass at once
+    pub flow: i32,
+    /// The number of ticks until the next entity may pass
+    pub pass_cooldown: i32,
+    /// The number of entities that waited at the door this tick
+    pub waiting: u32,
+    /// The average number of entities waiting at the door
+    pub congestion: f32,
+    /// The wall the door is placed on, found once an entity
+    /// walks through it
+    pub wall: Option<(Location, Direction)>,
 }
 component!(Door => Map);
 
@@ -310,6 +425,11 @@ impl Door {
             open: if open { 20 } else { 0 },
             was_open: open,
             open_time: 0,
+            flow: DEFAULT_DOOR_FLOW,
+            pass_cooldown: 0,
+            waiting: 0,
+            congestion: 0.0,
+            wall: None,
         }
     }
 
@@ -320,6 +440,81 @@ impl Door {
     pub fn open(&mut self) {
         self.open = 20;
     }
+
+    /// Attempts to pass through the door, returns false if
+    /// another entity passed through too recently
+    pub fn try_pass(&mut self) -> bool {
+        if self.pass_cooldown > 0 {
+            false
+        } else {
+            self.pass_cooldown = self.flow;
+            true
+        }
+    }
+
+    /// Updates the door's flow state, should be called once
+    /// per a tick after entities have tried to pass
+    pub fn tick_flow(&mut self) {
+        if self.pass_cooldown > 0 {
+            self.pass_cooldown -= 1;
+        }
+        self.congestion += (self.waiting as f32 - self.congestion) * CONGESTION_SMOOTHING;
+        self.waiting = 0;
+    }
+}
+
+/// The animations an object can play when triggered
+pub struct ObjectTriggers {
+    /// The object's triggers, sorted by name
+    pub triggers: common::AnimationTriggers,
+}
+component!(ObjectTriggers => Map);
+
+impl ObjectTriggers {
+    /// Returns the named trigger if the object has it
+    pub fn get(&self, name: &str) -> Option<&common::AnimationTrigger> {
+        common::trigger_index(&self.triggers, name)
+            .and_then(|idx| self.triggers.get(idx))
+    }
+}
+
+/// Marks an object as needing to play one of its triggers.
+///
+/// Removed once the animation has been queued
+pub struct TriggeredAnimation {
+    /// The index of the trigger in the object's `ObjectTriggers`
+    pub trigger: usize,
+}
+component!(TriggeredAnimation => Map);
+
+/// The last time the entity interacted with an object in a
+/// way that triggers one of the object's animations. Use
+/// `interact` to trigger a new interaction.
+#[derive(Debug, Clone, Copy)]
+pub struct Interacted {
+    /// Changed every time the entity interacts with an object
+    /// so that clients can tell interactions apart
+    pub id: u8,
+    /// The room the object belongs to
+    pub room_id: room::Id,
+    /// The index of the object in the room
+    pub object: usize,
+    /// The index of the trigger in the object's `ObjectTriggers`
+    pub trigger: usize,
+}
+component!(Interacted => Map);
+
+/// Records that the entity interacted with the object causing
+/// it to play the trigger's animations on clients.
+pub fn interact(entities: &mut ecs::Container, e: ecs::Entity, room_id: room::Id, object: usize, trigger: usize) {
+    let id = entities.get_component::<Interacted>(e)
+        .map_or(0, |v| v.id.wrapping_add(1));
+    entities.add_component(e, Interacted {
+        id,
+        room_id,
+        object,
+        trigger,
+    });
 }
 
 /// Marks an object as being alive (with walking/idle/etc
@@ -607,6 +802,8 @@ pub enum Controller {
     Room(RoomId),
     /// Free roam script
     FreeRoam,
+    /// An entity evacuating from a fire
+    Evacuate,
     /// A quitting entity
     Quit,
 }
@@ -710,6 +907,16 @@ pub enum Grade {
 }
 
 impl Grade {
+    /// Every grade from best to worst
+    pub const ALL: [Grade; 6] = [Grade::A, Grade::B, Grade::C, Grade::D, Grade::E, Grade::F];
+
+    /// Parses a grade from its displayable form
+    pub fn from_str(val: &str) -> Option<Grade> {
+        Grade::ALL.iter()
+            .cloned()
+            .find(|v| v.as_str() == val)
+    }
+
     /// Returns a displayable string of this grade
     pub fn as_str(self) -> &'static str {
         match self {
@@ -733,6 +940,11 @@ impl Grade {
             Grade::F => 5,
         }
     }
+
+    /// Returns whether the grade is a passing grade
+    pub fn is_passing(self) -> bool {
+        self <= Grade::D
+    }
 }
 
 /// Script properties for an entity not attached to a room
@@ -821,4 +1033,48 @@ pub struct Money {
     /// The amount of money the entity currently has
     pub money: UniDollar,
 }
-component!(Money => Vec);
\ No newline at end of file
+component!(Money => Vec);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn teleport_cancels_movement() {
+        let mut c = ecs::Container::new();
+        register_components(&mut c);
+        let e = c.new_entity();
+        c.add_component(e, Position { x: 1.0, y: 0.0, z: 1.0 });
+        c.add_component(e, pathfind::Target::new(10.0, 10.0));
+
+        teleport(&mut c, e, 5.0, 6.0);
+        {
+            let pos = c.get_component::<Position>(e).expect("Missing position");
+            assert_eq!((pos.x, pos.z), (5.0, 6.0));
+        }
+        assert!(c.get_component::<pathfind::Target>(e).is_none());
+        assert!(c.get_component::<Teleported>(e).map_or(false, |v| v.is_recent()));
+        let first = c.get_component::<Teleported>(e).map(|v| v.id);
+
+        // Each teleport must be distinguishable from the last
+        teleport(&mut c, e, 5.0, 6.0);
+        assert_ne!(c.get_component::<Teleported>(e).map(|v| v.id), first);
+    }
+
+    #[test]
+    fn interactions_are_distinct() {
+        let mut c = ecs::Container::new();
+        register_components(&mut c);
+        let e = c.new_entity();
+
+        interact(&mut c, e, room::Id(1), 2, 0);
+        let first = *c.get_component::<Interacted>(e).expect("Missing interaction");
+        assert_eq!((first.room_id, first.object, first.trigger), (room::Id(1), 2, 0));
+
+        // Using the same object again must still be seen as a
+        // new interaction
+        interact(&mut c, e, room::Id(1), 2, 0);
+        let second = *c.get_component::<Interacted>(e).expect("Missing interaction");
+        assert_ne!(first.id, second.id);
+    }
+}
@@ -2,6 +2,8 @@
 
 use std::cmp;
 use std::time;
+use std::rc::Rc;
+use std::cell::RefCell;
 use std::sync::{Arc, Weak, Mutex};
 use std::collections::VecDeque;
 
@@ -10,7 +12,7 @@ use png;
 
 use crate::ecs::{self as ecs, Write, Read, closure_system};
 use crate::level;
-use crate::util::{Location, FNVMap, FNVSet, Direction};
+use crate::util::{Location, FNVMap, FNVSet, Direction, ALL_DIRECTIONS};
 use super::*;
 
 /// Registers components required by this module
@@ -34,6 +36,12 @@ pub fn register_systems(sys: &mut ecs::Systems) {
 pub struct Pathfinder {
     requests: VecDeque<Weak<PathJob>>,
     limit: time::Duration,
+    congestion: Congestion,
+    /// Areas of the level edited since `unstuck_entity` last ran
+    edited: Rc<RefCell<Vec<Bound>>>,
+    /// Areas recently edited that entities may be stuck in and
+    /// the number of ticks left to check them for
+    recent_edits: Vec<(Bound, u32)>,
 }
 component!(Pathfinder => mut World);
 
@@ -45,6 +53,53 @@ struct PathJob {
     _required: bool,
 }
 
+/// The extra cost of a door per an entity waiting at it
+const CONGESTION_COST: f32 = 30.0;
+/// The number of ticks an edited area is checked for stuck
+/// entities for
+const EDIT_CHECK_TICKS: u32 = 40;
+
+/// The number of entities queueing at each door that
+/// paths try to avoid
+#[derive(Default, Clone)]
+pub struct Congestion {
+    doors: FNVMap<(Location, Direction), f32>,
+}
+
+impl Congestion {
+    /// Sets the congestion of the door on the passed wall
+    pub fn set(&mut self, loc: Location, dir: Direction, congestion: f32) {
+        // Stored from both sides so either can look it up
+        self.doors.insert((loc, dir), congestion);
+        self.doors.insert((loc.shift(dir), dir.reverse()), congestion);
+    }
+
+    /// Returns the congestion of the door on the passed wall
+    pub fn get(&self, loc: Location, dir: Direction) -> f32 {
+        self.doors.get(&(loc, dir)).cloned().unwrap_or(0.0)
+    }
+
+    /// Returns the congestion of the busiest door
+    pub fn max(&self) -> f32 {
+        self.doors.values().cloned().fold(0.0, f32::max)
+    }
+
+    /// Returns the extra movement cost of passing through
+    /// the door on the passed wall
+    fn cost(&self, loc: Location, dir: Direction) -> i32 {
+        (self.get(loc, dir) * CONGESTION_COST) as i32
+    }
+
+    /// Returns the extra movement cost of passing through any
+    /// of the doors around the tile
+    fn tile_cost(&self, loc: Location) -> i32 {
+        ALL_DIRECTIONS.iter()
+            .map(|v| self.cost(loc, *v))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
 /// The state of a path
 pub enum PathResult {
     /// The path is completed and ready for use
@@ -62,9 +117,48 @@ impl Pathfinder {
         Pathfinder {
             requests: VecDeque::new(),
             limit,
+            congestion: Congestion::default(),
+            edited: Rc::new(RefCell::new(vec![])),
+            recent_edits: vec![],
         }
     }
 
+    /// Watches the level for edits that could leave entities
+    /// stuck inside walls or objects.
+    ///
+    /// The whole level is checked once to begin with in case
+    /// it was loaded with stuck entities.
+    pub fn observe(&mut self, level: &level::Level) {
+        self.edited.borrow_mut().push(level.level_bounds);
+        let edited = self.edited.clone();
+        level.on_change(
+            level::ChangeKind::ROOM_PLACED
+                | level::ChangeKind::ROOM_REMOVED
+                | level::ChangeKind::WALL_CHANGED
+                | level::ChangeKind::OBJECT_PLACED,
+            move |change| {
+                // Walls on the edge of the area can block
+                // the tiles just outside it as well
+                let area = change.area();
+                edited.borrow_mut().push(Bound::new(
+                    Location::new(area.min.x - 1, area.min.y - 1),
+                    Location::new(area.max.x + 1, area.max.y + 1),
+                ));
+            }
+        );
+    }
+
+    /// Returns the congestion of doors used when creating paths
+    pub fn congestion(&self) -> &Congestion {
+        &self.congestion
+    }
+
+    /// Replaces the congestion of doors used when creating
+    /// paths from now on
+    pub fn set_congestion(&mut self, congestion: Congestion) {
+        self.congestion = congestion;
+    }
+
     /// Requests a path from the start position to the end position
     pub fn create_path(&mut self, start: (f32, f32), end: (f32, f32), required: bool) -> PathRequest {
         let req = Arc::new(PathJob {
@@ -100,6 +194,7 @@ pub fn tick_pathfinder(
 
     let start = time::Instant::now();
 
+    let congestion = &pathfinder.congestion;
     while start.elapsed() < pathfinder.limit && !pathfinder.requests.is_empty() {
         pathfinder.requests.par_iter_mut()
             .with_min_len(1)
@@ -108,7 +203,7 @@ pub fn tick_pathfinder(
             .filter_map(|v| v.upgrade())
             .for_each(|job| {
                 let mut path = assume!(log.log, job.path.lock());
-                *path = create_path(&log.log, tiles, rooms, job.start, job.end, job._required);
+                *path = create_path(&log.log, tiles, rooms, congestion, job.start, job.end, job._required);
             });
         let len = pathfinder.requests.len();
         pathfinder.requests.drain(..min(len, rayon::current_num_threads()));
@@ -142,26 +237,53 @@ closure_system!(fn unstuck_entity(
     position: Read<Position>,
     mut target_pos: Write<TargetPosition>,
     frozen: Read<Frozen>,
-    living: Read<Living>
+    living: Read<Living>,
+    mut pathfinder: Write<Pathfinder>
 ) {
     let world = Container::WORLD;
     let log = log.get_component(Container::WORLD).expect("Missing logger");
     let tiles = assume!(log.log, tiles.get_component(world));
     let rooms = assume!(log.log, rooms.get_component(world));
+    let pathfinder = assume!(log.log, pathfinder.get_component_mut(world));
 
-    for (e, pos) in em.group_mask(&position, |m| m
-        .and(&living)
-        .and_not(&frozen)
-        .and_not(&target_pos)
-    ) {
-        if !level::can_visit(tiles, rooms, (pos.x * 4.0) as usize, (pos.z * 4.0) as usize) {
-            target_pos.add_component(e, TargetPosition {
-                x: pos.x,
-                y: pos.y,
-                z: pos.z - 0.2,
-                ticks: 5.0
-            });
+    // Entities can only become stuck when the level changes
+    // around them so only the edited areas are checked
+    let edited = pathfinder.edited.borrow_mut().drain(..).collect::<Vec<_>>();
+    pathfinder.recent_edits.extend(edited.into_iter().map(|v| (v, EDIT_CHECK_TICKS)));
+    if pathfinder.recent_edits.is_empty() {
+        return;
+    }
+
+    // Areas containing stuck entities that can't be moved yet
+    // are kept until they can be
+    let mut waiting = vec![false; pathfinder.recent_edits.len()];
+    for (e, pos) in em.group_mask(&position, |m| m.and(&living)) {
+        let loc = Location::new(pos.x as i32, pos.z as i32);
+        let area = if let Some(idx) = pathfinder.recent_edits.iter().position(|v| v.0.in_bounds(loc)) {
+            idx
+        } else {
+            continue;
+        };
+        if level::can_visit(tiles, rooms, (pos.x * 4.0) as usize, (pos.z * 4.0) as usize) {
+            continue;
         }
+        if frozen.get_component(e).is_some() || target_pos.get_component(e).is_some() {
+            waiting[area] = true;
+            continue;
+        }
+        target_pos.add_component(e, TargetPosition {
+            x: pos.x,
+            y: pos.y,
+            z: pos.z - 0.2,
+            ticks: 5.0
+        });
+        waiting[area] = true;
+    }
+
+    let mut waiting = waiting.into_iter();
+    pathfinder.recent_edits.retain(|v| waiting.next().unwrap_or(false) || v.1 > 0);
+    for edit in &mut pathfinder.recent_edits {
+        edit.1 = edit.1.saturating_sub(1);
     }
 });
 
@@ -180,15 +302,21 @@ pub fn travel_path(
     door: &mut Write<Door>,
     target: &mut Write<Target>,
     adjust: &Read<LagMovementAdjust>,
-
+    living: &Read<Living>,
+    steering: &Read<steering::Steering>,
 ) {
     let world = Container::WORLD;
     let log = log.get_component(Container::WORLD).expect("Missing logger");
     let tiles = assume!(log.log, tiles.get_component(world));
     let rooms = assume!(log.log, rooms.get_component(world));
+    let agents = steering::Agents::collect(em, living, steering, position);
 
     'entities:
     for (e, (pos, speed))  in em.group_mask((position, speed), |m| m.and(info)) {
+        // Moved by `lod` instead
+        if info.get_component(e).map_or(false, |v| v.simplified) {
+            continue;
+        }
         // Check if ready for another target
         let remove = if target_pos.get_component(e).is_none() {
             let info = assume!(log.log, info.get_component_mut(e));
@@ -259,7 +387,11 @@ pub fn travel_path(
                             if let Some(door_e) = door_e {
                                 let door = assume!(log.log, door.get_component_mut(door_e));
                                 door.open();
-                                if door.open_time < 30 {
+                                door.wall = Some((t_pos, dir));
+                                // Wait for the door to open and then for
+                                // a turn to pass through it
+                                if door.open_time < 30 || !door.try_pass() {
+                                    door.waiting += 1;
                                     if info.waiting_for_door.is_none() {
                                         target_rotation.add_component(e, TargetRotation {
                                             rotation: Angle::new((pos.x - next.x).atan2(pos.z - next.z)),
@@ -291,15 +423,22 @@ pub fn travel_path(
                 } else { false };
 
                 if !remove {
+                    // Only steer between the nodes of the path so that the
+                    // entity still ends up exactly where it was sent
+                    let (x, z) = if info.nodes.len() > 1 {
+                        agents.steer(tiles, rooms, e, steering::Steering::radius_of(steering, e), (next.x, next.z))
+                    } else {
+                        (next.x, next.z)
+                    };
                     speed.speed = speed.base_speed * adjust;
                     target_pos.add_component(e, TargetPosition {
-                        x: next.x,
+                        x,
                         y: 0.0,
-                        z: next.z,
+                        z,
                         ticks: ((20.0 / 4.0) * f64::from(next.time)) / f64::from(speed.base_speed * adjust),
                     });
                     target_rotation.add_component(e, TargetRotation {
-                        rotation: Angle::new((pos.x - next.x).atan2(pos.z - next.z)),
+                        rotation: Angle::new((pos.x - x).atan2(pos.z - z)),
                         ticks: 4.0,
                     });
 
@@ -349,9 +488,12 @@ closure_system!(fn compute_path(
     position: Read<Position>,
     mut target: Write<Target>,
     mut target_f: Write<TargetFacing>,
-    mut info: Write<PathInfo>
+    mut info: Write<PathInfo>,
+    simplified: Read<lod::Simplified>
 ) {
-    let mask = target.mask().and(&position);
+    // Simplified entities skip pathfinding, `lod` handles
+    // their targets instead
+    let mask = target.mask().and(&position).and_not(&simplified);
     let world = Container::WORLD;
     let log = log.get_component(Container::WORLD).expect("Missing logger");
     let tiles = assume!(log.log, tiles.get_component(world));
@@ -438,7 +580,10 @@ impl PathPos {
     }
 }
 
-fn compute_cost(tiles: &level::LevelTiles, rooms: &level::LevelRooms, pos: PathPos, dir: PathDir) -> Option<i32> {
+fn compute_cost(
+    tiles: &level::LevelTiles, rooms: &level::LevelRooms, congestion: &Congestion,
+    pos: PathPos, dir: PathDir,
+) -> Option<i32> {
     if level::can_visit(tiles, rooms, pos.x as usize, pos.y as usize) {
         let extra_cost = if dir.standard() == None {
             let (ox, oy) = dir.offset();
@@ -452,22 +597,26 @@ fn compute_cost(tiles: &level::LevelTiles, rooms: &level::LevelRooms, pos: PathP
         // Get the cost
         let rx = pos.x & 0b11;
         let ry = pos.y & 0b11;
-        let flag = match (dir, rx, ry) {
+        let wall = match (dir, rx, ry) {
             (PathDir::North, _, 0)
             | (PathDir::South, _, 3)
             | (PathDir::East, 0, _)
-            | (PathDir::West, 3, _) => tiles.get_wall_info(pos.loc(), dir.standard().expect("Invalid direction in compute cost")).map(|v| v.flag),
+            | (PathDir::West, 3, _) => Some(dir.standard().expect("Invalid direction in compute cost")),
 
-            (PathDir::NorthEast, _, 0) | (PathDir::NorthWest, _, 0) => tiles.get_wall_info(pos.loc(), Direction::North).map(|v| v.flag),
-            (PathDir::SouthEast, _, 0) | (PathDir::SouthWest, _, 0) => tiles.get_wall_info(pos.loc(), Direction::South).map(|v| v.flag),
+            (PathDir::NorthEast, _, 0) | (PathDir::NorthWest, _, 0) => Some(Direction::North),
+            (PathDir::SouthEast, _, 0) | (PathDir::SouthWest, _, 0) => Some(Direction::South),
 
-            (PathDir::NorthEast, 0, _) | (PathDir::SouthEast, 0, _) => tiles.get_wall_info(pos.loc(), Direction::East).map(|v| v.flag),
-            (PathDir::NorthWest, 0, _) | (PathDir::SouthWest, 0, _) => tiles.get_wall_info(pos.loc(), Direction::West).map(|v| v.flag),
+            (PathDir::NorthEast, 0, _) | (PathDir::SouthEast, 0, _) => Some(Direction::East),
+            (PathDir::NorthWest, 0, _) | (PathDir::SouthWest, 0, _) => Some(Direction::West),
             _ => None,
         };
+        let flag = wall.and_then(|wall| tiles.get_wall_info(pos.loc(), wall).map(|v| (wall, v.flag)));
 
         match flag {
-            Some(level::TileWallFlag::Door) => Some(tiles.get_tile(pos.loc()).movement_cost + 40 + extra_cost),
+            Some((wall, level::TileWallFlag::Door)) => Some(
+                tiles.get_tile(pos.loc()).movement_cost + 40 + extra_cost
+                    + congestion.cost(pos.loc(), wall)
+            ),
             _ => Some({
                 let tile = tiles.get_tile(pos.loc());
                 if rx == 0 || rx == 3 || ry == 0 || ry == 3 {
@@ -900,7 +1049,7 @@ fn create_rough_path(
 /// the search space is optimizated.
 fn create_path(
     log: &Logger,
-    tiles: &level::LevelTiles, rooms: &level::LevelRooms,
+    tiles: &level::LevelTiles, rooms: &level::LevelRooms, congestion: &Congestion,
     start: (f32, f32), end: (f32, f32), required: bool
 ) -> PathResult {
     use std::collections::BinaryHeap;
@@ -969,7 +1118,7 @@ fn create_path(
                 continue;
             }
 
-            let cost = c_cost + if let Some(cost) = compute_cost(tiles, rooms, current.pos, *dir) {
+            let cost = c_cost + if let Some(cost) = compute_cost(tiles, rooms, congestion, current.pos, *dir) {
                 cost
             } else { continue };
 
@@ -999,6 +1148,7 @@ fn create_path(
             }],
             waiting_for_door: None,
             end_rotation: None,
+            simplified: false,
         };
 
         let mut cur = end_info;
@@ -1188,10 +1338,48 @@ pub struct PathInfo {
     waiting_for_door: Option<(f32, f32)>,
     /// The direction to face at the end of the path
     pub end_rotation: Option<Angle>,
+    /// Whether this path skips straight to the end for an
+    /// entity that isn't near any player. See `lod`
+    simplified: bool,
 }
 component!(PathInfo => Vec);
 
 impl PathInfo {
+    /// Creates a path that isn't travelled by `travel_path`.
+    /// Instead the entity is moved to the end by `lod` once
+    /// `time` has counted down to zero
+    pub(crate) fn simplified(end: (f32, f32), time: f32, end_rotation: Option<Angle>) -> PathInfo {
+        PathInfo {
+            nodes: vec![Node {
+                dir: None,
+                x: end.0,
+                z: end.1,
+                time,
+            }],
+            time,
+            waiting_for_door: None,
+            end_rotation,
+            simplified: true,
+        }
+    }
+
+    /// Returns whether this path was created by `simplified`
+    pub fn is_simplified(&self) -> bool {
+        self.simplified
+    }
+
+    /// Returns the distance in tiles left to travel along the
+    /// path starting from the position
+    pub fn distance(&self, from: (f32, f32)) -> f32 {
+        let mut last = from;
+        let mut distance = 0.0;
+        for node in &self.nodes {
+            distance += ((node.x - last.0) * (node.x - last.0) + (node.z - last.1) * (node.z - last.1)).sqrt();
+            last = (node.x, node.z);
+        }
+        distance
+    }
+
     /// Returns whether this path is empty
     pub fn is_empty(&self) -> bool {
         self.nodes.is_empty()
@@ -1216,6 +1404,93 @@ impl PathInfo {
     pub fn is_moving(&self) -> bool {
         self.waiting_for_door.is_none()
     }
+
+    /// Returns the positions of the remaining nodes of the path
+    pub fn positions(&self) -> impl Iterator<Item=(f32, f32)> + '_ {
+        self.nodes.iter().map(|v| (v.x, v.z))
+    }
+}
+
+/// Pathfinding information about an area of the level used
+/// by the navigation debug overlay
+pub struct NavigationDebug {
+    /// Whether each collision cell of the area can't be visited,
+    /// `x + y * width * 4`
+    pub blocked: Vec<bool>,
+    /// The movement cost of each tile of the area including the
+    /// congestion of doors around it, `x + y * width`
+    pub costs: Vec<i32>,
+    /// The sections that overlap the area and which of their
+    /// neighbours they are connected to
+    pub links: Vec<SectionLink>,
+}
+
+/// The connections of a section of the rough path graph to
+/// the sections after it
+#[derive(Debug, Clone, Copy)]
+pub struct SectionLink {
+    /// The section's x position in sections
+    pub x: usize,
+    /// The section's y position in sections
+    pub y: usize,
+    /// Whether an edge of the section connects to the next
+    /// section along the x axis
+    pub next_x: bool,
+    /// Whether an edge of the section connects to the next
+    /// section along the y axis
+    pub next_y: bool,
+}
+
+/// Collects the pathfinding information for the area starting
+/// at the passed tile
+pub fn navigation_debug(
+    tiles: &level::LevelTiles, rooms: &level::LevelRooms, congestion: &Congestion,
+    x: usize, y: usize, width: usize, height: usize,
+) -> NavigationDebug {
+    let mut blocked = Vec::with_capacity(width * height * 16);
+    for cy in y * 4 .. (y + height) * 4 {
+        for cx in x * 4 .. (x + width) * 4 {
+            blocked.push(!level::can_visit(tiles, rooms, cx, cy));
+        }
+    }
+    let mut costs = Vec::with_capacity(width * height);
+    for ty in y .. y + height {
+        for tx in x .. x + width {
+            let loc = Location::new(tx as i32, ty as i32);
+            costs.push(tiles.get_tile(loc).movement_cost + congestion.tile_cost(loc));
+        }
+    }
+
+    // Mirrors the edge handling in `create_rough_path`
+    let sections_w = (tiles.width as usize + 3) / 4;
+    let sections_h = (tiles.height as usize + 3) / 4;
+    let is_linked = |sx: usize, sy: usize, edge: (usize, usize), osx: usize, osy: usize, other: (usize, usize)| {
+        let (edge, other) = match (level::edge_to_id(edge.0, edge.1), level::edge_to_id(other.0, other.1)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return false,
+        };
+        tiles.get_pathable_edges(sx, sy, edge) & (1 << edge) != 0
+            && tiles.get_pathable_edges(osx, osy, other) & (1 << other) != 0
+    };
+    let mut links = Vec::new();
+    for sy in y / 4 .. cmp::min((y + height + 3) / 4, sections_h) {
+        for sx in x / 4 .. cmp::min((x + width + 3) / 4, sections_w) {
+            links.push(SectionLink {
+                x: sx,
+                y: sy,
+                next_x: sx + 1 < sections_w
+                    && (0 .. 16).any(|i| is_linked(sx, sy, (15, i), sx + 1, sy, (0, i))),
+                next_y: sy + 1 < sections_h
+                    && (0 .. 16).any(|i| is_linked(sx, sy, (i, 15), sx, sy + 1, (i, 0))),
+            });
+        }
+    }
+
+    NavigationDebug {
+        blocked,
+        costs,
+        links,
+    }
 }
 
 #[derive(Debug, Clone)]
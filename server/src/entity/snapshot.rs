@@ -3,12 +3,12 @@
 
 use std::mem;
 use std::io;
-use std::sync::Arc;
+use std::sync::{Arc, mpsc};
+use std::thread;
 use std::rc::Rc;
 use std::cell::RefCell;
 
 use crate::ecs;
-use crate::network;
 use crate::network::packet;
 use crate::entity::{self, Emote};
 use crate::util::*;
@@ -33,8 +33,9 @@ type EntityList = Vec<Option<ecs::Entity>>;
 /// limit.
 pub struct Snapshots {
     log: Logger,
-    frames: Box<[Option<Snapshot>]>,
-    player_frames: FNVMap<player::Id, Box<[Option<PlayerSnapshot>]>>,
+    // Shared with the encoder whilst a frame is being
+    // encoded
+    history: Arc<History>,
     current_frame: u16, // 14 bit
 
     /// A network id to entity map
@@ -63,6 +64,19 @@ pub trait EntityMarker {
     fn mark_idle_choice(&mut self, entity: Entity, player_id: PlayerId, idx: usize);
     /// Marks the entity as having no idle choice
     fn clear_idle_choice(&mut self, entity: Entity, player_id: PlayerId, idx: usize);
+    /// Called once a new entity from the snapshot has been
+    /// created.
+    ///
+    /// `quiet` is set for entities the server flagged as recently
+    /// teleported and for those in the first frame received, these
+    /// shouldn't draw attention to themselves.
+    fn entity_spawned(&mut self, _entities: &mut ecs::Container, _entity: Entity, _quiet: bool) {}
+    /// Called when an entity is no longer in the snapshot before
+    /// it is removed.
+    ///
+    /// Returning true keeps the entity around leaving removing it
+    /// to the marker. `quiet` is the same as `entity_spawned`.
+    fn entity_despawned(&mut self, _entities: &mut ecs::Container, _entity: Entity, _quiet: bool) -> bool { false }
 }
 
 impl Snapshots {
@@ -91,21 +105,33 @@ impl Snapshots {
                 money: UniDollar(0),
                 rating: 0,
                 config: player::PlayerConfig::default(),
+                shared: player::SharedState::default(),
             }));
             player_frames.insert(*player, frames.into_boxed_slice());
         }
 
         Snapshots {
             log,
-            frames: frames.into_boxed_slice(),
+            history: Arc::new(History {
+                frames: frames.into_boxed_slice(),
+                player_frames,
+            }),
             current_frame: 0,
 
             entity_map: Rc::new(RefCell::new(vec![])),
             next_entity_id: 0,
-            player_frames,
         }
     }
 
+    /// Returns the history for modification.
+    ///
+    /// The encoder only keeps hold of the history whilst
+    /// encoding a frame which must be finished before the
+    /// next frame is captured.
+    fn history_mut<'a>(log: &Logger, history: &'a mut Arc<History>) -> &'a mut History {
+        assume!(log, Arc::get_mut(history))
+    }
+
     /// Returns the entity with the given network id (if any)
     pub fn get_entity_by_id(&self, network_id: u32) -> Option<ecs::Entity> {
         self.entity_map
@@ -132,8 +158,9 @@ impl Snapshots {
         let frame_id = self.next_frame_id();
 
         // Player information update
+        let history = Self::history_mut(&self.log, &mut self.history);
         for (id, player) in players {
-            let player_frames = assume!(self.log, self.player_frames.get_mut(id));
+            let player_frames = assume!(self.log, history.player_frames.get_mut(id));
 
             let snapshot = PlayerSnapshot {
                 frame_id,
@@ -141,6 +168,7 @@ impl Snapshots {
                 money: player.get_money(),
                 rating: player.get_rating(),
                 config: player.get_config(),
+                shared: player.get_shared(),
             };
             player_frames[frame_id as usize % HISTORY_MAX_SIZE] = Some(snapshot);
         }
@@ -162,7 +190,7 @@ impl Snapshots {
             }
         }
 
-        entities.with(|
+        let snapshot = entities.with(|
             em: ecs::EntityManager<'_>,
             living: ecs::Read<super::Living>,
             position: ecs::Read<super::Position>,
@@ -176,6 +204,9 @@ impl Snapshots {
             room_owned: ecs::Read<super::RoomOwned>,
             icon_emotes: ecs::Read<super::IconEmote>,
             tints: ecs::Read<super::Tints>,
+            teleported: ecs::Read<super::Teleported>,
+            interacted: ecs::Read<super::Interacted>,
+            appearance: ecs::Read<super::appearance::Appearance>,
             mut network_id: ecs::Write<NetworkId>,
             controlled: ecs::Read<Controlled>,
         |{
@@ -205,9 +236,10 @@ impl Snapshots {
                     // Target can either be the current position of the
                     // entity or the end of the path that has been computed.
                     // Clients will handle pathfinding themselves to save
-                    // on bandwidth.
+                    // on bandwidth. Simplified entities stay put until
+                    // they jump to the end of their path.
                     let target = path.get_component(e)
-                        .filter(|v| !v.is_empty())
+                        .filter(|v| !v.is_empty() && !v.is_simplified())
                         .map_or_else(|| {
                             let pos = assume!(self.log, position.get_component(e));
                             ETarget {
@@ -259,11 +291,29 @@ impl Snapshots {
                         .map_or_else(Vec::new, |v| v.icons.iter()
                             .map(|v| EEmote(v.0, v.1))
                             .collect());
+                    // Clients generate the tints of entities with an
+                    // appearance themselves from the seed
+                    let appearance = appearance.get_component(e).map(|v| v.seed);
                     let tints = tints.get_component(e)
+                        .filter(|_| appearance.is_none())
                         .map_or_else(Vec::new, |v| v.tints.iter()
                             .map(|v| EColor(v.0, v.1, v.2, v.3))
                             .collect());
 
+                    let teleport = teleported.get_component(e).map(|v| ETeleport {
+                        id: v.id,
+                        x: v.x,
+                        z: v.z,
+                        recent: v.is_recent(),
+                    });
+
+                    let interaction = interacted.get_component(e).map(|v| EInteraction {
+                        id: v.id,
+                        room_id: v.room_id,
+                        object: v.object as u16,
+                        trigger: v.trigger as u8,
+                    });
+
                     let selected = selected.get_component(e).map(|v| v.holder);
                     let living = assume!(self.log, living.get_component(e));
                     snapshot.entities.push(Some(EntitySnapshot {
@@ -277,221 +327,39 @@ impl Snapshots {
 
                         entity: e,
                         target,
+                        teleport,
+                        interaction,
                         selected,
                         room,
                         data,
                         idle,
                         emotes,
                         tints,
+                        appearance,
                     }));
                 } else {
                     snapshot.entities.push(None);
                 }
             }
 
-            // Store the captured frame in the history buffer.
-            // This will overwrite any old frame that was stored there.
-            self.frames[frame_id as usize % HISTORY_MAX_SIZE] = Some(snapshot);
+            snapshot
         });
-    }
-
-    fn write_player_state<W, S>(
-        log: &Logger,
-        player_frames: &FNVMap<player::Id, Box<[Option<PlayerSnapshot>]>>,
-        player_info: &NetworkedPlayer<S>,
-        current_frame: u16,
-        current: &mut bitio::Writer<W>
-    )
-        where W: io::Write,
-              S: network::Socket,
-    {
-        let player = assume!(log, player_frames.get(&assume!(log, player_info.uid)));
-        let player_base = player.get(player_info.player_state as usize % HISTORY_MAX_SIZE)
-            .and_then(|v| v.as_ref())
-            .filter(|v| v.frame_id == player_info.player_state);
-        let player_cur = assume!(log, player.get(current_frame as usize % HISTORY_MAX_SIZE)
-            .and_then(|v| v.as_ref()));
 
-        let _ = current.write_unsigned(u64::from(player_base.as_ref().map_or(INVALID_FRAME, |v| v.frame_id)), 14);
-        let _ = player_cur.encode(player_base, current);
+        // Store the captured frame in the history buffer.
+        // This will overwrite any old frame that was stored there.
+        let history = Self::history_mut(&self.log, &mut self.history);
+        history.frames[frame_id as usize % HISTORY_MAX_SIZE] = Some(snapshot);
     }
 
-    /// Creates (and splits) either a delta frame based on the
-    /// last ack'd frames per an entity.
-    pub(crate) fn create_delta<S>(&self, player: &NetworkedPlayer<S>) -> Vec<packet::EntityFrame>
-        where S: network::Socket,
-    {
-        let entity_state = &player.entity_state;
-        let entity_map: &EntityList = &*self.entity_map.borrow();
-        let mut packets = vec![];
-        let mut current = bitio::Writer::new(vec![]);
-        // Buffer used for writing entity data before writing
-        // to `current`. Cleared and reused for every entity.
-        let mut entity_data = bitio::Writer::new(vec![]);
-
-        // Used to force the first valid entity to
-        // cause the start of a packet (with headers).
-        // Also used to mark as being able to skip over
-        // inactive entities
-        let mut first = true;
-        // The starting point of the current packet's entities
-        let mut offset = 0;
-        // The number of entities in the current packet
-        let mut count = 0;
-        // The base frame of the current packet
-        let mut base_frame = INVALID_FRAME;
-        let current_frame = assume!(self.log, self.frames[self.current_frame as usize % HISTORY_MAX_SIZE].as_ref());
-        for (id, e) in entity_map.iter().enumerate() {
-            if first && e.is_none()
-                    && entity_state.entities.get(id)
-                        .cloned()
-                        .unwrap_or(INVALID_FRAME) == INVALID_FRAME
-            {
-                offset += 1;
-                continue;
-            }
-
-            let mut entity_frame = entity_state.entities.get(id)
-                .cloned()
-                .unwrap_or(INVALID_FRAME);
-            // If the frame in our history buffer isn't the entities last frame
-            // then the frame was dropped for a newer frame and the client is
-            // lagging behind. We treat this entity as never have been sync'd
-            // in the first place and start from fresh.
-            if self.frames[entity_frame as usize % HISTORY_MAX_SIZE].as_ref().map_or(true, |v| v.frame_id != entity_frame) {
-                entity_frame = INVALID_FRAME;
-            }
-
-            let same_entity = {
-                if entity_frame == INVALID_FRAME {
-                    false
-                } else {
-                    let old_frame = assume!(self.log, self.frames[entity_frame as usize % HISTORY_MAX_SIZE].as_ref());
-                    let oe = old_frame.entities[id].as_ref();
-                    let ne = current_frame.entities[id].as_ref();
-                    if let (Some(ne), Some(oe)) = (ne, oe) {
-                        ne.entity == oe.entity
-                    } else {
-                        false
-                    }
-                }
-            };
-
-            // Serialize the entity state based on the previous frame.
-
-            let state = match (entity_frame, *e) {
-                // Didn't exist before, exists now
-                (INVALID_FRAME, Some(_))                 => EntityStateFlag::Add,
-                // Didn't exist before and still doesn't
-                (INVALID_FRAME, None)                    => EntityStateFlag::Empty,
-                // Existed but now removed
-                (_            , None)                    => EntityStateFlag::Removed,
-                // Reused entity id
-                (_            , Some(_)) if !same_entity => EntityStateFlag::Add,
-                // Existed and still exists
-                (_            , Some(_))                 => EntityStateFlag::Update,
-            };
-            let _ = entity_data.write_unsigned(u64::from(state.as_u8()), 2);
-            match state {
-                EntityStateFlag::Add => {
-                    let e = assume!(self.log, current_frame.entities[id].as_ref());
-                    let _ = e.encode(None, &mut entity_data);
-                },
-                EntityStateFlag::Update => {
-                    let old_frame = assume!(self.log, self.frames[entity_frame as usize % HISTORY_MAX_SIZE].as_ref());
-                    let oe = assume!(self.log, old_frame.entities[id].as_ref());
-                    let ne = assume!(self.log, current_frame.entities[id].as_ref());
-                    let _ = ne.encode(Some(oe), &mut entity_data);
-                },
-                // No need to send any state for these as they don't/no longer
-                // exist.
-                EntityStateFlag::Removed | EntityStateFlag::Empty => {},
-            }
-
-            // - The first entity needs to set the header, no avoiding that
-            // - Also split if the entity has a different base from the last entity.
-            //   This can happen due to the size limit below.
-            // - The packet is limited to 1000 bytes in size. If writing this
-            //   entity would set us over this limit then when split.
-            //   The system should be able to handle this.
-            // - The number of entities would overflow the u8 count
-            if first || entity_frame != base_frame || count >= 255 || entity_data.bit_len() + current.bit_len() > 1000 * 8 {
-                // Finish and write out the previous packet (if there was
-                // one).
-                if !first {
-                    let mut data = assume!(self.log, mem::replace(&mut current, bitio::Writer::new(vec![]))
-                        .finish());
-                    // Set the entity count in the space we
-                    // reserved.
-                    data[6] = count as u8;
-                    packets.push(packet::EntityFrame {
-                        data: packet::Raw(data),
-                    });
-                    offset += count;
-                    count = 0;
-                }
-
-                // Update the base frame. Follows the same
-                // rules as `entity_frame` above.
-                base_frame = entity_state.entities.get(id)
-                    .cloned()
-                    .unwrap_or(INVALID_FRAME);
-                if self.frames[base_frame as usize % HISTORY_MAX_SIZE].as_ref().map_or(true, |v| v.frame_id != base_frame) {
-                    base_frame = INVALID_FRAME;
-                }
-
-                // Write the header
-                let _ = current.write_unsigned(u64::from(self.current_frame), 14);
-                let _ = current.write_unsigned(u64::from(base_frame), 14);
-                let _ = current.write_unsigned(offset as u64, 20);
-                let _ = current.write_unsigned(0, 8); // Entity count placeholder
-
-                // Player state is special as the player isn't
-                // an entity (and even if it was it doesn't require
-                // the same information as other entities).
-                // We packet this into the first entity state
-                // packet instead of requiring another packet
-                // just for this
-                if first {
-                    let _ = current.write_bool(true);
-                    Self::write_player_state(&self.log, &self.player_frames, player, self.current_frame, &mut current);
-                } else {
-                    let _ = current.write_bool(false);
-                }
-
-                first = false;
-            }
-
-            let _ = entity_data.copy_into(&mut current);
-            entity_data.clear();
-            count += 1;
+    /// Prepares the current frame to be encoded for the
+    /// targets.
+    pub(crate) fn pending_frame<I>(&self, targets: Vec<DeltaTarget<I>>) -> PendingFrame<I> {
+        PendingFrame {
+            log: self.log.clone(),
+            history: self.history.clone(),
+            current_frame: self.current_frame,
+            targets,
         }
-
-        // Finish and write out the last packet (if there was
-        // one).
-        if count > 0 || first {
-            if first {
-                let _ = current.write_unsigned(u64::from(self.current_frame), 14);
-                let _ = current.write_unsigned(u64::from(base_frame), 14);
-                let _ = current.write_unsigned(offset as u64, 20);
-                let _ = current.write_unsigned(0, 8);
-                if first {
-                    let _ = current.write_bool(true);
-                    Self::write_player_state(&self.log, &self.player_frames, player, self.current_frame, &mut current);
-                } else {
-                    let _ = current.write_bool(false);
-                }
-            }
-            let mut data = assume!(self.log, current.finish());
-            // Set the entity count in the space we
-            // reserved.
-            data[6] = count as u8;
-            packets.push(packet::EntityFrame {
-                data: packet::Raw(data),
-            });
-        }
-
-        packets
     }
 
     /// Updates the entities using the passed frame (as long as its
@@ -517,6 +385,7 @@ impl Snapshots {
     {
         use std::cmp::max;
         let entity_map: &mut EntityList = &mut *self.entity_map.borrow_mut();
+        let history = Self::history_mut(&self.log, &mut self.history);
 
         let mut r = bitio::Reader::new(io::Cursor::new(frame.data.0));
         // Packet header
@@ -528,7 +397,7 @@ impl Snapshots {
         // Check for player state
         let player_ack = if r.read_bool()? {
             let player_base = r.read_unsigned(14)? as u16;
-            let player_frames = assume!(self.log, self.player_frames.get_mut(&player::Id(0)));
+            let player_frames = assume!(self.log, history.player_frames.get_mut(&player::Id(0)));
 
             if player_base == INVALID_FRAME || player_frames[player_base as usize % HISTORY_MAX_SIZE]
                 .as_ref()
@@ -552,6 +421,7 @@ impl Snapshots {
                     player.change_money(player_frame.money - cur_money);
                     player.set_rating(player_frame.rating);
                     player.set_config(player_frame.config.clone());
+                    player.set_shared(player_frame.shared.clone());
                     *day_tick = player_frame.day_tick;
                 }
                 player_frames[frame as usize % HISTORY_MAX_SIZE] = Some(player_frame);
@@ -564,7 +434,7 @@ impl Snapshots {
         } else { None };
 
         // Make sure we can actually work with this frame.
-        if base_frame != INVALID_FRAME && self.frames[base_frame as usize % HISTORY_MAX_SIZE]
+        if base_frame != INVALID_FRAME && history.frames[base_frame as usize % HISTORY_MAX_SIZE]
                 .as_ref()
                 .map_or(true, |v| v.frame_id != base_frame)
         {
@@ -572,16 +442,16 @@ impl Snapshots {
         }
 
         // If there isn't already a frame started, create one
-        if self.frames[frame as usize % HISTORY_MAX_SIZE]
+        if history.frames[frame as usize % HISTORY_MAX_SIZE]
                 .as_ref()
                 .map_or(true, |v| v.frame_id != frame) {
             // Don't replace newer frames with an old one. Bail out
-            if let Some(frm) = self.frames[frame as usize % HISTORY_MAX_SIZE].as_ref() {
+            if let Some(frm) = history.frames[frame as usize % HISTORY_MAX_SIZE].as_ref() {
                 if !is_previous_frame(frame, frm.frame_id) {
                     bail!("Old frame");
                 }
             }
-            self.frames[frame as usize % HISTORY_MAX_SIZE] = Some(Snapshot {
+            history.frames[frame as usize % HISTORY_MAX_SIZE] = Some(Snapshot {
                 frame_id: frame,
                 entities: vec![],
             })
@@ -597,13 +467,13 @@ impl Snapshots {
         // This is needed to because one is borrowed
         // mutably which would prevent any other borrows.
         let (snapshot, base_snap) = if f_idx < b_idx {
-            let (low, high) = self.frames.split_at_mut(b_idx);
+            let (low, high) = history.frames.split_at_mut(b_idx);
             (
                 assume!(self.log, low[f_idx].as_mut()),
                 assume!(self.log, high[0].as_mut()),
             )
         } else {
-            let (low, high) = self.frames.split_at_mut(f_idx);
+            let (low, high) = history.frames.split_at_mut(f_idx);
             (
                 assume!(self.log, high[0].as_mut()),
                 assume!(self.log, low[b_idx].as_mut()),
@@ -633,7 +503,8 @@ impl Snapshots {
                 EntityStateFlag::Update => {
                     let old = assume!(self.log, base_snap.entities[id].as_ref());
                     let en = EntitySnapshot::decode(Some(old), &mut r)?;
-                    update_target = en.target.x != old.target.x
+                    update_target = en.teleport != old.teleport
+                        || en.target.x != old.target.x
                         || en.target.z != old.target.z
                         || (en.target.time != old.target.time && en.target.time != 0.0);
                     snapshot.entities[id] = Some(en);
@@ -683,6 +554,24 @@ impl Snapshots {
                     if let (Some(rot), Some(face)) = (entities.get_component_mut::<entity::Rotation>(new_entity), e.target.facing) {
                         rot.rotation = Angle::new(face.0);
                     }
+                    if let Some(tp) = e.teleport.as_ref() {
+                        entities.add_component(new_entity, entity::Teleported {
+                            id: tp.id,
+                            x: tp.x,
+                            z: tp.z,
+                            ticks: if tp.recent { 0 } else { u8::MAX },
+                        });
+                    }
+                    if let Some(int) = e.interaction.as_ref() {
+                        // Only record the interaction, it most likely
+                        // happened before the entity was seen
+                        entities.add_component(new_entity, entity::Interacted {
+                            id: int.id,
+                            room_id: int.room_id,
+                            object: int.object as usize,
+                            trigger: int.trigger as usize,
+                        });
+                    }
                     if let Some(owner) = e.owner {
                         entities.add_component(new_entity, entity::Owned {
                             player_id: owner,
@@ -737,6 +626,12 @@ impl Snapshots {
                                 .collect(),
                         });
                     }
+                    if let Some(seed) = e.appearance {
+                        ty.set_appearance(entities, new_entity, e.info.variant as usize, seed);
+                    }
+                    let quiet = base_frame == INVALID_FRAME
+                        || e.teleport.as_ref().map_or(false, |v| v.recent);
+                    marker.entity_spawned(entities, new_entity, quiet);
                 },
                 EntityStateFlag::Update => {
                     let e = assume!(self.log, snapshot.entities[id].as_ref());
@@ -749,6 +644,59 @@ impl Snapshots {
                         entity_map[id] = None;
                         continue;
                     }
+                    // Frames are based on the last acked frame so the same
+                    // teleport can be seen more than once, only the first
+                    // should move the entity
+                    let teleport = e.teleport.as_ref()
+                        .filter(|_| e.selected != Some(player.get_uid()))
+                        .filter(|tp| entities.get_component::<entity::Teleported>(entity).map_or(true, |v| v.id != tp.id));
+                    if let Some(tp) = teleport {
+                        // Jump to where the server placed the entity, any
+                        // movement since is handled like normal below
+                        {
+                            let pos = assume!(self.log, entities.get_component_mut::<entity::Position>(entity));
+                            pos.x = tp.x;
+                            pos.z = tp.z;
+                        }
+                        entities.add_component(entity, entity::Teleported {
+                            id: tp.id,
+                            x: tp.x,
+                            z: tp.z,
+                            ticks: if tp.recent { 0 } else { u8::MAX },
+                        });
+                        entities.remove_component::<entity::TargetPosition>(entity);
+                        entities.remove_component::<entity::CatchupBuffer>(entity);
+                        entities.remove_component::<entity::pathfind::Target>(entity);
+                        entities.remove_component::<entity::pathfind::TargetTime>(entity);
+                        entities.remove_component::<entity::pathfind::PathInfo>(entity);
+                        if let (Some(rot), Some(face)) = (entities.get_component_mut::<entity::Rotation>(entity), e.target.facing) {
+                            rot.rotation = Angle::new(face.0);
+                        }
+                    }
+                    // Like teleports the same interaction can be seen
+                    // more than once
+                    let interaction = e.interaction.as_ref()
+                        .filter(|int| entities.get_component::<entity::Interacted>(entity).map_or(true, |v| v.id != int.id));
+                    if let Some(int) = interaction {
+                        entities.add_component(entity, entity::Interacted {
+                            id: int.id,
+                            room_id: int.room_id,
+                            object: int.object as usize,
+                            trigger: int.trigger as usize,
+                        });
+                        let objects = level.try_room_info(int.room_id)
+                            .and_then(|room| room.objects.get(int.object as usize)
+                                .and_then(|v| v.as_ref())
+                                .map(|v| v.1.get_entities()))
+                            .unwrap_or_default();
+                        for obj in objects {
+                            if entities.get_component::<entity::ObjectTriggers>(obj).is_some() {
+                                entities.add_component(obj, entity::TriggeredAnimation {
+                                    trigger: int.trigger as usize,
+                                });
+                            }
+                        }
+                    }
                     if update_target && e.selected != Some(player.get_uid()) {
                         let keep_pos = {
                             let pos = assume!(self.log, entities.get_component_mut::<entity::Position>(entity));
@@ -915,7 +863,14 @@ impl Snapshots {
                         continue
                     };
                     if entities.is_valid(entity) {
-                        entities.remove_entity(entity);
+                        // The entity's last state is still in the base frame
+                        let quiet = base_snap.entities.get(id)
+                            .and_then(|v| v.as_ref())
+                            .and_then(|v| v.teleport.as_ref())
+                            .map_or(false, |v| v.recent);
+                        if !marker.entity_despawned(entities, entity, quiet) {
+                            entities.remove_entity(entity);
+                        }
                     }
                 },
                 EntityStateFlag::Empty => {},
@@ -940,6 +895,301 @@ impl Snapshots {
     }
 }
 
+/// The state of a player required to encode a frame
+/// for them
+pub(crate) struct DeltaTarget<I> {
+    /// The connection the frame will be sent to
+    pub id: I,
+    /// The player the connection belongs to
+    pub uid: player::Id,
+    /// The last frames acknowledged by the player
+    pub entity_state: EntitySnapshotState,
+    /// The last player state frame acknowledged by the player
+    pub player_state: u16,
+}
+
+/// A captured frame waiting to be encoded for each
+/// connected player.
+///
+/// Holds its own reference to the history so that it can
+/// be encoded on another thread whilst the next tick runs.
+pub(crate) struct PendingFrame<I> {
+    log: Logger,
+    history: Arc<History>,
+    current_frame: u16,
+    targets: Vec<DeltaTarget<I>>,
+}
+
+impl <I> PendingFrame<I> {
+    /// Encodes the frame for every target returning the
+    /// packets to send to each.
+    pub(crate) fn encode(self) -> Vec<(I, Vec<packet::EntityFrame>)> {
+        let mut out = Vec::with_capacity(self.targets.len());
+        for target in &self.targets {
+            out.push(self.create_delta(target));
+        }
+        self.targets.into_iter()
+            .map(|v| v.id)
+            .zip(out)
+            .collect()
+    }
+
+    fn write_player_state<W>(
+        log: &Logger,
+        player_frames: &FNVMap<player::Id, Box<[Option<PlayerSnapshot>]>>,
+        player_info: &DeltaTarget<I>,
+        current_frame: u16,
+        current: &mut bitio::Writer<W>
+    )
+        where W: io::Write,
+    {
+        let player = assume!(log, player_frames.get(&player_info.uid));
+        let player_base = player.get(player_info.player_state as usize % HISTORY_MAX_SIZE)
+            .and_then(|v| v.as_ref())
+            .filter(|v| v.frame_id == player_info.player_state);
+        let player_cur = assume!(log, player.get(current_frame as usize % HISTORY_MAX_SIZE)
+            .and_then(|v| v.as_ref()));
+
+        let _ = current.write_unsigned(u64::from(player_base.as_ref().map_or(INVALID_FRAME, |v| v.frame_id)), 14);
+        let _ = player_cur.encode(player_base, current);
+    }
+
+    /// Creates (and splits) either a delta frame based on the
+    /// last ack'd frames per an entity.
+    fn create_delta(&self, player: &DeltaTarget<I>) -> Vec<packet::EntityFrame> {
+        let entity_state = &player.entity_state;
+        let mut packets = vec![];
+        let mut current = bitio::Writer::new(vec![]);
+        // Buffer used for writing entity data before writing
+        // to `current`. Cleared and reused for every entity.
+        let mut entity_data = bitio::Writer::new(vec![]);
+
+        // Used to force the first valid entity to
+        // cause the start of a packet (with headers).
+        // Also used to mark as being able to skip over
+        // inactive entities
+        let mut first = true;
+        // The starting point of the current packet's entities
+        let mut offset = 0;
+        // The number of entities in the current packet
+        let mut count = 0;
+        // The base frame of the current packet
+        let mut base_frame = INVALID_FRAME;
+        let current_frame = assume!(self.log, self.history.frames[self.current_frame as usize % HISTORY_MAX_SIZE].as_ref());
+        // The captured frame contains a slot for every entry in
+        // the entity map at the time of the capture
+        for (id, e) in current_frame.entities.iter().map(|v| v.as_ref().map(|v| v.entity)).enumerate() {
+            if first && e.is_none()
+                    && entity_state.entities.get(id)
+                        .cloned()
+                        .unwrap_or(INVALID_FRAME) == INVALID_FRAME
+            {
+                offset += 1;
+                continue;
+            }
+
+            let mut entity_frame = entity_state.entities.get(id)
+                .cloned()
+                .unwrap_or(INVALID_FRAME);
+            // If the frame in our history buffer isn't the entities last frame
+            // then the frame was dropped for a newer frame and the client is
+            // lagging behind. We treat this entity as never have been sync'd
+            // in the first place and start from fresh.
+            if self.history.frames[entity_frame as usize % HISTORY_MAX_SIZE].as_ref().map_or(true, |v| v.frame_id != entity_frame) {
+                entity_frame = INVALID_FRAME;
+            }
+
+            let same_entity = {
+                if entity_frame == INVALID_FRAME {
+                    false
+                } else {
+                    let old_frame = assume!(self.log, self.history.frames[entity_frame as usize % HISTORY_MAX_SIZE].as_ref());
+                    let oe = old_frame.entities[id].as_ref();
+                    let ne = current_frame.entities[id].as_ref();
+                    if let (Some(ne), Some(oe)) = (ne, oe) {
+                        ne.entity == oe.entity
+                    } else {
+                        false
+                    }
+                }
+            };
+
+            // Serialize the entity state based on the previous frame.
+
+            let state = match (entity_frame, e) {
+                // Didn't exist before, exists now
+                (INVALID_FRAME, Some(_))                 => EntityStateFlag::Add,
+                // Didn't exist before and still doesn't
+                (INVALID_FRAME, None)                    => EntityStateFlag::Empty,
+                // Existed but now removed
+                (_            , None)                    => EntityStateFlag::Removed,
+                // Reused entity id
+                (_            , Some(_)) if !same_entity => EntityStateFlag::Add,
+                // Existed and still exists
+                (_            , Some(_))                 => EntityStateFlag::Update,
+            };
+            let _ = entity_data.write_unsigned(u64::from(state.as_u8()), 2);
+            match state {
+                EntityStateFlag::Add => {
+                    let e = assume!(self.log, current_frame.entities[id].as_ref());
+                    let _ = e.encode(None, &mut entity_data);
+                },
+                EntityStateFlag::Update => {
+                    let old_frame = assume!(self.log, self.history.frames[entity_frame as usize % HISTORY_MAX_SIZE].as_ref());
+                    let oe = assume!(self.log, old_frame.entities[id].as_ref());
+                    let ne = assume!(self.log, current_frame.entities[id].as_ref());
+                    let _ = ne.encode(Some(oe), &mut entity_data);
+                },
+                // No need to send any state for these as they don't/no longer
+                // exist.
+                EntityStateFlag::Removed | EntityStateFlag::Empty => {},
+            }
+
+            // - The first entity needs to set the header, no avoiding that
+            // - Also split if the entity has a different base from the last entity.
+            //   This can happen due to the size limit below.
+            // - The packet is limited to 1000 bytes in size. If writing this
+            //   entity would set us over this limit then when split.
+            //   The system should be able to handle this.
+            // - The number of entities would overflow the u8 count
+            if first || entity_frame != base_frame || count >= 255 || entity_data.bit_len() + current.bit_len() > 1000 * 8 {
+                // Finish and write out the previous packet (if there was
+                // one).
+                if !first {
+                    let mut data = assume!(self.log, mem::replace(&mut current, bitio::Writer::new(vec![]))
+                        .finish());
+                    // Set the entity count in the space we
+                    // reserved.
+                    data[6] = count as u8;
+                    packets.push(packet::EntityFrame {
+                        data: packet::Raw(data),
+                    });
+                    offset += count;
+                    count = 0;
+                }
+
+                // Update the base frame. Follows the same
+                // rules as `entity_frame` above.
+                base_frame = entity_state.entities.get(id)
+                    .cloned()
+                    .unwrap_or(INVALID_FRAME);
+                if self.history.frames[base_frame as usize % HISTORY_MAX_SIZE].as_ref().map_or(true, |v| v.frame_id != base_frame) {
+                    base_frame = INVALID_FRAME;
+                }
+
+                // Write the header
+                let _ = current.write_unsigned(u64::from(self.current_frame), 14);
+                let _ = current.write_unsigned(u64::from(base_frame), 14);
+                let _ = current.write_unsigned(offset as u64, 20);
+                let _ = current.write_unsigned(0, 8); // Entity count placeholder
+
+                // Player state is special as the player isn't
+                // an entity (and even if it was it doesn't require
+                // the same information as other entities).
+                // We packet this into the first entity state
+                // packet instead of requiring another packet
+                // just for this
+                if first {
+                    let _ = current.write_bool(true);
+                    Self::write_player_state(&self.log, &self.history.player_frames, player, self.current_frame, &mut current);
+                } else {
+                    let _ = current.write_bool(false);
+                }
+
+                first = false;
+            }
+
+            let _ = entity_data.copy_into(&mut current);
+            entity_data.clear();
+            count += 1;
+        }
+
+        // Finish and write out the last packet (if there was
+        // one).
+        if count > 0 || first {
+            if first {
+                let _ = current.write_unsigned(u64::from(self.current_frame), 14);
+                let _ = current.write_unsigned(u64::from(base_frame), 14);
+                let _ = current.write_unsigned(offset as u64, 20);
+                let _ = current.write_unsigned(0, 8);
+                if first {
+                    let _ = current.write_bool(true);
+                    Self::write_player_state(&self.log, &self.history.player_frames, player, self.current_frame, &mut current);
+                } else {
+                    let _ = current.write_bool(false);
+                }
+            }
+            let mut data = assume!(self.log, current.finish());
+            // Set the entity count in the space we
+            // reserved.
+            data[6] = count as u8;
+            packets.push(packet::EntityFrame {
+                data: packet::Raw(data),
+            });
+        }
+
+        packets
+    }
+}
+
+/// Encodes captured frames on a background thread allowing
+/// the encoding of one frame to overlap with ticking the next.
+pub(crate) struct SnapshotEncoder<I> {
+    jobs: mpsc::Sender<PendingFrame<I>>,
+    results: mpsc::Receiver<Vec<(I, Vec<packet::EntityFrame>)>>,
+    pending: bool,
+}
+
+impl <I: Send + 'static> SnapshotEncoder<I> {
+    /// Starts the encoder's thread. The thread will stop
+    /// once the encoder is dropped.
+    pub(crate) fn new() -> SnapshotEncoder<I> {
+        let (jobs, job_recv) = mpsc::channel::<PendingFrame<I>>();
+        let (result_send, results) = mpsc::channel();
+        thread::spawn(move || {
+            for job in job_recv {
+                if result_send.send(job.encode()).is_err() {
+                    break;
+                }
+            }
+        });
+        SnapshotEncoder {
+            jobs,
+            results,
+            pending: false,
+        }
+    }
+
+    /// Starts encoding the frame.
+    ///
+    /// The previous frame must be finished before another
+    /// can be submitted.
+    pub(crate) fn submit(&mut self, frame: PendingFrame<I>) {
+        assert!(!self.pending, "Previous frame not finished");
+        if let Err(mpsc::SendError(frame)) = self.jobs.send(frame) {
+            // The thread has died, fallback to encoding on this
+            // thread instead of dropping the frame
+            warn!(frame.log, "Snapshot encoder stopped, encoding on the main thread");
+            let (send, results) = mpsc::channel();
+            let _ = send.send(frame.encode());
+            self.results = results;
+        }
+        self.pending = true;
+    }
+
+    /// Waits for the previously submitted frame to finish
+    /// encoding and returns the packets for each target.
+    ///
+    /// Returns `None` if no frame was submitted.
+    pub(crate) fn finish(&mut self) -> Option<Vec<(I, Vec<packet::EntityFrame>)>> {
+        if !self.pending {
+            return None;
+        }
+        self.pending = false;
+        self.results.recv().ok()
+    }
+}
+
 #[derive(DeltaEncode, Clone)]
 struct PlayerSnapshot {
     #[delta_default]
@@ -948,6 +1198,7 @@ struct PlayerSnapshot {
     money: UniDollar,
     rating: i16,
     config: player::PlayerConfig,
+    shared: player::SharedState,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -979,6 +1230,13 @@ impl EntityStateFlag {
     }
 }
 
+/// The captured frames shared between the snapshots and
+/// the encoder
+struct History {
+    frames: Box<[Option<Snapshot>]>,
+    player_frames: FNVMap<player::Id, Box<[Option<PlayerSnapshot>]>>,
+}
+
 struct Snapshot {
     frame_id: u16,
     entities: Vec<Option<EntitySnapshot>>
@@ -993,12 +1251,15 @@ struct EntitySnapshot {
     owner: Option<player::Id>,
 
     target: ETarget,
+    teleport: Option<ETeleport>,
+    interaction: Option<EInteraction>,
     selected: Option<player::Id>,
     room: Option<ERoom>,
     data: Option<ScriptData>,
     idle: Option<IdleChoice>,
     emotes: Vec<EEmote>,
     tints: Vec<EColor>,
+    appearance: Option<u32>,
 }
 
 #[derive(DeltaEncode, PartialEq, Clone)]
@@ -1045,6 +1306,24 @@ struct ETarget {
     facing: Option<EntityAngle>,
 }
 
+/// Where the entity was last teleported to
+#[derive(Debug, Clone, DeltaEncode, PartialEq)]
+struct ETeleport {
+    id: u8,
+    #[delta_fixed]
+    #[delta_diff]
+    #[delta_subbits = "4:7,6:7,10:7,16:7,-1:-1"]
+    x: f32,
+    #[delta_fixed]
+    #[delta_diff]
+    #[delta_subbits = "4:7,6:7,10:7,16:7,-1:-1"]
+    z: f32,
+    /// Whether the teleport was recent enough that the client
+    /// shouldn't play effects for the entity appearing or
+    /// disappearing
+    recent: bool,
+}
+
 #[derive(Debug, Clone, Copy, DeltaEncode, PartialEq)]
 struct EntityAngle(
     #[delta_fixed]
@@ -1052,6 +1331,15 @@ struct EntityAngle(
     f32
 );
 
+/// The last object the entity interacted with
+#[derive(Debug, Clone, DeltaEncode, PartialEq)]
+struct EInteraction {
+    id: u8,
+    room_id: room::Id,
+    object: u16,
+    trigger: u8,
+}
+
 #[derive(Clone, DeltaEncode, PartialEq)]
 struct ERoom {
     room_id: room::Id,
@@ -1059,6 +1347,7 @@ struct ERoom {
 
 /// Stores what frame each entity was
 /// last based on.
+#[derive(Clone)]
 pub struct EntitySnapshotState {
     entities: Vec<u16>
 }
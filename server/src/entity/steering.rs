@@ -0,0 +1,165 @@
+//! Local avoidance between walking entities.
+//!
+//! Paths from the pathfinder remain the guide for where an entity
+//! walks but each step along the path is nudged away from nearby
+//! entities so that entities sharing a corridor don't walk through
+//! each other. Steps are kept within the tile the path expected so
+//! walls and doors are still crossed where the path planned.
+//!
+//! Both the server and the clients steer whilst travelling paths so
+//! positions are rounded before use and nearby entities are always
+//! processed in the same order to give the same result everywhere.
+
+use std::cmp::Ordering;
+
+use crate::ecs::{self, Read, Write};
+use crate::level;
+use crate::util::FNVMap;
+use super::*;
+
+/// The radius in tiles used for entities without a `Steering`
+/// component
+pub const DEFAULT_RADIUS: f32 = 0.15;
+/// The largest radius in tiles an entity may have
+pub const MAX_RADIUS: f32 = 0.45;
+/// The number of steps per a tile positions are rounded to
+const PRECISION: f32 = 64.0;
+/// How close to the edge of a tile a step may be moved
+const TILE_MARGIN: f32 = 0.05;
+
+/// Controls how far other entities keep away from this entity
+/// whilst walking
+pub struct Steering {
+    /// The radius of the entity in tiles.
+    ///
+    /// A radius of zero disables avoidance for the entity
+    pub radius: f32,
+}
+component!(Steering => Map);
+
+impl Steering {
+    /// Returns the radius to use for the entity
+    pub fn radius_of(steering: &Read<Steering>, e: ecs::Entity) -> f32 {
+        steering.get_component(e)
+            .map_or(DEFAULT_RADIUS, |v| v.radius)
+            .max(0.0)
+            .min(MAX_RADIUS)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Agent {
+    entity: ecs::Entity,
+    x: f32,
+    z: f32,
+    radius: f32,
+}
+
+/// The positions of every living entity that others should avoid
+/// bucketed by tile
+pub struct Agents {
+    cells: FNVMap<(i32, i32), Vec<Agent>>,
+}
+
+impl Agents {
+    /// Collects the current positions of all living entities
+    pub fn collect(
+        em: &ecs::EntityManager<'_>,
+        living: &Read<Living>,
+        steering: &Read<Steering>,
+        position: &Write<Position>,
+    ) -> Agents {
+        let mut cells: FNVMap<(i32, i32), Vec<Agent>> = FNVMap::default();
+        let mask = living.mask().and(position);
+        for e in em.iter_mask(&mask) {
+            let pos = if let Some(pos) = position.get_component(e) {
+                pos
+            } else {
+                continue
+            };
+            let radius = Steering::radius_of(steering, e);
+            if radius <= 0.0 {
+                continue;
+            }
+            let agent = Agent {
+                entity: e,
+                x: round(pos.x),
+                z: round(pos.z),
+                radius,
+            };
+            cells.entry((agent.x as i32, agent.z as i32))
+                .or_insert_with(Vec::new)
+                .push(agent);
+        }
+        Agents {
+            cells,
+        }
+    }
+
+    /// Returns where the entity should step to instead of
+    /// `next` to avoid the entities around it.
+    ///
+    /// The result is always within the same tile as `next` and
+    /// `next` is returned unchanged if it would be moved somewhere
+    /// that can't be visited.
+    pub fn steer(
+        &self,
+        tiles: &level::LevelTiles, rooms: &level::LevelRooms,
+        e: ecs::Entity, radius: f32,
+        next: (f32, f32),
+    ) -> (f32, f32) {
+        if radius <= 0.0 {
+            return next;
+        }
+        let (nx, nz) = (round(next.0), round(next.1));
+        let (cx, cz) = (next.0 as i32, next.1 as i32);
+
+        let mut near = Vec::new();
+        for ox in -1 ..= 1 {
+            for oz in -1 ..= 1 {
+                if let Some(cell) = self.cells.get(&(cx + ox, cz + oz)) {
+                    near.extend(cell.iter().filter(|v| v.entity != e));
+                }
+            }
+        }
+        // Summing in a fixed order keeps the result identical
+        // regardless of how the entities are stored
+        near.sort_by(|a, b| (a.x, a.z, a.radius).partial_cmp(&(b.x, b.z, b.radius)).unwrap_or(Ordering::Equal));
+
+        let mut push = (0.0f32, 0.0f32);
+        for other in &near {
+            let dx = nx - other.x;
+            let dz = nz - other.z;
+            let min = radius + other.radius;
+            let dist_sq = dx * dx + dz * dz;
+            // Entities exactly on top of each other have no direction
+            // to separate in so are left for the next step
+            if dist_sq >= min * min || dist_sq <= ::std::f32::EPSILON {
+                continue;
+            }
+            let dist = dist_sq.sqrt();
+            let overlap = min - dist;
+            push.0 += (dx / dist) * overlap;
+            push.1 += (dz / dist) * overlap;
+        }
+        let len = (push.0 * push.0 + push.1 * push.1).sqrt();
+        if len <= 0.0 {
+            return next;
+        }
+        if len > radius {
+            push.0 *= radius / len;
+            push.1 *= radius / len;
+        }
+
+        let tx = round((nx + push.0).max(cx as f32 + TILE_MARGIN).min(cx as f32 + 1.0 - TILE_MARGIN));
+        let tz = round((nz + push.1).max(cz as f32 + TILE_MARGIN).min(cz as f32 + 1.0 - TILE_MARGIN));
+        if !level::can_visit(tiles, rooms, (tx * 4.0) as usize, (tz * 4.0) as usize) {
+            return next;
+        }
+        (tx, tz)
+    }
+}
+
+fn round(v: f32) -> f32 {
+    (v * PRECISION).round() / PRECISION
+}
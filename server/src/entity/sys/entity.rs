@@ -46,6 +46,7 @@ pub fn request_entity_now(
         living: ecs::Read<Living>,
         goto_room: ecs::Read<GotoRoom>,
         quitting: ecs::Read<Quitting>,
+        evacuating: ecs::Read<fire::Evacuating>,
         mut controlled: ecs::Write<Controlled>,
     | {
         let mask = living.mask()
@@ -53,7 +54,8 @@ pub fn request_entity_now(
             .and(&owned)
             .and_not(&goto_room)
             .and_not(&frozen)
-            .and_not(&quitting);
+            .and_not(&quitting)
+            .and_not(&evacuating);
         find_best_entity(
             log,
             player_id,
@@ -156,6 +158,7 @@ closure_system!(pub fn manage_entity_dispatch(
     frozen: Read<Frozen>,
     goto_room: Read<GotoRoom>,
     quitting: Read<Quitting>,
+    evacuating: Read<fire::Evacuating>,
     mut controlled: Write<Controlled>
 ) {
     let log = log.get_component(Container::WORLD).expect("Missing logger");
@@ -166,7 +169,8 @@ closure_system!(pub fn manage_entity_dispatch(
         .and(&owned)
         .and_not(&goto_room)
         .and_not(&frozen)
-        .and_not(&quitting);
+        .and_not(&quitting)
+        .and_not(&evacuating);
 
     // Try and complete requests
     for (_e, rc) in em.group(&mut rc) {
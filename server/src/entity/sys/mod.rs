@@ -2,6 +2,8 @@ use super::*;
 
 mod room;
 pub use self::room::*;
+mod reservation;
+pub use self::reservation::*;
 mod entity;
 pub use self::entity::*;
 mod student;
@@ -21,7 +23,9 @@ closure_system!(pub fn no_lerp_target_pos(
     mut door: Write<Door>,
     mut target: Write<pathfind::Target>,
     adjust: Read<LagMovementAdjust>,
-    mut catchup: Write<CatchupBuffer>
+    mut catchup: Write<CatchupBuffer>,
+    living: Read<Living>,
+    steering: Read<steering::Steering>
 ) {
     // Hack to make sure pathfinding runs first
     pathfind::travel_path(
@@ -29,7 +33,8 @@ closure_system!(pub fn no_lerp_target_pos(
         &log, &tiles, &rooms,
         &mut info, &mut speed, &mut position,
         &mut target_pos, &mut target_rotation,
-        &mut door, &mut target, &adjust
+        &mut door, &mut target, &adjust,
+        &living, &steering
     );
 
     for (e, p) in em.group(&mut position) {
@@ -95,9 +100,18 @@ closure_system!(pub fn no_lerp_target_rot(
     }
 });
 
-closure_system!(pub fn open_door_server(em: EntityManager<'_>, mut door: Write<Door>) {
+closure_system!(pub fn open_door_server(
+    em: EntityManager<'_>,
+    mut door: Write<Door>,
+    mut pathfinder: Write<pathfind::Pathfinder>
+) {
     use std::cmp::max;
+    let mut congestion = pathfind::Congestion::default();
     for (_e, d) in em.group(&mut door) {
+        d.tick_flow();
+        if let Some((loc, dir)) = d.wall {
+            congestion.set(loc, dir, d.congestion);
+        }
         if d.open > 0 {
             d.open = max(d.open - 1, 0);
         }
@@ -111,6 +125,9 @@ closure_system!(pub fn open_door_server(em: EntityManager<'_>, mut door: Write<D
             d.open_time = 0;
         }
     }
+    if let Some(pathfinder) = pathfinder.get_component_mut(Container::WORLD) {
+        pathfinder.set_congestion(congestion);
+    }
 });
 
 closure_system!(pub fn lifetime_sys(em: EntityManager<'_>, mut lifetime: Write<Lifetime>) {
@@ -122,6 +139,12 @@ closure_system!(pub fn lifetime_sys(em: EntityManager<'_>, mut lifetime: Write<L
     }
 });
 
+closure_system!(pub fn tick_teleported(em: EntityManager<'_>, mut teleported: Write<Teleported>) {
+    for (_e, teleported) in em.group(&mut teleported) {
+        teleported.ticks = teleported.ticks.saturating_add(1);
+    }
+});
+
 closure_system!(pub fn velocity_sys(
     em: EntityManager<'_>,
     mut position: Write<Position>,
@@ -218,12 +241,15 @@ closure_system!(pub(super) fn pay_staff(
     mut players: Write<crate::PlayerInfoMap>,
     mut paid: Write<Paid>,
     owned: Read<Owned>,
+    room_owned: Read<RoomOwned>,
     mut emotes: Write<IconEmote>,
+    mut finances: Write<crate::player::RoomFinances>,
     frozen: Read<Frozen>
 ) {
     let log = log.get_component(Container::WORLD).expect("Missing logger");
     let day = assume!(log.log, day.get_component(Container::WORLD));
     let players = assume!(log.log, players.get_component_mut(Container::WORLD));
+    let finances = assume!(log.log, finances.get_component_mut(Container::WORLD));
 
     for (e, (paid, owned)) in em.group_mask((&mut paid, &owned), |m| m.and_not(&frozen)) {
         if paid.last_payment.map_or(false, |v| day.day.wrapping_sub(v) < 3) {
@@ -235,6 +261,9 @@ closure_system!(pub(super) fn pay_staff(
         paid.wanted_cost += paid.cost / 50; // 2% increase
         let money = paid.cost;
         player.change_money(-money);
+        if let Some(room) = room_owned.get_component(e) {
+            finances.record(room.room_id, day.day, crate::player::FinanceKind::Wages, money);
+        }
         IconEmote::add(&mut emotes, e, Emote::Paid);
     }
 });
@@ -277,7 +306,6 @@ closure_system!(pub(crate) fn tick_professor_stats(
     paid: Read<Paid>
 ) {
     use crate::player::IssueState;
-    use std::collections::hash_map::Entry;
     let world = Container::WORLD;
     let log = log.get_component(world).expect("Missing logger");
 
@@ -323,8 +351,8 @@ closure_system!(pub(crate) fn tick_professor_stats(
 
         if job_satisfaction < 0.2 {
             // Ask for raise
-            if let Entry::Vacant(entry) = player.staff_issues.entry(e) {
-                entry.insert(IssueState::WantsPay);
+            if !player.staff_issues.contains_key(e) {
+                player.staff_issues.insert(e, IssueState::WantsPay);
                 // Prevent instantly quiting
                 vars.set_stat(Stats::PROFESSOR_JOB_SATISFACTION, 0.2);
             }
@@ -346,7 +374,8 @@ closure_system!(pub(crate) fn rest_staff(
     room_owned: Read<RoomOwned>,
     log: Read<CLogger>,
     mut rc: Write<RoomController>,
-    mut goto_room: Write<GotoRoom>
+    mut goto_room: Write<GotoRoom>,
+    construction: Read<construction::Construction>
 ) {
     let world = Container::WORLD;
     let log = log.get_component(world).expect("Missing logger");
@@ -366,12 +395,12 @@ closure_system!(pub(crate) fn rest_staff(
         let owner = owned.player_id;
         let nearest_sr = rooms.room_ids()
             .map(|v| rooms.get_room_info(v))
-            .filter(|v| v.state.is_done())
+            .filter(|v| v.state.is_done() && !construction::is_building(&construction, v.id))
             .filter(|v| v.key == staff_room)
             .filter(|v| v.owner == owner)
             .filter(|v| {
                 let rc = assume!(log.log, rc.get_component(v.controller));
-                rc.visitors.len() + rc.waiting_list.len() + rc.potential_list.len() < rc.capacity
+                rc.free_capacity() > 0
             })
             .min_by(|a, b| {
                 let adx = ((a.area.min.x + a.area.max.x) / 2) - pos.x as i32;
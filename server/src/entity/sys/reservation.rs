@@ -0,0 +1,186 @@
+use crate::ecs;
+use crate::util::*;
+
+/// The number of ticks an entity has to reach a room before its
+/// reservation expires
+pub const RESERVATION_TIME: u32 = 20 * 90;
+/// The number of ticks an entity will queue for space in a full
+/// room before giving up
+pub const MAX_QUEUE_TIME: u32 = 20 * 30;
+
+/// Tracks which entities have claimed the visitor slots of a room.
+///
+/// Entities must hold a reservation before walking to a room so
+/// that a room never has more visitors heading to it than it can
+/// fit. Entities that can't get a slot wait in a queue and are
+/// given slots in the order they asked as they become free.
+///
+/// Slots are held back for students timetabled into the current
+/// lesson until they claim them.
+#[derive(Default)]
+pub struct Reservations {
+    /// Entities holding a slot and the number of ticks remaining
+    /// before it expires
+    slots: FNVMap<ecs::Entity, u32>,
+    /// Entities waiting for a slot in the order they asked
+    queue: Vec<ecs::Entity>,
+    /// Timetabled students that haven't claimed their slot yet
+    held: FNVSet<ecs::Entity>,
+}
+
+impl Reservations {
+    /// Returns the number of slots that are reserved
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns whether no slots are reserved
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Returns whether the entity holds a slot
+    pub fn is_reserved(&self, e: ecs::Entity) -> bool {
+        self.slots.contains_key(&e)
+    }
+
+    /// Returns the number of entities waiting for a slot
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns the entity's position in the queue if it is waiting
+    /// for a slot
+    pub fn queue_position(&self, e: ecs::Entity) -> Option<usize> {
+        self.queue.iter().position(|v| *v == e)
+    }
+
+    /// Returns the number of slots available to entities that
+    /// aren't timetabled into the current lesson
+    pub fn free(&self, capacity: usize, occupied: usize) -> usize {
+        capacity.saturating_sub(occupied + self.slots.len() + self.held.len())
+    }
+
+    /// Attempts to claim a slot for the entity, queueing it if
+    /// there isn't one free.
+    ///
+    /// Renews the reservation if the entity already holds one.
+    pub fn try_reserve(&mut self, e: ecs::Entity, capacity: usize, occupied: usize) -> bool {
+        if let Some(remaining) = self.slots.get_mut(&e) {
+            *remaining = RESERVATION_TIME;
+            return true;
+        }
+        let booked = self.held.contains(&e);
+        let (free, position) = if booked {
+            // Timetabled students skip the queue as their
+            // slot was already held for them
+            (self.free(capacity, occupied) + 1, 0)
+        } else {
+            (
+                self.free(capacity, occupied),
+                self.queue_position(e).unwrap_or_else(|| self.queue.len())
+            )
+        };
+        if free > position {
+            self.queue.retain(|v| *v != e);
+            self.held.remove(&e);
+            self.slots.insert(e, RESERVATION_TIME);
+            true
+        } else {
+            if !self.queue.contains(&e) {
+                self.queue.push(e);
+            }
+            false
+        }
+    }
+
+    /// Resets the time remaining on the entity's reservation
+    /// if it holds one
+    pub fn renew(&mut self, e: ecs::Entity) {
+        if let Some(remaining) = self.slots.get_mut(&e) {
+            *remaining = RESERVATION_TIME;
+        }
+    }
+
+    /// Frees the entity's slot or removes it from the queue
+    pub fn release(&mut self, e: ecs::Entity) {
+        self.slots.remove(&e);
+        self.queue.retain(|v| *v != e);
+    }
+
+    /// Replaces the set of timetabled students that slots are
+    /// held for. Students that already hold a slot are ignored.
+    pub fn set_held<I>(&mut self, students: I)
+        where I: IntoIterator<Item=ecs::Entity>
+    {
+        self.held.clear();
+        for e in students {
+            if !self.slots.contains_key(&e) {
+                self.held.insert(e);
+            }
+        }
+    }
+
+    /// Counts down the remaining time on each reservation removing
+    /// expired ones and any entities that `keep` rejects
+    pub fn tick<F>(&mut self, mut keep: F)
+        where F: FnMut(ecs::Entity) -> bool
+    {
+        self.slots.retain(|e, remaining| {
+            *remaining = remaining.saturating_sub(1);
+            *remaining > 0 && keep(*e)
+        });
+        self.queue.retain(|e| keep(*e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entities(count: usize) -> Vec<ecs::Entity> {
+        let mut c = ecs::Container::new();
+        (0 .. count).map(|_| c.new_entity()).collect()
+    }
+
+    #[test]
+    fn queue_order() {
+        let e = entities(3);
+        let mut res = Reservations::default();
+        assert!(res.try_reserve(e[0], 1, 0));
+        assert!(!res.try_reserve(e[1], 1, 0));
+        assert!(!res.try_reserve(e[2], 1, 0));
+        assert_eq!(res.queued(), 2);
+
+        // The first in the queue gets the freed slot
+        res.release(e[0]);
+        assert!(!res.try_reserve(e[2], 1, 0));
+        assert!(res.try_reserve(e[1], 1, 0));
+        assert_eq!(res.queue_position(e[2]), Some(0));
+    }
+
+    #[test]
+    fn held_for_timetable() {
+        let e = entities(3);
+        let mut res = Reservations::default();
+        res.set_held(vec![e[0]]);
+        assert_eq!(res.free(2, 0), 1);
+        assert!(res.try_reserve(e[1], 2, 0));
+        assert!(!res.try_reserve(e[2], 2, 0));
+        // The booked student skips the queue
+        assert!(res.try_reserve(e[0], 2, 0));
+        assert_eq!(res.len(), 2);
+    }
+
+    #[test]
+    fn expires() {
+        let e = entities(2);
+        let mut res = Reservations::default();
+        assert!(res.try_reserve(e[0], 1, 0));
+        for _ in 0 .. RESERVATION_TIME {
+            res.tick(|_| true);
+        }
+        assert!(!res.is_reserved(e[0]));
+        assert!(res.try_reserve(e[1], 1, 0));
+    }
+}
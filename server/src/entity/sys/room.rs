@@ -15,8 +15,8 @@ pub struct RoomController {
     pub active: bool,
     /// List of entities waiting for this room to become active
     pub waiting_list: Vec<ecs::Entity>,
-    /// Set of entities heading to this room
-    pub potential_list: FNVSet<ecs::Entity>,
+    /// Visitor slots claimed by entities heading to this room
+    pub reservations: Reservations,
     /// Entities that this room currently controls
     pub entities: Vec<ecs::Entity>,
     /// Entities that are visiting this room
@@ -39,6 +39,32 @@ pub struct RoomController {
 }
 component!(RoomController => Map);
 
+impl RoomController {
+    /// Returns the number of visitor slots that are neither
+    /// occupied, reserved or held for timetabled students
+    pub fn free_capacity(&self) -> usize {
+        self.reservations.free(self.capacity, self.visitors.len())
+    }
+
+    /// Attempts to claim a visitor slot for the entity queueing
+    /// it if the room is full.
+    ///
+    /// Entities already visiting the room always succeed.
+    pub fn try_reserve(&mut self, e: ecs::Entity) -> bool {
+        if self.visitors.contains(&e) {
+            return true;
+        }
+        self.reservations.try_reserve(e, self.capacity, self.visitors.len())
+    }
+
+    /// Adds the entity as a visitor converting its reservation
+    /// into an occupied slot
+    pub(crate) fn add_visitor(&mut self, e: ecs::Entity) {
+        self.reservations.release(e);
+        self.visitors.push(e);
+    }
+}
+
 closure_system!(pub(crate) fn manage_room(
     em: EntityManager<'_>,
     log: Read<CLogger>,
@@ -55,7 +81,8 @@ closure_system!(pub(crate) fn manage_room(
     mut controlled: Write<Controlled>,
     day_tick: Read<DayTick>,
     booked: Read<Booked>,
-    quitting: Read<Quitting>
+    quitting: Read<Quitting>,
+    construction: Read<construction::Construction>
 ) {
     let log = log.get_component(Container::WORLD).expect("Missing logger");
     let rooms = assume!(log.log, rooms.get_component(Container::WORLD));
@@ -79,19 +106,31 @@ closure_system!(pub(crate) fn manage_room(
         );
         rc.entities.retain(|e| em.is_valid(*e));
         rc.visitors.retain(|e| em.is_valid(*e));
-        rc.potential_list.retain(|e|
-            em.is_valid(*e)
+        // Entities waiting for the room to become active are
+        // already there so shouldn't lose their slot
+        for e in &rc.waiting_list {
+            rc.reservations.renew(*e);
+        }
+        rc.reservations.tick(|e|
+            em.is_valid(e)
                 && (
-                    goto_room.get_component(*e).map_or(false, |v| v.room_id == room_id)
-                    || controlled.get_component(*e).map_or(false, |v| v.should_release)
+                    goto_room.get_component(e).map_or(false, |v| v.room_id == room_id)
+                    || controlled.get_component(e).map_or(false, |v| v.should_release)
                 )
         );
+        {
+            let RoomController { ref mut reservations, ref timetabled_visitors, ref visitors, .. } = *rc;
+            reservations.set_held(timetabled_visitors[day][activity_slot].iter()
+                .cloned()
+                .filter(|e| em.is_valid(*e) && !visitors.contains(e)));
+        }
         let room = assume!(log.log, assets.loader_open::<room::Loader>(room_info.key.borrow()));
 
-        if rc.visitors.is_empty()  {
+        let building = construction::is_building(&construction, room_id);
+        if rc.visitors.is_empty() || building {
             rc.active = false;
         }
-        if room.can_idle || !rc.visitors.is_empty() {
+        if (room.can_idle || !rc.visitors.is_empty()) && !building {
             rc.active = true;
         }
 
@@ -151,7 +190,7 @@ closure_system!(pub(crate) fn manage_room(
         }
 
         // If the room isn't done then everyone should be kicked out
-        if !room_info.state.is_done() || (!rc.visitors.is_empty() && missing_staff) {
+        if !room_info.state.is_done() || building || (!rc.visitors.is_empty() && missing_staff) {
             for e in &rc.entities {
                 let c = assume!(log.log, controlled.get_component_mut(*e));
                 c.should_release = true;
@@ -283,7 +322,7 @@ closure_system!(pub(crate) fn manage_room(
                 if room_owned.get_component(waiting).is_none() {
                     room_owned.add_component(waiting, RoomOwned::new(rc.room_id));
                     controlled.add_component(waiting, Controlled::new_by(Controller::Room(rc.room_id)));
-                    rc.visitors.push(waiting);
+                    rc.add_visitor(waiting);
                 } else {
                     let room_owned = assume!(log.log, room_owned.get_component(waiting));
                     error!(log.log, "Owned entity on the waiting list for a room";
@@ -303,12 +342,16 @@ closure_system!(pub(crate) fn manage_room(
 pub struct GotoRoom {
     state: GotoRoomState,
     pub(crate) room_id: room::Id,
-    request: Option<(pathfind::PathRequest, (f32, f32))>
+    request: Option<(pathfind::PathRequest, (f32, f32))>,
+    /// The number of ticks spent queueing for a slot
+    queued_time: u32,
 }
 component!(GotoRoom => Map);
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum GotoRoomState {
+    /// Waiting for a slot in the room to become free
+    Queued,
     FindRoom,
     WalkingToRoom,
     WaitingForRoom,
@@ -318,6 +361,9 @@ enum GotoRoomState {
 impl GotoRoom {
     /// Creates a component that will make an entity
     /// walk into a room.
+    ///
+    /// The entity will queue outside of the room until it can
+    /// reserve a slot if the room is full.
     pub fn new(
         log: &Logger,
         e: ecs::Entity,
@@ -326,11 +372,16 @@ impl GotoRoom {
     ) -> GotoRoom {
         let room = rooms.get_room_info(room_id);
         let rc = assume!(log, room_controller.get_component_mut(room.controller));
-        rc.potential_list.insert(e);
+        let state = if rc.try_reserve(e) {
+            GotoRoomState::FindRoom
+        } else {
+            GotoRoomState::Queued
+        };
         GotoRoom {
-            state: GotoRoomState::FindRoom,
+            state,
             room_id,
             request: None,
+            queued_time: 0,
         }
     }
 }
@@ -346,7 +397,9 @@ closure_system!(pub fn walk_to_room(
     mut info: Write<pathfind::PathInfo>,
     mut rc: Write<RoomController>,
     mut room_owned: Write<RoomOwned>,
-    mut controlled: Write<Controlled>
+    mut controlled: Write<Controlled>,
+    mut idle: Write<Idle>,
+    mut emotes: Write<IconEmote>
 ) {
     use rand::thread_rng;
     use rand::seq::SliceRandom;
@@ -358,6 +411,7 @@ closure_system!(pub fn walk_to_room(
 
     let mut rng = thread_rng();
     for e in em.iter_mask(&mask) {
+        let mut gave_up = false;
         let remove = goto_room.get_component_mut(e).map_or(false, |v| {
             !rooms.room_exists(v.room_id)
             || rooms.get_room_info(v.room_id).controller.is_invalid()
@@ -370,6 +424,15 @@ closure_system!(pub fn walk_to_room(
             // Check here to see if we can save a tick
 
             match goto_room.state {
+                GotoRoomState::Queued => {
+                    let rc = assume!(log.log, rc.get_component_mut(room.controller));
+                    if rc.try_reserve(e) {
+                        goto_room.state = GotoRoomState::FindRoom;
+                    } else {
+                        goto_room.queued_time += 1;
+                        gave_up = goto_room.queued_time >= MAX_QUEUE_TIME;
+                    }
+                },
                 GotoRoomState::FindRoom => {
                     let pos = assume!(log.log, position.get_component(e));
 
@@ -440,7 +503,11 @@ closure_system!(pub fn walk_to_room(
                 GotoRoomState::WalkingToRoom => {
                     if info.get_component(e).is_none() {
                         let rc = assume!(log.log, rc.get_component_mut(room.controller));
-                        if rc.active {
+                        // The reservation may have expired if the
+                        // entity was delayed on the way
+                        if !rc.try_reserve(e) {
+                            goto_room.state = GotoRoomState::Queued;
+                        } else if rc.active {
                             goto_room.state = GotoRoomState::Done;
                         } else {
                             rc.waiting_list.push(e);
@@ -451,11 +518,21 @@ closure_system!(pub fn walk_to_room(
                 GotoRoomState::WaitingForRoom
                 | GotoRoomState::Done => {},
             }
-            goto_room.state == GotoRoomState::Done
+            gave_up || goto_room.state == GotoRoomState::Done
         };
         if remove {
             let gr = assume!(log.log, goto_room.remove_component(e));
-            if rooms.room_exists(gr.room_id) {
+            if gave_up {
+                let room = rooms.get_room_info(gr.room_id);
+                if let Some(rc) = rc.get_component_mut(room.controller) {
+                    rc.reservations.release(e);
+                }
+                debug!(log.log, "Gave up waiting for space in a room"; "entity" => ?e, "room" => ?gr.room_id);
+                IconEmote::add(&mut emotes, e, Emote::Confused);
+                if idle.get_component(e).is_none() {
+                    idle.add_component(e, Idle::new());
+                }
+            } else if rooms.room_exists(gr.room_id) {
                 change_ownership(&log.log, rooms, e, &mut rc, &mut room_owned, &mut controlled, gr.room_id);
             }
         }
@@ -489,7 +566,7 @@ pub(super) fn change_ownership(
     let room = rooms.get_room_info(room_id);
     if !room.controller.is_invalid() {
         let rc = assume!(log, rc.get_component_mut(room.controller));
-        rc.visitors.push(e);
+        rc.add_visitor(e);
     }
 }
 
@@ -507,7 +584,9 @@ closure_system!(pub fn leave_room(
     mut room_owned: Write<RoomOwned>,
     mut p_target: Write<pathfind::Target>,
     mut force_leave: Write<ForceLeave>,
-    free_roam: Read<free_roam::FreeRoam>
+    free_roam: Read<free_roam::FreeRoam>,
+    construction: Read<construction::Construction>,
+    builder: Read<construction::Builder>
 ) {
     use rand::{thread_rng, Rng};
     let log = log.get_component(Container::WORLD).expect("Missing logger");
@@ -544,7 +623,12 @@ closure_system!(pub fn leave_room(
         // This cuts out rooms like roads which is fine for
         // anything to walk in/on
         let ty = assume!(log.log, assets.loader_open::<room::Loader>(room.key.borrow()));
-        if !force && (ty.controller.is_none() || ty.can_idle) && room.state.is_done() {
+        // Rooms being built are closed to all but the builders
+        let building = construction::is_building(&construction, room.id);
+        if building && builder.get_component(e).is_some() {
+            continue;
+        }
+        if !force && (ty.controller.is_none() || ty.can_idle) && room.state.is_done() && !building {
             continue;
         }
         force_leave.remove_component(e);
@@ -36,7 +36,9 @@ closure_system!(pub fn get_timetable(
     mut idle: Write<Idle>,
     mut rc: Write<RoomController>,
     mut quitting: Write<Quitting>,
-    mut controlled: Write<Controlled>
+    evacuating: Read<fire::Evacuating>,
+    mut controlled: Write<Controlled>,
+    construction: Read<construction::Construction>
 ) {
     let log = log.get_component(Container::WORLD).expect("Missing logger");
     let rooms = assume!(log.log, rooms.get_component(Container::WORLD));
@@ -48,6 +50,7 @@ closure_system!(pub fn get_timetable(
         .and_not(&frozen)
         .and_not(&goto_room)
         .and_not(&quitting)
+        .and_not(&evacuating)
     ) {
         if timetable.get_component(e).is_some() && timetable_completed.get_component(e).is_none() {
             continue;
@@ -62,12 +65,12 @@ closure_system!(pub fn get_timetable(
         let owner = owned.player_id;
         let nearest_reg = rooms.room_ids()
             .map(|v| rooms.get_room_info(v))
-            .filter(|v| v.state.is_done())
+            .filter(|v| v.state.is_done() && !construction::is_building(&construction, v.id))
             .filter(|v| v.key == reg_room)
             .filter(|v| v.owner == owner)
             .filter(|v| {
                 let rc = assume!(log.log, rc.get_component(v.controller));
-                rc.visitors.len() + rc.waiting_list.len() + rc.potential_list.len() < rc.capacity
+                rc.free_capacity() > 0
             })
             .min_by(|a, b| {
                 let adx = ((a.area.min.x + a.area.max.x) / 2) - pos.x as i32;
@@ -79,12 +82,12 @@ closure_system!(pub fn get_timetable(
                 a_dist.cmp(&b_dist)
             });
         if let Some(room) = nearest_reg {
-            // Add this student to the potential list early so that
-            // other students don't try and claim this spot as well.
+            // Reserve the spot early so that other students don't
+            // try and claim it as well.
             {
                 let room = rooms.get_room_info(room.id);
                 let rc = assume!(log.log, rc.get_component_mut(room.controller));
-                rc.potential_list.insert(e);
+                rc.try_reserve(e);
             }
 
             // If the entity is owned by a room (e.g. idling)
@@ -139,6 +142,7 @@ closure_system!(pub(crate) fn quit_sys(
     path_info: Read<pathfind::PathInfo>,
     mut target: Write<pathfind::Target>,
     mut timetable: Write<TimeTable>,
+    timetable_completed: Read<timetable::TimeTableCompleted>,
     mut rc: Write<RoomController>,
     mut sc: Write<StudentController>,
     mut grades: Write<Grades>,
@@ -162,7 +166,14 @@ closure_system!(pub(crate) fn quit_sys(
 
         // Clear the student's timetable to free up space in rooms
         if let Some(t) = timetable.remove_component(e) {
-            super::timetable::clear_time_table(&log.log, e, rooms, t, players, &owned, &mut rc, &mut sc, &mut grades);
+            let grade = super::timetable::clear_time_table(&log.log, e, rooms, t, players, &owned, &mut rc, &mut sc, &mut grades);
+            // Only students that stayed until the end of their course
+            // graduate
+            if timetable_completed.get_component(e).is_some() && grade.is_passing() {
+                if let Some(player) = owned.get_component(e).and_then(|v| players.get_mut(&v.player_id)) {
+                    player.graduated += 1;
+                }
+            }
         }
 
         // Clear the entity from any courses its assigned to
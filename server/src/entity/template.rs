@@ -0,0 +1,226 @@
+//! Entity templates
+//!
+//! Allows an existing entity to be captured into a named template
+//! which can later be used to spawn copies of it. Only components
+//! that make sense on a new entity are captured, anything tied to
+//! the original entity (position, rooms, timetables, idle tasks)
+//! is left behind.
+
+use crate::prelude::*;
+use crate::script;
+use crate::script_room::LuaEntityRef;
+use crate::ecs;
+use lua::{self, Ref, Scope};
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// A collection of named entity templates
+#[derive(Default)]
+pub struct EntityTemplates {
+    templates: FNVMap<String, EntityTemplate>,
+}
+
+/// The template safe parts of an entity
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntityTemplate {
+    /// The entity key
+    pub key: ResourceKey<'static>,
+    /// The variant type of the entity
+    pub variant: usize,
+    /// The first and second name of the entity
+    pub name: (String, String),
+    speed: Option<f32>,
+    owned: Option<PlayerId>,
+    paid: Option<TemplatePaid>,
+    tints: Option<Vec<(u8, u8, u8, u8)>>,
+    #[serde(default)]
+    appearance: Option<u32>,
+    vars: FNVMap<String, u32>,
+    money: Option<UniDollar>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct TemplatePaid {
+    cost: UniDollar,
+    wanted_cost: UniDollar,
+}
+
+impl EntityTemplates {
+    /// Captures the entity into a template with the given name
+    /// replacing any existing template with the same name.
+    pub fn capture(&mut self, entities: &mut Container, e: Entity, name: &str) -> UResult<()> {
+        let (key, variant, living_name) = {
+            let living = entities.get_component::<Living>(e)
+                .ok_or_else(|| ErrorKind::Msg("Only living entities can be captured into a template".into()))?;
+            (
+                living.key.clone(),
+                living.variant,
+                ((*living.name.0).to_owned(), (*living.name.1).to_owned()),
+            )
+        };
+        let vars = get_vars(entities, e)
+            .map(|v| v.iter()
+                .map(|(k, v)| (k.to_owned(), v))
+                .collect())
+            .unwrap_or_default();
+        let template = EntityTemplate {
+            key,
+            variant,
+            name: living_name,
+            speed: entities.get_component::<MovementSpeed>(e).map(|v| v.base_speed),
+            owned: entities.get_component::<Owned>(e).map(|v| v.player_id),
+            paid: entities.get_component::<Paid>(e).map(|v| TemplatePaid {
+                cost: v.cost,
+                wanted_cost: v.wanted_cost,
+            }),
+            tints: entities.get_component::<Tints>(e).map(|v| v.tints.clone()),
+            appearance: entities.get_component::<appearance::Appearance>(e).map(|v| v.seed),
+            vars,
+            money: entities.get_component::<Money>(e).map(|v| v.money),
+        };
+        self.insert(name.to_owned(), template);
+        Ok(())
+    }
+
+    /// Spawns a new entity from the named template at the
+    /// given location.
+    ///
+    /// If `owner` is passed then it replaces the owner that
+    /// was captured with the template.
+    pub fn spawn(
+        &self,
+        assets: &AssetManager, entities: &mut Container,
+        name: &str, pos: (f32, f32),
+        owner: Option<PlayerId>,
+    ) -> UResult<Entity> {
+        let template = self.templates.get(name)
+            .ok_or_else(|| ErrorKind::Msg(format!("No such entity template: {}", name)))?;
+        let ty = assets.loader_open::<Loader<ServerComponent>>(template.key.borrow())?;
+        if template.variant >= ty.variants.len() {
+            bail!("Invalid variant {} for entity template {}", template.variant, name);
+        }
+        let e = ty.create_entity(entities, template.variant, Some((
+            template.name.0.as_str().into(),
+            template.name.1.as_str().into(),
+        )));
+        if let Some(p) = entities.get_component_mut::<Position>(e) {
+            p.x = pos.0;
+            p.y = 0.2;
+            p.z = pos.1;
+        }
+        if let (Some(speed), Some(tspeed)) = (entities.get_component_mut::<MovementSpeed>(e), template.speed) {
+            speed.base_speed = tspeed;
+        }
+        if let (Some(paid), Some(tpaid)) = (entities.get_component_mut::<Paid>(e), template.paid.as_ref()) {
+            paid.cost = tpaid.cost;
+            paid.wanted_cost = tpaid.wanted_cost;
+        }
+        if let Some(seed) = template.appearance {
+            ty.set_appearance(entities, e, template.variant, seed);
+        }
+        if let Some(tints) = template.tints.clone() {
+            entities.add_component(e, Tints {
+                tints,
+            });
+        }
+        if let Some(player_id) = owner.or(template.owned) {
+            entities.add_component(e, Owned {
+                player_id,
+            });
+        }
+        if let Some(money) = template.money {
+            entities.add_component(e, Money {
+                money,
+            });
+        }
+        if let Some(vars) = get_vars(entities, e) {
+            for (k, v) in &template.vars {
+                vars.set_raw(k, *v);
+            }
+        }
+        Ok(e)
+    }
+
+    /// Adds the template replacing any existing template
+    /// with the same name
+    pub fn insert(&mut self, name: String, template: EntityTemplate) {
+        self.templates.insert(name, template);
+    }
+
+    /// Returns whether a template with the name exists
+    pub fn contains(&self, name: &str) -> bool {
+        self.templates.contains_key(name)
+    }
+
+    /// Removes the named template returning whether it existed
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.templates.remove(name).is_some()
+    }
+
+    /// Iterates over all templates and their names
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &EntityTemplate)> {
+        self.templates.iter()
+            .map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+/// Lua access to the game's entity templates
+pub(crate) enum TemplateStore {}
+
+impl lua::LuaUsable for TemplateStore {}
+impl script::LuaTracked for TemplateStore {
+    const KEY: script::NulledString = nul_str!("entity_templates");
+    type Storage = Rc<RefCell<EntityTemplates>>;
+    type Output = Rc<RefCell<EntityTemplates>>;
+    fn try_convert(s: &Self::Storage) -> Option<Self::Output> {
+        Some(s.clone())
+    }
+}
+
+/// Sets up a interface for scripts to capture and spawn templates
+pub fn init_templatelib(lua: &lua::Lua) {
+    lua.set(Scope::Global, "template_capture", lua::closure2(|lua, name: Ref<String>, id: i32| -> UResult<()> {
+        let templates = lua.get_tracked::<TemplateStore>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let entity_map = lua.get_tracked::<snapshot::EntityMap>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let e = entity_map.borrow().get(id as usize)
+            .and_then(|v| *v)
+            .ok_or_else(|| ErrorKind::Msg(format!("No entity with the id {}", id)))?;
+        let mut entities = lua.write_borrow::<Container>();
+        let mut templates = templates.borrow_mut();
+        templates.capture(&mut entities, e, &name)
+    }));
+    lua.set(Scope::Global, "template_spawn", lua::closure4(|lua, name: Ref<String>, x: f64, y: f64, owner: Option<i32>| -> UResult<_> {
+        let templates = lua.get_tracked::<TemplateStore>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let assets = lua.get_tracked::<AssetManager>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let mut entities = lua.write_borrow::<Container>();
+        let e = templates.borrow().spawn(
+            &assets, &mut entities,
+            &name, (x as f32, y as f32),
+            owner.map(|v| PlayerId(v as i16)),
+        )?;
+        Ok(entities.with(|
+            _em: EntityManager<'_>,
+            mut entity_ref: ecs::Write<LuaEntityRef>,
+            living: ecs::Read<Living>,
+            object: ecs::Read<Object>,
+        | {
+            LuaEntityRef::get_or_create(&mut entity_ref, &living, &object, lua, e, None)
+        }))
+    }));
+    lua.set(Scope::Global, "template_exists", lua::closure1(|lua, name: Ref<String>| -> UResult<bool> {
+        let templates = lua.get_tracked::<TemplateStore>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let templates = templates.borrow();
+        Ok(templates.contains(&name))
+    }));
+    lua.set(Scope::Global, "template_remove", lua::closure1(|lua, name: Ref<String>| -> UResult<bool> {
+        let templates = lua.get_tracked::<TemplateStore>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let mut templates = templates.borrow_mut();
+        Ok(templates.remove(&name))
+    }));
+}
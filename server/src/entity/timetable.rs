@@ -13,6 +13,8 @@ pub const NUM_TIMETABLE_SLOTS: usize = 4;
 /// Length in ticks of a single lesson
 pub const LESSON_LENGTH: i32 = 20 * 60 * 2;
 
+/// Removes the student from their course's lessons, returning
+/// the grade they were given for the course
 pub(crate) fn clear_time_table(
     log: &Logger,
     e: ecs::Entity,
@@ -23,7 +25,7 @@ pub(crate) fn clear_time_table(
     rc: &mut ecs::Write<RoomController>,
     sc: &mut ecs::Write<StudentController>,
     grades: &mut ecs::Write<Grades>,
-) {
+) -> Grade {
     let student = assume!(log, sc.get_component_mut(e));
     let grades = assume!(log, grades.get_component_mut(e));
     let owned = assume!(log, owned.get_component(e));
@@ -101,7 +103,8 @@ pub(crate) fn clear_time_table(
     grades.grades.push(GradeEntry {
         course: timetable.course,
         grade,
-    })
+    });
+    grade
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -175,29 +178,8 @@ pub(crate) fn generate_time_table(
 
             // Make sure there is space on this course
             // TODO: This may be a bit too slow as its a lot of checking
-
-            for (di, day) in course.timetable.iter().enumerate() {
-                for (pi, p) in day.iter().enumerate() {
-                    if let course::CourseEntry::Lesson{ref rooms, ..} = p {
-                        let mut used = 0;
-                        let mut total = 0;
-
-                        for lm in rooms {
-                            let rm = level_rooms.get_room_info(lm.room);
-                            let rc = if let Some(c) = rc.get_component(rm.controller) {
-                                c
-                            } else {
-                                continue 'courses;
-                            };
-                            used += rc.timetabled_visitors[di][pi].len();
-                            total += rc.capacity;
-                        }
-
-                        if used >= total {
-                            continue 'courses;
-                        }
-                    }
-                }
+            if !has_space(level_rooms, course, &rc) {
+                continue 'courses;
             }
 
             // If we made it this far then the course has space for the student
@@ -217,6 +199,102 @@ pub(crate) fn generate_time_table(
     })
 }
 
+/// Returns whether every lesson of the course has space for
+/// another student
+fn has_space(level_rooms: &LevelRooms, course: &course::Course, rc: &Write<RoomController>) -> bool {
+    for (di, day) in course.timetable.iter().enumerate() {
+        for (pi, p) in day.iter().enumerate() {
+            if let course::CourseEntry::Lesson{ref rooms, ..} = p {
+                let mut used = 0;
+                let mut total = 0;
+
+                for lm in rooms {
+                    let rm = level_rooms.get_room_info(lm.room);
+                    let rc = if let Some(c) = rc.get_component(rm.controller) {
+                        c
+                    } else {
+                        return false;
+                    };
+                    used += rc.timetabled_visitors[di][pi].len();
+                    total += rc.capacity;
+                }
+
+                if used >= total {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Moves a student from their current course on to another.
+///
+/// The student's lessons on the old course are given up without
+/// a grade and the new course isn't charged for again.
+pub(crate) fn change_course(
+    log: &Logger,
+    level_rooms: &LevelRooms,
+    player: &crate::player::PlayerInfo,
+    entities: &mut ecs::Container,
+    e: ecs::Entity,
+    course_id: course::CourseId,
+) -> errors::Result<()> {
+    entities.with(|
+        _em: EntityManager,
+        room_owned: Read<RoomOwned>,
+        mut rc: Write<RoomController>,
+        mut timetable: Write<TimeTable>,
+        mut grades: Write<Grades>,
+    | -> errors::Result<()> {
+        let current = timetable.get_component(e)
+            .ok_or_else(|| ErrorKind::Static("Not on a course"))?
+            .course;
+        if current == course_id {
+            bail!("Already on the course");
+        }
+        // Leaving part way through a lesson would leave the room
+        // holding on to the student
+        if room_owned.get_component(e).is_some() {
+            bail!("Student is busy");
+        }
+        let course = match player.courses.get(&course_id) {
+            Some(course) if !course.deprecated => course,
+            _ => bail!("Invalid course"),
+        };
+        if !has_space(level_rooms, course, &rc) {
+            return Err(ErrorKind::NoTimetableSpace.into());
+        }
+
+        if let Some(old) = player.courses.get(&current) {
+            for (di, day) in old.timetable.iter().enumerate() {
+                for (pi, p) in day.iter().enumerate() {
+                    if let course::CourseEntry::Lesson{ref rooms, ..} = p {
+                        for lm in rooms {
+                            let controller = if let Some(room) = level_rooms.try_room_info(lm.room) {
+                                room.controller
+                            } else {
+                                continue
+                            };
+                            if let Some(con) = rc.get_component_mut(controller) {
+                                con.timetabled_visitors[di][pi].remove(&e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(grades) = grades.get_component_mut(e) {
+            grades.timetable_grades = Default::default();
+        }
+        timetable.add_component(e, TimeTable {
+            course: course_id,
+        });
+        book_into_course(log, level_rooms, course, &mut rc, e);
+        Ok(())
+    })
+}
+
 pub(crate) fn book_into_course(
     log: &Logger,
     level_rooms: &LevelRooms, course: &course::Course,
@@ -275,6 +353,7 @@ closure_system!(pub(crate) fn manage_time_table(
     owned: Read<Owned>,
     student: Read<StudentController>,
     frozen: Read<Frozen>,
+    evacuating: Read<super::fire::Evacuating>,
     mut goto_room: Write<GotoRoom>,
     mut activity: Write<Activity>,
     mut timetable_start: Write<TimeTableStart>,
@@ -297,6 +376,7 @@ closure_system!(pub(crate) fn manage_time_table(
     for (e, (timetable, owned)) in em.group_mask((&mut timetable, &owned), |m| m
         .and(&student)
         .and_not(&frozen)
+        .and_not(&evacuating)
         .and_not(&goto_room)
         .and_not(&timetable_completed)
     ) {
@@ -0,0 +1,427 @@
+//! Visitors that look around a player's campus without studying or
+//! working there.
+//!
+//! Parents and tourists arrive from the road at the rates set by the
+//! server's `VisitorConfig`, more often during the open days in the
+//! calendar, and tour a few of the player's finished rooms before
+//! leaving again. Tourists on an open day arrive in groups that share
+//! a tour. Inspectors are spawned by their own scheduled task and are
+//! moved by their scripts instead but are otherwise treated the same.
+//!
+//! Whilst on campus every visitor keeps an impression of how clean
+//! (dirt and litter) and how crowded the places they walk through
+//! are. Once their visit ends the impression changes the player's
+//! rating, by more for inspectors than tourists.
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+use crate::ecs::{self, closure_system, Read, Write, EntityManager};
+use crate::assets::MonthDay;
+use crate::calendar;
+use std::cmp;
+use super::*;
+
+/// How often in ticks a visitor takes in their surroundings
+pub const OBSERVE_INTERVAL: u32 = 20 * 5;
+/// How long in ticks a visitor spends looking around each room
+/// on their tour
+pub const DWELL_TIME: u32 = 20 * 15;
+/// How close in tiles other entities have to be to count towards
+/// crowding
+pub const CROWD_RANGE: f32 = 3.0;
+
+/// Registers components required by this module
+pub fn register_components(c: &mut ecs::Container) {
+    c.register_component::<Visitor>();
+    c.register_component::<VisitorRules>();
+}
+
+/// Registers systems required by this module
+pub fn register_systems(sys: &mut ecs::Systems) {
+    sys.add(tick_visitors);
+}
+
+/// Controls how often visitors arrive and how much they affect
+/// the players' ratings
+#[derive(Clone, Debug)]
+pub struct VisitorConfig {
+    /// The average number of parents that visit each player per
+    /// an in-game day. Zero stops parents visiting
+    pub parent_rate: f32,
+    /// The average number of tourists that visit each player per
+    /// an in-game day. Zero stops tourists visiting
+    pub tourist_rate: f32,
+    /// The days of the year campuses hold an open day on
+    pub open_days: Vec<MonthDay>,
+    /// How many times more visitors arrive during an open day
+    pub open_day_multiplier: f32,
+    /// The most visitors a player's campus can have at once
+    pub max_visitors: u32,
+    /// The longest a visit lasts in ticks
+    pub visit_length: u32,
+    /// The number of entities around a visitor before they
+    /// start to feel crowded
+    pub crowd_limit: u32,
+    /// The most rating a single parent's visit can gain or lose,
+    /// scaled for the other kinds of visitor
+    pub rating: f32,
+    /// The entity spawned for parents
+    pub parent: ResourceKey<'static>,
+    /// The entity spawned for tourists
+    pub tourist: ResourceKey<'static>,
+}
+
+impl Default for VisitorConfig {
+    fn default() -> VisitorConfig {
+        VisitorConfig {
+            parent_rate: 2.0,
+            tourist_rate: 1.0,
+            open_days: vec![
+                MonthDay { month: 6, day: 20 },
+                MonthDay { month: 10, day: 10 },
+            ],
+            open_day_multiplier: 4.0,
+            max_visitors: 16,
+            visit_length: LESSON_LENGTH as u32 * 2,
+            crowd_limit: 8,
+            rating: 10.0,
+            parent: ResourceKey::new("base", "parent"),
+            tourist: ResourceKey::new("base", "tourist"),
+        }
+    }
+}
+
+/// The kinds of visitor that come to campuses
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VisitorKind {
+    /// Looks around the rooms used for teaching
+    Parent,
+    /// Takes a longer tour of any rooms
+    Tourist,
+    /// Moved by the inspector's script
+    Inspector,
+}
+
+impl VisitorKind {
+    /// Returns how much the visitor's impression counts towards
+    /// the player's rating compared to a parent's
+    pub fn weight(self) -> f32 {
+        match self {
+            VisitorKind::Parent => 1.0,
+            VisitorKind::Tourist => 0.5,
+            VisitorKind::Inspector => 2.0,
+        }
+    }
+
+    /// Returns the most rooms the visitor will tour
+    pub fn tour_length(self) -> usize {
+        match self {
+            VisitorKind::Parent => 3,
+            VisitorKind::Tourist => 6,
+            VisitorKind::Inspector => 0,
+        }
+    }
+}
+
+/// The visitor rates in use for the level.
+///
+/// Stored on the world entity
+#[derive(Default)]
+pub struct VisitorRules {
+    config: VisitorConfig,
+}
+component!(VisitorRules => Map);
+
+impl VisitorRules {
+    /// Creates the rules from the config
+    pub fn new(config: &VisitorConfig) -> VisitorRules {
+        VisitorRules {
+            config: VisitorConfig {
+                parent_rate: config.parent_rate.max(0.0),
+                tourist_rate: config.tourist_rate.max(0.0),
+                open_day_multiplier: config.open_day_multiplier.max(0.0),
+                visit_length: config.visit_length.max(1),
+                crowd_limit: config.crowd_limit.max(1),
+                rating: config.rating.max(0.0),
+                .. config.clone()
+            },
+        }
+    }
+
+    /// Returns the config the rules were created from
+    pub fn config(&self) -> &VisitorConfig {
+        &self.config
+    }
+
+    /// Returns whether the in-game day is an open day
+    pub fn is_open_day(&self, day: u32) -> bool {
+        self.config.open_days.contains(&calendar::month_day(day))
+    }
+
+    /// Returns the chance of a visitor of the kind arriving at a
+    /// campus within `interval` ticks on the in-game day
+    pub fn arrival_chance(&self, kind: VisitorKind, day: u32, interval: u32) -> f64 {
+        let rate = match kind {
+            VisitorKind::Parent => self.config.parent_rate,
+            VisitorKind::Tourist => self.config.tourist_rate,
+            // Inspections are scheduled separately
+            VisitorKind::Inspector => return 0.0,
+        };
+        let rate = if self.is_open_day(day) {
+            rate * self.config.open_day_multiplier
+        } else {
+            rate
+        };
+        let day_length = LESSON_LENGTH as f64 * 4.0;
+        (f64::from(rate) * f64::from(interval) / day_length).max(0.0).min(1.0)
+    }
+}
+
+/// What a visitor thinks of the campus so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Impression {
+    samples: u32,
+    cleanliness: f32,
+    space: f32,
+}
+
+impl Impression {
+    /// Records what the visitor can see: the dirt stage and pieces
+    /// of litter on their tile and the number of other entities
+    /// around them
+    pub fn observe(&mut self, dirt: u8, litter: u8, nearby: u32, crowd_limit: u32) {
+        let dirt = f32::from(dirt.min(dirt::MAX_STAGE)) / f32::from(dirt::MAX_STAGE);
+        let litter = f32::from(litter.min(litter::MAX_PIECES)) / f32::from(litter::MAX_PIECES);
+        self.samples += 1;
+        self.cleanliness += 1.0 - (dirt + litter) * 0.5;
+        self.space += 1.0 - (nearby as f32 / crowd_limit.max(1) as f32).min(1.0);
+    }
+
+    /// Returns the impression from `0.0` (awful) to `1.0` (perfect)
+    /// or `None` if the visitor hasn't seen anything yet
+    pub fn score(&self) -> Option<f32> {
+        if self.samples == 0 {
+            return None;
+        }
+        Some((self.cleanliness + self.space) / (2.0 * self.samples as f32))
+    }
+
+    /// Returns the change in rating the impression causes, at most
+    /// `scale` either way
+    pub fn rating_change(&self, scale: f32) -> i16 {
+        self.score()
+            .map_or(0, |v| ((v - 0.5) * 2.0 * scale).round() as i16)
+    }
+}
+
+/// An entity visiting a player's campus
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Visitor {
+    /// The kind of visitor
+    pub kind: VisitorKind,
+    /// The ticks left before the visitor leaves
+    pub remaining: u32,
+    /// The rooms the visitor still wants to see, in order
+    pub tour: Vec<RoomId>,
+    /// The ticks left looking around the current room
+    dwell: u32,
+    impression: Impression,
+}
+component!(Visitor => Map);
+
+impl Visitor {
+    /// Creates a visitor that will tour the rooms for at most
+    /// `length` ticks
+    pub fn new(kind: VisitorKind, tour: Vec<RoomId>, length: u32) -> Visitor {
+        Visitor {
+            kind,
+            remaining: length,
+            tour,
+            dwell: 0,
+            impression: Impression::default(),
+        }
+    }
+
+    /// Returns what the visitor thinks of the campus so far
+    pub fn impression(&self) -> Impression {
+        self.impression
+    }
+}
+
+/// Picks the rooms a visitor will look around from the player's
+/// finished rooms, `(id, used for teaching)`
+pub fn pick_tour<R: Rng>(mut rooms: Vec<(RoomId, bool)>, kind: VisitorKind, rng: &mut R) -> Vec<RoomId> {
+    rooms.shuffle(rng);
+    // Parents want to see where their children will be taught
+    if kind == VisitorKind::Parent {
+        rooms.sort_by_key(|v| !v.1);
+    }
+    rooms.into_iter()
+        .take(kind.tour_length())
+        .map(|v| v.0)
+        .collect()
+}
+
+closure_system!(fn tick_visitors(
+    em: EntityManager<'_>,
+    log: Read<CLogger>,
+    rules: Read<VisitorRules>,
+    rooms: Read<LevelRooms>,
+    position: Read<Position>,
+    living: Read<Living>,
+    owned: Read<Owned>,
+    frozen: Read<Frozen>,
+    free_roam: Read<free_roam::FreeRoam>,
+    path_info: Read<pathfind::PathInfo>,
+    dirt: Read<dirt::Dirt>,
+    litter: Read<litter::Litter>,
+    mut visitor: Write<Visitor>,
+    mut target: Write<pathfind::Target>,
+    mut quitting: Write<Quitting>,
+    mut players: Write<crate::PlayerInfoMap>
+) {
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let config = if let Some(rules) = rules.get_component(Container::WORLD) {
+        rules.config()
+    } else {
+        return
+    };
+    let rooms = assume!(log.log, rooms.get_component(Container::WORLD));
+    let dirt = dirt.get_component(Container::WORLD);
+    let litter = litter.get_component(Container::WORLD);
+    // Only collected on the ticks something is observed
+    let mut crowd: Option<Vec<(f32, f32)>> = None;
+
+    let mut finished = vec![];
+    for (e, pos) in em.group_mask(&position, |m| m
+        .and(&visitor)
+        .and(&owned)
+        .and_not(&frozen)
+        .and_not(&quitting)
+    ) {
+        let v = assume!(log.log, visitor.get_component_mut(e));
+        v.remaining = v.remaining.saturating_sub(1);
+        if v.remaining % OBSERVE_INTERVAL == 0 {
+            let loc = Location::new(pos.x as i32, pos.z as i32);
+            let crowd = crowd.get_or_insert_with(|| em.group_mask(&position, |m| m.and(&living))
+                .map(|(_, p)| (p.x, p.z))
+                .collect());
+            let nearby = crowd.iter()
+                .filter(|c| (c.0 - pos.x) * (c.0 - pos.x) + (c.1 - pos.z) * (c.1 - pos.z) <= CROWD_RANGE * CROWD_RANGE)
+                .count()
+                // Not counting the visitor themselves
+                .saturating_sub(1);
+            v.impression.observe(
+                dirt.map_or(0, |d| d.stage(loc)),
+                litter.map_or(0, |l| l.pieces(loc)),
+                nearby as u32,
+                config.crowd_limit,
+            );
+        }
+        if v.remaining == 0 {
+            finished.push(e);
+            continue;
+        }
+
+        // Scripted visitors (e.g. inspectors) move themselves
+        if free_roam.get_component(e).is_some()
+            || path_info.get_component(e).is_some()
+            || target.get_component(e).is_some()
+        {
+            continue;
+        }
+        if v.dwell > 0 {
+            v.dwell -= 1;
+            continue;
+        }
+        let mut next = None;
+        while !v.tour.is_empty() {
+            // Rooms may have been removed since the tour was planned
+            let room = v.tour.remove(0);
+            if let Some(info) = rooms.try_room_info(room).filter(|r| r.state.is_done()) {
+                next = Some((
+                    (info.area.min.x + info.area.width() / 2) as f32 + 0.5,
+                    (info.area.min.y + info.area.height() / 2) as f32 + 0.5,
+                ));
+                break;
+            }
+        }
+        if let Some((x, z)) = next {
+            v.dwell = DWELL_TIME;
+            target.add_component(e, pathfind::Target::try_new(x, z));
+        } else {
+            finished.push(e);
+        }
+    }
+
+    let players = assume!(log.log, players.get_component_mut(Container::WORLD));
+    for e in finished {
+        let v = assume!(log.log, visitor.remove_component(e));
+        let change = v.impression.rating_change(config.rating * v.kind.weight());
+        if let Some(player) = owned.get_component(e).and_then(|o| players.get_mut(&o.player_id)) {
+            player.rating = cmp::min(cmp::max(player.rating.saturating_add(change), -30_000), 30_000);
+        }
+        debug!(log.log, "Visit finished"; "entity" => ?e, "kind" => ?v.kind, "rating" => change);
+        // Scripted visitors leave when their script decides to
+        if free_roam.get_component(e).is_none() {
+            quitting.add_component(e, Quitting);
+        }
+    }
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn impressions() {
+        let mut clean = Impression::default();
+        assert_eq!(clean.score(), None);
+        assert_eq!(clean.rating_change(10.0), 0);
+        clean.observe(0, 0, 0, 8);
+        clean.observe(0, 0, 2, 8);
+        assert!(clean.score().map_or(false, |v| v > 0.9));
+        assert_eq!(clean.rating_change(10.0), 9);
+
+        let mut filthy = Impression::default();
+        filthy.observe(dirt::MAX_STAGE, litter::MAX_PIECES, 20, 8);
+        assert_eq!(filthy.score(), Some(0.0));
+        assert_eq!(filthy.rating_change(10.0), -10);
+        // Out of range values are clamped
+        filthy.observe(200, 200, 200, 0);
+        assert_eq!(filthy.score(), Some(0.0));
+    }
+
+    #[test]
+    fn arrivals() {
+        let rules = VisitorRules::new(&VisitorConfig {
+            parent_rate: 1.0,
+            tourist_rate: -1.0,
+            open_days: vec![calendar::month_day(10)],
+            .. VisitorConfig::default()
+        });
+        let day = LESSON_LENGTH as u32 * 4;
+        assert!((rules.arrival_chance(VisitorKind::Parent, 0, day / 4) - 0.25).abs() < 0.0001);
+        assert!((rules.arrival_chance(VisitorKind::Parent, 10, day / 4) - 1.0).abs() < 0.0001);
+        assert!(rules.is_open_day(10));
+        assert!(!rules.is_open_day(11));
+        assert_eq!(rules.arrival_chance(VisitorKind::Tourist, 10, day), 0.0);
+        assert_eq!(rules.arrival_chance(VisitorKind::Inspector, 0, day), 0.0);
+    }
+
+    #[test]
+    fn tours() {
+        let mut rng = rand::thread_rng();
+        let rooms: Vec<_> = (0 .. 10)
+            .map(|v| (RoomId(v), v % 3 == 0))
+            .collect();
+        let tour = pick_tour(rooms.clone(), VisitorKind::Parent, &mut rng);
+        assert_eq!(tour.len(), 3);
+        assert!(tour.iter().all(|v| v.0 % 3 == 0));
+
+        let tour = pick_tour(rooms.clone(), VisitorKind::Tourist, &mut rng);
+        assert_eq!(tour.len(), 6);
+        assert!(pick_tour(rooms, VisitorKind::Inspector, &mut rng).is_empty());
+        assert!(pick_tour(vec![], VisitorKind::Tourist, &mut rng).is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+//! Stable codes for failures reported to clients.
+//!
+//! Rejections sent over the network carry one of these codes in
+//! place of an english message so that clients can display them
+//! in the player's language and tools can act on specific
+//! failures. A code's number must never change once released,
+//! new failures get new numbers.
+
+use super::{Error, ErrorKind};
+
+macro_rules! error_codes {
+    (
+        $(
+            $(#[$attr:meta])*
+            $name:ident = $code:literal, $key:expr, $msg:expr;
+        )*
+    ) => {
+        /// A failure that can be reported to a client
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum ErrorCode {
+            $(
+                $(#[$attr])*
+                $name,
+            )*
+        }
+
+        /// Every error code in the catalogue
+        pub const ALL_CODES: &[ErrorCode] = &[
+            $(ErrorCode::$name,)*
+        ];
+
+        impl ErrorCode {
+            /// Returns the number used to identify the failure
+            /// over the network
+            pub fn code(self) -> u16 {
+                match self {
+                    $(ErrorCode::$name => $code,)*
+                }
+            }
+
+            /// Returns the key used to look up the translated
+            /// message for the failure
+            pub fn localization_key(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$name => $key,)*
+                }
+            }
+
+            /// Returns the english message for the failure used
+            /// when no translation is available
+            pub fn fallback_message(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$name => $msg,)*
+                }
+            }
+
+            /// Returns the failure with the passed number.
+            ///
+            /// Numbers from a newer version of the game that aren't
+            /// known are returned as `Unknown`
+            pub fn from_code(code: u16) -> ErrorCode {
+                match code {
+                    $($code => ErrorCode::$name,)*
+                    _ => ErrorCode::Unknown,
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// A failure without a more specific code
+    Unknown = 0, "error.unknown", "Unknown error";
+
+    // Connecting
+
+    /// The client is running a different version of the game
+    VersionMismatch = 100, "error.connect.version_mismatch", "The server is running a different version of the game";
+    /// The client's steam auth ticket was rejected
+    AuthenticationFailed = 101, "error.connect.authentication_failed", "Steam authentication failed";
+    /// The client sent the wrong password
+    IncorrectPassword = 102, "error.connect.incorrect_password", "Incorrect password";
+    /// The server has been locked to the current players
+    NotAcceptingPlayers = 103, "error.connect.not_accepting_players", "Server not accepting new players";
+    /// The game has already begun without the player
+    SessionStarted = 104, "error.connect.session_started", "Session already started";
+    /// The player was removed from the lobby for not doing
+    /// anything for too long
+    IdleTimeout = 105, "error.connect.idle_timeout", "Removed from the lobby for being idle";
+
+    // Commands
+
+    /// The command didn't make sense for the current state of
+    /// the game
+    InvalidCommand = 200, "error.command.invalid", "Invalid command";
+    /// The command was blocked, e.g. by an active tutorial
+    CommandNotAllowed = 201, "error.command.not_allowed", "Command not allowed";
+    /// The player doesn't have enough money
+    NotEnoughMoney = 202, "error.command.not_enough_money", "Not enough money";
+    /// An object or room was placed somewhere it can't be
+    InvalidPlacement = 203, "error.command.invalid_placement", "Invalid placement";
+    /// An object or room was placed in an area that can't be
+    /// built on
+    UnplaceableArea = 204, "error.command.unplaceable_area", "Area can't be built on";
+    /// The room's requirements aren't met
+    UnmetRoomRequirements = 205, "error.command.unmet_room_requirements", "Room requirements not met";
+    /// The object being acted on doesn't exist
+    MissingObject = 206, "error.command.missing_object", "Object doesn't exist";
+    /// The player isn't editing a room
+    NoActiveRoom = 207, "error.command.no_active_room", "No active room";
+    /// The player isn't in the correct state for the command
+    InvalidPlayerState = 208, "error.command.invalid_player_state", "Invalid player state";
+    /// The room isn't in the correct state for the command
+    InvalidRoomState = 209, "error.command.invalid_room_state", "Invalid room state";
+
+    // Trading
+
+    /// The other player of a trade doesn't exist or is the
+    /// player themselves
+    InvalidTradePlayer = 300, "error.trade.invalid_player", "Invalid player";
+    /// The amount of money traded wasn't positive
+    InvalidTradeAmount = 301, "error.trade.invalid_amount", "Invalid amount";
+    /// The transfer offer doesn't exist or was for another player
+    MissingOffer = 302, "error.trade.missing_offer", "Offer no longer exists";
+    /// The player has too many transfers waiting to be accepted
+    TooManyOffers = 303, "error.trade.too_many_offers", "Too many offers waiting";
+    /// The room can no longer be funded
+    FundingClosed = 304, "error.trade.funding_closed", "Room can't be funded";
+}
+
+impl <'a> From<&'a ErrorKind> for ErrorCode {
+    fn from(kind: &'a ErrorKind) -> ErrorCode {
+        match *kind {
+            ErrorKind::Rejected(code, _) => code,
+            ErrorKind::InvalidCommand => ErrorCode::InvalidCommand,
+            ErrorKind::CommandNotAllowed => ErrorCode::CommandNotAllowed,
+            ErrorKind::NotEnoughMoney => ErrorCode::NotEnoughMoney,
+            ErrorKind::InvalidPlacement(_)
+            | ErrorKind::RemoveInvalidPlacement(_) => ErrorCode::InvalidPlacement,
+            ErrorKind::UnplaceableArea => ErrorCode::UnplaceableArea,
+            ErrorKind::UnmetRoomRequirements => ErrorCode::UnmetRoomRequirements,
+            ErrorKind::MissingObject => ErrorCode::MissingObject,
+            ErrorKind::NoActiveRoom => ErrorCode::NoActiveRoom,
+            ErrorKind::InvalidPlayerState => ErrorCode::InvalidPlayerState,
+            ErrorKind::InvalidRoomState
+            | ErrorKind::RoomNoFullOwnership => ErrorCode::InvalidRoomState,
+            _ => ErrorCode::Unknown,
+        }
+    }
+}
+
+impl Error {
+    /// Returns the code used to report the error to clients
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::from(self.kind())
+    }
+}
+
+/// Formats the message for a failure sent by the server.
+///
+/// `detail` is extra information supplied by the server that
+/// isn't translated, e.g. the reason steam rejected a ticket.
+pub fn describe(code: u16, detail: Option<&str>) -> String {
+    let msg = ErrorCode::from_code(code).fallback_message();
+    if let Some(detail) = detail {
+        format!("{}: {}", msg, detail)
+    } else {
+        msg.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::FNVSet;
+
+    #[test]
+    fn unique_codes() {
+        let mut codes = FNVSet::default();
+        let mut keys = FNVSet::default();
+        for code in ALL_CODES {
+            assert!(codes.insert(code.code()), "duplicate code {}", code.code());
+            assert!(keys.insert(code.localization_key()), "duplicate key {}", code.localization_key());
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for code in ALL_CODES {
+            assert_eq!(ErrorCode::from_code(code.code()), *code);
+        }
+        assert_eq!(ErrorCode::from_code(9999), ErrorCode::Unknown);
+    }
+
+    #[test]
+    fn stable_codes() {
+        // These have been sent to clients and must not change
+        assert_eq!(ErrorCode::VersionMismatch.code(), 100);
+        assert_eq!(ErrorCode::IncorrectPassword.code(), 102);
+        assert_eq!(ErrorCode::InvalidCommand.code(), 200);
+        assert_eq!(ErrorCode::NotEnoughMoney.code(), 202);
+    }
+
+    #[test]
+    fn kind_codes() {
+        let err: Error = ErrorKind::NotEnoughMoney.into();
+        assert_eq!(err.code(), ErrorCode::NotEnoughMoney);
+        let err: Error = ErrorKind::Rejected(ErrorCode::SessionStarted, None).into();
+        assert_eq!(err.code(), ErrorCode::SessionStarted);
+        let err: Error = ErrorKind::NoData.into();
+        assert_eq!(err.code(), ErrorCode::Unknown);
+    }
+
+    #[test]
+    fn describe_detail() {
+        assert_eq!(describe(102, None), "Incorrect password");
+        assert_eq!(describe(101, Some("expired")), "Steam authentication failed: expired");
+    }
+}
@@ -1,6 +1,9 @@
 #![allow(deprecated)]
 //! Common error handling
 
+pub mod codes;
+pub use self::codes::ErrorCode;
+
 error_chain! {
     foreign_links {
         Io(::std::io::Error)
@@ -39,6 +42,9 @@ error_chain! {
         }
         /// Returned when a command was invalid
         InvalidCommand {}
+        /// Returned when a command was rejected before being
+        /// executed, e.g. by an active tutorial
+        CommandNotAllowed {}
         /// Returned when loading a level from the server state fails
         FailedLevelRecreation {}
         /// Returned when a room has an invalid state
@@ -151,6 +157,11 @@ error_chain! {
         NoPacketSlots {
             description("no free packet slots for the packet")
         }
+        /// Returned when a transfer's chunk doesn't fit the size
+        /// it was started with
+        InvalidTransfer {
+            description("invalid transfer chunk")
+        }
         /// Returned when a rule wasn't completely parsed
         IncompleteParse(remaining: String) {
             display("Failed to fully parse rule: {:?} was remaining", remaining)
@@ -163,6 +174,12 @@ error_chain! {
         Static(msg: &'static str) {
             display("{}", msg)
         }
+        /// A failure reported to a client with the passed code
+        /// and optional untranslated detail
+        Rejected(code: ErrorCode, detail: Option<String>) {
+            description("request rejected")
+            display("{}", codes::describe(code.code(), detail.as_ref().map(|v| v.as_str())))
+        }
     }
 }
 
@@ -0,0 +1,352 @@
+//! Statistics exporting
+//!
+//! Dumps a player's stat history along with the current state of
+//! their rooms and staff into CSV or JSON files so that they can be
+//! analyzed outside of the game (e.g. in a spreadsheet).
+
+use crate::prelude::*;
+use crate::level::room::RoomState;
+use crate::network::packet::HistoryEntry;
+use crate::saving::filesystem::FileSystem;
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+
+/// The format to export stats in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Comma separated values, one file per table
+    Csv,
+    /// A single json document
+    Json,
+}
+
+impl ExportFormat {
+    /// Returns the matching export format if any
+    pub fn from_str(val: &str) -> Option<ExportFormat> {
+        match val {
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Returns the string form of this format
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// A snapshot of a player's stats
+#[derive(Debug, Serialize)]
+pub struct StatsExport {
+    /// The player's current money
+    pub money: UniDollar,
+    /// The player's current rating
+    pub rating: i16,
+    /// The player's stat history, oldest first
+    pub history: Vec<HistoryEntry>,
+    /// The current state of each of the player's rooms
+    pub rooms: Vec<RoomStats>,
+    /// The current state of each of the player's staff
+    pub staff: Vec<StaffStats>,
+}
+
+/// The exported state of a single room
+#[derive(Debug, Serialize)]
+pub struct RoomStats {
+    /// The id of the room
+    pub id: i16,
+    /// The resource the room was loaded from
+    pub key: String,
+    /// The display name of the room
+    pub name: String,
+    /// The state of the room
+    pub state: RoomState,
+    /// The area of the room as (x, y, width, height)
+    pub area: (i32, i32, i32, i32),
+    /// The amount paid to place the room
+    pub placement_cost: UniDollar,
+    /// Whether the room currently has the entities it
+    /// needs to function
+    pub active: bool,
+    /// The number of entities the room controls
+    pub entities: usize,
+    /// The number of entities visiting the room
+    pub visitors: usize,
+    /// The number of entities waiting for the room
+    pub waiting: usize,
+    /// The number of visitors the room can hold
+    pub capacity: usize,
+}
+
+/// The exported state of a single staff member
+#[derive(Debug, Serialize)]
+pub struct StaffStats {
+    /// The network id of the staff member
+    pub id: u32,
+    /// The entity the staff member was created from
+    pub key: String,
+    /// The full name of the staff member
+    pub name: String,
+    /// The id of the room the staff member works in, if any
+    pub room: Option<i16>,
+    /// The cost of the staff member every term
+    pub wage: UniDollar,
+    /// The cost the staff member wants every term
+    pub wanted_wage: UniDollar,
+    /// The staff member's stats by name
+    pub stats: Vec<(String, f32)>,
+}
+
+/// Collects the current stats for the player
+pub(crate) fn collect(
+    assets: &AssetManager,
+    level: &Level, entities: &mut Container,
+    info: &PlayerInfo,
+) -> StatsExport {
+    let player = info.uid;
+    let rooms = level.room_ids()
+        .into_iter()
+        .map(|v| level.get_room_info(v))
+        .filter(|v| v.owner == player)
+        .map(|room| {
+            let name = assets.loader_open::<room::Loader>(room.key.borrow())
+                .map(|v| v.name.clone())
+                .unwrap_or_default();
+            let controller = if room.controller.is_invalid() {
+                None
+            } else {
+                entities.get_component::<RoomController>(room.controller)
+            };
+            RoomStats {
+                id: room.id.0,
+                key: room.key.as_string(),
+                name,
+                state: room.state,
+                area: (room.area.min.x, room.area.min.y, room.area.width(), room.area.height()),
+                placement_cost: room.placement_cost,
+                active: controller.map_or(false, |v| v.active),
+                entities: controller.map_or(0, |v| v.entities.len()),
+                visitors: controller.map_or(0, |v| v.visitors.len()),
+                waiting: controller.map_or(0, |v| v.waiting_list.len()),
+                capacity: controller.map_or(0, |v| v.capacity),
+            }
+        })
+        .collect();
+
+    let staff_entities = entities.with(|
+        em: EntityManager<'_>,
+        owned: Read<Owned>,
+        paid: Read<Paid>,
+        living: Read<Living>,
+        network_id: Read<NetworkId>,
+    | {
+        em.group_mask((&owned, &network_id), |m| m.and(&paid).and(&living))
+            .filter(|(_e, (o, _id))| o.player_id == player)
+            .map(|(e, (_o, id))| (e, id.0))
+            .collect::<Vec<_>>()
+    });
+
+    let mut staff = Vec::with_capacity(staff_entities.len());
+    for (e, id) in staff_entities {
+        let (key, name, variant) = {
+            let living = assume!(level.log, entities.get_component::<Living>(e));
+            let variant = assets.loader_open::<Loader<ServerComponent>>(living.key.borrow())
+                .map(|v| entity_variant(&v))
+                .unwrap_or(Stats::STUDENT);
+            (living.key.as_string(), format!("{} {}", living.name.0, living.name.1), variant)
+        };
+        let (wage, wanted_wage) = {
+            let paid = assume!(level.log, entities.get_component::<Paid>(e));
+            (paid.cost, paid.wanted_cost)
+        };
+        let room = entities.get_component::<RoomOwned>(e)
+            .map(|v| v.room_id.0);
+        let stats = if let Some(vars) = get_vars(entities, e) {
+            variant.stats().iter()
+                .map(|s| (s.as_string().to_owned(), vars.get_float(s.as_string()).unwrap_or(0.0)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        staff.push(StaffStats {
+            id,
+            key,
+            name,
+            room,
+            wage,
+            wanted_wage,
+            stats,
+        });
+    }
+
+    StatsExport {
+        money: info.money,
+        rating: info.rating,
+        history: info.history.iter().cloned().collect(),
+        rooms,
+        staff,
+    }
+}
+
+impl StatsExport {
+    /// Encodes the export in the requested format returning
+    /// a list of file names and their contents.
+    pub fn encode(&self, format: ExportFormat) -> UResult<Vec<(String, String)>> {
+        Ok(match format {
+            ExportFormat::Json => vec![
+                ("stats.json".into(), serde_json::to_string_pretty(self)?),
+            ],
+            ExportFormat::Csv => vec![
+                ("history.csv".into(), self.history_csv()),
+                ("rooms.csv".into(), self.rooms_csv()),
+                ("staff.csv".into(), self.staff_csv()),
+            ],
+        })
+    }
+
+    fn history_csv(&self) -> String {
+        let mut out = String::new();
+        csv_row(&mut out, &[
+            "step", "total", "income", "outcome", "students",
+            "grade_a", "grade_b", "grade_c", "grade_d", "grade_e", "grade_f",
+        ]);
+        for (idx, entry) in self.history.iter().enumerate() {
+            let mut row = vec![
+                idx.to_string(),
+                entry.total.0.to_string(),
+                entry.income.0.to_string(),
+                entry.outcome.0.to_string(),
+                entry.students.to_string(),
+            ];
+            row.extend(entry.grades.iter().map(|v| v.to_string()));
+            csv_row(&mut out, &row);
+        }
+        out
+    }
+
+    fn rooms_csv(&self) -> String {
+        let mut out = String::new();
+        csv_row(&mut out, &[
+            "id", "key", "name", "state", "x", "y", "width", "height",
+            "placement_cost", "active", "entities", "visitors", "waiting", "capacity",
+        ]);
+        for room in &self.rooms {
+            csv_row(&mut out, &[
+                room.id.to_string(),
+                room.key.clone(),
+                room.name.clone(),
+                format!("{:?}", room.state),
+                room.area.0.to_string(),
+                room.area.1.to_string(),
+                room.area.2.to_string(),
+                room.area.3.to_string(),
+                room.placement_cost.0.to_string(),
+                room.active.to_string(),
+                room.entities.to_string(),
+                room.visitors.to_string(),
+                room.waiting.to_string(),
+                room.capacity.to_string(),
+            ]);
+        }
+        out
+    }
+
+    fn staff_csv(&self) -> String {
+        let mut out = String::new();
+        csv_row(&mut out, &[
+            "id", "key", "name", "room", "wage", "wanted_wage", "stats",
+        ]);
+        for staff in &self.staff {
+            let mut stats = String::new();
+            for (idx, (name, val)) in staff.stats.iter().enumerate() {
+                if idx != 0 {
+                    stats.push(';');
+                }
+                let _ = write!(stats, "{}={}", name, val);
+            }
+            csv_row(&mut out, &[
+                staff.id.to_string(),
+                staff.key.clone(),
+                staff.name.clone(),
+                staff.room.map(|v| v.to_string()).unwrap_or_default(),
+                staff.wage.0.to_string(),
+                staff.wanted_wage.0.to_string(),
+                stats,
+            ]);
+        }
+        out
+    }
+}
+
+/// Writes the encoded files to the filesystem each prefixed
+/// with `prefix` returning the names of the written files.
+pub fn write_files<F: FileSystem>(fs: &F, prefix: &str, files: &[(String, String)]) -> UResult<Vec<String>> {
+    let mut names = Vec::with_capacity(files.len());
+    for (name, data) in files {
+        let name = format!("{}-{}", prefix, name);
+        let mut f = fs.write(&name)?;
+        f.write_all(data.as_bytes())?;
+        names.push(name);
+    }
+    Ok(names)
+}
+
+fn csv_row<S: AsRef<str>>(out: &mut String, values: &[S]) {
+    for (idx, val) in values.iter().enumerate() {
+        if idx != 0 {
+            out.push(',');
+        }
+        csv_escape(out, val.as_ref());
+    }
+    out.push_str("\r\n");
+}
+
+/// Quotes the value if it contains anything that would
+/// break the row up
+fn csv_escape(out: &mut String, val: &str) {
+    if val.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        out.push('"');
+        out.push_str(&val.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(val);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_escape() {
+        let mut out = String::new();
+        csv_row(&mut out, &["plain", "with,comma", "with \"quotes\"", ""]);
+        assert_eq!(out, "plain,\"with,comma\",\"with \"\"quotes\"\"\",\r\n");
+    }
+
+    #[test]
+    fn test_history_csv() {
+        let export = StatsExport {
+            money: UniDollar(100),
+            rating: 0,
+            history: vec![HistoryEntry {
+                total: UniDollar(100),
+                income: UniDollar(20),
+                outcome: UniDollar(5),
+                students: 3,
+                grades: [1, 2, 0, 0, 0, 0],
+            }],
+            rooms: vec![],
+            staff: vec![],
+        };
+        let files = export.encode(ExportFormat::Csv).unwrap();
+        assert_eq!(files[0].0, "history.csv");
+        let mut lines = files[0].1.lines();
+        assert_eq!(lines.next(), Some("step,total,income,outcome,students,grade_a,grade_b,grade_c,grade_d,grade_e,grade_f"));
+        assert_eq!(lines.next(), Some("0,100,20,5,3,1,2,0,0,0,0"));
+    }
+}
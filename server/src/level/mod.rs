@@ -3,6 +3,10 @@
 pub mod tile;
 pub mod object;
 pub mod room;
+pub mod prefab;
+pub mod observer;
+pub mod scatter;
+pub use self::observer::{ChangeKind, LevelChange, ObserverId, LevelObservers};
 
 mod script_helper;
 pub use self::script_helper::init_levellib;
@@ -21,6 +25,7 @@ mod virt;
 pub use self::virt::RoomVirtualLevel;
 pub use self::room::{RoomPlacement, RoomState};
 mod room_placement;
+pub use self::room_placement::ReshapedRoom;
 mod placement;
 use self::placement::*;
 
@@ -59,6 +64,11 @@ pub struct Level {
     ///
     /// Useful as an optimization when loading
     pub compute_path_data: bool,
+    /// Sharable storage for the handlers subscribed to edits
+    /// of the level
+    pub observers: Rc<RefCell<LevelObservers>>,
+    /// The decorative props painted onto the level
+    pub scatter: scatter::LevelScatter,
 }
 
 struct PathSection {
@@ -98,7 +108,7 @@ struct WallData {
 }
 
 /// Information about a wall
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct WallInfo {
     /// Flag set to modify the look of the wall and
     /// the way the wall is interacted with.
@@ -534,6 +544,7 @@ impl Level {
                     }
                     pathmap
                 },
+                changes: Default::default(),
             })),
             rooms: Rc::new(RefCell::new(LevelRooms {
                 log: log.clone(),
@@ -544,10 +555,13 @@ impl Level {
                 room_order: vec![],
             })),
             asset_manager: asset_manager.clone(),
+            observers: Default::default(),
+            scatter: Default::default(),
         };
 
         scripting.store_tracked::<LevelRooms>(Rc::downgrade(&lvl.rooms));
         scripting.store_tracked::<LevelTiles>(Rc::downgrade(&lvl.tiles));
+        scripting.store_tracked::<LevelObservers>(Rc::downgrade(&lvl.observers));
 
         lvl
     }
@@ -705,6 +719,32 @@ impl Level {
         self.tiles.borrow_mut().get_and_clear_dirty_section(x, y)
     }
 
+    /// Calls the handler with every change of the passed kinds
+    /// made to the level from now on.
+    ///
+    /// Changes are queued and handed out when `dispatch_changes`
+    /// is called.
+    pub fn on_change<F>(&self, kinds: ChangeKind, handler: F) -> ObserverId
+        where F: FnMut(&LevelChange) + 'static
+    {
+        observer::subscribe(&self.tiles, &self.observers, kinds, handler)
+    }
+
+    /// Stops the handler being called for changes to the level
+    pub fn remove_observer(&self, id: ObserverId) {
+        observer::unsubscribe(&self.tiles, &self.observers, id);
+    }
+
+    /// Hands the changes made since the last call to the handlers
+    /// subscribed to them
+    pub fn dispatch_changes(&self) {
+        observer::dispatch(&self.tiles, &self.observers);
+    }
+
+    fn push_change(&self, change: LevelChange) {
+        self.tiles.borrow_mut().changes.push(change);
+    }
+
     fn virt_placer(log: &Logger, rooms: &Rc<RefCell<LevelRooms>>, id: RoomId) -> virt::VirtualPlacer {
         virt::VirtualPlacer {
             rooms: rooms.clone(),
@@ -1016,6 +1056,13 @@ impl Level {
         for loc in area {
             self.flag_dirty(loc.x, loc.y);
         }
+        if ret.is_ok() {
+            self.push_change(LevelChange::ObjectRemoved {
+                room: room_id,
+                object: object_id,
+                area,
+            });
+        }
 
         ret
     }
@@ -1056,6 +1103,11 @@ impl Level {
         for loc in area {
             self.flag_dirty(loc.x, loc.y);
         }
+        self.push_change(LevelChange::ObjectPlaced {
+            room: room_id,
+            object: object_id,
+            area,
+        });
     }
 
     /// Returns the objects in the room
@@ -1226,6 +1278,10 @@ impl Level {
 
         self.do_update_room::<EC, _>(engine, entities, room_id);
         self.rebuild_path_sections(area);
+        self.push_change(LevelChange::RoomRemoved {
+            room: room_id,
+            area,
+        });
     }
 
     fn capture_area(&self, id: RoomId, bound: Bound) -> RoomVirtualLevel {
@@ -1383,6 +1439,12 @@ impl Level {
             }
         }
 
+        let strokes = self.scatter.strokes();
+        let _ = write_len_bits(&mut state, strokes.len());
+        for stroke in strokes {
+            let _ = stroke.encode(None, &mut state);
+        }
+
         let mut string_data = vec![String::new(); strings.len()];
         for (s, i) in strings {
             string_data[i] = s;
@@ -1471,6 +1533,12 @@ impl Level {
             }
             objects.push((id, to_load));
         }
+        let len = read_len_bits(&mut state)?;
+        let mut strokes = Vec::with_capacity(len.min(scatter::MAX_STROKES));
+        for _ in 0 .. len {
+            strokes.push(scatter::ScatterStroke::decode(None, &mut state)?);
+        }
+        self.scatter.load(&self.log, strokes);
         for (id, objects) in objects {
             let mut needs_gaps = vec![];
             self.compute_path_data = false;
@@ -1578,6 +1646,9 @@ pub struct LevelTiles {
     dirty_sections: Vec<bool>,
 
     pathmap: Vec<PathSection>,
+
+    /// Edits waiting to be handed to the level's observers
+    changes: observer::ChangeQueue,
 }
 
 impl lua::LuaUsable for LevelTiles {}
@@ -1783,7 +1854,13 @@ impl LevelTiles {
         if special_bounds.in_bounds(loc) {
             let idx = (loc.x + 1) + (loc.y + 1) * (self.width + 1) as i32;
             if let Some(wall) = self.walls.get_mut(idx as usize) {
-                wall.data[dir.as_usize() >> 1] = info;
+                let old = ::std::mem::replace(&mut wall.data[dir.as_usize() >> 1], info);
+                if old != info {
+                    self.changes.push(LevelChange::WallChanged {
+                        loc,
+                        dir,
+                    });
+                }
             }
         }
     }
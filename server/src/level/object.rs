@@ -8,7 +8,7 @@ use std::sync::Arc;
 use crate::assets;
 use super::*;
 
-use crate::common::{AnimationInfo, AnimationSet};
+use crate::common::{AnimationInfo, AnimationSet, AnimationTriggerInfo, AnimationTriggers};
 
 /// Loads object descriptions from an asset manager.
 pub enum Loader {}
@@ -56,9 +56,15 @@ impl <'a> assets::AssetLoader<'a> for Loader {
                     placer_parameters: params,
                     ty: info.ty,
                     animations: info.animations.map(|v| AnimationInfo::map_to_animation_set(val.key().module_key(), v)),
+                    animation_triggers: AnimationTriggerInfo::map_to_triggers(val.key().module_key(), info.animation_triggers),
                     lower_walls_placement: info.lower_walls_placement,
                     cost: info.cost.unwrap_or(UniDollar(0)),
                     placement_style: info.placement_style,
+                    flow: info.flow,
+                    flammability: info.flammability,
+                    suppression: info.suppression,
+                    branding: info.branding,
+                    bin_capacity: info.bin_capacity,
                 });
                 val.insert(obj).clone()
             }
@@ -92,6 +98,9 @@ pub struct Type {
     pub ty: Option<String>,
     /// Optional animation information for animated models
     pub animations: Option<AnimationSet>,
+    /// Animations to play on the model when entities interact
+    /// with the object
+    pub animation_triggers: AnimationTriggers,
     /// Whether walls should be lowered to ease placement
     /// of this object.
     pub lower_walls_placement: bool,
@@ -99,6 +108,20 @@ pub struct Type {
     pub cost: UniDollar,
     /// The style of placement to use for this object
     pub placement_style: Option<PlacementStyle>,
+    /// The number of ticks between entities passing through
+    /// this object if it is a door. Doors use `DEFAULT_DOOR_FLOW`
+    /// when not set.
+    pub flow: Option<i32>,
+    /// How easily fire spreads to and burns this object
+    pub flammability: f32,
+    /// How quickly this object puts out fires on the tiles
+    /// around it per a tick
+    pub suppression: f32,
+    /// The colour of the owner's campus to tint this object
+    /// with if any. Used for flags and signs
+    pub branding: Option<crate::player::BrandingSlot>,
+    /// The pieces of litter this object holds if it is a bin
+    pub bin_capacity: Option<u32>,
 }
 
 /// The style of placement to use
@@ -432,10 +455,25 @@ fn tag_object_entity(
 
     match obj.ty.as_ref().map(|v| v.as_ref()) {
         Some("door") => {
-            entities.add_component(e, Door::new(false));
+            let mut door = Door::new(false);
+            door.flow = obj.flow.unwrap_or(DEFAULT_DOOR_FLOW).max(0);
+            entities.add_component(e, door);
         },
         _ => {},
     }
+    if let Some(slot) = obj.branding {
+        entities.add_component(e, crate::player::CampusBranding {
+            slot,
+        });
+    }
+    if let Some(capacity) = obj.bin_capacity {
+        entities.add_component(e, crate::entity::litter::Bin::new(capacity));
+    }
+    if !obj.animation_triggers.is_empty() {
+        entities.add_component(e, ObjectTriggers {
+            triggers: obj.animation_triggers.clone(),
+        });
+    }
 
     entities.add_component(e, Object {
         key: key.into_owned(),
@@ -538,11 +576,23 @@ struct TypeInfo {
     #[serde(rename="type")]
     ty: Option<String>,
     animations: Option<FNVMap<String, AnimationInfo>>,
+    #[serde(default)]
+    animation_triggers: FNVMap<String, AnimationTriggerInfo>,
     #[serde(default = "return_true")]
     lower_walls_placement: bool,
     cost: Option<UniDollar>,
     #[serde(default)]
     placement_style: Option<PlacementStyle>,
+    #[serde(default)]
+    flow: Option<i32>,
+    #[serde(default)]
+    flammability: f32,
+    #[serde(default)]
+    suppression: f32,
+    #[serde(default)]
+    branding: Option<crate::player::BrandingSlot>,
+    #[serde(default)]
+    bin_capacity: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
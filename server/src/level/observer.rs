@@ -0,0 +1,298 @@
+//! Notifies subscribers of edits made to the level.
+//!
+//! Edits are queued with the level's tiles as they are made and
+//! handed to the handlers subscribed to them when
+//! `Level::dispatch_changes` is called once a tick. Handlers are
+//! run without the level borrowed so they are free to read it.
+
+use std::cell::RefCell;
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use crate::prelude::*;
+use crate::script;
+
+bitflags! {
+    /// The kinds of change a handler can subscribe to
+    #[derive(Default)]
+    pub struct ChangeKind: u8 {
+        /// A room finished building
+        const ROOM_PLACED = 0b0000_0001;
+        /// A built room was returned to building or removed
+        const ROOM_REMOVED = 0b0000_0010;
+        /// A wall was placed, removed or had its type changed
+        const WALL_CHANGED = 0b0000_0100;
+        /// An object was placed in a built room
+        const OBJECT_PLACED = 0b0000_1000;
+        /// An object was removed from a built room
+        const OBJECT_REMOVED = 0b0001_0000;
+    }
+}
+
+/// An edit made to the level
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelChange {
+    /// A room finished building
+    RoomPlaced {
+        /// The id of the room
+        room: RoomId,
+        /// The area the room covers
+        area: Bound,
+    },
+    /// A built room was returned to building or removed
+    RoomRemoved {
+        /// The id of the room
+        room: RoomId,
+        /// The area the room covered
+        area: Bound,
+    },
+    /// A wall was placed, removed or had its type changed
+    WallChanged {
+        /// The location of the tile the wall borders
+        loc: Location,
+        /// The side of the tile the wall is on
+        dir: Direction,
+    },
+    /// An object was placed in a built room
+    ObjectPlaced {
+        /// The room the object was placed in
+        room: RoomId,
+        /// The id of the object within the room
+        object: usize,
+        /// The area of the room
+        area: Bound,
+    },
+    /// An object was removed from a built room
+    ObjectRemoved {
+        /// The room the object was removed from
+        room: RoomId,
+        /// The id of the object within the room
+        object: usize,
+        /// The area of the room
+        area: Bound,
+    },
+}
+
+impl LevelChange {
+    /// Returns the kind of the change
+    pub fn kind(&self) -> ChangeKind {
+        match *self {
+            LevelChange::RoomPlaced{..} => ChangeKind::ROOM_PLACED,
+            LevelChange::RoomRemoved{..} => ChangeKind::ROOM_REMOVED,
+            LevelChange::WallChanged{..} => ChangeKind::WALL_CHANGED,
+            LevelChange::ObjectPlaced{..} => ChangeKind::OBJECT_PLACED,
+            LevelChange::ObjectRemoved{..} => ChangeKind::OBJECT_REMOVED,
+        }
+    }
+
+    /// Returns the area of the level affected by the change
+    pub fn area(&self) -> Bound {
+        match *self {
+            LevelChange::RoomPlaced{area, ..}
+            | LevelChange::RoomRemoved{area, ..}
+            | LevelChange::ObjectPlaced{area, ..}
+            | LevelChange::ObjectRemoved{area, ..} => area,
+            LevelChange::WallChanged{loc, dir} => Bound::new(loc, loc.shift(dir)),
+        }
+    }
+
+    /// Returns the room the change was made to if any
+    pub fn room(&self) -> Option<RoomId> {
+        match *self {
+            LevelChange::RoomPlaced{room, ..}
+            | LevelChange::RoomRemoved{room, ..}
+            | LevelChange::ObjectPlaced{room, ..}
+            | LevelChange::ObjectRemoved{room, ..} => Some(room),
+            LevelChange::WallChanged{..} => None,
+        }
+    }
+}
+
+/// The names of every kind of change as used by scripts
+pub const CHANGE_NAMES: [(ChangeKind, &str); 5] = [
+    (ChangeKind::ROOM_PLACED, "room_placed"),
+    (ChangeKind::ROOM_REMOVED, "room_removed"),
+    (ChangeKind::WALL_CHANGED, "wall_changed"),
+    (ChangeKind::OBJECT_PLACED, "object_placed"),
+    (ChangeKind::OBJECT_REMOVED, "object_removed"),
+];
+
+impl ChangeKind {
+    /// Parses the name of a kind of change as used by scripts
+    pub fn from_name(name: &str) -> UResult<ChangeKind> {
+        if let Some(kind) = CHANGE_NAMES.iter().find(|v| v.1 == name) {
+            Ok(kind.0)
+        } else {
+            bail!("Unknown level change {:?}", name)
+        }
+    }
+}
+
+/// Identifies a subscribed handler so that it can be removed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverId(pub u32);
+
+type Handler = Rc<RefCell<dyn FnMut(&LevelChange)>>;
+
+/// The handlers subscribed to changes of a level
+#[derive(Default)]
+pub struct LevelObservers {
+    next_id: u32,
+    handlers: Vec<(ObserverId, ChangeKind, Handler)>,
+}
+
+impl LevelObservers {
+    /// Subscribes the handler to the passed kinds of change
+    pub fn subscribe<F>(&mut self, kinds: ChangeKind, handler: F) -> ObserverId
+        where F: FnMut(&LevelChange) + 'static
+    {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.handlers.push((id, kinds, Rc::new(RefCell::new(handler))));
+        id
+    }
+
+    /// Removes a handler from its subscriptions
+    pub fn unsubscribe(&mut self, id: ObserverId) {
+        self.handlers.retain(|v| v.0 != id);
+    }
+
+    /// Returns every kind of change at least one handler wants
+    pub fn observed(&self) -> ChangeKind {
+        self.handlers.iter()
+            .fold(ChangeKind::empty(), |kinds, v| kinds | v.1)
+    }
+
+    fn handlers(&self) -> Vec<(ChangeKind, Handler)> {
+        self.handlers.iter()
+            .map(|v| (v.1, v.2.clone()))
+            .collect()
+    }
+}
+
+impl script::LuaTracked for LevelObservers {
+    const KEY: script::NulledString = nul_str!("level_observers");
+    type Storage = Weak<RefCell<LevelObservers>>;
+    type Output = Rc<RefCell<LevelObservers>>;
+    fn try_convert(s: &Self::Storage) -> Option<Self::Output> {
+        s.upgrade()
+    }
+}
+
+/// Changes made to the level waiting to be handed to the
+/// observers.
+///
+/// Kept with the tiles so that any edit to them can be recorded
+/// without access to the handlers.
+#[derive(Default)]
+pub(super) struct ChangeQueue {
+    /// Every kind of change at least one handler wants
+    pub(super) observed: ChangeKind,
+    pending: Vec<LevelChange>,
+}
+
+impl ChangeQueue {
+    /// Queues the change to be handed to its subscribers.
+    ///
+    /// Changes nothing is subscribed to are dropped
+    pub(super) fn push(&mut self, change: LevelChange) {
+        if self.observed.intersects(change.kind()) {
+            self.pending.push(change);
+        }
+    }
+
+    fn take(&mut self) -> Vec<LevelChange> {
+        mem::replace(&mut self.pending, vec![])
+    }
+}
+
+/// Subscribes the handler to the passed kinds of change made
+/// to the level
+pub(super) fn subscribe<F>(
+    tiles: &RefCell<LevelTiles>, observers: &RefCell<LevelObservers>,
+    kinds: ChangeKind, handler: F,
+) -> ObserverId
+    where F: FnMut(&LevelChange) + 'static
+{
+    let mut observers = observers.borrow_mut();
+    let id = observers.subscribe(kinds, handler);
+    tiles.borrow_mut().changes.observed = observers.observed();
+    id
+}
+
+/// Removes a handler from its subscriptions
+pub(super) fn unsubscribe(tiles: &RefCell<LevelTiles>, observers: &RefCell<LevelObservers>, id: ObserverId) {
+    let mut observers = observers.borrow_mut();
+    observers.unsubscribe(id);
+    tiles.borrow_mut().changes.observed = observers.observed();
+}
+
+/// Hands every queued change to the handlers subscribed to it.
+///
+/// Neither the level or the observers are borrowed whilst the
+/// handlers run
+pub(super) fn dispatch(tiles: &RefCell<LevelTiles>, observers: &RefCell<LevelObservers>) {
+    let changes = tiles.borrow_mut().changes.take();
+    if changes.is_empty() {
+        return;
+    }
+    let handlers = observers.borrow().handlers();
+    notify(&changes, &handlers);
+}
+
+fn notify(changes: &[LevelChange], handlers: &[(ChangeKind, Handler)]) {
+    for change in changes {
+        for (kinds, handler) in handlers {
+            if kinds.intersects(change.kind()) {
+                (&mut *handler.borrow_mut())(change);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn only_subscribed_kinds() {
+        let mut observers = LevelObservers::default();
+        let mut queue = ChangeQueue::default();
+        let walls = Rc::new(Cell::new(0));
+        let rooms = Rc::new(Cell::new(0));
+        {
+            let walls = walls.clone();
+            observers.subscribe(ChangeKind::WALL_CHANGED, move |_| walls.set(walls.get() + 1));
+        }
+        let id = {
+            let rooms = rooms.clone();
+            observers.subscribe(ChangeKind::ROOM_PLACED | ChangeKind::ROOM_REMOVED, move |_| rooms.set(rooms.get() + 1))
+        };
+        queue.observed = observers.observed();
+        let area = Bound::new(Location::new(0, 0), Location::new(3, 3));
+        queue.push(LevelChange::WallChanged{loc: Location::new(1, 1), dir: Direction::North});
+        queue.push(LevelChange::RoomPlaced{room: RoomId(1), area});
+        // Nothing wants objects so they aren't queued
+        queue.push(LevelChange::ObjectPlaced{room: RoomId(1), object: 0, area});
+        assert_eq!(queue.pending.len(), 2);
+
+        notify(&queue.take(), &observers.handlers());
+        assert_eq!(walls.get(), 1);
+        assert_eq!(rooms.get(), 1);
+
+        observers.unsubscribe(id);
+        queue.observed = observers.observed();
+        queue.push(LevelChange::RoomPlaced{room: RoomId(1), area});
+        assert!(queue.pending.is_empty());
+    }
+
+    #[test]
+    fn wall_area() {
+        let change = LevelChange::WallChanged{loc: Location::new(2, 2), dir: Direction::West};
+        let area = change.area();
+        assert!(area.in_bounds(Location::new(2, 2)));
+        assert!(area.in_bounds(Location::new(1, 2)));
+        assert_eq!(change.room(), None);
+    }
+}
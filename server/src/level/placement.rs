@@ -16,7 +16,6 @@ use super::object::{
 };
 use super::room;
 
-use std::cell::RefCell;
 
 pub(super) struct TempObjectPlacement {
     pub(super) key: assets::ResourceKey<'static>,
@@ -106,7 +105,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
         where E: script::Invokable,
               EntityCreator: entity::EntityCreator,
     {
-        use lua::{Scope, Ref};
+        use lua::{Scope, Ref, CellRef};
         if let Some(mut placement) = self.take_placement() {
             placement.position = pos;
             let obj = asset_manager.loader_open::<object::Loader>(placement.key.borrow())?;
@@ -126,13 +125,13 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
                 remove_on_error: bool,
             }
 
-            fn fail(_: &lua::Lua, p: Ref<RefCell<Placer>>, res: Ref<String>) {
+            fn fail(_: &lua::Lua, p: CellRef<Placer>, res: Ref<String>) {
                 let mut placer = p.borrow_mut();
                 placer.failed = Some(res.to_string());
             }
 
             #[allow(clippy::unit_arg)]
-            fn place_window(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: i32, y: i32, dir: Ref<String>, kind: Ref<String>) -> errors::Result<()> {
+            fn place_window(_: &lua::Lua, p: CellRef<Placer>, x: i32, y: i32, dir: Ref<String>, kind: Ref<String>) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 let kind = assets::LazyResourceKey::parse(&kind)
                     .or_module(placer.obj.module_key())
@@ -146,7 +145,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
                     })))
             }
 
-            fn absolute_name(lua: &lua::Lua, p: Ref<RefCell<Placer>>, res: Ref<String>) ->  Ref<String> {
+            fn absolute_name(lua: &lua::Lua, p: CellRef<Placer>, res: Ref<String>) ->  Ref<String> {
                 let placer = p.borrow();
                 let res = assets::LazyResourceKey::parse(&res)
                     .or_module(placer.obj.module_key());
@@ -154,7 +153,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn set_floor(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: i32, y: i32, tile: Ref<String>) -> errors::Result<()> {
+            fn set_floor(_: &lua::Lua, p: CellRef<Placer>, x: i32, y: i32, tile: Ref<String>) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 let tile = assets::LazyResourceKey::parse(&tile)
                     .or_module(placer.obj.module_key())
@@ -169,7 +168,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn set_tile(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: i32, y: i32, tile: Ref<String>) -> errors::Result<()> {
+            fn set_tile(_: &lua::Lua, p: CellRef<Placer>, x: i32, y: i32, tile: Ref<String>) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 let tile = assets::LazyResourceKey::parse(&tile)
                     .or_module(placer.obj.module_key())
@@ -184,7 +183,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn set_no_walls(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: i32, y: i32) -> errors::Result<()> {
+            fn set_no_walls(_: &lua::Lua, p: CellRef<Placer>, x: i32, y: i32) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 placer.placement.as_mut()
                     .ok_or(errors::ErrorKind::InvalidState)
@@ -195,7 +194,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn mark_door(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: i32, y: i32, dir: Ref<String>) -> errors::Result<()> {
+            fn mark_door(_: &lua::Lua, p: CellRef<Placer>, x: i32, y: i32, dir: Ref<String>) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 placer.placement.as_mut()
                     .ok_or(errors::ErrorKind::InvalidState)
@@ -207,7 +206,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn static_model(_: &lua::Lua, p: Ref<RefCell<Placer>>, name: Ref<String>, x: f64, y: f64, z: f64, dir: f64) -> errors::Result<()> {
+            fn static_model(_: &lua::Lua, p: CellRef<Placer>, name: Ref<String>, x: f64, y: f64, z: f64, dir: f64) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 let obj = assets::LazyResourceKey::parse(&name)
                     .or_module(placer.obj.module_key())
@@ -223,7 +222,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn static_model_tex(_: &lua::Lua, p: Ref<RefCell<Placer>>, name: Ref<String>, texture: Ref<String>, x: f64, y: f64, z: f64, dir: f64) -> errors::Result<()> {
+            fn static_model_tex(_: &lua::Lua, p: CellRef<Placer>, name: Ref<String>, texture: Ref<String>, x: f64, y: f64, z: f64, dir: f64) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 let obj = assets::LazyResourceKey::parse(&name)
                     .or_module(placer.obj.module_key())
@@ -242,7 +241,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn animated_model(_: &lua::Lua, p: Ref<RefCell<Placer>>, name: Ref<String>, animation: Ref<String>, x: f64, y: f64, z: f64, dir: f64) -> errors::Result<()> {
+            fn animated_model(_: &lua::Lua, p: CellRef<Placer>, name: Ref<String>, animation: Ref<String>, x: f64, y: f64, z: f64, dir: f64) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 let obj = assets::LazyResourceKey::parse(&name)
                     .or_module(placer.obj.module_key())
@@ -259,7 +258,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn animated_model_tex(_: &lua::Lua, p: Ref<RefCell<Placer>>, name: Ref<String>, texture: Ref<String>, animation: Ref<String>, x: f64, y: f64, z: f64, dir: f64) -> errors::Result<()> {
+            fn animated_model_tex(_: &lua::Lua, p: CellRef<Placer>, name: Ref<String>, texture: Ref<String>, animation: Ref<String>, x: f64, y: f64, z: f64, dir: f64) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 let obj = assets::LazyResourceKey::parse(&name)
                     .or_module(placer.obj.module_key())
@@ -278,7 +277,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
                     })))
             }
 
-            fn owns_tile(lua: &lua::Lua, p: Ref<RefCell<Placer>>, x: i32, y: i32) -> UResult<bool> {
+            fn owns_tile(lua: &lua::Lua, p: CellRef<Placer>, x: i32, y: i32) -> UResult<bool> {
                 let loc = Location::new(x, y);
                 let placer = p.borrow();
                 let tiles = lua.get_tracked::<LevelTiles>()
@@ -287,13 +286,13 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
                 Ok(tiles.get_room_owner(loc) == Some(placer.room_id))
             }
 
-            fn in_bounds(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: i32, y: i32) -> bool {
+            fn in_bounds(_: &lua::Lua, p: CellRef<Placer>, x: i32, y: i32) -> bool {
                 let loc = Location::new(x, y);
                 let placer = p.borrow();
                 placer.bounds.in_bounds(loc)
             }
 
-            fn contains_bound(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: f64, y: f64, width: f64, height: f64) -> bool {
+            fn contains_bound(_: &lua::Lua, p: CellRef<Placer>, x: f64, y: f64, width: f64, height: f64) -> bool {
                 let placer = p.borrow();
                 let bound = placer.bounds;
                 x >= f64::from(bound.min.x) && x + width <= f64::from(bound.max.x) + 1.0
@@ -301,7 +300,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn placement_bound(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: f64, y: f64, width: f64, height: f64) -> errors::Result<()> {
+            fn placement_bound(_: &lua::Lua, p: CellRef<Placer>, x: f64, y: f64, width: f64, height: f64) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 placer.placement.as_mut()
                     .ok_or(errors::ErrorKind::InvalidState)
@@ -312,7 +311,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn collision_bound(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: f64, y: f64, width: f64, height: f64) -> errors::Result<()> {
+            fn collision_bound(_: &lua::Lua, p: CellRef<Placer>, x: f64, y: f64, width: f64, height: f64) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 placer.placement.as_mut()
                     .ok_or(errors::ErrorKind::InvalidState)
@@ -323,7 +322,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn selection_bound(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: f64, y: f64, z: f64, width: f64, height: f64, depth: f64) -> errors::Result<()> {
+            fn selection_bound(_: &lua::Lua, p: CellRef<Placer>, x: f64, y: f64, z: f64, width: f64, height: f64, depth: f64) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 placer.placement.as_mut()
                     .ok_or(errors::ErrorKind::InvalidState)
@@ -334,22 +333,22 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
             }
 
             #[allow(clippy::unit_arg)]
-            fn blocks_tile(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: i32, y: i32) -> errors::Result<()> {
+            fn blocks_tile(_: &lua::Lua, p: CellRef<Placer>, x: i32, y: i32) -> errors::Result<()> {
                 let mut placer = p.borrow_mut();
                 placer.placement.as_mut()
                     .ok_or(errors::ErrorKind::InvalidState)
                     .and_then_into(|v| Ok(v.actions.0.push(ObjectPlacementAction::BlocksTile(Location::new(x, y)))))
             }
 
-            fn get_parameters(_: &lua::Lua, p: Ref<RefCell<Placer>>) -> Ref<lua::Table> {
+            fn get_parameters(_: &lua::Lua, p: CellRef<Placer>) -> Ref<lua::Table> {
                 p.borrow().parameters.clone()
             }
 
-            fn remove_on_error(_lua: &lua::Lua, p: Ref<RefCell<Placer>>) {
+            fn remove_on_error(_lua: &lua::Lua, p: CellRef<Placer>) {
                 p.borrow_mut().remove_on_error = true;
             }
 
-            fn get_room_key(lua: &lua::Lua, p: Ref<RefCell<Placer>>) -> UResult<Ref<String>> {
+            fn get_room_key(lua: &lua::Lua, p: CellRef<Placer>) -> UResult<Ref<String>> {
                 let rooms = lua.get_tracked::<LevelRooms>()
                     .ok_or_else(|| ErrorKind::InvalidState)?;
                 let rooms = rooms.borrow();
@@ -397,7 +396,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
                 });
 
             let id = self.id();
-            let placer = lua::Ref::new(engine, RefCell::new(Placer {
+            let placer = lua::Ref::new_cell(engine, Placer {
                 room_id: id,
                 obj: placement.key.clone(),
                 failed: None,
@@ -405,7 +404,7 @@ pub(super) trait ObjectPlaceable: super::LevelAccess + Sized {
                 bounds: self.bounds(),
                 parameters,
                 remove_on_error: false,
-            }));
+            });
 
             if Self::is_virtual() {
                 engine.set::<Option<i32>>(Scope::Registry, "level_virtual_mode", Some(i32::from(self.id().0)));
@@ -0,0 +1,294 @@
+//! Room templates that can be used to automatically furnish
+//! a room.
+
+use crate::prelude::*;
+
+/// A template of objects that can be placed into a room
+/// in one go.
+#[derive(Debug)]
+pub struct Prefab {
+    /// The display name of the prefab
+    pub name: String,
+    /// The smallest size of room that the prefab will
+    /// attempt to furnish (width, height)
+    pub min_size: (i32, i32),
+    /// The objects that make up the prefab in the order
+    /// they should be placed
+    pub objects: Vec<PrefabObject>,
+}
+
+/// An object that is placed as part of a prefab
+#[derive(Debug)]
+pub struct PrefabObject {
+    /// The object to place
+    pub key: ResourceKey<'static>,
+    /// The number of this object that must be placed
+    /// for the prefab to be valid
+    pub required: i32,
+    /// The max number of this object to place if the
+    /// budget allows. Defaults to `required`
+    pub max: i32,
+    /// Where within the room the object should be placed
+    pub rule: PlacementRule,
+}
+
+/// Controls where a prefab object is attempted to be placed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum PlacementRule {
+    /// Anywhere within the room
+    #[serde(rename = "any")]
+    Any,
+    /// Only along the walls of the room
+    #[serde(rename = "edge")]
+    Edge,
+    /// As close to the center of the room as possible
+    #[serde(rename = "center")]
+    Center,
+    /// On a grid with the given spacing starting from
+    /// the inside corner of the room
+    #[serde(rename = "grid")]
+    Grid {
+        /// The number of tiles between each grid point
+        spacing: i32,
+    },
+}
+
+impl Default for PlacementRule {
+    fn default() -> PlacementRule {
+        PlacementRule::Any
+    }
+}
+
+impl PlacementRule {
+    /// Returns the positions to try (in order) for the
+    /// given area.
+    pub fn candidates(self, area: Bound) -> Vec<(f32, f32)> {
+        use self::PlacementRule::*;
+        let locs: Vec<Location> = match self {
+            Any => area.iter().collect(),
+            Edge => area.iter()
+                .filter(|v| v.x == area.min.x || v.x == area.max.x
+                    || v.y == area.min.y || v.y == area.max.y)
+                .collect(),
+            Center => {
+                let mut locs: Vec<Location> = area.iter().collect();
+                let cx = area.min.x + area.max.x;
+                let cy = area.min.y + area.max.y;
+                // Compared at double scale to avoid rounding
+                // the center of even sized rooms
+                locs.sort_by_key(|v| {
+                    let dx = v.x * 2 - cx;
+                    let dy = v.y * 2 - cy;
+                    dx * dx + dy * dy
+                });
+                locs
+            },
+            Grid{spacing} => {
+                let spacing = spacing.max(1);
+                let inner = if area.width() > 2 && area.height() > 2 {
+                    area.inset(1)
+                } else {
+                    area
+                };
+                inner.iter()
+                    .filter(|v| (v.x - inner.min.x) % spacing == 0
+                        && (v.y - inner.min.y) % spacing == 0)
+                    .collect()
+            },
+        };
+        locs.into_iter()
+            .map(|v| (v.x as f32 + 0.5, v.y as f32 + 0.5))
+            .collect()
+    }
+}
+
+impl Prefab {
+    /// Returns whether a room with the given area is large
+    /// enough for this prefab.
+    ///
+    /// The area may be in either orientation.
+    pub fn fits(&self, area: Bound) -> bool {
+        let (w, h) = (area.width(), area.height());
+        let (mw, mh) = self.min_size;
+        (w >= mw && h >= mh) || (w >= mh && h >= mw)
+    }
+
+    /// Returns the cost of the required objects of the prefab
+    pub fn required_cost(&self, assets: &AssetManager) -> UResult<UniDollar> {
+        let mut cost = UniDollar(0);
+        for obj in &self.objects {
+            let ty = assets.loader_open::<object::Loader>(obj.key.borrow())?;
+            cost += ty.cost * obj.required;
+        }
+        Ok(cost)
+    }
+
+    /// Places the prefab's objects into the room spending no more
+    /// than the budget (if any).
+    ///
+    /// Required objects are placed first followed by any optional
+    /// objects that fit within the remaining budget. If the required
+    /// objects can't all be placed, any placed objects are removed
+    /// and an error is returned.
+    ///
+    /// Returns the ids of the placed objects in the order they were
+    /// placed.
+    pub fn furnish<E, EC>(
+        &self,
+        level: &mut Level, room_id: RoomId,
+        engine: &E,
+        entities: &mut Container,
+        mut budget: Option<UniDollar>,
+    ) -> UResult<Vec<usize>>
+        where E: Invokable,
+              EC: EntityCreator,
+    {
+        let area = level.get_room_info(room_id).area;
+        let mut placed = Vec::new();
+
+        let res = (|| -> UResult<()> {
+            // Required objects first so that optional ones
+            // can't use up the budget
+            for obj in &self.objects {
+                let cost = level.asset_manager.loader_open::<object::Loader>(obj.key.borrow())?.cost;
+                for _ in 0 .. obj.required {
+                    if budget.map_or(false, |v| v < cost) {
+                        return Err(ErrorKind::NotEnoughMoney.into());
+                    }
+                    let id = Self::place::<E, EC>(level, room_id, engine, entities, obj, area)?
+                        .ok_or_else(|| ErrorKind::UnplaceableArea)?;
+                    placed.push(id);
+                    budget = budget.map(|v| v - cost);
+                }
+            }
+            for obj in &self.objects {
+                let cost = level.asset_manager.loader_open::<object::Loader>(obj.key.borrow())?.cost;
+                for _ in obj.required .. obj.max {
+                    if budget.map_or(false, |v| v < cost) {
+                        break;
+                    }
+                    if let Some(id) = Self::place::<E, EC>(level, room_id, engine, entities, obj, area)? {
+                        placed.push(id);
+                        budget = budget.map(|v| v - cost);
+                    } else {
+                        break;
+                    }
+                }
+            }
+            Ok(())
+        })();
+
+        if let Err(err) = res {
+            // Removing an object removes the objects placed after
+            // it so remove in reverse to keep the ids stable.
+            for id in placed.into_iter().rev() {
+                assume!(level.log, level.remove_object::<EC>(entities, room_id, id));
+            }
+            return Err(err);
+        }
+        Ok(placed)
+    }
+
+    fn place<E, EC>(
+        level: &mut Level, room_id: RoomId,
+        engine: &E,
+        entities: &mut Container,
+        obj: &PrefabObject,
+        area: Bound,
+    ) -> UResult<Option<usize>>
+        where E: Invokable,
+              EC: EntityCreator,
+    {
+        level.cancel_object_placement::<EC>(room_id, entities);
+        level.begin_object_placement::<_, EC>(room_id, engine, entities, obj.key.borrow(), None)?;
+        for pos in obj.rule.candidates(area) {
+            for rotation in 0 .. 4 {
+                if level.move_active_object::<_, EC>(room_id, engine, entities, pos, None, rotation).is_err() {
+                    continue;
+                }
+                if let Ok(id) = level.finalize_object_placement::<_, EC>(room_id, engine, entities, None, rotation) {
+                    return Ok(Some(id));
+                }
+            }
+        }
+        level.cancel_object_placement::<EC>(room_id, entities);
+        Ok(None)
+    }
+}
+
+/// The format of a prefab within a room's description
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct PrefabInfo {
+    name: String,
+    #[serde(default)]
+    min_size: Option<(i32, i32)>,
+    objects: Vec<PrefabObjectInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PrefabObjectInfo {
+    object: String,
+    #[serde(default)]
+    required: i32,
+    #[serde(default)]
+    max: Option<i32>,
+    #[serde(default)]
+    placement: PlacementRule,
+}
+
+impl PrefabInfo {
+    /// Converts the prefab description into a prefab, resolving
+    /// keys relative to the passed module.
+    pub(super) fn into_prefab(self, module: ModuleKey<'_>, room_min_size: (i32, i32)) -> Prefab {
+        Prefab {
+            name: self.name,
+            min_size: self.min_size.unwrap_or(room_min_size),
+            objects: self.objects.into_iter()
+                .map(|v| PrefabObject {
+                    key: LazyResourceKey::parse(&v.object)
+                        .or_module(module.borrow())
+                        .into_owned(),
+                    required: v.required,
+                    max: v.max.unwrap_or(v.required).max(v.required),
+                    rule: v.placement,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let area = Bound::new(Location::new(2, 2), Location::new(6, 5));
+
+        let any = PlacementRule::Any.candidates(area);
+        assert_eq!(any.len(), 20);
+
+        let edge = PlacementRule::Edge.candidates(area);
+        assert_eq!(edge.len(), 14);
+        assert!(!edge.contains(&(4.5, 3.5)));
+
+        let center = PlacementRule::Center.candidates(area);
+        assert_eq!(center[0], (4.5, 3.5));
+
+        let grid = PlacementRule::Grid{spacing: 2}.candidates(area);
+        assert_eq!(grid, vec![(3.5, 3.5), (5.5, 3.5)]);
+    }
+
+    #[test]
+    fn test_fits() {
+        let prefab = Prefab {
+            name: "Test".into(),
+            min_size: (3, 5),
+            objects: vec![],
+        };
+        assert!(prefab.fits(Bound::new(Location::new(0, 0), Location::new(2, 4))));
+        assert!(prefab.fits(Bound::new(Location::new(0, 0), Location::new(4, 2))));
+        assert!(!prefab.fits(Bound::new(Location::new(0, 0), Location::new(2, 2))));
+    }
+}
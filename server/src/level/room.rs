@@ -72,10 +72,13 @@ impl <'a> assets::AssetLoader<'a> for Loader {
                     .into_owned());
             }
         }
+        // Seasonal objects are hidden outside of their season
+        valid_objects.retain(|v| !assets.is_gated(v.module_key(), &format!("objects/{}.json", v.resource())));
 
+        let min_size = (info.min_size.x, info.min_size.y);
         let room = Arc::new(Room {
             name: info.name,
-            min_size: (info.min_size.x, info.min_size.y),
+            min_size,
             tile: assets::LazyResourceKey::parse(&info.tile).or_module(resource.module_key()).into_owned(),
             border_tile: info.border_tile.map(|v| assets::LazyResourceKey::parse(&v).or_module(resource.module_key()).into_owned()),
             tile_placer: info.tile_placer.map(|v| {
@@ -146,6 +149,9 @@ impl <'a> assets::AssetLoader<'a> for Loader {
             cost_per_tile: info.cost_per_tile,
             can_idle: info.can_idle,
             used_for_teaching: info.used_for_teaching,
+            prefabs: info.prefabs.into_iter()
+                .map(|v| v.into_prefab(resource.module_key(), min_size))
+                .collect(),
         });
 
         data.by_name.insert(resource.into_owned(), room.clone());
@@ -218,6 +224,9 @@ pub struct Room {
     ///
     /// Used for inspectors and for student spawning calculations
     pub used_for_teaching: bool,
+    /// Templates that can be used to automatically furnish
+    /// the room
+    pub prefabs: Vec<prefab::Prefab>,
 }
 
 /// Information about walls within this room
@@ -336,6 +345,15 @@ impl Room {
         cost
     }
 
+    /// Calculates the cost to move a room of this size to
+    /// a new location.
+    ///
+    /// Objects are carried over with the room so only the
+    /// area is charged for, at half of its normal cost.
+    pub fn cost_for_move(&self, area: Bound) -> UniDollar {
+        self.cost_for_area(area) / 2
+    }
+
     /// Returns whether the player is able to build a room of this type
     pub fn check_requirements(&self, level: &Level, player: PlayerId) -> bool {
         for req in &self.requirements {
@@ -1044,6 +1062,13 @@ impl <'a> placement::ObjectPlaceable for LimitedRoom<'a> {
             room.area
         };
         self.level.rebuild_path_sections(area);
+        if let Ok(id) = ret {
+            self.level.push_change(LevelChange::ObjectPlaced {
+                room: self.room_id,
+                object: id,
+                area,
+            });
+        }
         ret
     }
 
@@ -1111,6 +1136,8 @@ struct RoomInfo {
     can_idle: bool,
     #[serde(default)]
     used_for_teaching: bool,
+    #[serde(default)]
+    prefabs: Vec<prefab::PrefabInfo>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -116,7 +116,7 @@ impl Level {
                     room_id: room.id,
                     active: false,
                     waiting_list: vec![],
-                    potential_list: Default::default(),
+                    reservations: Default::default(),
                     entities: vec![],
                     visitors: vec![],
                     timetabled_visitors: Default::default(),
@@ -151,6 +151,10 @@ impl Level {
             tiles.update_walls(virt.bounds);
         }
         self.rebuild_path_sections(area);
+        self.push_change(LevelChange::RoomPlaced {
+            room: room_id,
+            area,
+        });
         Ok(())
     }
 }
\ No newline at end of file
@@ -1,2 +1,5 @@
 mod plan;
-mod build;
\ No newline at end of file
+mod build;
+mod reshape;
+
+pub use self::reshape::ReshapedRoom;
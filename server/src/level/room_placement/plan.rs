@@ -75,6 +75,32 @@ impl Level {
     ) -> bool
         where E: Invokable,
               EC: EntityCreator,
+    {
+        let orig_bound = self.get_room_info(room_id).area;
+        let resized = self.rebuild_room_area(engine, owner, room_id, bound);
+        {
+            let mut rooms = self.rooms.borrow_mut();
+            let room = assume!(self.log, rooms.rooms.get_mut(room_id));
+            room.state = RoomState::Planning;
+        }
+
+        self.do_update_room_area::<EC, _>(engine, entities, Some(room_id), orig_bound);
+        self.do_update_room_area::<EC, _>(engine, entities, Some(room_id), bound);
+
+        resized
+    }
+
+    /// Gives the room's area back to the level and then builds the
+    /// room again in the new area. If the room can't be placed in
+    /// the new area then it is built in its old area instead.
+    ///
+    /// Returns whether the room was placed in the new area.
+    pub(super) fn rebuild_room_area<E>(
+            &mut self,
+            engine: &E,
+            owner: player::Id, room_id: room::Id, bound: Bound
+    ) -> bool
+        where E: Invokable,
     {
         use std::mem::replace;
         let (mut original_tiles, orig_bound, key) = {
@@ -102,15 +128,11 @@ impl Level {
             let mut rooms = self.rooms.borrow_mut();
             let room = assume!(self.log, rooms.rooms.get_mut(room_id));
             room.area = selected_bound;
-            room.state = RoomState::Planning;
             room.original_tiles = original_tiles;
             room.building_level = Some(Box::new(virt));
             room.tile_update_state = None;
         }
 
-        self.do_update_room_area::<EC, _>(engine, entities, Some(room_id), orig_bound);
-        self.do_update_room_area::<EC, _>(engine, entities, Some(room_id), bound);
-
         selected_bound == bound
     }
 
@@ -203,7 +225,7 @@ impl Level {
     ) -> UResult<()>
         where E: Invokable,
     {
-        use lua::Ref;
+        use lua::{Ref, CellRef};
         // Build the room
 
         let room: &room::Room = &*self.asset_manager.loader_open::<room::Loader>(room_key.borrow())?;
@@ -237,7 +259,7 @@ impl Level {
             }
 
             #[allow(clippy::needless_pass_by_value)] // Lua requirements
-            fn set_tile(_: &lua::Lua, p: Ref<RefCell<Placer>>, x: i32, y: i32, tile: Ref<String>) -> UResult<()> {
+            fn set_tile(_: &lua::Lua, p: CellRef<Placer>, x: i32, y: i32, tile: Ref<String>) -> UResult<()> {
                 let loc = Location::new(x, y);
                 let mut p = p.borrow_mut();
                 if !p.bound.in_bounds(loc) {
@@ -253,13 +275,13 @@ impl Level {
                 }
             }
 
-            let placer = lua::Ref::new(engine, RefCell::new(Placer {
+            let placer = lua::Ref::new_cell(engine, Placer {
                 bound: Bound::new(
                     Location::new(0, 0),
                     Location::new(bound.width(), bound.height()),
                 ),
                 tiles: vec![],
-            }));
+            });
 
             engine.invoke_function::<(Ref<String>, Ref<String>, Ref<String>, lua::Ref<_>, i32, i32), ()>("invoke_module_method", (
                 Ref::new_string(engine, placer_script.0.module()),
@@ -0,0 +1,173 @@
+use super::super::*;
+
+/// The result of changing the area of a room that is being built
+#[derive(Debug)]
+pub struct ReshapedRoom {
+    /// The area the room had before the change
+    pub old_area: Bound,
+    /// The objects that were placed in the room before the change.
+    ///
+    /// Empty slots are kept so that the object ids are the same
+    /// when the objects are restored.
+    pub old_objects: Vec<Option<ObjectPlacement>>,
+    /// The number of objects that couldn't be placed back into
+    /// the room after the change
+    pub dropped: usize,
+}
+
+impl Level {
+    /// Changes the area of a room that is being built. This is used
+    /// both to resize the room and to move it.
+    ///
+    /// The room's objects are removed and then placed again in the new
+    /// area, offset by the movement of the room's top left corner if
+    /// `shift_objects` is set. Objects that are no longer valid in the
+    /// new area are dropped.
+    ///
+    /// If the room can't be placed in the new area the room is left as
+    /// it was and an error is returned.
+    pub fn reshape_room<EC, E>(
+            &mut self,
+            engine: &E, entities: &mut Container,
+            room_id: room::Id, bound: Bound,
+            shift_objects: bool,
+    ) -> UResult<ReshapedRoom>
+        where E: Invokable,
+              EC: EntityCreator,
+    {
+        let (old_area, owner) = {
+            let room = self.try_room_info(room_id)
+                .ok_or_else(|| ErrorKind::InvalidRoomState)?;
+            if !room.state.is_building() || room.limited_editing || room.building_level.is_none() {
+                return Err(ErrorKind::InvalidRoomState.into());
+            }
+            (room.area, room.owner)
+        };
+        if old_area == bound {
+            bail!("Room already has that area");
+        }
+        if !self.check_room_size(self.get_room_info(room_id).key.borrow(), bound) {
+            return Err(ErrorKind::UnplaceableArea.into());
+        }
+
+        let old_objects = self.take_building_objects::<EC>(entities, room_id);
+        if !self.rebuild_room_area(engine, owner, room_id, bound) {
+            // Rebuilding in the old area removes all objects
+            // so put them back as they were
+            self.restore_building_objects::<EC>(entities, room_id, old_objects);
+            self.after_reshape::<EC, _>(engine, entities, room_id, old_area);
+            return Err(ErrorKind::UnplaceableArea.into());
+        }
+
+        let offset = if shift_objects {
+            ((bound.min.x - old_area.min.x) as f32, (bound.min.y - old_area.min.y) as f32)
+        } else {
+            (0.0, 0.0)
+        };
+        let mut dropped = 0;
+        for obj in old_objects.iter().filter_map(|v| v.as_ref()) {
+            let pos = (obj.position.x + offset.0, obj.position.y + offset.1);
+            let placed = self.begin_object_placement::<_, EC>(room_id, engine, entities, obj.key.borrow(), Some(obj.version))
+                .and_then(|_| self.move_active_object::<_, EC>(room_id, engine, entities, pos, Some(obj.version), obj.rotation))
+                .and_then(|_| self.finalize_object_placement::<_, EC>(room_id, engine, entities, Some(obj.version), obj.rotation));
+            if let Err(err) = placed {
+                debug!(self.log, "Dropping object from reshaped room"; "object" => ?obj.key, "error" => %err);
+                self.cancel_object_placement::<EC>(room_id, entities);
+                dropped += 1;
+            }
+        }
+        self.after_reshape::<EC, _>(engine, entities, room_id, old_area);
+
+        Ok(ReshapedRoom {
+            old_area,
+            old_objects,
+            dropped,
+        })
+    }
+
+    /// Reverts a room back to the state it was in before
+    /// `reshape_room` was called on it
+    pub fn undo_reshape_room<EC, E>(
+            &mut self,
+            engine: &E, entities: &mut Container,
+            room_id: room::Id, reshaped: ReshapedRoom,
+    )
+        where E: Invokable,
+              EC: EntityCreator,
+    {
+        let (area, owner) = {
+            let room = self.get_room_info(room_id);
+            (room.area, room.owner)
+        };
+        self.take_building_objects::<EC>(entities, room_id);
+        if !self.rebuild_room_area(engine, owner, room_id, reshaped.old_area) {
+            error!(self.log, "Failed to restore the area of a reshaped room"; "room" => ?room_id, "area" => ?reshaped.old_area);
+        }
+        self.restore_building_objects::<EC>(entities, room_id, reshaped.old_objects);
+        self.after_reshape::<EC, _>(engine, entities, room_id, area);
+    }
+
+    /// Removes all objects from the virtual level of the room returning
+    /// them in placement order
+    fn take_building_objects<EC: EntityCreator>(&mut self, entities: &mut Container, room_id: room::Id) -> Vec<Option<ObjectPlacement>> {
+        use std::mem;
+        self.cancel_object_placement::<EC>(room_id, entities);
+        let mut rooms = self.rooms.borrow_mut();
+        let room = assume!(self.log, rooms.rooms.get_mut(room_id));
+        let virt = assume!(self.log, room.building_level.as_mut());
+        virt.dirty = true;
+        let mut objects = Vec::with_capacity(virt.objects.len());
+        for obj in mem::replace(&mut virt.objects, vec![]).into_iter().rev() {
+            if let Some(obj) = obj {
+                assume!(self.log, obj.1.apply::<_, EC>(&self.log, virt, entities));
+                objects.push(Some(obj.0));
+            } else {
+                objects.push(None);
+            }
+        }
+        virt.placement_map.clear();
+        objects.reverse();
+        objects
+    }
+
+    /// Places the objects back into the virtual level of the room without
+    /// running their placement scripts again. The objects must have been
+    /// valid for the room's current area.
+    fn restore_building_objects<EC: EntityCreator>(&mut self, entities: &mut Container, room_id: room::Id, objects: Vec<Option<ObjectPlacement>>) {
+        let log = self.log.clone();
+        let mut rooms = self.rooms.borrow_mut();
+        let room = assume!(self.log, rooms.rooms.get_mut(room_id));
+        let virt = assume!(self.log, room.building_level.as_mut());
+        for obj in objects {
+            if let Some(obj) = obj {
+                let rev = assume!(log, obj.apply::<_, EC>(&log, &mut **virt, entities, room_id, false));
+                virt.objects.push(Some((obj, rev)));
+            } else {
+                virt.objects.push(None);
+            }
+        }
+        virt.rebuild_placement_map();
+        virt.dirty = true;
+    }
+
+    /// Updates the level around the old and new areas of a
+    /// reshaped room
+    fn after_reshape<EC: EntityCreator, E: Invokable>(&mut self, engine: &E, entities: &mut Container, room_id: room::Id, old_area: Bound) {
+        let area = {
+            let mut rooms = self.rooms.borrow_mut();
+            let room = assume!(self.log, rooms.rooms.get_mut(room_id));
+            room.state = RoomState::Building;
+            room.needs_update = true;
+            room.area
+        };
+        {
+            let mut tiles = self.tiles.borrow_mut();
+            tiles.update_walls(old_area);
+            tiles.update_walls(area);
+        }
+        self.do_update_room_area::<EC, _>(engine, entities, Some(room_id), old_area);
+        self.do_update_room::<EC, _>(engine, entities, room_id);
+        self.rebuild_path_sections(old_area);
+        self.rebuild_path_sections(area);
+    }
+}
@@ -0,0 +1,405 @@
+//! Decorative props (trees, bushes, rocks) scattered over the
+//! level by the level editor's scatter brush.
+//!
+//! Only the brush strokes are stored, each stroke places its props
+//! from its own seed so a stroke covering hundreds of trees only
+//! takes a few bytes in the save and the initial state sent to
+//! players. The props are generated again wherever they are needed,
+//! the client creating a static model for each one.
+//!
+//! Packs define the props a brush can paint in `scatter.json`:
+//!
+//! ```ignore
+//! {
+//!     "trees": {
+//!         "name": "Trees",
+//!         "props": [
+//!             {"model": "garden/tree", "weight": 3},
+//!             {"model": "garden/tree_tall", "texture": "garden/tree_dark"}
+//!         ]
+//!     }
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::entity::appearance::SeedRng;
+use serde_json;
+
+/// The smallest radius a stroke may have in tiles
+pub const MIN_RADIUS: f32 = 0.5;
+/// The largest radius a stroke may have in tiles
+pub const MAX_RADIUS: f32 = 12.0;
+/// The most props a stroke may place per tile at its centre
+pub const MAX_DENSITY: f32 = 2.0;
+/// The most props a single stroke may place
+pub const MAX_STROKE_PROPS: usize = 400;
+/// The most strokes a level may contain
+pub const MAX_STROKES: usize = 1024;
+
+/// A single stroke of the scatter brush
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DeltaEncode)]
+#[delta_always]
+pub struct ScatterStroke {
+    /// Identifies the stroke within the level
+    pub id: u32,
+    /// The palette the props are picked from
+    pub palette: ResourceKey<'static>,
+    /// The x position of the centre of the stroke
+    pub x: f32,
+    /// The y position of the centre of the stroke
+    pub y: f32,
+    /// The radius of the stroke in tiles
+    pub radius: f32,
+    /// The number of props per tile at the centre of the stroke
+    pub density: f32,
+    /// How much the density drops towards the edge of the stroke.
+    ///
+    /// 0 keeps the same density to the edge, 1 drops to nothing
+    pub falloff: f32,
+    /// The seed the props are generated from
+    pub seed: u32,
+}
+
+/// A prop placed by a stroke
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScatterProp {
+    /// The x position of the prop
+    pub x: f32,
+    /// The y position of the prop
+    pub y: f32,
+    /// The rotation of the prop in radians
+    pub rotation: f32,
+    /// A value between 0 and 1 used to pick the prop's variant
+    /// from the palette
+    pub variant: f32,
+}
+
+impl ScatterStroke {
+    /// Checks that the stroke's values are within the allowed
+    /// ranges
+    pub fn validate(&self) -> UResult<()> {
+        if !self.x.is_finite() || !self.y.is_finite() {
+            bail!("Invalid stroke position");
+        }
+        if !(MIN_RADIUS ..= MAX_RADIUS).contains(&self.radius) {
+            bail!("Stroke radius must be between {} and {}", MIN_RADIUS, MAX_RADIUS);
+        }
+        if !(0.0 ..= MAX_DENSITY).contains(&self.density) {
+            bail!("Stroke density must be between 0 and {}", MAX_DENSITY);
+        }
+        if !(0.0 ..= 1.0).contains(&self.falloff) {
+            bail!("Stroke falloff must be between 0 and 1");
+        }
+        Ok(())
+    }
+
+    /// Returns whether the point is covered by the stroke
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let dx = x - self.x;
+        let dy = y - self.y;
+        dx * dx + dy * dy <= self.radius * self.radius
+    }
+
+    /// Generates the props placed by the stroke.
+    ///
+    /// The same stroke always generates the same props
+    pub fn props(&self) -> Vec<ScatterProp> {
+        use std::f32::consts::PI;
+        let mut rng = SeedRng::new(self.seed);
+        let area = PI * self.radius * self.radius;
+        let count = ((area * self.density) as usize).min(MAX_STROKE_PROPS);
+        let mut props = Vec::with_capacity(count);
+        for _ in 0 .. count {
+            // Square root keeps the points evenly spread over the
+            // circle instead of bunching at the centre
+            let dist = rng.next_f32().sqrt();
+            let angle = rng.next_f32() * PI * 2.0;
+            let keep = rng.next_f32();
+            let rotation = rng.next_f32() * PI * 2.0;
+            let variant = rng.next_f32();
+            if keep > 1.0 - self.falloff * dist {
+                continue;
+            }
+            props.push(ScatterProp {
+                x: self.x + angle.cos() * dist * self.radius,
+                y: self.y + angle.sin() * dist * self.radius,
+                rotation,
+                variant,
+            });
+        }
+        props
+    }
+}
+
+/// The strokes painted onto the level
+#[derive(Debug, Default)]
+pub struct LevelScatter {
+    strokes: Vec<ScatterStroke>,
+    next_id: u32,
+    revision: u32,
+}
+
+impl LevelScatter {
+    /// Returns every stroke in the order they were painted
+    pub fn strokes(&self) -> &[ScatterStroke] {
+        &self.strokes
+    }
+
+    /// Returns a value that changes whenever a stroke is added
+    /// or removed
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// Returns the id the next stroke should use
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+
+    /// Adds the stroke to the level
+    pub fn add(&mut self, stroke: ScatterStroke) -> UResult<()> {
+        stroke.validate()?;
+        if self.strokes.len() >= MAX_STROKES {
+            bail!("Too many scatter strokes in the level");
+        }
+        if self.strokes.iter().any(|v| v.id == stroke.id) {
+            bail!("Duplicate scatter stroke id");
+        }
+        self.next_id = self.next_id.max(stroke.id.wrapping_add(1));
+        self.strokes.push(stroke);
+        self.revision = self.revision.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Removes the stroke with the given id, returning it
+    pub fn remove(&mut self, id: u32) -> Option<ScatterStroke> {
+        let idx = self.strokes.iter().position(|v| v.id == id)?;
+        self.revision = self.revision.wrapping_add(1);
+        Some(self.strokes.remove(idx))
+    }
+
+    /// Returns the ids of the strokes covering the point
+    pub fn strokes_at(&self, x: f32, y: f32) -> Vec<u32> {
+        self.strokes.iter()
+            .filter(|v| v.contains(x, y))
+            .map(|v| v.id)
+            .collect()
+    }
+
+    /// Replaces the strokes with ones loaded from a save or the
+    /// level's initial state, skipping any that are invalid
+    pub fn load(&mut self, log: &Logger, strokes: Vec<ScatterStroke>) {
+        self.strokes.clear();
+        for stroke in strokes {
+            if let Err(err) = self.add(stroke) {
+                warn!(log, "Skipping invalid scatter stroke"; "error" => %err);
+            }
+        }
+    }
+}
+
+/// A set of props that can be painted with the scatter brush
+#[derive(Debug)]
+pub struct ScatterPalette {
+    /// The name shown in the editor
+    pub name: String,
+    /// The models of the props and how often each is used
+    pub props: Vec<PaletteProp>,
+    total_weight: u32,
+}
+
+/// A single prop in a palette
+#[derive(Debug)]
+pub struct PaletteProp {
+    /// The model of the prop
+    pub model: ResourceKey<'static>,
+    /// The texture to use instead of the model's own
+    pub texture: Option<ResourceKey<'static>>,
+    weight: u32,
+}
+
+impl ScatterPalette {
+    /// Picks the prop to use for the variant value of a
+    /// generated prop
+    pub fn pick(&self, variant: f32) -> Option<&PaletteProp> {
+        if self.total_weight == 0 {
+            return None;
+        }
+        let mut target = (variant.max(0.0) * self.total_weight as f32) as u32;
+        for prop in &self.props {
+            if target < prop.weight {
+                return Some(prop);
+            }
+            target -= prop.weight;
+        }
+        self.props.last()
+    }
+}
+
+/// Every palette defined by the loaded packs
+#[derive(Debug, Default)]
+pub struct ScatterPalettes {
+    palettes: Vec<(ResourceKey<'static>, ScatterPalette)>,
+}
+
+impl ScatterPalettes {
+    /// Loads the palettes from every pack
+    pub fn load(log: &Logger, assets: &AssetManager) -> ScatterPalettes {
+        let mut palettes = ScatterPalettes::default();
+        for module in assets.get_packs() {
+            let file = match assets.open_from_pack(module.borrow(), "scatter.json") {
+                Ok(val) => val,
+                Err(_) => continue,
+            };
+            let info: FNVMap<String, PaletteInfo> = match serde_json::from_reader(file) {
+                Ok(val) => val,
+                Err(err) => {
+                    error!(log, "Failed to parse scatter.json for pack {:?}: {}", module, err);
+                    continue
+                }
+            };
+            palettes.add(module.borrow(), info);
+        }
+        palettes.palettes.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+        palettes
+    }
+
+    fn add(&mut self, module: ModuleKey<'_>, info: FNVMap<String, PaletteInfo>) {
+        let key = |v: &str| LazyResourceKey::parse(v).or_module(module.borrow()).into_owned();
+        for (name, info) in info {
+            let props: Vec<_> = info.props.into_iter()
+                .map(|v| PaletteProp {
+                    model: key(&v.model),
+                    texture: v.texture.as_ref().map(|v| key(v)),
+                    weight: v.weight,
+                })
+                .collect();
+            let total_weight = props.iter().map(|v| v.weight).sum();
+            self.palettes.push((ResourceKey::new(module.clone(), name.clone()).into_owned(), ScatterPalette {
+                name: info.name.unwrap_or(name),
+                props,
+                total_weight,
+            }));
+        }
+    }
+
+    /// Returns the palette with the given key if it exists
+    pub fn get(&self, key: ResourceKey<'_>) -> Option<&ScatterPalette> {
+        self.palettes.iter()
+            .find(|v| v.0 == key)
+            .map(|v| &v.1)
+    }
+
+    /// Returns every palette sorted by name
+    pub fn iter(&self) -> impl Iterator<Item=(ResourceKey<'_>, &ScatterPalette)> {
+        self.palettes.iter()
+            .map(|v| (v.0.borrow(), &v.1))
+    }
+}
+
+#[derive(Deserialize)]
+struct PaletteInfo {
+    #[serde(default)]
+    name: Option<String>,
+    props: Vec<PalettePropInfo>,
+}
+
+#[derive(Deserialize)]
+struct PalettePropInfo {
+    model: String,
+    #[serde(default)]
+    texture: Option<String>,
+    #[serde(default = "default_weight")]
+    weight: u32,
+}
+
+fn default_weight() -> u32 { 1 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stroke(id: u32) -> ScatterStroke {
+        ScatterStroke {
+            id,
+            palette: ResourceKey::new("base", "trees"),
+            x: 10.0,
+            y: 20.0,
+            radius: 4.0,
+            density: 0.5,
+            falloff: 0.5,
+            seed: 42,
+        }
+    }
+
+    #[test]
+    fn props_are_deterministic() {
+        let a = stroke(0).props();
+        let b = stroke(0).props();
+        assert!(!a.is_empty());
+        assert_eq!(a, b);
+        let other = ScatterStroke { seed: 43, .. stroke(0) }.props();
+        assert_ne!(a, other);
+    }
+
+    #[test]
+    fn props_within_stroke() {
+        let s = stroke(0);
+        for prop in s.props() {
+            assert!(s.contains(prop.x, prop.y));
+            assert!((0.0 .. 1.0).contains(&prop.variant));
+        }
+    }
+
+    #[test]
+    fn falloff_thins_props() {
+        let full = ScatterStroke { falloff: 0.0, .. stroke(0) }.props();
+        let faded = ScatterStroke { falloff: 1.0, .. stroke(0) }.props();
+        let area = std::f32::consts::PI * 16.0 * 0.5;
+        assert_eq!(full.len(), area as usize);
+        assert!(faded.len() < full.len());
+    }
+
+    #[test]
+    fn density_is_capped() {
+        let s = ScatterStroke {
+            radius: MAX_RADIUS,
+            density: MAX_DENSITY,
+            falloff: 0.0,
+            .. stroke(0)
+        };
+        assert_eq!(s.props().len(), MAX_STROKE_PROPS);
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut scatter = LevelScatter::default();
+        scatter.add(stroke(3)).unwrap();
+        assert_eq!(scatter.next_id(), 4);
+        assert!(scatter.add(stroke(3)).is_err());
+        assert!(scatter.add(ScatterStroke { radius: 100.0, .. stroke(4) }).is_err());
+        assert!(scatter.add(ScatterStroke { x: std::f32::NAN, .. stroke(4) }).is_err());
+        assert_eq!(scatter.strokes_at(11.0, 21.0), vec![3]);
+        assert!(scatter.strokes_at(30.0, 30.0).is_empty());
+        let revision = scatter.revision();
+        assert_eq!(scatter.remove(3), Some(stroke(3)));
+        assert_ne!(scatter.revision(), revision);
+        assert!(scatter.remove(3).is_none());
+    }
+
+    #[test]
+    fn palette_pick() {
+        let palette = ScatterPalette {
+            name: "Test".into(),
+            props: vec![
+                PaletteProp { model: ResourceKey::new("base", "a"), texture: None, weight: 3 },
+                PaletteProp { model: ResourceKey::new("base", "b"), texture: None, weight: 1 },
+            ],
+            total_weight: 4,
+        };
+        assert_eq!(palette.pick(0.0).map(|v| v.model.resource()), Some("a"));
+        assert_eq!(palette.pick(0.7).map(|v| v.model.resource()), Some("a"));
+        assert_eq!(palette.pick(0.8).map(|v| v.model.resource()), Some("b"));
+        assert_eq!(palette.pick(0.9999).map(|v| v.model.resource()), Some("b"));
+    }
+}
@@ -107,6 +107,11 @@ pub fn init_levellib<T: script::ScriptTypes>(lua: &lua::Lua) {
         let tile = assets.loader_open::<tile::ById>(id as tile::TileId)?;
         Ok(Ref::new_string(lua, tile.key.as_string()))
     }));
+    lua.set(Scope::Global, "is_season_active", lua::closure1(move |lua, name: Ref<String>| -> UResult<_> {
+        let assets = lua.get_tracked::<AssetManager>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        Ok(assets.is_season_active(&*name))
+    }));
     lua.set(Scope::Global, "level_tile_prop", lua::closure2(move |lua, id: i32, prop: Ref<String>| -> UResult<_> {
         let assets = lua.get_tracked::<AssetManager>()
             .ok_or_else(|| ErrorKind::InvalidState)?;
@@ -130,6 +135,44 @@ pub fn init_levellib<T: script::ScriptTypes>(lua: &lua::Lua) {
             Some(WallInfo{flag: TileWallFlag::Door}) => "door",
         }))
     }));
+    lua.set(Scope::Global, "level_on_change", lua::closure2(move |lua, kinds: Ref<Table>, func: Ref<lua::Function>| -> UResult<i32> {
+        let tiles = lua.get_tracked::<LevelTiles>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let observers = lua.get_tracked::<LevelObservers>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let log = lua.get_tracked::<Logger>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let mut wanted = ChangeKind::empty();
+        for (_, name) in kinds.iter::<i32, Ref<String>>() {
+            wanted |= ChangeKind::from_name(&name)?;
+        }
+        let names: Vec<_> = observer::CHANGE_NAMES.iter()
+            .map(|v| (v.0, lua.intern(v.1)))
+            .collect();
+        let id = observer::subscribe(&tiles, &observers, wanted, move |change| {
+            let name = names.iter()
+                .find(|v| v.0 == change.kind())
+                .map(|v| v.1.clone());
+            let area = change.area();
+            if let Err(err) = func.invoke::<_, ()>((
+                name,
+                area.min.x, area.min.y,
+                area.max.x, area.max.y,
+                change.room().map(|v| i32::from(v.0)),
+            )) {
+                warn!(log, "Level change handler failed: {}", err);
+            }
+        });
+        Ok(id.0 as i32)
+    }));
+    lua.set(Scope::Global, "level_remove_observer", lua::closure1(move |lua, id: i32| -> UResult<()> {
+        let tiles = lua.get_tracked::<LevelTiles>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let observers = lua.get_tracked::<LevelObservers>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        observer::unsubscribe(&tiles, &observers, ObserverId(id as u32));
+        Ok(())
+    }));
     lua.set(Scope::Global, "level_get_room_type_at", lua::closure2(move |lua, x: i32, y: i32| -> UResult<_>{
         let tiles = lua.get_tracked::<LevelTiles>()
             .ok_or_else(|| ErrorKind::InvalidState)?;
@@ -80,12 +80,18 @@ pub mod common;
 pub mod errors;
 pub mod prelude;
 pub mod notify;
+pub mod reward;
 mod spawning;
 pub mod saving;
 pub mod msg;
 pub mod steam;
 pub mod mission;
 pub mod choice;
+pub mod export;
+pub mod metrics;
+pub mod calendar;
+pub mod cutscene;
+pub mod effects;
 
 pub use crate::prelude::UResult;
 
@@ -102,6 +108,8 @@ use crate::assets::AssetsBuilder;
 use lua::{Ref, Table};
 use crate::entity::snapshot::{
     Snapshots,
+    SnapshotEncoder,
+    DeltaTarget,
 };
 use delta_encode::AlwaysVec;
 
@@ -114,6 +122,7 @@ pub fn register_loaders(builder: AssetsBuilder) -> AssetsBuilder {
            .register::<room::Loader>()
            .register::<object::Loader>()
            .register::<Loader<ServerComponent>>()
+           .register::<entity::appearance::Loader>()
 }
 
 /// Server initial configuration
@@ -141,12 +150,168 @@ pub struct ServerConfig {
     /// The name of the mission currently controling this instance
     /// if any.
     pub mission: Option<ResourceKey<'static>>,
+    /// The goal set to play free-play games with if any.
+    ///
+    /// Selected by players in the lobby and ignored when
+    /// a mission is active.
+    pub goals: Option<ResourceKey<'static>>,
     /// The tick rate of the server, default: 20
     pub tick_rate: Cell<u32>,
+    /// Forces the given seasons to be active instead of
+    /// selecting them based on the current date
+    pub seasons: Option<Vec<String>>,
+    /// Whether periodic saves may be written as a diff
+    /// against the last full save
+    pub incremental_saves: bool,
+    /// The number of ticks between writing recovery journals
+    /// for the save, zero disables them
+    pub journal_interval: u32,
+    /// Whether the game is saved when the server shuts down.
+    ///
+    /// When disabled any progress since the last autosave is
+    /// lost on exit
+    pub save_on_exit: bool,
+    /// How players that stop playing are handled
+    pub idle: player::IdleConfig,
+    /// How quickly floors get dirty from entities walking
+    /// across them
+    pub dirt: entity::dirt::DirtConfig,
+    /// How much litter students drop whilst walking around
+    pub litter: entity::litter::LitterConfig,
+    /// When entities far from every player are simplified
+    pub lod: entity::lod::LodConfig,
+    /// How long placed rooms take to build
+    pub construction: entity::construction::ConstructionConfig,
+    /// How often parents and tourists visit and how much they
+    /// affect ratings
+    pub visitors: entity::visitor::VisitorConfig,
+    /// How remote players are authenticated when steam
+    /// isn't available.
+    #[cfg(not(feature = "steam"))]
+    pub auth: ServerAuth,
+}
+
+/// Controls how remote players prove who they are when
+/// joining a server built without steam.
+///
+/// Local connections are never verified.
+#[cfg(not(feature = "steam"))]
+#[derive(Clone)]
+pub enum ServerAuth {
+    /// Anyone can join using any username
+    None,
+    /// Players must provide the server's password
+    Password(String),
+    /// Players must provide the token assigned to their
+    /// username. Usernames without a token can't join.
+    Tokens(FNVMap<String, String>),
+}
+
+#[cfg(not(feature = "steam"))]
+impl ServerAuth {
+    /// Returns whether the passed username and password/token
+    /// are allowed to join the server
+    pub fn verify(&self, name: &str, password: &str) -> bool {
+        match self {
+            ServerAuth::None => true,
+            ServerAuth::Password(expected) => expected == password,
+            ServerAuth::Tokens(tokens) => tokens.get(name).map_or(false, |v| v == password),
+        }
+    }
+}
+
+// Hides the passwords/tokens when the server's config
+// is logged.
+#[cfg(not(feature = "steam"))]
+impl std::fmt::Debug for ServerAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerAuth::None => write!(f, "None"),
+            ServerAuth::Password(_) => write!(f, "Password"),
+            ServerAuth::Tokens(tokens) => write!(f, "Tokens({} users)", tokens.len()),
+        }
+    }
 }
 
 type PlayerInfoMap = FNVMap<PlayerId, PlayerInfo>;
 
+/// Performance information about a single game tick
+#[derive(Clone, Copy, Debug)]
+pub struct TickStats {
+    /// The time taken by the tick
+    pub time: Duration,
+    /// The memory in use by the scripting engine in bytes
+    pub script_memory: usize,
+    /// The time taken by each part of the tick
+    pub phases: metrics::TickTimings,
+}
+
+/// A summary of the server's state for tools managing it,
+/// see `Server::report_status`
+#[derive(Clone, Debug, Serialize)]
+pub struct ServerStatus {
+    /// Either `lobby`, `starting` or `playing`
+    pub state: &'static str,
+    /// The name of the save the game is stored in
+    pub save_name: String,
+    /// The number of ticks the server runs a second
+    pub tick_rate: u32,
+    /// The number of players connected to the server
+    pub connections: usize,
+    /// The players in the game, connected or not
+    pub players: Vec<PlayerStatus>,
+}
+
+/// A player in the game
+#[derive(Clone, Debug, Serialize)]
+pub struct PlayerStatus {
+    /// The id of the player in this game
+    pub id: i16,
+    /// The player's name
+    pub name: String,
+    /// Whether the player is currently connected
+    pub connected: bool,
+    /// Whether the player was marked as away for being idle
+    pub afk: bool,
+    /// The player's money
+    pub money: i64,
+    /// The player's rating
+    pub rating: i16,
+}
+
+/// Progress of the server setting up the game after it
+/// has been started
+#[derive(Clone, Copy, Debug)]
+pub struct LoadProgress {
+    /// The part of the game being loaded
+    pub item: LoadItem,
+    /// The number of steps of the item that are complete
+    pub done: usize,
+    /// The total number of steps of the item
+    pub total: usize,
+}
+
+/// The parts of the game loaded by the server when it starts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadItem {
+    /// The scripts of each asset pack
+    Scripts,
+    /// The level either from the save file or newly generated
+    Level,
+    /// The running choices/idle tasks
+    Choices,
+}
+
+fn report_load(progress: Option<&mpsc::Sender<LoadProgress>>, item: LoadItem, done: usize, total: usize) {
+    if let Some(progress) = progress {
+        let _ = progress.send(LoadProgress {
+            item,
+            done,
+            total,
+        });
+    }
+}
+
 /// A single server instance
 pub struct Server<S: SocketListener, Steam> {
     /// The logger used by the server
@@ -164,6 +329,9 @@ pub struct Server<S: SocketListener, Steam> {
     /// Information about players currently in the game,
     /// connected or not.
     players_info: PlayerInfoMap,
+    /// Encodes the previous tick's snapshot whilst
+    /// the current tick runs
+    snapshot_encoder: SnapshotEncoder<<S::Socket as Socket>::Id>,
 
     next_uid: i16,
 
@@ -171,6 +339,16 @@ pub struct Server<S: SocketListener, Steam> {
     force_save: bool,
     icon_capture: Option<Box<dyn saving::IconCapture>>,
     command_submitter: Option<mpsc::Receiver<String>>,
+    tick_reporter: Option<mpsc::Sender<TickStats>>,
+    status_reporter: Option<mpsc::Sender<ServerStatus>>,
+    /// Ticks until the status is next reported
+    status_ticks: u32,
+    load_reporter: Option<mpsc::Sender<LoadProgress>>,
+    /// Averages the tick timings sent to players showing
+    /// the performance hud
+    perf_average: metrics::TimingAverage,
+    /// The local player's statistics across every game
+    lifetime: player::LifetimeTracker,
 }
 
 #[allow(clippy::large_enum_variant)] // Other variants aren't used much anyway
@@ -188,15 +366,24 @@ enum ServerState {
     Playing {
         // The name of the save file.
         save_name: String,
+        // The last full save that periodic saves are
+        // diffed against
+        incremental_saves: saving::IncrementalSaves,
         level: Level,
 
         spawning: spawning::Spawner,
+        // The types of tasks that can be scheduled to
+        // run later
+        scheduled_tasks: saving::scheduled::TaskRegistry,
         // Whether the game is paused or not
         paused: bool,
 
         scripting: ScriptEngine,
         mission: Option<mission::MissionController>,
-        extra_commands: Rc<RefCell<Vec<command::Command>>>,
+        extra_commands: Rc<RefCell<Vec<script_room::ScriptCommand>>>,
+        // Named entity templates that scripts can spawn
+        // copies of
+        templates: Rc<RefCell<entity::template::EntityTemplates>>,
 
         entities: Container,
         entity_systems: Systems,
@@ -222,6 +409,7 @@ pub fn init_rule_vars(log: &Logger, entities: Option<&mut Container>, assets: &A
     // Setup dynamic entity variables
     let mut globals = choice::BasicAlloc::new(());
     assume!(log, globals.storage_loc(choice::Type::Integer, "time"));
+    assume!(log, globals.storage_loc(choice::Type::Float, "door_congestion"));
 
     let mut student_alloc = choice::BasicAlloc::new(globals);
     for stat in Stats::STUDENT.stats() {
@@ -289,7 +477,8 @@ impl ServerState {
         assets: &AssetManager,
         fs: &F,
         players_info: &mut PlayerInfoMap,
-        config: &ServerConfig, players: &[PlayerId]
+        config: &ServerConfig, players: &[PlayerId],
+        progress: Option<&mpsc::Sender<LoadProgress>>,
     ) -> ServerState {
 
         let mut entities = Container::new();
@@ -297,6 +486,16 @@ impl ServerState {
 
         entities.add_component(Container::WORLD, CLogger{log: log.clone()});
         entities.add_component(Container::WORLD, course::LessonManager::new(log.clone(), assets));
+        entities.add_component(Container::WORLD, reward::RewardTables::load(log, assets));
+        entities.add_component(Container::WORLD, entity::fire::Fires::default());
+        entities.add_component(Container::WORLD, entity::dirt::Dirt::new(&config.dirt));
+        entities.add_component(Container::WORLD, entity::litter::Litter::new(&config.litter));
+        entities.add_component(Container::WORLD, entity::lod::Lod::new(&config.lod));
+        entities.add_component(Container::WORLD, entity::construction::Construction::new(&config.construction));
+        entities.add_component(Container::WORLD, entity::visitor::VisitorRules::new(&config.visitors));
+        entities.add_component(Container::WORLD, player::Trades::default());
+        entities.add_component(Container::WORLD, player::RoomFinances::default());
+        entities.add_component(Container::WORLD, saving::scheduled::ScheduledTasks::default());
 
         let mut systems = Systems::new();
         entity::register_systems(&mut systems);
@@ -306,8 +505,12 @@ impl ServerState {
 
         let scripting = {
             let scripting = ScriptEngine::new(log, assets.clone());
-            for pack in assets.get_packs() {
+            let packs = assets.get_packs();
+            report_load(progress, LoadItem::Scripts, 0, packs.len());
+            scripting.precompile_packs(assets);
+            for (idx, pack) in packs.iter().enumerate() {
                 scripting.init_pack(pack.module());
+                report_load(progress, LoadItem::Scripts, idx + 1, packs.len());
             }
             scripting
         };
@@ -337,6 +540,10 @@ impl ServerState {
                 mission::MissionController::new(log, scripting.clone(), v.borrow())
             });
 
+        let templates = Rc::new(RefCell::new(entity::template::EntityTemplates::default()));
+        scripting.store_tracked::<entity::template::TemplateStore>(templates.clone());
+
+        report_load(progress, LoadItem::Level, 0, 1);
         let level = match saving::load_game(
             fs,
             log,
@@ -349,6 +556,7 @@ impl ServerState {
             &choices,
             &mut running_choices,
             mission.as_mut(),
+            &templates,
             &mut day_tick,
         ) {
             Ok(sav) => sav,
@@ -360,33 +568,59 @@ impl ServerState {
                     players, config.player_area_size
                 ).expect("Failed to spawn level");
                 mission.as_mut().map(|v| v.init(players_info, &mut entities, None));
+                let goals = config.goals.as_ref()
+                    .filter(|_| config.mission.is_none())
+                    .and_then(|v| entity::goals::find_goal_set(log, assets, v.borrow()));
+                if let Some(goals) = goals {
+                    entities.add_component(Container::WORLD, entity::goals::Goals::new(goals));
+                }
                 lvl
             },
             Err(err) => {
                 panic!("Failed to load the save file: {:?}", err)
             },
         };
+        report_load(progress, LoadItem::Level, 1, 1);
+        let mut scheduled_tasks = saving::scheduled::TaskRegistry::default();
+        spawning::register_tasks(&mut scheduled_tasks);
+        mission::register_tasks(&mut scheduled_tasks);
+        spawning::schedule_inspections(log, &mut entities, players);
+        spawning::schedule_visitors(log, &mut entities, players);
+        player::store_campuses(&scripting, players_info.iter()
+            .map(|(id, v)| (*id, v.campus.clone()))
+            .collect());
         // Let choices load
+        report_load(progress, LoadItem::Choices, 0, 1);
         script_room::tick_choices(log, &mut entities, &scripting, players_info, &choices, &mut running_choices);
+        report_load(progress, LoadItem::Choices, 1, 1);
 
         let extra_commands = Rc::new(RefCell::new(vec![]));
         scripting.store_tracked::<script_room::ExtraCommands>(extra_commands.clone());
 
+        let mut pathfinder = Pathfinder::new(Duration::from_millis(10));
+        pathfinder.observe(&level);
+        if let Some(fires) = entities.get_component::<entity::fire::Fires>(Container::WORLD) {
+            fires.observe(&level);
+        }
+
         ServerState::Playing {
             save_name: config.save_name.clone(),
+            incremental_saves: saving::IncrementalSaves::new(config.incremental_saves),
             level,
             spawning: spawning::Spawner::new(log, players),
+            scheduled_tasks,
             paused: false,
             day_tick,
             scripting,
             mission,
+            templates,
             entities,
             entity_systems: systems,
             snapshots,
             choices,
             running_choices,
             entity_dispatcher: EntityDispatcher::new(),
-            pathfinder: Pathfinder::new(Duration::from_millis(10)),
+            pathfinder,
             server_player: script_room::ServerPlayer {
                 state: player::State::None,
                 config: player::PlayerConfig::default(),
@@ -433,6 +667,15 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
         };
         config.locked_players = locked_players;
 
+        let seasons = config.seasons.clone()
+            .unwrap_or_else(|| asset_manager.seasons_for_day(assets::today()));
+        if !seasons.is_empty() {
+            info!(log, "Active seasons: {}", seasons.join(", "));
+        }
+        asset_manager.set_active_seasons(seasons);
+
+        let lifetime = player::LifetimeTracker::load(&log, &fs);
+
         Ok((Server {
             state: ServerState::Lobby {
                 change_id: 0,
@@ -444,6 +687,7 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
             network,
             players: Default::default(),
             players_info,
+            snapshot_encoder: SnapshotEncoder::new(),
 
             asset_manager,
             next_uid: 1,
@@ -452,19 +696,52 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
             shutdown_channel,
             icon_capture,
             command_submitter,
+            tick_reporter: None,
+            status_reporter: None,
+            status_ticks: 0,
+            load_reporter: None,
+            perf_average: metrics::TimingAverage::default(),
             force_save: false,
+            lifetime,
         }, shutdown_wait))
     }
 
+    /// Causes the server to send the time taken and memory
+    /// used by each game tick to the passed channel.
+    ///
+    /// Used for benchmarking.
+    pub fn report_tick_times(&mut self, reporter: mpsc::Sender<TickStats>) {
+        self.tick_reporter = Some(reporter);
+    }
+
+    /// Causes the server to send a summary of its state to
+    /// the passed channel about once a second.
+    ///
+    /// Used by the dedicated server's admin interface.
+    pub fn report_status(&mut self, reporter: mpsc::Sender<ServerStatus>) {
+        self.status_reporter = Some(reporter);
+        self.status_ticks = 0;
+    }
+
+    /// Causes the server to send its progress loading the
+    /// game to the passed channel when the game begins.
+    pub fn report_load_progress(&mut self, reporter: mpsc::Sender<LoadProgress>) {
+        self.load_reporter = Some(reporter);
+    }
+
     /// Runs the server's ticking logic. Returns when the server is closing
     pub fn run(&mut self) {
         'server_loop:
         loop {
             let start = Instant::now();
+            let mut timings = metrics::TickTimings::default();
 
             self.tick();
+            let mark = timings.record(metrics::TickPhase::Network, start);
+            let local_player = self.local_player();
             if let ServerState::Playing{
                 ref save_name,
+                ref mut incremental_saves,
                 ref mut entities,
                 ref mut entity_systems,
                 ref mut level,
@@ -475,12 +752,15 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                 ref mut entity_dispatcher,
                 ref mut pathfinder,
                 ref mut spawning,
+                ref scheduled_tasks,
                 ref paused,
                 ref mut choices,
                 ref mut running_choices,
+                ref templates,
                 ..
             } = self.state {
                 script::handle_reloads(&self.log, scripting, &self.asset_manager);
+                scripting.step_gc();
                 if !*paused {
                     script_room::tick_rooms(&self.log, level, entities, scripting, &mut self.players_info);
                     entity::free_roam::server_tick(
@@ -488,6 +768,8 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                         entities, scripting,
                         &mut self.players_info
                     );
+                    notify::run_callbacks(&self.log, entities, scripting, &mut self.players_info);
+                    let mark = timings.record(metrics::TickPhase::Scripts, mark);
 
                     for player in self.players_info.values_mut() {
                         let network = &mut self.network;
@@ -500,6 +782,7 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
 
                         player.tick(&self.log, networked_player, &self.asset_manager, scripting, level, entities, day_tick);
                     }
+                    let mark = timings.record(metrics::TickPhase::Players, mark);
 
                     day_tick.current_tick += 1;
                     if day_tick.current_tick >= LESSON_LENGTH * 4 {
@@ -508,31 +791,72 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                     }
                     day_tick.time = day_tick.time.wrapping_add(1);
                     choices.global.set_int("time", day_tick.time as i32);
+                    choices.global.set_float("door_congestion", pathfinder.congestion().max());
                     scripting.set(lua::Scope::Global, "global_time", day_tick.time as i32);
 
                     if day_tick.current_tick % LESSON_LENGTH == 0 || self.force_save {
                         self.force_save = false;
                         info!(self.log, "Saving the game");
+                        self.lifetime.save(&self.log, &self.fs);
                         saving::save_game(
                             &mut self.fs,
                             save_name,
                             self.config.save_type,
+                            Some(incremental_saves),
                             &mut self.players_info, level, entities,
                             scripting,
                             choices,
                             running_choices,
                             mission.as_mut(),
+                            templates,
                             day_tick, self.icon_capture.as_ref().map(|v| v.as_ref()),
                         ).expect("Failed to save the game");
+                    } else if self.config.journal_interval != 0
+                        && day_tick.time % self.config.journal_interval == 0
+                    {
+                        // Not fatal, the last autosave is still there
+                        if let Err(err) = saving::write_journal(
+                            &self.fs,
+                            save_name,
+                            self.config.save_type,
+                            incremental_saves,
+                            &mut self.players_info, level, entities,
+                            scripting,
+                            choices,
+                            running_choices,
+                            mission.as_mut(),
+                            templates,
+                            day_tick,
+                        ) {
+                            warn!(self.log, "Failed to write the recovery journal"; "error" => %err);
+                        }
                     }
+                    let mark = timings.record(metrics::TickPhase::Saving, mark);
 
-                    spawning.handle_spawning(&self.asset_manager, &self.players_info, level, entities, scripting);
+                    spawning.handle_spawning(&self.asset_manager, &self.players_info, level, entities, scripting, self.config.idle.pause_intake);
+                    saving::scheduled::run_tasks(scheduled_tasks, &mut saving::scheduled::TaskContext {
+                        log: &self.log,
+                        assets: &self.asset_manager,
+                        level,
+                        entities,
+                        players: &mut self.players_info,
+                        mission: mission.as_mut(),
+                        day: day_tick.day,
+                    });
                     {
                         let pi = &mut self.players_info;
                         mission.as_mut().map(|v| v.update(pi, entities));
                     }
+                    let mark = timings.record(metrics::TickPhase::Spawning, mark);
 
                     script_room::tick_choices(&self.log, entities, scripting, &mut self.players_info, choices, running_choices);
+                    let mark = timings.record(metrics::TickPhase::Scripts, mark);
+                    player::tick_trades(level, entities, &mut self.players_info);
+                    level.dispatch_changes();
+                    let mark = timings.record(metrics::TickPhase::Players, mark);
+                    if let Some(lod) = entities.get_component_mut::<entity::lod::Lod>(Container::WORLD) {
+                        lod.set_areas(self.players.values().filter_map(|v| v.view_area));
+                    }
                     entity_systems.run_with_borrows(entities)
                         .borrow(&*level.tiles.borrow())
                         .borrow(&*level.rooms.borrow())
@@ -542,7 +866,23 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                         .borrow_mut(&mut self.players_info)
                         .borrow(day_tick)
                         .run();
-                    Self::sync_state(entities, *day_tick, snapshots, &mut self.network, &self.players, &self.players_info);
+                    if local_player.is_some() {
+                        self.lifetime.tick(self.config.tick_rate.get());
+                    }
+                    for player in self.players_info.values_mut() {
+                        let graduated = std::mem::replace(&mut player.graduated, 0);
+                        if local_player == Some(player.uid) {
+                            self.lifetime.graduated(graduated);
+                        }
+                    }
+                    let mark = timings.record(metrics::TickPhase::Systems, mark);
+                    Self::sync_state(entities, *day_tick, snapshots, &mut self.snapshot_encoder, &mut self.network, &self.players, &self.players_info);
+                    timings.record(metrics::TickPhase::Sync, mark);
+                } else {
+                    // Don't hold on to the last frame until the game is
+                    // unpaused
+                    Self::send_encoded(&mut self.snapshot_encoder, &mut self.network);
+                    timings.record(metrics::TickPhase::Sync, mark);
                 }
             }
 
@@ -570,14 +910,61 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                             self.force_save = true;
                             info!(self.log, "Forcing a save");
                         },
+                        cmd if cmd == "export" || cmd.starts_with("export ") => {
+                            let format = cmd["export".len()..].trim();
+                            let format = if format.is_empty() {
+                                Some(export::ExportFormat::Csv)
+                            } else {
+                                export::ExportFormat::from_str(format)
+                            };
+                            if let Some(format) = format {
+                                self.export_stats(format);
+                            } else {
+                                warn!(self.log, "Unknown export format, expected csv or json");
+                            }
+                        },
                         cmd => warn!(self.log, "Invalid command: {}", cmd),
                     }
                 }
             }
 
 
+            if self.status_reporter.is_some() {
+                if self.status_ticks == 0 {
+                    self.status_ticks = self.config.tick_rate.get();
+                    let status = self.status();
+                    let closed = self.status_reporter.as_ref()
+                        .map_or(false, |v| v.send(status).is_err());
+                    if closed {
+                        self.status_reporter = None;
+                    }
+                }
+                self.status_ticks -= 1;
+            }
+
             let target_frame_time = Duration::from_secs(1) / self.config.tick_rate.get();
             let frame_time = start.elapsed();
+            if let ServerState::Playing{ref scripting, ref mut spawning, ..} = self.state {
+                spawning.record_tick(frame_time);
+                let usage_changes = scripting.end_usage_tick();
+                let stats = TickStats {
+                    time: frame_time,
+                    script_memory: scripting.gc_count(),
+                    phases: timings,
+                };
+                let closed = self.tick_reporter.as_ref()
+                    .map_or(false, |v| v.send(stats).is_err());
+                if closed {
+                    self.tick_reporter = None;
+                }
+                if let Some(average) = self.perf_average.add(&timings) {
+                    let packs = scripting.pack_usage();
+                    self.send_perf_stats(&average, target_frame_time, packs);
+                }
+                for change in usage_changes {
+                    self.report_usage_change(change);
+                }
+            }
             if frame_time < target_frame_time {
                 thread::sleep(target_frame_time - frame_time);
             }
@@ -590,45 +977,211 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                 ref mut day_tick, ref mut mission,
                 ref choices,
                 ref running_choices,
+                ref templates,
                 ..
         } = self.state {
-            saving::save_game(
-                &mut self.fs,
-                save_name,
-                self.config.save_type,
-                &mut self.players_info, level, entities,
-                scripting,
-                choices,
-                running_choices,
-                mission.as_mut(),
-                day_tick, self.icon_capture.as_ref().map(|v| v.as_ref()),
-            )
-                .expect("Failed to save the game");
+            if self.config.save_on_exit {
+                // Always consolidate when shutting down so that the
+                // save doesn't depend on a diff
+                saving::save_game(
+                    &mut self.fs,
+                    save_name,
+                    self.config.save_type,
+                    None,
+                    &mut self.players_info, level, entities,
+                    scripting,
+                    choices,
+                    running_choices,
+                    mission.as_mut(),
+                    templates,
+                    day_tick, self.icon_capture.as_ref().map(|v| v.as_ref()),
+                )
+                    .expect("Failed to save the game");
+            } else if let Err(err) = saving::discard_journal(&self.fs, save_name) {
+                // A clean shutdown so the journal isn't needed
+                warn!(self.log, "Failed to remove the recovery journal"; "error" => %err);
+            }
         }
+        self.lifetime.save(&self.log, &self.fs);
         // Don't care about the error here as not all users of the server
         // wait on the channel.
         let _ = self.shutdown_channel.send(());
     }
 
+    /// Returns a summary of the server's current state
+    fn status(&self) -> ServerStatus {
+        let mut players: Vec<_> = self.players_info.iter()
+            .map(|(id, info)| {
+                let player = self.players.values()
+                    .find(|v| v.uid == Some(*id));
+                PlayerStatus {
+                    id: id.0,
+                    name: info.name.clone(),
+                    connected: player.is_some(),
+                    afk: player.map_or(false, |v| v.idle.is_afk()),
+                    money: info.money.0,
+                    rating: info.rating,
+                }
+            })
+            .collect();
+        players.sort_by_key(|v| v.id);
+        ServerStatus {
+            state: match self.state {
+                ServerState::Lobby{..} => "lobby",
+                ServerState::BeginGame => "starting",
+                ServerState::Playing{..} => "playing",
+            },
+            save_name: self.config.save_name.clone(),
+            tick_rate: self.config.tick_rate.get(),
+            connections: self.players.len(),
+            players,
+        }
+    }
+
+    /// Sends the tick timings to the host if they are showing
+    /// the performance hud
+    fn send_perf_stats(&mut self, timings: &metrics::TickTimings, budget: Duration, packs: Vec<(String, Duration, bool)>) {
+        let host = self.network.get_host();
+        for (id, player) in &self.players {
+            if !player.wants_perf_stats
+                || !(<S::Socket as Socket>::is_local() || host.as_ref() == Some(id))
+            {
+                continue;
+            }
+            if let Some(connection) = self.network.get_connection(id) {
+                let _ = connection.send(packet::PerfStats {
+                    budget: budget.as_micros() as u32,
+                    phases: AlwaysVec(metrics::TickPhase::ALL.iter()
+                        .map(|v| timings.get(*v).as_micros() as u32)
+                        .collect()),
+                    pack_budget: script::usage::PACK_BUDGET.as_micros() as u32,
+                    packs: AlwaysVec(packs.iter()
+                        .map(|(name, time, throttled)| packet::PackUsage {
+                            name: name.clone(),
+                            time: time.as_micros() as u32,
+                            throttled: *throttled,
+                        })
+                        .collect()),
+                });
+            }
+        }
+    }
+
+    /// Returns the id of the player running the server, either
+    /// the single player or the host of the game
+    fn local_player(&self) -> Option<PlayerId> {
+        if <S::Socket as Socket>::is_local() {
+            self.players.values().find_map(|v| v.uid)
+        } else {
+            self.network.get_host()
+                .and_then(|v| self.players.get(&v))
+                .and_then(|v| v.uid)
+        }
+    }
+
+    /// Tells the host when a pack's scripts are throttled
+    /// for using too much time or restored afterwards
+    fn report_usage_change(&mut self, change: script::usage::UsageChange) {
+        use crate::script::usage::{UsageChange, PACK_BUDGET};
+        let text = match change {
+            UsageChange::Throttled(pack, time) => {
+                warn!(self.log, "Throttling scripts for using too much time"; "pack" => &pack, "time" => ?time);
+                format!(
+                    "The scripts of \"{}\" are taking {:.1}ms a tick which is over their budget of {:.1}ms, \
+                    their idle behaviour will run less often until they use less.",
+                    pack,
+                    time.as_micros() as f64 / 1000.0,
+                    PACK_BUDGET.as_micros() as f64 / 1000.0,
+                )
+            },
+            UsageChange::Restored(pack) => {
+                info!(self.log, "Scripts are back within their budget"; "pack" => &pack);
+                format!("The scripts of \"{}\" are back within their budget.", pack)
+            },
+        };
+        let msg = crate::msg::Message::new()
+            .special()
+            .color(255, 211, 196)
+            .text(text)
+            .build();
+        let host = self.network.get_host();
+        for (id, player) in &self.players {
+            if player.uid.is_none()
+                || !(<S::Socket as Socket>::is_local() || host.as_ref() == Some(id))
+            {
+                continue;
+            }
+            if let Some(connection) = self.network.get_connection(id) {
+                let _ = connection.ensure_send(packet::Message {
+                    messages: AlwaysVec(vec![msg.clone()]),
+                });
+            }
+        }
+    }
+
+    /// Exports the stats of every player in the game into
+    /// the `exports` folder
+    fn export_stats(&mut self, format: export::ExportFormat) {
+        use crate::saving::filesystem::NativeFileSystem;
+        use std::path::Path;
+        if let ServerState::Playing{
+            ref level, ref mut entities,
+            ..
+        } = self.state {
+            let fs = NativeFileSystem::new(Path::new("./exports/"));
+            let time = chrono::Local::now().format("%Y%m%d-%H%M%S");
+            for info in self.players_info.values() {
+                let prefix = format!("stats-{}-{}", info.uid.0, time);
+                let res = export::collect(&self.asset_manager, level, entities, info)
+                    .encode(format)
+                    .and_then(|files| export::write_files(&fs, &prefix, &files));
+                match res {
+                    Ok(files) => info!(self.log, "Exported stats"; "player" => info.uid.0, "files" => ?files),
+                    Err(err) => error!(self.log, "Failed to export stats"; "player" => info.uid.0, "error" => %err),
+                }
+            }
+        } else {
+            warn!(self.log, "No game is running to export stats from");
+        }
+    }
+
     fn sync_state(
         entities: &mut Container,
         day_tick: DayTick,
         snapshots: &mut entity::snapshot::Snapshots,
+        encoder: &mut SnapshotEncoder<<S::Socket as Socket>::Id>,
         network: &mut NetworkManager<S>,
         players: &FNVMap<<S::Socket as Socket>::Id, NetworkedPlayer<S::Socket>>,
         player_info: &FNVMap<PlayerId, PlayerInfo>,
     ) {
+        // The previous frame was encoded whilst this tick was
+        // running and has to be finished before the snapshot
+        // history can be updated again
+        Self::send_encoded(encoder, network);
+
         snapshots.capture(entities, day_tick, player_info.iter());
+        let targets = players.values()
+            .filter(|v| v.remote_state == PlayerState::Playing)
+            .filter_map(|v| Some(DeltaTarget {
+                id: v.id.clone(),
+                uid: v.uid?,
+                entity_state: v.entity_state.clone(),
+                player_state: v.player_state,
+            }))
+            .collect();
+        encoder.submit(snapshots.pending_frame(targets));
+    }
+
+    fn send_encoded(
+        encoder: &mut SnapshotEncoder<<S::Socket as Socket>::Id>,
+        network: &mut NetworkManager<S>,
+    ) {
     'sync:
-        for connection in network.connections() {
-            let player = match players.get(&connection.id) {
+        for (id, packets) in encoder.finish().into_iter().flatten() {
+            let connection = match network.get_connection(&id) {
                 Some(val) => val,
                 None => continue,
             };
-            if player.remote_state != PlayerState::Playing {
-                continue;
-            }
-            let packets = snapshots.create_delta(player);
             for packet in packets {
                 if connection.send(packet).is_err() {
                     continue 'sync;
@@ -643,7 +1196,7 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
         let mut new_commands = Vec::with_capacity(4);
 
         if let ServerState::Playing{
-            ref mut mission, ref mut level,
+            ref mission, ref mut level,
             ref scripting,
             ref mut entities,
             ref mut snapshots,
@@ -653,27 +1206,23 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
             ref choices,
             ..
         } = self.state {
-            let mut list = vec![];
-            let handler = if let Some(mission) = mission.as_mut() {
-                let mut glist = mission.generated_commands.borrow_mut();
-                list.append(&mut *glist);
-                Some(mission.handler.clone())
-            } else {
-                None
-            };
-            {
-                let mut cmds = extra_commands.borrow_mut();
-                list.append(&mut *cmds);
-            }
+            let handler = mission.as_ref().map(|v| v.handler.clone());
+            let list = mem::replace(&mut *extra_commands.borrow_mut(), vec![]);
             if !list.is_empty() {
                 let mut done_cmds = Vec::with_capacity(list.len());
-                for mut cmd in list.drain(..) {
+                let mut failed = false;
+                for mut cmd in list {
+                    // Later commands may depend on the failed one
+                    if failed {
+                        cmd.fail("An earlier command failed".into());
+                        continue;
+                    }
                     let mut h = script_room::Handler {
                         running_choices,
                         choices,
                     };
 
-                    match cmd.execute(&mut h, server_player, command::CommandParams {
+                    match cmd.command.execute(&mut h, server_player, command::CommandParams {
                         log: &self.log,
                         level,
                         engine: scripting,
@@ -681,10 +1230,14 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                         snapshots,
                         mission_handler: handler.as_ref().map(|v| v.borrow()),
                     }) {
-                        Ok(_) => if cmd.should_sync() { done_cmds.push(cmd) },
+                        Ok(_) => {
+                            cmd.complete();
+                            if cmd.command.should_sync() { done_cmds.push(cmd.command) }
+                        },
                         Err(err) => {
                             error!(self.log, "failed to exec command: {:?}", err);
-                            break;
+                            cmd.fail(format!("{}", err));
+                            failed = true;
                         },
                     };
                 }
@@ -692,6 +1245,8 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
             }
         }
         let mut messages = vec![];
+        let mut voice = vec![];
+        let mut status_changes = vec![];
         let log = &self.log;
         for connection in self.network.connections() {
             let id = connection.id.clone();
@@ -700,13 +1255,23 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                 &mut self.state,
                 &self.asset_manager,
                 &mut self.fs,
-                &self.config,
+                &mut self.config,
                 connection, self.next_uid,
                 &mut self.players_info,
                 &self.steam,
             ) {
                 self.next_uid += 1;
+                let mut info = info;
+                let taken: Vec<u8> = self.players_info.values()
+                    .map(|v| v.profile.color)
+                    .collect();
+                info.profile.color = player::free_color(&taken);
                 self.players_info.insert(info.uid, info);
+                if let ServerState::Playing{ref scripting, ..} = self.state {
+                    player::store_campuses(scripting, self.players_info.iter()
+                        .map(|(id, v)| (*id, v.campus.clone()))
+                        .collect());
+                }
             }
             if player.wants_save {
                 self.force_save = true;
@@ -719,6 +1284,48 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                 player.local_state = PlayerState::Closed;
                 player.remote_state = PlayerState::Closed;
             }
+            // Idle check, no one to wait on in single player
+            if !<S::Socket as Socket>::is_local() {
+                let in_lobby = player.remote_state == PlayerState::Lobby;
+                let action = player.uid
+                    .and_then(|uid| self.players_info.get_mut(&uid))
+                    .and_then(|info| player.idle.check(Instant::now(), &self.config.idle, in_lobby).map(|v| (info, v)));
+                if let Some((info, action)) = action {
+                    match action {
+                        player::IdleAction::Warn(remaining) => {
+                            let _ = connection.ensure_send(packet::AfkWarning {
+                                seconds: remaining.as_secs() as u32,
+                                kick: player.idle.is_afk(),
+                            });
+                        },
+                        player::IdleAction::Away | player::IdleAction::Returned => {
+                            info.afk = action == player::IdleAction::Away;
+                            status_changes.push((info.uid, info.afk));
+                            let (r, g, b) = info.profile.rgb();
+                            messages.push(crate::msg::Message::new()
+                                .color(r, g, b)
+                                .text(info.name.as_str())
+                                .color(255, 255, 0)
+                                .text(if info.afk { " is away" } else { " is back" })
+                                .build());
+                            if let ServerState::Lobby{change_id, ..} = self.state {
+                                self.state = ServerState::Lobby {
+                                    change_id,
+                                    state_dirty: true
+                                };
+                            }
+                        },
+                        player::IdleAction::Kick => {
+                            info!(log, "Removing idle player from the lobby"; "uid" => ?info.uid);
+                            let _ = connection.ensure_send(packet::ServerConnectionFail {
+                                code: errors::ErrorCode::IdleTimeout.code(),
+                                detail: None,
+                            });
+                            player.local_state = PlayerState::Closed;
+                        },
+                    }
+                }
+            }
             if player.local_state == PlayerState::Closed || player.remote_state == PlayerState::Closed {
                 connection.close();
             }
@@ -728,6 +1335,7 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                     let cmds = mem::replace(&mut player.commands, vec![]);
                     new_commands.push((info.uid, cmds));
                     messages.append(&mut player.messages);
+                    voice.extend(player.voice.drain(..).map(|v| (uid, v)));
                 }
             }
         }
@@ -758,13 +1366,19 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                     }
                     if let Some(uid) = player.uid {
                         let info = assume!(log, players_info.get_mut(&uid));
+                        let (r, g, b) = info.profile.rgb();
                         let msg = crate::msg::Message::new()
-                            .color(130, 237, 123)
+                            .color(r, g, b)
                             .text(info.name.as_str())
                             .color(255, 255, 0)
                             .text(" has left the server")
                             .build();
                         messages.push(msg);
+                        // They start active again if they rejoin
+                        if info.afk {
+                            info.afk = false;
+                            status_changes.push((uid, false));
+                        }
                     }
                     // Players can freely drop in the lobby.
                     // The game however we keep their spot.
@@ -802,6 +1416,95 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
             });
         }
 
+        if let ServerState::Playing{ref mut spawning, ref entities, ..} = self.state {
+            if let Some(report) = spawning.stress_report(entities) {
+                messages.push(crate::msg::Message::new()
+                    .special()
+                    .color(255, 211, 196)
+                    .text(report)
+                    .build());
+            }
+        }
+
+        if !status_changes.is_empty() {
+            for connection in self.network.connections() {
+                let playing = self.players.get(&connection.id)
+                    .map_or(false, |v| v.uid.is_some() && v.remote_state == PlayerState::Playing);
+                if playing {
+                    for &(player, afk) in &status_changes {
+                        let _ = connection.ensure_send(packet::UpdatePlayerStatus {
+                            player,
+                            afk,
+                        });
+                    }
+                }
+            }
+        }
+
+        if let ServerState::Playing{ref mut entities, ..} = self.state {
+            let changes = entities.get_component_mut::<entity::dirt::Dirt>(Container::WORLD)
+                .map_or_else(Vec::new, |v| v.take_changes());
+            if !changes.is_empty() {
+                let tiles: Vec<_> = changes.into_iter()
+                    .map(|(loc, stage)| packet::DirtTile {
+                        x: loc.x,
+                        y: loc.y,
+                        stage,
+                    })
+                    .collect();
+                // Players still loading are sent every tile once
+                // they finish
+                for connection in self.network.connections() {
+                    let playing = self.players.get(&connection.id)
+                        .map_or(false, |v| v.uid.is_some() && v.remote_state == PlayerState::Playing);
+                    if playing {
+                        let _ = connection.ensure_send(packet::DirtUpdate {
+                            tiles: AlwaysVec(tiles.clone()),
+                        });
+                    }
+                }
+            }
+            let changes = entities.get_component_mut::<entity::litter::Litter>(Container::WORLD)
+                .map_or_else(Vec::new, |v| v.take_changes());
+            if !changes.is_empty() {
+                let tiles: Vec<_> = changes.into_iter()
+                    .map(|(loc, pieces)| packet::LitterTile {
+                        x: loc.x,
+                        y: loc.y,
+                        pieces,
+                    })
+                    .collect();
+                for connection in self.network.connections() {
+                    let playing = self.players.get(&connection.id)
+                        .map_or(false, |v| v.uid.is_some() && v.remote_state == PlayerState::Playing);
+                    if playing {
+                        let _ = connection.ensure_send(packet::LitterUpdate {
+                            tiles: AlwaysVec(tiles.clone()),
+                        });
+                    }
+                }
+            }
+            let changes = entities.get_component_mut::<entity::construction::Construction>(Container::WORLD)
+                .map_or_else(Vec::new, |v| v.take_changes());
+            if !changes.is_empty() {
+                let rooms: Vec<_> = changes.into_iter()
+                    .map(|(room_id, stage)| packet::ConstructionSite {
+                        room_id,
+                        stage,
+                    })
+                    .collect();
+                for connection in self.network.connections() {
+                    let playing = self.players.get(&connection.id)
+                        .map_or(false, |v| v.uid.is_some() && v.remote_state == PlayerState::Playing);
+                    if playing {
+                        let _ = connection.ensure_send(packet::ConstructionUpdate {
+                            rooms: AlwaysVec(rooms.clone()),
+                        });
+                    }
+                }
+            }
+        }
+
         if !messages.is_empty() {
             for connection in self.network.connections() {
                 let id = connection.id.clone();
@@ -816,6 +1519,66 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
             }
         }
 
+        // Relay voice to everyone but the speaker. Late voice is
+        // useless so this doesn't need to be reliable
+        if !voice.is_empty() {
+            for connection in self.network.connections() {
+                let uid = if let Some(uid) = self.players.get(&connection.id).and_then(|v| v.uid) {
+                    uid
+                } else {
+                    continue
+                };
+                for (speaker, data) in &voice {
+                    if *speaker != uid {
+                        let _ = connection.send(packet::RemoteVoiceData {
+                            player_id: *speaker,
+                            data: packet::Raw(data.clone()),
+                        });
+                    }
+                }
+            }
+        }
+
+        // Cutscenes are held until every connected player has
+        // loaded so that they all start it together
+        let all_loaded = self.players.values()
+            .filter(|v| v.uid.is_some())
+            .all(|v| v.remote_state == PlayerState::Playing);
+        if all_loaded {
+            for connection in self.network.connections() {
+                let info = self.players.get(&connection.id)
+                    .and_then(|v| v.uid)
+                    .and_then(|uid| self.players_info.get_mut(&uid));
+                if let Some(info) = info {
+                    for cutscene in info.cutscenes.drain(..) {
+                        let _ = connection.ensure_send(packet::PlayCutscene {
+                            cutscene,
+                        });
+                    }
+                }
+            }
+        }
+
+        // Effects only make sense as they happen so they are
+        // dropped for players that are still loading
+        for connection in self.network.connections() {
+            let player = self.players.get(&connection.id);
+            let playing = player.map_or(false, |v| v.remote_state == PlayerState::Playing);
+            let info = player
+                .and_then(|v| v.uid)
+                .and_then(|uid| self.players_info.get_mut(&uid));
+            if let Some(info) = info {
+                if playing && !info.effects.is_empty() {
+                    let _ = connection.ensure_send(packet::PlayEffects {
+                        effects: AlwaysVec(info.effects.drain(..).collect()),
+                    });
+                }
+            }
+        }
+        for info in self.players_info.values_mut() {
+            info.effects.clear();
+        }
+
         match self.state {
             // If there is a change in the lobby update players
             ServerState::Lobby{change_id, state_dirty: true} => {
@@ -836,6 +1599,9 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                             },
                             uid,
                             ready: p.remote_state == PlayerState::Lobby,
+                            afk: info.afk,
+                            campus: info.campus.clone(),
+                            profile: info.profile.clone(),
                         }
                     })
                     .collect();
@@ -846,6 +1612,19 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                 let can_start = player_count as u32 >= self.config.min_players
                             && player_count as u32 <= self.config.max_players;
 
+                // Loaded games keep the goals they were saved with
+                let goal_sets: Vec<_> = if self.config.mission.is_none() && !self.config.locked_players {
+                    entity::goals::load_goal_sets(&self.log, &self.asset_manager).into_iter()
+                        .map(|v| packet::GoalSetEntry {
+                            key: v.key,
+                            name: v.display_name,
+                            description: v.description,
+                        })
+                        .collect()
+                } else {
+                    vec![]
+                };
+
                 // Update players in the lobby with the new info
                 for connection in self.network.connections() {
                     if let Some(&mut NetworkedPlayer{remote_state: PlayerState::Lobby, ..}) = self.players.get_mut(&connection.id) {
@@ -854,6 +1633,8 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                             change_id: id,
                             players: AlwaysVec(players.clone()),
                             can_start,
+                            goal_sets: AlwaysVec(goal_sets.clone()),
+                            goals: self.config.goals.clone(),
                         });
                     }
                 }
@@ -863,16 +1644,41 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                 }
             },
             ServerState::BeginGame => {
+                // Packs may have changed since the campuses were picked
+                let parts = player::load_campus_parts(&self.log, &self.asset_manager);
+                for p in self.players_info.values_mut() {
+                    let fallback = player::CampusIdentity::named(&p.name).name;
+                    p.campus.sanitize(&parts, &fallback);
+                }
+                let parts = player::load_profile_parts(&self.log, &self.asset_manager);
+                // Earlier players keep their colour if two somehow match
+                let mut profiles: Vec<_> = self.players_info.values_mut().collect();
+                profiles.sort_by_key(|v| v.uid.0);
+                let mut taken = Vec::with_capacity(profiles.len());
+                for p in profiles {
+                    p.profile.sanitize(&parts, &taken);
+                    taken.push(p.profile.color);
+                }
                 let players: Vec<_> = self.players_info.values()
                     .map(|p| packet::PlayerEntry {
                         uid: p.uid,
                         username: p.name.clone(),
                         state: p.state.clone(),
+                        campus: p.campus.clone(),
+                        profile: p.profile.clone(),
                     })
                     .collect();
 
                 let player_ids: Vec<PlayerId> = players.iter().map(|v| v.uid).collect();
-                self.state = ServerState::create_play_state(&self.log, &self.asset_manager, &mut self.fs, &mut self.players_info, &self.config, &player_ids);
+                // Players are only locked when loading an existing save
+                if !self.config.locked_players && self.local_player().is_some() {
+                    self.lifetime.campus_created();
+                }
+                self.state = ServerState::create_play_state(
+                    &self.log, &self.asset_manager, &mut self.fs, &mut self.players_info,
+                    &self.config, &player_ids,
+                    self.load_reporter.as_ref(),
+                );
 
                 if let ServerState::Playing{
                     ref level, ref mission,
@@ -887,7 +1693,7 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                     for connection in self.network.connections() {
                         if let Some(player) = self.players.get_mut(&connection.id) {
                             if player.remote_state == PlayerState::Lobby && player.uid.is_some() {
-                                let _ = connection.ensure_send(packet::GameBegin {
+                                let _ = connection.send_large(packet::GameBegin {
                                     uid: assume!(self.log, player.uid).0,
                                     width: level.width,
                                     height: level.height,
@@ -896,6 +1702,7 @@ impl <S: SocketListener, Steam: steam::Steam> Server<S, Steam> {
                                     strings: AlwaysVec(lstr.clone()),
                                     state: lstate.clone(),
                                     idle_state: idle.clone(),
+                                    seasons: AlwaysVec(self.asset_manager.active_seasons()),
                                 });
                                 player.remote_state = PlayerState::Loading;
                                 player.local_state = PlayerState::Playing;
@@ -963,4 +1770,12 @@ pub struct ModMeta {
     /// The steamworks id of the mod
     #[cfg(feature = "steam")]
     pub workshop_id: steamworks::PublishedFileId,
+    /// The version of the script api the mod was written
+    /// against. Missing for mods made before the api was
+    /// versioned
+    #[serde(default)]
+    pub api_version: Option<u32>,
+    /// The modules that must be loaded before the mod
+    #[serde(default)]
+    pub dependencies: Vec<String>,
 }
\ No newline at end of file
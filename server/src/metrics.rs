@@ -0,0 +1,144 @@
+//! Timings of the parts of a server tick.
+//!
+//! Used to report where the server is spending its time to the
+//! host's performance hud.
+
+use std::time::{Duration, Instant};
+
+/// The number of ticks averaged into a single report
+pub const REPORT_INTERVAL: u32 = 20;
+
+/// A timed part of the server's tick
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TickPhase {
+    /// Handling packets and commands from players
+    Network,
+    /// Room, choice and idle scripts
+    Scripts,
+    /// Updating player state and trades
+    Players,
+    /// Saving the game
+    Saving,
+    /// Spawning students, scheduled tasks and missions
+    Spawning,
+    /// Running the entity systems
+    Systems,
+    /// Encoding and sending the entity state to players
+    Sync,
+}
+
+/// The number of phases in a tick
+pub const PHASE_COUNT: usize = 7;
+
+impl TickPhase {
+    /// Every phase in the order they run
+    pub const ALL: [TickPhase; PHASE_COUNT] = [
+        TickPhase::Network,
+        TickPhase::Scripts,
+        TickPhase::Players,
+        TickPhase::Saving,
+        TickPhase::Spawning,
+        TickPhase::Systems,
+        TickPhase::Sync,
+    ];
+
+    /// Returns a readable name for the phase
+    pub fn name(self) -> &'static str {
+        match self {
+            TickPhase::Network => "Network",
+            TickPhase::Scripts => "Scripts",
+            TickPhase::Players => "Players",
+            TickPhase::Saving => "Saving",
+            TickPhase::Spawning => "Spawning",
+            TickPhase::Systems => "Entity systems",
+            TickPhase::Sync => "Entity sync",
+        }
+    }
+}
+
+/// The time taken by each phase of a tick
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TickTimings {
+    phases: [Duration; PHASE_COUNT],
+}
+
+impl TickTimings {
+    /// Adds the time since `start` to the phase, returning
+    /// the current time to be used as the start of the
+    /// next phase
+    pub fn record(&mut self, phase: TickPhase, start: Instant) -> Instant {
+        let now = Instant::now();
+        self.phases[phase as usize] += now.duration_since(start);
+        now
+    }
+
+    /// Returns the time taken by the phase
+    pub fn get(&self, phase: TickPhase) -> Duration {
+        self.phases[phase as usize]
+    }
+
+    /// Returns the time taken by every phase
+    pub fn total(&self) -> Duration {
+        self.phases.iter().sum()
+    }
+}
+
+/// Averages tick timings over `REPORT_INTERVAL` ticks
+#[derive(Default)]
+pub struct TimingAverage {
+    total: TickTimings,
+    ticks: u32,
+}
+
+impl TimingAverage {
+    /// Adds a tick's timings returning the average once
+    /// enough ticks have been collected
+    pub fn add(&mut self, timings: &TickTimings) -> Option<TickTimings> {
+        for (total, time) in self.total.phases.iter_mut().zip(&timings.phases) {
+            *total += *time;
+        }
+        self.ticks += 1;
+        if self.ticks < REPORT_INTERVAL {
+            return None;
+        }
+        let mut average = TickTimings::default();
+        for (avg, total) in average.phases.iter_mut().zip(&self.total.phases) {
+            *avg = *total / self.ticks;
+        }
+        *self = TimingAverage::default();
+        Some(average)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average() {
+        let mut timings = TickTimings::default();
+        timings.phases[TickPhase::Systems as usize] = Duration::from_millis(4);
+        timings.phases[TickPhase::Sync as usize] = Duration::from_millis(2);
+
+        let mut avg = TimingAverage::default();
+        for _ in 0 .. REPORT_INTERVAL - 1 {
+            assert!(avg.add(&timings).is_none());
+        }
+        let mut slow = timings;
+        slow.phases[TickPhase::Systems as usize] += Duration::from_millis(REPORT_INTERVAL as u64);
+        let res = avg.add(&slow).unwrap();
+        assert_eq!(res.get(TickPhase::Systems), Duration::from_millis(5));
+        assert_eq!(res.get(TickPhase::Sync), Duration::from_millis(2));
+        assert_eq!(res.total(), Duration::from_millis(7));
+
+        // Starts again after reporting
+        assert!(avg.add(&timings).is_none());
+    }
+
+    #[test]
+    fn all_phases() {
+        for (idx, phase) in TickPhase::ALL.iter().enumerate() {
+            assert_eq!(*phase as usize, idx);
+        }
+    }
+}
@@ -4,8 +4,11 @@
 use crate::prelude::*;
 use crate::common;
 use lua;
-use std::cell::RefCell;
 use std::sync::Arc;
+use serde_cbor;
+use serde_transcode;
+use crate::script_room::{ScriptCommand, CommandResult};
+use crate::saving::scheduled::{ScheduledTask, ScheduledTasks, TaskContext, TaskRegistry};
 
 /// Manages mission scripts
 pub struct MissionController {
@@ -13,8 +16,6 @@ pub struct MissionController {
     engine: ScriptEngine,
     _info: common::MissionEntry,
     pub(crate) handler: ResourceKey<'static>,
-    /// List of mission generated commands
-    pub generated_commands: RefCell<Vec<Command>>,
 }
 
 impl MissionController {
@@ -36,7 +37,6 @@ impl MissionController {
             engine,
             handler: info.get_handler_key().into_owned(),
             _info: info,
-            generated_commands: RefCell::new(Vec::new()),
         }
     }
 
@@ -108,6 +108,61 @@ impl MissionController {
             },
         }
     }
+
+    /// Calls the scheduled event function for the mission with
+    /// the event scheduled via `control_schedule_event`
+    pub(crate) fn scheduled_event(
+        &mut self,
+        players: &mut crate::PlayerInfoMap,
+        entities: &mut Container,
+        name: &str,
+        data: &[u8],
+    ) -> UResult<()> {
+        let mut de = serde_cbor::de::Deserializer::from_slice(data);
+        let data = lua::with_table_serializer(&self.engine, |se| {
+            serde_transcode::transcode(&mut de, se)
+        })?;
+        self.engine.with_borrows()
+            .borrow(&MissionAllowed)
+            .borrow(self)
+            .borrow_mut(entities)
+            .borrow_mut(players)
+            .invoke_function::<_, ()>("invoke_module_method", (
+                lua::Ref::new_string(&self.engine, self.handler.module()),
+                lua::Ref::new_string(&self.engine, self.handler.resource()),
+                lua::Ref::new_string(&self.engine, "on_scheduled_event"),
+                lua::Ref::new_string(&self.engine, name),
+                data,
+            ))?;
+        Ok(())
+    }
+}
+
+/// Registers the scheduled tasks used by missions
+pub fn register_tasks(registry: &mut TaskRegistry) {
+    registry.register::<MissionEvent>();
+}
+
+/// An event scheduled by the mission script to be passed
+/// back to it once its delay has passed
+#[derive(Debug, Serialize, Deserialize)]
+struct MissionEvent {
+    name: String,
+    /// The script's data for the event encoded as cbor
+    data: Vec<u8>,
+}
+
+impl ScheduledTask for MissionEvent {
+    const KIND: &'static str = "mission:event";
+
+    fn run(self, ctx: &mut TaskContext<'_>) -> UResult<()> {
+        let mission = if let Some(mission) = ctx.mission.as_mut() {
+            mission
+        } else {
+            bail!("No mission is active for the event {}", self.name)
+        };
+        mission.scheduled_event(ctx.players, ctx.entities, &self.name, &self.data)
+    }
 }
 
 /// Used to restrict certain api calls to only run during
@@ -116,7 +171,7 @@ pub struct MissionAllowed;
 
 /// Sets up a interface for scripts to interface with
 pub fn init_missionlib(lua: &lua::Lua) {
-    use lua::{Ref, Scope, Table};
+    use lua::{Ref, Scope, Table, Unknown};
 
     lua.set(Scope::Global, "control_get_players", lua::closure(|lua| -> Ref<Table> {
         let _limit = lua.get_borrow::<MissionAllowed>();
@@ -133,13 +188,125 @@ pub fn init_missionlib(lua: &lua::Lua) {
         players.get_mut(&PlayerId(id as i16))
             .map(|v| v.change_money(UniDollar(i64::from(amount))))
     }));
-    lua.set(Scope::Global, "control_submit_command", lua::closure1(|lua, cmd: Ref<Command>| {
+    // Plays the cutscene for every player, see the `cutscene` module
+    // for the format
+    lua.set(Scope::Global, "control_play_cutscene", lua::closure1(|lua, cutscene: Ref<Table>| -> UResult<()> {
+        let _limit = lua.get_borrow::<MissionAllowed>();
+        let cutscene = crate::cutscene::Cutscene::from_lua(&cutscene)?;
+        let mut players = lua.write_borrow::<crate::PlayerInfoMap>();
+        for player in players.values_mut() {
+            player.cutscenes.push(cutscene.clone());
+        }
+        Ok(())
+    }));
+    // Plays a visual effect for every player, see the `effects`
+    // module for the effects and their parameters
+    lua.set(Scope::Global, "control_play_effect", lua::closure2(|lua, kind: Ref<String>, desc: Ref<Table>| -> UResult<()> {
         let _limit = lua.get_borrow::<MissionAllowed>();
-        let ctrl = lua.get_borrow::<MissionController>();
-        let mut list = ctrl.generated_commands.borrow_mut();
-        list.push(Command::clone(&cmd));
+        let effect = crate::effects::VisualEffect::from_lua(&kind, &desc)?;
+        let mut players = lua.write_borrow::<crate::PlayerInfoMap>();
+        for player in players.values_mut() {
+            if player.effects.len() >= crate::effects::MAX_PENDING {
+                bail!("Too many visual effects played at once, the limit is {}", crate::effects::MAX_PENDING);
+            }
+            player.effects.push(effect.clone());
+        }
+        Ok(())
+    }));
+    // Sets the tile on fire, see the `entity::fire` module
+    lua.set(Scope::Global, "control_start_fire", lua::closure2(|lua, x: i32, y: i32| {
+        let _limit = lua.get_borrow::<MissionAllowed>();
+        let mut entities = lua.write_borrow::<Container>();
+        if let Some(fires) = entities.get_component_mut::<crate::entity::fire::Fires>(Container::WORLD) {
+            fires.start(Location::new(x, y));
+        }
     }));
+    // Calls the mission's `on_scheduled_event` method with the
+    // name and data after the delay (in ticks). Pending events
+    // are kept in the save so the mission doesn't need to save
+    // them itself
+    lua.set(Scope::Global, "control_schedule_event", lua::closure3(|lua, delay: i32, name: Ref<String>, data: Ref<Table>| -> UResult<()> {
+        let _limit = lua.get_borrow::<MissionAllowed>();
+        let mut se = serde_cbor::ser::Serializer::new(vec![]);
+        lua::with_table_deserializer(&data, |de| {
+            serde_transcode::transcode(de, &mut se)
+        })?;
+        let mut entities = lua.write_borrow::<Container>();
+        let tasks = entities.get_component_mut::<ScheduledTasks>(Container::WORLD)
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        tasks.schedule(delay.max(0) as u32, &MissionEvent {
+            name: String::from(&*name),
+            data: se.into_inner(),
+        })
+    }));
+    lua.set(Scope::Global, "control_submit_command", lua::closure1(|lua, cmd: Ref<Command>| -> UResult<Ref<CommandResult>> {
+        let _limit = lua.get_borrow::<MissionAllowed>();
+        ScriptCommand::submit(lua, Command::clone(&cmd))
+    }));
+    // Submits a command to be validated and executed in the same way
+    // as commands from the network. Returns a handle to the result.
+    //
+    // Only commands for the server player (0) can be synced to
+    // every client so commands for other players are rejected.
+    lua.set(Scope::Global, "submit_command", lua::closure2(|lua, player: i32, cmd: Ref<Unknown>| -> UResult<Ref<CommandResult>> {
+        let cmd = match command_from_lua(lua, &cmd) {
+            Ok(cmd) => cmd,
+            Err(err) => return Ok(ScriptCommand::rejected(lua, "Unknown", format!("{}", err))),
+        };
+        if player != 0 {
+            return Ok(ScriptCommand::rejected(lua, cmd.name(), format!("Can't submit commands for player {}", player)));
+        }
+        ScriptCommand::submit(lua, cmd)
+    }));
+
+}
 
+/// Converts the script value into a command.
+///
+/// Accepts either a command created by one of the `control.cmd`
+/// constructors or a table describing the command, e.g.
+/// `{type = "exec_room", room = id, method = "name", data = data}`.
+///
+/// Several commands can be executed all-or-nothing with
+/// `{type = "batch", commands = {cmd_a, cmd_b}}`
+fn command_from_lua(lua: &lua::Lua, cmd: &lua::Ref<lua::Unknown>) -> UResult<Command> {
+    use lua::{Ref, Table, Unknown};
+    if let Ok(cmd) = cmd.try_convert::<Ref<Command>>() {
+        return Ok(Command::clone(&cmd));
+    }
+    let tbl = cmd.try_convert::<Ref<Table>>()
+        .map_err(|_| ErrorKind::Msg("Expected a command or a command table".into()))?;
+    let field = |name: &str| Ref::new_string(lua, name);
+    let data = || tbl.get::<_, Ref<Arc<bitio::Writer<Vec<u8>>>>>(field("data"))
+        .map(|v| common::ScriptData(Arc::clone(&v)))
+        .ok_or_else(|| ErrorKind::Msg("Missing command data".into()));
+    let ty = tbl.get::<_, Ref<String>>(field("type"))
+        .ok_or_else(|| ErrorKind::Msg("Missing command type".into()))?;
+    Ok(match &*ty {
+        "exec_mission" => {
+            let _limit = lua.get_borrow::<MissionAllowed>();
+            ExecMission::new(data()?).into()
+        },
+        "exec_room" => {
+            let room = tbl.get::<_, i32>(field("room"))
+                .ok_or_else(|| ErrorKind::Msg("Missing room id".into()))?;
+            let method = tbl.get::<_, Ref<String>>(field("method"))
+                .ok_or_else(|| ErrorKind::Msg("Missing room method".into()))?;
+            ExecRoom::new(RoomId(room as i16), method.to_string(), data()?).into()
+        },
+        "batch" => {
+            let list = tbl.get::<_, Ref<Table>>(field("commands"))
+                .ok_or_else(|| ErrorKind::Msg("Missing batch commands".into()))?;
+            let mut commands = Vec::with_capacity(list.length().max(0) as usize);
+            for idx in 1 ..= list.length() {
+                let cmd = list.get::<_, Ref<Unknown>>(idx)
+                    .ok_or_else(|| ErrorKind::Msg("Missing batch command".into()))?;
+                commands.push(command_from_lua(lua, &cmd)?);
+            }
+            Batch::new(commands)?.into()
+        },
+        ty => bail!("Unknown command type: {}", ty),
+    })
 }
 
 /// Sets up a interface for scripts to interface with
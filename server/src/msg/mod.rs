@@ -1,6 +1,7 @@
 //! Formatted message handling
 
 use crate::prelude::*;
+use crate::network::packet::HistoryEntry;
 
 /// Helper to create messages
 pub struct MessageBuilder {
@@ -34,6 +35,12 @@ impl MessageBuilder {
         self
     }
 
+    /// Appends a chart part to the message
+    pub fn chart(mut self, chart: SharedChart) -> Self {
+        self.parts.push(MsgPart::Chart(chart));
+        self
+    }
+
     /// Append a text part to the message based on the current settings
     pub fn text<S: Into<String>>(mut self, text: S) -> Self {
         self.parts.push(MsgPart::Text {
@@ -96,6 +103,45 @@ pub enum MsgPart {
         /// the client.
         special: bool,
     },
+    /// A stat chart shared by a player.
+    ///
+    /// Only the data is sent, the client renders it
+    Chart(SharedChart),
+}
+
+/// A snapshot of one of a player's stat charts
+#[derive(Debug, Clone, PartialEq, DeltaEncode)]
+pub struct SharedChart {
+    /// The stat being displayed
+    pub kind: ChartKind,
+    /// The player's stat history at the time it was
+    /// shared, oldest first
+    pub history: Vec<HistoryEntry>,
+}
+
+/// The types of stat charts that can be shared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DeltaEncode)]
+pub enum ChartKind {
+    /// The total money over time
+    Money,
+    /// The income and outcome over time
+    Income,
+    /// The number of students over time
+    Students,
+    /// The grades of students over time
+    Grades,
+}
+
+impl ChartKind {
+    /// Returns the display name of the chart
+    pub fn name(self) -> &'static str {
+        match self {
+            ChartKind::Money => "money",
+            ChartKind::Income => "income",
+            ChartKind::Students => "students",
+            ChartKind::Grades => "grades",
+        }
+    }
 }
 
 /// A color for a message part
@@ -38,6 +38,7 @@ impl Socket for LoopbackSocket {
             inner: self.send,
         }, Receiver {
             inner: self.recv,
+            transfers: Default::default(),
         })
     }
 }
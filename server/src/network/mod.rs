@@ -9,6 +9,9 @@ pub use self::local::*;
 pub mod udp;
 pub use self::udp::*;
 
+pub mod transfer;
+pub use self::transfer::{OutgoingTransfers, IncomingTransfers};
+
 #[cfg(feature = "steam")]
 pub mod steam;
 #[cfg(feature = "steam")]
@@ -58,6 +61,9 @@ impl <S: SocketListener> NetworkManager<S> {
             let id = socket.id();
             self.connections.insert(id.clone(), Connection::new(&self.log, socket));
         }
+        for connection in self.connections.values_mut() {
+            connection.tick_transfers();
+        }
     }
 
     /// Returns the connection with the given id if it exists
@@ -95,6 +101,7 @@ pub struct Connection<S: Socket> {
     closed: bool,
     send: Sender,
     recv: Receiver,
+    transfers: OutgoingTransfers,
 }
 
 impl <S: Socket> Connection<S> {
@@ -106,6 +113,7 @@ impl <S: Socket> Connection<S> {
             closed: false,
             send,
             recv,
+            transfers: OutgoingTransfers::default(),
         }
     }
 
@@ -140,9 +148,32 @@ impl <S: Socket> Connection<S> {
         ret
     }
 
+    /// Sends a packet that may be too large to be sent at once
+    /// in chunks, see `transfer`. The packet arrives as if it was
+    /// sent via `ensure_send`
+    pub fn send_large<P>(&mut self, data: P) -> errors::Result<()>
+        where P: Into<packet::Packet> + Debug
+    {
+        let ret = self.transfers.send(&mut self.send, data.into());
+        self.closed |= ret.is_err();
+        ret
+    }
+
+    fn tick_transfers(&mut self) {
+        if !self.transfers.is_empty() {
+            let ret = self.transfers.tick(&mut self.send);
+            self.closed |= ret.is_err();
+        }
+    }
+
     /// Reads a single Packet if available.
     pub fn recv(&mut self) -> errors::Result<packet::Packet> {
-        let ret = self.recv.try_recv();
+        let ret = loop {
+            match self.recv.try_recv_with(&mut self.send) {
+                Ok(packet::Packet::TransferAck(ack)) => self.transfers.handle_ack(&ack),
+                ret => break ret,
+            }
+        };
         self.closed |= ret.as_ref()
             .err()
             .map_or(false, |e| if let errors::ErrorKind::NoData = *e.kind() {
@@ -223,6 +254,7 @@ impl Sender {
 /// connection.
 pub struct Receiver {
     inner: mpsc::Receiver<packet::Packet>,
+    transfers: IncomingTransfers,
 }
 
 impl Receiver {
@@ -236,6 +268,23 @@ impl Receiver {
         })
     }
 
+    /// Reads a single Packet if available, reassembling packets
+    /// sent as a transfer and acking their chunks via the sender.
+    ///
+    /// Must be used instead of `try_recv` when the other side may
+    /// use `Connection::send_large`
+    pub fn try_recv_with(&mut self, sender: &mut Sender) -> errors::Result<packet::Packet> {
+        loop {
+            match self.try_recv()? {
+                packet::Packet::TransferStart(pck) => self.transfers.handle_start(&pck)?,
+                packet::Packet::TransferChunk(pck) => if let Some(pck) = self.transfers.handle_chunk(sender, pck)? {
+                    return Ok(pck);
+                },
+                pck => return Ok(pck),
+            }
+        }
+    }
+
     /// Reads a single Packet if available.
     pub fn recv_timeout(&mut self, time: time::Duration) -> errors::Result<packet::Packet> {
         let ret = self.inner.recv_timeout(time);
@@ -246,7 +295,7 @@ impl Receiver {
 /// A single socket
 pub trait Socket: Debug + Sized {
     /// The type of a unique hashable id for the socket
-    type Id: PartialEq + Eq + Hash + Clone + Debug + 'static;
+    type Id: PartialEq + Eq + Hash + Clone + Debug + Send + 'static;
 
     /// Returns whether this connection is local.
     /// Generally used to reduce auth requirements to
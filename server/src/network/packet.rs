@@ -43,7 +43,7 @@ macro_rules! define_packets {
             packet $name:ident {
                 $(
                     $(#[$fattr:meta])*
-                    field $fname:ident : $ftype:ty,
+                    field $fname:ident : $ftype:ty $([delta_bits = $fbits:tt])?,
                 )*
             }
         )*
@@ -63,6 +63,7 @@ macro_rules! define_packets {
             pub struct $name {
                 $(
                     $(#[$fattr])*
+                    $(#[delta_bits = $fbits])?
                     pub $fname : $ftype,
                 )*
             }
@@ -73,6 +74,37 @@ macro_rules! define_packets {
                 }
             }
         )*
+
+        /// Returns the layout of every packet in the order
+        /// of their ids.
+        #[allow(unused_doc_comments, unused_mut)]
+        pub fn packet_schema() -> Vec<PacketSchema> {
+            let mut packets = Vec::new();
+            $(
+                $(#[$pattr])*
+                {
+                    let mut fields = Vec::new();
+                    $(
+                        $(#[$fattr])*
+                        {
+                            fields.push(FieldSchema {
+                                name: stringify!($fname),
+                                ty: stringify!($ftype).replace(' ', ""),
+                                delta_bits: None $(.or($fbits.parse().ok()))?,
+                                docs: doc_text(&[$(stringify!($fattr)),*]),
+                            });
+                        }
+                    )*
+                    packets.push(PacketSchema {
+                        id: packets.len(),
+                        name: stringify!($name),
+                        docs: doc_text(&[$(stringify!($pattr)),*]),
+                        fields,
+                    });
+                }
+            )*
+            packets
+        }
     )
 }
 
@@ -99,6 +131,31 @@ define_packets! {
         /// The part id of this fragment
         field fragment_part: u16,
     }
+    /// Begins a transfer of a packet too large to be sent
+    /// at once. See `network::transfer`
+    packet TransferStart {
+        /// The id of the transfer
+        field id: u32,
+        /// The length of the encoded packet in bytes
+        field length: u32,
+    }
+    /// Part of a transfer's encoded packet
+    packet TransferChunk {
+        /// The id of the transfer
+        field id: u32,
+        /// The position of the chunk in the packet
+        field index: u32,
+        /// The chunk's part of the encoded packet
+        field data: Raw,
+    }
+    /// Sent by the receiver of a transfer to tell the sender
+    /// how many chunks it has received in order
+    packet TransferAck {
+        /// The id of the transfer
+        field id: u32,
+        /// The number of chunks received without a gap
+        field received: u32,
+    }
     /// Sent by the player to disconnect from the server.
     ///
     /// Due to udp there is a chance the server will never get this
@@ -114,6 +171,12 @@ define_packets! {
     }
     /// Begins a remote connection
     packet RemoteConnectionStart {
+        /// The hash of the client's protocol. Must match the
+        /// server's for the connection to be accepted.
+        ///
+        /// Kept as the first field so that it can be read
+        /// even when the rest of the packet can't be.
+        field protocol_hash: u64,
         /// The username of the client
         field name: String,
         /// The steam id of the connecting client
@@ -122,6 +185,9 @@ define_packets! {
         /// The steam auth ticket to verify
         #[cfg(feature = "steam")]
         field ticket: Raw,
+        /// The server password or the token for this username
+        #[cfg(not(feature = "steam"))]
+        field password: String,
     }
     /// Sent by the server after one of the previous connection
     /// packets to begin the game.
@@ -136,8 +202,12 @@ define_packets! {
     /// Sent by the server if the player was blocked from connecting
     /// for some reason.
     packet ServerConnectionFail {
-        /// The reason the connection failed,
-        field reason: String,
+        /// The code of the reason the connection failed.
+        ///
+        /// See `errors::ErrorCode`
+        field code: u16,
+        /// Extra untranslated information about the failure
+        field detail: Option<String>,
     }
 
     /// Sent by the client when it has entered the
@@ -151,6 +221,35 @@ define_packets! {
         field players: AlwaysVec<LobbyEntry>,
         /// Whether the game can be started
         field can_start: bool,
+        /// The goal sets that can be selected
+        field goal_sets: AlwaysVec<GoalSetEntry>,
+        /// The currently selected goal set if any
+        field goals: Option<ResourceKey<'static>>,
+    }
+    /// Sent by the client to select the goal set to play
+    /// with when in the lobby.
+    ///
+    /// `None` plays without any goals.
+    packet SetGoals {
+        /// The key of the goal set
+        field key: Option<ResourceKey<'static>>,
+    }
+    /// Sent by the client to change the identity of their
+    /// university when in the lobby.
+    ///
+    /// The server replaces anything the packs don't provide
+    packet SetCampus {
+        /// The new identity
+        field identity: player::CampusIdentity,
+    }
+    /// Sent by the client to change how they are shown to other
+    /// players when in the lobby.
+    ///
+    /// The server replaces anything the packs don't provide and
+    /// colours already used by another player
+    packet SetProfile {
+        /// The new profile
+        field profile: player::PlayerProfile,
     }
     /// Sent by the client to request the game to begin
     /// when in the lobby.
@@ -175,12 +274,55 @@ define_packets! {
         field strings: AlwaysVec<String>,
         /// The serialized state of idle tasks
         field idle_state: AlwaysVec<IdleState>,
+        /// The seasons active on the server
+        field seasons: AlwaysVec<String>,
         /// The serialized state of the level
         field state: Raw,
     }
 
     /// Sent to keep the connection open
     packet KeepAlive {}
+    /// Sent by the client whilst the player is doing something
+    /// that isn't otherwise sent to the server (e.g. moving the
+    /// camera) to prevent them being marked as away
+    packet PlayerActivity {}
+    /// Sent by the client when the camera moves so that the
+    /// server knows which entities are being watched. Entities
+    /// far from every player's view are simplified
+    packet ViewArea {
+        /// The position of the camera on the x axis in tiles
+        field x: f32,
+        /// The position of the camera on the z axis in tiles
+        field z: f32,
+    }
+    /// Sent by the client to measure the round trip time to
+    /// the server
+    packet Ping {
+        /// The id of the ping, returned in the `Pong`
+        field id: u32,
+    }
+    /// Sent by the server in reply to a `Ping`
+    packet Pong {
+        /// The id of the ping being replied to
+        field id: u32,
+    }
+    /// Sent by the server to warn the player that they will be
+    /// marked as away or removed unless they do something
+    packet AfkWarning {
+        /// The number of seconds until action is taken
+        field seconds: u32,
+        /// Whether the player will be removed from the lobby
+        /// instead of marked as away
+        field kick: bool,
+    }
+    /// Sent by the server when a player is marked as away
+    /// or returns
+    packet UpdatePlayerStatus {
+        /// The player that changed
+        field player: player::Id,
+        /// Whether the player is away
+        field afk: bool,
+    }
     /// Sets the pause state of the server.
     /// Only works in loopback mode.
     packet SetPauseGame {
@@ -190,6 +332,28 @@ define_packets! {
     /// Requests for the save to save the current game
     /// Only works in loopback mode
     packet SaveGame {}
+    /// Sent by the client to start or stop receiving the
+    /// server's tick timings for the performance hud.
+    ///
+    /// Only the host is sent timings
+    packet SetPerfStats {
+        /// Whether to send timings
+        field enabled: bool,
+    }
+    /// The server's tick timings averaged over
+    /// `metrics::REPORT_INTERVAL` ticks
+    packet PerfStats {
+        /// The time a tick should stay within in microseconds
+        field budget: u32,
+        /// The time taken by each phase of the tick in
+        /// microseconds, in the order of `metrics::TickPhase::ALL`
+        field phases: AlwaysVec<u32>,
+        /// The time each pack's scripts should stay within
+        /// in microseconds
+        field pack_budget: u32,
+        /// The time taken by each pack's scripts
+        field packs: AlwaysVec<PackUsage>,
+    }
 
     // Level packets
 
@@ -220,6 +384,10 @@ define_packets! {
         /// The id of the first rejected command.
         /// All after this are ignored
         field rejected_id: u32,
+        /// The code of the reason the command was rejected.
+        ///
+        /// See `errors::ErrorCode`
+        field code: u16,
     }
 
     /// List of commands executed by different client
@@ -247,14 +415,11 @@ define_packets! {
     /// state
     packet EntityAckFrame {
         /// The frame that is being ack'd
-        #[delta_bits = "14"]
-        field frame: u16,
+        field frame: u16 [delta_bits = "14"],
         /// The first entity id being ack'd
-        #[delta_bits = "20"]
-        field entity_offset: u32,
+        field entity_offset: u32 [delta_bits = "20"],
         /// The number of entities being acked
-        #[delta_bits = "14"]
-        field entity_count: u16,
+        field entity_count: u16 [delta_bits = "14"],
     }
     /// Ack's the player state from the server
     packet PlayerAckFrame {
@@ -272,11 +437,62 @@ define_packets! {
         /// The formatted message from the server
         field messages: AlwaysVec<crate::msg::Message>,
     }
+    /// Updates the player's progress towards the goals
+    /// selected for the game
+    packet GoalProgress {
+        /// The name of the goal set
+        field name: String,
+        /// Conditions that must all be met to win
+        field victory: AlwaysVec<GoalEntry>,
+        /// Conditions that each cause a loss when met
+        field defeat: AlwaysVec<GoalEntry>,
+        /// Whether the player has won
+        field won: bool,
+        /// Whether the player has lost
+        field lost: bool,
+    }
+    /// Starts a cutscene defined by the mission
+    packet PlayCutscene {
+        /// The cutscene to play
+        field cutscene: crate::cutscene::Cutscene,
+    }
+    /// Plays visual effects requested by the mission
+    packet PlayEffects {
+        /// The effects to play in order
+        field effects: AlwaysVec<crate::effects::VisualEffect>,
+    }
+    /// Sent by the client when the player acknowledges a
+    /// notification posted by a script
+    packet NotificationReply {
+        /// The id of the notification
+        field id: u32,
+        /// Whether the notification's action was clicked
+        /// instead of it being dismissed
+        field accepted: bool,
+    }
     /// A message to the server
     packet ChatMessage {
         /// The unformatted message from the client
         field message: String,
     }
+    /// Shares one of the player's stat charts with the
+    /// other players via chat
+    packet ShareChart {
+        /// The chart to share
+        field kind: crate::msg::ChartKind,
+    }
+    /// Compressed voice recorded by the client
+    packet VoiceData {
+        /// The voice data as returned by steam
+        field data: Raw,
+    }
+    /// Compressed voice recorded by another player
+    packet RemoteVoiceData {
+        /// The player that recorded the voice
+        field player_id: player::Id,
+        /// The voice data as returned by steam
+        field data: Raw,
+    }
     /// Updates the collected stats for the player
     packet UpdateStats {
         /// The update id.
@@ -287,6 +503,27 @@ define_packets! {
         /// stat history.
         field history: AlwaysVec<HistoryEntry>,
     }
+    /// Updates the dirt drawn on the floor. Sent with every
+    /// dirty tile once the player loads in and with changed
+    /// tiles afterwards
+    packet DirtUpdate {
+        /// The tiles that changed
+        field tiles: AlwaysVec<DirtTile>,
+    }
+    /// Updates the litter drawn on the floor. Sent with every
+    /// littered tile once the player loads in and with changed
+    /// tiles afterwards
+    packet LitterUpdate {
+        /// The tiles that changed
+        field tiles: AlwaysVec<LitterTile>,
+    }
+    /// Updates the rooms drawn as being built. Sent with every
+    /// room being built once the player loads in and with changed
+    /// rooms afterwards
+    packet ConstructionUpdate {
+        /// The rooms that changed
+        field rooms: AlwaysVec<ConstructionSite>,
+    }
 
     /// Generic request container
     packet Request {
@@ -308,6 +545,94 @@ define_packets! {
     }
 }
 
+/// The layout of a single packet
+#[derive(Debug, Serialize)]
+pub struct PacketSchema {
+    /// The id the packet is encoded with
+    pub id: usize,
+    /// The name of the packet
+    pub name: &'static str,
+    /// The packet's documentation
+    pub docs: String,
+    /// The fields of the packet in the order they are encoded
+    pub fields: Vec<FieldSchema>,
+}
+
+/// The layout of a single field of a packet
+#[derive(Debug, Serialize)]
+pub struct FieldSchema {
+    /// The name of the field
+    pub name: &'static str,
+    /// The rust type of the field
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The number of bits the field is encoded with if
+    /// it doesn't use the default for its type
+    pub delta_bits: Option<u8>,
+    /// The field's documentation
+    pub docs: String,
+}
+
+#[derive(Serialize)]
+struct ProtocolSchema {
+    protocol_hash: String,
+    packets: Vec<PacketSchema>,
+}
+
+/// Extracts the documentation from stringified attributes
+fn doc_text(attrs: &[&str]) -> String {
+    attrs.iter()
+        .map(|v| v.trim())
+        .filter(|v| v.starts_with("doc"))
+        .filter_map(|v| v.find('"').map(|idx| &v[idx..]))
+        .map(|v| v.trim_matches('"')
+            .replace("\\'", "'")
+            .replace("\\\"", "\"")
+            .trim()
+            .to_owned())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns a hash of the wire format of every packet.
+///
+/// Only the layout of the packets is included so changing
+/// documentation doesn't change the hash.
+pub fn protocol_hash() -> u64 {
+    use std::fmt::Write as FmtWrite;
+    let mut layout = String::new();
+    for packet in packet_schema() {
+        let _ = write!(layout, "{}:{}{{", packet.id, packet.name);
+        for field in packet.fields {
+            let _ = write!(layout, "{}:{}:{:?};", field.name, field.ty, field.delta_bits);
+        }
+        layout.push('}');
+    }
+    crc::crc64::checksum_ecma(layout.as_bytes())
+}
+
+/// Returns a machine readable (json) description of the
+/// protocol for use by external tools.
+pub fn protocol_schema_json() -> String {
+    serde_json::to_string_pretty(&ProtocolSchema {
+        protocol_hash: format!("{:016x}", protocol_hash()),
+        packets: packet_schema(),
+    }).expect("Failed to serialize the protocol schema")
+}
+
+/// The time taken by a pack's scripts averaged over
+/// `metrics::REPORT_INTERVAL` ticks
+#[derive(Debug, Clone, DeltaEncode)]
+pub struct PackUsage {
+    /// The name of the pack
+    pub name: String,
+    /// The time taken each tick in microseconds
+    pub time: u32,
+    /// Whether the pack's non-critical scripts are being
+    /// throttled for using too much time
+    pub throttled: bool,
+}
+
 /// Serialized state for an idle task
 #[derive(Debug, Clone, DeltaEncode)]
 pub struct IdleState {
@@ -320,7 +645,7 @@ pub struct IdleState {
 }
 
 /// An entry containing one step of a player's history
-#[derive(Debug, Clone, DeltaEncode, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, DeltaEncode, Default, Serialize, Deserialize)]
 pub struct HistoryEntry {
     /// Total money (income/outcome)
     pub total: UniDollar,
@@ -335,6 +660,38 @@ pub struct HistoryEntry {
     pub grades: [u32; 6],
 }
 
+/// The dirt on a single tile
+#[derive(Debug, Clone, DeltaEncode)]
+pub struct DirtTile {
+    /// The x position of the tile
+    pub x: i32,
+    /// The y position of the tile
+    pub y: i32,
+    /// The stage of the dirt, zero once cleaned
+    pub stage: u8,
+}
+
+/// The litter on a single tile
+#[derive(Debug, Clone, DeltaEncode)]
+pub struct LitterTile {
+    /// The x position of the tile
+    pub x: i32,
+    /// The y position of the tile
+    pub y: i32,
+    /// The pieces of litter on the tile, zero once picked up
+    pub pieces: u8,
+}
+
+/// The progress of a room being built
+#[derive(Debug, Clone, DeltaEncode)]
+pub struct ConstructionSite {
+    /// The room being built
+    pub room_id: RoomId,
+    /// The stage of construction, `None` once the room is
+    /// finished
+    pub stage: Option<u8>,
+}
+
 /// Consumes the remainder of the packet if read.
 ///
 /// Writing sends the byte array without a prefix.
@@ -384,6 +741,30 @@ pub struct CommandPair {
     pub command: command::Command,
 }
 
+/// A goal set that can be selected in a lobby
+#[derive(Debug, Clone, DeltaEncode, PartialEq)]
+pub struct GoalSetEntry {
+    /// The key used to select the set
+    pub key: ResourceKey<'static>,
+    /// The name of the set
+    pub name: String,
+    /// A description of the set
+    pub description: String,
+}
+
+/// A single condition of a goal set and the player's
+/// progress towards it
+#[derive(Debug, Clone, DeltaEncode, PartialEq, Serialize)]
+pub struct GoalEntry {
+    /// A description of the condition
+    pub description: String,
+    /// How close the condition is to being met between
+    /// 0.0 and 1.0
+    pub progress: f32,
+    /// Whether the condition has been met
+    pub complete: bool,
+}
+
 /// A player in a lobby
 #[derive(Debug, Clone, DeltaEncode, PartialEq)]
 pub struct LobbyEntry {
@@ -394,6 +775,12 @@ pub struct LobbyEntry {
     pub uid: player::Id,
     /// Whether the player has finished connecting
     pub ready: bool,
+    /// Whether the player has been marked as away
+    pub afk: bool,
+    /// The identity of the player's university
+    pub campus: player::CampusIdentity,
+    /// How the player is shown to others
+    pub profile: player::PlayerProfile,
 }
 
 /// A player in a lobby
@@ -405,4 +792,8 @@ pub struct PlayerEntry {
     pub uid: player::Id,
     /// The player's current state
     pub state: player::State,
+    /// The identity of the player's university
+    pub campus: player::CampusIdentity,
+    /// How the player is shown to others
+    pub profile: player::PlayerProfile,
 }
\ No newline at end of file
@@ -152,7 +152,8 @@ impl Socket for SteamClientSocket {
         (Sender::Unreliable {
             inner: output_send,
         }, Receiver {
-            inner: input_read
+            inner: input_read,
+            transfers: Default::default(),
         })
     }
 }
@@ -213,12 +214,14 @@ impl Socket for SteamSocket {
                     inner: send,
                 }, Receiver {
                     inner: recv,
+                    transfers: Default::default(),
                 }),
             SteamSocket::Remote{send, recv, ..} =>
                 (Sender::Unreliable {
                     inner: send,
                 }, Receiver {
                     inner: recv,
+                    transfers: Default::default(),
                 }),
         }
     }
@@ -0,0 +1,354 @@
+//! Chunked transfers of packets too large to send at once.
+//!
+//! Ensured packets are sent as a burst of fragments which all have
+//! to arrive before the slot tracking them times out, making large
+//! packets (e.g. the level state sent to a player joining a game)
+//! unreliable on slower connections. Instead the packet is encoded
+//! and split into chunks which are sent a window at a time.
+//!
+//! The receiver acks the number of chunks it has received in order
+//! and the sender moves its window on from there. If the sender stops
+//! hearing back it resumes from the last acked chunk instead of
+//! starting the transfer over.
+//!
+//! Once every chunk has arrived the packet is decoded and returned
+//! by `Receiver::try_recv_with` as if it had been sent normally.
+//! Reliable connections can already handle packets of any size so
+//! the packet is sent as is over those.
+
+use std::io;
+use std::cmp;
+use delta_encode::{bitio, DeltaEncodable};
+use super::packet;
+use super::Sender;
+use crate::errors;
+use crate::prelude::*;
+
+/// The number of bytes in each chunk
+const CHUNK_SIZE: usize = 1024;
+/// The number of chunks that may be waiting for an ack
+const WINDOW: u32 = 64;
+/// The number of chunks received between acks
+const ACK_EVERY: u32 = 16;
+/// The number of ticks without an ack before the sender
+/// resumes from the last acked chunk
+const RESEND_TICKS: u32 = 40;
+/// The largest packet that may be received as a transfer
+const MAX_TRANSFER_SIZE: usize = 64 * 1024 * 1024;
+
+struct OutgoingTransfer {
+    id: u32,
+    data: Vec<u8>,
+    chunks: u32,
+    acked: u32,
+    next: u32,
+    ticks_since_ack: u32,
+}
+
+impl OutgoingTransfer {
+    fn send_window(&mut self, sender: &mut Sender) -> errors::Result<()> {
+        let end = cmp::min(self.acked + WINDOW, self.chunks);
+        while self.next < end {
+            let offset = self.next as usize * CHUNK_SIZE;
+            let len = cmp::min(self.data.len() - offset, CHUNK_SIZE);
+            sender.send(packet::TransferChunk {
+                id: self.id,
+                index: self.next,
+                data: packet::Raw(self.data[offset .. offset + len].to_vec()),
+            })?;
+            self.next += 1;
+        }
+        Ok(())
+    }
+}
+
+/// The transfers being sent over a connection
+#[derive(Default)]
+pub struct OutgoingTransfers {
+    next_id: u32,
+    transfers: Vec<OutgoingTransfer>,
+}
+
+impl OutgoingTransfers {
+    /// Starts sending the packet as a transfer.
+    ///
+    /// Reliable senders are given the packet directly
+    pub fn send(&mut self, sender: &mut Sender, pck: packet::Packet) -> errors::Result<()> {
+        if let Sender::Reliable{..} = *sender {
+            return sender.ensure_send(pck);
+        }
+        let mut writer = bitio::Writer::new(Vec::new());
+        pck.encode(None, &mut writer)?;
+        let data = writer.finish()?;
+        if data.len() > MAX_TRANSFER_SIZE {
+            bail!(errors::ErrorKind::PacketTooLarge);
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let chunks = ((data.len() + CHUNK_SIZE - 1) / CHUNK_SIZE) as u32;
+        sender.ensure_send(packet::TransferStart {
+            id,
+            length: data.len() as u32,
+        })?;
+        let mut transfer = OutgoingTransfer {
+            id,
+            data,
+            chunks,
+            acked: 0,
+            next: 0,
+            ticks_since_ack: 0,
+        };
+        transfer.send_window(sender)?;
+        self.transfers.push(transfer);
+        Ok(())
+    }
+
+    /// Moves the transfer's window on to the chunks the
+    /// other side is waiting for
+    pub fn handle_ack(&mut self, ack: &packet::TransferAck) {
+        if let Some(transfer) = self.transfers.iter_mut().find(|v| v.id == ack.id) {
+            if ack.received > transfer.acked {
+                transfer.acked = cmp::min(ack.received, transfer.chunks);
+                transfer.next = cmp::max(transfer.next, transfer.acked);
+                transfer.ticks_since_ack = 0;
+            }
+        }
+        self.transfers.retain(|v| v.acked < v.chunks);
+    }
+
+    /// Sends the next chunks of each transfer, resending from
+    /// the last ack if the other side has gone quiet.
+    ///
+    /// Should be called once a tick
+    pub fn tick(&mut self, sender: &mut Sender) -> errors::Result<()> {
+        for transfer in &mut self.transfers {
+            transfer.ticks_since_ack += 1;
+            if transfer.ticks_since_ack > RESEND_TICKS {
+                transfer.ticks_since_ack = 0;
+                transfer.next = transfer.acked;
+            }
+            transfer.send_window(sender)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether any transfers are still being sent
+    pub fn is_empty(&self) -> bool {
+        self.transfers.is_empty()
+    }
+}
+
+struct IncomingTransfer {
+    id: u32,
+    length: usize,
+    chunks: Vec<Option<Vec<u8>>>,
+    /// The number of chunks received in order
+    received: u32,
+    last_ack: u32,
+}
+
+/// The transfers being received over a connection
+#[derive(Default)]
+pub struct IncomingTransfers {
+    transfers: Vec<IncomingTransfer>,
+}
+
+impl IncomingTransfers {
+    /// Prepares to receive the transfer's chunks
+    pub fn handle_start(&mut self, start: &packet::TransferStart) -> errors::Result<()> {
+        if self.transfers.iter().any(|v| v.id == start.id) {
+            return Ok(());
+        }
+        let length = start.length as usize;
+        if length > MAX_TRANSFER_SIZE {
+            bail!(errors::ErrorKind::PacketTooLarge);
+        }
+        self.transfers.push(IncomingTransfer {
+            id: start.id,
+            length,
+            chunks: vec![None; (length + CHUNK_SIZE - 1) / CHUNK_SIZE],
+            received: 0,
+            last_ack: 0,
+        });
+        Ok(())
+    }
+
+    /// Stores the chunk, acking it if needed, and returns the
+    /// transferred packet once every chunk has arrived.
+    ///
+    /// Chunks of unknown transfers are ignored, the sender
+    /// resends them once it isn't acked
+    pub fn handle_chunk(&mut self, sender: &mut Sender, chunk: packet::TransferChunk) -> errors::Result<Option<packet::Packet>> {
+        let idx = if let Some(idx) = self.transfers.iter().position(|v| v.id == chunk.id) {
+            idx
+        } else {
+            return Ok(None);
+        };
+        let done = {
+            let transfer = &mut self.transfers[idx];
+            let index = chunk.index as usize;
+            let expected_len = cmp::min(transfer.length.saturating_sub(index * CHUNK_SIZE), CHUNK_SIZE);
+            if index >= transfer.chunks.len() || chunk.data.0.len() != expected_len {
+                bail!(errors::ErrorKind::InvalidTransfer);
+            }
+            // A chunk that was already received means the sender
+            // is resending and needs to know where to resume from
+            let resent = transfer.chunks[index].is_some();
+            transfer.chunks[index] = Some(chunk.data.0);
+            while transfer.chunks.get(transfer.received as usize).map_or(false, |v| v.is_some()) {
+                transfer.received += 1;
+            }
+            let done = transfer.received as usize == transfer.chunks.len();
+            if resent || done || transfer.received >= transfer.last_ack + ACK_EVERY {
+                transfer.last_ack = transfer.received;
+                sender.ensure_send(packet::TransferAck {
+                    id: transfer.id,
+                    received: transfer.received,
+                })?;
+            }
+            done
+        };
+        if !done {
+            return Ok(None);
+        }
+        let transfer = self.transfers.remove(idx);
+        let mut data = Vec::with_capacity(transfer.length);
+        for chunk in transfer.chunks.into_iter().flatten() {
+            data.extend(chunk);
+        }
+        let mut reader = bitio::Reader::new(io::Cursor::new(data));
+        Ok(Some(packet::Packet::decode(None, &mut reader)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn unreliable() -> (Sender, mpsc::Receiver<(bool, packet::Packet)>) {
+        let (send, recv) = mpsc::channel();
+        (Sender::Unreliable {
+            inner: send,
+        }, recv)
+    }
+
+    /// A packet that takes 40 chunks to send
+    fn large_packet() -> packet::Packet {
+        packet::EntityFrame {
+            data: packet::Raw((0 .. 40 * CHUNK_SIZE - 1).map(|v| v as u8).collect()),
+        }.into()
+    }
+
+    /// Delivers every packet on the channel to the other side,
+    /// dropping chunks the filter rejects
+    fn deliver<F>(
+        from: &mpsc::Receiver<(bool, packet::Packet)>,
+        incoming: &mut IncomingTransfers, outgoing: &mut OutgoingTransfers,
+        ack_sender: &mut Sender, acks: &mpsc::Receiver<(bool, packet::Packet)>,
+        mut keep: F,
+    ) -> Option<packet::Packet>
+        where F: FnMut(u32) -> bool
+    {
+        let mut result = None;
+        while let Ok((_, pck)) = from.try_recv() {
+            match pck {
+                packet::Packet::TransferStart(pck) => incoming.handle_start(&pck).unwrap(),
+                packet::Packet::TransferChunk(pck) => if keep(pck.index) {
+                    if let Some(pck) = incoming.handle_chunk(ack_sender, pck).unwrap() {
+                        result = Some(pck);
+                    }
+                },
+                pck => panic!("Unexpected packet {:?}", pck),
+            }
+        }
+        while let Ok((_, pck)) = acks.try_recv() {
+            if let packet::Packet::TransferAck(pck) = pck {
+                outgoing.handle_ack(&pck);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn transfers_in_chunks() {
+        let (mut sender, sent) = unreliable();
+        let (mut ack_sender, acks) = unreliable();
+        let mut outgoing = OutgoingTransfers::default();
+        let mut incoming = IncomingTransfers::default();
+
+        outgoing.send(&mut sender, large_packet()).unwrap();
+        let result = deliver(&sent, &mut incoming, &mut outgoing, &mut ack_sender, &acks, |_| true);
+        match result {
+            Some(packet::Packet::EntityFrame(pck)) => {
+                assert_eq!(pck.data.0.len(), 40 * CHUNK_SIZE - 1);
+                assert_eq!(pck.data.0[CHUNK_SIZE + 3], 3);
+            },
+            pck => panic!("Unexpected result {:?}", pck),
+        }
+        assert!(outgoing.is_empty());
+    }
+
+    #[test]
+    fn resumes_after_lost_chunks() {
+        let (mut sender, sent) = unreliable();
+        let (mut ack_sender, acks) = unreliable();
+        let mut outgoing = OutgoingTransfers::default();
+        let mut incoming = IncomingTransfers::default();
+
+        outgoing.send(&mut sender, large_packet()).unwrap();
+        // Lose everything after the 20th chunk, only the first
+        // 16 will have been acked
+        assert!(deliver(&sent, &mut incoming, &mut outgoing, &mut ack_sender, &acks, |idx| idx < 20).is_none());
+        assert!(!outgoing.is_empty());
+
+        for _ in 0 .. RESEND_TICKS {
+            outgoing.tick(&mut sender).unwrap();
+        }
+        assert!(sent.try_recv().is_err());
+        outgoing.tick(&mut sender).unwrap();
+        // Resumes from the last ack instead of starting over
+        let resent: Vec<_> = sent.try_iter()
+            .filter_map(|(_, v)| if let packet::Packet::TransferChunk(pck) = v { Some(pck) } else { None })
+            .collect();
+        assert_eq!(resent.first().map(|v| v.index), Some(ACK_EVERY));
+        assert_eq!(resent.len(), 40 - ACK_EVERY as usize);
+
+        let mut result = None;
+        for chunk in resent {
+            if let Some(pck) = incoming.handle_chunk(&mut ack_sender, chunk).unwrap() {
+                result = Some(pck);
+            }
+        }
+        assert!(result.is_some());
+        deliver(&sent, &mut incoming, &mut outgoing, &mut ack_sender, &acks, |_| true);
+        assert!(outgoing.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_chunks() {
+        let (mut ack_sender, _acks) = unreliable();
+        let mut incoming = IncomingTransfers::default();
+        incoming.handle_start(&packet::TransferStart {
+            id: 3,
+            length: 10,
+        }).unwrap();
+        // Unknown transfers are ignored
+        assert!(incoming.handle_chunk(&mut ack_sender, packet::TransferChunk {
+            id: 4,
+            index: 0,
+            data: packet::Raw(vec![0; 10]),
+        }).unwrap().is_none());
+        assert!(incoming.handle_chunk(&mut ack_sender, packet::TransferChunk {
+            id: 3,
+            index: 1,
+            data: packet::Raw(vec![0; 10]),
+        }).is_err());
+        assert!(incoming.handle_chunk(&mut ack_sender, packet::TransferChunk {
+            id: 3,
+            index: 0,
+            data: packet::Raw(vec![0; 20]),
+        }).is_err());
+    }
+}
@@ -29,6 +29,9 @@
 //! split them into fragments which will be assembled on the overside.
 //! The other side will ack the fragments it recieves and the system will
 //! resend fragments they did not send.
+//!
+//! Packets larger than a few fragments should be sent via
+//! `Connection::send_large` instead which uses `transfer`.
 
 use super::*;
 use std::net::{
@@ -286,7 +289,8 @@ impl Socket for UdpRemoteSocket {
         (Sender::Unreliable {
             inner: self.output_send,
         }, Receiver {
-            inner: self.input_read
+            inner: self.input_read,
+            transfers: Default::default(),
         })
     }
 }
@@ -397,7 +401,8 @@ impl Socket for UdpClientSocket {
         (Sender::Unreliable {
             inner: output_send,
         }, Receiver {
-            inner: input_read
+            inner: input_read,
+            transfers: Default::default(),
         })
     }
 }
@@ -689,7 +694,8 @@ fn test_udp() {
     let client = UdpClientSocket::connect(addr).unwrap();
     let (mut send, _read) = client.split(&log);
     send.send(packet::ServerConnectionFail {
-        reason: "Testing 1 2 3".into()
+        code: 102,
+        detail: Some("Testing 1 2 3".into()),
     }).unwrap();
 
     // Prevent races
@@ -703,7 +709,8 @@ fn test_udp() {
     } else {
         panic!("Wrong packet");
     };
-    assert_eq!("Testing 1 2 3", packet.reason);
+    assert_eq!(102, packet.code);
+    assert_eq!(Some("Testing 1 2 3"), packet.detail.as_ref().map(|v| v.as_str()));
 }
 
 #[test]
@@ -717,7 +724,8 @@ fn test_udp_ensure() {
     let client = UdpClientSocket::connect(addr).unwrap();
     let (mut send, _read) = client.split(&log);
     send.ensure_send(packet::ServerConnectionFail {
-        reason: msg.clone(),
+        code: 0,
+        detail: Some(msg.clone()),
     }).unwrap();
 
     // Prevent races
@@ -731,5 +739,5 @@ fn test_udp_ensure() {
     } else {
         panic!("Wrong packet");
     };
-    assert_eq!(msg, packet.reason);
+    assert_eq!(Some(msg), packet.detail);
 }
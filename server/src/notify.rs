@@ -1,7 +1,44 @@
 //! Notification related types
+//!
+//! Scripts can post their own notifications to a player via
+//! `notify.post(player, desc)`:
+//!
+//! ```ignore
+//! notify.post(player, {
+//!     title = "Visitors",
+//!     description = "A group of visitors has arrived",
+//!     -- Optional, defaults to `info`
+//!     severity = "warning",
+//!     -- Optional
+//!     icon = "ui/icons/visitors",
+//!     -- Optional, `callback` is invoked with the notification's
+//!     -- id and `data` when the player clicks the button
+//!     action = {
+//!         label = "Greet",
+//!         callback = "visitors#on_greet",
+//!         data = { group = 3 },
+//!     },
+//! })
+//! ```
+//!
+//! Each pack may only post `RATE_LIMIT` notifications to a player
+//! within `RATE_WINDOW`. Notifications the player hasn't acknowledged
+//! yet are kept with the save and shown again when the game is loaded.
 
 use crate::prelude::*;
 use crate::common::ScriptData;
+use std::collections::VecDeque;
+use lua::{self, Ref, Scope, Table};
+
+/// The number of notifications a pack may post to a player
+/// within `RATE_WINDOW`
+pub const RATE_LIMIT: usize = 3;
+/// The period that `RATE_LIMIT` applies over
+pub const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// The number of notifications from a single pack that a player
+/// can have unacknowledged at once. The oldest are dropped to make
+/// room for new ones
+pub const MAX_PENDING: usize = 10;
 
 /// A notification that can be displayed to the player
 #[derive(Debug, DeltaEncode, PartialEq, Clone)]
@@ -50,5 +87,373 @@ pub enum Notification {
         func: String,
         /// The serialized data to pass to the script
         data: ScriptData,
+    },
+    /// A notification posted by a script via `notify.post`
+    Custom {
+        /// The id used to reply to the notification
+        id: u32,
+        /// The icon to use
+        icon: ResourceKey<'static>,
+        /// The title of the notification box
+        title: String,
+        /// The description of the notification box
+        description: String,
+        /// How important the notification is
+        severity: Severity,
+        /// The label of the action button, if any
+        action: Option<String>,
+    },
+}
+
+/// How important a script's notification is
+#[derive(Debug, DeltaEncode, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    /// General information
+    Info,
+    /// Something the player should look at soon
+    Warning,
+    /// Something the player should look at now
+    Critical,
+}
+
+impl Severity {
+    /// Returns the severity with the name as used by scripts
+    pub fn from_name(name: &str) -> Option<Severity> {
+        match name {
+            "info" => Some(Severity::Info),
+            "warning" => Some(Severity::Warning),
+            "critical" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the severity as used by scripts
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// A script's notification that the player hasn't acknowledged yet
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PendingNotification {
+    /// The id of the notification, unique per player
+    pub id: u32,
+    /// The pack that posted the notification
+    pub module: String,
+    /// The icon to use
+    pub icon: ResourceKey<'static>,
+    /// The title of the notification box
+    pub title: String,
+    /// The description of the notification box
+    pub description: String,
+    /// How important the notification is
+    pub severity: Severity,
+    /// The action button, if any
+    pub action: Option<NotificationAction>,
+}
+
+/// The button on a script's notification and what it calls
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NotificationAction {
+    /// The label of the button
+    pub label: String,
+    /// The script containing the callback
+    pub script: ResourceKey<'static>,
+    /// The function to call in the script
+    pub func: String,
+    /// The script's data for the callback encoded as cbor
+    pub data: Vec<u8>,
+}
+
+impl PendingNotification {
+    /// Returns the notification to send to the player
+    pub fn to_notification(&self) -> Notification {
+        Notification::Custom {
+            id: self.id,
+            icon: self.icon.clone(),
+            title: self.title.clone(),
+            description: self.description.clone(),
+            severity: self.severity,
+            action: self.action.as_ref().map(|v| v.label.clone()),
+        }
+    }
+}
+
+/// The notifications scripts have posted to a player
+#[derive(Debug, Default)]
+pub struct ScriptNotifications {
+    next_id: u32,
+    pending: Vec<PendingNotification>,
+    /// Notifications whose action was clicked waiting for their
+    /// callback to be run
+    accepted: Vec<PendingNotification>,
+    /// The times each pack recently posted a notification
+    recent: FNVMap<String, VecDeque<Instant>>,
+}
+
+impl ScriptNotifications {
+    /// Records the notification giving it an id, returns `None`
+    /// if the pack has posted too many recently
+    pub fn post(&mut self, now: Instant, mut notification: PendingNotification) -> Option<&PendingNotification> {
+        let recent = self.recent.entry(notification.module.clone())
+            .or_insert_with(VecDeque::new);
+        while recent.front().map_or(false, |v| now.duration_since(*v) >= RATE_WINDOW) {
+            recent.pop_front();
+        }
+        if recent.len() >= RATE_LIMIT {
+            return None;
+        }
+        recent.push_back(now);
+
+        let from_module = self.pending.iter()
+            .filter(|v| v.module == notification.module)
+            .count();
+        if from_module >= MAX_PENDING {
+            if let Some(idx) = self.pending.iter().position(|v| v.module == notification.module) {
+                self.pending.remove(idx);
+            }
+        }
+
+        notification.id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending.push(notification);
+        self.pending.last()
+    }
+
+    /// Handles the player's reply to the notification, queuing
+    /// its callback if the action was clicked.
+    ///
+    /// Returns whether the notification was waiting for a reply
+    pub fn reply(&mut self, id: u32, accepted: bool) -> bool {
+        if let Some(idx) = self.pending.iter().position(|v| v.id == id) {
+            let notification = self.pending.remove(idx);
+            if accepted && notification.action.is_some() {
+                self.accepted.push(notification);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the notifications whose action was clicked since
+    /// this was last called
+    pub fn take_accepted(&mut self) -> Vec<PendingNotification> {
+        ::std::mem::replace(&mut self.accepted, Vec::new())
+    }
+
+    /// Returns every notification that hasn't been acknowledged
+    pub fn pending(&self) -> &[PendingNotification] {
+        &self.pending
+    }
+
+    /// Restores the unacknowledged notifications from a save
+    pub fn load(&mut self, pending: Vec<PendingNotification>) {
+        self.next_id = pending.iter()
+            .map(|v| v.id.wrapping_add(1))
+            .max()
+            .unwrap_or(0);
+        self.pending = pending;
+    }
+}
+
+/// Invokes the callbacks of every notification whose action was
+/// clicked
+pub(crate) fn run_callbacks(
+    log: &Logger,
+    entities: &mut Container,
+    scripting: &script::Engine,
+    players: &mut crate::PlayerInfoMap,
+) {
+    let accepted: Vec<_> = players.values_mut()
+        .flat_map(|v| {
+            let player = v.uid;
+            v.script_notifications.take_accepted()
+                .into_iter()
+                .map(move |n| (player, n))
+        })
+        .collect();
+    for (player, notification) in accepted {
+        let action = assume!(log, notification.action);
+        let mut de = serde_cbor::de::Deserializer::from_slice(&action.data);
+        let data = match lua::with_table_serializer(scripting, |se| {
+            serde_transcode::transcode(&mut de, se)
+        }) {
+            Ok(val) => val,
+            Err(err) => {
+                error!(log, "Failed to decode notification data"; "script" => ?action.script, "error" => %err);
+                continue;
+            },
+        };
+        if let Err(err) = scripting.with_borrows()
+            .borrow_mut(entities)
+            .borrow_mut(players)
+            .invoke_function::<_, ()>("invoke_module_method", (
+                Ref::new_string(scripting, action.script.module()),
+                Ref::new_string(scripting, action.script.resource()),
+                Ref::new_string(scripting, action.func),
+                i32::from(player.0),
+                notification.id as i32,
+                data,
+            ))
+        {
+            error!(log, "Failed to run notification callback"; "script" => ?action.script, "error" => %err);
+        }
+    }
+}
+
+/// Sets up an interface for scripts to post notifications to
+/// players.
+///
+/// Only exposed to module scopes on the server so that the
+/// posting module is known for rate limiting
+pub fn init_notifylib(lua: &lua::Lua) {
+    lua.set(Scope::Global, "notify_post", lua::closure3(|lua, module: Ref<String>, player: i32, desc: Ref<Table>| -> UResult<Option<i32>> {
+        let module_key = ModuleKey::new(&*module);
+        let get_string = |tbl: &Ref<Table>, key: &str| tbl.get::<_, Ref<String>>(Ref::new_string(lua, key))
+            .map(|v| String::from(&*v));
+
+        let title = get_string(&desc, "title")
+            .ok_or_else(|| ErrorKind::Msg("Missing notification title".into()))?;
+        let description = get_string(&desc, "description").unwrap_or_default();
+        let severity = match get_string(&desc, "severity") {
+            Some(name) => Severity::from_name(&name)
+                .ok_or_else(|| ErrorKind::Msg(format!("Unknown notification severity {:?}", name)))?,
+            None => Severity::Info,
+        };
+        let icon = LazyResourceKey::parse(get_string(&desc, "icon").as_ref().map_or("base:ui/icons/inspection", |v| v.as_str()))
+            .or_module(module_key.borrow())
+            .into_owned();
+        let action = if let Some(action) = desc.get::<_, Ref<Table>>(Ref::new_string(lua, "action")) {
+            let label = get_string(&action, "label")
+                .ok_or_else(|| ErrorKind::Msg("Missing notification action label".into()))?;
+            let callback = get_string(&action, "callback")
+                .ok_or_else(|| ErrorKind::Msg("Missing notification action callback".into()))?;
+            let (script, method) = if let Some(pos) = callback.find('#') {
+                callback.split_at(pos)
+            } else {
+                bail!("invalid method description")
+            };
+            let data = action.get::<_, Ref<Table>>(Ref::new_string(lua, "data"))
+                .unwrap_or_else(|| Ref::new_table(lua));
+            let mut se = serde_cbor::ser::Serializer::new(vec![]);
+            lua::with_table_deserializer(&data, |de| {
+                serde_transcode::transcode(de, &mut se)
+            })?;
+            Some(NotificationAction {
+                label,
+                script: LazyResourceKey::parse(script)
+                    .or_module(module_key.borrow())
+                    .into_owned(),
+                func: method[1..].into(),
+                data: se.into_inner(),
+            })
+        } else {
+            None
+        };
+
+        let mut players = lua.write_borrow::<crate::PlayerInfoMap>();
+        let player = players.get_mut(&PlayerId(player as i16))
+            .ok_or_else(|| ErrorKind::Msg(format!("No player with the id {}", player)))?;
+        let posted = player.script_notifications.post(Instant::now(), PendingNotification {
+            id: 0,
+            module: String::from(&*module),
+            icon,
+            title,
+            description,
+            severity,
+            action,
+        }).map(|v| (v.id, v.to_notification()));
+        Ok(posted.map(|(id, notification)| {
+            player.notifications.push(notification);
+            id as i32
+        }))
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(module: &str, action: bool) -> PendingNotification {
+        PendingNotification {
+            id: 0,
+            module: module.into(),
+            icon: ResourceKey::new("base", "solid"),
+            title: "Test".into(),
+            description: "A test notification".into(),
+            severity: Severity::Info,
+            action: if action {
+                Some(NotificationAction {
+                    label: "Do it".into(),
+                    script: ResourceKey::new("base", "test"),
+                    func: "on_click".into(),
+                    data: vec![],
+                })
+            } else {
+                None
+            },
+        }
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut notifications = ScriptNotifications::default();
+        let now = Instant::now();
+        for _ in 0 .. RATE_LIMIT {
+            assert!(notifications.post(now, notification("base", false)).is_some());
+        }
+        assert!(notifications.post(now, notification("base", false)).is_none());
+        // Limits are per a pack
+        assert!(notifications.post(now, notification("other", false)).is_some());
+        // and only last for the window
+        assert!(notifications.post(now + RATE_WINDOW, notification("base", false)).is_some());
+        assert_eq!(notifications.pending().len(), RATE_LIMIT + 2);
+    }
+
+    #[test]
+    fn max_pending() {
+        let mut notifications = ScriptNotifications::default();
+        let mut now = Instant::now();
+        for _ in 0 .. MAX_PENDING + 2 {
+            now += RATE_WINDOW;
+            notifications.post(now, notification("base", false));
+        }
+        assert_eq!(notifications.pending().len(), MAX_PENDING);
+        // The oldest were dropped
+        assert_eq!(notifications.pending()[0].id, 2);
+    }
+
+    #[test]
+    fn reply() {
+        let mut notifications = ScriptNotifications::default();
+        let now = Instant::now();
+        notifications.post(now, notification("base", true));
+        notifications.post(now, notification("base", true));
+        notifications.post(now, notification("base", false));
+
+        assert!(notifications.reply(0, false));
+        assert!(notifications.reply(1, true));
+        assert!(notifications.reply(2, true));
+        assert!(!notifications.reply(1, true));
+        assert!(notifications.pending().is_empty());
+        // Only accepted notifications with actions have callbacks
+        let accepted = notifications.take_accepted();
+        assert_eq!(accepted.iter().map(|v| v.id).collect::<Vec<_>>(), vec![1]);
+        assert!(notifications.take_accepted().is_empty());
+    }
+
+    #[test]
+    fn load() {
+        let mut notifications = ScriptNotifications::default();
+        let mut saved = notification("base", false);
+        saved.id = 7;
+        notifications.load(vec![saved]);
+        notifications.post(Instant::now(), notification("base", false));
+        assert_eq!(notifications.pending().iter().map(|v| v.id).collect::<Vec<_>>(), vec![7, 8]);
     }
 }
@@ -0,0 +1,336 @@
+//! The names, colours and crests of each player's university.
+//!
+//! Packs provide the parts a campus can be customized with in
+//! `campus/crests.json`. Each player picks their identity in the
+//! lobby, after which it is fixed for the rest of the game and
+//! kept in the save. Objects marked with a `branding` slot are
+//! tinted with the owning player's colours and scripts can read
+//! the identity to use in flavor text.
+
+use serde_json;
+use std::rc::Rc;
+use crate::script;
+use lua::{self, Ref, Table, Scope};
+use crate::prelude::*;
+use super::Id;
+
+/// The longest name a campus may have in characters
+pub const MAX_NAME_LENGTH: usize = 32;
+/// The primary colour used when no packs provide any
+pub const DEFAULT_PRIMARY: CampusColor = CampusColor { r: 0x1E, g: 0x4D, b: 0x8C };
+/// The secondary colour used when no packs provide any
+pub const DEFAULT_SECONDARY: CampusColor = CampusColor { r: 0xF2, g: 0xC1, b: 0x4E };
+
+/// A colour used by a campus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DeltaEncode, Serialize, Deserialize)]
+pub struct CampusColor {
+    /// The red component of the colour
+    pub r: u8,
+    /// The green component of the colour
+    pub g: u8,
+    /// The blue component of the colour
+    pub b: u8,
+}
+
+impl CampusColor {
+    /// Returns the colour as a model tint
+    pub fn tint(self) -> (u8, u8, u8, u8) {
+        (self.r, self.g, self.b, 255)
+    }
+}
+
+/// The identity of a player's university
+#[derive(Debug, Clone, PartialEq, DeltaEncode, Serialize, Deserialize)]
+pub struct CampusIdentity {
+    /// The name of the university
+    pub name: String,
+    /// The main colour of the university
+    pub primary: CampusColor,
+    /// The accent colour of the university
+    pub secondary: CampusColor,
+    /// The image used as the outline of the crest
+    pub crest_shape: Option<ResourceKey<'static>>,
+    /// The image drawn inside the crest
+    pub crest_emblem: Option<ResourceKey<'static>>,
+}
+
+impl CampusIdentity {
+    /// Creates the default identity for the named player
+    pub fn named(player: &str) -> CampusIdentity {
+        CampusIdentity {
+            name: default_name(player),
+            primary: DEFAULT_PRIMARY,
+            secondary: DEFAULT_SECONDARY,
+            crest_shape: None,
+            crest_emblem: None,
+        }
+    }
+
+    /// Creates the default identity for the named player using
+    /// the first of each part provided by the packs
+    pub fn new(player: &str, parts: &CampusParts) -> CampusIdentity {
+        CampusIdentity {
+            name: default_name(player),
+            primary: parts.colors.first().cloned().unwrap_or(DEFAULT_PRIMARY),
+            secondary: parts.colors.get(1).cloned().unwrap_or(DEFAULT_SECONDARY),
+            crest_shape: parts.shapes.first().cloned(),
+            crest_emblem: parts.emblems.first().cloned(),
+        }
+    }
+
+    /// Replaces anything in the identity that a player shouldn't
+    /// be able to pick.
+    ///
+    /// The name is trimmed, stripped of control characters and
+    /// limited to `MAX_NAME_LENGTH`. An empty name is replaced
+    /// with `fallback`. Colours and crest parts must be one of
+    /// those provided by the packs, if any are provided.
+    pub fn sanitize(&mut self, parts: &CampusParts, fallback: &str) {
+        let name: String = self.name.chars()
+            .filter(|c| !c.is_control())
+            .collect();
+        let name: String = name.trim()
+            .chars()
+            .take(MAX_NAME_LENGTH)
+            .collect();
+        self.name = if name.trim().is_empty() {
+            fallback.to_owned()
+        } else {
+            name.trim_end().to_owned()
+        };
+
+        if !parts.colors.is_empty() {
+            if !parts.colors.contains(&self.primary) {
+                self.primary = parts.colors[0];
+            }
+            if !parts.colors.contains(&self.secondary) {
+                self.secondary = parts.colors.get(1).cloned().unwrap_or(parts.colors[0]);
+            }
+        }
+        if self.crest_shape.as_ref().map_or(false, |v| !parts.shapes.contains(v)) {
+            self.crest_shape = parts.shapes.first().cloned();
+        }
+        if self.crest_emblem.as_ref().map_or(false, |v| !parts.emblems.contains(v)) {
+            self.crest_emblem = parts.emblems.first().cloned();
+        }
+    }
+
+    /// Returns the colour for the branding slot, `None` for slots
+    /// that use the owner's profile instead
+    pub fn color(&self, slot: BrandingSlot) -> Option<CampusColor> {
+        match slot {
+            BrandingSlot::Primary => Some(self.primary),
+            BrandingSlot::Secondary => Some(self.secondary),
+            BrandingSlot::Owner => None,
+        }
+    }
+}
+
+fn default_name(player: &str) -> String {
+    let name = format!("{} University", player.trim());
+    name.chars().take(MAX_NAME_LENGTH).collect()
+}
+
+/// The parts a campus can be customized with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CampusParts {
+    /// The colours that can be picked
+    pub colors: Vec<CampusColor>,
+    /// The outlines that can be used for a crest
+    pub shapes: Vec<ResourceKey<'static>>,
+    /// The images that can be drawn inside a crest
+    pub emblems: Vec<ResourceKey<'static>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CampusPartsJson {
+    #[serde(default)]
+    colors: Vec<(u8, u8, u8)>,
+    #[serde(default)]
+    shapes: Vec<String>,
+    #[serde(default)]
+    emblems: Vec<String>,
+}
+
+/// Loads the campus parts provided by every pack
+pub fn load_parts(log: &Logger, assets: &AssetManager) -> CampusParts {
+    let mut parts = CampusParts::default();
+    for module in assets.get_packs() {
+        let file = match assets.open_from_pack(module.borrow(), "campus/crests.json") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let raw: CampusPartsJson = match serde_json::from_reader(file) {
+            Ok(val) => val,
+            Err(err) => {
+                error!(log, "Failed to parse crests.json for pack {:?}: {}", module, err);
+                continue
+            }
+        };
+        let key = |v: String| LazyResourceKey::parse(&v)
+            .or_module(module.borrow())
+            .into_owned();
+        parts.colors.extend(raw.colors.into_iter()
+            .map(|(r, g, b)| CampusColor { r, g, b }));
+        parts.shapes.extend(raw.shapes.into_iter().map(key));
+        parts.emblems.extend(raw.emblems.into_iter().map(key));
+    }
+    parts
+}
+
+/// Which of the owner's colours a branded object uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BrandingSlot {
+    /// The campus's main colour
+    Primary,
+    /// The campus's accent colour
+    Secondary,
+    /// The colour the owning player picked for themselves
+    Owner,
+}
+
+/// Marks an object as being tinted with the colours of the
+/// player that owns its room
+pub struct CampusBranding {
+    /// The colour to tint with
+    pub slot: BrandingSlot,
+}
+component!(CampusBranding => Map);
+
+/// The identities of every player in the game.
+///
+/// Stored in the world entity
+#[derive(Default)]
+pub struct Campuses {
+    /// The identity of each player
+    pub identities: FNVMap<Id, CampusIdentity>,
+}
+component!(Campuses => Map);
+
+/// Lua access to the identities of every player
+pub(crate) enum CampusStore {}
+
+impl lua::LuaUsable for CampusStore {}
+impl script::LuaTracked for CampusStore {
+    const KEY: script::NulledString = nul_str!("campuses");
+    type Storage = Rc<FNVMap<Id, CampusIdentity>>;
+    type Output = Rc<FNVMap<Id, CampusIdentity>>;
+    fn try_convert(s: &Self::Storage) -> Option<Self::Output> {
+        Some(s.clone())
+    }
+}
+
+/// Makes the identities available to scripts, replacing any
+/// previously stored
+pub fn store_campuses(lua: &lua::Lua, identities: FNVMap<Id, CampusIdentity>) {
+    lua.store_tracked::<CampusStore>(Rc::new(identities));
+}
+
+/// The view of an identity given to scripts
+#[derive(Serialize)]
+struct LuaCampus<'a> {
+    name: &'a str,
+    primary: CampusColor,
+    secondary: CampusColor,
+    crest_shape: Option<String>,
+    crest_emblem: Option<String>,
+}
+
+/// Sets up an interface for scripts to read the identities of
+/// players' universities
+pub fn init_campuslib(lua: &lua::Lua) {
+    lua.set(Scope::Global, "get_campus", lua::closure1(|lua, player: i32| -> UResult<Option<Ref<Table>>> {
+        let campuses = match lua.get_tracked::<CampusStore>() {
+            Some(val) => val,
+            None => return Ok(None),
+        };
+        let campus = match campuses.get(&Id(player as i16)) {
+            Some(val) => val,
+            None => return Ok(None),
+        };
+        Ok(Some(lua::to_table(lua, &LuaCampus {
+            name: &campus.name,
+            primary: campus.primary,
+            secondary: campus.secondary,
+            crest_shape: campus.crest_shape.as_ref().map(|v| v.as_string()),
+            crest_emblem: campus.crest_emblem.as_ref().map(|v| v.as_string()),
+        })?))
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts() -> CampusParts {
+        CampusParts {
+            colors: vec![
+                CampusColor { r: 255, g: 0, b: 0 },
+                CampusColor { r: 0, g: 255, b: 0 },
+                CampusColor { r: 0, g: 0, b: 255 },
+            ],
+            shapes: vec![ResourceKey::new("base", "campus/shield")],
+            emblems: vec![
+                ResourceKey::new("base", "campus/owl"),
+                ResourceKey::new("base", "campus/book"),
+            ],
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let campus = CampusIdentity::new("Alex", &parts());
+        assert_eq!(campus.name, "Alex University");
+        assert_eq!(campus.primary, CampusColor { r: 255, g: 0, b: 0 });
+        assert_eq!(campus.secondary, CampusColor { r: 0, g: 255, b: 0 });
+        assert_eq!(campus.crest_emblem, Some(ResourceKey::new("base", "campus/owl")));
+
+        let campus = CampusIdentity::new("Alex", &CampusParts::default());
+        assert_eq!(campus, CampusIdentity::named("Alex"));
+    }
+
+    #[test]
+    fn sanitize_name() {
+        let mut campus = CampusIdentity::named("Alex");
+        campus.name = "  Hill\n\tCollege  ".into();
+        campus.sanitize(&parts(), "Fallback");
+        assert_eq!(campus.name, "HillCollege");
+
+        campus.name = "x".repeat(100);
+        campus.sanitize(&parts(), "Fallback");
+        assert_eq!(campus.name.chars().count(), MAX_NAME_LENGTH);
+
+        campus.name = " \u{7} ".into();
+        campus.sanitize(&parts(), "Fallback");
+        assert_eq!(campus.name, "Fallback");
+    }
+
+    #[test]
+    fn sanitize_parts() {
+        let mut campus = CampusIdentity::new("Alex", &parts());
+        campus.primary = CampusColor { r: 0, g: 0, b: 255 };
+        campus.crest_emblem = Some(ResourceKey::new("base", "campus/book"));
+        campus.sanitize(&parts(), "Fallback");
+        // Valid choices are kept
+        assert_eq!(campus.primary, CampusColor { r: 0, g: 0, b: 255 });
+        assert_eq!(campus.crest_emblem, Some(ResourceKey::new("base", "campus/book")));
+
+        campus.secondary = CampusColor { r: 1, g: 2, b: 3 };
+        campus.crest_shape = Some(ResourceKey::new("base", "campus/missing"));
+        campus.crest_emblem = None;
+        campus.sanitize(&parts(), "Fallback");
+        assert_eq!(campus.secondary, CampusColor { r: 0, g: 255, b: 0 });
+        assert_eq!(campus.crest_shape, Some(ResourceKey::new("base", "campus/shield")));
+        // No emblem is always allowed
+        assert_eq!(campus.crest_emblem, None);
+    }
+
+    #[test]
+    fn any_color_without_parts() {
+        let mut campus = CampusIdentity::named("Alex");
+        campus.primary = CampusColor { r: 1, g: 2, b: 3 };
+        campus.sanitize(&CampusParts::default(), "Fallback");
+        assert_eq!(campus.primary, CampusColor { r: 1, g: 2, b: 3 });
+    }
+}
@@ -0,0 +1,237 @@
+//! Searching through the player's staff and students.
+//!
+//! The directory is built on the server from every entity the
+//! player owns and split into pages after filtering so that large
+//! universities don't have to send everyone to the client at once.
+
+use std::sync::Arc;
+use crate::ecs;
+use crate::entity::course;
+use crate::prelude::*;
+
+/// The number of entries sent in a single page
+pub const PAGE_SIZE: usize = 20;
+
+/// The type of entity listed in the directory
+#[derive(DeltaEncode, Clone, Copy, PartialEq, Eq, Debug)]
+#[delta_always]
+pub enum DirectoryKind {
+    /// Hired staff members
+    Staff,
+    /// Enrolled students
+    Students,
+}
+
+/// An inclusive range that a stat must be within
+#[derive(DeltaEncode, Clone, Copy, PartialEq, Debug)]
+#[delta_always]
+pub struct StatRange {
+    /// The lowest allowed value
+    pub min: f32,
+    /// The highest allowed value
+    pub max: f32,
+}
+
+impl StatRange {
+    fn contains(self, val: Option<f32>) -> bool {
+        val.map_or(false, |v| v >= self.min && v <= self.max)
+    }
+}
+
+/// Filters the entities listed in the directory
+#[derive(DeltaEncode, Clone, PartialEq, Debug)]
+#[delta_always]
+pub struct DirectoryFilter {
+    /// The type of entity to list
+    pub kind: DirectoryKind,
+    /// Only lists entities with a name containing this,
+    /// ignoring case. Empty lists everyone.
+    pub name: String,
+    /// Only lists students on this course
+    pub course: Option<course::CourseId>,
+    /// Only lists entities with a skill in this range.
+    ///
+    /// Entities without a skill stat are never listed
+    pub skill: Option<StatRange>,
+    /// Only lists entities with a happiness in this range
+    pub happiness: Option<StatRange>,
+}
+
+impl DirectoryFilter {
+    /// Creates a filter that lists every entity of the type
+    pub fn all(kind: DirectoryKind) -> DirectoryFilter {
+        DirectoryFilter {
+            kind,
+            name: String::new(),
+            course: None,
+            skill: None,
+            happiness: None,
+        }
+    }
+
+    /// Returns whether the entry should be listed
+    pub fn matches(&self, entry: &DirectoryEntry) -> bool {
+        if !self.name.is_empty() {
+            let name = self.name.to_lowercase();
+            if !entry.first_name.to_lowercase().contains(&name)
+                && !entry.surname.to_lowercase().contains(&name)
+                && !format!("{} {}", entry.first_name, entry.surname).to_lowercase().contains(&name)
+            {
+                return false;
+            }
+        }
+        if self.course.map_or(false, |v| entry.course != Some(v)) {
+            return false;
+        }
+        if self.skill.map_or(false, |v| !v.contains(entry.skill)) {
+            return false;
+        }
+        if self.happiness.map_or(false, |v| !v.contains(entry.happiness)) {
+            return false;
+        }
+        true
+    }
+}
+
+/// An entity listed in the directory
+#[derive(DeltaEncode, Clone, Debug)]
+#[delta_always]
+pub struct DirectoryEntry {
+    /// The network id of the entity
+    #[delta_bits = "20"]
+    pub entity_id: u32,
+    /// The type of the entity
+    pub key: ResourceKey<'static>,
+    /// The variant of the entity
+    pub variant: u16,
+    /// The entity's first name
+    pub first_name: Arc<str>,
+    /// The entity's surname
+    pub surname: Arc<str>,
+    /// The course the entity is on if a student
+    pub course: Option<course::CourseId>,
+    /// The entity's skill if it has one
+    pub skill: Option<f32>,
+    /// The entity's happiness if it has one
+    pub happiness: Option<f32>,
+}
+
+/// Returns every entity of the type owned by the player
+pub(crate) fn directory_entries(log: &Logger, entities: &mut ecs::Container, player: player::Id, kind: DirectoryKind) -> Vec<DirectoryEntry> {
+    let mask = entities.mask_for::<Owned>()
+        .and_component::<Living>(entities)
+        .and_component::<NetworkId>(entities);
+    let mask = match kind {
+        DirectoryKind::Staff => mask.and_component::<Paid>(entities),
+        DirectoryKind::Students => mask.and_component::<StudentController>(entities),
+    };
+    let owned = entities.iter_mask(&mask)
+        .filter(|v| entities.get_component::<Owned>(*v).map_or(false, |v| v.player_id == player))
+        .collect::<Vec<_>>();
+
+    let mut entries = Vec::with_capacity(owned.len());
+    for e in owned {
+        let entity_id = assume!(log, entities.get_component::<NetworkId>(e)).0;
+        let (key, variant, name) = {
+            let living = assume!(log, entities.get_component::<Living>(e));
+            (living.key.clone(), living.variant as u16, living.name.clone())
+        };
+        let course = entities.get_component::<TimeTable>(e).map(|v| v.course);
+        let (skill, happiness) = if let Some(vars) = get_vars(entities, e) {
+            (vars.get_float("skill"), vars.get_float("happiness"))
+        } else {
+            (None, None)
+        };
+        entries.push(DirectoryEntry {
+            entity_id,
+            key,
+            variant,
+            first_name: name.0,
+            surname: name.1,
+            course,
+            skill,
+            happiness,
+        });
+    }
+    entries
+}
+
+/// Filters and sorts the entries by name returning the requested
+/// page, the page actually returned and the number of pages.
+///
+/// The last page is returned if the requested one is out of bounds
+pub fn paginate(mut entries: Vec<DirectoryEntry>, filter: &DirectoryFilter, page: u16) -> (Vec<DirectoryEntry>, u16, u16) {
+    entries.retain(|v| filter.matches(v));
+    entries.sort_by(|a, b| a.surname.cmp(&b.surname)
+        .then_with(|| a.first_name.cmp(&b.first_name))
+        .then_with(|| a.entity_id.cmp(&b.entity_id)));
+    let num_pages = (entries.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+    let page = ::std::cmp::min(page as usize, num_pages.saturating_sub(1));
+    let entries = entries.into_iter()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .collect();
+    (entries, page as u16, num_pages as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u32, first: &str, surname: &str, course: Option<u32>, happiness: f32) -> DirectoryEntry {
+        DirectoryEntry {
+            entity_id: id,
+            key: ResourceKey::new("base", "student"),
+            variant: 0,
+            first_name: first.into(),
+            surname: surname.into(),
+            course: course.map(course::CourseId),
+            skill: None,
+            happiness: Some(happiness),
+        }
+    }
+
+    #[test]
+    fn filters() {
+        let alice = entry(1, "Alice", "Smith", Some(1), 0.9);
+        let bob = entry(2, "Bob", "Jones", None, 0.2);
+
+        let mut filter = DirectoryFilter::all(DirectoryKind::Students);
+        assert!(filter.matches(&alice) && filter.matches(&bob));
+
+        filter.name = "ice sm".to_owned();
+        assert!(filter.matches(&alice) && !filter.matches(&bob));
+
+        let mut filter = DirectoryFilter::all(DirectoryKind::Students);
+        filter.course = Some(course::CourseId(1));
+        assert!(filter.matches(&alice) && !filter.matches(&bob));
+
+        let mut filter = DirectoryFilter::all(DirectoryKind::Students);
+        filter.happiness = Some(StatRange { min: 0.0, max: 0.5 });
+        assert!(!filter.matches(&alice) && filter.matches(&bob));
+
+        // Entities without the stat never match
+        filter.skill = Some(StatRange { min: 0.0, max: 1.0 });
+        assert!(!filter.matches(&bob));
+    }
+
+    #[test]
+    fn pages() {
+        let entries = (0 .. PAGE_SIZE as u32 * 2 + 5)
+            .map(|v| entry(v, "Student", &format!("{:03}", v), None, 1.0))
+            .collect::<Vec<_>>();
+        let filter = DirectoryFilter::all(DirectoryKind::Students);
+
+        let (page, idx, num_pages) = paginate(entries.clone(), &filter, 1);
+        assert_eq!((idx, num_pages), (1, 3));
+        assert_eq!(page.len(), PAGE_SIZE);
+        assert_eq!(page[0].entity_id, PAGE_SIZE as u32);
+
+        let (page, idx, _) = paginate(entries, &filter, 10);
+        assert_eq!(idx, 2);
+        assert_eq!(page.len(), 5);
+
+        let (page, idx, num_pages) = paginate(Vec::new(), &filter, 3);
+        assert_eq!((page.len(), idx, num_pages), (0, 0, 0));
+    }
+}
@@ -0,0 +1,200 @@
+//! Money made and spent by each room.
+//!
+//! Income and costs that can be traced back to a room are recorded
+//! against it as they happen and summed up per day, letting players
+//! find the rooms that are losing them money:
+//!
+//! * Lessons - money given by entities in the room via `give_money`
+//! * Sales - services charged for in the room via `charge`
+//! * Wages - pay for the staff working in the room
+//! * Maintenance - repairing the room after a fire
+//!
+//! Only the last `HISTORY_DAYS` days are kept.
+
+use std::collections::VecDeque;
+use crate::ecs;
+use crate::level::room;
+use crate::prelude::*;
+
+/// The number of days of history kept for a room
+pub const HISTORY_DAYS: usize = 14;
+
+/// The type of money recorded against a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinanceKind {
+    /// Income from lessons held in the room
+    Lessons,
+    /// Income from services sold in the room
+    Sales,
+    /// Pay for the staff working in the room
+    Wages,
+    /// Repairs to the room
+    Maintenance,
+}
+
+/// The money made and spent by a room over a day.
+///
+/// Costs are stored as positive amounts
+#[derive(Debug, Clone, Default, PartialEq, DeltaEncode, Serialize, Deserialize)]
+pub struct RoomFinanceDay {
+    /// The day this covers
+    pub day: u32,
+    /// Income from lessons
+    pub lessons: UniDollar,
+    /// Income from sales
+    pub sales: UniDollar,
+    /// The cost of staff
+    pub wages: UniDollar,
+    /// The cost of repairs
+    pub maintenance: UniDollar,
+}
+
+impl RoomFinanceDay {
+    /// Returns the total income of the room for the day
+    pub fn income(&self) -> UniDollar {
+        self.lessons + self.sales
+    }
+
+    /// Returns the total costs of the room for the day
+    pub fn costs(&self) -> UniDollar {
+        self.wages + self.maintenance
+    }
+
+    /// Returns the money the room made (or lost) over the day
+    pub fn profit(&self) -> UniDollar {
+        self.income() - self.costs()
+    }
+}
+
+/// The finances of every room.
+///
+/// Stored on the world entity
+#[derive(Default)]
+pub struct RoomFinances {
+    rooms: FNVMap<room::Id, VecDeque<RoomFinanceDay>>,
+}
+component!(RoomFinances => Map);
+
+impl RoomFinances {
+    /// Records the money against the room for the day
+    pub fn record(&mut self, room: room::Id, day: u32, kind: FinanceKind, amount: UniDollar) {
+        let days = self.rooms.entry(room).or_insert_with(VecDeque::new);
+        if days.back().map_or(true, |v| v.day != day) {
+            days.push_back(RoomFinanceDay {
+                day,
+                .. RoomFinanceDay::default()
+            });
+            while days.len() > HISTORY_DAYS {
+                days.pop_front();
+            }
+        }
+        if let Some(current) = days.back_mut() {
+            match kind {
+                FinanceKind::Lessons => current.lessons += amount,
+                FinanceKind::Sales => current.sales += amount,
+                FinanceKind::Wages => current.wages += amount,
+                FinanceKind::Maintenance => current.maintenance += amount,
+            }
+        }
+    }
+
+    /// Returns the room's finances for each of the last
+    /// `HISTORY_DAYS` days up to and including `today`,
+    /// oldest first.
+    ///
+    /// Days without any money recorded are filled in
+    pub fn history(&self, room: room::Id, today: u32) -> Vec<RoomFinanceDay> {
+        let first = today.saturating_sub(HISTORY_DAYS as u32 - 1);
+        let recorded = self.rooms.get(&room);
+        (first ..= today)
+            .map(|day| recorded
+                .and_then(|v| v.iter().find(|v| v.day == day))
+                .cloned()
+                .unwrap_or_else(|| RoomFinanceDay {
+                    day,
+                    .. RoomFinanceDay::default()
+                }))
+            .collect()
+    }
+
+    /// Removes the history of the room
+    pub fn remove_room(&mut self, room: room::Id) {
+        self.rooms.remove(&room);
+    }
+
+    /// Returns the history of every room to be saved
+    pub(crate) fn save(&self) -> Vec<(room::Id, Vec<RoomFinanceDay>)> {
+        self.rooms.iter()
+            .map(|(id, days)| (*id, days.iter().cloned().collect()))
+            .collect()
+    }
+
+    /// Replaces the history of every room with one from a save
+    pub(crate) fn restore(&mut self, rooms: Vec<(room::Id, Vec<RoomFinanceDay>)>) {
+        self.rooms = rooms.into_iter()
+            .map(|(id, days)| (id, days.into()))
+            .collect();
+    }
+}
+
+/// Records the money against the room for the current day
+pub fn record_room_money(entities: &mut ecs::Container, room: room::Id, kind: FinanceKind, amount: UniDollar) {
+    let day = entities.get_component::<DayTick>(ecs::Container::WORLD).map_or(0, |v| v.day);
+    if let Some(finances) = entities.get_component_mut::<RoomFinances>(ecs::Container::WORLD) {
+        finances.record(room, day, kind, amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_per_day() {
+        let mut finances = RoomFinances::default();
+        let room = room::Id(1);
+        finances.record(room, 3, FinanceKind::Lessons, UniDollar(500));
+        finances.record(room, 3, FinanceKind::Sales, UniDollar(50));
+        finances.record(room, 3, FinanceKind::Wages, UniDollar(300));
+        finances.record(room, 5, FinanceKind::Maintenance, UniDollar(800));
+        finances.record(room::Id(2), 5, FinanceKind::Sales, UniDollar(10));
+
+        let history = finances.history(room, 5);
+        assert_eq!(history.len(), HISTORY_DAYS);
+        assert_eq!(history.last().map(|v| v.day), Some(5));
+        let day3 = &history[HISTORY_DAYS - 3];
+        assert_eq!(day3.day, 3);
+        assert_eq!(day3.income(), UniDollar(550));
+        assert_eq!(day3.costs(), UniDollar(300));
+        assert_eq!(day3.profit(), UniDollar(250));
+        assert_eq!(history[HISTORY_DAYS - 2].profit(), UniDollar(0));
+        assert_eq!(history[HISTORY_DAYS - 1].profit(), UniDollar(-800));
+    }
+
+    #[test]
+    fn drops_old_days() {
+        let mut finances = RoomFinances::default();
+        let room = room::Id(1);
+        for day in 0 .. 30 {
+            finances.record(room, day, FinanceKind::Sales, UniDollar(i64::from(day)));
+        }
+        assert_eq!(finances.rooms[&room].len(), HISTORY_DAYS);
+        let history = finances.history(room, 29);
+        assert_eq!(history[0].day, 16);
+        assert_eq!(history[0].sales, UniDollar(16));
+
+        finances.remove_room(room);
+        assert!(finances.history(room, 29).iter().all(|v| v.profit() == UniDollar(0)));
+    }
+
+    #[test]
+    fn save_and_restore() {
+        let mut finances = RoomFinances::default();
+        finances.record(room::Id(4), 2, FinanceKind::Wages, UniDollar(120));
+        let saved = finances.save();
+
+        let mut loaded = RoomFinances::default();
+        loaded.restore(saved);
+        assert_eq!(loaded.history(room::Id(4), 2).last().map(|v| v.wages), Some(UniDollar(120)));
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt::{self, Display};
 use std::collections::VecDeque;
 use std::sync::Arc;
 use std::mem;
+use std::cmp;
 
 use crate::prelude::*;
 use crate::command;
@@ -18,9 +19,16 @@ use delta_encode::AlwaysVec;
 use steamworks;
 use crate::steam;
 use crate::common;
+use crate::errors;
 use crate::network;
 use crate::saving::filesystem;
 
+/// The largest voice packet that will be relayed to other players
+const MAX_VOICE_DATA: usize = 8 * 1024;
+/// The largest area (in tiles) the navigation debug overlay
+/// can request at once
+const MAX_PATH_DEBUG_SIZE: u32 = 64;
+
 pub(crate) struct NetworkedPlayer<S: Socket> {
     log: Logger,
     pub id: S::Id,
@@ -38,15 +46,21 @@ pub(crate) struct NetworkedPlayer<S: Socket> {
     pub local_state: PlayerState,
 
     pub last_packet: Instant,
+    /// Used to mark the player as away once they stop playing
+    pub idle: player::IdleTracker,
 
     pub last_command: u32,
-    // The id of the last failed command, don't accept
-    // commands until the client has reverted its mistake
-    pub failed_command: Option<u32>,
+    // The id of the last failed command and why it failed,
+    // don't accept commands until the client has reverted
+    // its mistake
+    pub failed_command: Option<(u32, errors::ErrorCode)>,
 
     pub commands: Vec<Command>,
     pub remote_commands: RemoteCommandList,
     pub messages: Vec<Message>,
+    /// Voice data recorded by the player that is waiting
+    /// to be relayed to the other players
+    pub voice: Vec<Vec<u8>>,
 
     pub entity_state: EntitySnapshotState,
     pub player_state: u16,
@@ -55,6 +69,12 @@ pub(crate) struct NetworkedPlayer<S: Socket> {
 
     /// Used in single player if the player is forcing a save
     pub wants_save: bool,
+    /// Whether the player wants the tick timings for
+    /// the performance hud
+    pub wants_perf_stats: bool,
+    /// Where the player's camera is, entities far from every
+    /// player's camera are simplified. See `entity::lod`
+    pub view_area: Option<(f32, f32)>,
 }
 
 pub(crate) struct RemoteCommandList {
@@ -89,6 +109,7 @@ impl <S: Socket> NetworkedPlayer<S> {
             remote_state: PlayerState::Connecting,
             local_state: PlayerState::Connecting,
             last_packet: Instant::now(),
+            idle: player::IdleTracker::new(Instant::now()),
             last_command: 0,
             failed_command: None,
             commands: vec![],
@@ -97,9 +118,12 @@ impl <S: Socket> NetworkedPlayer<S> {
                 commands: vec![],
             },
             messages: Vec::new(),
+            voice: Vec::new(),
             entity_state: EntitySnapshotState::new(),
             player_state: INVALID_FRAME,
             wants_save: false,
+            wants_perf_stats: false,
+            view_area: None,
             request_manager: network::RequestManager::new(),
         }
     }
@@ -109,7 +133,7 @@ impl <S: Socket> NetworkedPlayer<S> {
         server_state: &mut ServerState,
         asset_manager: &AssetManager,
         fs: &F,
-        config: &crate::ServerConfig,
+        config: &mut crate::ServerConfig,
         connection: &mut Connection<S>, next_uid: i16,
         info: &mut FNVMap<PlayerId, PlayerInfo>,
         steam: &Steam,
@@ -129,7 +153,7 @@ impl <S: Socket> NetworkedPlayer<S> {
         server_state: &mut ServerState,
         asset_manager: &AssetManager,
         fs: &F,
-        config: &crate::ServerConfig,
+        config: &mut crate::ServerConfig,
         connection: &mut Connection<S>, next_uid: i16,
         info: &mut FNVMap<PlayerId, PlayerInfo>,
         steam: &Steam,
@@ -143,10 +167,22 @@ impl <S: Socket> NetworkedPlayer<S> {
         'packets:
         while let Ok(pck) = connection.recv() {
             self.last_packet = Instant::now();
+            if player::is_activity(&pck) {
+                self.idle.activity(self.last_packet);
+            }
             match (self.remote_state, pck) {
                 (Playing, SaveGame(_)) if S::is_local() => {
                     self.wants_save = true;
                 },
+                (Playing, SetPerfStats(pck)) => {
+                    self.wants_perf_stats = pck.enabled;
+                },
+                (Playing, NotificationReply(pck)) => {
+                    let info = assume!(self.log, info.get_mut(&
+                        assume!(self.log, self.uid)
+                    ));
+                    info.script_notifications.reply(pck.id, pck.accepted);
+                },
                 (Playing, ChatMessage(pck)) => {
                     let info = assume!(self.log, info.get_mut(&
                         assume!(self.log, self.uid)
@@ -208,6 +244,61 @@ impl <S: Socket> NetworkedPlayer<S> {
                                     }
                                 }
                             },
+                            "stresstest stop" => {
+                                if let SPlaying{ref mut spawning, ..} = *server_state {
+                                    let text = if spawning.stop_stress_test() {
+                                        "Stopped the stress test"
+                                    } else {
+                                        "No stress test is running"
+                                    };
+                                    let msg = crate::msg::Message::new()
+                                        .special()
+                                        .color(255, 211, 196)
+                                        .text(text)
+                                        .build();
+                                    connection.ensure_send(packet::Message {
+                                        messages: AlwaysVec(vec![msg]),
+                                    })?;
+                                }
+                            },
+                            cmd if cmd.starts_with("stresstest ") => {
+                                let mut args = cmd["stresstest ".len()..].split_whitespace()
+                                    .map(|v| v.parse::<u32>());
+                                if let (Some(Ok(count)), batch) = (args.next(), args.next()) {
+                                    let batch = batch.and_then(|v| v.ok()).unwrap_or(10);
+                                    if let SPlaying{ref mut spawning, ..} = *server_state {
+                                        let staff_list = load_staff_list(&self.log, asset_manager);
+                                        spawning.start_stress_test(info.uid, count, batch, staff_list);
+                                        let msg = crate::msg::Message::new()
+                                            .special()
+                                            .color(255, 211, 196)
+                                            .text(format!("Spawning {} entities, {} per a tick", count, batch))
+                                            .build();
+                                        connection.ensure_send(packet::Message {
+                                            messages: AlwaysVec(vec![msg]),
+                                        })?;
+                                    }
+                                }
+                            },
+                            cmd if cmd.starts_with("fire ") => {
+                                let mut args = cmd["fire ".len()..].split_whitespace()
+                                    .map(|v| v.parse::<i32>());
+                                if let (Some(Ok(x)), Some(Ok(y))) = (args.next(), args.next()) {
+                                    if let SPlaying{ref mut entities, ..} = *server_state {
+                                        if let Some(fires) = entities.get_component_mut::<crate::entity::fire::Fires>(Container::WORLD) {
+                                            fires.start(Location::new(x, y));
+                                        }
+                                        let msg = crate::msg::Message::new()
+                                            .special()
+                                            .color(255, 211, 196)
+                                            .text(format!("Started a fire at {}, {}", x, y))
+                                            .build();
+                                        connection.ensure_send(packet::Message {
+                                            messages: AlwaysVec(vec![msg]),
+                                        })?;
+                                    }
+                                }
+                            },
                             "notifytest" => {
                                 info.notifications.push(crate::notify::Notification::Text {
                                     icon: ResourceKey::new("base", "solid"),
@@ -239,6 +330,28 @@ impl <S: Socket> NetworkedPlayer<S> {
                         // self.messages.push(msg);
                     }
                 },
+                (Playing, ShareChart(pck)) => {
+                    let info = assume!(self.log, info.get(&assume!(self.log, self.uid)));
+                    let (r, g, b) = info.profile.rgb();
+                    let msg = crate::msg::Message::new()
+                        .color(r, g, b)
+                        .text(info.name.as_str())
+                        .color(255, 255, 255)
+                        .text(format!(" shared their {} chart", pck.kind.name()))
+                        .chart(crate::msg::SharedChart {
+                            kind: pck.kind,
+                            history: info.history.iter().cloned().collect(),
+                        })
+                        .build();
+                    self.messages.push(msg);
+                },
+                (Lobby, VoiceData(pck)) | (Playing, VoiceData(pck)) => {
+                    // Drop anything larger than steam would ever
+                    // produce to prevent abuse of the relay
+                    if pck.data.0.len() <= MAX_VOICE_DATA {
+                        self.voice.push(pck.data.0);
+                    }
+                },
                 (Playing, SetPauseGame(ref pck)) if S::is_local() => {
                     if let SPlaying{ref mut paused, ..} = *server_state {
                         *paused = pck.paused;
@@ -270,7 +383,7 @@ impl <S: Socket> NetworkedPlayer<S> {
                             // we drop all commands from them until the client
                             // sends a sorry command letting us know they
                             // are back in sync.
-                            if let Some(failed) = self.failed_command {
+                            if let Some((failed, _)) = self.failed_command {
                                 if id != failed {
                                     continue;
                                 }
@@ -287,7 +400,9 @@ impl <S: Socket> NetworkedPlayer<S> {
                             // The client sends the commands its executed, we
                             // need to execute the same command ourselves to
                             // validate what they did.
-                            let mut h = Handler;
+                            let mut h = Handler {
+                                level_editor: S::is_local(),
+                            };
 
                             match cmd.execute(&mut h, info, command::CommandParams {
                                 log: &self.log,
@@ -303,10 +418,12 @@ impl <S: Socket> NetworkedPlayer<S> {
                                     // Command failed to validate, either lag + interaction with another
                                     // player or a cheat attempt. Roll them back and ignore them until they
                                     // do.
-                                    self.failed_command = Some(id);
+                                    let code = err.code();
+                                    self.failed_command = Some((id, code));
                                     connection.send(packet::RejectCommands{
                                         accepted_id: self.last_command,
                                         rejected_id: id,
+                                        code: code.code(),
                                     })?;
                                     continue 'packets;
                                 },
@@ -317,10 +434,11 @@ impl <S: Socket> NetworkedPlayer<S> {
                         // If we are still waiting for the client to roll back
                         // send the request again as the packet may have been dropped
                         // the client never received it.
-                        if let Some(failed) = self.failed_command {
+                        if let Some((failed, code)) = self.failed_command {
                             connection.send(packet::RejectCommands{
                                 accepted_id: self.last_command,
                                 rejected_id: failed,
+                                code: code.code(),
                             })?;
                         } else {
                             // Let the client know its commands were accepted
@@ -335,10 +453,116 @@ impl <S: Socket> NetworkedPlayer<S> {
                     self.remote_state = Playing;
                     info!(self.log, "loaded in");
                     connection.ensure_send(packet::GameStart{})?;
+                    // Show any notifications from scripts that weren't
+                    // acknowledged before the player left
+                    if let Some(info) = self.uid.and_then(|v| info.get_mut(&v)) {
+                        let pending = info.script_notifications.pending().iter()
+                            .map(|v| v.to_notification())
+                            .collect::<Vec<_>>();
+                        info.notifications.retain(|v| !matches!(v, Notification::Custom{..}));
+                        info.notifications.extend(pending);
+                    }
+                    if let SPlaying{ref entities, ..} = *server_state {
+                        let tiles: Vec<_> = entities.get_component::<crate::entity::dirt::Dirt>(Container::WORLD)
+                            .map_or_else(Vec::new, |v| v.tiles()
+                                .map(|(loc, stage)| packet::DirtTile {
+                                    x: loc.x,
+                                    y: loc.y,
+                                    stage,
+                                })
+                                .collect());
+                        if !tiles.is_empty() {
+                            connection.ensure_send(packet::DirtUpdate {
+                                tiles: AlwaysVec(tiles),
+                            })?;
+                        }
+                        let tiles: Vec<_> = entities.get_component::<crate::entity::litter::Litter>(Container::WORLD)
+                            .map_or_else(Vec::new, |v| v.tiles()
+                                .map(|(loc, pieces)| packet::LitterTile {
+                                    x: loc.x,
+                                    y: loc.y,
+                                    pieces,
+                                })
+                                .collect());
+                        if !tiles.is_empty() {
+                            connection.ensure_send(packet::LitterUpdate {
+                                tiles: AlwaysVec(tiles),
+                            })?;
+                        }
+                        let rooms: Vec<_> = entities.get_component::<crate::entity::construction::Construction>(Container::WORLD)
+                            .map_or_else(Vec::new, |v| v.sites()
+                                .map(|(room_id, site)| packet::ConstructionSite {
+                                    room_id,
+                                    stage: Some(site.stage()),
+                                })
+                                .collect());
+                        if !rooms.is_empty() {
+                            connection.ensure_send(packet::ConstructionUpdate {
+                                rooms: AlwaysVec(rooms),
+                            })?;
+                        }
+                    }
                 },
                 (Lobby, RequestGameBegin(..)) => {
                     *server_state = ServerState::BeginGame;
                 },
+                (Lobby, SetGoals(pck)) => {
+                    if let ServerState::Lobby{change_id, ..} = *server_state {
+                        // Loaded games keep the goals they were saved with
+                        if config.mission.is_none() && !config.locked_players {
+                            config.goals = pck.key
+                                .filter(|v| crate::entity::goals::find_goal_set(&self.log, asset_manager, v.borrow()).is_some());
+                            *server_state = ServerState::Lobby{
+                                change_id,
+                                state_dirty: true
+                            };
+                        }
+                    }
+                },
+                (Lobby, SetCampus(pck)) => {
+                    if let ServerState::Lobby{change_id, ..} = *server_state {
+                        // Loaded games keep the campuses they were saved with
+                        if config.mission.is_none() && !config.locked_players {
+                            if let Some(info) = self.uid.and_then(|v| info.get_mut(&v)) {
+                                let parts = player::load_campus_parts(&self.log, asset_manager);
+                                let mut identity = pck.identity;
+                                identity.sanitize(&parts, &info.campus.name);
+                                info.campus = identity;
+                                *server_state = ServerState::Lobby{
+                                    change_id,
+                                    state_dirty: true
+                                };
+                            }
+                        }
+                    }
+                },
+                (Lobby, SetProfile(pck)) => {
+                    if let ServerState::Lobby{change_id, ..} = *server_state {
+                        // Unlike campuses profiles can be changed in loaded
+                        // games as they don't affect anything once playing
+                        if let Some(uid) = self.uid {
+                            let taken: Vec<u8> = info.values()
+                                .filter(|v| v.uid != uid)
+                                .map(|v| v.profile.color)
+                                .collect();
+                            if let Some(info) = info.get_mut(&uid) {
+                                let parts = player::load_profile_parts(&self.log, asset_manager);
+                                let mut profile = pck.profile;
+                                if taken.contains(&profile.color) {
+                                    // Keep the current colour instead of picking
+                                    // one the player didn't ask for
+                                    profile.color = info.profile.color;
+                                }
+                                profile.sanitize(&parts, &taken);
+                                info.profile = profile;
+                                *server_state = ServerState::Lobby{
+                                    change_id,
+                                    state_dirty: true
+                                };
+                            }
+                        }
+                    }
+                },
                 (Connecting, EnterLobby(..)) => {
                     self.remote_state = Lobby;
                     if let ServerState::Lobby{change_id, ..} = *server_state {
@@ -352,7 +576,7 @@ impl <S: Socket> NetworkedPlayer<S> {
                     if let ServerState::Lobby{..} = *server_state {
                         self.local_state = Connecting;
                         self.uid = Some(PlayerId(1));
-                        *server_state = ServerState::create_play_state(&self.log, asset_manager, fs, info, &config, &[PlayerId(1)]);
+                        *server_state = ServerState::create_play_state(&self.log, asset_manager, fs, info, config, &[PlayerId(1)], None);
                         let self_info = if let Some(info) = info.get_mut(&PlayerId(1)) {
                             // Already loaded from save
                             info.name = pck.name.clone();
@@ -363,10 +587,13 @@ impl <S: Socket> NetworkedPlayer<S> {
                             let key = PlayerKey::Steam(steamworks::SteamId::from_raw(pck.steam_id));
                             #[cfg(not(feature = "steam"))]
                             let key = PlayerKey::Username(pck.name.clone());
-                            Some(PlayerInfo::new(
+                            let mut self_info = PlayerInfo::new(
                                 key,
                                 pck.name.clone(), PlayerId(1), &staff_list
-                            ))
+                            );
+                            let fallback = self_info.campus.name.clone();
+                            self_info.campus.sanitize(&player::load_campus_parts(&self.log, asset_manager), &fallback);
+                            Some(self_info)
                         };
                         info!(self.log, "Player {:?} joined in", pck.name);
                         if let ServerState::Playing{
@@ -381,21 +608,25 @@ impl <S: Socket> NetworkedPlayer<S> {
                             self.local_state = Playing;
                             let (lstr, lstate) = level.create_initial_state();
                             let idle = crate::script_room::create_choices_state(&self.log, entities, scripting, choices, running_choices);
-                            connection.ensure_send(packet::GameBegin {
+                            connection.send_large(packet::GameBegin {
                                 uid: 1,
                                 width: level.width,
                                 height: level.height,
                                 players: AlwaysVec(info.values()
+                                    .chain(self_info.as_ref())
                                     .map(|p| packet::PlayerEntry {
                                         uid: p.uid,
                                         username: p.name.clone(),
                                         state: p.state.clone(),
+                                        campus: p.campus.clone(),
+                                        profile: p.profile.clone(),
                                     })
                                     .collect()),
                                 mission_handler: mission.as_ref().map(|v| v.handler.borrow().into_owned()),
                                 strings: AlwaysVec(lstr),
                                 state: lstate,
                                 idle_state: idle,
+                                seasons: AlwaysVec(asset_manager.active_seasons()),
                             })?;
                             return Ok(self_info);
                         } else {
@@ -404,6 +635,13 @@ impl <S: Socket> NetworkedPlayer<S> {
                     }
                 },
                 (Connecting, RemoteConnectionStart(pck)) => {
+                    if pck.protocol_hash != packet::protocol_hash() {
+                        connection.ensure_send(packet::ServerConnectionFail {
+                            code: errors::ErrorCode::VersionMismatch.code(),
+                            detail: None,
+                        })?;
+                        bail!("Player {:?} connected with a different protocol", pck.name);
+                    }
                     #[cfg(feature = "steam")]
                     let steam_id = steamworks::SteamId::from_raw(pck.steam_id);
                     #[cfg(feature = "steam")]
@@ -411,7 +649,8 @@ impl <S: Socket> NetworkedPlayer<S> {
                         if S::needs_verify() {
                             if let Err(err) = steam.begin_authentication_session(steam_id, &pck.ticket.0) {
                                 connection.ensure_send(packet::ServerConnectionFail {
-                                    reason: format!("Steam authentication failed: {}", err),
+                                    code: errors::ErrorCode::AuthenticationFailed.code(),
+                                    detail: Some(format!("{}", err)),
                                 })?;
                                 bail!("{}", err);
                             }
@@ -419,7 +658,16 @@ impl <S: Socket> NetworkedPlayer<S> {
                         PlayerKey::Steam(steam_id)
                     };
                     #[cfg(not(feature = "steam"))]
-                    let key = PlayerKey::Username(pck.name.clone());
+                    let key = {
+                        if S::needs_verify() && !config.auth.verify(&pck.name, &pck.password) {
+                            connection.ensure_send(packet::ServerConnectionFail {
+                                code: errors::ErrorCode::IncorrectPassword.code(),
+                                detail: None,
+                            })?;
+                            bail!("Player {:?} failed to authenticate", pck.name);
+                        }
+                        PlayerKey::Username(pck.name.clone())
+                    };
 
                     let msg = crate::msg::Message::new()
                         .color(130, 237, 123)
@@ -450,9 +698,10 @@ impl <S: Socket> NetworkedPlayer<S> {
                             } else {
                                 if config.locked_players {
                                     connection.ensure_send(packet::ServerConnectionFail {
-                                        reason: "Server not accepting new players".into(),
+                                        code: errors::ErrorCode::NotAcceptingPlayers.code(),
+                                        detail: None,
                                     })?;
-                                    bail!("Server not accepting new players");
+                                    bail!(errors::ErrorKind::Rejected(errors::ErrorCode::NotAcceptingPlayers, None));
                                 }
                                 self.uid = Some(PlayerId(next_uid));
                                 *server_state = ServerState::Lobby{
@@ -496,11 +745,13 @@ impl <S: Socket> NetworkedPlayer<S> {
                                             uid: p.uid,
                                             username: p.name.clone(),
                                             state: p.state.clone(),
+                                            campus: p.campus.clone(),
+                                            profile: p.profile.clone(),
                                         })
                                         .collect();
                                 let (lstr, lstate) = level.create_initial_state();
                                 let idle = crate::script_room::create_choices_state(&self.log, entities, scripting, choices, running_choices);
-                                connection.ensure_send(packet::GameBegin {
+                                connection.send_large(packet::GameBegin {
                                     uid: self_info.uid.0,
                                     width: level.width,
                                     height: level.height,
@@ -509,13 +760,15 @@ impl <S: Socket> NetworkedPlayer<S> {
                                     strings: AlwaysVec(lstr),
                                     state: lstate,
                                     idle_state: idle,
+                                    seasons: AlwaysVec(asset_manager.active_seasons()),
                                 })?;
                                 return Ok(None);
                             } else {
                                 connection.ensure_send(packet::ServerConnectionFail {
-                                    reason: "Session already started".into(),
+                                    code: errors::ErrorCode::SessionStarted.code(),
+                                    detail: None,
                                 })?;
-                                bail!("Session already started");
+                                bail!(errors::ErrorKind::Rejected(errors::ErrorCode::SessionStarted, None));
                             }
                         }
                         _ => {}
@@ -531,6 +784,14 @@ impl <S: Socket> NetworkedPlayer<S> {
                 (_, KeepAlive(..)) => {
                     connection.send(packet::KeepAlive{})?;
                 },
+                (_, Ping(pck)) => {
+                    connection.send(packet::Pong{id: pck.id})?;
+                },
+                // Only used for idle detection
+                (_, PlayerActivity(..)) => {},
+                (_, ViewArea(pck)) => if pck.x.is_finite() && pck.z.is_finite() {
+                    self.view_area = Some((pck.x, pck.z));
+                },
                 (_, Disconnect(..)) => {
                     self.local_state = PlayerState::Closed;
                     self.remote_state = PlayerState::Closed;
@@ -547,6 +808,9 @@ impl <S: Socket> NetworkedPlayer<S> {
                         notifications: AlwaysVec(mem::replace(&mut info.notifications, vec![])),
                     })?;
                 }
+                if let Some(progress) = info.goal_progress.take() {
+                    connection.ensure_send(progress)?;
+                }
             }
             // Only send commands if we have something to send
             if !self.remote_commands.commands.is_empty() {
@@ -652,6 +916,38 @@ impl <S: Socket> NetworkedPlayer<S> {
                     }
                 }
             });
+            req.handle::<super::RoomFinance, _>(|pck, rpl| {
+                if let SPlaying{
+                    ref level, ref entities,
+                    ..
+                } = *server_state {
+                    let owned = level.try_room_info(pck.room_id)
+                        .map_or(false, |v| Some(v.owner) == uid);
+                    let days = match (owned, entities.get_component::<player::RoomFinances>(Container::WORLD)) {
+                        (true, Some(finances)) => {
+                            let today = entities.get_component::<DayTick>(Container::WORLD).map_or(0, |v| v.day);
+                            finances.history(pck.room_id, today)
+                        },
+                        _ => Vec::new(),
+                    };
+                    rpl.reply(super::RoomFinanceReply {
+                        room_id: pck.room_id,
+                        days: AlwaysVec(days),
+                    });
+                }
+            });
+            req.handle::<super::EntityDirectory, _>(|pck, rpl| {
+                if let SPlaying{ref mut entities, ..} = *server_state {
+                    let entries = super::directory_entries(log, entities, assume!(log, uid), pck.filter.kind);
+                    let (entries, page, num_pages) = super::paginate_directory(entries, &pck.filter, pck.page);
+                    rpl.reply(super::EntityDirectoryReply {
+                        filter: pck.filter,
+                        page,
+                        num_pages,
+                        entries: AlwaysVec(entries),
+                    });
+                }
+            });
             req.handle::<super::EntityResults, _>(|pck, rpl| {
                 if let SPlaying{ref mut entities, ref snapshots, ..} = *server_state {
                     let info = assume!(log, info.get_mut(&assume!(log, uid)));
@@ -748,6 +1044,24 @@ impl <S: Socket> NetworkedPlayer<S> {
                     });
                 }
             });
+            req.handle::<super::EntityActivity, _>(|pck, rpl| {
+                if let SPlaying{ref entities, ref snapshots, ref level, ref choices, ..} = *server_state {
+                    let info = assume!(log, info.get(&assume!(log, uid)));
+                    let (activity, schedule) = if let Some(e) = snapshots.get_entity_by_id(pck.entity_id) {
+                        (
+                            super::describe_activity(entities, level, asset_manager, choices, e),
+                            super::schedule_for(log, entities, info, e),
+                        )
+                    } else {
+                        (String::new(), Vec::new())
+                    };
+                    rpl.reply(super::EntityActivityReply {
+                        entity_id: pck.entity_id,
+                        activity,
+                        schedule: AlwaysVec(schedule),
+                    });
+                }
+            });
             req.handle::<super::StaffPage, _>(|pck, rpl| {
                 let info = assume!(log, info.get_mut(&assume!(log, uid)));
                 if let Some(staff) = info.staff_for_hire.get(&pck.staff_key) {
@@ -834,7 +1148,11 @@ impl <S: Socket> NetworkedPlayer<S> {
                     ref level,
                     ..
                 } = *server_state {
-                    entities.with(|
+                    let course = pck.course;
+                    let day = pck.day;
+                    let period = pck.period;
+                    let player = assume!(log, uid);
+                    let options = entities.with(|
                         em: EntityManager,
                         lm: ecs::Read<course::LessonManager>,
                         owned: ecs::Read<Owned>,
@@ -843,44 +1161,149 @@ impl <S: Socket> NetworkedPlayer<S> {
                         network_id: ecs::Read<NetworkId>,
                         booked: ecs::Read<Booked>,
                     | {
-                        let course = pck.course;
-                        let day = pck.day;
-                        let period = pck.period;
-                        let player = assume!(log, uid);
                         let lm = assume!(log, lm.get_component(Container::WORLD));
-                        if let Some(lesson) = lm.get(pck.key) {
-                            let rooms = level.room_ids()
-                                .into_iter()
-                                .map(|v| level.get_room_info(v))
-                                .filter(|v| v.owner == player)
-                                .filter(|v| lesson.valid_rooms.contains(&v.key))
-                                .filter(|v| v.state.is_done())
-                                .filter(|v| !v.controller.is_invalid())
-                                .map(|v| (v.id, booked.get_component(v.controller)))
-                                .filter(|(_id, b)| b.map_or(true, |b| b.timetable[day as usize][period as usize]
+                        let lesson = lm.get(pck.key)?;
+                        let rooms = level.room_ids()
+                            .into_iter()
+                            .map(|v| level.get_room_info(v))
+                            .filter(|v| v.owner == player)
+                            .filter(|v| lesson.valid_rooms.contains(&v.key))
+                            .filter(|v| v.state.is_done())
+                            .filter(|v| !v.controller.is_invalid())
+                            .filter(|v| lesson.room_has_tags(log, asset_manager, v))
+                            .map(|v| (v.id, booked.get_component(v.controller)))
+                            .filter(|(_id, b)| b.map_or(true, |b| b.timetable[day as usize][period as usize]
+                                .map_or(true, |v| v == course)))
+                            .map(|(id, _b)| id)
+                            .collect::<Vec<_>>();
+
+                        let staff = em.group_mask((&living, &network_id), |m| m.and(&owned).and(&paid))
+                            .filter(|(_e, (l, _id))| lesson.valid_staff.contains(&l.key))
+                            // Allow unbooked staff and staff already booked for this course
+                            .filter(|(e, (_l, _id))| booked.get_component(*e)
+                                .map_or(true, |b| b.timetable[day as usize][period as usize]
                                     .map_or(true, |v| v == course)))
-                                .map(|(id, _b)| id)
-                                .collect::<Vec<_>>();
-
-                            let staff = em.group_mask((&living, &network_id), |m| m.and(&owned).and(&paid))
-                                .filter(|(_e, (l, _id))| lesson.valid_staff.contains(&l.key))
-                                // Allow unbooked staff and staff already booked for this course
-                                .filter(|(e, (_l, _id))| booked.get_component(*e)
-                                    .map_or(true, |b| b.timetable[day as usize][period as usize]
-                                        .map_or(true, |v| v == course)))
-                                .map(|(_e, (_, id))| *id)
-                                .collect::<Vec<_>>();
-
-                            rpl.reply(super::LessonValidOptionsReply {
-                                staff: AlwaysVec(staff),
-                                rooms: AlwaysVec(rooms),
-                            })
-                        } else {
-                            rpl.reply(super::LessonValidOptionsReply {
-                                staff: AlwaysVec(Vec::new()),
-                                rooms: AlwaysVec(Vec::new()),
+                            .map(|(e, (_, id))| (e, *id))
+                            .collect::<Vec<_>>();
+                        Some((lesson.staff_requirements.clone(), rooms, staff))
+                    });
+
+                    if let Some((requirements, rooms, staff)) = options {
+                        // Stats can't be accessed from within `with`
+                        let staff = staff.into_iter()
+                            .filter(|(e, _id)| requirements.is_empty() || get_vars(entities, *e)
+                                .map_or(false, |vars| requirements.iter().all(|v| v.is_met(&vars))))
+                            .map(|(_e, id)| id)
+                            .collect::<Vec<_>>();
+
+                        rpl.reply(super::LessonValidOptionsReply {
+                            staff: AlwaysVec(staff),
+                            rooms: AlwaysVec(rooms),
+                        })
+                    } else {
+                        rpl.reply(super::LessonValidOptionsReply {
+                            staff: AlwaysVec(Vec::new()),
+                            rooms: AlwaysVec(Vec::new()),
+                        })
+                    }
+                }
+            });
+            req.handle::<super::ExportStats, _>(|pck, rpl| {
+                if let ServerState::Playing{
+                    ref mut entities,
+                    ref level,
+                    ..
+                } = *server_state {
+                    let info = assume!(log, info.get(&assume!(log, uid)));
+                    let format = if pck.json {
+                        crate::export::ExportFormat::Json
+                    } else {
+                        crate::export::ExportFormat::Csv
+                    };
+                    let export = crate::export::collect(asset_manager, level, entities, info);
+                    match export.encode(format) {
+                        Ok(files) => rpl.reply(super::ExportStatsReply {
+                            files: AlwaysVec(files.into_iter()
+                                .map(|(name, data)| super::ExportedFile {
+                                    name,
+                                    data,
+                                })
+                                .collect()),
+                        }),
+                        Err(err) => warn!(log, "Failed to export stats"; "error" => % err),
+                    }
+                }
+            });
+            req.handle::<super::Trade, _>(|pck, rpl| {
+                if let ServerState::Playing{
+                    ref mut entities,
+                    ref level,
+                    ..
+                } = *server_state {
+                    use super::TradeAction;
+                    let uid = assume!(log, uid);
+                    let trades = assume!(log, entities.get_component_mut::<super::Trades>(Container::WORLD));
+                    let result = match pck.action {
+                        TradeAction::Offer{to, amount} => trades.offer(info, uid, to, amount).map(|_| ()),
+                        TradeAction::Accept{offer} => trades.accept(info, uid, offer),
+                        TradeAction::Decline{offer} => trades.decline(uid, offer),
+                        TradeAction::Contribute{room_id, amount} => {
+                            let owner = level.try_room_info(room_id)
+                                .filter(|v| !v.state.is_done())
+                                .map(|v| v.owner);
+                            if let Some(owner) = owner {
+                                trades.contribute(info, uid, room_id, owner, amount)
+                            } else {
+                                Err(ErrorKind::Rejected(errors::ErrorCode::FundingClosed, None).into())
+                            }
+                        },
+                    };
+                    if let Err(err) = result.as_ref() {
+                        warn!(log, "Rejected trade"; "action" => ?pck.action, "error" => %err);
+                    }
+                    // The new state of the trade is sent with the next
+                    // snapshot
+                    rpl.reply(super::TradeReply {
+                        error: result.err().map(|v| v.code().code()),
+                    });
+                }
+            });
+            req.handle::<super::PathDebug, _>(|pck, rpl| {
+                if let ServerState::Playing{
+                    ref entities,
+                    ref level,
+                    ref snapshots,
+                    ref pathfinder,
+                    ..
+                } = *server_state {
+                    use crate::entity::pathfind;
+                    let tiles = level.tiles.borrow();
+                    let rooms = level.rooms.borrow();
+                    let x = cmp::min(pck.x, tiles.width);
+                    let y = cmp::min(pck.y, tiles.height);
+                    let width = cmp::min(cmp::min(u32::from(pck.width), MAX_PATH_DEBUG_SIZE), tiles.width - x);
+                    let height = cmp::min(cmp::min(u32::from(pck.height), MAX_PATH_DEBUG_SIZE), tiles.height - y);
+                    let debug = pathfind::navigation_debug(&tiles, &rooms, pathfinder.congestion(), x as usize, y as usize, width as usize, height as usize);
+                    let path = pck.entity_id
+                        .and_then(|id| snapshots.get_entity_by_id(id))
+                        .and_then(|e| entities.get_component::<pathfind::PathInfo>(e))
+                        .map(|v| AlwaysVec(v.positions().collect()));
+                    rpl.reply(super::PathDebugReply {
+                        x,
+                        y,
+                        width: width as u8,
+                        height: height as u8,
+                        blocked: AlwaysVec(debug.blocked),
+                        costs: AlwaysVec(debug.costs),
+                        links: AlwaysVec(debug.links.into_iter()
+                            .map(|v| super::PathDebugLink {
+                                x: v.x as u16,
+                                y: v.y as u16,
+                                next_x: v.next_x,
+                                next_y: v.next_y,
                             })
-                        }
+                            .collect()),
+                        path,
                     });
                 }
             });
@@ -894,10 +1317,31 @@ impl <S: Socket> NetworkedPlayer<S> {
     }
 }
 
-pub(crate) struct Handler;
+pub(crate) struct Handler {
+    /// Whether the level editor's commands are allowed. Only
+    /// single player games may edit the level
+    level_editor: bool,
+}
 impl CommandHandler for Handler {
     type Player = PlayerInfo;
 
+    fn execute_paint_scatter<E>(&mut self, _cmd: &mut PaintScatter, _player: &mut PlayerInfo, _params: &mut CommandParams<'_, E>) -> UResult<()>
+        where E: Invokable,
+    {
+        if !self.level_editor {
+            bail!("The level editor is only available in single player");
+        }
+        Ok(())
+    }
+
+    fn execute_erase_scatter<E>(&mut self, _cmd: &mut EraseScatter, _player: &mut PlayerInfo, _params: &mut CommandParams<'_, E>) -> UResult<()>
+        where E: Invokable,
+    {
+        if !self.level_editor {
+            bail!("The level editor is only available in single player");
+        }
+        Ok(())
+    }
 
     fn execute_edit_room<E>(&mut self, cmd: &mut EditRoom, _player: &mut PlayerInfo, params: &mut CommandParams<'_, E>) -> UResult<()>
         where E: Invokable,
@@ -913,6 +1357,32 @@ impl CommandHandler for Handler {
         Ok(())
     }
 
+    fn execute_finalize_room<E>(&mut self, cmd: &mut FinalizeRoom, _player: &mut PlayerInfo, params: &mut CommandParams<'_, E>) -> UResult<()>
+        where E: Invokable,
+    {
+        use crate::entity::construction::Construction;
+        let room_id = assume!(params.log, cmd.room());
+        let room = params.level.get_room_info(room_id);
+        // Only rooms that are used for something need building,
+        // corridors and the like open straight away
+        let info = params.level.asset_manager.loader_open::<room::Loader>(room.key.borrow())?;
+        if info.controller.is_some() {
+            if let Some(construction) = params.entities.get_component_mut::<Construction>(Container::WORLD) {
+                construction.start(room_id, room.area);
+            }
+        }
+        Ok(())
+    }
+
+    fn undo_finalize_room<E>(&mut self, cmd: &mut FinalizeRoom, _player: &mut PlayerInfo, params: &mut CommandParams<'_, E>)
+        where E: Invokable,
+    {
+        use crate::entity::construction::Construction;
+        if let (Some(room_id), Some(construction)) = (cmd.room(), params.entities.get_component_mut::<Construction>(Container::WORLD)) {
+            construction.cancel(room_id);
+        }
+    }
+
     fn execute_fire_staff<E>(&mut self, cmd: &mut FireStaff, _player: &mut PlayerInfo, params: &mut CommandParams<'_, E>) -> UResult<()>
         where E: Invokable,
     {
@@ -944,10 +1414,9 @@ impl CommandHandler for Handler {
             let e = ty.create_entity(params.entities, member.variant, Some(member.name));
             {
                 let pos = assume!(params.log, params.entities.get_component_mut::<Position>(e));
-                pos.x = cmd.location.x;
                 pos.y = 0.2;
-                pos.z = cmd.location.y;
             }
+            teleport(params.entities, e, cmd.location.x, cmd.location.y);
             {
                 if let Some(vars) = get_vars(params.entities, e) {
                     for (stat, val) in entity_variant(&ty).stats().iter().zip(&member.stats) {
@@ -991,52 +1460,7 @@ impl CommandHandler for Handler {
             });
 
 
-            let room_id = params.entities.get_component::<RoomOwned>(e).map(|v| v.room_id);
-
-            if let Some(room_id) = room_id {
-                let assets = params.level.asset_manager.clone();
-                let ty = {
-                    let room = params.level.get_room_info_mut(room_id);
-                    if room.controller.is_invalid() {
-                        None
-                    } else {
-                        assets.loader_open::<room::Loader>(room.key.borrow()).ok()
-                    }
-                };
-                if let Some(controller) = ty.as_ref().and_then(|v| v.controller.as_ref()) {
-                    let lua_room = crate::script_room::LuaRoom::from_room(params.log, &params.level.rooms.borrow(), params.entities, room_id, &*params.engine);
-
-                    let lua: &lua::Lua = &*params.engine;
-                    let lua_entity = params.entities.with(|
-                            _em: EntityManager<'_>,
-                            mut entity_ref: ecs::Write<crate::script_room::LuaEntityRef>,
-                            living: ecs::Read<Living>,
-                            object: ecs::Read<Object>,
-                    | {
-                        crate::script_room::LuaEntityRef::get_or_create(&mut entity_ref, &living, &object, lua, e, Some(Controller::Room(room_id)))
-                    });
-
-                    lua.with_borrows()
-                        .borrow_mut(params.entities)
-                        .invoke_function::<_, lua::Ref<lua::Unknown>>("invoke_module_method", (
-                            lua::Ref::new_string(lua, controller.module()),
-                            lua::Ref::new_string(lua, controller.resource()),
-                            lua::Ref::new_string(lua, "force_entity_release"),
-                            lua_room,
-                            lua_entity,
-                    ))?;
-                }
-            }
-
-            // Release the entity from the room that currently controls them
-            if let Some(owner) = params.entities.remove_component::<RoomOwned>(e) {
-                params.entities.add_component(e, Controlled::new());
-                let room = assume!(params.log, params.level.try_room_info(owner.room_id));
-                let controller = assume!(params.log, params.entities.get_component_mut::<RoomController>(room.controller));
-                controller.entities.retain(|v| *v != e);
-                controller.visitors.retain(|v| *v != e);
-            }
-
+            release_from_room(params, e)?;
             Ok(())
         } else {
             bail!("incorrect state")
@@ -1052,28 +1476,23 @@ impl CommandHandler for Handler {
             {
                 let pos = assume!(params.log, params.entities.get_component_mut::<Position>(e));
                 cmd.rev = Some((e, pos.x, pos.z));
-                pos.x = cmd.location.x;
                 pos.y = 0.0;
-                pos.z = cmd.location.y;
-            }
-            if params.entities.get_component::<free_roam::FreeRoam>(e).is_none() {
-                let assets = params.level.asset_manager.clone();
-                if let Some(room_id) = params.level.get_room_owner(Location::new(cmd.location.x as i32, cmd.location.y as i32)) {
-                    let room = params.level.get_room_info_mut(room_id);
-                    let ty = assets.loader_open::<room::Loader>(room.key.borrow())?;
-                    if room.state.is_done() && ty.controller.is_some() {
-                        params.entities.add_component(e, RoomOwned::new(room_id));
-                        params.entities.add_component(e, Controlled::new_by(Controller::Room(room_id)));
-                        let rc = assume!(params.log, params.entities.get_component_mut::<RoomController>(room.controller));
-                        rc.entities.push(e);
-                    }
-                }
             }
+            teleport(params.entities, e, cmd.location.x, cmd.location.y);
+            assign_to_room(params, e, cmd.location.x, cmd.location.y)?;
             Ok(())
         } else {
             bail!("incorrect state")
         }
     }
+    fn execute_order_staff<E>(&mut self, cmd: &mut OrderStaff, _player: &mut PlayerInfo, params: &mut CommandParams<'_, E>) -> UResult<()>
+        where E: Invokable,
+    {
+        let (e, _, _) = assume!(params.log, cmd.rev);
+        release_from_room(params, e)?;
+        teleport(params.entities, e, cmd.location.x, cmd.location.y);
+        assign_to_room(params, e, cmd.location.x, cmd.location.y)
+    }
     fn execute_cancel_place_staff<E>(&mut self, cmd: &mut CancelPlaceStaff, _player: &mut PlayerInfo, _params: &mut CommandParams<'_, E>) -> UResult<()>
         where E: Invokable,
     {
@@ -1089,7 +1508,7 @@ impl CommandHandler for Handler {
         where E: Invokable,
     {
         if let Some(entity) = params.snapshots.get_entity_by_id(cmd.target) {
-            player.staff_issues.remove(&entity);
+            player.staff_issues.remove(entity);
             if let Some(vars) = params.entities.get_custom::<ProfessorVars>(entity) {
                 vars.set_stat(Stats::PROFESSOR_JOB_SATISFACTION, 1.0);
             }
@@ -1130,6 +1549,17 @@ impl CommandHandler for Handler {
         Ok(())
     }
 
+    fn execute_assign_course<E>(&mut self, cmd: &mut AssignCourse, player: &mut PlayerInfo, params: &mut CommandParams<'_, E>) -> UResult<()>
+        where E: Invokable,
+    {
+        let entity = if let Some(entity) = params.snapshots.get_entity_by_id(cmd.target) {
+            entity
+        } else {
+            bail!("Missing entity")
+        };
+        change_course(params.log, &*params.level.rooms.borrow(), player, params.entities, entity, cmd.course)
+    }
+
     fn execute_deprecate_course<E>(&mut self, cmd: &mut DeprecateCourse, player: &mut PlayerInfo, _params: &mut CommandParams<'_, E>) -> UResult<()>
         where E: Invokable,
     {
@@ -1140,6 +1570,80 @@ impl CommandHandler for Handler {
     }
 }
 
+/// Releases the staff member from the room that is currently
+/// controlling them, if any
+fn release_from_room<E>(params: &mut CommandParams<'_, E>, e: Entity) -> UResult<()>
+    where E: Invokable,
+{
+    let room_id = params.entities.get_component::<RoomOwned>(e).map(|v| v.room_id);
+
+    if let Some(room_id) = room_id {
+        let assets = params.level.asset_manager.clone();
+        let ty = {
+            let room = params.level.get_room_info_mut(room_id);
+            if room.controller.is_invalid() {
+                None
+            } else {
+                assets.loader_open::<room::Loader>(room.key.borrow()).ok()
+            }
+        };
+        if let Some(controller) = ty.as_ref().and_then(|v| v.controller.as_ref()) {
+            let lua_room = crate::script_room::LuaRoom::from_room(params.log, &params.level.rooms.borrow(), params.entities, room_id, &*params.engine);
+
+            let lua: &lua::Lua = &*params.engine;
+            let lua_entity = params.entities.with(|
+                    _em: EntityManager<'_>,
+                    mut entity_ref: ecs::Write<crate::script_room::LuaEntityRef>,
+                    living: ecs::Read<Living>,
+                    object: ecs::Read<Object>,
+            | {
+                crate::script_room::LuaEntityRef::get_or_create(&mut entity_ref, &living, &object, lua, e, Some(Controller::Room(room_id)))
+            });
+
+            lua.with_borrows()
+                .borrow_mut(params.entities)
+                .invoke_function::<_, lua::Ref<lua::Unknown>>("invoke_module_method", (
+                    lua::Ref::new_string(lua, controller.module()),
+                    lua::Ref::new_string(lua, controller.resource()),
+                    lua::Ref::new_string(lua, "force_entity_release"),
+                    lua_room,
+                    lua_entity,
+            ))?;
+        }
+    }
+
+    // Release the entity from the room that currently controls them
+    if let Some(owner) = params.entities.remove_component::<RoomOwned>(e) {
+        params.entities.add_component(e, Controlled::new());
+        let room = assume!(params.log, params.level.try_room_info(owner.room_id));
+        let controller = assume!(params.log, params.entities.get_component_mut::<RoomController>(room.controller));
+        controller.entities.retain(|v| *v != e);
+        controller.visitors.retain(|v| *v != e);
+    }
+    Ok(())
+}
+
+/// Gives the staff member to the room at the location if the
+/// room needs staff to run it
+fn assign_to_room<E>(params: &mut CommandParams<'_, E>, e: Entity, x: f32, z: f32) -> UResult<()>
+    where E: Invokable,
+{
+    if params.entities.get_component::<free_roam::FreeRoam>(e).is_none() {
+        let assets = params.level.asset_manager.clone();
+        if let Some(room_id) = params.level.get_room_owner(Location::new(x as i32, z as i32)) {
+            let room = params.level.get_room_info_mut(room_id);
+            let ty = assets.loader_open::<room::Loader>(room.key.borrow())?;
+            if room.state.is_done() && ty.controller.is_some() {
+                params.entities.add_component(e, RoomOwned::new(room_id));
+                params.entities.add_component(e, Controlled::new_by(Controller::Room(room_id)));
+                let rc = assume!(params.log, params.entities.get_component_mut::<RoomController>(room.controller));
+                rc.entities.push(e);
+            }
+        }
+    }
+    Ok(())
+}
+
 /// A player key is used to uniquely identify a player
 /// between games/saves&loads.
 ///
@@ -1166,7 +1670,16 @@ pub(crate) struct PlayerInfo {
     pub rating: i16,
 
     pub notifications: Vec<Notification>,
-    pub staff_issues: FNVMap<Entity, IssueState>,
+    /// Notifications posted by scripts that haven't been
+    /// acknowledged yet
+    pub script_notifications: crate::notify::ScriptNotifications,
+    /// Cutscenes waiting for every player to load
+    pub cutscenes: Vec<crate::cutscene::Cutscene>,
+    /// Visual effects played by the mission waiting to be sent
+    pub effects: Vec<crate::effects::VisualEffect>,
+    /// Progress towards the game's goals waiting to be sent
+    pub goal_progress: Option<packet::GoalProgress>,
+    pub staff_issues: EntityMap<IssueState>,
 
     pub courses: FNVMap<course::CourseId, course::Course>,
     pub next_course_id: u32,
@@ -1177,6 +1690,20 @@ pub(crate) struct PlayerInfo {
     pub current_income: UniDollar,
     pub current_outcome: UniDollar,
     pub grades: [u32; 6],
+    /// Students that graduated since the local player's
+    /// lifetime statistics were last updated
+    pub graduated: u32,
+    /// Money moved between this player and others
+    pub ledger: player::Ledger,
+    /// Trades involving this player, updated every tick
+    pub shared: player::SharedState,
+    /// The identity of the player's university, fixed once
+    /// the game begins
+    pub campus: player::CampusIdentity,
+    /// How the player is shown to others
+    pub profile: player::PlayerProfile,
+    /// Whether the player has stopped playing for a while
+    pub afk: bool,
 
     next_rating_update: i32,
     next_course_update: i32,
@@ -1223,6 +1750,9 @@ impl PlayerInfo {
     pub fn new(key: PlayerKey, name: String, uid: PlayerId, staff_types: &[StaffInfo]) -> PlayerInfo {
         PlayerInfo {
             uid,
+            campus: player::CampusIdentity::named(&name),
+            profile: player::PlayerProfile::default(),
+            afk: false,
             name,
             key,
             state: player::State::None,
@@ -1230,7 +1760,11 @@ impl PlayerInfo {
             rating: 0,
 
             notifications: vec![],
-            staff_issues: FNVMap::default(),
+            script_notifications: Default::default(),
+            cutscenes: vec![],
+            effects: vec![],
+            goal_progress: None,
+            staff_issues: EntityMap::new(),
 
             courses: FNVMap::default(),
             // The 0 id is reserved
@@ -1242,6 +1776,9 @@ impl PlayerInfo {
             current_income: UniDollar(0),
             current_outcome: UniDollar(0),
             grades: [0; 6],
+            graduated: 0,
+            ledger: player::Ledger::default(),
+            shared: player::SharedState::default(),
 
             next_rating_update: 20,
             next_course_update: 20,
@@ -1271,12 +1808,12 @@ impl PlayerInfo {
         use rand::{Rng, thread_rng};
         use rand::seq::SliceRandom;
 
-        self.staff_issues.retain(|e, _| entities.is_valid(*e));
-        for (e, state) in &mut self.staff_issues {
+        self.staff_issues.retain(|e, _| entities.is_valid(e));
+        for (e, state) in self.staff_issues.iter_mut() {
             match *state {
                 IssueState::WantsPay => {
-                    let nid = entities.get_component::<NetworkId>(*e).map(|v| v.0);
-                    if let Some(paid) = entities.get_component_mut::<Paid>(*e) {
+                    let nid = entities.get_component::<NetworkId>(e).map(|v| v.0);
+                    if let Some(paid) = entities.get_component_mut::<Paid>(e) {
                         // Always increase the wanted a amount incase payment wasn't the
                         // trigger for this
                         paid.wanted_cost += paid.cost / 100;
@@ -1292,10 +1829,10 @@ impl PlayerInfo {
                 },
                 IssueState::AskedForPay(_) => {},
                 IssueState::Quit => {
-                    if entities.get_component::<Quitting>(*e).is_none() {
-                        entities.add_component(*e, Quitting);
-                        entities.remove_component::<Owned>(*e);
-                        if let Some(id) = entities.get_component::<NetworkId>(*e).map(|v| v.0) {
+                    if entities.get_component::<Quitting>(e).is_none() {
+                        entities.add_component(e, Quitting);
+                        entities.remove_component::<Owned>(e);
+                        if let Some(id) = entities.get_component::<NetworkId>(e).map(|v| v.0) {
                             self.notifications.push(Notification::StaffQuit {
                                 entity_id: id,
                             });
@@ -1535,4 +2072,23 @@ impl player::Player for PlayerInfo {
     fn set_config(&mut self, cfg: PlayerConfig) {
         self.config = cfg;
     }
+
+    fn get_shared(&self) -> player::SharedState {
+        self.shared.clone()
+    }
+
+    fn set_shared(&mut self, shared: player::SharedState) {
+        self.shared = shared;
+    }
+}
+
+impl player::Account for PlayerInfo {
+    fn balance(&self) -> UniDollar {
+        self.money
+    }
+
+    fn transfer(&mut self, entry: player::LedgerEntry) {
+        self.change_money(entry.amount);
+        self.ledger.record(entry);
+    }
 }
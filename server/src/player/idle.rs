@@ -0,0 +1,223 @@
+//! Detects players that have stopped playing.
+//!
+//! A player that sends no commands or other activity (e.g. moving
+//! their camera) for a while is marked as away and shown as such to
+//! the other players. Away players can optionally have their student
+//! intake paused and, whilst in the lobby, be removed to free their
+//! slot. Players are warned shortly before either happens.
+
+use crate::network::packet::Packet;
+use crate::prelude::*;
+
+/// Controls how the server treats players that stop playing
+#[derive(Debug, Clone)]
+pub struct IdleConfig {
+    /// How long a player may go without any activity before
+    /// being marked as away. `None` disables idle detection
+    pub afk_after: Option<Duration>,
+    /// How long before any action is taken the player is warned
+    pub warning: Duration,
+    /// Whether students stop arriving at the universities of
+    /// away players
+    pub pause_intake: bool,
+    /// How long a player may go without any activity in the lobby
+    /// before being removed to free their slot. `None` never removes
+    /// players
+    pub kick_after: Option<Duration>,
+}
+
+impl Default for IdleConfig {
+    fn default() -> IdleConfig {
+        IdleConfig {
+            afk_after: Some(Duration::from_secs(5 * 60)),
+            warning: Duration::from_secs(30),
+            pause_intake: false,
+            kick_after: None,
+        }
+    }
+}
+
+impl IdleConfig {
+    /// Creates a config that never marks players as away
+    pub fn disabled() -> IdleConfig {
+        IdleConfig {
+            afk_after: None,
+            .. IdleConfig::default()
+        }
+    }
+}
+
+/// A change in a player's idle state that the server must act on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum IdleAction {
+    /// The player will be marked away or removed after the
+    /// duration unless they do something
+    Warn(Duration),
+    /// The player should be marked as away
+    Away,
+    /// The player should be removed from the lobby
+    Kick,
+    /// The player was away but has done something since
+    Returned,
+}
+
+/// Tracks the activity of a single connection
+#[derive(Debug)]
+pub(crate) struct IdleTracker {
+    last_activity: Instant,
+    afk: bool,
+    warned: bool,
+}
+
+impl IdleTracker {
+    pub(crate) fn new(now: Instant) -> IdleTracker {
+        IdleTracker {
+            last_activity: now,
+            afk: false,
+            warned: false,
+        }
+    }
+
+    /// Records that the player has done something
+    pub(crate) fn activity(&mut self, now: Instant) {
+        self.last_activity = now;
+        self.warned = false;
+    }
+
+    /// Returns whether the player is currently marked as away
+    pub(crate) fn is_afk(&self) -> bool {
+        self.afk
+    }
+
+    /// Returns the action to take for the player, if any.
+    ///
+    /// Each action is only returned once apart from `Kick` which
+    /// is returned until the player is removed.
+    pub(crate) fn check(&mut self, now: Instant, config: &IdleConfig, in_lobby: bool) -> Option<IdleAction> {
+        let afk_after = config.afk_after?;
+        let idle = now.duration_since(self.last_activity);
+        if self.afk && idle < afk_after {
+            self.afk = false;
+            self.warned = false;
+            return Some(IdleAction::Returned);
+        }
+        let next = if !self.afk {
+            afk_after
+        } else if in_lobby {
+            config.kick_after?
+        } else {
+            return None;
+        };
+        if idle >= next {
+            if self.afk {
+                return Some(IdleAction::Kick);
+            }
+            self.afk = true;
+            self.warned = false;
+            return Some(IdleAction::Away);
+        }
+        if !self.warned && idle + config.warning >= next {
+            self.warned = true;
+            return Some(IdleAction::Warn(next - idle));
+        }
+        None
+    }
+}
+
+/// Returns whether receiving the packet means the player
+/// is still playing.
+///
+/// Packets sent automatically by the client (keep alives,
+/// acks, voice) don't count.
+pub(crate) fn is_activity(pck: &Packet) -> bool {
+    matches!(pck,
+        Packet::ExecutedCommands(..)
+        | Packet::PlayerActivity(..)
+        | Packet::ChatMessage(..)
+        | Packet::NotificationReply(..)
+        | Packet::Request(..)
+        | Packet::EnterLobby(..)
+        | Packet::SetGoals(..)
+        | Packet::SetCampus(..)
+        | Packet::RequestGameBegin(..)
+        | Packet::SetPauseGame(..)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> IdleConfig {
+        IdleConfig {
+            afk_after: Some(Duration::from_secs(60)),
+            warning: Duration::from_secs(10),
+            pause_intake: true,
+            kick_after: Some(Duration::from_secs(120)),
+        }
+    }
+
+    #[test]
+    fn warn_then_away() {
+        let start = Instant::now();
+        let config = config();
+        let mut idle = IdleTracker::new(start);
+        assert_eq!(idle.check(start + Duration::from_secs(30), &config, false), None);
+        assert_eq!(
+            idle.check(start + Duration::from_secs(52), &config, false),
+            Some(IdleAction::Warn(Duration::from_secs(8)))
+        );
+        // Only warned once
+        assert_eq!(idle.check(start + Duration::from_secs(55), &config, false), None);
+        assert_eq!(idle.check(start + Duration::from_secs(60), &config, false), Some(IdleAction::Away));
+        assert!(idle.is_afk());
+        // Never kicked outside of the lobby
+        assert_eq!(idle.check(start + Duration::from_secs(600), &config, false), None);
+    }
+
+    #[test]
+    fn activity_resets() {
+        let start = Instant::now();
+        let config = config();
+        let mut idle = IdleTracker::new(start);
+        assert!(idle.check(start + Duration::from_secs(55), &config, false).is_some());
+        idle.activity(start + Duration::from_secs(56));
+        assert_eq!(idle.check(start + Duration::from_secs(70), &config, false), None);
+        // Warned again after doing something
+        assert_eq!(
+            idle.check(start + Duration::from_secs(110), &config, false),
+            Some(IdleAction::Warn(Duration::from_secs(6)))
+        );
+    }
+
+    #[test]
+    fn returned() {
+        let start = Instant::now();
+        let config = config();
+        let mut idle = IdleTracker::new(start);
+        assert_eq!(idle.check(start + Duration::from_secs(61), &config, false), Some(IdleAction::Away));
+        idle.activity(start + Duration::from_secs(90));
+        assert_eq!(idle.check(start + Duration::from_secs(91), &config, false), Some(IdleAction::Returned));
+        assert!(!idle.is_afk());
+    }
+
+    #[test]
+    fn lobby_kick() {
+        let start = Instant::now();
+        let config = config();
+        let mut idle = IdleTracker::new(start);
+        assert_eq!(idle.check(start + Duration::from_secs(61), &config, true), Some(IdleAction::Away));
+        assert_eq!(
+            idle.check(start + Duration::from_secs(115), &config, true),
+            Some(IdleAction::Warn(Duration::from_secs(5)))
+        );
+        assert_eq!(idle.check(start + Duration::from_secs(120), &config, true), Some(IdleAction::Kick));
+    }
+
+    #[test]
+    fn disabled() {
+        let start = Instant::now();
+        let mut idle = IdleTracker::new(start);
+        assert_eq!(idle.check(start + Duration::from_secs(60 * 60), &IdleConfig::disabled(), true), None);
+    }
+}
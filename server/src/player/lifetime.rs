@@ -0,0 +1,246 @@
+//! Statistics about the local player kept across every game.
+//!
+//! Unlike the rest of the player's information these aren't part
+//! of a save, they are stored once in `player_stats.json` at the
+//! root of the save filesystem (and so synced via steam cloud
+//! when available). Only the player running the server (single
+//! player or the host) has their statistics updated, dedicated
+//! servers have no local player to track.
+
+use serde_json;
+use crate::prelude::*;
+use crate::saving::filesystem::FileSystem;
+
+/// The name of the file the statistics are stored in
+pub const STATS_FILE: &str = "player_stats.json";
+
+/// Statistics collected over every game the player has played
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LifetimeStats {
+    /// The time in seconds spent playing (not paused)
+    #[serde(default)]
+    pub playtime: u64,
+    /// The number of new games started
+    #[serde(default)]
+    pub campuses_created: u32,
+    /// The number of students that finished their course with
+    /// a passing grade
+    #[serde(default)]
+    pub students_graduated: u32,
+}
+
+impl LifetimeStats {
+    /// Loads the statistics from the filesystem, returning the
+    /// default statistics if they haven't been saved before
+    pub fn load<F: FileSystem>(fs: &F) -> UResult<LifetimeStats> {
+        if !fs.exists(STATS_FILE) {
+            return Ok(LifetimeStats::default());
+        }
+        let file = fs.read(STATS_FILE)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Saves the statistics to the filesystem replacing the
+    /// previous copy
+    pub fn save<F: FileSystem>(&self, fs: &F) -> UResult<()> {
+        let file = fs.write(STATS_FILE)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Returns the value of the statistic
+    pub fn get(&self, stat: LifetimeStat) -> u64 {
+        match stat {
+            LifetimeStat::Playtime => self.playtime,
+            LifetimeStat::CampusesCreated => u64::from(self.campuses_created),
+            LifetimeStat::StudentsGraduated => u64::from(self.students_graduated),
+        }
+    }
+
+    /// Returns the progress towards each achievement
+    pub fn achievements(&self) -> impl Iterator<Item=(&'static Achievement, f32)> + '_ {
+        ACHIEVEMENTS.iter()
+            .map(move |a| (a, a.progress(self)))
+    }
+}
+
+/// A statistic that achievements can require
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifetimeStat {
+    /// Time spent playing in seconds
+    Playtime,
+    /// New games started
+    CampusesCreated,
+    /// Students graduated
+    StudentsGraduated,
+}
+
+/// A goal for one of the statistics
+#[derive(Debug)]
+pub struct Achievement {
+    /// The name shown to the player
+    pub name: &'static str,
+    /// A description of what is required
+    pub description: &'static str,
+    /// The statistic the achievement tracks
+    pub stat: LifetimeStat,
+    /// The value the statistic must reach
+    pub target: u64,
+}
+
+impl Achievement {
+    /// Returns the progress towards the achievement between
+    /// 0 and 1 where 1 is complete
+    pub fn progress(&self, stats: &LifetimeStats) -> f32 {
+        if self.target == 0 {
+            return 1.0;
+        }
+        (stats.get(self.stat) as f64 / self.target as f64).min(1.0) as f32
+    }
+}
+
+/// The achievements the player can work towards
+pub const ACHIEVEMENTS: &[Achievement] = &[
+    Achievement {
+        name: "Freshman",
+        description: "Found your first campus",
+        stat: LifetimeStat::CampusesCreated,
+        target: 1,
+    },
+    Achievement {
+        name: "Serial Founder",
+        description: "Found 10 campuses",
+        stat: LifetimeStat::CampusesCreated,
+        target: 10,
+    },
+    Achievement {
+        name: "Class of One",
+        description: "Graduate your first student",
+        stat: LifetimeStat::StudentsGraduated,
+        target: 1,
+    },
+    Achievement {
+        name: "Alma Mater",
+        description: "Graduate 1,000 students",
+        stat: LifetimeStat::StudentsGraduated,
+        target: 1_000,
+    },
+    Achievement {
+        name: "Tenure",
+        description: "Play for 10 hours",
+        stat: LifetimeStat::Playtime,
+        target: 10 * 60 * 60,
+    },
+];
+
+/// Updates the local player's statistics as the game runs and
+/// writes them out when the game saves
+pub(crate) struct LifetimeTracker {
+    stats: LifetimeStats,
+    /// Ticks played towards the next second of playtime
+    ticks: u32,
+    dirty: bool,
+}
+
+impl LifetimeTracker {
+    /// Loads the existing statistics, starting again if they
+    /// can't be read
+    pub(crate) fn load<F: FileSystem>(log: &Logger, fs: &F) -> LifetimeTracker {
+        let stats = LifetimeStats::load(fs)
+            .unwrap_or_else(|err| {
+                warn!(log, "Failed to load the player's statistics"; "error" => %err);
+                LifetimeStats::default()
+            });
+        LifetimeTracker {
+            stats,
+            ticks: 0,
+            dirty: false,
+        }
+    }
+
+    /// Returns the current statistics
+    pub(crate) fn stats(&self) -> &LifetimeStats {
+        &self.stats
+    }
+
+    /// Counts a single game tick towards the playtime
+    pub(crate) fn tick(&mut self, tick_rate: u32) {
+        self.ticks += 1;
+        if self.ticks >= tick_rate {
+            self.ticks = 0;
+            self.stats.playtime += 1;
+            self.dirty = true;
+        }
+    }
+
+    /// Records a new game being started
+    pub(crate) fn campus_created(&mut self) {
+        self.stats.campuses_created = self.stats.campuses_created.saturating_add(1);
+        self.dirty = true;
+    }
+
+    /// Records students graduating
+    pub(crate) fn graduated(&mut self, count: u32) {
+        if count > 0 {
+            self.stats.students_graduated = self.stats.students_graduated.saturating_add(count);
+            self.dirty = true;
+        }
+    }
+
+    /// Saves the statistics if they have changed since the
+    /// last save
+    pub(crate) fn save<F: FileSystem>(&mut self, log: &Logger, fs: &F) {
+        if !self.dirty {
+            return;
+        }
+        // Not fatal, only the statistics since the last save are lost
+        if let Err(err) = self.stats.save(fs) {
+            warn!(log, "Failed to save the player's statistics"; "error" => %err);
+        } else {
+            self.dirty = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playtime_counts_seconds() {
+        let mut tracker = LifetimeTracker {
+            stats: LifetimeStats::default(),
+            ticks: 0,
+            dirty: false,
+        };
+        for _ in 0 .. 45 {
+            tracker.tick(20);
+        }
+        assert_eq!(tracker.stats().playtime, 2);
+        assert!(tracker.dirty);
+    }
+
+    #[test]
+    fn achievement_progress() {
+        let stats = LifetimeStats {
+            students_graduated: 250,
+            .. LifetimeStats::default()
+        };
+        let progress: Vec<_> = stats.achievements()
+            .filter(|v| v.0.stat == LifetimeStat::StudentsGraduated)
+            .map(|v| v.1)
+            .collect();
+        assert_eq!(progress.len(), 2);
+        assert!(progress[0] >= 1.0);
+        assert!((progress[1] - 0.25).abs() < 0.001);
+    }
+
+    #[test]
+    fn missing_fields_default() {
+        let stats: LifetimeStats = serde_json::from_str(r#"{"playtime": 60}"#).unwrap();
+        assert_eq!(stats, LifetimeStats {
+            playtime: 60,
+            .. LifetimeStats::default()
+        });
+    }
+}
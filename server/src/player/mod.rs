@@ -8,6 +8,84 @@ pub use self::handle::{
     PlayerConfig,
     PlayerKey,
 };
+mod trade;
+pub use self::trade::{
+    Trades,
+    TradesState,
+    TransferOffer,
+    FundingShare,
+    LedgerReason,
+    LedgerEntry,
+    Ledger,
+    SharedState,
+};
+pub(crate) use self::trade::{
+    Account,
+    tick as tick_trades,
+};
+mod finance;
+pub use self::finance::{
+    FinanceKind,
+    RoomFinanceDay,
+    RoomFinances,
+    HISTORY_DAYS as FINANCE_HISTORY_DAYS,
+    record_room_money,
+};
+mod directory;
+pub use self::directory::{
+    DirectoryKind,
+    DirectoryFilter,
+    DirectoryEntry,
+    StatRange,
+    PAGE_SIZE as DIRECTORY_PAGE_SIZE,
+    paginate as paginate_directory,
+};
+pub(crate) use self::directory::directory_entries;
+mod spectate;
+pub use self::spectate::ScheduleEntry;
+pub(crate) use self::spectate::{
+    describe_activity,
+    schedule_for,
+};
+mod idle;
+pub use self::idle::IdleConfig;
+pub(crate) use self::idle::{
+    IdleAction,
+    IdleTracker,
+    is_activity,
+};
+mod campus;
+pub use self::campus::{
+    CampusColor,
+    CampusIdentity,
+    CampusParts,
+    CampusBranding,
+    Campuses,
+    BrandingSlot,
+    MAX_NAME_LENGTH as MAX_CAMPUS_NAME_LENGTH,
+    load_parts as load_campus_parts,
+    store_campuses,
+    init_campuslib,
+};
+mod profile;
+pub use self::profile::{
+    PlayerProfile,
+    ProfileParts,
+    Profiles,
+    PLAYER_COLORS,
+    MAX_TITLE_LENGTH,
+    free_color,
+    load_parts as load_profile_parts,
+};
+mod lifetime;
+pub use self::lifetime::{
+    LifetimeStats,
+    LifetimeStat,
+    Achievement,
+    ACHIEVEMENTS,
+    STATS_FILE,
+};
+pub(crate) use self::lifetime::LifetimeTracker;
 
 use crate::ecs;
 use crate::level::room;
@@ -51,6 +129,11 @@ pub trait Player {
     fn get_config(&self) -> PlayerConfig;
     /// Modifys the player's config
     fn set_config(&mut self, cfg: PlayerConfig);
+
+    /// Returns the trades and co-funded rooms involving the player
+    fn get_shared(&self) -> SharedState;
+    /// Replaces the trades and co-funded rooms involving the player
+    fn set_shared(&mut self, shared: SharedState);
 }
 
 /// Contains the state and related information for a player
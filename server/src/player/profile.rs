@@ -0,0 +1,180 @@
+//! The colour, icon and title each player is shown with.
+//!
+//! Unlike a campus the profile is about the player rather than
+//! their university. The colour is used wherever players need
+//! telling apart (the player list, chat and the rooms they own)
+//! so no two players in a game may share one. Packs provide the
+//! icons and titles that can be picked in `player/profile.json`.
+
+use serde_json;
+use crate::prelude::*;
+use super::Id;
+
+/// The colours a player can pick from, indexed by
+/// `PlayerProfile::color`.
+///
+/// The client swaps these for its colour blind palettes
+/// by index so the order matters.
+pub const PLAYER_COLORS: &[(u8, u8, u8)] = &[
+    (46, 65, 114),
+    (170, 57, 57),
+    (45, 136, 45),
+    (170, 108, 57),
+    (113, 47, 121),
+    (34, 102, 102),
+    (170, 170, 57),
+    (128, 128, 128),
+];
+/// The longest title a player may have in characters
+pub const MAX_TITLE_LENGTH: usize = 24;
+
+/// How a player is shown to others
+#[derive(Debug, Clone, Default, PartialEq, DeltaEncode, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    /// The index of the player's colour in `PLAYER_COLORS`
+    pub color: u8,
+    /// The image shown next to the player's name
+    pub icon: Option<ResourceKey<'static>>,
+    /// The title shown next to the player's name
+    pub title: Option<String>,
+}
+
+impl PlayerProfile {
+    /// Returns the player's colour
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        PLAYER_COLORS[usize::from(self.color) % PLAYER_COLORS.len()]
+    }
+
+    /// Replaces anything in the profile that a player shouldn't
+    /// be able to pick.
+    ///
+    /// The colour must not be in `taken` (the colours of the other
+    /// players), otherwise the first free colour is used instead.
+    /// The icon and title must be one of those provided by the packs.
+    pub fn sanitize(&mut self, parts: &ProfileParts, taken: &[u8]) {
+        if usize::from(self.color) >= PLAYER_COLORS.len() || taken.contains(&self.color) {
+            self.color = free_color(taken);
+        }
+        if self.icon.as_ref().map_or(false, |v| !parts.icons.contains(v)) {
+            self.icon = None;
+        }
+        if self.title.as_ref().map_or(false, |v| !parts.titles.contains(v)) {
+            self.title = None;
+        }
+    }
+}
+
+/// Returns the first colour not in `taken`.
+///
+/// Once every colour is taken they are reused in order
+pub fn free_color(taken: &[u8]) -> u8 {
+    (0..PLAYER_COLORS.len() as u8)
+        .find(|v| !taken.contains(v))
+        .unwrap_or_else(|| (taken.len() % PLAYER_COLORS.len()) as u8)
+}
+
+/// The icons and titles a player can pick from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProfileParts {
+    /// The images that can be shown next to a player's name
+    pub icons: Vec<ResourceKey<'static>>,
+    /// The titles that can be shown next to a player's name
+    pub titles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfilePartsJson {
+    #[serde(default)]
+    icons: Vec<String>,
+    #[serde(default)]
+    titles: Vec<String>,
+}
+
+/// Loads the profile parts provided by every pack
+pub fn load_parts(log: &Logger, assets: &AssetManager) -> ProfileParts {
+    let mut parts = ProfileParts::default();
+    for module in assets.get_packs() {
+        let file = match assets.open_from_pack(module.borrow(), "player/profile.json") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let raw: ProfilePartsJson = match serde_json::from_reader(file) {
+            Ok(val) => val,
+            Err(err) => {
+                error!(log, "Failed to parse profile.json for pack {:?}: {}", module, err);
+                continue
+            }
+        };
+        parts.icons.extend(raw.icons.into_iter()
+            .map(|v| LazyResourceKey::parse(&v)
+                .or_module(module.borrow())
+                .into_owned()));
+        parts.titles.extend(raw.titles.into_iter()
+            .map(|v| v.trim().chars().take(MAX_TITLE_LENGTH).collect::<String>())
+            .filter(|v| !v.is_empty()));
+    }
+    parts
+}
+
+/// The profiles of every player in the game.
+///
+/// Stored in the world entity
+#[derive(Default)]
+pub struct Profiles {
+    /// The profile of each player
+    pub profiles: FNVMap<Id, PlayerProfile>,
+}
+component!(Profiles => Map);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts() -> ProfileParts {
+        ProfileParts {
+            icons: vec![ResourceKey::new("base", "player/owl")],
+            titles: vec!["Dean".to_owned(), "Professor".to_owned()],
+        }
+    }
+
+    #[test]
+    fn free_colors() {
+        assert_eq!(free_color(&[]), 0);
+        assert_eq!(free_color(&[0, 1, 3]), 2);
+        let all: Vec<u8> = (0..PLAYER_COLORS.len() as u8).collect();
+        assert_eq!(free_color(&all), 0);
+    }
+
+    #[test]
+    fn sanitize_color() {
+        let mut profile = PlayerProfile { color: 3, ..Default::default() };
+        profile.sanitize(&parts(), &[0, 1]);
+        assert_eq!(profile.color, 3);
+
+        // Another player already has the colour
+        profile.sanitize(&parts(), &[0, 3]);
+        assert_eq!(profile.color, 1);
+
+        profile.color = 200;
+        profile.sanitize(&parts(), &[]);
+        assert_eq!(profile.color, 0);
+    }
+
+    #[test]
+    fn sanitize_parts() {
+        let mut profile = PlayerProfile {
+            color: 0,
+            icon: Some(ResourceKey::new("base", "player/owl")),
+            title: Some("Dean".to_owned()),
+        };
+        profile.sanitize(&parts(), &[]);
+        assert_eq!(profile.icon, Some(ResourceKey::new("base", "player/owl")));
+        assert_eq!(profile.title.as_ref().map(|v| v.as_str()), Some("Dean"));
+
+        profile.icon = Some(ResourceKey::new("base", "player/missing"));
+        profile.title = Some("Supreme Leader".to_owned());
+        profile.sanitize(&parts(), &[]);
+        assert_eq!(profile.icon, None);
+        assert_eq!(profile.title, None);
+    }
+}
@@ -105,6 +105,36 @@ impl Requestable for EntityResults {
     type Reply = EntityResultsReply;
 }
 
+/// Requests what an entity being followed by the camera is
+/// currently doing
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct EntityActivity {
+    /// The network id of the entity
+    #[delta_bits = "20"]
+    pub entity_id: u32,
+}
+
+/// What the followed entity is currently doing
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct EntityActivityReply {
+    /// The network id of the entity
+    #[delta_bits = "20"]
+    pub entity_id: u32,
+    /// A short description of the entity's current activity
+    pub activity: String,
+    /// The lessons left in the entity's day.
+    ///
+    /// Empty for staff and entities owned by other players
+    pub schedule: AlwaysVec<player::ScheduleEntry>,
+}
+
+impl Requestable for EntityActivity {
+    const ID: [u8; 4] = *b"enac";
+    type Reply = EntityActivityReply;
+}
+
 /// Requests information for a staff member that can be hired
 #[derive(DeltaEncode)]
 #[delta_always]
@@ -199,6 +229,64 @@ impl Requestable for CourseList {
     type Reply = CourseListReply;
 }
 
+/// Requests the money made and spent by a room owned by
+/// the player
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct RoomFinance {
+    /// The id of the room being requested
+    pub room_id: room::Id,
+}
+
+/// The money made and spent by the room over the last
+/// `FINANCE_HISTORY_DAYS` days
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct RoomFinanceReply {
+    /// The id of the room being requested
+    pub room_id: room::Id,
+    /// The room's finances for each day, oldest first.
+    ///
+    /// Empty if the room isn't owned by the player
+    pub days: AlwaysVec<player::RoomFinanceDay>,
+}
+
+impl Requestable for RoomFinance {
+    const ID: [u8; 4] = *b"rofi";
+    type Reply = RoomFinanceReply;
+}
+
+/// Requests a page of the player's staff or students
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct EntityDirectory {
+    /// The entities to list
+    pub filter: player::DirectoryFilter,
+    /// The page to return
+    pub page: u16,
+}
+
+/// A page of the player's staff or students
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct EntityDirectoryReply {
+    /// The filter the page was built with
+    pub filter: player::DirectoryFilter,
+    /// The page returned.
+    ///
+    /// May differ from the one requested if it was out of bounds
+    pub page: u16,
+    /// The number of pages matching the filter
+    pub num_pages: u16,
+    /// The entities on the page sorted by name
+    pub entries: AlwaysVec<player::DirectoryEntry>,
+}
+
+impl Requestable for EntityDirectory {
+    const ID: [u8; 4] = *b"endi";
+    type Reply = EntityDirectoryReply;
+}
+
 /// Requests the full information about a course
 #[derive(DeltaEncode)]
 #[delta_always]
@@ -248,4 +336,150 @@ pub struct LessonValidOptionsReply {
 impl Requestable for LessonValidOptions {
     const ID: [u8; 4] = *b"levo";
     type Reply = LessonValidOptionsReply;
-}
\ No newline at end of file
+}
+/// Requests an export of the player's stats
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct ExportStats {
+    /// Whether to export as json instead of csv
+    pub json: bool,
+}
+
+/// A single file of an export
+#[derive(DeltaEncode, Debug, Clone)]
+pub struct ExportedFile {
+    /// The name of the file
+    pub name: String,
+    /// The contents of the file
+    pub data: String,
+}
+
+/// The exported stats for the player
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct ExportStatsReply {
+    /// The exported files
+    pub files: AlwaysVec<ExportedFile>,
+}
+
+impl Requestable for ExportStats {
+    const ID: [u8; 4] = *b"exst";
+    type Reply = ExportStatsReply;
+}
+
+/// Requests the pathfinding data of an area of the level for
+/// the navigation debug overlay
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct PathDebug {
+    /// The x position of the first tile of the area
+    pub x: u32,
+    /// The y position of the first tile of the area
+    pub y: u32,
+    /// The width of the area in tiles
+    pub width: u8,
+    /// The height of the area in tiles
+    pub height: u8,
+    /// The network id of the entity to return the path of
+    pub entity_id: Option<u32>,
+}
+
+/// The pathfinding data of the requested area
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct PathDebugReply {
+    /// The x position of the first tile of the area
+    pub x: u32,
+    /// The y position of the first tile of the area
+    pub y: u32,
+    /// The width of the area in tiles after being limited
+    /// to the level
+    pub width: u8,
+    /// The height of the area in tiles after being limited
+    /// to the level
+    pub height: u8,
+    /// Whether each collision cell (4 per a tile) is blocked,
+    /// `x + y * width * 4`
+    pub blocked: AlwaysVec<bool>,
+    /// The movement cost of each tile, `x + y * width`
+    pub costs: AlwaysVec<i32>,
+    /// The connections between sections of the rough path graph
+    pub links: AlwaysVec<PathDebugLink>,
+    /// The remaining path of the requested entity if it has one
+    pub path: Option<AlwaysVec<(f32, f32)>>,
+}
+
+/// The connections of a section (4x4 tiles) to the sections
+/// after it
+#[derive(DeltaEncode, Debug, Clone, Copy)]
+#[delta_always]
+pub struct PathDebugLink {
+    /// The x position of the section in sections
+    pub x: u16,
+    /// The y position of the section in sections
+    pub y: u16,
+    /// Whether the section connects to the next section along
+    /// the x axis
+    pub next_x: bool,
+    /// Whether the section connects to the next section along
+    /// the y axis
+    pub next_y: bool,
+}
+
+impl Requestable for PathDebug {
+    const ID: [u8; 4] = *b"padb";
+    type Reply = PathDebugReply;
+}
+
+/// An action taken on a transfer or co-funded room
+#[derive(DeltaEncode, Debug, Clone)]
+#[delta_always]
+pub enum TradeAction {
+    /// Offers to send money to another player
+    Offer {
+        /// The player to send the money to
+        to: PlayerId,
+        /// The amount to send
+        amount: UniDollar,
+    },
+    /// Accepts an offer sent to the player
+    Accept {
+        /// The id of the offer
+        offer: u32,
+    },
+    /// Declines an offer sent to the player or withdraws one
+    /// sent by them
+    Decline {
+        /// The id of the offer
+        offer: u32,
+    },
+    /// Puts money towards another player's room whilst it is
+    /// being built
+    Contribute {
+        /// The id of the room
+        room_id: room::Id,
+        /// The amount to contribute
+        amount: UniDollar,
+    },
+}
+
+/// Requests an action on a transfer or co-funded room
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct Trade {
+    /// The action to take
+    pub action: TradeAction,
+}
+
+/// The result of a trade action
+#[derive(DeltaEncode)]
+#[delta_always]
+pub struct TradeReply {
+    /// The error code if the action was rejected
+    pub error: Option<u16>,
+}
+
+impl Requestable for Trade {
+    const ID: [u8; 4] = *b"trad";
+    type Reply = TradeReply;
+}
@@ -0,0 +1,112 @@
+//! Details about an entity being followed by a player's camera.
+//!
+//! Most of what the follow panel shows comes from the snapshots
+//! already sent to the client. This fills the rest in from state
+//! only the server has, e.g. the script in control of the entity
+//! and the lessons left in their day.
+
+use crate::ecs;
+use crate::entity::course;
+use crate::level::room;
+use crate::prelude::*;
+
+/// A lesson later in the day of a student being followed
+#[derive(DeltaEncode, Clone, Debug, PartialEq)]
+#[delta_always]
+pub struct ScheduleEntry {
+    /// The period of the day the lesson takes place in
+    pub period: u8,
+    /// The display name of the lesson
+    pub lesson: String,
+}
+
+/// Returns a short description of what the entity is doing
+pub(crate) fn describe_activity(
+        entities: &ecs::Container, level: &Level,
+        assets: &AssetManager, choices: &choice::Choices,
+        e: Entity,
+) -> String {
+    let room_name = |id: room::Id| level.try_room_info(id)
+        .and_then(|v| assets.loader_open::<room::Loader>(v.key.borrow()).ok())
+        .map_or_else(|| "room".to_owned(), |v| v.name.clone());
+
+    match entities.get_component::<Controlled>(e).and_then(|v| v.by) {
+        Some(Controller::Room(id)) => format!("In the {}", room_name(id)),
+        Some(Controller::Idle(idx)) => match choices.student_idle.get_choice_name_by_index(idx) {
+            Some(name) => format!("Taking a break ({})", name.resource()),
+            None => "Taking a break".to_owned(),
+        },
+        Some(Controller::FreeRoam) => "Wandering around campus".to_owned(),
+        Some(Controller::Evacuate) => "Evacuating from a fire".to_owned(),
+        Some(Controller::Quit) => "Leaving the university".to_owned(),
+        None => if let Some(goto) = entities.get_component::<GotoRoom>(e) {
+            format!("Heading to the {}", room_name(goto.room_id))
+        } else {
+            "Waiting around".to_owned()
+        },
+    }
+}
+
+/// Returns the lessons from the period onwards in a day of a
+/// course's timetable
+pub(crate) fn upcoming_lessons<'a, F>(slots: &[course::CourseEntry], period: usize, lesson_name: F) -> Vec<ScheduleEntry>
+    where F: Fn(ResourceKey<'static>) -> Option<&'a str>
+{
+    slots.iter()
+        .enumerate()
+        .skip(period)
+        .filter_map(|(idx, slot)| match slot {
+            course::CourseEntry::Lesson{key, ..} => Some(ScheduleEntry {
+                period: idx as u8,
+                lesson: lesson_name(key.clone())
+                    .map_or_else(|| key.resource().to_owned(), |v| v.to_owned()),
+            }),
+            course::CourseEntry::Free => None,
+        })
+        .collect()
+}
+
+/// Returns the lessons left today for the student owned by the
+/// player, empty for staff or students owned by someone else
+pub(crate) fn schedule_for(log: &Logger, entities: &ecs::Container, info: &super::PlayerInfo, e: Entity) -> Vec<ScheduleEntry> {
+    if entities.get_component::<Owned>(e).map_or(true, |v| v.player_id != info.uid) {
+        return Vec::new();
+    }
+    let course = match entities.get_component::<TimeTable>(e).and_then(|v| info.courses.get(&v.course)) {
+        Some(course) => course,
+        None => return Vec::new(),
+    };
+    let day_tick = assume!(log, entities.get_component::<DayTick>(Container::WORLD));
+    let lessons = assume!(log, entities.get_component::<course::LessonManager>(Container::WORLD));
+    let day = (day_tick.day % 7) as usize;
+    let period = (day_tick.current_tick / LESSON_LENGTH) as usize;
+    upcoming_lessons(&course.timetable[day], period, |key| lessons.get(key).map(|v| v.name.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lesson(name: &'static str) -> course::CourseEntry {
+        course::CourseEntry::Lesson {
+            key: ResourceKey::new("base", name),
+            rooms: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn upcoming() {
+        let slots = [lesson("maths"), course::CourseEntry::Free, lesson("art"), lesson("history")];
+        let name = |key: ResourceKey<'static>| if key.resource() == "art" { Some("Fine Art") } else { None };
+
+        let all = upcoming_lessons(&slots, 0, name);
+        assert_eq!(all.iter().map(|v| v.period).collect::<Vec<_>>(), vec![0, 2, 3]);
+        assert_eq!(all[0].lesson, "maths");
+        assert_eq!(all[1].lesson, "Fine Art");
+
+        // Earlier lessons are skipped
+        let later = upcoming_lessons(&slots, 3, name);
+        assert_eq!(later, vec![ScheduleEntry { period: 3, lesson: "history".to_owned() }]);
+        assert!(upcoming_lessons(&slots, 4, name).is_empty());
+    }
+}
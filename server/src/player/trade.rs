@@ -0,0 +1,558 @@
+//! Trading money between players and co-funding rooms.
+//!
+//! A transfer is offered by one player and only moves money once
+//! the receiving player accepts it. Money contributed towards
+//! another player's room is held in escrow until the room is built,
+//! at which point it is paid to the room's owner. Cancelling the
+//! room refunds every contributor. Once built the room is shared:
+//! the owner keeps half of the room's income and the contributors
+//! split the other half in proportion to what they paid.
+//!
+//! Everything here is decided by the server, clients only see the
+//! result through the `SharedState` in their player snapshot.
+
+use std::collections::VecDeque;
+use crate::ecs;
+use crate::level::room;
+use crate::errors::ErrorCode;
+use crate::prelude::*;
+use super::Id;
+
+/// The most transfers a player may have offered at once
+pub const MAX_OFFERS: usize = 8;
+/// The number of ledger entries kept for a player
+pub const LEDGER_SIZE: usize = 64;
+/// The number of recent ledger entries sent to the player
+pub const SHARED_LEDGER_SIZE: usize = 8;
+/// The percentage of a shared room's income kept by its owner
+pub const OWNER_INCOME_SHARE: i64 = 50;
+
+/// A transfer of money waiting for the receiver to accept it
+#[derive(Debug, Clone, PartialEq, DeltaEncode)]
+pub struct TransferOffer {
+    /// The id of the offer
+    pub id: u32,
+    /// The player sending the money
+    pub from: Id,
+    /// The player receiving the money
+    pub to: Id,
+    /// The amount being sent
+    pub amount: UniDollar,
+}
+
+/// A player's part in funding a room
+#[derive(Debug, Clone, PartialEq, DeltaEncode)]
+pub struct FundingShare {
+    /// The room being funded
+    pub room_id: room::Id,
+    /// The owner of the room
+    pub owner: Id,
+    /// The amount contributed by the player, or by everyone if
+    /// the player owns the room
+    pub contributed: UniDollar,
+    /// The amount contributed by every player
+    pub total: UniDollar,
+    /// Whether the room has been built and its income is shared
+    pub shared: bool,
+}
+
+/// Why money moved in or out of a player's account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DeltaEncode)]
+pub enum LedgerReason {
+    /// Money sent to or received from another player
+    Transfer,
+    /// Money put into escrow for another player's room
+    Contribution,
+    /// Escrowed money returned after the room was cancelled
+    Refund,
+    /// Escrowed money paid to the owner of a built room
+    Funding,
+    /// A share of a shared room's income
+    SharedIncome,
+    /// Money rolled from a reward table by a mission
+    Reward,
+}
+
+/// A single movement of money between players or from a
+/// mission
+#[derive(Debug, Clone, PartialEq, DeltaEncode)]
+pub struct LedgerEntry {
+    /// Why the money moved
+    pub reason: LedgerReason,
+    /// The change to the player's money
+    pub amount: UniDollar,
+    /// The other player involved if any
+    pub other: Option<Id>,
+    /// The room involved if any
+    pub room_id: Option<room::Id>,
+}
+
+/// The most recent movements of money between a player and
+/// other players or missions
+#[derive(Debug, Default)]
+pub struct Ledger {
+    entries: VecDeque<LedgerEntry>,
+}
+
+impl Ledger {
+    /// Adds the entry, dropping the oldest entry if full
+    pub fn record(&mut self, entry: LedgerEntry) {
+        if self.entries.len() >= LEDGER_SIZE {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Returns the entries, newest first
+    pub fn entries(&self) -> impl Iterator<Item=&LedgerEntry> {
+        self.entries.iter().rev()
+    }
+}
+
+/// The trades and room funding involving a single player.
+///
+/// Sent to the player as part of their snapshot
+#[derive(Debug, Clone, PartialEq, Default, DeltaEncode)]
+pub struct SharedState {
+    /// Offers sent by or to the player
+    pub offers: Vec<TransferOffer>,
+    /// Rooms the player owns or contributed to that are
+    /// co-funded
+    pub funding: Vec<FundingShare>,
+    /// The most recent ledger entries, newest first
+    pub ledger: Vec<LedgerEntry>,
+}
+
+/// A player that money can be moved in and out of
+pub(crate) trait Account {
+    /// Returns the money the player has
+    fn balance(&self) -> UniDollar;
+    /// Changes the player's money, recording the change in
+    /// their ledger
+    fn transfer(&mut self, entry: LedgerEntry);
+}
+
+/// The escrow and shares of a co-funded room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Funding {
+    owner: Id,
+    contributions: Vec<(Id, UniDollar)>,
+    /// Set once the room is built and the escrow has been paid
+    /// to the owner
+    complete: bool,
+}
+
+impl Funding {
+    fn total(&self) -> UniDollar {
+        UniDollar(self.contributions.iter().map(|v| (v.1).0).sum())
+    }
+}
+
+/// The saved state of every co-funded room
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TradesState {
+    funding: Vec<(room::Id, Funding)>,
+}
+
+/// Pending transfers and co-funded rooms.
+///
+/// Stored on the world entity on the server
+#[derive(Default)]
+pub struct Trades {
+    next_offer: u32,
+    offers: Vec<TransferOffer>,
+    funding: FNVMap<room::Id, Funding>,
+}
+component!(Trades => Map);
+
+fn rejected(code: ErrorCode) -> ErrorKind {
+    ErrorKind::Rejected(code, None)
+}
+
+impl Trades {
+    /// Restores the co-funded rooms from a save
+    pub fn load(state: TradesState) -> Trades {
+        Trades {
+            next_offer: 0,
+            offers: Vec::new(),
+            funding: state.funding.into_iter().collect(),
+        }
+    }
+
+    /// Returns the co-funded rooms for saving.
+    ///
+    /// Offers aren't saved as no money has moved yet
+    pub fn save(&self) -> TradesState {
+        let mut funding: Vec<_> = self.funding.iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        funding.sort_by_key(|v| v.0);
+        TradesState {
+            funding,
+        }
+    }
+
+    /// Offers to send money from one player to another
+    pub(crate) fn offer<A: Account>(&mut self, accounts: &FNVMap<Id, A>, from: Id, to: Id, amount: UniDollar) -> UResult<u32> {
+        if from == to || !accounts.contains_key(&to) {
+            bail!(rejected(ErrorCode::InvalidTradePlayer));
+        }
+        if amount <= UniDollar(0) {
+            bail!(rejected(ErrorCode::InvalidTradeAmount));
+        }
+        if self.offers.iter().filter(|v| v.from == from).count() >= MAX_OFFERS {
+            bail!(rejected(ErrorCode::TooManyOffers));
+        }
+        let from_account = accounts.get(&from).ok_or_else(|| rejected(ErrorCode::InvalidTradePlayer))?;
+        if from_account.balance() < amount {
+            bail!(ErrorKind::NotEnoughMoney);
+        }
+        let id = self.next_offer;
+        self.next_offer = self.next_offer.wrapping_add(1);
+        self.offers.push(TransferOffer {
+            id,
+            from,
+            to,
+            amount,
+        });
+        Ok(id)
+    }
+
+    /// Accepts an offer sent to the player, moving the money.
+    ///
+    /// The offer is removed even if the sender no longer has
+    /// enough money
+    pub(crate) fn accept<A: Account>(&mut self, accounts: &mut FNVMap<Id, A>, by: Id, id: u32) -> UResult<()> {
+        let pos = self.offers.iter()
+            .position(|v| v.id == id && v.to == by)
+            .ok_or_else(|| rejected(ErrorCode::MissingOffer))?;
+        let offer = self.offers.remove(pos);
+        {
+            let from = accounts.get_mut(&offer.from).ok_or_else(|| rejected(ErrorCode::InvalidTradePlayer))?;
+            if from.balance() < offer.amount {
+                bail!(ErrorKind::NotEnoughMoney);
+            }
+            from.transfer(LedgerEntry {
+                reason: LedgerReason::Transfer,
+                amount: UniDollar(-offer.amount.0),
+                other: Some(offer.to),
+                room_id: None,
+            });
+        }
+        let to = accounts.get_mut(&offer.to).ok_or_else(|| rejected(ErrorCode::InvalidTradePlayer))?;
+        to.transfer(LedgerEntry {
+            reason: LedgerReason::Transfer,
+            amount: offer.amount,
+            other: Some(offer.from),
+            room_id: None,
+        });
+        Ok(())
+    }
+
+    /// Removes an offer either declined by the receiver or
+    /// withdrawn by the sender
+    pub(crate) fn decline(&mut self, by: Id, id: u32) -> UResult<()> {
+        let pos = self.offers.iter()
+            .position(|v| v.id == id && (v.to == by || v.from == by))
+            .ok_or_else(|| rejected(ErrorCode::MissingOffer))?;
+        self.offers.remove(pos);
+        Ok(())
+    }
+
+    /// Moves money from the player into the escrow for another
+    /// player's room.
+    ///
+    /// The caller must check the room exists and is still being
+    /// built
+    pub(crate) fn contribute<A: Account>(&mut self, accounts: &mut FNVMap<Id, A>, by: Id, room_id: room::Id, owner: Id, amount: UniDollar) -> UResult<()> {
+        if by == owner {
+            bail!(rejected(ErrorCode::InvalidTradePlayer));
+        }
+        if amount <= UniDollar(0) {
+            bail!(rejected(ErrorCode::InvalidTradeAmount));
+        }
+        if self.funding.get(&room_id).map_or(false, |v| v.complete || v.owner != owner) {
+            bail!(rejected(ErrorCode::FundingClosed));
+        }
+        let account = accounts.get_mut(&by).ok_or_else(|| rejected(ErrorCode::InvalidTradePlayer))?;
+        if account.balance() < amount {
+            bail!(ErrorKind::NotEnoughMoney);
+        }
+        account.transfer(LedgerEntry {
+            reason: LedgerReason::Contribution,
+            amount: UniDollar(-amount.0),
+            other: Some(owner),
+            room_id: Some(room_id),
+        });
+
+        let funding = self.funding.entry(room_id)
+            .or_insert_with(|| Funding {
+                owner,
+                contributions: Vec::new(),
+                complete: false,
+            });
+        if let Some(existing) = funding.contributions.iter_mut().find(|v| v.0 == by) {
+            existing.1 += amount;
+        } else {
+            funding.contributions.push((by, amount));
+        }
+        Ok(())
+    }
+
+    /// Pays or refunds the escrow of rooms that have been built or
+    /// cancelled and drops offers from players that have left.
+    ///
+    /// `room` returns the owner of the room and whether it has
+    /// been built, or `None` if it no longer exists.
+    pub(crate) fn settle<A, F>(&mut self, accounts: &mut FNVMap<Id, A>, room: F)
+        where A: Account,
+              F: Fn(room::Id) -> Option<(Id, bool)>,
+    {
+        self.offers.retain(|v| accounts.contains_key(&v.from) && accounts.contains_key(&v.to));
+
+        self.funding.retain(|room_id, funding| {
+            match room(*room_id) {
+                Some((owner, done)) if owner == funding.owner => {
+                    if done && !funding.complete {
+                        funding.complete = true;
+                        if let Some(account) = accounts.get_mut(&funding.owner) {
+                            for &(from, amount) in &funding.contributions {
+                                account.transfer(LedgerEntry {
+                                    reason: LedgerReason::Funding,
+                                    amount,
+                                    other: Some(from),
+                                    room_id: Some(*room_id),
+                                });
+                            }
+                        }
+                    }
+                    true
+                },
+                // The room was cancelled or removed
+                _ => {
+                    if !funding.complete {
+                        for &(from, amount) in &funding.contributions {
+                            if let Some(account) = accounts.get_mut(&from) {
+                                account.transfer(LedgerEntry {
+                                    reason: LedgerReason::Refund,
+                                    amount,
+                                    other: Some(funding.owner),
+                                    room_id: Some(*room_id),
+                                });
+                            }
+                        }
+                    }
+                    false
+                },
+            }
+        });
+    }
+
+    /// Splits income earned by a room between the players that
+    /// funded it.
+    ///
+    /// Rooms that aren't shared give everything to `owner`. Any
+    /// remainder left from rounding goes to the owner.
+    pub fn split_income(&self, room_id: room::Id, owner: Id, amount: UniDollar) -> Vec<(Id, UniDollar)> {
+        let funding = match self.funding.get(&room_id) {
+            Some(funding) if funding.complete && funding.owner == owner && amount > UniDollar(0) => funding,
+            _ => return vec![(owner, amount)],
+        };
+        let total = funding.total().0;
+        if total <= 0 {
+            return vec![(owner, amount)];
+        }
+        let pool = amount.0 * (100 - OWNER_INCOME_SHARE) / 100;
+        let mut remaining = amount.0;
+        let mut split = Vec::with_capacity(funding.contributions.len() + 1);
+        for &(from, contributed) in &funding.contributions {
+            let share = pool * contributed.0 / total;
+            if share > 0 {
+                remaining -= share;
+                split.push((from, UniDollar(share)));
+            }
+        }
+        split.insert(0, (owner, UniDollar(remaining)));
+        split
+    }
+
+    /// Returns the trades involving the player
+    pub fn state_for(&self, player: Id, ledger: &Ledger) -> SharedState {
+        let mut funding: Vec<_> = self.funding.iter()
+            .filter_map(|(room_id, f)| {
+                let total = f.total();
+                let contributed = if f.owner == player {
+                    total
+                } else {
+                    f.contributions.iter().find(|v| v.0 == player)?.1
+                };
+                Some(FundingShare {
+                    room_id: *room_id,
+                    owner: f.owner,
+                    contributed,
+                    total,
+                    shared: f.complete,
+                })
+            })
+            .collect();
+        funding.sort_by_key(|v| v.room_id);
+        SharedState {
+            offers: self.offers.iter()
+                .filter(|v| v.from == player || v.to == player)
+                .cloned()
+                .collect(),
+            funding,
+            ledger: ledger.entries()
+                .take(SHARED_LEDGER_SIZE)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Settles trades and updates the shared state of every player
+pub(crate) fn tick(level: &Level, entities: &mut ecs::Container, players: &mut crate::PlayerInfoMap) {
+    let trades = if let Some(trades) = entities.get_component_mut::<Trades>(ecs::Container::WORLD) {
+        trades
+    } else {
+        return;
+    };
+    trades.settle(players, |id| level.try_room_info(id).map(|v| (v.owner, v.state.is_done())));
+    for info in players.values_mut() {
+        info.shared = trades.state_for(info.get_uid(), &info.ledger);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct TestAccount {
+        money: UniDollar,
+        ledger: Ledger,
+    }
+
+    impl Account for TestAccount {
+        fn balance(&self) -> UniDollar {
+            self.money
+        }
+
+        fn transfer(&mut self, entry: LedgerEntry) {
+            self.money += entry.amount;
+            self.ledger.record(entry);
+        }
+    }
+
+    fn accounts(money: &[i64]) -> FNVMap<Id, TestAccount> {
+        money.iter()
+            .enumerate()
+            .map(|(idx, m)| (Id(idx as i16), TestAccount {
+                money: UniDollar(*m),
+                ledger: Ledger::default(),
+            }))
+            .collect()
+    }
+
+    #[test]
+    fn transfer() {
+        let mut accounts = accounts(&[1000, 0]);
+        let mut trades = Trades::default();
+        let id = trades.offer(&accounts, Id(0), Id(1), UniDollar(400)).unwrap();
+        // Only the receiver can accept
+        assert!(trades.accept(&mut accounts, Id(0), id).is_err());
+        trades.accept(&mut accounts, Id(1), id).unwrap();
+        assert_eq!(accounts[&Id(0)].money, UniDollar(600));
+        assert_eq!(accounts[&Id(1)].money, UniDollar(400));
+        assert_eq!(accounts[&Id(1)].ledger.entries().next().map(|v| v.other), Some(Some(Id(0))));
+        assert!(trades.accept(&mut accounts, Id(1), id).is_err());
+    }
+
+    #[test]
+    fn transfer_validation() {
+        let accounts = accounts(&[1000, 0]);
+        let mut trades = Trades::default();
+        assert!(trades.offer(&accounts, Id(0), Id(0), UniDollar(10)).is_err());
+        assert!(trades.offer(&accounts, Id(0), Id(5), UniDollar(10)).is_err());
+        assert!(trades.offer(&accounts, Id(0), Id(1), UniDollar(-10)).is_err());
+        let err = trades.offer(&accounts, Id(0), Id(1), UniDollar(2000)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::NotEnoughMoney);
+        for _ in 0 .. MAX_OFFERS {
+            trades.offer(&accounts, Id(0), Id(1), UniDollar(10)).unwrap();
+        }
+        let err = trades.offer(&accounts, Id(0), Id(1), UniDollar(10)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::TooManyOffers);
+    }
+
+    #[test]
+    fn accept_without_money() {
+        let mut accounts = accounts(&[1000, 0]);
+        let mut trades = Trades::default();
+        let id = trades.offer(&accounts, Id(0), Id(1), UniDollar(1000)).unwrap();
+        accounts.get_mut(&Id(0)).unwrap().money = UniDollar(10);
+        assert!(trades.accept(&mut accounts, Id(1), id).is_err());
+        assert_eq!(accounts[&Id(1)].money, UniDollar(0));
+        assert!(trades.state_for(Id(1), &Ledger::default()).offers.is_empty());
+    }
+
+    #[test]
+    fn funding_refund() {
+        let mut accounts = accounts(&[0, 1000, 1000]);
+        let mut trades = Trades::default();
+        trades.contribute(&mut accounts, Id(1), room::Id(3), Id(0), UniDollar(300)).unwrap();
+        trades.contribute(&mut accounts, Id(2), room::Id(3), Id(0), UniDollar(200)).unwrap();
+        assert_eq!(accounts[&Id(1)].money, UniDollar(700));
+
+        // Still being built
+        trades.settle(&mut accounts, |_| Some((Id(0), false)));
+        assert_eq!(accounts[&Id(0)].money, UniDollar(0));
+
+        // Cancelled
+        trades.settle(&mut accounts, |_| None);
+        assert_eq!(accounts[&Id(0)].money, UniDollar(0));
+        assert_eq!(accounts[&Id(1)].money, UniDollar(1000));
+        assert_eq!(accounts[&Id(2)].money, UniDollar(1000));
+        assert!(trades.save().funding.is_empty());
+    }
+
+    #[test]
+    fn funding_shared_income() {
+        let mut accounts = accounts(&[0, 1000, 1000]);
+        let mut trades = Trades::default();
+        assert!(trades.contribute(&mut accounts, Id(0), room::Id(3), Id(0), UniDollar(300)).is_err());
+        trades.contribute(&mut accounts, Id(1), room::Id(3), Id(0), UniDollar(300)).unwrap();
+        trades.contribute(&mut accounts, Id(2), room::Id(3), Id(0), UniDollar(100)).unwrap();
+
+        trades.settle(&mut accounts, |_| Some((Id(0), true)));
+        assert_eq!(accounts[&Id(0)].money, UniDollar(400));
+        // Built rooms can't be funded further
+        assert!(trades.contribute(&mut accounts, Id(1), room::Id(3), Id(0), UniDollar(10)).is_err());
+
+        let split = trades.split_income(room::Id(3), Id(0), UniDollar(100));
+        assert_eq!(split, vec![
+            (Id(0), UniDollar(51)),
+            (Id(1), UniDollar(37)),
+            (Id(2), UniDollar(12)),
+        ]);
+        assert_eq!(trades.split_income(room::Id(4), Id(0), UniDollar(100)), vec![(Id(0), UniDollar(100))]);
+
+        let state = trades.state_for(Id(1), &Ledger::default());
+        assert_eq!(state.funding[0].contributed, UniDollar(300));
+        assert_eq!(state.funding[0].total, UniDollar(400));
+        assert!(state.funding[0].shared);
+
+        // Removing a built room doesn't refund anyone
+        trades.settle(&mut accounts, |_| None);
+        assert_eq!(accounts[&Id(1)].money, UniDollar(700));
+    }
+
+    #[test]
+    fn save_load() {
+        let mut accounts = accounts(&[0, 1000]);
+        let mut trades = Trades::default();
+        trades.contribute(&mut accounts, Id(1), room::Id(3), Id(0), UniDollar(300)).unwrap();
+        let trades = Trades::load(trades.save());
+        let state = trades.state_for(Id(0), &Ledger::default());
+        assert_eq!(state.funding[0].total, UniDollar(300));
+    }
+}
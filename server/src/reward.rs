@@ -0,0 +1,360 @@
+//! Weighted reward tables for missions to hand out rewards with.
+//!
+//! Each pack may contain a `rewards.json` file within its own
+//! module folder that defines named tables:
+//!
+//! ```ignore
+//! {
+//!     "exam_bonus": {
+//!         "title": "Exam results bonus",
+//!         "rolls": 2,
+//!         "entries": [
+//!             {"weight": 10, "reward": {"money": {"min": 500, "max": 2000}}},
+//!             {"weight": 5, "reward": {"rating": {"min": 10, "max": 50}}},
+//!             {"weight": 1, "reward": {"unlock": "rooms/observatory"}},
+//!             {"weight": 1, "reward": {"cosmetic": "statue_gold"}},
+//!             {"weight": 4, "reward": "nothing"}
+//!         ]
+//!     }
+//! }
+//! ```
+//!
+//! Tables are referenced by `module:name`. Later packs replace
+//! tables with the same key.
+//!
+//! Missions roll a table with `control.roll_rewards(player, table, rng)`
+//! using an rng from `rng_new(seed)` so the same seed always gives
+//! the same rewards. Money is paid into the player's account and
+//! recorded in their ledger, rating is applied straight away and
+//! the player is notified of everything received. Unlocks and
+//! cosmetics are returned to the mission to apply as the game
+//! has no concept of either itself.
+
+use std::cmp;
+use rand::Rng;
+use lua::{self, Ref, CellRef, Scope, Table};
+use crate::player::{self, PlayerInfo};
+use crate::script::ScriptRng;
+use crate::util::FNVMap;
+use crate::prelude::*;
+
+/// The most times a single table may be rolled at once
+pub const MAX_ROLLS: u32 = 16;
+
+/// Registers components required by this module
+pub fn register_components(c: &mut Container) {
+    c.register_component::<RewardTables>();
+}
+
+/// A reward rolled from a table
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reward {
+    /// Money paid to the player
+    Money(UniDollar),
+    /// Rating given to the player
+    Rating(i16),
+    /// Something unlocked for the player, applied by the mission
+    Unlock(ResourceKey<'static>),
+    /// A cosmetic item for the player, applied by the mission
+    Cosmetic(ResourceKey<'static>),
+}
+
+#[derive(Debug, Clone)]
+enum RewardKind {
+    Money(i64, i64),
+    Rating(i16, i16),
+    Unlock(ResourceKey<'static>),
+    Cosmetic(ResourceKey<'static>),
+    Nothing,
+}
+
+/// A weighted list of rewards
+#[derive(Debug, Clone)]
+pub struct RewardTable {
+    /// The name shown to players receiving rewards from the table
+    pub title: String,
+    rolls: u32,
+    entries: Vec<(u32, RewardKind)>,
+    total_weight: u32,
+}
+
+impl RewardTable {
+    /// Rolls the table returning the rewards picked
+    pub fn roll(&self, rng: &mut impl Rng) -> Vec<Reward> {
+        let mut rewards = Vec::new();
+        if self.total_weight == 0 {
+            return rewards;
+        }
+        for _ in 0 .. self.rolls {
+            let mut pick = rng.gen_range(0, self.total_weight);
+            let kind = self.entries.iter()
+                .find(|(weight, _)| if pick < *weight {
+                    true
+                } else {
+                    pick -= *weight;
+                    false
+                })
+                .map(|v| &v.1);
+            rewards.push(match kind {
+                Some(RewardKind::Money(min, max)) => Reward::Money(UniDollar(rng.gen_range(*min, *max + 1))),
+                Some(RewardKind::Rating(min, max)) => Reward::Rating(rng.gen_range(*min, *max + 1)),
+                Some(RewardKind::Unlock(key)) => Reward::Unlock(key.clone()),
+                Some(RewardKind::Cosmetic(key)) => Reward::Cosmetic(key.clone()),
+                Some(RewardKind::Nothing) | None => continue,
+            });
+        }
+        rewards
+    }
+}
+
+/// Every reward table defined by the loaded packs.
+///
+/// Stored on the world entity
+#[derive(Debug, Default)]
+pub struct RewardTables {
+    tables: FNVMap<ResourceKey<'static>, RewardTable>,
+}
+component!(RewardTables => Map);
+
+impl RewardTables {
+    /// Loads the tables from every pack
+    pub fn load(log: &Logger, assets: &AssetManager) -> RewardTables {
+        let mut tables = RewardTables::default();
+        for module in assets.get_packs() {
+            let file = match assets.open_from_pack(module.borrow(), "rewards.json") {
+                Ok(val) => val,
+                Err(_) => continue,
+            };
+            let info: FNVMap<String, TableInfo> = match serde_json::from_reader(file) {
+                Ok(val) => val,
+                Err(err) => {
+                    error!(log, "Failed to parse rewards.json for pack {:?}: {}", module, err);
+                    continue
+                }
+            };
+            tables.add(module.borrow(), log, info);
+        }
+        tables
+    }
+
+    fn add(&mut self, module: ModuleKey<'_>, log: &Logger, info: FNVMap<String, TableInfo>) {
+        let key = |v: &str| LazyResourceKey::parse(v).or_module(module.borrow()).into_owned();
+        for (name, info) in info {
+            let mut entries = Vec::with_capacity(info.entries.len());
+            for entry in info.entries {
+                let kind = match entry.reward {
+                    RewardInfo::Money{min, max} if min <= max => RewardKind::Money(min, max),
+                    RewardInfo::Rating{min, max} if min <= max => RewardKind::Rating(min, max),
+                    RewardInfo::Money{..} | RewardInfo::Rating{..} => {
+                        warn!(log, "Reward in table {:?} of pack {:?} has a min greater than its max", name, module);
+                        continue;
+                    },
+                    RewardInfo::Unlock(v) => RewardKind::Unlock(key(&v)),
+                    RewardInfo::Cosmetic(v) => RewardKind::Cosmetic(key(&v)),
+                    RewardInfo::Nothing => RewardKind::Nothing,
+                };
+                entries.push((entry.weight, kind));
+            }
+            if info.rolls > MAX_ROLLS {
+                warn!(log, "Reward table {:?} of pack {:?} rolls more than {} times", name, module, MAX_ROLLS);
+            }
+            let total_weight = entries.iter().map(|v| v.0).sum();
+            self.tables.insert(ResourceKey::new(module.clone(), name.clone()).into_owned(), RewardTable {
+                title: info.title.unwrap_or(name),
+                rolls: cmp::min(info.rolls, MAX_ROLLS),
+                entries,
+                total_weight,
+            });
+        }
+    }
+
+    /// Returns the table with the given key if it exists
+    pub fn get(&self, key: ResourceKey<'_>) -> Option<&RewardTable> {
+        self.tables.get(&key)
+    }
+}
+
+/// Gives the rewards to the player, notifying them of what they
+/// received
+pub(crate) fn grant(player: &mut PlayerInfo, title: &str, rewards: &[Reward]) {
+    let mut received = Vec::with_capacity(rewards.len());
+    for reward in rewards {
+        match reward {
+            Reward::Money(amount) => {
+                player::Account::transfer(player, player::LedgerEntry {
+                    reason: player::LedgerReason::Reward,
+                    amount: *amount,
+                    other: None,
+                    room_id: None,
+                });
+                received.push(amount.to_string());
+            },
+            Reward::Rating(rating) => {
+                player.rating = cmp::min(cmp::max(player.rating.saturating_add(*rating), -30_000), 30_000);
+                received.push(format!("{:+} rating", rating));
+            },
+            Reward::Unlock(key) | Reward::Cosmetic(key) => received.push(key.resource().to_owned()),
+        }
+    }
+    if received.is_empty() {
+        return;
+    }
+    player.notifications.push(crate::notify::Notification::Text {
+        icon: ResourceKey::new("base", "solid"),
+        title: title.to_owned(),
+        description: format!("You received {}", received.join(", ")),
+    });
+}
+
+#[derive(Debug, Deserialize)]
+struct TableInfo {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default = "default_rolls")]
+    rolls: u32,
+    entries: Vec<EntryInfo>,
+}
+
+fn default_rolls() -> u32 { 1 }
+
+#[derive(Debug, Deserialize)]
+struct EntryInfo {
+    weight: u32,
+    reward: RewardInfo,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RewardInfo {
+    Money {
+        min: i64,
+        max: i64,
+    },
+    Rating {
+        min: i16,
+        max: i16,
+    },
+    Unlock(String),
+    Cosmetic(String),
+    Nothing,
+}
+
+/// Sets up an interface for missions to roll reward tables
+pub fn init_rewardlib(lua: &lua::Lua) {
+    lua.set(Scope::Global, "control_roll_rewards", lua::closure3(|lua, id: i32, table: Ref<String>, rng: CellRef<ScriptRng>| -> UResult<Ref<Table>> {
+        let _limit = lua.get_borrow::<crate::mission::MissionAllowed>();
+        let key = LazyResourceKey::parse(&table)
+            .or_module(ModuleKey::new("base"));
+        let entities = lua.read_borrow::<Container>();
+        let tables = entities.get_component::<RewardTables>(Container::WORLD)
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let table = tables.get(key.borrow())
+            .ok_or_else(|| ErrorKind::Msg(format!("No reward table called {:?}", key)))?;
+        let rewards = table.roll(rng.borrow_mut().rng_mut());
+
+        let mut players = lua.write_borrow::<crate::PlayerInfoMap>();
+        let player = players.get_mut(&PlayerId(id as i16))
+            .ok_or_else(|| ErrorKind::Msg(format!("No player with the id {}", id)))?;
+        grant(player, &table.title, &rewards);
+
+        let field = |name: &str| Ref::new_string(lua, name);
+        let out = Ref::new_table(lua);
+        for reward in rewards {
+            let tbl = Ref::new_table(lua);
+            match reward {
+                Reward::Money(amount) => {
+                    tbl.insert(field("type"), field("money"));
+                    tbl.insert(field("amount"), amount.0 as f64);
+                },
+                Reward::Rating(rating) => {
+                    tbl.insert(field("type"), field("rating"));
+                    tbl.insert(field("amount"), i32::from(rating));
+                },
+                Reward::Unlock(key) => {
+                    tbl.insert(field("type"), field("unlock"));
+                    tbl.insert(field("key"), Ref::new_string(lua, key.as_string()));
+                },
+                Reward::Cosmetic(key) => {
+                    tbl.insert(field("type"), field("cosmetic"));
+                    tbl.insert(field("key"), Ref::new_string(lua, key.as_string()));
+                },
+            }
+            out.insert(out.length() + 1, tbl);
+        }
+        Ok(out)
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn tables(json: &str) -> RewardTables {
+        let log = Logger::root(::slog::Discard, o!());
+        let mut tables = RewardTables::default();
+        tables.add(ModuleKey::new("base"), &log, serde_json::from_str(json).unwrap());
+        tables
+    }
+
+    #[test]
+    fn deterministic() {
+        let tables = tables(r#"{
+            "bonus": {
+                "rolls": 4,
+                "entries": [
+                    {"weight": 3, "reward": {"money": {"min": 100, "max": 200}}},
+                    {"weight": 1, "reward": {"unlock": "rooms/lab"}}
+                ]
+            }
+        }"#);
+        let table = tables.get(ResourceKey::new("base", "bonus")).unwrap();
+        assert_eq!(table.title, "bonus");
+        let a = table.roll(&mut StdRng::seed_from_u64(7));
+        let b = table.roll(&mut StdRng::seed_from_u64(7));
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 4);
+        for reward in a {
+            match reward {
+                Reward::Money(v) => assert!(v >= UniDollar(100) && v <= UniDollar(200)),
+                Reward::Unlock(key) => assert_eq!(key, ResourceKey::new("base", "rooms/lab")),
+                other => panic!("Unexpected reward {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn weights() {
+        let tables = tables(r#"{
+            "never": {
+                "rolls": 50,
+                "entries": [
+                    {"weight": 0, "reward": {"rating": {"min": 1, "max": 1}}},
+                    {"weight": 1, "reward": "nothing"}
+                ]
+            },
+            "empty": {"entries": []}
+        }"#);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(tables.get(ResourceKey::new("base", "never")).unwrap().roll(&mut rng).is_empty());
+        assert!(tables.get(ResourceKey::new("base", "empty")).unwrap().roll(&mut rng).is_empty());
+    }
+
+    #[test]
+    fn invalid_entries() {
+        let tables = tables(r#"{
+            "bad": {
+                "rolls": 1000,
+                "entries": [
+                    {"weight": 1, "reward": {"money": {"min": 10, "max": 1}}},
+                    {"weight": 1, "reward": {"rating": {"min": 5, "max": 5}}}
+                ]
+            }
+        }"#);
+        let table = tables.get(ResourceKey::new("base", "bad")).unwrap();
+        let rewards = table.roll(&mut StdRng::seed_from_u64(3));
+        assert_eq!(rewards.len(), MAX_ROLLS as usize);
+        assert!(rewards.iter().all(|v| *v == Reward::Rating(5)));
+    }
+}
@@ -0,0 +1,320 @@
+//! Incremental saving.
+//!
+//! Writing the whole game every lesson gets expensive for large
+//! campuses so in between full saves only a diff against the last
+//! full save is written to `<name>.udiff`. Each record of the diff
+//! either refers to an unchanged record of the full save by its index
+//! or contains the new record. Once the diff grows too large compared
+//! to the full save a new full save is written instead.
+
+use crate::prelude::*;
+use super::SaveData;
+
+use std::io::{Write as IoWrite, Read as IoRead};
+use serde_cbor;
+use serde::Deserialize;
+use crc::crc64;
+
+/// The maximum size of a diff (compared to the size of the
+/// full save) before a full save is written instead
+const MAX_DIFF_RATIO: f64 = 0.5;
+
+/// Identifies a record by its encoded form
+type RecordKey = (u64, u32);
+
+fn record_key(data: &[u8]) -> RecordKey {
+    (crc64::checksum_ecma(data), data.len() as u32)
+}
+
+/// Tracks the last full save so that following saves can be
+/// written as a diff against it.
+pub struct IncrementalSaves {
+    base: Option<SaveBase>,
+    diffs: bool,
+}
+
+impl IncrementalSaves {
+    /// Creates a tracker with no full save yet.
+    ///
+    /// When `diffs` is false every save is a full save, the
+    /// last one is still tracked for recovery journals.
+    pub fn new(diffs: bool) -> IncrementalSaves {
+        IncrementalSaves {
+            base: None,
+            diffs,
+        }
+    }
+
+    /// Returns the base to diff against if the next save
+    /// doesn't need to be a full save
+    pub(super) fn diff_base(&mut self) -> Option<&mut SaveBase> {
+        if !self.diffs {
+            return None;
+        }
+        self.base.as_mut()
+            .filter(|v| (v.last_diff_len as f64) < v.file_len as f64 * MAX_DIFF_RATIO)
+    }
+
+    /// Returns the base that recovery journals are written
+    /// against if there has been a full save
+    pub(super) fn journal_base(&mut self) -> Option<&mut SaveBase> {
+        self.base.as_mut()
+    }
+
+    /// Replaces the base with a newly written full save
+    pub(super) fn set_base(&mut self, base: SaveBase) {
+        self.base = Some(base);
+    }
+}
+
+/// The records contained in a full save
+pub(super) struct SaveBase {
+    /// The size of the full save file. Used to detect
+    /// diffs that don't belong to the full save
+    pub(super) file_len: u64,
+    pub(super) record_count: u32,
+    records: FNVMap<RecordKey, u32>,
+    last_diff_len: u64,
+}
+
+/// The header of a diff save
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct DiffHeader {
+    /// The size of the full save file the diff was
+    /// created from
+    pub(super) base_len: u64,
+    /// The number of records in the full save the diff
+    /// was created from
+    pub(super) base_records: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum DiffRecord {
+    /// The record at the index in the full save is unchanged
+    Base(u32),
+    /// A new record follows this one
+    New,
+}
+
+/// A destination for save records
+pub(super) trait RecordWriter {
+    /// Writes the record to the save
+    fn write_record(&mut self, data: &SaveData) -> UResult<()>;
+}
+
+/// Writes the records of a full save whilst collecting
+/// the information needed to diff against it later
+pub(super) struct FullWriter<W> {
+    w: W,
+    buf: Vec<u8>,
+    records: FNVMap<RecordKey, u32>,
+    count: u32,
+    len: u64,
+}
+
+impl <W: IoWrite> FullWriter<W> {
+    pub(super) fn new(w: W) -> FullWriter<W> {
+        FullWriter {
+            w,
+            buf: Vec::new(),
+            records: FNVMap::default(),
+            count: 0,
+            len: 0,
+        }
+    }
+
+    /// Completes the save returning it as a base for future
+    /// diffs. `header_len` is the number of bytes written
+    /// before the first record.
+    pub(super) fn into_base(self, header_len: u64) -> SaveBase {
+        SaveBase {
+            file_len: header_len + self.len,
+            record_count: self.count,
+            records: self.records,
+            last_diff_len: 0,
+        }
+    }
+}
+
+impl <W: IoWrite> RecordWriter for FullWriter<W> {
+    fn write_record(&mut self, data: &SaveData) -> UResult<()> {
+        self.buf.clear();
+        serde_cbor::to_writer(&mut self.buf, data)?;
+        self.w.write_all(&self.buf)?;
+        // Identical records are only referenced once
+        self.records.entry(record_key(&self.buf)).or_insert(self.count);
+        self.count += 1;
+        self.len += self.buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// Writes the records of a save as a diff against
+/// the last full save
+pub(super) struct DiffWriter<'a, W> {
+    w: W,
+    buf: Vec<u8>,
+    base: &'a mut SaveBase,
+    used: Vec<bool>,
+    len: u64,
+}
+
+impl <'a, W: IoWrite> DiffWriter<'a, W> {
+    pub(super) fn new(w: W, base: &'a mut SaveBase) -> DiffWriter<'a, W> {
+        DiffWriter {
+            w,
+            buf: Vec::new(),
+            used: vec![false; base.record_count as usize],
+            base,
+            len: 0,
+        }
+    }
+}
+
+impl <'a, W: IoWrite> RecordWriter for DiffWriter<'a, W> {
+    fn write_record(&mut self, data: &SaveData) -> UResult<()> {
+        self.buf.clear();
+        serde_cbor::to_writer(&mut self.buf, data)?;
+        let key = record_key(&self.buf);
+        // Each base record can only be used once so that
+        // loading can take the records instead of copying them
+        let base_idx = self.base.records.get(&key)
+            .cloned()
+            .filter(|v| !self.used[*v as usize]);
+        if let Some(idx) = base_idx {
+            self.used[idx as usize] = true;
+            let mut out = Vec::with_capacity(8);
+            serde_cbor::to_writer(&mut out, &DiffRecord::Base(idx))?;
+            self.w.write_all(&out)?;
+            self.len += out.len() as u64;
+        } else {
+            let mut out = Vec::with_capacity(2);
+            serde_cbor::to_writer(&mut out, &DiffRecord::New)?;
+            self.w.write_all(&out)?;
+            self.w.write_all(&self.buf)?;
+            self.len += (out.len() + self.buf.len()) as u64;
+        }
+        Ok(())
+    }
+}
+
+impl <'a, W> Drop for DiffWriter<'a, W> {
+    fn drop(&mut self) {
+        // Only the most recent diff is kept so the size used
+        // to decide when to consolidate is this diff's
+        self.base.last_diff_len = self.len;
+    }
+}
+
+/// Decodes records from a save stream
+pub(super) fn read_record<R, T>(r: &mut R) -> Option<UResult<T>>
+    where R: IoRead,
+          T: for<'de> Deserialize<'de>,
+{
+    let mut de = serde_cbor::Deserializer::from_reader(r);
+    match T::deserialize(&mut de) {
+        Ok(val) => Some(Ok(val)),
+        Err(ref err) if err.is_eof() => None,
+        Err(err) => Some(Err(err.into()))
+    }
+}
+
+/// Replays a diff save on top of the records from the
+/// full save
+pub(super) struct DiffReplay<R> {
+    base: Vec<Option<SaveData>>,
+    diff: R,
+}
+
+impl <R: IoRead> DiffReplay<R> {
+    pub(super) fn new(base: Vec<Option<SaveData>>, diff: R) -> DiffReplay<R> {
+        DiffReplay {
+            base,
+            diff,
+        }
+    }
+}
+
+impl <R: IoRead> Iterator for DiffReplay<R> {
+    type Item = UResult<SaveData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(match read_record::<_, DiffRecord>(&mut self.diff)? {
+            Ok(DiffRecord::Base(idx)) => self.base.get_mut(idx as usize)
+                .and_then(|v| v.take())
+                .ok_or_else(|| ErrorKind::Msg(format!("Invalid diff record {}", idx)).into()),
+            Ok(DiffRecord::New) => match read_record(&mut self.diff) {
+                Some(val) => val,
+                None => Err(ErrorKind::Msg("Truncated diff save".into()).into()),
+            },
+            Err(err) => Err(err),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::GameState;
+
+    fn records() -> Vec<SaveData> {
+        vec![
+            SaveData::Level(100, 100),
+            SaveData::GameState(GameState {
+                day_tick: DayTick::default(),
+                goals: None,
+                trades: None,
+            }),
+            SaveData::MissionState(vec![1, 2, 3]),
+        ]
+    }
+
+    #[test]
+    fn test_diff_replay() {
+        let mut full = FullWriter::new(Vec::new());
+        for r in records() {
+            full.write_record(&r).unwrap();
+        }
+        let base_data = full.w.clone();
+        let mut base = full.into_base(0);
+        assert_eq!(base.file_len, base_data.len() as u64);
+
+        let mut new_records = records();
+        new_records.swap(0, 2);
+        new_records[1] = SaveData::MissionState(vec![4]);
+
+        let mut diff = Vec::new();
+        {
+            let mut writer = DiffWriter::new(&mut diff, &mut base);
+            for r in &new_records {
+                writer.write_record(r).unwrap();
+            }
+        }
+        assert!(diff.len() < base_data.len());
+
+        let mut reader = &base_data[..];
+        let mut base_records = vec![];
+        while let Some(r) = read_record::<_, SaveData>(&mut reader) {
+            base_records.push(Some(r.unwrap()));
+        }
+        let replayed = DiffReplay::new(base_records, &diff[..])
+            .collect::<UResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(format!("{:?}", replayed), format!("{:?}", new_records));
+    }
+
+    #[test]
+    fn test_journal_without_diffs() {
+        let mut full = FullWriter::new(Vec::new());
+        for r in records() {
+            full.write_record(&r).unwrap();
+        }
+        let mut incremental = IncrementalSaves::new(false);
+        assert!(incremental.journal_base().is_none());
+        incremental.set_base(full.into_base(0));
+        // Every save is a full save but journals still have
+        // something to diff against
+        assert!(incremental.diff_base().is_none());
+        assert!(incremental.journal_base().is_some());
+    }
+}
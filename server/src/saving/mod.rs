@@ -3,7 +3,9 @@
 use crate::prelude::*;
 
 pub mod filesystem;
+pub mod scheduled;
 mod conv;
+mod incremental;
 
 use std::io::{SeekFrom, Write as IoWrite, Read as IoRead, Seek, BufReader};
 use serde_cbor;
@@ -16,11 +18,16 @@ use steamworks;
 use byteorder::{WriteBytesExt, ReadBytesExt, LittleEndian};
 use crate::mission;
 use crate::script_room;
+use crate::entity::template::{EntityTemplates, EntityTemplate};
+use crate::entity::goals::{Goals, GoalState};
+use std::cell::RefCell;
 
 use crate::packet::HistoryEntry;
-use crate::player::PlayerConfig;
+use crate::player::{PlayerConfig, CampusIdentity, PlayerProfile};
 use crate::room::RoomState;
 use self::filesystem::*;
+pub use self::incremental::IncrementalSaves;
+use self::incremental::{RecordWriter, FullWriter, DiffWriter, DiffHeader, DiffReplay};
 
 /// The version number currently used by this version of the
 /// game.
@@ -29,6 +36,9 @@ pub const SAVE_VERSION: u32 = 6;
 /// The size of the save icon
 pub const SAVE_ICON_SIZE: (u32, u32) = (800, 600);
 
+/// The default number of ticks between recovery journals
+pub const DEFAULT_JOURNAL_INTERVAL: u32 = 20 * 20;
+
 /// Marks the type of save file. Used to
 /// filter saves.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -71,7 +81,11 @@ impl SaveType {
 pub fn delete_save<F: FileSystem>(fs: &F, name: &str) -> UResult<()> {
     let path = format!("{}.usav", name);
     fs.delete(&path)?;
-    Ok(())
+    let diff_path = format!("{}.udiff", name);
+    if fs.exists(&diff_path) {
+        fs.delete(&diff_path)?;
+    }
+    discard_journal(fs, name)
 }
 
 /// Returns whether the save file can be loaded.
@@ -141,331 +155,463 @@ pub trait IconCapture {
 }
 
 /// Saves the game's state with the given name
-/// in the default location
+/// in the default location.
+///
+/// When `incremental` is passed the save will be written
+/// as a diff against the last full save where possible.
 pub(crate) fn save_game<F: FileSystem>(
     fs: &F,
     name: &str,
     ty: SaveType,
+    mut incremental: Option<&mut IncrementalSaves>,
     players: &mut crate::PlayerInfoMap,
     level: &mut Level, entities: &mut Container,
     engine: &script::Engine,
     choices: &choice::Choices,
     running_choices: &script_room::RunningChoices,
     mission: Option<&mut mission::MissionController>,
+    templates: &RefCell<EntityTemplates>,
     day_tick: &DayTick, icon: Option<&dyn IconCapture>,
 ) -> UResult<()>
 {
-    let log = level.log.clone();
-    let assets = level.asset_manager.clone();
-    let name = format!("{}.usav", name);
+    let full_name = format!("{}.usav", name);
+    let diff_name = format!("{}.udiff", name);
+
+    if let Some(base) = incremental.as_mut().and_then(|v| v.diff_base()) {
+        let mut f = fs.write(&diff_name)?;
+        f.write_u32::<LittleEndian>(SAVE_VERSION)?;
+        f.write_u32::<LittleEndian>(ty.as_u32())?;
+        serde_cbor::to_writer(&mut f, &DiffHeader {
+            base_len: base.file_len,
+            base_records: base.record_count,
+        })?;
+        write_records(
+            &mut DiffWriter::new(&mut f, base),
+            players, level, entities, engine,
+            choices, running_choices, mission, templates, day_tick,
+        )?;
+        // The journal is older than the save now
+        return discard_journal(fs, name);
+    }
 
     let save_icon = icon.and_then(|v| v.capture());
-    {
-        let mut f = fs.write(&name)?;
+    let base = {
+        let mut f = fs.write(&full_name)?;
         f.write_u32::<LittleEndian>(SAVE_VERSION)?;
         f.write_u32::<LittleEndian>(ty.as_u32())?;
+        let mut header_len = 12;
         if let Some(icon) = save_icon {
             f.write_i32::<LittleEndian>(icon.len() as i32)?;
             f.write_all(&icon)?;
+            header_len += icon.len() as u64;
         } else {
             f.write_i32::<LittleEndian>(-1)?;
         }
 
-        let players_sf = entities.with(|
-            _em: EntityManager,
-            network_id: Read<NetworkId>,
-        | {
-            SaveData::Players(players.iter()
-                .map(|(k, v)| (*k, PlayerInfo {
-                    name: v.name.clone(),
-                    key: match v.key {
-                        #[cfg(feature = "steam")]
-                        player::PlayerKey::Steam(steam_id) => PlayerKey::Steam(steam_id.raw()),
-                        #[cfg(not(feature = "steam"))]
-                        player::PlayerKey::Username(ref name) => PlayerKey::Username(name.clone()),
-                    },
-                    money: v.money,
-                    rating: v.rating,
-                    history: v.history.clone().into(),
-                    current_income: v.current_income,
-                    current_outcome: v.current_outcome,
-                    state: match v.state {
-                        crate::player::State::BuildRoom{active_room} => PlayerState::BuildRoom {
-                            active_room
-                        },
-                        crate::player::State::EditRoom{active_room} => PlayerState::EditRoom {
-                            active_room
-                        },
-                        // Don't bother with this
-                        crate::player::State::None | crate::player::State::EditEntity{..} => PlayerState::None,
-                    },
-                    config: v.config.clone(),
-                    courses: v.courses.iter()
-                        .map(|(id, v)| (*id, SavableCourse {
-                            uid: v.uid,
-                            name: v.name.clone(),
-                            group: v.group.clone(),
-                            cost: v.cost,
-                            timetable: SavableCourse::conv_timetable(&network_id, &v.timetable),
-                            deprecated: v.deprecated,
-                        }))
-                        .collect(),
-                }))
-                .collect())
-        });
+        let mut out = FullWriter::new(&mut f);
+        write_records(
+            &mut out,
+            players, level, entities, engine,
+            choices, running_choices, mission, templates, day_tick,
+        )?;
+        out.into_base(header_len)
+    };
+    // Any existing diff was against the previous full save
+    if fs.exists(&diff_name) {
+        fs.delete(&diff_name)?;
+    }
+    if let Some(incremental) = incremental {
+        incremental.set_base(base);
+    }
+    discard_journal(fs, name)
+}
 
-        serde_cbor::to_writer(&mut f, &players_sf)?;
-        serde_cbor::to_writer(&mut f, &SaveData::GameState(GameState {
-            day_tick: *day_tick,
-        }))?;
-        serde_cbor::to_writer(&mut f, &SaveData::Level(level.width, level.height))?;
+/// Writes a recovery journal for the save.
+///
+/// Journals are written between saves as a diff against the
+/// last full save so that a crash loses less progress. They are
+/// removed by the next save so an existing journal means the game
+/// didn't shut down cleanly. Returns false without writing anything
+/// if there hasn't been a full save to diff against yet.
+pub(crate) fn write_journal<F: FileSystem>(
+    fs: &F,
+    name: &str,
+    ty: SaveType,
+    incremental: &mut IncrementalSaves,
+    players: &mut crate::PlayerInfoMap,
+    level: &mut Level, entities: &mut Container,
+    engine: &script::Engine,
+    choices: &choice::Choices,
+    running_choices: &script_room::RunningChoices,
+    mission: Option<&mut mission::MissionController>,
+    templates: &RefCell<EntityTemplates>,
+    day_tick: &DayTick,
+) -> UResult<bool>
+{
+    let base = if let Some(base) = incremental.journal_base() {
+        base
+    } else {
+        return Ok(false);
+    };
+    let mut f = fs.write(&format!("{}.ujournal", name))?;
+    f.write_u32::<LittleEndian>(SAVE_VERSION)?;
+    f.write_u32::<LittleEndian>(ty.as_u32())?;
+    serde_cbor::to_writer(&mut f, &DiffHeader {
+        base_len: base.file_len,
+        base_records: base.record_count,
+    })?;
+    write_records(
+        &mut DiffWriter::new(&mut f, base),
+        players, level, entities, engine,
+        choices, running_choices, mission, templates, day_tick,
+    )?;
+    Ok(true)
+}
 
+/// Returns whether the named save has a recovery journal
+/// that is newer than the save itself
+pub fn has_journal<F: FileSystem>(fs: &F, name: &str, ty: SaveType) -> bool {
+    let path = format!("{}.ujournal", name);
+    if !fs.exists(&path) {
+        return false;
+    }
+    let header = (|| -> UResult<bool> {
+        let mut journal = BufReader::new(fs.read(&path)?);
+        if journal.read_u32::<LittleEndian>()? != SAVE_VERSION
+            || SaveType::from_u32(journal.read_u32::<LittleEndian>()?) != Some(ty)
         {
-            let rooms = level.rooms.borrow();
-            for (_id, room) in rooms.iter_rooms() {
-                let room_info = SaveData::Room(RoomInfo {
-                    key: room.key.clone(),
-                    id: room.id,
-                    owner: room.owner,
-                    area: (
-                        room.area.min.x,
-                        room.area.min.y,
-                        room.area.max.x,
-                        room.area.max.y,
-                    ),
-                    state: room.state,
-                    tile_update_state: room.tile_update_state.clone(),
+            return Ok(false);
+        }
+        let header = incremental::read_record::<_, DiffHeader>(&mut journal)
+            .ok_or_else(|| ErrorKind::Msg("Truncated journal".into()))??;
+        // Journals for an older full save can't be replayed
+        let file_len = fs.read(&format!("{}.usav", name))?.seek(SeekFrom::End(0))?;
+        Ok(file_len == header.base_len)
+    })();
+    header.unwrap_or(false)
+}
+
+/// Replaces the save's state with the state from its
+/// recovery journal.
+///
+/// The journal takes the place of the save's diff so that
+/// loading the save afterwards loads the recovered state.
+pub fn restore_journal<F: FileSystem>(fs: &F, name: &str) -> UResult<()> {
+    let path = format!("{}.ujournal", name);
+    {
+        let mut journal = fs.read(&path)?;
+        let mut diff = fs.write(&format!("{}.udiff", name))?;
+        ::std::io::copy(&mut journal, &mut diff)?;
+    }
+    fs.delete(&path)?;
+    Ok(())
+}
+
+/// Removes the save's recovery journal if it has one
+pub fn discard_journal<F: FileSystem>(fs: &F, name: &str) -> UResult<()> {
+    let path = format!("{}.ujournal", name);
+    if fs.exists(&path) {
+        fs.delete(&path)?;
+    }
+    Ok(())
+}
+
+fn write_records(
+    out: &mut dyn RecordWriter,
+    players: &mut crate::PlayerInfoMap,
+    level: &mut Level, entities: &mut Container,
+    engine: &script::Engine,
+    choices: &choice::Choices,
+    running_choices: &script_room::RunningChoices,
+    mission: Option<&mut mission::MissionController>,
+    templates: &RefCell<EntityTemplates>,
+    day_tick: &DayTick,
+) -> UResult<()>
+{
+    let log = level.log.clone();
+    let assets = level.asset_manager.clone();
+
+    let players_sf = entities.with(|
+        _em: EntityManager,
+        network_id: Read<NetworkId>,
+    | {
+        SaveData::Players(players.iter()
+            .map(|(k, v)| (*k, PlayerInfo {
+                name: v.name.clone(),
+                key: match v.key {
+                    #[cfg(feature = "steam")]
+                    player::PlayerKey::Steam(steam_id) => PlayerKey::Steam(steam_id.raw()),
+                    #[cfg(not(feature = "steam"))]
+                    player::PlayerKey::Username(ref name) => PlayerKey::Username(name.clone()),
+                },
+                money: v.money,
+                rating: v.rating,
+                history: v.history.clone().into(),
+                current_income: v.current_income,
+                current_outcome: v.current_outcome,
+                state: match v.state {
+                    crate::player::State::BuildRoom{active_room} => PlayerState::BuildRoom {
+                        active_room
+                    },
+                    crate::player::State::EditRoom{active_room} => PlayerState::EditRoom {
+                        active_room
+                    },
+                    // Don't bother with this
+                    crate::player::State::None | crate::player::State::EditEntity{..} => PlayerState::None,
+                },
+                config: v.config.clone(),
+                campus: Some(v.campus.clone()),
+                profile: Some(v.profile.clone()),
+                notifications: v.script_notifications.pending().to_vec(),
+                courses: v.courses.iter()
+                    .map(|(id, v)| (*id, SavableCourse {
+                        uid: v.uid,
+                        name: v.name.clone(),
+                        group: v.group.clone(),
+                        cost: v.cost,
+                        timetable: SavableCourse::conv_timetable(&network_id, &v.timetable),
+                        deprecated: v.deprecated,
+                    }))
+                    .collect(),
+            }))
+            .collect())
+    });
+
+    out.write_record(&players_sf)?;
+    out.write_record(&SaveData::GameState(GameState {
+        day_tick: *day_tick,
+        goals: entities.get_component::<Goals>(Container::WORLD).map(|v| v.save()),
+        trades: entities.get_component::<player::Trades>(Container::WORLD).map(|v| v.save()),
+    }))?;
+    out.write_record(&SaveData::Level(level.width, level.height))?;
+
+    {
+        let rooms = level.rooms.borrow();
+        for (_id, room) in rooms.iter_rooms() {
+            let room_info = SaveData::Room(RoomInfo {
+                key: room.key.clone(),
+                id: room.id,
+                owner: room.owner,
+                area: (
+                    room.area.min.x,
+                    room.area.min.y,
+                    room.area.max.x,
+                    room.area.max.y,
+                ),
+                state: room.state,
+                tile_update_state: room.tile_update_state.clone(),
+            });
+            out.write_record(&room_info)?;
+        }
+        // TODO: Place these into the file in order of placement
+        for (id, room) in rooms.iter_rooms() {
+            let objs = if let Some(lvl) = room.building_level.as_ref() {
+                    lvl.objects.iter()
+                } else { room.objects.iter() }
+                .filter_map(|v| v.as_ref())
+                .map(|v| &v.0)
+                .map(|v| ObjectInfo {
+                    key: v.key.clone(),
+                    position: (v.position.x, v.position.y),
+                    rotation: v.rotation,
+                    version: v.version,
                 });
-                serde_cbor::to_writer(&mut f, &room_info)?;
-            }
-            // TODO: Place these into the file in order of placement
-            for (id, room) in rooms.iter_rooms() {
-                let objs = if let Some(lvl) = room.building_level.as_ref() {
-                        lvl.objects.iter()
-                    } else { room.objects.iter() }
-                    .filter_map(|v| v.as_ref())
-                    .map(|v| &v.0)
-                    .map(|v| ObjectInfo {
-                        key: v.key.clone(),
-                        position: (v.position.x, v.position.y),
-                        rotation: v.rotation,
-                        version: v.version,
-                    });
-                for obj in objs {
-                    serde_cbor::to_writer(&mut f, &SaveData::Object(id, obj))?;
-                }
+            for obj in objs {
+                out.write_record(&SaveData::Object(id, obj))?;
             }
+        }
 
-            entities.with(|
-                em: EntityManager<'_>,
-                living: Read<Living>,
-                position: Read<Position>,
-                target_position: Read<TargetPosition>,
-                rotation: Read<Rotation>,
-                target_rotation: Read<TargetRotation>,
-                speed: Read<MovementSpeed>,
-                room_owned: Read<RoomOwned>,
-                owned: Read<Owned>,
-                paid: Read<Paid>,
-                tints: Read<Tints>,
-                room_controller: Read<RoomController>,
-                timetable: Read<TimeTable>,
-                timetable_completed: Read<TimeTableCompleted>,
-                timetable_start: Read<TimeTableStart>,
-                activity: Read<Activity>,
-
-                grades: Read<Grades>,
-                idle: Read<Idle>,
-                network_id: Read<NetworkId>,
-                goto_room: Read<GotoRoom>,
-                money: Read<Money>,
-
-                mut student_vars: Write<StudentVars>,
-                mut professor_vars: Write<ProfessorVars>,
-                mut office_worker_vars: Write<OfficeWorkerVars>,
-                mut janitor_vars: Write<JanitorVars>,
-
-                frozen: Read<Frozen>,
-                quitting: Read<Quitting>,
-            | -> UResult<()> {
-                for (e, living) in em.group_mask(&living, |m| m
-                    .and_not(&frozen)
-                    .and_not(&quitting)
-                ) {
-                    let info = EntityInfo {
-                        key: living.key.clone(),
-                        variant: living.variant,
-                        name: (
-                            (*living.name.0).to_owned(),
-                            (*living.name.1).to_owned(),
-                        ),
-                        network_id: network_id.get_component(e).map(|v| v.0),
-                        position: if let Some(tp) = target_position.get_component(e) {
-                            Some(PositionInfo{ x: tp.x, y: tp.y, z: tp.z })
-                        } else if let Some(p) = position.get_component(e) {
-                            Some(PositionInfo{ x: p.x, y: p.y, z: p.z})
-                        } else {
-                            None
-                        },
-                        rotation: if let Some(tr) = target_rotation.get_component(e) {
-                            Some(tr.rotation)
-                        } else if let Some(r) = rotation.get_component(e) {
-                            Some(r.rotation)
-                        } else {
-                            None
-                        },
-                        speed: if let Some(speed) = speed.get_component(e) {
-                            Some(speed.base_speed)
-                        } else {
-                            None
-                        },
-                        room_owned: if let Some(ro) = room_owned.get_component(e) {
-                            let room = level.get_room_info(ro.room_id);
-                            if let Some(rc) = room_controller.get_component(room.controller) {
-                                Some(RoomOwnedInfo {
-                                    room_id: ro.room_id,
-                                    kind: if rc.entities.iter().any(|o| *o == e) {
-                                        OwnedKind::Owned
-                                    } else {
-                                        OwnedKind::Visitor
-                                    },
-                                    should_release_inactive: ro.should_release_inactive,
-                                    active: ro.active,
-                                })
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        },
-                        owned: if let Some(o) = owned.get_component(e) {
-                            Some(o.player_id)
-                        } else {
-                            None
-                        },
-                        paid: if let Some(paid) = paid.get_component(e) {
-                            Some(PaidInfo {
-                                cost: paid.cost,
-                                wanted_cost: paid.wanted_cost,
-                                last_payment: paid.last_payment,
-                            })
-                        } else {
-                            None
-                        },
-                        tints: if let Some(tints) = tints.get_component(e) {
-                            Some(tints.tints.clone())
-                        } else {
-                            None
-                        },
-                        vars: if let Some(vars) = student_vars.get_custom(e)
-                            .map(|v| v.remove_type())
-                            .or_else(|| professor_vars.get_custom(e)
-                                .map(|v| v.remove_type()))
-                            .or_else(|| office_worker_vars.get_custom(e)
-                                .map(|v| v.remove_type()))
-                            .or_else(|| janitor_vars.get_custom(e)
-                                .map(|v| v.remove_type()))
-                        {
-                            Some(VarsInfo {
-                                vars: vars.iter()
-                                    .map(|(k, v)| (k.to_owned(), v))
-                                    .collect(),
+        // Collected separately as the loop below already uses as
+        // many components as `with` allows
+        let appearances: FNVMap<Entity, u32> = entities.with(|
+            em: EntityManager<'_>,
+            appearance: Read<appearance::Appearance>,
+        | {
+            em.group(&appearance)
+                .map(|(e, v)| (e, v.seed))
+                .collect()
+        });
+        let visitors: FNVMap<Entity, crate::entity::visitor::Visitor> = entities.with(|
+            em: EntityManager<'_>,
+            visitor: Read<crate::entity::visitor::Visitor>,
+        | {
+            em.group(&visitor)
+                .map(|(e, v)| (e, v.clone()))
+                .collect()
+        });
+
+        entities.with(|
+            em: EntityManager<'_>,
+            living: Read<Living>,
+            position: Read<Position>,
+            target_position: Read<TargetPosition>,
+            rotation: Read<Rotation>,
+            target_rotation: Read<TargetRotation>,
+            speed: Read<MovementSpeed>,
+            room_owned: Read<RoomOwned>,
+            owned: Read<Owned>,
+            paid: Read<Paid>,
+            tints: Read<Tints>,
+            room_controller: Read<RoomController>,
+            timetable: Read<TimeTable>,
+            timetable_completed: Read<TimeTableCompleted>,
+            timetable_start: Read<TimeTableStart>,
+            activity: Read<Activity>,
+
+            grades: Read<Grades>,
+            idle: Read<Idle>,
+            network_id: Read<NetworkId>,
+            goto_room: Read<GotoRoom>,
+            money: Read<Money>,
+
+            mut student_vars: Write<StudentVars>,
+            mut professor_vars: Write<ProfessorVars>,
+            mut office_worker_vars: Write<OfficeWorkerVars>,
+            mut janitor_vars: Write<JanitorVars>,
+
+            frozen: Read<Frozen>,
+            quitting: Read<Quitting>,
+        | -> UResult<()> {
+            for (e, living) in em.group_mask(&living, |m| m
+                .and_not(&frozen)
+                .and_not(&quitting)
+            ) {
+                let info = EntityInfo {
+                    key: living.key.clone(),
+                    variant: living.variant,
+                    name: (
+                        (*living.name.0).to_owned(),
+                        (*living.name.1).to_owned(),
+                    ),
+                    network_id: network_id.get_component(e).map(|v| v.0),
+                    position: if let Some(tp) = target_position.get_component(e) {
+                        Some(PositionInfo{ x: tp.x, y: tp.y, z: tp.z })
+                    } else if let Some(p) = position.get_component(e) {
+                        Some(PositionInfo{ x: p.x, y: p.y, z: p.z})
+                    } else {
+                        None
+                    },
+                    rotation: if let Some(tr) = target_rotation.get_component(e) {
+                        Some(tr.rotation)
+                    } else if let Some(r) = rotation.get_component(e) {
+                        Some(r.rotation)
+                    } else {
+                        None
+                    },
+                    speed: if let Some(speed) = speed.get_component(e) {
+                        Some(speed.base_speed)
+                    } else {
+                        None
+                    },
+                    room_owned: if let Some(ro) = room_owned.get_component(e) {
+                        let room = level.get_room_info(ro.room_id);
+                        if let Some(rc) = room_controller.get_component(room.controller) {
+                            Some(RoomOwnedInfo {
+                                room_id: ro.room_id,
+                                kind: if rc.entities.iter().any(|o| *o == e) {
+                                    OwnedKind::Owned
+                                } else {
+                                    OwnedKind::Visitor
+                                },
+                                should_release_inactive: ro.should_release_inactive,
+                                active: ro.active,
                             })
                         } else {
                             None
-                        },
-                        timetable: timetable.get_component(e).cloned(),
-                        timetable_start: timetable_start.get_component(e).cloned(),
-                        timetable_completed: timetable_completed.get_component(e).is_some(),
-                        activity: activity.get_component(e).cloned(),
-                        grades: grades.get_component(e).cloned(),
-                        idle: if let Some(idle) = idle.get_component(e) {
-                            Some(IdleInfo {
-                                total_idle_time: idle.total_idle_time,
-                                current_choice: idle.current_choice
-                                    .and_then(|v| choices.student_idle.get_choice_name_by_index(v))
-                                    .map(|v| v.into_owned()),
-                            })
-                        } else { None },
-                        goto_room: goto_room.get_component(e).map(|v| GotoRoomInfo {
-                            room_id: v.room_id,
-                        }),
-                        money: money.get_component(e).map(|v| MoneyInfo {
-                            money: v.money,
-                        }),
-                    };
-                    serde_cbor::to_writer(&mut f, &SaveData::Entity(info))?;
-                }
-                Ok(())
-            })?;
-        }
-
-        for room_id in level.room_ids() {
-            let ty = {
-                let room = level.get_room_info(room_id);
-                if room.controller.is_invalid() {
-                    continue;
-                }
-                assume!(log, assets.loader_open::<room::Loader>(room.key.borrow()))
-            };
-            if let Some(controller) = ty.controller.as_ref() {
-                let lua_room = crate::script_room::LuaRoom::from_room(&log, &*level.rooms.borrow(), entities, room_id, engine);
-                let result = match engine.with_borrows()
-                    .borrow_mut(entities)
-                    .borrow_mut(players)
-                    .invoke_function::<_, Ref<Table>>("invoke_module_method", (
-                        Ref::new_string(engine, controller.module()),
-                        Ref::new_string(engine, controller.resource()),
-                        Ref::new_string(engine, "save"),
-                        lua_room
-                )) {
-                    Ok(ret) => ret,
-                    Err(err) => {
-                        bail!("Failed to save room: {}", err);
+                        }
+                    } else {
+                        None
+                    },
+                    owned: if let Some(o) = owned.get_component(e) {
+                        Some(o.player_id)
+                    } else {
+                        None
+                    },
+                    paid: if let Some(paid) = paid.get_component(e) {
+                        Some(PaidInfo {
+                            cost: paid.cost,
+                            wanted_cost: paid.wanted_cost,
+                            last_payment: paid.last_payment,
+                        })
+                    } else {
+                        None
+                    },
+                    tints: if let Some(tints) = tints.get_component(e) {
+                        Some(tints.tints.clone())
+                    } else {
+                        None
+                    },
+                    appearance: appearances.get(&e).cloned(),
+                    vars: if let Some(vars) = student_vars.get_custom(e)
+                        .map(|v| v.remove_type())
+                        .or_else(|| professor_vars.get_custom(e)
+                            .map(|v| v.remove_type()))
+                        .or_else(|| office_worker_vars.get_custom(e)
+                            .map(|v| v.remove_type()))
+                        .or_else(|| janitor_vars.get_custom(e)
+                            .map(|v| v.remove_type()))
+                    {
+                        Some(VarsInfo {
+                            vars: vars.iter()
+                                .map(|(k, v)| (k.to_owned(), v))
+                                .collect(),
+                        })
+                    } else {
+                        None
                     },
+                    timetable: timetable.get_component(e).cloned(),
+                    timetable_start: timetable_start.get_component(e).cloned(),
+                    timetable_completed: timetable_completed.get_component(e).is_some(),
+                    activity: activity.get_component(e).cloned(),
+                    grades: grades.get_component(e).cloned(),
+                    idle: if let Some(idle) = idle.get_component(e) {
+                        Some(IdleInfo {
+                            total_idle_time: idle.total_idle_time,
+                            current_choice: idle.current_choice
+                                .and_then(|v| choices.student_idle.get_choice_name_by_index(v))
+                                .map(|v| v.into_owned()),
+                        })
+                    } else { None },
+                    goto_room: goto_room.get_component(e).map(|v| GotoRoomInfo {
+                        room_id: v.room_id,
+                    }),
+                    money: money.get_component(e).map(|v| MoneyInfo {
+                        money: v.money,
+                    }),
+                    visitor: visitors.get(&e).cloned(),
                 };
+                out.write_record(&SaveData::Entity(info))?;
+            }
+            Ok(())
+        })?;
+    }
 
-                let mut se = serde_cbor::ser::Serializer::new(vec![]);
-                lua::with_table_deserializer(&result, |de| {
-                    serde_transcode::transcode(de, &mut se)
-                })?;
-                let data = se.into_inner();
+    for (name, template) in templates.borrow().iter() {
+        out.write_record(&SaveData::EntityTemplate(name.to_owned(), template.clone()))?;
+    }
 
-                serde_cbor::to_writer(&mut f, &SaveData::RoomScript(room_id, data))?;
-            }
+    for room_id in level.room_ids() {
+        let ty = {
             let room = level.get_room_info(room_id);
-            if let Some(rc) = entities.get_component::<RoomController>(room.controller) {
-                let state = RoomEntityState {
-                    timetabled_visitors: rc.timetabled_visitors.iter()
-                        .map(|v| v.iter()
-                            .map(|v| v.iter()
-                                .filter_map(|v| entities.get_component::<NetworkId>(*v))
-                                .map(|v| v.0)
-                                .collect())
-                            .collect())
-                        .collect(),
-                    active_staff: rc.active_staff
-                        .and_then(|v| entities.get_component::<NetworkId>(v))
-                        .map(|v| v.0),
-                };
-                serde_cbor::to_writer(&mut f, &SaveData::RoomEntityState(room_id, state))?;
+            if room.controller.is_invalid() {
+                continue;
             }
-        }
-
-        for ((player, idx), rc) in &running_choices.choices {
-            let script = assume!(log, choices.student_idle.get_choice_by_index(*idx));
+            assume!(log, assets.loader_open::<room::Loader>(room.key.borrow()))
+        };
+        if let Some(controller) = ty.controller.as_ref() {
+            let lua_room = crate::script_room::LuaRoom::from_room(&log, &*level.rooms.borrow(), entities, room_id, engine);
             let result = match engine.with_borrows()
                 .borrow_mut(entities)
                 .borrow_mut(players)
                 .invoke_function::<_, Ref<Table>>("invoke_module_method", (
-                    Ref::new_string(engine, script.script.module()),
-                    Ref::new_string(engine, script.script.resource()),
+                    Ref::new_string(engine, controller.module()),
+                    Ref::new_string(engine, controller.resource()),
                     Ref::new_string(engine, "save"),
-                    rc.handle.clone(),
+                    lua_room
             )) {
                 Ok(ret) => ret,
                 Err(err) => {
-                    bail!("Failed to save script: {}", err);
+                    bail!("Failed to save room: {}", err);
                 },
             };
 
@@ -475,19 +621,83 @@ pub(crate) fn save_game<F: FileSystem>(
             })?;
             let data = se.into_inner();
 
-            let name = assume!(log, choices.student_idle.get_choice_name_by_index(*idx));
-            serde_cbor::to_writer(&mut f, &SaveData::IdleScript(*player, name.into_owned(), data))?;
+            out.write_record(&SaveData::RoomScript(room_id, data))?;
         }
+        let room = level.get_room_info(room_id);
+        if let Some(rc) = entities.get_component::<RoomController>(room.controller) {
+            let state = RoomEntityState {
+                timetabled_visitors: rc.timetabled_visitors.iter()
+                    .map(|v| v.iter()
+                        .map(|v| v.iter()
+                            .filter_map(|v| entities.get_component::<NetworkId>(*v))
+                            .map(|v| v.0)
+                            .collect())
+                        .collect())
+                    .collect(),
+                active_staff: rc.active_staff
+                    .and_then(|v| entities.get_component::<NetworkId>(v))
+                    .map(|v| v.0),
+                construction: entities.get_component::<crate::entity::construction::Construction>(Container::WORLD)
+                    .and_then(|v| v.site(room_id)),
+            };
+            out.write_record(&SaveData::RoomEntityState(room_id, state))?;
+        }
+    }
 
-        if let Some(mission_state) = mission.and_then(|v| v.save(players, entities)) {
-            let mut se = serde_cbor::ser::Serializer::new(vec![]);
-            lua::with_table_deserializer(&mission_state, |de| {
-                serde_transcode::transcode(de, &mut se)
-            })?;
-            let data = se.into_inner();
-            serde_cbor::to_writer(&mut f, &SaveData::MissionState(data))?;
+    for ((player, idx), rc) in &running_choices.choices {
+        let script = assume!(log, choices.student_idle.get_choice_by_index(*idx));
+        let result = match engine.with_borrows()
+            .borrow_mut(entities)
+            .borrow_mut(players)
+            .invoke_function::<_, Ref<Table>>("invoke_module_method", (
+                Ref::new_string(engine, script.script.module()),
+                Ref::new_string(engine, script.script.resource()),
+                Ref::new_string(engine, "save"),
+                rc.handle.clone(),
+        )) {
+            Ok(ret) => ret,
+            Err(err) => {
+                bail!("Failed to save script: {}", err);
+            },
+        };
+
+        let mut se = serde_cbor::ser::Serializer::new(vec![]);
+        lua::with_table_deserializer(&result, |de| {
+            serde_transcode::transcode(de, &mut se)
+        })?;
+        let data = se.into_inner();
+
+        let name = assume!(log, choices.student_idle.get_choice_name_by_index(*idx));
+        out.write_record(&SaveData::IdleScript(*player, name.into_owned(), data))?;
+    }
+
+    if let Some(mission_state) = mission.and_then(|v| v.save(players, entities)) {
+        let mut se = serde_cbor::ser::Serializer::new(vec![]);
+        lua::with_table_deserializer(&mission_state, |de| {
+            serde_transcode::transcode(de, &mut se)
+        })?;
+        let data = se.into_inner();
+        out.write_record(&SaveData::MissionState(data))?;
+    }
+
+    if let Some(tasks) = entities.get_component::<scheduled::ScheduledTasks>(Container::WORLD) {
+        if !tasks.is_empty() {
+            out.write_record(&SaveData::ScheduledTasks(tasks.save()))?;
         }
     }
+
+    if let Some(finances) = entities.get_component::<player::RoomFinances>(Container::WORLD) {
+        let rooms: Vec<_> = finances.save().into_iter()
+            .filter(|v| level.try_room_info(v.0).is_some())
+            .collect();
+        if !rooms.is_empty() {
+            out.write_record(&SaveData::RoomFinances(rooms))?;
+        }
+    }
+
+    if !level.scatter.strokes().is_empty() {
+        out.write_record(&SaveData::Scatter(level.scatter.strokes().to_vec()))?;
+    }
     Ok(())
 }
 
@@ -505,6 +715,10 @@ fn init_players(
             #[cfg(not(feature = "steam"))]
             PlayerKey::Username(name) => player::PlayerKey::Username(name),
         };
+        let taken: Vec<u8> = players.values()
+            .filter(|v| v.uid != id)
+            .map(|v| v.profile.color)
+            .collect();
         let info = players.entry(id).or_insert_with(|| crate::player::PlayerInfo::new(key,name, id, &staff_list));
         info.money = player.money;
         info.rating = player.rating;
@@ -528,6 +742,15 @@ fn init_players(
             },
         };
         info.config = player.config;
+        if let Some(campus) = player.campus {
+            info.campus = campus;
+        }
+        if let Some(profile) = player.profile {
+            info.profile = profile;
+        } else {
+            info.profile.color = crate::player::free_color(&taken);
+        }
+        info.script_notifications.load(player.notifications);
         if let Some((level, snapshots, entities)) = world.as_mut() {
             info.courses.extend(player.courses.into_iter()
                 .map(|(id, v)| (id, v.to_course(snapshots))));
@@ -548,32 +771,7 @@ pub(crate) fn load_players<F: FileSystem>(
     players: &mut crate::PlayerInfoMap,
 ) -> UResult<()>
 {
-    let path = format!("{}.usav", name);
-    if !fs.exists(&path) {
-        return Err(ErrorKind::NoSuchSave.into());
-    }
-    let mut f = BufReader::new(fs.read(&path)?);
-    let version = f.read_u32::<LittleEndian>()?;
-    match version {
-        4 => {},
-        SAVE_VERSION => {},
-        _ => bail!("Invalid save version"),
-    }
-    if SaveType::from_u32(f.read_u32::<LittleEndian>()?) != Some(ty) {
-        bail!("Incorrect save type")
-    }
-    let len = f.read_i32::<LittleEndian>()?;
-    if len != -1 {
-        f.seek(SeekFrom::Current(i64::from(len)))?;
-    }
-
-    let spl = match version {
-        SAVE_VERSION => {
-            let mut sf = SaveStreamDecode::new(f);
-            sf.next().transpose()?
-        }
-        _ => unimplemented!(),
-    };
+    let spl = read_save(fs, name, ty)?.next().transpose()?;
 
     if let Some(SaveData::Players(sf_players)) = spl {
         let staff_list = load_staff_list(log, asset_manager);
@@ -599,29 +797,24 @@ pub(crate) fn load_game<F: FileSystem>(
     choices: &choice::Choices,
     running_choices: &mut script_room::RunningChoices,
     mission: Option<&mut mission::MissionController>,
+    templates: &RefCell<EntityTemplates>,
     day_tick: &mut DayTick,
 ) -> UResult<Level>
 {
-    let path = format!("{}.usav", name);
-    load_game_impl(fs, log, &*path, ty, players, asset_manager, entities, snapshots, engine, choices, running_choices, mission, day_tick)
+    let sf = read_save(fs, name, ty)?;
+    load_game_generic(log, sf, players, asset_manager, entities, snapshots, engine, choices, running_choices, mission, templates, day_tick)
 }
 
-fn load_game_impl<F: FileSystem>(
+/// Opens the named save returning its records. If the save
+/// has a diff the records are replayed from it.
+fn read_save<F: FileSystem>(
     fs: &F,
-    log: &Logger,
-    path: &str,
+    name: &str,
     ty: SaveType,
-    players: &mut crate::PlayerInfoMap,
-    asset_manager: &AssetManager, entities: &mut Container,
-    snapshots: &mut snapshot::Snapshots,
-    engine: &script::Engine,
-    choices: &choice::Choices,
-    running_choices: &mut script_room::RunningChoices,
-    mission: Option<&mut mission::MissionController>,
-    day_tick: &mut DayTick,
-) -> UResult<Level>
+) -> UResult<SaveRecords<BufReader<F::Reader>>>
 {
-    if !fs.exists(path) {
+    let path = format!("{}.usav", name);
+    if !fs.exists(&path) {
         return Err(ErrorKind::NoSuchSave.into());
     }
     let mut f = BufReader::new(fs.read(&path)?);
@@ -639,13 +832,39 @@ fn load_game_impl<F: FileSystem>(
         f.seek(SeekFrom::Current(i64::from(len)))?;
     }
 
-    match version {
-        SAVE_VERSION => {
-            let sf = SaveStreamDecode::new(f);
-            load_game_generic(log, sf, players, asset_manager, entities, snapshots, engine, choices, running_choices, mission, day_tick)
-        }
-        _ => unimplemented!(),
+    if version != SAVE_VERSION {
+        unimplemented!()
     }
+
+    let diff_path = format!("{}.udiff", name);
+    if !fs.exists(&diff_path) {
+        return Ok(SaveRecords::Full(SaveStreamDecode::new(f)));
+    }
+    let mut diff = BufReader::new(fs.read(&diff_path)?);
+    if diff.read_u32::<LittleEndian>()? != SAVE_VERSION
+        || SaveType::from_u32(diff.read_u32::<LittleEndian>()?) != Some(ty)
+    {
+        bail!("Invalid diff save")
+    }
+    let header = incremental::read_record::<_, DiffHeader>(&mut diff)
+        .ok_or_else(|| ErrorKind::Msg("Truncated diff save".into()))??;
+
+    let records_start = f.seek(SeekFrom::Current(0))?;
+    let file_len = f.seek(SeekFrom::End(0))?;
+    f.seek(SeekFrom::Start(records_start))?;
+    // A crash between writing a full save and removing the old
+    // diff can leave a diff for an older full save behind.
+    if file_len != header.base_len {
+        return Ok(SaveRecords::Full(SaveStreamDecode::new(f)));
+    }
+
+    let base = SaveStreamDecode::new(f)
+        .map(|v| v.map(Some))
+        .collect::<UResult<Vec<_>>>()?;
+    if base.len() != header.base_records as usize {
+        bail!("Diff save doesn't match the full save")
+    }
+    Ok(SaveRecords::Diff(DiffReplay::new(base, diff)))
 }
 
 fn load_game_generic(
@@ -658,6 +877,7 @@ fn load_game_generic(
     choices: &choice::Choices,
     running_choices: &mut script_room::RunningChoices,
     mission: Option<&mut mission::MissionController>,
+    templates: &RefCell<EntityTemplates>,
     day_tick: &mut DayTick,
 ) -> UResult<Level>
 {
@@ -669,6 +889,16 @@ fn load_game_generic(
 
     if let Some(SaveData::GameState(state)) = sf.next().transpose()? {
         *day_tick = state.day_tick;
+        if let Some(goals) = state.goals {
+            if let Some(goals) = Goals::load(log, asset_manager, goals) {
+                entities.add_component(Container::WORLD, goals);
+            } else {
+                warn!(log, "The goals of the save no longer exist");
+            }
+        }
+        if let Some(trades) = state.trades {
+            entities.add_component(Container::WORLD, player::Trades::load(trades));
+        }
     } else {
         bail!("Invalid save file layout - GameState");
     }
@@ -724,6 +954,9 @@ fn load_game_generic(
                         }
                     }
                 }
+                if let (Some(site), Some(construction)) = (state.construction, entities.get_component_mut::<crate::entity::construction::Construction>(Container::WORLD)) {
+                    construction.restore(id, site);
+                }
             },
             SaveData::Object(room_id, object) => {
                 level.begin_object_placement::<_, ServerEntityCreator>(room_id, engine, entities, object.key.borrow(), Some(object.version))?;
@@ -822,6 +1055,9 @@ fn load_game_generic(
                     paid.cost = p.cost;
                     paid.wanted_cost = ::std::cmp::max(p.wanted_cost, p.cost);
                 }
+                if let Some(seed) = entity.appearance {
+                    ty.set_appearance(entities, e, entity.variant, seed);
+                }
                 if let Some(tints) = entity.tints {
                     entities.add_component(e, Tints {
                         tints,
@@ -874,6 +1110,9 @@ fn load_game_generic(
                         money: UniDollar(10_000),
                     });
                 }
+                if let Some(visitor) = entity.visitor {
+                    entities.add_component(e, visitor);
+                }
             },
             SaveData::RoomScript(room_id, saved_state) => {
                 let ty = {
@@ -928,6 +1167,22 @@ fn load_game_generic(
                 })?;
                 mission_state = Some(state);
             },
+            SaveData::EntityTemplate(name, template) => {
+                templates.borrow_mut().insert(name, template);
+            },
+            SaveData::ScheduledTasks(tasks) => {
+                if let Some(scheduled) = entities.get_component_mut::<scheduled::ScheduledTasks>(Container::WORLD) {
+                    scheduled.restore(tasks);
+                }
+            },
+            SaveData::RoomFinances(rooms) => {
+                if let Some(finances) = entities.get_component_mut::<player::RoomFinances>(Container::WORLD) {
+                    finances.restore(rooms);
+                }
+            },
+            SaveData::Scatter(strokes) => {
+                level.scatter.load(log, strokes);
+            },
             _ => unimplemented!(),
         }
     }
@@ -956,6 +1211,24 @@ fn load_game_generic(
     Ok(level)
 }
 
+enum SaveRecords<R: IoRead> {
+    Full(SaveStreamDecode<R>),
+    Diff(DiffReplay<R>),
+}
+
+impl <R> Iterator for SaveRecords<R>
+    where R: IoRead
+{
+    type Item = UResult<SaveData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SaveRecords::Full(sf) => sf.next(),
+            SaveRecords::Diff(sf) => sf.next(),
+        }
+    }
+}
+
 struct SaveStreamDecode<R: IoRead> {
     r: R,
 }
@@ -999,11 +1272,19 @@ enum SaveData {
     Entity(EntityInfo),
     IdleScript(PlayerId, ResourceKey<'static>, Vec<u8>),
     MissionState(Vec<u8>),
+    EntityTemplate(String, EntityTemplate),
+    ScheduledTasks(Vec<scheduled::SavedTask>),
+    RoomFinances(Vec<(RoomId, Vec<player::RoomFinanceDay>)>),
+    Scatter(Vec<crate::level::scatter::ScatterStroke>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct GameState {
     day_tick: DayTick,
+    #[serde(default)]
+    goals: Option<GoalState>,
+    #[serde(default)]
+    trades: Option<player::TradesState>,
 }
 
 /// A player key is used to uniquely identify a player
@@ -1038,6 +1319,18 @@ pub(crate) struct PlayerInfo {
     current_income: UniDollar,
     current_outcome: UniDollar,
     courses: FNVMap<course::CourseId, SavableCourse>,
+    /// The identity of the player's university. Saves from
+    /// before campuses could be customized use the default
+    #[serde(default)]
+    campus: Option<CampusIdentity>,
+    /// How the player is shown to others. Saves from before
+    /// profiles existed pick a free colour when loaded
+    #[serde(default)]
+    profile: Option<PlayerProfile>,
+    /// Notifications posted by scripts that the player hasn't
+    /// acknowledged yet
+    #[serde(default)]
+    notifications: Vec<crate::notify::PendingNotification>,
 }
 
 /// Contains the state and related information for a player
@@ -1074,6 +1367,7 @@ pub(crate) struct EntityInfo {
     owned: Option<PlayerId>,
     paid: Option<PaidInfo>,
     tints: Option<Vec<(u8, u8, u8, u8)>>,
+    appearance: Option<u32>,
     vars: Option<VarsInfo>,
 
     timetable: Option<TimeTable>,
@@ -1086,6 +1380,7 @@ pub(crate) struct EntityInfo {
     goto_room: Option<GotoRoomInfo>,
 
     money: Option<MoneyInfo>,
+    visitor: Option<crate::entity::visitor::Visitor>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -1158,6 +1453,8 @@ pub struct RoomInfo {
 struct RoomEntityState {
     timetabled_visitors: Vec<Vec<Vec<u32>>>,
     active_staff: Option<u32>,
+    #[serde(default)]
+    construction: Option<crate::entity::construction::Site>,
 }
 
 /// Object placement information
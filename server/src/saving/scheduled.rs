@@ -0,0 +1,223 @@
+//! Tasks that run after a delay and survive saving and loading.
+//!
+//! Subsystems that need something to happen later (e.g. the next
+//! inspection or a mission's timed event) schedule a task instead
+//! of keeping their own countdown. Pending tasks are written to the
+//! save so reloading a save or restarting the server continues them
+//! where they were left instead of dropping them.
+//!
+//! Tasks are stored in their encoded form, each task type is found
+//! again via its `KIND` which the subsystem registers with the
+//! `TaskRegistry` when the game starts.
+
+use crate::prelude::*;
+use crate::mission;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_cbor;
+
+/// Registers components required by this module
+pub fn register_components(c: &mut Container) {
+    c.register_component::<ScheduledTasks>();
+}
+
+/// A task that runs once after a delay
+pub trait ScheduledTask: Serialize + DeserializeOwned + 'static {
+    /// The name used to find the task's type when loading a save.
+    ///
+    /// Must be unique and stay the same between versions
+    const KIND: &'static str;
+
+    /// Runs the task once its delay has passed
+    fn run(self, ctx: &mut TaskContext<'_>) -> UResult<()>;
+}
+
+/// The state of the game that tasks can access when they run
+pub struct TaskContext<'a> {
+    pub log: &'a Logger,
+    pub assets: &'a AssetManager,
+    pub level: &'a mut Level,
+    pub entities: &'a mut Container,
+    pub players: &'a mut crate::PlayerInfoMap,
+    pub mission: Option<&'a mut mission::MissionController>,
+    pub day: u32,
+}
+
+/// A task waiting to run in its encoded form
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SavedTask {
+    kind: String,
+    remaining: u32,
+    data: Vec<u8>,
+}
+
+/// Every task waiting to run.
+///
+/// Stored on the world entity
+#[derive(Default)]
+pub struct ScheduledTasks {
+    tasks: Vec<SavedTask>,
+}
+component!(ScheduledTasks => Map);
+
+impl ScheduledTasks {
+    /// Schedules the task to run after the number of ticks
+    pub fn schedule<T: ScheduledTask>(&mut self, delay: u32, task: &T) -> UResult<()> {
+        self.tasks.push(SavedTask {
+            kind: T::KIND.to_owned(),
+            remaining: delay,
+            data: serde_cbor::to_vec(task)?,
+        });
+        Ok(())
+    }
+
+    /// Returns the pending tasks of the type along with the
+    /// number of ticks left until they run
+    pub fn pending<T: ScheduledTask>(&self) -> impl Iterator<Item=(u32, T)> + '_ {
+        self.tasks.iter()
+            .filter(|v| v.kind == T::KIND)
+            .filter_map(|v| serde_cbor::from_slice(&v.data).ok().map(|t| (v.remaining, t)))
+    }
+
+    /// Returns the number of tasks waiting to run
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns whether no tasks are waiting to run
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Moves time on by a tick and returns the tasks that
+    /// are due in the order they were scheduled
+    pub(crate) fn tick(&mut self) -> Vec<SavedTask> {
+        let mut due = vec![];
+        let mut idx = 0;
+        while idx < self.tasks.len() {
+            let task = &mut self.tasks[idx];
+            task.remaining = task.remaining.saturating_sub(1);
+            if task.remaining == 0 {
+                due.push(self.tasks.remove(idx));
+            } else {
+                idx += 1;
+            }
+        }
+        due
+    }
+
+    /// Returns the pending tasks to be saved
+    pub(crate) fn save(&self) -> Vec<SavedTask> {
+        self.tasks.clone()
+    }
+
+    /// Replaces the pending tasks with ones from a save
+    pub(crate) fn restore(&mut self, tasks: Vec<SavedTask>) {
+        self.tasks = tasks;
+    }
+}
+
+type Runner = fn(&[u8], &mut TaskContext<'_>) -> UResult<()>;
+
+/// The types of tasks that can be run
+#[derive(Default)]
+pub struct TaskRegistry {
+    runners: FNVMap<&'static str, Runner>,
+}
+
+impl TaskRegistry {
+    /// Allows tasks of the type to be run
+    pub fn register<T: ScheduledTask>(&mut self) {
+        fn run<T: ScheduledTask>(data: &[u8], ctx: &mut TaskContext<'_>) -> UResult<()> {
+            let task: T = serde_cbor::from_slice(data)?;
+            task.run(ctx)
+        }
+        if self.runners.insert(T::KIND, run::<T>).is_some() {
+            panic!("Scheduled task {} registered twice", T::KIND);
+        }
+    }
+
+    /// Runs the task, failing if its type was never registered
+    pub(crate) fn run(&self, task: &SavedTask, ctx: &mut TaskContext<'_>) -> UResult<()> {
+        let runner = self.runners.get(task.kind.as_str())
+            .ok_or_else(|| ErrorKind::Msg(format!("Unknown scheduled task: {}", task.kind)))?;
+        runner(&task.data, ctx)
+    }
+}
+
+/// Runs every task that is due this tick
+pub(crate) fn run_tasks(registry: &TaskRegistry, ctx: &mut TaskContext<'_>) {
+    let due = if let Some(tasks) = ctx.entities.get_component_mut::<ScheduledTasks>(Container::WORLD) {
+        tasks.tick()
+    } else {
+        return;
+    };
+    for task in due {
+        if let Err(err) = registry.run(&task, ctx) {
+            warn!(ctx.log, "Failed to run scheduled task {}: {}", task.kind, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Ping {
+        id: u32,
+    }
+
+    impl ScheduledTask for Ping {
+        const KIND: &'static str = "test:ping";
+        fn run(self, _ctx: &mut TaskContext<'_>) -> UResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn runs_after_delay() {
+        let mut tasks = ScheduledTasks::default();
+        tasks.schedule(3, &Ping { id: 1 }).unwrap();
+        tasks.schedule(1, &Ping { id: 2 }).unwrap();
+        tasks.schedule(3, &Ping { id: 3 }).unwrap();
+        assert_eq!(tasks.pending::<Ping>().collect::<Vec<_>>(), vec![
+            (3, Ping { id: 1 }),
+            (1, Ping { id: 2 }),
+            (3, Ping { id: 3 }),
+        ]);
+
+        let due = tasks.tick();
+        assert_eq!(due.len(), 1);
+        assert_eq!(serde_cbor::from_slice::<Ping>(&due[0].data).unwrap(), Ping { id: 2 });
+        assert!(tasks.tick().is_empty());
+        let due: Vec<Ping> = tasks.tick().iter()
+            .map(|v| serde_cbor::from_slice(&v.data).unwrap())
+            .collect();
+        assert_eq!(due, vec![Ping { id: 1 }, Ping { id: 3 }]);
+        assert!(tasks.is_empty());
+    }
+
+    #[test]
+    fn zero_delay_runs_next_tick() {
+        let mut tasks = ScheduledTasks::default();
+        tasks.schedule(0, &Ping { id: 1 }).unwrap();
+        assert_eq!(tasks.tick().len(), 1);
+    }
+
+    #[test]
+    fn save_and_restore() {
+        let mut tasks = ScheduledTasks::default();
+        tasks.schedule(10, &Ping { id: 1 }).unwrap();
+        tasks.tick();
+        let saved = serde_cbor::to_vec(&tasks.save()).unwrap();
+
+        let mut loaded = ScheduledTasks::default();
+        loaded.restore(serde_cbor::from_slice(&saved).unwrap());
+        assert_eq!(loaded.pending::<Ping>().collect::<Vec<_>>(), vec![(9, Ping { id: 1 })]);
+        for _ in 0 .. 8 {
+            assert!(loaded.tick().is_empty());
+        }
+        assert_eq!(loaded.tick().len(), 1);
+    }
+}
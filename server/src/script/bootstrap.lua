@@ -51,7 +51,6 @@ safe_global_env = {
     tonumber = tonumber,
     tostring = tostring,
     type = type,
-    unpack = unpack,
     _VERSION = _VERSION,
     xpcall = xpcall,
     -- Lua Modules
@@ -95,9 +94,9 @@ safe_global_env = {
     table = lock_table {
         concat = table.concat,
         insert = table.insert,
-        maxn = table.maxn,
         remove = table.remove,
         sort = table.sort,
+        unpack = unpack,
     },
     -- Math is safe
     math = lock_table {
@@ -122,7 +121,6 @@ safe_global_env = {
         min = math.min,
         modf = math.modf,
         pi = math.pi,
-        pow = math.pow,
         rad = math.rad,
         random = math.random,
         randomseed = math.randomseed,
@@ -169,6 +167,37 @@ safe_global_env = {
         get_room_display_name = function(id)
             return level_get_room_display_name(id)
         end,
+        -- Calls func(kind, min_x, min_y, max_x, max_y, room) for every
+        -- change of the listed kinds made to the level. Returns an id
+        -- to pass to remove_observer
+        on_change = function(kinds, func)
+            return level_on_change(kinds, func)
+        end,
+        remove_observer = function(id)
+            level_remove_observer(id)
+        end,
+    },
+    -- Native utility library
+    vec2 = vec2_new,
+    vec3 = vec3_new,
+    coords = lock_table {
+        -- Returns the tile containing the world position
+        to_tile = coords_to_tile,
+        -- Returns the world position of the center of the tile
+        tile_center = coords_tile_center,
+        -- Returns the tile next to the tile in the direction
+        shift = coords_shift,
+    },
+    rng = lock_table {
+        -- Creates a generator that always produces the same
+        -- sequence for the seed
+        new = rng_new,
+    },
+    fmt = lock_table {
+        thousands = fmt_thousands,
+        money = fmt_money,
+        fixed = fmt_fixed,
+        percent = fmt_percent,
     },
     get_entity_by_id = get_entity_by_id,
     game_time = function() return global_time end,
@@ -234,8 +263,9 @@ safe_global_env = {
         get_players = function()
             return control_players
         end,
+        -- Returns a handle to the result of the command
         submit_command = function(cmd)
-            control_submit_command(cmd)
+            return control_submit_command(cmd)
         end,
         cmd = lock_table {
             exec_mission = function(data)
@@ -254,9 +284,85 @@ safe_global_env = {
         give_money = function(player, amount)
             return control_give_money(player, amount)
         end,
+        -- Rolls the named reward table for the player with an
+        -- rng from `rng_new`, giving them the rewards. Returns
+        -- the rewards rolled, unlocks and cosmetics are left for
+        -- the mission to apply
+        roll_rewards = function(player, table, rng)
+            return control_roll_rewards(player, table, rng)
+        end,
+        play_cutscene = function(cutscene)
+            return control_play_cutscene(cutscene)
+        end,
+        -- Visual effects played for every player, see the
+        -- `effects` module for the parameters of each
+        screen_shake = function(desc)
+            return control_play_effect("shake", desc or {})
+        end,
+        screen_flash = function(desc)
+            return control_play_effect("flash", desc or {})
+        end,
+        set_fog = function(desc)
+            return control_play_effect("fog", desc)
+        end,
+        confetti = function(desc)
+            return control_play_effect("confetti", desc)
+        end,
+        -- Calls the mission's `on_scheduled_event(name, data)`
+        -- after the delay in ticks. Survives saving and loading
+        schedule_event = function(delay, name, data)
+            return control_schedule_event(delay, name, data or {})
+        end,
     },
 }
 
+-- Functions removed from the api. Modules that declare an
+-- `api_version` older than `removed` in their meta.json still
+-- get these in their scope so they keep working, but the first
+-- use of each is reported as a deprecation warning
+local deprecated_api = {
+    {name = "unpack", func = unpack, removed = 2, replacement = "table.unpack"},
+    {lib = "table", name = "maxn", func = table.maxn, removed = 2, replacement = "the # operator"},
+    {lib = "math", name = "pow", func = math.pow, removed = 2, replacement = "the ^ operator"},
+}
+
+local function init_compat_scope(mod_name, scope)
+    local version = script_api_version(mod_name)
+    local libs = {}
+    for _, dep in ipairs(deprecated_api) do
+        if version < dep.removed then
+            local full_name = dep.name
+            if dep.lib then
+                full_name = dep.lib .. "." .. dep.name
+            end
+            local func = dep.func
+            local warned = false
+            local compat = function(...)
+                if not warned then
+                    warned = true
+                    script_deprecated(mod_name, full_name, dep.removed, dep.replacement)
+                end
+                return func(...)
+            end
+            if dep.lib then
+                libs[dep.lib] = libs[dep.lib] or {}
+                libs[dep.lib][dep.name] = compat
+            else
+                scope[dep.name] = compat
+            end
+        end
+    end
+    -- Replaces the library with one that has the deprecated
+    -- functions but falls back to the current version
+    for lib, funcs in pairs(libs) do
+        scope[lib] = setmetatable(funcs, {
+            __metatable = false,
+            __newindex = function() error("Immutable table") end,
+            __index = safe_global_env[lib],
+        })
+    end
+end
+
 missions = {}
 missions_by_name = {}
 
@@ -324,7 +430,7 @@ function get_module_scope(mod_name)
             end
             local loaded = scope._LOADED
             -- Bad attempt to prevent scripts leaving the `./scripts/`
-            -- folder. The asset loader (used by get_module_script) will
+            -- folder. The asset loader (used by load_module_script) will
             -- prevent it leaving the module's base folder however so
             -- this isn't a major issue.
             local lib = lib:gsub("/", ""):gsub("%.%.", "")
@@ -332,11 +438,7 @@ function get_module_scope(mod_name)
             if mod == nil then
                 -- Lua apparently uses `.` as a seperator
                 local lib_file = lib:gsub("%.", "/")
-                local file = get_module_script(mod_name, "scripts/" .. lib_file .. ".lua")
-                local script, err = loadstring(file, mod_name .. ":" .. lib_file .. ".lua")
-                if script == nil then
-                    error(err)
-                end
+                local script = load_module_script(mod_name, "scripts/" .. lib_file .. ".lua")
                 local scope = get_module_scope(mod_name)
                 -- Give the lib its own scope but have it share the global module scope
                 local inner_scope = setmetatable({}, {
@@ -351,6 +453,7 @@ function get_module_scope(mod_name)
             return mod
         end
         init_base_scope(mod_name, scope)
+        init_compat_scope(mod_name, scope)
         init_module_scope(mod_name, scope)
         setmetatable(scope, {
             __metatable = false,
@@ -437,11 +540,10 @@ end
 --
 -- Returns whether the module loaded successfully or not
 function load_module(mod_name)
-    local status, init_file = xpcall(get_module_script, gen_stack, mod_name, "scripts/init.lua")
+    local status, init_script = xpcall(load_module_script, gen_stack, mod_name, "scripts/init.lua")
     if not status then
-        error(init_file)
+        error(init_script)
     end
-    local init_script = loadstring(init_file, mod_name .. ":init.lua")
     local mod_scope = get_module_scope(mod_name)
     local inner_scope = setmetatable({}, {
         __metatable = false,
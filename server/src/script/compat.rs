@@ -0,0 +1,100 @@
+//! Versioning of the script api.
+//!
+//! Packs declare the version of the api they were written against
+//! as `api_version` in their `meta.json`. Functions removed from
+//! the api are kept in the scope of modules declaring an older
+//! version (see `deprecated_api` in `bootstrap.lua`) so old packs
+//! keep working, but the first use of each by a module is reported
+//! as a deprecation warning which the mod manager lists.
+
+use std::fmt;
+
+/// The current version of the script api
+pub const API_VERSION: u32 = 2;
+/// The version assumed for packs that don't declare one, these
+/// were written before the api was versioned
+pub const FIRST_API_VERSION: u32 = 1;
+
+/// Returns the api version to use for a pack given the
+/// version it declared
+pub fn resolve_version(declared: Option<u32>) -> u32 {
+    declared.unwrap_or(FIRST_API_VERSION)
+}
+
+/// A module using a function that was removed from the api
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecation {
+    /// The module using the function
+    pub module: String,
+    /// The name of the function including its library
+    /// e.g. `table.maxn`
+    pub function: String,
+    /// The api version the function was removed in
+    pub removed_in: u32,
+    /// What should be used instead
+    pub replacement: String,
+}
+
+impl fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} uses {} which was removed in api version {}, use {} instead",
+            self.module, self.function, self.removed_in, self.replacement)
+    }
+}
+
+/// The deprecation warnings raised since the game started
+#[derive(Debug, Default)]
+pub struct Deprecations {
+    warnings: Vec<Deprecation>,
+}
+
+impl Deprecations {
+    /// Records the warning, returning whether it is the first
+    /// time the module used the function
+    pub fn record(&mut self, warning: Deprecation) -> bool {
+        if self.warnings.iter().any(|v| v.module == warning.module && v.function == warning.function) {
+            return false;
+        }
+        self.warnings.push(warning);
+        true
+    }
+
+    /// Returns the warnings in the order they were raised
+    pub fn warnings(&self) -> &[Deprecation] {
+        &self.warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warning(module: &str, function: &str) -> Deprecation {
+        Deprecation {
+            module: module.to_owned(),
+            function: function.to_owned(),
+            removed_in: 2,
+            replacement: "table.unpack".to_owned(),
+        }
+    }
+
+    #[test]
+    fn undeclared_is_first_version() {
+        assert_eq!(resolve_version(None), FIRST_API_VERSION);
+        assert_eq!(resolve_version(Some(API_VERSION)), API_VERSION);
+    }
+
+    #[test]
+    fn records_once_per_module() {
+        let mut deprecations = Deprecations::default();
+        assert!(deprecations.record(warning("old_mod", "unpack")));
+        assert!(!deprecations.record(warning("old_mod", "unpack")));
+        assert!(deprecations.record(warning("old_mod", "math.pow")));
+        assert!(deprecations.record(warning("other_mod", "unpack")));
+        assert_eq!(deprecations.warnings().len(), 3);
+        assert_eq!(
+            deprecations.warnings()[0].to_string(),
+            "old_mod uses unpack which was removed in api version 2, use table.unpack instead"
+        );
+    }
+}
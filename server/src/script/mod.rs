@@ -8,7 +8,8 @@ use crate::errors;
 use crate::util::{FNVMap, FNVSet};
 use std::time::SystemTime;
 use std::ops::Deref;
-use std::cell::RefCell;
+use std::cell::{RefCell, Cell};
+use std::rc::Rc;
 use std::sync::Arc;
 use std::io::Read;
 use crate::prelude::*;
@@ -16,6 +17,14 @@ use crate::ecs;
 
 pub use crate::script_room::LuaObject;
 
+mod stdlib;
+mod precompile;
+pub mod compat;
+pub mod usage;
+
+pub use self::compat::{API_VERSION, Deprecation};
+pub use self::stdlib::ScriptRng;
+
 /// Script bootstrap code. Public so that the client can use it
 pub const SCRIPT_BOOTSTRAP: &str = include_str!("bootstrap.lua");
 
@@ -24,6 +33,12 @@ const SERVER_SCRIPT_BOOTSTRAP: &str = include_str!("server_bootstrap.lua");
 /// The registry key used to obtain the list of watched files
 const WATCHED_FILES: &str = "watched_files";
 
+/// The pause used for the collector. Higher than lua's default
+/// as `Engine::step_gc` normally starts each cycle.
+const GC_PAUSE: i32 = 300;
+/// The minimum amount of collection work done per tick in kilobytes
+const GC_MIN_STEP_KB: usize = 16;
+
 /// Contains a list of scripts loaded
 struct WatchedFiles {
     next_reload: i32,
@@ -41,26 +56,83 @@ pub fn init_unilib(log: Logger, asset_manager: assets::AssetManager, lua: &lua::
         info!(log, "{}", msg; "module" => %m)
     ));
 
+    // The api version each module was written against, used by the
+    // bootstrap to keep deprecated functions for older modules
+    let assets = asset_manager.clone();
+    lua.set(Scope::Global, "script_api_version", lua::closure1(move |_, m: lua::Ref<String>| -> i32 {
+        compat::resolve_version(assets.api_version(assets::ModuleKey::new(&*m))) as i32
+    }));
+    let assets = asset_manager.clone();
+    let log1 = log.clone();
+    lua.set(Scope::Global, "script_deprecated", lua::closure4(move |_,
+        m: lua::Ref<String>, func: lua::Ref<String>,
+        removed_in: i32, replacement: lua::Ref<String>
+    | {
+        let warning = compat::Deprecation {
+            module: String::from(&*m),
+            function: String::from(&*func),
+            removed_in: removed_in as u32,
+            replacement: String::from(&*replacement),
+        };
+        if assets.report_deprecation(warning.clone()) {
+            warn!(log1, "Deprecated: {}", warning; "module" => %m);
+        }
+    }));
+
     // Use registry for storing a list of loaded files
-    lua.set(Scope::Registry, WATCHED_FILES, lua::Ref::new(lua, RefCell::new(WatchedFiles {
+    lua.set(Scope::Registry, WATCHED_FILES, lua::Ref::new_cell(lua, WatchedFiles {
         next_reload: 120,
         files: FNVMap::default(),
-    })));
+    }));
 
-    lua.set(Scope::Global, "get_module_script", lua::closure2(move |lua, m: lua::Ref<String>, ff: lua::Ref<String>| -> errors::Result<_> {
-        let mut f = asset_manager.open_from_pack(assets::ModuleKey::new(&*m), &ff)?;
-        let mut out = String::new();
-        f.read_to_string(&mut out)?;
+    lua.set(Scope::Registry, precompile::PRECOMPILED, lua::Ref::new_cell(lua, precompile::Precompiled::default()));
 
-        let watched_files: lua::Ref<RefCell<WatchedFiles>> = lua.get(Scope::Registry, WATCHED_FILES)?;
-        let mut watched_files = watched_files.borrow_mut();
-        let key = (m.to_string(), ff.to_string());
-        let time = asset_manager.modified_time(assets::ModuleKey::new(&*key.0), &*key.1);
-        watched_files.files.insert(key, time);
+    let assets = asset_manager.clone();
+    lua.set(Scope::Global, "get_module_script", lua::closure2(move |lua, m: lua::Ref<String>, ff: lua::Ref<String>| -> errors::Result<_> {
+        let out = read_module_script(lua, &assets, &m, &ff)?;
         Ok(lua::Ref::new_string(lua, out))
     }));
+    // Loads the script as a function without running it, using
+    // the precompiled version if there is one
+    lua.set(Scope::Global, "load_module_script", lua::closure2(move |lua, m: lua::Ref<String>, ff: lua::Ref<String>| -> errors::Result<_> {
+        let precompiled: lua::CellRef<precompile::Precompiled> = lua.get(Scope::Registry, precompile::PRECOMPILED)?;
+        let code = precompiled.borrow_mut().take(&m, &ff);
+        if let Some(code) = code {
+            watch_file(lua, &asset_manager, &m, &ff)?;
+            Ok(lua.load_bytecode(&code)?)
+        } else {
+            let out = read_module_script(lua, &asset_manager, &m, &ff)?;
+            Ok(lua.load_chunk(&precompile::chunk_name(&m, &ff), out.as_bytes())?)
+        }
+    }));
 
     init_serialize(lua);
+    stdlib::init_stdlib(lua);
+}
+
+/// Compiles the scripts of every loaded pack on worker threads
+/// so that `init_pack` doesn't have to.
+///
+/// Should be called before the packs are initialized
+pub fn precompile_packs(log: &Logger, lua: &lua::Lua, asset_manager: &assets::AssetManager) {
+    let compiled = precompile::compile_packs(log, asset_manager, &asset_manager.get_packs());
+    lua.set(Scope::Registry, precompile::PRECOMPILED, lua::Ref::new_cell(lua, compiled));
+}
+
+fn read_module_script(lua: &lua::Lua, asset_manager: &assets::AssetManager, m: &str, ff: &str) -> errors::Result<String> {
+    let mut f = asset_manager.open_from_pack(assets::ModuleKey::new(m), ff)?;
+    let mut out = String::new();
+    f.read_to_string(&mut out)?;
+    watch_file(lua, asset_manager, m, ff)?;
+    Ok(out)
+}
+
+fn watch_file(lua: &lua::Lua, asset_manager: &assets::AssetManager, m: &str, ff: &str) -> errors::Result<()> {
+    let watched_files: lua::CellRef<WatchedFiles> = lua.get(Scope::Registry, WATCHED_FILES)?;
+    let mut watched_files = watched_files.borrow_mut();
+    let time = asset_manager.modified_time(assets::ModuleKey::new(m), ff);
+    watched_files.files.insert((m.to_owned(), ff.to_owned()), time);
+    Ok(())
 }
 
 /// Adds __index and __newindex fields to the type
@@ -202,6 +274,10 @@ pub struct Engine {
     /// Raw access to the lua engine. Use with care
     pub lua: lua::Lua,
     log: Logger,
+    /// The memory in use after the last garbage collection step
+    gc_last_count: Rc<Cell<usize>>,
+    /// The time used by each pack's scripts
+    usage: Rc<RefCell<usage::ScriptUsage>>,
 }
 
 impl Deref for Engine {
@@ -217,7 +293,7 @@ impl Invokable for Engine {
 /// Handles files that need reloading
 pub fn handle_reloads<I: Invokable>(log: &Logger, engine: &I, asset_manager: &assets::AssetManager) {
     let to_reload = {
-        let watched_files: lua::Ref<RefCell<WatchedFiles>> = assume!(log, engine.get(Scope::Registry, WATCHED_FILES));
+        let watched_files: lua::CellRef<WatchedFiles> = assume!(log, engine.get(Scope::Registry, WATCHED_FILES));
         let mut watched_files = watched_files.borrow_mut();
         watched_files.next_reload -= 1;
         if watched_files.next_reload > 0 {
@@ -245,14 +321,36 @@ impl Engine {
         let log = log.new(o!(
             "lua" => true,
         ));
+        // Scripts run in the whitelisted environment set up by the
+        // bootstrap, which only needs `os` and `debug` for itself.
+        // Anything else that could escape it is never opened.
+        let lua = lua::Lua::new_sandboxed(lua::LibSet::SAFE | lua::LibSet::OS | lua::LibSet::DEBUG);
+        lua.remove_unsafe_globals();
         let engine = Engine {
-            lua: lua::Lua::new(),
+            lua,
             log: log.clone(),
+            gc_last_count: Rc::new(Cell::new(0)),
+            usage: Rc::new(RefCell::new(usage::ScriptUsage::default())),
         };
+        engine.lua.gc_set_pause(GC_PAUSE);
         init_unilib(log.clone(), asset_manager.clone(), &engine);
+        // Used by the server bootstrap to time calls into modules
+        let usage = engine.usage.clone();
+        engine.set(Scope::Global, "script_usage_enter", lua::closure1(move |_, m: lua::Ref<String>| {
+            usage.borrow_mut().enter(&m);
+        }));
+        let usage = engine.usage.clone();
+        engine.set(Scope::Global, "script_usage_leave", lua::closure(move |_| {
+            usage.borrow_mut().leave();
+        }));
         level::init_levellib::<crate::script_room::Types>(&engine);
         crate::mission::init_missionlib(&engine);
         crate::mission::init_commandlib(&engine);
+        crate::entity::template::init_templatelib(&engine);
+        crate::entity::appearance::init_appearancelib(&engine);
+        crate::player::init_campuslib(&engine);
+        crate::notify::init_notifylib(&engine);
+        crate::reward::init_rewardlib(&engine);
 
         engine.store_tracked::<Logger>(LuaLogger(log.clone()));
         engine.store_tracked::<AssetManager>(asset_manager);
@@ -263,6 +361,46 @@ impl Engine {
         engine
     }
 
+    /// Performs a step of garbage collection sized by the memory
+    /// allocated since the last step.
+    ///
+    /// Called once a tick so that the collection work is spread
+    /// evenly instead of happening in bursts when lua decides to.
+    pub fn step_gc(&self) {
+        let count = self.lua.gc_count();
+        let allocated = count.saturating_sub(self.gc_last_count.get()) / 1024;
+        self.lua.gc_step(allocated.max(GC_MIN_STEP_KB) as i32);
+        self.gc_last_count.set(self.lua.gc_count());
+    }
+
+    /// Ends the tick for the script usage accounting, returning
+    /// the packs that have been throttled or restored.
+    ///
+    /// See `usage::ScriptUsage::end_tick`
+    pub fn end_usage_tick(&self) -> Vec<usage::UsageChange> {
+        self.usage.borrow_mut().end_tick()
+    }
+
+    /// Returns whether the pack's non-critical callbacks should
+    /// be run this tick. False for most ticks whilst the pack is
+    /// throttled for using too much time
+    pub fn allow_optional(&self, pack: &str) -> bool {
+        self.usage.borrow().allow_optional(pack)
+    }
+
+    /// Returns the average time each pack's scripts used per tick
+    /// and whether the pack is throttled
+    pub fn pack_usage(&self) -> Vec<(String, std::time::Duration, bool)> {
+        self.usage.borrow().averages()
+    }
+
+    /// Compiles the scripts of every loaded pack on worker threads.
+    ///
+    /// See `precompile_packs`
+    pub fn precompile_packs(&self, asset_manager: &assets::AssetManager) {
+        precompile_packs(&self.log, &self.lua, asset_manager);
+    }
+
     /// Loads and inits the named pack's scripts.
     ///
     /// Currently panics when it fails to load
@@ -0,0 +1,81 @@
+//! Compiles the scripts of packs on worker threads.
+//!
+//! Parsing every script on the main thread stalls loading when
+//! many packs are installed. Instead the scripts of every pack
+//! are compiled to bytecode in parallel before the packs are
+//! initialized and each is installed into the lua state when it
+//! is first required.
+
+use std::io::Read;
+use rayon::prelude::*;
+
+use lua;
+use crate::assets;
+use crate::prelude::*;
+
+/// The registry key used to obtain the precompiled scripts
+pub(super) const PRECOMPILED: &str = "precompiled_scripts";
+
+/// Bytecode of scripts waiting to be required
+#[derive(Default)]
+pub(super) struct Precompiled {
+    /// Scripts by module and file name
+    scripts: FNVMap<(String, String), lua::Bytecode>,
+}
+impl lua::LuaUsable for Precompiled {}
+
+impl Precompiled {
+    /// Removes and returns the bytecode for the named script.
+    ///
+    /// Removed so that reloading the script compiles its
+    /// current source instead
+    pub(super) fn take(&mut self, module: &str, file: &str) -> Option<lua::Bytecode> {
+        self.scripts.remove(&(module.to_owned(), file.to_owned()))
+    }
+}
+
+/// Returns the name given to the script's chunk, used by lua
+/// in errors and stack traces
+pub(super) fn chunk_name(module: &str, file: &str) -> String {
+    format!("{}:{}", module, file.trim_start_matches("scripts/"))
+}
+
+/// Compiles every script of the passed packs in parallel.
+///
+/// Scripts that fail to compile are skipped, the error is reported
+/// when the pack requires the script instead
+pub(super) fn compile_packs(log: &Logger, assets: &AssetManager, packs: &[ModuleKey<'static>]) -> Precompiled {
+    let start = Instant::now();
+    let files: Vec<(String, String)> = packs.iter()
+        .flat_map(|pack| assets.list_files(pack.borrow(), "scripts")
+            .into_iter()
+            .filter(|v| v.ends_with(".lua"))
+            .map(move |v| (pack.module().to_owned(), v)))
+        .collect();
+
+    let scripts: FNVMap<_, _> = files.into_par_iter()
+        .filter_map(|(module, file)| {
+            let mut source = String::new();
+            assets.open_from_pack(assets::ModuleKey::new(&*module), &file).ok()?
+                .read_to_string(&mut source).ok()?;
+            let code = lua::Bytecode::compile(&chunk_name(&module, &file), &source).ok()?;
+            Some(((module, file), code))
+        })
+        .collect();
+
+    info!(log, "Precompiled {} scripts", scripts.len(); "time" => ?start.elapsed());
+    Precompiled {
+        scripts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_names() {
+        assert_eq!(chunk_name("base", "scripts/init.lua"), "base:init.lua");
+        assert_eq!(chunk_name("base", "scripts/rooms/office.lua"), "base:rooms/office.lua");
+    }
+}
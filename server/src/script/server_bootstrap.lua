@@ -1,8 +1,66 @@
 safe_global_env.is_client = false
 
-function init_module_scope(mod_name, scope)
+-- Submits a command to be validated and executed like a command
+-- from the network. `cmd` can either be a command or a table
+-- describing one. Returns a handle with `done`, `ok` and `error`
+-- fields that are filled once the command has been executed.
+safe_global_env.submit_command = function(player, cmd)
+    return submit_command(player, cmd)
+end
+
+-- Named entity templates. Entities captured into a template
+-- can have copies spawned later and are kept with the save.
+safe_global_env.templates = lock_table {
+    -- Captures the entity with the given id into the named template
+    capture = function(name, id)
+        template_capture(name, id)
+    end,
+    -- Spawns a copy of the named template at the location.
+    -- Optionally the player that owns the new entity can be
+    -- overridden.
+    spawn_from_template = function(name, x, y, player)
+        return template_spawn(name, x, y, player)
+    end,
+    exists = function(name)
+        return template_exists(name)
+    end,
+    remove = function(name)
+        return template_remove(name)
+    end,
+}
 
+-- Times every call into a module so that the time can be
+-- charged to the module's pack
+local function leave_usage(ok, ...)
+    script_usage_leave()
+    if not ok then
+        error((...), 0)
+    end
+    return ...
+end
+
+local raw_invoke_module_method = invoke_module_method
+function invoke_module_method(module, ...)
+    script_usage_enter(module)
+    return leave_usage(pcall(raw_invoke_module_method, module, ...))
+end
+
+local raw_invoke_free_roam = invoke_free_roam
+function invoke_free_roam(module, ...)
+    script_usage_enter(module)
+    return leave_usage(pcall(raw_invoke_free_roam, module, ...))
+end
+
+function init_module_scope(mod_name, scope)
+    -- Custom notifications, see the `notify` module for the
+    -- format of `desc`. Returns the id of the notification or
+    -- nil if the module has posted too many recently
+    scope.notify = lock_table {
+        post = function(player, desc)
+            return notify_post(mod_name, player, desc)
+        end,
+    }
 end
 
 function clear_module_state(mod_name)
-end
\ No newline at end of file
+end
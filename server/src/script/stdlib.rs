@@ -0,0 +1,250 @@
+//! Utility types and functions provided to every script environment.
+//!
+//! These are implemented natively instead of leaving each pack to
+//! implement its own (slower) versions in lua. The bootstrap script
+//! exposes them to scripts as the `vec2`, `vec3`, `coords`, `rng`
+//! and `fmt` libraries.
+
+use lua::{self, Scope, Ref, Unknown, Operator, TypeBuilder};
+use crate::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+macro_rules! vector_type {
+    ($name:ident, $lua_name:expr, $($field:ident),+) => (
+        /// An immutable vector usable from lua
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub struct $name {
+            $(
+                #[allow(missing_docs)]
+                pub $field: f64,
+            )+
+        }
+
+        impl $name {
+            fn map<F: Fn(f64) -> f64>(self, f: F) -> $name {
+                $name {
+                    $($field: f(self.$field),)+
+                }
+            }
+
+            fn zip<F: Fn(f64, f64) -> f64>(self, other: $name, f: F) -> $name {
+                $name {
+                    $($field: f(self.$field, other.$field),)+
+                }
+            }
+
+            fn dot(self, other: $name) -> f64 {
+                0.0 $(+ self.$field * other.$field)+
+            }
+
+            fn length(self) -> f64 {
+                self.dot(self).sqrt()
+            }
+
+            fn normalized(self) -> $name {
+                let len = self.length();
+                if len == 0.0 {
+                    self
+                } else {
+                    self.map(|v| v / len)
+                }
+            }
+
+            /// Applies the operation to each component. Either side may
+            /// be a number instead of a vector.
+            fn scale(lua: &lua::Lua, a: Ref<Unknown>, b: Ref<Unknown>, op: fn(f64, f64) -> f64) -> Result<Ref<$name>, lua::Error> {
+                let val = match (a.try_convert::<Ref<$name>>(), b.try_convert::<Ref<$name>>()) {
+                    (Ok(a), Ok(b)) => a.zip(*b, op),
+                    (Ok(a), Err(_)) => {
+                        let b = b.try_convert::<f64>()?;
+                        a.map(|a| op(a, b))
+                    },
+                    (Err(_), Ok(b)) => {
+                        let a = a.try_convert::<f64>()?;
+                        b.map(|b| op(a, b))
+                    },
+                    (Err(err), Err(_)) => return Err(err),
+                };
+                Ok(Ref::new(lua, val))
+            }
+        }
+
+        impl lua::LuaUsable for $name {
+            fn fields(t: &TypeBuilder) {
+                $(
+                    t.field(concat!("get_", stringify!($field)), lua::closure1(|_, v: Ref<$name>| v.$field));
+                )+
+                t.field("unpack", lua::closure1(|_, v: Ref<$name>| ($(v.$field),+)));
+                t.field("length", lua::closure1(|_, v: Ref<$name>| v.length()));
+                t.field("length_squared", lua::closure1(|_, v: Ref<$name>| v.dot(*v)));
+                t.field("normalized", lua::closure1(|lua, v: Ref<$name>| Ref::new(lua, v.normalized())));
+                t.field("dot", lua::closure2(|_, a: Ref<$name>, b: Ref<$name>| a.dot(*b)));
+                t.field("distance", lua::closure2(|_, a: Ref<$name>, b: Ref<$name>| a.zip(*b, |a, b| a - b).length()));
+                t.field("lerp", lua::closure3(|lua, a: Ref<$name>, b: Ref<$name>, delta: f64| {
+                    Ref::new(lua, a.zip(*b, |a, b| a + (b - a) * delta))
+                }));
+            }
+
+            fn metatable(t: &TypeBuilder) {
+                super::support_getters_setters(t);
+                t.operator(Operator::Add, lua::closure2(|lua, a: Ref<$name>, b: Ref<$name>| Ref::new(lua, a.zip(*b, |a, b| a + b))));
+                t.operator(Operator::Sub, lua::closure2(|lua, a: Ref<$name>, b: Ref<$name>| Ref::new(lua, a.zip(*b, |a, b| a - b))));
+                t.operator(Operator::Mul, lua::closure2(|lua, a: Ref<Unknown>, b: Ref<Unknown>| $name::scale(lua, a, b, |a, b| a * b)));
+                t.operator(Operator::Div, lua::closure2(|lua, a: Ref<Unknown>, b: Ref<Unknown>| $name::scale(lua, a, b, |a, b| a / b)));
+                t.operator(Operator::Unm, lua::closure2(|lua, a: Ref<$name>, _: Ref<$name>| Ref::new(lua, a.map(|v| -v))));
+                t.operator(Operator::Eq, lua::closure2(|_, a: Ref<$name>, b: Ref<$name>| *a == *b));
+                t.operator(Operator::ToString, lua::closure1(|lua, v: Ref<$name>| {
+                    let parts: &[f64] = &[$(v.$field),+];
+                    let parts = parts.iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<_>>();
+                    Ref::new_string(lua, format!("{}({})", $lua_name, parts.join(", ")))
+                }));
+            }
+        }
+    )
+}
+
+vector_type!(Vec2, "vec2", x, y);
+vector_type!(Vec3, "vec3", x, y, z);
+
+/// A random number generator that produces the same
+/// sequence for a given seed
+#[derive(lua::LuaBind)]
+#[lua(cell, methods)]
+pub struct ScriptRng {
+    rng: StdRng,
+}
+
+#[lua::lua_methods(cell)]
+impl ScriptRng {
+    /// Returns a random number between 0.0 and 1.0
+    #[lua]
+    fn next(&mut self) -> f64 {
+        self.rng.gen::<f64>()
+    }
+
+    /// Returns a random whole number between min and max inclusive
+    #[lua]
+    fn range(&mut self, min: i32, max: i32) -> Result<i32, lua::Error> {
+        if min > max {
+            return Err(lua::Error::Raw { msg: "min must not be greater than max".into() });
+        }
+        Ok(self.rng.gen_range(min, max + 1))
+    }
+
+    /// Returns a random number between min and max
+    #[lua]
+    fn float(&mut self, min: f64, max: f64) -> f64 {
+        min + self.rng.gen::<f64>() * (max - min)
+    }
+
+    /// Returns true with the passed probability
+    #[lua]
+    fn chance(&mut self, chance: f64) -> bool {
+        self.rng.gen::<f64>() < chance
+    }
+}
+
+impl ScriptRng {
+    /// Returns the generator for use outside of scripts
+    pub(crate) fn rng_mut(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+}
+
+/// Formats the number with a separator between each
+/// group of thousands
+fn format_thousands(val: i64) -> String {
+    let digits = val.abs().to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+    if val < 0 {
+        out.push('-');
+    }
+    for (idx, c) in digits.chars().enumerate() {
+        if idx != 0 && (digits.len() - idx) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Adds the utility library to the passed state
+pub fn init_stdlib(lua: &lua::Lua) {
+    lua.set(Scope::Global, "vec2_new", lua::closure2(|lua, x: f64, y: f64| Ref::new(lua, Vec2 {x, y})));
+    lua.set(Scope::Global, "vec3_new", lua::closure3(|lua, x: f64, y: f64, z: f64| Ref::new(lua, Vec3 {x, y, z})));
+
+    // Coordinates, matching `Location`
+    lua.set(Scope::Global, "coords_to_tile", lua::closure2(|_, x: f64, y: f64| {
+        (x.floor() as i32, y.floor() as i32)
+    }));
+    lua.set(Scope::Global, "coords_tile_center", lua::closure2(|_, x: i32, y: i32| {
+        (f64::from(x) + 0.5, f64::from(y) + 0.5)
+    }));
+    lua.set(Scope::Global, "coords_shift", lua::closure3(|_, x: i32, y: i32, dir: Ref<String>| -> Result<(i32, i32), String> {
+        let loc = Location::new(x, y).shift(Direction::from_str(&dir)?);
+        Ok((loc.x, loc.y))
+    }));
+
+    lua.set(Scope::Global, "rng_new", lua::closure1(|lua, seed: f64| {
+        Ref::new_cell(lua, ScriptRng {
+            rng: StdRng::seed_from_u64(seed as i64 as u64),
+        })
+    }));
+
+    lua.set(Scope::Global, "fmt_thousands", lua::closure1(|lua, val: f64| {
+        Ref::new_string(lua, format_thousands(val.round() as i64))
+    }));
+    lua.set(Scope::Global, "fmt_money", lua::closure1(|lua, val: f64| {
+        Ref::new_string(lua, UniDollar(val.round() as i64).to_string())
+    }));
+    lua.set(Scope::Global, "fmt_fixed", lua::closure2(|lua, val: f64, places: i32| {
+        Ref::new_string(lua, format!("{:.*}", places.max(0) as usize, val))
+    }));
+    lua.set(Scope::Global, "fmt_percent", lua::closure2(|lua, val: f64, places: i32| {
+        Ref::new_string(lua, format!("{:.*}%", places.max(0) as usize, val * 100.0))
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vectors() {
+        let lua = lua::Lua::new();
+        init_stdlib(&lua);
+
+        let v = lua.execute_string::<Ref<Vec2>>("return (vec2_new(1, 2) + vec2_new(3, 4)) * 2").unwrap();
+        assert_eq!(*v, Vec2 {x: 8.0, y: 12.0});
+        let v = lua.execute_string::<Ref<Vec3>>("return 2 * -vec3_new(1, 2, 3) / vec3_new(1, 2, 4)").unwrap();
+        assert_eq!(*v, Vec3 {x: -2.0, y: -2.0, z: -1.5});
+        assert!(lua.execute_string::<bool>("return vec2_new(1, 2) == vec2_new(1, 2)").unwrap());
+        assert_eq!(lua.execute_string::<f64>("return vec2_new(3, 4):length()").unwrap(), 5.0);
+        assert_eq!(lua.execute_string::<f64>("return vec3_new(1, 2, 3).z").unwrap(), 3.0);
+        assert_eq!(&*lua.execute_string::<Ref<String>>("return tostring(vec2_new(1.5, 2))").unwrap(), "vec2(1.5, 2)");
+    }
+
+    #[test]
+    fn test_rng_deterministic() {
+        let lua = lua::Lua::new();
+        init_stdlib(&lua);
+
+        let (a, b) = lua.execute_string::<(i32, i32)>(r#"
+local a = rng_new(1234)
+local b = rng_new(1234)
+return a:range(0, 1000000), b:range(0, 1000000)
+"#).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_format_thousands() {
+        assert_eq!(format_thousands(0), "0");
+        assert_eq!(format_thousands(999), "999");
+        assert_eq!(format_thousands(1000), "1,000");
+        assert_eq!(format_thousands(-1_234_567), "-1,234,567");
+    }
+}
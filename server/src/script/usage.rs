@@ -0,0 +1,200 @@
+//! Accounting of the time spent running each pack's scripts.
+//!
+//! Every call from the server into a module's script is timed and
+//! charged to the module's pack. Time spent in a nested call into
+//! another pack is only charged to the inner pack. Packs that keep
+//! going over their budget have their non-critical callbacks
+//! throttled until they settle down again.
+
+use crate::prelude::*;
+use crate::metrics::REPORT_INTERVAL;
+use std::time::{Duration, Instant};
+
+/// The time a single pack's scripts may take each tick on
+/// average before it counts as over budget
+pub const PACK_BUDGET: Duration = Duration::from_millis(5);
+/// The number of reporting windows in a row a pack has to be
+/// over budget before it is throttled
+const THROTTLE_AFTER: u32 = 5;
+/// The number of reporting windows in a row a throttled pack
+/// has to stay under half its budget before it is restored
+const RESTORE_AFTER: u32 = 10;
+/// Throttled packs only have their non-critical callbacks run
+/// once every this many ticks
+const THROTTLED_INTERVAL: u64 = 4;
+
+/// The time used by each pack's scripts
+#[derive(Default)]
+pub struct ScriptUsage {
+    /// The packs currently being run, innermost last, with the
+    /// time they started being charged
+    running: Vec<(String, Instant)>,
+    packs: FNVMap<String, PackUsage>,
+    window_ticks: u32,
+    tick: u64,
+}
+
+#[derive(Default)]
+struct PackUsage {
+    window: Duration,
+    average: Duration,
+    over_windows: u32,
+    under_windows: u32,
+    throttled: bool,
+}
+
+/// A change to whether a pack is throttled
+#[derive(Debug, PartialEq)]
+pub enum UsageChange {
+    /// The pack kept going over budget and has been throttled,
+    /// contains the average time it used each tick
+    Throttled(String, Duration),
+    /// The pack is back under budget
+    Restored(String),
+}
+
+impl ScriptUsage {
+    /// Starts charging time to the pack until the matching `leave`
+    pub fn enter(&mut self, pack: &str) {
+        let now = Instant::now();
+        self.charge_running(now);
+        self.running.push((pack.to_owned(), now));
+    }
+
+    /// Stops charging time to the pack most recently entered
+    pub fn leave(&mut self) {
+        let now = Instant::now();
+        self.charge_running(now);
+        self.running.pop();
+        if let Some(outer) = self.running.last_mut() {
+            outer.1 = now;
+        }
+    }
+
+    fn charge_running(&mut self, now: Instant) {
+        if let Some((pack, start)) = self.running.last_mut() {
+            let time = now.duration_since(*start);
+            *start = now;
+            self.packs.entry(pack.clone())
+                .or_default()
+                .window += time;
+        }
+    }
+
+    #[cfg(test)]
+    fn charge(&mut self, pack: &str, time: Duration) {
+        self.packs.entry(pack.to_owned())
+            .or_default()
+            .window += time;
+    }
+
+    /// Ends the current tick, once every `REPORT_INTERVAL` ticks
+    /// the averages are updated and the packs whose throttling
+    /// changed are returned
+    pub fn end_tick(&mut self) -> Vec<UsageChange> {
+        self.tick = self.tick.wrapping_add(1);
+        self.window_ticks += 1;
+        if self.window_ticks < REPORT_INTERVAL {
+            return Vec::new();
+        }
+        let ticks = self.window_ticks;
+        self.window_ticks = 0;
+
+        let mut changes = Vec::new();
+        for (name, pack) in &mut self.packs {
+            pack.average = pack.window / ticks;
+            pack.window = Duration::from_secs(0);
+            if pack.average > PACK_BUDGET {
+                pack.over_windows += 1;
+                pack.under_windows = 0;
+            } else if pack.average < PACK_BUDGET / 2 {
+                pack.over_windows = 0;
+                pack.under_windows += 1;
+            } else {
+                pack.over_windows = 0;
+                pack.under_windows = 0;
+            }
+
+            if !pack.throttled && pack.over_windows >= THROTTLE_AFTER {
+                pack.throttled = true;
+                changes.push(UsageChange::Throttled(name.clone(), pack.average));
+            } else if pack.throttled && pack.under_windows >= RESTORE_AFTER {
+                pack.throttled = false;
+                changes.push(UsageChange::Restored(name.clone()));
+            }
+        }
+        changes
+    }
+
+    /// Returns whether the pack's non-critical callbacks
+    /// (e.g. idle choices) should be run this tick
+    pub fn allow_optional(&self, pack: &str) -> bool {
+        self.packs.get(pack)
+            .map_or(true, |v| !v.throttled || self.tick % THROTTLED_INTERVAL == 0)
+    }
+
+    /// Returns the average time each pack used per tick during
+    /// the last reporting window and whether it is throttled,
+    /// sorted by name
+    pub fn averages(&self) -> Vec<(String, Duration, bool)> {
+        let mut packs: Vec<_> = self.packs.iter()
+            .map(|(name, pack)| (name.clone(), pack.average, pack.throttled))
+            .collect();
+        packs.sort_by(|a, b| a.0.cmp(&b.0));
+        packs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_windows(usage: &mut ScriptUsage, pack: &str, per_tick: Duration, windows: u32) -> Vec<UsageChange> {
+        let mut changes = Vec::new();
+        for _ in 0 .. windows * REPORT_INTERVAL {
+            usage.charge(pack, per_tick);
+            changes.extend(usage.end_tick());
+        }
+        changes
+    }
+
+    #[test]
+    fn throttle_and_restore() {
+        let mut usage = ScriptUsage::default();
+        let slow = PACK_BUDGET * 2;
+        assert!(run_windows(&mut usage, "slow", slow, THROTTLE_AFTER - 1).is_empty());
+        assert!(usage.allow_optional("slow"));
+
+        assert_eq!(
+            run_windows(&mut usage, "slow", slow, 1),
+            vec![UsageChange::Throttled("slow".into(), slow)]
+        );
+        let allowed = (0 .. THROTTLED_INTERVAL)
+            .filter(|_| {
+                let allowed = usage.allow_optional("slow");
+                usage.end_tick();
+                allowed
+            })
+            .count();
+        assert_eq!(allowed, 1);
+        assert!(usage.allow_optional("other"));
+
+        // Close to the budget isn't enough to be restored
+        assert!(run_windows(&mut usage, "slow", PACK_BUDGET, RESTORE_AFTER).is_empty());
+        assert_eq!(
+            run_windows(&mut usage, "slow", Duration::from_millis(1), RESTORE_AFTER),
+            vec![UsageChange::Restored("slow".into())]
+        );
+        assert!(usage.allow_optional("slow"));
+    }
+
+    #[test]
+    fn brief_spikes_are_ignored() {
+        let mut usage = ScriptUsage::default();
+        for _ in 0 .. THROTTLE_AFTER * 2 {
+            assert!(run_windows(&mut usage, "spiky", PACK_BUDGET * 3, THROTTLE_AFTER - 1).is_empty());
+            assert!(run_windows(&mut usage, "spiky", Duration::from_millis(0), 1).is_empty());
+        }
+        assert_eq!(usage.averages(), vec![("spiky".into(), Duration::from_millis(0), false)]);
+    }
+}
@@ -194,13 +194,11 @@ impl lua::LuaUsable for LuaRoom {
             room.tile_update_state = Some(data);
             Ok(())
         }));
-        // Submits a command that will be executed by all clients and the server
+        // Submits a command that will be executed by all clients and the server.
+        //
+        // Returns a handle to the result of the command
         t.field("execute_command", lua::closure3(|lua, this: Ref<LuaRoom>, method: Ref<String>, data: Ref<Arc<bitio::Writer<Vec<u8>>>>| -> UResult<_> {
-            let commands = lua.get_tracked::<ExtraCommands>()
-                .ok_or_else(|| ErrorKind::InvalidState)?;
-            let mut commands = commands.borrow_mut();
-            commands.push(command::ExecRoom::new(this.id, method.to_string(), common::ScriptData(Arc::clone(&data))).into());
-            Ok(())
+            ScriptCommand::submit(lua, command::ExecRoom::new(this.id, method.to_string(), common::ScriptData(Arc::clone(&data))).into())
         }));
         // Attempts to find an entity with the required tag.
         // Returns nil if one can't be found otherwise it
@@ -276,13 +274,12 @@ impl lua::LuaUsable for LuaRoom {
                     object: idx,
                     _types: PhantomData,
                 })
-                .fold((Ref::new_table(lua), 1), |(tbl, idx), t| {
-                    tbl.insert(idx as i32, Ref::new(lua, t));
-                    (tbl, idx + 1)
-                })
-                .0;
+                .enumerate()
+                .map(|(idx, t)| (idx as i32 + 1, Ref::new(lua, t)));
+            let tbl = Ref::new_table(lua);
+            tbl.extend_from_iter(objects);
 
-            Ok(objects)
+            Ok(tbl)
         }));
         t.field("object_index", lua::closure2(|_lua, _this: Ref<LuaRoom>, o: Option<Ref<LuaObject<Types>>>| {
             o.map(|o| o.object as i32 + 1)
@@ -312,6 +309,47 @@ impl lua::LuaUsable for LuaRoom {
                 .ok_or(ErrorKind::StaleScriptReference)?;
             Ok(rc.capacity as i32)
         }));
+        // Returns the number of visitor slots that are free
+        //
+        // Slots reserved by entities heading to the room or held
+        // for timetabled students are not free
+        t.field("get_free_capacity", lua::closure1(|lua, this: Ref<LuaRoom>| -> UResult<i32> {
+            let entities = lua.read_borrow::<Container>();
+            let rooms = lua.get_tracked::<LevelRooms>()
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            let rooms = rooms.borrow();
+            let room = rooms.try_room_info(this.id)
+                .ok_or(ErrorKind::StaleScriptReference)?;
+            let rc = entities.get_component::<RoomController>(room.controller)
+                .ok_or(ErrorKind::StaleScriptReference)?;
+            Ok(rc.free_capacity() as i32)
+        }));
+        // Returns the number of visitor slots reserved by entities
+        // heading to the room
+        t.field("get_reserved", lua::closure1(|lua, this: Ref<LuaRoom>| -> UResult<i32> {
+            let entities = lua.read_borrow::<Container>();
+            let rooms = lua.get_tracked::<LevelRooms>()
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            let rooms = rooms.borrow();
+            let room = rooms.try_room_info(this.id)
+                .ok_or(ErrorKind::StaleScriptReference)?;
+            let rc = entities.get_component::<RoomController>(room.controller)
+                .ok_or(ErrorKind::StaleScriptReference)?;
+            Ok(rc.reservations.len() as i32)
+        }));
+        // Returns the number of entities queueing for space in
+        // the room
+        t.field("get_queued", lua::closure1(|lua, this: Ref<LuaRoom>| -> UResult<i32> {
+            let entities = lua.read_borrow::<Container>();
+            let rooms = lua.get_tracked::<LevelRooms>()
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            let rooms = rooms.borrow();
+            let room = rooms.try_room_info(this.id)
+                .ok_or(ErrorKind::StaleScriptReference)?;
+            let rc = entities.get_component::<RoomController>(room.controller)
+                .ok_or(ErrorKind::StaleScriptReference)?;
+            Ok(rc.reservations.queued() as i32)
+        }));
         // Returns whether the room has entities waiting to enter
         // the room
         t.field("get_has_waiting", lua::closure1(|lua, this: Ref<LuaRoom>| -> UResult<bool> {
@@ -683,6 +721,149 @@ impl lua::LuaUsable for LuaEntity {
             });
             Ok(())
         }));
+        // Instantly moves the entity to the location
+        // within the room without walking there
+        t.field("teleport_to", lua::closure3(|lua, this: Ref<LuaEntity>, mut x: f64, mut y: f64| -> UResult<()> {
+            let mut entities = lua.write_borrow::<Container>();
+            let rooms = lua.get_tracked::<LevelRooms>()
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            let rooms = rooms.borrow();
+            let bound = if let Some(Controller::Room(room_id)) = entities.get_component::<Controlled>(this.entity).and_then(|v| v.by) {
+                let room = rooms.get_room_info(room_id);
+                room.area
+            } else {
+                rooms.level_bounds
+            };
+            x += f64::from(bound.min.x);
+            y += f64::from(bound.min.y);
+            let loc = util::Location::new(x as i32, y as i32);
+            if !bound.in_bounds(loc) {
+                bail!("Can't teleport outside room {:#?} not in {:#?}. {:?}", loc, bound, entities.get_component::<Controlled>(this.entity));
+            }
+            entity::teleport(&mut entities, this.entity, x as f32, y as f32);
+            Ok(())
+        }));
+        // Plays the named animation trigger on one of the objects
+        // in the entity's room, returning whether the object has it
+        t.field("interact", lua::closure3(|lua, this: Ref<LuaEntity>, object_id: i32, name: Ref<String>| -> UResult<bool> {
+            let mut entities = lua.write_borrow::<Container>();
+            let assets = lua.get_tracked::<AssetManager>()
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            let rooms = lua.get_tracked::<LevelRooms>()
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            let rooms = rooms.borrow();
+            let room_id = if let Some(Controller::Room(room_id)) = entities.get_component::<Controlled>(this.entity).and_then(|v| v.by) {
+                room_id
+            } else {
+                bail!("Entity isn't in a room")
+            };
+            let room = rooms.try_room_info(room_id)
+                .ok_or(ErrorKind::StaleScriptReference)?;
+            let idx = (object_id - 1) as usize;
+            let key = room.objects.get(idx)
+                .and_then(|v| v.as_ref())
+                .map(|v| &v.0.key)
+                .ok_or(ErrorKind::StaleScriptReference)?;
+            let ty = assets.loader_open::<object::Loader>(key.borrow())?;
+            Ok(if let Some(trigger) = common::trigger_index(&ty.animation_triggers, &name) {
+                entity::interact(&mut entities, this.entity, room_id, idx, trigger);
+                true
+            } else {
+                false
+            })
+        }));
+        // Removes dirt from the tile the entity is standing on
+        // returning whether any is left
+        t.field("clean_floor", lua::closure2(|lua, this: Ref<LuaEntity>, amount: f64| -> UResult<bool> {
+            let mut entities = lua.write_borrow::<Container>();
+            let loc = if let Some(pos) = entities.get_component::<Position>(this.entity) {
+                util::Location::new(pos.x as i32, pos.z as i32)
+            } else {
+                bail!("Invalid entity")
+            };
+            let dirt = entities.get_component_mut::<entity::dirt::Dirt>(Container::WORLD)
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            Ok(dirt.clean(loc, amount as f32))
+        }));
+        // Returns the position of the closest tile with at
+        // least the given stage of dirt within the area the
+        // entity can walk in
+        t.field("nearest_dirt", lua::closure2(|lua, this: Ref<LuaEntity>, min_stage: i32| -> UResult<Option<(f64, f64)>> {
+            let entities = lua.read_borrow::<Container>();
+            let rooms = lua.get_tracked::<LevelRooms>()
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            let rooms = rooms.borrow();
+            let bound = if let Some(Controller::Room(room_id)) = entities.get_component::<Controlled>(this.entity).and_then(|v| v.by) {
+                let room = rooms.get_room_info(room_id);
+                room.area
+            } else {
+                rooms.level_bounds
+            };
+            let loc = if let Some(pos) = entities.get_component::<Position>(this.entity) {
+                util::Location::new(pos.x as i32, pos.z as i32)
+            } else {
+                bail!("Invalid entity")
+            };
+            let dirt = entities.get_component::<entity::dirt::Dirt>(Container::WORLD)
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            Ok(dirt.nearest(loc, min_stage.max(0).min(255) as u8, bound)
+                .map(|v| (
+                    f64::from(v.x - bound.min.x) + 0.5,
+                    f64::from(v.y - bound.min.y) + 0.5,
+                )))
+        }));
+        // Claims the closest litter or full bin within the area the
+        // entity can walk in that no other janitor is working on.
+        // Returns the kind of task ("litter" or "bin") and its position
+        t.field("claim_litter_task", lua::closure1(|lua, this: Ref<LuaEntity>| -> UResult<Option<(Ref<String>, f64, f64)>> {
+            let mut entities = lua.write_borrow::<Container>();
+            let rooms = lua.get_tracked::<LevelRooms>()
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            let rooms = rooms.borrow();
+            let bound = if let Some(Controller::Room(room_id)) = entities.get_component::<Controlled>(this.entity).and_then(|v| v.by) {
+                let room = rooms.get_room_info(room_id);
+                room.area
+            } else {
+                rooms.level_bounds
+            };
+            Ok(entity::litter::claim_task(&mut entities, this.entity, bound)
+                .map(|(task, loc)| (
+                    Ref::new_string(lua, match task {
+                        entity::litter::Task::Litter(..) => "litter",
+                        entity::litter::Task::EmptyBin(..) => "bin",
+                    }),
+                    f64::from(loc.x - bound.min.x) + 0.5,
+                    f64::from(loc.y - bound.min.y) + 0.5,
+                )))
+        }));
+        // Gives up the entity's claimed litter task so another
+        // janitor can take it
+        t.field("release_litter_task", lua::closure1(|lua, this: Ref<LuaEntity>| -> UResult<()> {
+            let mut entities = lua.write_borrow::<Container>();
+            let litter = entities.get_component_mut::<entity::litter::Litter>(Container::WORLD)
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            litter.release(this.entity);
+            Ok(())
+        }));
+        // Picks up a piece of litter from the tile the entity is
+        // standing on returning whether any is left
+        t.field("pick_up_litter", lua::closure1(|lua, this: Ref<LuaEntity>| -> UResult<bool> {
+            let mut entities = lua.write_borrow::<Container>();
+            let loc = if let Some(pos) = entities.get_component::<Position>(this.entity) {
+                util::Location::new(pos.x as i32, pos.z as i32)
+            } else {
+                bail!("Invalid entity")
+            };
+            let litter = entities.get_component_mut::<entity::litter::Litter>(Container::WORLD)
+                .ok_or_else(|| ErrorKind::InvalidState)?;
+            Ok(litter.pick_up(loc))
+        }));
+        // Empties the bin claimed by the entity if it is next to it
+        // returning whether it was emptied
+        t.field("empty_bin", lua::closure1(|lua, this: Ref<LuaEntity>| -> bool {
+            let mut entities = lua.write_borrow::<Container>();
+            entity::litter::empty_claimed_bin(&mut entities, this.entity)
+        }));
         // Returns the type key of this entity
         t.field("get_key", lua::closure1(|_lua, this: Ref<LuaEntity>| -> Ref<String> {
             this.key.clone()
@@ -714,6 +895,13 @@ impl lua::LuaUsable for LuaEntity {
                 None
             }
         }));
+        // Returns the seed this entity's appearance was generated
+        // from if it has one. Can be passed to `generate_character`
+        t.field("get_appearance_seed", lua::closure1(|lua, this: Ref<LuaEntity>| -> Option<i32> {
+            let entities = lua.read_borrow::<Container>();
+            entities.get_component::<entity::appearance::Appearance>(this.entity)
+                .map(|v| v.seed as i32)
+        }));
         // Returns the size of this entity
         t.field("get_size", lua::closure1(|lua, this: Ref<LuaEntity>| -> Option<(f64, f64)> {
             let entities = lua.read_borrow::<Container>();
@@ -911,9 +1099,30 @@ impl lua::LuaUsable for LuaEntity {
             let _ = service; // TODO: Log somewhere
             let mut players = lua.write_borrow::<PlayerInfoMap>();
             if let Some(owner) = entities.get_component::<Owned>(this.entity).map(|v| v.player_id) {
-                let player = assume!(log, players.get_mut(&owner));
                 let money = UniDollar(i64::from(money));
-                player.change_money(money);
+                // Income from co-funded rooms is shared with the players
+                // that funded it
+                let room = entities.get_component::<RoomOwned>(this.entity).map(|v| v.room_id);
+                let split = match (room, entities.get_component::<player::Trades>(Container::WORLD)) {
+                    (Some(room), Some(trades)) => trades.split_income(room, owner, money),
+                    _ => vec![(owner, money)],
+                };
+                for (id, amount) in split {
+                    if id == owner {
+                        let player = assume!(log, players.get_mut(&owner));
+                        player.change_money(amount);
+                    } else if let Some(player) = players.get_mut(&id) {
+                        player::Account::transfer(player, player::LedgerEntry {
+                            reason: player::LedgerReason::SharedIncome,
+                            amount,
+                            other: Some(owner),
+                            room_id: room,
+                        });
+                    }
+                }
+                if let Some(room) = room {
+                    player::record_room_money(&mut entities, room, player::FinanceKind::Sales, money);
+                }
                 entities.with(|
                     _em: EntityManager<'_>,
                     mut emotes: Write<IconEmote>
@@ -1013,7 +1222,7 @@ impl lua::LuaUsable for LuaEntity {
 
         // Charges the entity for a service
         t.field("give_money", lua::closure3(|lua, this: Ref<LuaEntity>, reason: Ref<String>, money: i32| -> UResult<_>{
-            let entities = lua.write_borrow::<Container>();
+            let mut entities = lua.write_borrow::<Container>();
             let log = lua.get_tracked::<Logger>()
                 .ok_or_else(|| ErrorKind::InvalidState)?;
             let _ = reason; // TODO: Log somewhere
@@ -1022,6 +1231,9 @@ impl lua::LuaUsable for LuaEntity {
                 let player = assume!(log, players.get_mut(&owner));
                 let money = UniDollar(i64::from(money));
                 player.change_money(money);
+                if let Some(room) = entities.get_component::<RoomOwned>(this.entity).map(|v| v.room_id) {
+                    player::record_room_money(&mut entities, room, player::FinanceKind::Lessons, money);
+                }
             }
             Ok(())
         }));
@@ -1042,37 +1254,67 @@ impl lua::LuaUsable for LuaEntity {
         }));
         // Adds a grade to the student's current lesson
         t.field("give_grade", lua::closure2(|lua, this: Ref<LuaEntity>, grade: Ref<String>| -> UResult<bool>{
-            let mut entities = lua.write_borrow::<Container>();
-            let log = lua.get_tracked::<Logger>()
-                .ok_or_else(|| ErrorKind::InvalidState)?;
-            let grade = match &*grade {
-                "A" => Grade::A,
-                "B" => Grade::B,
-                "C" => Grade::C,
-                "D" => Grade::D,
-                "E" => Grade::E,
-                "F" => Grade::F,
-                _ => bail!("Invalid grade"),
+            let grade = Grade::from_str(&grade)
+                .ok_or_else(|| ErrorKind::Msg("Invalid grade".into()))?;
+            record_grade(lua, this.entity, grade)
+        }));
+        // Adds a grade to the student's current lesson picked from
+        // the lesson's outcomes.
+        //
+        // The score (0 - 1) controls where in the distribution the
+        // grade is picked from. Returns the grade given.
+        t.field("give_lesson_grade", lua::closure2(|lua, this: Ref<LuaEntity>, score: f64| -> UResult<Ref<String>>{
+            let grade = {
+                let entities = lua.read_borrow::<Container>();
+                let players = lua.read_borrow::<crate::PlayerInfoMap>();
+                let (day, slot) = entities.get_component::<Activity>(this.entity)
+                    .map(|v| (v.day as usize, v.slot as usize))
+                    .ok_or_else(|| ErrorKind::Msg("Missing activity".into()))?;
+                let course = entities.get_component::<TimeTable>(this.entity)
+                    .map(|v| v.course)
+                    .ok_or_else(|| ErrorKind::Msg("Missing timetable".into()))?;
+                let key = entities.get_component::<Owned>(this.entity)
+                    .and_then(|v| players.get(&v.player_id))
+                    .and_then(|v| v.courses.get(&course))
+                    .and_then(|v| match &v.timetable[day][slot] {
+                        course::CourseEntry::Lesson{key, ..} => Some(key.clone()),
+                        course::CourseEntry::Free => None,
+                    })
+                    .ok_or_else(|| ErrorKind::Msg("Not in a lesson".into()))?;
+                let lm = entities.get_component::<course::LessonManager>(Container::WORLD)
+                    .ok_or_else(|| ErrorKind::InvalidState)?;
+                let lesson = lm.get(key.borrow())
+                    .ok_or_else(|| ErrorKind::Msg(format!("Unknown lesson {:?}", key)))?;
+                lesson.outcomes.pick(score as f32)
             };
-            let player = entities.get_component::<Owned>(this.entity).map(|v| v.player_id);
+            record_grade(lua, this.entity, grade)?;
+            Ok(Ref::new_string(lua, grade.as_str()))
+        }));
+    }
+}
 
-            if let Some(owner) = player {
-                let mut players = lua.write_borrow::<crate::PlayerInfoMap>();
-                let player = assume!(log, players.get_mut(&owner));
-                player.grades[grade.as_index()] += 1;
-            }
+/// Stores the grade for the student's current lesson
+fn record_grade(lua: &lua::Lua, e: Entity, grade: Grade) -> UResult<bool> {
+    let mut entities = lua.write_borrow::<Container>();
+    let log = lua.get_tracked::<Logger>()
+        .ok_or_else(|| ErrorKind::InvalidState)?;
+    let player = entities.get_component::<Owned>(e).map(|v| v.player_id);
+
+    if let Some(owner) = player {
+        let mut players = lua.write_borrow::<crate::PlayerInfoMap>();
+        let player = assume!(log, players.get_mut(&owner));
+        player.grades[grade.as_index()] += 1;
+    }
 
-            if let Some((day, slot)) = entities.get_component::<Activity>(this.entity).map(|v| (v.day, v.slot)) {
-                if let Some(grades) = entities.get_component_mut::<Grades>(this.entity) {
-                    grades.timetable_grades[day as usize][slot as usize] = Some(grade);
-                    return Ok(true);
-                } else {
-                    bail!("Missing grades")
-                }
-            } else {
-                bail!("Missing activity")
-            }
-        }));
+    if let Some((day, slot)) = entities.get_component::<Activity>(e).map(|v| (v.day, v.slot)) {
+        if let Some(grades) = entities.get_component_mut::<Grades>(e) {
+            grades.timetable_grades[day as usize][slot as usize] = Some(grade);
+            Ok(true)
+        } else {
+            bail!("Missing grades")
+        }
+    } else {
+        bail!("Missing activity")
     }
 }
 
@@ -1125,71 +1367,70 @@ impl RunningChoice {
     }
 }
 
+#[derive(lua::LuaBind)]
+#[lua(methods, metatable = "script::support_getters_setters")]
 pub(crate) struct IdleScriptHandle {
     player: PlayerId,
     idx: usize,
+    /// Property storage
+    #[lua(get, name = "properties")]
     props: Ref<Table>,
 }
 
-impl lua::LuaUsable for IdleScriptHandle {
-    fn metatable(t: &lua::TypeBuilder) {
-        script::support_getters_setters(t);
+#[lua::lua_methods]
+impl IdleScriptHandle {
+    /// Returns the rooms owned by the player running the script
+    #[lua]
+    fn get_rooms(&self, lua: &Lua) -> UResult<Ref<Table>> {
+        get_rooms_for_player::<Types>(lua, self.player)
     }
 
-    fn fields(t: &lua::TypeBuilder) {
-        // // Returns the room's id
-        t.field("get_rooms", lua::closure1(|lua, this: Ref<IdleScriptHandle>| {
-            get_rooms_for_player::<Types>(lua, this.player)
-        }));
-        // // Returns property storage
-        t.field("get_properties", lua::closure1(|_lua, this: Ref<IdleScriptHandle>| {
-            this.props.clone()
-        }));
-        // // Returns the room's id
-        t.field("get_room_by_id", lua::closure2(|lua, this: Ref<IdleScriptHandle>, id: i32| -> UResult<_> {
-            use crate::script::ScriptTypes;
-            let log = lua.get_tracked::<Logger>()
-                .ok_or_else(|| ErrorKind::InvalidState)?;
-            let rooms = lua.get_tracked::<LevelRooms>()
-                .ok_or_else(|| ErrorKind::InvalidState)?;
-            let rooms = rooms.borrow();
-            let mut entities = lua.write_borrow::<Container>();
+    /// Returns the room with the passed id if it is owned by
+    /// the player running the script
+    #[lua]
+    fn get_room_by_id(&self, lua: &Lua, id: i32) -> UResult<Ref<LuaRoom>> {
+        use crate::script::ScriptTypes;
+        let log = lua.get_tracked::<Logger>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let rooms = lua.get_tracked::<LevelRooms>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let rooms = rooms.borrow();
+        let mut entities = lua.write_borrow::<Container>();
 
-            let id = RoomId(id as i16);
+        let id = RoomId(id as i16);
 
-            entities.with(|
-                _em: EntityManager<'_>,
-                rc: ecs::Read<RoomController>,
-                mut entity_ref: ecs::Write<<Types as ScriptTypes>::EntityRef>,
-                mut room_ref: ecs::Write<<Types as ScriptTypes>::RoomRef>,
-                living: ecs::Read<Living>,
-                object: ecs::Read<Object>,
-            | {
-                if rooms.try_room_info(id).map_or(false, |v| v.owner == this.player) {
-                    Ok(Types::from_room(
-                        &log,
-                        lua,
-                        &rooms,
-                        &mut room_ref,
-                        &rc,
-                        &mut entity_ref,
-                        &living,
-                        &object,
-                        id
-                    ))
-                } else {
-                    bail!("Invalid room id")
-                }
-            })
-        }));
-        // Submits a command that will be executed by all clients and the server
-        t.field("execute_command", lua::closure3(|lua, this: Ref<IdleScriptHandle>, method: Ref<String>, data: Ref<Arc<bitio::Writer<Vec<u8>>>>| -> UResult<_> {
-            let commands = lua.get_tracked::<ExtraCommands>()
-                .ok_or_else(|| ErrorKind::InvalidState)?;
-            let mut commands = commands.borrow_mut();
-            commands.push(command::ExecIdle::new(this.player, this.idx, method.to_string(), common::ScriptData(Arc::clone(&data))).into());
-            Ok(())
-        }));
+        entities.with(|
+            _em: EntityManager<'_>,
+            rc: ecs::Read<RoomController>,
+            mut entity_ref: ecs::Write<<Types as ScriptTypes>::EntityRef>,
+            mut room_ref: ecs::Write<<Types as ScriptTypes>::RoomRef>,
+            living: ecs::Read<Living>,
+            object: ecs::Read<Object>,
+        | {
+            if rooms.try_room_info(id).map_or(false, |v| v.owner == self.player) {
+                Ok(Types::from_room(
+                    &log,
+                    lua,
+                    &rooms,
+                    &mut room_ref,
+                    &rc,
+                    &mut entity_ref,
+                    &living,
+                    &object,
+                    id
+                ))
+            } else {
+                bail!("Invalid room id")
+            }
+        })
+    }
+
+    /// Submits a command that will be executed by all clients and the server.
+    ///
+    /// Returns a handle to the result of the command
+    #[lua]
+    fn execute_command(&self, lua: &Lua, method: String, data: Ref<Arc<bitio::Writer<Vec<u8>>>>) -> UResult<Ref<CommandResult>> {
+        ScriptCommand::submit(lua, command::ExecIdle::new(self.player, self.idx, method, common::ScriptData(Arc::clone(&data))).into())
     }
 }
 
@@ -1256,7 +1497,12 @@ pub(super) fn tick_choices(
         mut idle: Write<Idle>,
         mut vars: Write<StudentVars>,
         mut controlled: Write<Controlled>,
+        simplified: Read<entity::lod::Simplified>,
+        lod: Read<entity::lod::Lod>,
     |{
+        // Simplified entities only pick new choices every so often
+        let simplified_choice = lod.get_component(Container::WORLD)
+            .map_or(true, |v| v.allows_choice());
         let mut to_remove = vec![];
         for (e, (idle, _living, owned, c)) in em.group_mask((&mut idle, &living, &owned, &mut controlled), |m| m.and(&vars)) {
             // A room owns this entity. Ask them to free it
@@ -1282,6 +1528,9 @@ pub(super) fn tick_choices(
             }
 
             if idle.current_choice.is_none() {
+                if !simplified_choice && simplified.get_component(e).is_some() {
+                    continue;
+                }
                 let vars = assume!(log, vars.get_custom(e));
                 let selected = choices.student_idle.choose(&mut rng, &choices.global.wrap(&vars));
                 if let Some((idx, _selected)) = selected {
@@ -1348,6 +1597,11 @@ pub(super) fn tick_choices(
                 error!(log, "Failed to load script"; "script" => ?script, "error" => %err);
             }
         }
+        // New entities are always added above but updating them
+        // can wait whilst the pack is throttled
+        if !scripting.allow_optional(script.script.module()) {
+            continue;
+        }
         if let Err(err) = scripting.with_borrows()
             .borrow_mut(entities)
             .borrow_mut(players)
@@ -1371,69 +1625,79 @@ pub(super) fn tick_rooms(
 ) {
     let asset_manager = level.asset_manager.clone();
 
-    for room in level.room_ids() {
-        let (needs_update, ty) = {
-            let mut room = level.get_room_info_mut(room);
-            let nu = room.needs_update;
-            room.needs_update = false;
-            if room.controller.is_invalid() {
-                continue;
+    // Pinned once for every room instead of creating and
+    // dropping registry references per call
+    scripting.scope(|s| {
+        let invoke = match s.function("invoke_module_method") {
+            Ok(val) => val,
+            Err(err) => {
+                error!(log, "Failed to tick rooms: {}", err);
+                return;
             }
-            if !room.state.is_done() {
-                entities.with(|
-                    _em: EntityManager<'_>,
-                    mut c: Write<Controlled>,
-                    mut gr: Write<GotoRoom>,
-                    mut props: Write<LuaRoomProperties>,
-                    mut rc: Write<RoomController>,
-                |{
-                    let rc = assume!(log, rc.get_component_mut(room.controller));
-                    for e in &rc.entities {
-                        let c = assume!(log, c.get_component_mut(*e));
-                        c.should_release = true;
-                    }
-                    for e in &rc.visitors {
-                        let c = assume!(log, c.get_component_mut(*e));
-                        c.should_release = true;
-                    }
-                    for e in rc.waiting_list.drain(..) {
-                        gr.remove_component(e);
+        };
+        let update = s.string("update");
+        let server = s.string("server");
+        for room in level.room_ids() {
+            let (needs_update, ty) = {
+                let mut room = level.get_room_info_mut(room);
+                let nu = room.needs_update;
+                room.needs_update = false;
+                if room.controller.is_invalid() {
+                    continue;
+                }
+                if !room.state.is_done() {
+                    entities.with(|
+                        _em: EntityManager<'_>,
+                        mut c: Write<Controlled>,
+                        mut gr: Write<GotoRoom>,
+                        mut props: Write<LuaRoomProperties>,
+                        mut rc: Write<RoomController>,
+                    |{
+                        let rc = assume!(log, rc.get_component_mut(room.controller));
+                        for e in &rc.entities {
+                            let c = assume!(log, c.get_component_mut(*e));
+                            c.should_release = true;
+                        }
+                        for e in &rc.visitors {
+                            let c = assume!(log, c.get_component_mut(*e));
+                            c.should_release = true;
+                        }
+                        for e in rc.waiting_list.drain(..) {
+                            gr.remove_component(e);
+                        }
+                        if rc.entities.is_empty() && rc.visitors.is_empty() {
+                            props.remove_component(room.controller);
+                        }
+                    });
+                }
+                (nu, assume!(log, asset_manager.loader_open::<room::Loader>(room.key.borrow())))
+            };
+            if let Some(controller) = ty.controller.as_ref() {
+                let lua_room = LuaRoom::from_room(log, &*level.rooms.borrow(), entities, room, scripting);
+                s.scope(|s| {
+                    let lua_room = s.pin(&lua_room);
+                    let module = s.string(controller.module());
+                    let resource = s.string(controller.resource());
+                    if needs_update {
+                        if let Err(err) = scripting.with_borrows()
+                            .borrow_mut(entities)
+                            .borrow_mut(players)
+                            .invoke::<_, ()>(invoke, (module, resource, update, lua_room))
+                        {
+                            error!(log, "Failed to update room: {}", err; "room" => ?room, "type" => &ty.name);
+                        }
                     }
-                    if rc.entities.is_empty() && rc.visitors.is_empty() {
-                        props.remove_component(room.controller);
+                    if let Err(err) = scripting.with_borrows()
+                        .borrow_mut(entities)
+                        .borrow_mut(players)
+                        .invoke::<_, ()>(invoke, (module, resource, server, lua_room))
+                    {
+                        error!(log, "Failed to tick room"; "room" => ?room, "type" => &ty.name, "error" => %err);
                     }
                 });
             }
-            (nu, assume!(log, asset_manager.loader_open::<room::Loader>(room.key.borrow())))
-        };
-        if let Some(controller) = ty.controller.as_ref() {
-            let lua_room = LuaRoom::from_room(log, &*level.rooms.borrow(), entities, room, scripting);
-            if needs_update {
-                if let Err(err) = scripting.with_borrows()
-                    .borrow_mut(entities)
-                    .borrow_mut(players)
-                    .invoke_function::<_, ()>("invoke_module_method", (
-                        Ref::new_string(scripting, controller.module()),
-                        Ref::new_string(scripting, controller.resource()),
-                        Ref::new_string(scripting, "update"),
-                        lua_room.clone()
-                )) {
-                    error!(log, "Failed to update room: {}", err; "room" => ?room, "type" => &ty.name);
-                }
-            }
-            if let Err(err) = scripting.with_borrows()
-                .borrow_mut(entities)
-                .borrow_mut(players)
-                .invoke_function::<_, ()>("invoke_module_method", (
-                    Ref::new_string(scripting, controller.module()),
-                    Ref::new_string(scripting, controller.resource()),
-                    Ref::new_string(scripting, "server"),
-                    lua_room
-            )) {
-                error!(log, "Failed to tick room"; "room" => ?room, "type" => &ty.name, "error" => %err);
-            }
         }
-    }
+    });
 }
 
 pub enum Types {}
@@ -1508,6 +1772,10 @@ impl player::Player for ServerPlayer {
     fn set_config(&mut self, cfg: player::PlayerConfig) {
         self.config = cfg;
     }
+    fn get_shared(&self) -> player::SharedState {
+        player::SharedState::default()
+    }
+    fn set_shared(&mut self, _shared: player::SharedState) {}
 }
 
 pub(crate) struct Handler<'a> {
@@ -1547,9 +1815,112 @@ pub(crate) enum ExtraCommands {}
 impl lua::LuaUsable for ExtraCommands {}
 impl script::LuaTracked for ExtraCommands {
     const KEY: script::NulledString = nul_str!("extra_commands");
-    type Storage = Rc<RefCell<Vec<command::Command>>>;
-    type Output = Rc<RefCell<Vec<command::Command>>>;
+    type Storage = Rc<RefCell<Vec<ScriptCommand>>>;
+    type Output = Rc<RefCell<Vec<ScriptCommand>>>;
     fn try_convert(s: &Self::Storage) -> Option<Self::Output> {
         Some(s.clone())
     }
-}
\ No newline at end of file
+}
+
+/// The state of a command submitted by a script
+#[derive(Debug)]
+enum CommandStatus {
+    /// The command hasn't been executed yet
+    Pending,
+    /// The command was executed
+    Done,
+    /// The command failed to validate
+    Failed(String),
+}
+
+/// A command submitted by a script that is waiting to
+/// be executed by the server
+pub(crate) struct ScriptCommand {
+    pub(crate) command: command::Command,
+    status: Rc<RefCell<CommandStatus>>,
+}
+
+impl ScriptCommand {
+    /// Queues the command to be executed at the start of the next
+    /// tick returning a handle the script can check the result with
+    pub(crate) fn submit(lua: &Lua, command: command::Command) -> UResult<Ref<CommandResult>> {
+        let commands = lua.get_tracked::<ExtraCommands>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let status = Rc::new(RefCell::new(CommandStatus::Pending));
+        let result = Ref::new(lua, CommandResult {
+            name: command.name(),
+            status: status.clone(),
+        });
+        commands.borrow_mut().push(ScriptCommand {
+            command,
+            status,
+        });
+        Ok(result)
+    }
+
+    /// Returns a handle for a command that was rejected before
+    /// it could be queued
+    pub(crate) fn rejected(lua: &Lua, name: &'static str, reason: String) -> Ref<CommandResult> {
+        Ref::new(lua, CommandResult {
+            name,
+            status: Rc::new(RefCell::new(CommandStatus::Failed(reason))),
+        })
+    }
+
+    /// Marks the command as executed
+    pub(crate) fn complete(&self) {
+        *self.status.borrow_mut() = CommandStatus::Done;
+    }
+
+    /// Marks the command as failed with the passed reason
+    pub(crate) fn fail(&self, reason: String) {
+        *self.status.borrow_mut() = CommandStatus::Failed(reason);
+    }
+}
+
+/// Script handle to the result of a submitted command.
+///
+/// Commands are executed on the next tick so `done` will
+/// be false until then.
+#[derive(lua::LuaBind)]
+#[lua(methods, metatable = "script::support_getters_setters")]
+pub struct CommandResult {
+    name: &'static str,
+    status: Rc<RefCell<CommandStatus>>,
+}
+
+#[lua::lua_methods]
+impl CommandResult {
+    /// Returns the name of the command's type
+    #[lua]
+    fn get_command(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns whether the command has been executed or rejected
+    #[lua]
+    fn get_done(&self) -> bool {
+        match *self.status.borrow() {
+            CommandStatus::Pending => false,
+            _ => true,
+        }
+    }
+
+    /// Returns whether the command was executed successfully
+    #[lua]
+    fn get_ok(&self) -> bool {
+        match *self.status.borrow() {
+            CommandStatus::Done => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the reason the command failed if it did
+    #[lua]
+    fn get_error(&self, lua: &Lua) -> Option<Ref<String>> {
+        match *self.status.borrow() {
+            CommandStatus::Failed(ref reason) => Some(Ref::new_string(lua, reason.as_str())),
+            _ => None,
+        }
+    }
+}
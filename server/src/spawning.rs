@@ -1,20 +1,47 @@
 
 use crate::prelude::*;
+use crate::saving::scheduled::{ScheduledTask, ScheduledTasks, TaskContext, TaskRegistry};
+use crate::entity::visitor::{self, Visitor, VisitorKind, VisitorRules};
+use rand::Rng;
 
 const SPAWN_CHECK_INTERVAL: u32 = 20 * 60; // 1 Minute
+/// The delay before a player's first inspection
+const FIRST_INSPECTION: u32 = 20 * 60 * 5; // 5 Minutes
+/// The number of ticks between checking for new visitors
+const VISITOR_CHECK_INTERVAL: u32 = 20 * 30; // 30 Seconds
+/// The most tourists in a group on an open day
+const MAX_TOUR_GROUP: u32 = 5;
+/// The number of ticks between each stress test report
+const STRESS_REPORT_INTERVAL: usize = 20;
 
 pub struct Spawner {
     pub info: Vec<SpawnInfo>,
     spawn_check: u32,
     log: Logger,
+    stress_test: Option<StressTest>,
+}
+
+/// Spawns large numbers of students and staff in batches,
+/// reporting the server's tick times whilst doing so.
+///
+/// Used to validate performance work on a real game instead
+/// of only synthetic benchmarks. Started with the
+/// `/stresstest <count> [batch size]` chat command and stopped
+/// with `/stresstest stop`. Reports continue after spawning has
+/// finished until stopped.
+struct StressTest {
+    owner: PlayerId,
+    remaining: u32,
+    batch: u32,
+    spawned: u32,
+    staff: Vec<StaffInfo>,
+    tick_times: Vec<Duration>,
 }
 
 pub struct SpawnInfo {
     pub id: PlayerId,
 
     pub required_students: u32,
-
-    inspector_cooldown: i32,
 }
 
 impl Spawner {
@@ -25,13 +52,69 @@ impl Spawner {
                 .map(|v| SpawnInfo {
                     id: *v,
                     required_students: 0,
-                    inspector_cooldown: 20 * 60 * 5,
                 })
                 .collect(),
             spawn_check: 0,
+            stress_test: None,
+        }
+    }
+
+    /// Starts spawning `count` entities for the player, `batch`
+    /// per a tick. Replaces any running stress test.
+    pub fn start_stress_test(&mut self, owner: PlayerId, count: u32, batch: u32, staff: Vec<StaffInfo>) {
+        info!(self.log, "Starting stress test"; "count" => count, "batch" => batch);
+        self.stress_test = Some(StressTest {
+            owner,
+            remaining: count,
+            batch: batch.max(1),
+            spawned: 0,
+            staff,
+            tick_times: Vec::with_capacity(STRESS_REPORT_INTERVAL),
+        });
+    }
+
+    /// Stops the running stress test returning whether one was
+    /// running. Entities that have already been spawned remain.
+    pub fn stop_stress_test(&mut self) -> bool {
+        self.stress_test.take().is_some()
+    }
+
+    /// Records the time taken by a tick for the stress test's
+    /// report
+    pub fn record_tick(&mut self, time: Duration) {
+        if let Some(test) = self.stress_test.as_mut() {
+            test.tick_times.push(time);
         }
     }
 
+    /// Returns a report on the server's performance once enough
+    /// ticks have been recorded by a running stress test
+    pub fn stress_report(&mut self, entities: &Container) -> Option<String> {
+        let test = self.stress_test.as_mut()?;
+        if test.tick_times.len() < STRESS_REPORT_INTERVAL {
+            return None;
+        }
+        let total: Duration = test.tick_times.iter().sum();
+        let average = total / test.tick_times.len() as u32;
+        let max = test.tick_times.iter().max().cloned().unwrap_or_default();
+        test.tick_times.clear();
+
+        let entity_count = entities.iter_all().count();
+        info!(self.log, "Stress test";
+            "spawned" => test.spawned,
+            "remaining" => test.remaining,
+            "entities" => entity_count,
+            "average_tick_ms" => average.as_secs_f64() * 1000.0,
+            "max_tick_ms" => max.as_secs_f64() * 1000.0
+        );
+        Some(format!(
+            "Stress test: {} spawned, {} remaining, {} entities, tick avg {:.2}ms max {:.2}ms",
+            test.spawned, test.remaining, entity_count,
+            average.as_secs_f64() * 1000.0,
+            max.as_secs_f64() * 1000.0,
+        ))
+    }
+
     pub(crate) fn handle_spawning(
         &mut self,
         assets: &AssetManager,
@@ -39,11 +122,10 @@ impl Spawner {
         level: &mut Level,
         entities: &mut Container,
         scripting: &script::Engine,
+        pause_afk: bool,
     ) {
-        use rand::{Rng, thread_rng};
+        use rand::thread_rng;
         use std::cmp::{min, max};
-        use lua::{Ref, Table};
-        use rand::seq::SliceRandom;
 
         self.spawn_check += 1;
         if self.spawn_check >= SPAWN_CHECK_INTERVAL {
@@ -90,137 +172,399 @@ impl Spawner {
         }
 
         let mut rng = thread_rng();
-        let student = ResourceKey::new("base", "student");
-        let inspector = ResourceKey::new("base", "inspector");
-        let vip = ResourceKey::new("base", "vip");
-
-        let generate = Ref::new_string(scripting, "generate");
-        let base = Ref::new_string(scripting, "base");
-        let student_creation = Ref::new_string(scripting, "student_creation");
-
         for player in &mut self.info {
-            player.inspector_cooldown -= 1;
-            if player.inspector_cooldown <= 0 && rng.gen_bool(1.0 / 500.0) {
-                player.inspector_cooldown = rng.gen_range(20 * 60 * 5, 20 * 60 * 15);
-
-                let ety = assume!(self.log, assets.loader_open::<Loader<ServerComponent>>(if rng.gen_bool(1.0 / 10.0) {
-                    vip.borrow()
-                } else {
-                    inspector.borrow()
-                }));
-                let variant = rng.gen_range(0, ety.variants.len());
-                let e = ety.create_entity(entities, variant, None);
-
-                let (tx, ty) = gen_spawn(level, &mut rng);
-                {
-                    let pos = assume!(self.log, entities.get_component_mut::<Position>(e));
-                    pos.x = tx as f32 + 0.5;
-                    pos.y = 0.2;
-                    pos.z = ty as f32 + 0.5;
-                }
-                entities.add_component(e, Owned {
-                    player_id: player.id,
-                });
-            }
+            // Away players don't get new students if the server
+            // is configured to pause them
+            let paused = pause_afk && player_info[&player.id].afk;
             if player.required_students > 0
+                && !paused
                 && rng.gen_bool(1.0 / f64::from(max(1, min(150, (LESSON_LENGTH as u32 * 4 * 3) / player.required_students))))
             {
-                #[derive(Serialize)]
-                struct PlayerInfo {
-                }
-                let player_script_info = assume!(self.log, lua::to_table(scripting, &PlayerInfo {
-                }));
-
-                #[derive(Deserialize)]
-                struct StudentInfo {
-                    #[serde(default)]
-                    first_name: Option<String>,
-                    #[serde(default)]
-                    surname: Option<String>,
-                    #[serde(default)]
-                    variant: Option<usize>,
-                    #[serde(default)]
-                    vars: FNVMap<String, VarValue>,
-                    money: UniDollar,
-                }
+                player.required_students -= 1;
+                spawn_student(&self.log, assets, level, entities, scripting, player.id, &mut rng);
+            }
+        }
 
-                #[derive(Deserialize, Debug)]
-                #[serde(untagged)]
-                enum VarValue {
-                    Bool(bool),
-                    Float(f64),
-                }
+        self.spawn_stress_test(assets, level, entities, scripting, &mut rng);
+    }
 
-                player.required_students -= 1;
-                let (t_x, t_y) = gen_spawn(level, &mut rng);
-                let ety = assume!(self.log, assets.loader_open::<Loader<ServerComponent>>(student.borrow()));
-
-                let student_info = match scripting.with_borrows()
-                    .borrow_mut(entities)
-                    .invoke_function::<_, Ref<Table>>("invoke_module_method", (
-                        base.clone(),
-                        student_creation.clone(),
-                        generate.clone(),
-                        player_script_info.clone(),
-                    )) {
-                    Err(err) => {
-                        error!(self.log, "Failed to generate student"; "error" => % err);
-                        continue;
-                    },
-                    Ok(val) => val,
-                };
-                let student_info: StudentInfo = match lua::from_table(&student_info) {
-                    Ok(val) => val,
-                    Err(err) => {
-                        error!(self.log, "Failed to generate student"; "error" => % err);
-                        continue;
-                    },
-                };
+    fn spawn_stress_test<R: Rng>(
+        &mut self,
+        assets: &AssetManager,
+        level: &Level,
+        entities: &mut Container,
+        scripting: &script::Engine,
+        rng: &mut R,
+    ) {
+        use rand::seq::SliceRandom;
+        let test = match self.stress_test.as_mut() {
+            Some(val) => val,
+            None => return,
+        };
+        let count = test.remaining.min(test.batch);
+        for _ in 0 .. count {
+            // Mostly students to match a normal game
+            let spawned = match test.staff.choose(rng) {
+                Some(staff) if rng.gen_bool(1.0 / 5.0) => spawn_staff(&self.log, assets, level, entities, staff, test.owner, rng),
+                _ => spawn_student(&self.log, assets, level, entities, scripting, test.owner, rng),
+            };
+            test.remaining -= 1;
+            if spawned.is_some() {
+                test.spawned += 1;
+            }
+        }
+    }
+}
 
-                let e_variant = student_info.variant
-                    .unwrap_or_else(|| rng.gen_range(0, ety.variants.len()));
+/// Registers the scheduled tasks used for spawning
+pub fn register_tasks(registry: &mut TaskRegistry) {
+    registry.register::<Inspection>();
+    registry.register::<VisitorArrival>();
+}
 
-                let name = if let (Some(f), Some(s)) = (
-                    student_info.first_name,
-                    student_info.surname,
-                ) {
-                    ((*f).into(), (*s).into())
-                } else {
-                    let variant = &ety.variants[e_variant];
-                    (
-                        variant.name_list.first.choose(&mut rng).cloned().unwrap_or_else(|| "Missing".into()),
-                        variant.name_list.second.choose(&mut rng).cloned().unwrap_or_else(|| "Name".into()),
-                    )
-                };
+/// Schedules the first inspection for every player that doesn't
+/// already have one pending, e.g. from a loaded save
+pub fn schedule_inspections(log: &Logger, entities: &mut Container, players: &[PlayerId]) {
+    let mut rng = ::rand::thread_rng();
+    let tasks = assume!(log, entities.get_component_mut::<ScheduledTasks>(Container::WORLD));
+    let pending: Vec<PlayerId> = tasks.pending::<Inspection>()
+        .map(|v| v.1.player)
+        .collect();
+    for player in players {
+        if !pending.contains(player) {
+            let delay = FIRST_INSPECTION + Inspection::random_wait(&mut rng);
+            assume!(log, tasks.schedule(delay, &Inspection { player: *player }));
+        }
+    }
+}
 
-                let e = ety.create_entity(entities, e_variant, Some(name));
-                {
-                    let pos = assume!(self.log, entities.get_component_mut::<Position>(e));
-                    pos.x = t_x as f32 + 0.5;
-                    pos.y = 0.2;
-                    pos.z = t_y as f32 + 0.5;
-                }
-                entities.add_component(e, Owned {
-                    player_id: player.id,
-                });
-                entities.add_component(e, Money {
-                    money: student_info.money,
-                });
-                let vars = assume!(self.log, entities.get_custom::<choice::StudentVars>(e));
-
-                for (k, v) in student_info.vars {
-                    match v {
-                        VarValue::Bool(v) => vars.set_boolean(&k, v),
-                        VarValue::Float(v) => if vars.get_type(&k) == Some(choice::Type::Integer) {
-                            vars.set_integer(&k, v as i32)
-                        } else {
-                            vars.set_float(&k, v as f32)
-                        },
-                    };
+/// Sends an inspector (or occasionally a vip) to the player's
+/// campus and schedules the next inspection
+#[derive(Debug, Serialize, Deserialize)]
+struct Inspection {
+    player: PlayerId,
+}
+
+impl Inspection {
+    /// A random extra wait so that inspections don't arrive
+    /// at predictable times
+    fn random_wait<R: Rng>(rng: &mut R) -> u32 {
+        rng.gen_range(0, 1000)
+    }
+}
+
+impl ScheduledTask for Inspection {
+    const KIND: &'static str = "spawning:inspection";
+
+    fn run(self, ctx: &mut TaskContext<'_>) -> UResult<()> {
+        let mut rng = ::rand::thread_rng();
+        let key = if rng.gen_bool(1.0 / 10.0) {
+            ResourceKey::new("base", "vip")
+        } else {
+            ResourceKey::new("base", "inspector")
+        };
+        let ety = ctx.assets.loader_open::<Loader<ServerComponent>>(key.borrow())?;
+        let variant = rng.gen_range(0, ety.variants.len());
+        let e = ety.create_entity(ctx.entities, variant, None);
+
+        let (tx, ty) = gen_spawn(ctx.level, &mut rng);
+        {
+            let pos = assume!(ctx.log, ctx.entities.get_component_mut::<Position>(e));
+            pos.y = 0.2;
+        }
+        teleport(ctx.entities, e, tx as f32 + 0.5, ty as f32 + 0.5);
+        ctx.entities.add_component(e, Owned {
+            player_id: self.player,
+        });
+        let visit_length = ctx.entities.get_component::<VisitorRules>(Container::WORLD)
+            .map(|v| v.config().visit_length);
+        if let Some(length) = visit_length {
+            ctx.entities.add_component(e, Visitor::new(VisitorKind::Inspector, Vec::new(), length));
+        }
+
+        let delay = rng.gen_range(20 * 60 * 5, 20 * 60 * 15) + Self::random_wait(&mut rng);
+        let tasks = ctx.entities.get_component_mut::<ScheduledTasks>(Container::WORLD)
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        tasks.schedule(delay, &self)
+    }
+}
+
+/// Schedules the visitor checks for every player that doesn't
+/// already have one pending, e.g. from a loaded save
+pub fn schedule_visitors(log: &Logger, entities: &mut Container, players: &[PlayerId]) {
+    let tasks = assume!(log, entities.get_component_mut::<ScheduledTasks>(Container::WORLD));
+    let pending: Vec<PlayerId> = tasks.pending::<VisitorArrival>()
+        .map(|v| v.1.player)
+        .collect();
+    for player in players {
+        if !pending.contains(player) {
+            assume!(log, tasks.schedule(VISITOR_CHECK_INTERVAL, &VisitorArrival { player: *player }));
+        }
+    }
+}
+
+/// Sends parents and tourists to the player's campus at the rates
+/// in the `VisitorRules` and schedules the next check
+#[derive(Debug, Serialize, Deserialize)]
+struct VisitorArrival {
+    player: PlayerId,
+}
+
+impl ScheduledTask for VisitorArrival {
+    const KIND: &'static str = "spawning:visitor_arrival";
+
+    fn run(self, ctx: &mut TaskContext<'_>) -> UResult<()> {
+        let mut rng = ::rand::thread_rng();
+        let day = ctx.day;
+        let player = self.player;
+        let visiting = ctx.entities.with(|em: EntityManager<'_>, owned: Read<Owned>, visitor: Read<Visitor>| {
+            em.group_mask(&owned, |m| m.and(&visitor))
+                .filter(|v| v.1.player_id == player)
+                .count() as u32
+        });
+        let arrivals = if let Some(rules) = ctx.entities.get_component::<VisitorRules>(Container::WORLD) {
+            let config = rules.config();
+            let open_day = rules.is_open_day(day);
+            let mut space = config.max_visitors.saturating_sub(visiting);
+            let mut arrivals = vec![];
+            for &(kind, key) in &[(VisitorKind::Parent, &config.parent), (VisitorKind::Tourist, &config.tourist)] {
+                if space == 0 || !rng.gen_bool(rules.arrival_chance(kind, day, VISITOR_CHECK_INTERVAL)) {
+                    continue;
                 }
+                // Tourists are shown around in groups on open days
+                // instead of wandering around alone
+                let count = if kind == VisitorKind::Tourist && open_day {
+                    rng.gen_range(2, MAX_TOUR_GROUP + 1).min(space)
+                } else {
+                    1
+                };
+                space -= count;
+                arrivals.push((kind, key.clone(), count, config.visit_length));
             }
+            arrivals
+        } else {
+            vec![]
+        };
+        for (kind, key, count, length) in arrivals {
+            spawn_visitors(ctx, self.player, kind, key, count, length, &mut rng);
+        }
+
+        let tasks = ctx.entities.get_component_mut::<ScheduledTasks>(Container::WORLD)
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        tasks.schedule(VISITOR_CHECK_INTERVAL, &self)
+    }
+}
+
+/// Spawns a group of visitors that share a tour of the player's
+/// campus. Nobody visits campuses with nothing to see.
+fn spawn_visitors<R: Rng>(
+    ctx: &mut TaskContext<'_>,
+    owner: PlayerId,
+    kind: VisitorKind,
+    key: ResourceKey<'_>,
+    count: u32,
+    length: u32,
+    rng: &mut R,
+) {
+    use rand::seq::SliceRandom;
+
+    let ety = match ctx.assets.loader_open::<Loader<ServerComponent>>(key.borrow()) {
+        Ok(val) => val,
+        Err(err) => {
+            error!(ctx.log, "Failed to load visitor"; "ty" => ?key, "error" => %err);
+            return;
+        }
+    };
+    let building = ctx.entities.get_component::<construction::Construction>(Container::WORLD);
+    let rooms = ctx.level.room_ids()
+        .into_iter()
+        .map(|v| ctx.level.get_room_info(v))
+        .filter(|v| v.owner == owner && v.state.is_done() && !v.controller.is_invalid())
+        .filter(|v| !building.map_or(false, |b| b.is_building(v.id)))
+        .filter_map(|v| ctx.assets.loader_open::<room::Loader>(v.key.borrow())
+            .ok()
+            .map(|ty| (v.id, ty.used_for_teaching)))
+        .collect();
+    let tour = visitor::pick_tour(rooms, kind, rng);
+    if tour.is_empty() {
+        return;
+    }
+
+    let (t_x, t_y) = gen_spawn(ctx.level, rng);
+    for _ in 0 .. count {
+        let e_variant = rng.gen_range(0, ety.variants.len());
+        let name = {
+            let variant = &ety.variants[e_variant];
+            (
+                variant.name_list.first.choose(rng).cloned().unwrap_or_else(|| "Missing".into()),
+                variant.name_list.second.choose(rng).cloned().unwrap_or_else(|| "Name".into()),
+            )
+        };
+        let e = ety.create_entity(ctx.entities, e_variant, Some(name));
+        {
+            let pos = assume!(ctx.log, ctx.entities.get_component_mut::<Position>(e));
+            pos.y = 0.2;
         }
+        teleport(ctx.entities, e, t_x as f32 + 0.5, t_y as f32 + 0.5);
+        ctx.entities.add_component(e, Owned {
+            player_id: owner,
+        });
+        ctx.entities.add_component(e, Visitor::new(kind, tour.clone(), length));
+    }
+}
+
+/// Spawns a student generated by the `student_creation` script
+/// for the player
+fn spawn_student<R: Rng>(
+    log: &Logger,
+    assets: &AssetManager,
+    level: &Level,
+    entities: &mut Container,
+    scripting: &script::Engine,
+    owner: PlayerId,
+    rng: &mut R,
+) -> Option<Entity> {
+    use lua::{Ref, Table};
+    use rand::seq::SliceRandom;
+
+    #[derive(Serialize)]
+    struct PlayerInfo {
+    }
+    let player_script_info = assume!(log, lua::to_table(scripting, &PlayerInfo {
+    }));
+
+    #[derive(Deserialize)]
+    struct StudentInfo {
+        #[serde(default)]
+        first_name: Option<String>,
+        #[serde(default)]
+        surname: Option<String>,
+        #[serde(default)]
+        variant: Option<usize>,
+        #[serde(default)]
+        vars: FNVMap<String, VarValue>,
+        money: UniDollar,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(untagged)]
+    enum VarValue {
+        Bool(bool),
+        Float(f64),
+    }
+
+    let (t_x, t_y) = gen_spawn(level, rng);
+    let ety = assume!(log, assets.loader_open::<Loader<ServerComponent>>(ResourceKey::new("base", "student")));
+
+    let student_info = match scripting.with_borrows()
+        .borrow_mut(entities)
+        .invoke_function::<_, Ref<Table>>("invoke_module_method", (
+            Ref::new_string(scripting, "base"),
+            Ref::new_string(scripting, "student_creation"),
+            Ref::new_string(scripting, "generate"),
+            player_script_info,
+        )) {
+        Err(err) => {
+            error!(log, "Failed to generate student"; "error" => % err);
+            return None;
+        },
+        Ok(val) => val,
+    };
+    let student_info: StudentInfo = match lua::from_table(&student_info) {
+        Ok(val) => val,
+        Err(err) => {
+            error!(log, "Failed to generate student"; "error" => % err);
+            return None;
+        },
+    };
+
+    let e_variant = student_info.variant
+        .unwrap_or_else(|| rng.gen_range(0, ety.variants.len()));
+
+    let name = if let (Some(f), Some(s)) = (
+        student_info.first_name,
+        student_info.surname,
+    ) {
+        ((*f).into(), (*s).into())
+    } else {
+        let variant = &ety.variants[e_variant];
+        (
+            variant.name_list.first.choose(rng).cloned().unwrap_or_else(|| "Missing".into()),
+            variant.name_list.second.choose(rng).cloned().unwrap_or_else(|| "Name".into()),
+        )
+    };
+
+    let e = ety.create_entity(entities, e_variant, Some(name));
+    {
+        let pos = assume!(log, entities.get_component_mut::<Position>(e));
+        pos.y = 0.2;
+    }
+    teleport(entities, e, t_x as f32 + 0.5, t_y as f32 + 0.5);
+    entities.add_component(e, Owned {
+        player_id: owner,
+    });
+    entities.add_component(e, Money {
+        money: student_info.money,
+    });
+    let vars = assume!(log, entities.get_custom::<choice::StudentVars>(e));
+
+    for (k, v) in student_info.vars {
+        match v {
+            VarValue::Bool(v) => vars.set_boolean(&k, v),
+            VarValue::Float(v) => if vars.get_type(&k) == Some(choice::Type::Integer) {
+                vars.set_integer(&k, v as i32)
+            } else {
+                vars.set_float(&k, v as f32)
+            },
+        };
+    }
+    Some(e)
+}
+
+/// Spawns an unpaid staff member of a random variant for the
+/// player
+fn spawn_staff<R: Rng>(
+    log: &Logger,
+    assets: &AssetManager,
+    level: &Level,
+    entities: &mut Container,
+    staff: &StaffInfo,
+    owner: PlayerId,
+    rng: &mut R,
+) -> Option<Entity> {
+    use rand::seq::SliceRandom;
+
+    let ty = match assets.loader_open::<Loader<ServerComponent>>(staff.entity.borrow()) {
+        Ok(val) => val,
+        Err(err) => {
+            error!(log, "Failed to load staff"; "ty" => ?staff.entity, "error" => %err);
+            return None;
+        }
+    };
+    let e_variant = rng.gen_range(0, ty.variants.len());
+    let name = {
+        let variant = &ty.variants[e_variant];
+        (
+            variant.name_list.first.choose(rng).cloned().unwrap_or_else(|| "Missing".into()),
+            variant.name_list.second.choose(rng).cloned().unwrap_or_else(|| "Name".into()),
+        )
+    };
+    let (t_x, t_y) = gen_spawn(level, rng);
+    let e = ty.create_entity(entities, e_variant, Some(name));
+    {
+        let pos = assume!(log, entities.get_component_mut::<Position>(e));
+        pos.y = 0.2;
+    }
+    teleport(entities, e, t_x as f32 + 0.5, t_y as f32 + 0.5);
+    // Don't bankrupt the player whilst testing
+    if let Some(paid) = entities.get_component_mut::<Paid>(e) {
+        paid.cost = UniDollar(0);
+        paid.wanted_cost = UniDollar(0);
     }
+    entities.add_component(e, Owned {
+        player_id: owner,
+    });
+    Some(e)
 }
 
 fn gen_spawn<R>(level: &Level, rng: &mut R) -> (i32, i32)
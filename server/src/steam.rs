@@ -20,6 +20,28 @@ pub trait Steam {
     fn end_authentication_session(&self, user: steamworks::SteamId);
 }
 
+/// A steam implementation picked at runtime.
+///
+/// Allows servers to be built without caring whether steam
+/// is available.
+pub type BoxedSteam = Box<dyn Steam>;
+
+impl <S: Steam + ?Sized> Steam for Box<S> {
+    #[cfg(feature = "steam")]
+    fn steam_id(&self) -> steamworks::SteamId {
+        (**self).steam_id()
+    }
+    #[cfg(feature = "steam")]
+    fn begin_authentication_session(&self, user: steamworks::SteamId, ticket: &[u8]) -> Result<(), steamworks::AuthSessionError> {
+        (**self).begin_authentication_session(user, ticket)
+    }
+    #[cfg(feature = "steam")]
+    fn end_authentication_session(&self, user: steamworks::SteamId) {
+        (**self).end_authentication_session(user)
+    }
+}
+
+/// Stub used when built without steam
 #[cfg(not(feature = "steam"))]
 impl Steam for () {}
 
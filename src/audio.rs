@@ -7,6 +7,7 @@ use univercity_audio::{
     AudioBuffer,
     OggStream,
     SoundRef,
+    StreamSource,
 };
 use sdl2::AudioSubsystem;
 use sdl2::audio::{
@@ -19,6 +20,7 @@ use std::rc::{Rc, Weak};
 use std::cell::RefCell;
 use std::sync::{Arc, Mutex};
 use cgmath;
+use crate::music::{MusicDirector, MusicSignals};
 
 /// Manages the audio device
 pub struct AudioManager {
@@ -63,10 +65,13 @@ impl AudioManager {
                 loaded_sounds: FNVMap::default(),
                 playing_sounds: Vec::new(),
                 positioned_sounds: Vec::new(),
+                voice_sounds: Vec::new(),
                 music_volume: 0.5,
                 sound_volume: 1.0,
+                voice_volume: 1.0,
                 songs: Vec::new(),
                 playing_song: None,
+                director: MusicDirector::new(),
                 camera: (0.0, 0.0, cgmath::Deg(0.0)),
             }))),
             playlist: None,
@@ -83,6 +88,15 @@ impl AudioManager {
         controller.camera = (camera_x, camera_y, camera_rotation);
 
         controller.playing_sounds.retain(|v| !v.has_ended());
+        controller.voice_sounds.retain(|v| !v.has_ended());
+
+        controller.director.tick(&controller.log, &controller.mixer, &controller.assets, controller.music_volume);
+        // Stem sets replace the playlist's songs
+        if controller.director.has_sets() {
+            if let Some(song) = controller.playing_song.as_mut() {
+                fade_out_song(song);
+            }
+        }
 
         if let Some(song) = controller.playing_song.as_mut() {
             if let Some(remaining) = song.length.checked_sub(song.start.elapsed()) {
@@ -95,7 +109,7 @@ impl AudioManager {
             }
         }
 
-        if controller.playing_song.as_ref().map_or(true, |v| v.sound.has_ended()) {
+        if !controller.director.has_sets() && controller.playing_song.as_ref().map_or(true, |v| v.sound.has_ended()) {
             let mut rng = thread_rng();
             if let Some(song) = controller.songs.choose(&mut rng) {
                 let asset = assume!(controller.log, controller.assets.open_from_pack(
@@ -168,12 +182,16 @@ impl AudioManager {
         }
         controller.songs = songs;
         if let Some(song) = controller.playing_song.as_mut() {
-            if let Some(remaining) = song.length.checked_sub(song.start.elapsed()) {
-                if remaining > FADE_TIME {
-                    song.length = (song.length - remaining) + FADE_TIME;
-                }
-            }
+            fade_out_song(song);
         }
+        let controller = &mut *controller;
+        controller.director.load_playlist(&controller.log, &controller.assets, list);
+    }
+
+    /// Updates the signals used to select music stems for
+    /// the current playlist
+    pub fn set_music_signals(&self, signals: MusicSignals) {
+        self.controller.borrow_mut().director.set_signals(signals);
     }
 
     /// Updates volume settings from the config
@@ -181,13 +199,18 @@ impl AudioManager {
         let mut controller = self.controller.borrow_mut();
         controller.music_volume = config.music_volume.get().powi(4);
         controller.sound_volume = config.sound_volume.get().powi(4);
+        controller.voice_volume = config.voice_volume.get().powi(4);
 
         if let Some(snd) = controller.playing_song.as_ref() {
             snd.sound.set_volume(controller.music_volume as f32);
         }
+        controller.director.update_volume(controller.music_volume);
         for snd in &controller.playing_sounds {
             snd.set_volume(controller.sound_volume as f32);
         }
+        for snd in &controller.voice_sounds {
+            snd.set_volume(controller.voice_volume as f32);
+        }
         controller.update_positioned();
     }
 }
@@ -200,13 +223,16 @@ pub struct AudioController {
 
     music_volume: f64,
     sound_volume: f64,
+    voice_volume: f64,
 
     loaded_sounds: FNVMap<ResourceKey<'static>, AudioBuffer>,
     playing_sounds: Vec<SoundRef>,
     positioned_sounds: Vec<PositionedSound>,
+    voice_sounds: Vec<SoundRef>,
 
     songs: Vec<ResourceKey<'static>>,
     playing_song: Option<PlayingSong>,
+    director: MusicDirector,
 
     camera: (f32, f32, cgmath::Deg<f32>),
 }
@@ -217,6 +243,15 @@ struct PlayingSong {
     start: Instant,
 }
 
+/// Shortens the song so that it fades out over `FADE_TIME`
+fn fade_out_song(song: &mut PlayingSong) {
+    if let Some(remaining) = song.length.checked_sub(song.start.elapsed()) {
+        if remaining > FADE_TIME {
+            song.length = (song.length - remaining) + FADE_TIME;
+        }
+    }
+}
+
 impl script::LuaTracked for AudioController {
     const KEY: script::NulledString = nul_str!("audio_ref");
     type Storage = Weak<RefCell<AudioController>>;
@@ -227,10 +262,26 @@ impl script::LuaTracked for AudioController {
     }
 }
 
+/// The distance after which positioned sounds can't be heard
+const HEARING_DISTANCE: f32 = 15.0;
+/// How much a solid wall between the camera and a sound muffles it
+const WALL_OCCLUSION: f32 = 1.0;
+/// How much a wall with a window muffles a sound
+const WINDOW_OCCLUSION: f32 = 0.5;
+/// How much an open door muffles a sound
+const DOOR_OCCLUSION: f32 = 0.25;
+/// How much moving between rooms muffles a sound when there
+/// isn't a wall in the way
+const ROOM_OCCLUSION: f32 = 0.25;
+/// The level of occlusion at which a sound is fully muffled
+const MAX_OCCLUSION: f32 = 2.0;
+
 struct PositionedSound {
     position: Arc<Mutex<(f32, f32)>>,
     sound: SoundRef,
     first: bool,
+    /// How much the level blocks the sound, `0.0` being not at all
+    occlusion: f32,
 }
 
 /// A reference to a positional sound
@@ -293,13 +344,43 @@ impl AudioController {
         self.playing_sounds.push(snd.clone());
     }
 
+    /// Plays the streamed voice of another player.
+    ///
+    /// Voices are controlled by the voice volume instead of
+    /// the sound volume.
+    pub fn play_voice(&mut self, voice: StreamSource) -> SoundRef {
+        let snd = self.mixer.play(
+            voice.resampled(44_100)
+                .volume(self.voice_volume as f32)
+        );
+        snd.play();
+        self.voice_sounds.push(snd.clone());
+        snd
+    }
+
+    /// Plays the named sound file on repeat until stopped
+    pub fn play_sound_looped(&mut self, sound: ResourceKey<'_>) -> SoundRef {
+        let buffer = self.load_sound(sound);
+        let snd = self.mixer.play(
+            buffer.looped_source()
+                .volume(self.sound_volume as f32)
+        );
+        snd.play();
+        self.playing_sounds.push(snd.clone());
+        snd
+    }
+
     fn make_sound(&mut self, sound: ResourceKey<'_>) -> SoundRef {
+        let buffer = self.load_sound(sound);
+        self.mixer.play(
+            buffer.source()
+                .volume(self.sound_volume as f32)
+        )
+    }
+
+    fn load_sound(&mut self, sound: ResourceKey<'_>) -> AudioBuffer {
         if let Some(sound) = self.loaded_sounds.get(&sound).cloned() {
-            let snd = self.mixer.play(
-                sound.source()
-                    .volume(self.sound_volume as f32)
-            );
-            return snd;
+            return sound;
         }
         let asset = assume!(self.log, self.assets.open_from_pack(
             sound.module_key(),
@@ -309,21 +390,24 @@ impl AudioController {
             .resampled(44_100)
             .into_buffer();
         self.loaded_sounds.insert(sound.into_owned(), ogg.clone());
-        self.mixer.play(
-            ogg.source()
-                .volume(self.sound_volume as f32)
-        )
+        ogg
     }
 
     /// Plays the named sound file at the target position
     pub fn play_sound_at(&mut self, sound: ResourceKey<'_>, position: (f32, f32)) -> PositionRef {
-        let snd = self.make_sound(sound);
+        let buffer = self.load_sound(sound);
+        let snd = self.mixer.play(
+            buffer.source()
+                .volume(self.sound_volume as f32)
+                .low_pass()
+        );
         let position = Arc::new(Mutex::new(position));
 
         self.positioned_sounds.push(PositionedSound {
             sound: snd.clone(),
             position: position.clone(),
             first: true,
+            occlusion: 0.0,
         });
 
         PositionRef {
@@ -333,6 +417,16 @@ impl AudioController {
         }
     }
 
+    /// Recomputes how much of each positioned sound is blocked
+    /// by the walls and rooms between it and the camera.
+    pub fn update_occlusion<L: LevelView>(&mut self, level: &L) {
+        let camera = (self.camera.0, self.camera.1);
+        for snd in &mut self.positioned_sounds {
+            let position = { *assume!(self.log, snd.position.lock()) };
+            snd.occlusion = occlusion_between(level, camera, position);
+        }
+    }
+
     fn update_positioned(&mut self) {
         use std::f32::consts::PI;
         self.positioned_sounds.retain(|v| !v.sound.has_ended());
@@ -349,12 +443,13 @@ impl AudioController {
 
             let distance = (y - self.camera.1).hypot(x - self.camera.0);
 
-            let (left, right) = if distance > 15.0 {
+            let (left, right) = if distance > HEARING_DISTANCE {
                 (0.0, 0.0)
             } else {
                 let side = (y2 - y1) * x - (x2 - x1) * y + x2 * y1 - y2 * x1;
 
-                let dvol = 1.0 - distance / 15.0;
+                let muffle = (snd.occlusion / MAX_OCCLUSION).min(1.0);
+                let dvol = (1.0 - distance / HEARING_DISTANCE) * (1.0 - muffle * 0.7);
 
                 let mut vol = if side < 0.0 {
                     let am = side.abs() / 30.0;
@@ -379,6 +474,7 @@ impl AudioController {
                 left * self.sound_volume as f32,
                 right * self.sound_volume as f32
             );
+            snd.sound.set_low_pass((snd.occlusion / MAX_OCCLUSION).min(1.0));
 
             if snd.first {
                 snd.sound.play();
@@ -387,6 +483,56 @@ impl AudioController {
     }
 }
 
+/// Walks the tiles along the line between the two points adding
+/// up the walls crossed and the rooms entered.
+///
+/// Doors and windows let more sound through than solid walls.
+fn occlusion_between<L: LevelView>(level: &L, from: (f32, f32), to: (f32, f32)) -> f32 {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let mut loc = Location::new(from.0.floor() as i32, from.1.floor() as i32);
+    let end = Location::new(to.0.floor() as i32, to.1.floor() as i32);
+
+    let step_x = if dx < 0.0 { -1 } else { 1 };
+    let step_y = if dy < 0.0 { -1 } else { 1 };
+    // How far along the line the next tile edge on each
+    // axis is and how far apart the edges are. Lines along
+    // an axis end up with an infinite delta for the other.
+    let delta_x = 1.0 / dx.abs();
+    let delta_y = 1.0 / dy.abs();
+    let mut next_x = if dx < 0.0 { from.0 - from.0.floor() } else { from.0.floor() + 1.0 - from.0 } * delta_x;
+    let mut next_y = if dy < 0.0 { from.1 - from.1.floor() } else { from.1.floor() + 1.0 - from.1 } * delta_y;
+
+    let mut occlusion = 0.0;
+    let mut room = level.get_room_owner(loc);
+    // Sounds further than this can't be heard anyway
+    let max_steps = (HEARING_DISTANCE as i32 + 1) * 2;
+    for _ in 0 .. max_steps {
+        if loc == end || occlusion >= MAX_OCCLUSION {
+            break;
+        }
+        let dir = if next_x < next_y {
+            next_x += delta_x;
+            Direction::from_offset(step_x, 0)
+        } else {
+            next_y += delta_y;
+            Direction::from_offset(0, step_y)
+        };
+        let wall = level.get_wall_info(loc, dir);
+        loc = loc.shift(dir);
+        let next_room = level.get_room_owner(loc);
+
+        occlusion += match wall.map(|v| v.flag) {
+            Some(TileWallFlag::None) => WALL_OCCLUSION,
+            Some(TileWallFlag::Window(_)) => WINDOW_OCCLUSION,
+            Some(TileWallFlag::Door) => DOOR_OCCLUSION,
+            None if next_room != room => ROOM_OCCLUSION,
+            None => 0.0,
+        };
+        room = next_room;
+    }
+    occlusion
+}
+
 struct SDLAudioCallback {
     inner: AudioMixer,
 }
@@ -0,0 +1,367 @@
+//! Benchmark mode.
+//!
+//! Loads the benchmark campus provided by the base asset pack,
+//! moves the camera along a fixed path and records the
+//! performance of both the client and the local server. The
+//! results are saved to `./benchmarks/` and displayed once the
+//! run completes so that settings and builds can be compared.
+
+use std::cell::RefCell;
+use std::f32::consts::PI;
+use std::fs;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::time::{self, Duration};
+
+use serde_json;
+
+use crate::prelude::*;
+use super::{GameState, GameInstance};
+use crate::errors;
+use crate::instance;
+use crate::server;
+use crate::state;
+use crate::ui;
+
+/// The name of the save used for benchmarks. Removed before
+/// each run so that every run starts from the same state.
+const BENCHMARK_SAVE: &str = "missions/benchmark";
+const REPORT_FOLDER: &str = "./benchmarks";
+/// Time to wait after loading before recording begins
+const WARMUP: Duration = Duration::from_secs(3);
+/// The length of the recorded camera path
+const RUN_LENGTH: Duration = Duration::from_secs(60);
+/// How often the memory usage is sampled
+const MEMORY_SAMPLE_RATE: Duration = Duration::from_secs(1);
+
+/// Starts a benchmark run returning the state to switch to
+pub(crate) fn start(state: &mut GameState) -> errors::Result<instance::BaseState> {
+    let _ = server::saving::delete_save(&state.filesystem, BENCHMARK_SAVE);
+    let (instance, _hosted_server, tick_times) = GameInstance::benchmark(
+        &state.global_logger, &state.asset_manager,
+        #[cfg(feature = "steam")] state.steam.clone(),
+        BENCHMARK_SAVE.into(),
+        ResourceKey::new("base", "benchmark").into_owned(),
+    )?;
+    Ok(instance::BaseState::with_overlay(instance, Box::new(BenchmarkState::new(tick_times))))
+}
+
+/// Emitted once a benchmark completes to display the report
+pub(crate) struct ShowReport(pub Report);
+
+/// The results of a benchmark run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Report {
+    /// Seconds since the unix epoch when the run completed
+    pub timestamp: u64,
+    /// The number of frames rendered during the run
+    pub frames: usize,
+    /// The average frames per second
+    pub average_fps: f64,
+    /// The average frames per second of the slowest 1% of frames
+    pub one_percent_low_fps: f64,
+    /// The number of server ticks during the run
+    pub server_ticks: usize,
+    /// The average time taken by a server tick in milliseconds
+    pub server_tick_average_ms: f64,
+    /// The time 99% of server ticks completed within in milliseconds
+    pub server_tick_99th_ms: f64,
+    /// The slowest server tick in milliseconds
+    pub server_tick_max_ms: f64,
+    /// The peak resident memory of the process in megabytes if known
+    pub peak_memory_mb: Option<f64>,
+    /// The peak memory used by the server's scripts in megabytes
+    pub peak_script_memory_mb: f64,
+    /// The settings used for the run
+    pub settings: ReportSettings,
+}
+
+/// The settings that affect performance at the time of the run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ReportSettings {
+    pub resolution: (u32, u32),
+    pub target_fps: u32,
+    pub render_scale: f32,
+    pub render_shadow_res: u32,
+    pub render_ssao: u32,
+    pub render_fxaa: bool,
+    pub ui_scale: f32,
+}
+
+struct Recording {
+    tick_times: mpsc::Receiver<server::TickStats>,
+    started: Option<time::Instant>,
+    frame_times: Vec<f64>,
+    server_ticks: Vec<f64>,
+    next_memory_sample: Duration,
+    peak_memory: Option<u64>,
+    peak_script_memory: usize,
+    finished: bool,
+}
+
+/// Moves the camera along the benchmark path whilst recording
+/// frame and tick times.
+///
+/// Sits on top of the game's base state without taking
+/// focus so the game continues to run and render as normal.
+pub(crate) struct BenchmarkState {
+    recording: Rc<RefCell<Recording>>,
+}
+
+impl BenchmarkState {
+    fn new(tick_times: mpsc::Receiver<server::TickStats>) -> BenchmarkState {
+        BenchmarkState {
+            recording: Rc::new(RefCell::new(Recording {
+                tick_times,
+                started: None,
+                frame_times: Vec::with_capacity(60 * 60),
+                server_ticks: Vec::with_capacity(20 * 60),
+                next_memory_sample: Duration::from_secs(0),
+                peak_memory: None,
+                peak_script_memory: 0,
+                finished: false,
+            })),
+        }
+    }
+}
+
+impl state::State for BenchmarkState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(BenchmarkState {
+            recording: self.recording.clone(),
+        })
+    }
+
+    fn tick(&mut self, instance: &mut Option<GameInstance>, state: &mut GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let mut rec = self.recording.borrow_mut();
+        if rec.finished {
+            return state::Action::Nothing;
+        }
+
+        let started = *rec.started.get_or_insert_with(time::Instant::now);
+        let elapsed = started.elapsed();
+
+        // Update the camera even during the warmup so the
+        // first recorded frames aren't a jump to the path
+        let progress = if elapsed > WARMUP {
+            duration_secs(elapsed - WARMUP) / duration_secs(RUN_LENGTH)
+        } else {
+            0.0
+        };
+        let bounds = instance.level.level_bounds;
+        move_camera(&mut state.renderer, bounds, progress.min(1.0) as f32);
+
+        if elapsed <= WARMUP {
+            // Drop any ticks from loading
+            for _ in rec.tick_times.try_iter() {}
+            return state::Action::Nothing;
+        }
+
+        rec.frame_times.push(state.delta * (1000.0 / 60.0));
+        let ticks = rec.tick_times.try_iter()
+            .collect::<Vec<_>>();
+        for tick in ticks {
+            rec.server_ticks.push(duration_secs(tick.time) * 1000.0);
+            rec.peak_script_memory = rec.peak_script_memory.max(tick.script_memory);
+        }
+
+        let recorded = elapsed - WARMUP;
+        if recorded >= rec.next_memory_sample {
+            rec.next_memory_sample = recorded + MEMORY_SAMPLE_RATE;
+            if let Some(mem) = memory_usage() {
+                rec.peak_memory = Some(rec.peak_memory.map_or(mem, |v| v.max(mem)));
+            }
+        }
+
+        if recorded >= RUN_LENGTH {
+            rec.finished = true;
+            let report = build_report(&rec, state);
+            if let Err(err) = save_report(&report) {
+                error!(state.global_logger, "Failed to save the benchmark report: {:?}", err);
+            }
+            info!(state.global_logger, "Benchmark complete: {:#?}", report);
+            state.bus.emit(ShowReport(report));
+            instance.disconnect();
+        }
+        state::Action::Nothing
+    }
+}
+
+/// Moves the camera along the benchmark path.
+///
+/// The path circles the level once whilst rotating
+/// and zooming in and out twice to cover both dense
+/// and wide views.
+fn move_camera(renderer: &mut crate::render::Renderer, bounds: Bound, progress: f32) {
+    let cx = (bounds.min.x + bounds.max.x) as f32 / 2.0;
+    let cy = (bounds.min.y + bounds.max.y) as f32 / 2.0;
+    let radius = bounds.width().min(bounds.height()) as f32 / 3.0;
+    let angle = progress * PI * 2.0;
+    renderer.set_camera(
+        cx + angle.cos() * radius,
+        cy + angle.sin() * radius,
+    );
+    let zoom = 0.4 + (1.0 - (angle * 2.0).cos()) * 0.6;
+    renderer.set_camera_info(cgmath::Deg(progress * 360.0), zoom);
+}
+
+fn build_report(rec: &Recording, state: &GameState) -> Report {
+    let mut frame_times = rec.frame_times.clone();
+    frame_times.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    let total: f64 = frame_times.iter().sum();
+    let average_fps = if total > 0.0 {
+        frame_times.len() as f64 / (total / 1000.0)
+    } else {
+        0.0
+    };
+    // Frame times are sorted slowest first
+    let low_count = (frame_times.len() / 100).max(1).min(frame_times.len());
+    let low_total: f64 = frame_times[..low_count].iter().sum();
+    let one_percent_low_fps = if low_total > 0.0 {
+        low_count as f64 / (low_total / 1000.0)
+    } else {
+        0.0
+    };
+
+    let mut ticks = rec.server_ticks.clone();
+    ticks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let server_tick_average_ms = if ticks.is_empty() {
+        0.0
+    } else {
+        ticks.iter().sum::<f64>() / ticks.len() as f64
+    };
+    let server_tick_99th_ms = if ticks.is_empty() {
+        0.0
+    } else {
+        ticks[((ticks.len() - 1) * 99) / 100]
+    };
+
+    Report {
+        timestamp: time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|v| v.as_secs())
+            .unwrap_or(0),
+        frames: frame_times.len(),
+        average_fps,
+        one_percent_low_fps,
+        server_ticks: ticks.len(),
+        server_tick_average_ms,
+        server_tick_99th_ms,
+        server_tick_max_ms: ticks.last().cloned().unwrap_or(0.0),
+        peak_memory_mb: rec.peak_memory.map(|v| v as f64 / (1024.0 * 1024.0)),
+        peak_script_memory_mb: rec.peak_script_memory as f64 / (1024.0 * 1024.0),
+        settings: ReportSettings {
+            resolution: state.window.drawable_size(),
+            target_fps: state.config.target_fps.get(),
+            render_scale: state.config.render_scale.get(),
+            render_shadow_res: state.config.render_shadow_res.get(),
+            render_ssao: state.config.render_ssao.get(),
+            render_fxaa: state.config.render_fxaa.get(),
+            ui_scale: state.config.ui_scale.get(),
+        },
+    }
+}
+
+fn save_report(report: &Report) -> UResult<()> {
+    fs::create_dir_all(REPORT_FOLDER)?;
+    let f = fs::File::create(format!("{}/{}.json", REPORT_FOLDER, report.timestamp))?;
+    serde_json::to_writer_pretty(f, report)?;
+    Ok(())
+}
+
+fn duration_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1_000_000_000.0
+}
+
+/// Returns the resident memory usage of the process in bytes
+#[cfg(target_os = "linux")]
+fn memory_usage() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status.lines()
+        .find(|v| v.starts_with("VmRSS:"))
+        .and_then(|v| v.split_whitespace().nth(1))
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(|v| v * 1024)
+}
+
+/// Returns the resident memory usage of the process in bytes
+#[cfg(not(target_os = "linux"))]
+fn memory_usage() -> Option<u64> {
+    None
+}
+
+/// Displays the results of a benchmark run
+pub(crate) struct ReportState {
+    ui: Option<ui::Node>,
+    report: Report,
+}
+
+impl ReportState {
+    pub(crate) fn new(report: Report) -> ReportState {
+        ReportState {
+            ui: None,
+            report,
+        }
+    }
+}
+
+impl state::State for ReportState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(ReportState {
+            ui: self.ui.clone(),
+            report: self.report.clone(),
+        })
+    }
+
+    fn takes_focus(&self) -> bool { true }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) -> state::Action {
+        let node = state.ui_manager.create_node(ResourceKey::new("base", "menus/benchmark"));
+        let report = &self.report;
+        let settings = &report.settings;
+
+        if let Some(content) = query!(node, scroll_panel > content).next() {
+            let lines = vec![
+                ("Average FPS", format!("{:.1}", report.average_fps)),
+                ("1% low FPS", format!("{:.1}", report.one_percent_low_fps)),
+                ("Frames", report.frames.to_string()),
+                ("Server tick (average)", format!("{:.2}ms", report.server_tick_average_ms)),
+                ("Server tick (99%)", format!("{:.2}ms", report.server_tick_99th_ms)),
+                ("Server tick (max)", format!("{:.2}ms", report.server_tick_max_ms)),
+                ("Peak memory", report.peak_memory_mb
+                    .map_or_else(|| "Unknown".to_owned(), |v| format!("{:.0}MB", v))),
+                ("Peak script memory", format!("{:.1}MB", report.peak_script_memory_mb)),
+                ("Resolution", format!("{}x{}", settings.resolution.0, settings.resolution.1)),
+                ("Render scale", format!("{:.0}%", settings.render_scale * 100.0)),
+                ("Shadow resolution", settings.render_shadow_res.to_string()),
+                ("SSAO samples", settings.render_ssao.to_string()),
+                ("FXAA", if settings.render_fxaa { "On" } else { "Off" }.to_owned()),
+            ];
+            for (name, value) in lines {
+                content.add_child(node!{
+                    benchmark_entry {
+                        name {
+                            @text(name)
+                        }
+                        value {
+                            @text(value)
+                        }
+                    }
+                });
+            }
+        }
+        if let Some(txt) = query!(node, saved_to > @text).next() {
+            txt.set_text(format!("Saved to {}/{}.json", REPORT_FOLDER, report.timestamp));
+        }
+
+        self.ui = Some(node);
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) {
+        if let Some(node) = self.ui.take() {
+            state.ui_manager.remove_node(node);
+        }
+    }
+}
@@ -5,6 +5,7 @@ use crate::prelude::*;
 use super::{GameState, GameInstance};
 use crate::state;
 use crate::ui;
+use crate::loading;
 use crate::server::lua;
 use crate::server::common::MissionEntry;
 
@@ -122,13 +123,16 @@ impl state::State for MenuState {
                 } else {
                     let _ = server::saving::delete_save(&state.filesystem, &name);
                     let key = mission.get_name_key().into_owned();
-                    let (instance, _hosted_server) = GameInstance::single_player(
+                    let pending = GameInstance::start_single_player(
                         &state.global_logger, &state.asset_manager,
                         #[cfg(feature = "steam")] state.steam.clone(), name,
-                        Some(key)
-                    )
-                        .expect("Failed to connect to single player instance");
-                    action = state::Action::Switch(Box::new(crate::instance::BaseState::new(instance)));
+                        Some(key), state.config.construction_config(),
+                    state.config.save_on_exit.get()
+                    );
+                    action = state::Action::Switch(Box::new(loading::LoadingState::new(
+                        loading::LoadJob::Local(pending),
+                        |_| Box::new(MenuState::new()),
+                    )));
                 }
             }
         });
@@ -138,13 +142,16 @@ impl state::State for MenuState {
                 let mission = &self.missions[entry];
                 let key = mission.get_name_key().into_owned();
                 let name = format!("missions/{}", mission.save_key);
-                let (instance, _hosted_server) = GameInstance::single_player(
+                let pending = GameInstance::start_single_player(
                     &state.global_logger, &state.asset_manager,
                     #[cfg(feature = "steam")] state.steam.clone(), name,
-                    Some(key)
-                )
-                    .expect("Failed to connect to single player instance");
-                action = state::Action::Switch(Box::new(crate::instance::BaseState::new(instance)));
+                    Some(key), state.config.construction_config(),
+                    state.config.save_on_exit.get()
+                );
+                action = state::Action::Switch(Box::new(loading::LoadingState::new(
+                    loading::LoadJob::Local(pending),
+                    |_| Box::new(MenuState::new()),
+                )));
             }
         });
         evt.handle_event::<SelectEntry, _>(|SelectEntry(idx)| {
@@ -7,6 +7,10 @@ pub(super) struct GraphicsMenuState {
     current_shadow_res: i32,
     current_ssao: i32,
     current_fxaa: i32,
+    current_palette: i32,
+    current_high_contrast: i32,
+    current_reduce_effects: i32,
+    current_narration: i32,
     next_render_update: i32,
 }
 
@@ -19,6 +23,11 @@ struct GraphicsUI {
     fxaa: ui::Node,
     render_scale: ui::Node,
     ui_scale: ui::Node,
+    ui_text_scale: ui::Node,
+    high_contrast: ui::Node,
+    reduce_effects: ui::Node,
+    colour_palette: ui::Node,
+    narration: ui::Node,
 
     placement_valid: (ui::Node, ui::Node, ui::Node, ui::Node),
     placement_invalid: (ui::Node, ui::Node, ui::Node, ui::Node),
@@ -31,6 +40,10 @@ impl GraphicsMenuState {
             current_shadow_res: 0,
             current_ssao: 0,
             current_fxaa: 0,
+            current_palette: 0,
+            current_high_contrast: 0,
+            current_reduce_effects: 0,
+            current_narration: 0,
             paused,
             next_render_update: -1,
         }
@@ -44,6 +57,10 @@ impl state::State for GraphicsMenuState {
             current_shadow_res: self.current_shadow_res,
             current_ssao: self.current_ssao,
             current_fxaa: self.current_fxaa,
+            current_palette: self.current_palette,
+            current_high_contrast: self.current_high_contrast,
+            current_reduce_effects: self.current_reduce_effects,
+            current_narration: self.current_narration,
             paused: self.paused,
             next_render_update: self.next_render_update,
         })
@@ -90,6 +107,32 @@ impl state::State for GraphicsMenuState {
             .next());
         ui_scale.set_property("value", f64::from(state.config.ui_scale.get()));
 
+        let ui_text_scale = assume!(state.global_logger, query!(node, slider(id="ui_text_scale"))
+            .next());
+        ui_text_scale.set_property("value", f64::from(state.config.ui_text_scale.get()));
+
+        let high_contrast = assume!(state.global_logger, query!(node, dropdown(id="high_contrast")).next());
+        self.current_high_contrast = if state.config.ui_high_contrast.get() { 2 } else { 1 };
+        high_contrast.set_property("value", self.current_high_contrast);
+
+        let reduce_effects = assume!(state.global_logger, query!(node, dropdown(id="reduce_effects")).next());
+        self.current_reduce_effects = if state.config.reduce_effects.get() { 2 } else { 1 };
+        reduce_effects.set_property("value", self.current_reduce_effects);
+
+        let colour_palette = assume!(state.global_logger, query!(node, dropdown(id="colour_palette")).next());
+        let palette = state.config.colour_palette.get();
+        self.current_palette = ColourPalette::ALL.iter()
+            .position(|v| *v == palette)
+            .map_or(1, |v| v as i32 + 1);
+        colour_palette.set_property("value", self.current_palette);
+
+        let narration = assume!(state.global_logger, query!(node, dropdown(id="narration")).next());
+        let mode = state.config.narration.get();
+        self.current_narration = NarrationMode::ALL.iter()
+            .position(|v| *v == mode)
+            .map_or(1, |v| v as i32 + 1);
+        narration.set_property("value", self.current_narration);
+
         let (pr, pg, pb) = state.config.placement_valid_colour.get();
         let pv_r = assume!(state.global_logger, query!(node, slider(id="placement_valid_red"))
             .next());
@@ -127,6 +170,11 @@ impl state::State for GraphicsMenuState {
             fxaa,
             render_scale,
             ui_scale,
+            ui_text_scale,
+            high_contrast,
+            reduce_effects,
+            colour_palette,
+            narration,
             placement_valid,
             placement_invalid,
         });
@@ -184,6 +232,57 @@ impl state::State for GraphicsMenuState {
             self.next_render_update = 30;
         }
 
+        let ui_text_scale = ((ui.ui_text_scale.get_property::<f64>("value").unwrap_or(1.0) * 10.0).round() / 10.0) as f32;
+        if ui_text_scale != state.config.ui_text_scale.get() {
+            state.config.ui_text_scale.set(ui_text_scale);
+            self.next_render_update = 30;
+        }
+
+        let high_contrast = ui.high_contrast.get_property::<i32>("value")
+            .unwrap_or(1);
+        if high_contrast != self.current_high_contrast {
+            self.current_high_contrast = high_contrast;
+            state.config.ui_high_contrast.set(high_contrast == 2);
+            self.next_render_update = 30;
+        }
+
+        let reduce_effects = ui.reduce_effects.get_property::<i32>("value")
+            .unwrap_or(1);
+        if reduce_effects != self.current_reduce_effects {
+            self.current_reduce_effects = reduce_effects;
+            state.config.reduce_effects.set(reduce_effects == 2);
+        }
+
+        let palette = ui.colour_palette.get_property::<i32>("value")
+            .unwrap_or(1);
+        if palette != self.current_palette {
+            self.current_palette = palette;
+            let palette = ColourPalette::ALL.get((palette - 1).max(0) as usize)
+                .cloned()
+                .unwrap_or(ColourPalette::Standard);
+            state.config.colour_palette.set(palette);
+            // Reset the placement colours to the palette's defaults,
+            // they can still be customised afterwards.
+            for &(colour, nodes) in &[
+                (palette.placement_valid(), &ui.placement_valid),
+                (palette.placement_invalid(), &ui.placement_invalid),
+            ] {
+                nodes.1.set_property("value", i32::from(colour.0));
+                nodes.2.set_property("value", i32::from(colour.1));
+                nodes.3.set_property("value", i32::from(colour.2));
+            }
+        }
+
+        let narration = ui.narration.get_property::<i32>("value")
+            .unwrap_or(1);
+        if narration != self.current_narration {
+            self.current_narration = narration;
+            let mode = NarrationMode::ALL.get((narration - 1).max(0) as usize)
+                .cloned()
+                .unwrap_or(NarrationMode::Off);
+            state.config.narration.set(mode);
+        }
+
         let (pr, pg, pb) = state.config.placement_valid_colour.get();
         let pv_r = ui.placement_valid.1.get_property::<i32>("value").unwrap_or(0) as u8;
         let pv_g = ui.placement_valid.2.get_property::<i32>("value").unwrap_or(0) as u8;
@@ -210,7 +309,9 @@ impl state::State for GraphicsMenuState {
         if self.next_render_update == 0 {
             self.next_render_update = -1;
             state.renderer.set_ui_scale(1.0 / state.config.ui_scale.get());
+            state.renderer.set_ui_text_scale(state.config.ui_text_scale.get());
             state.ui_manager.set_ui_scale(1.0 / state.config.ui_scale.get());
+            state.ui_manager.load_pack_styles(state.config.ui_high_contrast.get());
             state.renderer.rebuild_pipeline();
         }
 
@@ -29,6 +29,12 @@ pub enum KeyCollection {
     /// The collection used when selecting a room to
     /// edit
     EditRoom,
+    /// The collection used when moving the build
+    /// cursor with the keyboard
+    BuildCursor,
+    /// The collection used when painting in the
+    /// level editor
+    LevelEditor,
 }
 
 impl KeyCollection {
@@ -43,6 +49,8 @@ impl KeyCollection {
             PlaceObject => "Place Object",
             PlaceStaff => "Place Staff",
             EditRoom => "Edit Room",
+            BuildCursor => "Build Cursor",
+            LevelEditor => "Level Editor",
         }
     }
 
@@ -58,6 +66,8 @@ impl KeyCollection {
             "Place Object" => Some(PlaceObject),
             "Place Staff" => Some(PlaceStaff),
             "Edit Room" => Some(EditRoom),
+            "Build Cursor" => Some(BuildCursor),
+            "Level Editor" => Some(LevelEditor),
             _ => None,
         }
     }
@@ -71,6 +81,16 @@ pub enum KeyAction {
     SystemMenu,
     /// Begins a chat message
     BeginChat,
+    /// Opens photo mode
+    PhotoMode,
+    /// Shows or hides the performance hud
+    TogglePerfHud,
+    /// Shows or hides the connection statistics
+    ToggleNetStats,
+    /// Starts transmitting voice to other players
+    PushToTalk,
+    /// Stops transmitting voice to other players
+    PushToTalkStop,
 
     // Render actions
     /// Requests that the renderer zooms in
@@ -98,6 +118,9 @@ pub enum KeyAction {
     RenderCameraDown,
     /// Stops moving the camera up
     RenderCameraDownStop,
+    /// Toggles whether the camera is limited to the
+    /// player's campus in multiplayer
+    RenderCameraFreeRoam,
 
     // Room actions
     /// Starts selecting at the mouse's current position
@@ -110,6 +133,22 @@ pub enum KeyAction {
     RoomStartRoomResize,
     /// Stops resizing the current room
     RoomFinishRoomResize,
+    /// Moves the room being built one tile left
+    RoomMoveLeft,
+    /// Moves the room being built one tile right
+    RoomMoveRight,
+    /// Moves the room being built one tile up
+    RoomMoveUp,
+    /// Moves the room being built one tile down
+    RoomMoveDown,
+    /// Makes the room being built one tile wider
+    RoomGrowWidth,
+    /// Makes the room being built one tile narrower
+    RoomShrinkWidth,
+    /// Makes the room being built one tile taller
+    RoomGrowHeight,
+    /// Makes the room being built one tile shorter
+    RoomShrinkHeight,
 
     // Placement actions
     /// Places the active object/entity
@@ -130,9 +169,58 @@ pub enum KeyAction {
     /// Inspect's a student or staff member
     /// under the mouse
     InspectMember,
-
+    /// Starts dragging a box to select staff
+    /// members within
+    SelectStart,
+    /// Starts adding to the current selection
+    /// instead of replacing it
+    SelectAdd,
+    /// Stops adding to the current selection
+    SelectAddStop,
+    /// Orders the selected staff members to the
+    /// mouse's location
+    OrderSelected,
+    /// Starts storing the selection into control
+    /// groups instead of selecting them
+    ControlGroupAssign,
+    /// Stops storing the selection into control
+    /// groups
+    ControlGroupAssignStop,
+    /// Selects the numbered control group
+    ControlGroup(u8),
+
+    // Keyboard build actions
+    /// Moves the build cursor one tile left
+    BuildCursorLeft,
+    /// Moves the build cursor one tile right
+    BuildCursorRight,
+    /// Moves the build cursor one tile up
+    BuildCursorUp,
+    /// Moves the build cursor one tile down
+    BuildCursorDown,
+    /// Starts/finishes a selection (or placement) at
+    /// the build cursor
+    BuildCursorConfirm,
+    /// Cancels the current selection or leaves the
+    /// build mode
+    BuildCursorCancel,
+
+    // Level editor actions
+    /// Paints props with the scatter brush at the
+    /// mouse's location
+    ScatterPaint,
+    /// Erases the scatter brush strokes at the mouse's
+    /// location
+    ScatterErase,
 }
 
+/// The names of the control groups as used in the config
+const CONTROL_GROUP_NAMES: [&str; 9] = [
+    "Control Group 1", "Control Group 2", "Control Group 3",
+    "Control Group 4", "Control Group 5", "Control Group 6",
+    "Control Group 7", "Control Group 8", "Control Group 9",
+];
+
 impl KeyAction {
     // Helpers for the keybinds UI
 
@@ -153,19 +241,44 @@ impl KeyAction {
             | RenderCameraRight
             | RenderCameraUp
             | RenderCameraDown
+            | PushToTalk
+            | SelectAdd
+            | ControlGroupAssign
             | RoomStartAreaSelect
             | RoomStartRoomResize
-            | PlacementDragStart => Some(false),
+            | PlacementDragStart
+            | BuildCursorLeft
+            | BuildCursorRight
+            | BuildCursorUp
+            | BuildCursorDown => Some(false),
             SystemMenu
             | BeginChat
+            | PhotoMode
+            | TogglePerfHud
+            | ToggleNetStats
             | RenderRotateLeft
             | RenderRotateRight
+            | RenderCameraFreeRoam
             | SelectEditRoom
             | PlacementMove
             | PlacementRemove
             | PlacementFinish
             | PlacementRotate
-            | InspectMember => Some(true),
+            | RoomMoveLeft
+            | RoomMoveRight
+            | RoomMoveUp
+            | RoomMoveDown
+            | RoomGrowWidth
+            | RoomShrinkWidth
+            | RoomGrowHeight
+            | RoomShrinkHeight
+            | InspectMember
+            | OrderSelected
+            | ControlGroup(_)
+            | BuildCursorConfirm
+            | BuildCursorCancel
+            | ScatterPaint
+            | ScatterErase => Some(true),
             _ => None,
         }
     }
@@ -179,9 +292,13 @@ impl KeyAction {
             RenderCameraRight => Some(RenderCameraRightStop),
             RenderCameraUp => Some(RenderCameraUpStop),
             RenderCameraDown => Some(RenderCameraDownStop),
+            PushToTalk => Some(PushToTalkStop),
             RoomStartAreaSelect => Some(RoomFinishAreaSelect),
             RoomStartRoomResize => Some(RoomFinishRoomResize),
             PlacementFinish => Some(PlacementDragStart),
+            InspectMember => Some(SelectStart),
+            SelectAdd => Some(SelectAddStop),
+            ControlGroupAssign => Some(ControlGroupAssignStop),
             _ => None,
         }
     }
@@ -194,9 +311,13 @@ impl KeyAction {
             | RenderCameraRightStop
             | RenderCameraUpStop
             | RenderCameraDownStop
+            | PushToTalkStop
             | RoomFinishAreaSelect
             | RoomFinishRoomResize
             | PlacementDragStart
+            | SelectStart
+            | SelectAddStop
+            | ControlGroupAssignStop
               => true,
             _ => false,
         }
@@ -207,6 +328,11 @@ impl KeyAction {
         match self {
             SystemMenu => "Opens the system menu allowing you to save/exit a game. Pauses in single player",
             BeginChat => "Begins a chat message",
+            PhotoMode => "Opens photo mode allowing you to frame and capture a screenshot. Pauses in single player",
+            TogglePerfHud => "Shows where the time of each frame is spent and, when hosting, the server's tick timings",
+            ToggleNetStats => "Shows the round trip time, packet loss and traffic of your connection to the server",
+            PushToTalk => "Starts transmitting your voice to the other players whilst held",
+            PushToTalkStop => "Stops transmitting your voice to the other players",
             RenderZoomIn => "Causes the #camera# to zoom in",
             RenderZoomOut => "Causes the #camera# to zoom out",
             RenderRotateLeft => "Rotates the #camera# to the left",
@@ -219,18 +345,42 @@ impl KeyAction {
             RenderCameraUpStop => "Stops the #camera# from moving up if it was currently moving",
             RenderCameraDown => "Causes the #camera# to start moving down",
             RenderCameraDownStop => "Stops the #camera# from moving down if it was currently moving",
+            RenderCameraFreeRoam => "Toggles whether the #camera# can leave your campus in multiplayer",
             RoomStartAreaSelect => "Starts selecting an area for a *building* or *room*",
             RoomStartAreaSelectStop => "Stops selecting an area for a *building* or *room*",
             RoomFinishAreaSelect => "Finishes selecting an area for a *building* or *room*",
             RoomStartRoomResize => "Starts resizing a *building* or *room*",
             RoomFinishRoomResize => "Stops resizing a *building* or *room*",
+            RoomMoveLeft => "Moves the *room* being built one tile to the left",
+            RoomMoveRight => "Moves the *room* being built one tile to the right",
+            RoomMoveUp => "Moves the *room* being built one tile up",
+            RoomMoveDown => "Moves the *room* being built one tile down",
+            RoomGrowWidth => "Makes the *room* being built one tile wider",
+            RoomShrinkWidth => "Makes the *room* being built one tile narrower",
+            RoomGrowHeight => "Makes the *room* being built one tile taller",
+            RoomShrinkHeight => "Makes the *room* being built one tile shorter",
             PlacementFinish => "Finishes placing an *object* or *staff* member",
             PlacementDragStart => "Starts placing an *object* if it can be drag placed",
             PlacementRotate => "Rotates an *object*",
             PlacementMove => "Starts moving a placed *object*",
             PlacementRemove => "Removes a placed *object*",
             SelectEditRoom => "Begins editting a placed *building* or *room*",
-            InspectMember => "Inspects the *student* or *staff* member under the mouse",
+            InspectMember => "Inspects the *student*, *staff* member or *room* under the mouse, or drags a box to select *staff*",
+            SelectStart => "Starts dragging a box to select *staff*",
+            SelectAdd => "Adds to the selected *staff* instead of replacing them whilst held",
+            SelectAddStop => "Stops adding to the selected *staff*",
+            OrderSelected => "Orders the selected *staff* to the mouse's location",
+            ControlGroupAssign => "Stores the selected *staff* into a control group instead of selecting it whilst held",
+            ControlGroupAssignStop => "Stops storing the selected *staff* into control groups",
+            ControlGroup(_) => "Selects the *staff* in the control group",
+            BuildCursorLeft => "Moves the build cursor one tile to the left",
+            BuildCursorRight => "Moves the build cursor one tile to the right",
+            BuildCursorUp => "Moves the build cursor one tile up",
+            BuildCursorDown => "Moves the build cursor one tile down",
+            BuildCursorConfirm => "Starts or finishes selecting an area for a *building* or *room* at the build cursor",
+            BuildCursorCancel => "Cancels the current selection or stops building",
+            ScatterPaint => "Paints props with the scatter brush in the level editor",
+            ScatterErase => "Erases the props painted under the mouse in the level editor",
         }
     }
 
@@ -239,6 +389,11 @@ impl KeyAction {
         match self {
             SystemMenu => "System Menu",
             BeginChat => "Begin Chat",
+            PhotoMode => "Photo Mode",
+            TogglePerfHud => "Performance HUD",
+            ToggleNetStats => "Connection Statistics",
+            PushToTalk => "Push To Talk",
+            PushToTalkStop => "Push To Talk Stop",
             RenderZoomIn => "Zoom In",
             RenderZoomOut => "Zoom Out",
             RenderRotateLeft => "Rotate Left",
@@ -251,11 +406,20 @@ impl KeyAction {
             RenderCameraUpStop => "Camera Up Stop",
             RenderCameraDown => "Camera Down",
             RenderCameraDownStop => "Camera Down Stop",
+            RenderCameraFreeRoam => "Camera Free Roam",
             RoomStartAreaSelect => "Start Area Select",
             RoomStartAreaSelectStop => "Stop Area Select",
             RoomFinishAreaSelect => "Finish Area Select",
             RoomStartRoomResize => "Start Room Resize",
             RoomFinishRoomResize => "Finish Room Resize",
+            RoomMoveLeft => "Room Move Left",
+            RoomMoveRight => "Room Move Right",
+            RoomMoveUp => "Room Move Up",
+            RoomMoveDown => "Room Move Down",
+            RoomGrowWidth => "Room Grow Width",
+            RoomShrinkWidth => "Room Shrink Width",
+            RoomGrowHeight => "Room Grow Height",
+            RoomShrinkHeight => "Room Shrink Height",
             PlacementDragStart => "Placement Drag Start",
             PlacementFinish => "Placement Finish",
             PlacementRotate => "Placement Rotate",
@@ -263,6 +427,21 @@ impl KeyAction {
             PlacementRemove => "Placement Remove",
             SelectEditRoom => "Select Edit Room",
             InspectMember => "Inspect Member",
+            SelectStart => "Select Start",
+            SelectAdd => "Select Add",
+            SelectAddStop => "Select Add Stop",
+            OrderSelected => "Order Selected",
+            ControlGroupAssign => "Control Group Assign",
+            ControlGroupAssignStop => "Control Group Assign Stop",
+            ControlGroup(idx) => CONTROL_GROUP_NAMES.get(idx as usize).cloned().unwrap_or("Control Group"),
+            BuildCursorLeft => "Build Cursor Left",
+            BuildCursorRight => "Build Cursor Right",
+            BuildCursorUp => "Build Cursor Up",
+            BuildCursorDown => "Build Cursor Down",
+            BuildCursorConfirm => "Build Cursor Confirm",
+            BuildCursorCancel => "Build Cursor Cancel",
+            ScatterPaint => "Scatter Paint",
+            ScatterErase => "Scatter Erase",
         }
     }
 
@@ -271,6 +450,11 @@ impl KeyAction {
         match val {
             "System Menu" => Some(SystemMenu),
             "Begin Chat" => Some(BeginChat),
+            "Photo Mode" => Some(PhotoMode),
+            "Performance HUD" => Some(TogglePerfHud),
+            "Connection Statistics" => Some(ToggleNetStats),
+            "Push To Talk" => Some(PushToTalk),
+            "Push To Talk Stop" => Some(PushToTalkStop),
             "Zoom In" => Some(RenderZoomIn),
             "Zoom Out" => Some(RenderZoomOut),
             "Rotate Left" => Some(RenderRotateLeft),
@@ -283,11 +467,20 @@ impl KeyAction {
             "Camera Up Stop" => Some(RenderCameraUpStop),
             "Camera Down" => Some(RenderCameraDown),
             "Camera Down Stop" => Some(RenderCameraDownStop),
+            "Camera Free Roam" => Some(RenderCameraFreeRoam),
             "Start Area Select" => Some(RoomStartAreaSelect),
             "Stop Area Select" => Some(RoomStartAreaSelectStop),
             "Finish Area Select" => Some(RoomFinishAreaSelect),
             "Start Room Resize" => Some(RoomStartRoomResize),
             "Finish Room Resize" => Some(RoomFinishRoomResize),
+            "Room Move Left" => Some(RoomMoveLeft),
+            "Room Move Right" => Some(RoomMoveRight),
+            "Room Move Up" => Some(RoomMoveUp),
+            "Room Move Down" => Some(RoomMoveDown),
+            "Room Grow Width" => Some(RoomGrowWidth),
+            "Room Shrink Width" => Some(RoomShrinkWidth),
+            "Room Grow Height" => Some(RoomGrowHeight),
+            "Room Shrink Height" => Some(RoomShrinkHeight),
             "Placement Drag Start" => Some(PlacementDragStart),
             "Placement Finish" => Some(PlacementFinish),
             "Placement Rotate" => Some(PlacementRotate),
@@ -295,7 +488,23 @@ impl KeyAction {
             "Placement Remove" => Some(PlacementRemove),
             "Select Edit Room" => Some(SelectEditRoom),
             "Inspect Member" => Some(InspectMember),
-            _ => None,
+            "Select Start" => Some(SelectStart),
+            "Select Add" => Some(SelectAdd),
+            "Select Add Stop" => Some(SelectAddStop),
+            "Order Selected" => Some(OrderSelected),
+            "Control Group Assign" => Some(ControlGroupAssign),
+            "Control Group Assign Stop" => Some(ControlGroupAssignStop),
+            "Build Cursor Left" => Some(BuildCursorLeft),
+            "Build Cursor Right" => Some(BuildCursorRight),
+            "Build Cursor Up" => Some(BuildCursorUp),
+            "Build Cursor Down" => Some(BuildCursorDown),
+            "Build Cursor Confirm" => Some(BuildCursorConfirm),
+            "Build Cursor Cancel" => Some(BuildCursorCancel),
+            "Scatter Paint" => Some(ScatterPaint),
+            "Scatter Erase" => Some(ScatterErase),
+            val => CONTROL_GROUP_NAMES.iter()
+                .position(|v| *v == val)
+                .map(|v| ControlGroup(v as u8)),
         }
     }
 }
@@ -422,6 +631,8 @@ impl BindTransformer {
         binds.def_staff_placement(&config);
         binds.def_room_build(&config);
         binds.def_edit_room(&config);
+        binds.def_build_cursor(&config);
+        binds.def_level_editor(&config);
         binds
     }
 
@@ -548,7 +759,8 @@ impl BindTransformer {
 
     // Keybinds for the default state
     fn def_root(&mut self, config: &ConfigMap) {
-        let binds = BindCollection::default();
+        let mut binds = BindCollection::default();
+        binds.set_bind(BindType::Key(Keycode::V), Some(KeyAction::PushToTalk), Some(KeyAction::PushToTalkStop));
         self.load_collection(config, KeyCollection::Root, binds);
     }
 
@@ -558,12 +770,26 @@ impl BindTransformer {
 
         binds.set_bind(BindType::Key(Keycode::Escape), None, Some(KeyAction::SystemMenu));
         binds.set_bind(BindType::Key(Keycode::Return), None, Some(KeyAction::BeginChat));
+        binds.set_bind(BindType::Key(Keycode::P), None, Some(KeyAction::PhotoMode));
+        binds.set_bind(BindType::Key(Keycode::F3), None, Some(KeyAction::TogglePerfHud));
+        binds.set_bind(BindType::Key(Keycode::F4), None, Some(KeyAction::ToggleNetStats));
 
         binds.set_bind(BindType::MouseWheel(true), Some(KeyAction::RenderZoomIn), None);
         binds.set_bind(BindType::MouseWheel(false), Some(KeyAction::RenderZoomOut), None);
         binds.set_bind(BindType::Key(Keycode::PageDown), None, Some(KeyAction::RenderRotateLeft));
         binds.set_bind(BindType::Key(Keycode::PageUp), None, Some(KeyAction::RenderRotateRight));
-        binds.set_bind(BindType::Mouse(MouseButton::Left), None, Some(KeyAction::InspectMember));
+        binds.set_bind(BindType::Key(Keycode::Home), None, Some(KeyAction::RenderCameraFreeRoam));
+        binds.set_bind(BindType::Mouse(MouseButton::Left), Some(KeyAction::SelectStart), Some(KeyAction::InspectMember));
+        binds.set_bind(BindType::Mouse(MouseButton::Right), None, Some(KeyAction::OrderSelected));
+        binds.set_bind(BindType::Key(Keycode::LShift), Some(KeyAction::SelectAdd), Some(KeyAction::SelectAddStop));
+        binds.set_bind(BindType::Key(Keycode::LCtrl), Some(KeyAction::ControlGroupAssign), Some(KeyAction::ControlGroupAssignStop));
+        for (idx, key) in [
+            Keycode::Num1, Keycode::Num2, Keycode::Num3,
+            Keycode::Num4, Keycode::Num5, Keycode::Num6,
+            Keycode::Num7, Keycode::Num8, Keycode::Num9,
+        ].iter().enumerate() {
+            binds.set_bind(BindType::Key(*key), None, Some(KeyAction::ControlGroup(idx as u8)));
+        }
 
         // Camera controls
         for &(a, b, action, stop) in &[
@@ -614,6 +840,18 @@ impl BindTransformer {
             None,
             Some(KeyAction::PlacementRemove)
         );
+        for &(key, action) in &[
+            (Keycode::J, KeyAction::RoomMoveLeft),
+            (Keycode::L, KeyAction::RoomMoveRight),
+            (Keycode::I, KeyAction::RoomMoveUp),
+            (Keycode::K, KeyAction::RoomMoveDown),
+            (Keycode::H, KeyAction::RoomGrowWidth),
+            (Keycode::F, KeyAction::RoomShrinkWidth),
+            (Keycode::G, KeyAction::RoomGrowHeight),
+            (Keycode::T, KeyAction::RoomShrinkHeight),
+        ] {
+            binds.set_bind(BindType::Key(key), None, Some(action));
+        }
         self.load_collection(config, KeyCollection::BuildRoom, binds);
     }
 
@@ -652,4 +890,32 @@ impl BindTransformer {
 
         self.load_collection(config, KeyCollection::EditRoom, binds);
     }
+
+    // Keybinds for building with the keyboard
+    fn def_build_cursor(&mut self, config: &ConfigMap) {
+        let mut binds = BindCollection::default();
+
+        for &(key, action) in &[
+            (Keycode::Left, KeyAction::BuildCursorLeft),
+            (Keycode::Right, KeyAction::BuildCursorRight),
+            (Keycode::Up, KeyAction::BuildCursorUp),
+            (Keycode::Down, KeyAction::BuildCursorDown),
+        ] {
+            binds.set_bind(BindType::Key(key), Some(action), None);
+        }
+        binds.set_bind(BindType::Key(Keycode::Space), None, Some(KeyAction::BuildCursorConfirm));
+        binds.set_bind(BindType::Key(Keycode::Backspace), None, Some(KeyAction::BuildCursorCancel));
+
+        self.load_collection(config, KeyCollection::BuildCursor, binds);
+    }
+
+    // Keybinds for painting in the level editor
+    fn def_level_editor(&mut self, config: &ConfigMap) {
+        let mut binds = BindCollection::default();
+
+        binds.set_bind(BindType::Mouse(MouseButton::Left), None, Some(KeyAction::ScatterPaint));
+        binds.set_bind(BindType::Mouse(MouseButton::Right), None, Some(KeyAction::ScatterErase));
+
+        self.load_collection(config, KeyCollection::LevelEditor, binds);
+    }
 }
\ No newline at end of file
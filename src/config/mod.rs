@@ -14,6 +14,10 @@ use crate::state;
 use crate::GameState;
 use crate::instance::GameInstance;
 use crate::server::event;
+use crate::render::palette::ColourPalette;
+use crate::render::backend::BackendKind;
+use crate::narration::NarrationMode;
+use crate::ui::HudLayouts;
 use sdl2::video::FullscreenType;
 use sdl2;
 
@@ -23,6 +27,8 @@ pub struct Config {
     pub music_volume: Cell<f64>,
     /// The volume level (0.0, 1.0) of music
     pub sound_volume: Cell<f64>,
+    /// The volume level (0.0, 1.0) of other players' voices
+    pub voice_volume: Cell<f64>,
     /// The target fps for the game to run at
     pub target_fps: Cell<u32>,
     /// Sets the mode of the game's window
@@ -30,12 +36,22 @@ pub struct Config {
     /// Sets the resolution of the game when fullscreen
     pub fullscreen_res: Cell<(u32, u32)>,
 
+    /// The graphics API used to render the game.
+    ///
+    /// Only takes effect after a restart
+    pub render_backend: Cell<BackendKind>,
     /// The size of the shadow texture
     pub render_shadow_res: Cell<u32>,
     /// The number of SSAO samples
     pub render_ssao: Cell<u32>,
     /// Whether to use fxaa or not
     pub render_fxaa: Cell<bool>,
+    /// Whether to select entities by what is drawn under
+    /// the cursor instead of by their bounds
+    pub render_gpu_picking: Cell<bool>,
+    /// Whether to bake ambient occlusion and bounce lighting
+    /// into the level
+    pub render_baked_lighting: Cell<bool>,
     /// The scale of the render output.
     ///
     /// e.g. 0.5 means to render at half the normal size
@@ -43,6 +59,43 @@ pub struct Config {
     pub render_scale: Cell<f32>,
     /// The scale of the UI.
     pub ui_scale: Cell<f32>,
+    /// The scale of text in the UI, applied on top of the
+    /// UI scale.
+    pub ui_text_scale: Cell<f32>,
+    /// Whether to load the high contrast UI styles
+    pub ui_high_contrast: Cell<bool>,
+    /// Whether screen shake, flashes and confetti played by
+    /// missions are toned down
+    pub reduce_effects: Cell<bool>,
+    /// The palette used for colours that carry meaning
+    pub colour_palette: Cell<ColourPalette>,
+    /// Where descriptions of the focused element are sent
+    /// for screen readers
+    pub narration: Cell<NarrationMode>,
+
+    /// Whether the camera moves when the mouse is at the
+    /// edge of the window
+    pub camera_edge_scroll: Cell<bool>,
+    /// Multiplier for the speed the camera pans at
+    pub camera_pan_speed: Cell<f32>,
+    /// Multiplier for the speed the camera zooms at
+    pub camera_zoom_speed: Cell<f32>,
+    /// Whether panning moves the camera in the opposite direction
+    pub camera_invert_pan: Cell<bool>,
+    /// Whether zooming in and out are swapped
+    pub camera_invert_zoom: Cell<bool>,
+    /// Whether zooming keeps the point under the cursor in place
+    pub camera_zoom_to_cursor: Cell<bool>,
+    /// Whether the camera may leave the player's campus in
+    /// multiplayer
+    pub camera_free_roam: Cell<bool>,
+
+    /// Whether rooms in games started by this player open as
+    /// soon as they are placed instead of being built over time
+    pub instant_construction: Cell<bool>,
+    /// Whether games started by this player are saved when
+    /// they are closed instead of only by the autosave
+    pub save_on_exit: Cell<bool>,
 
     /// The colour of the placement grid when valid
     pub placement_valid_colour: Cell<(u8, u8, u8)>,
@@ -50,38 +103,104 @@ pub struct Config {
     pub placement_invalid_colour: Cell<(u8, u8, u8)>,
     /// The additional asset packs to load
     pub asset_packs: RefCell<Vec<String>>,
+    /// The player's arrangements of the HUD panels
+    pub hud_layouts: RefCell<HudLayouts>,
+    /// The install time of each subscribed workshop item when
+    /// its latest update was last accepted
+    pub workshop_versions: RefCell<FNVMap<u64, u32>>,
+    /// Workshop items whose latest update is left disabled until
+    /// the player enables it
+    pub postponed_workshop_items: RefCell<Vec<u64>>,
+    /// The log levels used when the game starts, e.g.
+    /// `info,univercity_server::network=debug`
+    pub log_filter: RefCell<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct ConfigFormat {
     music_volume: f64,
     sound_volume: f64,
+    #[serde(default = "voice_volume_default")]
+    voice_volume: f64,
     target_fps: u32,
     fullscreen_mode: String,
     fullscreen_res: (u32, u32),
+    #[serde(default = "render_backend_default")]
+    render_backend: String,
     #[serde(default = "shadow_default")]
     render_shadow_res: u32,
     #[serde(default = "ssao_default")]
     render_ssao: u32,
     #[serde(default = "fxaa_default")]
     render_fxaa: bool,
+    #[serde(default = "gpu_picking_default")]
+    render_gpu_picking: bool,
+    #[serde(default = "baked_lighting_default")]
+    render_baked_lighting: bool,
     #[serde(default = "render_scale_default")]
     render_scale: f32,
     #[serde(default = "ui_scale_default")]
     ui_scale: f32,
+    #[serde(default = "ui_scale_default")]
+    ui_text_scale: f32,
+    #[serde(default)]
+    ui_high_contrast: bool,
+    #[serde(default)]
+    reduce_effects: bool,
+    #[serde(default = "colour_palette_default")]
+    colour_palette: String,
+    #[serde(default = "narration_default")]
+    narration: String,
+    #[serde(default = "edge_scroll_default")]
+    camera_edge_scroll: bool,
+    #[serde(default = "camera_speed_default")]
+    camera_pan_speed: f32,
+    #[serde(default = "camera_speed_default")]
+    camera_zoom_speed: f32,
+    #[serde(default)]
+    camera_invert_pan: bool,
+    #[serde(default)]
+    camera_invert_zoom: bool,
+    #[serde(default = "zoom_to_cursor_default")]
+    camera_zoom_to_cursor: bool,
+    #[serde(default)]
+    camera_free_roam: bool,
+    #[serde(default)]
+    instant_construction: bool,
+    #[serde(default = "save_on_exit_default")]
+    save_on_exit: bool,
     #[serde(default = "placement_valid_def")]
     placement_valid_colour: (u8, u8, u8),
     #[serde(default = "placement_invalid_def")]
     placement_invalid_colour: (u8, u8, u8),
     #[serde(default)]
     asset_packs: Vec<String>,
+    #[serde(default)]
+    hud_layouts: HudLayouts,
+    #[serde(default)]
+    workshop_versions: FNVMap<u64, u32>,
+    #[serde(default)]
+    postponed_workshop_items: Vec<u64>,
+    #[serde(default = "log_filter_default")]
+    log_filter: String,
 }
 
+fn voice_volume_default() -> f64 { 1.0 }
+fn render_backend_default() -> String { BackendKind::OpenGL.as_str().to_owned() }
 fn shadow_default() -> u32 { 2048 }
 fn ssao_default() -> u32 { 16 }
 fn fxaa_default() -> bool { true }
+fn gpu_picking_default() -> bool { true }
+fn baked_lighting_default() -> bool { true }
 fn render_scale_default() -> f32 { 1.0 }
 fn ui_scale_default() -> f32 { 1.0 }
+fn colour_palette_default() -> String { ColourPalette::Standard.as_str().to_owned() }
+fn narration_default() -> String { NarrationMode::Off.as_str().to_owned() }
+fn edge_scroll_default() -> bool { true }
+fn camera_speed_default() -> f32 { 1.0 }
+fn zoom_to_cursor_default() -> bool { true }
+fn log_filter_default() -> String { "info".to_owned() }
+fn save_on_exit_default() -> bool { true }
 
 fn placement_valid_def() -> (u8, u8, u8) { (46, 65, 114) }
 fn placement_invalid_def() -> (u8, u8, u8) { (170, 57, 57) }
@@ -95,17 +214,39 @@ impl Config {
         Rc::new(Config {
             music_volume: Cell::new(0.5),
             sound_volume: Cell::new(1.0),
+            voice_volume: Cell::new(voice_volume_default()),
             target_fps: Cell::new(60),
             fullscreen_mode: Cell::new(FullscreenType::Off),
             fullscreen_res: Cell::new(res),
+            render_backend: Cell::new(BackendKind::OpenGL),
             render_shadow_res: Cell::new(2048),
             render_ssao: Cell::new(16),
             render_fxaa: Cell::new(true),
+            render_gpu_picking: Cell::new(true),
+            render_baked_lighting: Cell::new(true),
             render_scale: Cell::new(1.0),
             ui_scale: Cell::new(1.0),
+            ui_text_scale: Cell::new(1.0),
+            ui_high_contrast: Cell::new(false),
+            reduce_effects: Cell::new(false),
+            colour_palette: Cell::new(ColourPalette::Standard),
+            narration: Cell::new(NarrationMode::Off),
+            camera_edge_scroll: Cell::new(true),
+            camera_pan_speed: Cell::new(1.0),
+            camera_zoom_speed: Cell::new(1.0),
+            camera_invert_pan: Cell::new(false),
+            camera_invert_zoom: Cell::new(false),
+            camera_zoom_to_cursor: Cell::new(true),
+            camera_free_roam: Cell::new(false),
+            instant_construction: Cell::new(false),
+            save_on_exit: Cell::new(save_on_exit_default()),
             placement_valid_colour: Cell::new(placement_valid_def()),
             placement_invalid_colour: Cell::new(placement_invalid_def()),
             asset_packs: RefCell::new(Vec::new()),
+            hud_layouts: RefCell::new(HudLayouts::default()),
+            workshop_versions: RefCell::new(FNVMap::default()),
+            postponed_workshop_items: RefCell::new(Vec::new()),
+            log_filter: RefCell::new(log_filter_default()),
         })
     }
 
@@ -118,29 +259,61 @@ impl Config {
 
         self.music_volume.set(config.music_volume);
         self.sound_volume.set(config.sound_volume);
+        self.voice_volume.set(config.voice_volume);
         self.target_fps.set(config.target_fps);
         self.fullscreen_mode.set(match config.fullscreen_mode.as_str() {
             "borderless" => FullscreenType::Desktop,
             _ => FullscreenType::Off,
         });
         self.fullscreen_res.set(config.fullscreen_res);
+        self.render_backend.set(BackendKind::from_str(&config.render_backend));
         self.render_shadow_res.set(config.render_shadow_res);
         self.render_ssao.set(config.render_ssao);
         self.render_fxaa.set(config.render_fxaa);
+        self.render_gpu_picking.set(config.render_gpu_picking);
+        self.render_baked_lighting.set(config.render_baked_lighting);
         self.render_scale.set(config.render_scale);
         self.placement_valid_colour.set(config.placement_valid_colour);
         self.placement_invalid_colour.set(config.placement_invalid_colour);
         self.ui_scale.set(config.ui_scale.max(0.1));
+        self.ui_text_scale.set(config.ui_text_scale.max(0.5).min(3.0));
+        self.ui_high_contrast.set(config.ui_high_contrast);
+        self.reduce_effects.set(config.reduce_effects);
+        self.colour_palette.set(ColourPalette::from_str(&config.colour_palette));
+        self.narration.set(NarrationMode::from_str(&config.narration));
+        self.camera_edge_scroll.set(config.camera_edge_scroll);
+        self.camera_pan_speed.set(config.camera_pan_speed.max(0.1).min(5.0));
+        self.camera_zoom_speed.set(config.camera_zoom_speed.max(0.1).min(5.0));
+        self.camera_invert_pan.set(config.camera_invert_pan);
+        self.camera_invert_zoom.set(config.camera_invert_zoom);
+        self.camera_zoom_to_cursor.set(config.camera_zoom_to_cursor);
+        self.camera_free_roam.set(config.camera_free_roam);
+        self.instant_construction.set(config.instant_construction);
+        self.save_on_exit.set(config.save_on_exit);
         self.asset_packs.replace(config.asset_packs);
+        self.hud_layouts.replace(config.hud_layouts);
+        self.workshop_versions.replace(config.workshop_versions);
+        self.postponed_workshop_items.replace(config.postponed_workshop_items);
+        self.log_filter.replace(config.log_filter);
         Ok(())
     }
 
+    /// Returns how long rooms take to build in games started
+    /// by this player
+    pub fn construction_config(&self) -> crate::server::entity::construction::ConstructionConfig {
+        crate::server::entity::construction::ConstructionConfig {
+            instant: self.instant_construction.get(),
+            .. Default::default()
+        }
+    }
+
     /// Tries to save the configuration from the default location
     pub fn save(&self) -> UResult<()> {
         let f = File::create("./config.json")?;
         serde_json::to_writer_pretty(f, &ConfigFormat {
             music_volume: self.music_volume.get(),
             sound_volume: self.sound_volume.get(),
+            voice_volume: self.voice_volume.get(),
             target_fps: self.target_fps.get(),
             fullscreen_mode: match self.fullscreen_mode.get() {
                 FullscreenType::Off => "windowed",
@@ -148,14 +321,35 @@ impl Config {
                 FullscreenType::True => "fullscreen", // Disabled for now so shouldn't happen
             }.to_owned(),
             fullscreen_res: self.fullscreen_res.get(),
+            render_backend: self.render_backend.get().as_str().to_owned(),
             render_shadow_res: self.render_shadow_res.get(),
             render_ssao: self.render_ssao.get(),
             render_fxaa: self.render_fxaa.get(),
+            render_gpu_picking: self.render_gpu_picking.get(),
+            render_baked_lighting: self.render_baked_lighting.get(),
             render_scale: self.render_scale.get(),
             ui_scale: self.ui_scale.get(),
+            ui_text_scale: self.ui_text_scale.get(),
+            ui_high_contrast: self.ui_high_contrast.get(),
+            reduce_effects: self.reduce_effects.get(),
+            colour_palette: self.colour_palette.get().as_str().to_owned(),
+            narration: self.narration.get().as_str().to_owned(),
+            camera_edge_scroll: self.camera_edge_scroll.get(),
+            camera_pan_speed: self.camera_pan_speed.get(),
+            camera_zoom_speed: self.camera_zoom_speed.get(),
+            camera_invert_pan: self.camera_invert_pan.get(),
+            camera_invert_zoom: self.camera_invert_zoom.get(),
+            camera_zoom_to_cursor: self.camera_zoom_to_cursor.get(),
+            camera_free_roam: self.camera_free_roam.get(),
+            instant_construction: self.instant_construction.get(),
+            save_on_exit: self.save_on_exit.get(),
             placement_valid_colour: self.placement_valid_colour.get(),
             placement_invalid_colour: self.placement_invalid_colour.get(),
             asset_packs: self.asset_packs.borrow().clone(),
+            hud_layouts: self.hud_layouts.borrow().clone(),
+            workshop_versions: self.workshop_versions.borrow().clone(),
+            postponed_workshop_items: self.postponed_workshop_items.borrow().clone(),
+            log_filter: self.log_filter.borrow().clone(),
         })?;
         Ok(())
     }
@@ -174,6 +368,7 @@ struct OptionsUI {
 
     sound_volume: ui::Node,
     music_volume: ui::Node,
+    voice_volume: Option<ui::Node>,
     fps: ui::Node,
     fullscreen: ui::Node,
     // res: ui::Node,
@@ -269,6 +464,11 @@ impl state::State for OptionsMenuState {
         let sound_volume = assume!(state.global_logger, query!(node, slider(id="sound_volume"))
             .next());
         sound_volume.set_property("value", state.config.sound_volume.get() * 100.0);
+        // Only shown by builds with voice chat
+        let voice_volume = query!(node, slider(id="voice_volume")).next();
+        if let Some(voice_volume) = voice_volume.as_ref() {
+            voice_volume.set_property("value", state.config.voice_volume.get() * 100.0);
+        }
 
         if let Some(list) = query!(node, options_list > scroll_panel > content).next() {
             for col in [
@@ -280,6 +480,8 @@ impl state::State for OptionsMenuState {
                 keybinds::KeyCollection::EditRoom,
                 keybinds::KeyCollection::PlaceObject,
                 keybinds::KeyCollection::PlaceStaff,
+                keybinds::KeyCollection::BuildCursor,
+                keybinds::KeyCollection::LevelEditor,
             ].iter().cloned() {
                 let collection = state.keybinds.collections.get(&col).unwrap();
                 if collection.binds.is_empty() {
@@ -406,6 +608,7 @@ impl state::State for OptionsMenuState {
 
             music_volume,
             sound_volume,
+            voice_volume,
             fps: dfps,
             fullscreen,
             // res: dres,
@@ -429,6 +632,14 @@ impl state::State for OptionsMenuState {
             state.audio.update_settings(&state.config);
         }
 
+        if let Some(voice_volume) = ui.voice_volume.as_ref() {
+            let voice_volume = voice_volume.get_property::<f64>("value").unwrap_or(0.0) / 100.0;
+            if voice_volume != state.config.voice_volume.get() {
+                state.config.voice_volume.set(voice_volume);
+                state.audio.update_settings(&state.config);
+            }
+        }
+
         let fps = ui.fps.get_property::<i32>("value")
             .and_then(|v| ui.fps.get_property::<i32>(&format!("option{}_value", v)))
             .unwrap_or(60) as u32;
@@ -13,7 +13,14 @@ pub enum ClientComponent {
     AnimateMovement {
         /// The animation speed modifier
         modifier: f64,
-    }
+    },
+    /// Effects played when the entity appears or disappears
+    LifecycleEffects {
+        /// Played when the entity appears
+        spawn: Option<super::LifecycleEffect>,
+        /// Played when the entity disappears
+        despawn: Option<super::LifecycleEffect>,
+    },
 }
 
 impl entity::ComponentCreator for ClientComponent {
@@ -29,6 +36,10 @@ impl entity::ComponentCreator for ClientComponent {
             ServerClientComponentInfo::Client(AnimateMovement{modifier}) => ClientComponent::AnimateMovement {
                 modifier,
             },
+            ServerClientComponentInfo::Client(LifecycleEffects{spawn, despawn}) => ClientComponent::LifecycleEffects {
+                spawn: spawn.map(|v| v.into_effect(module)),
+                despawn: despawn.map(|v| v.into_effect(module)),
+            },
         }
     }
 
@@ -42,7 +53,13 @@ impl entity::ComponentCreator for ClientComponent {
                 em.add_component::<super::AnimationMovementSpeed>(e, super::AnimationMovementSpeed {
                     modifier,
                 });
-            }
+            },
+            ClientComponent::LifecycleEffects{ref spawn, ref despawn} => {
+                em.add_component(e, super::LifecycleEffects {
+                    spawn: spawn.clone(),
+                    despawn: despawn.clone(),
+                });
+            },
         }
     }
 }
@@ -61,5 +78,35 @@ pub enum ServerClientComponentInfo {
 pub enum ClientComponentInfo {
     AnimateMovement {
         modifier: f64,
+    },
+    LifecycleEffects {
+        #[serde(default)]
+        spawn: Option<LifecycleEffectInfo>,
+        #[serde(default)]
+        despawn: Option<LifecycleEffectInfo>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[doc(hidden)]
+pub struct LifecycleEffectInfo {
+    #[serde(rename = "effect")]
+    kind: super::LifecycleEffectKind,
+    /// In ticks
+    duration: i32,
+    #[serde(default)]
+    sound: Option<String>,
+}
+
+impl LifecycleEffectInfo {
+    fn into_effect(self, module: assets::ModuleKey<'_>) -> super::LifecycleEffect {
+        super::LifecycleEffect {
+            kind: self.kind,
+            duration: self.duration,
+            sound: self.sound
+                .map(|v| assets::LazyResourceKey::parse(&v)
+                    .or_module(module)
+                    .into_owned()),
+        }
     }
 }
\ No newline at end of file
@@ -10,7 +10,9 @@ use crate::server::assets;
 use crate::server::entity::*;
 use crate::server::common;
 use crate::render::animated_model;
+use crate::server::calendar::{self, SeasonVariants, SeasonBlend};
 use crate::prelude::*;
+use std::sync::Arc;
 
 /// Registers components required by the client
 pub fn register_components(c: &mut ecs::Container) {
@@ -24,23 +26,31 @@ pub fn register_components(c: &mut ecs::Container) {
     c.register_component::<Icon>();
     c.register_component::<Color>();
     c.register_component::<FadeOverLife>();
+    c.register_component::<LifecycleEffects>();
+    c.register_component::<PlayingLifecycleEffect>();
     c.register_component::<crate::audio::AudioController>();
     c.register_component::<animated_model::InfoTick>();
     c.register_component::<Highlighted>();
     c.register_component::<crate::instance::scripting::LuaEntityRef>();
     c.register_component::<AttachedTo>();
     c.register_component::<ClientBooked>();
+    c.register_component::<CurrentSeason>();
+    c.register_component::<SeasonalModel>();
 }
 
 /// Registers systems required by the client
 pub fn register_systems(sys: &mut ecs::Systems) {
     sys.add(sys::animate_door);
+    sys.add(sys::animate_triggers);
     sys.add(sys::animate_walking);
     sys.add(sys::animate_movement_speed);
     sys.add(sys::fade_lifetime);
+    sys.add(sys::tick_lifecycle_effects);
     sys.add(sys::tick_emotes);
     sys.add(sys::remove_attachments);
     sys.add(sys::remove_attachments_room);
+    sys.add(sys::apply_campus_branding);
+    sys.add(sys::apply_outdoor_season);
 }
 
 /// Registers systems required by the client that will be run
@@ -117,6 +127,40 @@ pub struct ModelTexture {
 }
 component!(ModelTexture => Vec);
 
+/// The outdoor season used to pick the models of static
+/// entities.
+///
+/// Stored on the world entity
+pub struct CurrentSeason {
+    /// The models and textures used for each season
+    pub variants: Arc<SeasonVariants>,
+    /// The current season
+    pub blend: SeasonBlend,
+}
+component!(CurrentSeason => const World);
+
+impl CurrentSeason {
+    /// Creates the season state starting at the first day
+    pub fn new(variants: SeasonVariants) -> CurrentSeason {
+        CurrentSeason {
+            variants: Arc::new(variants),
+            blend: calendar::season_blend(0, 0.0),
+        }
+    }
+}
+
+/// The model and texture a static entity was created with
+/// before being swapped for a seasonal variant
+pub struct SeasonalModel {
+    /// The original model
+    pub model: assets::ResourceKey<'static>,
+    /// The original texture if any
+    pub texture: Option<assets::ResourceKey<'static>>,
+    /// The season currently applied to the entity
+    pub applied: Option<calendar::OutdoorSeason>,
+}
+component!(SeasonalModel => Map);
+
 /// Marks a model as static (not animated)
 #[derive(Default)]
 pub struct StaticModel;
@@ -224,6 +268,105 @@ component!(Color => Map);
 pub struct FadeOverLife;
 component!(FadeOverLife => Marker);
 
+/// How an entity appears or disappears
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleEffectKind {
+    /// Smoothly fades the entity in or out
+    Fade,
+    /// Fades the entity whilst flickering so that it
+    /// appears to break up
+    Dissolve,
+}
+
+/// An effect played whilst an entity appears or disappears
+#[derive(Clone, Debug)]
+pub struct LifecycleEffect {
+    /// The type of effect
+    pub kind: LifecycleEffectKind,
+    /// The length of the effect in ticks
+    pub duration: i32,
+    /// The sound to play when the effect starts
+    pub sound: Option<assets::ResourceKey<'static>>,
+}
+
+/// The effects to play when the entity appears in or
+/// disappears from the snapshots sent by the server
+pub struct LifecycleEffects {
+    /// Played when the entity is spawned or first seen
+    pub spawn: Option<LifecycleEffect>,
+    /// Played when the entity is removed, the entity is
+    /// kept around by the client until it finishes
+    pub despawn: Option<LifecycleEffect>,
+}
+component!(LifecycleEffects => Map);
+
+/// A lifecycle effect currently playing on the entity
+pub struct PlayingLifecycleEffect {
+    /// The effect being played
+    pub effect: LifecycleEffect,
+    /// Whether the entity is disappearing and should be
+    /// removed once the effect finishes
+    pub despawn: bool,
+    /// The number of ticks the effect has played for
+    pub time: i32,
+    /// Whether the entity had its own color before the
+    /// effect which should be left once the effect finishes
+    pub had_color: bool,
+}
+component!(PlayingLifecycleEffect => Map);
+
+/// Starts the entity's spawn effect if it has one
+pub fn start_spawn_effect(entities: &mut ecs::Container, e: ecs::Entity) {
+    let effect = entities.get_component::<LifecycleEffects>(e)
+        .and_then(|v| v.spawn.clone());
+    if let Some(effect) = effect {
+        start_lifecycle_effect(entities, e, effect, false);
+    }
+}
+
+/// Starts the entity's despawn effect if it has one.
+///
+/// Returns whether the effect was started, if so the entity
+/// will be removed once the effect finishes.
+pub fn start_despawn_effect(entities: &mut ecs::Container, e: ecs::Entity) -> bool {
+    let effect = entities.get_component::<LifecycleEffects>(e)
+        .and_then(|v| v.despawn.clone());
+    if let Some(effect) = effect {
+        // No longer controlled by the server so stop any movement
+        // and anything that may refer to the entity by its id
+        entities.remove_component::<NetworkId>(e);
+        entities.remove_component::<TargetPosition>(e);
+        entities.remove_component::<CatchupBuffer>(e);
+        entities.remove_component::<pathfind::Target>(e);
+        entities.remove_component::<pathfind::TargetTime>(e);
+        entities.remove_component::<pathfind::PathInfo>(e);
+        entities.remove_component::<SelectedEntity>(e);
+        start_lifecycle_effect(entities, e, effect, true);
+        true
+    } else {
+        false
+    }
+}
+
+fn start_lifecycle_effect(entities: &mut ecs::Container, e: ecs::Entity, effect: LifecycleEffect, despawn: bool) {
+    // A despawn can replace a spawn that hasn't finished yet
+    let had_color = entities.get_component::<PlayingLifecycleEffect>(e)
+        .map(|v| v.had_color)
+        .unwrap_or_else(|| entities.get_component::<Color>(e).is_some());
+    if !had_color {
+        entities.add_component(e, Color {
+            color: (255, 255, 255, if despawn { 255 } else { 0 }),
+        });
+    }
+    entities.add_component(e, PlayingLifecycleEffect {
+        effect,
+        despawn,
+        time: 0,
+        had_color,
+    });
+}
+
 /// Highlights the entity with the given color
 pub struct Highlighted {
     /// The hightlight color
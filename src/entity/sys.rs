@@ -211,14 +211,17 @@ closure_system!(pub fn lerp_target_pos(
     mut door: Write<Door>,
     mut target: Write<pathfind::Target>,
     adjust: Read<LagMovementAdjust>,
-    mut catchup: Write<CatchupBuffer>
+    mut catchup: Write<CatchupBuffer>,
+    living: Read<Living>,
+    steering: Read<steering::Steering>
 ) {
     // Hack to make sure pathfinding runs first
     pathfind::travel_path(
         &em, &log, &tiles, &rooms, 
         &mut info, &mut speed, &mut position, 
         &mut target_pos, &mut target_rotation,
-        &mut door, &mut target, &adjust
+        &mut door, &mut target, &adjust,
+        &living, &steering
     );
     let log = log.get_component(Container::WORLD).expect("Missing logger");
 
@@ -291,6 +294,7 @@ closure_system!(pub fn animate_door(
     em: EntityManager<'_>,
     log: Read<CLogger>,
     position: Read<Position>,
+    triggers: Read<ObjectTriggers>,
     mut door: Write<Door>,
     mut model: Write<AnimatedModel>,
     mut audio: Write<AudioController>
@@ -298,22 +302,26 @@ closure_system!(pub fn animate_door(
     use std::cmp::max;
     let log = log.get_component(Container::WORLD).expect("Missing logger");
     let audio = assume!(log.log, audio.get_component_mut(Container::WORLD));
-    for (_e, (animated_model, d, pos)) in em.group((&mut model, &mut door, &position)) {
+    for (e, (animated_model, d, pos)) in em.group((&mut model, &mut door, &position)) {
+        d.tick_flow();
         if d.open > 0 {
             d.open = max(d.open - 1, 0);
         }
         let open = d.open > 0;
         if open != d.was_open {
             d.was_open = open;
-            if open {
+            let name = if open { "open" } else { "close" };
+            // Doors without their own triggers use the default
+            // door animations and sounds
+            if let Some(trigger) = triggers.get_component(e).and_then(|v| v.get(name)) {
+                play_trigger(animated_model, audio, pos, trigger);
+            } else if open {
                 animated_model.queue_animation("opening");
                 animated_model.queue_animation("open");
-                // TODO: Allow changing
                 audio.play_sound_at(ResourceKey::new("base", "door_open"), (pos.x as f32, pos.z as f32));
             } else {
                 animated_model.queue_animation("closing");
                 animated_model.queue_animation("closed");
-                // TODO: Allow changing
                 audio.play_sound_at(ResourceKey::new("base", "door_close"), (pos.x as f32, pos.z as f32));
             }
         }
@@ -325,6 +333,40 @@ closure_system!(pub fn animate_door(
     }
 });
 
+closure_system!(pub fn animate_triggers(
+    em: EntityManager<'_>,
+    log: Read<CLogger>,
+    position: Read<Position>,
+    triggers: Read<ObjectTriggers>,
+    mut triggered: Write<TriggeredAnimation>,
+    mut model: Write<AnimatedModel>,
+    mut audio: Write<AudioController>
+) {
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let audio = assume!(log.log, audio.get_component_mut(Container::WORLD));
+    let mask = triggered.mask().and(&triggers);
+    for e in em.iter_mask(&mask).collect::<Vec<_>>() {
+        let idx = assume!(log.log, triggered.remove_component(e)).trigger;
+        let trigger = assume!(log.log, triggers.get_component(e)).triggers.get(idx);
+        if let (Some(trigger), Some(animated_model), Some(pos)) = (trigger, model.get_component_mut(e), position.get_component(e)) {
+            play_trigger(animated_model, audio, pos, trigger);
+        }
+    }
+});
+
+fn play_trigger(animated_model: &mut AnimatedModel, audio: &mut AudioController, pos: &Position, trigger: &crate::server::common::AnimationTrigger) {
+    let mut animations = trigger.animations.iter();
+    if let Some(first) = animations.next() {
+        animated_model.set_animation(first.as_str());
+    }
+    for animation in animations {
+        animated_model.queue_animation(animation.as_str());
+    }
+    if let Some(sound) = trigger.sound.as_ref() {
+        audio.play_sound_at(sound.borrow(), (pos.x as f32, pos.z as f32));
+    }
+}
+
 closure_system!(pub fn fade_lifetime(
     em: EntityManager<'_>,
     lifetime: Read<Lifetime>,
@@ -336,6 +378,54 @@ closure_system!(pub fn fade_lifetime(
     }
 });
 
+closure_system!(pub fn tick_lifecycle_effects(
+    em: EntityManager<'_>,
+    log: Read<CLogger>,
+    position: Read<Position>,
+    mut playing: Write<PlayingLifecycleEffect>,
+    mut color: Write<Color>,
+    mut audio: Write<AudioController>
+) {
+    use rand::{thread_rng, Rng};
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let audio = assume!(log.log, audio.get_component_mut(Container::WORLD));
+    let mut rng = thread_rng();
+    for e in em.iter_mask(&playing.mask()).collect::<Vec<_>>() {
+        let (finished, despawn, had_color) = {
+            let effect = assume!(log.log, playing.get_component_mut(e));
+            if effect.time == 0 {
+                if let (Some(sound), Some(pos)) = (effect.effect.sound.as_ref(), position.get_component(e)) {
+                    audio.play_sound_at(sound.borrow(), (pos.x as f32, pos.z as f32));
+                }
+            }
+            effect.time += 1;
+            let progress = (effect.time as f32 / effect.effect.duration.max(1) as f32).min(1.0);
+            let mut alpha = if effect.despawn { 1.0 - progress } else { progress };
+            if effect.effect.kind == LifecycleEffectKind::Dissolve && progress < 1.0 {
+                alpha *= rng.gen_range(0.25, 1.0);
+            }
+            if let Some(color) = color.get_component_mut(e) {
+                color.color.3 = (alpha * 255.0) as u8;
+            }
+            (progress >= 1.0, effect.despawn, effect.had_color)
+        };
+        if finished {
+            if despawn {
+                em.remove_entity(e);
+            } else {
+                playing.remove_component(e);
+                if had_color {
+                    if let Some(color) = color.get_component_mut(e) {
+                        color.color.3 = 255;
+                    }
+                } else {
+                    color.remove_component(e);
+                }
+            }
+        }
+    }
+});
+
 closure_system!(pub fn tick_emotes(
     em: EntityManager<'_>,
     log: Read<CLogger>,
@@ -413,4 +503,102 @@ closure_system!(pub fn remove_attachments_room(
             em.remove_entity(e);
         }
     }
-});
\ No newline at end of file
+});
+closure_system!(pub fn apply_campus_branding(
+    em: EntityManager<'_>,
+    log: Read<CLogger>,
+    rooms: Read<LevelRooms>,
+    campuses: Read<player::Campuses>,
+    profiles: Read<player::Profiles>,
+    branding: Read<player::CampusBranding>,
+    room_owned: Read<RoomOwned>,
+    mut color: Write<Color>
+) {
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let rooms = assume!(log.log, rooms.get_component(Container::WORLD));
+    let campuses = if let Some(campuses) = campuses.get_component(Container::WORLD) {
+        campuses
+    } else {
+        return;
+    };
+
+    let profiles = profiles.get_component(Container::WORLD);
+
+    for (e, (branding, room_owned)) in em.group((&branding, &room_owned)) {
+        let owner = rooms.try_room_info(room_owned.room_id).map(|v| v.owner);
+        let tint = match branding.slot {
+            player::BrandingSlot::Owner => owner
+                .and_then(|o| profiles.and_then(|v| v.profiles.get(&o)))
+                .map(|v| {
+                    let (r, g, b) = v.rgb();
+                    (r, g, b, 255)
+                }),
+            slot => owner
+                .and_then(|o| campuses.identities.get(&o))
+                .and_then(|v| v.color(slot))
+                .map(|v| v.tint()),
+        };
+        if let Some(tint) = tint {
+            if color.get_component(e).map_or(true, |v| v.color != tint) {
+                color.add_component(e, Color {
+                    color: tint,
+                });
+            }
+        }
+    }
+});
+closure_system!(pub fn apply_outdoor_season(
+    em: EntityManager<'_>,
+    log: Read<CLogger>,
+    season: Read<CurrentSeason>,
+    position: Read<Position>,
+    static_model: Read<StaticModel>,
+    mut seasonal: Write<SeasonalModel>,
+    mut model: Write<Model>,
+    mut texture: Write<ModelTexture>
+) {
+    use crate::server::calendar;
+    let log = log.get_component(Container::WORLD).expect("Missing logger");
+    let season = if let Some(season) = season.get_component(Container::WORLD) {
+        season
+    } else {
+        return;
+    };
+    if season.variants.is_empty() {
+        return;
+    }
+
+    for (e, (model, pos)) in em.group_mask((&mut model, &position), |m| m.and(&static_model)) {
+        if seasonal.get_component(e).is_none() {
+            let is_seasonal = season.variants.is_seasonal(
+                model.name.borrow(),
+                texture.get_component(e).map(|v| v.name.borrow())
+            );
+            if !is_seasonal {
+                continue;
+            }
+            let tex = texture.get_component(e).map(|v| v.name.clone());
+            seasonal.add_component(e, SeasonalModel {
+                model: model.name.clone(),
+                texture: tex,
+                applied: None,
+            });
+        }
+        let seasonal = assume!(log.log, seasonal.get_component_mut(e));
+        // Props change over a few at a time like the ground does
+        let current = season.blend.season_for(calendar::location_seed(Location::new(pos.x as i32, pos.z as i32)));
+        if seasonal.applied == Some(current) {
+            continue;
+        }
+        seasonal.applied = Some(current);
+        model.name = season.variants.model(current, seasonal.model.borrow())
+            .unwrap_or_else(|| seasonal.model.clone());
+        if let Some(tex) = seasonal.texture.as_ref() {
+            let name = season.variants.texture(current, tex.borrow())
+                .unwrap_or_else(|| tex.clone());
+            texture.add_component(e, ModelTexture {
+                name,
+            });
+        }
+    }
+});
@@ -253,9 +253,12 @@ struct CourseEdit {
     course: course::NetworkCourse,
 
     autofill: Option<AutoFillState>,
+    template_fill: Option<TemplateFillState>,
     blocked_periods: [[bool; 4]; 7],
 
     is_new: bool,
+    /// The selected value of the template dropdown
+    template: i32,
     last_cost: String,
 }
 
@@ -417,14 +420,92 @@ impl AutoFillState {
     }
 }
 
+/// Books rooms and staff for each lesson of a course template
+/// in turn
+struct TemplateFillState {
+    periods: Vec<(usize, usize, ResourceKey<'static>)>,
+    current: usize,
+
+    request: Option<network::RequestTicket<player::LessonValidOptions>>,
+    reply: Option<player::LessonValidOptionsReply>,
+}
+
+impl TemplateFillState {
+    fn new(template: &course::CourseTemplate) -> TemplateFillState {
+        TemplateFillState {
+            periods: template.periods()
+                .map(|(day, period, key)| (day, period, key.clone()))
+                .collect(),
+            current: 0,
+
+            request: None,
+            reply: None,
+        }
+    }
+
+    fn handle_event(
+        &mut self,
+        evt: &mut event::EventHandler,
+    ) {
+        if let Some(req) = self.request {
+            network::RequestManager::handle_reply(evt, req, |pck| {
+                self.request = None;
+                self.reply = Some(pck);
+            });
+        }
+    }
+
+    fn do_fill(
+        &mut self,
+        target: &mut course::NetworkCourse,
+        req: &mut network::RequestManager,
+    ) -> bool {
+        use rand::seq::SliceRandom;
+        if self.request.is_some() {
+            return false;
+        }
+        let (day, period, key) = if let Some(p) = self.periods.get(self.current) {
+            p.clone()
+        } else {
+            return true;
+        };
+        if let Some(reply) = self.reply.take() {
+            // Lessons without a free room or professor are left for
+            // the player to book
+            let rooms = match (reply.staff.0.first(), reply.rooms.0.choose(&mut rand::thread_rng())) {
+                (Some(staff), Some(room)) => vec![course::NetworkLessonRoom {
+                    staff: staff.0,
+                    room: *room,
+                }],
+                _ => Vec::new(),
+            };
+            target.timetable[day][period] = course::NetworkCourseEntry::Lesson {
+                key,
+                rooms: delta_encode::AlwaysVec(rooms),
+            };
+            self.current += 1;
+        } else {
+            self.request = Some(req.request(player::LessonValidOptions {
+                course: target.uid,
+                key,
+                day: day as u8,
+                period: period as u8,
+            }));
+        }
+        false
+    }
+}
+
 impl CourseEdit {
     pub fn new(course: course::NetworkCourse, is_new: bool) -> CourseEdit {
         CourseEdit {
             ui: None,
             course,
             is_new,
+            template: 1,
             last_cost: "0".into(),
             autofill: None,
+            template_fill: None,
             blocked_periods: [[false; 4]; 7]
         }
     }
@@ -436,8 +517,10 @@ impl state::State for CourseEdit {
             ui: self.ui.clone(),
             course: self.course.clone(),
             is_new: self.is_new,
+            template: self.template,
             last_cost: self.last_cost.clone(),
             autofill: None,
+            template_fill: None,
             blocked_periods: self.blocked_periods,
         })
     }
@@ -493,6 +576,20 @@ impl state::State for CourseEdit {
             }
         }
 
+        if let Some(templates) = query!(ui, dropdown(id="template")).next() {
+            templates.set_property("option1", "No Template".to_string());
+            let mut count = 1;
+            if self.is_new {
+                for template in lm.templates_in_group(&self.course.group) {
+                    templates.set_property(&format!("option{}", count + 1), template.name.clone());
+                    count += 1;
+                }
+            }
+            templates.set_property("options", count);
+            templates.set_property("value", self.template);
+            templates.set_property("disabled", count == 1);
+        }
+
         if let Some(overview) = query!(ui, course_timetable_overview).next() {
             for (idx, day) in DAYS.iter().enumerate() {
                 let n = node! {
@@ -546,12 +643,15 @@ impl state::State for CourseEdit {
 
         let remove = if let Some(autofill) = self.autofill.as_mut() {
             autofill.do_fill(&mut self.course, &instance.snapshots, &mut instance.entities, &mut instance.request_manager)
+        } else if let Some(fill) = self.template_fill.as_mut() {
+            fill.do_fill(&mut self.course, &mut instance.request_manager)
         } else {
             false
         };
         if remove {
             let mut new = Self::new(self.course.clone(), self.is_new);
             new.blocked_periods = self.blocked_periods;
+            new.template = self.template;
             return state::Action::Switch(Box::new(new));
         }
 
@@ -566,6 +666,36 @@ impl state::State for CourseEdit {
                 return state::Action::Switch(Box::new(Self::new(self.course.clone(), self.is_new)));
             }
         }
+        if let Some(templates) = query!(ui, dropdown(id="template")).next() {
+            let val: i32 = templates.get_property("value").unwrap_or(1);
+            if val != self.template && self.is_new && self.autofill.is_none() && self.template_fill.is_none() {
+                self.template = val;
+                self.course.timetable = Default::default();
+                let template = if val >= 2 {
+                    lm.templates_in_group(&self.course.group).nth((val - 2) as usize)
+                } else {
+                    None
+                };
+                if let Some(template) = template {
+                    if self.course.name.is_empty() {
+                        self.course.name = template.name.clone();
+                        if let Some(name) = query!(ui, textbox(id="name") > content > @text).next() {
+                            name.set_text(self.course.name.as_str());
+                        }
+                    }
+                    ui.add_child(node!(autofill {
+                        info {
+                            content {
+                                @text("Applying template")
+                            }
+                        }
+                    }));
+                    self.template_fill = Some(TemplateFillState::new(template));
+                } else {
+                    return state::Action::Switch(Box::new(Self::new(self.course.clone(), self.is_new)));
+                }
+            }
+        }
         if let Some(name) = query!(ui, textbox(id="name") > content > @text).next() {
             let name = assume!(state.global_logger, name.text());
             if *name != self.course.name {
@@ -605,6 +735,9 @@ impl state::State for CourseEdit {
         if let Some(autofill) = self.autofill.as_mut() {
             autofill.handle_event(evt);
         }
+        if let Some(fill) = self.template_fill.as_mut() {
+            fill.handle_event(evt);
+        }
 
         let mut action = state::Action::Nothing;
         let ui = assume!(state.global_logger, self.ui.clone());
@@ -636,6 +769,9 @@ impl state::State for CourseEdit {
             }
         });
         evt.handle_event::<AutoFill, _>(|_| {
+            if self.template_fill.is_some() {
+                return;
+            }
             ui.add_child(node!(autofill {
                 info {
                     content {
@@ -928,6 +1064,24 @@ impl state::State for CoursePeriodEdit {
                                 n.set_property("room", true);
                                 details.add_child(n);
                             }
+                            if !lesson.required_tags.is_empty() {
+                                details.add_child(ui::Node::new_text("\n\nRoom must contain:"));
+                                for tag in &lesson.required_tags {
+                                    details.add_child(ui::Node::new_text("\n"));
+                                    details.add_child(ui::Node::new("bullet_point"));
+                                    details.add_child(ui::Node::new_text(format!(" {}", super::fix_case(tag))));
+                                }
+                            }
+                            if !lesson.staff_requirements.is_empty() {
+                                details.add_child(ui::Node::new_text("\n\nProfessor requires:"));
+                                for req in &lesson.staff_requirements {
+                                    details.add_child(ui::Node::new_text("\n"));
+                                    details.add_child(ui::Node::new("bullet_point"));
+                                    let n = ui::Node::new_text(format!(" {} of at least {:.0}%", super::fix_case(req.stat.as_string()), req.min * 100.0));
+                                    n.set_property("staff", true);
+                                    details.add_child(n);
+                                }
+                            }
                         }
                         course::NetworkCourseEntry::Lesson {
                             key: lesson.key.clone(),
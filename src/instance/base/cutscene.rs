@@ -0,0 +1,198 @@
+
+use super::*;
+use crate::server::cutscene::Cutscene;
+
+/// Plays a cutscene sent by the mission.
+///
+/// Input is suppressed whilst the cutscene plays apart from
+/// skipping it which only ends it for this player.
+pub struct CutsceneState {
+    ui: Option<ui::Node>,
+    cutscene: Rc<Cutscene>,
+    /// The time in seconds since the cutscene started
+    time: f32,
+    /// The index of the dialogue currently displayed
+    dialogue: Option<usize>,
+    /// Entities highlighted by spotlights
+    highlighted: Vec<ecs::Entity>,
+    /// The camera before the cutscene started so that it can
+    /// be restored on exit
+    old_camera: (f32, f32),
+    old_info: (cgmath::Deg<f32>, f32),
+}
+
+struct SkipCutscene;
+
+impl CutsceneState {
+    pub(crate) fn new(cutscene: Cutscene) -> CutsceneState {
+        CutsceneState {
+            ui: None,
+            cutscene: Rc::new(cutscene),
+            time: 0.0,
+            dialogue: None,
+            highlighted: vec![],
+            old_camera: (0.0, 0.0),
+            old_info: (cgmath::Deg(0.0), 1.0),
+        }
+    }
+
+    fn clear_highlights(&mut self, instance: &mut GameInstance) {
+        for e in self.highlighted.drain(..) {
+            if instance.entities.is_valid(e) {
+                instance.entities.remove_component::<entity::Highlighted>(e);
+            }
+        }
+    }
+
+    fn update_dialogue(&mut self, ui: &ui::Node) {
+        let time = self.time;
+        let current = self.cutscene.dialogue.0.iter()
+            .rposition(|v| time >= v.time && time < v.time + v.duration);
+        if current == self.dialogue {
+            return;
+        }
+        self.dialogue = current;
+        let area = match query!(ui, dialogue_area).next() {
+            Some(val) => val,
+            None => return,
+        };
+        for c in area.children() {
+            area.remove_child(c);
+        }
+        if let Some(dialogue) = current.and_then(|v| self.cutscene.dialogue.0.get(v)) {
+            let node = node! {
+                dialogue {
+                    content {
+                        @text(dialogue.text.clone())
+                    }
+                }
+            };
+            if let Some(speaker) = dialogue.speaker.as_ref() {
+                node.add_child_first(node! {
+                    speaker {
+                        @text(speaker.clone())
+                    }
+                });
+            }
+            area.add_child(node);
+        }
+    }
+}
+
+impl state::State for CutsceneState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(CutsceneState {
+            ui: self.ui.clone(),
+            cutscene: self.cutscene.clone(),
+            time: self.time,
+            dialogue: self.dialogue,
+            highlighted: self.highlighted.clone(),
+            old_camera: self.old_camera,
+            old_info: self.old_info,
+        })
+    }
+
+    fn takes_focus(&self) -> bool { true }
+
+    fn added(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        self.old_camera = state.renderer.get_camera();
+        self.old_info = state.renderer.get_camera_info();
+        state::Action::Nothing
+    }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = state.ui_manager.create_node(ResourceKey::new("base", "manage/cutscene"));
+        ui.set_property("letterbox", self.cutscene.letterbox);
+        if let Some(btn) = query!(ui, button(id="skip")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(SkipCutscene);
+                true
+            }));
+        }
+        self.dialogue = None;
+        self.update_dialogue(&ui);
+        self.ui = Some(ui);
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn removed(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        self.clear_highlights(instance);
+        // Only return to the previous position if the cutscene
+        // didn't move the camera itself
+        if self.cutscene.camera.0.is_empty() {
+            state.renderer.set_camera(self.old_camera.0, self.old_camera.1);
+        }
+        state.renderer.set_camera_info(self.old_info.0, self.old_info.1);
+    }
+
+    fn tick(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let ui = assume!(state.global_logger, self.ui.clone());
+        self.time += (state.delta / 60.0) as f32;
+        if self.time > self.cutscene.length() {
+            return state::Action::Pop;
+        }
+
+        if let Some(((x, y), rotation, zoom)) = self.cutscene.camera_at(self.time) {
+            let (cur_rotation, cur_zoom) = state.renderer.get_camera_info();
+            state.renderer.set_camera(x, y);
+            state.renderer.set_camera_info(
+                rotation.map_or(cur_rotation, cgmath::Deg),
+                zoom.unwrap_or(cur_zoom)
+            );
+        }
+
+        // Spotlights override the camera path whilst active
+        self.clear_highlights(instance);
+        let time = self.time;
+        let cutscene = self.cutscene.clone();
+        for spotlight in cutscene.spotlights.0.iter()
+            .filter(|v| time >= v.time && time < v.time + v.duration)
+        {
+            let target = if let Some(id) = spotlight.entity {
+                let entity = match instance.snapshots.get_entity_by_id(id) {
+                    Some(val) => val,
+                    None => continue,
+                };
+                instance.entities.add_component(entity, entity::Highlighted {
+                    color: (255, 230, 120),
+                });
+                self.highlighted.push(entity);
+                match instance.entities.get_component::<Position>(entity) {
+                    Some(pos) => (pos.x, pos.z),
+                    None => continue,
+                }
+            } else {
+                (spotlight.x, spotlight.y)
+            };
+            state.renderer.set_camera(target.0, target.1);
+        }
+
+        self.update_dialogue(&ui);
+        state::Action::Nothing
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, _state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        evt.handle_event::<SkipCutscene, _>(|_| {
+            action = state::Action::Pop;
+        });
+        action
+    }
+
+    fn key_action(&mut self, _instance: &mut Option<GameInstance>, _state: &mut crate::GameState, action: keybinds::KeyAction, _mouse_pos: (i32, i32)) -> state::Action {
+        use crate::keybinds::KeyAction::*;
+
+        match action {
+            SystemMenu => state::Action::Pop,
+            _ => state::Action::Nothing,
+        }
+    }
+}
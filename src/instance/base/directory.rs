@@ -0,0 +1,449 @@
+
+use super::super::*;
+use std::cmp;
+use crate::state;
+use crate::server::event;
+use crate::server::assets;
+use crate::server::network;
+use crate::server::player::{DirectoryKind, DirectoryFilter, StatRange};
+
+/// The ranges that the skill and happiness filters can be
+/// set to, after the first 'Any' option
+const STAT_RANGES: &[(&str, f32, f32)] = &[
+    ("Low", 0.0, 0.33),
+    ("Medium", 0.33, 0.66),
+    ("High", 0.66, 1.0),
+];
+
+/// Lists every staff member or student owned by the player
+/// a page at a time, filtered by name, course and stats.
+///
+/// Entries can be selected to be fired or moved onto another
+/// course all at once.
+pub struct DirectoryState {
+    ui: Option<ui::Node>,
+    filter: DirectoryFilter,
+    page: u16,
+    num_pages: u16,
+    courses: Vec<(course::CourseId, String)>,
+    selected: Vec<u32>,
+    request_ticket: Option<network::RequestTicket<player::EntityDirectory>>,
+    course_ticket: Option<network::RequestTicket<player::CourseList>>,
+    dirty: bool,
+    next_update: f64,
+}
+
+impl DirectoryState {
+    pub fn new() -> DirectoryState {
+        DirectoryState {
+            ui: None,
+            filter: DirectoryFilter::all(DirectoryKind::Staff),
+            page: 0,
+            num_pages: 0,
+            courses: Vec::new(),
+            selected: Vec::new(),
+            request_ticket: None,
+            course_ticket: None,
+            dirty: true,
+            next_update: 0.0,
+        }
+    }
+
+    fn execute_command(&mut self, req: &mut state::CaptureRequester, instance: &mut GameInstance, state: &mut crate::GameState, mut cmd: Command) {
+        let mut proxy = super::GameProxy::proxy(state);
+        try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, CommandParams {
+            log: &instance.log,
+            level: &mut instance.level,
+            engine: &instance.scripting,
+            entities: &mut instance.entities,
+            snapshots: &instance.snapshots,
+            mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
+        }), {
+            instance.push_command(cmd, req);
+            self.selected.clear();
+            self.dirty = true;
+        });
+    }
+}
+
+impl state::State for DirectoryState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(DirectoryState {
+            ui: self.ui.clone(),
+            filter: self.filter.clone(),
+            page: self.page,
+            num_pages: self.num_pages,
+            courses: self.courses.clone(),
+            selected: self.selected.clone(),
+            request_ticket: self.request_ticket,
+            course_ticket: self.course_ticket,
+            dirty: self.dirty,
+            next_update: self.next_update,
+        })
+    }
+
+    fn active(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "manage/directory"));
+
+        for (id, evt) in &[
+            ("staff", SetKind(DirectoryKind::Staff)),
+            ("students", SetKind(DirectoryKind::Students)),
+        ] {
+            if let Some(btn) = query!(ui, button(id=*id)).next() {
+                let evt = *evt;
+                btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |e, _, _| {
+                    e.emit(evt);
+                    true
+                }));
+            }
+        }
+        for (id, offset) in &[("prev", -1), ("next", 1)] {
+            if let Some(btn) = query!(ui, button(id=*id)).next() {
+                let offset = *offset;
+                btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+                    evt.emit(ChangePage(offset));
+                    true
+                }));
+            }
+        }
+        if let Some(btn) = query!(ui, button(id="fire_selected")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(FireSelected);
+                true
+            }));
+        }
+        if let Some(btn) = query!(ui, button(id="assign_selected")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(AssignSelected);
+                true
+            }));
+        }
+        for id in &["skill", "happiness"] {
+            if let Some(list) = query!(ui, dropdown(id=*id)).next() {
+                list.set_property("option1", "Any".to_owned());
+                for (idx, range) in STAT_RANGES.iter().enumerate() {
+                    list.set_property(&format!("option{}", idx + 2), range.0.to_owned());
+                }
+                list.set_property("options", STAT_RANGES.len() as i32 + 1);
+                list.set_property("value", 1);
+            }
+        }
+
+        self.course_ticket = Some(instance.request_manager.request(player::CourseList {}));
+        self.dirty = true;
+        self.next_update = 0.0;
+        update_kind(&ui, self.filter.kind);
+
+        state.ui_manager.events().emit(CloseWindowOthers(ui.clone()));
+        self.ui = Some(ui);
+        state::Action::Nothing
+    }
+
+    fn tick(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let ui = assume!(state.global_logger, self.ui.clone());
+
+        if let Some(name) = query!(ui, textbox(id="name") > content > @text).next() {
+            let name = assume!(state.global_logger, name.text());
+            if *name != self.filter.name {
+                self.filter.name = (*name).into();
+                self.page = 0;
+                self.dirty = true;
+            }
+        }
+        if let Some(list) = query!(ui, dropdown(id="course")).next() {
+            let val = list.get_property::<i32>("value").unwrap_or(1) - 2;
+            let course = if val >= 0 {
+                self.courses.get(val as usize).map(|v| v.0)
+            } else {
+                None
+            };
+            if course != self.filter.course {
+                self.filter.course = course;
+                self.page = 0;
+                self.dirty = true;
+            }
+        }
+        for (id, range) in &mut [
+            ("skill", &mut self.filter.skill),
+            ("happiness", &mut self.filter.happiness),
+        ] {
+            if let Some(list) = query!(ui, dropdown(id=*id)).next() {
+                let val = list.get_property::<i32>("value").unwrap_or(1) - 2;
+                let new = if val >= 0 {
+                    STAT_RANGES.get(val as usize).map(|v| StatRange { min: v.1, max: v.2 })
+                } else {
+                    None
+                };
+                if new != **range {
+                    **range = new;
+                    self.page = 0;
+                    self.dirty = true;
+                }
+            }
+        }
+
+        // Stats change over time so refresh the page every so often
+        self.next_update -= state.delta;
+        if self.next_update <= 0.0 {
+            self.dirty = true;
+        }
+        if self.dirty && self.request_ticket.is_none() {
+            self.dirty = false;
+            self.next_update = 60.0 * 5.0;
+            self.request_ticket = Some(instance.request_manager.request(player::EntityDirectory {
+                filter: self.filter.clone(),
+                page: self.page,
+            }));
+        }
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn ui_event_req(&mut self, req: &mut state::CaptureRequester, instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        let instance = assume!(state.global_logger, instance.as_mut());
+        evt.handle_event_if::<super::CloseWindowOthers, _, _>(|evt| {
+            // Handle in event_if in order to not consume the event and let
+            // other windows read it too
+            if !evt.0.is_same(&ui) {
+                action = state::Action::Pop;
+            }
+            false
+        }, |_| {});
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            action = state::Action::Pop;
+        });
+
+        if let Some(ticket) = self.course_ticket {
+            network::RequestManager::handle_reply(evt, ticket, |res| {
+                self.course_ticket = None;
+                self.courses = res.courses.0.into_iter()
+                    .filter(|v| !v.deprecated)
+                    .map(|v| (v.uid, v.name))
+                    .collect();
+                for (id, first) in &[("course", "Any course"), ("assign", "Select course")] {
+                    if let Some(list) = query!(ui, dropdown(id=*id)).next() {
+                        list.set_property("option1", (*first).to_owned());
+                        for (idx, c) in self.courses.iter().enumerate() {
+                            list.set_property(&format!("option{}", idx + 2), c.1.clone());
+                        }
+                        list.set_property("options", self.courses.len() as i32 + 1);
+                        list.set_property("value", 1);
+                    }
+                }
+            });
+        }
+        if let Some(ticket) = self.request_ticket {
+            network::RequestManager::handle_reply(evt, ticket, |res| {
+                self.request_ticket = None;
+                if res.filter != self.filter {
+                    // Outdated, the filter changed whilst waiting
+                    self.dirty = true;
+                    return;
+                }
+                self.page = res.page;
+                self.num_pages = res.num_pages;
+                show_entries(state, instance, &ui, &res.entries.0, &self.courses, &self.selected);
+                if let Some(page) = query!(ui, page_number > @text).next() {
+                    page.set_text(format!("Page {} of {}", self.page + 1, cmp::max(1, self.num_pages)));
+                }
+            });
+        }
+
+        evt.handle_event::<SetKind, _>(|SetKind(kind)| {
+            if self.filter.kind != kind {
+                self.filter.kind = kind;
+                self.page = 0;
+                self.selected.clear();
+                self.dirty = true;
+                update_kind(&ui, kind);
+            }
+        });
+        evt.handle_event::<ChangePage, _>(|ChangePage(offset)| {
+            let page = i32::from(self.page) + offset;
+            if page >= 0 && page < i32::from(self.num_pages) {
+                self.page = page as u16;
+                self.dirty = true;
+            }
+        });
+        evt.handle_event::<ToggleSelect, _>(|ToggleSelect(id)| {
+            if let Some(idx) = self.selected.iter().position(|v| *v == id) {
+                self.selected.remove(idx);
+            } else {
+                self.selected.push(id);
+            }
+            if let Some(btn) = query!(ui, directory_entry(entity_id=id as i32) > buttons > button(id="select")).next() {
+                btn.set_property("selected", self.selected.contains(&id));
+            }
+        });
+        evt.handle_event::<FindEntity, _>(|FindEntity(id)| {
+            if let Some(e) = instance.snapshots.get_entity_by_id(id) {
+                if instance.entities.is_valid(e) {
+                    action = state::Action::Push(Box::new(super::EntityInfoState::new(e)));
+                }
+            }
+        });
+        evt.handle_event::<FireEntity, _>(|FireEntity(id)| {
+            self.execute_command(req, instance, state, FireStaff::new(id).into());
+        });
+        evt.handle_event::<FireSelected, _>(|_| {
+            if self.filter.kind != DirectoryKind::Staff || self.selected.is_empty() {
+                return;
+            }
+            let cmds = self.selected.iter()
+                .map(|v| Command::from(FireStaff::new(*v)))
+                .collect();
+            match Batch::new(cmds) {
+                Ok(batch) => self.execute_command(req, instance, state, batch.into()),
+                Err(err) => error!(instance.log, "Failed to batch the commands"; "error" => %err),
+            }
+        });
+        evt.handle_event::<AssignSelected, _>(|_| {
+            if self.filter.kind != DirectoryKind::Students || self.selected.is_empty() {
+                return;
+            }
+            let course = query!(ui, dropdown(id="assign")).next()
+                .and_then(|v| v.get_property::<i32>("value"))
+                .and_then(|v| if v >= 2 {
+                    self.courses.get(v as usize - 2).map(|v| v.0)
+                } else {
+                    None
+                });
+            let course = if let Some(course) = course {
+                course
+            } else {
+                return;
+            };
+            let cmds = self.selected.iter()
+                .map(|v| Command::from(AssignCourse::new(*v, course)))
+                .collect();
+            match Batch::new(cmds) {
+                Ok(batch) => self.execute_command(req, instance, state, batch.into()),
+                Err(err) => error!(instance.log, "Failed to batch the commands"; "error" => %err),
+            }
+        });
+        action
+    }
+
+    fn key_action(&mut self, _instance: &mut Option<GameInstance>, _state: &mut crate::GameState, action: keybinds::KeyAction, _mouse_pos: (i32, i32)) -> state::Action {
+        use crate::keybinds::KeyAction::*;
+
+        match action {
+            SystemMenu => state::Action::Pop,
+            _ => state::Action::Nothing,
+        }
+    }
+}
+
+/// Marks the tab of the listed type as active and hides the
+/// controls that don't apply to it
+fn update_kind(ui: &ui::Node, kind: DirectoryKind) {
+    if let Some(btn) = query!(ui, button(id="staff")).next() {
+        btn.set_property("selected", kind == DirectoryKind::Staff);
+    }
+    if let Some(btn) = query!(ui, button(id="students")).next() {
+        btn.set_property("selected", kind == DirectoryKind::Students);
+    }
+    ui.set_property("students", kind == DirectoryKind::Students);
+}
+
+fn show_entries(
+    state: &mut crate::GameState, instance: &GameInstance,
+    ui: &ui::Node, entries: &[player::DirectoryEntry],
+    courses: &[(course::CourseId, String)], selected: &[u32],
+) {
+    let list = assume!(state.global_logger,
+        query!(ui, content(style="directory") > scroll_panel > content).next()
+    );
+    for c in list.children() {
+        list.remove_child(c);
+    }
+
+    for entry in entries {
+        let id = entry.entity_id;
+        let icon = state.asset_manager.loader_open::<server::entity::Loader<entity::ClientComponent>>(entry.key.borrow())
+            .ok()
+            .and_then(|v| v.variants.get(entry.variant as usize).map(|v| v.icon.as_string()))
+            .unwrap_or_default();
+        let course = entry.course
+            .and_then(|c| courses.iter().find(|v| v.0 == c))
+            .map_or("", |v| v.1.as_str());
+        let stat = |v: Option<f32>| v.map_or_else(|| "-".to_owned(), |v| format!("{:.0}%", v * 100.0));
+        let n = node!{
+            directory_entry(entity_id = id as i32) {
+                icon(img=icon)
+                name {
+                    @text(format!("{} {}", entry.first_name, entry.surname))
+                }
+                course {
+                    @text(course)
+                }
+                skill {
+                    @text(stat(entry.skill))
+                }
+                happiness {
+                    @text(stat(entry.happiness))
+                }
+                buttons {
+                    button(id="select".to_owned(), selected = selected.contains(&id)) {
+                        content {
+                            @text("Select")
+                        }
+                    }
+                    button(id="find".to_owned()) {
+                        content {
+                            @text("Find")
+                        }
+                    }
+                }
+            }
+        };
+        if let Some(btn) = query!(n, button(id="select")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+                evt.emit(ToggleSelect(id));
+                true
+            }));
+        }
+        if let Some(btn) = query!(n, button(id="find")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+                evt.emit(FindEntity(id));
+                true
+            }));
+        }
+        // Students can't be fired
+        let is_staff = instance.snapshots.get_entity_by_id(id)
+            .map_or(false, |e| instance.entities.get_component::<Paid>(e).is_some());
+        if is_staff {
+            let buttons = assume!(state.global_logger, query!(n, buttons).next());
+            buttons.add_child(node!{
+                button(id="fire".to_owned(), on_click = ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+                    evt.emit(FireEntity(id));
+                    true
+                })) {
+                    content {
+                        @text("Fire")
+                    }
+                }
+            });
+        }
+        list.add_child(n);
+        list.add_child(ui::Node::new("seperator"));
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SetKind(DirectoryKind);
+struct ChangePage(i32);
+struct ToggleSelect(u32);
+struct FindEntity(u32);
+struct FireEntity(u32);
+struct FireSelected;
+struct AssignSelected;
@@ -172,6 +172,12 @@ impl state::State for EntityInfoState {
                 true
             }));
         }
+        if let Some(btn) = query!(ui, button(id="follow")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(FollowEntity);
+                true
+            }));
+        }
         if let Some(btn) = query!(ui, button(id="move")).next() {
             btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
                 evt.emit(MoveEntity);
@@ -259,6 +265,11 @@ impl state::State for EntityInfoState {
                 state.renderer.suggest_camera_position(pos.x, pos.z, 45.0);
             }
         });
+        evt.handle_event::<FollowEntity, _>(|_| {
+            if instance.entities.is_valid(self.target) {
+                action = state::Action::Switch(Box::new(super::spectate::SpectateState::new(self.target)));
+            }
+        });
         evt.handle_event::<ChangeTab, _>(|c| {
             self.current_tab = c.0;
 
@@ -542,6 +553,7 @@ pub (super) fn fix_case(s: &str) -> String {
 }
 
 struct FocusEntity;
+struct FollowEntity;
 struct MoveEntity;
 struct FireEntity;
 struct FocusRoom(room::Id);
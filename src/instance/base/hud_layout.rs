@@ -0,0 +1,155 @@
+
+use super::*;
+
+/// Lets the player drag the HUD's panels around and manage
+/// their named layouts.
+///
+/// Doesn't take focus so that the HUD stays visible whilst
+/// being arranged.
+pub struct HudLayoutEditor {
+    ui: Option<ui::Node>,
+}
+
+impl HudLayoutEditor {
+    pub(crate) fn new() -> HudLayoutEditor {
+        HudLayoutEditor {
+            ui: None,
+        }
+    }
+
+    fn update_ui(&self, state: &crate::GameState) {
+        let ui = assume!(state.global_logger, self.ui.as_ref());
+        let layouts = state.ui_manager.layouts();
+        if let Some(txt) = query!(ui, button(id="layout") > content > @text).next() {
+            txt.set_text(layouts.active.clone());
+        }
+        if let Some(btn) = query!(ui, button(id="delete")).next() {
+            btn.set_property("disabled", layouts.active == ui::DEFAULT_LAYOUT);
+        }
+    }
+
+    fn save(state: &crate::GameState) {
+        let layouts = state.ui_manager.layouts().clone();
+        state.config.hud_layouts.replace(layouts);
+        assume!(state.global_logger, state.config.save());
+    }
+}
+
+#[derive(Clone, Copy)]
+enum LayoutEvent {
+    /// Switches to the next saved layout
+    Cycle,
+    /// Saves the current layout under the entered name
+    SaveAs,
+    /// Removes the current layout
+    Delete,
+    /// Returns every panel to its default position
+    Reset,
+    /// Stops editing the layout
+    Close,
+}
+
+impl state::State for HudLayoutEditor {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(HudLayoutEditor {
+            ui: self.ui.clone(),
+        })
+    }
+
+    fn added(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        state.ui_manager.set_layout_edit(true);
+        state::Action::Nothing
+    }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = state.ui_manager.create_node(ResourceKey::new("base", "menus/hud_layout"));
+        for (id, evt) in &[
+            ("layout", LayoutEvent::Cycle),
+            ("save_as", LayoutEvent::SaveAs),
+            ("delete", LayoutEvent::Delete),
+            ("reset", LayoutEvent::Reset),
+            ("close", LayoutEvent::Close),
+        ] {
+            if let Some(btn) = query!(ui, button(id=*id)).next() {
+                let evt = *evt;
+                btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evts, _, _| {
+                    evts.emit(evt);
+                    true
+                }));
+            }
+        }
+        self.ui = Some(ui);
+        self.update_ui(state);
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn removed(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        state.ui_manager.set_layout_edit(false);
+        Self::save(state);
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        let mut changed = false;
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            action = state::Action::Pop;
+        });
+        evt.handle_event::<LayoutEvent, _>(|evt| match evt {
+            LayoutEvent::Cycle => {
+                state.ui_manager.edit_layouts(|layouts| {
+                    let next = layouts.layouts.keys()
+                        .skip_while(|v| **v != layouts.active)
+                        .nth(1)
+                        .or_else(|| layouts.layouts.keys().next())
+                        .cloned();
+                    if let Some(next) = next {
+                        layouts.select(&next);
+                    }
+                });
+                changed = true;
+            },
+            LayoutEvent::SaveAs => {
+                let name = query!(ui, textbox(id="layout_name") > content > @text).next();
+                let name = name.as_ref()
+                    .and_then(|v| v.text());
+                if let Some(name) = name {
+                    let name = name.trim();
+                    if !name.is_empty() {
+                        state.ui_manager.edit_layouts(|layouts| layouts.save_as(name));
+                        changed = true;
+                    }
+                }
+            },
+            LayoutEvent::Delete => {
+                state.ui_manager.edit_layouts(|layouts| layouts.remove_active());
+                changed = true;
+            },
+            LayoutEvent::Reset => {
+                state.ui_manager.edit_layouts(|layouts| layouts.reset_active());
+                changed = true;
+            },
+            LayoutEvent::Close => action = state::Action::Pop,
+        });
+        if changed {
+            Self::save(state);
+            self.update_ui(state);
+        }
+        action
+    }
+
+    fn key_action(&mut self, _instance: &mut Option<GameInstance>, _state: &mut crate::GameState, action: keybinds::KeyAction, _mouse_pos: (i32, i32)) -> state::Action {
+        use crate::keybinds::KeyAction::*;
+
+        match action {
+            SystemMenu => state::Action::Pop,
+            _ => state::Action::Nothing,
+        }
+    }
+}
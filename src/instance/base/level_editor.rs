@@ -0,0 +1,152 @@
+
+use super::*;
+use crate::server::level::scatter::{self, ScatterStroke};
+use rand::{thread_rng, Rng};
+
+/// Allows the player to decorate the level in single player.
+///
+/// Currently only has the scatter brush which paints props
+/// (trees, bushes etc) from a palette around the mouse
+pub struct LevelEditor {
+    ui: Option<EditorUI>,
+    /// The palettes in the order shown in the dropdown
+    palettes: Vec<ResourceKey<'static>>,
+}
+
+#[derive(Clone)]
+struct EditorUI {
+    root: ui::Node,
+
+    palette: ui::Node,
+    radius: ui::Node,
+    density: ui::Node,
+    falloff: ui::Node,
+}
+
+impl LevelEditor {
+    pub(crate) fn new() -> LevelEditor {
+        LevelEditor {
+            ui: None,
+            palettes: vec![],
+        }
+    }
+
+    /// Creates a stroke at the location from the current brush
+    /// settings
+    fn stroke(&self, instance: &GameInstance, x: f32, y: f32) -> Option<ScatterStroke> {
+        let ui = self.ui.as_ref()?;
+        // The dropdown's options start at 1
+        let palette = ui.palette.get_property::<i32>("value")
+            .filter(|v| *v >= 1)
+            .and_then(|v| self.palettes.get((v - 1) as usize))?;
+        let value = |node: &ui::Node, min: f32, max: f32, def: f32| node.get_property::<f64>("value")
+            .map_or(def, |v| (v as f32).max(min).min(max));
+        Some(ScatterStroke {
+            id: instance.level.scatter.next_id(),
+            palette: palette.clone(),
+            x,
+            y,
+            radius: value(&ui.radius, scatter::MIN_RADIUS, scatter::MAX_RADIUS, 4.0),
+            density: value(&ui.density, 0.0, scatter::MAX_DENSITY, 0.5),
+            falloff: value(&ui.falloff, 0.0, 1.0, 0.5),
+            seed: thread_rng().gen(),
+        })
+    }
+}
+
+impl state::State for LevelEditor {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(LevelEditor {
+            ui: self.ui.clone(),
+            palettes: self.palettes.clone(),
+        })
+    }
+
+    fn added(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        self.palettes = instance.scatter_props.palettes.iter()
+            .map(|v| v.0.into_owned())
+            .collect();
+        state::Action::Nothing
+    }
+
+    fn active(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        state.keybinds.add_collection(keybinds::KeyCollection::LevelEditor);
+
+        let ui = state.ui_manager.create_node(ResourceKey::new("base", "manage/level_editor"));
+        if let Some(btn) = query!(ui, button(id="close")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(CloseEditor);
+                true
+            }));
+        }
+
+        let palette = assume!(state.global_logger, query!(ui, dropdown(id="palette")).next());
+        for (idx, key) in self.palettes.iter().enumerate() {
+            let name = instance.scatter_props.palettes.get(key.borrow())
+                .map_or_else(|| key.as_string(), |v| v.name.clone());
+            palette.set_property(&format!("option{}", idx + 1), name);
+        }
+        palette.set_property("options", self.palettes.len() as i32);
+        palette.set_property("value", 1);
+
+        self.ui = Some(EditorUI {
+            root: ui.clone(),
+            palette,
+            radius: assume!(state.global_logger, query!(ui, slider(id="radius")).next()),
+            density: assume!(state.global_logger, query!(ui, slider(id="density")).next()),
+            falloff: assume!(state.global_logger, query!(ui, slider(id="falloff")).next()),
+        });
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        state.keybinds.remove_collection(keybinds::KeyCollection::LevelEditor);
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui.root);
+        }
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui.root), |_| {
+            action = state::Action::Pop;
+        });
+        evt.handle_event::<CloseEditor, _>(|_| {
+            action = state::Action::Pop;
+        });
+        action
+    }
+
+    fn key_action_req(&mut self, req: &mut state::CaptureRequester, instance: &mut Option<GameInstance>, state: &mut crate::GameState, action: keybinds::KeyAction, mouse_pos: (i32, i32)) -> state::Action {
+        use crate::keybinds::KeyAction::*;
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let (lx, ly) = state.renderer.mouse_to_level(mouse_pos.0, mouse_pos.1);
+        let mut cmd: Command = match action {
+            SystemMenu => return state::Action::Pop,
+            ScatterPaint => if let Some(stroke) = self.stroke(instance, lx, ly) {
+                PaintScatter::new(stroke).into()
+            } else {
+                return state::Action::Nothing;
+            },
+            ScatterErase => EraseScatter::new(lx, ly).into(),
+            _ => return state::Action::Nothing,
+        };
+        let mut proxy = super::GameProxy::proxy(state);
+        try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, CommandParams {
+            log: &instance.log,
+            level: &mut instance.level,
+            engine: &instance.scripting,
+            entities: &mut instance.entities,
+            snapshots: &instance.snapshots,
+            mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
+        }), {
+            instance.push_command(cmd, req);
+        });
+        state::Action::Nothing
+    }
+}
+
+struct CloseEditor;
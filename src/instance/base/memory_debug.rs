@@ -0,0 +1,121 @@
+
+use super::*;
+use crate::server::assets;
+use crate::render::memory;
+
+/// The number of ticks between refreshing the displayed usage
+const REFRESH_RATE: i32 = 20;
+/// The amount of GPU memory a single pack is expected to stay
+/// within. Packs using more are highlighted
+const PACK_BUDGET: u64 = 256 * 1024 * 1024;
+
+/// Displays the GPU memory allocated by the renderer broken down
+/// by category and pack.
+///
+/// Opened via the `/memdebug` chat command.
+pub struct MemoryDebugState {
+    ui: Option<ui::Node>,
+    next_refresh: i32,
+}
+
+impl MemoryDebugState {
+    /// Creates the overlay
+    pub(crate) fn new() -> MemoryDebugState {
+        MemoryDebugState {
+            ui: None,
+            next_refresh: 0,
+        }
+    }
+}
+
+impl state::State for MemoryDebugState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(MemoryDebugState {
+            ui: self.ui.clone(),
+            next_refresh: self.next_refresh,
+        })
+    }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "manage/memory_debug"));
+        self.ui = Some(ui);
+        self.next_refresh = 0;
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn tick(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        self.next_refresh -= 1;
+        if self.next_refresh > 0 {
+            return state::Action::Nothing;
+        }
+        self.next_refresh = REFRESH_RATE;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        if let Some(content) = query!(ui, scroll_panel > content).next() {
+            for c in content.children() {
+                content.remove_child(c);
+            }
+            fill_usage(&content);
+        }
+        state::Action::Nothing
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            action = state::Action::Pop;
+        });
+        action
+    }
+}
+
+fn fill_usage(content: &ui::Node) {
+    let usage = memory::report();
+    let total: u64 = usage.iter().map(|v| v.bytes).sum();
+    content.add_child(node! {
+        memory_total {
+            @text(format!("Total: {}", memory::format_bytes(total)))
+        }
+    });
+
+    for cat in usage {
+        content.add_child(node! {
+            memory_category {
+                @text(format!("{}: {}", cat.category.name(), memory::format_bytes(cat.bytes)))
+            }
+        });
+        for group in cat.groups {
+            content.add_child(node! {
+                memory_group {
+                    @text(format!("{}: {} ({} objects)", group.name, memory::format_bytes(group.bytes), group.objects))
+                }
+            });
+            for (pack, bytes) in group.packs {
+                content.add_child(node! {
+                    memory_pack {
+                        @text(format!("{}: {}", pack, memory::format_bytes(bytes)))
+                    }
+                });
+            }
+        }
+    }
+
+    content.add_child(node! {
+        memory_category {
+            @text(format!("Packs (budget {} each)", memory::format_bytes(PACK_BUDGET)))
+        }
+    });
+    for (pack, bytes) in memory::pack_totals() {
+        content.add_child(node! {
+            memory_pack(over_budget = bytes > PACK_BUDGET) {
+                @text(format!("{}: {}", pack, memory::format_bytes(bytes)))
+            }
+        });
+    }
+}
@@ -5,6 +5,8 @@ mod staff;
 pub use self::staff::*;
 mod staff_list;
 pub use self::staff_list::*;
+mod directory;
+pub use self::directory::*;
 mod entity_info;
 pub use self::entity_info::*;
 mod stats;
@@ -16,6 +18,19 @@ pub use self::edit_room::*;
 mod courses;
 pub use self::courses::*;
 mod system_menu;
+mod photo_mode;
+mod hud_layout;
+mod nav_debug;
+mod occlusion_debug;
+mod memory_debug;
+mod perf_hud;
+mod net_panel;
+mod selection;
+mod spectate;
+mod trade;
+mod room_finance;
+mod cutscene;
+mod level_editor;
 
 use super::*;
 use crate::state;
@@ -24,7 +39,6 @@ use crate::server::event;
 use crate::util::*;
 use crate::render;
 use chrono::prelude::*;
-use chrono;
 use std::collections::VecDeque;
 
 pub struct Notification {
@@ -50,8 +64,15 @@ pub enum KeepReason {
     EntityOwned(Entity),
     /// Keep until a room is active
     RoomActive(RoomId),
+    /// Keep until dismissed, letting the server know once it
+    /// is. Contains the server's id for the notification
+    Script(u32),
 }
 
+/// The number of chart cards that can be displayed in the
+/// chat at once before the oldest is redrawn
+const MAX_CHART_CARDS: u32 = 8;
+
 /// The base state of a running game.
 ///
 /// When this state is removed the game instance is removed
@@ -65,9 +86,15 @@ pub struct BaseState {
     first_frame: bool,
     highlighted_entity: Option<Entity>,
     highlighted_room: Option<HighlightedRoom>,
+    selection: selection::Selection,
     mouse_pos: (i32, i32),
     fly_queue: VecDeque<(ResourceKey<'static>, ui::Node)>,
     current_fly: Option<(ui::Node, ui::Node)>,
+    // The image slot for the next chart card shown in chat
+    next_chart_card: u32,
+    /// A state to push once the instance has been
+    /// setup
+    overlay: Option<Box<dyn state::State>>,
 }
 
 #[derive(Clone)]
@@ -93,9 +120,22 @@ impl BaseState {
             first_frame: true,
             highlighted_entity: None,
             highlighted_room: None,
+            selection: selection::Selection::default(),
             mouse_pos: (0, 0),
             fly_queue: VecDeque::new(),
             current_fly: None,
+            next_chart_card: 0,
+            overlay: None,
+        }
+    }
+
+    /// Creates a new base state that will use the passed game instance
+    /// at the active game and pushes the passed state on top of itself
+    /// once the game has started.
+    pub fn with_overlay(instance: GameInstance, overlay: Box<dyn state::State>) -> BaseState {
+        BaseState {
+            overlay: Some(overlay),
+            .. BaseState::new(instance)
         }
     }
 }
@@ -111,9 +151,12 @@ impl state::State for BaseState {
             first_frame: true,
             highlighted_entity: self.highlighted_entity,
             highlighted_room: self.highlighted_room.clone(),
+            selection: self.selection.clone(),
             mouse_pos: self.mouse_pos,
             fly_queue: VecDeque::new(),
             current_fly: None,
+            next_chart_card: 0,
+            overlay: None,
         })
     }
 
@@ -127,6 +170,7 @@ impl state::State for BaseState {
         state.renderer.set_level(&instance.level);
         state.renderer.set_camera(instance.level.width as f32 / 2.0, instance.level.height as f32 / 2.0);
         state.ui_manager.set_script_engine(&state.audio, &instance.scripting);
+        instance.scripting.precompile_packs(&state.asset_manager);
         for pack in state.asset_manager.get_packs() {
             instance.scripting.init_pack(pack.module());
         }
@@ -177,12 +221,24 @@ impl state::State for BaseState {
                 }
                 state::Action::Push(Box::new(build::BuildRoom::new(limited)))
             }
-            _ => state::Action::Nothing,
+            _ => self.overlay.take().map_or(state::Action::Nothing, state::Action::Push),
         }
     }
 
     fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
-        self.hud = Some(state.ui_manager.create_node(ResourceKey::new("base", "hud")));
+        let hud = state.ui_manager.create_node(ResourceKey::new("base", "hud"));
+        // Mark the parts of the hud that the player can move around,
+        // see `HudLayoutEditor`
+        for (name, node) in &[
+            ("money", query!(hud, stats).next()),
+            ("notifications", query!(hud, notifications).next()),
+            ("chat", query!(hud, chat_area).next()),
+        ] {
+            if let Some(node) = node {
+                node.set_property("panel", (*name).to_owned());
+            }
+        }
+        self.hud = Some(hud);
         self.first_frame = true;
         state.renderer.set_mouse_sprite(ResourceKey::new("base", "ui/cursor/normal"));
         state.audio.set_playlist("game");
@@ -242,6 +298,11 @@ impl state::State for BaseState {
         use rand::{thread_rng, Rng};
         let instance = assume!(state.global_logger, instance.as_mut());
 
+        if !instance.cutscenes.is_empty() {
+            let cutscene = instance.cutscenes.remove(0);
+            return state::Action::Push(Box::new(cutscene::CutsceneState::new(cutscene)));
+        }
+
         let hud = assume!(state.global_logger, self.hud.clone());
 
         if instance.player.money != self.current_money || self.first_frame {
@@ -329,7 +390,7 @@ impl state::State for BaseState {
                 }
             }
             match not.keep_reason {
-                KeepReason::None | KeepReason::RoomActive(_) => {},
+                KeepReason::None | KeepReason::RoomActive(_) | KeepReason::Script(_) => {},
                 KeepReason::EntityOwned(e) => {
                     let player_id = instance.player.id;
                     if !instance.entities.is_valid(e) || !instance.entities.get_component::<Owned>(e).map_or(false, |v| v.player_id == player_id) {
@@ -373,13 +434,21 @@ impl state::State for BaseState {
                         }
                         MsgPart::Text{text, color, special} => {
                             if let Some(col) = color {
-                                last_color = format!("rgb({}, {}, {})", col.r, col.g, col.b);
+                                let (r, g, b) = state.config.colour_palette.get().correct((col.r, col.g, col.b));
+                                last_color = format!("rgb({}, {}, {})", r, g, b);
                             }
                             let txt = ui::Node::new_text(text);
                             txt.set_property("color", last_color.clone());
                             txt.set_property("special", special);
                             msg.add_child(txt);
                         }
+                        MsgPart::Chart(chart) => {
+                            let img = stats::draw_chart_card(&mut state.renderer, &chart, self.next_chart_card);
+                            self.next_chart_card = (self.next_chart_card + 1) % MAX_CHART_CARDS;
+                            msg.add_child(node!{
+                                chat_chart(img=img, kind=chart.kind.name().to_owned())
+                            });
+                        }
                     }
                 }
                 chat_area.add_child_first(msg);
@@ -426,7 +495,7 @@ impl state::State for BaseState {
 
         // TODO: Optimize?
         if let Some(day) = query!(hud, day > @text).next() {
-            let sim_day = NaiveDate::from_ymd(2018, 1, 1) + chrono::Duration::days(i64::from(instance.day_tick.day));
+            let sim_day = crate::server::calendar::date(instance.day_tick.day);
             day.set_text(format!("{}{} {}", sim_day.format("%B %e"), match sim_day.day() {
                 1 | 21 | 31 => "st",
                 2 | 22 => "nd",
@@ -466,6 +535,9 @@ impl state::State for BaseState {
         evt.handle_event::<super::OpenStaffListMenu, _>(|_| {
             action = state::Action::Toggle(Box::new(StaffListState::new()));
         });
+        evt.handle_event::<super::OpenDirectoryMenu, _>(|_| {
+            action = state::Action::Toggle(Box::new(DirectoryState::new()));
+        });
         evt.handle_event::<super::OpenStatsMenu, _>(|_| {
             action = state::Action::Toggle(Box::new(StatsState::new()));
         });
@@ -565,7 +637,25 @@ impl state::State for BaseState {
                 state.ui_manager.add_node(window);
             }
         });
+        evt.handle_event::<AcceptNotification, _>(|c| {
+            if let Some(id) = script_notification(instance, c.0) {
+                assume!(state.global_logger, instance.ensure_send(packet::NotificationReply {
+                    id,
+                    accepted: true,
+                }));
+                // Stop the close that follows from dismissing it
+                if let Some(not) = instance.notifications.iter_mut().find(|v| v.id == c.0) {
+                    not.keep_reason = KeepReason::None;
+                }
+            }
+        });
         evt.handle_event::<CloseNotification, _>(|c| {
+            if let Some(id) = script_notification(instance, c.0) {
+                assume!(state.global_logger, instance.ensure_send(packet::NotificationReply {
+                    id,
+                    accepted: false,
+                }));
+            }
             instance.notifications.retain(|v| if v.id == c.0 {
                 if let Some(ui) = v.ui.clone() {
                     if let Some(target) = query!(assume!(state.global_logger, self.hud.as_ref()), notifications)
@@ -591,6 +681,43 @@ impl state::State for BaseState {
             match msg.as_str() {
                 "/prebuild" => state.renderer.rebuild_pipeline(),
                 "/crashme" => panic!("Forced crash"),
+                "/culldebug" => action = state::Action::Toggle(Box::new(occlusion_debug::OcclusionDebugState::new())),
+                "/pathdebug" => action = state::Action::Toggle(Box::new(nav_debug::NavigationDebugState::new(None))),
+                "/memdebug" => action = state::Action::Toggle(Box::new(memory_debug::MemoryDebugState::new())),
+                "/perfhud" => action = state::Action::Toggle(Box::new(perf_hud::PerfHudState::new())),
+                "/netstats" => action = state::Action::Toggle(Box::new(net_panel::NetStatsPanel::new())),
+                "/trade" => action = state::Action::Toggle(Box::new(trade::TradeState::new())),
+                "/editor" => if instance.is_local {
+                    action = state::Action::Push(Box::new(level_editor::LevelEditor::new()));
+                } else {
+                    instance.chat_messages.push(Message::new()
+                        .color(255, 255, 0)
+                        .text("The level editor is only available in single player")
+                        .build());
+                },
+                cmd if cmd.starts_with("/pathdebug ") => {
+                    let entity_id = cmd["/pathdebug ".len()..].trim().parse().ok();
+                    action = state::Action::Toggle(Box::new(nav_debug::NavigationDebugState::new(entity_id)));
+                },
+                // `/log` prints the current filter, `/log reset` returns to
+                // the configured one and anything else is applied on top
+                // e.g. `/log univercity_server::network=trace`
+                cmd if cmd == "/log" || cmd.starts_with("/log ") => {
+                    let filter = cmd["/log".len()..].trim();
+                    let res = match filter {
+                        "" => Ok(()),
+                        "reset" => state.log_filter.reset(&state.config.log_filter.borrow()),
+                        filter => state.log_filter.apply(filter),
+                    };
+                    let text = match res {
+                        Ok(()) => format!("Log filter: {}", state.log_filter.describe()),
+                        Err(err) => err,
+                    };
+                    instance.chat_messages.push(Message::new()
+                        .color(255, 255, 0)
+                        .text(text)
+                        .build());
+                },
                 _ => {
                     let _ = instance.ensure_send(packet::ChatMessage {
                         message: msg,
@@ -601,7 +728,7 @@ impl state::State for BaseState {
         action
     }
 
-    fn key_action(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState, action: keybinds::KeyAction, mouse_pos: (i32, i32)) -> state::Action {
+    fn key_action_req(&mut self, req: &mut state::CaptureRequester, instance: &mut Option<GameInstance>, state: &mut crate::GameState, action: keybinds::KeyAction, mouse_pos: (i32, i32)) -> state::Action {
         use crate::keybinds::KeyAction::*;
 
         let instance = assume!(state.global_logger, instance.as_mut());
@@ -612,6 +739,15 @@ impl state::State for BaseState {
             SystemMenu => {
                 return state::Action::Push(Box::new(system_menu::SystemMenu::new()));
             },
+            PhotoMode => {
+                return state::Action::Push(Box::new(photo_mode::PhotoMode::new()));
+            },
+            TogglePerfHud => {
+                return state::Action::Toggle(Box::new(perf_hud::PerfHudState::new()));
+            },
+            ToggleNetStats => {
+                return state::Action::Toggle(Box::new(net_panel::NetStatsPanel::new()));
+            },
             BeginChat => {
                 if query!(hud, textbox(id="chat_sendbox")).next().is_none() {
                     let txt = node!(
@@ -636,8 +772,22 @@ impl state::State for BaseState {
                     hud.add_child_first(txt);
                 }
             },
+            SelectStart => self.selection.start_drag(mouse_pos),
+            SelectAdd => self.selection.set_adding(true),
+            SelectAddStop => self.selection.set_adding(false),
+            ControlGroupAssign => self.selection.set_assigning(true),
+            ControlGroupAssignStop => self.selection.set_assigning(false),
+            ControlGroup(idx) => self.selection.control_group(instance, usize::from(idx)),
+            OrderSelected => self.selection.order(req, instance, state, mouse_pos),
             InspectMember => {
-                if let Some(entity) = find_entity_at(&state.renderer, &mut instance.entities, mouse_pos) {
+                if self.selection.finish_drag(instance, &state.renderer, mouse_pos) {
+                    return state::Action::Nothing;
+                }
+                let entity = find_entity_at(&mut state.renderer, &mut instance.entities, mouse_pos);
+                if self.selection.click(instance, entity) {
+                    return state::Action::Nothing;
+                }
+                if let Some(entity) = entity {
                     if !instance.entities.get_component::<Owned>(entity).map_or(false, |v| v.player_id == instance.player.id) {
                         return state::Action::Nothing;
                     }
@@ -647,6 +797,12 @@ impl state::State for BaseState {
                         // Student
                         return state::Action::Push(Box::new(EntityInfoState::new(entity)));
                     }
+                } else if let Some(hroom) = self.highlighted_room.as_ref() {
+                    let owned = instance.level.try_room_info(hroom.id)
+                        .map_or(false, |v| v.owner == instance.player.id && v.state.is_done());
+                    if owned {
+                        return state::Action::Push(Box::new(room_finance::RoomFinanceState::new(hroom.id)));
+                    }
                 }
             },
             _ => {},
@@ -657,7 +813,7 @@ impl state::State for BaseState {
     fn mouse_move_ui(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState,  _mouse_pos: (i32, i32)) -> state::Action {
         let instance = assume!(state.global_logger, instance.as_mut());
         if let Some(entity) = self.highlighted_entity.take() {
-            instance.entities.remove_component::<entity::Highlighted>(entity);
+            self.selection.unhighlight(&mut instance.entities, entity);
         }
         if let Some(room) = self.highlighted_room.take() {
             state.ui_manager.hide_tooltip(&format!("room_{:?}", room.id));
@@ -669,9 +825,14 @@ impl state::State for BaseState {
         let instance = assume!(state.global_logger, instance.as_mut());
         self.mouse_pos = mouse_pos;
 
+        self.selection.update_drag(instance, &state.renderer, mouse_pos);
+        if self.selection.is_dragging() {
+            return state::Action::Nothing;
+        }
+
         // TODO: Tidy this up
         //       Its really messy
-        if let Some(entity) = find_entity_at(&state.renderer, &mut instance.entities, mouse_pos) {
+        if let Some(entity) = find_entity_at(&mut state.renderer, &mut instance.entities, mouse_pos) {
             let ty = {
                 instance.entities.get_component::<Living>(entity)
                     .and_then(|v| state.asset_manager.loader_open::<Loader<entity::ClientComponent>>(v.key.borrow()).ok())
@@ -679,7 +840,7 @@ impl state::State for BaseState {
             if let Some(highlight) = ty.as_ref().and_then(|v| v.highlight.as_ref()) {
                 if self.highlighted_entity != Some(entity) {
                     if let Some(entity) = self.highlighted_entity.take() {
-                        instance.entities.remove_component::<entity::Highlighted>(entity);
+                        self.selection.unhighlight(&mut instance.entities, entity);
                     }
                 } else {
                     return state::Action::Nothing;
@@ -715,7 +876,7 @@ impl state::State for BaseState {
             }
         } else {
             if let Some(entity) = self.highlighted_entity.take() {
-                instance.entities.remove_component::<entity::Highlighted>(entity);
+                self.selection.unhighlight(&mut instance.entities, entity);
                 state.ui_manager.hide_tooltip(&format!("entity_{:?}", entity));
             }
 
@@ -758,10 +919,41 @@ impl state::State for BaseState {
 
 struct ChatMessage(String);
 pub(crate) struct ClickNotification(pub(crate) u32);
+pub(crate) struct AcceptNotification(pub(crate) u32);
 pub(crate) struct CloseNotification(pub(crate) u32);
 pub(crate) struct CloseNotificationWindow;
 
-fn find_entity_at(renderer: &render::Renderer, entities: &mut Container, pos: (i32, i32)) -> Option<Entity> {
+/// Returns the server's id for the notification if it was
+/// posted by a script
+fn script_notification(instance: &GameInstance, id: u32) -> Option<u32> {
+    instance.notifications.iter()
+        .find(|v| v.id == id)
+        .and_then(|v| if let KeepReason::Script(id) = v.keep_reason {
+            Some(id)
+        } else {
+            None
+        })
+}
+
+fn find_entity_at(renderer: &mut render::Renderer, entities: &mut Container, pos: (i32, i32)) -> Option<Entity> {
+    if renderer.gpu_picking() {
+        // The pick is from the last frame so the entity may have
+        // been removed since
+        let mut entity = renderer.pick_entity(pos.0, pos.1)
+            .filter(|&e| entities.is_valid(e))?;
+        // Attachments select the entity wearing them
+        while let Some(attached) = entities.get_component::<entity::AttachedTo>(entity) {
+            entity = attached.target;
+            if !entities.is_valid(entity) {
+                return None;
+            }
+        }
+        let selectable = entities.get_component::<Living>(entity).is_some()
+            && entities.get_component::<Position>(entity).is_some()
+            && entities.get_component::<Size>(entity).is_some();
+        return if selectable { Some(entity) } else { None };
+    }
+
     let ray = renderer.get_mouse_ray(pos.0, pos.1);
 
     entities.with(|em: EntityManager<'_>,
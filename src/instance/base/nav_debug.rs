@@ -0,0 +1,195 @@
+
+use super::*;
+use crate::server::assets;
+use crate::server::network;
+
+/// The size of the area requested around the camera in tiles
+const AREA_SIZE: u32 = 64;
+/// The number of ticks between requests for new data
+const REQUEST_RATE: i32 = 20;
+/// The number of pixels used for each tile, one per a
+/// collision cell
+const TILE_PIXELS: usize = 4;
+/// The number of tiles along each side of a section of
+/// the rough path graph
+const SECTION_SIZE: usize = 4;
+
+/// Displays the pathfinding data around the camera as seen
+/// by the server.
+///
+/// Opened via the `/pathdebug [entity id]` chat command, the
+/// data is only requested from the server whilst this is open.
+pub struct NavigationDebugState {
+    ui: Option<ui::Node>,
+    entity_id: Option<u32>,
+    request_ticket: Option<network::RequestTicket<player::PathDebug>>,
+    next_request: i32,
+}
+
+impl NavigationDebugState {
+    /// Creates the overlay optionally displaying the path of the
+    /// entity with the passed network id
+    pub(crate) fn new(entity_id: Option<u32>) -> NavigationDebugState {
+        NavigationDebugState {
+            ui: None,
+            entity_id,
+            request_ticket: None,
+            next_request: 0,
+        }
+    }
+}
+
+impl state::State for NavigationDebugState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(NavigationDebugState {
+            ui: self.ui.clone(),
+            entity_id: self.entity_id,
+            request_ticket: self.request_ticket,
+            next_request: self.next_request,
+        })
+    }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "manage/navigation_debug"));
+        if let Some(txt) = query!(ui, entity_id > @text).next() {
+            txt.set_text(self.entity_id.map_or_else(|| "None".to_owned(), |v| v.to_string()));
+        }
+        self.ui = Some(ui);
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn tick(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        if self.request_ticket.is_some() {
+            return state::Action::Nothing;
+        }
+        let instance = assume!(state.global_logger, instance.as_mut());
+        self.next_request -= 1;
+        if self.next_request <= 0 {
+            let (cx, cy) = state.renderer.get_camera();
+            let half = (AREA_SIZE / 2) as f32;
+            self.request_ticket = Some(instance.request_manager.request(player::PathDebug {
+                x: (cx - half).max(0.0) as u32,
+                y: (cy - half).max(0.0) as u32,
+                width: AREA_SIZE as u8,
+                height: AREA_SIZE as u8,
+                entity_id: self.entity_id,
+            }));
+        }
+        state::Action::Nothing
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            action = state::Action::Pop;
+        });
+        if let Some(req) = self.request_ticket {
+            network::RequestManager::handle_reply(evt, req, |res| {
+                self.request_ticket = None;
+                self.next_request = REQUEST_RATE;
+                let img = draw_navigation(&mut state.renderer, &res);
+                if let Some(image) = query!(ui, navigation_image).next() {
+                    image.set_property("img", img);
+                }
+                if let Some(txt) = query!(ui, area > @text).next() {
+                    txt.set_text(format!("{}, {} ({}x{})", res.x, res.y, res.width, res.height));
+                }
+            });
+        }
+        action
+    }
+}
+
+/// Draws the pathfinding data returning the key of the image
+///
+/// * Blocked collision cells are red
+/// * Walkable tiles are shaded by their movement cost, darker
+///   tiles are more expensive
+/// * Connections between sections are yellow
+/// * The entity's path is cyan
+fn draw_navigation(renderer: &mut crate::render::Renderer, res: &player::PathDebugReply) -> String {
+    let tiles_w = usize::from(res.width);
+    let tiles_h = usize::from(res.height);
+    let width = (tiles_w * TILE_PIXELS).max(1);
+    let height = (tiles_h * TILE_PIXELS).max(1);
+    let mut data = vec![0; width * height * 4];
+
+    let set = |data: &mut [u8], x: isize, y: isize, (r, g, b): (u8, u8, u8)| {
+        if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+            return;
+        }
+        let idx = (x as usize + y as usize * width) * 4;
+        data[idx    ] = r;
+        data[idx + 1] = g;
+        data[idx + 2] = b;
+        data[idx + 3] = 255;
+    };
+
+    let max_cost = res.costs.0.iter().cloned().max().unwrap_or(1).max(1);
+    for y in 0 .. height {
+        for x in 0 .. width {
+            let colour = if res.blocked.0.get(x + y * width).cloned().unwrap_or(true) {
+                (200, 40, 40)
+            } else {
+                let cost = res.costs.0.get(x / TILE_PIXELS + (y / TILE_PIXELS) * tiles_w).cloned().unwrap_or(0);
+                let shade = 230 - ((cost.max(0) as f64 / f64::from(max_cost)) * 150.0) as u8;
+                (shade, shade, shade)
+            };
+            set(&mut data, x as isize, y as isize, colour);
+        }
+    }
+
+    // Positions are relative to the start of the area
+    let to_pixel = |x: f32, y: f32| -> (isize, isize) {
+        (
+            ((x - res.x as f32) * TILE_PIXELS as f32) as isize,
+            ((y - res.y as f32) * TILE_PIXELS as f32) as isize,
+        )
+    };
+    let section_centre = |sx: u16, sy: u16| to_pixel(
+        (usize::from(sx) * SECTION_SIZE) as f32 + SECTION_SIZE as f32 / 2.0,
+        (usize::from(sy) * SECTION_SIZE) as f32 + SECTION_SIZE as f32 / 2.0,
+    );
+
+    for link in &res.links.0 {
+        let start = section_centre(link.x, link.y);
+        if link.next_x {
+            draw_line(&mut data, &set, start, section_centre(link.x + 1, link.y), (230, 200, 0));
+        }
+        if link.next_y {
+            draw_line(&mut data, &set, start, section_centre(link.x, link.y + 1), (230, 200, 0));
+        }
+    }
+
+    if let Some(path) = res.path.as_ref() {
+        for pair in path.0.windows(2) {
+            let start = to_pixel(pair[0].0, pair[0].1);
+            let end = to_pixel(pair[1].0, pair[1].1);
+            draw_line(&mut data, &set, start, end, (0, 200, 230));
+        }
+    }
+
+    let key = ResourceKey::new("dynamic", format!("{}@{}@navigation_debug", width, height));
+    let img = key.as_string();
+    renderer.update_image(key, width as u32, height as u32, data);
+    img
+}
+
+fn draw_line<F>(data: &mut [u8], set: &F, start: (isize, isize), end: (isize, isize), colour: (u8, u8, u8))
+    where F: Fn(&mut [u8], isize, isize, (u8, u8, u8))
+{
+    let steps = (end.0 - start.0).abs().max((end.1 - start.1).abs()).max(1);
+    for i in 0 ..= steps {
+        let t = i as f64 / steps as f64;
+        let x = start.0 as f64 + (end.0 - start.0) as f64 * t;
+        let y = start.1 as f64 + (end.1 - start.1) as f64 * t;
+        set(data, x.round() as isize, y.round() as isize, colour);
+    }
+}
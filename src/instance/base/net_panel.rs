@@ -0,0 +1,131 @@
+
+use super::*;
+use crate::server::assets;
+use crate::render::memory::format_bytes;
+use crate::instance::net_stats::{NetStats, PacketCategory};
+use std::time::Duration;
+
+/// The number of ticks between refreshing the displayed statistics
+const REFRESH_RATE: i32 = 20;
+/// The round trip time above which the connection is highlighted
+/// as slow
+pub(super) const HIGH_RTT: Duration = Duration::from_millis(200);
+/// The fraction of lost pings above which the connection is
+/// highlighted as unreliable
+pub(super) const HIGH_LOSS: f32 = 0.05;
+
+/// Displays the round trip time, packet loss and traffic of the
+/// connection to the server split by the type of packet.
+///
+/// Toggled via the `ToggleNetStats` key action or the `/netstats`
+/// chat command.
+pub struct NetStatsPanel {
+    ui: Option<ui::Node>,
+    next_refresh: i32,
+}
+
+impl NetStatsPanel {
+    /// Creates the panel
+    pub(crate) fn new() -> NetStatsPanel {
+        NetStatsPanel {
+            ui: None,
+            next_refresh: 0,
+        }
+    }
+}
+
+impl state::State for NetStatsPanel {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(NetStatsPanel {
+            ui: self.ui.clone(),
+            next_refresh: self.next_refresh,
+        })
+    }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "manage/net_stats"));
+        self.ui = Some(ui);
+        self.next_refresh = 0;
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn tick(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        self.next_refresh -= 1;
+        if self.next_refresh > 0 {
+            return state::Action::Nothing;
+        }
+        self.next_refresh = REFRESH_RATE;
+        let instance = assume!(state.global_logger, instance.as_ref());
+        let ui = assume!(state.global_logger, self.ui.clone());
+        if let Some(content) = query!(ui, content).next() {
+            for c in content.children() {
+                content.remove_child(c);
+            }
+            fill_stats(&content, &instance.net_stats, instance.is_local);
+        }
+        state::Action::Nothing
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            action = state::Action::Pop;
+        });
+        action
+    }
+}
+
+fn fill_stats(content: &ui::Node, stats: &NetStats, local: bool) {
+    content.add_child(node! {
+        net_header {
+            @text(if local { "Connection (local)" } else { "Connection" })
+        }
+    });
+    content.add_child(node! {
+        net_entry(warning = stats.rtt().map_or(false, |v| v > HIGH_RTT)) {
+            @text(format!("Round trip: {}", format_rtt(stats)))
+        }
+    });
+    let loss = stats.loss();
+    content.add_child(node! {
+        net_entry(warning = loss > HIGH_LOSS) {
+            @text(format!("Packet loss: {:.0}%", loss * 100.0))
+        }
+    });
+
+    let rates = stats.rates();
+    content.add_child(node! {
+        net_header {
+            @text(format!(
+                "Traffic (in {}/s, out {}/s)",
+                format_bytes(u64::from(rates.total_incoming())),
+                format_bytes(u64::from(rates.total_outgoing())),
+            ))
+        }
+    });
+    for (idx, cat) in PacketCategory::ALL.iter().enumerate() {
+        content.add_child(node! {
+            net_entry {
+                @text(format!(
+                    "{}: in {}/s, out {}/s",
+                    cat.name(),
+                    format_bytes(u64::from(rates.incoming[idx])),
+                    format_bytes(u64::from(rates.outgoing[idx])),
+                ))
+            }
+        });
+    }
+}
+
+/// Formats the round trip time for display
+pub(super) fn format_rtt(stats: &NetStats) -> String {
+    stats.rtt()
+        .map_or_else(|| "waiting".to_owned(), |v| format!("{}ms", v.as_millis()))
+}
@@ -0,0 +1,127 @@
+
+use super::*;
+use crate::server::assets;
+use crate::render::SectionVisibility;
+
+/// The number of pixels used for each terrain section
+const SECTION_PIXELS: usize = 8;
+/// The number of ticks between redrawing the overlay
+const REFRESH_RATE: i32 = 10;
+
+/// Displays which terrain sections were hidden by occlusion
+/// culling last frame.
+///
+/// Opened via the `/culldebug` chat command.
+pub struct OcclusionDebugState {
+    ui: Option<ui::Node>,
+    next_update: i32,
+}
+
+impl OcclusionDebugState {
+    /// Creates the overlay
+    pub(crate) fn new() -> OcclusionDebugState {
+        OcclusionDebugState {
+            ui: None,
+            next_update: 0,
+        }
+    }
+}
+
+impl state::State for OcclusionDebugState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(OcclusionDebugState {
+            ui: self.ui.clone(),
+            next_update: self.next_update,
+        })
+    }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "manage/occlusion_debug"));
+        self.ui = Some(ui);
+        self.next_update = 0;
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn tick(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        self.next_update -= 1;
+        if self.next_update > 0 {
+            return state::Action::Nothing;
+        }
+        self.next_update = REFRESH_RATE;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        let (width, height, sections) = if let Some(v) = state.renderer.section_visibility() {
+            v
+        } else {
+            return state::Action::Nothing;
+        };
+        let img = draw_sections(&mut state.renderer, width, height, &sections);
+        if let Some(image) = query!(ui, occlusion_image).next() {
+            image.set_property("img", img);
+        }
+        if let Some(txt) = query!(ui, stats > @text).next() {
+            let occluded = sections.iter().filter(|v| **v == SectionVisibility::Occluded).count();
+            let visible = sections.iter().filter(|v| **v == SectionVisibility::Visible).count();
+            txt.set_text(if state.renderer.is_occlusion_culling() {
+                format!(
+                    "Sections: {} drawn, {} occluded\nEntities occluded: {}",
+                    visible, occluded, state.renderer.occluded_entities()
+                )
+            } else {
+                "Occlusion culling inactive".to_owned()
+            });
+        }
+        state::Action::Nothing
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            action = state::Action::Pop;
+        });
+        action
+    }
+}
+
+/// Draws the visibility of each section returning the key of
+/// the image
+///
+/// * Sections outside of the camera's view are dark grey
+/// * Drawn sections are green
+/// * Sections hidden behind walls are red
+fn draw_sections(renderer: &mut crate::render::Renderer, sections_w: usize, sections_h: usize, sections: &[SectionVisibility]) -> String {
+    let width = (sections_w * SECTION_PIXELS).max(1);
+    let height = (sections_h * SECTION_PIXELS).max(1);
+    let mut data = vec![0; width * height * 4];
+
+    for y in 0 .. height {
+        for x in 0 .. width {
+            let section = sections.get(x / SECTION_PIXELS + (y / SECTION_PIXELS) * sections_w)
+                .cloned()
+                .unwrap_or(SectionVisibility::OutOfView);
+            let edge = x % SECTION_PIXELS == 0 || y % SECTION_PIXELS == 0;
+            let (r, g, b) = match section {
+                _ if edge => (20, 20, 20),
+                SectionVisibility::OutOfView => (60, 60, 60),
+                SectionVisibility::Visible => (40, 170, 40),
+                SectionVisibility::Occluded => (200, 40, 40),
+            };
+            let idx = (x + y * width) * 4;
+            data[idx    ] = r;
+            data[idx + 1] = g;
+            data[idx + 2] = b;
+            data[idx + 3] = 255;
+        }
+    }
+
+    let key = ResourceKey::new("dynamic", format!("{}@{}@occlusion_debug", width, height));
+    let img = key.as_string();
+    renderer.update_image(key, width as u32, height as u32, data);
+    img
+}
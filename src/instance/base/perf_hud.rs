@@ -0,0 +1,231 @@
+
+use super::*;
+use std::time::Duration;
+use crate::server::assets;
+use crate::server::metrics::TickPhase;
+use crate::perf::FramePhase;
+use crate::render::memory::format_bytes;
+use super::net_panel;
+
+/// The number of ticks between refreshing the displayed timings
+const REFRESH_RATE: i32 = 30;
+/// The fraction of the budget a timing can use before being
+/// highlighted as a warning
+const WARN_FRACTION: f64 = 0.75;
+
+/// Displays where the time of each frame is spent and, when
+/// hosting, the server's tick timings compared against the
+/// time each is allowed. When connected to a remote server a
+/// summary of the connection is shown as well.
+///
+/// Toggled via the `TogglePerfHud` key action or the `/perfhud`
+/// chat command. Doesn't take focus so the game can still be
+/// played whilst it is open.
+pub struct PerfHudState {
+    ui: Option<ui::Node>,
+    next_refresh: i32,
+}
+
+impl PerfHudState {
+    /// Creates the overlay
+    pub(crate) fn new() -> PerfHudState {
+        PerfHudState {
+            ui: None,
+            next_refresh: 0,
+        }
+    }
+}
+
+impl state::State for PerfHudState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(PerfHudState {
+            ui: self.ui.clone(),
+            next_refresh: self.next_refresh,
+        })
+    }
+
+    fn added(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        assume!(state.global_logger, instance.ensure_send(packet::SetPerfStats {
+            enabled: true,
+        }));
+        state::Action::Nothing
+    }
+
+    fn removed(&mut self, instance: &mut Option<GameInstance>, _state: &mut crate::GameState) {
+        // The instance may already be gone if the game is closing
+        if let Some(instance) = instance.as_mut() {
+            let _ = instance.ensure_send(packet::SetPerfStats {
+                enabled: false,
+            });
+            instance.server_perf = None;
+        }
+    }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "manage/perf_hud"));
+        self.ui = Some(ui);
+        self.next_refresh = 0;
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn tick(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        self.next_refresh -= 1;
+        if self.next_refresh > 0 {
+            return state::Action::Nothing;
+        }
+        self.next_refresh = REFRESH_RATE;
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let ui = assume!(state.global_logger, self.ui.clone());
+        if let Some(content) = query!(ui, content).next() {
+            for c in content.children() {
+                content.remove_child(c);
+            }
+            fill_client(&content, state);
+            fill_server(&content, instance.server_perf.as_ref());
+            fill_connection(&content, instance);
+        }
+        state::Action::Nothing
+    }
+}
+
+fn fill_client(content: &ui::Node, state: &crate::GameState) {
+    let report = if let Some(report) = state.perf.report() {
+        report
+    } else {
+        return;
+    };
+    let target = state.config.target_fps.get();
+    // Aim for 60 frames a second when the frame rate isn't limited
+    let budget = if target == i32::max_value() as u32 || target == 0 {
+        Duration::from_secs(1) / 60
+    } else {
+        Duration::from_secs(1) / target
+    };
+
+    content.add_child(node! {
+        perf_header {
+            @text(format!("Frame (budget {})", format_time(budget)))
+        }
+    });
+    content.add_child(perf_entry("Total", report.frame, budget, false));
+    for phase in &FramePhase::ALL {
+        content.add_child(perf_entry(phase.name(), report.get(*phase), budget, false));
+        if *phase == FramePhase::Render {
+            for &(pass, time) in &report.passes {
+                content.add_child(perf_entry(pass, time, budget, true));
+            }
+        }
+    }
+}
+
+fn fill_server(content: &ui::Node, stats: Option<&packet::PerfStats>) {
+    let stats = if let Some(stats) = stats {
+        stats
+    } else {
+        content.add_child(node! {
+            perf_header {
+                @text("Server timings are only sent to the host")
+            }
+        });
+        return;
+    };
+    let budget = Duration::from_micros(u64::from(stats.budget));
+    content.add_child(node! {
+        perf_header {
+            @text(format!("Server tick (budget {})", format_time(budget)))
+        }
+    });
+    let phases = TickPhase::ALL.iter()
+        .zip(&stats.phases.0)
+        .map(|(phase, time)| (*phase, Duration::from_micros(u64::from(*time))))
+        .collect::<Vec<_>>();
+    let total = phases.iter().map(|v| v.1).sum();
+    content.add_child(perf_entry("Total", total, budget, false));
+    for (phase, time) in phases {
+        content.add_child(perf_entry(phase.name(), time, budget, false));
+    }
+
+    if stats.packs.0.is_empty() {
+        return;
+    }
+    let pack_budget = Duration::from_micros(u64::from(stats.pack_budget));
+    content.add_child(node! {
+        perf_header {
+            @text(format!("Scripts per pack (budget {})", format_time(pack_budget)))
+        }
+    });
+    for pack in &stats.packs.0 {
+        let name = if pack.throttled {
+            format!("{} (throttled)", pack.name)
+        } else {
+            pack.name.clone()
+        };
+        content.add_child(perf_entry(&name, Duration::from_micros(u64::from(pack.time)), pack_budget, true));
+    }
+}
+
+/// Shows a summary of the connection so slow ticks can be told
+/// apart from a slow connection. The full breakdown is in the
+/// connection statistics panel
+fn fill_connection(content: &ui::Node, instance: &GameInstance) {
+    if instance.is_local {
+        return;
+    }
+    let stats = &instance.net_stats;
+    let rates = stats.rates();
+    content.add_child(node! {
+        perf_header {
+            @text(format!(
+                "Connection (in {}/s, out {}/s, {:.0}% loss)",
+                format_bytes(u64::from(rates.total_incoming())),
+                format_bytes(u64::from(rates.total_outgoing())),
+                stats.loss() * 100.0,
+            ))
+        }
+    });
+    if let Some(rtt) = stats.rtt() {
+        content.add_child(perf_entry("Round trip", rtt, net_panel::HIGH_RTT, false));
+    } else {
+        content.add_child(node! {
+            perf_header {
+                @text(format!("Round trip: {}", net_panel::format_rtt(stats)))
+            }
+        });
+    }
+}
+
+/// Creates a row showing the time and a bar filled by the
+/// fraction of the budget it uses
+fn perf_entry(name: &str, time: Duration, budget: Duration, sub: bool) -> ui::Node {
+    let fraction = duration_secs(time) / duration_secs(budget).max(0.000_001);
+    node! {
+        perf_entry(
+            sub = sub,
+            warning = (WARN_FRACTION ..= 1.0).contains(&fraction),
+            over_budget = fraction > 1.0
+        ) {
+            name {
+                @text(name)
+            }
+            time {
+                @text(format_time(time))
+            }
+            perf_bar(value = fraction.min(1.0))
+        }
+    }
+}
+
+fn duration_secs(time: Duration) -> f64 {
+    time.as_secs() as f64 + f64::from(time.subsec_nanos()) / 1_000_000_000.0
+}
+
+fn format_time(time: Duration) -> String {
+    format!("{:.2}ms", duration_secs(time) * 1000.0)
+}
@@ -0,0 +1,269 @@
+
+use super::*;
+use crate::server::assets;
+use crate::render::{PhotoSettings, PhotoHidden};
+use serde_json;
+
+/// Pauses the game (in single player) and allows the player
+/// to frame and capture a photo of their university
+pub struct PhotoMode {
+    ui: Option<PhotoUI>,
+    filters: Vec<FilterInfo>,
+    /// The state of the game before photo mode was entered
+    /// so that it can be restored on exit
+    was_paused: bool,
+    was_paused_effect: bool,
+    old_zoom: f32,
+}
+
+#[derive(Clone)]
+struct PhotoUI {
+    root: ui::Node,
+
+    focus_distance: ui::Node,
+    blur: ui::Node,
+    exposure: ui::Node,
+    pitch: ui::Node,
+    supersample: ui::Node,
+    filter: ui::Node,
+    hidden: Vec<(PhotoHidden, ui::Node)>,
+}
+
+#[derive(Clone, Debug)]
+struct FilterInfo {
+    name: String,
+    lut: assets::ResourceKey<'static>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FilterInfoJson {
+    name: String,
+    lut: String,
+}
+
+impl PhotoMode {
+    pub(crate) fn new() -> PhotoMode {
+        PhotoMode {
+            ui: None,
+            filters: vec![],
+            was_paused: false,
+            was_paused_effect: false,
+            old_zoom: 0.0,
+        }
+    }
+}
+
+impl state::State for PhotoMode {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(PhotoMode {
+            ui: self.ui.clone(),
+            filters: self.filters.clone(),
+            was_paused: self.was_paused,
+            was_paused_effect: self.was_paused_effect,
+            old_zoom: self.old_zoom,
+        })
+    }
+
+    fn takes_focus(&self) -> bool { true }
+
+    fn added(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        self.was_paused = instance.paused;
+        self.was_paused_effect = state.renderer.paused;
+        if instance.is_local && !instance.paused {
+            instance.paused = true;
+            assume!(state.global_logger, instance.ensure_send(packet::SetPauseGame {
+                paused: true
+            }));
+        }
+        // The pause effect would end up in the photo
+        state.renderer.paused = false;
+        state.renderer.photo = Some(PhotoSettings::default());
+        self.old_zoom = state.renderer.get_camera_info().1;
+
+        let assets = instance.asset_manager.clone();
+        for module in assets.get_packs() {
+            let filter_file = match assets.open_from_pack(module.borrow(), "photo/filters.json") {
+                Ok(val) => val,
+                Err(_) => continue,
+            };
+            let filters_raw: Vec<FilterInfoJson> = match serde_json::from_reader(filter_file) {
+                Ok(val) => val,
+                Err(err) => {
+                    error!(instance.log, "Failed to parse filters.json for pack {:?}: {}", module, err);
+                    continue
+                }
+            };
+            self.filters.extend(filters_raw.into_iter()
+                .map(|v| FilterInfo {
+                    name: v.name,
+                    lut: assets::LazyResourceKey::parse(&v.lut)
+                        .or_module(module.borrow())
+                        .into_owned(),
+                }));
+        }
+        state::Action::Nothing
+    }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = state.ui_manager.create_node(ResourceKey::new("base", "menus/photo_mode"));
+        let photo = assume!(state.global_logger, state.renderer.photo.clone());
+
+        for (id, evt) in &[
+            ("capture", PhotoEvent::Capture),
+            ("hide_ui", PhotoEvent::HideUI),
+            ("close", PhotoEvent::Close),
+        ] {
+            if let Some(btn) = query!(ui, button(id=*id)).next() {
+                let evt = *evt;
+                btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evts, _, _| {
+                    evts.emit(evt);
+                    true
+                }));
+            }
+        }
+
+        let focus_distance = assume!(state.global_logger, query!(ui, slider(id="focus_distance")).next());
+        focus_distance.set_property("value", f64::from(photo.focus_distance));
+        let blur = assume!(state.global_logger, query!(ui, slider(id="blur")).next());
+        blur.set_property("value", f64::from(photo.blur * 100.0));
+        let exposure = assume!(state.global_logger, query!(ui, slider(id="exposure")).next());
+        exposure.set_property("value", f64::from(photo.exposure));
+        let pitch = assume!(state.global_logger, query!(ui, slider(id="pitch")).next());
+        pitch.set_property("value", f64::from(-state.renderer.get_camera_pitch().0));
+        let supersample = assume!(state.global_logger, query!(ui, slider(id="supersample")).next());
+        supersample.set_property("value", f64::from(photo.supersample));
+
+        let filter = assume!(state.global_logger, query!(ui, dropdown(id="filter")).next());
+        filter.set_property("option1", "None".to_owned());
+        let mut value = 1;
+        for (idx, f) in self.filters.iter().enumerate() {
+            filter.set_property(&format!("option{}", idx + 2), f.name.clone());
+            if photo.filter.as_ref() == Some(&f.lut) {
+                value = idx as i32 + 2;
+            }
+        }
+        filter.set_property("options", self.filters.len() as i32 + 1);
+        filter.set_property("value", value);
+
+        let mut hidden = Vec::with_capacity(4);
+        for &(id, flag) in &[
+            ("hide_students", PhotoHidden::STUDENTS),
+            ("hide_staff", PhotoHidden::STAFF),
+            ("hide_objects", PhotoHidden::OBJECTS),
+            ("hide_icons", PhotoHidden::ICONS),
+        ] {
+            let node = assume!(state.global_logger, query!(ui, dropdown(id=id)).next());
+            node.set_property("value", if photo.hidden.contains(flag) { 2 } else { 1 });
+            hidden.push((flag, node));
+        }
+
+        self.ui = Some(PhotoUI {
+            root: ui,
+            focus_distance,
+            blur,
+            exposure,
+            pitch,
+            supersample,
+            filter,
+            hidden,
+        });
+        state::Action::Nothing
+    }
+
+    fn tick(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = assume!(state.global_logger, self.ui.as_ref());
+
+        let pitch = ui.pitch.get_property::<f64>("value")
+            .map_or(-render::DEFAULT_PITCH, |v| v as f32);
+        state.renderer.set_camera_pitch(cgmath::Deg(-pitch));
+
+        let photo = assume!(state.global_logger, state.renderer.photo.as_mut());
+        if let Some(v) = ui.focus_distance.get_property::<f64>("value") {
+            photo.focus_distance = v as f32;
+        }
+        if let Some(v) = ui.blur.get_property::<f64>("value") {
+            photo.blur = v as f32 / 100.0;
+        }
+        if let Some(v) = ui.exposure.get_property::<f64>("value") {
+            photo.exposure = v as f32;
+        }
+        if let Some(v) = ui.supersample.get_property::<f64>("value") {
+            photo.supersample = v.round().max(1.0) as u32;
+        }
+        let filter = ui.filter.get_property::<i32>("value")
+            .unwrap_or(1);
+        // The first option is always no filter
+        photo.filter = if filter >= 2 {
+            self.filters.get((filter - 2) as usize)
+                .map(|v| v.lut.clone())
+        } else {
+            None
+        };
+        for (flag, node) in &ui.hidden {
+            let hide = node.get_property::<i32>("value").unwrap_or(1) == 2;
+            photo.hidden.set(*flag, hide);
+        }
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui.root);
+        }
+    }
+
+    fn removed(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        state.renderer.photo = None;
+        state.renderer.paused = self.was_paused_effect;
+        state.renderer.set_camera_pitch(cgmath::Deg(render::DEFAULT_PITCH));
+        let (rotation, _) = state.renderer.get_camera_info();
+        state.renderer.set_camera_info(rotation, self.old_zoom);
+        if instance.is_local && !self.was_paused {
+            instance.paused = false;
+            assume!(state.global_logger, instance.ensure_send(packet::SetPauseGame {
+                paused: false
+            }));
+        }
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui.root), |_| {
+            action = state::Action::Pop;
+        });
+        evt.handle_event::<PhotoEvent, _>(|evt| match evt {
+            PhotoEvent::Capture => state.renderer.request_photo(),
+            PhotoEvent::HideUI => if let Some(photo) = state.renderer.photo.as_mut() {
+                photo.hide_ui = true;
+            },
+            PhotoEvent::Close => action = state::Action::Pop,
+        });
+        action
+    }
+
+    fn key_action(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, action: keybinds::KeyAction, _mouse_pos: (i32, i32)) -> state::Action {
+        use crate::keybinds::KeyAction::*;
+
+        match action {
+            // Bring back the ui if hidden before leaving
+            SystemMenu if state.renderer.hide_ui() => {
+                if let Some(photo) = state.renderer.photo.as_mut() {
+                    photo.hide_ui = false;
+                }
+                state::Action::Nothing
+            },
+            SystemMenu | PhotoMode => state::Action::Pop,
+            _ => state::Action::Nothing,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PhotoEvent {
+    Capture,
+    HideUI,
+    Close,
+}
@@ -0,0 +1,208 @@
+
+use super::*;
+use crate::server::assets;
+use crate::server::network;
+use crate::server::level::room;
+
+/// The size of the daily profit sparkline
+const SPARK_WIDTH: usize = 168;
+const SPARK_HEIGHT: usize = 40;
+
+/// Shows the money a room made and spent over the last few
+/// days to help find the rooms that are losing money.
+///
+/// Opened by inspecting a room owned by the player.
+pub struct RoomFinanceState {
+    ui: Option<ui::Node>,
+    room_id: RoomId,
+    request_ticket: Option<network::RequestTicket<player::RoomFinance>>,
+    next_update: f64,
+}
+
+impl RoomFinanceState {
+    /// Creates the window for the room
+    pub(crate) fn new(room_id: RoomId) -> RoomFinanceState {
+        RoomFinanceState {
+            ui: None,
+            room_id,
+            request_ticket: None,
+            next_update: 0.0,
+        }
+    }
+}
+
+impl state::State for RoomFinanceState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(RoomFinanceState {
+            ui: self.ui.clone(),
+            room_id: self.room_id,
+            request_ticket: self.request_ticket,
+            next_update: self.next_update,
+        })
+    }
+
+    fn active(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "manage/room_finance"));
+        if let Some(title) = query!(ui, title > @text).next() {
+            let name = instance.level.try_room_info(self.room_id)
+                .and_then(|v| state.asset_manager.loader_open::<room::Loader>(v.key.borrow()).ok())
+                .map_or_else(|| "Room".to_owned(), |v| v.name.clone());
+            title.set_text(name);
+        }
+        state.ui_manager.events().emit(CloseWindowOthers(ui.clone()));
+        self.ui = Some(ui);
+        self.next_update = 0.0;
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn tick(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        if instance.level.try_room_info(self.room_id).map_or(true, |v| v.owner != instance.player.id) {
+            // The room was removed
+            return state::Action::Pop;
+        }
+        self.next_update -= state.delta;
+        if self.next_update <= 0.0 && self.request_ticket.is_none() {
+            self.next_update = 60.0 * 10.0;
+            self.request_ticket = Some(instance.request_manager.request(player::RoomFinance {
+                room_id: self.room_id,
+            }));
+        }
+        state::Action::Nothing
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        evt.handle_event_if::<super::CloseWindowOthers, _, _>(|evt| {
+            // Handle in event_if in order to not consume the event and let
+            // other windows read it too
+            if !evt.0.is_same(&ui) {
+                action = state::Action::Pop;
+            }
+            false
+        }, |_| {});
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            action = state::Action::Pop;
+        });
+        if let Some(req) = self.request_ticket {
+            let room_id = self.room_id;
+            network::RequestManager::handle_reply(evt, req, |res| {
+                self.request_ticket = None;
+                if res.room_id == room_id {
+                    show_finances(state, &ui, &res.days.0);
+                }
+            });
+        }
+        action
+    }
+
+    fn key_action(&mut self, _instance: &mut Option<GameInstance>, _state: &mut crate::GameState, action: keybinds::KeyAction, _mouse_pos: (i32, i32)) -> state::Action {
+        use crate::keybinds::KeyAction::*;
+
+        match action {
+            SystemMenu => state::Action::Pop,
+            _ => state::Action::Nothing,
+        }
+    }
+}
+
+fn show_finances(state: &mut crate::GameState, ui: &ui::Node, days: &[player::RoomFinanceDay]) {
+    let today = days.last().cloned().unwrap_or_default();
+    let mut total = player::RoomFinanceDay::default();
+    for day in days {
+        total.lessons += day.lessons;
+        total.sales += day.sales;
+        total.wages += day.wages;
+        total.maintenance += day.maintenance;
+    }
+
+    if let Some(content) = query!(ui, breakdown).next() {
+        for c in content.children() {
+            content.remove_child(c);
+        }
+        content.add_child(node! {
+            finance_header {
+                finance_label { @text("") }
+                finance_value { @text("Today") }
+                finance_value { @text(format!("{} days", days.len())) }
+            }
+        });
+        let rows: [(&str, UniDollar, UniDollar); 5] = [
+            ("Lessons", today.lessons, total.lessons),
+            ("Sales", today.sales, total.sales),
+            ("Wages", -today.wages, -total.wages),
+            ("Maintenance", -today.maintenance, -total.maintenance),
+            ("Profit", today.profit(), total.profit()),
+        ];
+        for &(label, today, total) in &rows {
+            let today_losing = today < UniDollar(0);
+            let total_losing = total < UniDollar(0);
+            content.add_child(node! {
+                finance_row(losing = total_losing) {
+                    finance_label { @text(label) }
+                    finance_value(losing = today_losing) { @text(format!("{}", today)) }
+                    finance_value(losing = total_losing) { @text(format!("{}", total)) }
+                }
+            });
+        }
+    }
+
+    let img = draw_sparkline(&mut state.renderer, days);
+    if let Some(spark) = query!(ui, sparkline).next() {
+        spark.set_property("img", img);
+    }
+}
+
+/// Draws the room's profit for each day as a bar from zero,
+/// green for days that made money and red for those that lost it
+fn draw_sparkline(renderer: &mut crate::render::Renderer, days: &[player::RoomFinanceDay]) -> String {
+    let (min_val, mut max_val) = days.iter()
+        .map(|v| v.profit().0 as f64)
+        .fold((0.0f64, 0.0f64), |(min, max), v| (min.min(v), max.max(v)));
+    if min_val == max_val {
+        max_val += 10.0;
+    }
+    let to_y = |v: f64| ((v - min_val) / (max_val - min_val) * (SPARK_HEIGHT - 1) as f64) as usize;
+    let zero = to_y(0.0);
+
+    let mut spark = vec![0; SPARK_WIDTH * SPARK_HEIGHT * 4];
+    for x in 0 .. SPARK_WIDTH {
+        let idx = (x + (SPARK_HEIGHT - 1 - zero) * SPARK_WIDTH) * 4;
+        spark[idx    ] = 167;
+        spark[idx + 1] = 202;
+        spark[idx + 2] = 214;
+        spark[idx + 3] = 255;
+    }
+    if !days.is_empty() {
+        let bar_width = SPARK_WIDTH / days.len();
+        for (i, day) in days.iter().enumerate() {
+            let profit = day.profit();
+            let y = to_y(profit.0 as f64);
+            let (low, high) = if y < zero { (y, zero) } else { (zero, y) };
+            let (r, g, b) = if profit < UniDollar(0) { (180, 0, 0) } else { (0, 180, 0) };
+            // Leave a gap between the bars
+            for x in i * bar_width .. (i + 1) * bar_width - 1 {
+                for yy in low ..= high {
+                    let idx = (x + (SPARK_HEIGHT - 1 - yy) * SPARK_WIDTH) * 4;
+                    spark[idx    ] = r;
+                    spark[idx + 1] = g;
+                    spark[idx + 2] = b;
+                    spark[idx + 3] = 255;
+                }
+            }
+        }
+    }
+
+    let key = ResourceKey::new("dynamic", format!("{}@{}@room_finance", SPARK_WIDTH, SPARK_HEIGHT));
+    let img = key.as_string();
+    renderer.update_image(key, SPARK_WIDTH as u32, SPARK_HEIGHT as u32, spark);
+    img
+}
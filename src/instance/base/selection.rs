@@ -0,0 +1,277 @@
+
+use super::*;
+use crate::server::command::MAX_BATCH_SIZE;
+
+/// The color selected staff members are highlighted with
+const SELECTED_COLOR: (u8, u8, u8) = (80, 200, 255);
+/// The distance in pixels the mouse has to move whilst held
+/// before a drag selection is started instead of a click
+const DRAG_THRESHOLD: i32 = 6;
+/// The distance in tiles between staff members when ordered
+/// to a location together
+const ORDER_SPACING: f32 = 0.75;
+/// The number of control groups that can be stored
+const CONTROL_GROUPS: usize = 9;
+
+/// The staff members selected to be ordered around together.
+///
+/// Staff can be selected by dragging a box around them or
+/// shift-clicking them and stored into numbered control groups
+/// to be quickly selected again later. Selected staff are
+/// highlighted until deselected.
+#[derive(Clone, Default)]
+pub(super) struct Selection {
+    entities: Vec<Entity>,
+    groups: [Vec<Entity>; CONTROL_GROUPS],
+    drag: Option<Drag>,
+    adding: bool,
+    assigning: bool,
+}
+
+#[derive(Clone)]
+struct Drag {
+    start: (i32, i32),
+    /// The selection before the drag started that is kept
+    /// when adding to it
+    kept: Vec<Entity>,
+    active: bool,
+}
+
+impl Selection {
+    /// Sets whether selecting adds to the current selection
+    pub(super) fn set_adding(&mut self, adding: bool) {
+        self.adding = adding;
+    }
+
+    /// Sets whether control group keys store the selection
+    /// instead of selecting the group
+    pub(super) fn set_assigning(&mut self, assigning: bool) {
+        self.assigning = assigning;
+    }
+
+    /// Returns whether a drag selection is being made
+    pub(super) fn is_dragging(&self) -> bool {
+        self.drag.as_ref().map_or(false, |v| v.active)
+    }
+
+    /// Begins a possible drag selection at the mouse's location
+    pub(super) fn start_drag(&mut self, mouse_pos: (i32, i32)) {
+        self.drag = Some(Drag {
+            start: mouse_pos,
+            kept: if self.adding { self.entities.clone() } else { Vec::new() },
+            active: false,
+        });
+    }
+
+    /// Updates the drag selection (if any) to the mouse's location,
+    /// highlighting the staff within it as it goes
+    pub(super) fn update_drag(&mut self, instance: &mut GameInstance, renderer: &render::Renderer, mouse_pos: (i32, i32)) {
+        let (start, mut selected) = if let Some(drag) = self.drag.as_mut() {
+            let dx = drag.start.0 - mouse_pos.0;
+            let dy = drag.start.1 - mouse_pos.1;
+            if !drag.active && dx*dx + dy*dy < DRAG_THRESHOLD * DRAG_THRESHOLD {
+                return;
+            }
+            drag.active = true;
+            (drag.start, drag.kept.clone())
+        } else {
+            return;
+        };
+        let corners = [
+            renderer.mouse_to_level(start.0, start.1),
+            renderer.mouse_to_level(mouse_pos.0, start.1),
+            renderer.mouse_to_level(mouse_pos.0, mouse_pos.1),
+            renderer.mouse_to_level(start.0, mouse_pos.1),
+        ];
+        for e in selectable_staff(instance) {
+            let pos = assume!(instance.log, instance.entities.get_component::<Position>(e));
+            if in_quad(&corners, (pos.x, pos.z)) && !selected.contains(&e) {
+                selected.push(e);
+            }
+        }
+        self.set(&mut instance.entities, selected);
+    }
+
+    /// Finishes the drag selection if one was being made.
+    ///
+    /// Returns whether the click was used by the selection and
+    /// shouldn't be handled as normal
+    pub(super) fn finish_drag(&mut self, instance: &mut GameInstance, renderer: &render::Renderer, mouse_pos: (i32, i32)) -> bool {
+        if self.is_dragging() {
+            self.update_drag(instance, renderer, mouse_pos);
+            self.drag = None;
+            return true;
+        }
+        self.drag = None;
+        false
+    }
+
+    /// Handles a click on the entity (if any), shift-clicking staff
+    /// toggles whether they are selected whilst any other click
+    /// deselects everything.
+    ///
+    /// Returns whether the click was used by the selection and
+    /// shouldn't be handled as normal
+    pub(super) fn click(&mut self, instance: &mut GameInstance, entity: Option<Entity>) -> bool {
+        if !self.adding {
+            self.set(&mut instance.entities, Vec::new());
+            return false;
+        }
+        let entity = if let Some(entity) = entity.filter(|v| is_selectable(instance, *v)) {
+            entity
+        } else {
+            return false;
+        };
+        let mut selected = self.entities.clone();
+        if let Some(idx) = selected.iter().position(|v| *v == entity) {
+            selected.remove(idx);
+        } else {
+            selected.push(entity);
+        }
+        self.set(&mut instance.entities, selected);
+        true
+    }
+
+    /// Selects the control group or stores the current selection
+    /// into it when assigning
+    pub(super) fn control_group(&mut self, instance: &mut GameInstance, idx: usize) {
+        if idx >= CONTROL_GROUPS {
+            return;
+        }
+        if self.assigning {
+            self.groups[idx] = self.entities.clone();
+        } else {
+            let group: Vec<_> = self.groups[idx].iter()
+                .cloned()
+                .filter(|v| is_selectable(instance, *v))
+                .collect();
+            self.groups[idx] = group.clone();
+            self.set(&mut instance.entities, group);
+        }
+    }
+
+    /// Orders every selected staff member to the mouse's location,
+    /// spreading them out around it so they don't stand on top
+    /// of each other
+    pub(super) fn order(&mut self, req: &mut state::CaptureRequester, instance: &mut GameInstance, state: &mut crate::GameState, mouse_pos: (i32, i32)) {
+        let selected: Vec<_> = self.entities.iter()
+            .cloned()
+            .filter(|v| is_selectable(instance, *v))
+            .collect();
+        if selected.is_empty() {
+            return;
+        }
+        let (lx, ly) = state.renderer.mouse_to_level(mouse_pos.0, mouse_pos.1);
+        let can_visit_at = |instance: &GameInstance, x: f32, y: f32| x >= 0.0 && y >= 0.0 && can_visit(
+            &*instance.level.tiles.borrow(), &*instance.level.rooms.borrow(),
+            (x * 4.0) as usize, (y * 4.0) as usize
+        );
+        if !can_visit_at(instance, lx, ly) {
+            return;
+        }
+
+        let columns = (selected.len() as f32).sqrt().ceil() as usize;
+        let offset = (columns - 1) as f32 * ORDER_SPACING / 2.0;
+        let cmds = selected.iter()
+            .enumerate()
+            .filter_map(|(idx, e)| {
+                let id = instance.entities.get_component::<NetworkId>(*e)?.0;
+                let x = lx + (idx % columns) as f32 * ORDER_SPACING - offset;
+                let y = ly + (idx / columns) as f32 * ORDER_SPACING - offset;
+                // Walls or other rooms may be in the way of the spread
+                // so fallback to the clicked location
+                let target = if can_visit_at(instance, x, y) { (x, y) } else { (lx, ly) };
+                Some(Command::from(OrderStaff::new(id, target)))
+            })
+            .collect::<Vec<_>>();
+
+        for chunk in cmds.chunks(MAX_BATCH_SIZE) {
+            let mut cmd: Command = match Batch::new(chunk.to_vec()) {
+                Ok(batch) => batch.into(),
+                Err(err) => {
+                    error!(instance.log, "Failed to batch the commands"; "error" => %err);
+                    return;
+                },
+            };
+            let mut proxy = super::GameProxy::proxy(state);
+            try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, CommandParams {
+                log: &instance.log,
+                level: &mut instance.level,
+                engine: &instance.scripting,
+                entities: &mut instance.entities,
+                snapshots: &instance.snapshots,
+                mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
+            }), {
+                instance.push_command(cmd, req);
+            });
+        }
+    }
+
+    /// Removes the hover highlight from the entity, keeping the
+    /// selection's highlight if it is selected
+    pub(super) fn unhighlight(&self, entities: &mut Container, entity: Entity) {
+        if self.entities.contains(&entity) {
+            entities.add_component(entity, entity::Highlighted {
+                color: SELECTED_COLOR,
+            });
+        } else {
+            entities.remove_component::<entity::Highlighted>(entity);
+        }
+    }
+
+    /// Replaces the selection, updating the highlights of the
+    /// staff that changed
+    fn set(&mut self, entities: &mut Container, selected: Vec<Entity>) {
+        for e in &self.entities {
+            if !selected.contains(e) && entities.is_valid(*e) {
+                entities.remove_component::<entity::Highlighted>(*e);
+            }
+        }
+        for e in &selected {
+            entities.add_component(*e, entity::Highlighted {
+                color: SELECTED_COLOR,
+            });
+        }
+        self.entities = selected;
+    }
+}
+
+/// Returns whether the entity is a staff member owned by
+/// the player
+fn is_selectable(instance: &GameInstance, e: Entity) -> bool {
+    instance.entities.is_valid(e)
+        && instance.entities.get_component::<Owned>(e).map_or(false, |v| v.player_id == instance.player.id)
+        && instance.entities.get_component::<Paid>(e).is_some()
+        && instance.entities.get_component::<Position>(e).is_some()
+}
+
+/// Returns every staff member owned by the player
+fn selectable_staff(instance: &mut GameInstance) -> Vec<Entity> {
+    let player_id = instance.player.id;
+    instance.entities.with(|em: EntityManager<'_>,
+        position: ecs::Read<Position>,
+        owned: ecs::Read<Owned>,
+        paid: ecs::Read<Paid>
+    | {
+        em.group_mask(&owned, |m| m.and(&position).and(&paid))
+            .filter(|&(_e, owned)| owned.player_id == player_id)
+            .map(|v| v.0)
+            .collect()
+    })
+}
+
+/// Returns whether the point is within the convex quad
+fn in_quad(corners: &[(f32, f32); 4], p: (f32, f32)) -> bool {
+    let mut sign = 0.0;
+    for (idx, a) in corners.iter().enumerate() {
+        let b = corners[(idx + 1) % corners.len()];
+        let cross = (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+        if cross * sign < 0.0 {
+            return false;
+        }
+        if cross != 0.0 {
+            sign = cross;
+        }
+    }
+    true
+}
@@ -0,0 +1,161 @@
+
+use super::*;
+use crate::server::assets;
+use crate::server::network;
+
+/// The number of frames the camera takes to catch up with
+/// the followed entity
+const FOLLOW_SMOOTHING: f64 = 20.0;
+/// The time between requesting the entity's activity
+const REFRESH_RATE: f64 = 60.0 * 2.0;
+
+/// Follows a student or staff member around with the camera
+/// whilst showing what they are doing in a side panel.
+///
+/// Opened from the entity's info window and closed by the
+/// panel's close button or once the entity is gone.
+pub struct SpectateState {
+    ui: Option<ui::Node>,
+    target: Entity,
+    request_ticket: Option<network::RequestTicket<player::EntityActivity>>,
+    next_update: f64,
+}
+
+impl SpectateState {
+    /// Creates the state following the entity
+    pub(crate) fn new(target: Entity) -> SpectateState {
+        SpectateState {
+            ui: None,
+            target,
+            request_ticket: None,
+            next_update: 0.0,
+        }
+    }
+}
+
+impl state::State for SpectateState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(SpectateState {
+            ui: self.ui.clone(),
+            target: self.target,
+            request_ticket: self.request_ticket,
+            next_update: self.next_update,
+        })
+    }
+
+    fn active(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "manage/spectate"));
+        if let (Some(name), Some(living)) = (
+            query!(ui, name > @text).next(),
+            instance.entities.get_component::<Living>(self.target),
+        ) {
+            name.set_text(format!("{} {}", living.name.0, living.name.1));
+        }
+        if let Some(activity) = query!(ui, activity > @text).next() {
+            activity.set_text("Loading...");
+        }
+        if let Some(btn) = query!(ui, button(id="stop")).next() {
+            let node = ui.clone();
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+                evt.emit(CancelEvent(node.clone()));
+                true
+            }));
+        }
+        state.ui_manager.events().emit(CloseOtherInfos(ui.clone()));
+        self.ui = Some(ui);
+        self.next_update = 0.0;
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn tick(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        if !instance.entities.is_valid(self.target) {
+            // Left the university or was fired
+            return state::Action::Pop;
+        }
+        if let Some(pos) = instance.entities.get_component::<Position>(self.target) {
+            state.renderer.suggest_camera_position(pos.x, pos.z, FOLLOW_SMOOTHING);
+        }
+
+        self.next_update -= state.delta;
+        if self.next_update <= 0.0 && self.request_ticket.is_none() {
+            self.next_update = REFRESH_RATE;
+            if let Some(id) = instance.entities.get_component::<NetworkId>(self.target) {
+                self.request_ticket = Some(instance.request_manager.request(player::EntityActivity {
+                    entity_id: id.0,
+                }));
+            }
+        }
+        state::Action::Nothing
+    }
+
+    fn ui_event(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        let instance = assume!(state.global_logger, instance.as_mut());
+        evt.handle_event_if::<CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            action = state::Action::Pop;
+        });
+        evt.inspect_event::<CloseOtherInfos, _>(|evt| {
+            if !evt.0.is_same(&ui) {
+                action = state::Action::Pop;
+            }
+        });
+        if let Some(req) = self.request_ticket {
+            let id = instance.entities.get_component::<NetworkId>(self.target).map(|v| v.0);
+            network::RequestManager::handle_reply(evt, req, |res| {
+                self.request_ticket = None;
+                if Some(res.entity_id) == id {
+                    show_activity(&ui, &res);
+                }
+            });
+        }
+        action
+    }
+
+    fn key_action(&mut self, _instance: &mut Option<GameInstance>, _state: &mut crate::GameState, action: keybinds::KeyAction, _mouse_pos: (i32, i32)) -> state::Action {
+        use crate::keybinds::KeyAction::*;
+
+        match action {
+            SystemMenu => state::Action::Pop,
+            _ => state::Action::Nothing,
+        }
+    }
+}
+
+fn show_activity(ui: &ui::Node, res: &player::EntityActivityReply) {
+    if let Some(activity) = query!(ui, activity > @text).next() {
+        activity.set_text(res.activity.as_str());
+    }
+    if let Some(schedule) = query!(ui, schedule).next() {
+        for c in schedule.children() {
+            schedule.remove_child(c);
+        }
+        if res.schedule.0.is_empty() {
+            schedule.add_child(node! {
+                schedule_empty {
+                    @text("No more lessons today")
+                }
+            });
+        }
+        for entry in &res.schedule.0 {
+            schedule.add_child(node! {
+                schedule_entry {
+                    period {
+                        @text(format!("Period {}", entry.period + 1))
+                    }
+                    lesson {
+                        @text(entry.lesson.as_str())
+                    }
+                }
+            });
+        }
+    }
+}
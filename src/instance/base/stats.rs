@@ -3,12 +3,17 @@ use super::super::*;
 use crate::state;
 use crate::server::event;
 use crate::server::assets;
+use crate::server::export::{self, ExportFormat};
+use crate::server::msg::{SharedChart, ChartKind};
+use crate::server::saving::filesystem::NativeFileSystem;
+use std::path::Path;
 
 pub struct StatsState {
     ui: Option<ui::Node>,
     tab: Tab,
 
     next_update: f64,
+    export_request: Option<network::RequestTicket<player::ExportStats>>,
 }
 
 impl StatsState {
@@ -17,6 +22,7 @@ impl StatsState {
             ui: None,
             tab: Tab::Money,
             next_update: 0.0,
+            export_request: None,
         }
     }
 }
@@ -275,6 +281,15 @@ fn draw_students(instance: &mut crate::GameInstance, state: &mut crate::GameStat
     state.renderer.update_image(ResourceKey::new("dynamic", "650@400@graph"), 650, 400, graph);
 }
 
+const GRADES: &[(Grade, u8, u8, u8)] = &[
+    (Grade::F, 255, 0, 0),
+    (Grade::E, 204, 51, 0),
+    (Grade::D, 153, 102, 0),
+    (Grade::C, 102, 153, 0),
+    (Grade::B, 51, 204, 0),
+    (Grade::A, 0, 255, 0),
+];
+
 fn draw_grades(instance: &mut crate::GameInstance, state: &mut crate::GameState, ui: &ui::Node) {
     use std::cmp::max;
     let min_val = 0;
@@ -323,14 +338,6 @@ fn draw_grades(instance: &mut crate::GameInstance, state: &mut crate::GameState,
         }
     }
 
-    const GRADES: &[(Grade, u8, u8, u8)] = &[
-        (Grade::F, 255, 0, 0),
-        (Grade::E, 204, 51, 0),
-        (Grade::D, 153, 102, 0),
-        (Grade::C, 102, 153, 0),
-        (Grade::B, 51, 204, 0),
-        (Grade::A, 0, 255, 0),
-    ];
     for (xx, data) in instance.player.history.windows(2).enumerate() {
         let mut offset_a = 0;
         let mut offset_b = 0;
@@ -366,6 +373,89 @@ fn draw_grades(instance: &mut crate::GameInstance, state: &mut crate::GameState,
     state.renderer.update_image(ResourceKey::new("dynamic", "650@400@graph"), 650, 400, graph);
 }
 
+const CARD_WIDTH: usize = 160;
+const CARD_HEIGHT: usize = 96;
+
+/// Renders a small version of a chart shared by a player for
+/// displaying in the chat. `slot` selects the image to draw
+/// into so that older cards aren't replaced straight away.
+pub(super) fn draw_chart_card(renderer: &mut crate::render::Renderer, chart: &SharedChart, slot: u32) -> String {
+    // Each series is filled from zero to its value
+    let series: Vec<(Vec<f64>, (u8, u8, u8))> = match chart.kind {
+        ChartKind::Money => vec![
+            (chart.history.iter().map(|v| v.total.0 as f64).collect(), (0, 180, 0)),
+        ],
+        ChartKind::Income => vec![
+            (chart.history.iter().map(|v| v.income.0 as f64).collect(), (0, 180, 0)),
+            (chart.history.iter().map(|v| -v.outcome.0 as f64).collect(), (180, 0, 0)),
+        ],
+        ChartKind::Students => vec![
+            (chart.history.iter().map(|v| f64::from(v.students)).collect(), (180, 180, 0)),
+        ],
+        ChartKind::Grades => {
+            // Stacked so the largest is drawn first and then
+            // covered by the lower grades
+            let mut offsets = vec![0.0; chart.history.len()];
+            let mut series = Vec::with_capacity(GRADES.len());
+            for &(grade, r, g, b) in GRADES {
+                for (offset, entry) in offsets.iter_mut().zip(&chart.history) {
+                    *offset += f64::from(entry.grades[grade.as_index()]);
+                }
+                series.push((offsets.clone(), (r, g, b)));
+            }
+            series.reverse();
+            series
+        },
+    };
+
+    let (min_val, mut max_val) = series.iter()
+        .flat_map(|v| v.0.iter())
+        .fold((0.0f64, 0.0f64), |(min, max), v| (min.min(*v), max.max(*v)));
+    if min_val == max_val {
+        max_val += 10.0;
+    }
+    let to_y = |v: f64| ((v - min_val) / (max_val - min_val) * (CARD_HEIGHT - 1) as f64) as usize;
+    let zero = to_y(0.0);
+
+    let mut card = vec![255; CARD_WIDTH * CARD_HEIGHT * 4];
+    for y in 0 .. CARD_HEIGHT {
+        for x in 0 .. CARD_WIDTH {
+            if x % 20 == 0 || y % 24 == 0 {
+                let idx = (x + y * CARD_WIDTH) * 4;
+                card[idx    ] = 167;
+                card[idx + 1] = 202;
+                card[idx + 2] = 214;
+            }
+        }
+    }
+    for (values, (r, g, b)) in series {
+        if values.len() < 2 {
+            continue;
+        }
+        for x in 0 .. CARD_WIDTH {
+            let pos = x as f64 / (CARD_WIDTH - 1) as f64 * (values.len() - 1) as f64;
+            let i = (pos as usize).min(values.len() - 2);
+            let t = pos - i as f64;
+            let y = to_y(values[i] * (1.0 - t) + values[i + 1] * t);
+            let (low, high) = if y < zero { (y, zero) } else { (zero, y) };
+            for yy in low ..= high {
+                let idx = (x + (CARD_HEIGHT - 1 - yy) * CARD_WIDTH) * 4;
+                card[idx    ] = r;
+                card[idx + 1] = g;
+                card[idx + 2] = b;
+            }
+        }
+    }
+
+    let key = ResourceKey::new("dynamic", format!("{}@{}@chart_card_{}", CARD_WIDTH, CARD_HEIGHT, slot));
+    let img = key.as_string();
+    renderer.update_image(key, CARD_WIDTH as u32, CARD_HEIGHT as u32, card);
+    img
+}
+
+struct ExportStats(ExportFormat);
+struct ShareChart;
+
 #[derive(Clone, Copy, Debug)]
 enum Tab {
     Money,
@@ -383,6 +473,15 @@ impl Tab {
             Tab::Grades => "grades",
         }
     }
+
+    fn chart(self) -> ChartKind {
+        match self {
+            Tab::Money => ChartKind::Money,
+            Tab::Income => ChartKind::Income,
+            Tab::Students => ChartKind::Students,
+            Tab::Grades => ChartKind::Grades,
+        }
+    }
 }
 
 impl state::State for StatsState {
@@ -391,6 +490,7 @@ impl state::State for StatsState {
             ui: self.ui.clone(),
             tab: self.tab,
             next_update: self.next_update,
+            export_request: self.export_request,
         })
     }
 
@@ -425,6 +525,26 @@ impl state::State for StatsState {
             }));
         }
 
+        if let Some(btn) = query!(ui, button(export="csv")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(ExportStats(ExportFormat::Csv));
+                true
+            }));
+        }
+        if let Some(btn) = query!(ui, button(export="json")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(ExportStats(ExportFormat::Json));
+                true
+            }));
+        }
+
+        if let Some(btn) = query!(ui, button(id="share_chart")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(ShareChart);
+                true
+            }));
+        }
+
         draw_money(instance, state, &ui);
         self.next_update = 60.0 * 10.0;
 
@@ -459,7 +579,8 @@ impl state::State for StatsState {
         }
     }
 
-    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+    fn ui_event(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
         let mut action = state::Action::Nothing;
         let ui = assume!(state.global_logger, self.ui.clone());
         evt.handle_event_if::<super::CloseWindowOthers, _, _>(|evt| {
@@ -484,6 +605,45 @@ impl state::State for StatsState {
                 btn.set_property("selected", true);
             }
         });
+        evt.handle_event::<ExportStats, _>(|ExportStats(format)| {
+            if self.export_request.is_none() {
+                self.export_request = Some(instance.request_manager.request(player::ExportStats {
+                    json: format == ExportFormat::Json,
+                }));
+            }
+        });
+        evt.handle_event::<ShareChart, _>(|_| {
+            let kind = self.tab.chart();
+            if let Err(err) = instance.ensure_send(packet::ShareChart { kind }) {
+                error!(state.global_logger, "Failed to share chart"; "error" => %err);
+            } else {
+                state.narrate(&format!("Shared the {} chart", kind.name()));
+            }
+        });
+        if let Some(req) = self.export_request {
+            let mut result = None;
+            network::RequestManager::handle_reply(evt, req, |res| {
+                let fs = NativeFileSystem::new(Path::new("./exports/"));
+                let prefix = format!("stats-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+                let files = res.files.0.into_iter()
+                    .map(|v| (v.name, v.data))
+                    .collect::<Vec<_>>();
+                result = Some(export::write_files(&fs, &prefix, &files));
+            });
+            match result {
+                Some(Ok(files)) => {
+                    info!(state.global_logger, "Exported stats"; "files" => ?files);
+                    state.narrate(&format!("Exported stats to {}", files.join(", ")));
+                    self.export_request = None;
+                },
+                Some(Err(err)) => {
+                    error!(state.global_logger, "Failed to export stats"; "error" => %err);
+                    state.narrate("Failed to export stats");
+                    self.export_request = None;
+                },
+                None => {},
+            }
+        }
         action
     }
 
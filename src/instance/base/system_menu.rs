@@ -55,6 +55,18 @@ impl state::State for SystemMenu {
                 parent.set_property("rows", parent.get_property::<i32>("rows").unwrap_or(4) - 1);
             }
         }
+        if let Some(photo_mode) = query!(ui, button(id="photo_mode")).next() {
+            photo_mode.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(PhotoModeEvent);
+                true
+            }));
+        }
+        if let Some(hud_layout) = query!(ui, button(id="hud_layout")).next() {
+            hud_layout.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(HudLayoutEvent);
+                true
+            }));
+        }
         if let Some(options) = query!(ui, button(id="options")).next() {
             options.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
                 evt.emit(OptionsMenu);
@@ -98,6 +110,14 @@ impl state::State for SystemMenu {
             instance.disconnect();
             action = state::Action::Pop;
         });
+        evt.handle_event::<PhotoModeEvent, _>(|_| {
+            action = state::Action::Push(Box::new(super::photo_mode::PhotoMode::new()));
+        });
+        evt.handle_event::<HudLayoutEvent, _>(|_| {
+            // Replaces the menu so that the HUD is visible whilst
+            // it is being arranged
+            action = state::Action::Switch(Box::new(super::hud_layout::HudLayoutEditor::new()));
+        });
         evt.handle_event::<OptionsMenu, _>(|_| {
             action = state::Action::Push(Box::new(
                 crate::config::OptionsMenuState::new(true)
@@ -118,4 +138,5 @@ impl state::State for SystemMenu {
 
 struct Disconnect;
 struct SaveGame;
-struct OptionsMenu;
\ No newline at end of file
+struct OptionsMenu;
+struct HudLayoutEvent;
\ No newline at end of file
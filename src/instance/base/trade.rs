@@ -0,0 +1,229 @@
+
+use super::*;
+use crate::server::assets;
+use crate::server::network;
+use crate::server::errors::codes;
+use crate::server::level::room;
+
+/// The amounts that can be sent or contributed in one go
+const AMOUNTS: &[i64] = &[1_000, 10_000];
+
+/// Lists the money transfers offered to and by the player and lets
+/// them send money to or fund the rooms of other players.
+///
+/// Opened via the `/trade` chat command.
+pub struct TradeState {
+    ui: Option<ui::Node>,
+    shown: Option<player::SharedState>,
+    request_ticket: Option<network::RequestTicket<player::Trade>>,
+}
+
+impl TradeState {
+    /// Creates the trade window
+    pub(crate) fn new() -> TradeState {
+        TradeState {
+            ui: None,
+            shown: None,
+            request_ticket: None,
+        }
+    }
+}
+
+/// Requests the action is taken by the server
+struct TakeAction(player::TradeAction);
+
+impl state::State for TradeState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(TradeState {
+            ui: self.ui.clone(),
+            shown: self.shown.clone(),
+            request_ticket: self.request_ticket,
+        })
+    }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "manage/trade"));
+        self.ui = Some(ui);
+        self.shown = None;
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut crate::GameState) {
+        if let Some(ui) = self.ui.take() {
+            state.ui_manager.remove_node(ui);
+        }
+    }
+
+    fn tick(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let shared = instance.player.get_shared();
+        if self.shown.as_ref() == Some(&shared) {
+            return state::Action::Nothing;
+        }
+        let ui = assume!(state.global_logger, self.ui.clone());
+        if let Some(content) = query!(ui, scroll_panel > content).next() {
+            for c in content.children() {
+                content.remove_child(c);
+            }
+            fill_trades(&content, instance, &state.asset_manager, &shared);
+        }
+        self.shown = Some(shared);
+        state::Action::Nothing
+    }
+
+    fn ui_event(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState, evt: &mut event::EventHandler) -> state::Action {
+        let instance = assume!(state.global_logger, instance.as_mut());
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            action = state::Action::Pop;
+        });
+        evt.handle_event::<TakeAction, _>(|TakeAction(trade)| {
+            // Only one action at a time, the buttons will be rebuilt
+            // once the server replies
+            if self.request_ticket.is_none() {
+                self.request_ticket = Some(instance.request_manager.request(player::Trade {
+                    action: trade,
+                }));
+            }
+        });
+        if let Some(req) = self.request_ticket {
+            network::RequestManager::handle_reply(evt, req, |res| {
+                self.request_ticket = None;
+                if let Some(code) = res.error {
+                    state.narrate(&codes::describe(code, None));
+                }
+            });
+        }
+        action
+    }
+}
+
+fn player_name(instance: &GameInstance, id: player::Id) -> String {
+    if id == instance.player.get_uid() {
+        "You".to_owned()
+    } else {
+        instance.players.get(&id)
+            .map_or_else(|| "Unknown player".to_owned(), |v| v.name.clone())
+    }
+}
+
+fn action_button(label: String, trade: player::TradeAction) -> ui::Node {
+    let btn = node! {
+        button {
+            content {
+                @text(label)
+            }
+        }
+    };
+    btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+        evt.emit(TakeAction(trade.clone()));
+        true
+    }));
+    btn
+}
+
+fn fill_trades(content: &ui::Node, instance: &GameInstance, assets: &AssetManager, shared: &player::SharedState) {
+    let me = instance.player.get_uid();
+
+    content.add_child(node! { trade_header { @text("Offers") } });
+    for offer in &shared.offers {
+        let entry = if offer.to == me {
+            let entry = node! {
+                trade_entry {
+                    @text(format!("{} offers you {}", player_name(instance, offer.from), offer.amount))
+                }
+            };
+            entry.add_child(action_button("Accept".into(), player::TradeAction::Accept { offer: offer.id }));
+            entry.add_child(action_button("Decline".into(), player::TradeAction::Decline { offer: offer.id }));
+            entry
+        } else {
+            let entry = node! {
+                trade_entry {
+                    @text(format!("You offered {} {}", player_name(instance, offer.to), offer.amount))
+                }
+            };
+            entry.add_child(action_button("Withdraw".into(), player::TradeAction::Decline { offer: offer.id }));
+            entry
+        };
+        content.add_child(entry);
+    }
+
+    content.add_child(node! { trade_header { @text("Send money") } });
+    let mut players: Vec<_> = instance.players.values()
+        .filter(|v| v.id != me)
+        .collect();
+    players.sort_by_key(|v| v.id.0);
+    for p in players {
+        let entry = node! {
+            trade_entry {
+                @text(if p.afk { format!("{} (Away)", p.name) } else { p.name.clone() })
+            }
+        };
+        for amount in AMOUNTS {
+            entry.add_child(action_button(format!("Send {}", UniDollar(*amount)), player::TradeAction::Offer {
+                to: p.id,
+                amount: UniDollar(*amount),
+            }));
+        }
+        content.add_child(entry);
+    }
+
+    content.add_child(node! { trade_header { @text("Fund rooms") } });
+    {
+        let rooms = instance.level.rooms.borrow();
+        for (id, room) in rooms.iter_rooms() {
+            if room.owner == me || room.state.is_done() {
+                continue;
+            }
+            let name = match assets.loader_open::<room::Loader>(room.key.borrow()) {
+                Ok(info) => info.name.clone(),
+                Err(_) => continue,
+            };
+            let entry = node! {
+                trade_entry {
+                    @text(format!("{} being built by {}", name, player_name(instance, room.owner)))
+                }
+            };
+            for amount in AMOUNTS {
+                entry.add_child(action_button(format!("Contribute {}", UniDollar(*amount)), player::TradeAction::Contribute {
+                    room_id: id,
+                    amount: UniDollar(*amount),
+                }));
+            }
+            content.add_child(entry);
+        }
+    }
+
+    content.add_child(node! { trade_header { @text("Co-funded rooms") } });
+    for share in &shared.funding {
+        let text = if share.owner == me {
+            format!("Room {}: others contributed {}", share.room_id.0, share.total)
+        } else {
+            format!("Room {} of {}: you contributed {} of {}", share.room_id.0, player_name(instance, share.owner), share.contributed, share.total)
+        };
+        content.add_child(node! {
+            trade_entry(shared = share.shared) {
+                @text(text)
+            }
+        });
+    }
+
+    content.add_child(node! { trade_header { @text("Recent") } });
+    for entry in &shared.ledger {
+        let reason = match entry.reason {
+            player::LedgerReason::Transfer => "Transfer",
+            player::LedgerReason::Contribution => "Contribution",
+            player::LedgerReason::Refund => "Refund",
+            player::LedgerReason::Funding => "Funding",
+            player::LedgerReason::SharedIncome => "Shared income",
+            player::LedgerReason::Reward => "Reward",
+        };
+        let other = entry.other.map_or_else(String::new, |v| format!(" ({})", player_name(instance, v)));
+        content.add_child(node! {
+            ledger_entry {
+                @text(format!("{}{}: {}", reason, other, entry.amount))
+            }
+        });
+    }
+}
@@ -49,6 +49,9 @@ pub struct BuildState {
     ui: Option<ui::Node>,
     pan_edge: PanEdge,
     last_mouse: (i32, i32),
+    /// The location of the cursor when controlled by the
+    /// keyboard. Cleared when the mouse moves.
+    key_cursor: Option<Location>,
 }
 
 impl BuildState {
@@ -59,6 +62,127 @@ impl BuildState {
             ui: None,
             pan_edge: PanEdge::empty(),
             last_mouse: (0, 0),
+            key_cursor: None,
+        }
+    }
+
+    /// Returns the tile currently being pointed at either by
+    /// the keyboard cursor or the mouse
+    fn cursor_location(&self, renderer: &render::Renderer, mouse_pos: (i32, i32)) -> Location {
+        if let Some(loc) = self.key_cursor {
+            return loc;
+        }
+        let (lx, ly) = renderer.mouse_to_level(mouse_pos.0, mouse_pos.1);
+        Location::new(lx.floor() as i32, ly.floor() as i32)
+    }
+
+    fn begin_selection(&mut self, instance: &mut GameInstance, state: &mut crate::GameState, loc: Location, mouse_pos: (i32, i32)) {
+        // If we aren't currently selecting an area
+        // begin a selection.
+        if self.selection.is_some() {
+            return;
+        }
+        let content = node! {
+            content {
+                @text("1x1")
+            }
+        };
+        let text = assume!(state.global_logger, query!(content, @text).next());
+        state.ui_manager.show_tooltip("current_size", content, mouse_pos.0, mouse_pos.1);
+        let sel = Selection {
+            start: loc,
+            end: loc,
+            tooltip: text,
+        };
+        // Being rendering the selection grid and set the
+        // cursor to notify the player
+        state.renderer.start_selection(instance.player.id, &mut instance.level, self.room.borrow(), sel.start.x, sel.start.y);
+        state.renderer.set_mouse_sprite(ResourceKey::new("base", "ui/cursor/selection"));
+        self.selection = Some(sel);
+        state.narrate(&format!("Selecting from {}, {}", loc.x, loc.y));
+    }
+
+    /// Moves the end of the current selection (if any) to
+    /// the passed location updating the size and cost
+    fn update_selection(&mut self, instance: &mut GameInstance, state: &mut crate::GameState, loc: Location) {
+        if let Some(sel) = self.selection.as_mut() {
+            sel.end = loc;
+            state.renderer.move_selection(instance.player.id, &mut instance.level, self.room.borrow(), sel.end.x, sel.end.y);
+
+            let area = Bound::new(sel.start, sel.end);
+            sel.tooltip.set_text(format!("{}x{}", area.width(), area.height()));
+
+            let room = assume!(state.global_logger, instance.level.asset_manager.loader_open::<room::Loader>(self.room.borrow()));
+            let cost = room.cost_for_area(area);
+            if let Some(price_tag) = query!(assume!(state.global_logger, self.ui.as_ref()), price_tag).next() {
+                price_tag.set_property("can_afford", instance.player.get_money() >= cost);
+                if let Some(txt) = query!(price_tag, @text).next() {
+                    txt.set_text(format!("Cost: {}", cost));
+                }
+            }
+            state.narrate(&format!(
+                "Cursor at {}, {}. Selecting {}x{}, cost {}",
+                loc.x, loc.y, area.width(), area.height(), cost,
+            ));
+        }
+    }
+
+    fn finish_selection(&mut self, req: &mut state::CaptureRequester, instance: &mut GameInstance, state: &mut crate::GameState, loc: Location) -> state::Action {
+        // If there is a selection in progress end it
+        // and take the selected area.
+        if let Some(sel) = self.selection.take() {
+            state.ui_manager.hide_tooltip("current_size");
+
+            // Stop rendering the current selection now as
+            // it needs to stop whether the selection is
+            // successful or not.
+            state.renderer.stop_selection(&mut instance.level, sel.end.x, sel.end.y);
+
+            // Attempt to place a room with the given bounds
+            let mut cmd: command::Command = command::PlaceSelection::new(
+                self.room.borrow(),
+                sel.start, loc
+            ).into();
+            let mut proxy = super::GameProxy::proxy(state);
+
+            try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
+                log: &instance.log,
+                level: &mut instance.level,
+                engine: &instance.scripting,
+                entities: &mut instance.entities,
+                snapshots: &instance.snapshots,
+                mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
+            }), {
+                // Success, preform the same command on the
+                // the server and move to the next screen
+                instance.push_command(cmd, req);
+                return state::Action::Switch(Box::new(FinalizePlacement::new()))
+            });
+            state.narrate("Invalid area");
+        }
+        state::Action::Nothing
+    }
+
+    /// Moves the keyboard cursor by the passed amount keeping
+    /// it within the level
+    fn move_key_cursor(&mut self, instance: &mut GameInstance, state: &mut crate::GameState, dx: i32, dy: i32) {
+        let current = self.cursor_location(&state.renderer, self.last_mouse);
+        let next = Location::new(current.x + dx, current.y + dy);
+        let next = if instance.level.level_bounds.in_bounds(next) {
+            next
+        } else {
+            current
+        };
+        self.key_cursor = Some(next);
+        state.renderer.cursor.model_matrix = cgmath::Matrix4::from_translation(Vector3::new(
+            next.x as f32,
+            0.001,
+            next.y as f32,
+        ));
+        if self.selection.is_some() {
+            self.update_selection(instance, state, next);
+        } else {
+            state.narrate(&format!("Cursor at {}, {}", next.x, next.y));
         }
     }
 }
@@ -80,14 +204,14 @@ fn preload_room_textures(renderer: &mut render::Renderer, level: &mut Level, roo
     for tile in tiles {
         let tile = assume!(renderer.log, level.asset_manager.loader_open::<tile::Loader>(tile.borrow()));
         for tex in tile.get_possible_textures() {
-            renderer.preload_texture(tex.borrow());
+            renderer.preload_texture(assets::TypedKey::from_key(tex.borrow()));
         }
     }
 
     if let Some(wall) = room.wall.as_ref() {
-        renderer.preload_texture(wall.texture.borrow());
+        renderer.preload_texture(assets::TypedKey::from_key(wall.texture.borrow()));
         wall.texture_top.as_ref()
-            .map(|v| renderer.preload_texture(v.borrow()));
+            .map(|v| renderer.preload_texture(assets::TypedKey::from_key(v.borrow())));
     }
 }
 
@@ -99,6 +223,7 @@ impl state::State for BuildState {
             ui: self.ui.clone(),
             pan_edge: self.pan_edge,
             last_mouse: self.last_mouse,
+            key_cursor: self.key_cursor,
         })
     }
 
@@ -106,6 +231,7 @@ impl state::State for BuildState {
         let instance = assume!(state.global_logger, instance.as_mut());
         // Bind placement controls
         state.keybinds.add_collection(keybinds::KeyCollection::RoomPlacement);
+        state.keybinds.add_collection(keybinds::KeyCollection::BuildCursor);
         state.renderer.cursor_visible = true;
 
         // Spawn the room placement ui so that
@@ -121,6 +247,10 @@ impl state::State for BuildState {
         if let Some(txt) = query!(ui, requirements > @text).next() {
             txt.set_text(format!("Requires at least {x} by {y} tiles of space", x = room.min_size.0, y = room.min_size.1));
         }
+        state.narrate(&format!(
+            "Placing {name}, requires at least {x} by {y} tiles of space",
+            name = room.name, x = room.min_size.0, y = room.min_size.1,
+        ));
         if let Some(price_tag) = query!(ui, price_tag).next() {
             let cost = room.base_cost.unwrap_or(UniDollar(0));
             price_tag.set_property("can_afford", instance.player.get_money() >= cost);
@@ -179,6 +309,7 @@ impl state::State for BuildState {
         state.renderer.cursor_visible = false;
         state.renderer.set_mouse_sprite(ResourceKey::new("base", "ui/cursor/normal"));
         state.keybinds.remove_collection(keybinds::KeyCollection::RoomPlacement);
+        state.keybinds.remove_collection(keybinds::KeyCollection::BuildCursor);
         state.renderer.clear_lowered_region();
         instance.level.tiles.borrow_mut().flag_all_dirty();
 
@@ -201,42 +332,35 @@ impl state::State for BuildState {
     }
 
     fn mouse_move(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState, mouse_pos: (i32, i32)) -> state::Action {
+        // Moving the mouse takes control back from the keyboard
+        if mouse_pos != self.last_mouse {
+            self.key_cursor = None;
+        }
         self.last_mouse = mouse_pos;
         let instance = assume!(state.global_logger, instance.as_mut());
-        let (lx, ly) = state.renderer.mouse_to_level(mouse_pos.0, mouse_pos.1);
 
         // If we are currently selecting update the position using
         // the mouse's current position. (Snapped to tiles)
-        let mouse_loc = Location::new(lx.floor() as i32, ly.floor() as i32);
+        let mouse_loc = self.cursor_location(&state.renderer, mouse_pos);
         self.pan_edge = PanEdge::empty();
-        if let Some(sel) = self.selection.as_mut() {
-            sel.end = mouse_loc;
-            state.renderer.move_selection(instance.player.id, &mut instance.level, self.room.borrow(), sel.end.x, sel.end.y);
+        if self.selection.is_some() {
             state.ui_manager.move_tooltip("current_size", mouse_pos.0, mouse_pos.1);
 
             // Pan the camera for the player if they are near to the edge of the screen
-            if mouse_pos.0 <= EDGE_DISTANCE {
-                self.pan_edge |= PanEdge::LEFT;
-            } else if mouse_pos.0 >= state.renderer.width as i32 - EDGE_DISTANCE {
-                self.pan_edge |= PanEdge::RIGHT;
-            }
-            if mouse_pos.1 <= EDGE_DISTANCE {
-                self.pan_edge |= PanEdge::UP;
-            } else if mouse_pos.1 >= state.renderer.height as i32 - EDGE_DISTANCE {
-                self.pan_edge |= PanEdge::DOWN;
-            }
-
-            let area = Bound::new(sel.start, sel.end);
-            sel.tooltip.set_text(format!("{}x{}", area.width(), area.height()));
-
-            let room = assume!(state.global_logger, instance.level.asset_manager.loader_open::<room::Loader>(self.room.borrow()));
-            if let Some(price_tag) = query!(assume!(state.global_logger, self.ui.as_ref()), price_tag).next() {
-                let cost = room.cost_for_area(area);
-                price_tag.set_property("can_afford", instance.player.get_money() >= cost);
-                if let Some(txt) = query!(price_tag, @text).next() {
-                    txt.set_text(format!("Cost: {}", cost));
+            if self.key_cursor.is_none() {
+                if mouse_pos.0 <= EDGE_DISTANCE {
+                    self.pan_edge |= PanEdge::LEFT;
+                } else if mouse_pos.0 >= state.renderer.width as i32 - EDGE_DISTANCE {
+                    self.pan_edge |= PanEdge::RIGHT;
+                }
+                if mouse_pos.1 <= EDGE_DISTANCE {
+                    self.pan_edge |= PanEdge::UP;
+                } else if mouse_pos.1 >= state.renderer.height as i32 - EDGE_DISTANCE {
+                    self.pan_edge |= PanEdge::DOWN;
                 }
             }
+
+            self.update_selection(instance, state, mouse_loc);
         }
 
         state::Action::Nothing
@@ -249,65 +373,35 @@ impl state::State for BuildState {
 
         match action {
             RoomStartAreaSelect => {
-                // If we aren't currently selecting an area
-                // begin a selection.
-                if self.selection.is_none() {
-                    let (lx, ly) = state.renderer.mouse_to_level(mouse_pos.0, mouse_pos.1);
-                    let (lx, ly) = (lx.floor() as i32, ly.floor() as i32);
-
-                    let content = node! {
-                        content {
-                            @text("1x1")
-                        }
-                    };
-                    let text = assume!(state.global_logger, query!(content, @text).next());
-                    state.ui_manager.show_tooltip("current_size", content, mouse_pos.0, mouse_pos.1);
-                    let sel = Selection {
-                        start: Location::new(lx, ly),
-                        end: Location::new(lx, ly),
-                        tooltip: text,
-                    };
-                    // Being rendering the selection grid and set the
-                    // cursor to notify the player
-                    state.renderer.start_selection(instance.player.id, &mut instance.level, self.room.borrow(), sel.start.x, sel.start.y);
-                    state.renderer.set_mouse_sprite(ResourceKey::new("base", "ui/cursor/selection"));
-                    self.selection = Some(sel);
-                }
+                self.key_cursor = None;
+                let loc = self.cursor_location(&state.renderer, mouse_pos);
+                self.begin_selection(instance, state, loc, mouse_pos);
             },
-
             RoomFinishAreaSelect => {
-                // If there is a selection in progress end it
-                // and take the selected area.
+                self.key_cursor = None;
+                let loc = self.cursor_location(&state.renderer, mouse_pos);
+                return self.finish_selection(req, instance, state, loc);
+            },
+
+            BuildCursorLeft => self.move_key_cursor(instance, state, -1, 0),
+            BuildCursorRight => self.move_key_cursor(instance, state, 1, 0),
+            BuildCursorUp => self.move_key_cursor(instance, state, 0, -1),
+            BuildCursorDown => self.move_key_cursor(instance, state, 0, 1),
+            BuildCursorConfirm => {
+                let loc = self.cursor_location(&state.renderer, mouse_pos);
+                if self.selection.is_some() {
+                    return self.finish_selection(req, instance, state, loc);
+                }
+                self.begin_selection(instance, state, loc, mouse_pos);
+            },
+            BuildCursorCancel => {
                 if let Some(sel) = self.selection.take() {
                     state.ui_manager.hide_tooltip("current_size");
-                    let (lx, ly) = state.renderer.mouse_to_level(mouse_pos.0, mouse_pos.1);
-                    let (lx, ly) = (lx.floor() as i32, ly.floor() as i32);
-
-                    // Stop rendering the current selection now as
-                    // it needs to stop whether the selection is
-                    // successful or not.
                     state.renderer.stop_selection(&mut instance.level, sel.end.x, sel.end.y);
-
-                    // Attempt to place a room with the given bounds
-                    let mut cmd: command::Command = command::PlaceSelection::new(
-                        self.room.borrow(),
-                        sel.start, Location::new(lx, ly)
-                    ).into();
-                    let mut proxy = super::GameProxy::proxy(state);
-
-                    try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
-                        log: &instance.log,
-                        level: &mut instance.level,
-                        engine: &instance.scripting,
-                        entities: &mut instance.entities,
-                        snapshots: &instance.snapshots,
-                        mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
-                    }), {
-                        // Success, preform the same command on the
-                        // the server and move to the next screen
-                        instance.push_command(cmd, req);
-                        return state::Action::Switch(Box::new(FinalizePlacement::new()))
-                    });
+                    state.renderer.set_mouse_sprite(ResourceKey::new("base", "ui/cursor/normal"));
+                    state.narrate("Selection cancelled");
+                } else {
+                    return state::Action::Pop;
                 }
             },
             _ => {},
@@ -345,6 +439,82 @@ impl FinalizePlacement {
             last_bounds_try: Bound::new(Location::zero(), Location::zero()),
         }
     }
+
+    /// Attempts to resize the active room to the passed bounds
+    fn try_resize(&mut self, req: &mut state::CaptureRequester, instance: &mut GameInstance, state: &mut crate::GameState, room: room::Id, new_bounds: Bound) {
+        let bounds = instance.level.get_room_info(room).area;
+        // Save bandwidth by only updating the server
+        // when the size changes
+        if bounds == new_bounds || new_bounds == self.last_bounds_try {
+            return;
+        }
+        self.last_bounds_try = new_bounds;
+        let mut cmd: command::Command = command::ResizeRoom::new(new_bounds).into();
+        let mut proxy = super::GameProxy::proxy(state);
+        try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
+            log: &instance.log,
+            level: &mut instance.level,
+            engine: &instance.scripting,
+            entities: &mut instance.entities,
+            snapshots: &instance.snapshots,
+            mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
+        }), {
+            instance.push_command(cmd, req);
+
+            let room = instance.level.get_room_info(room);
+            let room_info = assume!(proxy.state.global_logger, instance.asset_manager.loader_open::<room::Loader>(room.key.borrow()));
+            let cost = room_info.cost_for_area(room.area) - room.placement_cost;
+            let cost = if cost < UniDollar(0) {
+                UniDollar(0)
+            } else {
+                cost
+            };
+            if let Some(price_tag) = query!(assume!(proxy.state.global_logger, self.ui.as_ref()), price_tag).next() {
+                price_tag.set_property("can_afford", instance.player.get_money() >= cost);
+                if let Some(txt) = query!(price_tag, @text).next() {
+                    txt.set_text(format!("Cost: {}", cost));
+                }
+            }
+            proxy.state.narrate(&format!(
+                "Room resized to {}x{}, cost {}",
+                room.area.width(), room.area.height(), cost,
+            ));
+        });
+    }
+
+    fn cancel_placement(req: &mut state::CaptureRequester, instance: &mut GameInstance, state: &mut crate::GameState) -> state::Action {
+        let mut cmd: command::Command = command::CancelRoomPlacement::default().into();
+        let mut proxy = super::GameProxy::proxy(state);
+        try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
+            log: &instance.log,
+            level: &mut instance.level,
+            engine: &instance.scripting,
+            entities: &mut instance.entities,
+            snapshots: &instance.snapshots,
+            mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
+        }), {
+            instance.push_command(cmd, req);
+            return state::Action::Pop;
+        });
+        state::Action::Nothing
+    }
+
+    fn finalize_placement(req: &mut state::CaptureRequester, instance: &mut GameInstance, state: &mut crate::GameState) -> state::Action {
+        let mut cmd: command::Command = command::FinalizeRoomPlacement::default().into();
+        let mut proxy = super::GameProxy::proxy(state);
+        try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
+            log: &instance.log,
+            level: &mut instance.level,
+            engine: &instance.scripting,
+            entities: &mut instance.entities,
+            snapshots: &instance.snapshots,
+            mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
+        }), {
+            instance.push_command(cmd, req);
+            return state::Action::Switch(Box::new(BuildRoom::new(false)));
+        });
+        state::Action::Nothing
+    }
 }
 
 impl state::State for FinalizePlacement {
@@ -371,6 +541,7 @@ impl state::State for FinalizePlacement {
 
         // Update the cursor and spawn the planning ui
         state.renderer.set_mouse_sprite(ResourceKey::new("base", "ui/cursor/question"));
+        state.keybinds.add_collection(keybinds::KeyCollection::BuildCursor);
 
         let ui = state.ui_manager.create_node(assets::ResourceKey::new("base", "room/plan_room"));
         self.ui = Some(ui.clone());
@@ -418,6 +589,7 @@ impl state::State for FinalizePlacement {
         // If the resize keybinds are bound remove
         // them.
         state.keybinds.remove_collection(keybinds::KeyCollection::RoomResize);
+        state.keybinds.remove_collection(keybinds::KeyCollection::BuildCursor);
     }
 
     fn tick_req(&mut self, req: &mut state::CaptureRequester, instance: &mut Option<GameInstance>, state: &mut crate::GameState) -> state::Action {
@@ -484,38 +656,7 @@ impl state::State for FinalizePlacement {
                 new_bounds.max.y = mouse_loc.y;
             }
 
-            // Save bandwidth by only updating the server
-            // when the size changes
-            if bounds != new_bounds && new_bounds != self.last_bounds_try {
-                self.last_bounds_try = new_bounds;
-                let mut cmd: command::Command = command::ResizeRoom::new(new_bounds).into();
-                let mut proxy = super::GameProxy::proxy(state);
-                try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
-                    log: &instance.log,
-                    level: &mut instance.level,
-                    engine: &instance.scripting,
-                    entities: &mut instance.entities,
-                    snapshots: &instance.snapshots,
-                    mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
-                }), {
-                    instance.push_command(cmd, req);
-
-                    let room = instance.level.get_room_info(room);
-                    let room_info = assume!(proxy.state.global_logger, instance.asset_manager.loader_open::<room::Loader>(room.key.borrow()));
-                    if let Some(price_tag) = query!(assume!(proxy.state.global_logger, self.ui.as_ref()), price_tag).next() {
-                        let cost = room_info.cost_for_area(room.area) - room.placement_cost;
-                        let cost = if cost < UniDollar(0) {
-                            UniDollar(0)
-                        } else {
-                            cost
-                        };
-                        price_tag.set_property("can_afford", instance.player.get_money() >= cost);
-                        if let Some(txt) = query!(price_tag, @text).next() {
-                            txt.set_text(format!("Cost: {}", cost));
-                        }
-                    }
-                });
-            }
+            self.try_resize(req, instance, state, room, new_bounds);
         } else {
             let room = instance.level.get_room_info(room);
             let ray = state.renderer.get_mouse_ray(mouse_pos.0, mouse_pos.1);
@@ -545,7 +686,7 @@ impl state::State for FinalizePlacement {
         state::Action::Nothing
     }
 
-    fn key_action(&mut self, instance: &mut Option<GameInstance>, state: &mut crate::GameState, action: keybinds::KeyAction, mouse_pos: (i32, i32)) -> state::Action {
+    fn key_action_req(&mut self, req: &mut state::CaptureRequester, instance: &mut Option<GameInstance>, state: &mut crate::GameState, action: keybinds::KeyAction, mouse_pos: (i32, i32)) -> state::Action {
         use crate::keybinds::KeyAction::*;
         let instance = assume!(state.global_logger, instance.as_mut());
         match action {
@@ -604,6 +745,27 @@ impl state::State for FinalizePlacement {
                 state.renderer.set_mouse_sprite(ResourceKey::new("base", "ui/cursor/resize"));
                 self.resize_edges = None;
             },
+
+            // Keyboard resizing moves the far corner of the room
+            BuildCursorLeft | BuildCursorRight | BuildCursorUp | BuildCursorDown => {
+                let room = if let State::BuildRoom{active_room} = instance.player.state {
+                    active_room
+                } else {
+                    return state::Action::Nothing;
+                };
+                let mut new_bounds = instance.level.get_room_info(room).area;
+                match action {
+                    BuildCursorLeft => new_bounds.max.x -= 1,
+                    BuildCursorRight => new_bounds.max.x += 1,
+                    BuildCursorUp => new_bounds.max.y -= 1,
+                    _ => new_bounds.max.y += 1,
+                }
+                if new_bounds.max.x >= new_bounds.min.x && new_bounds.max.y >= new_bounds.min.y {
+                    self.try_resize(req, instance, state, room, new_bounds);
+                }
+            },
+            BuildCursorConfirm => return Self::finalize_placement(req, instance, state),
+            BuildCursorCancel => return Self::cancel_placement(req, instance, state),
             _ => {},
         }
         state::Action::Nothing
@@ -616,36 +778,12 @@ impl state::State for FinalizePlacement {
 
         // Cancel placement
         evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
-            let mut cmd: command::Command = command::CancelRoomPlacement::default().into();
-            let mut proxy = super::GameProxy::proxy(state);
-            try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
-                log: &instance.log,
-                level: &mut instance.level,
-                engine: &instance.scripting,
-                entities: &mut instance.entities,
-                snapshots: &instance.snapshots,
-                mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
-            }), {
-                instance.push_command(cmd, req);
-                action = state::Action::Pop;
-            });
+            action = Self::cancel_placement(req, instance, state);
         });
 
         // Attempt placement and continue to the next state
         evt.handle_event_if::<super::AcceptEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
-            let mut cmd: command::Command = command::FinalizeRoomPlacement::default().into();
-            let mut proxy = super::GameProxy::proxy(state);
-            try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
-                log: &instance.log,
-                level: &mut instance.level,
-                engine: &instance.scripting,
-                entities: &mut instance.entities,
-                snapshots: &instance.snapshots,
-                mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
-            }), {
-                instance.push_command(cmd, req);
-                action = state::Action::Switch(Box::new(BuildRoom::new(false)));
-            });
+            action = Self::finalize_placement(req, instance, state);
         });
         action
     }
@@ -678,6 +816,7 @@ enum PlacementStyleData {
 struct SetObject(assets::ResourceKey<'static>, bool, i16);
 struct ToggleWallsEvent;
 struct ShowRequiredEvent;
+struct AutoFurnishEvent(usize);
 
 impl BuildRoom {
     pub fn new(limited_mode: bool) -> BuildRoom {
@@ -726,6 +865,44 @@ impl BuildRoom {
         }
     }
 
+    /// Resizes or moves the active room with the passed command and
+    /// updates the ui to match the room's new area
+    fn reshape_room(&mut self, req: &mut state::CaptureRequester, instance: &mut GameInstance, state: &mut crate::GameState, mut cmd: command::Command) {
+        let room_id = match instance.player.state {
+            State::EditRoom{active_room} => active_room,
+            _ => panic!("Player is in the incorrect state"),
+        };
+        let mut proxy = super::GameProxy::proxy(state);
+        match cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
+            log: &instance.log,
+            level: &mut instance.level,
+            engine: &instance.scripting,
+            entities: &mut instance.entities,
+            snapshots: &instance.snapshots,
+            mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
+        }) {
+            Ok(_) => {
+                instance.push_command(cmd, req);
+                let area = instance.level.get_room_info(room_id).area;
+                proxy.state.renderer.set_focused_region(area);
+                proxy.state.narrate(&format!(
+                    "Room changed to {}x{} at {}, {}",
+                    area.width(), area.height(), area.min.x, area.min.y,
+                ));
+                let valid = self.is_room_valid(instance, &mut proxy.state.ui_manager);
+                if let Some(btn) = query!(assume!(proxy.state.global_logger, self.ui.as_ref()), button(id="accept")).next() {
+                    btn.set_property("disabled", !valid);
+                }
+            },
+            Err(err) => {
+                proxy.state.audio.controller
+                    .borrow_mut()
+                    .play_sound(ResourceKey::new("base", "place_fail"));
+                error!(instance.log, "Failed to execute command: {:?}", err);
+            },
+        }
+    }
+
     fn is_room_waiting(&self, instance: &mut GameInstance) -> bool {
         if self.limited_mode {
             return false;
@@ -824,6 +1001,41 @@ impl BuildRoom {
                 content.remove_child(c);
             }
 
+            // Prefabs are listed first as a quick way to fill the room
+            if !required_only && !room.prefabs.is_empty() {
+                let group_node = node! {
+                    object_group(entries=room.prefabs.len() as i32, r=1.0, g=1.0, b=1.0, open=true) {
+                        background
+                        title {
+                            @text("Prefabs")
+                        }
+                        objects {
+                        }
+                    }
+                };
+                if let Some(objects) = query!(group_node, objects).next() {
+                    for (id, prefab) in room.prefabs.iter().enumerate() {
+                        let cost = assume!(instance.log, prefab.required_cost(&instance.asset_manager));
+                        let node = node! {
+                            prefab_entry(id = id as i32, min_width = prefab.min_size.0, min_height = prefab.min_size.1) {
+                                name {
+                                    @text(prefab.name.clone())
+                                }
+                                price(can_afford=true) {
+                                    @text(cost.to_string())
+                                }
+                            }
+                        };
+                        node.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+                            evt.emit(AutoFurnishEvent(id));
+                            true
+                        }));
+                        objects.add_child(node);
+                    }
+                }
+                content.add_child(group_node);
+            }
+
             let mut groups = FNVMap::default();
             for (id, object) in room.valid_objects.iter().enumerate() {
                 let obj = assume!(instance.log, instance.asset_manager.loader_open::<object::Loader>(object.borrow()));
@@ -1197,7 +1409,7 @@ impl state::State for BuildRoom {
                 {
                     for e in &entities {
                         instance.entities.add_component(*e, entity::Highlighted {
-                            color: (0, 255, 255)
+                            color: state.config.colour_palette.get().object_highlight()
                         });
                     }
                     self.highlighted_entities = Some(entities);
@@ -1416,6 +1628,44 @@ impl state::State for BuildRoom {
                     btn.set_property("disabled", !valid);
                 }
             }
+            // Moving and resizing is only possible when the whole room is
+            // being rebuilt and there isn't an object being placed
+            RoomMoveLeft | RoomMoveRight | RoomMoveUp | RoomMoveDown => {
+                if self.limited_mode || self.placement_obj.is_some() {
+                    return state::Action::Nothing;
+                }
+                let room_id = match instance.player.state {
+                    State::EditRoom{active_room} => active_room,
+                    _ => panic!("Player is in the incorrect state"),
+                };
+                let mut target = instance.level.get_room_info(room_id).area.min;
+                match action {
+                    RoomMoveLeft => target.x -= 1,
+                    RoomMoveRight => target.x += 1,
+                    RoomMoveUp => target.y -= 1,
+                    _ => target.y += 1,
+                }
+                self.reshape_room(req, instance, state, command::MoveRoom::new(target).into());
+            },
+            RoomGrowWidth | RoomShrinkWidth | RoomGrowHeight | RoomShrinkHeight => {
+                if self.limited_mode || self.placement_obj.is_some() {
+                    return state::Action::Nothing;
+                }
+                let room_id = match instance.player.state {
+                    State::EditRoom{active_room} => active_room,
+                    _ => panic!("Player is in the incorrect state"),
+                };
+                let mut new_bounds = instance.level.get_room_info(room_id).area;
+                match action {
+                    RoomGrowWidth => new_bounds.max.x += 1,
+                    RoomShrinkWidth => new_bounds.max.x -= 1,
+                    RoomGrowHeight => new_bounds.max.y += 1,
+                    _ => new_bounds.max.y -= 1,
+                }
+                if new_bounds.max.x >= new_bounds.min.x && new_bounds.max.y >= new_bounds.min.y {
+                    self.reshape_room(req, instance, state, command::ResizeEditRoom::new(new_bounds, false).into());
+                }
+            },
             _ => {},
         }
         state::Action::Nothing
@@ -1593,6 +1843,30 @@ impl state::State for BuildRoom {
             }
         });
 
+        // Clicking a prefab in the list
+        evt.handle_event::<AutoFurnishEvent, _>(|AutoFurnishEvent(id)| {
+            if self.placement_obj.is_some() {
+                self.cancel_object_placement(instance, state);
+            }
+            let mut cmd: command::Command = command::AutoFurnish::new(id).into();
+            let mut proxy = super::GameProxy::proxy(state);
+            try_cmd!(instance.log, cmd.execute(&mut proxy, &mut instance.player, command::CommandParams {
+                log: &instance.log,
+                level: &mut instance.level,
+                engine: &instance.scripting,
+                entities: &mut instance.entities,
+                snapshots: &instance.snapshots,
+                mission_handler: instance.mission_handler.as_ref().map(|v| v.borrow()),
+            }), {
+                instance.push_command(cmd, req);
+                // Update the requirements list
+                self.is_room_valid(instance, &mut proxy.state.ui_manager);
+                proxy.state.audio.controller
+                    .borrow_mut()
+                    .play_sound(ResourceKey::new("base", "place"));
+            });
+        });
+
         // Lowers the walls to make placing/moving easier
         evt.handle_event::<ToggleWallsEvent, _>(|_| {
             let room_id = match instance.player.state {
@@ -0,0 +1,202 @@
+//! Plays the visual effects requested by the mission.
+//!
+//! The server already limits the effects, the limits here stop
+//! several effects stacking into something unpleasant and apply
+//! the player's `reduce_effects` setting.
+
+use crate::ecs;
+use crate::entity::{Icon, Color, FadeOverLife};
+use crate::render;
+use crate::server::assets::ResourceKey;
+use crate::server::effects::VisualEffect;
+use crate::server::entity::{Lifetime, Position, Velocity};
+use rand::{thread_rng, Rng};
+
+/// The furthest the camera may be moved by shaking in tiles
+const MAX_SHAKE_OFFSET: f32 = 0.4;
+/// The most a flash may cover the scene
+const MAX_FLASH: f32 = 0.7;
+/// The most a flash may cover the scene with reduced effects
+const REDUCED_FLASH: f32 = 0.15;
+/// The most confetti that may exist at once
+const MAX_LIVE_CONFETTI: usize = 300;
+/// The fraction of confetti created with reduced effects
+const REDUCED_CONFETTI: u16 = 4;
+
+const CONFETTI_COLORS: [(u8, u8, u8); 5] = [
+    (237, 85, 101),
+    (255, 206, 84),
+    (72, 207, 173),
+    (93, 156, 236),
+    (172, 146, 236),
+];
+
+#[derive(Clone, Copy)]
+struct Timed {
+    /// The time in seconds the effect has been playing
+    time: f32,
+    /// The length of the effect in seconds
+    duration: f32,
+}
+
+impl Timed {
+    fn new(duration: f32) -> Timed {
+        Timed {
+            time: 0.0,
+            duration,
+        }
+    }
+
+    /// Returns the fraction of the effect remaining
+    fn remaining(self) -> f32 {
+        if self.duration <= 0.0 {
+            0.0
+        } else {
+            (1.0 - self.time / self.duration).max(0.0)
+        }
+    }
+}
+
+/// The state of the effects currently playing
+pub(crate) struct EffectPlayer {
+    shake: Option<(f32, Timed)>,
+    /// The camera offset picked by the last step of shaking
+    shake_offset: (f32, f32),
+    flash: Option<((f32, f32, f32), Timed)>,
+    fog_from: f32,
+    fog_to: f32,
+    fog_fade: Timed,
+    confetti: Vec<ecs::Entity>,
+}
+
+impl EffectPlayer {
+    pub(crate) fn new() -> EffectPlayer {
+        EffectPlayer {
+            shake: None,
+            shake_offset: (0.0, 0.0),
+            flash: None,
+            fog_from: 0.0,
+            fog_to: 0.0,
+            fog_fade: Timed::new(0.0),
+            confetti: Vec::new(),
+        }
+    }
+
+    /// Starts playing the effect
+    pub(crate) fn play(&mut self, entities: &mut ecs::Container, effect: VisualEffect, reduce: bool) {
+        match effect {
+            // Shaking is skipped entirely with reduced effects as
+            // even small amounts can be uncomfortable
+            VisualEffect::Shake { .. } if reduce => {},
+            VisualEffect::Shake { strength, duration } => {
+                // A new shake replaces the current one instead of
+                // adding to it
+                let strength = self.shake
+                    .filter(|v| v.1.remaining() > 0.0)
+                    .map_or(strength, |v| (v.0 * v.1.remaining()).max(strength));
+                self.shake = Some((strength.min(1.0), Timed::new(duration)));
+            },
+            VisualEffect::Flash { r, g, b, duration } => {
+                let color = (f32::from(r) / 255.0, f32::from(g) / 255.0, f32::from(b) / 255.0);
+                self.flash = Some((color, Timed::new(duration)));
+            },
+            VisualEffect::Fog { density, fade } => {
+                self.fog_from = self.fog_density();
+                self.fog_to = density;
+                self.fog_fade = Timed::new(fade);
+            },
+            VisualEffect::Confetti { x, y, count } => {
+                let count = if reduce { count / REDUCED_CONFETTI } else { count };
+                self.spawn_confetti(entities, x, y, count);
+            },
+        }
+    }
+
+    fn fog_density(&self) -> f32 {
+        let t = 1.0 - self.fog_fade.remaining();
+        self.fog_from + (self.fog_to - self.fog_from) * t
+    }
+
+    fn spawn_confetti(&mut self, entities: &mut ecs::Container, x: f32, y: f32, count: u16) {
+        self.confetti.retain(|e| entities.is_valid(*e));
+        let count = usize::from(count).min(MAX_LIVE_CONFETTI.saturating_sub(self.confetti.len()));
+        let mut rng = thread_rng();
+        for _ in 0 .. count {
+            let e = entities.new_entity();
+            let angle = rng.gen_range(0.0, ::std::f32::consts::PI * 2.0);
+            let speed = rng.gen_range(0.02, 0.08);
+            let up = rng.gen_range(0.06, 0.14);
+            let velocity = (angle.cos() * speed, up, angle.sin() * speed);
+            let life = rng.gen_range(60, 100);
+            entities.add_component(e, Position {
+                x, y: 0.5, z: y,
+            });
+            entities.add_component(e, Velocity {
+                velocity,
+                // Slows to a stop over the first half of its life
+                friction: (
+                    velocity.0 / (life / 2) as f32,
+                    velocity.1 / (life / 2) as f32,
+                    velocity.2 / (life / 2) as f32,
+                ),
+            });
+            entities.add_component(e, Icon {
+                texture: ResourceKey::new("base", "icons/confetti"),
+                size: (0.12, 0.12),
+            });
+            let color = CONFETTI_COLORS[rng.gen_range(0, CONFETTI_COLORS.len())];
+            entities.add_component(e, Color {
+                color: (color.0, color.1, color.2, 255),
+            });
+            entities.add_component(e, Lifetime::new(life));
+            entities.add_component(e, FadeOverLife);
+            self.confetti.push(e);
+        }
+    }
+
+    /// Advances the effects by the fixed steps and applies them to
+    /// the renderer.
+    ///
+    /// `step` is in the same units as the frame delta (60ths of
+    /// a second). Stepping at a fixed rate keeps shaking at the same
+    /// speed whatever the frame rate
+    pub(crate) fn update(&mut self, renderer: &mut render::RenderState, steps: u32, step: f64, reduce: bool) {
+        let secs = (step / 60.0) as f32;
+        let mut rng = thread_rng();
+        for _ in 0 .. steps {
+            if let Some((strength, timed)) = self.shake.as_mut() {
+                timed.time += secs;
+                let remaining = timed.remaining();
+                if remaining <= 0.0 || reduce {
+                    self.shake = None;
+                    self.shake_offset = (0.0, 0.0);
+                } else {
+                    let offset = *strength * remaining * MAX_SHAKE_OFFSET;
+                    self.shake_offset = (
+                        rng.gen_range(-1.0, 1.0) * offset,
+                        rng.gen_range(-1.0, 1.0) * offset,
+                    );
+                }
+            }
+            if let Some((_, timed)) = self.flash.as_mut() {
+                timed.time += secs;
+                if timed.remaining() <= 0.0 {
+                    self.flash = None;
+                }
+            }
+            self.fog_fade.time = (self.fog_fade.time + secs).min(self.fog_fade.duration);
+        }
+
+        let mut effects = render::ScreenEffects::default();
+        if self.shake.is_some() && !reduce {
+            effects.shake = self.shake_offset;
+        }
+        if let Some((color, timed)) = self.flash.as_ref() {
+            let max = if reduce { REDUCED_FLASH } else { MAX_FLASH };
+            effects.flash = (color.0, color.1, color.2, timed.remaining() * max);
+        }
+        effects.fog_density = self.fog_density();
+
+        renderer.screen_effects = effects;
+    }
+}
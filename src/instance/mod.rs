@@ -1,9 +1,12 @@
 //! Game instance management
 
 use std::thread;
+use std::cmp;
 use std::sync::mpsc;
 use crate::util::FNVMap;
 use std::time;
+use std::rc::Rc;
+use std::cell::RefCell;
 #[cfg(feature = "steam")]
 use server::steamworks;
 
@@ -11,7 +14,11 @@ use server::steamworks;
 mod base;
 pub use self::base::BaseState;
 mod build;
+mod effects;
+pub(crate) mod net_stats;
+pub(crate) mod scatter;
 pub(crate) mod scripting;
+pub(crate) mod tutorial;
 
 use crate::state;
 use crate::ui;
@@ -72,6 +79,16 @@ pub struct GameInstance {
     // Number of ticks until next keep alive packet
     next_keep_alive: i8,
     last_keep_alive_reply: time::Instant,
+    // Camera position when activity was last reported to the
+    // server, used to stop us being marked as away whilst
+    // looking around
+    last_activity_camera: (f32, f32),
+    last_activity_sent: time::Instant,
+    // Camera position last sent to the server as our view
+    // area along with when, resent every so often as it can be
+    // dropped
+    last_view_area: Option<(f32, f32)>,
+    last_view_area_sent: time::Instant,
     // Command tracking
     next_command_id: u32,
     // List of command we've executed recently.
@@ -104,11 +121,26 @@ pub struct GameInstance {
     delayed_notifications: Vec<server::notify::Notification>,
     notification_next_id: u32,
     chat_messages: Vec<Message>,
+    /// Cutscenes waiting to be played
+    pub(crate) cutscenes: Vec<server::cutscene::Cutscene>,
+    screen_effects: effects::EffectPlayer,
 
     pub(crate) screenshot_helper: Option<ScreenshotHelper>,
 
     server_player: ServerPlayer,
     mission_handler: Option<ResourceKey<'static>>,
+
+    tutorial: Rc<RefCell<tutorial::TutorialState>>,
+    tutorial_overlay: tutorial::TutorialOverlay,
+
+    /// The latest tick timings from the server if the
+    /// performance hud asked for them
+    pub(crate) server_perf: Option<packet::PerfStats>,
+    /// Round trip time and traffic of the connection to the
+    /// server
+    pub(crate) net_stats: net_stats::NetStats,
+    /// The props painted with the level editor's scatter brush
+    pub(crate) scatter_props: scatter::ScatterProps,
 }
 
 pub(crate) struct ScreenshotHelper {
@@ -128,6 +160,101 @@ impl server::saving::IconCapture for ServerScreenshot {
     }
 }
 
+/// How long the local server can go without reporting any
+/// progress before loading is considered to have failed
+const LOCAL_LOAD_TIMEOUT: time::Duration = time::Duration::from_secs(30);
+/// How far in tiles the camera has to move before the server
+/// is told about our new view area
+const VIEW_AREA_STEP: f32 = 4.0;
+
+/// A local server that is still setting up the game.
+///
+/// Polled by the loading screen until the server begins the
+/// game at which point the client's instance can be created.
+/// Dropping this before then cancels the load.
+pub(crate) struct PendingSinglePlayer {
+    log: Logger,
+    #[cfg(feature = "steam")]
+    steam: steamworks::Client,
+    socket: mpsc::Receiver<(network::LoopbackSocket, mpsc::Receiver<()>)>,
+    connection: Option<(Sender, Receiver, mpsc::Receiver<()>)>,
+    progress_recv: mpsc::Receiver<server::LoadProgress>,
+    progress: Option<server::LoadProgress>,
+    screenshot: Option<ScreenshotHelper>,
+    server_thread: Option<thread::JoinHandle<()>>,
+    last_activity: time::Instant,
+}
+
+impl PendingSinglePlayer {
+    /// Returns the last progress reported by the server
+    pub(crate) fn progress(&self) -> Option<server::LoadProgress> {
+        self.progress
+    }
+
+    /// Checks whether the server has started the game, returning
+    /// the game's initial state once it has.
+    pub(crate) fn poll(&mut self) -> errors::Result<Option<packet::GameBegin>> {
+        while let Ok(progress) = self.progress_recv.try_recv() {
+            self.progress = Some(progress);
+            self.last_activity = time::Instant::now();
+        }
+        if self.connection.is_none() {
+            match self.socket.try_recv() {
+                Ok((socket, shutdown)) => {
+                    let (mut sender, receiver) = socket.split(&self.log);
+                    #[cfg(feature = "steam")]
+                    sender.ensure_send(packet::LocalConnectionStart {
+                        name: self.steam.friends().name(),
+                        steam_id: self.steam.user().steam_id().raw(),
+                    })?;
+                    #[cfg(not(feature = "steam"))]
+                    sender.ensure_send(packet::LocalConnectionStart {
+                        name: "Player".into(),
+                    })?;
+                    self.connection = Some((sender, receiver, shutdown));
+                    self.last_activity = time::Instant::now();
+                },
+                Err(mpsc::TryRecvError::Empty) => {},
+                Err(mpsc::TryRecvError::Disconnected) => bail!("Local server failed to start"),
+            }
+        }
+        if let Some((sender, receiver, _)) = self.connection.as_mut() {
+            match receiver.try_recv_with(sender) {
+                Ok(packet::Packet::GameBegin(pck)) => return Ok(Some(pck)),
+                Ok(pck) => bail!("wrong packet: {:?}", pck),
+                Err(server::errors::Error(server::errors::ErrorKind::NoData, _)) => {},
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if self.last_activity.elapsed() > LOCAL_LOAD_TIMEOUT {
+            bail!("Timed out waiting for the local server");
+        }
+        Ok(None)
+    }
+
+    /// Creates the client's instance from the game's initial state
+    ///
+    /// This loads the level's assets and scripts so has to be done
+    /// on the main thread.
+    pub(crate) fn finish(mut self, asset_manager: &AssetManager, pck: packet::GameBegin) -> errors::Result<(GameInstance, thread::JoinHandle<()>)> {
+        let (sender, receiver, shutdown) = assume!(self.log, self.connection.take());
+        let mut instance = GameInstance::multi_player(&self.log, asset_manager, #[cfg(feature = "steam")] self.steam.clone(), pck, sender, receiver)?;
+        instance.is_local = true;
+        instance.shutdown_waiter = Some(shutdown);
+        instance.screenshot_helper = self.screenshot.take();
+        Ok((instance, assume!(self.log, self.server_thread.take())))
+    }
+}
+
+impl Drop for PendingSinglePlayer {
+    fn drop(&mut self) {
+        // The server shuts down once the player leaves
+        if let Some((mut sender, _, _)) = self.connection.take() {
+            let _ = sender.send(packet::Disconnect {});
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum NetworkState {
     Loading,
@@ -144,7 +271,77 @@ impl GameInstance {
         name: String,
         mission: Option<ResourceKey<'static>>,
     ) -> errors::Result<(GameInstance, thread::JoinHandle<()>)> {
+        Self::single_player_impl(log, asset_manager, #[cfg(feature = "steam")] steam, name, mission, None)
+    }
+
+    /// Creates a game instance with a single player server
+    /// running the passed mission that reports the time taken
+    /// by each server tick.
+    pub fn benchmark(
+        log: &Logger, asset_manager: &AssetManager,
+        #[cfg(feature = "steam")]
+        steam: steamworks::Client,
+        name: String,
+        mission: ResourceKey<'static>,
+    ) -> errors::Result<(GameInstance, thread::JoinHandle<()>, mpsc::Receiver<server::TickStats>)> {
+        let (tick_send, tick_recv) = mpsc::channel();
+        let (instance, server) = Self::single_player_impl(
+            log, asset_manager,
+            #[cfg(feature = "steam")] steam,
+            name, Some(mission), Some(tick_send),
+        )?;
+        Ok((instance, server, tick_recv))
+    }
+
+    /// Starts a single player server on another thread returning
+    /// a handle that can be polled until the game begins without
+    /// blocking the UI.
+    pub(crate) fn start_single_player(
+        log: &Logger, asset_manager: &AssetManager,
+        #[cfg(feature = "steam")]
+        steam: steamworks::Client,
+        name: String,
+        mission: Option<ResourceKey<'static>>,
+        construction: server::entity::construction::ConstructionConfig,
+        save_on_exit: bool,
+    ) -> PendingSinglePlayer {
+        Self::start_single_player_impl(log, asset_manager, #[cfg(feature = "steam")] steam, name, mission, construction, save_on_exit, None)
+    }
+
+    fn single_player_impl(
+        log: &Logger, asset_manager: &AssetManager,
+        #[cfg(feature = "steam")]
+        steam: steamworks::Client,
+        name: String,
+        mission: Option<ResourceKey<'static>>,
+        tick_reporter: Option<mpsc::Sender<server::TickStats>>,
+    ) -> errors::Result<(GameInstance, thread::JoinHandle<()>)> {
+        let mut pending = Self::start_single_player_impl(
+            log, asset_manager, #[cfg(feature = "steam")] steam, name, mission,
+            server::entity::construction::ConstructionConfig::default(),
+            true,
+            tick_reporter,
+        );
+        loop {
+            if let Some(pck) = pending.poll()? {
+                return pending.finish(asset_manager, pck);
+            }
+            thread::sleep(time::Duration::from_millis(5));
+        }
+    }
+
+    fn start_single_player_impl(
+        log: &Logger, asset_manager: &AssetManager,
+        #[cfg(feature = "steam")]
+        steam: steamworks::Client,
+        name: String,
+        mission: Option<ResourceKey<'static>>,
+        construction: server::entity::construction::ConstructionConfig,
+        save_on_exit: bool,
+        tick_reporter: Option<mpsc::Sender<server::TickStats>>,
+    ) -> PendingSinglePlayer {
         let (socket_send, socket_recv) = mpsc::channel();
+        let (progress_send, progress_recv) = mpsc::channel();
         let assets = asset_manager.clone();
 
         // Screenshot handling
@@ -176,40 +373,50 @@ impl GameInstance {
                 player_area_size: 100,
                 locked_players: false,
                 mission,
+                goals: None,
                 tick_rate: std::cell::Cell::new(20),
+                seasons: None,
+                incremental_saves: true,
+                journal_interval: server::saving::DEFAULT_JOURNAL_INTERVAL,
+                save_on_exit,
+                idle: server::player::IdleConfig::disabled(),
+                dirt: server::entity::dirt::DirtConfig::default(),
+                litter: server::entity::litter::LitterConfig::default(),
+                lod: server::entity::lod::LodConfig::default(),
+                construction,
+                visitors: server::entity::visitor::VisitorConfig::default(),
+                #[cfg(not(feature = "steam"))]
+                auth: server::ServerAuth::None,
             }, Some(Box::new(screenshot_server)), None)
                 .expect("Failed to start local server");
+            if let Some(reporter) = tick_reporter {
+                server.report_tick_times(reporter);
+            }
+            server.report_load_progress(progress_send);
             let socket = server.client_localsocket();
-            assume!(server.log, socket_send.send((socket, shutdown)));
+            if socket_send.send((socket, shutdown)).is_err() {
+                // Loading was cancelled before the server started
+                info!(server.log, "Local server start cancelled");
+                return;
+            }
             server.run();
         });
-        let (socket, shutdown) = assume!(log, socket_recv.recv());
-        let (mut sender, mut receiver) = socket.split(log);
 
-        #[cfg(feature = "steam")]
-        sender.ensure_send(packet::LocalConnectionStart {
-            name: steamworks.friends().name(),
-            steam_id: steamworks.user().steam_id().raw(),
-        })?;
-        #[cfg(not(feature = "steam"))]
-        sender.ensure_send(packet::LocalConnectionStart {
-            name: "Player".into(),
-        })?;
-
-        let pck = receiver.recv_timeout(time::Duration::from_secs(30))?;
-        let pck = match pck {
-            packet::Packet::GameBegin(pck) => pck,
-            pck => bail!("wrong packet: {:?}", pck),
-        };
-
-        let mut instance = Self::multi_player(log, asset_manager, #[cfg(feature = "steam")] steamworks, pck, sender, receiver)?;
-        instance.is_local = true;
-        instance.shutdown_waiter = Some(shutdown);
-        instance.screenshot_helper = Some(ScreenshotHelper {
-            req: req_recv,
-            reply: reply_send,
-        });
-        Ok((instance, server_thread))
+        PendingSinglePlayer {
+            log: log.clone(),
+            #[cfg(feature = "steam")]
+            steam: steamworks,
+            socket: socket_recv,
+            connection: None,
+            progress_recv,
+            progress: None,
+            screenshot: Some(ScreenshotHelper {
+                req: req_recv,
+                reply: reply_send,
+            }),
+            server_thread: Some(server_thread),
+            last_activity: time::Instant::now(),
+        }
     }
 
     /// Creates a game instance that connects to a remote server.
@@ -223,18 +430,28 @@ impl GameInstance {
         receiver: Receiver
     ) -> errors::Result<GameInstance> {
         use crate::server::lua::Scope;
+        // Match the server's seasonal content before any of the
+        // level's assets are loaded
+        asset_manager.set_active_seasons(pck.seasons.0);
         let mut instance = Self::create_instance(log, asset_manager, pck.mission_handler, #[cfg(feature = "steam")] steam, sender, receiver, pck.width, pck.height);
         instance.player.id = player::Id(pck.uid);
         instance.scripting.set(Scope::Global, "control_player", i32::from(instance.player.id.0));
 
+        let mut campuses = player::Campuses::default();
+        let mut profiles = player::Profiles::default();
         for player in pck.players.0 {
             if player.uid == instance.player.id {
                 instance.player.state = player.state.clone();
             }
+            campuses.identities.insert(player.uid, player.campus);
+            profiles.profiles.insert(player.uid, player.profile);
             let mut rplayer = RemotePlayer::new(player.uid, player.username);
             rplayer.state = player.state;
             instance.players.insert(player.uid, rplayer);
         }
+        player::store_campuses(&instance.scripting, campuses.identities.clone());
+        instance.entities.add_component(Container::WORLD, campuses);
+        instance.entities.add_component(Container::WORLD, profiles);
 
         instance.level.load_initial_state::<entity::ClientEntityCreator, _>(&instance.scripting, &mut instance.entities, pck.strings.0, pck.state)?;
         for packet::IdleState{player,idx,state} in pck.idle_state.0 {
@@ -277,6 +494,9 @@ impl GameInstance {
 
         entities.add_component(Container::WORLD, CLogger{log: log.clone()});
         entities.add_component(Container::WORLD, course::LessonManager::new(log.clone(), asset_manager));
+        entities.add_component(Container::WORLD, entity::CurrentSeason::new(
+            server::calendar::SeasonVariants::load(log, asset_manager)
+        ));
 
         let log = log.new(o!("client" => true));
         let scripting = script::Engine::new(&log, asset_manager.clone());
@@ -290,8 +510,12 @@ impl GameInstance {
 
         let snapshots = snapshot::Snapshots::new(&log, &[player::Id(0)]);
         scripting.store_tracked::<snapshot::EntityMap>(snapshot::EntityMap(snapshots.entity_map.clone()));
+        let tutorial = Rc::new(RefCell::new(tutorial::TutorialState::default()));
+        scripting.store_tracked::<tutorial::Tutorial>(Rc::downgrade(&tutorial));
+        let level = assume!(log, Level::new_raw(log.new(o!("type" => "level")), asset_manager, &scripting, width, height));
+        let scatter_props = scatter::ScatterProps::new(&log, asset_manager, &level);
         GameInstance {
-            level: assume!(log, Level::new_raw(log.new(o!("type" => "level")), asset_manager, &scripting, width, height)),
+            level,
             log: log.clone(),
             asset_manager: asset_manager.clone(),
             #[cfg(feature = "steam")]
@@ -316,6 +540,10 @@ impl GameInstance {
 
             next_keep_alive: 0,
             last_keep_alive_reply: time::Instant::now(),
+            last_activity_camera: (0.0, 0.0),
+            last_activity_sent: time::Instant::now(),
+            last_view_area: None,
+            last_view_area_sent: time::Instant::now(),
             next_command_id: 1,
             commands: Vec::with_capacity(MAX_QUEUE_HISTORY),
             request_manager: network::RequestManager::new(),
@@ -338,6 +566,8 @@ impl GameInstance {
             delayed_notifications: Vec::new(),
             notification_next_id: 0,
             chat_messages: vec![],
+            cutscenes: vec![],
+            screen_effects: effects::EffectPlayer::new(),
 
             screenshot_helper: None,
 
@@ -346,6 +576,12 @@ impl GameInstance {
                 config: player::PlayerConfig::default(),
             },
             mission_handler,
+
+            tutorial,
+            tutorial_overlay: tutorial::TutorialOverlay::default(),
+            server_perf: None,
+            net_stats: net_stats::NetStats::new(),
+            scatter_props,
         }
     }
 
@@ -373,9 +609,21 @@ impl GameInstance {
         }
 
         for p in self.request_manager.packets() {
+            self.net_stats.record_outgoing(&p);
             let _ = self.sender.ensure_send(p);
         }
 
+        #[cfg(feature = "steam")]
+        {
+            state.voice.tick();
+            // No one to talk to in single player
+            if !self.is_local {
+                if let Some(data) = state.voice.capture(&state.steam) {
+                    let _ = self.send(packet::VoiceData{data: packet::Raw(data)});
+                }
+            }
+        }
+
         for cmd in &mut self.commands {
             manager.collect_capture(&mut cmd.2);
         }
@@ -401,10 +649,21 @@ impl GameInstance {
             self.last_tick = 0.0;
         }
 
+        // Keep the camera on the player's campus unless they
+        // chose to look around the other campuses
+        let free_roam = !self.players.is_empty() && state.config.camera_free_roam.get();
+        state.renderer.set_camera_bounds(if free_roam { None } else { self.campus_bounds() });
+
+        self.screen_effects.update(&mut state.renderer, state.effect_steps, state.effect_clock.step(), state.config.reduce_effects.get());
+        // Done every frame so the level editor's changes show
+        // whilst paused
+        self.scatter_props.update(&self.level, &mut self.entities);
+
         // Tick entities (frame systems)
 
         if !self.paused {
             let d = entity::Delta(delta);
+            let systems_start = time::Instant::now();
             self.frame_systems.run_with_borrows(&mut self.entities)
                 .borrow(&self.last_cursor_position)
                 .borrow(&*self.level.tiles.borrow())
@@ -415,7 +674,26 @@ impl GameInstance {
                 .borrow_mut(&mut state.renderer.animated_info.info)
                 .borrow(&d)
                 .run();
+            state.perf.add(crate::perf::FramePhase::EntitySystems, systems_start.elapsed());
         }
+
+        self.tutorial_overlay.update(&mut self.tutorial.borrow_mut(), &state.ui_manager);
+    }
+
+    /// Returns the area covering every room owned by the player
+    fn campus_bounds(&self) -> Option<Bound> {
+        let rooms = self.level.rooms.borrow();
+        rooms.iter_rooms()
+            .map(|(_, v)| v)
+            .filter(|v| v.owner == self.player.id)
+            .map(|v| v.area)
+            .fold(None, |bounds: Option<Bound>, area| Some(match bounds {
+                Some(b) => Bound::new(
+                    Location::new(cmp::min(b.min.x, area.min.x), cmp::min(b.min.y, area.min.y)),
+                    Location::new(cmp::max(b.max.x, area.max.x), cmp::max(b.max.y, area.max.y)),
+                ),
+                None => area,
+            }))
     }
 
     fn tick_minor(&mut self, state: &mut crate::GameState) -> errors::Result<()> {
@@ -455,7 +733,17 @@ impl GameInstance {
                 self.do_notification(state, not);
             }
 
+            self.level.dispatch_changes();
+
+            // Keep the outdoor season in step with the calendar
+            let blend = server::calendar::season_blend(self.day_tick.day, self.day_fraction());
+            if let Some(season) = self.entities.get_component_mut::<entity::CurrentSeason>(Container::WORLD) {
+                season.blend = blend;
+                state.renderer.set_season(&season.variants, blend);
+            }
+
             // Tick entities
+            let systems_start = time::Instant::now();
             self.systems.run_with_borrows(&mut self.entities)
                 .borrow(&self.last_cursor_position)
                 .borrow(&*self.level.tiles.borrow())
@@ -464,8 +752,13 @@ impl GameInstance {
                 .borrow_mut(&mut *state.audio.controller.borrow_mut())
                 .borrow(&self.asset_manager)
                 .run();
+            state.perf.add(crate::perf::FramePhase::EntitySystems, systems_start.elapsed());
         }
 
+        let signals = self.music_signals();
+        state.audio.set_music_signals(signals);
+        state.audio.controller.borrow_mut().update_occlusion(&self.level);
+
         // Keep the connection open to the server
         // by firing keep alive packets at it every
         // few ticks. No need to make sure it arrives
@@ -475,6 +768,33 @@ impl GameInstance {
             self.next_keep_alive = 60;
             self.send(packet::KeepAlive{})?;
         }
+        if let Some(ping) = self.net_stats.tick() {
+            self.send(ping)?;
+        }
+
+        // Moving the camera doesn't send any commands so let the
+        // server know we are still here. Rate limited as the
+        // server only cares about minutes without activity.
+        let camera = state.renderer.get_camera();
+        if !self.is_local
+            && camera != self.last_activity_camera
+            && self.last_activity_sent.elapsed() > Duration::from_secs(30)
+        {
+            self.last_activity_camera = camera;
+            self.last_activity_sent = time::Instant::now();
+            self.send(packet::PlayerActivity{})?;
+        }
+
+        // Entities far from every player's camera are simplified
+        // by the server so keep it roughly up to date with ours
+        let moved = self.last_view_area.map_or(true, |v| {
+            (v.0 - camera.0).abs() >= VIEW_AREA_STEP || (v.1 - camera.1).abs() >= VIEW_AREA_STEP
+        });
+        if moved || self.last_view_area_sent.elapsed() > Duration::from_secs(5) {
+            self.last_view_area = Some(camera);
+            self.last_view_area_sent = time::Instant::now();
+            self.send(packet::ViewArea{x: camera.0, z: camera.1})?;
+        }
 
         let timeout_time = if self.is_local {
             Duration::from_secs(500)
@@ -498,6 +818,51 @@ impl GameInstance {
         })
     }
 
+    /// Collects the signals used to select the music from the
+    /// current state of the game
+    fn music_signals(&mut self) -> crate::music::MusicSignals {
+        let player_id = self.player.id;
+        let (total, confused) = self.entities.with(|
+            em: EntityManager<'_>,
+            living: ecs::Read<Living>,
+            owned: ecs::Read<Owned>,
+            emotes: ecs::Read<IconEmote>,
+        | {
+            let mask = living.mask().and(&owned);
+            em.iter_mask(&mask)
+                .filter(|e| owned.get_component(*e).map_or(false, |v| v.player_id == player_id))
+                .fold((0u32, 0u32), |(total, confused), e| {
+                    let is_confused = emotes.get_component(e)
+                        .map_or(false, |v| v.icons.iter().any(|i| i.1 == Emote::Confused));
+                    (total + 1, confused + is_confused as u32)
+                })
+        });
+        // Confused students and staff are more noticeable than
+        // the rating so weight them higher
+        let confused = if total == 0 { 0.0 } else { (confused as f32 / total as f32) * 4.0 };
+        let rating = -f32::from(self.player.rating) / 30_000.0;
+        let trouble = confused.max(rating).max(0.0).min(1.0);
+
+        let money_trend = self.player.history.last()
+            .map_or(0.0, |v| {
+                let change = (v.income - v.outcome).0 as f32;
+                let scale = v.income.0.max(v.outcome.0).max(1) as f32;
+                change / scale
+            });
+
+        crate::music::MusicSignals {
+            trouble,
+            money_trend: money_trend.max(-1.0).min(1.0),
+            time_of_day: self.day_fraction(),
+        }
+    }
+
+    /// Returns how far through the current day it is, from
+    /// `0.0` to `1.0`
+    fn day_fraction(&self) -> f32 {
+        self.day_tick.current_tick as f32 / (LESSON_LENGTH * NUM_TIMETABLE_SLOTS as i32) as f32
+    }
+
     /// Handles the mouse moving
     ///
     /// Special due to the event spam it would cause
@@ -725,6 +1090,40 @@ impl GameInstance {
                 desc.add_child(buttons);
                 self.display_notifcation_reason(icon, title, desc, false, base::KeepReason::RoomActive(room_id));
             },
+            notify::Notification::Custom { id, icon, title, description, severity, action } => {
+                let desc = node! {
+                    active_notification(style="script_text".to_owned(), severity=severity.name().to_owned()) {
+                        content {
+                            @text(description)
+                        }
+                    }
+                };
+                if let Some(label) = action {
+                    let buttons = ui::Node::new("buttons");
+                    let btn = node! {
+                        button {
+                            content {
+                                @text(label)
+                            }
+                        }
+                    };
+                    btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, node, _| {
+                        if let Some(id) = node.parent() // buttons
+                            .and_then(|v| v.parent()) // active_notification
+                            .and_then(|v| v.parent()) // content
+                            .and_then(|v| v.get_property::<i32>("id"))
+                        {
+                            evt.emit(base::AcceptNotification(id as u32));
+                            evt.emit(base::CloseNotification(id as u32));
+                        }
+                        evt.emit(base::CloseNotificationWindow);
+                        true
+                    }));
+                    buttons.add_child(btn);
+                    desc.add_child(buttons);
+                }
+                self.display_notifcation_reason(icon, title, desc, true, base::KeepReason::Script(id));
+            },
             notify::Notification::Script { script, func, data } => {
                 let (icon, title, description) = match self.scripting.with_borrows()
                     .borrow_mut(&mut self.level)
@@ -768,11 +1167,41 @@ impl GameInstance {
         }
     }
 
+    /// Passes the player's progress towards the game's goals to
+    /// the `base:goals` script which displays it using the same
+    /// objective list as missions
+    fn show_goal_progress(&mut self, pck: packet::GoalProgress) {
+        let lua = &self.scripting;
+        let progress = Ref::new_table(lua);
+        progress.insert(Ref::new_string(lua, "name"), Ref::new_string(lua, pck.name.as_str()));
+        progress.insert(Ref::new_string(lua, "won"), pck.won);
+        progress.insert(Ref::new_string(lua, "lost"), pck.lost);
+        match (lua::to_table(lua, &pck.victory.0), lua::to_table(lua, &pck.defeat.0)) {
+            (Ok(victory), Ok(defeat)) => {
+                progress.insert(Ref::new_string(lua, "victory"), victory);
+                progress.insert(Ref::new_string(lua, "defeat"), defeat);
+            },
+            (Err(err), _) | (_, Err(err)) => {
+                warn!(self.log, "Failed to convert goal progress: {}", err);
+                return;
+            },
+        }
+        if let Err(err) = lua.invoke_function::<_, ()>("invoke_module_method", (
+            Ref::new_string(lua, "base"),
+            Ref::new_string(lua, "goals"),
+            Ref::new_string(lua, "client_update"),
+            progress,
+        )) {
+            warn!(self.log, "Failed to show goal progress: {}", err);
+        }
+    }
+
     /// Handles incoming packets
     pub fn handle_packets(&mut self, state: &mut crate::GameState, manager: &mut state::StateManager) -> errors::Result<()> {
         use crate::server::network::packet::Packet::*;
         use self::NetworkState::*;
-        while let Ok(pck) = self.receiver.try_recv() {
+        while let Ok(pck) = self.receiver.try_recv_with(&mut self.sender) {
+            self.net_stats.record_incoming(&pck);
             match (self.remote_network_state, pck) {
                 (_, UpdateStats(pck)) => {
                     if pck.update_id <= self.player.update_id {
@@ -784,6 +1213,36 @@ impl GameInstance {
                 (_, Message(pck)) => {
                     self.chat_messages.extend(pck.messages.0);
                 },
+                (_, PlayCutscene(pck)) => {
+                    self.cutscenes.push(pck.cutscene);
+                },
+                (_, PlayEffects(pck)) => {
+                    let reduce = state.config.reduce_effects.get();
+                    for effect in pck.effects.0 {
+                        self.screen_effects.play(&mut self.entities, effect, reduce);
+                    }
+                },
+                (Playing, DirtUpdate(pck)) => {
+                    state.renderer.set_dirt(pck.tiles.0.into_iter()
+                        .map(|v| (Location::new(v.x, v.y), v.stage)));
+                },
+                (Playing, LitterUpdate(pck)) => {
+                    state.renderer.set_litter(pck.tiles.0.into_iter()
+                        .map(|v| (Location::new(v.x, v.y), v.pieces)));
+                },
+                (Playing, ConstructionUpdate(pck)) => {
+                    for site in pck.rooms.0 {
+                        let area = self.level.try_room_info(site.room_id).map(|v| v.area);
+                        state.renderer.set_construction(site.room_id, site.stage.and_then(|stage| area.map(|a| (a, stage))));
+                    }
+                },
+                (Playing, GoalProgress(pck)) => {
+                    self.show_goal_progress(pck);
+                },
+                #[cfg(feature = "steam")]
+                (_, RemoteVoiceData(pck)) => {
+                    state.voice.play(&state.steam, &state.audio, pck.player_id, &pck.data.0);
+                },
                 (Playing, Notification(pck)) => {
                     for not in pck.notifications.0 {
                         self.do_notification(state, not);
@@ -899,7 +1358,8 @@ impl GameInstance {
                     })?;
                 },
                 (Playing, RejectCommands(pck)) => {
-                    error!(self.log, "Out of sync with the server, rolling back");
+                    let reason = server::errors::ErrorCode::from_code(pck.code);
+                    error!(self.log, "Out of sync with the server, rolling back"; "code" => pck.code, "reason" => reason.localization_key());
                     // Remove all the accepted commands from the queue
                     if let Some(pos) = self.commands.iter().position(|v| v.0 == pck.accepted_id) {
                         for cmd in self.commands.drain(..=pos) {
@@ -950,6 +1410,29 @@ impl GameInstance {
                 (_, KeepAlive(..)) => {
                     self.last_keep_alive_reply = time::Instant::now();
                 },
+                (_, Pong(pck)) => {
+                    self.net_stats.handle_pong(&pck);
+                },
+                (_, AfkWarning(pck)) => {
+                    let text = if pck.kick {
+                        format!("You will be removed from the game in {} seconds unless you do something", pck.seconds)
+                    } else {
+                        format!("You will be marked as away in {} seconds unless you do something", pck.seconds)
+                    };
+                    state.narrate(&text);
+                    self.chat_messages.push(Message::new()
+                        .color(255, 255, 0)
+                        .text(text)
+                        .build());
+                },
+                (_, PerfStats(pck)) => {
+                    self.server_perf = Some(pck);
+                },
+                (_, UpdatePlayerStatus(pck)) => {
+                    if let Some(player) = self.players.get_mut(&pck.player) {
+                        player.afk = pck.afk;
+                    }
+                },
                 (state, pck) => error!(self.log, "Unhandled packet: {:?} -> {:?}", state, pck),
             }
         }
@@ -960,15 +1443,44 @@ impl GameInstance {
     pub fn push_command<C>(&mut self, c: Command, cap: &mut C)
         where C: state::Capturable
     {
+        self.tutorial_command(&c);
         let id = self.next_command_id;
         self.next_command_id = self.next_command_id.wrapping_add(1);
         self.commands.push((id, c, cap.request_capture()));
     }
 
+    /// Informs the mission of commands the player completes
+    /// whilst a tutorial is active
+    fn tutorial_command(&mut self, cmd: &Command) {
+        if let Command::Sorry(..) = cmd {
+            return;
+        }
+        if !self.tutorial.borrow().is_active() {
+            return;
+        }
+        if let Some(handler) = self.mission_handler.as_ref() {
+            if let Err(err) = self.scripting.with_borrows()
+                .borrow(&crate::server::mission::MissionAllowed)
+                .borrow_mut(&mut self.level)
+                .borrow_mut(&mut self.entities)
+                .invoke_function::<_, ()>("invoke_module_method", (
+                    lua::Ref::new_string(&self.scripting, handler.module()),
+                    lua::Ref::new_string(&self.scripting, handler.resource()),
+                    lua::Ref::new_string(&self.scripting, "client_tutorial_command"),
+                    lua::Ref::new_string(&self.scripting, cmd.name()),
+                ))
+            {
+                warn!(self.log, "Failed to update tutorial: {}", err);
+            }
+        }
+    }
+
     /// Attempts to send a packet to the target.
     /// Order of the frames when recieved by the target and
     /// whether the data arrives at all isn't guaranteed.
     pub fn send<P: Into<packet::Packet>>(&mut self, data: P) -> errors::Result<()> {
+        let data = data.into();
+        self.net_stats.record_outgoing(&data);
         self.sender.send(data).map_err(|e| e.into())
     }
 
@@ -980,6 +1492,8 @@ impl GameInstance {
     /// defined window then the socket should be closed and an
     /// error returned for all future `send*` and `recv` calls.
     pub fn ensure_send<P: Into<packet::Packet>>(&mut self, data: P) -> errors::Result<()> {
+        let data = data.into();
+        self.net_stats.record_outgoing(&data);
         self.sender.ensure_send(data).map_err(|e| e.into())
     }
 
@@ -1038,6 +1552,7 @@ pub struct PlayerInfo {
     update_id: u32,
     history: Vec<packet::HistoryEntry>,
     config: player::PlayerConfig,
+    shared: player::SharedState,
 }
 
 impl PlayerInfo {
@@ -1052,6 +1567,7 @@ impl PlayerInfo {
             first_set: false,
             waiting_first: true,
             config: player::PlayerConfig::default(),
+            shared: player::SharedState::default(),
         }
     }
 }
@@ -1103,6 +1619,14 @@ impl Player for PlayerInfo {
     fn set_config(&mut self, cfg: player::PlayerConfig) {
         self.config = cfg;
     }
+
+    fn get_shared(&self) -> player::SharedState {
+        self.shared.clone()
+    }
+
+    fn set_shared(&mut self, shared: player::SharedState) {
+        self.shared = shared;
+    }
 }
 
 struct GameProxy<'a> {
@@ -1120,6 +1644,18 @@ impl <'a> GameProxy<'a> {
 impl <'a> CommandHandler for GameProxy<'a> {
     type Player = PlayerInfo;
 
+    fn filter_command<E>(&mut self, cmd: &Command, _player: &mut PlayerInfo, params: &mut CommandParams<'_, E>) -> server::errors::Result<()>
+        where E: server::script::Invokable,
+    {
+        let allowed = params.engine.get_tracked::<tutorial::Tutorial>()
+            .map_or(true, |v| v.borrow().is_allowed(cmd));
+        if allowed {
+            Ok(())
+        } else {
+            Err(server::errors::ErrorKind::CommandNotAllowed.into())
+        }
+    }
+
     fn execute_edit_room<E>(&mut self, cmd: &mut EditRoom, _player: &mut PlayerInfo, params: &mut CommandParams<'_, E>) -> server::errors::Result<()>
         where E: server::script::Invokable,
     {
@@ -1134,16 +1670,18 @@ impl <'a> CommandHandler for GameProxy<'a> {
 
 struct RemotePlayer {
     id: player::Id,
-    _name: String,
+    name: String,
     state: State,
+    afk: bool,
 }
 
 impl RemotePlayer {
     fn new(id: player::Id, name: String) -> RemotePlayer {
         RemotePlayer {
             id,
-            _name: name,
+            name,
             state: State::None,
+            afk: false,
         }
     }
 }
@@ -1218,6 +1756,13 @@ impl Player for RemotePlayer {
 
     fn set_config(&mut self, _cfg: player::PlayerConfig) {
     }
+
+    fn get_shared(&self) -> player::SharedState {
+        player::SharedState::default()
+    }
+
+    fn set_shared(&mut self, _shared: player::SharedState) {
+    }
 }
 
 
@@ -1259,6 +1804,10 @@ impl player::Player for ServerPlayer {
     fn set_config(&mut self, cfg: player::PlayerConfig) {
         self.config = cfg;
     }
+    fn get_shared(&self) -> player::SharedState {
+        player::SharedState::default()
+    }
+    fn set_shared(&mut self, _shared: player::SharedState) {}
 }
 
 struct ServerHandler<'a> {
@@ -1311,6 +1860,7 @@ impl <'a> CommandHandler for ServerHandler<'a> {
 pub(crate) struct OpenBuyRoomMenu;
 pub(crate) struct OpenBuyStaffMenu;
 pub(crate) struct OpenStaffListMenu;
+pub(crate) struct OpenDirectoryMenu;
 pub(crate) struct OpenStatsMenu;
 pub(crate) struct OpenSettingsMenu;
 pub(crate) struct OpenCoursesMenu;
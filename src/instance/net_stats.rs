@@ -0,0 +1,256 @@
+//! Statistics about the connection to the server.
+//!
+//! Used to tell whether a slow game is caused by the server
+//! struggling or by the connection to it.
+
+use crate::server::network::packet::{self, Packet};
+use delta_encode::{bitio, DeltaEncodable};
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// The number of ticks between pings
+const PING_INTERVAL: i32 = 10;
+/// The time a ping can take before it is counted as lost
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+/// The number of pings the loss estimate is worked out over
+const PING_HISTORY: usize = 40;
+/// The time the byte rates are averaged over
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// A group of packets that the traffic is split by
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PacketCategory {
+    /// Entity snapshots and changes to the level
+    World,
+    /// Commands and requests along with their replies
+    Commands,
+    /// Chat, notifications and stats
+    Messages,
+    /// Voice chat
+    Voice,
+    /// Keep alives, pings and connection handling
+    Connection,
+    /// Everything else
+    Other,
+}
+
+/// The number of packet categories
+pub const CATEGORY_COUNT: usize = 6;
+
+impl PacketCategory {
+    /// Every category in the order they are displayed
+    pub const ALL: [PacketCategory; CATEGORY_COUNT] = [
+        PacketCategory::World,
+        PacketCategory::Commands,
+        PacketCategory::Messages,
+        PacketCategory::Voice,
+        PacketCategory::Connection,
+        PacketCategory::Other,
+    ];
+
+    /// Returns the category the packet belongs to
+    pub fn of(pck: &Packet) -> PacketCategory {
+        use self::Packet::*;
+        match pck {
+            EntityFrame(..) | EntityAckFrame(..) | PlayerAckFrame(..)
+            | DirtUpdate(..) | LitterUpdate(..) | ConstructionUpdate(..)
+            | ViewArea(..) => PacketCategory::World,
+            ExecutedCommands(..) | AckCommands(..) | RejectCommands(..)
+            | RemoteExecutedCommands(..) | AckRemoteCommands(..)
+            | Request(..) | Reply(..) => PacketCategory::Commands,
+            Message(..) | ChatMessage(..) | Notification(..) | NotificationReply(..)
+            | ShareChart(..) | UpdateStats(..) | GoalProgress(..) => PacketCategory::Messages,
+            VoiceData(..) | RemoteVoiceData(..) => PacketCategory::Voice,
+            KeepAlive(..) | Ping(..) | Pong(..) | PlayerActivity(..)
+            | Ensured(..) | EnsuredAck(..) | Disconnect(..)
+            | TransferStart(..) | TransferChunk(..) | TransferAck(..) => PacketCategory::Connection,
+            _ => PacketCategory::Other,
+        }
+    }
+
+    /// Returns a readable name for the category
+    pub fn name(self) -> &'static str {
+        match self {
+            PacketCategory::World => "World",
+            PacketCategory::Commands => "Commands",
+            PacketCategory::Messages => "Messages",
+            PacketCategory::Voice => "Voice",
+            PacketCategory::Connection => "Connection",
+            PacketCategory::Other => "Other",
+        }
+    }
+}
+
+/// The bytes per second sent and received for each category
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ByteRates {
+    /// Bytes received per second, in the order of `PacketCategory::ALL`
+    pub incoming: [u32; CATEGORY_COUNT],
+    /// Bytes sent per second, in the order of `PacketCategory::ALL`
+    pub outgoing: [u32; CATEGORY_COUNT],
+}
+
+impl ByteRates {
+    /// The total bytes received per second
+    pub fn total_incoming(&self) -> u32 {
+        self.incoming.iter().sum()
+    }
+
+    /// The total bytes sent per second
+    pub fn total_outgoing(&self) -> u32 {
+        self.outgoing.iter().sum()
+    }
+}
+
+struct SentPing {
+    id: u32,
+    sent: Instant,
+    reply: Option<Duration>,
+}
+
+/// Tracks the round trip time, loss and traffic of the
+/// connection to the server
+pub struct NetStats {
+    next_ping: i32,
+    next_ping_id: u32,
+    pings: VecDeque<SentPing>,
+    rtt: Option<Duration>,
+
+    window_start: Instant,
+    incoming: [u64; CATEGORY_COUNT],
+    outgoing: [u64; CATEGORY_COUNT],
+    rates: ByteRates,
+}
+
+impl NetStats {
+    /// Creates an empty set of statistics
+    pub fn new() -> NetStats {
+        NetStats {
+            next_ping: 0,
+            next_ping_id: 0,
+            pings: VecDeque::with_capacity(PING_HISTORY),
+            rtt: None,
+            window_start: Instant::now(),
+            incoming: [0; CATEGORY_COUNT],
+            outgoing: [0; CATEGORY_COUNT],
+            rates: ByteRates::default(),
+        }
+    }
+
+    /// Called once a tick, returns a ping to send to the
+    /// server if one is due
+    pub fn tick(&mut self) -> Option<packet::Ping> {
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= RATE_WINDOW {
+            let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000_000.0;
+            let rate = |bytes: &mut u64| {
+                let rate = (*bytes as f64 / secs) as u32;
+                *bytes = 0;
+                rate
+            };
+            for (rate_in, bytes) in self.rates.incoming.iter_mut().zip(&mut self.incoming) {
+                *rate_in = rate(bytes);
+            }
+            for (rate_out, bytes) in self.rates.outgoing.iter_mut().zip(&mut self.outgoing) {
+                *rate_out = rate(bytes);
+            }
+            self.window_start = Instant::now();
+        }
+
+        self.next_ping -= 1;
+        if self.next_ping > 0 {
+            return None;
+        }
+        self.next_ping = PING_INTERVAL;
+        let id = self.next_ping_id;
+        self.next_ping_id = self.next_ping_id.wrapping_add(1);
+        if self.pings.len() >= PING_HISTORY {
+            self.pings.pop_front();
+        }
+        self.pings.push_back(SentPing {
+            id,
+            sent: Instant::now(),
+            reply: None,
+        });
+        Some(packet::Ping { id })
+    }
+
+    /// Handles the server's reply to a ping
+    pub fn handle_pong(&mut self, pong: &packet::Pong) {
+        let ping = if let Some(ping) = self.pings.iter_mut().find(|v| v.id == pong.id) {
+            ping
+        } else {
+            return;
+        };
+        // Duplicated replies don't count twice
+        if ping.reply.is_some() {
+            return;
+        }
+        let time = ping.sent.elapsed();
+        ping.reply = Some(time);
+        // Smoothed so that a single slow reply doesn't jump around
+        self.rtt = Some(self.rtt.map_or(time, |rtt| (rtt * 7 + time) / 8));
+    }
+
+    /// Records a packet received from the server
+    pub fn record_incoming(&mut self, pck: &Packet) {
+        self.incoming[PacketCategory::of(pck) as usize] += encoded_len(pck);
+    }
+
+    /// Records a packet sent to the server
+    pub fn record_outgoing(&mut self, pck: &Packet) {
+        self.outgoing[PacketCategory::of(pck) as usize] += encoded_len(pck);
+    }
+
+    /// Returns the smoothed round trip time to the server if a
+    /// ping has been replied to
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Returns the fraction of recent pings that were never
+    /// replied to.
+    ///
+    /// Pings that are lost on the way to the server and replies
+    /// lost on the way back are both counted.
+    pub fn loss(&self) -> f32 {
+        let (sent, lost) = self.pings.iter()
+            .filter(|v| v.reply.is_some() || v.sent.elapsed() > PING_TIMEOUT)
+            .fold((0, 0), |(sent, lost), v| (sent + 1, lost + v.reply.is_none() as u32));
+        if sent == 0 {
+            0.0
+        } else {
+            lost as f32 / sent as f32
+        }
+    }
+
+    /// Returns the traffic over the last second
+    pub fn rates(&self) -> &ByteRates {
+        &self.rates
+    }
+}
+
+/// Counts the bytes written without storing them
+struct ByteCounter(u64);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the size of the packet once encoded. Doesn't include
+/// any overhead added by the socket e.g. headers or resends
+fn encoded_len(pck: &Packet) -> u64 {
+    let mut writer = bitio::Writer::new(ByteCounter(0));
+    if pck.encode(None, &mut writer).is_err() {
+        return 0;
+    }
+    writer.finish().map_or(0, |v| v.0)
+}
@@ -0,0 +1,111 @@
+//! Creates the props painted by the level editor's scatter brush.
+//!
+//! The level only stores the brush strokes, the props for each
+//! stroke are generated here and created as static models so that
+//! they are drawn with the instanced path.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::prelude::*;
+use crate::entity::ClientEntityCreator;
+use crate::server::level::scatter::{ScatterPalettes, ScatterStroke};
+
+/// Keeps the props of each stroke in step with the level
+pub(crate) struct ScatterProps {
+    /// The palettes strokes pick their props from
+    pub(crate) palettes: ScatterPalettes,
+    props: FNVMap<u32, Vec<ecs::Entity>>,
+    revision: Option<u32>,
+    /// Areas where rooms were placed or removed since the last
+    /// update. Props under rooms are hidden so strokes covering
+    /// these are created again
+    changed: Rc<RefCell<Vec<Bound>>>,
+}
+
+impl ScatterProps {
+    pub(crate) fn new(log: &Logger, assets: &AssetManager, level: &Level) -> ScatterProps {
+        let changed: Rc<RefCell<Vec<Bound>>> = Default::default();
+        {
+            let changed = changed.clone();
+            level.on_change(ChangeKind::ROOM_PLACED | ChangeKind::ROOM_REMOVED, move |change| {
+                changed.borrow_mut().push(change.area());
+            });
+        }
+        ScatterProps {
+            palettes: ScatterPalettes::load(log, assets),
+            props: FNVMap::default(),
+            revision: None,
+            changed,
+        }
+    }
+
+    /// Creates the props of new strokes and removes those of
+    /// strokes that no longer exist
+    pub(crate) fn update(&mut self, level: &Level, entities: &mut ecs::Container) {
+        let changed = std::mem::replace(&mut *self.changed.borrow_mut(), Vec::new());
+        if changed.is_empty() && self.revision == Some(level.scatter.revision()) {
+            return;
+        }
+        self.revision = Some(level.scatter.revision());
+
+        let strokes = level.scatter.strokes();
+        self.props.retain(|id, props| {
+            let keep = strokes.iter()
+                .find(|v| v.id == *id)
+                .map_or(false, |stroke| !changed.iter().any(|area| overlaps(stroke, *area)));
+            if !keep {
+                for e in props.drain(..) {
+                    entities.remove_entity(e);
+                }
+            }
+            keep
+        });
+
+        for stroke in strokes {
+            if self.props.contains_key(&stroke.id) {
+                continue;
+            }
+            let props = self.create_props(level, entities, stroke);
+            self.props.insert(stroke.id, props);
+        }
+    }
+
+    fn create_props(&self, level: &Level, entities: &mut ecs::Container, stroke: &ScatterStroke) -> Vec<ecs::Entity> {
+        let palette = if let Some(palette) = self.palettes.get(stroke.palette.borrow()) {
+            palette
+        } else {
+            return Vec::new();
+        };
+        stroke.props().into_iter()
+            .filter(|prop| {
+                let loc = Location::new(prop.x.floor() as i32, prop.y.floor() as i32);
+                level.level_bounds.in_bounds(loc) && level.get_room_owner(loc).is_none()
+            })
+            .filter_map(|prop| {
+                let info = palette.pick(prop.variant)?;
+                let e = ClientEntityCreator::static_model(
+                    entities,
+                    info.model.borrow(),
+                    info.texture.as_ref().map(|v| v.borrow()),
+                );
+                if let Some(pos) = entities.get_component_mut::<Position>(e) {
+                    pos.x = prop.x;
+                    pos.z = prop.y;
+                }
+                if let Some(rot) = entities.get_component_mut::<Rotation>(e) {
+                    rot.rotation = Angle::new(prop.rotation);
+                }
+                Some(e)
+            })
+            .collect()
+    }
+}
+
+/// Returns whether any of the stroke may cover the area
+fn overlaps(stroke: &ScatterStroke, area: Bound) -> bool {
+    stroke.x + stroke.radius >= area.min.x as f32
+        && stroke.x - stroke.radius <= (area.max.x + 1) as f32
+        && stroke.y + stroke.radius >= area.min.y as f32
+        && stroke.y - stroke.radius <= (area.max.y + 1) as f32
+}
@@ -102,13 +102,12 @@ impl lua::LuaUsable for LuaRoom {
                     object: idx,
                     _types: PhantomData,
                 })
-                .fold((Ref::new_table(lua), 1), |(tbl, idx), t| {
-                    tbl.insert(idx as i32, Ref::new(lua, t));
-                    (tbl, idx + 1)
-                })
-                .0;
+                .enumerate()
+                .map(|(idx, t)| (idx as i32 + 1, Ref::new(lua, t)));
+            let tbl = Ref::new_table(lua);
+            tbl.extend_from_iter(objects);
 
-            Ok(objects)
+            Ok(tbl)
         }));
         t.field("object_index", lua::closure2(|_lua, _this: Ref<LuaRoom>, o: Option<Ref<LuaObject<Types>>>| {
             o.map(|o| o.object as i32 + 1)
@@ -210,6 +209,14 @@ impl snapshot::EntityMarker for RunningChoices {
             c.entities.retain(|v| *v != entity);
         }
     }
+    fn entity_spawned(&mut self, entities: &mut Container, entity: Entity, quiet: bool) {
+        if !quiet {
+            crate::entity::start_spawn_effect(entities, entity);
+        }
+    }
+    fn entity_despawned(&mut self, entities: &mut Container, entity: Entity, quiet: bool) -> bool {
+        !quiet && crate::entity::start_despawn_effect(entities, entity)
+    }
 }
 pub(crate) struct IdleScriptHandle {
     pub(crate) player: PlayerId,
@@ -405,7 +412,8 @@ pub fn client_tick(
         log,
         entities, scripting,
         &mut (),
-        "client_handler"
+        "client_handler",
+        |_| true,
     );
 }
 
@@ -0,0 +1,173 @@
+//! Guided tutorials driven by mission scripts.
+//!
+//! A mission's client script describes the current step via
+//! `tutorial.show_step`:
+//!
+//! ```ignore
+//! tutorial.show_step {
+//!     text = "Open the build menu to start building a room",
+//!     highlight = "build_room",
+//!     allow = {"PlaceSelection"},
+//! }
+//! ```
+//!
+//! Whilst a step is active only the commands listed in `allow`
+//! can be performed by the player (every command is allowed if
+//! it is missing) and every command the player completes is
+//! passed to the mission's `client_tutorial_command` method so
+//! that it can move on to the next step.
+
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+
+use crate::server::lua::{self, Ref, Table};
+use crate::server::mission;
+use crate::script;
+use crate::ui;
+use crate::prelude::*;
+
+/// A single step of a tutorial
+#[derive(Debug)]
+pub struct TutorialStep {
+    /// The text displayed to the player
+    pub text: String,
+    /// The id of the ui element to highlight
+    pub highlight: Option<String>,
+    /// The names of the commands the player may perform.
+    ///
+    /// Every command is allowed if `None`
+    pub allowed_commands: Option<Vec<String>>,
+}
+
+/// The state of the current tutorial, shared with the
+/// scripting engine
+#[derive(Default)]
+pub struct TutorialState {
+    step: Option<TutorialStep>,
+    // Set whenever the step changes so that the overlay
+    // can be rebuilt
+    dirty: bool,
+}
+
+impl TutorialState {
+    /// Returns whether a tutorial step is currently being shown
+    pub fn is_active(&self) -> bool {
+        self.step.is_some()
+    }
+
+    /// Returns whether the current step allows the player to
+    /// perform the command
+    pub fn is_allowed(&self, cmd: &Command) -> bool {
+        self.step.as_ref()
+            .and_then(|v| v.allowed_commands.as_ref())
+            .map_or(true, |v| v.iter().any(|v| v == cmd.name()))
+    }
+}
+
+pub(crate) enum Tutorial {}
+
+impl lua::LuaUsable for Tutorial {}
+impl script::LuaTracked for Tutorial {
+    const KEY: script::NulledString = nul_str!("tutorial_state");
+    type Storage = Weak<RefCell<TutorialState>>;
+    type Output = Rc<RefCell<TutorialState>>;
+
+    fn try_convert(s: &Self::Storage) -> Option<Self::Output> {
+        s.upgrade()
+    }
+}
+
+/// Displays the current step's text and outlines the
+/// element it highlights
+#[derive(Default)]
+pub(crate) struct TutorialOverlay {
+    step: Option<ui::Node>,
+    highlight: Option<ui::Node>,
+}
+
+impl TutorialOverlay {
+    /// Updates the overlay to match the tutorial's state
+    pub(crate) fn update(&mut self, tutorial: &mut TutorialState, ui: &ui::Manager) {
+        if tutorial.dirty {
+            tutorial.dirty = false;
+            if let Some(node) = self.step.take() {
+                ui.remove_node(node);
+            }
+            if let Some(step) = tutorial.step.as_ref() {
+                let node = node! {
+                    tutorial_step {
+                        content {
+                            @text(step.text.clone())
+                        }
+                    }
+                };
+                ui.add_node(node.clone());
+                self.step = Some(node);
+            }
+        }
+
+        // The highlighted element may move or only appear
+        // later (e.g. once a menu is opened) so it is tracked
+        // every frame instead of only when the step changes
+        let rect = tutorial.step.as_ref()
+            .and_then(|v| v.highlight.as_ref())
+            .and_then(|v| ui.find_node(v))
+            .and_then(|v| v.render_position());
+        match rect {
+            Some(rect) => if let Some(highlight) = self.highlight.as_ref()
+                .and_then(|v| query!(v, tutorial_highlight_holder > tutorial_highlight).next())
+            {
+                highlight.set_property("x", rect.x);
+                highlight.set_property("y", rect.y);
+                highlight.set_property("width", rect.width);
+                highlight.set_property("height", rect.height);
+            } else {
+                let node = node! {
+                    tutorial_highlight_holder {
+                        tutorial_highlight(x=rect.x, y=rect.y, width=rect.width, height=rect.height)
+                    }
+                };
+                ui.add_node(node.clone());
+                self.highlight = Some(node);
+            },
+            None => if let Some(node) = self.highlight.take() {
+                ui.remove_node(node);
+            },
+        }
+    }
+}
+
+/// Sets up the interface for mission scripts to drive tutorials
+pub fn init_tutoriallib(lua: &lua::Lua) {
+    use lua::Scope;
+
+    lua.set(Scope::Global, "tutorial_show_step", lua::closure1(|lua, step: Ref<Table>| -> UResult<()> {
+        let _limit = lua.get_borrow::<mission::MissionAllowed>();
+        let tutorial = lua.get_tracked::<Tutorial>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let mut tutorial = tutorial.borrow_mut();
+
+        let text = step.get::<_, Ref<String>>("text")
+            .ok_or_else(|| ErrorKind::Msg("missing tutorial step text".into()))?;
+        tutorial.step = Some(TutorialStep {
+            text: text.to_string(),
+            highlight: step.get::<_, Ref<String>>("highlight")
+                .map(|v| v.to_string()),
+            allowed_commands: step.get::<_, Ref<Table>>("allow")
+                .map(|v| v.iter::<i32, Ref<String>>()
+                    .map(|(_, v)| v.to_string())
+                    .collect()),
+        });
+        tutorial.dirty = true;
+        Ok(())
+    }));
+    lua.set(Scope::Global, "tutorial_clear", lua::closure(|lua| -> UResult<()> {
+        let _limit = lua.get_borrow::<mission::MissionAllowed>();
+        let tutorial = lua.get_tracked::<Tutorial>()
+            .ok_or_else(|| ErrorKind::InvalidState)?;
+        let mut tutorial = tutorial.borrow_mut();
+        tutorial.step = None;
+        tutorial.dirty = true;
+        Ok(())
+    }));
+}
@@ -0,0 +1,218 @@
+//! Loading screen shown whilst a game is being started.
+//!
+//! Local servers create the game on their own thread and report
+//! their progress back to this state. Once the server begins the
+//! game the client's instance is built and the game state is
+//! switched to. The client's part has to be done on the main
+//! thread as the scripting and rendering state cannot be shared.
+
+use std::rc::Rc;
+#[cfg(feature = "steam")]
+use server::steamworks;
+
+use crate::prelude::*;
+use super::{GameState, GameInstance};
+use crate::instance::{self, PendingSinglePlayer};
+use crate::server;
+use crate::server::network::{self, packet};
+use crate::state;
+use crate::ui;
+
+/// The number of stages shown by the progress bar. The server's
+/// load items followed by building the client's instance.
+const STAGES: f64 = 4.0;
+
+/// A game that is being loaded
+pub(crate) enum LoadJob {
+    /// A local server that is creating the game
+    Local(PendingSinglePlayer),
+    /// A remote server that has sent the game's initial state
+    Remote {
+        pck: packet::GameBegin,
+        sender: network::Sender,
+        receiver: network::Receiver,
+        #[cfg(feature = "steam")]
+        auth_ticket: Option<steamworks::AuthTicket>,
+    },
+}
+
+struct CancelLoad;
+
+/// Displays the progress of loading a game.
+///
+/// `return_func` is used to create the state to return to if
+/// loading is cancelled (`None`) or fails (`Some(error)`).
+pub(crate) struct LoadingState {
+    ui: Option<ui::Node>,
+    job: Option<LoadJob>,
+    begin: Option<packet::GameBegin>,
+    building: bool,
+    return_func: Rc<dyn Fn(Option<String>) -> Box<dyn state::State>>,
+}
+
+impl LoadingState {
+    pub(crate) fn new<F>(job: LoadJob, return_func: F) -> LoadingState
+        where F: Fn(Option<String>) -> Box<dyn state::State> + 'static
+    {
+        LoadingState {
+            ui: None,
+            job: Some(job),
+            begin: None,
+            building: false,
+            return_func: Rc::new(return_func),
+        }
+    }
+
+    fn update_progress(&self, stage: f64, status: &str) {
+        let ui = match self.ui.as_ref() {
+            Some(val) => val,
+            None => return,
+        };
+        if let Some(bar) = query!(ui, loading_bar).next() {
+            bar.set_property("progress", (stage / STAGES).min(1.0));
+        }
+        if let Some(txt) = query!(ui, loading_status > @text).next() {
+            txt.set_text(status);
+        }
+    }
+
+    fn finish(&mut self, state: &mut GameState) -> state::Action {
+        let instance = match (self.job.take(), self.begin.take()) {
+            (Some(LoadJob::Local(pending)), Some(pck)) => pending.finish(&state.asset_manager, pck)
+                .map(|(instance, _hosted_server)| instance),
+            (Some(LoadJob::Remote{pck, sender, receiver, #[cfg(feature = "steam")] auth_ticket}), _) => {
+                GameInstance::multi_player(&state.global_logger, &state.asset_manager, #[cfg(feature = "steam")] state.steam.clone(), pck, sender, receiver)
+                    .map(|mut instance| {
+                        #[cfg(feature = "steam")]
+                        {
+                            instance.auth_ticket = auth_ticket;
+                        }
+                        instance
+                    })
+            },
+            _ => return state::Action::Nothing,
+        };
+        match instance {
+            Ok(instance) => state::Action::Switch(Box::new(instance::BaseState::new(instance))),
+            Err(err) => self.fail(state, format!("{}", err)),
+        }
+    }
+
+    fn fail(&mut self, state: &mut GameState, err: String) -> state::Action {
+        error!(state.global_logger, "Failed to load the game"; "error" => %err);
+        self.cancel(state);
+        state::Action::Switch((self.return_func)(Some(err)))
+    }
+
+    fn cancel(&mut self, #[allow(unused_variables)] state: &mut GameState) {
+        self.begin = None;
+        self.building = false;
+        // Local jobs disconnect from the server when dropped
+        if let Some(LoadJob::Remote{mut sender, #[cfg(feature = "steam")] auth_ticket, ..}) = self.job.take() {
+            let _ = sender.send(packet::Disconnect {});
+            #[cfg(feature = "steam")]
+            {
+                if let Some(ticket) = auth_ticket {
+                    state.steam.user().cancel_authentication_ticket(ticket);
+                }
+            }
+        }
+    }
+}
+
+fn describe(item: server::LoadItem) -> &'static str {
+    match item {
+        server::LoadItem::Scripts => "Loading scripts",
+        server::LoadItem::Level => "Loading the level",
+        server::LoadItem::Choices => "Starting staff and students",
+    }
+}
+
+impl state::State for LoadingState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(LoadingState {
+            ui: self.ui.clone(),
+            job: None,
+            begin: None,
+            building: false,
+            return_func: self.return_func.clone(),
+        })
+    }
+
+    fn takes_focus(&self) -> bool { true }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) -> state::Action {
+        let node = state.ui_manager.create_node(ResourceKey::new("base", "menus/loading"));
+        if let Some(btn) = query!(node, button(id="cancel")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(CancelLoad);
+                true
+            }));
+        }
+        self.ui = Some(node);
+        match self.job {
+            Some(LoadJob::Remote{..}) => self.update_progress(STAGES - 1.0, "Building the level"),
+            _ => self.update_progress(0.0, "Starting the server"),
+        }
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) {
+        if let Some(node) = self.ui.take() {
+            state.ui_manager.remove_node(node);
+        }
+    }
+
+    fn tick(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) -> state::Action {
+        // The previous tick displayed that the level was being built,
+        // now actually build it
+        if self.building {
+            return self.finish(state);
+        }
+        let (result, progress) = match self.job.as_mut() {
+            Some(LoadJob::Local(pending)) => (pending.poll(), pending.progress()),
+            Some(LoadJob::Remote{..}) => {
+                self.building = true;
+                return state::Action::Nothing;
+            },
+            None => return state::Action::Nothing,
+        };
+        if let Some(progress) = progress {
+            let stage = match progress.item {
+                server::LoadItem::Scripts => 0.0,
+                server::LoadItem::Level => 1.0,
+                server::LoadItem::Choices => 2.0,
+            };
+            let done = if progress.total == 0 {
+                1.0
+            } else {
+                progress.done as f64 / progress.total as f64
+            };
+            self.update_progress(stage + done, describe(progress.item));
+        }
+        match result {
+            Ok(Some(pck)) => {
+                self.begin = Some(pck);
+                self.building = true;
+                self.update_progress(STAGES - 1.0, "Building the level");
+                state::Action::Nothing
+            },
+            Ok(None) => state::Action::Nothing,
+            Err(err) => self.fail(state, format!("{}", err)),
+        }
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState, evt: &mut server::event::EventHandler) -> state::Action {
+        let mut action = state::Action::Nothing;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        let mut cancel = false;
+        evt.handle_event::<CancelLoad, _>(|_| cancel = true);
+        evt.handle_event_if::<crate::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| cancel = true);
+        if cancel && self.job.is_some() {
+            info!(state.global_logger, "Loading cancelled");
+            self.cancel(state);
+            action = state::Action::Switch((self.return_func)(None));
+        }
+        action
+    }
+}
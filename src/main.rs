@@ -63,6 +63,7 @@ use server::steamworks;
 try_force_gpu!();
 
 pub mod audio;
+mod music;
 pub mod render;
 pub mod ui;
 pub mod config;
@@ -76,8 +77,15 @@ mod multiplayer;
 mod save_file;
 mod campaign;
 mod credits;
+mod player_stats;
+mod benchmark;
 pub mod prelude;
 mod main_menu;
+mod narration;
+mod perf;
+mod loading;
+#[cfg(feature = "steam")]
+mod voice;
 
 use crate::instance::*;
 pub(crate) use crate::multiplayer::MultiPlayer;
@@ -85,7 +93,7 @@ pub(crate) use crate::multiplayer::MultiPlayer;
 use std::sync::mpsc::channel;
 use crate::prelude::*;
 use crate::config::keybinds;
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::video::FullscreenType;
 use std::thread;
 use std::rc::Rc;
@@ -104,6 +112,12 @@ static GLOBAL: std::alloc::System = std::alloc::System;
 #[cfg(feature = "steam")]
 pub const STEAM_APP_ID: steamworks::AppId = steamworks::AppId(808160);
 
+/// The length of a step of the cosmetic simulations in 60ths
+/// of a second
+const EFFECT_STEP: f64 = 1.0;
+/// The most steps the cosmetic simulations may run in a frame
+const MAX_EFFECT_STEPS: u32 = 4;
+
 fn main() {
     let decorator = slog_term::TermDecorator::new().build();
     let drain = slog_term::FullFormat::new(decorator).build().fuse();
@@ -113,8 +127,11 @@ fn main() {
         json_drain
     ).fuse())
         .build_with_guard();
-    let drain = drain.fuse();
-    // TODO: Filter debug logging at some point
+    // Filtered before the async drain so that dropped records
+    // are never formatted. Replaced by the configured filter
+    // once the config is loaded
+    let log_filter = LogFilter::new(DEFAULT_LOG_LEVEL);
+    let drain = log_filter.drain(drain.fuse()).fuse();
 
     let log = slog::Logger::root(drain, o!());
     log_panics(&log, server::GAME_HASH, true);
@@ -138,28 +155,27 @@ fn main() {
     let sdl = sdl2::init()
         .expect("Failed to initialize SDL2");
     sdl2::hint::set_video_minimize_on_focus_loss(false);
+    // Let the input method draw its candidate list, the composition
+    // itself is previewed by the ui
+    sdl2::hint::set("SDL_IME_SHOW_UI", "1");
     let video = sdl.video()
         .expect("Failed to create a video backend");
 
-    let gl_attr = video.gl_attr();
-    gl_attr.set_stencil_size(8);
-    gl_attr.set_depth_size(24);
-    gl_attr.set_context_major_version(3);
-    gl_attr.set_context_minor_version(2);
-    gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
-
     loop {
-        let mut window = video.window("UniverCity", 800, 480)
-            .position_centered()
-            .opengl()
-            .resizable()
-            .build()
-            .expect("Failed to open a window");
-        window.maximize();
-
         let config = config::Config::default(&video);
         assume!(log, config.load());
         assume!(log, config.save());
+        if let Err(err) = log_filter.reset(&config.log_filter.borrow()) {
+            warn!(log, "Invalid log filter in the config: {}", err);
+        }
+
+        let mut builder = video.window("UniverCity", 800, 480);
+        builder.position_centered()
+            .resizable();
+        config.render_backend.get().configure_window(&video, &mut builder);
+        let mut window = builder.build()
+            .expect("Failed to open a window");
+        window.maximize();
 
         let mut packs = config.asset_packs.borrow().clone();
         packs.insert(0, "base".to_owned());
@@ -167,6 +183,8 @@ fn main() {
         // Check if we are waiting for workshop items
         let mut waiting_for_workshop = false;
         #[cfg(feature = "steam")]
+        let mut workshop_updates = vec![];
+        #[cfg(feature = "steam")]
         {
             let ugc = steam.ugc();
             let mut extra_packs = vec![];
@@ -179,11 +197,33 @@ fn main() {
                 }
                 assert!(state.contains(steamworks::ItemState::INSTALLED));
                 let info = assume!(log, ugc.item_install_info(item));
+                let known = config.workshop_versions.borrow().get(&item.0).cloned();
+                match known {
+                    // Newly subscribed items aren't updates
+                    None => {
+                        config.workshop_versions.borrow_mut().insert(item.0, info.timestamp);
+                    },
+                    Some(version) if version != info.timestamp => {
+                        // Postponed updates stay disabled until the
+                        // player enables them from the modding menu
+                        if config.postponed_workshop_items.borrow().contains(&item.0) {
+                            continue;
+                        }
+                        workshop_updates.push(main_menu::WorkshopUpdate {
+                            id: item,
+                            version: info.timestamp,
+                        });
+                    },
+                    _ => {},
+                }
                 extra_packs.push(format!("workshop:{}", info.folder));
             }
-            if !waiting_for_workshop {
+            if waiting_for_workshop {
+                workshop_updates.clear();
+            } else {
                 packs.append(&mut extra_packs);
             }
+            assume!(log, config.save());
         }
 
         let asset_manager = server::register_loaders(AssetManager::with_packs(&log, &packs))
@@ -196,7 +236,12 @@ fn main() {
         let audio = audio::AudioManager::new(&log, audio, asset_manager.clone());
 
         if let Some(renderer) = render::Renderer::new(&log, &window, asset_manager.clone(), config.clone()) {
-            match tick_game(log.clone(), window, renderer, audio, &asset_manager, #[cfg(feature = "steam")] steam.clone(), #[cfg(feature = "steam")] single_steam, config, waiting_for_workshop) {
+            match tick_game(
+                log.clone(), log_filter.clone(), window, renderer, audio, &asset_manager,
+                #[cfg(feature = "steam")] steam.clone(), #[cfg(feature = "steam")] single_steam,
+                config, waiting_for_workshop,
+                #[cfg(feature = "steam")] workshop_updates,
+            ) {
                 TickExitReason::GameEnd => return,
                 #[cfg(feature = "steam")]
                 TickExitReason::ReloadAssets(steam) => {
@@ -246,18 +291,50 @@ pub struct GameState {
     pub config: Rc<config::Config>,
     /// The last delta value
     pub delta: f64,
+    /// Runs cosmetic simulations (screen effects, ui animations)
+    /// at a fixed rate whatever the frame rate
+    pub effect_clock: FixedTimestep,
+    /// The number of fixed steps cosmetic simulations should
+    /// run this frame
+    pub effect_steps: u32,
     /// The logger from instance should be preferred over this
     pub global_logger: Logger,
+    /// Controls which records are written by the loggers
+    pub log_filter: LogFilter,
     /// Access to the steamworks API
     #[cfg(feature = "steam")]
     pub steam: steamworks::Client,
     /// Access to the steamworks API
     #[cfg(feature = "steam")]
     pub steam_single: steamworks::SingleClient,
+    /// Voice chat with other players
+    #[cfg(feature = "steam")]
+    pub voice: voice::VoiceChat,
     /// Access to the host filesystem (e.g. for save data)
     pub filesystem: BoxedFileSystem,
     /// Whether the game should restart
     pub should_restart: bool,
+    /// Outputs descriptions of the game for screen readers
+    pub narrator: narration::Narrator,
+    /// Delivers events from states and the instance to the game
+    pub bus: state::EventBus<Game>,
+    /// Timings of the current and recent frames
+    pub perf: perf::FrameTimings,
+}
+
+impl GameState {
+    /// Narrates the passed text using the configured
+    /// narration mode
+    pub fn narrate(&mut self, text: &str) {
+        self.narrator.narrate(self.config.narration.get(), text);
+    }
+
+    /// Narrates the currently focused ui element
+    pub fn narrate_focus(&mut self) {
+        if let Some(desc) = self.ui_manager.describe_focused() {
+            self.narrate(&desc);
+        }
+    }
 }
 
 fn make_filesystem(#[cfg(feature = "steam")] steam: &steamworks::Client) -> BoxedFileSystem {
@@ -341,7 +418,7 @@ enum TickExitReason {
 }
 
 fn tick_game(
-        log: Logger,
+        log: Logger, log_filter: LogFilter,
         window: sdl2::video::Window, mut renderer: render::Renderer,
         audio: audio::AudioManager, asset_manager: &AssetManager,
         #[cfg(feature = "steam")]
@@ -349,6 +426,8 @@ fn tick_game(
         #[cfg(feature = "steam")]
         single_steam: steamworks::SingleClient,
         config: Rc<Config>, waiting_for_workshop: bool,
+        #[cfg(feature = "steam")]
+        workshop_updates: Vec<main_menu::WorkshopUpdate>,
 ) -> TickExitReason {
     use crate::server::network;
     use std::env;
@@ -357,20 +436,26 @@ fn tick_game(
     let mut sdl_events = sdl.event_pump()
         .expect("Failed to get the event pump");
 
+    let bus = create_event_bus();
     let mut ui_manager = ui::Manager::new(
         log.new(o!("source" => "ui_manager")),
-        asset_manager.clone()
+        asset_manager.clone(),
+        bus.queue(),
     );
     renderer.init_ui(&mut *ui_manager.manager.borrow_mut());
     renderer.set_ui_scale(1.0 / config.ui_scale.get());
+    renderer.set_ui_text_scale(config.ui_text_scale.get());
     ui_manager.set_ui_scale(1.0 / config.ui_scale.get());
+    ui_manager.set_layouts(config.hud_layouts.borrow().clone());
     ui_manager.clear_script_engine(&audio);
 
     let pause_func = get_pause_fn(&log);
+    let narrator = {
+        let video = sdl.video().expect("Failed to get the video subsystem");
+        narration::Narrator::new(&log, &video)
+    };
 
-    for pack in asset_manager.get_packs() {
-        ui_manager.load_styles(ResourceKey::new(pack, "default"));
-    }
+    ui_manager.load_pack_styles(config.ui_high_contrast.get());
 
     #[cfg(feature = "steam")]
     let mut target_lobby: Option<steamworks::LobbyId> = None;
@@ -392,12 +477,16 @@ fn tick_game(
         state::StateManager::new(main_menu::ModDownloadWait {
             ui: None,
         })
+    } else if !workshop_updates.is_empty() {
+        state::StateManager::new(main_menu::ModsUpdated::new(workshop_updates))
     } else {
         state::StateManager::new(main_menu::MainMenuState::new())
     };
     #[cfg(not(feature = "steam"))]
     let state = state::StateManager::new(main_menu::MainMenuState::new());
 
+    #[cfg(feature = "steam")]
+    let voice_chat = voice::VoiceChat::new(&log);
     let mut game = Game {
         instance: None,
         dummy_instance: None,
@@ -416,7 +505,10 @@ fn tick_game(
             window,
             config,
             delta: 1.0,
+            effect_clock: FixedTimestep::new(EFFECT_STEP, MAX_EFFECT_STEPS),
+            effect_steps: 0,
             global_logger: log,
+            log_filter,
             #[cfg(feature = "steam")]
             filesystem: make_filesystem(&steam),
             #[cfg(not(feature = "steam"))]
@@ -425,7 +517,12 @@ fn tick_game(
             steam,
             #[cfg(feature = "steam")]
             steam_single: single_steam,
+            #[cfg(feature = "steam")]
+            voice: voice_chat,
             should_restart: false,
+            narrator,
+            bus,
+            perf: perf::FrameTimings::default(),
         },
     };
 
@@ -452,6 +549,7 @@ fn tick_game(
         last_frame = start;
         let delta = diff.as_nanos() as f64 / (1_000_000_000.0 / 60.0);
         game.game_state.delta = delta;
+        game.game_state.effect_steps = game.game_state.effect_clock.advance(delta);
 
         let (width, height) = game.game_state.window.drawable_size();
         game.width = width;
@@ -480,15 +578,27 @@ fn tick_game(
                 // Core events that can't be handled by the bind system
                 match *sdlevent {
                     Event::TextInput{ref text, ..} => {
+                        game.game_state.ui_manager.clear_composition();
                         for c in text.chars() {
                             game.game_state.ui_manager.focused_event::<ui::CharInputEvent>(ui::CharInput {
                                 input: c,
                             });
                         }
                     },
+                    Event::TextEditing{ref text, start, length, ..} => {
+                        game.game_state.ui_manager.text_editing(text, start, length);
+                    },
+                    // Keys belong to the input method whilst it's composing
+                    Event::KeyDown{..} | Event::KeyUp{..} if game.game_state.ui_manager.is_composing() => {
+                        continue 'events;
+                    },
                     Event::MouseMotion{x, y, xrel, yrel, mousestate, ..} => {
                         game.mouse_pos = (x, y);
                         game.game_state.renderer.set_mouse_position(x, y);
+                        // Moving a panel takes priority over everything
+                        if game.game_state.ui_manager.panel_mouse_move(x, y) {
+                            continue 'events;
+                        }
                         // UI always is handled first
                         if game.game_state.ui_manager.mouse_move(x, y) {
                             game.state.mouse_move_ui(&mut game.instance, &mut game.game_state, game.mouse_pos);
@@ -499,10 +609,7 @@ fn tick_game(
                         if let Some(instance) = game.instance.as_mut() {
                             if mousestate.middle() {
                                 // Move the game's focus position
-                                game.game_state.renderer.move_camera(
-                                    (xrel as f32 / width as f32) * 25.0,
-                                    (yrel as f32 / height as f32) * 25.0,
-                                );
+                                game.game_state.renderer.drag_camera(xrel, yrel);
                             }
 
                             instance.mouse_move_event(&mut game.game_state, game.mouse_pos);
@@ -515,6 +622,11 @@ fn tick_game(
                         }
                     },
                     Event::MouseButtonDown{x, y, mouse_btn, ..} => {
+                        if mouse_btn == sdl2::mouse::MouseButton::Left
+                            && game.game_state.ui_manager.panel_mouse_down(x, y)
+                        {
+                            continue 'events;
+                        }
                         if game.game_state.ui_manager.mouse_event::<ui::MouseDownEvent>(
                             x, y,
                             ui::MouseClick { button: mouse_btn.into(), x: x, y: y },
@@ -523,6 +635,12 @@ fn tick_game(
                         }
                     }
                     Event::MouseButtonUp{x, y, mouse_btn, ..} => {
+                        if game.game_state.ui_manager.panel_mouse_up(x, y) {
+                            let layouts = game.game_state.ui_manager.layouts().clone();
+                            game.game_state.config.hud_layouts.replace(layouts);
+                            assume!(game.game_state.global_logger, game.game_state.config.save());
+                            continue 'events;
+                        }
                         if game.game_state.ui_manager.mouse_event::<ui::MouseUpEvent>(
                             x, y,
                             ui::MouseClick { button: mouse_btn.into(), x: x, y: y },
@@ -544,9 +662,8 @@ fn tick_game(
                         }
                     }
                     Event::KeyDown{scancode: Some(sdl2::keyboard::Scancode::Grave), ..} => {
-                        for pack in game.game_state.asset_manager.get_packs() {
-                            game.game_state.ui_manager.load_styles(ResourceKey::new(pack, "default"));
-                        }
+                        let high_contrast = game.game_state.config.ui_high_contrast.get();
+                        game.game_state.ui_manager.load_pack_styles(high_contrast);
                         continue 'events;
                     },
                     Event::KeyUp{scancode: Some(sdl2::keyboard::Scancode::Grave), ..} => {
@@ -554,6 +671,7 @@ fn tick_game(
                     },
                     Event::KeyDown{keycode: Some(sdl2::keyboard::Keycode::Tab), ..} => {
                         game.game_state.ui_manager.cycle_focus();
+                        game.game_state.narrate_focus();
                     },
                     Event::KeyDown{scancode: Some(sdl2::keyboard::Scancode::F10), ..} => {
                         draw_ui = !draw_ui
@@ -572,6 +690,14 @@ fn tick_game(
                             continue 'events;
                         }
                     },
+                    Event::Window{win_event: WindowEvent::Enter, ..}
+                    | Event::Window{win_event: WindowEvent::FocusGained, ..} => {
+                        game.game_state.renderer.set_mouse_in_window(true);
+                    },
+                    Event::Window{win_event: WindowEvent::Leave, ..}
+                    | Event::Window{win_event: WindowEvent::FocusLost, ..} => {
+                        game.game_state.renderer.set_mouse_in_window(false);
+                    },
                     Event::Quit{..} => {
                         game.running = false;
                         return TickExitReason::GameEnd;
@@ -588,6 +714,8 @@ fn tick_game(
             }
         }
 
+        let mark = game.game_state.perf.record(perf::FramePhase::Events, start);
+
         if let Err(err) = game.handle_packets() {
             // TODO: This duplicates instance's disconnect
             //       handling.
@@ -599,14 +727,24 @@ fn tick_game(
             game.state.add_state(main_menu::MainMenuState::new());
         }
         game.tick(delta);
+        if let Some(dummy) = game.dummy_instance.as_mut() {
+            dummy.tick(&mut game.game_state.renderer, delta);
+        }
+        let mark = game.game_state.perf.record(perf::FramePhase::Game, mark);
+        game.game_state.perf.exclude(perf::FramePhase::Game, perf::FramePhase::EntitySystems);
+
+        // Switching GPUs or a driver crash can reset the context
+        game.game_state.renderer.recover_lost_context(&game.game_state.window);
 
         if let Some(level) = game.instance.as_mut().map(|v| &mut v.level) {
             game.game_state.renderer.update_level(level);
         } else if let Some(level) = game.dummy_instance.as_mut().map(|v| &mut v.level) {
             game.game_state.renderer.update_level(level);
         }
+        let mark = game.game_state.perf.record(perf::FramePhase::Level, mark);
 
-        game.game_state.ui_manager.update(&mut game.game_state.renderer, delta);
+        game.game_state.ui_manager.update(&mut game.game_state.renderer, game.game_state.effect_steps, game.game_state.effect_clock.step());
+        let mark = game.game_state.perf.record(perf::FramePhase::Ui, mark);
 
         {
             let dummy = game.dummy_instance.as_mut();
@@ -618,6 +756,16 @@ fn tick_game(
             game.game_state.renderer.tick(entities, Some(&mut *game.game_state.ui_manager.manager.borrow_mut()), delta, width, height);
         }
 
+        // Photos are also taken before the UI is rendered
+        if game.game_state.renderer.photo_requested() {
+            let entities = game.instance.as_mut().map(|v| &mut v.entities);
+            let photo = game.game_state.renderer.take_photo(entities, width, height);
+            match save_photo(&photo) {
+                Ok(path) => info!(game.game_state.global_logger, "Saved photo to {:?}", path),
+                Err(err) => error!(game.game_state.global_logger, "Failed to save photo: {}", err),
+            }
+        }
+
         // Take a screenshot before the UI is rendered for save icons
         if let Some(scr) = game.instance.as_mut().and_then(|v| v.screenshot_helper.as_mut()) {
             if scr.req.try_recv().is_ok() {
@@ -625,19 +773,22 @@ fn tick_game(
                 let _ = scr.reply.send(screenshot);
             }
         }
+        let mark = game.game_state.perf.record(perf::FramePhase::Render, mark);
 
-
-        if draw_ui {
+        if draw_ui && !game.game_state.renderer.hide_ui() {
             game.game_state.renderer.draw_ui(&mut *game.game_state.ui_manager.manager.borrow_mut());
         }
+        let mark = game.game_state.perf.record(perf::FramePhase::UiDraw, mark);
 
-        game.game_state.window.gl_swap_window();
+        game.game_state.renderer.present(&game.game_state.window);
+        game.game_state.perf.record(perf::FramePhase::Present, mark);
+        game.game_state.perf.end_frame(start.elapsed(), game.game_state.renderer.pass_times());
         #[cfg(feature = "steam")]
         game.game_state.steam_single.run_callbacks();
 
         #[cfg(feature = "steam")]
         for steam_evt in rx.try_iter() {
-            game.game_state.ui_manager.events().emit(steam_evt);
+            game.game_state.bus.emit(steam_evt);
         }
 
         if game.game_state.should_restart {
@@ -656,24 +807,55 @@ fn tick_game(
     TickExitReason::GameEnd
 }
 
+/// Creates the event bus used to pass events from states
+/// and the instance to the game
+fn create_event_bus() -> state::EventBus<Game> {
+    use crate::state::{Priority, Handled};
+    let mut bus = state::EventBus::new();
+    bus.subscribe(Priority::Normal, |game: &mut Game, _: &ExitGame| {
+        game.running = false;
+        Handled::Consume
+    });
+    bus.subscribe(Priority::Normal, |game: &mut Game, e: &SwitchMenu| {
+        game.switch_menu(&e.0);
+        Handled::Consume
+    });
+    bus.subscribe(Priority::Normal, |game: &mut Game, e: &SetCursor| {
+        game.game_state.renderer.set_mouse_sprite(e.0.clone());
+        Handled::Consume
+    });
+    bus.subscribe(Priority::Normal, |game: &mut Game, e: &benchmark::ShowReport| {
+        game.state.pop_all();
+        game.state.add_state(benchmark::ReportState::new(e.0.clone()));
+        Handled::Consume
+    });
+    #[cfg(feature = "steam")]
+    bus.subscribe(Priority::Normal, |game: &mut Game, e: &SteamRequestJoinLobby| {
+        use crate::server::network;
+        let lobby = e.0;
+        game.state.pop_all();
+        game.state.add_state(multiplayer::ConnectingState::<multiplayer::MenuState, network::SteamClientSocket, _>::new(
+            move |state| SteamClientSocket::connect(&state.global_logger, state.steam.clone(), &state.steam_single, lobby)
+        ));
+        Handled::Consume
+    });
+    bus
+}
+
 impl Game {
     fn tick(&mut self, delta: f64) {
-        use crate::server::network;
+        // Events emitted whilst delivering are delivered
+        // next frame
+        for evt in self.game_state.bus.take_pending() {
+            evt.deliver(self);
+        }
         let events = {
             self.game_state.ui_manager.events().handle_events()
         };
         for mut evt in events {
-            evt.handle_event::<ui::FocusNode, _>(|f| self.game_state.ui_manager.focus_node(f.0));
-            evt.handle_event::<ExitGame, _>(|_| self.running = false);
-            evt.handle_event::<SwitchMenu, _>(|e| self.switch_menu(&e.0));
-            evt.handle_event::<SetCursor, _>(|s| self.game_state.renderer.set_mouse_sprite(s.0));
-            #[cfg(feature = "steam")]
-            evt.handle_event::<SteamRequestJoinLobby, _>(|e| {
-                let lobby = e.0;
-                self.state.pop_all();
-                self.state.add_state(multiplayer::ConnectingState::<multiplayer::MenuState, network::SteamClientSocket, _>::new(
-                    move |state| SteamClientSocket::connect(&state.global_logger, state.steam.clone(), &state.steam_single, lobby)
-                ));
+            evt.handle_event::<ui::FocusNode, _>(|f| {
+                self.game_state.ui_manager.focus_node(f.0);
+                self.game_state.narrate_focus();
             });
             self.state.ui_event(&mut self.instance, &mut self.game_state, &mut evt);
             if let Some(instance) = self.instance.as_mut() {
@@ -694,6 +876,12 @@ impl Game {
     }
 
     fn handle_key_action(&mut self, action: keybinds::KeyAction) {
+        #[cfg(feature = "steam")]
+        match action {
+            keybinds::KeyAction::PushToTalk => self.game_state.voice.set_recording(&self.game_state.steam, true),
+            keybinds::KeyAction::PushToTalkStop => self.game_state.voice.set_recording(&self.game_state.steam, false),
+            _ => {},
+        }
         self.state.key_action(&mut self.instance, &mut self.game_state, action, self.mouse_pos);
         if let Some(instance) = self.instance.as_mut() {
             instance.handle_key_action(action, &mut self.game_state, self.mouse_pos);
@@ -711,14 +899,29 @@ impl Game {
             "singleplayer" => self.state.add_state(save_file::MenuState::new(
                 server::saving::SaveType::FreePlay,
                 |state, name| {
-                    let (instance, _hosted_server) = GameInstance::single_player(&state.global_logger, &state.asset_manager, #[cfg(feature = "steam")] state.steam.clone(), name.to_owned(), None)
-                        .expect("Failed to connect to single player instance");
-                    Box::new(instance::BaseState::new(instance))
+                    let pending = GameInstance::start_single_player(
+                        &state.global_logger, &state.asset_manager,
+                        #[cfg(feature = "steam")] state.steam.clone(), name.to_owned(), None,
+                        state.config.construction_config(),
+                        state.config.save_on_exit.get()
+                    );
+                    Box::new(loading::LoadingState::new(
+                        loading::LoadJob::Local(pending),
+                        |_| Box::new(main_menu::MainMenuState::new()),
+                    ))
                 }
             )),
             "campaign" => self.state.add_state(campaign::MenuState::new()),
             "multiplayer" => self.state.add_state(multiplayer::MenuState::new(None)),
             "credits" => self.state.add_state(credits::MenuState::new()),
+            "player_stats" => self.state.add_state(player_stats::MenuState::new()),
+            "benchmark" => match benchmark::start(&mut self.game_state) {
+                Ok(state) => self.state.add_state(state),
+                Err(err) => {
+                    error!(self.game_state.global_logger, "Failed to start the benchmark: {:?}", err);
+                    self.state.add_state(main_menu::MainMenuState::new());
+                },
+            },
             _ => error!(self.game_state.global_logger, "Unknown menu: {}", menu),
         }
     }
@@ -728,18 +931,46 @@ impl Game {
         server::entity::register_components(&mut entities);
         entity::register_components(&mut entities);
 
-        let level = assume!(self.game_state.global_logger, main_menu::create_level(
-            &self.game_state.global_logger,
+        let log = &self.game_state.global_logger;
+        let mut scene = main_menu::pick_scene(log, &self.game_state.asset_manager);
+        let level = match main_menu::create_level(
+            log,
             &self.game_state.asset_manager,
             &mut self.game_state.ui_manager,
             &mut entities,
-        ));
+            scene.as_ref(),
+        ) {
+            Ok(level) => level,
+            Err(err) => {
+                // Fallback to the default layout instead of failing
+                // to start due to a broken pack
+                error!(log, "Failed to create the menu scene"; "error" => %err);
+                scene = None;
+                entities = ecs::Container::new();
+                server::entity::register_components(&mut entities);
+                entity::register_components(&mut entities);
+                assume!(log, main_menu::create_level(
+                    log,
+                    &self.game_state.asset_manager,
+                    &mut self.game_state.ui_manager,
+                    &mut entities,
+                    None,
+                ))
+            }
+        };
         self.game_state.renderer.set_level(&level);
         self.game_state.renderer.set_camera(32.0 + 17.0, 32.0 + 17.0 + 50.0);
 
+        let ambient = scene.as_ref()
+            .and_then(|v| v.ambient())
+            .map(|v| self.game_state.audio.controller.borrow_mut().play_sound_looped(v));
+
         self.dummy_instance = Some(main_menu::DummyInstance {
             entities,
             level,
+            scene,
+            time: 0.0,
+            ambient,
         });
     }
 }
@@ -777,6 +1008,28 @@ fn take_screenshot(log: &Logger, width: u32, height: u32) -> Vec<u8> {
     output
 }
 
+const PHOTO_LOCATION: &str = "./screenshots/";
+
+/// Saves a photo taken in photo mode returning the
+/// path it was saved to.
+fn save_photo(photo: &render::Photo) -> UResult<std::path::PathBuf> {
+    use std::fs;
+    use std::io::BufWriter;
+    fs::create_dir_all(PHOTO_LOCATION)?;
+    let path = Path::new(PHOTO_LOCATION)
+        .join(format!("photo-{}.png", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S")));
+    let file = BufWriter::new(fs::File::create(&path)?);
+
+    let mut enc = png::Encoder::new(file, photo.width, photo.height);
+    enc.set_color(png::ColorType::RGB);
+    enc.set_depth(png::BitDepth::Eight);
+    let mut writer = enc.write_header()
+        .map_err(|err| ErrorKind::Msg(format!("Failed to write the photo: {}", err)))?;
+    writer.write_image_data(&photo.data)
+        .map_err(|err| ErrorKind::Msg(format!("Failed to write the photo: {}", err)))?;
+    Ok(path)
+}
+
 /// Attempts to open the passed url in the user's default
 /// browser.
 #[cfg(target_os = "linux")]
@@ -5,15 +5,219 @@ use crate::entity;
 use crate::server::event;
 
 use std::sync::mpsc;
+use crate::server::cutscene::{self, CameraKey};
+use crate::server::assets::{MonthDay, today};
+use univercity_audio::SoundRef;
+use serde_json;
+
+/// The size of the level used by the main menu
+const MENU_LEVEL_SIZE: u32 = 100;
+
+/// A background scene for the main menu provided by a pack.
+///
+/// Each pack may contain a `menu/scenes.json` file within its
+/// own module folder describing its scenes:
+///
+/// ```ignore
+/// {
+///     "campus": {
+///         "weight": 2,
+///         "camera": [
+///             {"time": 0.0, "x": 49.0, "y": 99.0, "rotation": 0.0, "zoom": 1.0},
+///             {"time": 30.0, "x": 60.0, "y": 90.0, "rotation": 45.0}
+///         ],
+///         "ambient": "ambient/campus",
+///         "rooms": [
+///             {
+///                 "room": "lecture_room",
+///                 "min": [49, 94],
+///                 "max": [53, 98],
+///                 "prefab": "Lecture Hall",
+///                 "objects": [
+///                     {"object": "doors/basic", "x": 2.5, "y": 4.5}
+///                 ]
+///             }
+///         ]
+///     },
+///     "campus_halloween": {
+///         "season": "halloween",
+///         ...
+///     },
+///     "new_year": {
+///         "dates": {"start": {"month": 12, "day": 31}, "end": {"month": 1, "day": 1}},
+///         ...
+///     }
+/// }
+/// ```
+///
+/// Scenes limited to a `season` or `dates` are preferred whilst
+/// they are active, otherwise one of the remaining scenes is picked
+/// at random using their `weight`. The camera loops along its path
+/// and `ambient` is played on repeat whilst the menu is displayed.
+/// Object positions are relative to the room's `min` corner.
+pub struct MenuScene {
+    name: String,
+    camera: Vec<CameraKey>,
+    ambient: Option<ResourceKey<'static>>,
+    rooms: Vec<MenuRoom>,
+}
+
+struct MenuRoom {
+    room: ResourceKey<'static>,
+    area: Bound,
+    prefab: Option<String>,
+    objects: Vec<(ResourceKey<'static>, MenuObjectInfo)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MenuSceneInfo {
+    #[serde(default = "default_weight")]
+    weight: u32,
+    #[serde(default)]
+    season: Option<String>,
+    #[serde(default)]
+    dates: Option<DateRange>,
+    #[serde(default)]
+    camera: Vec<CameraKey>,
+    #[serde(default)]
+    ambient: Option<String>,
+    #[serde(default)]
+    rooms: Vec<MenuRoomInfo>,
+}
+
+fn default_weight() -> u32 { 1 }
+
+#[derive(Debug, Deserialize)]
+struct DateRange {
+    start: MonthDay,
+    end: MonthDay,
+}
+
+#[derive(Debug, Deserialize)]
+struct MenuRoomInfo {
+    room: String,
+    min: (i32, i32),
+    max: (i32, i32),
+    #[serde(default)]
+    prefab: Option<String>,
+    #[serde(default)]
+    objects: Vec<MenuObjectInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MenuObjectInfo {
+    object: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    rotation: i16,
+}
+
+impl MenuScene {
+    /// Returns the sound to play on repeat whilst the scene is
+    /// displayed
+    pub fn ambient(&self) -> Option<ResourceKey<'_>> {
+        self.ambient.as_ref().map(|v| v.borrow())
+    }
+
+    fn new(module: ModuleKey<'_>, name: String, info: MenuSceneInfo) -> MenuScene {
+        let mut camera = info.camera;
+        camera.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(::std::cmp::Ordering::Equal));
+        MenuScene {
+            name,
+            camera,
+            ambient: info.ambient.map(|v| LazyResourceKey::parse(&v)
+                .or_module(module.borrow())
+                .into_owned()),
+            rooms: info.rooms.into_iter()
+                .map(|room| MenuRoom {
+                    room: LazyResourceKey::parse(&room.room)
+                        .or_module(module.borrow())
+                        .into_owned(),
+                    area: Bound::new(
+                        Location::new(room.min.0, room.min.1),
+                        Location::new(room.max.0, room.max.1),
+                    ),
+                    prefab: room.prefab,
+                    objects: room.objects.into_iter()
+                        .map(|obj| (
+                            LazyResourceKey::parse(&obj.object)
+                                .or_module(module.borrow())
+                                .into_owned(),
+                            obj
+                        ))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Picks the scene to display behind the main menu from the
+/// scenes provided by every pack.
+///
+/// Returns `None` if no pack provides a scene
+pub fn pick_scene(log: &Logger, assets: &AssetManager) -> Option<MenuScene> {
+    use rand::thread_rng;
+    use rand::seq::SliceRandom;
+
+    let day = today();
+    let mut dated = Vec::new();
+    let mut normal = Vec::new();
+    for module in assets.get_packs() {
+        let file = match assets.open_from_pack(module.borrow(), "menu/scenes.json") {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let scenes: FNVMap<String, MenuSceneInfo> = match serde_json::from_reader(file) {
+            Ok(val) => val,
+            Err(err) => {
+                error!(log, "Failed to load the menu scenes for {:?}: {}", module, err);
+                continue
+            }
+        };
+        for (name, info) in scenes {
+            let limited = info.season.is_some() || info.dates.is_some();
+            let active = info.season.as_ref().map_or(true, |v| assets.is_season_active(v))
+                && info.dates.as_ref().map_or(true, |v| day.is_between(v.start, v.end));
+            if !active {
+                continue;
+            }
+            let weight = info.weight;
+            let scene = MenuScene::new(module.borrow(), name, info);
+            if limited {
+                dated.push((weight, scene));
+            } else {
+                normal.push((weight, scene));
+            }
+        }
+    }
+    let mut scenes = if dated.is_empty() { normal } else { dated };
+    // The map's order isn't stable
+    scenes.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    let mut rng = thread_rng();
+    let idx = (0 .. scenes.len()).collect::<Vec<_>>()
+        .choose_weighted(&mut rng, |v| scenes[*v].0)
+        .ok()
+        .cloned()?;
+    let (_, scene) = scenes.swap_remove(idx);
+    info!(log, "Using menu scene {}", scene.name);
+    Some(scene)
+}
 
-pub fn create_level(log: &Logger, assets: &AssetManager, ui: &mut ui::Manager, entities: &mut Container) -> server::errors::Result<Level> {
+/// Creates the level displayed behind the main menu from the
+/// scene if any otherwise the default layout is used
+pub fn create_level(log: &Logger, assets: &AssetManager, ui: &mut ui::Manager, entities: &mut Container, scene: Option<&MenuScene>) -> server::errors::Result<Level> {
+    if let Some(scene) = scene {
+        return create_scene_level(log, assets, ui, entities, scene);
+    }
     let level = Level::new::<entity::ClientEntityCreator, _>(
         log.new(o!("type" => "level")),
         ui.get_script_engine(),
         assets,
         entities,
         &[PlayerId(1)],
-        100,
+        MENU_LEVEL_SIZE,
     );
     let mut level = assume!(log, level);
 
@@ -99,14 +303,91 @@ pub fn create_level(log: &Logger, assets: &AssetManager, ui: &mut ui::Manager, e
     Ok(level)
 }
 
+fn create_scene_level(log: &Logger, assets: &AssetManager, ui: &mut ui::Manager, entities: &mut Container, scene: &MenuScene) -> server::errors::Result<Level> {
+    use crate::server::level::room;
+    type EC = entity::ClientEntityCreator;
 
-pub struct MainMenuState {
-    ui: Option<ui::Node>,
+    let mut level = Level::new::<EC, _>(
+        log.new(o!("type" => "level")),
+        ui.get_script_engine(),
+        assets,
+        entities,
+        &[PlayerId(1)],
+        MENU_LEVEL_SIZE,
+    )?;
+    let player = PlayerId(1);
+    let engine = &*ui.get_script_engine();
+
+    for room in &scene.rooms {
+        let id = level.place_room_id::<EC, _>(
+            engine, entities,
+            RoomId(-1), player,
+            room.room.borrow(),
+            room.area
+        ).ok_or_else(|| server::errors::ErrorKind::UnplaceableArea)?;
+        let id = level.finalize_placement(id);
+        if let Some(name) = room.prefab.as_ref() {
+            let ty = assets.loader_open::<room::Loader>(room.room.borrow())?;
+            let prefab = ty.prefabs.iter()
+                .find(|v| v.name == *name)
+                .ok_or_else(|| format!("Missing prefab {} for room {:?}", name, room.room))?;
+            prefab.furnish::<_, EC>(&mut level, id, engine, entities, None)?;
+        }
+        for (key, obj) in &room.objects {
+            let pos = (room.area.min.x as f32 + obj.x, room.area.min.y as f32 + obj.y);
+            level.begin_object_placement::<_, EC>(id, engine, entities, key.borrow(), None)?;
+            level.move_active_object::<_, EC>(id, engine, entities, pos, None, obj.rotation)?;
+            level.finalize_object_placement::<_, EC>(id, engine, entities, None, obj.rotation)?;
+        }
+        level.finalize_room::<EC, _>(engine, entities, id)?;
+    }
+
+    Ok(level)
 }
 
+/// The level displayed behind the main menu
 pub struct DummyInstance {
     pub entities: ecs::Container,
     pub level: Level,
+    /// The scene used to create the level if any
+    pub scene: Option<MenuScene>,
+    /// The time in seconds the scene has been displayed for
+    pub time: f32,
+    /// The scene's ambient sound whilst playing
+    pub ambient: Option<SoundRef>,
+}
+
+impl DummyInstance {
+    /// Moves the camera along the scene's path
+    pub fn tick(&mut self, renderer: &mut render::Renderer, delta: f64) {
+        let scene = match self.scene.as_ref() {
+            Some(val) => val,
+            None => return,
+        };
+        self.time += (delta / 60.0) as f32;
+        let length = scene.camera.last().map_or(0.0, |v| v.time);
+        let time = if length > 0.0 { self.time % length } else { 0.0 };
+        if let Some(((x, y), rotation, zoom)) = cutscene::camera_at(&scene.camera, time) {
+            let (cur_rotation, cur_zoom) = renderer.get_camera_info();
+            renderer.set_camera(x, y);
+            renderer.set_camera_info(
+                rotation.map_or(cur_rotation, cgmath::Deg),
+                zoom.unwrap_or(cur_zoom)
+            );
+        }
+    }
+}
+
+impl Drop for DummyInstance {
+    fn drop(&mut self) {
+        if let Some(ambient) = self.ambient.take() {
+            ambient.stop();
+        }
+    }
+}
+
+pub struct MainMenuState {
+    ui: Option<ui::Node>,
 }
 
 impl MainMenuState {
@@ -133,6 +414,25 @@ impl state::State for MainMenuState {
 
         state.audio.set_playlist("menu");
 
+        if let Some(buttons) = query!(node, menu_buttons).next() {
+            let count = buttons.get_property::<i32>("buttons").unwrap_or(6);
+            buttons.set_property("buttons", count + 2);
+            buttons.add_child(node! {
+                button(on_click="init#ui.emit_event('switch_menu', 'player_stats')".to_owned()) {
+                    content {
+                        @text("Statistics".to_owned())
+                    }
+                }
+            });
+            buttons.add_child(node! {
+                button(on_click="init#ui.emit_event('switch_menu', 'benchmark')".to_owned()) {
+                    content {
+                        @text("Benchmark".to_owned())
+                    }
+                }
+            });
+        }
+
         // If the assets folder contains more than just the default, allow for publishing
         let assets_folders = assume!(state.global_logger, std::fs::read_dir("./assets"))
             .flat_map(|v| v.ok())
@@ -141,7 +441,8 @@ impl state::State for MainMenuState {
             .count();
         if assets_folders > 0 {
             if let Some(buttons) = query!(node, menu_buttons).next() {
-                buttons.set_property("buttons", 7);
+                let count = buttons.get_property::<i32>("buttons").unwrap_or(6);
+                buttons.set_property("buttons", count + 1);
                 buttons.add_child_first(node! {
                     button(on_click="init#ui.emit_event('switch_menu', 'modding')".to_owned()) {
                         content {
@@ -222,6 +523,86 @@ impl state::State for ModMenuState {
                 };
                 mod_list.add_child(node);
             }
+
+            // Workshop updates the player chose to leave disabled
+            for id in state.config.postponed_workshop_items.borrow().iter() {
+                let id = steamworks::PublishedFileId(*id);
+                let evt = ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _node, _| {
+                    evt.emit(EnableModUpdate(id));
+                    true
+                });
+                mod_list.add_child(node! {
+                    mod_entry {
+                        name {
+                            @text(format!("Workshop item {} (update postponed)", id.0))
+                        }
+                        button(id="enable_update".to_owned(), on_click=evt) {
+                            content {
+                                @text("Enable update")
+                            }
+                        }
+                    }
+                });
+            }
+        }
+
+        // Packs fighting over the same files, the last pack loaded
+        // is the one used
+        if let Some(conflict_list) = query!(ui, conflict_list > scroll_panel > content).next() {
+            for conflict in &state.asset_manager.conflicts().conflicts {
+                let node = node! {
+                    conflict_entry(kind=conflict.kind.as_str().to_owned(), script=conflict.is_script()) {
+                        @text(conflict.to_string())
+                    }
+                };
+                conflict_list.add_child(node);
+            }
+        }
+
+        // Packs relying on functions removed from the script api
+        if let Some(deprecation_list) = query!(ui, deprecation_list > scroll_panel > content).next() {
+            for deprecation in state.asset_manager.deprecations() {
+                let node = node! {
+                    deprecation_entry(module=deprecation.module.clone()) {
+                        @text(deprecation.to_string())
+                    }
+                };
+                deprecation_list.add_child(node);
+            }
+        }
+
+        // The load order of the packs along with what they need
+        // and which other packs' files they replace
+        if let Some(pack_graph) = query!(ui, pack_graph > scroll_panel > content).next() {
+            use crate::server::assets::graph::DependencyState;
+            let graph = state.asset_manager.pack_graph();
+            for (idx, pack) in graph.packs.iter().enumerate() {
+                let mut desc = format!("{}. {} ({})", idx + 1, pack.name, pack.module);
+                if !pack.dependencies.is_empty() {
+                    let deps: Vec<_> = pack.dependencies.iter()
+                        .map(|v| match v.state {
+                            DependencyState::Loaded => v.module.clone(),
+                            DependencyState::LoadedAfter => format!("{} (loaded after)", v.module),
+                            DependencyState::Missing => format!("{} (missing)", v.module),
+                        })
+                        .collect();
+                    desc.push_str(&format!(" - needs {}", deps.join(", ")));
+                }
+                for ov in graph.overrides.iter().filter(|v| v.pack == pack.name) {
+                    desc.push_str(&format!(" - overrides {} files of {}", ov.resources.len(), ov.module));
+                    if !ov.shadowed.is_empty() {
+                        desc.push_str(&format!(" ({} unused)", ov.shadowed.len()));
+                    }
+                }
+                let problem = pack.dependencies.iter()
+                    .any(|v| v.state != DependencyState::Loaded);
+                let node = node! {
+                    pack_entry(module=pack.module.clone(), problem=problem) {
+                        @text(desc)
+                    }
+                };
+                pack_graph.add_child(node);
+            }
         }
 
         self.ui = Some(ui);
@@ -255,6 +636,16 @@ impl state::State for ModMenuState {
             self.start_watcher = Some(rx);
             do_upload(&state.global_logger, tx, state.steam.clone(), evt.0);
         });
+        evt.handle_event::<EnableModUpdate, _>(|evt| {
+            let id = evt.0;
+            if let Some(info) = state.steam.ugc().item_install_info(id) {
+                state.config.workshop_versions.borrow_mut().insert(id.0, info.timestamp);
+            }
+            state.config.postponed_workshop_items.borrow_mut().retain(|v| *v != id.0);
+            assume!(state.global_logger, state.config.save());
+            // Reload the assets to load the updated pack
+            state.should_restart = true;
+        });
         action
     }
 }
@@ -283,6 +674,8 @@ fn do_upload(log: &Logger, sender: mpsc::Sender<steamworks::UpdateWatchHandle<st
                     assume!(log, serde_json::to_writer_pretty(meta, &server::ModMeta {
                         main: name.clone(),
                         workshop_id: id,
+                        api_version: Some(server::script::API_VERSION),
+                        dependencies: vec![],
                     }));
                     let sender = sender.clone();
                     do_upload(&log, sender, steam.clone(), name.clone());
@@ -407,4 +800,201 @@ impl state::State for ModDownloadWait {
         }
         state::Action::Nothing
     }
-}
\ No newline at end of file
+}
+/// A subscribed workshop item that changed since the player
+/// last accepted its updates
+#[cfg(feature = "steam")]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WorkshopUpdate {
+    pub(crate) id: steamworks::PublishedFileId,
+    /// The install time of the new version
+    pub(crate) version: u32,
+}
+
+/// The changes made to a workshop item as described by its author
+#[cfg(feature = "steam")]
+struct Changelog {
+    id: steamworks::PublishedFileId,
+    title: String,
+    notes: String,
+}
+
+/// Lists the workshop items that were updated since the last
+/// time the game was started before going to the main menu.
+///
+/// Updates can be postponed which leaves the updated packs
+/// disabled so saves using the previous versions keep working
+/// until the player enables them from the modding menu.
+#[cfg(feature = "steam")]
+pub(crate) struct ModsUpdated {
+    ui: Option<ui::Node>,
+    updates: Vec<WorkshopUpdate>,
+    changelogs: Vec<Changelog>,
+    receiver: Option<mpsc::Receiver<Vec<Changelog>>>,
+}
+
+#[cfg(feature = "steam")]
+impl ModsUpdated {
+    pub(crate) fn new(updates: Vec<WorkshopUpdate>) -> ModsUpdated {
+        ModsUpdated {
+            ui: None,
+            updates,
+            changelogs: Vec::new(),
+            receiver: None,
+        }
+    }
+
+    fn update_description(&self) {
+        let ui = if let Some(ui) = self.ui.as_ref() {
+            ui
+        } else {
+            return;
+        };
+        let mut description = String::new();
+        for update in &self.updates {
+            if let Some(log) = self.changelogs.iter().find(|v| v.id == update.id) {
+                description.push_str(&format!("{}\n{}\n\n", log.title, log.notes));
+            } else if self.receiver.is_some() {
+                description.push_str(&format!("Workshop item {}\nFetching changelog...\n\n", update.id.0));
+            } else {
+                description.push_str(&format!("Workshop item {}\n\n", update.id.0));
+            }
+        }
+        if let Some(desc) = query!(ui, description > @text).next() {
+            desc.set_text(description.trim_end());
+        }
+    }
+
+    fn finish(&mut self, state: &mut GameState, enable: bool) -> state::Action {
+        let config = &state.config;
+        if enable {
+            let mut versions = config.workshop_versions.borrow_mut();
+            for update in &self.updates {
+                versions.insert(update.id.0, update.version);
+            }
+        } else {
+            let mut postponed = config.postponed_workshop_items.borrow_mut();
+            for update in &self.updates {
+                if !postponed.contains(&update.id.0) {
+                    postponed.push(update.id.0);
+                }
+            }
+        }
+        assume!(state.global_logger, config.save());
+        if enable {
+            state::Action::Switch(Box::new(MainMenuState::new()))
+        } else {
+            // The updated packs are already loaded so reload without them
+            state.should_restart = true;
+            state::Action::Nothing
+        }
+    }
+}
+
+#[cfg(feature = "steam")]
+impl state::State for ModsUpdated {
+    fn copy(&self) -> Box<dyn state::State> {
+        unimplemented!()
+    }
+
+    fn takes_focus(&self) -> bool { true }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) -> state::Action {
+        let node = state.ui_manager.create_node(ResourceKey::new("base", "prompt/confirm"));
+
+        if let Some(title) = query!(node, title > @text).next() {
+            title.set_text("Mods updated");
+        }
+        if let Some(accept) = query!(node, button(id="accept") > content > @text).next() {
+            accept.set_text("Enable updates");
+        }
+        if let Some(cancel) = query!(node, button(id="cancel") > content > @text).next() {
+            cancel.set_text("Postpone");
+        }
+        self.ui = Some(node);
+
+        if self.changelogs.is_empty() && self.receiver.is_none() {
+            let (tx, rx) = mpsc::channel();
+            fetch_changelogs(&state.global_logger, &state.steam, self.updates.iter().map(|v| v.id).collect(), tx);
+            self.receiver = Some(rx);
+        }
+        self.update_description();
+
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) {
+        if let Some(node) = self.ui.take() {
+            state.ui_manager.remove_node(node);
+        }
+    }
+
+    fn tick(&mut self, _instance: &mut Option<GameInstance>, _state: &mut GameState) -> state::Action {
+        let res = self.receiver.as_ref().map(|v| v.try_recv());
+        match res {
+            Some(Ok(changelogs)) => {
+                self.changelogs = changelogs;
+                self.receiver = None;
+                self.update_description();
+            },
+            // The query failed, show what is known
+            Some(Err(mpsc::TryRecvError::Disconnected)) => {
+                self.receiver = None;
+                self.update_description();
+            },
+            _ => {},
+        }
+        state::Action::Nothing
+    }
+
+    fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState, evt: &mut event::EventHandler) -> state::Action {
+        let mut response = None;
+        let ui = assume!(state.global_logger, self.ui.clone());
+        evt.handle_event_if::<super::AcceptEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            response = Some(true);
+        });
+        evt.handle_event_if::<super::CancelEvent, _, _>(|evt| evt.0.is_same(&ui), |_| {
+            response = Some(false);
+        });
+        if let Some(enable) = response {
+            self.finish(state, enable)
+        } else {
+            state::Action::Nothing
+        }
+    }
+}
+
+/// Fetches the descriptions of the workshop items, sending them
+/// once they arrive.
+///
+/// Nothing is sent if the query fails
+#[cfg(feature = "steam")]
+fn fetch_changelogs(log: &Logger, steam: &steamworks::Client, items: Vec<steamworks::PublishedFileId>, sender: mpsc::Sender<Vec<Changelog>>) {
+    let query = match steam.ugc().query_items(items) {
+        Ok(val) => val,
+        Err(err) => {
+            warn!(log, "Failed to query updated mods: {:?}", err);
+            return;
+        }
+    };
+    let log = log.clone();
+    query.set_return_long_description(true)
+        .fetch(move |res| match res {
+            Ok(results) => {
+                let changelogs = results.iter()
+                    .flatten()
+                    .map(|v| Changelog {
+                        id: v.published_file_id,
+                        title: v.title,
+                        notes: v.description,
+                    })
+                    .collect();
+                let _ = sender.send(changelogs);
+            },
+            Err(err) => warn!(log, "Failed to fetch updated mods: {:?}", err),
+        });
+}
+
+/// Enables the postponed update of a workshop item
+#[cfg(feature = "steam")]
+struct EnableModUpdate(steamworks::PublishedFileId);
@@ -73,9 +73,21 @@ impl state::State for MenuState {
                 }
             }
             match parse_addr(&addr) {
-                Ok(addr) => action = state::Action::Switch(Box::new(ConnectingState::<MenuState, network::UdpClientSocket, _>::new(
-                    move |_state| network::UdpClientSocket::connect(addr)
-                ))),
+                Ok(addr) => {
+                    let connecting = ConnectingState::<MenuState, network::UdpClientSocket, _>::new(
+                        move |_state| network::UdpClientSocket::connect(addr)
+                    );
+                    #[cfg(not(feature = "steam"))]
+                    let connecting = {
+                        let password = self.ui.as_ref()
+                            .and_then(|ui| query!(ui, server_password > @text).next())
+                            .and_then(|v| v.text())
+                            .map(|v| v.to_string())
+                            .unwrap_or_default();
+                        connecting.with_password(password)
+                    };
+                    action = state::Action::Switch(Box::new(connecting));
+                },
                 Err(err) => {
                     let ui = assume!(state.global_logger, self.ui.clone());
                     if let Some(error_box) = query!(ui, server_connect_error).next() {
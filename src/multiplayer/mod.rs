@@ -18,7 +18,7 @@ use super::{GameState, GameInstance};
 use crate::state;
 use crate::ui;
 use crate::errors;
-use crate::instance;
+use crate::loading;
 use crate::render;
 
 mod dedicated_server;
@@ -52,6 +52,12 @@ impl MultiplayerReturn for MenuState {
 
 struct ModeDedicatedServer;
 struct ModeHostSteam;
+struct CycleGoals;
+struct CycleCampus(CampusPart);
+struct RenameCampus;
+struct CycleProfile(ProfilePart);
+#[cfg(feature = "steam")]
+struct ToggleMute(player::Id);
 #[cfg(feature = "steam")]
 struct JoinSteam(steamworks::LobbyId);
 
@@ -189,6 +195,8 @@ impl state::State for MenuState {
                     let server_log = state.global_logger.new(o!("server" => true, "local" => true));
                     let steam = state.steam.clone();
                     let name = name.to_owned();
+                    let construction = state.config.construction_config();
+                    let save_on_exit = state.config.save_on_exit.get();
                     let _server_thread = thread::spawn(move || {
                         let fs = crate::make_filesystem(#[cfg(feature = "steam")] &steam);
                         let fs = fs.into_boxed();
@@ -201,7 +209,18 @@ impl state::State for MenuState {
                             player_area_size: 100,
                             locked_players: false,
                             mission: None,
+                            goals: None,
                             tick_rate: std::cell::Cell::new(20),
+                            seasons: None,
+                            incremental_saves: true,
+                            journal_interval: server::saving::DEFAULT_JOURNAL_INTERVAL,
+                            save_on_exit,
+                            idle: server::player::IdleConfig::default(),
+                            dirt: server::entity::dirt::DirtConfig::default(),
+                            litter: server::entity::litter::LitterConfig::default(),
+                            lod: server::entity::lod::LodConfig::default(),
+                            construction,
+                            visitors: server::entity::visitor::VisitorConfig::default(),
                         }, None, None)
                             .expect("Failed to start local server");
                         let socket = server.client_localsocket();
@@ -235,6 +254,23 @@ struct ConnectInfo {
     auth_ticket: Option<steamworks::AuthTicket>,
 }
 
+/// Creates the state that loads the game the server has begun
+fn loading_state<R: MultiplayerReturn>(pck: packet::GameBegin, info: ConnectInfo) -> loading::LoadingState {
+    loading::LoadingState::new(
+        loading::LoadJob::Remote {
+            pck,
+            sender: info.sender,
+            receiver: info.receiver,
+            #[cfg(feature = "steam")]
+            auth_ticket: info.auth_ticket,
+        },
+        |err| match err {
+            Some(err) => Box::new(R::return_error(err)),
+            None => Box::new(R::return_ok()),
+        },
+    )
+}
+
 pub(crate) trait MultiplayerReturn: state::State {
     fn return_ok() -> Self;
     fn return_error<S: Into<String>>(err: S) -> Self;
@@ -248,6 +284,8 @@ pub(crate) struct ConnectingState<R, S, F> {
 
     ui: Option<ui::Node>,
     info: Option<ConnectInfo>,
+    #[cfg(not(feature = "steam"))]
+    password: String,
 }
 
 impl <R, S, F> ConnectingState<R, S, F>
@@ -263,8 +301,18 @@ impl <R, S, F> ConnectingState<R, S, F>
 
             ui: None,
             info: None,
+            #[cfg(not(feature = "steam"))]
+            password: String::new(),
         }
     }
+
+    /// Sets the password (or token) sent to the server
+    /// when connecting
+    #[cfg(not(feature = "steam"))]
+    pub(crate) fn with_password(mut self, password: String) -> ConnectingState<R, S, F> {
+        self.password = password;
+        self
+    }
 }
 
 impl <R, S, F> state::State for ConnectingState<R, S, F>
@@ -281,6 +329,8 @@ impl <R, S, F> state::State for ConnectingState<R, S, F>
 
             ui: self.ui.clone(),
             info: None,
+            #[cfg(not(feature = "steam"))]
+            password: self.password.clone(),
         })
     }
 
@@ -301,6 +351,7 @@ impl <R, S, F> state::State for ConnectingState<R, S, F>
         let (auth_ticket, ticket) = state.steam.user().authentication_session_ticket();
 
         if let Err(err) = sender.ensure_send(packet::RemoteConnectionStart {
+            protocol_hash: packet::protocol_hash(),
             #[cfg(feature = "steam")]
             name: state.steam.friends().name(),
             #[cfg(feature = "steam")]
@@ -309,6 +360,8 @@ impl <R, S, F> state::State for ConnectingState<R, S, F>
             name: "Player".into(),
             #[cfg(feature = "steam")]
             ticket: packet::Raw(ticket),
+            #[cfg(not(feature = "steam"))]
+            password: self.password.clone(),
         }) {
             return state::Action::Switch(Box::new(R::return_error(format!("{}", err))));
         }
@@ -329,27 +382,18 @@ impl <R, S, F> state::State for ConnectingState<R, S, F>
 
     fn tick(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) -> state::Action {
         if let Some(mut info) = self.info.take() {
-            match info.receiver.try_recv() {
+            match info.receiver.try_recv_with(&mut info.sender) {
                 Ok(packet::Packet::ServerConnectionStart(pck)) => {
                     return state::Action::Switch(Box::new(
                         LobbyState::<R>::new(pck.uid, info)
                     ))
                 }
                 Ok(packet::Packet::ServerConnectionFail(pck)) => {
-                    return state::Action::Switch(Box::new(R::return_error(pck.reason)));
+                    let msg = server::errors::codes::describe(pck.code, pck.detail.as_ref().map(|v| v.as_str()));
+                    return state::Action::Switch(Box::new(R::return_error(msg)));
                 }
                 Ok(packet::Packet::GameBegin(pck)) => {
-                    match GameInstance::multi_player(&state.global_logger, &state.asset_manager, #[cfg(feature = "steam")] state.steam.clone(), pck, info.sender, info.receiver) {
-                        Ok(mut instance) => {
-                            #[cfg(feature = "steam")]
-                            {
-                                instance.auth_ticket = info.auth_ticket;
-                            }
-                            return state::Action::Switch(Box::new(instance::BaseState::new(instance)))
-                        },
-                        Err(err) =>
-                            return state::Action::Switch(Box::new(R::return_error(format!("{}", err)))),
-                    }
+                    return state::Action::Switch(Box::new(loading_state::<R>(pck, info)));
                 }
                 Ok(_) =>
                     return state::Action::Switch(Box::new(R::return_error("Incorrect packet".to_owned()))),
@@ -384,11 +428,51 @@ struct LobbyState<R> {
     last_ping: time::Instant,
     current_players: Vec<packet::LobbyEntry>,
     can_start: bool,
+    goal_sets: Vec<packet::GoalSetEntry>,
+    goals: Option<ResourceKey<'static>>,
+    campus_parts: player::CampusParts,
+    profile_parts: player::ProfileParts,
+    // Whether the mouse has moved since the last ping, sent with
+    // the ping so that the server doesn't think we are away
+    moved: bool,
 
     ui: Option<ui::Node>,
     info: Option<ConnectInfo>,
 }
 
+/// The part of the player's campus changed by a button
+#[derive(Clone, Copy)]
+enum CampusPart {
+    Primary,
+    Secondary,
+    Shape,
+    Emblem,
+}
+
+/// The part of the player's profile changed by a button
+#[derive(Clone, Copy)]
+enum ProfilePart {
+    Color,
+    Icon,
+    Title,
+}
+
+/// Returns the item after `current` in `items`, wrapping around
+/// to the start
+fn cycle<T: Clone + PartialEq>(items: &[T], current: &T) -> Option<T> {
+    let idx = items.iter().position(|v| v == current)
+        .map_or(0, |v| (v + 1) % items.len());
+    items.get(idx).cloned()
+}
+
+/// Like `cycle` but includes `None` after the last item
+fn cycle_optional<T: Clone + PartialEq>(items: &[T], current: &Option<T>) -> Option<T> {
+    match current.as_ref().and_then(|c| items.iter().position(|v| v == c)) {
+        Some(idx) => items.get(idx + 1).cloned(),
+        None => items.first().cloned(),
+    }
+}
+
 impl <R> LobbyState<R> {
     fn new(uid: i16, info: ConnectInfo) -> LobbyState<R> {
         LobbyState {
@@ -398,13 +482,125 @@ impl <R> LobbyState<R> {
             last_ping: time::Instant::now(),
             current_players: vec![],
             can_start: false,
+            goal_sets: vec![],
+            goals: None,
+            campus_parts: player::CampusParts::default(),
+            profile_parts: player::ProfileParts::default(),
+            moved: false,
 
             ui: None,
             info: Some(info),
         }
     }
 
-    fn rebuild_player_list(&mut self, #[cfg(feature = "steam")] steam: &steamworks::Client, renderer: &mut render::Renderer) {
+    fn own_campus(&self) -> Option<&player::CampusIdentity> {
+        self.current_players.iter()
+            .find(|v| v.uid.0 == self.uid)
+            .map(|v| &v.campus)
+    }
+
+    fn update_campus(&self) {
+        let ui = self.ui.as_ref().expect("UI not created");
+        let campus = if let Some(campus) = self.own_campus() {
+            campus
+        } else {
+            return;
+        };
+        let buttons = [
+            ("campus_primary", CampusPart::Primary, self.campus_parts.colors.is_empty()),
+            ("campus_secondary", CampusPart::Secondary, self.campus_parts.colors.is_empty()),
+            ("campus_shape", CampusPart::Shape, self.campus_parts.shapes.is_empty()),
+            ("campus_emblem", CampusPart::Emblem, self.campus_parts.emblems.is_empty()),
+        ];
+        for &(id, part, disabled) in &buttons {
+            if let Some(btn) = query!(ui, button(id=id)).next() {
+                btn.set_property("disabled", disabled);
+                btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+                    evt.emit(CycleCampus(part));
+                    true
+                }));
+            }
+        }
+        if let Some(btn) = query!(ui, button(id="campus_rename")).next() {
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(RenameCampus);
+                true
+            }));
+        }
+        if let Some(preview) = query!(ui, campus_preview).next() {
+            let colour = |c: player::CampusColor| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b);
+            preview.set_property("primary", colour(campus.primary));
+            preview.set_property("secondary", colour(campus.secondary));
+            preview.set_property("shape", campus.crest_shape.as_ref().map_or_else(String::new, |v| v.as_string()));
+            preview.set_property("emblem", campus.crest_emblem.as_ref().map_or_else(String::new, |v| v.as_string()));
+            if let Some(txt) = query!(preview, @text).next() {
+                txt.set_text(campus.name.as_str());
+            }
+        }
+    }
+
+    fn own_profile(&self) -> Option<&player::PlayerProfile> {
+        self.current_players.iter()
+            .find(|v| v.uid.0 == self.uid)
+            .map(|v| &v.profile)
+    }
+
+    fn update_profile(&self, renderer: &render::Renderer) {
+        let ui = self.ui.as_ref().expect("UI not created");
+        let profile = if let Some(profile) = self.own_profile() {
+            profile
+        } else {
+            return;
+        };
+        let buttons = [
+            ("profile_color", ProfilePart::Color, false),
+            ("profile_icon", ProfilePart::Icon, self.profile_parts.icons.is_empty()),
+            ("profile_title", ProfilePart::Title, self.profile_parts.titles.is_empty()),
+        ];
+        for &(id, part, disabled) in &buttons {
+            if let Some(btn) = query!(ui, button(id=id)).next() {
+                btn.set_property("disabled", disabled);
+                btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+                    evt.emit(CycleProfile(part));
+                    true
+                }));
+            }
+        }
+        if let Some(preview) = query!(ui, profile_preview).next() {
+            let (r, g, b) = renderer.colour_palette().player_colour(usize::from(profile.color));
+            preview.set_property("colour", format!("#{:02x}{:02x}{:02x}", r, g, b));
+            preview.set_property("icon", profile.icon.as_ref().map_or_else(String::new, |v| v.as_string()));
+            if let Some(txt) = query!(preview, @text).next() {
+                txt.set_text(profile.title.as_ref().map_or("No title", |v| v.as_str()));
+            }
+        }
+    }
+
+    fn update_goals(&self) {
+        let ui = self.ui.as_ref().expect("UI not created");
+        let selected = self.goals.as_ref()
+            .and_then(|key| self.goal_sets.iter().find(|v| v.key == *key));
+        if let Some(btn) = query!(ui, button(id="goals_button")).next() {
+            btn.set_property("disabled", self.goal_sets.is_empty());
+            btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(|evt, _, _| {
+                evt.emit(CycleGoals);
+                true
+            }));
+            if let Some(txt) = query!(btn, content > @text).next() {
+                txt.set_text(selected.map_or("No goals", |v| v.name.as_str()));
+            }
+        }
+        if let Some(txt) = query!(ui, goals_description > @text).next() {
+            txt.set_text(selected.map_or("", |v| v.description.as_str()));
+        }
+    }
+
+    fn rebuild_player_list(
+        &mut self,
+        #[cfg(feature = "steam")] steam: &steamworks::Client,
+        #[cfg(feature = "steam")] voice: &crate::voice::VoiceChat,
+        renderer: &mut render::Renderer,
+    ) {
         let ui = self.ui.as_ref().expect("UI not created");
         #[cfg(feature = "steam")]
         let friends = steam.friends();
@@ -428,36 +624,72 @@ impl <R> LobbyState<R> {
             for c in lobby_list.children() {
                 lobby_list.remove_child(c);
             }
-            for (idx, player) in self.current_players.iter().enumerate() {
+            let palette = renderer.colour_palette();
+            for player in &self.current_players {
+                let (r, g, b) = palette.player_colour(usize::from(player.profile.color));
+                let colour = format!("#{:02x}{:02x}{:02x}", r, g, b);
+                let title = player.profile.title.clone().unwrap_or_default();
                 #[cfg(feature = "steam")]
                 {
                     let friend = friends.get_friend(steamworks::SteamId::from_raw(player.steam_id));
-                    let icon = if let Some(data) = friend.medium_avatar() {
-                        let icon = ResourceKey::new("dynamic", format!("64@64@steam_icon_{}", idx));
+                    // A picked icon takes the place of the avatar
+                    let icon = if let Some(icon) = player.profile.icon.as_ref() {
+                        icon.as_string()
+                    } else if let Some(data) = friend.medium_avatar() {
+                        let icon = ResourceKey::new("dynamic", format!("64@64@steam_icon_{}", player.uid.0));
                         let s = icon.as_string();
                         renderer.update_image(icon, 64, 64, data);
                         s
                     } else {
                         "solid".to_owned()
                     };
-                    lobby_list.add_child(node! {
-                        entry(ready = player.ready) {
+                    let entry = node! {
+                        entry(ready = player.ready, afk = player.afk, colour = colour) {
                             player_icon(icon = icon)
                             content {
                                 @text(friend.name())
                             }
+                            player_title {
+                                @text(title)
+                            }
+                            campus_name {
+                                @text(player.campus.name.clone())
+                            }
                         }
-                    });
+                    };
+                    if player.uid.0 != self.uid {
+                        let uid = player.uid;
+                        let btn = node! {
+                            button(mute = voice.is_muted(uid)) {
+                                content {
+                                    @text(if voice.is_muted(uid) { "Unmute" } else { "Mute" })
+                                }
+                            }
+                        };
+                        btn.set_property("on_click", ui::MethodDesc::<ui::MouseUpEvent>::native(move |evt, _, _| {
+                            evt.emit(ToggleMute(uid));
+                            true
+                        }));
+                        entry.add_child(btn);
+                    }
+                    lobby_list.add_child(entry);
                 }
                 #[cfg(not(feature = "steam"))]
                 {
 
+                    let icon = player.profile.icon.as_ref().map_or_else(|| "solid".to_owned(), |v| v.as_string());
                     lobby_list.add_child(node! {
-                        entry(ready = player.ready) {
-                            player_icon(icon = "solid".to_owned())
+                        entry(ready = player.ready, afk = player.afk, colour = colour) {
+                            player_icon(icon = icon)
                             content {
                                 @text("Player".to_owned())
                             }
+                            player_title {
+                                @text(title)
+                            }
+                            campus_name {
+                                @text(player.campus.name.clone())
+                            }
                         }
                     });
                 }
@@ -477,6 +709,11 @@ impl <R> state::State for LobbyState<R>
             last_ping: self.last_ping,
             current_players: self.current_players.clone(),
             can_start: self.can_start,
+            goal_sets: self.goal_sets.clone(),
+            goals: self.goals.clone(),
+            campus_parts: self.campus_parts.clone(),
+            profile_parts: self.profile_parts.clone(),
+            moved: self.moved,
 
             ui: self.ui.clone(),
             info: None,
@@ -493,6 +730,8 @@ impl <R> state::State for LobbyState<R>
             }
         }
 
+        self.campus_parts = player::load_campus_parts(&state.global_logger, &state.asset_manager);
+        self.profile_parts = player::load_profile_parts(&state.global_logger, &state.asset_manager);
         self.ui = Some(ui);
         state::Action::Nothing
     }
@@ -507,40 +746,65 @@ impl <R> state::State for LobbyState<R>
         use crate::server::network::packet::Packet;
         let ui = self.ui.clone().expect("UI not created");
         if let Some(mut info) = self.info.take() {
-            match info.receiver.try_recv() {
+            match info.receiver.try_recv_with(&mut info.sender) {
                 Ok(Packet::UpdateLobby(pck)) => {
                     self.current_players = pck.players.0;
-                    self.rebuild_player_list(#[cfg(feature = "steam")] &state.steam, &mut state.renderer);
+                    self.rebuild_player_list(#[cfg(feature = "steam")] &state.steam, #[cfg(feature = "steam")] &state.voice, &mut state.renderer);
                     self.can_start = pck.can_start;
                     if let Some(btn) = query!(ui, button(id="start_button")).next()
                     {
                         btn.set_property("disabled", !self.can_start);
                     }
+                    self.goal_sets = pck.goal_sets.0;
+                    self.goals = pck.goals;
+                    self.update_goals();
+                    self.update_campus();
+                    self.update_profile(&state.renderer);
                 }
                 Ok(Packet::GameBegin(pck)) => {
-                    match GameInstance::multi_player(&state.global_logger, &state.asset_manager, #[cfg(feature = "steam")] state.steam.clone(), pck, info.sender, info.receiver) {
-                        Ok(mut instance) => {
-                            #[cfg(feature = "steam")]
-                            {
-                                instance.auth_ticket = info.auth_ticket;
-                            }
-                            return state::Action::Switch(Box::new(instance::BaseState::new(instance)))
-                        },
-                        Err(err) =>
-                            return state::Action::Switch(Box::new(R::return_error(format!("{}", err)))),
-                    }
+                    return state::Action::Switch(Box::new(loading_state::<R>(pck, info)));
+                }
+                #[cfg(feature = "steam")]
+                Ok(Packet::RemoteVoiceData(pck)) => {
+                    state.voice.play(&state.steam, &state.audio, pck.player_id, &pck.data.0);
+                }
+                Ok(Packet::AfkWarning(pck)) => {
+                    state.narrate(&if pck.kick {
+                        format!("You will be removed from the lobby in {} seconds unless you do something", pck.seconds)
+                    } else {
+                        format!("You will be marked as away in {} seconds unless you do something", pck.seconds)
+                    });
+                }
+                Ok(Packet::ServerConnectionFail(pck)) => {
+                    let msg = server::errors::codes::describe(pck.code, pck.detail.as_ref().map(|v| v.as_str()));
+                    return state::Action::Switch(Box::new(R::return_error(msg)));
                 }
                 Ok(Packet::KeepAlive(..)) | Err(server::errors::Error(server::errors::ErrorKind::NoData, _)) => {},
                 Ok(pck) => warn!(state.global_logger, "Incorrect packet: {:?}", pck),
                 Err(err) =>
                     return state::Action::Switch(Box::new(R::return_error(format!("{}", err)))),
             }
+            #[cfg(feature = "steam")]
+            {
+                state.voice.tick();
+                if let Some(data) = state.voice.capture(&state.steam) {
+                    if let Err(err) = info.sender.send(packet::VoiceData{data: packet::Raw(data)}) {
+                        return state::Action::Switch(Box::new(R::return_error(format!("{}", err))));
+                    }
+                }
+            }
             if self.last_ping.elapsed() > time::Duration::from_secs(3) {
                 self.last_ping = time::Instant::now();
-                self.rebuild_player_list(#[cfg(feature = "steam")] &state.steam, &mut state.renderer);
+                self.rebuild_player_list(#[cfg(feature = "steam")] &state.steam, #[cfg(feature = "steam")] &state.voice, &mut state.renderer);
                 if let Err(err) = info.sender.send(packet::KeepAlive{}){
                     return state::Action::Switch(Box::new(R::return_error(format!("{}", err))));
                 }
+                if self.moved {
+                    self.moved = false;
+                    if let Err(err) = info.sender.send(packet::PlayerActivity{}){
+                        return state::Action::Switch(Box::new(R::return_error(format!("{}", err))));
+                    }
+                }
             }
             self.info = Some(info);
         }
@@ -548,6 +812,11 @@ impl <R> state::State for LobbyState<R>
         state::Action::Nothing
     }
 
+    fn mouse_move_ui(&mut self, _instance: &mut Option<GameInstance>, _state: &mut GameState, _mouse_pos: (i32, i32)) -> state::Action {
+        self.moved = true;
+        state::Action::Nothing
+    }
+
     fn ui_event(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState, evt: &mut server::event::EventHandler) -> state::Action {
         let mut action = state::Action::Nothing;
         let ui = assume!(state.global_logger, self.ui.clone());
@@ -562,7 +831,93 @@ impl <R> state::State for LobbyState<R>
                 }
             }
         });
+        evt.handle_event::<CycleGoals, _>(|_| {
+            // Steps through each set in turn followed by no goals
+            let next = match self.goals.as_ref().and_then(|key| self.goal_sets.iter().position(|v| v.key == *key)) {
+                Some(idx) => self.goal_sets.get(idx + 1),
+                None => self.goal_sets.first(),
+            };
+            if let Some(info) = info.as_mut() {
+                let _ = info.sender.ensure_send(packet::SetGoals {
+                    key: next.map(|v| v.key.clone()),
+                });
+            }
+        });
+        let mut campus = self.own_campus().cloned();
+        let parts = &self.campus_parts;
+        let mut changed = false;
+        evt.handle_event::<CycleCampus, _>(|CycleCampus(part)| {
+            if let Some(campus) = campus.as_mut() {
+                match part {
+                    CampusPart::Primary => if let Some(c) = cycle(&parts.colors, &campus.primary) {
+                        campus.primary = c;
+                    },
+                    CampusPart::Secondary => if let Some(c) = cycle(&parts.colors, &campus.secondary) {
+                        campus.secondary = c;
+                    },
+                    CampusPart::Shape => campus.crest_shape = cycle_optional(&parts.shapes, &campus.crest_shape),
+                    CampusPart::Emblem => campus.crest_emblem = cycle_optional(&parts.emblems, &campus.crest_emblem),
+                }
+                changed = true;
+            }
+        });
+        evt.handle_event::<RenameCampus, _>(|_| {
+            let name = query!(ui, textbox(id="campus_name") > content > @text).next();
+            let name = name.as_ref()
+                .and_then(|v| v.text());
+            if let (Some(campus), Some(name)) = (campus.as_mut(), name) {
+                let name = name.trim();
+                if !name.is_empty() {
+                    campus.name = name.chars().take(player::MAX_CAMPUS_NAME_LENGTH).collect();
+                    changed = true;
+                }
+            }
+        });
+        if changed {
+            if let (Some(info), Some(identity)) = (info.as_mut(), campus) {
+                // The server replies with the sanitized identity in
+                // the next lobby update
+                let _ = info.sender.ensure_send(packet::SetCampus {
+                    identity,
+                });
+            }
+        }
+        let mut profile = self.own_profile().cloned();
+        let taken: Vec<u8> = self.current_players.iter()
+            .filter(|v| v.uid.0 != self.uid)
+            .map(|v| v.profile.color)
+            .collect();
+        let profile_parts = &self.profile_parts;
+        let mut profile_changed = false;
+        evt.handle_event::<CycleProfile, _>(|CycleProfile(part)| {
+            if let Some(profile) = profile.as_mut() {
+                match part {
+                    // Skips colours the other players are using
+                    ProfilePart::Color => if let Some(c) = (1..=player::PLAYER_COLORS.len())
+                        .map(|v| ((usize::from(profile.color) + v) % player::PLAYER_COLORS.len()) as u8)
+                        .find(|v| !taken.contains(v))
+                    {
+                        profile.color = c;
+                    },
+                    ProfilePart::Icon => profile.icon = cycle_optional(&profile_parts.icons, &profile.icon),
+                    ProfilePart::Title => profile.title = cycle_optional(&profile_parts.titles, &profile.title),
+                }
+                profile_changed = true;
+            }
+        });
+        if profile_changed {
+            if let (Some(info), Some(profile)) = (info.as_mut(), profile) {
+                let _ = info.sender.ensure_send(packet::SetProfile {
+                    profile,
+                });
+            }
+        }
         self.info = info;
+        #[cfg(feature = "steam")]
+        evt.handle_event::<ToggleMute, _>(|ToggleMute(uid)| {
+            state.voice.toggle_mute(uid);
+            self.rebuild_player_list(&state.steam, &state.voice, &mut state.renderer);
+        });
         action
     }
 }
\ No newline at end of file
@@ -0,0 +1,352 @@
+//! Layered music that reacts to the state of the game.
+//!
+//! Packs can describe sets of stems for a playlist in
+//! `sound/music/<playlist>.json`. When a playlist has stem sets
+//! they are played instead of the playlist's songs.
+//!
+//! ```json
+//! {
+//!     "sets": [
+//!         {
+//!             "name": "trouble",
+//!             "priority": 10,
+//!             "when": { "trouble": { "min": 0.4 } },
+//!             "stems": [
+//!                 { "sound": "music/trouble/base" },
+//!                 { "sound": "music/trouble/drums", "when": { "trouble": { "min": 0.7 } } }
+//!             ]
+//!         },
+//!         {
+//!             "name": "calm",
+//!             "fade": 4.0,
+//!             "min_time": 30.0,
+//!             "stems": [
+//!                 { "sound": "music/calm/base" },
+//!                 { "sound": "music/calm/strings", "when": { "money_trend": { "min": 0.2 } } },
+//!                 { "sound": "music/calm/night", "when": { "time_of_day": { "min": 0.75 } } }
+//!             ]
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! The set with the highest priority whose conditions match the
+//! current signals is played. Every stem in a set loops together
+//! and is faded in or out over `fade` seconds depending on its own
+//! conditions. Switching between sets crossfades between them but
+//! only once the current set has played for `min_time` seconds.
+
+use crate::prelude::*;
+use univercity_audio::{
+    AudioMixer,
+    AudioDataSource,
+    AudioBuffer,
+    OggStream,
+    SoundRef,
+};
+use serde_json;
+
+/// The default time in seconds to fade a stem in or out over
+const DEFAULT_FADE: f32 = 2.0;
+
+/// Signals from the simulation used to select the music
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MusicSignals {
+    /// How much trouble the campus is in between 0.0 and 1.0
+    pub trouble: f32,
+    /// Whether the player's money is falling (-1.0) or rising (1.0)
+    pub money_trend: f32,
+    /// How far through the day it is between 0.0 and 1.0
+    pub time_of_day: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct DirectorDesc {
+    sets: Vec<StemSetDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StemSetDesc {
+    name: String,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    when: Conditions,
+    #[serde(default = "default_fade")]
+    fade: f32,
+    #[serde(default)]
+    min_time: f32,
+    stems: Vec<StemDesc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StemDesc {
+    sound: String,
+    #[serde(default)]
+    when: Conditions,
+}
+
+fn default_fade() -> f32 { DEFAULT_FADE }
+
+/// Limits on the signals for a set or stem to play
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Conditions {
+    trouble: Range,
+    money_trend: Range,
+    time_of_day: Range,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Range {
+    min: Option<f32>,
+    max: Option<f32>,
+}
+
+impl Range {
+    fn contains(&self, v: f32) -> bool {
+        self.min.map_or(true, |min| v >= min)
+            && self.max.map_or(true, |max| v <= max)
+    }
+}
+
+impl Conditions {
+    fn matches(&self, signals: &MusicSignals) -> bool {
+        self.trouble.contains(signals.trouble)
+            && self.money_trend.contains(signals.money_trend)
+            && self.time_of_day.contains(signals.time_of_day)
+    }
+}
+
+struct StemSet {
+    name: String,
+    priority: i32,
+    when: Conditions,
+    fade: f32,
+    min_time: f32,
+    stems: Vec<Stem>,
+}
+
+struct Stem {
+    sound: ResourceKey<'static>,
+    when: Conditions,
+}
+
+struct PlayingSet {
+    /// The index of the set in the director's sets, `None` if
+    /// the sets have been replaced since
+    index: Option<usize>,
+    fade: f32,
+    stems: Vec<PlayingStem>,
+    started: Instant,
+}
+
+struct PlayingStem {
+    sound: SoundRef,
+    volume: f32,
+}
+
+/// Selects and layers music stems based on `MusicSignals`
+pub(crate) struct MusicDirector {
+    sets: Vec<StemSet>,
+    signals: MusicSignals,
+    current: Option<PlayingSet>,
+    /// Sets that have been replaced and are fading out
+    fading: Vec<PlayingSet>,
+    buffers: FNVMap<ResourceKey<'static>, AudioBuffer>,
+    last_tick: Instant,
+}
+
+impl MusicDirector {
+    pub(crate) fn new() -> MusicDirector {
+        MusicDirector {
+            sets: vec![],
+            signals: MusicSignals::default(),
+            current: None,
+            fading: vec![],
+            buffers: FNVMap::default(),
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// Returns whether the current playlist has any stem sets
+    pub(crate) fn has_sets(&self) -> bool {
+        !self.sets.is_empty()
+    }
+
+    /// Updates the signals used to select the music
+    pub(crate) fn set_signals(&mut self, signals: MusicSignals) {
+        self.signals = signals;
+    }
+
+    /// Replaces the stem sets with the ones for the named playlist
+    /// fading out any currently playing music.
+    pub(crate) fn load_playlist(&mut self, log: &Logger, assets: &AssetManager, list: &str) {
+        let file_name = format!("sound/music/{}.json", list);
+        let mut sets = Vec::new();
+        for m_key in assets.get_packs() {
+            let file = if let Ok(f) = assets.open_from_pack(m_key.borrow(), &file_name) {
+                f
+            } else {
+                continue
+            };
+            let desc: DirectorDesc = match serde_json::from_reader(file) {
+                Ok(val) => val,
+                Err(err) => {
+                    error!(log, "Failed to parse {} for pack {:?}: {}", file_name, m_key, err);
+                    continue
+                }
+            };
+            sets.extend(desc.sets.into_iter()
+                .map(|set| StemSet {
+                    name: set.name,
+                    priority: set.priority,
+                    when: set.when,
+                    fade: set.fade.max(0.0),
+                    min_time: set.min_time,
+                    stems: set.stems.into_iter()
+                        .map(|stem| Stem {
+                            sound: LazyResourceKey::parse(&stem.sound)
+                                .or_module(m_key.borrow())
+                                .into_owned(),
+                            when: stem.when,
+                        })
+                        .collect(),
+                }));
+        }
+        // Stable so that earlier sets win ties
+        sets.sort_by_key(|v| -v.priority);
+
+        if let Some(mut current) = self.current.take() {
+            current.index = None;
+            self.fading.push(current);
+        }
+        for set in &mut self.fading {
+            set.index = None;
+        }
+        self.buffers.retain(|k, _| sets.iter()
+            .flat_map(|v| &v.stems)
+            .any(|v| v.sound == *k));
+        self.sets = sets;
+    }
+
+    /// Switches sets if required and fades stems towards their
+    /// target volumes
+    pub(crate) fn tick(&mut self, log: &Logger, mixer: &AudioMixer, assets: &AssetManager, music_volume: f64) {
+        let delta = self.last_tick.elapsed().as_secs_f32();
+        self.last_tick = Instant::now();
+
+        let signals = self.signals;
+        let wanted = self.sets.iter()
+            .position(|v| v.when.matches(&signals));
+        let current_index = self.current.as_ref().and_then(|v| v.index);
+        if wanted != current_index {
+            let can_switch = match (self.current.as_ref(), current_index) {
+                (Some(cur), Some(idx)) => cur.started.elapsed().as_secs_f32() >= self.sets[idx].min_time,
+                _ => true,
+            };
+            if can_switch {
+                if let Some(current) = self.current.take() {
+                    self.fading.push(current);
+                }
+                let next = wanted.map(|idx| self.start_set(log, mixer, assets, idx));
+                self.current = next;
+            }
+        }
+
+        let volume = music_volume as f32;
+        if let Some(current) = self.current.as_mut() {
+            let set = current.index.and_then(|v| self.sets.get(v));
+            for (idx, stem) in current.stems.iter_mut().enumerate() {
+                let target = set.and_then(|v| v.stems.get(idx))
+                    .map_or(false, |v| v.when.matches(&signals));
+                fade_stem(stem, if target { 1.0 } else { 0.0 }, current.fade, delta, volume);
+            }
+        }
+        self.fading.retain(|set| {
+            let mut playing = false;
+            for stem in &set.stems {
+                playing |= stem.volume > 0.0;
+            }
+            if !playing {
+                for stem in &set.stems {
+                    stem.sound.stop();
+                }
+            }
+            playing
+        });
+        for set in &mut self.fading {
+            for stem in &mut set.stems {
+                fade_stem(stem, 0.0, set.fade, delta, volume);
+            }
+        }
+    }
+
+    /// Applies a change to the music volume
+    pub(crate) fn update_volume(&self, music_volume: f64) {
+        for set in self.current.iter().chain(&self.fading) {
+            for stem in &set.stems {
+                stem.sound.set_volume(stem.volume * music_volume as f32);
+            }
+        }
+    }
+
+    fn start_set(&mut self, log: &Logger, mixer: &AudioMixer, assets: &AssetManager, index: usize) -> PlayingSet {
+        let set = &self.sets[index];
+        debug!(log, "Switching music"; "set" => &set.name);
+        let buffers = &mut self.buffers;
+        // Create every stem before starting them so that
+        // they remain in sync with each other
+        let stems: Vec<_> = set.stems.iter()
+            .filter_map(|stem| {
+                let buffer = if let Some(buffer) = buffers.get(&stem.sound) {
+                    buffer.clone()
+                } else {
+                    let asset = match assets.open_from_pack(
+                        stem.sound.module_key(),
+                        &format!("sound/{}.ogg", stem.sound.resource())
+                    ) {
+                        Ok(val) => val,
+                        Err(err) => {
+                            error!(log, "Failed to open music stem {:?}: {}", stem.sound, err);
+                            return None;
+                        }
+                    };
+                    let buffer = assume!(log, OggStream::load(asset))
+                        .resampled(44_100)
+                        .into_buffer();
+                    buffers.insert(stem.sound.clone(), buffer.clone());
+                    buffer
+                };
+                Some(PlayingStem {
+                    sound: mixer.play(buffer.looped_source().volume(0.0)),
+                    volume: 0.0,
+                })
+            })
+            .collect();
+        for stem in &stems {
+            stem.sound.play();
+        }
+        PlayingSet {
+            index: Some(index),
+            fade: set.fade,
+            stems,
+            started: Instant::now(),
+        }
+    }
+}
+
+fn fade_stem(stem: &mut PlayingStem, target: f32, fade: f32, delta: f32, music_volume: f32) {
+    if stem.volume == target {
+        return;
+    }
+    if fade <= 0.0 {
+        stem.volume = target;
+    } else if stem.volume < target {
+        stem.volume = (stem.volume + delta / fade).min(target);
+    } else {
+        stem.volume = (stem.volume - delta / fade).max(target);
+    }
+    stem.sound.set_volume(stem.volume * music_volume);
+}
@@ -0,0 +1,109 @@
+//! Optional narration channel for screen readers.
+//!
+//! Describes the focused ui element and the current build state
+//! as plain text. The text is either appended to a log file that
+//! external tools can follow or copied to the clipboard.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+use crate::prelude::*;
+
+const NARRATION_LOG: &str = "./narration.log";
+
+/// Where narration text is sent to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NarrationMode {
+    /// Narration is disabled
+    Off,
+    /// Narration is appended to `narration.log`
+    TextLog,
+    /// Narration replaces the contents of the clipboard
+    Clipboard,
+}
+
+impl NarrationMode {
+    /// All modes in the order they are displayed in the options menu
+    pub const ALL: &'static [NarrationMode] = &[
+        NarrationMode::Off,
+        NarrationMode::TextLog,
+        NarrationMode::Clipboard,
+    ];
+
+    /// Returns the name used to store this mode in the config
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NarrationMode::Off => "off",
+            NarrationMode::TextLog => "text_log",
+            NarrationMode::Clipboard => "clipboard",
+        }
+    }
+
+    /// Parses the mode from its config name, falling back
+    /// to `Off` if unknown
+    pub fn from_str(v: &str) -> NarrationMode {
+        match v {
+            "text_log" => NarrationMode::TextLog,
+            "clipboard" => NarrationMode::Clipboard,
+            _ => NarrationMode::Off,
+        }
+    }
+}
+
+/// Sends textual descriptions of the game to the selected
+/// narration output
+pub struct Narrator {
+    log: Logger,
+    clipboard: sdl2::clipboard::ClipboardUtil,
+    file: Option<File>,
+    last: String,
+}
+
+impl Narrator {
+    /// Creates a narrator using the passed video subsystem
+    /// for clipboard access
+    pub fn new(log: &Logger, video: &sdl2::VideoSubsystem) -> Narrator {
+        Narrator {
+            log: log.new(o!("source" => "narrator")),
+            clipboard: video.clipboard(),
+            file: None,
+            last: String::new(),
+        }
+    }
+
+    /// Outputs the text using the passed mode.
+    ///
+    /// Repeating the previous text does nothing to avoid
+    /// flooding the output whilst the state is unchanged.
+    pub fn narrate(&mut self, mode: NarrationMode, text: &str) {
+        if mode == NarrationMode::Off || text.is_empty() || self.last == text {
+            return;
+        }
+        self.last.clear();
+        self.last.push_str(text);
+        match mode {
+            NarrationMode::Off => {},
+            NarrationMode::TextLog => {
+                if self.file.is_none() {
+                    self.file = match OpenOptions::new().create(true).append(true).open(NARRATION_LOG) {
+                        Ok(f) => Some(f),
+                        Err(err) => {
+                            warn!(self.log, "Failed to open the narration log: {}", err);
+                            return;
+                        },
+                    };
+                }
+                if let Some(file) = self.file.as_mut() {
+                    if let Err(err) = writeln!(file, "{}", text).and_then(|_| file.flush()) {
+                        warn!(self.log, "Failed to write to the narration log: {}", err);
+                    }
+                }
+            },
+            NarrationMode::Clipboard => {
+                if let Err(err) = self.clipboard.set_clipboard_text(text) {
+                    warn!(self.log, "Failed to set the clipboard: {}", err);
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Frame timings shown by the performance hud.
+//!
+//! Each part of the frame is timed every frame and averaged
+//! over a short period so the hud doesn't flicker.
+
+use std::time::{Duration, Instant};
+
+/// The number of frames averaged into a single report
+const AVERAGE_FRAMES: u32 = 30;
+
+/// A timed part of the client's frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FramePhase {
+    /// Window events and input
+    Events,
+    /// Packets, states and the game instance excluding entity systems
+    Game,
+    /// The client side entity systems
+    EntitySystems,
+    /// Rebuilding changed parts of the level
+    Level,
+    /// Updating the ui
+    Ui,
+    /// Laying out the ui and drawing the world
+    Render,
+    /// Drawing the ui
+    UiDraw,
+    /// Presenting the frame
+    Present,
+}
+
+const PHASE_COUNT: usize = 8;
+
+impl FramePhase {
+    /// Every phase in the order they run
+    pub const ALL: [FramePhase; PHASE_COUNT] = [
+        FramePhase::Events,
+        FramePhase::Game,
+        FramePhase::EntitySystems,
+        FramePhase::Level,
+        FramePhase::Ui,
+        FramePhase::Render,
+        FramePhase::UiDraw,
+        FramePhase::Present,
+    ];
+
+    /// Returns a readable name for the phase
+    pub fn name(self) -> &'static str {
+        match self {
+            FramePhase::Events => "Events",
+            FramePhase::Game => "Game",
+            FramePhase::EntitySystems => "Entity systems",
+            FramePhase::Level => "Level",
+            FramePhase::Ui => "UI",
+            FramePhase::Render => "Render",
+            FramePhase::UiDraw => "UI draw",
+            FramePhase::Present => "Present",
+        }
+    }
+}
+
+/// Averaged timings of recent frames
+#[derive(Clone, Debug)]
+pub struct FrameReport {
+    /// The average time taken by a frame
+    pub frame: Duration,
+    /// The average time taken by each phase
+    pub phases: [Duration; PHASE_COUNT],
+    /// The average time taken by each render pass
+    pub passes: Vec<(&'static str, Duration)>,
+}
+
+impl FrameReport {
+    /// Returns the average time taken by the phase
+    pub fn get(&self, phase: FramePhase) -> Duration {
+        self.phases[phase as usize]
+    }
+}
+
+/// Collects the timings of each frame
+#[derive(Default)]
+pub struct FrameTimings {
+    current: [Duration; PHASE_COUNT],
+    total: [Duration; PHASE_COUNT],
+    total_frame: Duration,
+    total_passes: Vec<(&'static str, Duration)>,
+    frames: u32,
+    report: Option<FrameReport>,
+}
+
+impl FrameTimings {
+    /// Adds the time since `start` to the phase, returning
+    /// the current time to be used as the start of the
+    /// next phase
+    pub fn record(&mut self, phase: FramePhase, start: Instant) -> Instant {
+        let now = Instant::now();
+        self.add(phase, now.duration_since(start));
+        now
+    }
+
+    /// Adds the time to the phase for the current frame
+    pub fn add(&mut self, phase: FramePhase, time: Duration) {
+        self.current[phase as usize] += time;
+    }
+
+    /// Removes the time counted by `inner` from `outer`, used
+    /// when a phase is timed whilst inside of another
+    pub fn exclude(&mut self, outer: FramePhase, inner: FramePhase) {
+        let time = self.current[inner as usize];
+        let outer = &mut self.current[outer as usize];
+        *outer = outer.checked_sub(time).unwrap_or_default();
+    }
+
+    /// Finishes the current frame
+    pub fn end_frame(&mut self, frame: Duration, passes: &[(&'static str, Duration)]) {
+        for (total, time) in self.total.iter_mut().zip(&self.current) {
+            *total += *time;
+        }
+        self.current = Default::default();
+        self.total_frame += frame;
+        for &(name, time) in passes {
+            if let Some(pass) = self.total_passes.iter_mut().find(|v| v.0 == name) {
+                pass.1 += time;
+            } else {
+                self.total_passes.push((name, time));
+            }
+        }
+        self.frames += 1;
+
+        if self.frames >= AVERAGE_FRAMES {
+            let frames = self.frames;
+            let mut phases: [Duration; PHASE_COUNT] = Default::default();
+            for (avg, total) in phases.iter_mut().zip(&self.total) {
+                *avg = *total / frames;
+            }
+            self.report = Some(FrameReport {
+                frame: self.total_frame / frames,
+                phases,
+                passes: self.total_passes.drain(..)
+                    .map(|(name, time)| (name, time / frames))
+                    .collect(),
+            });
+            self.total = Default::default();
+            self.total_frame = Duration::from_secs(0);
+            self.frames = 0;
+        }
+    }
+
+    /// Returns the latest averaged report if any
+    pub fn report(&self) -> Option<&FrameReport> {
+        self.report.as_ref()
+    }
+}
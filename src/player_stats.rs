@@ -0,0 +1,99 @@
+
+use crate::prelude::*;
+use super::{GameState, GameInstance};
+use crate::state;
+use crate::ui;
+use crate::server::player::LifetimeStats;
+
+/// Shows the statistics the player has built up over every game
+/// along with their progress towards each achievement
+pub(crate) struct MenuState {
+    ui: Option<ui::Node>,
+}
+
+impl MenuState {
+    pub(crate) fn new() -> MenuState {
+        MenuState {
+            ui: None,
+        }
+    }
+}
+
+impl state::State for MenuState {
+    fn copy(&self) -> Box<dyn state::State> {
+        Box::new(MenuState {
+            ui: self.ui.clone(),
+        })
+    }
+
+    fn takes_focus(&self) -> bool { true }
+
+    fn active(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) -> state::Action {
+        let node = state.ui_manager.create_node(ResourceKey::new("base", "menus/player_stats"));
+
+        let stats = LifetimeStats::load(&state.filesystem)
+            .unwrap_or_else(|err| {
+                warn!(state.global_logger, "Failed to load the player's statistics"; "error" => %err);
+                LifetimeStats::default()
+            });
+
+        if let Some(content) = query!(node, scroll_panel > content).next() {
+            content.add_child(node! {
+                stats_header {
+                    @text("Statistics")
+                }
+            });
+            let entries = [
+                ("Time played", format_playtime(stats.playtime)),
+                ("Campuses created", stats.campuses_created.to_string()),
+                ("Students graduated", stats.students_graduated.to_string()),
+            ];
+            for (name, value) in &entries {
+                content.add_child(node! {
+                    stats_entry {
+                        @text(format!("{}: {}", name, value))
+                    }
+                });
+            }
+
+            content.add_child(node!(stats_spacer));
+            content.add_child(node! {
+                stats_header {
+                    @text("Achievements")
+                }
+            });
+            for (achievement, progress) in stats.achievements() {
+                content.add_child(node! {
+                    achievement(complete = progress >= 1.0) {
+                        achievement_name {
+                            @text(achievement.name)
+                        }
+                        achievement_description {
+                            @text(format!("{} ({:.0}%)", achievement.description, progress * 100.0))
+                        }
+                    }
+                });
+            }
+        }
+
+        self.ui = Some(node);
+
+        state::Action::Nothing
+    }
+
+    fn inactive(&mut self, _instance: &mut Option<GameInstance>, state: &mut GameState) {
+        if let Some(node) = self.ui.take() {
+            state.ui_manager.remove_node(node);
+        }
+    }
+}
+
+fn format_playtime(secs: u64) -> String {
+    let hours = secs / (60 * 60);
+    let minutes = (secs / 60) % 60;
+    if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
@@ -1,7 +1,7 @@
 
 use cgmath;
 
-use super::{gl, exmodel, atlas, image, pipeline, PassFlag, ModelKey, ModelKeyBorrow};
+use super::{gl, exmodel, atlas, image, pipeline, memory, picking, PassFlag, ModelKey, ModelKeyBorrow};
 use crate::util::FNVMap;
 use crate::server::assets;
 use crate::ecs;
@@ -36,6 +36,7 @@ struct Attributes {
     highlight: gl::Attribute,
     bone_offset: gl::Attribute,
     tint_offset: gl::Attribute,
+    pick: gl::Attribute,
 }
 
 pub(super) struct GLModel {
@@ -56,7 +57,7 @@ pub(super) struct GLModel {
     tint_col_texture: gl::Texture,
 
     max_count: usize,
-    pub(super) entity_map: FNVMap<ecs::Entity, usize>,
+    pub(super) entity_map: ecs::EntityMap<usize>,
     pub(super) dyn_info: Vec<DynInfo>,
     pub(super) bone_info: Vec<cgmath::Matrix4<f32>>,
     // Used for attachments
@@ -86,6 +87,7 @@ pub(super) struct DynInfo {
     highlight: (u8, u8, u8, u8),
     pub(super) bone_offset: i32,
     tint_offset: i32,
+    pick: (u8, u8, u8, u8),
 }
 
 impl Info {
@@ -110,9 +112,10 @@ impl Info {
                 program.attribute("attrib_tint_offset").expect("Missing `attrib_tint_offset`"),
             )
         };
+        let attrib_pick = ctx.program("animated_pick").attribute("attrib_pick").expect("Missing `attrib_pick`");
         let log = log.new(o!("source" => "animated_model"));
 
-        let img = assume!(log, asset_manager.loader_open::<image::Loader>(ResourceKey::new("base", "no_tint")));
+        let img = assume!(log, asset_manager.loader_open::<image::Loader>(assets::TypedKey::new("base", "no_tint")));
         let tex_data = img.wait_take_image().expect("Missing default tint texture");
 
         let texture = gl::Texture::new();
@@ -149,6 +152,7 @@ impl Info {
                 highlight: attrib_highlight,
                 bone_offset: attrib_bone_offset,
                 tint_offset: attrib_tint_offset,
+                pick: attrib_pick,
             },
 
             standard_tint: texture,
@@ -191,6 +195,7 @@ impl <'a> super::EntityRender<'a> for Info {
         model_key: assets::ResourceKey<'_>,
         texture: Option<assets::ResourceKey<'_>>,
         ents: &[ecs::Entity],
+        picks: &mut picking::PickTable,
         _delta: f64,
     ) {
         use std::collections::hash_map::Entry;
@@ -226,6 +231,7 @@ impl <'a> super::EntityRender<'a> for Info {
         }
 
         let selection_invalid = config.placement_invalid_colour.get();
+        let palette = config.colour_palette.get();
 
         let root_inv = model.info.root_node.transform.invert()
             .expect("Failed to invert root transform");
@@ -240,7 +246,10 @@ impl <'a> super::EntityRender<'a> for Info {
             highlight: (0, 0, 0, 0),
             bone_offset: 0,
             tint_offset: 0,
+            pick: (0, 0, 0, 0),
         });
+        let pick_base = picks.len();
+        picks.extend_from_slice(ents);
 
         let minfo = &model.info;
         let animations = &model.animations;
@@ -327,7 +336,8 @@ impl <'a> super::EntityRender<'a> for Info {
                 let mut hightlighted = false;
                 let highlight = if let Some(h) = highlight.get_component(*e) {
                     hightlighted = true;
-                    (h.color.0, h.color.1, h.color.2, 255)
+                    let col = palette.correct(h.color);
+                    (col.0, col.1, col.2, 255)
                 } else {
                     (0, 0, 0, 0)
                 };
@@ -339,6 +349,7 @@ impl <'a> super::EntityRender<'a> for Info {
                     highlight,
                     bone_offset: bone_offset as i32,
                     tint_offset: tint_offset as i32,
+                    pick: picking::encode_id(pick_base + idx),
                 };
                 hightlighted
             })
@@ -422,7 +433,7 @@ pub(crate) fn init_model<'a>(
         asset_manager: &assets::AssetManager,
         val: VacantEntry<'a, ResourceKey<'static>, Model>
 ) -> &'a mut Model {
-    let mut file = assume!(log, asset_manager.open_from_pack(val.key().module_key(), &format!("models/{}.uamod", val.key().resource())));
+    let mut file = assume!(log, assets::TypedKey::<assets::AnimatedModelAsset>::from_key(val.key().borrow()).open(asset_manager));
     let minfo = assume!(log, exmodel::AniModel::read_from(&mut file));
 
     let mut bones = FNVMap::default();
@@ -459,6 +470,7 @@ fn init_gl_model<'a>(
 ) -> &'a mut GLModel {
     use std::mem;
 
+    let _memory = memory::scope(memory::Category::Models, "Animated models", Some(val.key().0.module()));
     let minfo = &model.info;
 
     let array = gl::VertexArray::new();
@@ -515,6 +527,9 @@ fn init_gl_model<'a>(
     attributes.tint_offset.enable();
     attributes.tint_offset.vertex_int_pointer(1, gl::Type::Int, mem::size_of::<DynInfo>() as i32, 4 * 4 * 4 + 4 + 4 + 4);
     attributes.tint_offset.divisor(1);
+    attributes.pick.enable();
+    attributes.pick.vertex_pointer(4, gl::Type::UnsignedByte, true, mem::size_of::<DynInfo>() as i32, 4 * 4 * 4 + 4 + 4 + 4 + 4);
+    attributes.pick.divisor(1);
 
 
     let texture = {
@@ -525,7 +540,7 @@ fn init_gl_model<'a>(
             log,
             asset_manager,
             global_atlas,
-            tex
+            assets::TypedKey::from_key(tex)
         )
     };
 
@@ -535,7 +550,7 @@ fn init_gl_model<'a>(
         let key = format!("{}_tint", minfo.texture);
         let tex = assets::LazyResourceKey::parse(&key)
             .or_module(val.key().0.module_key());
-        let img = assume!(log, asset_manager.loader_open::<image::Loader>(tex.borrow()));
+        let img = assume!(log, asset_manager.loader_open::<image::Loader>(assets::TypedKey::from_key(tex.borrow())));
         if let Ok(tint_tex) = img.wait_take_image() {
             let mut highest = 0;
             for c in tint_tex.data.chunks_exact(4) {
@@ -545,7 +560,10 @@ fn init_gl_model<'a>(
             }
             tint_count = highest as usize + 1;
 
-            let texture = gl::Texture::new();
+            let texture = {
+                let _memory = memory::scope(memory::Category::Textures, "Tint maps", Some(val.key().0.module()));
+                gl::Texture::new()
+            };
             texture.bind(gl::TextureTarget::Texture2D);
             texture.image_2d_ex(
                 gl::TextureTarget::Texture2D, 0,
@@ -592,7 +610,7 @@ fn init_gl_model<'a>(
         matrix_buffer,
         max_count: 0,
 
-        entity_map: FNVMap::default(),
+        entity_map: ecs::EntityMap::new(),
         dyn_info: vec![],
 
         bone_matrix_buffer,
@@ -0,0 +1,294 @@
+//! Abstracts the graphics API used by the renderer.
+//!
+//! The renderer talks to the graphics API through a `Backend`,
+//! which owns the connection to the window, and the `Device` it
+//! hands out which creates and uses resources. Parts of the
+//! renderer are moved over to these traits from calling `gl`
+//! directly one at a time so that another backend can be developed
+//! alongside the OpenGL one without breaking the game.
+//!
+//! The backend is picked from the config when the game starts.
+//! OpenGL is the only backend currently implemented.
+
+use std::thread;
+use std::time::Duration;
+
+use crate::prelude::*;
+use super::gl;
+use sdl2;
+use sdl2::video::{Window, WindowBuilder};
+
+/// The graphics APIs the renderer can use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    /// OpenGL 3.3 core
+    OpenGL,
+}
+
+impl BackendKind {
+    /// All backends in the order they are displayed in the options menu
+    pub const ALL: &'static [BackendKind] = &[
+        BackendKind::OpenGL,
+    ];
+
+    /// Returns the name used to store this backend in the config
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackendKind::OpenGL => "opengl",
+        }
+    }
+
+    /// Parses the backend from its config name, falling back
+    /// to `OpenGL` if unknown
+    pub fn from_str(v: &str) -> BackendKind {
+        BackendKind::ALL.iter()
+            .cloned()
+            .find(|b| b.as_str() == v)
+            .unwrap_or(BackendKind::OpenGL)
+    }
+
+    /// Returns the name of the backend as displayed to the user
+    pub fn name(self) -> &'static str {
+        match self {
+            BackendKind::OpenGL => "OpenGL 3.3",
+        }
+    }
+
+    /// Sets up the video subsystem and window for the backend.
+    ///
+    /// Must be called before the window is built
+    pub fn configure_window(self, video: &sdl2::VideoSubsystem, builder: &mut WindowBuilder) {
+        match self {
+            BackendKind::OpenGL => {
+                let gl_attr = video.gl_attr();
+                gl_attr.set_stencil_size(8);
+                gl_attr.set_depth_size(24);
+                gl_attr.set_context_major_version(3);
+                gl_attr.set_context_minor_version(2);
+                gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
+                builder.opengl();
+            }
+        }
+    }
+}
+
+/// The backend used by the renderer.
+///
+/// Only one backend is compiled in currently so this is
+/// always OpenGL.
+pub type Active = GlBackend;
+/// The device of the backend used by the renderer
+pub type ActiveDevice = <Active as Backend>::Device;
+
+/// Owns the connection between a window and the graphics API
+pub trait Backend: Sized {
+    /// Creates and uses resources for the backend
+    type Device: Device;
+
+    /// Connects to the window and makes the backend current
+    /// for the calling thread
+    fn create(log: &Logger, window: &Window) -> Result<Self, String>;
+
+    /// Returns the kind of the backend
+    fn kind(&self) -> BackendKind;
+
+    /// Returns the device used to create resources
+    fn device(&self) -> Self::Device;
+
+    /// Returns the reason the device was lost if it has been
+    /// lost since the last call
+    fn lost(&self) -> Option<String>;
+
+    /// Reconnects to the window after the device was lost.
+    ///
+    /// Every resource created before this must be recreated
+    fn recreate(&mut self, log: &Logger, window: &Window) -> Result<(), String>;
+
+    /// Displays the rendered frame in the window
+    fn present(&self, window: &Window);
+}
+
+/// Creates and uses resources on the graphics API
+pub trait Device: Copy {
+    /// A buffer of vertex, index or uniform data
+    type Buffer;
+    /// A texture
+    type Texture;
+    /// A linked set of shaders
+    type Program;
+
+    /// Creates an empty buffer
+    fn create_buffer(self) -> Self::Buffer;
+    /// Replaces the contents of the buffer
+    fn buffer_data<T>(self, buffer: &Self::Buffer, target: gl::BufferTarget, data: &[T], usage: gl::BufferUsage);
+    /// Creates an empty texture
+    fn create_texture(self) -> Self::Texture;
+    /// Compiles the vertex and fragment shader and links them
+    /// into a program, binding the named attributes to the
+    /// passed locations.
+    ///
+    /// The sources shouldn't include a version header, the
+    /// device adds the one it requires
+    fn compile_program(self, vertex: &str, fragment: &str, attributes: &[(&str, u32)]) -> Result<Self::Program, String>;
+    /// Sets the area of the target drawn to
+    fn viewport(self, x: u32, y: u32, width: u32, height: u32);
+    /// Clears the passed buffers of the current target
+    fn clear(self, buffers: gl::BufferBit);
+    /// Draws `count` vertices from the bound buffers
+    fn draw_arrays(self, ty: gl::DrawType, offset: usize, count: usize);
+    /// Draws `instances` copies of the `count` indexed vertices
+    /// from the bound buffers
+    fn draw_elements_instanced(self, ty: gl::DrawType, count: usize, index_ty: gl::Type, instances: usize);
+}
+
+/// Renders using OpenGL 3.3 core
+pub struct GlBackend {
+    context: sdl2::video::GLContext,
+}
+
+impl GlBackend {
+    fn create_context(log: &Logger, window: &Window) -> Result<sdl2::video::GLContext, String> {
+        use sdl2::sys::{SDL_GL_SetAttribute, SDL_GLattr, SDL_GLContextResetNotification};
+        let gl_attr = window.subsystem().gl_attr();
+        gl_attr.set_context_flags().robust_access().set();
+        unsafe {
+            SDL_GL_SetAttribute(
+                SDL_GLattr::SDL_GL_CONTEXT_RESET_NOTIFICATION,
+                SDL_GLContextResetNotification::SDL_GL_CONTEXT_RESET_LOSE_CONTEXT as i32,
+            );
+        }
+        let context = window.gl_create_context()
+            .or_else(|err| {
+                warn!(log, "Failed to create a robust context, losing the context will be fatal"; "error" => %err);
+                gl_attr.set_context_flags().set();
+                unsafe {
+                    SDL_GL_SetAttribute(
+                        SDL_GLattr::SDL_GL_CONTEXT_RESET_NOTIFICATION,
+                        SDL_GLContextResetNotification::SDL_GL_CONTEXT_RESET_NO_NOTIFICATION as i32,
+                    );
+                }
+                window.gl_create_context()
+            })?;
+        window.gl_make_current(&context)?;
+        let video = window.subsystem();
+        gl::load_with(|s| video.gl_get_proc_address(s) as *const _);
+        Self::init_state();
+        Ok(context)
+    }
+
+    fn init_state() {
+        gl::enable(gl::Flag::DepthTest);
+        gl::enable(gl::Flag::CullFace);
+        gl::front_face(gl::Face::ClockWise);
+        gl::cull_face(gl::CullFace::Back);
+        gl::depth_func(gl::Func::GreaterOrEqual);
+        gl::clear_depth(0.0);
+    }
+}
+
+impl Backend for GlBackend {
+    type Device = GlDevice;
+
+    fn create(log: &Logger, window: &Window) -> Result<GlBackend, String> {
+        Ok(GlBackend {
+            context: Self::create_context(log, window)?,
+        })
+    }
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::OpenGL
+    }
+
+    fn device(&self) -> GlDevice {
+        GlDevice { _private: () }
+    }
+
+    fn lost(&self) -> Option<String> {
+        let status = gl::graphics_reset_status();
+        if status == gl::ResetStatus::NoError {
+            None
+        } else {
+            Some(format!("{:?}", status))
+        }
+    }
+
+    fn recreate(&mut self, log: &Logger, window: &Window) -> Result<(), String> {
+        // The driver keeps reporting the reset until it has
+        // finished resetting
+        while gl::graphics_reset_status() != gl::ResetStatus::NoError {
+            thread::sleep(Duration::from_millis(10));
+        }
+        // Everything currently allocated was destroyed with the
+        // old context so must not be deleted from the new one
+        gl::invalidate_context();
+        self.context = Self::create_context(log, window)?;
+        Ok(())
+    }
+
+    fn present(&self, window: &Window) {
+        window.gl_swap_window();
+    }
+}
+
+/// Creates resources using the current OpenGL context
+#[derive(Clone, Copy)]
+pub struct GlDevice {
+    _private: (),
+}
+
+impl Device for GlDevice {
+    type Buffer = gl::Buffer;
+    type Texture = gl::Texture;
+    type Program = gl::Program;
+
+    fn create_buffer(self) -> gl::Buffer {
+        gl::Buffer::new()
+    }
+
+    fn buffer_data<T>(self, buffer: &gl::Buffer, target: gl::BufferTarget, data: &[T], usage: gl::BufferUsage) {
+        buffer.bind(target);
+        buffer.set_data(target, data, usage);
+    }
+
+    fn create_texture(self) -> gl::Texture {
+        gl::Texture::new()
+    }
+
+    fn compile_program(self, vertex: &str, fragment: &str, attributes: &[(&str, u32)]) -> Result<gl::Program, String> {
+        let program = gl::Program::new();
+        program.attach_shader(compile_shader(gl::ShaderType::Vertex, vertex)?);
+        program.attach_shader(compile_shader(gl::ShaderType::Fragment, fragment)?);
+        for &(name, index) in attributes {
+            program.bind_attribute_location(name, index);
+        }
+        program.link();
+        Ok(program)
+    }
+
+    fn viewport(self, x: u32, y: u32, width: u32, height: u32) {
+        gl::view_port(x, y, width, height);
+    }
+
+    fn clear(self, buffers: gl::BufferBit) {
+        gl::clear(buffers);
+    }
+
+    fn draw_arrays(self, ty: gl::DrawType, offset: usize, count: usize) {
+        gl::draw_arrays(ty, offset, count);
+    }
+
+    fn draw_elements_instanced(self, ty: gl::DrawType, count: usize, index_ty: gl::Type, instances: usize) {
+        gl::draw_elements_instanced(ty, count, index_ty, instances);
+    }
+}
+
+fn compile_shader(ty: gl::ShaderType, src: &str) -> Result<gl::Shader, String> {
+    let shader = gl::Shader::new(ty);
+    // The version number must go first otherwise the shader
+    // wouldn't compile with any defines
+    let mut full = String::from("#version 330 core\n");
+    full.push_str(src);
+    shader.set_source(&full);
+    shader.compile()?;
+    Ok(shader)
+}
@@ -7,7 +7,52 @@ use std::ffi;
 use std::ptr;
 use std::mem;
 use std::marker::PhantomData;
+use std::cell::Cell;
 use crate::prelude::*;
+use super::memory;
+
+thread_local! {
+    static CONTEXT_GENERATION: Cell<u32> = Cell::new(0);
+}
+
+/// Returns the generation of the current context.
+///
+/// Objects remember the generation they were created in
+/// so that objects from a lost context aren't deleted from
+/// the context that replaced it.
+pub fn context_generation() -> u32 {
+    CONTEXT_GENERATION.with(|v| v.get())
+}
+
+/// Marks every existing object as belonging to a lost context
+pub fn invalidate_context() {
+    CONTEXT_GENERATION.with(|v| v.set(v.get().wrapping_add(1)));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetStatus {
+    NoError,
+    Guilty,
+    Innocent,
+    Unknown,
+}
+
+/// Returns whether the context has been lost since the
+/// last call.
+///
+/// Always returns `NoError` if the driver doesn't support
+/// `GL_ARB_robustness`
+pub fn graphics_reset_status() -> ResetStatus {
+    if !gl::GetGraphicsResetStatusARB::is_loaded() {
+        return ResetStatus::NoError;
+    }
+    match unsafe { gl::GetGraphicsResetStatusARB() } {
+        gl::NO_ERROR => ResetStatus::NoError,
+        gl::GUILTY_CONTEXT_RESET_ARB => ResetStatus::Guilty,
+        gl::INNOCENT_CONTEXT_RESET_ARB => ResetStatus::Innocent,
+        _ => ResetStatus::Unknown,
+    }
+}
 
 bitflags! {
     pub struct BufferBit: u32 {
@@ -54,7 +99,6 @@ pub enum Type {
 }
 
 impl Type {
-    #[allow(dead_code)]
     fn size(self) -> usize {
         match self {
             Type::UnsignedByte => 1,
@@ -65,7 +109,7 @@ impl Type {
             Type::Short => 2,
             Type::Int => 4,
             Type::HalfFloat => 2,
-            Type::Float => 2,
+            Type::Float => 4,
         }
     }
 }
@@ -287,6 +331,8 @@ pub enum StencilOp {
 
 pub struct Buffer {
     internal: u32,
+    generation: u32,
+    memory: memory::Allocation,
     _not_send_sync: PhantomData<*mut ()>,
 }
 
@@ -298,6 +344,9 @@ impl Buffer {
             gl::GenBuffers(1, &mut buffer);
             Buffer {
                 internal: buffer,
+
+                generation: context_generation(),
+                memory: memory::Allocation::current(),
                 _not_send_sync: PhantomData,
             }
         }
@@ -319,6 +368,7 @@ impl Buffer {
 
     #[inline]
     pub fn alloc_size<T>(&self, target: BufferTarget, len: usize, usage: BufferUsage) {
+        self.memory.set((len * mem::size_of::<T>()) as u64);
         unsafe {
             gl::BufferData(target as u32, (len * mem::size_of::<T>()) as isize, ptr::null(), usage as u32);
         }
@@ -326,6 +376,7 @@ impl Buffer {
 
     #[inline]
     pub fn set_data<T>(&self, target: BufferTarget, data: &[T], usage: BufferUsage) {
+        self.memory.set((data.len() * mem::size_of::<T>()) as u64);
         unsafe {
             gl::BufferData(target as u32, (data.len() * mem::size_of::<T>()) as isize, data.as_ptr() as *const _, usage as u32);
         }
@@ -356,6 +407,10 @@ impl Buffer {
 
 impl Drop for Buffer {
     fn drop(&mut self) {
+        // Objects from a lost context were destroyed with it
+        if self.generation != context_generation() {
+            return;
+        }
         unsafe {
             gl::DeleteBuffers(1, &self.internal);
         }
@@ -395,6 +450,7 @@ impl <'a, T> Drop for MappedWriteBuffer<'a, T> {
 
 pub struct VertexArray {
     internal: u32,
+    generation: u32,
     _not_send_sync: PhantomData<*mut ()>,
 }
 
@@ -405,6 +461,8 @@ impl VertexArray {
             gl::GenVertexArrays(1, &mut array);
             VertexArray {
                 internal: array,
+
+                generation: context_generation(),
                 _not_send_sync: PhantomData,
             }
         }
@@ -419,6 +477,9 @@ impl VertexArray {
 
 impl Drop for VertexArray {
     fn drop(&mut self) {
+        if self.generation != context_generation() {
+            return;
+        }
         unsafe {
             gl::DeleteVertexArrays(1, &self.internal);
         }
@@ -427,6 +488,7 @@ impl Drop for VertexArray {
 
 pub struct Program {
     internal: u32,
+    generation: u32,
     _not_send_sync: PhantomData<*mut ()>,
 }
 
@@ -435,6 +497,8 @@ impl Program {
         unsafe {
             Program {
                 internal: gl::CreateProgram(),
+
+                generation: context_generation(),
                 _not_send_sync: PhantomData,
             }
         }
@@ -516,6 +580,9 @@ impl Program {
 
 impl Drop for Program {
     fn drop(&mut self) {
+        if self.generation != context_generation() {
+            return;
+        }
         unsafe {
             gl::DeleteProgram(self.internal);
         }
@@ -524,6 +591,7 @@ impl Drop for Program {
 
 pub struct Shader {
     internal: u32,
+    generation: u32,
     _not_send_sync: PhantomData<*mut ()>,
 }
 
@@ -532,6 +600,8 @@ impl Shader {
         unsafe {
             Shader {
                 internal: gl::CreateShader(ty as u32),
+
+                generation: context_generation(),
                 _not_send_sync: PhantomData,
             }
         }
@@ -566,6 +636,9 @@ impl Shader {
 
 impl Drop for Shader {
     fn drop(&mut self) {
+        if self.generation != context_generation() {
+            return;
+        }
         unsafe {
             gl::DeleteShader(self.internal);
         }
@@ -749,7 +822,6 @@ pub enum TextureFormat {
 }
 
 impl TextureFormat {
-    #[allow(dead_code)]
     fn size_per_element(&self, ty: Type) -> usize {
         match self {
             TextureFormat::Red => ty.size(),
@@ -797,6 +869,7 @@ pub fn active_texture(id: u32) {
 #[repr(u32)]
 pub enum PixelStore {
     UnpackAlignment = gl::UNPACK_ALIGNMENT,
+    PackAlignment = gl::PACK_ALIGNMENT,
 }
 
 pub fn pixel_store(pname: PixelStore, val: i32) {
@@ -807,6 +880,8 @@ pub fn pixel_store(pname: PixelStore, val: i32) {
 
 pub struct Texture {
     internal: u32,
+    generation: u32,
+    memory: memory::Allocation,
     _not_send_sync: PhantomData<*mut ()>,
 }
 
@@ -817,6 +892,9 @@ impl Texture {
             gl::GenTextures(1, &mut t);
             Texture{
                 internal: t,
+
+                generation: context_generation(),
+                memory: memory::Allocation::current(),
                 _not_send_sync: PhantomData,
             }
         }
@@ -839,6 +917,7 @@ impl Texture {
         internal_format: TextureFormat, format: TextureFormat,
         ty: Type, pix: Option<&[u8]>)
     {
+        self.track_level(level, u64::from(width) * u64::from(height) * internal_format.size_per_element(ty) as u64);
         unsafe {
             let ptr = match pix {
                 Some(val) => val.as_ptr() as *const _,
@@ -857,6 +936,7 @@ impl Texture {
         internal_format: TextureFormat, format: TextureFormat,
         ty: Type, pix: Option<&[T]>)
     {
+        self.track_level(level, u64::from(width) * u64::from(height) * internal_format.size_per_element(ty) as u64);
         unsafe {
             let ptr = match pix {
                 Some(val) => val.as_ptr() as *const _,
@@ -876,6 +956,7 @@ impl Texture {
         internal_format: TextureFormat, format: TextureFormat,
         ty: Type, pix: Option<&[u8]>)
     {
+        self.track_level(level, u64::from(width) * u64::from(height) * u64::from(depth) * internal_format.size_per_element(ty) as u64);
         unsafe {
             let ptr = match pix {
                 Some(val) => val.as_ptr() as *const _,
@@ -889,6 +970,27 @@ impl Texture {
         }
     }
 
+    pub fn sub_image_2d(&self, target: TextureTarget,
+        level: i32,
+        x: u32, y: u32,
+        width: u32, height: u32,
+        format: TextureFormat,
+        ty: Type, pix: Option<&[u8]>)
+    {
+        unsafe {
+            let ptr = match pix {
+                Some(val) => val.as_ptr() as *const _,
+                None => ptr::null(),
+            };
+            gl::TexSubImage2D(
+                target as u32, level,
+                x as i32, y as i32,
+                width as i32, height as i32, format as u32,
+                ty as u32, ptr
+            );
+        }
+    }
+
     pub fn sub_image_3d(&self, target: TextureTarget,
         level: i32,
         x: u32, y: u32, z: u32,
@@ -921,10 +1023,23 @@ impl Texture {
             P::set_parameter(target, value);
         }
     }
+
+    /// Records the size of a mipmap level. Uploading the base
+    /// level replaces the texture so resets the size.
+    fn track_level(&self, level: i32, bytes: u64) {
+        if level == 0 {
+            self.memory.set(bytes);
+        } else {
+            self.memory.add(bytes);
+        }
+    }
 }
 
 impl Drop for Texture {
     fn drop(&mut self) {
+        if self.generation != context_generation() {
+            return;
+        }
         unsafe {
             gl::DeleteTextures(1, &self.internal);
         }
@@ -1075,6 +1190,7 @@ pub fn bind_frag_data_location(p: &Program, cn: u32, name: &str) {
 
 pub struct Framebuffer {
     internal: u32,
+    generation: u32,
     _not_send_sync: PhantomData<*mut ()>,
 }
 
@@ -1085,6 +1201,8 @@ impl Framebuffer {
             gl::GenFramebuffers(1, &mut fb);
             Framebuffer {
                 internal: fb,
+
+                generation: context_generation(),
                 _not_send_sync: PhantomData,
             }
         }
@@ -1111,6 +1229,9 @@ impl Framebuffer {
 
 impl Drop for Framebuffer {
     fn drop(&mut self) {
+        if self.generation != context_generation() {
+            return;
+        }
         unsafe {
             gl::DeleteFramebuffers(1, &self.internal);
         }
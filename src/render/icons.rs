@@ -37,6 +37,7 @@ struct Vertex {
 
 impl Icons {
     pub fn new(log: &Logger, ctx: &mut pipeline::Context<'_>) -> Icons {
+        let _memory = memory::scope(memory::Category::Ui, "Icons", None);
         let log = log.new(o!("source" => "icons"));
         let (
             attrib_position, attrib_color, attrib_vert, attrib_uv, attrib_size,
@@ -114,6 +115,7 @@ impl RenderState {
             entities: &mut ecs::Container,
     ) {
         use crate::entity::{Icon, Color};
+        let palette = self.config.colour_palette.get();
 
         entities.with(|
             em: ecs::EntityManager<'_>,
@@ -125,12 +127,13 @@ impl RenderState {
                 .map(|(e, (icon, pos))| {
                     let color = color.get_component(e)
                             .map_or((255, 255, 255, 255), |v| v.color);
+                    let (r, g, b) = palette.correct((color.0, color.1, color.2));
 
                     let (atlas, rect) = super::RenderState::texture_info_for(
                         &self.log,
                         &self.asset_manager,
                         &mut self.global_atlas,
-                        icon.texture.borrow(),
+                        assets::TypedKey::from_key(icon.texture.borrow()),
                     );
 
                     UniqueData {
@@ -144,9 +147,9 @@ impl RenderState {
                         texture_y: rect.y as u16,
                         texture_w: rect.width as u16,
                         texture_h: rect.height as u16,
-                        r: color.0,
-                        g: color.1,
-                        b: color.2,
+                        r,
+                        g,
+                        b,
                         a: color.3,
                         _padding: 0,
                     }
@@ -91,7 +91,7 @@ impl Loader {
 
 impl <'a> assets::AssetLoader<'a> for Loader {
     type LoaderData = LoaderData;
-    type Key = assets::ResourceKey<'a>;
+    type Key = assets::TypedKey<'a, assets::TextureAsset>;
     type Return = ImageFuture;
 
     fn init(assets: &assets::Store) -> Self::LoaderData {
@@ -103,9 +103,9 @@ impl <'a> assets::AssetLoader<'a> for Loader {
         }
     }
 
-    fn load(data: &mut Self::LoaderData, assets: &assets::AssetManager, key: assets::ResourceKey<'_>) -> server::UResult<Self::Return> {
+    fn load(data: &mut Self::LoaderData, assets: &assets::AssetManager, key: assets::TypedKey<'_, assets::TextureAsset>) -> server::UResult<Self::Return> {
         let task = Arc::new((
-            key.borrow().into_owned(),
+            key.key().into_owned(),
             Mutex::new(FutureInner {
                 state: State::Loading,
             }),
@@ -116,7 +116,7 @@ impl <'a> assets::AssetLoader<'a> for Loader {
         };
 
         // Read the header here
-        let file = try_task!(ret.inner, assets.open_from_pack(key.module_key(), &format!("textures/{}.png", key.resource())), return Ok(ret));
+        let file = try_task!(ret.inner, key.open(assets), return Ok(ret));
         let decoder = png::Decoder::new(file);
         let (info, reader) = try_task!(ret.inner, decoder.read_info(), return Ok(ret));
         {
@@ -0,0 +1,303 @@
+//! Tracks the GPU memory allocated by the renderer.
+//!
+//! Every buffer and texture records the size of the data uploaded
+//! to it against the scope it was created in. The parts of the
+//! renderer that create resources open a scope describing what the
+//! resource is for and which pack it came from so that the memory
+//! debug overlay can break the usage down for modders.
+//!
+//! Resources created outside of any scope are counted as `Other`.
+
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use crate::prelude::*;
+
+/// What a resource is used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Category {
+    /// Texture atlases and standalone textures
+    Textures,
+    /// Vertex, index and instance buffers of models
+    Models,
+    /// Buffers for the level's terrain
+    Terrain,
+    /// Buffers used to draw the user interface and icons
+    Ui,
+    /// Render targets used by the rendering pipeline
+    Pipeline,
+    /// Anything not created within a scope
+    Other,
+}
+
+impl Category {
+    /// Returns the name of the category as displayed to the user
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Textures => "Textures",
+            Category::Models => "Models",
+            Category::Terrain => "Terrain",
+            Category::Ui => "UI",
+            Category::Pipeline => "Pipeline",
+            Category::Other => "Other",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    category: Category,
+    group: String,
+    pack: Option<String>,
+}
+
+#[derive(Default)]
+struct Tracker {
+    keys: Vec<Key>,
+    lookup: FNVMap<Key, usize>,
+    bytes: Vec<u64>,
+    objects: Vec<u32>,
+    /// Bytes of shared resources attributed to a pack by
+    /// `attribute`, these aren't included in the totals
+    regions: Vec<u64>,
+    scopes: Vec<usize>,
+}
+
+impl Tracker {
+    fn id(&mut self, key: Key) -> usize {
+        if let Some(id) = self.lookup.get(&key) {
+            return *id;
+        }
+        let id = self.keys.len();
+        self.keys.push(key.clone());
+        self.lookup.insert(key, id);
+        self.bytes.push(0);
+        self.objects.push(0);
+        self.regions.push(0);
+        id
+    }
+
+    fn current(&mut self) -> usize {
+        if let Some(id) = self.scopes.last() {
+            *id
+        } else {
+            self.id(Key {
+                category: Category::Other,
+                group: "Untracked".into(),
+                pack: None,
+            })
+        }
+    }
+}
+
+thread_local! {
+    static TRACKER: RefCell<Tracker> = RefCell::new(Tracker::default());
+}
+
+/// Attributes every resource created whilst this is alive
+/// to the scope's category, group and pack
+pub struct Scope {
+    _not_send_sync: PhantomData<*mut ()>,
+}
+
+/// Opens a scope for resources created until the returned
+/// value is dropped
+pub fn scope(category: Category, group: &str, pack: Option<&str>) -> Scope {
+    TRACKER.with(|v| {
+        let mut tracker = v.borrow_mut();
+        let id = tracker.id(Key {
+            category,
+            group: group.into(),
+            pack: pack.map(|v| v.into()),
+        });
+        tracker.scopes.push(id);
+    });
+    Scope {
+        _not_send_sync: PhantomData,
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let _ = TRACKER.try_with(|v| v.borrow_mut().scopes.pop());
+    }
+}
+
+/// Records that part of a shared resource, for example a region
+/// of a texture atlas, is used by the pack.
+///
+/// This only changes how the group is broken down by pack and not
+/// its total.
+pub fn attribute(category: Category, group: &str, pack: &str, bytes: u64) {
+    TRACKER.with(|v| {
+        let mut tracker = v.borrow_mut();
+        let id = tracker.id(Key {
+            category,
+            group: group.into(),
+            pack: Some(pack.into()),
+        });
+        tracker.regions[id] += bytes;
+    });
+}
+
+/// The memory used by a single buffer or texture
+pub struct Allocation {
+    id: usize,
+    bytes: Cell<u64>,
+}
+
+impl Allocation {
+    /// Creates an empty allocation in the current scope
+    pub fn current() -> Allocation {
+        let id = TRACKER.with(|v| {
+            let mut tracker = v.borrow_mut();
+            let id = tracker.current();
+            tracker.objects[id] += 1;
+            id
+        });
+        Allocation {
+            id,
+            bytes: Cell::new(0),
+        }
+    }
+
+    /// Replaces the size of the allocation
+    pub fn set(&self, bytes: u64) {
+        let old = self.bytes.replace(bytes);
+        let id = self.id;
+        TRACKER.with(|v| {
+            let mut tracker = v.borrow_mut();
+            tracker.bytes[id] = tracker.bytes[id] - old + bytes;
+        });
+    }
+
+    /// Grows the allocation by the passed number of bytes
+    pub fn add(&self, bytes: u64) {
+        self.set(self.bytes.get() + bytes);
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        let id = self.id;
+        let bytes = self.bytes.get();
+        let _ = TRACKER.try_with(|v| {
+            let mut tracker = v.borrow_mut();
+            tracker.bytes[id] -= bytes;
+            tracker.objects[id] -= 1;
+        });
+    }
+}
+
+/// The memory used by a group of resources within a category,
+/// for example a single texture atlas
+pub struct GroupUsage {
+    /// The name of the group
+    pub name: String,
+    /// The number of bytes allocated
+    pub bytes: u64,
+    /// The number of buffers and textures in the group
+    pub objects: u32,
+    /// The bytes used by each pack sorted largest first
+    pub packs: Vec<(String, u64)>,
+}
+
+/// The memory used by a category
+pub struct CategoryUsage {
+    /// The category
+    pub category: Category,
+    /// The number of bytes allocated
+    pub bytes: u64,
+    /// The groups within the category sorted largest first
+    pub groups: Vec<GroupUsage>,
+}
+
+/// Returns the memory currently allocated by the renderer
+/// broken down by category
+pub fn report() -> Vec<CategoryUsage> {
+    TRACKER.with(|v| {
+        let tracker = v.borrow();
+        let mut categories: Vec<CategoryUsage> = vec![];
+        for (id, key) in tracker.keys.iter().enumerate() {
+            let bytes = tracker.bytes[id];
+            let objects = tracker.objects[id];
+            let pack_bytes = bytes + tracker.regions[id];
+            if objects == 0 && pack_bytes == 0 {
+                continue;
+            }
+            let cat = if let Some(cat) = categories.iter_mut().position(|v| v.category == key.category) {
+                cat
+            } else {
+                categories.push(CategoryUsage {
+                    category: key.category,
+                    bytes: 0,
+                    groups: vec![],
+                });
+                categories.len() - 1
+            };
+            let cat = &mut categories[cat];
+            cat.bytes += bytes;
+            let group = if let Some(group) = cat.groups.iter_mut().position(|v| v.name == key.group) {
+                group
+            } else {
+                cat.groups.push(GroupUsage {
+                    name: key.group.clone(),
+                    bytes: 0,
+                    objects: 0,
+                    packs: vec![],
+                });
+                cat.groups.len() - 1
+            };
+            let group = &mut cat.groups[group];
+            group.bytes += bytes;
+            group.objects += objects;
+            if let Some(pack) = key.pack.as_ref() {
+                if pack_bytes > 0 {
+                    group.packs.push((pack.clone(), pack_bytes));
+                }
+            }
+        }
+        categories.sort_by_key(|v| v.category);
+        for cat in &mut categories {
+            cat.groups.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+            for group in &mut cat.groups {
+                group.packs.sort_by(|a, b| b.1.cmp(&a.1));
+            }
+        }
+        categories
+    })
+}
+
+/// Returns the total bytes used by each pack across every category
+/// sorted largest first
+pub fn pack_totals() -> Vec<(String, u64)> {
+    TRACKER.with(|v| {
+        let tracker = v.borrow();
+        let mut totals: FNVMap<&str, u64> = FNVMap::default();
+        for (id, key) in tracker.keys.iter().enumerate() {
+            if let Some(pack) = key.pack.as_ref() {
+                *totals.entry(pack.as_str()).or_insert(0) += tracker.bytes[id] + tracker.regions[id];
+            }
+        }
+        let mut totals: Vec<_> = totals.into_iter()
+            .filter(|v| v.1 > 0)
+            .map(|(k, v)| (k.to_owned(), v))
+            .collect();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        totals
+    })
+}
+
+/// Formats the number of bytes for display
+pub fn format_bytes(bytes: u64) -> String {
+    const KIB: f64 = 1024.0;
+    let b = bytes as f64;
+    if b >= KIB * KIB * KIB {
+        format!("{:.2} GiB", b / (KIB * KIB * KIB))
+    } else if b >= KIB * KIB {
+        format!("{:.2} MiB", b / (KIB * KIB))
+    } else if b >= KIB {
+        format!("{:.1} KiB", b / KIB)
+    } else {
+        format!("{} B", bytes)
+    }
+}
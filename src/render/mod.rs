@@ -6,6 +6,7 @@
 #[allow(clippy::new_without_default)]
 #[allow(clippy::too_many_arguments)]
 pub mod gl;
+pub mod backend;
 mod atlas;
 mod model;
 mod terrain;
@@ -14,6 +15,10 @@ mod static_model;
 pub(crate) mod animated_model;
 pub(crate) mod ui;
 mod icons;
+pub mod palette;
+mod photo;
+mod picking;
+pub(crate) mod memory;
 #[macro_use]
 mod pipeline;
 
@@ -22,6 +27,7 @@ use crate::entity;
 use crate::server::level;
 use sdl2;
 use crate::server::assets;
+use crate::server::calendar;
 use crate::util::*;
 use cgmath::{self, Matrix4};
 use cgmath::prelude::*;
@@ -32,19 +38,50 @@ use crate::prelude::*;
 
 use ::model as exmodel;
 use self::pipeline::Pipeline;
+use self::backend::{Backend, Device};
 use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
+use std::mem;
+use std::sync::Arc;
 use std::borrow::Borrow;
 use sdl2::mouse::Cursor;
 
+pub use self::photo::{PhotoSettings, PhotoHidden, Photo};
+pub use self::terrain::SectionVisibility;
+
 const ATLAS_SIZE: i32 = 2048;
 /// The texture unit the global texture atlas will be bound to.
 const GLOBAL_TEXTURE_LOCATION: u32 = 5;
 /// The texture unit the shadow map will be bound to.
 pub const SHADOW_MAP_LOCATION: u32 = 4;
+/// The texture unit the baked lighting will be bound to.
+const LIGHT_MAP_LOCATION: u32 = 7;
 
 const MAX_ZOOM_OUT: f32 = 0.2;
 const MAX_ZOOM_IN: f32 = 2.5;
+// Photo mode allows the camera to move more freely
+const PHOTO_MAX_ZOOM_OUT: f32 = 0.1;
+const PHOTO_MAX_ZOOM_IN: f32 = 5.0;
+/// How far a single zoom action changes the zoom
+const ZOOM_STEP: f32 = 0.05;
+/// The fraction of the remaining zoom and rotation the
+/// camera covers each frame
+const CAMERA_SMOOTHING: f64 = 0.25;
+/// The distance in pixels from the edge of the window
+/// that edge scrolling starts at
+const EDGE_SCROLL_SIZE: i32 = 8;
+/// How far outside of the player's campus the camera
+/// can move
+const CAMPUS_BORDER: f32 = 16.0;
+/// The default pitch of the camera in degrees
+pub const DEFAULT_PITCH: f32 = -35.264;
+/// Occlusion culling is used when the camera is pitched
+/// closer to the ground than this in degrees. Steeper angles
+/// see over walls so little would be culled.
+const OCCLUSION_MAX_PITCH: f32 = 30.0;
+/// Occlusion culling is used when zoomed in at least this
+/// far as few sections are in view making the tests cheap
+const OCCLUSION_MIN_ZOOM: f32 = 1.5;
 
 type GlobalTextureMap = FNVMap<assets::ResourceKey<'static>, (i32, atlas::Rect)>;
 type LoadingTexture = Vec<(image::ImageFuture, i32, atlas::Rect)>;
@@ -57,7 +94,7 @@ pub struct Renderer {
     state: RenderState,
     pipeline: Pipeline<PassFlag>,
     ui_renderer: ui::Renderer,
-    _context: sdl2::video::GLContext,
+    backend: backend::Active,
 }
 
 impl Deref for Renderer {
@@ -86,6 +123,12 @@ pub struct RenderState {
     time: f64,
     /// Whether to use the paused effect
     pub paused: bool,
+    /// The current photo mode settings if photo mode is active
+    pub photo: Option<PhotoSettings>,
+    /// Screen effects played by the mission
+    pub screen_effects: ScreenEffects,
+    photo_lut: Option<(ResourceKey<'static>, Option<Rc<gl::Texture>>)>,
+    photo_requested: bool,
 
     /// The width of the screen
     pub width: u32,
@@ -116,6 +159,13 @@ pub struct RenderState {
     static_info: static_model::Info,
     pub(crate) animated_info: animated_model::Info,
     icons: icons::Icons,
+
+    // The entities drawn last frame by their pick id
+    picks: picking::PickTable,
+    picker: picking::Picker,
+    // The number of entities hidden by occlusion culling
+    // last frame
+    occluded_entities: usize,
 }
 
 struct Camera {
@@ -124,9 +174,33 @@ struct Camera {
     target: Option<(f32, f32, f64)>,
     /// Sets the rotation of the camera in degrees.
     rotation: cgmath::Deg<f32>,
+    /// The rotation the camera is animating towards
+    rotation_target: cgmath::Deg<f32>,
     /// Controls the zoom level of the camera
     zoom: f32,
+    /// The zoom level the camera is animating towards
+    zoom_target: f32,
+    /// The angle the camera looks down at the level
+    pitch: cgmath::Deg<f32>,
     movement: [bool; 4],
+    /// The area the camera is limited to, the whole level
+    /// if not set
+    bounds: Option<Bound>,
+    /// Whether the mouse is in the focused window, used
+    /// for edge scrolling
+    mouse_in_window: bool,
+}
+
+/// Effects applied on top of the whole scene
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScreenEffects {
+    /// The offset applied to the camera's position
+    pub shake: (f32, f32),
+    /// The density of the fog covering the scene, 0 being none
+    pub fog_density: f32,
+    /// The color blended over the scene and how much of it to
+    /// blend (the last component)
+    pub flash: (f32, f32, f32, f32),
 }
 
 struct GlobalAtlas {
@@ -134,6 +208,9 @@ struct GlobalAtlas {
     texture: gl::Texture,
     atlases: Vec<atlas::TextureAtlas>,
     loading_textures: LoadingTexture,
+    // Copies of images that can't be reloaded from the
+    // assets in case the context is lost
+    dynamic_images: FNVMap<assets::ResourceKey<'static>, image::Image>,
 }
 
 struct Selection {
@@ -157,45 +234,26 @@ impl Renderer {
     /// to the current thread. Only one renderer instance should exist at any given time
     /// due to the dependance on OpenGL which uses thread locals.
     pub fn new(log: &Logger, window: &sdl2::video::Window, asset_manager: assets::AssetManager, config: Rc<Config>) -> Option<Renderer> {
+        let kind = config.render_backend.get();
         let log = log.new(o!(
-            "gl_version" => "3.3 Core"
+            "backend" => kind.name()
         ));
         let video = window.subsystem();
-        let gl_context = match window.gl_create_context() {
+        let backend = match backend::Active::create(&log, window) {
             Ok(val) => val,
             Err(err) => {
-                error!(log, "Failed to create GL3.3 context"; "error" => %err);
+                error!(log, "Failed to create the render backend"; "error" => %err);
                 return None;
             }
         };
-        window.gl_make_current(&gl_context).expect("Could not set current context.");
-        gl::load_with(|s| video.gl_get_proc_address(s) as *const _);
-        info!(log, "Using renderer: OpenGL 3.3");
-
-        gl::enable(gl::Flag::DepthTest);
-        gl::enable(gl::Flag::CullFace);
-        gl::front_face(gl::Face::ClockWise);
-        gl::cull_face(gl::CullFace::Back);
-        gl::depth_func(gl::Func::GreaterOrEqual);
-        gl::clear_depth(0.0);
+        if backend.kind() != kind {
+            warn!(log, "Render backend isn't available, falling back"; "backend" => kind.name());
+        }
+        info!(log, "Using renderer: {}", backend.kind().name());
 
-        let texture = gl::Texture::new();
-        texture.bind(gl::TextureTarget::Texture2DArray);
-        texture.image_3d(
-            gl::TextureTarget::Texture2DArray, 0,
-            ATLAS_SIZE as u32, ATLAS_SIZE as u32, 1,
-            gl::TextureFormat::Srgba8, gl::TextureFormat::Rgba,
-            gl::Type::UnsignedByte,
-            None
-        );
-        texture.set_parameter::<gl::TextureMinFilter>(gl::TextureTarget::Texture2DArray, gl::TextureFilter::Nearest);
-        texture.set_parameter::<gl::TextureMagFilter>(gl::TextureTarget::Texture2DArray, gl::TextureFilter::Nearest);
-        texture.set_parameter::<gl::TextureWrapS>(gl::TextureTarget::Texture2DArray, gl::TextureWrap::ClampToEdge);
-        texture.set_parameter::<gl::TextureWrapT>(gl::TextureTarget::Texture2DArray, gl::TextureWrap::ClampToEdge);
-        texture.set_parameter::<gl::TextureBaseLevel>(gl::TextureTarget::Texture2DArray, 0);
-        texture.set_parameter::<gl::TextureMaxLevel>(gl::TextureTarget::Texture2DArray, 0);
+        let texture = Self::create_atlas_texture(1);
 
-        let mut pipeline = Self::build_pipeline(&log, &config, &asset_manager);
+        let mut pipeline = Self::build_pipeline(&log, &config, &asset_manager, backend.device());
 
         let static_info = static_model::Info::new(&log, &mut pipeline.context(), &asset_manager);
         let animated_info = animated_model::Info::new(&log, &asset_manager, &mut pipeline.context());
@@ -212,22 +270,12 @@ impl Renderer {
                 config,
                 time: 0.0,
                 paused: false,
+                photo: None,
+                screen_effects: ScreenEffects::default(),
+                photo_lut: None,
+                photo_requested: false,
 
-                cursor: model::Model::new(
-                    &mut pipeline.context(),
-                    "cursor",
-                    vec![
-                        model::Attribute {name: "attrib_position", count: 3, ty: gl::Type::Float, offset: 0, int: false},
-                    ],
-                    vec![
-                        cgmath::Vector3 {x: 0.0, y: 0.0, z: 0.0},
-                        cgmath::Vector3 {x: 0.0, y: 0.0, z: 1.0},
-                        cgmath::Vector3 {x: 1.0, y: 0.0, z: 0.0},
-                        cgmath::Vector3 {x: 1.0, y: 0.0, z: 0.0},
-                        cgmath::Vector3 {x: 0.0, y: 0.0, z: 1.0},
-                        cgmath::Vector3 {x: 1.0, y: 0.0, z: 1.0}
-                    ],
-                ),
+                cursor: Self::cursor_model(&mut pipeline.context()),
                 cursor_visible: false,
 
                 terrain: None,
@@ -238,8 +286,13 @@ impl Renderer {
                     y: 0.0,
                     target: None,
                     rotation: cgmath::Deg(0.0),
+                    rotation_target: cgmath::Deg(0.0),
                     zoom: 0.8,
+                    zoom_target: 0.8,
+                    pitch: cgmath::Deg(DEFAULT_PITCH),
                     movement: [false; 4],
+                    bounds: None,
+                    mouse_in_window: false,
                 },
                 shadow_rotation: cgmath::Deg(-90.0),
 
@@ -260,26 +313,157 @@ impl Renderer {
                     textures: FNVMap::default(),
                     atlases: vec![],
                     loading_textures: vec![],
+                    dynamic_images: FNVMap::default(),
                 },
 
                 static_info,
                 animated_info,
                 icons,
+
+                picks: Vec::new(),
+                occluded_entities: 0,
+                picker: picking::Picker::new(),
             },
-            _context: gl_context,
+            backend,
             pipeline,
         };
         renderer.set_mouse_sprite(ResourceKey::new("base", "ui/cursor/normal"));
         Some(renderer)
     }
 
+    /// Creates a context, preferring one that reports when it is lost
+    fn create_atlas_texture(layers: u32) -> gl::Texture {
+        let _memory = memory::scope(memory::Category::Textures, "Global atlas", None);
+        let texture = gl::Texture::new();
+        texture.bind(gl::TextureTarget::Texture2DArray);
+        texture.image_3d(
+            gl::TextureTarget::Texture2DArray, 0,
+            ATLAS_SIZE as u32, ATLAS_SIZE as u32, layers,
+            gl::TextureFormat::Srgba8, gl::TextureFormat::Rgba,
+            gl::Type::UnsignedByte,
+            None
+        );
+        texture.set_parameter::<gl::TextureMinFilter>(gl::TextureTarget::Texture2DArray, gl::TextureFilter::Nearest);
+        texture.set_parameter::<gl::TextureMagFilter>(gl::TextureTarget::Texture2DArray, gl::TextureFilter::Nearest);
+        texture.set_parameter::<gl::TextureWrapS>(gl::TextureTarget::Texture2DArray, gl::TextureWrap::ClampToEdge);
+        texture.set_parameter::<gl::TextureWrapT>(gl::TextureTarget::Texture2DArray, gl::TextureWrap::ClampToEdge);
+        texture.set_parameter::<gl::TextureBaseLevel>(gl::TextureTarget::Texture2DArray, 0);
+        texture.set_parameter::<gl::TextureMaxLevel>(gl::TextureTarget::Texture2DArray, 0);
+        texture
+    }
+
+    fn cursor_model(ctx: &mut pipeline::Context<'_>) -> model::Model<cgmath::Vector3<f32>> {
+        model::Model::new(
+            ctx,
+            "cursor",
+            vec![
+                model::Attribute {name: "attrib_position", count: 3, ty: gl::Type::Float, offset: 0, int: false},
+            ],
+            vec![
+                cgmath::Vector3 {x: 0.0, y: 0.0, z: 0.0},
+                cgmath::Vector3 {x: 0.0, y: 0.0, z: 1.0},
+                cgmath::Vector3 {x: 1.0, y: 0.0, z: 0.0},
+                cgmath::Vector3 {x: 1.0, y: 0.0, z: 0.0},
+                cgmath::Vector3 {x: 0.0, y: 0.0, z: 1.0},
+                cgmath::Vector3 {x: 1.0, y: 0.0, z: 1.0}
+            ],
+        )
+    }
+
+    /// Displays the rendered frame in the window
+    pub fn present(&self, window: &sdl2::video::Window) {
+        self.backend.present(window);
+    }
+
+    /// Checks whether the context has been lost (e.g. the driver
+    /// reset or the system switched GPUs) and recreates it if so.
+    ///
+    /// Everything stored on the GPU is recreated from the asset
+    /// manager or from copies kept by the renderer. Returns whether
+    /// the context was recreated.
+    pub fn recover_lost_context(&mut self, window: &sdl2::video::Window) -> bool {
+        let reason = if let Some(reason) = self.backend.lost() {
+            reason
+        } else {
+            return false;
+        };
+        warn!(self.log, "Render device lost, recreating"; "reason" => reason);
+        self.backend.recreate(&self.state.log, window)
+            .expect("Failed to recreate the render device");
+
+        self.reupload_textures();
+
+        self.pipeline = Self::build_pipeline(&self.state.log, &self.state.config, &self.state.asset_manager, self.backend.device());
+        let mut ctx = self.pipeline.context();
+        let state = &mut self.state;
+        state.static_info = static_model::Info::new(&state.log, &mut ctx, &state.asset_manager);
+        // The animation state is kept so that entities don't
+        // restart their animations
+        let mut animated_info = animated_model::Info::new(&state.log, &state.asset_manager, &mut ctx);
+        mem::swap(&mut animated_info.info, &mut state.animated_info.info);
+        state.animated_info = animated_info;
+        state.icons = icons::Icons::new(&state.log, &mut ctx);
+        state.picks.clear();
+        state.picker = picking::Picker::new();
+        self.ui_renderer.recreate(&mut ctx);
+
+        let model_matrix = state.cursor.model_matrix;
+        state.cursor = Self::cursor_model(&mut ctx);
+        state.cursor.model_matrix = model_matrix;
+        if let Some(selection) = state.selection.as_mut() {
+            selection.selection_model = Self::selection_model(&mut ctx, vec![]);
+            // Forces the selection to be rebuilt the next
+            // time it moves
+            selection.current = Location::new(-1, -1);
+        }
+        if let Some(terrain) = state.terrain.as_mut() {
+            terrain.recreate(&mut ctx);
+        }
+        state.photo_lut = None;
+        true
+    }
+
+    /// Re-uploads every texture in the global atlas to a new
+    /// texture keeping their positions so that anything referencing
+    /// them remains valid
+    fn reupload_textures(&mut self) {
+        let state = &mut self.state;
+        let atlas = &mut state.global_atlas;
+        atlas.texture = Self::create_atlas_texture(atlas.atlases.len().max(1) as u32);
+        atlas.loading_textures.clear();
+        for (key, &(idx, rect)) in &atlas.textures {
+            if key.module() == "dynamic" {
+                let img = atlas.dynamic_images.get(key)
+                    .map_or_else(|| image::Image {
+                        width: rect.width as u32,
+                        height: rect.height as u32,
+                        data: vec![0; (rect.width * rect.height * 4) as usize],
+                    }, |v| image::Image {
+                        width: v.width,
+                        height: v.height,
+                        data: v.data.clone(),
+                    });
+                RenderState::upload_texture(&atlas.texture, Some(img), idx, rect);
+                continue;
+            }
+            let img = assume!(state.log, state.asset_manager.loader_open::<image::Loader>(assets::TypedKey::from_key(key.borrow())));
+            let loaded_img = img.take_image();
+            let is_not_loaded = loaded_img.is_none();
+            RenderState::upload_texture(&atlas.texture, loaded_img, idx, rect);
+            if is_not_loaded {
+                atlas.loading_textures.push((img, idx, rect));
+            }
+        }
+    }
+
     /// Causes the renderer to rebuild the whole pipeline with the current settings
     pub fn rebuild_pipeline(&mut self) {
         self.pipeline.clear();
-        self.pipeline = Self::build_pipeline(&self.log, &self.config, &self.asset_manager);
+        let device = self.backend.device();
+        self.pipeline = Self::build_pipeline(&self.log, &self.config, &self.asset_manager, device);
     }
 
-    fn build_pipeline(log: &Logger, config: &Config, asset_manager: &AssetManager) -> Pipeline<PassFlag> {
+    fn build_pipeline(log: &Logger, config: &Config, asset_manager: &AssetManager, device: backend::ActiveDevice) -> Pipeline<PassFlag> {
         use cgmath::Vector3;
 
         // Flags
@@ -412,7 +596,7 @@ impl Renderer {
             ("attrib_position", 0)
         ];
 
-        Pipeline::<PassFlag>::new(log, asset_manager.clone(), render_scale)
+        Pipeline::<PassFlag>::new(log, asset_manager.clone(), device, render_scale)
             // UI Shaders
             .program("ui/clip", |_, p| p
                 .vertex("ui/clip_vert")
@@ -507,6 +691,10 @@ impl Renderer {
                 .vertex("fullscreen_vert")
                 .fragment("focused_effect")
                 .attribute_binds(fullscreen_attribs))
+            .program("photo_effect", |_, p| p
+                .vertex("fullscreen_vert")
+                .fragment("photo_effect")
+                .attribute_binds(fullscreen_attribs))
             .program("scale", |_, p| p
                 .enabled(render_scaling)
                 .vertex("fullscreen_vert")
@@ -614,6 +802,19 @@ impl Renderer {
                         ("attrib_texture", assume!(log, base.attribute("attrib_texture")).index()),
                     ])
             })
+            .program("terrain_pick", |c, p| {
+                let base = c.program("terrain");
+                p
+                    .vertex("terrain_vert")
+                    .vertex_defines(vec!["pick"])
+                    .fragment("terrain_frag")
+                    .fragment_defines(vec!["pick"])
+                    .attribute_binds(&[
+                        ("attrib_position", assume!(log, base.attribute("attrib_position")).index()),
+                        ("attrib_normal", assume!(log, base.attribute("attrib_normal")).index()),
+                        ("attrib_texture", assume!(log, base.attribute("attrib_texture")).index()),
+                    ])
+            })
             .program("static", |_, p| p
                 .vertex("static_vert")
                 .fragment("static_frag")
@@ -677,6 +878,23 @@ impl Renderer {
                         ("attrib_highlight", assume!(log, base.attribute("attrib_highlight")).index()),
                     ])
             })
+            .program("static_pick", |c, p| {
+                let base = c.program("static");
+                p
+                    .vertex("static_vert")
+                    .vertex_defines(vec!["pick"])
+                    .fragment("static_frag")
+                    .fragment_defines(vec!["pick"])
+                    .attribute_binds(&[
+                        ("attrib_position", assume!(log, base.attribute("attrib_position")).index()),
+                        ("attrib_normal", assume!(log, base.attribute("attrib_normal")).index()),
+                        ("attrib_uv", assume!(log, base.attribute("attrib_uv")).index()),
+                        ("attrib_matrix", assume!(log, base.attribute("attrib_matrix")).index()),
+                        ("attrib_tint", assume!(log, base.attribute("attrib_tint")).index()),
+                        ("attrib_highlight", assume!(log, base.attribute("attrib_highlight")).index()),
+                        ("attrib_pick", 9),
+                    ])
+            })
             .program("animated", |_, p| p
                 .vertex("animated_vert")
                 .fragment("animated_frag")
@@ -736,6 +954,27 @@ impl Renderer {
                         ("attrib_tint_offset", assume!(log, base.attribute("attrib_tint_offset")).index()),
                     ])
             })
+            .program("animated_pick", |c, p| {
+                let base = c.program("animated");
+                p
+                    .vertex("animated_vert")
+                    .vertex_defines(vec!["pick"])
+                    .fragment("animated_frag")
+                    .fragment_defines(vec!["pick"])
+                    .attribute_binds(&[
+                        ("attrib_position", assume!(log, base.attribute("attrib_position")).index()),
+                        ("attrib_normal", assume!(log, base.attribute("attrib_normal")).index()),
+                        ("attrib_uv", assume!(log, base.attribute("attrib_uv")).index()),
+                        ("attrib_bones", assume!(log, base.attribute("attrib_bones")).index()),
+                        ("attrib_bone_weights", assume!(log, base.attribute("attrib_bone_weights")).index()),
+                        ("attrib_matrix", assume!(log, base.attribute("attrib_matrix")).index()),
+                        ("attrib_tint", assume!(log, base.attribute("attrib_tint")).index()),
+                        ("attrib_highlight", assume!(log, base.attribute("attrib_highlight")).index()),
+                        ("attrib_bone_offset", assume!(log, base.attribute("attrib_bone_offset")).index()),
+                        ("attrib_tint_offset", assume!(log, base.attribute("attrib_tint_offset")).index()),
+                        ("attrib_pick", 13),
+                    ])
+            })
             .pass("shadow", |_c, p| p
                 .enabled(shadows_enabled)
                 .size(shadow_size, shadow_size)
@@ -890,7 +1129,13 @@ impl Renderer {
                             .cloned();
                         let shadow_view_matrix = ctx.var::<Matrix4<f32>>("shadow_view_matrix")
                             .cloned();
+                        let effects = ctx.var::<ScreenEffects>("screen_effects")
+                            .cloned()
+                            .unwrap_or_default();
                         let p = ctx.program("merge");
+                        p.uniform("fog_density").map(|v| v.set_float(effects.fog_density));
+                        let (r, g, b, a) = effects.flash;
+                        p.uniform("flash_color").map(|v| v.set_float4(r, g, b, a));
                         shadow_projection.map(|m|
                             p.uniform("shadow_projection").map(|v| v.set_matrix4(&m))
                         );
@@ -925,6 +1170,43 @@ impl Renderer {
                 )
                 .clear_flags(gl::BufferBit::COLOR)
                 .flag(PassFlag::empty()))
+            .pass("photo_effect",  |_, p| p
+                .final_color()
+                .runtime_enable("photo_mode")
+                .fullscreen(|f| f
+                    .shader("photo_effect")
+                    .input_final("g_color")
+                    .input("g_position", "base", "position")
+                    .pre(move |ctx| {
+                        let view_matrix_inv = ctx.var::<cgmath::Matrix4<f32>>("view_matrix")
+                            .and_then(|v| v.invert())
+                            .expect("Missing view matrix");
+                        let settings = ctx.var::<Option<PhotoSettings>>("photo")
+                            .and_then(|v| v.clone())
+                            .expect("Missing photo settings");
+                        let focus_center = *ctx.var::<(f32, f32)>("photo_focus")
+                            .expect("Missing photo focus");
+                        let lut = ctx.var::<Option<Rc<gl::Texture>>>("photo_lut")
+                            .and_then(|v| v.clone());
+                        let p = ctx.program("photo_effect");
+                        if let Some(lut) = lut {
+                            gl::active_texture(1);
+                            lut.bind(gl::TextureTarget::Texture2D);
+                            gl::active_texture(0);
+                            p.uniform("lut").map(|v| v.set_int(1));
+                            p.uniform("has_lut").map(|v| v.set_int(1));
+                        } else {
+                            p.uniform("has_lut").map(|v| v.set_int(0));
+                        }
+                        p.uniform("focus_center").map(|v| v.set_float2(focus_center.0, focus_center.1));
+                        p.uniform("focus_distance").map(|v| v.set_float(settings.focus_distance));
+                        p.uniform("blur").map(|v| v.set_float(settings.blur));
+                        p.uniform("exposure").map(|v| v.set_float(settings.exposure));
+                        p.uniform("view_matrix_inv").map(|v| v.set_matrix4(&view_matrix_inv));
+                    })
+                )
+                .clear_flags(gl::BufferBit::COLOR)
+                .flag(PassFlag::empty()))
             .pass("fxaa", |_, p| p
                 .enabled(fxaa_enabled)
                 .final_color()
@@ -979,6 +1261,11 @@ impl Renderer {
         self.ui_renderer.ui_scale
     }
 
+    /// Changes the scale of text in the ui
+    pub fn set_ui_text_scale(&mut self, scale: f32) {
+        self.ui_renderer.set_text_scale(scale);
+    }
+
     /// Replaces/creates the named image with the passed data
     pub fn update_image(&mut self,
         name: ResourceKey<'_>,
@@ -1004,20 +1291,127 @@ impl Renderer {
 
         // Move the camera if required
         self.camera_update(delta);
+        self.update_photo_lut();
+
+        self.draw_frame(&mut entities, delta, width, height, None);
+
+        gl::view_port(0, 0, width, height);
+
+        let input = self.video_system.text_input();
+        if !self.is_text_input && input.is_active() {
+            input.stop();
+            self.text_input_region = None;
+        }
+        self.is_text_input = false;
+    }
+
+    /// Renders the world at a larger size than the screen and
+    /// scales it back down to produce a higher quality image.
+    ///
+    /// The user interface isn't included.
+    pub fn take_photo(&mut self, mut entities: Option<&mut ecs::Container>, width: u32, height: u32) -> Photo {
+        self.photo_requested = false;
+        let scale = self.photo.as_ref()
+            .map_or(1, |v| v.supersample)
+            .max(1);
+        let (w, h) = (width * scale, height * scale);
+
+        let texture = gl::Texture::new();
+        texture.bind(gl::TextureTarget::Texture2D);
+        texture.image_2d_ex(
+            gl::TextureTarget::Texture2D, 0,
+            w, h,
+            gl::TextureFormat::Rgb8, gl::TextureFormat::Rgb,
+            gl::Type::UnsignedByte,
+            None
+        );
+        texture.set_parameter::<gl::TextureMinFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Nearest);
+        texture.set_parameter::<gl::TextureMagFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Nearest);
+        let framebuffer = gl::Framebuffer::new();
+        framebuffer.bind(gl::TargetFramebuffer::Both);
+        framebuffer.texture_2d(gl::TargetFramebuffer::Both, gl::Attachment::Color0, gl::TextureTarget::Texture2D, &texture, 0);
+
+        self.draw_frame(&mut entities, 0.0, w, h, Some(&framebuffer));
+
+        let mut buffer = vec![0; (w * h * 3) as usize];
+        framebuffer.bind(gl::TargetFramebuffer::Read);
+        gl::pixel_store(gl::PixelStore::PackAlignment, 1);
+        unsafe {
+            gl::read_pixels(0, 0, w as _, h as _, gl::TextureFormat::Rgb, gl::Type::UnsignedByte, &mut buffer);
+        }
+        gl::pixel_store(gl::PixelStore::PackAlignment, 4);
+        gl::Framebuffer::unbind(gl::TargetFramebuffer::Both);
+        gl::view_port(0, 0, width, height);
+
+        Photo::from_framebuffer(w, h, scale, &buffer)
+    }
+
+    /// Requests that a photo is taken at the end of the frame
+    pub fn request_photo(&mut self) {
+        self.photo_requested = true;
+    }
+
+    /// Returns the cpu time spent on each render pass during
+    /// the last frame
+    pub fn pass_times(&self) -> &[(&'static str, std::time::Duration)] {
+        self.pipeline.pass_times()
+    }
+
+    /// Returns whether a photo has been requested
+    pub fn photo_requested(&self) -> bool {
+        self.photo_requested
+    }
 
+    /// Returns whether the user interface should be hidden
+    pub fn hide_ui(&self) -> bool {
+        self.photo.as_ref().map_or(false, |v| v.hide_ui)
+    }
+
+    fn update_photo_lut(&mut self) {
+        let filter = self.state.photo.as_ref().and_then(|v| v.filter.as_ref());
+        if self.state.photo_lut.as_ref().map(|v| &v.0) == filter {
+            return;
+        }
+        let lut = filter.map(|key| (
+            key.clone(),
+            photo::load_lut(&self.state.log, &self.state.asset_manager, key.borrow()),
+        ));
+        self.state.photo_lut = lut;
+    }
+
+    fn draw_frame(&mut self,
+        entities: &mut Option<&mut ecs::Container>,
+        delta: f64,
+        width: u32, height: u32,
+        target: Option<&gl::Framebuffer>,
+    ) {
+        // Shaking only moves the view, picking still uses the
+        // steady camera so the cursor doesn't jitter
+        let (shake_x, shake_y) = self.screen_effects.shake;
         let view_matrix = RenderState::get_view_matrix(
-            self.camera.x, self.camera.y,
-            self.camera.zoom, self.camera.rotation,
+            self.camera.x + shake_x, self.camera.y + shake_y,
+            self.camera.zoom, self.camera.pitch, self.camera.rotation,
         );
         let projection = RenderState::get_projection_matrix (
             width, height, self.camera.zoom
         );
         let frustum = Frustum::from_matrix(projection * view_matrix);
+        let occlusion_view = if self.camera.pitch.0.abs() < OCCLUSION_MAX_PITCH || self.camera.zoom >= OCCLUSION_MIN_ZOOM {
+            // The camera looks along the negative z axis
+            view_matrix.invert()
+                .map(|v| v.transform_vector(cgmath::Vector3::new(0.0, 0.0, -1.0)).normalize())
+                .filter(|v| v.y > 0.0)
+        } else {
+            None
+        };
+        if let Some(ter) = self.terrain.as_mut() {
+            ter.set_occlusion_view(occlusion_view);
+        }
         let (tx, ty, ts) = self.terrain.as_ref()
             .map_or(Default::default(), |v| v.get_render_bounds(&frustum));
         let shadow_view_matrix = RenderState::get_view_matrix(
             tx, ty,
-            1.0, self.shadow_rotation,
+            1.0, cgmath::Deg(DEFAULT_PITCH), self.shadow_rotation,
         );
         let shadow_projection: cgmath::Matrix4<f32> = cgmath::ortho(
             (-ts * 0.5) * 70.0,
@@ -1032,14 +1426,20 @@ impl Renderer {
 
         gl::Program::unbind();
         gl::Framebuffer::unbind(gl::TargetFramebuffer::Both);
+        if let Some(target) = target {
+            target.bind(gl::TargetFramebuffer::Both);
+        }
 
         gl::view_port(0, 0, width, height);
         gl::clear_color(0.0, 0.0, 0.0, 1.0);
         gl::clear(gl::BufferBit::COLOR | gl::BufferBit::DEPTH);
 
+        let hidden = self.photo.as_ref()
+            .map_or(PhotoHidden::empty(), |v| v.hidden);
+
         // Entity frame building
         if let Some(entities) = entities.as_mut() {
-            self.compute_entities(entities, &frustum, delta);
+            self.compute_entities(entities, &frustum, hidden, delta);
         }
         {
             self.time += delta;
@@ -1050,9 +1450,19 @@ impl Renderer {
             let paused = self.paused;
             let state = &mut self.state;
             let focused_area = state.focused_region;
+            let photo = state.photo.clone();
+            let photo_lut = state.photo_lut.as_ref()
+                .and_then(|v| v.1.clone());
+            let screen_effects = state.screen_effects;
             self.pipeline
                 .begin_draw()
+                .target(target)
                 .var("paused", paused)
+                .var("screen_effects", screen_effects)
+                .var("photo_mode", photo.is_some())
+                .var("photo", photo)
+                .var("photo_focus", (state.camera.x, state.camera.y))
+                .var("photo_lut", photo_lut)
                 .var("time", time)
                 .var("focused", focused_area.is_some())
                 .var("focused_area", focused_area)
@@ -1067,22 +1477,69 @@ impl Renderer {
                 .pass_var("base", "projection", projection)
                 .pass_var("base", "shadow_view_matrix", shadow_view_matrix)
                 .pass_var("base", "shadow_projection", shadow_projection)
-                .draw(width, height, |ctx, flag| state.draw(ctx, *flag, &mut entities, delta));
+                .draw(width, height, |ctx, flag| state.draw(ctx, *flag, entities, delta));
 
-            if let Some(entities) = entities.as_mut() {
+            if let Some(entities) = entities.as_mut().filter(|_| !hidden.contains(PhotoHidden::ICONS)) {
+                if let Some(target) = target {
+                    target.bind(gl::TargetFramebuffer::Draw);
+                }
                 gl::clear(gl::BufferBit::DEPTH);
                 state.draw_icons(&mut self.pipeline.context(), &projection, &view_matrix, entities);
             }
         }
 
-        gl::view_port(0, 0, width, height);
+    }
 
-        let input = self.video_system.text_input();
-        if !self.is_text_input && input.is_active() {
-            input.stop();
-            self.text_input_region = None;
+    /// Returns the entity drawn at the position on the screen during
+    /// the last frame.
+    ///
+    /// Returns `None` if nothing was drawn there or if gpu picking is
+    /// disabled, in which case `gpu_picking` returns `false` and the
+    /// caller should fall back to testing bounds instead.
+    pub fn pick_entity(&mut self, x: i32, y: i32) -> Option<ecs::Entity> {
+        if !self.gpu_picking() || self.state.picks.is_empty() {
+            return None;
         }
-        self.is_text_input = false;
+        let view_matrix = RenderState::get_view_matrix(
+            self.camera.x, self.camera.y,
+            self.camera.zoom, self.camera.pitch, self.camera.rotation,
+        );
+        let projection = picking::pick_matrix(x, y, self.width, self.height)
+            * RenderState::get_projection_matrix(self.width, self.height, self.camera.zoom);
+        let frustum = Frustum::from_matrix(projection * view_matrix);
+        // The shadow matrices aren't used by the pick programs but the
+        // model renderers expect them to be provided
+        let identity: Matrix4<f32> = Matrix4::identity();
+
+        let mut replacements = FNVMap::default();
+        replacements.insert("static", "static_pick");
+        replacements.insert("static_water", "static_pick");
+        replacements.insert("animated", "animated_pick");
+        replacements.insert("terrain", "terrain_pick");
+        replacements.insert("terrain_selection", "terrain_pick");
+
+        gl::active_texture(GLOBAL_TEXTURE_LOCATION);
+        self.state.global_atlas.texture.bind(gl::TextureTarget::Texture2DArray);
+        gl::Program::unbind();
+
+        let state = &mut self.state;
+        state.picker.begin();
+        {
+            let mut ctx = self.pipeline.context_replacing(&replacements);
+            if let Some(ter) = state.terrain.as_mut() {
+                ter.draw(&mut ctx, &frustum, &projection, &view_matrix, None, None);
+            }
+            state.render_entities(&mut ctx, &projection, &view_matrix, Some(&identity), Some(&identity), PassFlag::empty());
+        }
+        let id = state.picker.finish();
+        gl::view_port(0, 0, state.width, state.height);
+
+        id.and_then(|v| state.picks.get(v).cloned())
+    }
+
+    /// Returns whether entities are picked using the gpu
+    pub fn gpu_picking(&self) -> bool {
+        self.config.render_gpu_picking.get()
     }
 
     /// Marks a region as taking text input this frame.
@@ -1105,6 +1562,8 @@ impl Renderer {
         self.terrain = None;
         self.terrain = Some(terrain::Terrain::new(&self.state.log, &self.state.asset_manager, level, &mut self.pipeline.context()));
         self.camera.zoom = 0.8;
+        self.camera.zoom_target = 0.8;
+        self.camera.bounds = None;
     }
 
     /// Removes the level currently being rendered by this renderer
@@ -1115,7 +1574,10 @@ impl Renderer {
     /// Updates the internal state of the level for the renderer.
     pub fn update_level(&mut self, level: &mut level::Level) {
         if let Some(t) = self.state.terrain.as_mut() {
-            t.update(&mut self.pipeline.context(), &mut self.state.global_atlas, level);
+            t.update(
+                &mut self.pipeline.context(), &mut self.state.global_atlas, level,
+                self.state.config.render_baked_lighting.get(),
+            );
         }
     }
 
@@ -1134,6 +1596,55 @@ impl Renderer {
             t.lowered_region = Some(region);
         }
     }
+    /// Sets the stage of the dirt drawn on each of the tiles,
+    /// zero removing it
+    pub fn set_dirt<I>(&mut self, tiles: I)
+        where I: IntoIterator<Item=(Location, u8)>
+    {
+        if let Some(t) = self.state.terrain.as_mut() {
+            for (loc, stage) in tiles {
+                t.set_dirt(loc, stage);
+            }
+        }
+    }
+    /// Sets the pieces of litter drawn on each of the tiles,
+    /// zero removing it
+    pub fn set_litter<I>(&mut self, tiles: I)
+        where I: IntoIterator<Item=(Location, u8)>
+    {
+        if let Some(t) = self.state.terrain.as_mut() {
+            for (loc, pieces) in tiles {
+                t.set_litter(loc, pieces);
+            }
+        }
+    }
+    /// Sets the stage of construction drawn over the room's
+    /// area, `None` once it has been built
+    pub fn set_construction(&mut self, room: level::room::Id, site: Option<(Bound, u8)>) {
+        if let Some(t) = self.state.terrain.as_mut() {
+            t.set_construction(room, site);
+        }
+    }
+    /// Sets the season outdoor tiles are drawn with
+    pub fn set_season(&mut self, variants: &Arc<calendar::SeasonVariants>, blend: calendar::SeasonBlend) {
+        if let Some(t) = self.state.terrain.as_mut() {
+            t.set_season(variants, blend);
+        }
+    }
+    /// Returns the number of terrain sections along each axis
+    /// and whether each was drawn last frame
+    pub fn section_visibility(&self) -> Option<(usize, usize, Vec<SectionVisibility>)> {
+        self.state.terrain.as_ref().map(|v| v.section_visibility())
+    }
+    /// Returns whether occlusion culling was used last frame
+    pub fn is_occlusion_culling(&self) -> bool {
+        self.state.terrain.as_ref().map_or(false, |v| v.occlusion().is_some())
+    }
+    /// Returns the number of entities hidden by occlusion
+    /// culling last frame
+    pub fn occluded_entities(&self) -> usize {
+        self.state.occluded_entities
+    }
     /// Gets the currently lowered region if any
     pub fn get_lowered_region(&self) -> Option<Bound> {
         if let Some(t) = self.state.terrain.as_ref() {
@@ -1159,25 +1670,29 @@ impl Renderer {
         let verts = RenderState::gen_selection_verts(&mut self.state.global_atlas, assume!(self.state.log, self.state.terrain.as_mut()), level, room, bound, owner_id);
         self.selection = Some(Selection {
             cycle: 0.0,
-            selection_model: model::Model::new(
-                &mut self.pipeline.context(),
-                "terrain_selection",
-                vec![
-                    model::Attribute{name: "attrib_position", count: 3, ty: gl::Type::Float, offset: 0, int: false},
-                    model::Attribute{name: "attrib_normal", count: 3, ty: gl::Type::Float, offset: 12, int: false},
-                    model::Attribute{name: "attrib_texture", count: 4, ty: gl::Type::Float, offset: 24, int: false},
-                ],
-                verts
-            ),
-                start: Location::new(x, y),
-                current: Location::new(x, y),
+            selection_model: Self::selection_model(&mut self.pipeline.context(), verts),
+            start: Location::new(x, y),
+            current: Location::new(x, y),
         });
-        let mdl = self.selection.as_mut().expect("Missing selection after setting");
-        mdl.selection_model.uniforms.insert("u_cycle", model::UniformValue::Float(0.0));
-        mdl.selection_model.uniforms.insert("u_textures", model::UniformValue::Int(GLOBAL_TEXTURE_LOCATION as i32));
-        mdl.selection_model.uniforms.insert("shadow_map", model::UniformValue::Int(SHADOW_MAP_LOCATION as i32));
-        mdl.selection_model.uniforms.insert("shadow_matrix", model::UniformValue::Matrix4(cgmath::SquareMatrix::identity()));
-        mdl.selection_model.uniforms.insert("shadow_projection", model::UniformValue::Matrix4(cgmath::SquareMatrix::identity()));
+    }
+
+    fn selection_model(ctx: &mut pipeline::Context<'_>, verts: Vec<terrain::GLVertex>) -> model::Model<terrain::GLVertex> {
+        let mut model = model::Model::new(
+            ctx,
+            "terrain_selection",
+            vec![
+                model::Attribute{name: "attrib_position", count: 3, ty: gl::Type::Float, offset: 0, int: false},
+                model::Attribute{name: "attrib_normal", count: 3, ty: gl::Type::Float, offset: 12, int: false},
+                model::Attribute{name: "attrib_texture", count: 4, ty: gl::Type::Float, offset: 24, int: false},
+            ],
+            verts
+        );
+        model.uniforms.insert("u_cycle", model::UniformValue::Float(0.0));
+        model.uniforms.insert("u_textures", model::UniformValue::Int(GLOBAL_TEXTURE_LOCATION as i32));
+        model.uniforms.insert("shadow_map", model::UniformValue::Int(SHADOW_MAP_LOCATION as i32));
+        model.uniforms.insert("shadow_matrix", model::UniformValue::Matrix4(cgmath::SquareMatrix::identity()));
+        model.uniforms.insert("shadow_projection", model::UniformValue::Matrix4(cgmath::SquareMatrix::identity()));
+        model
     }
 
     /// Ends the selection area and stops drawing it.
@@ -1193,21 +1708,7 @@ impl Renderer {
                 selection.current = pos;
                 let bound = Bound::new(selection.start, selection.current);
                 let verts = RenderState::gen_selection_verts(&mut self.state.global_atlas, assume!(self.state.log, self.state.terrain.as_mut()), level, room, bound, owner_id);
-                selection.selection_model = model::Model::new(
-                    &mut self.pipeline.context(),
-                    "terrain_selection",
-                    vec![
-                        model::Attribute{name: "attrib_position", count: 3, ty: gl::Type::Float, offset: 0, int: false},
-                        model::Attribute{name: "attrib_normal", count: 3, ty: gl::Type::Float, offset: 12, int: false},
-                        model::Attribute{name: "attrib_texture", count: 4, ty: gl::Type::Float, offset: 24, int: false},
-                    ],
-                    verts
-                );
-                selection.selection_model.uniforms.insert("u_cycle", model::UniformValue::Float(0.0));
-                selection.selection_model.uniforms.insert("u_textures", model::UniformValue::Int(GLOBAL_TEXTURE_LOCATION as i32));
-                selection.selection_model.uniforms.insert("shadow_map", model::UniformValue::Int(SHADOW_MAP_LOCATION as i32));
-                selection.selection_model.uniforms.insert("shadow_matrix", model::UniformValue::Matrix4(cgmath::SquareMatrix::identity()));
-                selection.selection_model.uniforms.insert("shadow_projection", model::UniformValue::Matrix4(cgmath::SquareMatrix::identity()));
+                selection.selection_model = Self::selection_model(&mut self.pipeline.context(), verts);
             }
         }
     }
@@ -1287,23 +1788,24 @@ impl RenderState {
     pub fn handle_key_action(&mut self, action: keybinds::KeyAction) {
         use crate::keybinds::KeyAction::*;
         match action {
-            RenderZoomOut => {
-                self.camera.zoom -= 0.05;
-                if self.camera.zoom <= MAX_ZOOM_OUT {
-                    self.camera.zoom = MAX_ZOOM_OUT;
-                }
-            },
+            RenderZoomOut |
             RenderZoomIn => {
-                self.camera.zoom += 0.05;
-                if self.camera.zoom >= MAX_ZOOM_IN {
-                    self.camera.zoom = MAX_ZOOM_IN;
-                }
+                let (min, max) = self.zoom_limits();
+                let zoom_in = (action == RenderZoomIn) != self.config.camera_invert_zoom.get();
+                let step = ZOOM_STEP * self.config.camera_zoom_speed.get();
+                let target = self.camera.zoom_target + if zoom_in { step } else { -step };
+                self.camera.zoom_target = target.max(min).min(max);
             },
             RenderRotateLeft => {
-                self.camera.rotation -= cgmath::Deg(45.0);
+                self.camera.rotation_target = Self::snap_rotation(self.camera.rotation_target) - cgmath::Deg(45.0);
             },
             RenderRotateRight => {
-                self.camera.rotation += cgmath::Deg(45.0);
+                self.camera.rotation_target = Self::snap_rotation(self.camera.rotation_target) + cgmath::Deg(45.0);
+            },
+            RenderCameraFreeRoam => {
+                let free_roam = !self.config.camera_free_roam.get();
+                self.config.camera_free_roam.set(free_roam);
+                assume!(self.log, self.config.save());
             },
             RenderCameraDown |
             RenderCameraUp |
@@ -1322,9 +1824,53 @@ impl RenderState {
         }
     }
 
+    /// Rounds the rotation to the nearest 45 degrees
+    fn snap_rotation(rotation: cgmath::Deg<f32>) -> cgmath::Deg<f32> {
+        cgmath::Deg((rotation.0 / 45.0).round() * 45.0)
+    }
+
+    /// Moves the zoom and rotation of the camera towards
+    /// their targets
+    fn camera_animate(&mut self, delta: f64) {
+        let amount = (CAMERA_SMOOTHING * delta).min(1.0) as f32;
+
+        let zoom = self.camera.zoom;
+        if self.camera.zoom_target != zoom {
+            let anchor = if self.config.camera_zoom_to_cursor.get() && self.camera.mouse_in_window {
+                Some(self.mouse_to_level(self.mouse_pos.0, self.mouse_pos.1))
+            } else {
+                None
+            };
+            if (self.camera.zoom_target - zoom).abs() < 0.001 {
+                self.camera.zoom = self.camera.zoom_target;
+            } else {
+                self.camera.zoom += (self.camera.zoom_target - zoom) * amount;
+            }
+            if let Some((px, py)) = anchor {
+                // Keep the point under the cursor in place. The
+                // view scales linearly with the zoom so the offset
+                // from the camera scales inversely.
+                let scale = zoom / self.camera.zoom;
+                self.camera.x = px - (px - self.camera.x) * scale;
+                self.camera.y = py - (py - self.camera.y) * scale;
+                self.camera.target = None;
+                self.clamp_camera();
+            }
+        }
+
+        let rotation = self.camera.rotation_target - self.camera.rotation;
+        if rotation.0.abs() < 0.01 {
+            self.camera.rotation = self.camera.rotation_target;
+        } else {
+            self.camera.rotation += rotation * amount;
+        }
+    }
+
     fn camera_update(&mut self, delta: f64) {
         use crate::keybinds::KeyAction::*;
 
+        self.camera_animate(delta);
+
         if let Some(mut tar) = self.camera.target.take() {
             tar.2 -= delta;
             if tar.2 > 0.0 {
@@ -1344,19 +1890,32 @@ impl RenderState {
         let mut dx = 0.0;
         let mut dy = 0.0;
 
-        const SPEED: f64 = 0.12;
+        let speed = 0.12 * f64::from(self.config.camera_pan_speed.get());
+
+        let edge_scroll = self.config.camera_edge_scroll.get()
+            && self.camera.mouse_in_window
+            && self.photo.is_none();
+        let (mx, my) = self.mouse_pos;
 
-        if self.camera.movement[Self::keyaction_to_dir(RenderCameraDown)] {
-            dy -= SPEED;
+        if self.camera.movement[Self::keyaction_to_dir(RenderCameraDown)]
+            || (edge_scroll && my >= self.height as i32 - EDGE_SCROLL_SIZE)
+        {
+            dy -= speed;
         }
-        if self.camera.movement[Self::keyaction_to_dir(RenderCameraUp)] {
-            dy += SPEED;
+        if self.camera.movement[Self::keyaction_to_dir(RenderCameraUp)]
+            || (edge_scroll && my < EDGE_SCROLL_SIZE)
+        {
+            dy += speed;
         }
-        if self.camera.movement[Self::keyaction_to_dir(RenderCameraLeft)] {
-            dx += SPEED;
+        if self.camera.movement[Self::keyaction_to_dir(RenderCameraLeft)]
+            || (edge_scroll && mx < EDGE_SCROLL_SIZE)
+        {
+            dx += speed;
         }
-        if self.camera.movement[Self::keyaction_to_dir(RenderCameraRight)] {
-            dx -= SPEED;
+        if self.camera.movement[Self::keyaction_to_dir(RenderCameraRight)]
+            || (edge_scroll && mx >= self.width as i32 - EDGE_SCROLL_SIZE)
+        {
+            dx -= speed;
         }
         self.move_camera((dx * delta) as f32, (dy * delta) as f32);
     }
@@ -1378,7 +1937,7 @@ impl RenderState {
     }
 
     /// Requests that the render starts loading the texture now
-    pub fn preload_texture(&mut self, tex: assets::ResourceKey<'_>) {
+    pub fn preload_texture(&mut self, tex: assets::TypedKey<'_, assets::TextureAsset>) {
         Self::texture_info_for(
             &self.log,
             &self.asset_manager,
@@ -1390,15 +1949,19 @@ impl RenderState {
     fn texture_info_for(
         log: &Logger,
         asset_manager: &assets::AssetManager,
-        atlas: &mut GlobalAtlas, tex: assets::ResourceKey<'_>,
+        atlas: &mut GlobalAtlas, tex: assets::TypedKey<'_, assets::TextureAsset>,
     ) -> (i32, atlas::Rect) {
-        if let Some(info) = atlas.textures.get(&tex) {
+        if let Some(info) = atlas.textures.get(&tex.key()) {
             return *info;
         }
 
         let img = assume!(log, asset_manager.loader_open::<image::Loader>(tex.borrow()));
         let info = Self::place_texture_atlas(atlas, img);
-        atlas.textures.insert(tex.into_owned(), info);
+        memory::attribute(
+            memory::Category::Textures, "Global atlas", tex.module(),
+            (info.1.width * info.1.height * 4) as u64,
+        );
+        atlas.textures.insert(tex.into_key().into_owned(), info);
         info
     }
 
@@ -1502,6 +2065,7 @@ trait EntityRender<'a> {
         model_key: assets::ResourceKey<'_>,
         texture: Option<assets::ResourceKey<'_>>,
         ents: &[ecs::Entity],
+        picks: &mut picking::PickTable,
         delta: f64,
     );
 
@@ -1540,32 +2104,42 @@ impl RenderState {
         &mut self,
         entities: &mut ecs::Container,
         frustum: &Frustum,
+        hidden: PhotoHidden,
         delta: f64,
     ) {
+        self.picks.clear();
+        let occlusion = self.terrain.as_ref().and_then(|v| v.occlusion());
         // Static models
         self.static_info.clear();
-        Self::compute_entities_for(
+        let mut occluded = Self::compute_entities_for(
             &self.log,
             &mut self.static_info,
             &self.asset_manager,
             &self.config,
             &mut self.global_atlas,
+            &mut self.picks,
             entities,
             frustum,
+            occlusion,
+            hidden,
             delta,
         );
         // Animated models
         self.animated_info.clear();
-        Self::compute_entities_for(
+        occluded += Self::compute_entities_for(
             &self.log,
             &mut self.animated_info,
             &self.asset_manager,
             &self.config,
             &mut self.global_atlas,
+            &mut self.picks,
             entities,
             frustum,
+            occlusion,
+            hidden,
             delta,
         );
+        self.occluded_entities = occluded;
 
         entities.with(|
             em: EntityManager<'_>,
@@ -1591,7 +2165,7 @@ impl RenderState {
                     let other_model = if let Some(m) = self.animated_info.gl_models.get(&key) {
                         m
                     } else { continue };
-                    if let Some(dyn_offset) = other_model.entity_map.get(&attachment.target).cloned() {
+                    if let Some(dyn_offset) = other_model.entity_map.get(attachment.target).cloned() {
                         let r#dyn = &other_model.dyn_info[dyn_offset];
                         let bone_id = if let Some(id) = model.bones.get(&attachment.bone) {
                             id
@@ -1615,7 +2189,7 @@ impl RenderState {
                     let model = if let Some(m) = self.static_info.models.get_mut(&ModelKeyBorrow(model.name.borrow(), tex)) {
                         m
                     } else { continue };
-                    if let Some(r#dyn) = model.entity_map.get(&e).cloned() {
+                    if let Some(r#dyn) = model.entity_map.get(e).cloned() {
                         {
                             let r#dyn = &mut model.dyn_info[r#dyn];
                             r#dyn.matrix = base
@@ -1639,10 +2213,13 @@ impl RenderState {
         asset_manager: &assets::AssetManager,
         config: &Config,
         global_atlas: &mut GlobalAtlas,
+        picks: &mut picking::PickTable,
         entities: &mut ecs::Container,
         frustum: &Frustum,
+        occlusion: Option<(&terrain::OcclusionMap, cgmath::Vector3<f32>)>,
+        hidden: PhotoHidden,
         delta: f64,
-    )
+    ) -> usize
         where ER: for<'a> EntityRender<'a>
     {
         use crate::entity::{Model, ModelTexture};
@@ -1654,6 +2231,10 @@ impl RenderState {
             size: Read<Size>,
             model: Read<Model>,
             model_tex: Read<ModelTexture>,
+            living: Read<Living>,
+            paid: Read<Paid>,
+            object: Read<Object>,
+            attachment: Read<entity::AttachedTo>,
             params: ER::Params,
         | {
             let mask = component.mask()
@@ -1662,8 +2243,18 @@ impl RenderState {
 
             let mut ents = em.iter_mask(&mask)
                 .collect::<SmallVec<[_; 32]>>();
+            let mut occluded = 0;
 
             ents.retain(|v| {
+                if !hidden.is_empty() {
+                    // Attachments are hidden along with the entity
+                    // they are attached to
+                    let target = attachment.get_component(*v)
+                        .map_or(*v, |a| a.target);
+                    if hidden.hides(target, &living, &paid, &object) {
+                        return false;
+                    }
+                }
                 let pos = assume!(log, pos.get_component(*v));
                 let pos = cgmath::Vector3::new(
                     pos.x,
@@ -1676,10 +2267,19 @@ impl RenderState {
                         size.height / 2.0 + 1.5,
                         size.depth / 2.0 + 1.5
                     );
-                    frustum.contains_aabb(
-                        pos - dims,
-                        pos + dims,
-                    )
+                    if !frustum.contains_aabb(pos - dims, pos + dims) {
+                        return false;
+                    }
+                    if let Some((map, view)) = occlusion {
+                        // Models can be slightly larger than their size
+                        let min = cgmath::Vector3::new(pos.x - size.width / 2.0 - 0.25, pos.y, pos.z - size.depth / 2.0 - 0.25);
+                        let max = cgmath::Vector3::new(pos.x + size.width / 2.0 + 0.25, pos.y + size.height + 0.1, pos.z + size.depth / 2.0 + 0.25);
+                        if map.is_box_hidden(min, max, view) {
+                            occluded += 1;
+                            return false;
+                        }
+                    }
+                    true
                 } else {
                     frustum.contains_sphere(pos, 1.5)
                 }
@@ -1720,10 +2320,12 @@ impl RenderState {
                     model_key,
                     tex.map(|v| v.name.borrow()),
                     eq,
+                    picks,
                     delta
                 );
             }
-        });
+            occluded
+        })
     }
 }
 
@@ -1738,7 +2340,7 @@ impl RenderState {
         let view_matrix = Self::get_projection_matrix(self.width, self.height, self.camera.zoom)
             * Self::get_view_matrix(
                 self.camera.x, self.camera.y,
-                self.camera.zoom, self.camera.rotation,
+                self.camera.zoom, self.camera.pitch, self.camera.rotation,
             );
         let view_matrix = assume!(self.log, view_matrix.invert());
 
@@ -1793,10 +2395,10 @@ impl RenderState {
         cgmath::ortho(-w, w, -h, h, -h * 2.0 * scale, h * 2.0 * scale)
     }
 
-    fn get_view_matrix(x: f32, y: f32, zoom: f32, rotation: cgmath::Deg<f32>) -> Matrix4<f32> {
+    fn get_view_matrix(x: f32, y: f32, zoom: f32, pitch: cgmath::Deg<f32>, rotation: cgmath::Deg<f32>) -> Matrix4<f32> {
         use cgmath::{Basis3, Matrix3, Deg, Vector3};
         Matrix4::from_scale(75.0 * zoom)
-            * Matrix4::from(Matrix3::from(Basis3::from_angle_x(Deg(-35.264f32))))
+            * Matrix4::from(Matrix3::from(Basis3::from_angle_x(pitch)))
             * Matrix4::from(Matrix3::from(Basis3::from_angle_y(Deg(-45.0f32) + rotation)))
             * Matrix4::from_translation(Vector3::new(
                 -x,
@@ -1816,7 +2418,7 @@ impl RenderState {
         use sdl2::pixels::PixelFormatEnum;
         if self.mouse_sprite != sprite {
             let mut img = assume!(self.log, assume!(self.log, self.asset_manager.loader_open::<image::Loader>(
-                sprite.clone()
+                assets::TypedKey::from_key(sprite.clone())
             )).wait_take_image());
 
             let sur = assume!(self.log, Surface::from_data(&mut img.data, img.width, img.height, 4 * img.width, PixelFormatEnum::ABGR8888));
@@ -1838,6 +2440,65 @@ impl RenderState {
         (self.camera.rotation, self.camera.zoom)
     }
 
+    /// Sets the rotation and zoom of the camera
+    pub fn set_camera_info(&mut self, rotation: cgmath::Deg<f32>, zoom: f32) {
+        let (min, max) = self.zoom_limits();
+        self.camera.rotation = rotation;
+        self.camera.rotation_target = rotation;
+        self.camera.zoom = zoom.max(min).min(max);
+        self.camera.zoom_target = self.camera.zoom;
+    }
+
+    /// Limits the camera to the area, or the whole level
+    /// if `None`
+    pub fn set_camera_bounds(&mut self, bounds: Option<Bound>) {
+        self.camera.bounds = bounds;
+    }
+
+    /// Sets whether the mouse is within the focused window
+    pub fn set_mouse_in_window(&mut self, in_window: bool) {
+        self.camera.mouse_in_window = in_window;
+    }
+
+    /// Moves the camera as the mouse is dragged by the
+    /// passed number of pixels
+    pub fn drag_camera(&mut self, x: i32, y: i32) {
+        let speed = 25.0 * self.config.camera_pan_speed.get()
+            * if self.config.camera_invert_pan.get() { -1.0 } else { 1.0 };
+        let x = (x as f32 / self.width as f32) * speed;
+        let y = (y as f32 / self.height as f32) * speed;
+        self.move_camera(x, y);
+    }
+
+    /// Returns the pitch of the camera
+    pub fn get_camera_pitch(&self) -> cgmath::Deg<f32> {
+        self.camera.pitch
+    }
+
+    /// Sets the pitch of the camera.
+    ///
+    /// Only used by photo mode, the normal view
+    /// uses `DEFAULT_PITCH`
+    pub fn set_camera_pitch(&mut self, pitch: cgmath::Deg<f32>) {
+        self.camera.pitch = pitch;
+    }
+
+    /// Returns the min and max zoom of the camera.
+    ///
+    /// Photo mode allows for a larger range
+    fn zoom_limits(&self) -> (f32, f32) {
+        if self.photo.is_some() {
+            (PHOTO_MAX_ZOOM_OUT, PHOTO_MAX_ZOOM_IN)
+        } else {
+            (MAX_ZOOM_OUT, MAX_ZOOM_IN)
+        }
+    }
+
+    /// Returns the colour palette currently in use
+    pub fn colour_palette(&self) -> palette::ColourPalette {
+        self.config.colour_palette.get()
+    }
+
     /// Sets the position of the camera.
     pub fn set_camera(&mut self, x: f32, y: f32) {
         self.camera.x = x;
@@ -1859,14 +2520,32 @@ impl RenderState {
         let s = ang.sin();
         self.camera.x -= len * s;
         self.camera.y -= len * c;
-        if let Some(terrain) = self.terrain.as_ref() {
-            self.camera.x = self.camera.x.min(terrain.width as f32 - 48.0).max(48.0);
-            self.camera.y = self.camera.y.min(terrain.height as f32 - 48.0).max(48.0);
-        }
+        self.clamp_camera();
         // Override any automatic movement going on
         self.camera.target = None;
     }
 
+    /// Keeps the camera within the level and, outside of
+    /// photo mode, the camera's bounds
+    fn clamp_camera(&mut self) {
+        if let Some(terrain) = self.terrain.as_ref() {
+            // Photo mode allows the camera to reach the edge of the level
+            let border = if self.photo.is_some() { 0.0 } else { 48.0 };
+            let mut min_x = border;
+            let mut min_y = border;
+            let mut max_x = terrain.width as f32 - border;
+            let mut max_y = terrain.height as f32 - border;
+            if let (Some(bounds), None) = (self.camera.bounds, self.photo.as_ref()) {
+                min_x = min_x.max(bounds.min.x as f32 - CAMPUS_BORDER);
+                min_y = min_y.max(bounds.min.y as f32 - CAMPUS_BORDER);
+                max_x = max_x.min((bounds.max.x + 1) as f32 + CAMPUS_BORDER).max(min_x);
+                max_y = max_y.min((bounds.max.y + 1) as f32 + CAMPUS_BORDER).max(min_y);
+            }
+            self.camera.x = self.camera.x.min(max_x).max(min_x);
+            self.camera.y = self.camera.y.min(max_y).max(min_y);
+        }
+    }
+
     fn gen_selection_verts<'a>(
         target_atlas: &mut GlobalAtlas,
         terrain: &mut terrain::Terrain, level: &mut level::Level,
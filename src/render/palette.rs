@@ -0,0 +1,155 @@
+//! Colour palettes used by the renderer.
+//!
+//! Colours that carry meaning (placement validity, highlights,
+//! player colours, status icons) should be looked up through the
+//! selected palette instead of being hard-coded so that colour
+//! blind players can tell them apart.
+
+/// The colour palette the renderer uses for colours that carry
+/// meaning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColourPalette {
+    /// The default palette
+    Standard,
+    /// Adjusted for green-weak vision
+    Deuteranopia,
+    /// Adjusted for red-weak vision
+    Protanopia,
+    /// Adjusted for blue-weak vision
+    Tritanopia,
+}
+
+/// Player colours for the standard palette, the same as the
+/// colours players pick between in the lobby
+const STANDARD_PLAYERS: &[(u8, u8, u8)] = crate::server::player::PLAYER_COLORS;
+
+/// Player colours for the colour blind palettes.
+///
+/// Based on the Okabe-Ito palette which remains distinct
+/// for all common forms of colour blindness.
+const SAFE_PLAYERS: &[(u8, u8, u8)] = &[
+    (0, 114, 178),
+    (230, 159, 0),
+    (86, 180, 233),
+    (0, 158, 115),
+    (240, 228, 66),
+    (213, 94, 0),
+    (204, 121, 167),
+    (128, 128, 128),
+];
+
+impl ColourPalette {
+    /// All palettes in the order they are displayed in the options menu
+    pub const ALL: &'static [ColourPalette] = &[
+        ColourPalette::Standard,
+        ColourPalette::Deuteranopia,
+        ColourPalette::Protanopia,
+        ColourPalette::Tritanopia,
+    ];
+
+    /// Returns the name used to store this palette in the config
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ColourPalette::Standard => "standard",
+            ColourPalette::Deuteranopia => "deuteranopia",
+            ColourPalette::Protanopia => "protanopia",
+            ColourPalette::Tritanopia => "tritanopia",
+        }
+    }
+
+    /// Parses the palette from its config name, falling back
+    /// to the standard palette if unknown
+    pub fn from_str(v: &str) -> ColourPalette {
+        match v {
+            "deuteranopia" => ColourPalette::Deuteranopia,
+            "protanopia" => ColourPalette::Protanopia,
+            "tritanopia" => ColourPalette::Tritanopia,
+            _ => ColourPalette::Standard,
+        }
+    }
+
+    /// The default colour of the placement grid when valid
+    pub fn placement_valid(self) -> (u8, u8, u8) {
+        match self {
+            ColourPalette::Standard => (46, 65, 114),
+            ColourPalette::Deuteranopia
+            | ColourPalette::Protanopia => (0, 114, 178),
+            ColourPalette::Tritanopia => (0, 158, 115),
+        }
+    }
+
+    /// The default colour of the placement grid when invalid
+    pub fn placement_invalid(self) -> (u8, u8, u8) {
+        match self {
+            ColourPalette::Standard => (170, 57, 57),
+            ColourPalette::Deuteranopia
+            | ColourPalette::Protanopia => (230, 159, 0),
+            ColourPalette::Tritanopia => (213, 94, 0),
+        }
+    }
+
+    /// The colour used to highlight objects in build modes
+    pub fn object_highlight(self) -> (u8, u8, u8) {
+        match self {
+            ColourPalette::Standard => (0, 255, 255),
+            ColourPalette::Deuteranopia
+            | ColourPalette::Protanopia
+            | ColourPalette::Tritanopia => (255, 255, 255),
+        }
+    }
+
+    /// Returns the colour for the player colour at the given index
+    pub fn player_colour(self, idx: usize) -> (u8, u8, u8) {
+        let colours = if self == ColourPalette::Standard {
+            STANDARD_PLAYERS
+        } else {
+            SAFE_PLAYERS
+        };
+        colours[idx % colours.len()]
+    }
+
+    /// Adjusts an arbitrary colour (e.g. one provided by an
+    /// asset pack) so that it remains distinguishable with
+    /// this palette's vision type.
+    ///
+    /// Uses daltonization: the information lost when simulating
+    /// the colour deficiency is shifted into the channels that
+    /// can still be seen.
+    pub fn correct(self, col: (u8, u8, u8)) -> (u8, u8, u8) {
+        let sim: [[f32; 3]; 3] = match self {
+            ColourPalette::Standard => return col,
+            ColourPalette::Deuteranopia => [
+                [0.625, 0.375, 0.0],
+                [0.7, 0.3, 0.0],
+                [0.0, 0.3, 0.7],
+            ],
+            ColourPalette::Protanopia => [
+                [0.567, 0.433, 0.0],
+                [0.558, 0.442, 0.0],
+                [0.0, 0.242, 0.758],
+            ],
+            ColourPalette::Tritanopia => [
+                [0.95, 0.05, 0.0],
+                [0.0, 0.433, 0.567],
+                [0.0, 0.475, 0.525],
+            ],
+        };
+        let orig = [
+            f32::from(col.0) / 255.0,
+            f32::from(col.1) / 255.0,
+            f32::from(col.2) / 255.0,
+        ];
+        let mut err = [0.0; 3];
+        for ((e, row), o) in err.iter_mut().zip(&sim).zip(&orig) {
+            let simulated = row[0] * orig[0] + row[1] * orig[1] + row[2] * orig[2];
+            *e = o - simulated;
+        }
+        let shifted = [
+            orig[0],
+            orig[1] + 0.7 * err[0] + err[1],
+            orig[2] + 0.7 * err[0] + err[2],
+        ];
+        let to_u8 = |v: f32| (v.max(0.0).min(1.0) * 255.0).round() as u8;
+        (to_u8(shifted[0]), to_u8(shifted[1]), to_u8(shifted[2]))
+    }
+}
@@ -0,0 +1,159 @@
+//! Settings and helpers used by photo mode
+
+use super::gl;
+use super::image;
+use crate::server::assets;
+use crate::prelude::*;
+
+use std::rc::Rc;
+
+bitflags! {
+    /// Categories of things that can be hidden whilst
+    /// taking a photo
+    pub struct PhotoHidden: u8 {
+        const STUDENTS = 0b0000_0001;
+        const STAFF    = 0b0000_0010;
+        const OBJECTS  = 0b0000_0100;
+        const ICONS    = 0b0000_1000;
+    }
+}
+
+impl PhotoHidden {
+    /// Returns whether the entity belongs to one of the
+    /// hidden categories
+    pub(super) fn hides(
+        self, e: Entity,
+        living: &Read<Living>, paid: &Read<Paid>, object: &Read<Object>,
+    ) -> bool {
+        if living.get_component(e).is_some() {
+            if paid.get_component(e).is_some() {
+                self.contains(PhotoHidden::STAFF)
+            } else {
+                self.contains(PhotoHidden::STUDENTS)
+            }
+        } else if object.get_component(e).is_some() {
+            self.contains(PhotoHidden::OBJECTS)
+        } else {
+            false
+        }
+    }
+}
+
+/// Controls how the world is rendered whilst in photo mode
+#[derive(Clone, Debug)]
+pub struct PhotoSettings {
+    /// The distance (in tiles) from the center of the screen
+    /// that remains in focus
+    pub focus_distance: f32,
+    /// The strength of the depth of field blur.
+    ///
+    /// Zero disables the blur
+    pub blur: f32,
+    /// The exposure adjustment in stops
+    pub exposure: f32,
+    /// The color lookup table used to filter the image
+    pub filter: Option<ResourceKey<'static>>,
+    /// Categories of entities that won't be rendered
+    pub hidden: PhotoHidden,
+    /// Whether the user interface should be hidden
+    pub hide_ui: bool,
+    /// The number of times larger than the screen a
+    /// capture is rendered at before being scaled down
+    pub supersample: u32,
+}
+
+impl Default for PhotoSettings {
+    fn default() -> PhotoSettings {
+        PhotoSettings {
+            focus_distance: 8.0,
+            blur: 0.0,
+            exposure: 0.0,
+            filter: None,
+            hidden: PhotoHidden::empty(),
+            hide_ui: false,
+            supersample: 2,
+        }
+    }
+}
+
+/// A captured photo
+pub struct Photo {
+    /// The width of the photo in pixels
+    pub width: u32,
+    /// The height of the photo in pixels
+    pub height: u32,
+    /// The RGB pixels of the photo starting from the
+    /// top left
+    pub data: Vec<u8>,
+}
+
+impl Photo {
+    /// Creates a photo from pixels read from the framebuffer,
+    /// averaging each `scale` by `scale` block into a single
+    /// pixel.
+    pub(super) fn from_framebuffer(width: u32, height: u32, scale: u32, buffer: &[u8]) -> Photo {
+        let out_w = width / scale;
+        let out_h = height / scale;
+        let samples = scale * scale;
+        let mut data = vec![0; (out_w * out_h * 3) as usize];
+        for y in 0 .. out_h {
+            for x in 0 .. out_w {
+                let mut total = [0u32; 3];
+                for sy in 0 .. scale {
+                    for sx in 0 .. scale {
+                        let i_x = x * scale + sx;
+                        // The framebuffer starts at the bottom left
+                        let i_y = height - 1 - (y * scale + sy);
+                        let i_idx = ((i_x + i_y * width) * 3) as usize;
+                        for (t, v) in total.iter_mut().zip(&buffer[i_idx .. i_idx + 3]) {
+                            *t += u32::from(*v);
+                        }
+                    }
+                }
+                let o_idx = ((x + y * out_w) * 3) as usize;
+                for (o, t) in data[o_idx .. o_idx + 3].iter_mut().zip(&total) {
+                    *o = (t / samples) as u8;
+                }
+            }
+        }
+        Photo {
+            width: out_w,
+            height: out_h,
+            data,
+        }
+    }
+}
+
+/// Loads the color lookup table used for a filter.
+///
+/// Lookup tables are stored as a strip of 16 16x16 slices
+/// with the blue channel selecting the slice.
+pub(super) fn load_lut(log: &Logger, asset_manager: &assets::AssetManager, key: ResourceKey<'_>) -> Option<Rc<gl::Texture>> {
+    let img = match asset_manager.loader_open::<image::Loader>(assets::TypedKey::from_key(key.borrow()))
+        .map_err(crate::errors::Error::from)
+        .and_then(|v| v.wait_take_image())
+    {
+        Ok(val) => val,
+        Err(err) => {
+            warn!(log, "Failed to load the filter {:?}: {}", key, err);
+            return None;
+        }
+    };
+
+    let texture = gl::Texture::new();
+    texture.bind(gl::TextureTarget::Texture2D);
+    texture.image_2d_ex(
+        gl::TextureTarget::Texture2D, 0,
+        img.width, img.height,
+        gl::TextureFormat::Rgba8, gl::TextureFormat::Rgba,
+        gl::Type::UnsignedByte,
+        Some(&img.data)
+    );
+    texture.set_parameter::<gl::TextureMinFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Linear);
+    texture.set_parameter::<gl::TextureMagFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Linear);
+    texture.set_parameter::<gl::TextureWrapS>(gl::TextureTarget::Texture2D, gl::TextureWrap::ClampToEdge);
+    texture.set_parameter::<gl::TextureWrapT>(gl::TextureTarget::Texture2D, gl::TextureWrap::ClampToEdge);
+    texture.set_parameter::<gl::TextureBaseLevel>(gl::TextureTarget::Texture2D, 0);
+    texture.set_parameter::<gl::TextureMaxLevel>(gl::TextureTarget::Texture2D, 0);
+    Some(Rc::new(texture))
+}
@@ -0,0 +1,108 @@
+//! Pixel accurate picking of the entity under the cursor.
+//!
+//! Every model instance rendered in a frame is given an id which
+//! the `pick` variants of the model shaders write out instead of
+//! a colour. Picking redraws just the pixel under the cursor into a
+//! tiny offscreen target, reusing the instance data built for the
+//! last frame, and reads the id back. Unlike testing a ray against
+//! bounding boxes this follows the real shape of skinned models and
+//! picks the model that is actually in front when they overlap.
+
+use super::{gl, memory};
+use crate::ecs;
+use cgmath::{self, Matrix4};
+
+/// The size of the target rendered into when picking
+const PICK_SIZE: u32 = 1;
+
+/// The entities rendered this frame indexed by their pick id
+pub(super) type PickTable = Vec<ecs::Entity>;
+
+pub(super) struct Picker {
+    framebuffer: gl::Framebuffer,
+    ids: gl::Texture,
+    _depth: gl::Texture,
+}
+
+impl Picker {
+    pub(super) fn new() -> Picker {
+        let _memory = memory::scope(memory::Category::Pipeline, "Picking", None);
+        let framebuffer = gl::Framebuffer::new();
+        framebuffer.bind(gl::TargetFramebuffer::Both);
+
+        let ids = gl::Texture::new();
+        ids.bind(gl::TextureTarget::Texture2D);
+        ids.image_2d_ex(
+            gl::TextureTarget::Texture2D, 0,
+            PICK_SIZE, PICK_SIZE,
+            gl::TextureFormat::Rgba8, gl::TextureFormat::Rgba,
+            gl::Type::UnsignedByte,
+            None
+        );
+        ids.set_parameter::<gl::TextureMinFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Nearest);
+        ids.set_parameter::<gl::TextureMagFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Nearest);
+        framebuffer.texture_2d(gl::TargetFramebuffer::Both, gl::Attachment::Color0, gl::TextureTarget::Texture2D, &ids, 0);
+
+        let depth = gl::Texture::new();
+        depth.bind(gl::TextureTarget::Texture2D);
+        depth.image_2d_ex(
+            gl::TextureTarget::Texture2D, 0,
+            PICK_SIZE, PICK_SIZE,
+            gl::TextureFormat::DepthComponent24, gl::TextureFormat::DepthComponent,
+            gl::Type::UnsignedInt,
+            None
+        );
+        depth.set_parameter::<gl::TextureMinFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Nearest);
+        depth.set_parameter::<gl::TextureMagFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Nearest);
+        framebuffer.texture_2d(gl::TargetFramebuffer::Both, gl::Attachment::Depth, gl::TextureTarget::Texture2D, &depth, 0);
+
+        gl::Framebuffer::unbind(gl::TargetFramebuffer::Both);
+        Picker {
+            framebuffer,
+            ids,
+            _depth: depth,
+        }
+    }
+
+    /// Binds and clears the pick target ready to be drawn to
+    pub(super) fn begin(&self) {
+        self.framebuffer.bind(gl::TargetFramebuffer::Draw);
+        gl::draw_buffers(&[gl::Attachment::Color0]);
+        gl::view_port(0, 0, PICK_SIZE, PICK_SIZE);
+        gl::clear_buffer(gl::TargetBuffer::Color, 0, &[0.0, 0.0, 0.0, 0.0]);
+        gl::clear_buffer(gl::TargetBuffer::Depth, 0, &[0.0]);
+    }
+
+    /// Reads back the id drawn to the pick target
+    pub(super) fn finish(&self) -> Option<usize> {
+        gl::Framebuffer::unbind(gl::TargetFramebuffer::Draw);
+        let mut pixel = [0; (PICK_SIZE * PICK_SIZE * 4) as usize];
+        self.ids.bind(gl::TextureTarget::Texture2D);
+        self.ids.get_data(gl::TextureTarget::Texture2D, 0, gl::TextureFormat::Rgba, gl::Type::UnsignedByte, &mut pixel);
+        decode_id([pixel[0], pixel[1], pixel[2], pixel[3]])
+    }
+}
+
+/// Returns a matrix that, applied after the projection, stretches
+/// the pixel at the position to fill the pick target
+pub(super) fn pick_matrix(x: i32, y: i32, width: u32, height: u32) -> Matrix4<f32> {
+    let cx = ((x as f32 + 0.5) / width as f32) * 2.0 - 1.0;
+    let cy = -(((y as f32 + 0.5) / height as f32) * 2.0 - 1.0);
+    Matrix4::from_nonuniform_scale(width as f32 / PICK_SIZE as f32, height as f32 / PICK_SIZE as f32, 1.0)
+        * Matrix4::from_translation(cgmath::Vector3::new(-cx, -cy, 0.0))
+}
+
+/// Encodes the index into the pick table as a colour. Zero
+/// is used for pixels without an entity
+pub(super) fn encode_id(idx: usize) -> (u8, u8, u8, u8) {
+    let id = idx + 1;
+    (id as u8, (id >> 8) as u8, (id >> 16) as u8, 255)
+}
+
+/// Decodes the colour back into an index into the pick table
+pub(super) fn decode_id(pixel: [u8; 4]) -> Option<usize> {
+    let id = usize::from(pixel[0])
+        | (usize::from(pixel[1]) << 8)
+        | (usize::from(pixel[2]) << 16);
+    id.checked_sub(1)
+}
@@ -2,14 +2,18 @@
 
 use crate::prelude::*;
 use super::gl;
+use super::memory;
+use super::backend::{ActiveDevice, Device};
 use std::any::Any;
 use std::cell::Cell;
 use std::borrow::Cow;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 pub struct Pipeline<Flag> {
     log: Logger,
     assets: AssetManager,
+    device: ActiveDevice,
     global_scale: f32,
 
     programs: FNVMap<&'static str, Program>,
@@ -17,6 +21,11 @@ pub struct Pipeline<Flag> {
     attachments: Vec<InternalAttachment>,
     final_color_a: InternalAttachment,
     final_color_b: InternalAttachment,
+    /// The time spent submitting each pass during the last draw.
+    ///
+    /// This is cpu time only, the gpu may still be working on
+    /// the pass afterwards
+    pass_times: Vec<(&'static str, Duration)>,
 
     quad_vao: gl::VertexArray,
     _quad_buffer: gl::Buffer,
@@ -25,6 +34,7 @@ pub struct Pipeline<Flag> {
 pub struct PipelineBuilder<Flag> {
     log: Logger,
     assets: AssetManager,
+    device: ActiveDevice,
     global_scale: f32,
 
     programs: FNVMap<&'static str, Program>,
@@ -36,13 +46,12 @@ pub struct PipelineBuilder<Flag> {
 
 impl <Flag> Pipeline<Flag> {
     #[allow(clippy::new_ret_no_self)]
-    pub fn new(log: &Logger, assets: AssetManager, global_scale: f32) -> PipelineBuilder<Flag> {
+    pub fn new(log: &Logger, assets: AssetManager, device: ActiveDevice, global_scale: f32) -> PipelineBuilder<Flag> {
         let log = log.new(o!("source" => "pipeline"));
         let vao = gl::VertexArray::new();
         vao.bind();
-        let buf = gl::Buffer::new();
-        buf.bind(gl::BufferTarget::Array);
-        buf.set_data(gl::BufferTarget::Array, &[
+        let buf = device.create_buffer();
+        device.buffer_data(&buf, gl::BufferTarget::Array, &[
             -1.0f32, 1.0,
             1.0, 1.0,
             1.0, -1.0,
@@ -59,6 +68,7 @@ impl <Flag> Pipeline<Flag> {
         PipelineBuilder {
             log,
             assets,
+            device,
             global_scale,
             programs: FNVMap::default(),
             passes: Vec::new(),
@@ -77,6 +87,17 @@ impl <Flag> Pipeline<Flag> {
         }
     }
 
+    /// Returns a context that swaps programs like a pass would
+    /// for drawing outside of the pipeline's passes
+    pub fn context_replacing<'b>(&'b mut self, replacements: &'b FNVMap<&'static str, &'static str>) -> Context<'b> {
+        Context {
+            log: &self.log,
+            programs: &mut self.programs,
+            vars: FNVMap::default(),
+            replacements: Some(replacements),
+        }
+    }
+
     pub fn get_program(&mut self, name: &'static str) -> &mut Program {
         assume!(self.log, self.programs.get_mut(name))
     }
@@ -86,9 +107,15 @@ impl <Flag> Pipeline<Flag> {
             pipeline: self,
             vars: FNVMap::default(),
             pass_vars: FNVMap::default(),
+            target: None,
         }
     }
 
+    /// Returns the time spent on each pass during the last draw
+    pub fn pass_times(&self) -> &[(&'static str, Duration)] {
+        &self.pass_times
+    }
+
     /// Clears most of the pipeline's resources
     pub fn clear(&mut self) {
         self.programs.clear();
@@ -101,6 +128,7 @@ impl <Flag> PipelineBuilder<Flag>
 {
 
     pub fn build(self) -> Pipeline<Flag> {
+        let _memory = memory::scope(memory::Category::Pipeline, "Render targets", None);
         let mut attachments: Vec<(usize, AInfo, InternalAttachment)> = vec![];
         let mut passes = vec![];
 
@@ -319,6 +347,7 @@ impl <Flag> PipelineBuilder<Flag>
         Pipeline {
             log: self.log,
             assets: self.assets,
+            device: self.device,
             global_scale: self.global_scale,
             programs: self.programs,
             passes,
@@ -327,6 +356,7 @@ impl <Flag> PipelineBuilder<Flag>
                 .collect(),
             final_color_a: final_color_a,
             final_color_b: final_color_b,
+            pass_times: Vec::new(),
             quad_vao: self.quad_vao,
             _quad_buffer: self._quad_buffer,
         }
@@ -355,12 +385,7 @@ impl <Flag> PipelineBuilder<Flag>
         if !builder.enabled {
             return self;
         }
-        let program = gl::Program::new();
-
-        let vertex_shader = gl::Shader::new(gl::ShaderType::Vertex);
-        // Ensure the version number goes first otherwise the
-        // the shader wouldn't compile when we add defines.
-        let mut vert_full = String::from("#version 330 core\n");
+        let mut vert_full = String::new();
         if let Some(defines) = builder.vertex_defines {
             for def in defines {
                 vert_full.push_str("#define ");
@@ -370,12 +395,8 @@ impl <Flag> PipelineBuilder<Flag>
         }
         // Load the shader from the file
         vert_full.push_str(&assume!(self.log, load_shader(&self.assets, builder.vertex)));
-        vertex_shader.set_source(&vert_full);
-        assume!(self.log, vertex_shader.compile());
 
-        let frag_shader = gl::Shader::new(gl::ShaderType::Fragment);
-        // See above
-        let mut frag_full = String::from("#version 330 core\n");
+        let mut frag_full = String::new();
         if let Some(defines) = builder.fragment_defines {
             for def in defines {
                 frag_full.push_str("#define ");
@@ -383,21 +404,12 @@ impl <Flag> PipelineBuilder<Flag>
                 frag_full.push_str("\n");
             }
         }
-        // Load the shader from the file
         frag_full.push_str(&assume!(self.log, load_shader(&self.assets, builder.fragment)));
-        frag_shader.set_source(&frag_full);
-        assume!(self.log, frag_shader.compile());
 
-        program.attach_shader(vertex_shader);
-        program.attach_shader(frag_shader);
-
-        let mut attributes = FNVMap::default();
-        for (k, v) in builder.attribute_binds  {
-            program.bind_attribute_location(k, v);
-            attributes.insert(k, gl::Attribute::new(v));
-        }
-
-        program.link();
+        let program = assume!(self.log, self.device.compile_program(&vert_full, &frag_full, &builder.attribute_binds));
+        let attributes = builder.attribute_binds.iter()
+            .map(|&(k, v)| (k, gl::Attribute::new(v)))
+            .collect();
         program.use_program();
 
         self.programs.insert(name, Program {
@@ -436,6 +448,7 @@ pub struct DrawSetup<'a, Flag> {
     pipeline: &'a mut Pipeline<Flag>,
     vars: FNVMap<&'static str, Box<dyn Any>>,
     pass_vars: FNVMap<&'static str, FNVMap<&'static str, Box<dyn Any>>>,
+    target: Option<&'a gl::Framebuffer>,
 }
 
 impl <'a, Flag> DrawSetup<'a, Flag> {
@@ -453,6 +466,13 @@ impl <'a, Flag> DrawSetup<'a, Flag> {
         self
     }
 
+    /// Draws the final pass into the passed framebuffer instead
+    /// of the screen if set
+    pub fn target(mut self, target: Option<&'a gl::Framebuffer>) -> Self {
+        self.target = target;
+        self
+    }
+
     pub fn draw<F>(mut self, width: u32, height: u32, mut f: F)
         where F: FnMut(&mut Context<'_>, &Flag)
     {
@@ -480,7 +500,9 @@ impl <'a, Flag> DrawSetup<'a, Flag> {
         let mut current_final = 0;
 
         let mut buffers = vec![];
+        self.pipeline.pass_times.clear();
         for (idx, pass) in passes.into_iter().enumerate() {
+            let pass_start = Instant::now();
             if let Some(p_vars) = self.pass_vars.remove(pass.name) {
                 for (k, v) in p_vars {
                     ctx.vars.insert(k, v);
@@ -553,30 +575,35 @@ impl <'a, Flag> DrawSetup<'a, Flag> {
             }
 
             if pass.final_color && idx == num_passes - 1 {
-                gl::Framebuffer::unbind(gl::TargetFramebuffer::Draw);
+                if let Some(target) = self.target {
+                    target.bind(gl::TargetFramebuffer::Draw);
+                    gl::draw_buffers(&[gl::Attachment::Color0]);
+                } else {
+                    gl::Framebuffer::unbind(gl::TargetFramebuffer::Draw);
+                }
             }
 
             if let Some((width, height)) = pass.size {
-                gl::view_port(
+                self.pipeline.device.viewport(
                     0, 0,
                     width / (pass.scale as u32),
                     height / (pass.scale as u32)
                 );
             } else if !pass.attachments.is_empty() {
-                gl::view_port(
+                self.pipeline.device.viewport(
                     0, 0,
                     ((width / (pass.scale as u32)) as f32 * self.pipeline.global_scale) as u32,
                     ((height / (pass.scale as u32)) as f32 * self.pipeline.global_scale) as u32
                 );
             } else {
-                gl::view_port(
+                self.pipeline.device.viewport(
                     0, 0,
                     width / (pass.scale as u32),
                     height / (pass.scale as u32)
                 );
             }
             if let Some(flags) = pass.clear_flags {
-                gl::clear(flags);
+                self.pipeline.device.clear(flags);
             }
             if let Some(fullscreen) = pass.fullscreen.as_ref() {
                 {
@@ -621,10 +648,11 @@ impl <'a, Flag> DrawSetup<'a, Flag> {
                 }
 
                 self.pipeline.quad_vao.bind();
-                gl::draw_arrays(gl::DrawType::Triangles, 0, 6);
+                self.pipeline.device.draw_arrays(gl::DrawType::Triangles, 0, 6);
             } else {
                 f(&mut ctx, &pass.flag);
             }
+            self.pipeline.pass_times.push((pass.name, pass_start.elapsed()));
         }
         gl::Framebuffer::unbind(gl::TargetFramebuffer::Draw);
     }
@@ -1,7 +1,7 @@
 
 use cgmath;
 
-use super::{gl, exmodel, atlas, pipeline, PassFlag, ModelKey, ModelKeyBorrow};
+use super::{gl, exmodel, atlas, pipeline, memory, picking, PassFlag, ModelKey, ModelKeyBorrow};
 use crate::util::FNVMap;
 use crate::server::assets;
 use crate::ecs;
@@ -21,6 +21,7 @@ pub struct Info {
     attrib_matrix: gl::Attribute,
     attrib_tint: gl::Attribute,
     attrib_highlight: gl::Attribute,
+    attrib_pick: gl::Attribute,
 }
 
 pub(super) struct Model {
@@ -38,7 +39,7 @@ pub(super) struct Model {
     index_ty: gl::Type,
     pub(super) matrix_buffer: gl::Buffer,
 
-    pub(super) entity_map: FNVMap<ecs::Entity, usize>,
+    pub(super) entity_map: ecs::EntityMap<usize>,
     max_count: usize,
     pub(super) dyn_info: Vec<DynInfo>,
 }
@@ -48,6 +49,7 @@ pub(super) struct DynInfo {
     pub(super) matrix: cgmath::Matrix4<f32>,
     tint: (u8, u8, u8, u8),
     highlight: (u8, u8, u8, u8),
+    pick: (u8, u8, u8, u8),
 }
 
 impl Info {
@@ -68,8 +70,9 @@ impl Info {
                 assume!(log, program.attribute("attrib_highlight")),
             )
         };
+        let attrib_pick = assume!(log, ctx.program("static_pick").attribute("attrib_pick"));
 
-        let img = assume!(log, asset_manager.loader_open::<image::Loader>(ResourceKey::new("base", "models/garden/water_normal")));
+        let img = assume!(log, asset_manager.loader_open::<image::Loader>(assets::TypedKey::new("base", "models/garden/water_normal")));
         let img = assume!(log, img.wait_take_image());
         let mut normal_data = Vec::with_capacity((img.width * img.height * 3) as usize);
         for d in img.data.chunks_exact(4) {
@@ -78,7 +81,10 @@ impl Info {
             normal_data.push(d[2]);
         }
 
-        let water_normal = gl::Texture::new();
+        let water_normal = {
+            let _memory = memory::scope(memory::Category::Textures, "Water", None);
+            gl::Texture::new()
+        };
         water_normal.bind(gl::TextureTarget::Texture2D);
         water_normal.image_2d_any(gl::TextureTarget::Texture2D, 0, img.width, img.height, gl::TextureFormat::Rgb8, gl::TextureFormat::Rgb, gl::Type::UnsignedByte, Some(&normal_data));
         water_normal.set_parameter::<gl::TextureMinFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Linear);
@@ -98,6 +104,7 @@ impl Info {
             attrib_matrix,
             attrib_tint,
             attrib_highlight,
+            attrib_pick,
         }
     }
 }
@@ -133,6 +140,7 @@ impl <'a> super::EntityRender<'a> for Info {
         model_key: assets::ResourceKey<'_>,
         texture: Option<assets::ResourceKey<'_>>,
         ents: &[ecs::Entity],
+        picks: &mut picking::PickTable,
         delta: f64,
     ) {
         use std::collections::hash_map::Entry;
@@ -148,7 +156,8 @@ impl <'a> super::EntityRender<'a> for Info {
         let model = match self.models.entry(key) {
             Entry::Occupied(val) => val.into_mut(),
             Entry::Vacant(val) => {
-                let mut file = assume!(self.log, asset_manager.open_from_pack(val.key().0.module_key(), &format!("models/{}.umod", val.key().0.resource())));
+                let _memory = memory::scope(memory::Category::Models, "Static models", Some(val.key().0.module()));
+                let mut file = assume!(self.log, assets::TypedKey::<assets::ModelAsset>::from_key(val.key().0.borrow()).open(asset_manager));
                 let minfo = assume!(self.log, exmodel::Model::read_from(&mut file));
 
                 let array = gl::VertexArray::new();
@@ -194,7 +203,9 @@ impl <'a> super::EntityRender<'a> for Info {
                 self.attrib_highlight.enable();
                 self.attrib_highlight.vertex_pointer(4, gl::Type::UnsignedByte, true, mem::size_of::<DynInfo>() as i32, 4 * 4 * 4 + 4);
                 self.attrib_highlight.divisor(1);
-
+                self.attrib_pick.enable();
+                self.attrib_pick.vertex_pointer(4, gl::Type::UnsignedByte, true, mem::size_of::<DynInfo>() as i32, 4 * 4 * 4 + 4 + 4);
+                self.attrib_pick.divisor(1);
 
                 let texture = {
                     let tex = texture.unwrap_or_else(|| assets::LazyResourceKey::parse(&minfo.texture)
@@ -203,7 +214,7 @@ impl <'a> super::EntityRender<'a> for Info {
                         &self.log,
                         asset_manager,
                         global_atlas,
-                        tex
+                        assets::TypedKey::from_key(tex)
                     )
                 };
 
@@ -223,7 +234,7 @@ impl <'a> super::EntityRender<'a> for Info {
                     index_ty,
                     matrix_buffer,
                     max_count: 0,
-                    entity_map: FNVMap::default(),
+                    entity_map: ecs::EntityMap::new(),
                     dyn_info: vec![],
                 })
             }
@@ -238,6 +249,7 @@ impl <'a> super::EntityRender<'a> for Info {
         let log = &self.log;
 
         let selection_invalid = config.placement_invalid_colour.get();
+        let palette = config.colour_palette.get();
 
         let mut has_highlights = false;
         for (idx, e) in ents.iter().enumerate() {
@@ -262,7 +274,8 @@ impl <'a> super::EntityRender<'a> for Info {
             };
             let highlight = if let Some(h) = highlight.get_component(*e) {
                 has_highlights = true;
-                (h.color.0, h.color.1, h.color.2, 255)
+                let col = palette.correct(h.color);
+                (col.0, col.1, col.2, 255)
             } else {
                 (0, 0, 0, 0)
             };
@@ -272,7 +285,9 @@ impl <'a> super::EntityRender<'a> for Info {
                 matrix: mat * model.info.transform,
                 tint,
                 highlight,
+                pick: picking::encode_id(picks.len()),
             });
+            picks.push(*e);
         }
         model.has_highlights = has_highlights;
         model.matrix_buffer.bind(gl::BufferTarget::Array);
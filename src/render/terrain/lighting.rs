@@ -0,0 +1,244 @@
+//! Baked ambient occlusion and bounce lighting for the level.
+//!
+//! Rather than computing indirect lighting every frame the level is
+//! sampled a few times per tile to find how much of the surrounding
+//! area is blocked by walls and objects. This is baked into a
+//! texture covering the whole level which the terrain shader blends
+//! into its ambient light:
+//!
+//! * Red - ambient occlusion, dark in corners, along walls and under
+//!   objects. 255 is unoccluded.
+//! * Green - how open the area around the tile is, enclosed interiors
+//!   receive less light bounced in from outside. 255 is fully open.
+//!
+//! Sections are re-baked a few at a time when they (or a neighbour,
+//! as the samples reach across section edges) change.
+
+use std::cmp;
+use crate::util::{Location, ALL_DIRECTIONS};
+use crate::server::level::{self, Level, SECTION_SIZE};
+use crate::render::gl;
+use super::super::memory;
+
+/// The number of samples per tile along each axis
+const LIGHT_RES: usize = 4;
+/// The distance, in samples, checked for occluders
+const AO_RADIUS: i32 = 6;
+/// How dark a fully occluded sample is
+const AO_STRENGTH: f32 = 0.6;
+/// The distance, in samples, checked for walls enclosing the area
+const BOUNCE_RADIUS: i32 = 12;
+/// The number of sections baked per update
+const BAKE_BUDGET: usize = 2;
+
+const SAMPLE_DIRECTIONS: [(i32, i32); 8] = [
+    (1, 0), (1, 1), (0, 1), (-1, 1),
+    (-1, 0), (-1, -1), (0, -1), (1, -1),
+];
+
+/// The baked lighting of the level
+pub(super) struct LightMap {
+    texture: gl::Texture,
+    width: u32,
+    height: u32,
+    /// Sections waiting to be baked
+    pending: Vec<(usize, usize)>,
+    enabled: bool,
+}
+
+impl LightMap {
+    /// Creates a light map for a level of the size and queues
+    /// every section to be baked
+    pub(super) fn new(width: u32, height: u32) -> LightMap {
+        let _memory = memory::scope(memory::Category::Terrain, "Light map", None);
+        let texture = gl::Texture::new();
+        texture.bind(gl::TextureTarget::Texture2D);
+        let (tw, th) = (width as usize * LIGHT_RES, height as usize * LIGHT_RES);
+        texture.image_2d_ex(
+            gl::TextureTarget::Texture2D, 0,
+            tw as u32, th as u32,
+            gl::TextureFormat::Rg8, gl::TextureFormat::Rg,
+            gl::Type::UnsignedByte, Some(&vec![255; tw * th * 2]),
+        );
+        texture.set_parameter::<gl::TextureMinFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Linear);
+        texture.set_parameter::<gl::TextureMagFilter>(gl::TextureTarget::Texture2D, gl::TextureFilter::Linear);
+        texture.set_parameter::<gl::TextureWrapS>(gl::TextureTarget::Texture2D, gl::TextureWrap::ClampToEdge);
+        texture.set_parameter::<gl::TextureWrapT>(gl::TextureTarget::Texture2D, gl::TextureWrap::ClampToEdge);
+        texture.set_parameter::<gl::TextureBaseLevel>(gl::TextureTarget::Texture2D, 0);
+        texture.set_parameter::<gl::TextureMaxLevel>(gl::TextureTarget::Texture2D, 0);
+
+        let mut map = LightMap {
+            texture,
+            width,
+            height,
+            pending: Vec::new(),
+            enabled: true,
+        };
+        map.queue_all();
+        map
+    }
+
+    fn sections(&self) -> (usize, usize) {
+        (
+            (self.width as usize + (SECTION_SIZE - 1)) / SECTION_SIZE,
+            (self.height as usize + (SECTION_SIZE - 1)) / SECTION_SIZE,
+        )
+    }
+
+    fn queue_all(&mut self) {
+        let (sw, sh) = self.sections();
+        self.pending.clear();
+        for y in 0 .. sh {
+            for x in 0 .. sw {
+                self.pending.push((x, y));
+            }
+        }
+    }
+
+    /// Queues the section and its neighbours to be baked again
+    pub(super) fn queue_around(&mut self, sx: usize, sy: usize) {
+        if !self.enabled {
+            return;
+        }
+        let (sw, sh) = self.sections();
+        for y in sy.saturating_sub(1) ..= cmp::min(sy + 1, sh - 1) {
+            for x in sx.saturating_sub(1) ..= cmp::min(sx + 1, sw - 1) {
+                if !self.pending.contains(&(x, y)) {
+                    self.pending.push((x, y));
+                }
+            }
+        }
+    }
+
+    /// Turns baking on or off. Whilst off the map is left
+    /// neutral so the level is lit as if nothing was baked
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        if self.enabled == enabled {
+            return;
+        }
+        self.enabled = enabled;
+        if enabled {
+            self.queue_all();
+        } else {
+            self.pending.clear();
+            let (tw, th) = (self.width * LIGHT_RES as u32, self.height * LIGHT_RES as u32);
+            self.texture.bind(gl::TextureTarget::Texture2D);
+            self.texture.sub_image_2d(
+                gl::TextureTarget::Texture2D, 0,
+                0, 0, tw, th,
+                gl::TextureFormat::Rg,
+                gl::Type::UnsignedByte, Some(&vec![255; (tw * th * 2) as usize]),
+            );
+        }
+    }
+
+    /// Bakes the next few queued sections
+    pub(super) fn bake(&mut self, level: &Level) {
+        let count = cmp::min(BAKE_BUDGET, self.pending.len());
+        if count == 0 {
+            return;
+        }
+        let _memory = memory::scope(memory::Category::Terrain, "Light map", None);
+        self.texture.bind(gl::TextureTarget::Texture2D);
+        for (sx, sy) in self.pending.drain(.. count) {
+            let min_x = sx * SECTION_SIZE;
+            let min_y = sy * SECTION_SIZE;
+            let width = cmp::min(SECTION_SIZE, self.width as usize - min_x);
+            let height = cmp::min(SECTION_SIZE, self.height as usize - min_y);
+            let data = bake_area(level, min_x as i32, min_y as i32, width, height);
+            self.texture.sub_image_2d(
+                gl::TextureTarget::Texture2D, 0,
+                (min_x * LIGHT_RES) as u32, (min_y * LIGHT_RES) as u32,
+                (width * LIGHT_RES) as u32, (height * LIGHT_RES) as u32,
+                gl::TextureFormat::Rg,
+                gl::Type::UnsignedByte, Some(&data),
+            );
+        }
+    }
+
+    pub(super) fn bind(&self) {
+        self.texture.bind(gl::TextureTarget::Texture2D);
+    }
+}
+
+/// Bakes the lighting of the tiles in the area, returning the
+/// red and green values of each sample row by row
+fn bake_area(level: &Level, min_x: i32, min_y: i32, width: usize, height: usize) -> Vec<u8> {
+    let res = LIGHT_RES as i32;
+    let samples_w = width as i32 * res;
+    let samples_h = height as i32 * res;
+
+    // Occluders in and around the area
+    let margin = BOUNCE_RADIUS;
+    let grid_min_x = min_x * res - margin;
+    let grid_min_y = min_y * res - margin;
+    let grid_w = samples_w + margin * 2;
+    let grid_h = samples_h + margin * 2;
+    let mut blocked = vec![false; (grid_w * grid_h) as usize];
+    let tile_min = Location::new((grid_min_x / res).max(0), (grid_min_y / res).max(0));
+    let tile_max = Location::new(
+        cmp::min((grid_min_x + grid_w + res - 1) / res, level.width as i32),
+        cmp::min((grid_min_y + grid_h + res - 1) / res, level.height as i32),
+    );
+    for ty in tile_min.y .. tile_max.y {
+        for tx in tile_min.x .. tile_max.x {
+            let loc = Location::new(tx, ty);
+            let walls: Vec<(i32, i32)> = ALL_DIRECTIONS.iter()
+                .filter(|&&dir| level.get_wall_info(loc, dir)
+                    .map_or(false, |v| v.flag != level::TileWallFlag::Door))
+                .map(|dir| dir.offset())
+                .collect();
+            let room = level.get_room_owner(loc)
+                .map(|v| level.get_room_info(v));
+            for sy in 0 .. res {
+                for sx in 0 .. res {
+                    let (px, py) = (tx * res + sx, ty * res + sy);
+                    let (gx, gy) = (px - grid_min_x, py - grid_min_y);
+                    if gx < 0 || gy < 0 || gx >= grid_w || gy >= grid_h {
+                        continue;
+                    }
+                    let on_wall = walls.iter().any(|&(ox, oy)|
+                        (ox == 1 && sx == res - 1) || (ox == -1 && sx == 0)
+                        || (oy == 1 && sy == res - 1) || (oy == -1 && sy == 0)
+                    );
+                    let under_object = room.as_ref().map_or(false, |v| !v.is_placeable_scaled(px, py));
+                    blocked[(gx + gy * grid_w) as usize] = on_wall || under_object;
+                }
+            }
+        }
+    }
+    let is_blocked = |gx: i32, gy: i32| {
+        gx >= 0 && gy >= 0 && gx < grid_w && gy < grid_h
+            && blocked[(gx + gy * grid_w) as usize]
+    };
+    // Returns the distance to the first occluder in the direction
+    // if there is one within the radius
+    let trace = |gx: i32, gy: i32, (dx, dy): (i32, i32), radius: i32| {
+        (1 ..= radius).find(|&d| is_blocked(gx + dx * d, gy + dy * d))
+    };
+
+    let mut data = vec![0; (samples_w * samples_h * 2) as usize];
+    for sy in 0 .. samples_h {
+        for sx in 0 .. samples_w {
+            let (gx, gy) = (sx + margin, sy + margin);
+            let ao = if is_blocked(gx, gy) {
+                1.0 - AO_STRENGTH
+            } else {
+                let occlusion: f32 = SAMPLE_DIRECTIONS.iter()
+                    .filter_map(|&dir| trace(gx, gy, dir, AO_RADIUS))
+                    .map(|d| (AO_RADIUS - d + 1) as f32 / AO_RADIUS as f32)
+                    .sum();
+                1.0 - AO_STRENGTH * (occlusion / SAMPLE_DIRECTIONS.len() as f32)
+            };
+            let openness: f32 = SAMPLE_DIRECTIONS.iter()
+                .map(|&dir| trace(gx, gy, dir, BOUNCE_RADIUS)
+                    .map_or(1.0, |d| d as f32 / BOUNCE_RADIUS as f32))
+                .sum::<f32>() / SAMPLE_DIRECTIONS.len() as f32;
+
+            let idx = ((sx + sy * samples_w) * 2) as usize;
+            data[idx] = (ao * 255.0) as u8;
+            data[idx + 1] = (openness * 255.0) as u8;
+        }
+    }
+    data
+}
@@ -1,18 +1,23 @@
 
-use crate::util::{Location, FNVMap};
+use crate::util::{Location, Bound, FNVMap};
 use std::mem;
+use std::sync::Arc;
 
-use super::{model, pipeline};
+use super::{model, pipeline, memory};
 use super::atlas::Rect;
 use crate::server::level::{self, SECTION_SIZE, room};
 use crate::render::gl;
 use cgmath::{self, Matrix4};
 use crate::util::Direction;
 use crate::server::assets;
+use crate::server::calendar::{self, SeasonVariants, SeasonBlend};
 use crate::math::Frustum;
 use crate::prelude::*;
 
 mod window;
+mod lighting;
+mod occlusion;
+pub use self::occlusion::{SectionVisibility, OcclusionMap};
 
 /// Handles terrain rendering for levels.
 pub struct Terrain {
@@ -27,6 +32,31 @@ pub struct Terrain {
 
     pub lowered_region: Option<Bound>,
     windows: Vec<window::Model>,
+    /// The stage of the dirt on each dirty tile
+    dirt: FNVMap<Location, u8>,
+    /// The pieces of litter on each littered tile
+    litter: FNVMap<Location, u8>,
+    /// The area and stage of each room being built
+    construction: FNVMap<room::Id, (Bound, u8)>,
+    /// The season outdoor tiles are drawn with
+    season: Option<Season>,
+    // Set when the season changed enough that tiles need
+    // to be rebuilt
+    season_dirty: bool,
+    light_map: lighting::LightMap,
+    /// The walls that hide sections and entities behind them
+    occlusion: occlusion::OcclusionMap,
+    /// The direction towards the camera when occlusion culling
+    /// is enabled
+    occlusion_view: Option<cgmath::Vector3<f32>>,
+    // Set when the context was lost to rebuild every
+    // section on the next update
+    rebuild: bool,
+}
+
+struct Season {
+    variants: Arc<SeasonVariants>,
+    blend: SeasonBlend,
 }
 
 struct EditSection {
@@ -58,6 +88,20 @@ struct TerrainSection {
     buffer: gl::Buffer,
     count: usize,
     max_count: usize,
+    /// Whether the section was drawn last frame
+    visibility: SectionVisibility,
+
+    decals: Decals,
+}
+
+/// The dirt and litter decals of a section, drawn after the rest of
+/// the terrain with blending
+struct Decals {
+    array: gl::VertexArray,
+    buffer: gl::Buffer,
+    count: usize,
+    max_count: usize,
+    dirty: bool,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -115,6 +159,43 @@ pub struct GLVertex {
 impl Terrain {
     pub(super) fn new(log: &Logger, asset_manager: &assets::AssetManager, level: &level::Level, ctx: &mut pipeline::Context<'_>) -> Terrain {
         let log = log.new(o!("source" => "terrain"));
+        let sections = Self::create_sections(&log, level.width, level.height, ctx);
+
+        Terrain {
+            log,
+            asset_manager: asset_manager.clone(),
+            width: level.width,
+            height: level.height,
+
+            sections,
+            edit_sections: FNVMap::default(),
+            placement_guides: FNVMap::default(),
+            lowered_region: None,
+            windows: Vec::new(),
+            dirt: FNVMap::default(),
+            litter: FNVMap::default(),
+            construction: FNVMap::default(),
+            season: None,
+            season_dirty: false,
+            light_map: lighting::LightMap::new(level.width, level.height),
+            occlusion: occlusion::OcclusionMap::new(level.width, level.height),
+            occlusion_view: None,
+            rebuild: false,
+        }
+    }
+
+    /// Replaces the objects stored on the GPU after the
+    /// context was lost
+    pub(super) fn recreate(&mut self, ctx: &mut pipeline::Context<'_>) {
+        self.sections = Self::create_sections(&self.log, self.width, self.height, ctx);
+        self.edit_sections.clear();
+        self.placement_guides.clear();
+        self.light_map = lighting::LightMap::new(self.width, self.height);
+        self.rebuild = true;
+    }
+
+    fn create_sections(log: &Logger, width: u32, height: u32, ctx: &mut pipeline::Context<'_>) -> Vec<TerrainSection> {
+        let _memory = memory::scope(memory::Category::Terrain, "Level", None);
         let prog = ctx.program("terrain");
         prog.use_program();
         let a_position = assume!(log, prog.attribute("attrib_position"));
@@ -122,23 +203,27 @@ impl Terrain {
         let a_normal = assume!(log, prog.attribute("attrib_normal"));
 
         // Build placeholders for the terrain sections
-        let sw = (level.width as usize + (SECTION_SIZE - 1)) / SECTION_SIZE;
-        let sh = (level.height as usize + (SECTION_SIZE - 1)) / SECTION_SIZE;
+        let sw = (width as usize + (SECTION_SIZE - 1)) / SECTION_SIZE;
+        let sh = (height as usize + (SECTION_SIZE - 1)) / SECTION_SIZE;
         let mut sections = Vec::with_capacity(sw * sh);
+        let create_array = || {
+            let array = gl::VertexArray::new();
+            array.bind();
+            let buffer = gl::Buffer::new();
+            buffer.bind(gl::BufferTarget::Array);
+
+            a_position.enable();
+            a_position.vertex_pointer(3, gl::Type::Float, false, mem::size_of::<GLVertex>() as i32, 0);
+            a_normal.enable();
+            a_normal.vertex_pointer(3, gl::Type::Float, false, mem::size_of::<GLVertex>() as i32, 12);
+            a_texture.enable();
+            a_texture.vertex_pointer(4, gl::Type::Float, false, mem::size_of::<GLVertex>() as i32, 24);
+            (array, buffer)
+        };
         for x in 0 .. sw {
             for y in 0 .. sh {
-                let array = gl::VertexArray::new();
-                array.bind();
-                let buffer = gl::Buffer::new();
-                buffer.bind(gl::BufferTarget::Array);
-
-                a_position.enable();
-                a_position.vertex_pointer(3, gl::Type::Float, false, mem::size_of::<GLVertex>() as i32, 0);
-                a_normal.enable();
-                a_normal.vertex_pointer(3, gl::Type::Float, false, mem::size_of::<GLVertex>() as i32, 12);
-                a_texture.enable();
-                a_texture.vertex_pointer(4, gl::Type::Float, false, mem::size_of::<GLVertex>() as i32, 24);
-
+                let (array, buffer) = create_array();
+                let (decal_array, decal_buffer) = create_array();
 
                 sections.push(TerrainSection {
                     x,
@@ -147,25 +232,26 @@ impl Terrain {
                     buffer,
                     count: 0,
                     max_count: 0,
+                    visibility: SectionVisibility::OutOfView,
+                    decals: Decals {
+                        array: decal_array,
+                        buffer: decal_buffer,
+                        count: 0,
+                        max_count: 0,
+                        dirty: true,
+                    },
                 });
             }
         }
-
-        Terrain {
-            log,
-            asset_manager: asset_manager.clone(),
-            width: level.width,
-            height: level.height,
-
-            sections,
-            edit_sections: FNVMap::default(),
-            placement_guides: FNVMap::default(),
-            lowered_region: None,
-            windows: Vec::new(),
-        }
+        sections
     }
 
-    pub(super) fn update(&mut self, ctx: &mut pipeline::Context<'_>, target_atlas: &mut super::GlobalAtlas, level: &mut level::Level) {
+    pub(super) fn update(
+        &mut self, ctx: &mut pipeline::Context<'_>, target_atlas: &mut super::GlobalAtlas, level: &mut level::Level,
+        baked_lighting: bool,
+    ) {
+        let _memory = memory::scope(memory::Category::Terrain, "Level", None);
+        self.light_map.set_enabled(baked_lighting);
         for edit in self.edit_sections.values_mut() {
             edit.touched = false;
         }
@@ -174,7 +260,7 @@ impl Terrain {
             let dirty = {
                 let mut room = level.get_room_info_mut(room_id);
                 if let Some(virt) = room.building_level.as_mut() {
-                    if virt.dirty {
+                    if virt.dirty || self.rebuild {
                         virt.dirty = false;
                         true
                     } else {
@@ -209,7 +295,7 @@ impl Terrain {
                                 wall_height, Some(wall_bounds)
                             );
                         } else {
-                            Self::make_tile(&self.log, &self.asset_manager, target_atlas, virt, loc, &mut data);
+                            Self::make_tile(&self.log, &self.asset_manager, target_atlas, virt, loc, self.season.as_ref(), &mut data);
                             Self::place_walls_limit(
                                 &self.log,
                                 &self.asset_manager, target_atlas,
@@ -281,9 +367,10 @@ impl Terrain {
 
         let mut data = vec![];
         for section in &mut self.sections {
-            if !level.get_and_clear_dirty_section(section.x, section.y) {
+            if !level.get_and_clear_dirty_section(section.x, section.y) && !self.rebuild && !self.season_dirty {
                 continue;
             }
+            self.light_map.queue_around(section.x, section.y);
             data.clear();
 
             let min_x = if section.x == 0 { -1 } else { (section.x * SECTION_SIZE) as i32 };
@@ -301,10 +388,12 @@ impl Terrain {
                     }
                     let loc = Location::new(x, y);
                     if level.get_tile_flags(loc).contains(level::TileFlag::BUILDING) {
+                        // Rooms being built may have their walls lowered
+                        self.occlusion.update_tile(level, loc, true);
                         continue;
                     }
                     if x >= 0 && y >= 0 {
-                        Self::make_tile(&self.log, &self.asset_manager, target_atlas, level, loc, &mut data);
+                        Self::make_tile(&self.log, &self.asset_manager, target_atlas, level, loc, self.season.as_ref(), &mut data);
                     }
                     // Walls
                     let (wall_height, bounds) = if let Some(lower) = lower_regions {
@@ -320,6 +409,7 @@ impl Terrain {
                     } else {
                         (1.0, None)
                     };
+                    self.occlusion.update_tile(level, loc, wall_height < 1.0);
 
                     Self::place_walls_limit(
                         &self.log,
@@ -341,6 +431,75 @@ impl Terrain {
             }
             section.count = count;
         }
+
+        for section in &mut self.sections {
+            if !section.decals.dirty && !self.rebuild {
+                continue;
+            }
+            section.decals.dirty = false;
+            data.clear();
+
+            let min_x = (section.x * SECTION_SIZE) as i32;
+            let min_y = (section.y * SECTION_SIZE) as i32;
+            for y in min_y .. min_y + SECTION_SIZE as i32 {
+                for x in min_x .. min_x + SECTION_SIZE as i32 {
+                    let loc = Location::new(x, y);
+                    // Scaffolding covers the floor of rooms being built
+                    if let Some(stage) = self.construction.values()
+                        .find(|v| v.0.in_bounds(loc))
+                        .map(|v| v.1)
+                    {
+                        let tex = assets::ResourceKey::new("base", format!("decals/scaffolding_{}", stage));
+                        let texture_id = Self::get_texture_id(&self.log, &self.asset_manager, target_atlas, tex);
+                        Self::make_wall(
+                            &mut data, FACE_FLOOR.iter(),
+                            texture_id,
+                            (x as f32, 0.01, y as f32), (0.0, 0.0, 0.0), (1.0, 1.0, 1.0),
+                            (0.0, 1.0, 0.0),
+                            |vx, _, vz| (vx, 1.0 - vz)
+                        );
+                    }
+                    if let Some(stage) = self.dirt.get(&loc) {
+                        let tex = assets::ResourceKey::new("base", format!("decals/dirt_{}", stage));
+                        let texture_id = Self::get_texture_id(&self.log, &self.asset_manager, target_atlas, tex);
+                        // Raised slightly to stay above the floor
+                        Self::make_wall(
+                            &mut data, FACE_FLOOR.iter(),
+                            texture_id,
+                            (x as f32, 0.005, y as f32), (0.0, 0.0, 0.0), (1.0, 1.0, 1.0),
+                            (0.0, 1.0, 0.0),
+                            |vx, _, vz| (vx, 1.0 - vz)
+                        );
+                    }
+                    if let Some(pieces) = self.litter.get(&loc) {
+                        let tex = assets::ResourceKey::new("base", format!("decals/litter_{}", pieces));
+                        let texture_id = Self::get_texture_id(&self.log, &self.asset_manager, target_atlas, tex);
+                        // Drawn over the dirt
+                        Self::make_wall(
+                            &mut data, FACE_FLOOR.iter(),
+                            texture_id,
+                            (x as f32, 0.008, y as f32), (0.0, 0.0, 0.0), (1.0, 1.0, 1.0),
+                            (0.0, 1.0, 0.0),
+                            |vx, _, vz| (vx, 1.0 - vz)
+                        );
+                    }
+                }
+            }
+            let decals = &mut section.decals;
+            let count = data.len();
+            decals.array.bind();
+            decals.buffer.bind(gl::BufferTarget::Array);
+            if decals.max_count < count {
+                decals.buffer.set_data(gl::BufferTarget::Array, &data, gl::BufferUsage::Dynamic);
+                decals.max_count = count;
+            } else {
+                decals.buffer.set_data_range(gl::BufferTarget::Array, &data, 0);
+            }
+            decals.count = count;
+        }
+        self.light_map.bake(level);
+        self.rebuild = false;
+        self.season_dirty = false;
         self.update_guides(ctx, level);
     }
 
@@ -475,6 +634,86 @@ impl Terrain {
         }
     }
 
+    /// Sets the stage of the dirt on the tile, zero removing it
+    pub(super) fn set_dirt(&mut self, loc: Location, stage: u8) {
+        if loc.x < 0 || loc.y < 0 {
+            return;
+        }
+        let changed = if stage == 0 {
+            self.dirt.remove(&loc).is_some()
+        } else {
+            self.dirt.insert(loc, stage) != Some(stage)
+        };
+        if changed {
+            self.flag_decals(loc);
+        }
+    }
+
+    /// Sets the pieces of litter on the tile, zero removing it
+    pub(super) fn set_litter(&mut self, loc: Location, pieces: u8) {
+        if loc.x < 0 || loc.y < 0 {
+            return;
+        }
+        let changed = if pieces == 0 {
+            self.litter.remove(&loc).is_some()
+        } else {
+            self.litter.insert(loc, pieces) != Some(pieces)
+        };
+        if changed {
+            self.flag_decals(loc);
+        }
+    }
+
+    /// Marks the decals of the section containing the tile
+    /// as needing a rebuild
+    fn flag_decals(&mut self, loc: Location) {
+        let (sx, sy) = (loc.x as usize / SECTION_SIZE, loc.y as usize / SECTION_SIZE);
+        if let Some(section) = self.sections.iter_mut().find(|v| v.x == sx && v.y == sy) {
+            section.decals.dirty = true;
+        }
+    }
+
+    /// Sets the stage of construction of the room, `None`
+    /// removing its scaffolding
+    pub(super) fn set_construction(&mut self, room: room::Id, site: Option<(Bound, u8)>) {
+        let old = if let Some(site) = site {
+            if self.construction.insert(room, site) == Some(site) {
+                return;
+            }
+            Some(site.0)
+        } else {
+            self.construction.remove(&room).map(|v| v.0)
+        };
+        if let Some(area) = old {
+            let (min_x, min_y) = (area.min.x.max(0) as usize / SECTION_SIZE, area.min.y.max(0) as usize / SECTION_SIZE);
+            let (max_x, max_y) = (area.max.x.max(0) as usize / SECTION_SIZE, area.max.y.max(0) as usize / SECTION_SIZE);
+            for section in &mut self.sections {
+                if section.x >= min_x && section.x <= max_x && section.y >= min_y && section.y <= max_y {
+                    section.decals.dirty = true;
+                }
+            }
+        }
+    }
+
+    /// Sets the season outdoor tiles are drawn with, rebuilding
+    /// the level only when the tiles would change
+    pub(super) fn set_season(&mut self, variants: &Arc<SeasonVariants>, blend: SeasonBlend) {
+        if variants.is_empty() {
+            return;
+        }
+        let changed = self.season.as_ref().map_or(true, |v|
+            !Arc::ptr_eq(&v.variants, variants)
+            || (v.blend.from, v.blend.to, v.blend.step()) != (blend.from, blend.to, blend.step())
+        );
+        if changed {
+            self.season = Some(Season {
+                variants: variants.clone(),
+                blend,
+            });
+            self.season_dirty = true;
+        }
+    }
+
     pub(super) fn gen_verts_for_tile<L: level::LevelView>(
         &mut self,
         target_atlas: &mut super::GlobalAtlas,
@@ -483,7 +722,7 @@ impl Terrain {
         loc: Location
     ) -> Vec<GLVertex> {
         let mut data = vec![];
-        Self::make_tile(&self.log, &self.asset_manager, target_atlas, level, loc, &mut data);
+        Self::make_tile(&self.log, &self.asset_manager, target_atlas, level, loc, self.season.as_ref(), &mut data);
         Self::place_walls(&self.log, &self.asset_manager, target_atlas, &mut self.windows, real_level, level, loc, &mut data);
         data
     }
@@ -799,6 +1038,7 @@ impl Terrain {
         asset_manager: &assets::AssetManager,
         target_atlas: &mut super::GlobalAtlas,
         level: &L, loc: Location,
+        season: Option<&Season>,
         data: &mut Vec<GLVertex>
     ) {
         let cx = loc.x as f32;
@@ -806,6 +1046,11 @@ impl Terrain {
         let t_self = level.get_tile(loc);
         // Find the texture for the floor.
         let tex = t_self.get_texture_for(level, loc);
+        // Swap to the season's variant if it has one. Tiles change
+        // over a few at a time whilst the season is changing
+        let tex = season
+            .and_then(|v| v.variants.texture(v.blend.season_for(calendar::location_seed(loc)), tex.borrow()))
+            .unwrap_or(tex);
         let texture_id = Self::get_texture_id(log, asset_manager, target_atlas, tex);
         // Tile's floor texture
         Self::make_wall(
@@ -849,6 +1094,30 @@ impl Terrain {
         }
     }
 
+    /// Sets the direction towards the camera used for occlusion
+    /// culling, `None` disables culling
+    pub(super) fn set_occlusion_view(&mut self, view: Option<cgmath::Vector3<f32>>) {
+        self.occlusion_view = view;
+    }
+
+    /// Returns the walls used for occlusion culling if culling
+    /// is enabled along with the direction to the camera
+    pub(super) fn occlusion(&self) -> Option<(&occlusion::OcclusionMap, cgmath::Vector3<f32>)> {
+        self.occlusion_view.map(|v| (&self.occlusion, v))
+    }
+
+    /// Returns the number of sections along each axis and
+    /// whether each section was drawn last frame
+    pub(super) fn section_visibility(&self) -> (usize, usize, Vec<SectionVisibility>) {
+        let sw = (self.width as usize + (SECTION_SIZE - 1)) / SECTION_SIZE;
+        let sh = (self.height as usize + (SECTION_SIZE - 1)) / SECTION_SIZE;
+        let mut out = vec![SectionVisibility::OutOfView; sw * sh];
+        for section in &self.sections {
+            out[section.x + section.y * sw] = section.visibility;
+        }
+        (sw, sh, out)
+    }
+
     pub fn get_render_bounds(&self, frustum: &Frustum) -> (f32, f32, f32) {
         use std::f32;
         let mut total_x = 0.0;
@@ -909,12 +1178,20 @@ impl Terrain {
             prog.uniform("projection_matrix").map(|v| v.set_matrix4(projection));
             prog.uniform("u_textures").map(|v| v.set_int(super::GLOBAL_TEXTURE_LOCATION as _));
             prog.uniform("shadow_map").map(|v| v.set_int(super::SHADOW_MAP_LOCATION as _));
+            gl::active_texture(super::LIGHT_MAP_LOCATION);
+            self.light_map.bind();
+            gl::active_texture(0);
+            prog.uniform("u_light_map").map(|v| v.set_int(super::LIGHT_MAP_LOCATION as _));
+            prog.uniform("u_light_map_size").map(|v| v.set_float2(self.width as f32, self.height as f32));
             if let (Some(sv), Some(sp)) = (shadow_view_matrix, shadow_projection) {
                 prog.uniform("shadow_matrix").map(|v| v.set_matrix4(sv));
                 prog.uniform("shadow_projection").map(|v| v.set_matrix4(sp));
             }
 
-            for section in &self.sections {
+            // Occlusion culling only applies to the main pass, hidden
+            // sections can still cast shadows
+            let occlusion_view = self.occlusion_view.filter(|_| shadow_view_matrix.is_some());
+            for section in &mut self.sections {
                 let min = cgmath::Vector3::new(
                     (section.x * SECTION_SIZE) as f32 - 1.5,
                     0.0,
@@ -925,10 +1202,52 @@ impl Terrain {
                     2.5,
                     (section.y * SECTION_SIZE) as f32 + SECTION_SIZE as f32 + 1.5,
                 );
-                if section.count > 0 && frustum.contains_aabb(min, max){
-                    section.array.bind();
-                    gl::draw_arrays(gl::DrawType::Triangles, 0, section.count);
+                if section.count == 0 || !frustum.contains_aabb(min, max) {
+                    section.visibility = SectionVisibility::OutOfView;
+                    continue;
+                }
+                if let Some(view) = occlusion_view {
+                    // Walls sticking up out of the section can always
+                    // be seen so only flat sections can be hidden
+                    let area = Bound::new(
+                        Location::new((section.x * SECTION_SIZE) as i32, (section.y * SECTION_SIZE) as i32),
+                        Location::new(((section.x + 1) * SECTION_SIZE) as i32 - 1, ((section.y + 1) * SECTION_SIZE) as i32 - 1),
+                    );
+                    if !self.occlusion.has_walls(area) && self.occlusion.is_floor_hidden(area, view) {
+                        section.visibility = SectionVisibility::Occluded;
+                        continue;
+                    }
+                    section.visibility = SectionVisibility::Visible;
+                } else if shadow_view_matrix.is_some() {
+                    section.visibility = SectionVisibility::Visible;
+                }
+                section.array.bind();
+                gl::draw_arrays(gl::DrawType::Triangles, 0, section.count);
+            }
+
+            // Dirt decals, these don't cast shadows
+            if shadow_view_matrix.is_some() {
+                gl::enable_i(gl::Flag::Blend, 0);
+                gl::blend_func(gl::BlendFunc::SrcAlpha, gl::BlendFunc::OneMinusSrcAlpha);
+                gl::depth_mask(false);
+                for section in &self.sections {
+                    let min = cgmath::Vector3::new(
+                        (section.x * SECTION_SIZE) as f32,
+                        0.0,
+                        (section.y * SECTION_SIZE) as f32
+                    );
+                    let max = cgmath::Vector3::new(
+                        (section.x * SECTION_SIZE) as f32 + SECTION_SIZE as f32,
+                        0.1,
+                        (section.y * SECTION_SIZE) as f32 + SECTION_SIZE as f32,
+                    );
+                    if section.decals.count > 0 && section.visibility != SectionVisibility::Occluded && frustum.contains_aabb(min, max) {
+                        section.decals.array.bind();
+                        gl::draw_arrays(gl::DrawType::Triangles, 0, section.decals.count);
+                    }
                 }
+                gl::depth_mask(true);
+                gl::disable_i(gl::Flag::Blend, 0);
             }
         }
 
@@ -966,7 +1285,7 @@ impl Terrain {
             log,
             asset_manager,
             target_atlas,
-            tex
+            assets::TypedKey::from_key(tex)
         )
     }
 }
@@ -0,0 +1,173 @@
+//! Coarse occlusion culling using the walls of the level.
+//!
+//! Only full height solid walls hide anything. Doors and windows
+//! can be seen through and lowered walls are too short to hide
+//! anything behind them.
+
+use crate::util::{Location, Bound, Direction, ALL_DIRECTIONS};
+use crate::server::level::{self, LevelView};
+use cgmath::Vector3;
+
+/// The height of a full wall
+const WALL_HEIGHT: f32 = 1.0;
+/// The number of points tested along each side of a tile
+/// when testing whether a floor is hidden
+const SAMPLES_PER_TILE: usize = 2;
+
+/// Whether a terrain section was drawn last frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SectionVisibility {
+    /// Outside of the camera's view
+    OutOfView,
+    /// Drawn
+    Visible,
+    /// Inside the camera's view but hidden behind walls
+    Occluded,
+}
+
+/// The walls of the level that can hide things behind them
+pub struct OcclusionMap {
+    width: u32,
+    height: u32,
+    /// A bit per a direction for each tile, set when the
+    /// tile has a wall that occludes on that side
+    solid: Vec<u8>,
+    /// A bit per a direction for each tile, set when the
+    /// tile has any wall on that side
+    walls: Vec<u8>,
+}
+
+impl OcclusionMap {
+    /// Creates an empty map for a level of the given size
+    pub fn new(width: u32, height: u32) -> OcclusionMap {
+        let size = (width * height) as usize;
+        OcclusionMap {
+            width,
+            height,
+            solid: vec![0; size],
+            walls: vec![0; size],
+        }
+    }
+
+    fn index(&self, loc: Location) -> Option<usize> {
+        if loc.x < 0 || loc.y < 0 || loc.x >= self.width as i32 || loc.y >= self.height as i32 {
+            None
+        } else {
+            Some(loc.x as usize + loc.y as usize * self.width as usize)
+        }
+    }
+
+    /// Updates the walls of the tile from the level.
+    ///
+    /// Lowered walls are never solid
+    pub fn update_tile(&mut self, level: &level::Level, loc: Location, lowered: bool) {
+        let idx = if let Some(idx) = self.index(loc) {
+            idx
+        } else {
+            return;
+        };
+        let mut solid = 0;
+        let mut walls = 0;
+        for dir in &ALL_DIRECTIONS {
+            if let Some(info) = level.get_wall_info(loc, *dir) {
+                let bit = 1 << dir.as_usize();
+                walls |= bit;
+                if !lowered && info.flag == level::TileWallFlag::None {
+                    solid |= bit;
+                }
+            }
+        }
+        self.solid[idx] = solid;
+        self.walls[idx] = walls;
+    }
+
+    fn is_solid(&self, loc: Location, dir: Direction) -> bool {
+        self.index(loc)
+            .map_or(false, |idx| self.solid[idx] & (1 << dir.as_usize()) != 0)
+    }
+
+    /// Returns whether any tile in the area has a wall
+    pub fn has_walls(&self, area: Bound) -> bool {
+        area.into_iter()
+            .filter_map(|loc| self.index(loc))
+            .any(|idx| self.walls[idx] != 0)
+    }
+
+    /// Returns whether the point is hidden behind a wall when
+    /// looking from the direction `to_camera`.
+    ///
+    /// `to_camera` should be normalized and point upwards
+    pub fn is_point_hidden(&self, point: Vector3<f32>, to_camera: Vector3<f32>) -> bool {
+        if point.y >= WALL_HEIGHT || to_camera.y <= 0.0 {
+            return false;
+        }
+        // The ray can't be blocked once it is above the walls
+        let max_t = (WALL_HEIGHT - point.y) / to_camera.y;
+
+        let mut loc = Location::new(point.x.floor() as i32, point.z.floor() as i32);
+        let step_x = if to_camera.x < 0.0 { -1 } else { 1 };
+        let step_y = if to_camera.z < 0.0 { -1 } else { 1 };
+        // Axis aligned rays end up with an infinite delta
+        let delta_x = 1.0 / to_camera.x.abs();
+        let delta_y = 1.0 / to_camera.z.abs();
+        let mut next_x = if to_camera.x < 0.0 { point.x - point.x.floor() } else { point.x.floor() + 1.0 - point.x } * delta_x;
+        let mut next_y = if to_camera.z < 0.0 { point.z - point.z.floor() } else { point.z.floor() + 1.0 - point.z } * delta_y;
+
+        loop {
+            let (t, dir) = if next_x < next_y {
+                let t = next_x;
+                next_x += delta_x;
+                (t, Direction::from_offset(step_x, 0))
+            } else {
+                let t = next_y;
+                next_y += delta_y;
+                (t, Direction::from_offset(0, step_y))
+            };
+            if t >= max_t {
+                return false;
+            }
+            if self.is_solid(loc, dir) || self.is_solid(loc.shift(dir), dir.reverse()) {
+                return true;
+            }
+            loc = loc.shift(dir);
+            if self.index(loc).is_none() {
+                return false;
+            }
+        }
+    }
+
+    /// Returns whether the top of the box is hidden behind walls.
+    ///
+    /// Only the corners and the centre of the top are tested
+    pub fn is_box_hidden(&self, min: Vector3<f32>, max: Vector3<f32>, to_camera: Vector3<f32>) -> bool {
+        let points = [
+            Vector3::new(min.x, max.y, min.z),
+            Vector3::new(max.x, max.y, min.z),
+            Vector3::new(min.x, max.y, max.z),
+            Vector3::new(max.x, max.y, max.z),
+            Vector3::new((min.x + max.x) * 0.5, max.y, (min.z + max.z) * 0.5),
+        ];
+        points.iter().all(|v| self.is_point_hidden(*v, to_camera))
+    }
+
+    /// Returns whether the floor of the area is hidden behind
+    /// walls
+    pub fn is_floor_hidden(&self, area: Bound, to_camera: Vector3<f32>) -> bool {
+        // Kept slightly inside the area so that points aren't
+        // on the walls around it
+        let min_x = area.min.x as f32 + 0.05;
+        let min_y = area.min.y as f32 + 0.05;
+        let width = area.width() as f32 - 0.1;
+        let height = area.height() as f32 - 0.1;
+        let samples_x = area.width() as usize * SAMPLES_PER_TILE;
+        let samples_y = area.height() as usize * SAMPLES_PER_TILE;
+        (0 ..= samples_y).all(|y| (0 ..= samples_x).all(|x| {
+            let point = Vector3::new(
+                min_x + width * (x as f32 / samples_x as f32),
+                0.0,
+                min_y + height * (y as f32 / samples_y as f32),
+            );
+            self.is_point_hidden(point, to_camera)
+        }))
+    }
+}
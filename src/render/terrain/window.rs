@@ -15,7 +15,7 @@ impl Model {
                 target_atlas: &mut render::GlobalAtlas,
                 key: ResourceKey<'_>) -> Model
     {
-        let mut file = assume!(log, assets.open_from_pack(key.module_key(), &format!("models/{}.umod", key.resource())));
+        let mut file = assume!(log, assets::TypedKey::<assets::ModelAsset>::from_key(key.borrow()).open(assets));
         let minfo = assume!(log, exmodel::Model::read_from(&mut file));
 
         let mut mdl = Model {
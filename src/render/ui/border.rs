@@ -296,8 +296,8 @@ impl <'a, 'b> Builder<'a, 'b> {
             self.log,
             self.assets,
             self.global_atlas,
-            LazyResourceKey::parse(&image)
-                .or_module(ModuleKey::new("base")),
+            assets::TypedKey::from_key(LazyResourceKey::parse(&image)
+                .or_module(ModuleKey::new("base"))),
         );
 
         let atlas = atlas as u16;
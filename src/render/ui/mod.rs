@@ -1,13 +1,15 @@
 //! fungui UI renderer
 
 use crate::prelude::*;
+use crate::server::assets;
 use fungui::*;
 use crate::render::gl;
 use crate::render::pipeline;
+use crate::render::memory;
 use cgmath;
 use std::mem;
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 use super::ATLAS_SIZE;
 use super::atlas;
@@ -24,6 +26,9 @@ pub struct Renderer {
     width: u32,
     height: u32,
     pub(super) ui_scale: f32,
+    /// Scale applied to font sizes and line heights, shared
+    /// with the `lined` layout engine
+    text_scale: Rc<Cell<f32>>,
 
     fonts: Rc<RefCell<FNVMap<String, Font>>>,
     font_texture: gl::Texture,
@@ -40,6 +45,7 @@ struct Font {
 
 impl Renderer {
     pub fn new(log: &Logger, assets: &AssetManager, ctx: &mut pipeline::Context<'_>) -> Renderer {
+        let _memory = memory::scope(memory::Category::Ui, "Interface", None);
         let attrib_position = {
             let program = ctx.program("ui/clip");
             program.use_program();
@@ -70,7 +76,10 @@ impl Renderer {
         buffer.set_data(gl::BufferTarget::Array, verts, gl::BufferUsage::Static);
 
 
-        let texture = gl::Texture::new();
+        let texture = {
+            let _memory = memory::scope(memory::Category::Textures, "Font atlas", None);
+            gl::Texture::new()
+        };
         texture.bind(gl::TextureTarget::Texture2DArray);
         texture.image_3d(
             gl::TextureTarget::Texture2DArray, 0,
@@ -93,6 +102,7 @@ impl Renderer {
             width: 800,
             height: 480,
             ui_scale: 1.0,
+            text_scale: Rc::new(Cell::new(1.0)),
 
             clip_array: array,
             _clip_buffer: buffer,
@@ -113,6 +123,22 @@ impl Renderer {
         }
     }
 
+    /// Sets the scale of text separately to the ui scale.
+    ///
+    /// Layouts are only recomputed once styles are reloaded.
+    pub fn set_text_scale(&mut self, scale: f32) {
+        if self.text_scale.get() == scale {
+            return;
+        }
+        self.text_scale.set(scale);
+        for font in self.fonts.borrow_mut().values_mut() {
+            font.chars.clear();
+        }
+        for atlas in &mut self.font_atlases {
+            *atlas = atlas::TextureAtlas::new(ATLAS_SIZE, ATLAS_SIZE);
+        }
+    }
+
     pub fn init(&mut self, manager: &mut Manager<UniverCityUI>) {
         manager.add_func_raw("rgb", color::rgb);
         manager.add_func_raw("rgba", color::rgba);
@@ -128,10 +154,12 @@ impl Renderer {
 
         let fonts = self.fonts.clone();
         let assets = self.assets.clone();
+        let text_scale = self.text_scale.clone();
         manager.add_layout_engine(move || {
             Lined::new(
                 assets.clone(),
                 fonts.clone(),
+                text_scale.clone(),
             )
         });
     }
@@ -142,6 +170,11 @@ impl Renderer {
         width: u32, height: u32, data: Vec<u8>
     ) {
         use super::image::{ImageFuture, Image};
+        // Kept in case the context is lost as dynamic images
+        // can't be reloaded from the assets
+        global_atlas.dynamic_images.insert(name.clone().into_owned(), Image {
+            width, height, data: data.clone(),
+        });
         if let Some((idx, rect)) = global_atlas.textures.get(&name) {
             // Update existing
             let img = Image {
@@ -155,6 +188,19 @@ impl Renderer {
         global_atlas.textures.insert(name.into_owned(), info);
     }
 
+    /// Replaces the objects stored on the GPU after the
+    /// context was lost
+    pub(super) fn recreate(&mut self, ctx: &mut pipeline::Context<'_>) {
+        let fresh = Renderer::new(&self.log, &self.assets, ctx);
+        self.clip_array = fresh.clip_array;
+        self._clip_buffer = fresh._clip_buffer;
+        self.font_texture = fresh.font_texture;
+        self.font_atlases.clear();
+        for font in self.fonts.borrow_mut().values_mut() {
+            font.chars.clear();
+        }
+    }
+
     pub fn layout(&mut self, manager: &mut Manager<UniverCityUI>, width: u32, height: u32) {
         // TODO: return value?
         self.width = (width as f32 * self.ui_scale) as u32;
@@ -168,6 +214,7 @@ impl Renderer {
         global_atlas: &mut super::GlobalAtlas,
     ) {
         // TODO: Don't do this all the time?
+        let _memory = memory::scope(memory::Category::Ui, "Interface", None);
 
         let view_matrix = cgmath::ortho(0.0, self.width as f32, self.height as f32, 0.0, -1.0, 10.0);
 
@@ -184,6 +231,7 @@ impl Renderer {
         manager.render(&mut Builder {
             log: &self.log,
             ui_scale: self.ui_scale,
+            text_scale: self.text_scale.get(),
             view_matrix,
             ctx,
             clip_array: &self.clip_array,
@@ -382,6 +430,7 @@ struct BorderVertex {
 struct Builder<'a, 'b> {
     log: &'b Logger,
     ui_scale: f32,
+    text_scale: f32,
     view_matrix: cgmath::Matrix4<f32>,
     clip_array: &'b gl::VertexArray,
     ctx: &'a mut pipeline::Context<'a>,
@@ -441,7 +490,9 @@ impl <'a, 'b> RenderVisitor<UniverCityUI> for Builder<'a, 'b> {
         let height = obj.draw_rect.height as f32;
 
         let render_loc = (position.0, position.1, width, height);
-        if obj.ext.render_loc != render_loc {
+        // Also rebuilt if they were created before the context was lost
+        let generation = gl::context_generation();
+        if obj.ext.render_loc != render_loc || obj.ext.render_generation != generation {
             obj.ext.image_render = None;
             obj.ext.box_render = None;
             obj.ext.text_render = None;
@@ -449,12 +500,14 @@ impl <'a, 'b> RenderVisitor<UniverCityUI> for Builder<'a, 'b> {
             obj.ext.shadow_render.clear();
 
             obj.ext.render_loc = render_loc;
+            obj.ext.render_generation = generation;
         }
 
         if let Some(text) = obj.value.text()
             .filter(|_| obj.ext.text_render.is_none() || obj.text_changed)
             .filter(|_| obj.ext.font_size > 0.0)
         {
+            let font_size = obj.ext.font_size * self.text_scale;
             obj.text_changed = false;
             // Can only render if we have a font to work with
             let fonts = &mut *self.fonts;
@@ -466,7 +519,7 @@ impl <'a, 'b> RenderVisitor<UniverCityUI> for Builder<'a, 'b> {
                 if obj.ext.text_splits.is_empty() {
                     obj.ext.text_splits.push((0, text.len(), obj.draw_rect));
                 }
-                let size = (obj.ext.font_size * 4.0).round() as i32;
+                let size = (font_size * 4.0).round() as i32;
                 let color = obj.ext.font_color;
 
                 let shadow_info = obj.ext.text_shadow.as_ref();
@@ -474,7 +527,7 @@ impl <'a, 'b> RenderVisitor<UniverCityUI> for Builder<'a, 'b> {
                 let mut shadow_verts = shadow_info.map(|v| (v, Vec::with_capacity(text.len() * 6)));
                 let mut verts = Vec::with_capacity(text.len() * 6);
 
-                let scale = rusttype::Scale::uniform(obj.ext.font_size);
+                let scale = rusttype::Scale::uniform(font_size);
 
                 let voffset = {
                     let m = font.font.v_metrics(scale);
@@ -943,7 +996,7 @@ impl <'a, 'b> Builder<'a, 'b> {
             self.log,
             self.assets,
             self.global_atlas,
-            img,
+            assets::TypedKey::from_key(img),
         );
 
         let atlas = atlas as u16;
@@ -1019,6 +1072,7 @@ pub fn deg<'a>(params: &mut (dyn Iterator<Item=FResult<'a, ui::Value>> + 'a)) ->
 struct Lined {
     assets: AssetManager,
     fonts: Rc<RefCell<FNVMap<String, Font>>>,
+    text_scale: Rc<Cell<f32>>,
 
     line_height: i32,
     recompute: bool,
@@ -1032,10 +1086,11 @@ struct Lined {
 }
 
 impl Lined {
-    fn new(assets: AssetManager, fonts: Rc<RefCell<FNVMap<String, Font>>>) -> Lined {
+    fn new(assets: AssetManager, fonts: Rc<RefCell<FNVMap<String, Font>>>, text_scale: Rc<Cell<f32>>) -> Lined {
         Lined {
             assets,
             fonts,
+            text_scale,
 
             line_height: 16,
             recompute: true,
@@ -1048,6 +1103,10 @@ impl Lined {
             word_wrap: true,
         }
     }
+
+    fn scaled_line_height(&self) -> i32 {
+        (self.line_height as f32 * self.text_scale.get()).round() as i32
+    }
 }
 
 struct LinedChild {
@@ -1183,7 +1242,7 @@ impl LayoutEngine<UniverCityUI> for Lined {
                     x: 0,
                     y: 0,
                     width: current.width,
-                    height: self.line_height,
+                    height: self.scaled_line_height(),
                 };
             }
         }
@@ -1200,7 +1259,7 @@ impl LayoutEngine<UniverCityUI> for Lined {
             for i in 0 .. children.len() {
                 let (r, _, _) = children.get(i).expect("Missing child");
                 self.bounded_size.width = max(self.bounded_size.width, r.x + r.width);
-                self.bounded_size.height = (self.line + 1) * self.line_height;
+                self.bounded_size.height = (self.line + 1) * self.scaled_line_height();
             }
         }
         self.recompute = false;
@@ -1225,7 +1284,7 @@ impl LayoutEngine<UniverCityUI> for Lined {
                 if let Some(font) = ext.font.as_ref()
                     .and_then(|font| load_font(&self.assets, fonts, font))
                 {
-                    let size = ext.font_size;
+                    let size = ext.font_size * self.text_scale.get();
                     ext.text_splits.clear();
                     if size <= 0.0 {
                         return current;
@@ -1269,9 +1328,9 @@ impl LayoutEngine<UniverCityUI> for Lined {
                                 current.0, current.1,
                                 Rect {
                                     x: self.width - self.remaining,
-                                    y: self.line * self.line_height,
+                                    y: self.line * self.scaled_line_height(),
                                     width: current_size.ceil() as i32,
-                                    height: self.line_height,
+                                    height: self.scaled_line_height(),
                                 }
                             ));
                             current.0 = word.0;
@@ -1300,9 +1359,9 @@ impl LayoutEngine<UniverCityUI> for Lined {
                         current.0, current.1,
                         Rect {
                             x: self.width - self.remaining,
-                            y: self.line * self.line_height,
+                            y: self.line * self.scaled_line_height(),
                             width: width,
-                            height: self.line_height,
+                            height: self.scaled_line_height(),
                         }
                     ));
                     self.remaining -= width;
@@ -1329,7 +1388,7 @@ impl LayoutEngine<UniverCityUI> for Lined {
                     self.remaining = self.width;
                 }
                 current.x = self.width - self.remaining;
-                current.y = self.line * self.line_height + (self.line_height - current.height) / 2;
+                current.y = self.line * self.scaled_line_height() + (self.scaled_line_height() - current.height) / 2;
 
                 self.remaining -= current.width;
                 data.planned = current;
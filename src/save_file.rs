@@ -148,11 +148,40 @@ impl <F> state::State for MenuState<F>
         evt.handle_event::<LoadGame, _>(|_| {
             if let Some(cur) = query!(ui, save_entry(entry=self.selected_item as i32)).next() {
                 let name = assume!(state.global_logger, cur.get_property_ref::<String>("name"));
-                if cur.get_property::<bool>("valid").unwrap_or(false) {
+                if !cur.get_property::<bool>("valid").unwrap_or(false) {
+                    return;
+                }
+                if server::saving::has_journal(&state.filesystem, &name, self.save_type) {
+                    let fs = crate::make_filesystem(#[cfg(feature = "steam")] &state.steam);
+                    let events = state.ui_manager.events.clone();
+                    let name = name.clone();
+                    action = state::Action::Push(Box::new(ui::prompt::Confirm::new(
+                        ui::prompt::ConfirmConfig {
+                            title: "Recovery".into(),
+                            description: format!(
+                                "\"{}\" wasn't closed properly. Do you wish to restore the progress made since it was last saved?",
+                                name
+                            ),
+                            accept: "Restore".into(),
+                            cancel: "Discard".into(),
+                        },
+                        move |rpl| {
+                            let _ = if rpl == ui::prompt::ConfirmResponse::Accept {
+                                server::saving::restore_journal(&fs, &name)
+                            } else {
+                                server::saving::discard_journal(&fs, &name)
+                            };
+                            events.borrow_mut().emit(StartSave(name.clone()));
+                        }
+                    )));
+                } else {
                     action = state::Action::Switch((self.start_func)(state, &*name));
                 }
             }
         });
+        evt.handle_event::<StartSave, _>(|StartSave(name)| {
+            action = state::Action::Switch((self.start_func)(state, &name));
+        });
         evt.handle_event::<DeleteEntry, _>(|DeleteEntry(idx)| {
             if let Some(cur) = query!(ui, save_entry(entry=idx as i32)).next() {
                 let fs = crate::make_filesystem(#[cfg(feature = "steam")] &state.steam);
@@ -210,6 +239,7 @@ impl <F> state::State for MenuState<F>
 
 struct NewGame;
 struct LoadGame;
+struct StartSave(String);
 struct SelectEntry(usize);
 struct DeleteEntry(usize);
 
@@ -94,6 +94,14 @@ function init_module_scope(mod_name, scope)
     scope.new_matrix = function()
         return new_matrix()
     end
+    scope.tutorial = lock_table({
+        show_step = function(step)
+            tutorial_show_step(step)
+        end,
+        clear = function()
+            tutorial_clear()
+        end,
+    })
 end
 
 function clear_module_state(mod_name)
@@ -64,7 +64,9 @@ impl Engine {
         level::init_levellib::<instance::scripting::Types>(&engine);
         audio::init_audiolib(&engine);
         mission::init_commandlib(&engine);
+        crate::server::player::init_campuslib(&engine);
         clientlib(&engine);
+        instance::tutorial::init_tutoriallib(&engine);
 
         engine.store_tracked::<Logger>(script::LuaLogger(engine.log.clone()));
         engine.store_tracked::<AssetManager>(asset_manager);
@@ -75,6 +77,13 @@ impl Engine {
         engine
     }
 
+    /// Compiles the scripts of every loaded pack on worker threads.
+    ///
+    /// See `script::precompile_packs`
+    pub fn precompile_packs(&self, asset_manager: &assets::AssetManager) {
+        script::precompile_packs(&self.log, &self.lua, asset_manager);
+    }
+
     /// Loads and inits the named pack's scripts.
     ///
     /// Currently panics when it fails to load
@@ -6,6 +6,8 @@ use crate::server::event;
 use crate::util::FNVMap;
 use std::any;
 use std::mem;
+use std::rc::Rc;
+use std::cell::RefCell;
 
 /// Manages a list of states the game is currently in
 /// or able to go back to.
@@ -522,3 +524,137 @@ pub trait State: any::Any {
     }
 }
 
+/// Controls the order subscribers see an event in.
+///
+/// Subscribers with a higher priority see the event first,
+/// subscribers with the same priority see it in the order
+/// they subscribed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Sees the event after all others
+    Low,
+    /// The default priority
+    Normal,
+    /// Sees the event before all others
+    High,
+}
+
+/// Returned by a subscriber to control whether the remaining
+/// subscribers see an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Handled {
+    /// Pass the event on to the next subscriber
+    Pass,
+    /// Stop the event from reaching any more subscribers
+    Consume,
+}
+
+/// A queue of events waiting to be delivered by an `EventBus`.
+///
+/// Cheap to clone so that anything that needs to emit events
+/// without access to the bus (e.g. ui scripts) can keep a copy.
+#[derive(Clone, Default)]
+pub struct EventQueue {
+    events: Rc<RefCell<Vec<Box<dyn any::Any>>>>,
+}
+
+impl EventQueue {
+    /// Queues the event to be delivered the next time the
+    /// bus delivers its events
+    pub fn emit<E: any::Any>(&self, evt: E) {
+        self.events.borrow_mut().push(Box::new(evt));
+    }
+}
+
+type Handler<C> = Rc<dyn Fn(&mut C, &dyn any::Any) -> Handled>;
+
+/// Delivers typed events between states, the instance and the
+/// game.
+///
+/// Subscribers are registered by the type of the event they
+/// handle and are passed the context `C` alongside the event
+/// when it is delivered.
+pub struct EventBus<C> {
+    queue: EventQueue,
+    subscribers: FNVMap<any::TypeId, Vec<(Priority, Handler<C>)>>,
+}
+
+impl <C> EventBus<C> {
+    /// Creates an event bus without any subscribers
+    pub fn new() -> EventBus<C> {
+        EventBus {
+            queue: EventQueue::default(),
+            subscribers: FNVMap::default(),
+        }
+    }
+
+    /// Returns the queue that events for this bus are
+    /// emitted to
+    pub fn queue(&self) -> EventQueue {
+        self.queue.clone()
+    }
+
+    /// Queues the event to be delivered the next time the
+    /// bus delivers its events
+    pub fn emit<E: any::Any>(&self, evt: E) {
+        self.queue.emit(evt);
+    }
+
+    /// Subscribes to events of the type `E`
+    pub fn subscribe<E, F>(&mut self, priority: Priority, func: F)
+        where E: any::Any,
+              F: Fn(&mut C, &E) -> Handled + 'static,
+              C: 'static,
+    {
+        let handler: Handler<C> = Rc::new(move |ctx: &mut C, evt: &dyn any::Any| {
+            match evt.downcast_ref::<E>() {
+                Some(evt) => func(ctx, evt),
+                None => Handled::Pass,
+            }
+        });
+        let subs = self.subscribers.entry(any::TypeId::of::<E>())
+            .or_insert_with(Vec::new);
+        let idx = subs.iter()
+            .position(|v| v.0 < priority)
+            .unwrap_or_else(|| subs.len());
+        subs.insert(idx, (priority, handler));
+    }
+
+    /// Removes the queued events along with their subscribers
+    /// so that they can be delivered.
+    ///
+    /// Events without any subscribers are dropped.
+    pub fn take_pending(&self) -> Vec<PendingEvent<C>> {
+        let events = mem::replace(&mut *self.queue.events.borrow_mut(), Vec::new());
+        events.into_iter()
+            .filter_map(|evt| {
+                let subscribers = self.subscribers.get(&(*evt).type_id())?
+                    .iter()
+                    .map(|v| v.1.clone())
+                    .collect();
+                Some(PendingEvent {
+                    event: evt,
+                    subscribers,
+                })
+            })
+            .collect()
+    }
+}
+
+/// An event removed from an `EventBus` waiting to be delivered
+#[must_use]
+pub struct PendingEvent<C> {
+    event: Box<dyn any::Any>,
+    subscribers: Vec<Handler<C>>,
+}
+
+impl <C> PendingEvent<C> {
+    /// Delivers the event to its subscribers in order of
+    /// priority until one consumes it.
+    ///
+    /// Returns whether the event was consumed.
+    pub fn deliver(self, ctx: &mut C) -> bool {
+        self.subscribers.iter()
+            .any(|v| v(ctx, &*self.event) == Handled::Consume)
+    }
+}
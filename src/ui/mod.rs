@@ -2,12 +2,15 @@
 
 mod layout;
 pub mod prompt;
+mod panels;
+pub use self::panels::{Dock, PanelPosition, HudLayout, HudLayouts, DEFAULT_LAYOUT};
 
 use crate::server::assets;
 use crate::render;
 use crate::server::event;
 use crate::server::lua::{self, Ref, Table, Scope, Function};
 use crate::script;
+use crate::state;
 use crate::prelude::*;
 
 use sdl2::keyboard::Keycode;
@@ -51,6 +54,17 @@ impl script::LuaTracked for Events {
     }
 }
 
+enum GameEvents {}
+impl lua::LuaUsable for state::EventQueue {}
+impl script::LuaTracked for GameEvents {
+    const KEY: script::NulledString = nul_str!("game_events_ref");
+    type Storage = state::EventQueue;
+    type Output = state::EventQueue;
+
+    fn try_convert(s: &Self::Storage) -> Option<Self::Output> {
+        Some(s.clone())
+    }
+}
 
 struct Tooltip {
     key: String,
@@ -132,6 +146,7 @@ define_ui_events! {
     on_unfocus = FocusEvent;
 
     on_char_input = CharInputEvent;
+    on_text_edit = TextEditEvent;
     on_key_down = KeyDownEvent;
     on_key_up = KeyUpEvent;
 
@@ -156,6 +171,8 @@ pub struct NodeData {
     // Rendering
 
     pub(crate) render_loc: (f32, f32, f32, f32),
+    /// The generation of the context the renders were created in
+    pub(crate) render_generation: u32,
 
     pub(crate) image: Option<String>,
     pub(crate) image_render: Option<render::ui::ImageRender>,
@@ -206,6 +223,7 @@ impl fungui::Extension for UniverCityUI {
             can_focus: false,
 
             render_loc: Default::default(),
+            render_generation: render::gl::context_generation(),
 
             image: None,
             image_render: None,
@@ -453,10 +471,14 @@ pub struct Manager {
     pub manager: Rc<RefCell<fungui::Manager<UniverCityUI>>>,
     /// Contains events emitted by ui elements
     pub events: Rc<RefCell<event::Container>>,
+    /// Events emitted by ui scripts that are handled
+    /// by the game
+    game_events: state::EventQueue,
 
     tooltip: Rc<RefCell<Option<Tooltip>>>,
 
     current_focus: Option<WeakNode>,
+    composition: Option<Node>,
     last_hover: Option<WeakNode>,
     assets: AssetManager,
     /// The scripting engine used by the UI system.
@@ -466,6 +488,13 @@ pub struct Manager {
 
     style_groups: FNVMap<ResourceKey<'static>, Vec<String>>,
 
+    layouts: HudLayouts,
+    layout_edit: bool,
+    panel_drag: Option<panels::PanelDrag>,
+    // The size of the screen in ui pixels, used to keep docked
+    // panels attached to their edges
+    screen_size: (i32, i32),
+
     // Used for init/deinit checking
     cycle: bool,
     nodes: Vec<Node>,
@@ -481,7 +510,7 @@ fn list<'a>(args: &mut (dyn Iterator<Item = fungui::FResult<'a, Value>> + 'a)) -
 impl Manager {
     /// Creates a new ui manager that loads the ui descriptions from
     /// the passed asset maanger.
-    pub fn new(log: Logger, asset_manager: AssetManager) -> Manager {
+    pub fn new(log: Logger, asset_manager: AssetManager, game_events: state::EventQueue) -> Manager {
         Manager {
             scripting: script::Engine::empty(&log),
             log,
@@ -502,14 +531,21 @@ impl Manager {
                 manager
             })),
             events: Rc::new(RefCell::new(event::Container::new())),
+            game_events,
 
             tooltip: Rc::new(RefCell::new(None)),
 
             current_focus: None,
+            composition: None,
             last_hover: None,
 
             style_groups: FNVMap::default(),
 
+            layouts: HudLayouts::default(),
+            layout_edit: false,
+            panel_drag: None,
+            screen_size: (0, 0),
+
             cycle: false,
             nodes: Vec::new(),
         }
@@ -520,27 +556,47 @@ impl Manager {
         self.ui_scale = scale;
     }
 
-    /// Handles text boxes
-    pub fn update(&mut self, renderer: &mut render::Renderer, delta: f64) {
+    /// Handles text boxes and runs the `on_update` events.
+    ///
+    /// `on_update` runs once for each of the fixed `steps` being
+    /// passed the length of a step as its delta so that animations
+    /// run at the same speed at any frame rate
+    pub fn update(&mut self, renderer: &mut render::Renderer, steps: u32, step: f64) {
         crate::server::script::handle_reloads(&self.log, &self.scripting, &self.assets);
-        if let Some(node) = self.manager.borrow().query()
+        let focused = self.manager.borrow().query()
             .property("focused", true)
-            .next()
-        {
+            .next();
+        let mut text_input = false;
+        if let Some(node) = focused {
             let n = node.borrow();
             if !n.ext.events.borrow().on_char_input.is_empty() {
+                text_input = true;
                 if let Some(rect) = node.render_position() {
+                    // The input method's candidate window is positioned
+                    // in window space, not ui space
                     renderer.mark_text_input(
-                        rect.x,
-                        rect.y,
-                        rect.width,
-                        rect.height,
+                        ((rect.x as f32) / self.ui_scale) as i32,
+                        ((rect.y as f32) / self.ui_scale) as i32,
+                        ((rect.width as f32) / self.ui_scale) as i32,
+                        ((rect.height as f32) / self.ui_scale) as i32,
                     );
                 }
             }
         } else {
             self.current_focus = None;
         }
+        if !text_input {
+            self.clear_composition();
+        }
+
+        let screen_size = (
+            (renderer.width as f32 * self.ui_scale) as i32,
+            (renderer.height as f32 * self.ui_scale) as i32,
+        );
+        if screen_size != self.screen_size {
+            self.screen_size = screen_size;
+            self.apply_layout();
+        }
 
         self.cycle = !self.cycle;
         let scripting = self.scripting.clone();
@@ -550,10 +606,17 @@ impl Manager {
             node.raw_set_property("$cycle", self.cycle);
             if node.has_layout() && node.get_property::<bool>("$init").is_none() {
                 node.raw_set_property("$init", true);
+                if let Some(name) = node.get_property::<String>("panel") {
+                    let pos = self.layouts.active().panels.get(&name).cloned();
+                    panels::apply_position(&node, pos, self.screen_size);
+                    node.set_property("layout_edit", self.layout_edit);
+                }
                 invoke_event(&self.log, &mut events, &scripting, &node, |v| &mut v.on_init, &());
                 self.nodes.push(node.clone());
             }
-            invoke_event(&self.log, &mut events, &scripting, &node, |v| &mut v.on_update, &delta);
+            for _ in 0 .. steps {
+                invoke_event(&self.log, &mut events, &scripting, &node, |v| &mut v.on_update, &step);
+            }
         }
 
         let cycle = self.cycle;
@@ -571,6 +634,28 @@ impl Manager {
         self.events.borrow_mut().join(events);
     }
 
+    /// Loads the default style rules from every pack.
+    ///
+    /// When `high_contrast` is set the `high_contrast` style rules
+    /// are loaded on top of the defaults for any pack that provides
+    /// them, otherwise they are unloaded.
+    pub fn load_pack_styles(&mut self, high_contrast: bool) {
+        for pack in self.assets.get_packs() {
+            self.load_styles(ResourceKey::new(pack.clone(), "default"));
+            let key = ResourceKey::new(pack, "high_contrast");
+            if high_contrast {
+                if self.assets.open_from_pack(key.module_key(), "ui/high_contrast.list").is_ok() {
+                    self.load_styles(key);
+                }
+            } else {
+                let manager: &mut fungui::Manager<_> = &mut *self.manager.borrow_mut();
+                for old in self.style_groups.remove(&key).into_iter().flat_map(|v| v) {
+                    manager.remove_styles(&old);
+                }
+            }
+        }
+    }
+
     /// Loads the named style rules
     pub fn load_styles(&mut self, key: ResourceKey<'_>) {
         use std::io::Read;
@@ -660,6 +745,14 @@ impl Manager {
         self.manager.borrow_mut().remove_node(node);
     }
 
+    /// Returns the first node with the given id
+    pub fn find_node(&self, id: &str) -> Option<Node> {
+        self.manager.borrow().query()
+            .property("id", id.to_owned())
+            .matches()
+            .next()
+    }
+
     /// Handles events targetting the focused element
     pub fn focused_event<E>(&mut self, param: E::Param) -> bool
         where E: Event + 'static,
@@ -675,6 +768,71 @@ impl Manager {
         false
     }
 
+    /// Handles text being composed by an input method editor.
+    ///
+    /// The focused element is informed of the change and a preview
+    /// of the composition is displayed below it until the text is
+    /// committed or the composition is cancelled.
+    pub fn text_editing(&mut self, text: &str, start: i32, length: i32) {
+        self.focused_event::<TextEditEvent>(TextEdit {
+            text: text.to_owned(),
+            start,
+            length,
+        });
+        if text.is_empty() {
+            self.clear_composition();
+            return;
+        }
+        let rect = match self.current_focus
+            .as_ref()
+            .and_then(|v| v.upgrade())
+            .and_then(|v| v.render_position())
+        {
+            Some(rect) => rect,
+            None => return,
+        };
+
+        if let Some(preview) = self.composition.as_ref()
+            .and_then(|v| query!(v, ime_composition_holder > ime_composition).next())
+        {
+            preview.set_property("x", rect.x);
+            preview.set_property("y", rect.y + rect.height);
+            preview.set_property("cursor", start);
+            preview.set_property("selection", length);
+            if let Some(txt) = query!(preview, ime_composition > @text).next() {
+                txt.set_text(text);
+            }
+            return;
+        }
+
+        let node = node!{
+            ime_composition_holder {
+                ime_composition(x=rect.x, y=rect.y + rect.height, cursor=start, selection=length) {
+                    @text(text)
+                }
+            }
+        };
+        self.manager.borrow_mut().add_node(node.clone());
+        self.composition = Some(node);
+    }
+
+    /// Removes the input method composition preview if one is
+    /// being displayed
+    pub fn clear_composition(&mut self) {
+        if let Some(node) = self.composition.take() {
+            self.manager.borrow_mut().remove_node(node);
+        }
+    }
+
+    /// Returns whether an input method editor is currently
+    /// composing text for the focused element.
+    ///
+    /// Key presses during composition belong to the input method
+    /// and shouldn't be handled by the ui or game.
+    pub fn is_composing(&self) -> bool {
+        self.composition.is_some()
+    }
+
     /// Handles mouse move events
     pub fn mouse_event<E>(&mut self, x: i32, y: i32, param: E::Param) -> bool
         where E: Event + 'static,
@@ -748,6 +906,123 @@ impl Manager {
         false
     }
 
+    /// Replaces the player's panel layouts and moves any
+    /// existing panels to match
+    pub fn set_layouts(&mut self, mut layouts: HudLayouts) {
+        layouts.validate();
+        self.layouts = layouts;
+        self.apply_layout();
+    }
+
+    /// Returns the player's panel layouts
+    pub fn layouts(&self) -> &HudLayouts {
+        &self.layouts
+    }
+
+    /// Changes the player's panel layouts and moves any existing
+    /// panels to match
+    pub fn edit_layouts<F>(&mut self, func: F)
+        where F: FnOnce(&mut HudLayouts)
+    {
+        func(&mut self.layouts);
+        self.layouts.validate();
+        self.apply_layout();
+    }
+
+    fn apply_layout(&self) {
+        let layout = self.layouts.active();
+        let nodes = self.manager.borrow().query()
+            .matches()
+            .filter_map(|v| v.get_property::<String>("panel").map(|n| (n, v)))
+            .collect::<Vec<_>>();
+        for (name, node) in nodes {
+            panels::apply_position(&node, layout.panels.get(&name).cloned(), self.screen_size);
+        }
+    }
+
+    /// Enables or disables moving panels with the mouse
+    pub fn set_layout_edit(&mut self, edit: bool) {
+        self.layout_edit = edit;
+        self.panel_drag = None;
+        let nodes = self.manager.borrow().query()
+            .matches()
+            .filter(|v| v.get_property::<String>("panel").is_some())
+            .collect::<Vec<_>>();
+        for node in nodes {
+            node.set_property("layout_edit", edit);
+        }
+    }
+
+    /// Returns whether panels can currently be moved with the mouse
+    pub fn is_layout_edit(&self) -> bool {
+        self.layout_edit
+    }
+
+    /// Starts moving the panel under the mouse if in layout edit
+    /// mode. Returns whether the click was used
+    pub fn panel_mouse_down(&mut self, x: i32, y: i32) -> bool {
+        if !self.layout_edit {
+            return false;
+        }
+        let x = ((x as f32) * self.ui_scale) as i32;
+        let y = ((y as f32) * self.ui_scale) as i32;
+        let matches = {
+            let manager = self.manager.borrow();
+            manager.query_at(x, y).matches()
+        };
+        for node in matches {
+            if let Some((name, panel)) = panels::find_panel(&node) {
+                let rect = match panel.render_position() {
+                    Some(rect) => rect,
+                    None => continue,
+                };
+                panel.set_property("dragging", true);
+                self.panel_drag = Some(panels::PanelDrag {
+                    node: panel.weak(),
+                    name,
+                    offset: (x - rect.x, y - rect.y),
+                    size: (rect.width, rect.height),
+                });
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Moves the panel being dragged, if any. Returns whether
+    /// the movement was used
+    pub fn panel_mouse_move(&mut self, x: i32, y: i32) -> bool {
+        let drag = match self.panel_drag.as_ref() {
+            Some(drag) => drag,
+            None => return false,
+        };
+        let x = ((x as f32) * self.ui_scale) as i32;
+        let y = ((y as f32) * self.ui_scale) as i32;
+        if let Some(node) = drag.node.upgrade() {
+            let pos = PanelPosition::dropped(x - drag.offset.0, y - drag.offset.1, drag.size, self.screen_size);
+            panels::apply_position(&node, Some(pos), self.screen_size);
+        }
+        true
+    }
+
+    /// Drops the panel being dragged, if any, saving its position
+    /// in the current layout. Returns whether a panel was moved
+    pub fn panel_mouse_up(&mut self, x: i32, y: i32) -> bool {
+        let drag = match self.panel_drag.take() {
+            Some(drag) => drag,
+            None => return false,
+        };
+        let x = ((x as f32) * self.ui_scale) as i32;
+        let y = ((y as f32) * self.ui_scale) as i32;
+        let pos = PanelPosition::dropped(x - drag.offset.0, y - drag.offset.1, drag.size, self.screen_size);
+        if let Some(node) = drag.node.upgrade() {
+            node.set_property("dragging", false);
+            panels::apply_position(&node, Some(pos), self.screen_size);
+        }
+        self.layouts.active_mut().panels.insert(drag.name, pos);
+        true
+    }
+
     // Proxy methods to Elements
 
     /// Returns the event container
@@ -761,6 +1036,7 @@ impl Manager {
     pub fn set_script_engine(&mut self, audio: &AudioManager, engine: &script::Engine) {
         engine.store_tracked::<SManager>(Rc::downgrade(&self.manager));
         engine.store_tracked::<Events>(Rc::downgrade(&self.events));
+        engine.store_tracked::<GameEvents>(self.game_events.clone());
         engine.store_tracked::<Tooltip>(Rc::downgrade(&self.tooltip));
         engine.store_tracked::<AudioController>(Rc::downgrade(&audio.controller));
         self.scripting = engine.clone();
@@ -793,6 +1069,7 @@ impl Manager {
     pub fn clear_script_engine(&mut self, audio: &AudioManager) {
         let scripting = script::Engine::new(&self.log, self.assets.clone());
         self.set_script_engine(audio, &scripting);
+        scripting.precompile_packs(&self.assets);
         for pack in self.assets.get_packs() {
             scripting.init_pack(pack.module());
         }
@@ -805,6 +1082,7 @@ impl Manager {
 
     /// Focuses the passed node
     pub fn focus_node(&mut self, node: Node) {
+        self.clear_composition();
         if let Some(current) = self.current_focus
             .as_ref()
             .and_then(|v| v.upgrade())
@@ -854,6 +1132,34 @@ impl Manager {
         self.events.borrow_mut().join(events);
     }
 
+    /// Returns a plain text description of the focused element
+    /// for narration if an element is focused.
+    ///
+    /// The description is the element's name followed by any
+    /// text contained within it.
+    pub fn describe_focused(&self) -> Option<String> {
+        fn collect_text(node: &Node, out: &mut Vec<String>) {
+            if let Some(txt) = node.text() {
+                let txt = txt.trim();
+                if !txt.is_empty() {
+                    out.push(txt.to_owned());
+                }
+            }
+            for c in node.children() {
+                collect_text(&c, out);
+            }
+        }
+        let node = self.current_focus
+            .as_ref()
+            .and_then(|v| v.upgrade())?;
+        let mut parts = Vec::new();
+        if let Some(name) = node.name() {
+            parts.push(name.replace('_', " "));
+        }
+        collect_text(&node, &mut parts);
+        Some(parts.join(", "))
+    }
+
     /// Returns the key of the current tooltip
     pub fn current_tooltip(&mut self) -> Option<String> {
         let tooltip = self.tooltip.borrow();
@@ -1313,6 +1619,39 @@ impl Event for CharInputEvent {
     }
 }
 
+/// Parameter to events that report the text currently
+/// being composed by an input method editor.
+pub struct TextEdit {
+    /// The text being composed. Empty once composition ends
+    pub text: String,
+    /// The position of the editing cursor within the text
+    pub start: i32,
+    /// The number of characters selected from the cursor
+    pub length: i32,
+}
+
+impl EventParam for TextEdit {
+    fn as_lua_table(&self, lua: &lua::Lua) -> Ref<Table> {
+        let tbl = Ref::new_table(lua);
+        tbl.insert(Ref::new_string(lua, "text"), Ref::new_string(lua, self.text.as_str()));
+        tbl.insert(Ref::new_string(lua, "start"), self.start);
+        tbl.insert(Ref::new_string(lua, "length"), self.length);
+        tbl
+    }
+}
+
+/// Event that is fired when the text being composed by an
+/// input method editor changes
+pub enum TextEditEvent {}
+
+impl Event for TextEditEvent {
+    type Param = TextEdit;
+
+    fn event_funcs(data: &mut NodeEvents) -> &mut [MethodDesc<Self>] {
+        &mut data.on_text_edit
+    }
+}
+
 /// Parameter to events that invoke a key being
 /// pressed.
 pub struct KeyInput {
@@ -1642,28 +1981,30 @@ pub fn init_uilib(state: &lua::Lua) {
 
     // Event handling
     state.set(Scope::Global, "ui_emit_0", lua::closure1(|lua, evt: Ref<String>| -> UResult<()> {
-        let events = lua.get_tracked::<Events>()
+        let game_events = lua.get_tracked::<GameEvents>()
             .ok_or_else(|| ErrorKind::UINotBound)?;
-        let mut events = events.borrow_mut();
         match &evt[..] {
-            "exit_game" => events.emit(crate::ExitGame),
+            "exit_game" => game_events.emit(crate::ExitGame),
             _ => bail!("unknown event type {:?}", evt),
         }
         Ok(())
     }));
     state.set(Scope::Global, "ui_emit_1", lua::closure2(|lua, evt: Ref<String>, p1: Ref<String>| -> UResult<()> {
+        let game_events = lua.get_tracked::<GameEvents>()
+            .ok_or_else(|| ErrorKind::UINotBound)?;
         let events = lua.get_tracked::<Events>()
             .ok_or_else(|| ErrorKind::UINotBound)?;
         let mut events = events.borrow_mut();
         match &evt[..] {
-            "switch_menu" => events.emit(crate::SwitchMenu(p1.to_string())),
-            "set_cursor" => events.emit(crate::SetCursor(LazyResourceKey::parse(&p1)
+            "switch_menu" => game_events.emit(crate::SwitchMenu(p1.to_string())),
+            "set_cursor" => game_events.emit(crate::SetCursor(LazyResourceKey::parse(&p1)
                 .or_module(ModuleKey::new("base"))
                 .into_owned())),
             "open" => match &p1[..] {
                 "room_buy" => events.emit(crate::instance::OpenBuyRoomMenu),
                 "staff_buy" => events.emit(crate::instance::OpenBuyStaffMenu),
                 "staff_list" => events.emit(crate::instance::OpenStaffListMenu),
+                "directory" => events.emit(crate::instance::OpenDirectoryMenu),
                 "stats" => events.emit(crate::instance::OpenStatsMenu),
                 "settings" => events.emit(crate::instance::OpenSettingsMenu),
                 "courses" => events.emit(crate::instance::OpenCoursesMenu),
@@ -1741,6 +2082,8 @@ pub enum UValue {
     /// Event handlers
     MethodCharInput(Vec<MethodDesc<CharInputEvent>>),
     /// Event handlers
+    MethodTextEdit(Vec<MethodDesc<TextEditEvent>>),
+    /// Event handlers
     MethodKeyUp(Vec<MethodDesc<KeyUpEvent>>),
     /// Event handlers
     MethodKeyDown(Vec<MethodDesc<KeyDownEvent>>),
@@ -1869,6 +2212,7 @@ impl fungui::ConvertValue<UniverCityUI> for Vec<Value> {
 }
 
 value_method!(CharInputEvent, MethodCharInput);
+value_method!(TextEditEvent, MethodTextEdit);
 value_method!(KeyUpEvent, MethodKeyUp);
 value_method!(KeyDownEvent, MethodKeyDown);
 value_method!(MouseDownEvent, MethodMouseDown);
@@ -0,0 +1,257 @@
+//! Player arranged positions of HUD panels.
+//!
+//! Any node with a `panel` property can be moved by the player
+//! whilst the ui manager is in layout edit mode. Panels dropped
+//! near the edge of the screen dock to it and stay attached to
+//! that edge when the window is resized. Positions are applied to
+//! the panel via the `panel_x`, `panel_y` and `dock` properties
+//! for the styles to use, panels without a saved position have
+//! `panel_custom` unset and keep their default position.
+
+use std::collections::BTreeMap;
+use crate::ui::*;
+
+/// The name of the layout that always exists
+pub const DEFAULT_LAYOUT: &str = "Default";
+/// How close to the edge of the screen in ui pixels a panel
+/// has to be dropped to dock to it
+pub const DOCK_DISTANCE: i32 = 24;
+
+/// The edge or corner of the screen that a panel is attached to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dock {
+    /// Not attached to any edge
+    Free,
+    /// Attached to the left edge
+    Left,
+    /// Attached to the right edge
+    Right,
+    /// Attached to the top edge
+    Top,
+    /// Attached to the bottom edge
+    Bottom,
+    /// Attached to the top left corner
+    TopLeft,
+    /// Attached to the top right corner
+    TopRight,
+    /// Attached to the bottom left corner
+    BottomLeft,
+    /// Attached to the bottom right corner
+    BottomRight,
+}
+
+impl Dock {
+    /// Returns the name of the dock as used by styles
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Dock::Free => "free",
+            Dock::Left => "left",
+            Dock::Right => "right",
+            Dock::Top => "top",
+            Dock::Bottom => "bottom",
+            Dock::TopLeft => "top_left",
+            Dock::TopRight => "top_right",
+            Dock::BottomLeft => "bottom_left",
+            Dock::BottomRight => "bottom_right",
+        }
+    }
+
+    fn right(self) -> bool {
+        matches!(self, Dock::Right | Dock::TopRight | Dock::BottomRight)
+    }
+
+    fn bottom(self) -> bool {
+        matches!(self, Dock::Bottom | Dock::BottomLeft | Dock::BottomRight)
+    }
+}
+
+/// Where a single panel is placed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PanelPosition {
+    /// The horizontal offset of the panel from the left
+    /// edge of the screen, or from the right edge if docked
+    /// to the right
+    pub x: i32,
+    /// The vertical offset of the panel from the top edge
+    /// of the screen, or from the bottom edge if docked to
+    /// the bottom
+    pub y: i32,
+    /// The edge the panel is attached to
+    pub dock: Dock,
+}
+
+impl PanelPosition {
+    /// Computes the position of a panel dropped with its
+    /// top left corner at `x`, `y`
+    pub fn dropped(x: i32, y: i32, size: (i32, i32), screen: (i32, i32)) -> PanelPosition {
+        let x = x.max(0).min((screen.0 - size.0).max(0));
+        let y = y.max(0).min((screen.1 - size.1).max(0));
+        let right_gap = screen.0 - (x + size.0);
+        let bottom_gap = screen.1 - (y + size.1);
+
+        let left = x <= DOCK_DISTANCE;
+        let right = !left && right_gap <= DOCK_DISTANCE;
+        let top = y <= DOCK_DISTANCE;
+        let bottom = !top && bottom_gap <= DOCK_DISTANCE;
+
+        let dock = match (left, right, top, bottom) {
+            (true, _, true, _) => Dock::TopLeft,
+            (true, _, _, true) => Dock::BottomLeft,
+            (_, true, true, _) => Dock::TopRight,
+            (_, true, _, true) => Dock::BottomRight,
+            (true, ..) => Dock::Left,
+            (_, true, ..) => Dock::Right,
+            (_, _, true, _) => Dock::Top,
+            (_, _, _, true) => Dock::Bottom,
+            _ => Dock::Free,
+        };
+        PanelPosition {
+            x: if left || right { 0 } else { x },
+            y: if top || bottom { 0 } else { y },
+            dock,
+        }
+    }
+
+    /// Returns the top left corner of a panel of the given
+    /// size at this position
+    pub fn resolve(self, size: (i32, i32), screen: (i32, i32)) -> (i32, i32) {
+        let x = if self.dock.right() {
+            screen.0 - size.0 - self.x
+        } else {
+            self.x
+        };
+        let y = if self.dock.bottom() {
+            screen.1 - size.1 - self.y
+        } else {
+            self.y
+        };
+        (
+            x.max(0).min((screen.0 - size.0).max(0)),
+            y.max(0).min((screen.1 - size.1).max(0)),
+        )
+    }
+}
+
+/// The positions of every moved panel. Panels not in the
+/// layout are left where the styles place them
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HudLayout {
+    /// The position of each panel by its `panel` property
+    pub panels: BTreeMap<String, PanelPosition>,
+}
+
+/// A player's named layouts and the one currently in use
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HudLayouts {
+    /// The name of the layout in use
+    pub active: String,
+    /// Every saved layout by name
+    pub layouts: BTreeMap<String, HudLayout>,
+}
+
+impl Default for HudLayouts {
+    fn default() -> HudLayouts {
+        let mut layouts = BTreeMap::new();
+        layouts.insert(DEFAULT_LAYOUT.to_owned(), HudLayout::default());
+        HudLayouts {
+            active: DEFAULT_LAYOUT.to_owned(),
+            layouts,
+        }
+    }
+}
+
+impl HudLayouts {
+    /// Returns the layout currently in use
+    pub fn active(&self) -> &HudLayout {
+        self.layouts.get(&self.active)
+            .or_else(|| self.layouts.get(DEFAULT_LAYOUT))
+            .expect("Missing default layout")
+    }
+
+    /// Returns the layout currently in use for editing
+    pub fn active_mut(&mut self) -> &mut HudLayout {
+        self.layouts.entry(self.active.clone())
+            .or_insert_with(HudLayout::default)
+    }
+
+    /// Switches to the named layout, returning whether it exists
+    pub fn select(&mut self, name: &str) -> bool {
+        if self.layouts.contains_key(name) {
+            self.active = name.to_owned();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Saves a copy of the current layout with the given name
+    /// and switches to it
+    pub fn save_as(&mut self, name: &str) {
+        let layout = self.active().clone();
+        self.layouts.insert(name.to_owned(), layout);
+        self.active = name.to_owned();
+    }
+
+    /// Removes the current layout and switches back to the
+    /// default. The default layout can't be removed
+    pub fn remove_active(&mut self) {
+        if self.active != DEFAULT_LAYOUT {
+            self.layouts.remove(&self.active);
+            self.active = DEFAULT_LAYOUT.to_owned();
+        }
+    }
+
+    /// Returns every panel in the current layout to its
+    /// default position
+    pub fn reset_active(&mut self) {
+        self.active_mut().panels.clear();
+    }
+
+    /// Fixes up layouts loaded from an older or hand edited
+    /// config
+    pub fn validate(&mut self) {
+        self.layouts.entry(DEFAULT_LAYOUT.to_owned())
+            .or_insert_with(HudLayout::default);
+        if !self.layouts.contains_key(&self.active) {
+            self.active = DEFAULT_LAYOUT.to_owned();
+        }
+    }
+}
+
+/// A panel being moved by the player
+pub(super) struct PanelDrag {
+    pub(super) node: WeakNode,
+    pub(super) name: String,
+    pub(super) offset: (i32, i32),
+    pub(super) size: (i32, i32),
+}
+
+/// Returns the name of the panel the node is part of along with
+/// the panel's node
+pub(super) fn find_panel(node: &Node) -> Option<(String, Node)> {
+    let mut current = Some(node.clone());
+    while let Some(node) = current {
+        if let Some(name) = node.get_property::<String>("panel") {
+            return Some((name, node));
+        }
+        current = node.parent();
+    }
+    None
+}
+
+/// Updates the properties of the panel to match the position
+pub(super) fn apply_position(node: &Node, pos: Option<PanelPosition>, screen: (i32, i32)) {
+    if let Some(pos) = pos {
+        let size = node.render_position()
+            .map_or((0, 0), |v| (v.width, v.height));
+        let (x, y) = pos.resolve(size, screen);
+        node.set_property("panel_custom", true);
+        node.set_property("panel_x", x);
+        node.set_property("panel_y", y);
+        node.set_property("dock", pos.dock.as_str().to_owned());
+    } else {
+        node.set_property("panel_custom", false);
+        node.set_property("dock", Dock::Free.as_str().to_owned());
+    }
+}
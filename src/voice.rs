@@ -0,0 +1,147 @@
+//! Voice chat between players using steam's voice API.
+//!
+//! Voice is recorded whilst push to talk is held and sent to
+//! the server over the game's existing connection which relays
+//! it to the other players. Each speaking player gets their own
+//! stream in the mixer, controlled by the voice volume.
+
+use crate::prelude::*;
+use crate::server::steamworks;
+use univercity_audio::{self, StreamWriter, SoundRef};
+
+/// The sample rate voice is decompressed at
+const VOICE_SAMPLE_RATE: u32 = 22_050;
+/// How long a player can be silent for before their stream
+/// is removed from the mixer
+const SPEAKER_TIMEOUT: Duration = Duration::from_secs(5);
+/// The amount of buffered samples before new voice is dropped
+/// to prevent the delay growing if we fall behind
+const MAX_BUFFERED: usize = VOICE_SAMPLE_RATE as usize;
+
+/// Records and plays voice for the current game
+pub struct VoiceChat {
+    log: Logger,
+    recording: bool,
+    muted: FNVSet<player::Id>,
+    speakers: FNVMap<player::Id, Speaker>,
+}
+
+struct Speaker {
+    writer: StreamWriter,
+    sound: SoundRef,
+    last_voice: Instant,
+}
+
+impl VoiceChat {
+    /// Creates a voice chat that isn't recording
+    pub fn new(log: &Logger) -> VoiceChat {
+        VoiceChat {
+            log: log.new(o!("source" => "voice_chat")),
+            recording: false,
+            muted: FNVSet::default(),
+            speakers: FNVMap::default(),
+        }
+    }
+
+    /// Starts or stops recording the player's voice
+    pub fn set_recording(&mut self, steam: &steamworks::Client, recording: bool) {
+        if self.recording == recording {
+            return;
+        }
+        self.recording = recording;
+        let user = steam.user();
+        if recording {
+            user.start_voice_recording();
+        } else {
+            // Steam keeps returning voice for a short time after
+            // this so the end of what was said isn't cut off
+            user.stop_voice_recording();
+        }
+    }
+
+    /// Returns whether the player's voice is being recorded
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Returns whether the passed player has been muted
+    pub fn is_muted(&self, player: player::Id) -> bool {
+        self.muted.contains(&player)
+    }
+
+    /// Toggles whether the passed player is muted returning
+    /// whether they are now muted
+    pub fn toggle_mute(&mut self, player: player::Id) -> bool {
+        if self.muted.remove(&player) {
+            false
+        } else {
+            self.muted.insert(player);
+            if let Some(speaker) = self.speakers.remove(&player) {
+                speaker.sound.stop();
+            }
+            true
+        }
+    }
+
+    /// Returns any compressed voice that has been recorded
+    /// since the last call
+    pub fn capture(&mut self, steam: &steamworks::Client) -> Option<Vec<u8>> {
+        let user = steam.user();
+        match user.get_available_voice() {
+            Ok(0) | Err(_) => None,
+            Ok(_) => match user.get_voice() {
+                Ok(data) => if data.is_empty() { None } else { Some(data) },
+                Err(err) => {
+                    debug!(self.log, "Failed to get recorded voice"; "error" => ?err);
+                    None
+                }
+            },
+        }
+    }
+
+    /// Decompresses the voice of another player and queues it
+    /// to be played
+    pub fn play(&mut self, steam: &steamworks::Client, audio: &AudioManager, player: player::Id, data: &[u8]) {
+        if self.is_muted(player) {
+            return;
+        }
+        let pcm = match steam.user().decompress_voice(data, VOICE_SAMPLE_RATE) {
+            Ok(pcm) => pcm,
+            Err(err) => {
+                debug!(self.log, "Failed to decompress voice"; "player" => ?player, "error" => ?err);
+                return;
+            }
+        };
+        let samples: Vec<i16> = pcm.chunks_exact(2)
+            .map(|v| i16::from_le_bytes([v[0], v[1]]))
+            .collect();
+
+        let speaker = self.speakers.entry(player)
+            .or_insert_with(|| {
+                let (writer, source) = univercity_audio::stream(VOICE_SAMPLE_RATE);
+                let sound = audio.controller.borrow_mut().play_voice(source);
+                Speaker {
+                    writer,
+                    sound,
+                    last_voice: Instant::now(),
+                }
+            });
+        speaker.last_voice = Instant::now();
+        if speaker.writer.buffered() < MAX_BUFFERED {
+            speaker.writer.write(&samples);
+        }
+    }
+
+    /// Removes the streams of players that have stopped talking
+    pub fn tick(&mut self) {
+        self.speakers.retain(|_, v| v.last_voice.elapsed() < SPEAKER_TIMEOUT && !v.sound.has_ended());
+    }
+
+    /// Stops all playing voices. Used when leaving a game
+    pub fn clear(&mut self, steam: &steamworks::Client) {
+        self.set_recording(steam, false);
+        for (_, speaker) in self.speakers.drain() {
+            speaker.sound.stop();
+        }
+    }
+}
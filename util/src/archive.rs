@@ -0,0 +1,256 @@
+//! A single file archive of assets with an index.
+//!
+//! Opening a pack made of thousands of small files is slow on some
+//! systems (spinning disks, virus scanners checking each file) so
+//! packs can be shipped packed into a single file instead. The file
+//! starts with a header, followed by the data of each entry and
+//! finally an index of the entries:
+//!
+//! * header: `UCPK`, the format version (`u32`) and the offset of
+//!   the index (`u64`)
+//! * index: the number of entries (`u32`) followed by each entry's
+//!   path (`u16` length then utf-8), compression (`u8`), offset (`u64`),
+//!   stored length (`u64`) and original length (`u64`)
+//!
+//! All numbers are little endian. Writing the index last allows the
+//! archive to be written in a single pass.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// The bytes every archive starts with
+pub const ARCHIVE_MAGIC: [u8; 4] = *b"UCPK";
+/// The current version of the archive format
+pub const ARCHIVE_VERSION: u32 = 1;
+/// The size of the archive's header in bytes
+const HEADER_SIZE: u64 = 16;
+
+/// How an entry's data is stored in the archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveCompression {
+    /// Stored as is, allowing it to be read from the archive
+    /// directly
+    None,
+    /// Compressed with raw deflate
+    Deflate,
+}
+
+impl ArchiveCompression {
+    fn id(self) -> u8 {
+        match self {
+            ArchiveCompression::None => 0,
+            ArchiveCompression::Deflate => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<ArchiveCompression> {
+        Some(match id {
+            0 => ArchiveCompression::None,
+            1 => ArchiveCompression::Deflate,
+            _ => return None,
+        })
+    }
+}
+
+/// A file stored in an archive
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveEntry {
+    /// The path of the file, `module/path/to/file`
+    pub path: String,
+    /// How the data is stored
+    pub compression: ArchiveCompression,
+    /// The offset of the data from the start of the archive
+    pub offset: u64,
+    /// The length of the data in the archive
+    pub stored_len: u64,
+    /// The length of the data once decompressed
+    pub len: u64,
+}
+
+/// Reads the index of the archive.
+///
+/// Every entry is checked to be within `data` so they can be
+/// sliced out of it without further checks.
+pub fn read_archive_index(data: &[u8]) -> io::Result<Vec<ArchiveEntry>> {
+    let mut header = io::Cursor::new(data);
+    let mut magic = [0; 4];
+    header.read_exact(&mut magic)
+        .map_err(|_| invalid("missing archive header"))?;
+    if magic != ARCHIVE_MAGIC {
+        return Err(invalid("not an asset archive"));
+    }
+    let version = header.read_u32::<LittleEndian>()?;
+    if version != ARCHIVE_VERSION {
+        return Err(invalid(format!("unsupported archive version {}", version)));
+    }
+    let index_offset = header.read_u64::<LittleEndian>()?;
+    if index_offset < HEADER_SIZE || index_offset > data.len() as u64 {
+        return Err(invalid("index outside of the archive"));
+    }
+
+    let mut index = io::Cursor::new(&data[index_offset as usize..]);
+    let count = index.read_u32::<LittleEndian>()?;
+    // Capped so a corrupt count can't reserve a huge amount of memory
+    let mut entries = Vec::with_capacity((count as usize).min(4096));
+    for _ in 0 .. count {
+        let path_len = index.read_u16::<LittleEndian>()?;
+        let mut path = vec![0; path_len as usize];
+        index.read_exact(&mut path)?;
+        let path = String::from_utf8(path)
+            .map_err(|_| invalid("entry path isn't utf-8"))?;
+        let compression = ArchiveCompression::from_id(index.read_u8()?)
+            .ok_or_else(|| invalid(format!("unknown compression for {}", path)))?;
+        let offset = index.read_u64::<LittleEndian>()?;
+        let stored_len = index.read_u64::<LittleEndian>()?;
+        let len = index.read_u64::<LittleEndian>()?;
+        let in_bounds = offset >= HEADER_SIZE
+            && offset.checked_add(stored_len).map_or(false, |end| end <= index_offset);
+        if !in_bounds {
+            return Err(invalid(format!("{} is outside of the archive", path)));
+        }
+        if compression == ArchiveCompression::None && stored_len != len {
+            return Err(invalid(format!("{} has a mismatched length", path)));
+        }
+        entries.push(ArchiveEntry {
+            path,
+            compression,
+            offset,
+            stored_len,
+            len,
+        });
+    }
+    Ok(entries)
+}
+
+fn invalid<E>(err: E) -> io::Error
+    where E: Into<Box<dyn std::error::Error + Send + Sync>>
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Writes entries into an archive
+pub struct ArchiveWriter<W> {
+    out: W,
+    offset: u64,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl <W: Write + Seek> ArchiveWriter<W> {
+    /// Starts a new archive, writing its header to `out`
+    pub fn new(mut out: W) -> io::Result<ArchiveWriter<W>> {
+        out.write_all(&ARCHIVE_MAGIC)?;
+        out.write_u32::<LittleEndian>(ARCHIVE_VERSION)?;
+        // Replaced with the real offset once the index is written
+        out.write_u64::<LittleEndian>(0)?;
+        Ok(ArchiveWriter {
+            out,
+            offset: HEADER_SIZE,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Adds an entry to the archive.
+    ///
+    /// `data` must already be compressed using `compression` and
+    /// `len` is the length of the data once decompressed.
+    pub fn add(&mut self, path: &str, compression: ArchiveCompression, data: &[u8], len: u64) -> io::Result<()> {
+        if path.len() > u16::max_value() as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("path too long: {}", path)));
+        }
+        if compression == ArchiveCompression::None && data.len() as u64 != len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("mismatched length for {}", path)));
+        }
+        self.out.write_all(data)?;
+        self.entries.push(ArchiveEntry {
+            path: path.to_owned(),
+            compression,
+            offset: self.offset,
+            stored_len: data.len() as u64,
+            len,
+        });
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    /// Writes the index and returns the output
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.entries.len() > u32::max_value() as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many entries"));
+        }
+        self.out.write_u32::<LittleEndian>(self.entries.len() as u32)?;
+        for entry in &self.entries {
+            self.out.write_u16::<LittleEndian>(entry.path.len() as u16)?;
+            self.out.write_all(entry.path.as_bytes())?;
+            self.out.write_u8(entry.compression.id())?;
+            self.out.write_u64::<LittleEndian>(entry.offset)?;
+            self.out.write_u64::<LittleEndian>(entry.stored_len)?;
+            self.out.write_u64::<LittleEndian>(entry.len)?;
+        }
+        self.out.seek(SeekFrom::Start(8))?;
+        self.out.write_u64::<LittleEndian>(self.offset)?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build() -> Vec<u8> {
+        let mut writer = ArchiveWriter::new(io::Cursor::new(Vec::new())).unwrap();
+        writer.add("base/test.json", ArchiveCompression::None, b"{}", 2).unwrap();
+        writer.add("base/models/box.umod", ArchiveCompression::Deflate, &[1, 2, 3], 100).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = build();
+        let entries = read_archive_index(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "base/test.json");
+        assert_eq!(entries[0].compression, ArchiveCompression::None);
+        let start = entries[0].offset as usize;
+        assert_eq!(&data[start .. start + entries[0].stored_len as usize], b"{}");
+        assert_eq!(entries[1].path, "base/models/box.umod");
+        assert_eq!(entries[1].compression, ArchiveCompression::Deflate);
+        assert_eq!(entries[1].stored_len, 3);
+        assert_eq!(entries[1].len, 100);
+    }
+
+    #[test]
+    fn empty() {
+        let writer = ArchiveWriter::new(io::Cursor::new(Vec::new())).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        assert!(read_archive_index(&data).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_archives() {
+        assert!(read_archive_index(b"").is_err());
+        assert!(read_archive_index(b"PK\x03\x04not an archive").is_err());
+
+        let mut data = build();
+        // Future versions
+        data[4] = 2;
+        assert!(read_archive_index(&data).is_err());
+
+        // Truncated index
+        let data = build();
+        assert!(read_archive_index(&data[.. data.len() - 4]).is_err());
+
+        // An entry pointing past the data
+        let mut data = build();
+        let len = data.len();
+        data[len - 17] = 0xFF;
+        assert!(read_archive_index(&data).is_err());
+    }
+
+    #[test]
+    fn mismatched_length() {
+        let mut writer = ArchiveWriter::new(io::Cursor::new(Vec::new())).unwrap();
+        assert!(writer.add("base/test.json", ArchiveCompression::None, b"{}", 5).is_err());
+    }
+}
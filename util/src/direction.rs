@@ -1,6 +1,6 @@
 
 /// A direction along one of the 2 axis (x, z)
-#[derive(Clone, Copy, PartialEq, Eq, Debug, DeltaEncode)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, DeltaEncode)]
 pub enum Direction {
     /// Negative along the z axis
     North,
@@ -55,6 +55,12 @@ mod iter;
 pub use self::iter::*;
 mod angle;
 pub use self::angle::*;
+mod log_filter;
+pub use self::log_filter::*;
+mod timestep;
+pub use self::timestep::*;
+mod archive;
+pub use self::archive::*;
 
 use std::fmt::Debug;
 use std::panic;
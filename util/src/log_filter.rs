@@ -0,0 +1,265 @@
+//! Filtering of log records by level and module that can be
+//! changed whilst the game is running.
+//!
+//! Filters are written as a comma separated list where each entry
+//! is either a level (`info`) which applies to every module or a
+//! module path followed by a level (`univercity_server::network=trace`)
+//! which applies to that module and the modules inside of it. The
+//! most specific module wins.
+
+use slog::{Drain, Level, FilterLevel, Record, OwnedKVList};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The level used when a filter doesn't specify one
+pub const DEFAULT_LOG_LEVEL: FilterLevel = FilterLevel::Info;
+
+/// A shared handle to a set of filter rules.
+///
+/// Cloned handles share the same rules so a change made through
+/// one is seen by every drain created from the others.
+#[derive(Clone)]
+pub struct LogFilter {
+    rules: Arc<RwLock<FilterRules>>,
+    /// The most verbose level any rule allows, used to reject
+    /// records without locking the rules
+    max_level: Arc<AtomicUsize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct FilterRules {
+    level: FilterLevel,
+    modules: Vec<(String, FilterLevel)>,
+}
+
+impl FilterRules {
+    fn level_for(&self, module: &str) -> FilterLevel {
+        self.modules.iter()
+            .filter(|(path, _)| module == path
+                || (module.starts_with(path.as_str()) && module[path.len()..].starts_with("::")))
+            .max_by_key(|(path, _)| path.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> FilterLevel {
+        self.modules.iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, |a, b| if b.as_usize() > a.as_usize() { b } else { a })
+    }
+
+    fn apply(&mut self, changes: Vec<(Option<String>, FilterLevel)>) {
+        for (module, level) in changes {
+            if let Some(module) = module {
+                self.modules.retain(|(path, _)| *path != module);
+                self.modules.push((module, level));
+            } else {
+                self.level = level;
+            }
+        }
+    }
+}
+
+impl LogFilter {
+    /// Creates a filter which allows records at `level` and
+    /// above from every module
+    pub fn new(level: FilterLevel) -> LogFilter {
+        LogFilter {
+            rules: Arc::new(RwLock::new(FilterRules {
+                level,
+                modules: Vec::new(),
+            })),
+            max_level: Arc::new(AtomicUsize::new(level.as_usize())),
+        }
+    }
+
+    /// Wraps the drain so that only records allowed by this
+    /// filter are passed on to it
+    pub fn drain<D: Drain>(&self, drain: D) -> FilteredDrain<D> {
+        FilteredDrain {
+            filter: self.clone(),
+            drain,
+        }
+    }
+
+    /// Returns whether a record from the module at the level
+    /// would be logged
+    pub fn is_enabled(&self, module: &str, level: Level) -> bool {
+        if level.as_usize() > self.max_level.load(Ordering::Relaxed) {
+            return false;
+        }
+        let rules = match self.rules.read() {
+            Ok(val) => val,
+            Err(err) => err.into_inner(),
+        };
+        level.as_usize() <= rules.level_for(module).as_usize()
+    }
+
+    /// Applies the filter on top of the current rules.
+    ///
+    /// Nothing is changed if any part of the filter is invalid
+    pub fn apply(&self, filter: &str) -> Result<(), String> {
+        let changes = parse_filter(filter)?;
+        self.update(|rules| rules.apply(changes));
+        Ok(())
+    }
+
+    /// Replaces the current rules with the filter.
+    ///
+    /// Modules not mentioned by the filter go back to using the
+    /// global level which is `DEFAULT_LOG_LEVEL` unless the filter
+    /// sets it. Nothing is changed if any part of the filter is invalid
+    pub fn reset(&self, filter: &str) -> Result<(), String> {
+        let changes = parse_filter(filter)?;
+        self.update(|rules| {
+            rules.level = DEFAULT_LOG_LEVEL;
+            rules.modules.clear();
+            rules.apply(changes);
+        });
+        Ok(())
+    }
+
+    fn update<F: FnOnce(&mut FilterRules)>(&self, f: F) {
+        let mut rules = match self.rules.write() {
+            Ok(val) => val,
+            Err(err) => err.into_inner(),
+        };
+        f(&mut rules);
+        self.max_level.store(rules.max_level().as_usize(), Ordering::Relaxed);
+    }
+
+    /// Returns the current rules in the same format that
+    /// `apply` accepts
+    pub fn describe(&self) -> String {
+        let rules = match self.rules.read() {
+            Ok(val) => val,
+            Err(err) => err.into_inner(),
+        };
+        let mut out = level_name(rules.level).to_owned();
+        for (module, level) in &rules.modules {
+            out.push(',');
+            out.push_str(module);
+            out.push('=');
+            out.push_str(level_name(*level));
+        }
+        out
+    }
+}
+
+fn level_name(level: FilterLevel) -> &'static str {
+    match level {
+        FilterLevel::Off => "off",
+        FilterLevel::Critical => "critical",
+        FilterLevel::Error => "error",
+        FilterLevel::Warning => "warn",
+        FilterLevel::Info => "info",
+        FilterLevel::Debug => "debug",
+        FilterLevel::Trace => "trace",
+    }
+}
+
+fn parse_level(level: &str) -> Result<FilterLevel, String> {
+    let level = level.trim();
+    Ok(match level.to_ascii_lowercase().as_str() {
+        "off" => FilterLevel::Off,
+        "critical" | "crit" => FilterLevel::Critical,
+        "error" => FilterLevel::Error,
+        "warn" | "warning" => FilterLevel::Warning,
+        "info" => FilterLevel::Info,
+        "debug" => FilterLevel::Debug,
+        "trace" => FilterLevel::Trace,
+        _ => return Err(format!("Unknown log level {:?}", level)),
+    })
+}
+
+fn parse_filter(filter: &str) -> Result<Vec<(Option<String>, FilterLevel)>, String> {
+    filter.split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            let first = parts.next().unwrap_or("").trim();
+            if let Some(level) = parts.next() {
+                if first.is_empty() {
+                    return Err(format!("Missing module name in {:?}", entry));
+                }
+                Ok((Some(first.to_owned()), parse_level(level)?))
+            } else {
+                Ok((None, parse_level(first)?))
+            }
+        })
+        .collect()
+}
+
+/// A drain that passes on the records allowed by a `LogFilter`
+pub struct FilteredDrain<D> {
+    filter: LogFilter,
+    drain: D,
+}
+
+impl <D: Drain> Drain for FilteredDrain<D> {
+    type Ok = Option<D::Ok>;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        if self.filter.is_enabled(record.module(), record.level()) {
+            self.drain.log(record, values).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn is_enabled(&self, level: Level) -> bool {
+        level.as_usize() <= self.filter.max_level.load(Ordering::Relaxed)
+            && self.drain.is_enabled(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_level() {
+        let filter = LogFilter::new(FilterLevel::Info);
+        assert!(filter.is_enabled("univercity", Level::Info));
+        assert!(!filter.is_enabled("univercity", Level::Debug));
+
+        assert!(filter.apply("debug").is_ok());
+        assert!(filter.is_enabled("univercity", Level::Debug));
+        assert!(!filter.is_enabled("univercity", Level::Trace));
+    }
+
+    #[test]
+    fn module_levels() {
+        let filter = LogFilter::new(FilterLevel::Warning);
+        assert!(filter.apply("univercity_server::network=trace, univercity_server::network::udp=error").is_ok());
+        assert!(filter.is_enabled("univercity_server::network", Level::Trace));
+        assert!(filter.is_enabled("univercity_server::network::steam", Level::Trace));
+        // The more specific module wins
+        assert!(!filter.is_enabled("univercity_server::network::udp", Level::Warning));
+        // Only whole path segments match
+        assert!(!filter.is_enabled("univercity_server::networking", Level::Info));
+        assert!(!filter.is_enabled("univercity", Level::Info));
+    }
+
+    #[test]
+    fn reset_and_describe() {
+        let filter = LogFilter::new(FilterLevel::Info);
+        assert!(filter.apply("trace,univercity::audio=off").is_ok());
+        assert_eq!(filter.describe(), "trace,univercity::audio=off");
+        assert!(filter.reset("univercity::render=debug").is_ok());
+        assert_eq!(filter.describe(), "info,univercity::render=debug");
+    }
+
+    #[test]
+    fn invalid_filters() {
+        let filter = LogFilter::new(FilterLevel::Info);
+        assert!(filter.apply("loud").is_err());
+        assert!(filter.apply("debug,univercity=loud").is_err());
+        assert!(filter.apply("=debug").is_err());
+        // Nothing is applied if any part fails
+        assert_eq!(filter.describe(), "info");
+        assert!(filter.apply("WARNING").is_ok());
+        assert_eq!(filter.describe(), "warn");
+    }
+}
@@ -0,0 +1,124 @@
+//! Running simulations at a fixed rate independent of the frame
+//! rate.
+//!
+//! The time between frames is added to an accumulator which is
+//! then spent in whole steps, leaving the remainder for the next
+//! frame. A simulation advanced this way behaves the same at any
+//! frame rate. The units of time are up to the user, the only
+//! requirement is that the step and the elapsed time use the same
+//! ones.
+
+/// Converts elapsed time into a number of fixed steps
+#[derive(Clone, Debug)]
+pub struct FixedTimestep {
+    step: f64,
+    max_steps: u32,
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    /// Creates an accumulator that runs a step each time `step`
+    /// passes.
+    ///
+    /// At most `max_steps` are run per call to `advance`, any time
+    /// past that is dropped to stop a long frame (e.g. loading)
+    /// causing a burst of steps that slows the following frames
+    /// down further.
+    pub fn new(step: f64, max_steps: u32) -> FixedTimestep {
+        FixedTimestep {
+            step: if step > 0.0 { step } else { 1.0 },
+            max_steps: max_steps.max(1),
+            accumulator: 0.0,
+        }
+    }
+
+    /// Returns the length of a single step
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// Adds the elapsed time and returns the number of steps
+    /// that should be run
+    pub fn advance(&mut self, elapsed: f64) -> u32 {
+        if elapsed.is_finite() && elapsed > 0.0 {
+            self.accumulator += elapsed;
+        }
+        let steps = (self.accumulator / self.step).floor();
+        if steps >= f64::from(self.max_steps) {
+            self.accumulator = 0.0;
+            return self.max_steps;
+        }
+        self.accumulator -= steps * self.step;
+        steps as u32
+    }
+
+    /// Returns how far through the next step the accumulator is
+    /// between 0.0 and 1.0.
+    ///
+    /// Useful for interpolating between the last two steps when
+    /// drawing
+    pub fn alpha(&self) -> f64 {
+        (self.accumulator / self.step).max(0.0).min(1.0)
+    }
+
+    /// Drops any time that hasn't been spent yet
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
+
+/// Runs `func` once for each step the elapsed time covers,
+/// passing the length of the step
+pub fn run_steps<F>(timestep: &mut FixedTimestep, elapsed: f64, mut func: F)
+    where F: FnMut(f64)
+{
+    let step = timestep.step();
+    for _ in 0 .. timestep.advance(elapsed) {
+        func(step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_rate_independent() {
+        // The same second at 30, 60 and 144 frames per second
+        for &fps in &[30.0, 60.0, 144.0] {
+            let mut timestep = FixedTimestep::new(1.0 / 60.0, 8);
+            let mut steps = 0;
+            for _ in 0 .. fps as u32 {
+                steps += timestep.advance(1.0 / fps);
+            }
+            // Rounding can leave the last step a hair short
+            assert!(steps == 60 || steps == 59, "{} fps ran {} steps", fps, steps);
+        }
+    }
+
+    #[test]
+    fn remainder() {
+        let mut timestep = FixedTimestep::new(2.0, 8);
+        assert_eq!(timestep.advance(3.0), 1);
+        assert!((timestep.alpha() - 0.5).abs() < 0.0001);
+        assert_eq!(timestep.advance(1.0), 1);
+        assert!(timestep.alpha().abs() < 0.0001);
+        assert_eq!(timestep.advance(1.0), 0);
+        timestep.reset();
+        assert_eq!(timestep.advance(1.0), 0);
+    }
+
+    #[test]
+    fn max_steps() {
+        let mut timestep = FixedTimestep::new(1.0, 3);
+        assert_eq!(timestep.advance(100.0), 3);
+        // The rest of the long frame is dropped
+        assert_eq!(timestep.advance(0.5), 0);
+        assert_eq!(timestep.advance(::std::f64::NAN), 0);
+        assert_eq!(timestep.advance(-4.0), 0);
+
+        let mut total = 0.0;
+        run_steps(&mut timestep, 2.5, |step| total += step);
+        assert!((total - 3.0).abs() < 0.0001);
+    }
+}